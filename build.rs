@@ -0,0 +1,76 @@
+// Records what the binary was built from for GET /api/version: the git commit
+// and whether the tree had uncommitted changes, the build time and the rustc
+// version. Each is left unset when it can't be found, as in a packaged crate.
+//
+// Generates the gRPC service and messages from proto/void_shrine.proto when
+// built with the `grpc` feature, with a bundled protoc so none needs installing.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    build_info();
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/void_shrine.proto");
+        if std::env::var_os("PROTOC").is_none() {
+            let protoc = protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc for this platform; set PROTOC");
+            std::env::set_var("PROTOC", protoc);
+        }
+        tonic_build::compile_protos("proto/void_shrine.proto").expect("compiling proto/void_shrine.proto");
+    }
+}
+
+fn build_info() {
+    // Rerun whenever the crate itself would be rebuilt, or the commit moves,
+    // so the dirty flag and build time describe this build
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    if let Some(git_dir) = output("git", &["rev-parse", "--git-dir"]) {
+        for file in ["HEAD", "index"] {
+            let path = Path::new(&git_dir).join(file);
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+        if let Some(head) = output("git", &["symbolic-ref", "-q", "HEAD"]) {
+            let path = Path::new(&git_dir).join(head);
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    }
+
+    if let Some(commit) = output("git", &["rev-parse", "HEAD"]) {
+        println!("cargo:rustc-env=VOID_SHRINE_GIT_COMMIT={}", commit);
+        if let Some(status) = output("git", &["status", "--porcelain", "--untracked-files=no"]) {
+            println!("cargo:rustc-env=VOID_SHRINE_GIT_DIRTY={}", !status.is_empty());
+        }
+    }
+
+    // Reproducible builds pin the time
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.trim().parse::<u64>().ok())
+        .or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|elapsed| elapsed.as_secs()));
+    if let Some(secs) = built_at {
+        println!("cargo:rustc-env=VOID_SHRINE_BUILT_AT={}", secs);
+    }
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    if let Some(version) = output(&rustc, &["--version"]) {
+        println!("cargo:rustc-env=VOID_SHRINE_RUSTC_VERSION={}", version);
+    }
+}
+
+/// Trimmed stdout of a command that succeeded
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
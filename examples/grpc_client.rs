@@ -0,0 +1,69 @@
+//! Asks a Void Shrine server one question over gRPC, then streams the answer
+//! to it. Start the server with `grpc_port` set, then run
+//!
+//!     VOID_SHRINE_API_KEY=<secret> cargo run --features grpc --example grpc_client -- http://localhost:50051 "What is the void shrine?"
+//!
+//! leaving out `VOID_SHRINE_API_KEY` when the server has no keys configured.
+
+use std::io::Write;
+use anyhow::Context;
+use tonic::metadata::MetadataValue;
+use tonic::Request;
+use void_shrine_mcp::grpc::proto::inference_event::Event;
+use void_shrine_mcp::grpc::proto::void_shrine_client::VoidShrineClient;
+use void_shrine_mcp::grpc::proto::{InferRequest, Params};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let endpoint = args.next().unwrap_or_else(|| "http://localhost:50051".to_string());
+    let prompt = args.next().unwrap_or_else(|| "What is the void shrine?".to_string());
+    let authorization = match std::env::var("VOID_SHRINE_API_KEY") {
+        Ok(secret) => Some(MetadataValue::try_from(format!("Bearer {}", secret)).context("VOID_SHRINE_API_KEY")?),
+        Err(_) => None,
+    };
+    let request = |params: &Params| {
+        let mut request = Request::new(InferRequest { params: Some(params.clone()), request_id: None });
+        if let Some(authorization) = &authorization {
+            request.metadata_mut().insert("authorization", authorization.clone());
+        }
+        request
+    };
+
+    let mut client = VoidShrineClient::connect(endpoint.clone()).await.with_context(|| format!("connecting to {}", endpoint))?;
+    let params = Params {
+        agent_id: "grpc-example".to_string(),
+        prompt,
+        max_tokens: 256,
+        temperature: 0.7,
+        use_rag: true,
+        context_window: 4096,
+        ..Params::default()
+    };
+
+    let response = client.infer(request(&params)).await?.into_inner();
+    let result = response.result.unwrap_or_default();
+    let metadata = response.metadata.unwrap_or_default();
+    println!("{}\n", result.response);
+    println!(
+        "request {}: {} citations, confidence {:.3}",
+        metadata.request_id,
+        result.citations.len(),
+        result.metrics.map_or(0.0, |metrics| metrics.confidence_score)
+    );
+
+    println!("\nStreamed:");
+    let mut events = client.infer_stream(request(&params)).await?.into_inner();
+    while let Some(event) = events.message().await? {
+        match event.event {
+            Some(Event::Delta(delta)) => {
+                print!("{}", delta.text);
+                std::io::stdout().flush()?;
+            }
+            Some(Event::Done(done)) => println!("\n\n({} tokens)", done.metrics.map_or(0, |metrics| metrics.token_count)),
+            Some(Event::Error(error)) => anyhow::bail!("stream failed: {}", error.message),
+            _ => {}
+        }
+    }
+    Ok(())
+}
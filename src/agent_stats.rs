@@ -0,0 +1,276 @@
+//! Per-agent activity over the last hour, behind
+//! `GET /api/agents/{id}/metrics`. Activity is counted in one-minute buckets
+//! keyed by wall-clock minute, each with a latency histogram of fixed bounds,
+//! so percentiles over any of the `StatsWindow`s cost the same memory however
+//! busy an agent is. Percentiles are the upper bound of the bucket the rank
+//! falls in, capped at the slowest latency seen.
+
+use std::collections::VecDeque;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::load::LatencyPercentiles;
+
+/// Upper bounds of the latency histogram, in milliseconds; slower requests
+/// land in a final open-ended bucket
+const LATENCY_BOUNDS_MS: [u64; 14] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10_000, 25_000, 60_000, 120_000];
+const LATENCY_BUCKETS: usize = LATENCY_BOUNDS_MS.len() + 1;
+
+/// The aggregation periods on offer, the longest being how much is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatsWindow {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[default]
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+impl StatsWindow {
+    pub fn minutes(self) -> i64 {
+        match self {
+            StatsWindow::OneMinute => 1,
+            StatsWindow::FiveMinutes => 5,
+            StatsWindow::FifteenMinutes => 15,
+            StatsWindow::OneHour => 60,
+        }
+    }
+}
+
+/// Latencies counted against `LATENCY_BOUNDS_MS`, and the slowest seen
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    latencies: [u64; LATENCY_BUCKETS],
+    max_latency_ms: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self { latencies: [0; LATENCY_BUCKETS], max_latency_ms: 0 }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency_ms: u64) {
+        let index = LATENCY_BOUNDS_MS.iter().position(|bound| latency_ms <= *bound).unwrap_or(LATENCY_BOUNDS_MS.len());
+        self.latencies[index] += 1;
+        self.max_latency_ms = self.max_latency_ms.max(latency_ms);
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (total, count) in self.latencies.iter_mut().zip(other.latencies) {
+            *total += count;
+        }
+        self.max_latency_ms = self.max_latency_ms.max(other.max_latency_ms);
+    }
+
+    pub fn percentiles(&self) -> LatencyPercentiles {
+        let samples: u64 = self.latencies.iter().sum();
+        let at = |percent: u64| {
+            let rank = (samples * percent).div_ceil(100);
+            let mut seen = 0;
+            let index = self.latencies.iter().position(|count| {
+                seen += count;
+                rank > 0 && seen >= rank
+            });
+            index.map_or(0, |index| LATENCY_BOUNDS_MS.get(index).map_or(self.max_latency_ms, |bound| (*bound).min(self.max_latency_ms)))
+        };
+        LatencyPercentiles { samples: samples as usize, p50_ms: at(50), p90_ms: at(90), p95_ms: at(95), p99_ms: at(99) }
+    }
+}
+
+/// One wall-clock minute of an agent's activity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct MinuteBucket {
+    /// Minutes since the Unix epoch
+    minute: i64,
+    requests: u64,
+    succeeded: u64,
+    failed: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    throttled: u64,
+    chaos_events: u64,
+    #[serde(flatten)]
+    latency: LatencyHistogram,
+}
+
+impl MinuteBucket {
+    fn new(minute: i64) -> Self {
+        Self {
+            minute,
+            requests: 0,
+            succeeded: 0,
+            failed: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            throttled: 0,
+            chaos_events: 0,
+            latency: LatencyHistogram::default(),
+        }
+    }
+}
+
+/// An agent's activity over one window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowedStats {
+    pub window: StatsWindow,
+    pub requests: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Of the requests finished in the window; None when none did
+    pub success_rate: Option<f64>,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Requests held back or refused by load-based throttling
+    pub throttled: u64,
+    pub chaos_events: u64,
+    pub latency: LatencyPercentiles,
+}
+
+/// The last hour of an agent's activity, oldest minute first
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AgentStats {
+    minutes: VecDeque<MinuteBucket>,
+}
+
+impl AgentStats {
+    pub fn record_request(&mut self, now: DateTime<Utc>) {
+        self.bucket(now).requests += 1;
+    }
+
+    pub fn record_outcome(&mut self, now: DateTime<Utc>, latency_ms: Option<u64>, success: bool) {
+        let bucket = self.bucket(now);
+        if success {
+            bucket.succeeded += 1;
+        } else {
+            bucket.failed += 1;
+        }
+        if let Some(latency_ms) = latency_ms {
+            bucket.latency.record(latency_ms);
+        }
+    }
+
+    pub fn record_tokens(&mut self, now: DateTime<Utc>, prompt_tokens: u32, completion_tokens: u32) {
+        let bucket = self.bucket(now);
+        bucket.prompt_tokens += u64::from(prompt_tokens);
+        bucket.completion_tokens += u64::from(completion_tokens);
+    }
+
+    pub fn record_throttled(&mut self, now: DateTime<Utc>) {
+        self.bucket(now).throttled += 1;
+    }
+
+    pub fn record_chaos(&mut self, now: DateTime<Utc>) {
+        self.bucket(now).chaos_events += 1;
+    }
+
+    /// Totals and latency percentiles over the `window` minutes up to `now`
+    pub fn summary(&self, window: StatsWindow, now: DateTime<Utc>) -> WindowedStats {
+        Self::combined([self], window, now)
+    }
+
+    /// `summary` over several agents' activity together, their latency
+    /// histograms merged rather than their percentiles averaged
+    pub fn combined<'a>(all: impl IntoIterator<Item = &'a AgentStats>, window: StatsWindow, now: DateTime<Utc>) -> WindowedStats {
+        let since = minute_of(now) - window.minutes();
+        let mut stats = WindowedStats {
+            window,
+            requests: 0,
+            succeeded: 0,
+            failed: 0,
+            success_rate: None,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            throttled: 0,
+            chaos_events: 0,
+            latency: LatencyPercentiles::default(),
+        };
+        let mut latency = LatencyHistogram::default();
+        for bucket in all.into_iter().flat_map(|stats| &stats.minutes).filter(|bucket| bucket.minute > since) {
+            stats.requests += bucket.requests;
+            stats.succeeded += bucket.succeeded;
+            stats.failed += bucket.failed;
+            stats.prompt_tokens += bucket.prompt_tokens;
+            stats.completion_tokens += bucket.completion_tokens;
+            stats.throttled += bucket.throttled;
+            stats.chaos_events += bucket.chaos_events;
+            latency.merge(&bucket.latency);
+        }
+        let finished = stats.succeeded + stats.failed;
+        stats.success_rate = (finished > 0).then(|| stats.succeeded as f64 / finished as f64);
+        stats.latency = latency.percentiles();
+        stats
+    }
+
+    /// The bucket of `now`'s minute, dropping those too old for any window
+    fn bucket(&mut self, now: DateTime<Utc>) -> &mut MinuteBucket {
+        let minute = minute_of(now);
+        let oldest = minute - StatsWindow::OneHour.minutes();
+        while self.minutes.front().is_some_and(|bucket| bucket.minute <= oldest) {
+            self.minutes.pop_front();
+        }
+        // A clock stepping back counts in the latest minute rather than reordering
+        if self.minutes.back().is_none_or(|bucket| bucket.minute < minute) {
+            self.minutes.push_back(MinuteBucket::new(minute));
+        }
+        self.minutes.back_mut().expect("a bucket was just ensured")
+    }
+}
+
+fn minute_of(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_cover_their_minutes_only() {
+        let start = DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let at = |minutes: i64| start + chrono::Duration::minutes(minutes);
+        let mut stats = AgentStats::default();
+        for latency_ms in [40, 80, 90, 300] {
+            stats.record_request(at(0));
+            stats.record_outcome(at(0), Some(latency_ms), latency_ms < 300);
+        }
+        stats.record_tokens(at(0), 100, 20);
+        stats.record_request(at(10));
+        stats.record_outcome(at(10), Some(7000), true);
+        stats.record_chaos(at(10));
+
+        let recent = stats.summary(StatsWindow::FiveMinutes, at(12));
+        assert_eq!((recent.requests, recent.chaos_events, recent.success_rate), (1, 1, Some(1.0)));
+        assert_eq!(recent.latency.p50_ms, 7000);
+        let hour = stats.summary(StatsWindow::OneHour, at(12));
+        assert_eq!((hour.requests, hour.failed, hour.prompt_tokens, hour.completion_tokens), (5, 1, 100, 20));
+        assert_eq!((hour.latency.samples, hour.latency.p50_ms, hour.latency.p95_ms), (5, 100, 7000));
+
+        // An hour on, the first minute is gone from every window
+        stats.record_request(at(61));
+        assert_eq!(stats.summary(StatsWindow::OneHour, at(61)).requests, 2);
+        assert_eq!(stats.summary(StatsWindow::OneMinute, at(90)).latency, LatencyPercentiles::default());
+    }
+
+    #[test]
+    fn combined_merges_histograms() {
+        let at = DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let mut quick = AgentStats::default();
+        let mut slow = AgentStats::default();
+        for _ in 0..3 {
+            quick.record_request(at);
+            quick.record_outcome(at, Some(8), true);
+        }
+        slow.record_request(at);
+        slow.record_outcome(at, Some(900), false);
+
+        let both = AgentStats::combined([&quick, &slow], StatsWindow::OneMinute, at);
+        assert_eq!((both.requests, both.failed, both.success_rate), (4, 1, Some(0.75)));
+        assert_eq!((both.latency.samples, both.latency.p50_ms, both.latency.p99_ms), (4, 10, 900));
+    }
+}
@@ -0,0 +1,395 @@
+//! Agent registrations: the specialty, default model, concurrency, tags and
+//! description an agent declares through `POST /api/agents`. Requests from a
+//! registered agent leaving `model` or `specialty` empty get the registered
+//! ones, and in strict mode a request claiming another specialty is refused.
+//! Unregistered agents are served as before. With a `path`, registrations are
+//! saved to a JSON file on every change and reloaded on start.
+//!
+//! `GET /api/agents` lists registered agents together with those only seen
+//! making requests; ones idle for `stale_after_secs` are marked stale. An
+//! agent registered with a tenant's key belongs to that tenant.
+//!
+//! Agents may also send `POST /api/agents/{id}/heartbeat`. One that has is
+//! held to `heartbeat_timeout_secs` instead: it goes stale once neither a
+//! heartbeat nor a request has come for that long. Going stale is logged and
+//! sent as an `agent_stale` webhook, and forgets the agent's recent load and
+//! scaling history, so it isn't throttled on old figures when it returns.
+//! With `evict_after_secs`, stale agents idle that long lose their metrics.
+//!
+//! An agent's requests may also take other params from defaults of its own:
+//! the `defaults` of its registration, else `[agents.defaults.<agent_id>]` in
+//! the config file, which serves agents that never register. Params the
+//! request gives always win.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentsConfig {
+    /// Refuse requests from registered agents claiming another specialty
+    pub strict: bool,
+    /// JSON file registrations are kept in; unset keeps them in memory only
+    pub path: Option<PathBuf>,
+    /// Agents without a request for this long, or registered this long ago
+    /// and never seen, are listed as stale
+    pub stale_after_secs: u64,
+    /// Agents that send heartbeats go stale once this long passes without a
+    /// heartbeat or a request
+    pub heartbeat_timeout_secs: u64,
+    /// Stale agents idle this long have their metrics removed; unset keeps them
+    pub evict_after_secs: Option<u64>,
+    /// Params for requests from each agent that leave them out, under its
+    /// registration's own `defaults`
+    pub defaults: BTreeMap<String, AgentDefaults>,
+}
+
+impl Default for AgentsConfig {
+    fn default() -> Self {
+        Self { strict: false, path: None, stale_after_secs: 3600, heartbeat_timeout_secs: 90, evict_after_secs: None, defaults: BTreeMap::new() }
+    }
+}
+
+impl AgentsConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.stale_after_secs == 0 || self.heartbeat_timeout_secs == 0 {
+            problems.push("agents.stale_after_secs and heartbeat_timeout_secs must be positive".to_string());
+        }
+        if let Some(evict_after) = self.evict_after_secs {
+            if evict_after < self.stale_after_secs.max(self.heartbeat_timeout_secs) {
+                problems.push(format!("agents.evict_after_secs ({}) must be at least stale_after_secs and heartbeat_timeout_secs", evict_after));
+            }
+        }
+        for (agent_id, defaults) in &self.defaults {
+            for (field, constraint, value) in defaults.out_of_range() {
+                problems.push(format!("agents.defaults.{}.{} must be {} (got {})", agent_id, field, constraint, value));
+            }
+        }
+        problems
+    }
+}
+
+/// Params an agent's requests take when they leave them out, ahead of the
+/// server's defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentDefaults {
+    /// Only in the config file; registrations have `default_model`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Only in the config file; registrations have `specialty`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub specialty: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_rag: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl AgentDefaults {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Each of these defaults, else the one of `fallback`
+    pub fn or(self, fallback: AgentDefaults) -> AgentDefaults {
+        AgentDefaults {
+            model: self.model.or(fallback.model),
+            specialty: self.specialty.or(fallback.specialty),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            temperature: self.temperature.or(fallback.temperature),
+            use_rag: self.use_rag.or(fallback.use_rag),
+            context_window: self.context_window.or(fallback.context_window),
+            template: self.template.or(fallback.template),
+        }
+    }
+
+    /// Values no request could take, as field, constraint and value. Limits
+    /// of the server's own are met when a request takes them.
+    pub fn out_of_range(&self) -> Vec<(&'static str, &'static str, serde_json::Value)> {
+        let mut problems = Vec::new();
+        let blank = |text: &Option<String>| text.as_deref().is_some_and(|text| text.trim().is_empty());
+        if blank(&self.model) {
+            problems.push(("model", "non-empty when given", serde_json::Value::from("")));
+        }
+        if blank(&self.specialty) {
+            problems.push(("specialty", "non-empty when given", serde_json::Value::from("")));
+        }
+        if let Some(temperature) = self.temperature.filter(|temperature| !(0.0..=2.0).contains(temperature)) {
+            problems.push(("temperature", "between 0 and 2", serde_json::Value::from(temperature)));
+        }
+        if self.max_tokens == Some(0) {
+            problems.push(("max_tokens", "positive", serde_json::Value::from(0)));
+        }
+        if self.context_window == Some(0) {
+            problems.push(("context_window", "positive", serde_json::Value::from(0)));
+        }
+        problems
+    }
+}
+
+/// Tags per registration
+pub const MAX_AGENT_TAGS: usize = 32;
+/// Bytes per tag
+pub const MAX_AGENT_TAG_LEN: usize = 64;
+pub const MAX_AGENT_DESCRIPTION_BYTES: usize = 1024;
+
+/// A registration as `POST /api/agents` and `PUT /api/agents/{id}` take it.
+/// `agent_id` is required when registering; on update it comes from the path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentSpec {
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    pub specialty: String,
+    #[serde(default)]
+    pub default_model: Option<String>,
+    /// Requests handled at once; more are refused as throttled
+    #[serde(default)]
+    pub max_concurrency: Option<u32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Params for the agent's requests that leave them out
+    #[serde(default)]
+    pub defaults: AgentDefaults,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentRegistration {
+    pub agent_id: String,
+    pub specialty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<u32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "AgentDefaults::is_empty")]
+    pub defaults: AgentDefaults,
+    pub registered_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl AgentRegistration {
+    fn new(agent_id: &str, spec: AgentSpec, registered_at: DateTime<Utc>, tenant: Option<String>) -> Self {
+        Self {
+            agent_id: agent_id.to_string(),
+            specialty: spec.specialty,
+            default_model: spec.default_model,
+            max_concurrency: spec.max_concurrency,
+            tags: spec.tags,
+            description: spec.description,
+            defaults: spec.defaults,
+            registered_at,
+            updated_at: Utc::now(),
+            tenant,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentsResponse {
+    /// Sorted by agent_id
+    pub agents: Vec<AgentRegistration>,
+}
+
+#[derive(Debug)]
+pub struct AgentRegistry {
+    agents: DashMap<String, AgentRegistration>,
+    path: Option<PathBuf>,
+    strict: bool,
+    stale_after: Duration,
+    heartbeat_timeout: Duration,
+    evict_after: Option<Duration>,
+    defaults: BTreeMap<String, AgentDefaults>,
+    /// Held while changing and saving, so saves land in the order of changes
+    writing: Mutex<()>,
+}
+
+impl AgentRegistry {
+    /// A registry holding what was saved at `config.path`, if anything
+    pub fn open(config: &AgentsConfig) -> Result<Self> {
+        let agents = DashMap::new();
+        if let Some(path) = config.path.as_ref().filter(|path| path.exists()) {
+            let text = std::fs::read_to_string(path).with_context(|| format!("reading agent registrations from {}", path.display()))?;
+            let saved: Vec<AgentRegistration> =
+                serde_json::from_str(&text).with_context(|| format!("parsing agent registrations in {}", path.display()))?;
+            for registration in saved {
+                agents.insert(registration.agent_id.clone(), registration);
+            }
+        }
+        Ok(Self {
+            agents,
+            path: config.path.clone(),
+            strict: config.strict,
+            stale_after: Duration::from_secs(config.stale_after_secs),
+            heartbeat_timeout: Duration::from_secs(config.heartbeat_timeout_secs),
+            evict_after: config.evict_after_secs.map(Duration::from_secs),
+            defaults: config.defaults.clone(),
+            writing: Mutex::new(()),
+        })
+    }
+
+    /// Whether registered agents must keep to their specialty
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// How long an agent goes unseen before it is listed as stale
+    pub fn stale_after(&self) -> Duration {
+        self.stale_after
+    }
+
+    /// How long an agent that sends heartbeats may go quiet before it is stale
+    pub fn heartbeat_timeout(&self) -> Duration {
+        self.heartbeat_timeout
+    }
+
+    /// How long a stale agent stays idle before its metrics are removed
+    pub fn evict_after(&self) -> Option<Duration> {
+        self.evict_after
+    }
+
+    /// The agent's defaults: its registration's, with its default model and
+    /// specialty, else the configured ones
+    pub fn defaults(&self, agent_id: &str) -> AgentDefaults {
+        let configured = self.defaults.get(agent_id).cloned().unwrap_or_default();
+        match self.get(agent_id) {
+            Some(registration) => AgentDefaults { model: registration.default_model, specialty: Some(registration.specialty), ..registration.defaults }.or(configured),
+            None => configured,
+        }
+    }
+
+    pub fn get(&self, agent_id: &str) -> Option<AgentRegistration> {
+        self.agents.get(agent_id).map(|entry| entry.value().clone())
+    }
+
+    pub fn list(&self) -> AgentsResponse {
+        let mut agents: Vec<AgentRegistration> = self.agents.iter().map(|entry| entry.value().clone()).collect();
+        agents.sort_by(|a, b| a.agent_id.cmp(&b.agent_id));
+        AgentsResponse { agents }
+    }
+
+    /// The new registration, or None when `agent_id` is registered already
+    pub fn register(&self, agent_id: &str, spec: AgentSpec, tenant: Option<&str>) -> Result<Option<AgentRegistration>> {
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        if self.agents.contains_key(agent_id) {
+            return Ok(None);
+        }
+        let registration = AgentRegistration::new(agent_id, spec, Utc::now(), tenant.map(str::to_string));
+        self.agents.insert(agent_id.to_string(), registration.clone());
+        self.save_or_restore(agent_id, None)?;
+        Ok(Some(registration))
+    }
+
+    /// The updated registration, or None when `agent_id` isn't registered;
+    /// the agent stays with its tenant
+    pub fn update(&self, agent_id: &str, spec: AgentSpec) -> Result<Option<AgentRegistration>> {
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(previous) = self.get(agent_id) else {
+            return Ok(None);
+        };
+        let registration = AgentRegistration::new(agent_id, spec, previous.registered_at, previous.tenant.clone());
+        self.agents.insert(agent_id.to_string(), registration.clone());
+        self.save_or_restore(agent_id, Some(previous))?;
+        Ok(Some(registration))
+    }
+
+    /// The removed registration, or None when `agent_id` isn't registered
+    pub fn deregister(&self, agent_id: &str) -> Result<Option<AgentRegistration>> {
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        let Some((_, removed)) = self.agents.remove(agent_id) else {
+            return Ok(None);
+        };
+        self.save_or_restore(agent_id, Some(removed.clone()))?;
+        Ok(Some(removed))
+    }
+
+    /// Saves every registration, putting `previous` back when that fails so
+    /// memory and file agree
+    fn save_or_restore(&self, agent_id: &str, previous: Option<AgentRegistration>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let saved = serde_json::to_vec_pretty(&self.list().agents).map_err(anyhow::Error::from).and_then(|json| {
+            let staged = path.with_extension("saving");
+            std::fs::write(&staged, json)?;
+            std::fs::rename(&staged, path)?;
+            Ok(())
+        });
+        if let Err(e) = saved {
+            match previous {
+                Some(previous) => self.agents.insert(agent_id.to_string(), previous),
+                None => self.agents.remove(agent_id).map(|(_, removed)| removed),
+            };
+            return Err(e.context(format!("saving agent registrations to {}", path.display())));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(specialty: &str) -> AgentSpec {
+        AgentSpec {
+            agent_id: None,
+            specialty: specialty.to_string(),
+            default_model: Some("llama3.2".to_string()),
+            max_concurrency: Some(2),
+            tags: vec!["night-shift".to_string()],
+            description: None,
+            defaults: AgentDefaults::default(),
+        }
+    }
+
+    #[test]
+    fn registrations_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("void-shrine-agents-test-{}.json", uuid::Uuid::new_v4()));
+        let config = AgentsConfig { path: Some(path.clone()), ..AgentsConfig::default() };
+
+        let registry = AgentRegistry::open(&config).unwrap();
+        let registered = registry.register("scout", spec("science"), Some("acme")).unwrap().unwrap();
+        assert!(registry.register("scout", spec("tactical"), None).unwrap().is_none());
+        registry.register("medic", spec("medical"), None).unwrap();
+        registry.deregister("medic").unwrap().unwrap();
+        let updated = registry.update("scout", spec("tactical")).unwrap().unwrap();
+        assert_eq!(updated.registered_at, registered.registered_at);
+        assert_eq!(updated.tenant.as_deref(), Some("acme"));
+        assert!(registry.update("medic", spec("medical")).unwrap().is_none());
+
+        let reopened = AgentRegistry::open(&config).unwrap();
+        assert_eq!(reopened.list().agents, [updated]);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn a_failed_save_leaves_nothing_changed() {
+        let dir = std::env::temp_dir().join(format!("void-shrine-agents-test-{}", uuid::Uuid::new_v4()));
+        let config = AgentsConfig { path: Some(dir.join("missing").join("agents.json")), ..AgentsConfig::default() };
+
+        let registry = AgentRegistry::open(&config).unwrap();
+        assert!(registry.register("scout", spec("science"), None).is_err());
+        assert!(registry.get("scout").is_none());
+    }
+}
@@ -0,0 +1,1604 @@
+//! The `/api/mcp`, job, knowledge base, chaos config and report, audit, session, agent, metrics, usage, quota, concurrency, overload, model and cache REST routes, the health probes, response compression, and the
+//! recovery handler that turns rejections into JSON error bodies. Every failure a client sees has the `ErrorResponse`
+//! shape: a machine-readable `error` code, a `message`, and the `request_id`
+//! when one had been assigned.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use warp::hyper::body::{Bytes, HttpBody};
+use warp::Buf;
+use warp::http::{header, HeaderMap, Method, StatusCode};
+use warp::reject::{InvalidQuery, MethodNotAllowed, PayloadTooLarge, Reject, UnsupportedMediaType};
+use warp::filters::path::FullPath;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Rejection, Reply};
+use crate::mcp_server::{
+    AgentListParams, AgentMetricsParams, HeartbeatRequest, AnalyticsParams, BackupRequest, BatchRequest, ChaosConfig, ChaosRequest, DashboardParams, EmbedRequest,
+    ErrorResponse, FailedRequest, FieldError, IndexDocumentRequest, IndexUrlRequest, MCPError, MCPParams, MCPRequest, MaintenanceRequest, MetricsParams,
+    MetricsPruneRequest, MoralPreviewRequest, MoralRequest, RagQuery, RagSearchRequest, ScalingRequest, ThrottleQuery, VoidShrineMCP,
+};
+use crate::agents::AgentSpec;
+use crate::audit::AuditQuery;
+use crate::replay::ReplayRequest;
+use crate::auth::Tenancy;
+use crate::chaos_stats::ChaosReportParams;
+use crate::compression::{self, CompressionConfig, DecodeError};
+use crate::concurrency::ConcurrencyUpdate;
+use crate::overload::OverloadUpdate;
+use crate::quota::QuotaConfig;
+use crate::scaling::ScalingHistoryParams;
+use crate::history::ExportParams;
+use crate::usage::UsageParams;
+use crate::config::CorsConfig;
+use crate::jobs::{JobQueue, JobSubmission};
+use crate::rag_engine::{DocumentPatch, RankingConfig};
+use crate::route_metrics::{RouteMetrics, RouteTimer};
+use crate::tokens::TokenVerifyRequest;
+use crate::trace;
+
+/// Carries a client's request id in, and the id in use back out
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Runs a request once per agent and key, as `MCPRequest::idempotency_key`
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+impl Reject for FailedRequest {}
+
+/// Rejects with `error`, to be rendered by `recover`
+pub fn reject(error: impl Into<FailedRequest>) -> Rejection {
+    warp::reject::custom(error.into())
+}
+
+/// Sets the `X-Request-Id` response header
+fn with_request_id(mut response: warp::reply::Response, request_id: &str) -> warp::reply::Response {
+    if let Ok(value) = header::HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// A body that isn't the JSON its route takes
+#[derive(Debug)]
+struct InvalidJson(serde_json::Error);
+
+impl Reject for InvalidJson {}
+
+/// A body whose Content-Type isn't JSON
+#[derive(Debug)]
+struct NotJson;
+
+impl Reject for NotJson {}
+
+/// A body in a Content-Encoding its route doesn't take
+#[derive(Debug)]
+struct UnsupportedEncoding(String);
+
+impl Reject for UnsupportedEncoding {}
+
+/// The request body, refused with 413 once it is known to be over `limit`
+/// bytes: at once when its Content-Length says so, else as soon as that much
+/// has streamed in, so no more than `limit` bytes are ever buffered
+pub fn body_bytes(limit: u64) -> impl Filter<Extract = (Bytes,), Error = Rejection> + Clone {
+    warp::header::optional::<u64>("content-length")
+        .and_then(move |length: Option<u64>| async move {
+            match length {
+                Some(length) if length > limit => Err(reject(MCPError::PayloadTooLarge { limit_bytes: limit })),
+                _ => Ok(length),
+            }
+        })
+        .and(warp::body::stream())
+        .and_then(move |length: Option<u64>, chunks| read_body(chunks, length, limit))
+}
+
+async fn read_body<B: Buf>(
+    chunks: impl futures::Stream<Item = Result<B, warp::Error>>,
+    length: Option<u64>,
+    limit: u64,
+) -> Result<Bytes, Rejection> {
+    let mut chunks = std::pin::pin!(chunks);
+    let mut body = Vec::with_capacity(length.unwrap_or(0) as usize);
+    while let Some(chunk) = chunks.next().await {
+        let mut chunk = chunk.map_err(|e| reject(MCPError::InvalidParams(format!("reading the request body: {}", e))))?;
+        if (body.len() + chunk.remaining()) as u64 > limit {
+            return Err(reject(MCPError::PayloadTooLarge { limit_bytes: limit }));
+        }
+        while chunk.has_remaining() {
+            let part = chunk.chunk();
+            let read = part.len();
+            body.extend_from_slice(part);
+            chunk.advance(read);
+        }
+    }
+    Ok(Bytes::from(body))
+}
+
+/// A JSON body of at most `limit` bytes, read as `body_bytes` does. A
+/// Content-Type, if sent, must be JSON.
+pub fn json_body<T: DeserializeOwned + Send>(limit: u64) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    json_content_type()
+        .and(body_bytes(limit))
+        .and_then(|body: Bytes| async move { deserialize_body(&body) })
+}
+
+/// `json_body`, but the body may also be sent with `Content-Encoding: gzip`,
+/// in which case `limit` holds for it decompressed as well
+pub fn encoded_json_body<T: DeserializeOwned + Send>(limit: u64) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    json_content_type()
+        .and(warp::header::optional::<String>("content-encoding"))
+        .and(body_bytes(limit))
+        .and_then(move |encoding: Option<String>, body: Bytes| async move {
+            match encoding.as_deref().map(|value| value.trim().to_ascii_lowercase()).as_deref() {
+                None | Some("identity") => deserialize_body(&body),
+                Some("gzip") | Some("x-gzip") => match compression::gunzip(&body, limit) {
+                    Ok(decoded) => deserialize_body(&decoded),
+                    Err(DecodeError::TooLarge) => Err(reject(MCPError::PayloadTooLarge { limit_bytes: limit })),
+                    Err(DecodeError::Corrupt(e)) => Err(reject(MCPError::InvalidParams(format!("request body isn't valid gzip: {}", e)))),
+                },
+                Some(other) => Err(warp::reject::custom(UnsupportedEncoding(other.to_string()))),
+            }
+        })
+}
+
+/// `json_body`, but an empty body is `T::default()`
+pub fn optional_json_body<T: DeserializeOwned + Default + Send>(limit: u64) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    json_content_type().and(body_bytes(limit)).and_then(|body: Bytes| async move {
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(T::default());
+        }
+        deserialize_body(&body)
+    })
+}
+
+/// Passes requests whose Content-Type, if sent, is JSON
+fn json_content_type() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("content-type")
+        .and_then(|content_type: Option<String>| async move {
+            let essence = content_type.as_deref().map(|value| value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase());
+            match essence.as_deref() {
+                None | Some("application/json") => Ok(()),
+                Some(_) => Err(warp::reject::custom(NotJson)),
+            }
+        })
+        .untuple_one()
+}
+
+/// `body` as a `T`. Data of the wrong shape is refused as invalid params
+/// naming the field, what was expected there and the value found; JSON that
+/// doesn't parse, or isn't the right shape at the top, as it is.
+fn deserialize_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, Rejection> {
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        let path = e.path().clone();
+        let error = e.into_inner();
+        match field_error(body, &path, &error) {
+            Some(field) => reject(MCPError::InvalidFields(vec![field])),
+            None => warp::reject::custom(InvalidJson(error)),
+        }
+    })?;
+    deserializer.end().map_err(|e| warp::reject::custom(InvalidJson(e)))?;
+    Ok(value)
+}
+
+/// The field a data error in `body` arose at, e.g. `params.temperature`
+fn field_error(body: &[u8], path: &serde_path_to_error::Path, error: &serde_json::Error) -> Option<FieldError> {
+    if !error.is_data() {
+        return None;
+    }
+    let message = error.to_string();
+    let message = message.strip_suffix(&format!(" at line {} column {}", error.line(), error.column())).unwrap_or(&message);
+    let mut segments: Vec<String> = path.iter().map(ToString::to_string).collect();
+    if let Some(missing) = message.strip_prefix("missing field `").and_then(|rest| rest.strip_suffix('`')) {
+        segments.push(missing.to_string());
+        return Some(FieldError::new(&field_name(&segments), "present", serde_json::Value::Null));
+    }
+    if segments.is_empty() {
+        return None;
+    }
+    let expected = message.rsplit_once(", expected ").map_or(message, |(_, expected)| expected);
+    let found = serde_json::from_slice(body).ok().and_then(|body| value_at(body, path)).unwrap_or_default();
+    Some(FieldError::new(&field_name(&segments), expected, found))
+}
+
+/// `params.doc_ids[2]` from the segments `params`, `doc_ids` and `[2]`
+fn field_name(segments: &[String]) -> String {
+    segments.iter().fold(String::new(), |name, segment| {
+        if name.is_empty() || segment.starts_with('[') {
+            name + segment
+        } else {
+            name + "." + segment
+        }
+    })
+}
+
+fn value_at(mut value: serde_json::Value, path: &serde_path_to_error::Path) -> Option<serde_json::Value> {
+    use serde_path_to_error::Segment;
+    for segment in path.iter() {
+        value = match segment {
+            Segment::Seq { index } => value.get_mut(*index)?.take(),
+            Segment::Map { key } => value.get_mut(key)?.take(),
+            Segment::Enum { .. } | Segment::Unknown => return None,
+        };
+    }
+    Some(value)
+}
+
+/// POST /api/mcp: one request, one response. The request id comes from the
+/// body's `request_id`, else the `X-Request-Id` header, and is echoed in that
+/// header. An `Idempotency-Key` header stands in for the body's
+/// `idempotency_key`. A `traceparent` header continues the caller's trace.
+pub fn mcp_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.inference_bytes;
+    warp::path("api")
+        .and(warp::path("mcp"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::header::optional::<String>(REQUEST_ID_HEADER))
+        .and(warp::header::optional::<String>(IDEMPOTENCY_KEY_HEADER))
+        .and(warp::header::headers_cloned())
+        .and(json_body(limit))
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|header_id: Option<String>, header_key: Option<String>, headers: HeaderMap, mut request: MCPRequest, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            request.request_id = request.request_id.or(header_id);
+            request.idempotency_key = request.idempotency_key.or(header_key);
+            service.admit_agent(&tenancy, &request.params.agent_id).map_err(reject)?;
+            match trace::continue_remote(&headers, service.handle_mcp_request(request)).await {
+                Ok(response) => {
+                    let request_id = response.metadata.request_id.clone();
+                    Ok(with_request_id(warp::reply::json(&response).into_response(), &request_id))
+                }
+                Err(failure) => {
+                    tracing::warn!("MCP request failed: {}", failure);
+                    Err(reject(failure))
+                }
+            }
+        })
+}
+
+/// POST /api/mcp/stream: the inference method as server-sent events. Invalid
+/// and over-limit requests are refused before the stream starts. Takes and
+/// echoes `X-Request-Id`, and follows `traceparent`, like `mcp_route`.
+pub fn stream_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.inference_bytes;
+    warp::path("api")
+        .and(warp::path("mcp"))
+        .and(warp::path("stream"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::header::optional::<String>(REQUEST_ID_HEADER))
+        .and(warp::header::headers_cloned())
+        .and(json_body(limit))
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|request_id: Option<String>, headers: HeaderMap, params: MCPParams, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            service.admit_agent(&tenancy, &params.agent_id).map_err(reject)?;
+            // Dropping the stream when the client disconnects cancels the inference
+            let events = trace::continue_remote(&headers, async { service.stream_llm_inference(params, request_id) })
+                .await
+                .map_err(reject)?;
+            let request_id = events.request_id.clone();
+            let events = events.map(|event| warp::sse::Event::default().event(event.name()).json_data(&event));
+            let response = warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response();
+            Ok::<_, Rejection>(with_request_id(response, &request_id))
+        })
+}
+
+/// POST /api/mcp/batch: one method over a list of params, answered with every
+/// item's response or error in input order. Only an empty, oversized or
+/// unsupported batch fails as a whole.
+pub fn batch_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.inference_bytes;
+    warp::path("api")
+        .and(warp::path("mcp"))
+        .and(warp::path("batch"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|request: BatchRequest, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            for params in &request.items {
+                service.admit_agent(&tenancy, &params.agent_id).map_err(reject)?;
+            }
+            match service.handle_batch(request).await {
+                Ok(response) => Ok(warp::reply::json(&response)),
+                Err(e) => Err(reject(e)),
+            }
+        })
+}
+
+/// POST /api/mcp/cancel/{request_id} cancels a running request, streamed or
+/// not; it then fails with `cancelled`. An id that finished already gets a
+/// 409, one never seen a 404.
+pub fn cancel_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("mcp"))
+        .and(warp::path("cancel"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|request_id: String, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_cancel(&request_id) {
+                Ok(()) => Ok(warp::reply::json(&serde_json::json!({ "request_id": request_id, "cancelled": true }))),
+                Err(e) => Err(reject(FailedRequest { request_id: Some(request_id), error: e })),
+            }
+        })
+}
+
+/// The background job routes:
+///
+/// - POST /api/jobs queues an `MCPRequest`, answering 202 with its job id;
+///   invalid requests get a 400 and a full queue a 429. A `callback_url`
+///   alongside the request gets the result POSTed to it once finished.
+/// - GET /api/jobs/{id} shows the job, with its response or error once finished
+/// - DELETE /api/jobs/{id} cancels a queued or running job
+pub fn job_routes(
+    jobs: Arc<JobQueue>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = jobs.body_limit();
+    let tenancy = jobs.auth().tenancy_filter();
+    let jobs = warp::any().map(move || Arc::clone(&jobs));
+    let base = warp::path("api").and(warp::path("jobs"));
+
+    let submit = base
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(job_submission(limit))
+        .and(tenancy)
+        .and(jobs.clone())
+        .and_then(|submission: JobSubmission, tenancy: Tenancy, jobs: Arc<JobQueue>| async move {
+            let job = jobs.submit(&tenancy, submission).map_err(reject)?;
+            Ok::<_, Rejection>(warp::reply::with_status(warp::reply::json(&job), StatusCode::ACCEPTED))
+        });
+    let get = base
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(jobs.clone())
+        .and_then(|job_id: String, jobs: Arc<JobQueue>| async move {
+            Ok::<_, Rejection>(warp::reply::json(&jobs.get(&job_id).map_err(reject)?))
+        });
+    let cancel = base
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(jobs)
+        .and_then(|job_id: String, jobs: Arc<JobQueue>| async move {
+            Ok::<_, Rejection>(warp::reply::json(&jobs.cancel(&job_id).map_err(reject)?))
+        });
+    submit.or(get).or(cancel)
+}
+
+/// The job options sent alongside the request's own fields
+#[derive(Deserialize)]
+struct JobOptions {
+    #[serde(default)]
+    callback_url: Option<String>,
+}
+
+/// An `MCPRequest` body, with the `JobOptions` next to its fields
+fn job_submission(limit: u64) -> impl Filter<Extract = (JobSubmission,), Error = Rejection> + Clone {
+    json_content_type().and(body_bytes(limit)).and_then(|body: Bytes| async move {
+        let request = deserialize_body(&body)?;
+        let JobOptions { callback_url } = deserialize_body(&body)?;
+        Ok::<_, Rejection>(JobSubmission { request, callback_url })
+    })
+}
+
+/// `?engine=`, naming the knowledge base a route works on
+#[derive(Deserialize)]
+struct EngineParam {
+    #[serde(default)]
+    engine: Option<String>,
+}
+
+/// The knowledge base `?engine=` names; the default one when absent
+fn engine_param() -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Clone {
+    warp::query::<EngineParam>().map(|param: EngineParam| param.engine)
+}
+
+/// The knowledge base document routes, all answering 503 `rag_unavailable`
+/// until the engine is initialized:
+///
+/// - POST /api/rag/documents indexes a document, generating its id if absent
+/// - GET and DELETE /api/rag/documents/{id}
+/// - GET /api/rag/stats
+/// - GET /api/rag/engines lists the knowledge bases with their stats
+/// - POST /api/rag/query searches with metadata filters and tags
+/// - GET /api/rag/query?q=... makes the retrieval an inference with prompt
+///   `q` would; see `RagQuery`. It carries an ETag and revalidates.
+///
+/// Each works on the engine `?engine=` names, the default one when absent;
+/// an unknown name is a 400 listing the known ones. A tenant's keys index
+/// into, and find, only their tenant's documents.
+pub fn document_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limits = service.body_limits;
+    let tenancy = service.auth.tenancy_filter();
+    let service = warp::any().map(move || Arc::clone(&service));
+    let documents = warp::path("api").and(warp::path("rag")).and(warp::path("documents"));
+
+    let index = documents
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(encoded_json_body(limits.documents_bytes))
+        .and(engine_param())
+        .and(tenancy.clone())
+        .and(service.clone())
+        .and_then(|request: IndexDocumentRequest, engine: Option<String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_index_document(&tenancy, engine.as_deref(), request).await {
+                Ok(response) => Ok(warp::reply::json(&response)),
+                Err(e) => {
+                    tracing::error!("Document indexing failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let get = documents
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(engine_param())
+        .and(tenancy.clone())
+        .and(service.clone())
+        .and_then(|document_id: String, engine: Option<String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            service.handle_get_document(&tenancy, engine.as_deref(), &document_id).await.map(|document| warp::reply::json(&document)).map_err(reject)
+        });
+    let delete = documents
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(engine_param())
+        .and(tenancy.clone())
+        .and(service.clone())
+        .and_then(|document_id: String, engine: Option<String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_delete_document(&tenancy, engine.as_deref(), &document_id).await {
+                Ok(response) => Ok(warp::reply::json(&response)),
+                Err(e) => {
+                    tracing::warn!("Deleting document {} failed: {}", document_id, e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let stats = warp::path("api")
+        .and(warp::path("rag"))
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(engine_param())
+        .and(service.clone())
+        .and_then(|engine: Option<String>, service: Arc<VoidShrineMCP>| async move {
+            service.handle_rag_stats(engine.as_deref()).await.map(|stats| warp::reply::json(&stats)).map_err(reject)
+        });
+    let engines = warp::path("api")
+        .and(warp::path("rag"))
+        .and(warp::path("engines"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(service.clone())
+        .then(|service: Arc<VoidShrineMCP>| async move { warp::reply::json(&service.handle_rag_engines().await) });
+    let query = warp::path("api")
+        .and(warp::path("rag"))
+        .and(warp::path("query"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limits.admin_bytes))
+        .and(engine_param())
+        .and(tenancy.clone())
+        .and(service.clone())
+        .and_then(|request: RagSearchRequest, engine: Option<String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            service.handle_rag_search(&tenancy, engine.as_deref(), request).await.map(|response| warp::reply::json(&response)).map_err(reject)
+        });
+    let query_get = warp::path("api")
+        .and(warp::path("rag"))
+        .and(warp::path("query"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(tenancy)
+        .and(service)
+        .and_then(|query: HashMap<String, String>, if_none_match: Option<String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            let query = RagQuery::parse(query).map_err(reject)?;
+            if let Some(agent_id) = &query.agent_id {
+                service.admit_agent(&tenancy, agent_id).map_err(reject)?;
+            }
+            let response = service.handle_rag_query_get(&tenancy, query).await.map_err(reject)?;
+            // Through a Value, whose sorted keys keep the metadata maps, and so the ETag, stable
+            let body = serde_json::to_value(&response).and_then(|body| serde_json::to_vec(&body)).expect("search results serialize");
+            // Results change with the corpus, so caches revalidate; per key, as tenants see different documents
+            Ok::<_, Rejection>(etagged_json(body, if_none_match, "private, no-cache"))
+        });
+
+    index.or(get).or(delete).or(stats).or(engines).or(query).or(query_get)
+}
+
+/// GET /api/audit: recorded requests, newest first, filtered by the
+/// `agent_id`, `since` and `until` (RFC 3339) and `limit` query parameters.
+/// 501 when no audit sink is configured.
+pub fn audit_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("audit"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<AuditQuery>())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|query: AuditQuery, service: Arc<VoidShrineMCP>| async move {
+            service.handle_audit_query(query).await.map(|response| warp::reply::json(&response)).map_err(reject)
+        })
+}
+
+/// POST /api/audit/replay: recorded `llm_inference` requests, named by id or
+/// matched by the audit filters, sent through the pipeline as it is now, live,
+/// against the mock backend or as dry runs, each compared with its replay.
+/// 501 when no audit sink is configured.
+pub fn audit_replay_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    warp::path("api")
+        .and(warp::path("audit"))
+        .and(warp::path("replay"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|request: ReplayRequest, service: Arc<VoidShrineMCP>| async move {
+            service.handle_audit_replay(request).await.map(|response| warp::reply::json(&response)).map_err(reject)
+        })
+}
+
+/// GET /api/sessions lists open conversation sessions, most recently active
+/// first; DELETE /api/sessions/{id} ends one, 404 when there is none
+pub fn session_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let service = warp::any().map(move || Arc::clone(&service));
+    let sessions = warp::path("api").and(warp::path("sessions"));
+
+    let list = sessions
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(service.clone())
+        .map(|service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_list_sessions()));
+    let delete = sessions
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(service)
+        .and_then(|session_id: String, service: Arc<VoidShrineMCP>| async move {
+            service.handle_delete_session(&session_id).map_err(reject)?;
+            Ok::<_, Rejection>(warp::reply::json(&serde_json::json!({ "session_id": session_id, "deleted": true })))
+        });
+    list.or(delete)
+}
+
+/// The agent registration routes:
+///
+/// - POST /api/agents registers an agent, 409 when its id is taken
+/// - GET /api/agents lists registered and seen agents, `?sort=load|recency`
+///   and `?include_stale=false` optional; GET /api/agents/{id} shows one in
+///   full, 404 when it is neither
+/// - GET /api/agents/{id}/metrics shows its lifetime totals and, for
+///   `?window=1m|5m|15m|1h` (5m by default), its counts and latency
+///   percentiles; 404 like GET /api/agents/{id}
+/// - DELETE /api/agents/{id}/metrics clears its metrics, 404 when it has none
+/// - PUT /api/agents/{id} replaces a registration
+/// - DELETE /api/agents/{id} deregisters, keeping the agent's metrics
+/// - POST /api/agents/{id}/heartbeat records that the agent is alive, with
+///   an optional `queue_depth`; the body may be left out
+///
+/// A tenant's keys see only their tenant's agents; another tenant's agent
+/// gets the 404 of one that doesn't exist.
+pub fn agent_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    let tenancy = service.auth.tenancy_filter();
+    let service = warp::any().map(move || Arc::clone(&service)).and(tenancy);
+    let agents = warp::path("api").and(warp::path("agents"));
+
+    let register = agents
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(service.clone())
+        .and_then(|spec: AgentSpec, service: Arc<VoidShrineMCP>, tenancy: Tenancy| async move {
+            let registration = service.handle_register_agent(&tenancy, spec).map_err(reject)?;
+            Ok::<_, Rejection>(warp::reply::with_status(warp::reply::json(&registration), StatusCode::CREATED))
+        });
+    let list = agents
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<AgentListParams>())
+        .and(service.clone())
+        .map(|params: AgentListParams, service: Arc<VoidShrineMCP>, tenancy: Tenancy| {
+            warp::reply::json(&service.handle_list_agents(&tenancy, &params))
+        });
+    let get = agents
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(service.clone())
+        .and_then(|agent_id: String, service: Arc<VoidShrineMCP>, tenancy: Tenancy| async move {
+            service.handle_get_agent(&tenancy, &agent_id).map(|detail| warp::reply::json(&detail)).map_err(reject)
+        });
+    let metrics = agents
+        .and(warp::path::param::<String>())
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<AgentMetricsParams>())
+        .and(service.clone())
+        .and_then(|agent_id: String, params: AgentMetricsParams, service: Arc<VoidShrineMCP>, tenancy: Tenancy| async move {
+            service.handle_agent_metrics(&tenancy, &agent_id, &params).map(|detail| warp::reply::json(&detail)).map_err(reject)
+        });
+    let reset_metrics = agents
+        .and(warp::path::param::<String>())
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(service.clone())
+        .and_then(|agent_id: String, service: Arc<VoidShrineMCP>, tenancy: Tenancy| async move {
+            service.handle_reset_agent_metrics(&tenancy, &agent_id).map(|reset| warp::reply::json(&reset)).map_err(reject)
+        });
+    let update = agents
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(json_body(limit))
+        .and(service.clone())
+        .and_then(|agent_id: String, spec: AgentSpec, service: Arc<VoidShrineMCP>, tenancy: Tenancy| async move {
+            service.handle_update_agent(&tenancy, &agent_id, spec).map(|registration| warp::reply::json(&registration)).map_err(reject)
+        });
+    let deregister = agents
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(service.clone())
+        .and_then(|agent_id: String, service: Arc<VoidShrineMCP>, tenancy: Tenancy| async move {
+            service.handle_deregister_agent(&tenancy, &agent_id).map_err(reject)?;
+            Ok::<_, Rejection>(warp::reply::json(&serde_json::json!({ "agent_id": agent_id, "deregistered": true })))
+        });
+    let heartbeat = agents
+        .and(warp::path::param::<String>())
+        .and(warp::path("heartbeat"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(optional_json_body(limit))
+        .and(service)
+        .and_then(|agent_id: String, request: HeartbeatRequest, service: Arc<VoidShrineMCP>, tenancy: Tenancy| async move {
+            service.handle_heartbeat(&tenancy, &agent_id, request).map(|received| warp::reply::json(&received)).map_err(reject)
+        });
+    register.or(list).or(get).or(metrics).or(reset_metrics).or(update).or(deregister).or(heartbeat)
+}
+
+/// POST /api/metrics/prune removes the metrics of agents without a request
+/// since `last_seen_before`, answering with how many and which
+pub fn metrics_prune_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    warp::path("api")
+        .and(warp::path("metrics"))
+        .and(warp::path("prune"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .map(|request: MetricsPruneRequest, tenancy: Tenancy, service: Arc<VoidShrineMCP>| {
+            warp::reply::json(&service.handle_prune_metrics(&tenancy, &request))
+        })
+}
+
+/// GET /api/specialties lists the specialties; POST /api/specialties/reload
+/// reads `specialties.path` again, 501 without one
+pub fn specialty_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let service = warp::any().map(move || Arc::clone(&service));
+    let specialties = warp::path("api").and(warp::path("specialties"));
+
+    let list = specialties
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(service.clone())
+        .map(|service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_list_specialties()));
+    let reload = specialties
+        .and(warp::path("reload"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(service)
+        .and_then(|service: Arc<VoidShrineMCP>| async move {
+            service.handle_reload_specialties().map(|specialties| warp::reply::json(&specialties)).map_err(reject)
+        });
+    list.or(reload)
+}
+
+/// POST /api/embed: vectors for a batch of texts from the knowledge base's
+/// embedding provider, with their token counts. Too many or too long texts
+/// get a 400, a server without a provider a 503 `embeddings_unavailable`.
+/// `?engine=` picks the knowledge base whose provider answers. An `agent_id`
+/// must be one the key may act for, and is charged the usage.
+pub fn embed_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.inference_bytes;
+    warp::path("api")
+        .and(warp::path("embed"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(engine_param())
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|request: EmbedRequest, engine: Option<String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            if let Some(agent_id) = &request.agent_id {
+                service.admit_agent(&tenancy, agent_id).map_err(reject)?;
+            }
+            service.handle_embed(engine.as_deref(), request).await.map(|response| warp::reply::json(&response)).map_err(reject)
+        })
+}
+
+/// POST /api/tokens/verify checks a response's `void_shrine_token`,
+/// answering 200 with `valid` and the reason it isn't
+pub fn token_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    warp::path("api")
+        .and(warp::path("tokens"))
+        .and(warp::path("verify"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .map(|request: TokenVerifyRequest, service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_verify_token(&request)))
+}
+
+/// GET /api/concurrency shows the global concurrency limit and its use;
+/// PUT changes `max_in_flight` or `max_wait_ms` without a restart
+pub fn concurrency_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    let service = warp::any().map(move || Arc::clone(&service));
+    let concurrency = warp::path("api").and(warp::path("concurrency")).and(warp::path::end());
+
+    let get = concurrency
+        .and(warp::get())
+        .and(service.clone())
+        .map(|service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_concurrency()));
+    let put = concurrency
+        .and(warp::put())
+        .and(json_body(limit))
+        .and(service)
+        .and_then(|update: ConcurrencyUpdate, service: Arc<VoidShrineMCP>| async move {
+            service.handle_update_concurrency(update).map(|status| warp::reply::json(&status)).map_err(reject)
+        });
+    get.or(put)
+}
+
+/// GET /api/overload shows whether new inference requests are being shed
+/// for overload, the signals it is judged by and its thresholds; PUT changes
+/// any of the thresholds without a restart
+pub fn overload_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    let service = warp::any().map(move || Arc::clone(&service));
+    let overload = warp::path("api").and(warp::path("overload")).and(warp::path::end());
+
+    let get = overload
+        .and(warp::get())
+        .and(service.clone())
+        .map(|service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_overload()));
+    let put = overload
+        .and(warp::put())
+        .and(json_body(limit))
+        .and(service)
+        .and_then(|update: OverloadUpdate, service: Arc<VoidShrineMCP>| async move {
+            service.handle_update_overload(update).map(|status| warp::reply::json(&status)).map_err(reject)
+        });
+    get.or(put)
+}
+
+/// GET /api/quotas shows each quota with what its current window has used
+/// and when it resets; PUT replaces the quotas without a restart
+pub fn quota_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    let service = warp::any().map(move || Arc::clone(&service));
+    let quotas = warp::path("api").and(warp::path("quotas")).and(warp::path::end());
+
+    let get = quotas
+        .and(warp::get())
+        .and(service.clone())
+        .map(|service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_quotas()));
+    let put = quotas
+        .and(warp::put())
+        .and(json_body(limit))
+        .and(service)
+        .and_then(|config: QuotaConfig, service: Arc<VoidShrineMCP>| async move {
+            service.handle_update_quotas(config).map(|status| warp::reply::json(&status)).map_err(reject)
+        });
+    get.or(put)
+}
+
+/// GET /api/models lists routable models with their context length, whether
+/// they stream, and the health of their backend. The ETag is a digest of the
+/// body, so a client sending it back in If-None-Match gets a bodiless 304
+/// until the listing changes.
+pub fn models_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("models"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .map(|if_none_match: Option<String>, service: Arc<VoidShrineMCP>| {
+            let body = serde_json::to_vec(&service.handle_list_models()).expect("the model listing serializes");
+            // Health changes without notice, so caches must revalidate every time
+            etagged_json(body, if_none_match, "no-cache")
+        })
+}
+
+/// `body` as JSON with its ETag and `cache_control`, or a bodiless 304 when
+/// `if_none_match` names the ETag
+fn etagged_json(body: Vec<u8>, if_none_match: Option<String>, cache_control: &'static str) -> warp::reply::Response {
+    let etag = etag(&body);
+    let mut response = if if_none_match.is_some_and(|tags| etag_matches(&tags, &etag)) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let mut response = warp::reply::Response::new(body.into());
+        response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+        response
+    };
+    let headers = response.headers_mut();
+    if let Ok(value) = header::HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static(cache_control));
+    response
+}
+
+/// A strong validator for `body`: the first half of its SHA-256 digest
+fn etag(body: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, body);
+    format!("\"{}\"", URL_SAFE_NO_PAD.encode(&digest.as_ref()[..16]))
+}
+
+/// Whether an If-None-Match value names `etag`, comparing weakly as GET does
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// DELETE /api/cache empties the response cache
+pub fn cache_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("cache"))
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .map(|service: Arc<VoidShrineMCP>| warp::reply::json(&serde_json::json!({ "cleared": service.handle_clear_cache() })))
+}
+
+/// POST /api/admin/reload reads the config file again and swaps in what can
+/// change while running, answering with what was applied and skipped; a
+/// file refused for its problems gets 422 with them, a server started
+/// without a file 501
+pub fn config_reload_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("admin"))
+        .and(warp::path("reload"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|service: Arc<VoidShrineMCP>| async move {
+            let report = service.handle_reload_config().await.map_err(reject)?;
+            let status = if report.errors.is_empty() { StatusCode::OK } else { StatusCode::UNPROCESSABLE_ENTITY };
+            Ok::<_, Rejection>(warp::reply::with_status(warp::reply::json(&report), status))
+        })
+}
+
+/// GET /api/chaos/config shows the chaos config, including its targeting
+/// rules; PUT replaces it, refusing invalid configs with every problem found
+pub fn chaos_config_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    let service = warp::any().map(move || Arc::clone(&service));
+    let config = warp::path("api").and(warp::path("chaos")).and(warp::path("config")).and(warp::path::end());
+
+    let get = config
+        .and(warp::get())
+        .and(service.clone())
+        .and_then(|service: Arc<VoidShrineMCP>| async move {
+            Ok::<_, Rejection>(warp::reply::json(&service.handle_chaos_config().await))
+        });
+    let put = config
+        .and(warp::put())
+        .and(json_body(limit))
+        .and(service)
+        .and_then(|config: ChaosConfig, service: Arc<VoidShrineMCP>| async move {
+            service.handle_update_chaos_config(config).await.map(|config| warp::reply::json(&config)).map_err(reject)
+        });
+    get.or(put)
+}
+
+/// POST /api/chaos: whether chaos would apply to a request, and what it would do
+pub fn chaos_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    warp::path("api")
+        .and(warp::path("chaos"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .then(|request: ChaosRequest, service: Arc<VoidShrineMCP>| async move { warp::reply::json(&service.handle_chaos(request).await) })
+}
+
+/// GET /api/chaos/report: chaos decisions applied and skipped since `since`,
+/// by type and per agent, with the realized against the expected rate
+pub fn chaos_report_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("chaos"))
+        .and(warp::path("report"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<ChaosReportParams>())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .then(|params: ChaosReportParams, service: Arc<VoidShrineMCP>| async move { warp::reply::json(&service.handle_chaos_report(&params).await) })
+}
+
+/// GET /api/throttle/{agent_id}: what the agent's next request would meet;
+/// `?verbose=false` for only whether it should back off
+pub fn throttle_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("throttle"))
+        .and(warp::path::param::<String>())
+        .and(warp::get())
+        .and(warp::query::<ThrottleQuery>())
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|agent_id: String, query: ThrottleQuery, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            let response = match query.verbose {
+                true => serde_json::to_value(service.handle_throttle(&tenancy, agent_id).await.map_err(reject)?),
+                false => serde_json::to_value(service.handle_throttle_check(&tenancy, agent_id).await.map_err(reject)?),
+            };
+            Ok::<_, Rejection>(warp::reply::json(&response.unwrap_or_default()))
+        })
+}
+
+/// GET /api/metrics: per-agent metrics and server counters, optionally for
+/// one agent or recent activity; GET /metrics: the Prometheus scrape target
+pub fn metrics_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let tenancy = service.auth.tenancy_filter();
+    let service = warp::any().map(move || Arc::clone(&service));
+    let metrics = warp::path("api")
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<MetricsParams>())
+        .and(tenancy)
+        .and(service.clone())
+        .map(|params: MetricsParams, tenancy: Tenancy, service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_metrics(&tenancy, &params)));
+    let prometheus = warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(service)
+        .then(|service: Arc<VoidShrineMCP>| async move {
+            warp::reply::with_header(service.handle_prometheus().await, "content-type", prometheus::TEXT_FORMAT)
+        });
+    metrics.or(prometheus)
+}
+
+/// GET /api/usage: prompt and completion tokens per period of the agents the
+/// caller sees, filtered by `agent_id`, `model`, `since` and `until`, hourly
+/// or daily by `granularity`, with backend, cached and dry-run usage apart and
+/// estimated costs when a price table is configured
+pub fn usage_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("usage"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<UsageParams>())
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|params: UsageParams, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            service.handle_usage(&tenancy, &params).map(|response| warp::reply::json(&response)).map_err(reject)
+        })
+}
+
+/// GET /api/metrics/export: hourly or daily activity per agent the caller
+/// sees, between `since` and `until`, as a CSV or JSON lines download
+/// streamed a period at a time
+pub fn metrics_export_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("metrics"))
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<ExportParams>())
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|params: ExportParams, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            let (plan, chunks) = service.handle_metrics_export(&tenancy, &params).map_err(reject)?;
+            let mut response = warp::reply::Response::new(warp::hyper::Body::wrap_stream(chunks));
+            let headers = response.headers_mut();
+            headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static(plan.format.content_type()));
+            let disposition = format!("attachment; filename=\"{}\"", plan.filename());
+            headers.insert(header::CONTENT_DISPOSITION, header::HeaderValue::from_str(&disposition).expect("filenames are ASCII"));
+            Ok::<_, Rejection>(response)
+        })
+}
+
+/// GET /api/dashboard: uptime, recent request and error rates, the busiest
+/// agents with their throttle state, knowledge base stats, chaos, circuit
+/// breakers and the latest scaling decisions, for the explorer UI to poll
+pub fn dashboard_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("dashboard"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<DashboardParams>())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .then(|params: DashboardParams, service: Arc<VoidShrineMCP>| async move { warp::reply::json(&service.handle_dashboard(&params).await) })
+}
+
+/// GET /api/version: the crate version, the commit and toolchain it was built
+/// from, its cargo features, and the config file the server started with
+pub fn version_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path("version"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || warp::reply::json(&service.handle_version()))
+}
+
+/// Names the build in the `Server` header of every response, errors
+/// included when applied after `recover`
+pub fn server_header() -> warp::filters::reply::WithHeader {
+    warp::reply::with::header(header::SERVER, crate::build_info::version_string())
+}
+
+/// Times every request `filter` answers into `metrics`, by method and route
+/// pattern; see `route_metrics`. Goes around the recovered routes, so
+/// rejections are counted with the status they end up with:
+/// `.with(warp::wrap_fn(|filter| api::observe_routes(Arc::clone(&metrics), filter)))`.
+pub fn observe_routes<F, R>(metrics: Arc<RouteMetrics>, filter: F) -> impl Filter<Extract = (warp::reply::Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (R,), Error = Infallible> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::method()
+        .and(warp::path::full())
+        .map(move |method: Method, path: FullPath| metrics.start(method.as_str(), path.as_str()))
+        .and(filter)
+        .map(|timer: RouteTimer, reply: R| {
+            let response = reply.into_response();
+            timer.finish(response.status());
+            response
+        })
+}
+
+/// Compresses what `filter` answers, as `compression` describes, for clients
+/// whose Accept-Encoding allows. Goes outermost, around the recovered
+/// routes, so error bodies are compressed too:
+/// `.with(warp::wrap_fn(|filter| api::compress(config.clone(), filter)))`.
+pub fn compress<F, R>(config: CompressionConfig, filter: F) -> impl Filter<Extract = (warp::reply::Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (R,), Error = Infallible> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::header::headers_cloned().and(filter).then(move |headers: HeaderMap, reply: R| {
+        let config = config.clone();
+        let response = reply.into_response();
+        async move { compress_response(&config, &headers, response).await }
+    })
+}
+
+async fn compress_response(config: &CompressionConfig, request: &HeaderMap, mut response: warp::reply::Response) -> warp::reply::Response {
+    let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    let status = response.status();
+    if !config.enabled
+        || !compression::compressible(content_type)
+        || response.headers().contains_key(header::CONTENT_ENCODING)
+        || status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+    {
+        return response;
+    }
+    // The body depends on Accept-Encoding whether or not this one is compressed
+    response.headers_mut().append(header::VARY, header::HeaderValue::from_static("accept-encoding"));
+    // Streamed bodies, of unknown length, go out as they come
+    let Some(length) = response.body().size_hint().exact() else {
+        return response;
+    };
+    let encoding = request.get(header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok()).and_then(compression::negotiate);
+    let Some(encoding) = encoding.filter(|_| length >= config.min_bytes as u64) else {
+        return response;
+    };
+    let (mut parts, body) = response.into_parts();
+    let body = match warp::hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!("Couldn't read a response body to compress it: {}", e);
+            return warp::reply::Response::from_parts(parts, warp::hyper::Body::empty());
+        }
+    };
+    match compression::encode(config, encoding, &body) {
+        Ok(compressed) if compressed.len() < body.len() => {
+            parts.headers.insert(header::CONTENT_ENCODING, header::HeaderValue::from_static(encoding.as_str()));
+            parts.headers.remove(header::CONTENT_LENGTH);
+            warp::reply::Response::from_parts(parts, compressed.into())
+        }
+        Ok(_) => warp::reply::Response::from_parts(parts, body.into()),
+        Err(e) => {
+            tracing::warn!("Couldn't {}-compress a response: {}", encoding.as_str(), e);
+            warp::reply::Response::from_parts(parts, body.into())
+        }
+    }
+}
+
+/// POST /api/scaling: scaling advice for an agent from a finished request;
+/// GET /api/scaling/history: the advice given, newest first, filtered by
+/// `agent_id` and `since`, at most `limit`, with a summary
+pub fn scaling_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.admin_bytes;
+    let service = warp::any().map(move || Arc::clone(&service));
+    let advise = warp::path("api")
+        .and(warp::path("scaling"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(service.clone())
+        .then(|request: ScalingRequest, service: Arc<VoidShrineMCP>| async move { warp::reply::json(&service.handle_scaling(request).await) });
+    let history = warp::path("api")
+        .and(warp::path("scaling"))
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<ScalingHistoryParams>())
+        .and(service)
+        .map(|params: ScalingHistoryParams, service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_scaling_history(&params)));
+    advise.or(history)
+}
+
+/// POST /api/moral-recentering: a prompt recentered on an ethical framework.
+/// POST /api/moral-recentering/preview: a batch of prompts recentered on
+/// one, each with the text every rule inserted and where, the care ethics
+/// score before and after, and a summary of how many changed and by how much.
+/// GET /api/moral-recentering/config: every text recentering can insert and
+/// where it was configured. POST /api/moral-recentering/reload reads
+/// `moral.texts_path` again, 501 without one.
+pub fn moral_route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limit = service.body_limits.inference_bytes;
+    let with_service = warp::any().map(move || Arc::clone(&service));
+    let recenter = warp::path::end()
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(with_service.clone())
+        .and_then(|request: MoralRequest, service: Arc<VoidShrineMCP>| async move {
+            service.handle_moral_recentering(request).await.map(|response| warp::reply::json(&response)).map_err(reject)
+        });
+    let preview = warp::path("preview")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limit))
+        .and(with_service.clone())
+        .and_then(|request: MoralPreviewRequest, service: Arc<VoidShrineMCP>| async move {
+            service.handle_moral_preview(request).map(|response| warp::reply::json(&response)).map_err(reject)
+        });
+    let config = warp::path("config")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_service.clone())
+        .map(|service: Arc<VoidShrineMCP>| warp::reply::json(&service.handle_moral_config()));
+    let reload = warp::path("reload")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_service)
+        .and_then(|service: Arc<VoidShrineMCP>| async move {
+            service.handle_reload_moral().map(|texts| warp::reply::json(&texts)).map_err(reject)
+        });
+    warp::path("api").and(warp::path("moral-recentering")).and(recenter.or(preview).or(config).or(reload))
+}
+
+/// The knowledge base administration routes:
+///
+/// - POST /api/rag/index-url fetches and indexes a page
+/// - DELETE /api/rag/documents deletes by metadata query parameters
+/// - PATCH /api/rag/documents/{id} changes metadata and tags without reindexing
+/// - PUT /api/rag/ranking changes recency decay and boosts at runtime
+/// - GET /api/rag/stats/by/{key} groups statistics by a metadata key
+/// - GET /api/rag/analytics reports on the opt-in query log
+/// - POST /api/rag/backup snapshots the knowledge base into the backup directory
+/// - POST /api/rag/maintenance checks integrity, repairs and vacuums, holding
+///   the engine exclusively while it runs
+/// - POST /api/rag/rebuild-fts refills the full-text index from the stored
+///   chunks in the background, answering 202 with a task
+/// - GET /api/rag/tasks/{id} reports a background task's progress
+///
+/// As the document routes, each but the last works on the engine `?engine=`
+/// names.
+pub fn rag_admin_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limits = service.body_limits;
+    let tenancy = service.auth.tenancy_filter();
+    let service = warp::any().map(move || Arc::clone(&service));
+    let rag = warp::path("api").and(warp::path("rag"));
+
+    let index_url = rag
+        .and(warp::path("index-url"))
+        .and(warp::post())
+        .and(json_body(limits.admin_bytes))
+        .and(engine_param())
+        .and(tenancy.clone())
+        .and(service.clone())
+        .and_then(|request: IndexUrlRequest, engine: Option<String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_index_url(&tenancy, engine.as_deref(), request).await {
+                Ok(response) => Ok(warp::reply::json(&response)),
+                Err(e) => {
+                    tracing::error!("URL indexing failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let delete_documents = rag
+        .and(warp::path("documents"))
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(tenancy.clone())
+        .and(service.clone())
+        .and_then(|params: HashMap<String, String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_delete_documents(&tenancy, params).await {
+                Ok(response) => Ok(warp::reply::json(&response)),
+                Err(e) => {
+                    tracing::error!("Document deletion failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let patch_document = rag
+        .and(warp::path("documents"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::patch())
+        .and(json_body(limits.documents_bytes))
+        .and(engine_param())
+        .and(tenancy)
+        .and(service.clone())
+        .and_then(|document_id: String, patch: DocumentPatch, engine: Option<String>, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_patch_document(&tenancy, engine.as_deref(), document_id.clone(), patch).await {
+                Ok(Some(document)) => Ok(warp::reply::json(&document)),
+                Ok(None) => Err(reject(MCPError::DocumentNotFound(document_id))),
+                Err(e) => {
+                    tracing::error!("Document update failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let ranking = rag
+        .and(warp::path("ranking"))
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(json_body(limits.admin_bytes))
+        .and(engine_param())
+        .and(service.clone())
+        .and_then(|config: RankingConfig, engine: Option<String>, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_set_ranking(engine.as_deref(), config).await {
+                Ok(config) => Ok(warp::reply::json(&config)),
+                Err(e) => {
+                    tracing::error!("Ranking update failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let stats_by = rag
+        .and(warp::path("stats"))
+        .and(warp::path("by"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(engine_param())
+        .and(service.clone())
+        .and_then(|metadata_key: String, engine: Option<String>, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_stats_by(engine.as_deref(), metadata_key).await {
+                Ok(groups) => Ok(warp::reply::json(&groups)),
+                Err(e) => {
+                    tracing::error!("Grouped stats failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let analytics = rag
+        .and(warp::path("analytics"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<AnalyticsParams>())
+        .and(engine_param())
+        .and(service.clone())
+        .and_then(|params: AnalyticsParams, engine: Option<String>, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_analytics(engine.as_deref(), params).await {
+                Ok(analytics) => Ok(warp::reply::json(&analytics)),
+                Err(e) => {
+                    tracing::error!("Query analytics failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let backup = rag
+        .and(warp::path("backup"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limits.admin_bytes))
+        .and(engine_param())
+        .and(service.clone())
+        .and_then(|request: BackupRequest, engine: Option<String>, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_backup(engine.as_deref(), request).await {
+                Ok(report) => Ok(warp::reply::json(&report)),
+                Err(e) => {
+                    tracing::error!("Backup failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+    let maintenance = rag
+        .and(warp::path("maintenance"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body(limits.admin_bytes))
+        .and(engine_param())
+        .and(service.clone())
+        .and_then(|request: MaintenanceRequest, engine: Option<String>, service: Arc<VoidShrineMCP>| async move {
+            match service.handle_maintenance(engine.as_deref(), request).await {
+                Ok(report) => Ok(warp::reply::json(&report)),
+                Err(e) => {
+                    tracing::error!("Maintenance failed: {}", e);
+                    Err(reject(e))
+                }
+            }
+        });
+
+    let rebuild_fts = rag
+        .and(warp::path("rebuild-fts"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(engine_param())
+        .and(service.clone())
+        .and_then(|engine: Option<String>, service: Arc<VoidShrineMCP>| async move {
+            let task = service.handle_rebuild_fts(engine.as_deref()).await.map_err(reject)?;
+            Ok::<_, Rejection>(warp::reply::with_status(warp::reply::json(&task), StatusCode::ACCEPTED))
+        });
+    let task = rag
+        .and(warp::path("tasks"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(service)
+        .and_then(|task_id: String, service: Arc<VoidShrineMCP>| async move {
+            service.handle_rag_task(&task_id).map(|task| warp::reply::json(&task)).map_err(reject)
+        });
+
+    index_url
+        .or(delete_documents)
+        .or(patch_document)
+        .or(ranking)
+        .or(stats_by)
+        .or(analytics)
+        .or(backup)
+        .or(maintenance)
+        .or(rebuild_fts)
+        .or(task)
+}
+
+/// Every HTTP route the server answers, behind `service`'s API keys, with
+/// `jobs` working the job queue. Rejections are left for the caller, so the
+/// routes can sit beside an application's own: finish with
+/// `.recover(api::recover)` for the JSON error bodies, and wrap in `cors`
+/// as the server does. Boxed, so that embedding them needs no raised
+/// recursion limit.
+pub fn routes(service: Arc<VoidShrineMCP>, jobs: Arc<JobQueue>) -> BoxedFilter<(warp::reply::Response,)> {
+    let service = || Arc::clone(&service);
+    // Boxed in groups, keeping each group's future off the stack; the stream
+    // and batch routes go first, as mcp_route also matches /api/mcp/stream and /api/mcp/batch
+    let inference = probe_routes(service())
+        .or(stream_route(service()))
+        .or(batch_route(service()))
+        .or(cancel_route(service()))
+        .or(job_routes(jobs))
+        .or(mcp_route(service()))
+        .or(crate::mcp_protocol::route(service()))
+        .or(crate::websocket::route(service()))
+        .map(Reply::into_response)
+        .boxed();
+    let administration = chaos_route(service())
+        .or(chaos_config_routes(service()))
+        .or(chaos_report_route(service()))
+        .or(config_reload_route(service()))
+        .or(audit_route(service()))
+        .or(audit_replay_route(service()))
+        .or(session_routes(service()))
+        .or(agent_routes(service()))
+        .or(specialty_routes(service()))
+        .or(token_route(service()))
+        .or(concurrency_routes(service()))
+        .or(overload_routes(service()))
+        .or(metrics_prune_route(service()))
+        .or(cache_route(service()))
+        .or(throttle_route(service()))
+        .or(models_route(service()))
+        .or(metrics_routes(service()))
+        .or(crate::live::route(service()))
+        .or(usage_route(service()))
+        .or(metrics_export_route(service()))
+        .or(quota_routes(service()))
+        .or(dashboard_route(service()))
+        .or(version_route(service()))
+        .or(scaling_route(service()))
+        .or(moral_route(service()))
+        .map(Reply::into_response)
+        .boxed();
+    let knowledge_base = document_routes(service())
+        .or(embed_route(service()))
+        .or(rag_admin_routes(service()))
+        .map(Reply::into_response)
+        .boxed();
+    service()
+        .auth
+        .filter()
+        .and(inference.or(administration).unify().or(knowledge_base).unify())
+        .boxed()
+}
+
+/// GET /health answers while the process runs; GET /ready only while it
+/// takes new requests, turning 503 as soon as shutdown starts. After a
+/// startup self-check /ready lists each component's result, and says
+/// `degraded` while serving without one that failed.
+pub fn probe_routes(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let health = warp::path("health")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::json(&serde_json::json!({ "status": "ok" })));
+    let ready = warp::path("ready")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(move || {
+            let draining = service.shutdown.is_draining();
+            let checks = service.startup_checks();
+            async move {
+                if draining {
+                    return Err(reject(MCPError::ShuttingDown));
+                }
+                let Some(checks) = checks else {
+                    return Ok(warp::reply::json(&serde_json::json!({ "status": "ready" })));
+                };
+                let status = if checks.passed() { "ready" } else { "degraded" };
+                Ok(warp::reply::json(&serde_json::json!({ "status": status, "components": checks.components })))
+            }
+        });
+    health.or(ready)
+}
+
+/// The CORS policy of `config`, to wrap every route in
+pub fn cors(config: &CorsConfig) -> warp::cors::Builder {
+    let builder = warp::cors()
+        .allow_methods(config.allowed_methods.iter().map(String::as_str))
+        .allow_headers(config.allowed_headers.iter().map(String::as_str))
+        .expose_headers(config.exposed_headers.iter().map(String::as_str))
+        .max_age(std::time::Duration::from_secs(config.max_age_secs))
+        .allow_credentials(config.allow_credentials);
+    match config.allows_any_origin() {
+        true => builder.allow_any_origin(),
+        false => builder.allow_origins(config.allowed_origins.iter().map(String::as_str)),
+    }
+}
+
+/// Renders any rejection as a JSON `ErrorResponse` with a matching status
+pub async fn recover(rejection: Rejection) -> Result<warp::reply::Response, Infallible> {
+    if let Some(failure) = rejection.find::<FailedRequest>() {
+        let status = StatusCode::from_u16(failure.error.http_status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = warp::reply::with_status(warp::reply::json(&ErrorResponse::from(failure)), status).into_response();
+        if let Some(retry_after) = failure.error.retry_after() {
+            // Whole seconds, rounded up so clients never retry early
+            let seconds = retry_after.as_millis().div_ceil(1000) as u64;
+            response.headers_mut().insert(header::RETRY_AFTER, seconds.into());
+        }
+        if let MCPError::Unauthorized(_) = failure.error {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+        }
+        if let Some(request_id) = &failure.request_id {
+            response = with_request_id(response, request_id);
+        }
+        return Ok(response);
+    }
+
+    let (status, code, message) = if let Some(e) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, "invalid_params", e.to_string())
+    } else if let Some(InvalidJson(e)) = rejection.find() {
+        (StatusCode::BAD_REQUEST, "invalid_params", format!("Request body deserialize error: {}", e))
+    } else if let Some(UnsupportedEncoding(encoding)) = rejection.find() {
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type", format!("Content-Encoding '{}' isn't supported; send gzip or identity", encoding))
+    } else if let Some(e) = rejection.find::<InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, "invalid_params", e.to_string())
+    } else if rejection.find::<MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", "method not allowed".to_string())
+    } else if rejection.find::<PayloadTooLarge>().is_some() {
+        (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", "request body too large".to_string())
+    } else if rejection.find::<UnsupportedMediaType>().is_some() || rejection.find::<NotJson>().is_some() {
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type", "expected a JSON body".to_string())
+    } else if let Some(e) = rejection.find::<warp::cors::CorsForbidden>() {
+        (StatusCode::FORBIDDEN, "cors_forbidden", e.to_string())
+    } else if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, "not_found", "no such route".to_string())
+    } else {
+        tracing::error!("Unhandled rejection: {:?}", rejection);
+        (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "internal server error".to_string())
+    };
+    let body = ErrorResponse {
+        error: code.to_string(),
+        message,
+        request_id: None,
+        fields: Vec::new(),
+        retry_after_ms: None,
+        stage: None,
+        limit_bytes: None,
+        method: None,
+        supported_methods: Vec::new(),
+        did_you_mean: None,
+        quota: None,
+        upstream_status: None,
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&body), status).into_response())
+}
@@ -0,0 +1,650 @@
+//! The audit log: a durable record of each MCP request, with the prompt that
+//! went to the backend, the knowledge base documents and moral adjustments
+//! behind it, and the response or error that came back, and of
+//! administrative changes such as clearing agent metrics. Records are queued
+//! and written by a background task, so auditing never holds up a response;
+//! the store is an append-only JSONL file or a SQLite table. Records older
+//! than the retention period are pruned hourly, and bodies can be redacted
+//! while keeping the metadata.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sqlite::{Connection, ConnectionThreadSafe, State};
+use tokio::sync::{mpsc, oneshot};
+use crate::content_filter::ContentFilterReport;
+use crate::context_screen::ContextFlag;
+use crate::mcp_server::{MCPError, MCPMetadata, MCPParams, MCPResult, ResponseMetrics};
+use crate::replay::ReplayConfig;
+
+/// Records returned by one query unless it asks for fewer
+pub const DEFAULT_QUERY_LIMIT: usize = 100;
+pub const MAX_QUERY_LIMIT: usize = 1000;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSink {
+    /// No audit log
+    #[default]
+    None,
+    /// One JSON record per line
+    Jsonl,
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub sink: AuditSink,
+    /// The JSONL file or SQLite database; required with a sink
+    pub path: Option<PathBuf>,
+    /// Records older than this are pruned; 0 keeps them forever
+    pub retention_days: u64,
+    /// Leave prompts, params and responses out, keeping everything else
+    pub redact_bodies: bool,
+    /// Records waiting to be written; more are dropped with a warning
+    pub queue_size: usize,
+    /// `POST /api/audit/replay`
+    pub replay: ReplayConfig,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            sink: AuditSink::None,
+            path: None,
+            retention_days: 90,
+            redact_bodies: false,
+            queue_size: 1024,
+            replay: ReplayConfig::default(),
+        }
+    }
+}
+
+impl AuditConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.sink != AuditSink::None && self.path.is_none() {
+            problems.push("audit.path must be set when audit.sink is".to_string());
+        }
+        if self.queue_size == 0 {
+            problems.push("audit.queue_size must be positive".to_string());
+        }
+        if self.replay.max_records == 0 {
+            problems.push("audit.replay.max_records must be positive".to_string());
+        }
+        if self.replay.concurrency == 0 {
+            problems.push("audit.replay.concurrency must be positive".to_string());
+        }
+        problems
+    }
+}
+
+/// How a request failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditError {
+    pub code: String,
+    pub message: String,
+    pub status: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub request_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub agent_id: String,
+    pub method: String,
+    /// The system instructions sent to the backend
+    pub system_prompt: Option<String>,
+    /// The final user turn sent to the backend, knowledge base context included
+    pub prompt: Option<String>,
+    pub rag_document_ids: Vec<String>,
+    pub chaos_applied: bool,
+    pub chaos_type: Option<String>,
+    pub moral_recentered: bool,
+    pub ethical_adjustments: Vec<String>,
+    pub response: Option<String>,
+    pub metrics: Option<ResponseMetrics>,
+    pub error: Option<AuditError>,
+    /// What the content filter did to the response, and the rules that fired
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_filter: Option<ContentFilterReport>,
+    /// Knowledge base context that screening dropped, neutralized or flagged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context_flags: Vec<ContextFlag>,
+    /// Whether prompts and response were left out
+    #[serde(default)]
+    pub redacted: bool,
+    /// The request's params, which a replay sends again; None
+    /// for administrative changes, redacted records and records written
+    /// before params were kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<MCPParams>,
+    /// The request this one replayed, if it was a replay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_of: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn from_response(agent_id: &str, method: &str, result: &MCPResult, metadata: &MCPMetadata) -> Self {
+        Self {
+            request_id: metadata.request_id.clone(),
+            timestamp: metadata.timestamp,
+            agent_id: agent_id.to_string(),
+            method: method.to_string(),
+            system_prompt: result.prompt.as_ref().and_then(|prompt| prompt.system.clone()),
+            prompt: result.prompt.as_ref().map(|prompt| prompt.user.clone()),
+            rag_document_ids: cited_documents(result),
+            chaos_applied: metadata.chaos_applied,
+            chaos_type: metadata.chaos_type.clone(),
+            moral_recentered: metadata.moral_recentered,
+            ethical_adjustments: result
+                .moral_recentering
+                .as_ref()
+                .map(|report| report.ethical_adjustments.clone())
+                .unwrap_or_default(),
+            response: Some(result.response.clone()),
+            metrics: Some(result.metrics.clone()),
+            error: None,
+            content_filter: metadata.content_filter.clone(),
+            context_flags: metadata.context_flags.clone(),
+            redacted: false,
+            params: None,
+            replay_of: None,
+        }
+    }
+
+    pub fn from_failure(request_id: &str, agent_id: &str, method: &str, error: &MCPError) -> Self {
+        Self {
+            request_id: request_id.to_string(),
+            timestamp: Utc::now(),
+            agent_id: agent_id.to_string(),
+            method: method.to_string(),
+            system_prompt: None,
+            prompt: None,
+            rag_document_ids: Vec::new(),
+            chaos_applied: false,
+            chaos_type: None,
+            moral_recentered: false,
+            ethical_adjustments: Vec::new(),
+            response: None,
+            metrics: None,
+            error: Some(AuditError { code: error.code().to_string(), message: error.to_string(), status: error.http_status() }),
+            content_filter: None,
+            context_flags: Vec::new(),
+            redacted: false,
+            params: None,
+            replay_of: None,
+        }
+    }
+
+    /// An administrative change to server state, e.g. clearing an agent's
+    /// metrics, with what it did as the response
+    pub fn admin(action: &str, agent_id: &str, outcome: String) -> Self {
+        Self {
+            request_id: format!("admin-{}", uuid::Uuid::new_v4()),
+            timestamp: Utc::now(),
+            agent_id: agent_id.to_string(),
+            method: action.to_string(),
+            system_prompt: None,
+            prompt: None,
+            rag_document_ids: Vec::new(),
+            chaos_applied: false,
+            chaos_type: None,
+            moral_recentered: false,
+            ethical_adjustments: Vec::new(),
+            response: Some(outcome),
+            metrics: None,
+            error: None,
+            content_filter: None,
+            context_flags: Vec::new(),
+            redacted: false,
+            params: None,
+            replay_of: None,
+        }
+    }
+
+    pub fn with_params(mut self, params: Option<MCPParams>) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Marks this as a replay of the request `original`
+    pub fn replaying(mut self, original: &str) -> Self {
+        self.replay_of = Some(original.to_string());
+        self
+    }
+
+    fn redact(&mut self) {
+        self.system_prompt = None;
+        self.prompt = None;
+        self.response = None;
+        self.params = None;
+        self.redacted = true;
+    }
+}
+
+/// The documents `result` cites, each once, in citation order
+pub(crate) fn cited_documents(result: &MCPResult) -> Vec<String> {
+    let mut documents: Vec<String> = Vec::new();
+    for citation in result.citations.iter().flatten() {
+        if !documents.contains(&citation.document_id) {
+            documents.push(citation.document_id.clone());
+        }
+    }
+    documents
+}
+
+/// `GET /api/audit` filters; newest records first
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    pub agent_id: Option<String>,
+    /// Only records at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only records before this time
+    pub until: Option<DateTime<Utc>>,
+    /// At most `MAX_QUERY_LIMIT`
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT)
+    }
+
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.agent_id.as_ref().is_none_or(|agent_id| *agent_id == record.agent_id)
+            && self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp < until)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditResponse {
+    pub records: Vec<AuditRecord>,
+}
+
+/// Sortable text form of a record's timestamp, as stored in SQLite
+fn timestamp_key(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+enum AuditStore {
+    Jsonl(PathBuf),
+    Sqlite(ConnectionThreadSafe),
+}
+
+impl AuditStore {
+    fn open(sink: AuditSink, path: &Path) -> Result<Self> {
+        match sink {
+            AuditSink::None => Err(anyhow!("no audit sink configured")),
+            AuditSink::Jsonl => {
+                std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+                Ok(AuditStore::Jsonl(path.to_path_buf()))
+            }
+            AuditSink::Sqlite => {
+                let db = Connection::open_thread_safe(path)?;
+                db.execute(
+                    "CREATE TABLE IF NOT EXISTS audit_log (
+                        request_id TEXT NOT NULL,
+                        timestamp TEXT NOT NULL,
+                        agent_id TEXT NOT NULL,
+                        method TEXT NOT NULL,
+                        record TEXT NOT NULL
+                    );
+                    CREATE INDEX IF NOT EXISTS audit_log_timestamp ON audit_log (timestamp);
+                    CREATE INDEX IF NOT EXISTS audit_log_agent ON audit_log (agent_id, timestamp);",
+                )?;
+                Ok(AuditStore::Sqlite(db))
+            }
+        }
+    }
+
+    fn append(&self, records: &[AuditRecord]) -> Result<()> {
+        match self {
+            AuditStore::Jsonl(path) => {
+                let mut lines = Vec::new();
+                for record in records {
+                    serde_json::to_writer(&mut lines, record)?;
+                    lines.push(b'\n');
+                }
+                let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+                file.write_all(&lines)?;
+                file.sync_data()?;
+            }
+            AuditStore::Sqlite(db) => {
+                db.execute("BEGIN")?;
+                let inserted = records.iter().try_for_each(|record| {
+                    let mut stmt = db.prepare(
+                        "INSERT INTO audit_log (request_id, timestamp, agent_id, method, record) VALUES (?, ?, ?, ?, ?)",
+                    )?;
+                    stmt.bind((1, record.request_id.as_str()))?;
+                    stmt.bind((2, timestamp_key(&record.timestamp).as_str()))?;
+                    stmt.bind((3, record.agent_id.as_str()))?;
+                    stmt.bind((4, record.method.as_str()))?;
+                    stmt.bind((5, serde_json::to_string(record)?.as_str()))?;
+                    stmt.next()?;
+                    Ok::<_, anyhow::Error>(())
+                });
+                db.execute(if inserted.is_ok() { "COMMIT" } else { "ROLLBACK" })?;
+                inserted?;
+            }
+        }
+        Ok(())
+    }
+
+    fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        let limit = query.limit();
+        match self {
+            AuditStore::Jsonl(path) => {
+                let mut records = Vec::new();
+                for line in BufReader::new(std::fs::File::open(path)?).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let record: AuditRecord = serde_json::from_str(&line).context("reading the audit log")?;
+                    if query.matches(&record) {
+                        records.push(record);
+                    }
+                }
+                records.sort_by_key(|record| std::cmp::Reverse(record.timestamp));
+                records.truncate(limit);
+                Ok(records)
+            }
+            AuditStore::Sqlite(db) => {
+                let mut sql = "SELECT record FROM audit_log WHERE 1 = 1".to_string();
+                if query.agent_id.is_some() {
+                    sql.push_str(" AND agent_id = :agent_id");
+                }
+                if query.since.is_some() {
+                    sql.push_str(" AND timestamp >= :since");
+                }
+                if query.until.is_some() {
+                    sql.push_str(" AND timestamp < :until");
+                }
+                sql.push_str(" ORDER BY timestamp DESC LIMIT :limit");
+                let mut stmt = db.prepare(sql)?;
+                if let Some(agent_id) = &query.agent_id {
+                    stmt.bind((":agent_id", agent_id.as_str()))?;
+                }
+                if let Some(since) = &query.since {
+                    stmt.bind((":since", timestamp_key(since).as_str()))?;
+                }
+                if let Some(until) = &query.until {
+                    stmt.bind((":until", timestamp_key(until).as_str()))?;
+                }
+                stmt.bind((":limit", limit as i64))?;
+                let mut records = Vec::new();
+                while let State::Row = stmt.next()? {
+                    records.push(serde_json::from_str(&stmt.read::<String, _>(0)?).context("reading the audit log")?);
+                }
+                Ok(records)
+            }
+        }
+    }
+
+    /// The records with these request ids, in no particular order
+    fn find(&self, request_ids: &[String]) -> Result<Vec<AuditRecord>> {
+        match self {
+            AuditStore::Jsonl(path) => {
+                let mut records = Vec::new();
+                for line in BufReader::new(std::fs::File::open(path)?).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let record: AuditRecord = serde_json::from_str(&line).context("reading the audit log")?;
+                    if request_ids.contains(&record.request_id) {
+                        records.push(record);
+                    }
+                }
+                Ok(records)
+            }
+            AuditStore::Sqlite(db) => {
+                let mut records = Vec::new();
+                for request_id in request_ids {
+                    let mut stmt = db.prepare("SELECT record FROM audit_log WHERE request_id = ?")?;
+                    stmt.bind((1, request_id.as_str()))?;
+                    while let State::Row = stmt.next()? {
+                        records.push(serde_json::from_str(&stmt.read::<String, _>(0)?).context("reading the audit log")?);
+                    }
+                }
+                Ok(records)
+            }
+        }
+    }
+
+    /// Removes records older than `before`, returning how many
+    fn prune(&self, before: DateTime<Utc>) -> Result<usize> {
+        match self {
+            AuditStore::Jsonl(path) => {
+                let mut kept = Vec::new();
+                let mut removed = 0;
+                for line in BufReader::new(std::fs::File::open(path)?).lines() {
+                    let line = line?;
+                    // Unreadable lines are kept for a person to look at
+                    let old = serde_json::from_str::<AuditRecord>(&line).is_ok_and(|record| record.timestamp < before);
+                    if old {
+                        removed += 1;
+                    } else if !line.trim().is_empty() {
+                        kept.extend_from_slice(line.as_bytes());
+                        kept.push(b'\n');
+                    }
+                }
+                if removed > 0 {
+                    let staged = path.with_extension("pruning");
+                    std::fs::write(&staged, &kept)?;
+                    std::fs::rename(&staged, path)?;
+                }
+                Ok(removed)
+            }
+            AuditStore::Sqlite(db) => {
+                let mut stmt = db.prepare("DELETE FROM audit_log WHERE timestamp < ?")?;
+                stmt.bind((1, timestamp_key(&before).as_str()))?;
+                stmt.next()?;
+                Ok(db.change_count())
+            }
+        }
+    }
+}
+
+enum Command {
+    Record(Box<AuditRecord>),
+    /// Answered once everything queued before it is written
+    Flush(oneshot::Sender<()>),
+}
+
+pub struct AuditLog {
+    queue: mpsc::Sender<Command>,
+    store: Arc<Mutex<AuditStore>>,
+    redact_bodies: bool,
+}
+
+impl AuditLog {
+    /// The log `config` describes, or None without a sink. Starts the writer
+    /// task, so it must be called within the Tokio runtime.
+    pub fn open(config: &AuditConfig) -> Result<Option<Self>> {
+        let Some(path) = config.path.as_deref().filter(|_| config.sink != AuditSink::None) else {
+            return Ok(None);
+        };
+        let runtime = tokio::runtime::Handle::try_current().context("the audit log needs a Tokio runtime")?;
+        let store = AuditStore::open(config.sink, path).with_context(|| format!("opening the audit log {}", path.display()))?;
+        let store = Arc::new(Mutex::new(store));
+        let (queue, commands) = mpsc::channel(config.queue_size.max(1));
+        let retention = (config.retention_days > 0).then(|| chrono::Duration::days(config.retention_days as i64));
+        runtime.spawn(write_queued(Arc::clone(&store), commands, retention));
+        Ok(Some(Self { queue, store, redact_bodies: config.redact_bodies }))
+    }
+
+    /// Queues `record` and returns at once; a full queue drops it with a warning
+    pub fn record(&self, mut record: AuditRecord) {
+        if self.redact_bodies {
+            record.redact();
+        }
+        let request_id = record.request_id.clone();
+        if self.queue.try_send(Command::Record(Box::new(record))).is_err() {
+            tracing::warn!("Audit queue full, dropping the record of request {}", request_id);
+        }
+    }
+
+    /// Waits until every record queued so far is written
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        if self.queue.send(Command::Flush(done)).await.is_ok() {
+            let _ = written.await;
+        }
+    }
+
+    pub async fn query(&self, query: AuditQuery) -> Result<Vec<AuditRecord>> {
+        let store = Arc::clone(&self.store);
+        tokio::task::spawn_blocking(move || store.lock().unwrap_or_else(|e| e.into_inner()).query(&query)).await?
+    }
+
+    /// The records with these request ids, in no particular order
+    pub async fn find(&self, request_ids: Vec<String>) -> Result<Vec<AuditRecord>> {
+        let store = Arc::clone(&self.store);
+        tokio::task::spawn_blocking(move || store.lock().unwrap_or_else(|e| e.into_inner()).find(&request_ids)).await?
+    }
+}
+
+async fn write_queued(
+    store: Arc<Mutex<AuditStore>>,
+    mut commands: mpsc::Receiver<Command>,
+    retention: Option<chrono::Duration>,
+) {
+    let mut prune = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        let first = tokio::select! {
+            command = commands.recv() => match command {
+                Some(command) => command,
+                None => return,
+            },
+            _ = prune.tick(), if retention.is_some() => {
+                let before = Utc::now() - retention.unwrap_or_default();
+                let store = Arc::clone(&store);
+                match tokio::task::spawn_blocking(move || store.lock().unwrap_or_else(|e| e.into_inner()).prune(before)).await {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(removed)) => tracing::info!("Pruned {} audit records from before {}", removed, before),
+                    Ok(Err(e)) => tracing::error!("Failed to prune the audit log: {}", e),
+                    Err(e) => tracing::error!("Audit pruning panicked: {}", e),
+                }
+                continue;
+            }
+        };
+
+        // Everything already waiting goes out in the same write
+        let mut records = Vec::new();
+        let mut flushed = Vec::new();
+        let mut next = Some(first);
+        while let Some(command) = next {
+            match command {
+                Command::Record(record) => records.push(*record),
+                Command::Flush(done) => flushed.push(done),
+            }
+            next = commands.try_recv().ok();
+        }
+        if !records.is_empty() {
+            let count = records.len();
+            let store = Arc::clone(&store);
+            match tokio::task::spawn_blocking(move || store.lock().unwrap_or_else(|e| e.into_inner()).append(&records)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::error!("Failed to write {} audit records: {}", count, e),
+                Err(e) => tracing::error!("Audit write panicked: {}", e),
+            }
+        }
+        for done in flushed {
+            let _ = done.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(agent_id: &str, minutes_ago: i64) -> AuditRecord {
+        AuditRecord {
+            request_id: format!("{agent_id}-{minutes_ago}"),
+            timestamp: Utc::now() - chrono::Duration::minutes(minutes_ago),
+            agent_id: agent_id.to_string(),
+            method: "llm_inference".to_string(),
+            system_prompt: Some("Be kind".to_string()),
+            prompt: Some("hello".to_string()),
+            rag_document_ids: vec!["doc-1".to_string()],
+            chaos_applied: false,
+            chaos_type: None,
+            moral_recentered: true,
+            ethical_adjustments: Vec::new(),
+            response: Some("hi".to_string()),
+            metrics: None,
+            error: None,
+            content_filter: None,
+            context_flags: Vec::new(),
+            redacted: false,
+            params: None,
+            replay_of: None,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("void-shrine-audit-{}-{}", uuid::Uuid::new_v4(), name))
+    }
+
+    #[test]
+    fn stores_filter_order_and_prune_alike() {
+        for (sink, path) in [(AuditSink::Jsonl, temp_path("log.jsonl")), (AuditSink::Sqlite, temp_path("log.db"))] {
+            let store = AuditStore::open(sink, &path).unwrap();
+            store.append(&[record("a", 300), record("b", 30), record("a", 20)]).unwrap();
+            store.append(&[record("a", 10)]).unwrap();
+
+            let all = store.query(&AuditQuery::default()).unwrap();
+            let ids: Vec<&str> = all.iter().map(|r| r.request_id.as_str()).collect();
+            assert_eq!(ids, ["a-10", "a-20", "b-30", "a-300"], "{sink:?}");
+            assert_eq!((all[0].prompt.as_deref(), all[0].response.as_deref()), (Some("hello"), Some("hi")), "{sink:?}");
+
+            let query = AuditQuery {
+                agent_id: Some("a".to_string()),
+                since: Some(Utc::now() - chrono::Duration::minutes(60)),
+                limit: Some(1),
+                ..AuditQuery::default()
+            };
+            let recent: Vec<String> = store.query(&query).unwrap().into_iter().map(|r| r.request_id).collect();
+            assert_eq!(recent, ["a-10"], "{sink:?}");
+            let older = AuditQuery { until: Some(Utc::now() - chrono::Duration::minutes(25)), ..AuditQuery::default() };
+            let older: Vec<String> = store.query(&older).unwrap().into_iter().map(|r| r.request_id).collect();
+            assert_eq!(older, ["b-30", "a-300"], "{sink:?}");
+            let mut found: Vec<String> =
+                store.find(&["a-20".to_string(), "b-30".to_string(), "missing".to_string()]).unwrap().into_iter().map(|r| r.request_id).collect();
+            found.sort();
+            assert_eq!(found, ["a-20", "b-30"], "{sink:?}");
+
+            assert_eq!(store.prune(Utc::now() - chrono::Duration::minutes(60)).unwrap(), 1, "{sink:?}");
+            assert_eq!(store.query(&AuditQuery::default()).unwrap().len(), 3, "{sink:?}");
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[tokio::test]
+    async fn redaction_keeps_the_metadata() {
+        let path = temp_path("redacted.jsonl");
+        let config = AuditConfig { sink: AuditSink::Jsonl, path: Some(path.clone()), redact_bodies: true, ..AuditConfig::default() };
+        let log = AuditLog::open(&config).unwrap().unwrap();
+        log.record(record("a", 0));
+        log.flush().await;
+
+        let stored = log.query(AuditQuery::default()).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].redacted);
+        assert_eq!((&stored[0].prompt, &stored[0].system_prompt, &stored[0].response), (&None, &None, &None));
+        assert_eq!(stored[0].rag_document_ids, ["doc-1"]);
+        assert!(stored[0].moral_recentered);
+        let _ = std::fs::remove_file(path);
+    }
+}
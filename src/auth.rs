@@ -0,0 +1,363 @@
+//! Bearer-token authentication for the HTTP and WebSocket endpoints. Each key
+//! has an id, a secret and the scopes it may use; the id (never the secret) is
+//! recorded on the request span and in `audit` log events. With no keys
+//! configured every request is let through, which keeps local development
+//! frictionless.
+//!
+//! A key may belong to a tenant. A tenant's keys see only the agents, agent
+//! metrics and documents of their tenant, and references to anything else
+//! are answered as if it didn't exist; server-wide admin endpoints need the
+//! `cross_tenant` scope, which also sees every tenant. Once any key has a
+//! tenant, every key needs one or that scope.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use warp::http::Method;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+use crate::api;
+use crate::mcp_server::MCPError;
+
+/// What a key may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Inference and read-only status: MCP requests and jobs, models, throttle, moral recentering
+    Inference,
+    /// Agents, agent metrics and knowledge base documents, of the key's tenant
+    Admin,
+    /// Every tenant, and the server-wide settings: chaos, scaling, limits,
+    /// the audit log, knowledge base maintenance. Implied by `admin` on keys
+    /// without a tenant.
+    CrossTenant,
+}
+
+impl Scope {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "inference" => Ok(Scope::Inference),
+            "admin" => Ok(Scope::Admin),
+            "cross_tenant" => Ok(Scope::CrossTenant),
+            other => Err(anyhow!("unknown scope '{}', expected inference, admin or cross_tenant", other)),
+        }
+    }
+
+    /// Health probes, open to orchestrators without a key
+    pub fn is_public(path: &str) -> bool {
+        matches!(path, "/health" | "/ready")
+    }
+
+    /// The scope needed for a request path. Anything not known to be an
+    /// inference or tenant-scoped admin endpoint needs cross-tenant admin.
+    pub fn for_path(path: &str) -> Self {
+        const INFERENCE_PATHS: [&str; 9] = [
+            "/api/mcp",
+            "/api/embed",
+            "/mcp",
+            "/ws/mcp",
+            "/api/jobs",
+            "/api/models",
+            "/api/throttle",
+            "/api/moral-recentering",
+            "/api/tokens",
+        ];
+        const TENANT_ADMIN_PATHS: [&str; 6] =
+            ["/api/agents", "/api/metrics", "/api/usage", "/api/rag/documents", "/api/rag/index-url", "/api/rag/query"];
+        let under = |prefixes: &[&str]| {
+            prefixes.iter().any(|prefix| path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+        };
+        if under(&INFERENCE_PATHS) {
+            Scope::Inference
+        } else if under(&TENANT_ADMIN_PATHS) {
+            Scope::Admin
+        } else {
+            Scope::CrossTenant
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Inference => write!(f, "inference"),
+            Scope::Admin => write!(f, "admin"),
+            Scope::CrossTenant => write!(f, "cross_tenant"),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    pub id: String,
+    pub secret: String,
+    pub scopes: Vec<Scope>,
+    /// Lowercase letters, digits, `-` and `_`
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+impl ApiKey {
+    /// Parses `id:secret:scope+scope`, optionally followed by `:tenant`, the
+    /// form used in `VOID_SHRINE_API_KEYS`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut parts = spec.trim().splitn(4, ':');
+        let (id, secret, scopes) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(secret), Some(scopes)) if !id.is_empty() && !secret.is_empty() => (id, secret, scopes),
+            _ => return Err(anyhow!("API keys are written id:secret:scope+scope, optionally followed by :tenant")),
+        };
+        let scopes = scopes.split('+').map(Scope::parse).collect::<Result<Vec<_>>>()?;
+        let tenant = parts.next().map(str::to_string);
+        Ok(Self { id: id.to_string(), secret: secret.to_string(), scopes, tenant })
+    }
+
+    /// Whether the key may use `scope`: cross-tenant keys may administer,
+    /// and admin keys outside any tenant act across tenants
+    pub fn holds(&self, scope: Scope) -> bool {
+        let has = |scope| self.scopes.contains(&scope);
+        match scope {
+            Scope::Inference => has(Scope::Inference),
+            Scope::Admin => has(Scope::Admin) || has(Scope::CrossTenant),
+            Scope::CrossTenant => has(Scope::CrossTenant) || (self.tenant.is_none() && has(Scope::Admin)),
+        }
+    }
+
+    /// Whose agents and documents the key sees
+    pub fn tenancy(&self) -> Tenancy {
+        match &self.tenant {
+            Some(tenant) if !self.holds(Scope::CrossTenant) => Tenancy::Tenant(tenant.clone()),
+            _ => Tenancy::All,
+        }
+    }
+}
+
+/// Secrets stay out of logs
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKey").field("id", &self.id).field("scopes", &self.scopes).field("tenant", &self.tenant).finish_non_exhaustive()
+    }
+}
+
+/// Whose agents, agent metrics and documents a request may see
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Tenancy {
+    /// Everyone's: authentication is off, or the key acts across tenants
+    #[default]
+    All,
+    Tenant(String),
+}
+
+impl Tenancy {
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            Tenancy::All => None,
+            Tenancy::Tenant(tenant) => Some(tenant),
+        }
+    }
+
+    /// Whether something belonging to `owner` is visible; what belongs to no
+    /// tenant is visible across tenants only
+    pub fn sees(&self, owner: Option<&str>) -> bool {
+        match self {
+            Tenancy::All => true,
+            Tenancy::Tenant(tenant) => owner == Some(tenant.as_str()),
+        }
+    }
+}
+
+/// The configured keys; empty disables authentication
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    keys: Arc<Vec<ApiKey>>,
+}
+
+impl Auth {
+    pub fn new(keys: Vec<ApiKey>) -> Result<Self> {
+        let tenanted = keys.iter().any(|key| key.tenant.is_some());
+        for (i, key) in keys.iter().enumerate() {
+            if keys[..i].iter().any(|other| other.id == key.id) {
+                return Err(anyhow!("API key id '{}' is used twice", key.id));
+            }
+            if let Some(tenant) = &key.tenant {
+                let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+                if tenant.is_empty() || tenant.len() > 64 || !tenant.chars().all(valid) {
+                    return Err(anyhow!("API key '{}': tenant '{}' must be 1 to 64 lowercase letters, digits, - or _", key.id, tenant));
+                }
+            } else if tenanted && !key.scopes.contains(&Scope::CrossTenant) {
+                return Err(anyhow!("API key '{}' needs a tenant or the cross_tenant scope, as other keys have tenants", key.id));
+            }
+        }
+        Ok(Self { keys: Arc::new(keys) })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// The id of the key in `authorization` if it holds `scope`; None when
+    /// authentication is disabled
+    pub fn authorize(&self, authorization: Option<&str>, scope: Scope) -> Result<Option<&str>, MCPError> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let key = self.key(authorization)?;
+        if !key.holds(scope) {
+            return Err(MCPError::Forbidden { key_id: key.id.clone(), scope });
+        }
+        Ok(Some(&key.id))
+    }
+
+    /// Whose agents and documents the key in `authorization` sees. Everyone's
+    /// without authentication; a request without a valid key never gets this
+    /// far past `filter`.
+    pub fn tenancy(&self, authorization: Option<&str>) -> Tenancy {
+        match self.key(authorization) {
+            Ok(key) => key.tenancy(),
+            Err(_) => Tenancy::All,
+        }
+    }
+
+    /// Extracts the request's `Tenancy`
+    pub fn tenancy_filter(&self) -> impl Filter<Extract = (Tenancy,), Error = Infallible> + Clone {
+        let auth = self.clone();
+        warp::header::optional::<String>("authorization")
+            .or_else(|_| async { Ok::<_, Infallible>((None,)) })
+            .map(move |authorization: Option<String>| auth.tenancy(authorization.as_deref()))
+    }
+
+    fn key(&self, authorization: Option<&str>) -> Result<&ApiKey, MCPError> {
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(MCPError::Unauthorized("missing bearer token"))?;
+        // Compare against every key so timing does not reveal which one matched
+        let mut matched = None;
+        for key in self.keys.iter() {
+            if constant_time_eq(key.secret.as_bytes(), token.trim().as_bytes()) {
+                matched = Some(key);
+            }
+        }
+        matched.ok_or(MCPError::Unauthorized("unknown API key"))
+    }
+
+    /// Rejects requests without a key holding the scope their path needs. Sits
+    /// in front of all routes, inside a span with an empty `key_id` field.
+    pub fn filter(&self) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let auth = self.clone();
+        warp::path::full()
+            .and(warp::method())
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(move |path: FullPath, method: Method, authorization: Option<String>| {
+                let auth = auth.clone();
+                async move {
+                    if Scope::is_public(path.as_str()) {
+                        return Ok(());
+                    }
+                    auth.admit(&method, path.as_str(), authorization.as_deref()).map(drop).map_err(api::reject)
+                }
+            })
+            .untuple_one()
+    }
+
+    /// Checks the key in `authorization` holds the scope `path` needs, and
+    /// says whose agents and documents it sees. Decisions are logged to the
+    /// `audit` target and the key id recorded on the current span. Used by
+    /// `filter`, and by the gRPC service with the path each RPC mirrors.
+    pub fn admit(&self, method: &dyn std::fmt::Display, path: &str, authorization: Option<&str>) -> Result<Tenancy, MCPError> {
+        let scope = Scope::for_path(path);
+        match self.authorize(authorization, scope) {
+            Ok(Some(key_id)) => {
+                tracing::Span::current().record("key_id", key_id);
+                tracing::info!(target: "audit", key_id, %method, path, %scope, "authorized");
+                Ok(self.tenancy(authorization))
+            }
+            Ok(None) => Ok(Tenancy::All),
+            Err(e) => {
+                tracing::warn!(target: "audit", %method, path, %scope, "denied: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Equality whose running time depends only on the lengths
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = a.len() ^ b.len();
+    for i in 0..a.len().max(b.len()) {
+        diff |= usize::from(a.get(i).copied().unwrap_or(0) ^ b.get(i).copied().unwrap_or(0));
+    }
+    diff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> Auth {
+        Auth::new(vec![
+            ApiKey::parse("agents:s3cret:inference").unwrap(),
+            ApiKey::parse("ops:0ps-key:inference+admin").unwrap(),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn keys_need_the_scope_of_the_path() {
+        let auth = auth();
+        assert_eq!(auth.authorize(Some("Bearer s3cret"), Scope::Inference).unwrap(), Some("agents"));
+        assert_eq!(auth.authorize(Some("Bearer 0ps-key"), Scope::Admin).unwrap(), Some("ops"));
+
+        let forbidden = auth.authorize(Some("Bearer s3cret"), Scope::Admin).unwrap_err();
+        assert_eq!((forbidden.code(), forbidden.http_status()), ("forbidden", 403));
+        for header in [None, Some("s3cret"), Some("Bearer s3cre"), Some("Bearer s3cret!")] {
+            assert_eq!(auth.authorize(header, Scope::Inference).unwrap_err().http_status(), 401, "{:?}", header);
+        }
+        assert_eq!(Auth::default().authorize(None, Scope::Admin).unwrap(), None);
+    }
+
+    #[test]
+    fn paths_map_to_scopes() {
+        assert_eq!(Scope::for_path("/api/mcp/stream"), Scope::Inference);
+        assert_eq!(Scope::for_path("/ws/mcp"), Scope::Inference);
+        assert_eq!(Scope::for_path("/api/jobs/4f1c"), Scope::Inference);
+        assert_eq!(Scope::for_path("/api/throttle/agent-1"), Scope::Inference);
+        assert_eq!(Scope::for_path("/api/mcpx"), Scope::CrossTenant);
+        assert_eq!(Scope::for_path("/api/chaos"), Scope::CrossTenant);
+        assert_eq!(Scope::for_path("/api/rag/documents"), Scope::Admin);
+        assert_eq!(Scope::for_path("/api/agents/scout/metrics"), Scope::Admin);
+        assert_eq!(Scope::for_path("/api/rag/rebuild"), Scope::CrossTenant);
+        assert!(Scope::is_public("/ready") && !Scope::is_public("/ready/now"));
+    }
+
+    #[test]
+    fn key_specs_are_checked() {
+        assert!(ApiKey::parse("id:secret:root").is_err());
+        assert!(ApiKey::parse("id::inference").is_err());
+        assert!(ApiKey::parse("id:secret").is_err());
+        assert!(!format!("{:?}", ApiKey::parse("id:hunter2:admin").unwrap()).contains("hunter2"));
+        assert!(Auth::new(vec![ApiKey::parse("a:x:admin").unwrap(), ApiKey::parse("a:y:admin").unwrap()]).is_err());
+        assert!(Auth::new(vec![ApiKey::parse("a:x:admin:Acme").unwrap()]).is_err());
+        // Once tenancy is on, keys outside a tenant must say they span tenants
+        assert!(Auth::new(vec![ApiKey::parse("a:x:admin:acme").unwrap(), ApiKey::parse("b:y:admin").unwrap()]).is_err());
+        assert!(Auth::new(vec![ApiKey::parse("a:x:admin:acme").unwrap(), ApiKey::parse("b:y:cross_tenant").unwrap()]).is_ok());
+    }
+
+    #[test]
+    fn tenant_keys_see_their_tenant_only() {
+        let auth = Auth::new(vec![
+            ApiKey::parse("acme:acme-key:inference+admin:acme").unwrap(),
+            ApiKey::parse("root:root-key:inference+cross_tenant").unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(auth.tenancy(Some("Bearer acme-key")), Tenancy::Tenant("acme".to_string()));
+        assert_eq!(auth.tenancy(Some("Bearer root-key")), Tenancy::All);
+        assert!(auth.authorize(Some("Bearer acme-key"), Scope::Admin).is_ok());
+        assert_eq!(auth.authorize(Some("Bearer acme-key"), Scope::CrossTenant).unwrap_err().http_status(), 403);
+        assert!(auth.authorize(Some("Bearer root-key"), Scope::Admin).is_ok());
+
+        let acme = Tenancy::Tenant("acme".to_string());
+        assert!(acme.sees(Some("acme")) && !acme.sees(Some("globex")) && !acme.sees(None));
+        assert!(Tenancy::All.sees(None));
+    }
+}
@@ -0,0 +1,558 @@
+//! Load generation for `void-shrine-bench`: concurrent workers sending a
+//! weighted mix of inference, RAG inference, `rag_query` and moral
+//! recentering requests to a server over HTTP, or to a service in this
+//! process, for a duration or a number of requests. The report has the
+//! throughput, latency percentiles per request type and errors by code; run
+//! in process, it also times every knowledge base retrieval on its own.
+//!
+//! Request `n` of a run depends only on the seed and `n`, so the same seed
+//! and request count replay the same workload at any concurrency.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+use crate::mcp_server::{ErrorResponse, MCPRequest, MoralRequest, VoidShrineMCP};
+
+/// What a benchmark request asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RequestKind {
+    /// `llm_inference` without knowledge base context
+    Inference,
+    /// `llm_inference` with it
+    RagInference,
+    RagQuery,
+    MoralRecentering,
+}
+
+impl RequestKind {
+    pub const ALL: [RequestKind; 4] =
+        [RequestKind::Inference, RequestKind::RagInference, RequestKind::RagQuery, RequestKind::MoralRecentering];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RequestKind::Inference => "inference",
+            RequestKind::RagInference => "rag_inference",
+            RequestKind::RagQuery => "rag_query",
+            RequestKind::MoralRecentering => "moral_recentering",
+        }
+    }
+}
+
+impl FromStr for RequestKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name).ok_or_else(|| {
+            let known: Vec<&str> = Self::ALL.iter().map(|kind| kind.as_str()).collect();
+            format!("unknown request type '{}'; known: {}", name, known.join(", "))
+        })
+    }
+}
+
+/// Relative weights of the request types
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestMix {
+    weights: Vec<(RequestKind, u32)>,
+}
+
+impl Default for RequestMix {
+    fn default() -> Self {
+        Self {
+            weights: vec![
+                (RequestKind::Inference, 4),
+                (RequestKind::RagInference, 3),
+                (RequestKind::RagQuery, 2),
+                (RequestKind::MoralRecentering, 1),
+            ],
+        }
+    }
+}
+
+impl RequestMix {
+    fn pick(&self, rng: &mut StdRng) -> RequestKind {
+        let total: u32 = self.weights.iter().map(|(_, weight)| weight).sum();
+        let mut roll = rng.gen_range(0..total);
+        for (kind, weight) in &self.weights {
+            if roll < *weight {
+                return *kind;
+            }
+            roll -= weight;
+        }
+        unreachable!("the roll is below the total weight")
+    }
+}
+
+/// `inference=4,rag_query=1`; types left out are not sent
+impl FromStr for RequestMix {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut weights = Vec::new();
+        for entry in text.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (name, weight) = entry.split_once('=').ok_or_else(|| format!("'{}' is not TYPE=WEIGHT", entry))?;
+            let kind: RequestKind = name.trim().parse()?;
+            let weight: u32 = weight.trim().parse().map_err(|_| format!("weight of {} must be a whole number (got {})", name, weight))?;
+            weights.retain(|(other, _)| *other != kind);
+            weights.push((kind, weight));
+        }
+        if weights.iter().all(|(_, weight)| *weight == 0) {
+            return Err("the request mix needs a type with a positive weight".to_string());
+        }
+        weights.retain(|(_, weight)| *weight > 0);
+        Ok(Self { weights })
+    }
+}
+
+/// How prompt lengths are spread between `min_words` and `max_words`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeDistribution {
+    Uniform,
+    /// Log-uniform: mostly short prompts, with a long tail
+    Skewed,
+}
+
+impl FromStr for SizeDistribution {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "uniform" => Ok(SizeDistribution::Uniform),
+            "skewed" => Ok(SizeDistribution::Skewed),
+            _ => Err(format!("prompt distribution must be uniform or skewed (got {})", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptSizes {
+    pub min_words: usize,
+    pub max_words: usize,
+    pub distribution: SizeDistribution,
+}
+
+impl Default for PromptSizes {
+    fn default() -> Self {
+        Self { min_words: 8, max_words: 64, distribution: SizeDistribution::Uniform }
+    }
+}
+
+impl PromptSizes {
+    /// `MIN-MAX` words, or one number for every prompt
+    pub fn parse_range(text: &str, distribution: SizeDistribution) -> Result<Self, String> {
+        let number = |text: &str| text.trim().parse::<usize>().map_err(|_| format!("prompt words must be N or MIN-MAX (got {})", text));
+        let (min_words, max_words) = match text.split_once('-') {
+            Some((min, max)) => (number(min)?, number(max)?),
+            None => (number(text)?, number(text)?),
+        };
+        if min_words == 0 || min_words > max_words {
+            return Err(format!("prompt words must be at least 1 and MIN no more than MAX (got {})", text));
+        }
+        Ok(Self { min_words, max_words, distribution })
+    }
+
+    fn pick(&self, rng: &mut StdRng) -> usize {
+        match self.distribution {
+            SizeDistribution::Uniform => rng.gen_range(self.min_words..=self.max_words),
+            SizeDistribution::Skewed => {
+                let (min, max) = ((self.min_words as f64).ln(), (self.max_words as f64).ln());
+                let words = if max > min { rng.gen_range(min..=max).exp().round() as usize } else { self.min_words };
+                words.clamp(self.min_words, self.max_words)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchConfig {
+    /// Requests in flight at once
+    pub concurrency: usize,
+    /// Stop sending after this long; with `requests` too, at whichever comes first
+    pub duration: Option<Duration>,
+    /// Stop after this many requests
+    pub requests: Option<u64>,
+    pub seed: u64,
+    pub mix: RequestMix,
+    pub prompt_sizes: PromptSizes,
+    /// Distinct agent ids the requests are spread across
+    pub agents: usize,
+    pub max_tokens: u32,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            duration: Some(Duration::from_secs(10)),
+            requests: None,
+            seed: 0,
+            mix: RequestMix::default(),
+            prompt_sizes: PromptSizes::default(),
+            agents: 8,
+            max_tokens: 128,
+        }
+    }
+}
+
+const SPECIALTIES: [&str; 5] = ["general", "science", "engineering", "tactical", "creative"];
+const FRAMEWORKS: [&str; 4] = ["care-ethics", "consequentialist", "deontological", "virtue-ethics"];
+const WORDS: [&str; 24] = [
+    "void", "shrine", "emergence", "care", "ethics", "agents", "coordinate", "knowledge", "community", "wellbeing", "pattern",
+    "system", "stakeholders", "absence", "intelligence", "balance", "memory", "signal", "garden", "resilience", "question",
+    "design", "trust", "change",
+];
+
+/// One request of a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedRequest {
+    pub index: u64,
+    pub kind: RequestKind,
+    pub agent_id: String,
+    pub specialty: &'static str,
+    pub prompt: String,
+}
+
+impl BenchConfig {
+    /// Request `index` of a run with this seed, mix and prompt sizes
+    pub fn plan(&self, index: u64) -> PlannedRequest {
+        let mut rng = StdRng::seed_from_u64(self.seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let kind = self.mix.pick(&mut rng);
+        let agent = rng.gen_range(0..self.agents.max(1));
+        let specialty = SPECIALTIES[rng.gen_range(0..SPECIALTIES.len())];
+        let words = self.prompt_sizes.pick(&mut rng);
+        let prompt: Vec<&str> = (0..words).map(|_| WORDS[rng.gen_range(0..WORDS.len())]).collect();
+        PlannedRequest { index, kind, agent_id: format!("bench-agent-{}", agent), specialty, prompt: prompt.join(" ") }
+    }
+}
+
+impl PlannedRequest {
+    fn mcp_request(&self, max_tokens: u32) -> Value {
+        let method = if self.kind == RequestKind::RagQuery { "rag_query" } else { "llm_inference" };
+        json!({
+            "method": method,
+            "params": {
+                "agent_id": self.agent_id,
+                "prompt": self.prompt,
+                "specialty": self.specialty,
+                "max_tokens": max_tokens,
+                "use_rag": self.kind != RequestKind::Inference,
+            },
+        })
+    }
+
+    fn moral_request(&self) -> MoralRequest {
+        MoralRequest {
+            original_prompt: self.prompt.clone(),
+            specialty: self.specialty.to_string(),
+            void_shrine_context: self.index % 2 == 1,
+            ethical_framework: FRAMEWORKS[self.index as usize % FRAMEWORKS.len()].to_string(),
+            strict: None,
+        }
+    }
+}
+
+/// Where the requests go
+pub enum Target {
+    InProcess(Arc<VoidShrineMCP>),
+    /// A server's base URL, and the bearer key to send
+    Http { client: reqwest::Client, url: String, api_key: Option<String> },
+}
+
+impl Target {
+    pub fn http(url: &str, api_key: Option<String>) -> Self {
+        Target::Http { client: reqwest::Client::new(), url: url.trim_end_matches('/').to_string(), api_key }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Target::InProcess(_) => "in-process".to_string(),
+            Target::Http { url, .. } => url.clone(),
+        }
+    }
+
+    /// Sends the request, failing with the error's code
+    async fn send(&self, request: &PlannedRequest, max_tokens: u32) -> Result<(), String> {
+        match self {
+            Target::InProcess(service) => match request.kind {
+                RequestKind::MoralRecentering => {
+                    service.handle_moral_recentering(request.moral_request()).await.map(drop).map_err(|e| e.code().to_string())
+                }
+                _ => {
+                    let mcp: MCPRequest = serde_json::from_value(request.mcp_request(max_tokens)).map_err(|_| "invalid_params".to_string())?;
+                    service.handle_mcp_request(mcp).await.map(drop).map_err(|failed| failed.error.code().to_string())
+                }
+            },
+            Target::Http { client, url, api_key } => {
+                let (path, body) = match request.kind {
+                    RequestKind::MoralRecentering => ("/api/moral-recentering", json!(request.moral_request())),
+                    _ => ("/api/mcp", request.mcp_request(max_tokens)),
+                };
+                let mut http = client.post(format!("{}{}", url, path)).json(&body);
+                if let Some(key) = api_key {
+                    http = http.bearer_auth(key);
+                }
+                let response = http.send().await.map_err(|_| "transport".to_string())?;
+                let status = response.status();
+                if status.is_success() {
+                    // Read whole, as a client would
+                    return response.bytes().await.map(drop).map_err(|_| "transport".to_string());
+                }
+                match response.json::<ErrorResponse>().await {
+                    Ok(error) => Err(error.error),
+                    Err(_) => Err(format!("http_{}", status.as_u16())),
+                }
+            }
+        }
+    }
+}
+
+/// Latency of a set of requests, in milliseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencySummary {
+    /// Nearest-rank percentiles of `samples`
+    pub fn of(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let rank = |p: f64| sorted[((p / 100.0 * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1];
+        Self {
+            count: sorted.len() as u64,
+            mean_ms: round(sorted.iter().sum::<f64>() / sorted.len() as f64),
+            p50_ms: round(rank(50.0)),
+            p95_ms: round(rank(95.0)),
+            p99_ms: round(rank(99.0)),
+            max_ms: round(sorted[sorted.len() - 1]),
+        }
+    }
+}
+
+fn round(ms: f64) -> f64 {
+    (ms * 1000.0).round() / 1000.0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KindReport {
+    pub requests: u64,
+    pub errors: u64,
+    pub throughput_rps: f64,
+    /// Of the requests that succeeded
+    pub latency: LatencySummary,
+}
+
+/// What a run did; `--json` prints it as is
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    /// The server's URL, or `in-process`
+    pub target: String,
+    pub seed: u64,
+    pub concurrency: usize,
+    pub elapsed_secs: f64,
+    pub requests: u64,
+    pub errors: u64,
+    pub throughput_rps: f64,
+    /// Of the requests that succeeded
+    pub latency: LatencySummary,
+    /// By request type
+    pub kinds: BTreeMap<String, KindReport>,
+    /// By error code: `transport` when no answer came back, `http_<status>`
+    /// for one without an error body
+    pub errors_by_code: BTreeMap<String, u64>,
+    /// Every knowledge base retrieval, timed inside the service; only run in process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_retrieval: Option<LatencySummary>,
+}
+
+/// One request sent
+struct Sample {
+    kind: RequestKind,
+    latency_ms: f64,
+    error: Option<String>,
+}
+
+/// Sends `config`'s workload to `target` and reports on it. `retrievals`,
+/// when installed for an in-process target, adds the retrieval latencies.
+pub async fn run(target: Arc<Target>, config: &BenchConfig, retrievals: Option<&RagTimings>) -> BenchReport {
+    if let Some(retrievals) = retrievals {
+        retrievals.take();
+    }
+    let next = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..config.concurrency.max(1))
+        .map(|_| {
+            let (target, next, config) = (Arc::clone(&target), Arc::clone(&next), config.clone());
+            tokio::spawn(async move {
+                let mut samples = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let out_of_time = config.duration.is_some_and(|duration| started.elapsed() >= duration);
+                    if out_of_time || config.requests.is_some_and(|requests| index >= requests) {
+                        break;
+                    }
+                    let request = config.plan(index);
+                    let sent = Instant::now();
+                    let error = target.send(&request, config.max_tokens).await.err();
+                    samples.push(Sample { kind: request.kind, latency_ms: sent.elapsed().as_secs_f64() * 1000.0, error });
+                }
+                samples
+            })
+        })
+        .collect();
+    let mut samples = Vec::new();
+    for worker in workers {
+        samples.extend(worker.await.unwrap_or_default());
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    let rag_retrieval = retrievals.map(|retrievals| LatencySummary::of(&retrievals.take()));
+    report(target.name(), config, elapsed, &samples, rag_retrieval)
+}
+
+fn report(target: String, config: &BenchConfig, elapsed: f64, samples: &[Sample], rag_retrieval: Option<LatencySummary>) -> BenchReport {
+    let rate = |count: usize| if elapsed > 0.0 { round(count as f64 / elapsed) } else { 0.0 };
+    let succeeded = |samples: &[&Sample]| -> Vec<f64> {
+        samples.iter().filter(|sample| sample.error.is_none()).map(|sample| sample.latency_ms).collect()
+    };
+    let all: Vec<&Sample> = samples.iter().collect();
+    let mut kinds = BTreeMap::new();
+    for kind in RequestKind::ALL {
+        let of_kind: Vec<&Sample> = samples.iter().filter(|sample| sample.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        let errors = of_kind.iter().filter(|sample| sample.error.is_some()).count();
+        kinds.insert(
+            kind.as_str().to_string(),
+            KindReport {
+                requests: of_kind.len() as u64,
+                errors: errors as u64,
+                throughput_rps: rate(of_kind.len()),
+                latency: LatencySummary::of(&succeeded(&of_kind)),
+            },
+        );
+    }
+    let mut errors_by_code = BTreeMap::new();
+    for code in samples.iter().filter_map(|sample| sample.error.as_ref()) {
+        *errors_by_code.entry(code.clone()).or_insert(0) += 1;
+    }
+    BenchReport {
+        target,
+        seed: config.seed,
+        concurrency: config.concurrency,
+        elapsed_secs: round(elapsed),
+        requests: samples.len() as u64,
+        errors: errors_by_code.values().sum(),
+        throughput_rps: rate(samples.len()),
+        latency: LatencySummary::of(&succeeded(&all)),
+        kinds,
+        errors_by_code,
+        rag_retrieval,
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "target {}, seed {}, concurrency {}", self.target, self.seed, self.concurrency)?;
+        writeln!(
+            f,
+            "{} requests in {:.2}s: {:.1} req/s, {} errors",
+            self.requests, self.elapsed_secs, self.throughput_rps, self.errors
+        )?;
+        writeln!(f)?;
+        writeln!(f, "{:<20} {:>8} {:>7} {:>9} {:>9} {:>9} {:>9} {:>9}", "type", "requests", "errors", "req/s", "p50 ms", "p95 ms", "p99 ms", "max ms")?;
+        let row = |f: &mut fmt::Formatter<'_>, name: &str, requests: u64, errors: u64, rps: f64, latency: &LatencySummary| {
+            writeln!(
+                f,
+                "{:<20} {:>8} {:>7} {:>9.1} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+                name, requests, errors, rps, latency.p50_ms, latency.p95_ms, latency.p99_ms, latency.max_ms
+            )
+        };
+        for (name, kind) in &self.kinds {
+            row(f, name, kind.requests, kind.errors, kind.throughput_rps, &kind.latency)?;
+        }
+        row(f, "all", self.requests, self.errors, self.throughput_rps, &self.latency)?;
+        if let Some(retrieval) = &self.rag_retrieval {
+            let rps = if self.elapsed_secs > 0.0 { retrieval.count as f64 / self.elapsed_secs } else { 0.0 };
+            row(f, "rag retrieval", retrieval.count, 0, rps, retrieval)?;
+        }
+        if !self.errors_by_code.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "errors:")?;
+            for (code, count) in &self.errors_by_code {
+                writeln!(f, "  {:<28} {}", code, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Times the service's `rag_retrieval` spans, from creation to close, when
+/// installed in the process's subscriber
+#[derive(Clone, Default)]
+pub struct RagTimings {
+    samples: Arc<Mutex<Vec<f64>>>,
+}
+
+impl RagTimings {
+    /// The milliseconds recorded since the last call
+    pub fn take(&self) -> Vec<f64> {
+        std::mem::take(&mut *self.samples.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// When a retrieval span was created
+struct RetrievalStarted(Instant);
+
+/// A retrieval span's `use_rag`; spans of requests not searching don't count
+struct UsesRag(bool);
+
+impl Visit for UsesRag {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == "use_rag" {
+            self.0 = value;
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for RagTimings {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != "rag_retrieval" {
+            return;
+        }
+        let mut uses_rag = UsesRag(true);
+        attrs.record(&mut uses_rag);
+        if let (true, Some(span)) = (uses_rag.0, ctx.span(id)) {
+            span.extensions_mut().insert(RetrievalStarted(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let extensions = span.extensions();
+        if let Some(RetrievalStarted(started)) = extensions.get::<RetrievalStarted>() {
+            self.samples.lock().unwrap_or_else(|e| e.into_inner()).push(started.elapsed().as_secs_f64() * 1000.0);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use warp::Filter;
+use anyhow::Context;
+use void_shrine_mcp::{api, build_info, reload, shutdown, tls};
+use void_shrine_mcp::tls::CertificateStore;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::jobs::JobQueue;
+use void_shrine_mcp::mcp_server::VoidShrineMCP;
+use void_shrine_mcp::rag_engines::DEFAULT_ENGINE;
+use void_shrine_mcp::self_check::{self_check, OnFailedCheck, SelfCheckReport};
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    // In stdio mode stdout carries the protocol, and with `--check` the
+    // report, so logs go to stderr
+    let stdio = std::env::args().skip(1).any(|arg| arg == "--stdio");
+    // `--check` runs the startup checks, prints them and exits
+    let check = std::env::args().skip(1).any(|arg| arg == "--check");
+    #[cfg(feature = "otel")]
+    let _telemetry = void_shrine_mcp::telemetry::init(stdio || check)?;
+    #[cfg(not(feature = "otel"))]
+    if stdio || check {
+        tracing_subscriber::fmt().with_writer(std::io::stderr).init();
+    } else {
+        tracing_subscriber::fmt::init();
+    }
+    
+    let config_path = config_path()?;
+    let config = match Config::load(config_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) if check => exit_checked(&SelfCheckReport::failed("config", &e)),
+        Err(e) => return Err(e),
+    };
+    if let Some(source) = &config.source {
+        tracing::info!("Loaded configuration from {} (sha256 {})", source.path.display(), &source.sha256[..12]);
+    }
+    let mcp_service = match VoidShrineMCP::new(&config) {
+        Ok(service) => Arc::new(service),
+        Err(e) if check => exit_checked(&SelfCheckReport::failed("service", &e)),
+        Err(e) => return Err(e),
+    };
+    // `--check` only inspects the knowledge bases, which opening could write to
+    if check {
+        exit_checked(&self_check(&config, &mcp_service).await);
+    }
+
+    // An unusable database path stops startup here rather than at the first
+    // query, unless the self-check may serve degraded without it
+    let engines = std::iter::once((DEFAULT_ENGINE, config.rag.default_engine()))
+        .chain(config.rag.engines.iter().map(|(name, engine)| (name.as_str(), engine.clone())));
+    for (name, settings) in engines {
+        let rag = match settings.open().await.with_context(|| format!("knowledge base {}", name)) {
+            Ok(rag) => rag,
+            Err(e) if config.self_check.on_failure == OnFailedCheck::Degrade => {
+                tracing::error!("{:#}; serving without it", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let stats = rag.get_stats().await?;
+        tracing::info!(
+            "Knowledge base {} ready with {} documents in {} chunks ({})",
+            name,
+            stats.document_count,
+            stats.chunk_count,
+            settings.db_path.as_ref().map_or_else(|| "in memory".to_string(), |path| path.display().to_string())
+        );
+        *mcp_service.rag_engines.get("engine", Some(name))?.write().await = Some(rag);
+    }
+
+    // Finds out now what traffic would find later
+    let report = self_check(&config, &mcp_service).await;
+    for component in &report.components {
+        match component.passed {
+            true => tracing::info!("Self-check passed {}: {}", component.component, component.detail),
+            false => tracing::error!("Self-check failed {}: {}", component.component, component.detail),
+        }
+    }
+    if !report.passed() {
+        let failed: Vec<&str> = report.failures().map(|component| component.component.as_str()).collect();
+        match config.self_check.on_failure {
+            OnFailedCheck::Abort => anyhow::bail!("self-check failed for {}", failed.join(", ")),
+            OnFailedCheck::Degrade => tracing::warn!("Serving degraded, without {}", failed.join(", ")),
+        }
+    }
+    mcp_service.persist_agent_metrics(Duration::from_secs(config.metrics.save_interval_secs));
+    if config.metrics.prune_interval_secs > 0 {
+        let idle = Duration::from_secs(config.metrics.prune_idle_secs);
+        mcp_service.prune_agent_metrics_every(Duration::from_secs(config.metrics.prune_interval_secs), idle);
+    }
+    // A few sweeps per heartbeat timeout, so agents are found stale promptly
+    mcp_service.sweep_stale_agents_every(Duration::from_secs((config.agents.heartbeat_timeout_secs / 3).clamp(1, 60)));
+    mcp_service.refresh_models_every(Duration::from_secs(config.backends.model_refresh_secs));
+    if config.templates.reload_poll_secs > 0 {
+        mcp_service.templates.watch(Duration::from_secs(config.templates.reload_poll_secs));
+    }
+    // SIGHUP rereads the file, as POST /api/admin/reload does
+    if config.source.is_some() {
+        reload::on_hangup(Arc::clone(&mcp_service));
+    }
+    if !config.backends.backends.is_empty() {
+        tracing::info!(
+            "Routing {} model patterns across {} backends",
+            mcp_service.backends.current().models().len(),
+            config.backends.backends.len()
+        );
+    }
+    let auth = mcp_service.auth.clone();
+    
+    if stdio {
+        tracing::info!("🌀 Void Shrine MCP Server {} serving JSON-RPC on stdio", build_info::version_string());
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        void_shrine_mcp::mcp_protocol::serve_lines(&mcp_service, stdin, tokio::io::stdout()).await?;
+        tracing::info!("stdin closed, shutting down");
+        save_agent_metrics(&mcp_service);
+        return Ok(());
+    }
+
+    // Follow a docs directory when built with the `watch` feature
+    #[cfg(feature = "watch")]
+    let _watcher = match std::env::var("VOID_SHRINE_WATCH_DIR") {
+        Ok(dir) => Some(void_shrine_mcp::watcher::watch_directory(
+            Arc::clone(&mcp_service.rag_engine),
+            dir,
+            Default::default(),
+        )?),
+        Err(_) => None,
+    };
+
+    // Queued requests for inferences too long to hold a connection open, with their own workers
+    let jobs = Arc::new(JobQueue::start(Arc::clone(&mcp_service), config.jobs.clone()));
+    let audit = mcp_service.audit.clone();
+    // Kept for shutdown, after the routes have taken the service
+    let draining = Arc::clone(&mcp_service.shutdown);
+    // gRPC alongside HTTP, answered by the same handlers
+    #[cfg(feature = "grpc")]
+    let grpc_service = Arc::clone(&mcp_service);
+    let rag_engines = Arc::clone(&mcp_service.rag_engines);
+    let persisted = Arc::clone(&mcp_service);
+
+    let route_metrics = Arc::clone(&mcp_service.route_metrics);
+    let compression = config.server.compression.clone();
+    // Failures come back as JSON error bodies
+    let routes = api::routes(mcp_service, jobs)
+        .recover(api::recover)
+        .with(warp::trace(|info| {
+            tracing::info_span!("request", method = %info.method(), path = info.path(), key_id = tracing::field::Empty)
+        }))
+        .with(api::cors(&config.server.cors))
+        // Requests from origins the policy refuses
+        .recover(api::recover)
+        // Rejections included, as the status they were recovered into
+        .with(warp::wrap_fn(move |filter| api::observe_routes(Arc::clone(&route_metrics), filter)))
+        .with(api::server_header())
+        .with(warp::wrap_fn(move |filter| api::compress(compression.clone(), filter)));
+
+    let addr = config.server.socket_addr();
+    let scheme = if config.server.tls.is_some() { "https" } else { "http" };
+    if !auth.is_enabled() {
+        tracing::warn!("No API keys configured; every endpoint is open to anyone who can reach {}://{}", scheme, addr);
+    }
+    
+    // On a signal: stop accepting connections, flip /ready, and let open
+    // requests finish until the drain timeout cuts them off with a 503
+    let signalled = Arc::clone(&draining);
+    let on_signal = async move {
+        let signal = shutdown::signal().await;
+        tracing::info!("{} received, draining for up to {} s", signal, signalled.drain_timeout().as_secs());
+        signalled.begin();
+    };
+    let server: Pin<Box<dyn Future<Output = ()> + Send>> = match &config.server.tls {
+        Some(tls_config) => {
+            let certs = CertificateStore::load(&tls_config.cert_path, &tls_config.key_path)?;
+            certs.watch(Duration::from_secs(tls_config.reload_poll_secs));
+            let listener = TcpListener::bind(addr).await.with_context(|| format!("binding {}", addr))?;
+            if let Some(port) = tls_config.redirect_http_port {
+                let redirect_addr = SocketAddr::new(addr.ip(), port);
+                let redirecting = Arc::clone(&draining);
+                let (_, redirects) = warp::serve(tls::redirect_to_https(addr.port()))
+                    .try_bind_with_graceful_shutdown(redirect_addr, async move { redirecting.draining().await })?;
+                tokio::spawn(redirects);
+                tracing::info!("Redirecting http://{} to HTTPS", redirect_addr);
+            }
+            let incoming = tls::incoming(listener, certs.server_config()?, Arc::clone(&draining));
+            Box::pin(warp::serve(routes).serve_incoming_with_graceful_shutdown(incoming, on_signal))
+        }
+        None => Box::pin(warp::serve(routes).try_bind_with_graceful_shutdown(addr, on_signal)?.1),
+    };
+    tracing::info!("🌀 Void Shrine MCP Server {} listening on {}://{}", build_info::version_string(), scheme, addr);
+    if let Some(port) = config.server.grpc_port {
+        #[cfg(feature = "grpc")]
+        {
+            let grpc_addr = SocketAddr::new(addr.ip(), port);
+            let stopping = Arc::clone(&draining);
+            let grpc = void_shrine_mcp::grpc::bind(grpc_service, grpc_addr, async move { stopping.draining().await })?;
+            tokio::spawn(grpc);
+            tracing::info!("Serving gRPC on {}", grpc_addr);
+        }
+        #[cfg(not(feature = "grpc"))]
+        tracing::warn!("server.grpc_port is {} but this build lacks the grpc feature; not serving gRPC", port);
+    }
+    tokio::select! {
+        _ = server => {}
+        // Responses cut off at the deadline still need a moment to be written
+        _ = async {
+            draining.drain_expired().await;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        } => tracing::warn!("Connections still open after the drain timeout, closing them"),
+    }
+
+    if let Some(audit) = &audit {
+        audit.flush().await;
+    }
+    save_agent_metrics(&persisted);
+    for (name, engine) in rag_engines.iter() {
+        if let Some(rag) = engine.read().await.as_ref() {
+            match rag.checkpoint().await {
+                Ok(_) => tracing::info!("Knowledge base {} flushed", name),
+                Err(e) => tracing::error!("Failed to flush knowledge base {}: {}", name, e),
+            }
+        }
+    }
+    tracing::info!("Shutdown complete");
+    Ok(())
+}
+
+/// Prints `report` as a table, exiting 1 if anything failed
+fn exit_checked(report: &SelfCheckReport) -> ! {
+    println!("{}", report);
+    std::process::exit(if report.passed() { 0 } else { 1 })
+}
+
+/// The last save of agent metrics before exiting
+fn save_agent_metrics(service: &VoidShrineMCP) {
+    match service.save_agent_metrics() {
+        Ok(Some(agents)) => tracing::info!("Saved the metrics of {} agents", agents),
+        Ok(None) => {}
+        Err(e) => tracing::error!("{:#}", e),
+    }
+}
+
+/// `--config <path>` or `--config=<path>`, else `VOID_SHRINE_CONFIG`
+fn config_path() -> Result<Option<PathBuf>, anyhow::Error> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(|path| Some(PathBuf::from(path))).context("--config needs a path");
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Ok(Some(PathBuf::from(path)));
+        }
+    }
+    Ok(std::env::var_os("VOID_SHRINE_CONFIG").map(PathBuf::from))
+}
@@ -0,0 +1,37 @@
+use anyhow::Result;
+use void_shrine_mcp::RAGEngine;
+
+// Binary for running RAG engine standalone
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    
+    let mut rag = RAGEngine::new().await?;
+    
+    // Index void shrine knowledge
+    rag.index_void_shrine_knowledge().await?;
+    
+    // Test queries
+    let test_queries = vec![
+        "What are the core principles of void shrine?",
+        "How do agents coordinate?",
+        "What is care ethics?",
+        "Explain emergence over engineering",
+    ];
+
+    tracing::info!("🔍 Testing RAG Engine:");
+    
+    for query in test_queries {
+        println!("\n🔍 Query: {}", query);
+        let results = rag.query(query, 3).await?;
+        
+        for (i, result) in results.iter().enumerate() {
+            println!("  {}. {}", i + 1, result);
+        }
+    }
+
+    let stats = rag.get_stats().await?;
+    println!("\n📊 RAG Stats: {:#?}", stats);
+
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,208 @@
+//! Load generator for Void Shrine servers.
+//!
+//!     void-shrine-bench [--url URL | --in-process [--config PATH]] [options]
+//!
+//! Sends a seeded mix of requests from concurrent workers and prints the
+//! throughput, latency percentiles per request type and errors by code, as a
+//! table or, with `--json`, as JSON; `--report PATH` saves the JSON as well,
+//! for comparing runs across commits. In process, the service answers with
+//! its mock backends and every knowledge base retrieval is timed too.
+//! Usage errors exit with 2.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use tracing_subscriber::layer::SubscriberExt;
+use void_shrine_mcp::bench::{self, BenchConfig, PromptSizes, RagTimings, SizeDistribution, Target};
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::VoidShrineMCP;
+
+const USAGE: &str = "\
+usage: void-shrine-bench [--url URL | --in-process [--config PATH]] [options]
+
+options:
+  --concurrency N          requests in flight at once (default 8)
+  --duration SECS          how long to send for (default 10, unless --requests)
+  --requests N             how many requests to send
+  --mix TYPE=W,...         weights of inference, rag_inference, rag_query and
+                           moral_recentering (default 4,3,2,1)
+  --prompt-words N|MIN-MAX prompt length in words (default 8-64)
+  --prompt-distribution D  uniform or skewed (default uniform)
+  --agents N               distinct agent ids to send as (default 8)
+  --max-tokens N           max_tokens of inference requests (default 128)
+  --seed N                 workload seed (default 0)
+  --no-chaos               in process, turn chaos off whatever the config says
+  --json                   print the report as JSON
+  --report PATH            also write the JSON report to PATH
+
+environment:
+  VOID_SHRINE_URL      server base URL when neither --url nor --in-process is given
+  VOID_SHRINE_API_KEY  bearer key sent with every request
+  VOID_SHRINE_CONFIG   config file of the in-process service when --config is not given";
+
+/// Options that take no value
+const SWITCHES: &[&str] = &["in-process", "no-chaos", "json", "help"];
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => match e.downcast::<Usage>() {
+            Ok(usage) => {
+                eprintln!("error: {}\n\n{}", usage.0, USAGE);
+                ExitCode::from(2)
+            }
+            Err(e) => {
+                eprintln!("error: {:#}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+/// A command line that doesn't parse
+#[derive(Debug)]
+struct Usage(String);
+
+impl std::fmt::Display for Usage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Usage {}
+
+fn usage(message: impl Into<String>) -> anyhow::Error {
+    Usage(message.into()).into()
+}
+
+/// Every `--name value` (or `--name=value`) and switch, by name
+fn parse(raw: Vec<String>) -> anyhow::Result<HashMap<String, String>> {
+    let mut options = HashMap::new();
+    let mut raw = raw.into_iter();
+    while let Some(arg) = raw.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            return Err(usage(format!("unexpected argument '{}'", arg)));
+        };
+        let (name, value) = match name.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None if SWITCHES.contains(&name) => (name.to_string(), String::new()),
+            None => {
+                let value = raw.next().ok_or_else(|| usage(format!("--{} needs a value", name)))?;
+                (name.to_string(), value)
+            }
+        };
+        options.insert(name, value);
+    }
+    Ok(options)
+}
+
+fn number<T: std::str::FromStr>(options: &HashMap<String, String>, name: &str) -> anyhow::Result<Option<T>> {
+    options
+        .get(name)
+        .map(|value| value.parse().map_err(|_| usage(format!("--{} must be a number (got {})", name, value))))
+        .transpose()
+}
+
+fn bench_config(options: &HashMap<String, String>) -> anyhow::Result<BenchConfig> {
+    let defaults = BenchConfig::default();
+    let requests = number(options, "requests")?;
+    let duration = match number::<f64>(options, "duration")? {
+        Some(secs) if secs > 0.0 => Some(Duration::from_secs_f64(secs)),
+        Some(secs) => return Err(usage(format!("--duration must be positive (got {})", secs))),
+        None if requests.is_some() => None,
+        None => defaults.duration,
+    };
+    let concurrency = number(options, "concurrency")?.unwrap_or(defaults.concurrency);
+    if concurrency == 0 {
+        return Err(usage("--concurrency must be at least 1"));
+    }
+    let distribution: SizeDistribution = match options.get("prompt-distribution") {
+        Some(name) => name.parse().map_err(usage)?,
+        None => defaults.prompt_sizes.distribution,
+    };
+    let prompt_sizes = match options.get("prompt-words") {
+        Some(range) => PromptSizes::parse_range(range, distribution).map_err(usage)?,
+        None => PromptSizes { distribution, ..defaults.prompt_sizes },
+    };
+    Ok(BenchConfig {
+        concurrency,
+        duration,
+        requests,
+        seed: number(options, "seed")?.unwrap_or(defaults.seed),
+        mix: match options.get("mix") {
+            Some(mix) => mix.parse().map_err(usage)?,
+            None => defaults.mix,
+        },
+        prompt_sizes,
+        agents: number(options, "agents")?.unwrap_or(defaults.agents).max(1),
+        max_tokens: number(options, "max-tokens")?.unwrap_or(defaults.max_tokens),
+    })
+}
+
+/// The service of the config at `path`, or the default one, with its
+/// knowledge bases opened as the server opens them. Whatever backends the
+/// config or the environment name, the mock ones answer.
+async fn in_process(path: Option<PathBuf>, no_chaos: bool) -> anyhow::Result<Arc<VoidShrineMCP>> {
+    let mut config = Config::load(path.as_deref())?;
+    config.backends = Default::default();
+    let service = VoidShrineMCP::new(&config)?;
+    if no_chaos {
+        service.chaos_config.write().await.enabled = false;
+    }
+    let engines = std::iter::once((None, config.rag.default_engine()))
+        .chain(config.rag.engines.iter().map(|(name, engine)| (Some(name.as_str()), engine.clone())));
+    for (name, settings) in engines {
+        let rag = settings.open().await.with_context(|| format!("knowledge base {}", name.unwrap_or("default")))?;
+        *service.rag_engines.get("engine", name)?.write().await = Some(rag);
+    }
+    Ok(Arc::new(service))
+}
+
+async fn run(raw: Vec<String>) -> anyhow::Result<()> {
+    let options = parse(raw)?;
+    if options.contains_key("help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let config = bench_config(&options)?;
+    let in_process_run = options.contains_key("in-process");
+    if in_process_run && options.contains_key("url") {
+        return Err(usage("--url and --in-process are exclusive"));
+    }
+    if !in_process_run && (options.contains_key("config") || options.contains_key("no-chaos")) {
+        return Err(usage("--config and --no-chaos need --in-process"));
+    }
+
+    let timings = RagTimings::default();
+    let target = if in_process_run {
+        // The service's spans are timed, not logged
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(timings.clone()))
+            .context("installing the retrieval timer")?;
+        let path = options.get("config").map(PathBuf::from).or_else(|| std::env::var_os("VOID_SHRINE_CONFIG").map(PathBuf::from));
+        Target::InProcess(in_process(path, options.contains_key("no-chaos")).await?)
+    } else {
+        let url = options
+            .get("url")
+            .cloned()
+            .or_else(|| std::env::var("VOID_SHRINE_URL").ok())
+            .unwrap_or_else(|| "http://localhost:3030".to_string());
+        Target::http(&url, std::env::var("VOID_SHRINE_API_KEY").ok())
+    };
+
+    let report = bench::run(Arc::new(target), &config, in_process_run.then_some(&timings)).await;
+    let json = serde_json::to_string_pretty(&report)?;
+    if let Some(path) = options.get("report") {
+        std::fs::write(path, &json).with_context(|| format!("writing the report to {}", path))?;
+    }
+    if options.contains_key("json") {
+        println!("{}", json);
+    } else {
+        print!("{}", report);
+    }
+    Ok(())
+}
@@ -0,0 +1,566 @@
+//! Command-line client for a running Void Shrine server.
+//!
+//!     void-shrine-cli [--url URL] [--json] <command>
+//!
+//! The server is `--url`, else `VOID_SHRINE_URL`, else http://localhost:3030;
+//! requests carry `VOID_SHRINE_API_KEY` as a bearer key when it is set.
+//! Server errors are shown as the server structured them and exit with 1;
+//! usage errors exit with 2.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::process::ExitCode;
+
+use anyhow::Context;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use void_shrine_mcp::mcp_server::{
+    AgentListResponse, ChaosConfig, DeleteDocumentsResponse, ErrorResponse, IndexUrlResponse, InferenceEvent,
+    MCPResponse, MetricsResponse, RagSearchResponse,
+};
+use void_shrine_mcp::rag_engine::RAGStats;
+
+const USAGE: &str = "\
+usage: void-shrine-cli [--url URL] [--json] <command>
+
+commands:
+  infer --agent ID --prompt TEXT [--specialty S] [--model M] [--max-tokens N]
+        [--temperature T] [--context-window N] [--session ID] [--rag] [--stream]
+  rag query TEXT [--limit N] [--tag TAG]... [--filter KEY=PATTERN]...
+  rag index (--content TEXT | --file PATH) [--title T] [--id ID] [--metadata KEY=VALUE]...
+  rag delete ID
+  rag stats
+  chaos get
+  chaos set [--enabled | --disabled] [--intensity X] [--types A,B,...] [--seed N]
+  agents list
+  metrics [--agent ID]
+
+environment:
+  VOID_SHRINE_URL      server base URL (default http://localhost:3030)
+  VOID_SHRINE_API_KEY  bearer key sent with every request";
+
+/// Options that take no value
+const SWITCHES: &[&str] = &["json", "rag", "stream", "enabled", "disabled", "help"];
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => match e.downcast::<Usage>() {
+            Ok(usage) => {
+                eprintln!("{}\n\n{}", usage, USAGE);
+                ExitCode::from(2)
+            }
+            // Server errors format themselves, in the form `--json` asked for
+            Err(e) if e.is::<ServerError>() => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+            Err(e) => {
+                eprintln!("error: {:#}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+/// A command line that doesn't parse
+#[derive(Debug)]
+struct Usage(String);
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.0)
+    }
+}
+
+impl std::error::Error for Usage {}
+
+fn usage(message: impl Into<String>) -> anyhow::Error {
+    Usage(message.into()).into()
+}
+
+/// A non-2xx answer, with the server's error body when it sent one
+#[derive(Debug)]
+struct ServerError {
+    status: StatusCode,
+    body: Result<ErrorResponse, String>,
+    json: bool,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let error = match &self.body {
+            Ok(error) if self.json => return write!(f, "{}", serde_json::to_string_pretty(error).map_err(|_| fmt::Error)?),
+            Ok(error) => error,
+            Err(body) if body.trim().is_empty() => return write!(f, "error: HTTP {}", self.status),
+            Err(body) => return write!(f, "error: HTTP {}: {}", self.status, body.trim()),
+        };
+        write!(f, "error: {} (HTTP {}): {}", error.error, self.status.as_u16(), error.message)?;
+        for field in &error.fields {
+            write!(f, "\n  {}: {} (got {})", field.field, field.constraint, field.value)?;
+        }
+        if let Some(request_id) = &error.request_id {
+            write!(f, "\n  request id: {}", request_id)?;
+        }
+        if let Some(retry_after_ms) = error.retry_after_ms {
+            write!(f, "\n  retry after: {} ms", retry_after_ms)?;
+        }
+        if let Some(limit_bytes) = error.limit_bytes {
+            write!(f, "\n  limit: {} bytes", limit_bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// The command line after the global options: positional words in order,
+/// then every `--name value` (or `--name=value`) and switch
+struct Args {
+    words: std::collections::VecDeque<String>,
+    options: HashMap<String, Vec<String>>,
+}
+
+impl Args {
+    fn parse(raw: Vec<String>) -> anyhow::Result<Args> {
+        let mut words = std::collections::VecDeque::new();
+        let mut options: HashMap<String, Vec<String>> = HashMap::new();
+        let mut raw = raw.into_iter();
+        while let Some(arg) = raw.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                words.push_back(arg);
+                continue;
+            };
+            let (name, value) = match name.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None if SWITCHES.contains(&name) => (name.to_string(), String::new()),
+                None => {
+                    let value = raw.next().ok_or_else(|| usage(format!("--{} needs a value", name)))?;
+                    (name.to_string(), value)
+                }
+            };
+            options.entry(name).or_default().push(value);
+        }
+        Ok(Args { words, options })
+    }
+
+    fn word(&mut self, what: &str) -> anyhow::Result<String> {
+        self.words.pop_front().ok_or_else(|| usage(format!("missing {}", what)))
+    }
+
+    fn switch(&mut self, name: &str) -> bool {
+        self.options.remove(name).is_some()
+    }
+
+    fn value(&mut self, name: &str) -> anyhow::Result<Option<String>> {
+        match self.options.remove(name) {
+            None => Ok(None),
+            Some(mut values) if values.len() == 1 => Ok(values.pop()),
+            Some(_) => Err(usage(format!("--{} given more than once", name))),
+        }
+    }
+
+    fn required(&mut self, name: &str) -> anyhow::Result<String> {
+        self.value(name)?.ok_or_else(|| usage(format!("--{} is required", name)))
+    }
+
+    fn parsed<T: std::str::FromStr>(&mut self, name: &str) -> anyhow::Result<Option<T>> {
+        self.value(name)?
+            .map(|value| value.parse().map_err(|_| usage(format!("--{}: invalid value {:?}", name, value))))
+            .transpose()
+    }
+
+    fn values(&mut self, name: &str) -> Vec<String> {
+        self.options.remove(name).unwrap_or_default()
+    }
+
+    /// `KEY=VALUE` pairs of a repeatable option
+    fn pairs(&mut self, name: &str) -> anyhow::Result<HashMap<String, String>> {
+        self.values(name)
+            .into_iter()
+            .map(|pair| match pair.split_once('=') {
+                Some((key, value)) => Ok((key.to_string(), value.to_string())),
+                None => Err(usage(format!("--{} takes KEY=VALUE, got {:?}", name, pair))),
+            })
+            .collect()
+    }
+
+    /// Refuses whatever the command didn't use
+    fn finish(self) -> anyhow::Result<()> {
+        if let Some(word) = self.words.front() {
+            return Err(usage(format!("unexpected argument {:?}", word)));
+        }
+        match self.options.keys().min() {
+            Some(name) => Err(usage(format!("unknown option --{}", name))),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Client {
+    http: reqwest::Client,
+    base: String,
+    api_key: Option<String>,
+    json: bool,
+}
+
+impl Client {
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.base, path));
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// Sends `request`, failing with the server's error unless it succeeded
+    async fn send(&self, request: reqwest::RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let response = request.send().await.with_context(|| format!("cannot reach {}", self.base))?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let body = serde_json::from_str::<ErrorResponse>(&body).map_err(|_| body);
+        Err(ServerError { status, body, json: self.json }.into())
+    }
+
+    /// The response body both raw, for `--json`, and as `T`
+    async fn call<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> anyhow::Result<(Value, T)> {
+        let body: Value = self.send(request).await?.json().await.context("reading the response")?;
+        let parsed = serde_json::from_value(body.clone()).context("unexpected response from the server")?;
+        Ok((body, parsed))
+    }
+}
+
+async fn run(raw: Vec<String>) -> anyhow::Result<()> {
+    let mut args = Args::parse(raw)?;
+    if args.switch("help") || args.words.is_empty() {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let base = match args.value("url")? {
+        Some(url) => url,
+        None => std::env::var("VOID_SHRINE_URL").unwrap_or_else(|_| "http://localhost:3030".to_string()),
+    };
+    let client = Client {
+        http: reqwest::Client::new(),
+        base: base.trim_end_matches('/').to_string(),
+        api_key: std::env::var("VOID_SHRINE_API_KEY").ok().filter(|key| !key.is_empty()),
+        json: args.switch("json"),
+    };
+
+    let command = args.word("command")?;
+    match command.as_str() {
+        "infer" => infer(&client, args).await,
+        "rag" => match args.word("rag subcommand")?.as_str() {
+            "query" => rag_query(&client, args).await,
+            "index" => rag_index(&client, args).await,
+            "delete" => rag_delete(&client, args).await,
+            "stats" => rag_stats(&client, args).await,
+            other => Err(usage(format!("unknown rag subcommand {:?}", other))),
+        },
+        "chaos" => match args.word("chaos subcommand")?.as_str() {
+            "get" => chaos_get(&client, args).await,
+            "set" => chaos_set(&client, args).await,
+            other => Err(usage(format!("unknown chaos subcommand {:?}", other))),
+        },
+        "agents" => match args.word("agents subcommand")?.as_str() {
+            "list" => agents_list(&client, args).await,
+            other => Err(usage(format!("unknown agents subcommand {:?}", other))),
+        },
+        "metrics" => metrics(&client, args).await,
+        other => Err(usage(format!("unknown command {:?}", other))),
+    }
+}
+
+fn print_json(value: &Value) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn infer(client: &Client, mut args: Args) -> anyhow::Result<()> {
+    let mut params = json!({
+        "agent_id": args.required("agent")?,
+        "prompt": args.required("prompt")?,
+        "specialty": args.value("specialty")?.unwrap_or_default(),
+        "model": args.value("model")?.unwrap_or_default(),
+        "max_tokens": args.parsed::<u32>("max-tokens")?.unwrap_or(512),
+        "temperature": args.parsed::<f64>("temperature")?.unwrap_or(0.7),
+        "context_window": args.parsed::<u32>("context-window")?.unwrap_or(4096),
+        "use_rag": args.switch("rag"),
+    });
+    if let Some(session) = args.value("session")? {
+        params["session_id"] = json!(session);
+    }
+    let stream = args.switch("stream");
+    args.finish()?;
+    if stream {
+        return infer_stream(client, params).await;
+    }
+
+    let request = client.request(Method::POST, "/api/mcp").json(&json!({ "method": "llm_inference", "params": params }));
+    let (body, response): (_, MCPResponse) = client.call(request).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    println!("{}", response.result.response);
+    for citation in response.result.citations.iter().flatten() {
+        println!("[{}] {} ({}, score {:.3})", citation.index, citation.title, citation.document_id, citation.score);
+    }
+    let metrics = &response.result.metrics;
+    eprintln!(
+        "request {}: {} tokens in {} ms, confidence {:.3}{}",
+        response.metadata.request_id,
+        metrics.token_count,
+        metrics.response_time_ms,
+        metrics.confidence_score,
+        response.metadata.chaos_type.as_deref().map(|chaos| format!(", chaos {}", chaos)).unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Prints deltas as they arrive; `--json` prints every event as a line of JSON
+async fn infer_stream(client: &Client, params: Value) -> anyhow::Result<()> {
+    let mut response = client.send(client.request(Method::POST, "/api/mcp/stream").json(&params)).await?;
+    let mut buffer = String::new();
+    let mut finished = false;
+    let mut stdout = std::io::stdout();
+    while let Some(chunk) = response.chunk().await.context("reading the event stream")? {
+        buffer.push_str(&String::from_utf8_lossy(&chunk).replace('\r', ""));
+        while let Some(end) = buffer.find("\n\n") {
+            let block: String = buffer.drain(..end + 2).collect();
+            let data: Vec<&str> = block.lines().filter_map(|line| line.strip_prefix("data:")).map(str::trim_start).collect();
+            if data.is_empty() {
+                // Keep-alive comments
+                continue;
+            }
+            let data = data.join("\n");
+            if client.json {
+                println!("{}", data);
+            }
+            let event: InferenceEvent = serde_json::from_str(&data).with_context(|| format!("unexpected event {}", data))?;
+            match event {
+                InferenceEvent::Delta { text } if !client.json => {
+                    print!("{}", text);
+                    stdout.flush()?;
+                }
+                InferenceEvent::Done { metrics, metadata, .. } => {
+                    finished = true;
+                    if !client.json {
+                        println!();
+                        eprintln!(
+                            "request {}: {} tokens in {} ms, confidence {:.3}",
+                            metadata.request_id, metrics.token_count, metrics.response_time_ms, metrics.confidence_score
+                        );
+                    }
+                }
+                InferenceEvent::Error { code, message } => {
+                    if !client.json {
+                        println!();
+                    }
+                    anyhow::bail!("stream failed ({}): {}", code, message);
+                }
+                _ => {}
+            }
+        }
+    }
+    if !finished {
+        anyhow::bail!("the stream ended without a response");
+    }
+    Ok(())
+}
+
+async fn rag_query(client: &Client, mut args: Args) -> anyhow::Result<()> {
+    let query = args.word("query text")?;
+    let mut body = json!({
+        "query": query,
+        "tags": args.values("tag"),
+        "filters": args.pairs("filter")?,
+    });
+    if let Some(limit) = args.parsed::<usize>("limit")? {
+        body["limit"] = json!(limit);
+    }
+    args.finish()?;
+
+    let (body, response): (_, RagSearchResponse) = client.call(client.request(Method::POST, "/api/rag/query").json(&body)).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    if response.results.is_empty() {
+        println!("No results");
+    }
+    for (i, result) in response.results.iter().enumerate() {
+        println!("{}. {} ({}) score {:.3}", i + 1, result.title, result.document_id, result.similarity_score);
+        println!("   {}", result.content.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+    Ok(())
+}
+
+async fn rag_index(client: &Client, mut args: Args) -> anyhow::Result<()> {
+    let file = args.value("file")?;
+    let content = match (args.value("content")?, &file) {
+        (Some(content), None) => content,
+        (None, Some(path)) => std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?,
+        _ => return Err(usage("rag index needs exactly one of --content and --file")),
+    };
+    let title = match (args.value("title")?, &file) {
+        (Some(title), _) => title,
+        (None, Some(path)) => std::path::Path::new(path).file_stem().map_or_else(|| path.clone(), |stem| stem.to_string_lossy().into_owned()),
+        (None, None) => return Err(usage("--title is required with --content")),
+    };
+    let mut body = json!({ "title": title, "content": content, "metadata": args.pairs("metadata")? });
+    if let Some(id) = args.value("id")? {
+        body["id"] = json!(id);
+    }
+    args.finish()?;
+
+    let (body, response): (_, IndexUrlResponse) = client.call(client.request(Method::POST, "/api/rag/documents").json(&body)).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    println!("Indexed document {}", response.document_id);
+    Ok(())
+}
+
+async fn rag_delete(client: &Client, mut args: Args) -> anyhow::Result<()> {
+    let id = args.word("document id")?;
+    args.finish()?;
+    // Ids are letters, digits, `-`, `_` and the like, so need no escaping
+    let request = client.request(Method::DELETE, &format!("/api/rag/documents/{}", id));
+    let (body, response): (_, DeleteDocumentsResponse) = client.call(request).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    println!("Deleted {} document{}", response.deleted, if response.deleted == 1 { "" } else { "s" });
+    Ok(())
+}
+
+async fn rag_stats(client: &Client, args: Args) -> anyhow::Result<()> {
+    args.finish()?;
+    let (body, stats): (_, RAGStats) = client.call(client.request(Method::GET, "/api/rag/stats")).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    println!("documents:    {}", stats.document_count);
+    println!("chunks:       {}", stats.chunk_count);
+    println!("chunk size:   {} (overlap {})", stats.chunk_size, stats.overlap_size);
+    println!("tokenizer:    {}", stats.tokenizer);
+    println!("journal mode: {}", stats.journal_mode);
+    Ok(())
+}
+
+fn print_chaos(config: &ChaosConfig) {
+    println!("enabled:   {}", config.enabled);
+    println!("intensity: {}", config.intensity);
+    println!("types:     {}", config.chaos_types.join(", "));
+    println!("seed:      {}", config.seed.map_or_else(|| "none".to_string(), |seed| seed.to_string()));
+}
+
+async fn chaos_get(client: &Client, args: Args) -> anyhow::Result<()> {
+    args.finish()?;
+    let (body, config): (_, ChaosConfig) = client.call(client.request(Method::GET, "/api/chaos/config")).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    print_chaos(&config);
+    Ok(())
+}
+
+/// Changes the given settings of the current config, leaving the rest
+async fn chaos_set(client: &Client, mut args: Args) -> anyhow::Result<()> {
+    let enabled = match (args.switch("enabled"), args.switch("disabled")) {
+        (true, true) => return Err(usage("--enabled and --disabled contradict each other")),
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None,
+    };
+    let intensity = args.parsed::<f64>("intensity")?;
+    let types = args.value("types")?;
+    let seed = args.parsed::<u64>("seed")?;
+    args.finish()?;
+
+    let (_, mut config): (Value, ChaosConfig) = client.call(client.request(Method::GET, "/api/chaos/config")).await?;
+    if let Some(enabled) = enabled {
+        config.enabled = enabled;
+    }
+    if let Some(intensity) = intensity {
+        config.intensity = intensity;
+    }
+    if let Some(types) = types {
+        config.chaos_types = types.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect();
+    }
+    if seed.is_some() {
+        config.seed = seed;
+    }
+    let (body, config): (_, ChaosConfig) = client.call(client.request(Method::PUT, "/api/chaos/config").json(&config)).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    print_chaos(&config);
+    Ok(())
+}
+
+async fn agents_list(client: &Client, args: Args) -> anyhow::Result<()> {
+    args.finish()?;
+    let (body, list): (_, AgentListResponse) = client.call(client.request(Method::GET, "/api/agents")).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    if list.agents.is_empty() {
+        println!("No agents");
+        return Ok(());
+    }
+    println!("{:<24} {:>6} {:>9} {:>9} {:>8}  LAST SEEN", "AGENT", "LOAD", "IN FLIGHT", "REQUESTS", "SUCCESS");
+    for agent in &list.agents {
+        let last_seen = agent.last_seen.map_or_else(|| "never".to_string(), |at| at.to_rfc3339());
+        println!(
+            "{:<24} {:>6.2} {:>9} {:>9} {:>7.1}%  {}{}",
+            agent.agent_id,
+            agent.current_load,
+            agent.in_flight,
+            agent.total_requests,
+            agent.success_rate * 100.0,
+            last_seen,
+            if agent.stale { " (stale)" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+async fn metrics(client: &Client, mut args: Args) -> anyhow::Result<()> {
+    let mut request = client.request(Method::GET, "/api/metrics");
+    if let Some(agent) = args.value("agent")? {
+        request = request.query(&[("agent_id", agent)]);
+    }
+    args.finish()?;
+    let (body, metrics): (_, MetricsResponse) = client.call(request).await?;
+    if client.json {
+        return print_json(&body);
+    }
+    let server = &metrics.server;
+    println!("requests:    {} since {}", server.total_requests, server.started_at.to_rfc3339());
+    for (method, count) in &server.requests_by_method {
+        println!("  {:<22} {}", method, count);
+    }
+    println!("errors:      {}", server.errors_by_class.values().sum::<u64>());
+    for (class, count) in &server.errors_by_class {
+        println!("  {:<22} {}", class, count);
+    }
+    println!("rag queries: {}", server.rag_queries);
+    println!("chaos:       {}", server.chaos_events);
+    for agent in &metrics.agents {
+        println!(
+            "agent {}: {} requests, {:.1}% ok, {:.0} ms average, load {:.2}",
+            agent.agent_id,
+            agent.total_requests,
+            agent.success_rate * 100.0,
+            agent.avg_response_time_ms,
+            agent.current_load
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,342 @@
+//! Circuit breakers, one per backend. A backend failing `failure_threshold`
+//! times within `window_secs` is cut off for `cooldown_secs`: calls to it
+//! fail fast, or go to its fallback when one is configured. Then up to
+//! `probes` calls at a time are let through; the first to succeed closes the
+//! breaker, the first to fail opens it again. Each change of state is
+//! published to the live metrics feed.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use crate::live::{LiveEvent, LiveFeed};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BreakerConfig {
+    pub enabled: bool,
+    pub failure_threshold: u32,
+    pub window_secs: u64,
+    pub cooldown_secs: u64,
+    /// Calls let through at once to see whether a backend recovered
+    pub probes: u32,
+    /// Backend to call, by name, while a backend's breaker is open
+    pub fallbacks: BTreeMap<String, String>,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self { enabled: true, failure_threshold: 5, window_secs: 30, cooldown_secs: 30, probes: 1, fallbacks: BTreeMap::new() }
+    }
+}
+
+impl BreakerConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.failure_threshold == 0 || self.window_secs == 0 || self.cooldown_secs == 0 || self.probes == 0 {
+            problems.push("breakers.failure_threshold, window_secs, cooldown_secs and probes must be positive".to_string());
+        }
+        for (backend, fallback) in &self.fallbacks {
+            if backend == fallback {
+                problems.push(format!("breakers.fallbacks: backend '{}' can't fall back to itself", backend));
+            }
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    /// Letting probes through
+    HalfOpen,
+}
+
+/// A breaker as the metrics endpoint shows it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BreakerReport {
+    pub backend: String,
+    pub state: BreakerState,
+    /// Failures within the window, while closed
+    pub recent_failures: u32,
+    /// Until probes are let through, while open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+    /// Times the breaker has opened
+    pub opened: u64,
+}
+
+#[derive(Debug)]
+enum Circuit {
+    Closed { failures: VecDeque<Instant> },
+    Open { until: Instant },
+    HalfOpen { probing: u32 },
+}
+
+#[derive(Debug)]
+struct Breaker {
+    circuit: Circuit,
+    opened: u64,
+}
+
+impl Breaker {
+    fn new() -> Self {
+        Self { circuit: Circuit::Closed { failures: VecDeque::new() }, opened: 0 }
+    }
+
+    fn open(&mut self, config: &BreakerConfig, now: Instant) {
+        self.circuit = Circuit::Open { until: now + Duration::from_secs(config.cooldown_secs) };
+        self.opened += 1;
+    }
+
+    fn report(&self, backend: &str, config: &BreakerConfig, now: Instant) -> BreakerReport {
+        let window = Duration::from_secs(config.window_secs);
+        let (state, recent_failures, retry_after) = match &self.circuit {
+            Circuit::Closed { failures } => {
+                let recent = failures.iter().filter(|failed| now.saturating_duration_since(**failed) < window).count();
+                (BreakerState::Closed, recent as u32, None)
+            }
+            Circuit::Open { until } if now < *until => (BreakerState::Open, 0, Some(*until - now)),
+            // Due to let probes through, which the next call does
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => (BreakerState::HalfOpen, 0, None),
+        };
+        BreakerReport {
+            backend: backend.to_string(),
+            state,
+            recent_failures,
+            retry_after_ms: retry_after.map(|wait| wait.as_millis() as u64),
+            opened: self.opened,
+        }
+    }
+}
+
+/// A call let through a breaker. Dropped without `finish`, e.g. when the
+/// request is cancelled, it counts neither way.
+#[must_use]
+pub struct Permit {
+    breaker: Option<(String, Arc<Mutex<Breaker>>)>,
+    probe: bool,
+    config: Arc<BreakerConfig>,
+    feed: Arc<LiveFeed>,
+}
+
+impl Permit {
+    /// Records the call's outcome: `healthy` unless the backend itself failed
+    pub fn finish(self, healthy: bool) {
+        self.finish_at(healthy, Instant::now());
+    }
+
+    fn finish_at(mut self, healthy: bool, now: Instant) {
+        let Some((backend, breaker)) = self.breaker.take() else {
+            return;
+        };
+        let mut breaker = breaker.lock().unwrap_or_else(|e| e.into_inner());
+        let window = Duration::from_secs(self.config.window_secs);
+        match (&mut breaker.circuit, healthy) {
+            (Circuit::HalfOpen { .. }, true) => {
+                tracing::info!("Circuit breaker for backend {} closed: a probe succeeded", backend);
+                breaker.circuit = Circuit::Closed { failures: VecDeque::new() };
+                transition(&self.feed, &backend, BreakerState::HalfOpen, BreakerState::Closed);
+            }
+            (Circuit::HalfOpen { .. }, false) => {
+                tracing::warn!("Circuit breaker for backend {} reopened: a probe failed", backend);
+                breaker.open(&self.config, now);
+                transition(&self.feed, &backend, BreakerState::HalfOpen, BreakerState::Open);
+            }
+            (Circuit::Closed { failures }, false) => {
+                while failures.front().is_some_and(|failed| now.saturating_duration_since(*failed) >= window) {
+                    failures.pop_front();
+                }
+                failures.push_back(now);
+                if failures.len() >= self.config.failure_threshold as usize {
+                    tracing::warn!(
+                        "Circuit breaker for backend {} opened after {} failures in {}s",
+                        backend,
+                        failures.len(),
+                        self.config.window_secs
+                    );
+                    breaker.open(&self.config, now);
+                    transition(&self.feed, &backend, BreakerState::Closed, BreakerState::Open);
+                }
+            }
+            // Calls let through before the breaker opened, finishing late
+            _ => {}
+        }
+    }
+}
+
+fn transition(feed: &LiveFeed, backend: &str, from: BreakerState, to: BreakerState) {
+    feed.publish(LiveEvent::CircuitTransition { backend: backend.to_string(), from, to });
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let (true, Some((_, breaker))) = (self.probe, &self.breaker) {
+            let mut breaker = breaker.lock().unwrap_or_else(|e| e.into_inner());
+            if let Circuit::HalfOpen { probing } = &mut breaker.circuit {
+                *probing = probing.saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// Breakers by backend name, made on first use
+#[derive(Debug)]
+pub struct Breakers {
+    config: Arc<BreakerConfig>,
+    breakers: DashMap<String, Arc<Mutex<Breaker>>>,
+    feed: Arc<LiveFeed>,
+}
+
+impl Default for Breakers {
+    fn default() -> Self {
+        Self::new(BreakerConfig::default())
+    }
+}
+
+impl Breakers {
+    pub fn new(config: BreakerConfig) -> Self {
+        Self { config: Arc::new(config), breakers: DashMap::new(), feed: Arc::new(LiveFeed::default()) }
+    }
+
+    /// Publishes changes of state to `feed`
+    pub fn with_feed(mut self, feed: Arc<LiveFeed>) -> Self {
+        self.feed = feed;
+        self
+    }
+
+    pub fn config(&self) -> &BreakerConfig {
+        &self.config
+    }
+
+    pub fn fallback(&self, backend: &str) -> Option<&str> {
+        self.config.fallbacks.get(backend).map(String::as_str)
+    }
+
+    /// Lets a call to `backend` through, or says how long its breaker stays open
+    pub fn acquire(&self, backend: &str) -> Result<Permit, Duration> {
+        self.acquire_at(backend, Instant::now())
+    }
+
+    fn acquire_at(&self, backend: &str, now: Instant) -> Result<Permit, Duration> {
+        if !self.config.enabled {
+            return Ok(Permit { breaker: None, probe: false, config: Arc::clone(&self.config), feed: Arc::clone(&self.feed) });
+        }
+        let entry = Arc::clone(&self.breakers.entry(backend.to_string()).or_insert_with(|| Arc::new(Mutex::new(Breaker::new()))));
+        let mut breaker = entry.lock().unwrap_or_else(|e| e.into_inner());
+        let probe = match &mut breaker.circuit {
+            Circuit::Closed { .. } => false,
+            Circuit::Open { until } if now < *until => return Err(*until - now),
+            Circuit::Open { .. } => {
+                tracing::info!("Circuit breaker for backend {} half open: letting probes through", backend);
+                breaker.circuit = Circuit::HalfOpen { probing: 1 };
+                transition(&self.feed, backend, BreakerState::Open, BreakerState::HalfOpen);
+                true
+            }
+            Circuit::HalfOpen { probing } if *probing < self.config.probes => {
+                *probing += 1;
+                true
+            }
+            // Probes are out already; the next one goes when one finishes
+            Circuit::HalfOpen { .. } => return Err(Duration::from_secs(1)),
+        };
+        drop(breaker);
+        Ok(Permit { breaker: Some((backend.to_string(), entry)), probe, config: Arc::clone(&self.config), feed: Arc::clone(&self.feed) })
+    }
+
+    /// The state of `backend`'s breaker; closed until it is first called
+    pub fn state(&self, backend: &str) -> BreakerState {
+        self.breakers.get(backend).map_or(BreakerState::Closed, |entry| {
+            entry.value().lock().unwrap_or_else(|e| e.into_inner()).report(backend, &self.config, Instant::now()).state
+        })
+    }
+
+    /// Every backend called so far, by name
+    pub fn reports(&self) -> Vec<BreakerReport> {
+        let now = Instant::now();
+        let mut reports: Vec<BreakerReport> = self
+            .breakers
+            .iter()
+            .map(|entry| entry.value().lock().unwrap_or_else(|e| e.into_inner()).report(entry.key(), &self.config, now))
+            .collect();
+        reports.sort_by(|a, b| a.backend.cmp(&b.backend));
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breakers(probes: u32) -> Breakers {
+        Breakers::new(BreakerConfig { failure_threshold: 3, window_secs: 10, cooldown_secs: 30, probes, ..BreakerConfig::default() })
+    }
+
+    fn fail(breakers: &Breakers, now: Instant) {
+        breakers.acquire_at("flaky", now).unwrap().finish_at(false, now);
+    }
+
+    fn state(breakers: &Breakers) -> BreakerState {
+        breakers.reports()[0].state
+    }
+
+    #[test]
+    fn opens_after_enough_failures_within_the_window() {
+        let breakers = breakers(1);
+        let start = Instant::now();
+        // The first failure leaves the window before the third
+        fail(&breakers, start);
+        fail(&breakers, start + Duration::from_secs(8));
+        fail(&breakers, start + Duration::from_secs(12));
+        assert_eq!(state(&breakers), BreakerState::Closed);
+        assert_eq!(breakers.reports()[0].recent_failures, 2);
+
+        fail(&breakers, start + Duration::from_secs(13));
+        let retry_after = breakers.acquire_at("flaky", start + Duration::from_secs(23)).err().unwrap();
+        assert_eq!(retry_after, Duration::from_secs(20));
+        assert_eq!((state(&breakers), breakers.reports()[0].opened), (BreakerState::Open, 1));
+        assert!(breakers.acquire_at("steady", start).is_ok());
+    }
+
+    #[test]
+    fn probes_after_the_cooldown_close_or_reopen_it() {
+        let breakers = breakers(2);
+        let start = Instant::now();
+        for _ in 0..3 {
+            fail(&breakers, start);
+        }
+        let after_cooldown = start + Duration::from_secs(30);
+
+        // Two probes at a time; one abandoned frees its place
+        let first = breakers.acquire_at("flaky", after_cooldown).unwrap();
+        let second = breakers.acquire_at("flaky", after_cooldown).unwrap();
+        assert!(breakers.acquire_at("flaky", after_cooldown).is_err());
+        drop(second);
+        let third = breakers.acquire_at("flaky", after_cooldown).unwrap();
+
+        first.finish_at(false, after_cooldown);
+        assert!(breakers.acquire_at("flaky", after_cooldown).is_err());
+        assert_eq!(breakers.reports()[0].opened, 2);
+        // A late probe finishing after the breaker reopened changes nothing
+        third.finish_at(true, after_cooldown);
+        assert_eq!(state(&breakers), BreakerState::Open);
+
+        let later = after_cooldown + Duration::from_secs(30);
+        breakers.acquire_at("flaky", later).unwrap().finish_at(true, later);
+        assert_eq!(state(&breakers), BreakerState::Closed);
+        assert_eq!(breakers.reports()[0].recent_failures, 0);
+    }
+
+    #[test]
+    fn disabled_breakers_never_open() {
+        let breakers = Breakers::new(BreakerConfig { enabled: false, failure_threshold: 1, ..BreakerConfig::default() });
+        let now = Instant::now();
+        for _ in 0..3 {
+            breakers.acquire_at("flaky", now).unwrap().finish_at(false, now);
+        }
+        assert!(breakers.reports().is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+//! What this binary was built from, as recorded by `build.rs`, for telling
+//! deployed instances apart: served at GET /api/version, and as a short
+//! version string in every response's `Server` header and the startup log.
+
+use std::sync::OnceLock;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The crate version
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Cargo features this build was compiled with
+pub const FEATURES: &[(&str, bool)] = &[
+    ("grpc", cfg!(feature = "grpc")),
+    ("otel", cfg!(feature = "otel")),
+    ("tiktoken", cfg!(feature = "tiktoken")),
+    ("watch", cfg!(feature = "watch")),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    /// Full hash of the commit built; None outside a git checkout
+    pub git_commit: Option<String>,
+    /// Whether tracked files differed from that commit
+    pub git_dirty: Option<bool>,
+    /// `SOURCE_DATE_EPOCH` when set, for reproducible builds
+    pub built_at: Option<DateTime<Utc>>,
+    pub rustc: Option<String>,
+    pub features: Vec<String>,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: VERSION.to_string(),
+            git_commit: option_env!("VOID_SHRINE_GIT_COMMIT").map(str::to_string),
+            git_dirty: option_env!("VOID_SHRINE_GIT_DIRTY").and_then(|dirty| dirty.parse().ok()),
+            built_at: option_env!("VOID_SHRINE_BUILT_AT")
+                .and_then(|secs| secs.parse().ok())
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            rustc: option_env!("VOID_SHRINE_RUSTC_VERSION").map(str::to_string),
+            features: FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect(),
+        }
+    }
+
+    /// `void-shrine-mcp/0.1.0 (3f2a9c1)`, with `-dirty` after the commit when
+    /// the tree had changes, and no parenthesis without a commit
+    pub fn version_string(&self) -> String {
+        let product = format!("{}/{}", env!("CARGO_PKG_NAME"), self.version);
+        match &self.git_commit {
+            Some(commit) => {
+                let dirty = if self.git_dirty == Some(true) { "-dirty" } else { "" };
+                format!("{} ({}{})", product, &commit[..commit.len().min(7)], dirty)
+            }
+            None => product,
+        }
+    }
+}
+
+/// `BuildInfo::current().version_string()`, worked out once
+pub fn version_string() -> &'static str {
+    static VERSION_STRING: OnceLock<String> = OnceLock::new();
+    VERSION_STRING.get_or_init(|| BuildInfo::current().version_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_strings_name_a_short_commit() {
+        let mut build = BuildInfo::current();
+        build.version = "1.2.3".to_string();
+        build.git_commit = Some("3f2a9c1d0e5b".to_string());
+        build.git_dirty = Some(false);
+        assert_eq!(build.version_string(), "void-shrine-mcp/1.2.3 (3f2a9c1)");
+        build.git_dirty = Some(true);
+        assert_eq!(build.version_string(), "void-shrine-mcp/1.2.3 (3f2a9c1-dirty)");
+        build.git_commit = None;
+        assert_eq!(build.version_string(), "void-shrine-mcp/1.2.3");
+    }
+}
@@ -0,0 +1,265 @@
+//! Responses to identical `llm_inference` requests, kept for a while so
+//! retries and fan-out don't each pay for a completion. Off unless enabled.
+//! Requests are identical when method, model, specialty, the final prompt,
+//! temperature, max_tokens and `flat_rag_context` all match and the knowledge
+//! base is unchanged: keys carry the RAG engine's `generation`, so reindexing
+//! strands earlier answers. Requests hotter than `max_temperature` want
+//! variety and bypass the cache.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use ring::digest::{Context, Digest, SHA256};
+use serde::{Deserialize, Serialize};
+use crate::llm_backend::Prompt;
+use crate::mcp_server::{MCPParams, MCPResult};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Entries older than this are not served
+    pub ttl_secs: u64,
+    /// Entries kept; the oldest makes way for a new one
+    pub max_entries: usize,
+    /// Requests with a higher temperature bypass the cache
+    pub max_temperature: f64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { enabled: false, ttl_secs: 300, max_entries: 1000, max_temperature: 0.3 }
+    }
+}
+
+impl CacheConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.ttl_secs == 0 || self.max_entries == 0 {
+            problems.push("cache.ttl_secs and max_entries must be positive".to_string());
+        }
+        if self.max_temperature.is_nan() || self.max_temperature < 0.0 {
+            problems.push(format!("cache.max_temperature must not be negative (got {})", self.max_temperature));
+        }
+        problems
+    }
+}
+
+/// SHA-256 of everything that makes two requests identical
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey([u8; 32]);
+
+impl CacheKey {
+    /// `generation` is the RAG engine's when the prompt drew on it
+    pub fn new(method: &str, params: &MCPParams, prompt: &Prompt, generation: Option<u64>) -> Self {
+        let mut context = Context::new(&SHA256);
+        // Length-prefixed, so no two field lists run together the same way
+        let mut field = |bytes: &[u8]| {
+            context.update(&(bytes.len() as u64).to_le_bytes());
+            context.update(bytes);
+        };
+        field(method.as_bytes());
+        field(params.model.as_bytes());
+        field(params.specialty.as_bytes());
+        field(prompt.system.as_deref().unwrap_or_default().as_bytes());
+        field(&[prompt.system.is_some() as u8]);
+        field(prompt.user.as_bytes());
+        field(&params.temperature.to_bits().to_le_bytes());
+        field(&params.max_tokens.to_le_bytes());
+        field(&[params.flat_rag_context as u8]);
+        field(&generation.map_or([0; 9], |generation| {
+            let mut bytes = [1; 9];
+            bytes[1..].copy_from_slice(&generation.to_le_bytes());
+            bytes
+        }));
+        Self(digest_bytes(context.finish()))
+    }
+}
+
+fn digest_bytes(digest: Digest) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    bytes.copy_from_slice(digest.as_ref());
+    bytes
+}
+
+#[derive(Debug)]
+struct Entry {
+    result: MCPResult,
+    stored: Instant,
+}
+
+/// Counts for the metrics endpoint
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub entries: usize,
+    pub hits: u64,
+    /// Lookups that found nothing fresh; bypassing requests aren't counted
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+pub struct ResponseCache {
+    config: CacheConfig,
+    ttl: Duration,
+    entries: DashMap<CacheKey, Entry>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new(CacheConfig::default())
+    }
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Self {
+        let ttl = Duration::from_secs(config.ttl_secs);
+        Self { config, ttl, entries: DashMap::new(), hits: AtomicU64::new(0), misses: AtomicU64::new(0) }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// The request's key, or None when it bypasses the cache
+    pub fn key(&self, method: &str, params: &MCPParams, prompt: &Prompt, generation: Option<u64>) -> Option<CacheKey> {
+        if !self.config.enabled || params.temperature > self.config.max_temperature {
+            return None;
+        }
+        Some(CacheKey::new(method, params, prompt, generation))
+    }
+
+    /// The stored result, unless missing or expired; counts a hit or a miss
+    pub fn get(&self, key: &CacheKey) -> Option<MCPResult> {
+        let now = Instant::now();
+        let found = match self.entries.get(key) {
+            Some(entry) if now.saturating_duration_since(entry.stored) < self.ttl => Some(entry.result.clone()),
+            Some(entry) => {
+                drop(entry);
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Stores `result`, first dropping expired entries and then the oldest
+    /// if `max_entries` are kept
+    pub fn insert(&self, key: CacheKey, result: MCPResult) {
+        let now = Instant::now();
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.config.max_entries {
+            self.entries.retain(|_, entry| now.saturating_duration_since(entry.stored) < self.ttl);
+            if self.entries.len() >= self.config.max_entries {
+                let oldest = self.entries.iter().min_by_key(|entry| entry.stored).map(|entry| *entry.key());
+                if let Some(oldest) = oldest {
+                    self.entries.remove(&oldest);
+                }
+            }
+        }
+        self.entries.insert(key, Entry { result, stored: now });
+    }
+
+    /// Drops every entry, returning how many there were
+    pub fn clear(&self) -> usize {
+        let cleared = self.entries.len();
+        self.entries.clear();
+        cleared
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            enabled: self.config.enabled,
+            entries: self.entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_server::ResponseMetrics;
+
+    fn params(temperature: f64) -> MCPParams {
+        serde_json::from_value(serde_json::json!({
+            "agent_id": "agent", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+            "max_tokens": 64, "temperature": temperature, "use_rag": false, "context_window": 4096
+        }))
+        .unwrap()
+    }
+
+    fn result(response: &str) -> MCPResult {
+        MCPResult {
+            response: response.to_string(),
+            metrics: ResponseMetrics {
+                response_time_ms: 0,
+                token_count: 0,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                rag_documents_used: 0,
+                confidence_score: 1.0,
+                confidence_breakdown: None,
+                throttle_delay_ms: 0,
+                rag_chunks_included: 0,
+                rag_chunks_dropped: 0,
+                attempts: 0,
+                retry_delay_ms: 0,
+                retrieval: None,
+                sampling: None,
+            },
+            rag_context: None,
+            citations: None,
+            moral_recentering: None,
+            prompt: None,
+            prompt_preview: None,
+        }
+    }
+
+    fn enabled(max_entries: usize) -> ResponseCache {
+        ResponseCache::new(CacheConfig { enabled: true, max_entries, ..CacheConfig::default() })
+    }
+
+    #[test]
+    fn keys_cover_the_prompt_sampling_and_corpus() {
+        let prompt = Prompt::user("care ethics");
+        let key = |params: &MCPParams, prompt: &Prompt, generation| CacheKey::new("llm_inference", params, prompt, generation);
+        let base = key(&params(0.0), &prompt, Some(1));
+        assert_eq!(base, key(&params(0.0), &prompt, Some(1)));
+        assert_ne!(base, key(&params(0.1), &prompt, Some(1)));
+        assert_ne!(base, key(&params(0.0), &prompt.clone().with_system("Be brief."), Some(1)));
+        assert_ne!(base, key(&params(0.0), &prompt, Some(2)));
+        assert_ne!(base, key(&params(0.0), &prompt, None));
+        assert_ne!(base, CacheKey::new("rag_answer", &params(0.0), &prompt, Some(1)));
+    }
+
+    #[test]
+    fn hot_requests_and_disabled_caches_bypass() {
+        let prompt = Prompt::user("care ethics");
+        assert!(ResponseCache::default().key("llm_inference", &params(0.0), &prompt, None).is_none());
+        let cache = enabled(10);
+        assert!(cache.key("llm_inference", &params(0.3), &prompt, None).is_some());
+        assert!(cache.key("llm_inference", &params(0.9), &prompt, None).is_none());
+    }
+
+    #[test]
+    fn the_oldest_entry_makes_way_and_counts_are_kept() {
+        let cache = enabled(2);
+        let keys: Vec<CacheKey> = (0..3).map(|i| CacheKey::new("llm_inference", &params(0.0), &Prompt::user(i.to_string()), None)).collect();
+        for (i, key) in keys.iter().enumerate() {
+            cache.insert(*key, result(&i.to_string()));
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert!(cache.get(&keys[0]).is_none());
+        assert_eq!(cache.get(&keys[2]).unwrap().response, "2");
+        assert_eq!(cache.stats(), CacheStats { enabled: true, entries: 2, hits: 1, misses: 1 });
+
+        assert_eq!(cache.clear(), 2);
+        assert!(cache.get(&keys[1]).is_none());
+    }
+}
@@ -0,0 +1,291 @@
+//! Every chaos decision, counted. Requests chaos may strike, through
+//! `llm_inference` and the other methods or as advice from `POST /api/chaos`,
+//! are counted as applied, shadowed or skipped, by chaos type, by agent and
+//! by specialty, with the delay advised and the errors injected. Shadowed decisions drew
+//! chaos of a type in shadow mode, and count the delay and errors it would
+//! have brought apart from the real ones. Each decision also adds the chance
+//! it was given, so the rate chaos actually struck can be set against the
+//! rate the config promised. Lifetime totals go in `GET /api/metrics`;
+//! per-minute buckets, kept for `metrics.chaos_retention_hours`, back
+//! `GET /api/chaos/report`.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What one decision came to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChaosOutcome<'a> {
+    /// Chaos struck with `chaos_type`, advising `delay_ms` of delay
+    Applied { chaos_type: &'a str, delay_ms: u64 },
+    /// Chaos drew `chaos_type` in shadow mode, and would have advised `delay_ms`
+    Shadowed { chaos_type: &'a str, delay_ms: u64 },
+    /// Chaos passed the request by; `chaos_type` is the one asked about, if any
+    Skipped { chaos_type: Option<&'a str> },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChaosTypeCounts {
+    pub applied: u64,
+    #[serde(default)]
+    pub shadowed: u64,
+    pub skipped: u64,
+}
+
+/// Decisions over some stretch of time
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChaosCounts {
+    /// Requests chaos had a chance at: enabled, and the agent targeted
+    pub decisions: u64,
+    pub applied: u64,
+    /// Decisions that drew chaos in shadow mode, applied no more than skips
+    #[serde(default)]
+    pub shadowed: u64,
+    pub skipped: u64,
+    /// Delay advised across applied decisions
+    pub injected_delay_ms: u64,
+    pub errors_injected: u64,
+    /// Delay and errors shadowed decisions would have brought
+    #[serde(default)]
+    pub shadow_delay_ms: u64,
+    #[serde(default)]
+    pub shadow_errors: u64,
+    /// Sum of each decision's chance of chaos
+    pub expected_applied: f64,
+    /// Share of decisions chaos struck
+    pub realized_rate: Option<f64>,
+    /// Share of decisions chaos would have struck in shadow mode
+    #[serde(default)]
+    pub shadow_rate: Option<f64>,
+    /// Mean chance of chaos the config gave those decisions
+    pub expected_rate: Option<f64>,
+    /// Skips are only attributed to a type when advice was asked for one
+    pub by_type: BTreeMap<String, ChaosTypeCounts>,
+}
+
+impl ChaosCounts {
+    fn record(&mut self, outcome: ChaosOutcome<'_>, chance: f64) {
+        self.decisions += 1;
+        self.expected_applied += chance.clamp(0.0, 1.0);
+        match outcome {
+            ChaosOutcome::Applied { chaos_type, delay_ms } => {
+                self.applied += 1;
+                self.injected_delay_ms += delay_ms;
+                if chaos_type == "error_injection" {
+                    self.errors_injected += 1;
+                }
+                self.by_type.entry(chaos_type.to_string()).or_default().applied += 1;
+            }
+            ChaosOutcome::Shadowed { chaos_type, delay_ms } => {
+                self.shadowed += 1;
+                self.shadow_delay_ms += delay_ms;
+                if chaos_type == "error_injection" {
+                    self.shadow_errors += 1;
+                }
+                self.by_type.entry(chaos_type.to_string()).or_default().shadowed += 1;
+            }
+            ChaosOutcome::Skipped { chaos_type } => {
+                self.skipped += 1;
+                if let Some(chaos_type) = chaos_type {
+                    self.by_type.entry(chaos_type.to_string()).or_default().skipped += 1;
+                }
+            }
+        }
+        self.finish();
+    }
+
+    pub fn add(&mut self, other: &ChaosCounts) {
+        self.decisions += other.decisions;
+        self.applied += other.applied;
+        self.shadowed += other.shadowed;
+        self.skipped += other.skipped;
+        self.injected_delay_ms += other.injected_delay_ms;
+        self.errors_injected += other.errors_injected;
+        self.shadow_delay_ms += other.shadow_delay_ms;
+        self.shadow_errors += other.shadow_errors;
+        self.expected_applied += other.expected_applied;
+        for (chaos_type, counts) in &other.by_type {
+            let total = self.by_type.entry(chaos_type.clone()).or_default();
+            total.applied += counts.applied;
+            total.shadowed += counts.shadowed;
+            total.skipped += counts.skipped;
+        }
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        let decisions = (self.decisions > 0).then_some(self.decisions as f64);
+        self.realized_rate = decisions.map(|decisions| self.applied as f64 / decisions);
+        self.shadow_rate = decisions.map(|decisions| self.shadowed as f64 / decisions);
+        self.expected_rate = decisions.map(|decisions| self.expected_applied / decisions);
+    }
+}
+
+/// Query parameters of `GET /api/chaos/report`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosReportParams {
+    /// Only minutes ending after this time; everything kept without it
+    pub since: Option<DateTime<Utc>>,
+    pub agent_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentChaos {
+    pub agent_id: String,
+    #[serde(flatten)]
+    pub counts: ChaosCounts,
+}
+
+/// Decisions for requests of one specialty
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpecialtyChaos {
+    pub specialty: String,
+    /// `chaos.targeting.specialty_multipliers` for it now, 1 when unset
+    pub multiplier: f64,
+    #[serde(flatten)]
+    pub counts: ChaosCounts,
+}
+
+/// `GET /api/chaos/report`: decisions since `since`, in total, per agent and
+/// per specialty
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChaosReport {
+    pub generated_at: DateTime<Utc>,
+    /// The start of the oldest minute counted
+    pub since: Option<DateTime<Utc>>,
+    pub enabled: bool,
+    /// `chaos.intensity` now; targeting and specialty settings may give
+    /// decisions other chances, which `expected_rate` accounts for
+    pub configured_intensity: f64,
+    /// Types now in shadow mode, whose decisions are counted as shadowed
+    #[serde(default)]
+    pub shadow_types: Vec<String>,
+    pub totals: ChaosCounts,
+    /// Sorted by agent_id
+    pub agents: Vec<AgentChaos>,
+    /// Sorted by specialty; decisions with none known are only in the totals
+    #[serde(default)]
+    pub specialties: Vec<SpecialtyChaos>,
+}
+
+/// Counts by minute, agent and specialty, the last empty when unknown
+type Buckets = BTreeMap<(DateTime<Utc>, String, String), ChaosCounts>;
+
+/// Lifetime totals and per-minute, per-agent and per-specialty buckets
+#[derive(Debug)]
+pub struct ChaosLedger {
+    retention_hours: u32,
+    totals: Mutex<ChaosCounts>,
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for ChaosLedger {
+    fn default() -> Self {
+        Self::new(24)
+    }
+}
+
+impl ChaosLedger {
+    pub fn new(retention_hours: u32) -> Self {
+        Self { retention_hours, totals: Mutex::default(), buckets: Mutex::default() }
+    }
+
+    /// Counts one decision for the agent and its request's specialty, which
+    /// had `chance` of chaos
+    pub fn record(&self, now: DateTime<Utc>, agent_id: &str, specialty: Option<&str>, outcome: ChaosOutcome<'_>, chance: f64) {
+        self.totals.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).record(outcome, chance);
+        let minute = now.duration_trunc(Duration::minutes(1)).unwrap_or(now);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let key = (minute, agent_id.to_string(), specialty.unwrap_or_default().to_string());
+        buckets.entry(key).or_default().record(outcome, chance);
+        let cutoff = minute - Duration::hours(i64::from(self.retention_hours));
+        while buckets.first_key_value().is_some_and(|((minute, _, _), _)| *minute < cutoff) {
+            buckets.pop_first();
+        }
+    }
+
+    /// Every decision since the service started
+    pub fn totals(&self) -> ChaosCounts {
+        self.totals.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// The kept minutes ending after `params.since`, per agent; the start of
+    /// the oldest of them
+    pub fn agents(&self, params: &ChaosReportParams) -> (Option<DateTime<Utc>>, Vec<AgentChaos>) {
+        let mut agents: BTreeMap<String, ChaosCounts> = BTreeMap::new();
+        let oldest = self.each_bucket(params, |agent_id, _, counts| agents.entry(agent_id.to_string()).or_default().add(counts));
+        (oldest, agents.into_iter().map(|(agent_id, counts)| AgentChaos { agent_id, counts }).collect())
+    }
+
+    /// The kept minutes ending after `params.since`, per specialty, each
+    /// with `multiplier(specialty)`
+    pub fn specialties(&self, params: &ChaosReportParams, multiplier: impl Fn(&str) -> f64) -> Vec<SpecialtyChaos> {
+        let mut specialties: BTreeMap<String, ChaosCounts> = BTreeMap::new();
+        self.each_bucket(params, |_, specialty, counts| {
+            if !specialty.is_empty() {
+                specialties.entry(specialty.to_string()).or_default().add(counts);
+            }
+        });
+        specialties
+            .into_iter()
+            .map(|(specialty, counts)| SpecialtyChaos { multiplier: multiplier(&specialty), specialty, counts })
+            .collect()
+    }
+
+    /// Calls `visit` with the agent, specialty and counts of every bucket
+    /// `params` selects, oldest first; the start of the oldest of them
+    fn each_bucket(&self, params: &ChaosReportParams, mut visit: impl FnMut(&str, &str, &ChaosCounts)) -> Option<DateTime<Utc>> {
+        let mut oldest = None;
+        let buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for ((minute, agent_id, specialty), counts) in buckets.iter() {
+            let in_range = params.since.is_none_or(|since| *minute + Duration::minutes(1) > since);
+            if in_range && params.agent_id.as_ref().is_none_or(|wanted| wanted == agent_id) {
+                oldest = oldest.or(Some(*minute));
+                visit(agent_id, specialty, counts);
+            }
+        }
+        oldest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn decisions_are_counted_by_type_agent_and_minute() {
+        let ledger = ChaosLedger::new(1);
+        ledger.record(at("2026-03-01T09:00:10Z"), "scout", Some("science"), ChaosOutcome::Applied { chaos_type: "network_delay", delay_ms: 700 }, 0.5);
+        ledger.record(at("2026-03-01T09:00:20Z"), "scout", Some("science"), ChaosOutcome::Skipped { chaos_type: Some("network_delay") }, 0.5);
+        ledger.record(at("2026-03-01T09:01:00Z"), "scout", Some("tactical"), ChaosOutcome::Applied { chaos_type: "error_injection", delay_ms: 0 }, 0.5);
+        ledger.record(at("2026-03-01T09:02:00Z"), "courier", None, ChaosOutcome::Skipped { chaos_type: None }, 0.1);
+
+        let totals = ledger.totals();
+        assert_eq!((totals.decisions, totals.applied, totals.skipped, totals.injected_delay_ms, totals.errors_injected), (4, 2, 2, 700, 1));
+        assert_eq!(totals.realized_rate, Some(0.5));
+        assert!((totals.expected_rate.unwrap() - 0.4).abs() < 1e-9, "{:?}", totals.expected_rate);
+        assert_eq!(totals.by_type["network_delay"], ChaosTypeCounts { applied: 1, shadowed: 0, skipped: 1 });
+
+        let (since, agents) = ledger.agents(&ChaosReportParams { since: Some(at("2026-03-01T09:00:30Z")), ..ChaosReportParams::default() });
+        assert_eq!(since, Some(at("2026-03-01T09:00:00Z")));
+        assert_eq!(agents.iter().map(|agent| (agent.agent_id.as_str(), agent.counts.decisions)).collect::<Vec<_>>(), [("courier", 1), ("scout", 3)]);
+        let (_, later) = ledger.agents(&ChaosReportParams { since: Some(at("2026-03-01T09:01:00Z")), agent_id: Some("scout".to_string()) });
+        assert_eq!(later[0].counts.errors_injected, 1);
+        assert_eq!(later[0].counts.decisions, 1);
+
+        let specialties = ledger.specialties(&ChaosReportParams::default(), |specialty| if specialty == "tactical" { 2.0 } else { 1.0 });
+        let specialties: Vec<_> = specialties.iter().map(|s| (s.specialty.as_str(), s.multiplier, s.counts.decisions, s.counts.applied)).collect();
+        assert_eq!(specialties, [("science", 1.0, 2, 1), ("tactical", 2.0, 1, 1)]);
+
+        // An hour on, the first minutes are dropped, but not from the totals
+        ledger.record(at("2026-03-01T10:01:30Z"), "scout", None, ChaosOutcome::Skipped { chaos_type: None }, 0.5);
+        assert_eq!(ledger.agents(&ChaosReportParams::default()).0, Some(at("2026-03-01T09:01:00Z")));
+        assert_eq!(ledger.totals().decisions, 5);
+    }
+}
@@ -0,0 +1,161 @@
+//! Compressed HTTP bodies. Responses are compressed with brotli or gzip,
+//! whichever the client's `Accept-Encoding` prefers, when they are JSON or
+//! text of at least `min_bytes`; event streams and responses of unknown
+//! length are sent as they are, so server-sent events reach `EventSource`
+//! clients event by event. Document uploads may be sent gzip-compressed,
+//! and are held to their route's body limit once decompressed too.
+
+use std::io::{Read, Write};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Smaller responses aren't worth compressing
+    pub min_bytes: usize,
+    /// 0 to 9
+    pub gzip_level: u32,
+    /// 0 to 11
+    pub brotli_quality: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self { enabled: true, min_bytes: 1024, gzip_level: 6, brotli_quality: 5 }
+    }
+}
+
+impl CompressionConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.gzip_level > 9 {
+            problems.push(format!("server.compression.gzip_level must be at most 9 (got {})", self.gzip_level));
+        }
+        if self.brotli_quality > 11 {
+            problems.push(format!("server.compression.brotli_quality must be at most 11 (got {})", self.brotli_quality));
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// As in `Content-Encoding`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// The encoding an `Accept-Encoding` value weighs highest, brotli winning
+/// ties; none when it accepts neither. `*` stands for any not named.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut weights = [(Encoding::Brotli, None), (Encoding::Gzip, None)];
+    let mut wildcard = None;
+    for coding in accept_encoding.split(',') {
+        let mut parts = coding.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let weight = parts
+            .find_map(|parameter| parameter.strip_prefix("q=").or_else(|| parameter.strip_prefix("Q=")))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok());
+        let Some(weight) = weight else {
+            continue;
+        };
+        match name.as_str() {
+            "br" => weights[0].1 = Some(weight),
+            "gzip" | "x-gzip" => weights[1].1 = Some(weight),
+            "*" => wildcard = Some(weight),
+            _ => {}
+        }
+    }
+    weights
+        .into_iter()
+        .filter_map(|(encoding, weight)| weight.or(wildcard).filter(|weight| *weight > 0.0).map(|weight| (encoding, weight)))
+        .fold(None, |best: Option<(Encoding, f32)>, candidate| match best {
+            Some((_, weight)) if weight >= candidate.1 => best,
+            _ => Some(candidate),
+        })
+        .map(|(encoding, _)| encoding)
+}
+
+/// Whether a response of this `Content-Type` is worth compressing: JSON and
+/// text, but not event streams, which must reach the client as they happen
+pub fn compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    essence != "text/event-stream" && (essence.starts_with("text/") || essence == "application/json" || essence.ends_with("+json"))
+}
+
+pub fn encode(config: &CompressionConfig, encoding: Encoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::with_capacity(body.len() / 4), flate2::Compression::new(config.gzip_level));
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(Vec::with_capacity(body.len() / 4), 4096, config.brotli_quality, 22);
+            encoder.write_all(body)?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+/// Why a compressed request body was refused
+#[derive(Debug)]
+pub enum DecodeError {
+    /// Decompressed, the body is over the route's limit
+    TooLarge,
+    Corrupt(std::io::Error),
+}
+
+/// Decompresses a gzip request body of at most `limit` bytes decompressed
+pub fn gunzip(body: &[u8], limit: u64) -> Result<Vec<u8>, DecodeError> {
+    let mut decoded = Vec::new();
+    flate2::read::GzDecoder::new(body).take(limit.saturating_add(1)).read_to_end(&mut decoded).map_err(DecodeError::Corrupt)?;
+    if decoded.len() as u64 > limit {
+        return Err(DecodeError::TooLarge);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_most_wanted_encoding_is_chosen() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip;q=1.0, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("*;q=0.5, br;q=0"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn bodies_round_trip_within_their_limit() {
+        let config = CompressionConfig::default();
+        let body = br#"{"response":"the shrine is quiet"}"#.repeat(100);
+        let gzipped = encode(&config, Encoding::Gzip, &body).unwrap();
+        assert!(gzipped.len() < body.len() / 10);
+        assert_eq!(gunzip(&gzipped, body.len() as u64).unwrap(), body);
+        assert!(matches!(gunzip(&gzipped, body.len() as u64 - 1), Err(DecodeError::TooLarge)));
+        assert!(matches!(gunzip(b"not gzip", 1024), Err(DecodeError::Corrupt(_))));
+
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(encode(&config, Encoding::Brotli, &body).unwrap().as_slice(), 4096).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, body);
+        assert!(compressible("application/json") && compressible("text/plain; version=0.0.4"));
+        assert!(!compressible("text/event-stream") && !compressible("image/png"));
+    }
+}
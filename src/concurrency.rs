@@ -0,0 +1,248 @@
+//! The global bound on requests doing work at once, across every agent and
+//! transport. A request waits up to `max_wait_ms` for a slot and is then shed
+//! with 503 `overloaded` and a retry hint, rather than queueing without bound.
+//! Queued jobs wait as long as it takes instead, so the job queue spends the
+//! same budget without being shed. `PUT /api/concurrency` changes the limit
+//! at runtime; lowering it below the requests in flight lets them finish and
+//! admits no more until they have.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Requests handled at once
+    pub max_in_flight: u32,
+    /// How long a request waits for a slot before it is shed
+    pub max_wait_ms: u64,
+    /// When shed requests are told to try again
+    pub retry_after_ms: u64,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self { max_in_flight: 256, max_wait_ms: 100, retry_after_ms: 1000 }
+    }
+}
+
+impl ConcurrencyConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.max_in_flight == 0 {
+            problems.push("concurrency.max_in_flight must be positive".to_string());
+        }
+        if self.retry_after_ms == 0 {
+            problems.push("concurrency.retry_after_ms must be positive".to_string());
+        }
+        problems
+    }
+}
+
+/// `PUT /api/concurrency`; unset settings are kept
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyUpdate {
+    #[serde(default)]
+    pub max_in_flight: Option<u32>,
+    #[serde(default)]
+    pub max_wait_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConcurrencyStatus {
+    pub max_in_flight: u32,
+    pub max_wait_ms: u64,
+    /// Slots held right now, which may exceed a just-lowered limit
+    pub in_use: u32,
+    /// Requests and jobs waiting for a slot
+    pub waiting: u32,
+    /// Requests refused for want of a slot since the server started
+    pub shed_total: u64,
+}
+
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    max_in_flight: AtomicU32,
+    max_wait_ms: AtomicU64,
+    retry_after: Duration,
+    in_use: AtomicU32,
+    waiting: AtomicU32,
+    shed: AtomicU64,
+    released: Notify,
+}
+
+impl Default for ConcurrencyLimiter {
+    fn default() -> Self {
+        Self::new(&ConcurrencyConfig::default())
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        Self {
+            max_in_flight: AtomicU32::new(config.max_in_flight),
+            max_wait_ms: AtomicU64::new(config.max_wait_ms),
+            retry_after: Duration::from_millis(config.retry_after_ms),
+            in_use: AtomicU32::new(0),
+            waiting: AtomicU32::new(0),
+            shed: AtomicU64::new(0),
+            released: Notify::new(),
+        }
+    }
+
+    /// A slot if one is free now
+    pub fn try_acquire(self: &Arc<Self>) -> Option<ConcurrencyPermit> {
+        let limit = self.max_in_flight.load(Ordering::Acquire);
+        self.in_use
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_use| (in_use < limit).then_some(in_use + 1))
+            .ok()
+            .map(|_| ConcurrencyPermit(Arc::clone(self)))
+    }
+
+    /// A slot, waiting up to `max_wait_ms` for one; else the request is
+    /// counted as shed and the retry hint returned
+    pub async fn acquire(self: &Arc<Self>) -> Result<ConcurrencyPermit, Duration> {
+        let wait = Duration::from_millis(self.max_wait_ms.load(Ordering::Relaxed));
+        match self.wait_for_slot(Some(tokio::time::Instant::now() + wait)).await {
+            Some(permit) => Ok(permit),
+            None => Err(self.shed()),
+        }
+    }
+
+    /// A slot, however long it takes to free up; for work already queued
+    pub async fn acquire_queued(self: &Arc<Self>) -> ConcurrencyPermit {
+        self.wait_for_slot(None).await.expect("waiting without a deadline ends with a slot")
+    }
+
+    /// Counts a request refused without waiting, returning its retry hint
+    pub fn shed(&self) -> Duration {
+        self.shed.fetch_add(1, Ordering::Relaxed);
+        self.retry_after
+    }
+
+    async fn wait_for_slot(self: &Arc<Self>, until: Option<tokio::time::Instant>) -> Option<ConcurrencyPermit> {
+        if let Some(permit) = self.try_acquire() {
+            return Some(permit);
+        }
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let _waiting = Waiting(&self.waiting);
+        loop {
+            // Registered before looking, so a release in between still wakes us
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            if let Some(permit) = self.try_acquire() {
+                return Some(permit);
+            }
+            match until {
+                Some(until) => {
+                    if tokio::time::timeout_at(until, released).await.is_err() {
+                        // A wake-up that raced the timeout goes to the next waiter
+                        self.released.notify_one();
+                        return None;
+                    }
+                }
+                None => released.await,
+            }
+        }
+    }
+
+    pub fn update(&self, update: &ConcurrencyUpdate) -> ConcurrencyStatus {
+        if let Some(max_wait_ms) = update.max_wait_ms {
+            self.max_wait_ms.store(max_wait_ms, Ordering::Relaxed);
+        }
+        if let Some(max_in_flight) = update.max_in_flight {
+            let previous = self.max_in_flight.swap(max_in_flight, Ordering::AcqRel);
+            if max_in_flight > previous {
+                self.released.notify_waiters();
+            }
+            tracing::warn!("Concurrency limit changed from {} to {}", previous, max_in_flight);
+        }
+        self.status()
+    }
+
+    pub fn status(&self) -> ConcurrencyStatus {
+        ConcurrencyStatus {
+            max_in_flight: self.max_in_flight.load(Ordering::Relaxed),
+            max_wait_ms: self.max_wait_ms.load(Ordering::Relaxed),
+            in_use: self.in_use.load(Ordering::Relaxed),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            shed_total: self.shed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A slot, given back when dropped
+#[derive(Debug)]
+pub struct ConcurrencyPermit(Arc<ConcurrencyLimiter>);
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        self.0.in_use.fetch_sub(1, Ordering::AcqRel);
+        self.0.released.notify_one();
+    }
+}
+
+struct Waiting<'a>(&'a AtomicU32);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_in_flight: u32) -> Arc<ConcurrencyLimiter> {
+        Arc::new(ConcurrencyLimiter::new(&ConcurrencyConfig { max_in_flight, max_wait_ms: 20, retry_after_ms: 500 }))
+    }
+
+    #[tokio::test]
+    async fn requests_past_the_limit_wait_briefly_then_are_shed() {
+        let limiter = limiter(1);
+        let held = limiter.acquire().await.unwrap();
+        assert_eq!(limiter.acquire().await.unwrap_err(), Duration::from_millis(500));
+
+        let waiter = tokio::spawn({
+            let limiter = Arc::clone(&limiter);
+            async move { limiter.acquire_queued().await }
+        });
+        while limiter.status().waiting == 0 {
+            tokio::task::yield_now().await;
+        }
+        drop(held);
+        let queued = waiter.await.unwrap();
+        assert_eq!((limiter.status().in_use, limiter.status().shed_total), (1, 1));
+        drop(queued);
+        assert_eq!(limiter.status().in_use, 0);
+    }
+
+    #[tokio::test]
+    async fn the_limit_changes_at_runtime() {
+        let limiter = limiter(2);
+        let first = limiter.try_acquire().unwrap();
+        let _second = limiter.try_acquire().unwrap();
+
+        limiter.update(&ConcurrencyUpdate { max_in_flight: Some(1), max_wait_ms: None });
+        drop(first);
+        // Still one in flight, which is the new limit
+        assert!(limiter.try_acquire().is_none());
+
+        let waiter = tokio::spawn({
+            let limiter = Arc::clone(&limiter);
+            async move { limiter.acquire_queued().await }
+        });
+        while limiter.status().waiting == 0 {
+            tokio::task::yield_now().await;
+        }
+        let status = limiter.update(&ConcurrencyUpdate { max_in_flight: Some(4), max_wait_ms: Some(0) });
+        waiter.await.unwrap();
+        assert_eq!((status.max_in_flight, status.max_wait_ms), (4, 0));
+    }
+}
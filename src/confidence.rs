@@ -0,0 +1,203 @@
+//! The confidence score in each response's metrics: a weighted mean of
+//! components between 0 and 1, each derived from something observed while
+//! answering, scaled down when chaos corrupted the response.
+//!
+//! - retrieval, when knowledge base context was searched for: half the best
+//!   chunk score and a quarter the mean, each squashed into [0, 1) as
+//!   `s / (s + score_midpoint)`, plus a quarter times the share of
+//!   `relevant_chunks` that scored at least `relevant_score`; 0 when nothing
+//!   was found
+//! - finish: 1 when the backend stopped on its own, 0.6 for a reason it
+//!   didn't give, 0.4 when cut off at `max_tokens`, 0.2 when its own content
+//!   filter stopped it
+//! - logprob, when the backend reports token log probabilities: the geometric
+//!   mean token probability, `exp(mean logprob)`
+//! - length: 0 for an empty response, 1 up to 90% of `max_tokens`, then
+//!   falling linearly to 0.5 at `max_tokens`, where the answer was likely cut short
+//!
+//! `score = Σ weight·component / Σ weight` over the components present, times
+//! `1 - corruption_penalty` when `response_corruption` was applied, rounded to
+//! thousandths. Nothing random goes in, so identical requests with the same
+//! retrieval and backend behaviour score the same.
+
+use serde::{Deserialize, Serialize};
+use crate::llm_backend::{CompletionOutput, FinishReason};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfidenceConfig {
+    pub retrieval_weight: f64,
+    pub finish_weight: f64,
+    pub logprob_weight: f64,
+    pub length_weight: f64,
+    /// Chunk score counted as 0.5 once squashed; keyword scores count term matches
+    pub score_midpoint: f64,
+    /// Chunk score at which a chunk counts as relevant
+    pub relevant_score: f64,
+    /// Relevant chunks needed for full marks on that part of retrieval
+    pub relevant_chunks: u32,
+    /// Share of the score taken off responses chaos corrupted
+    pub corruption_penalty: f64,
+}
+
+impl Default for ConfidenceConfig {
+    fn default() -> Self {
+        Self {
+            retrieval_weight: 0.35,
+            finish_weight: 0.25,
+            logprob_weight: 0.2,
+            length_weight: 0.2,
+            score_midpoint: 2.0,
+            relevant_score: 1.0,
+            relevant_chunks: 3,
+            corruption_penalty: 0.5,
+        }
+    }
+}
+
+impl ConfidenceConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (key, weight) in [
+            ("retrieval_weight", self.retrieval_weight),
+            ("finish_weight", self.finish_weight),
+            ("logprob_weight", self.logprob_weight),
+            ("length_weight", self.length_weight),
+        ] {
+            if !weight.is_finite() || weight < 0.0 {
+                problems.push(format!("confidence.{} must be a non-negative number", key));
+            }
+        }
+        if self.finish_weight + self.length_weight <= 0.0 {
+            problems.push("confidence.finish_weight and confidence.length_weight can't both be 0".to_string());
+        }
+        if self.score_midpoint.is_nan() || self.score_midpoint <= 0.0 {
+            problems.push("confidence.score_midpoint must be positive".to_string());
+        }
+        if self.relevant_chunks == 0 {
+            problems.push("confidence.relevant_chunks must be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.corruption_penalty) {
+            problems.push("confidence.corruption_penalty must be between 0 and 1".to_string());
+        }
+        problems
+    }
+
+    /// Components from the chunk scores retrieval found, if it was wanted,
+    /// and the backend's output, if one answered
+    pub fn breakdown(&self, retrieval: Option<&[f64]>, output: Option<(&CompletionOutput, u32, u32)>) -> ConfidenceBreakdown {
+        let mut breakdown = ConfidenceBreakdown { retrieval: retrieval.map(|scores| self.retrieval(scores)), corruption: 1.0, ..ConfidenceBreakdown::default() };
+        if let Some((output, completion_tokens, max_tokens)) = output {
+            breakdown.finish = Some(match output.finish_reason {
+                FinishReason::Stop => 1.0,
+                FinishReason::Other => 0.6,
+                FinishReason::Length => 0.4,
+                FinishReason::ContentFilter => 0.2,
+            });
+            breakdown.logprob = output.mean_logprob.map(|logprob| logprob.min(0.0).exp());
+            breakdown.length = Some(length(completion_tokens, max_tokens, output.text.trim().is_empty()));
+        }
+        breakdown.score = self.score(&breakdown);
+        breakdown
+    }
+
+    fn retrieval(&self, scores: &[f64]) -> f64 {
+        if scores.is_empty() {
+            return 0.0;
+        }
+        let squash = |score: f64| score.max(0.0) / (score.max(0.0) + self.score_midpoint);
+        let best = scores.iter().copied().fold(f64::MIN, f64::max);
+        let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+        let relevant = scores.iter().filter(|score| **score >= self.relevant_score).count() as f64;
+        0.5 * squash(best) + 0.25 * squash(mean) + 0.25 * (relevant / f64::from(self.relevant_chunks)).min(1.0)
+    }
+
+    fn score(&self, breakdown: &ConfidenceBreakdown) -> f64 {
+        let weighted = [
+            (breakdown.retrieval, self.retrieval_weight),
+            (breakdown.finish, self.finish_weight),
+            (breakdown.logprob, self.logprob_weight),
+            (breakdown.length, self.length_weight),
+        ];
+        let (sum, weights) = weighted
+            .iter()
+            .filter_map(|(component, weight)| component.map(|component| (component * weight, *weight)))
+            .fold((0.0, 0.0), |(sum, weights), (value, weight)| (sum + value, weights + weight));
+        let mean = if weights > 0.0 { sum / weights } else { 0.0 };
+        (mean * breakdown.corruption * 1000.0).round() / 1000.0
+    }
+
+    /// Takes the corruption penalty off a response chaos corrupted
+    pub fn corrupted(&self, breakdown: &mut ConfidenceBreakdown) {
+        breakdown.corruption = 1.0 - self.corruption_penalty;
+        breakdown.score = self.score(breakdown);
+    }
+}
+
+/// 1 up to 90% of `max_tokens`, then down to 0.5 at `max_tokens`
+fn length(completion_tokens: u32, max_tokens: u32, empty: bool) -> f64 {
+    if empty {
+        return 0.0;
+    }
+    let used = f64::from(completion_tokens) / f64::from(max_tokens.max(1));
+    0.5 + ((1.0 - used) * 10.0).clamp(0.0, 1.0) * 0.5
+}
+
+/// The score and what went into it; absent components had nothing to go on
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceBreakdown {
+    pub score: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieval: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprob: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<f64>,
+    /// What the rest is multiplied by: 1 unless chaos corrupted the response
+    pub corruption: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(text: &str, finish_reason: FinishReason, mean_logprob: Option<f64>) -> CompletionOutput {
+        CompletionOutput { text: text.to_string(), prompt_tokens: 10, completion_tokens: 0, finish_reason, generation_time: None, mean_logprob }
+    }
+
+    #[test]
+    fn signals_move_the_score_and_nothing_else_does() {
+        let config = ConfidenceConfig::default();
+        let answered = output("The tides follow the moon.", FinishReason::Stop, None);
+        let grounded = config.breakdown(Some(&[6.0, 4.0, 2.0]), Some((&answered, 40, 256)));
+        assert_eq!(grounded, config.breakdown(Some(&[6.0, 4.0, 2.0]), Some((&answered, 40, 256))));
+        assert_eq!((grounded.finish, grounded.length, grounded.logprob), (Some(1.0), Some(1.0), None));
+
+        let ungrounded = config.breakdown(Some(&[]), Some((&answered, 40, 256)));
+        assert_eq!(ungrounded.retrieval, Some(0.0));
+        assert!(ungrounded.score < grounded.score);
+
+        let truncated = config.breakdown(Some(&[6.0, 4.0, 2.0]), Some((&output("The tides", FinishReason::Length, None), 256, 256)));
+        assert_eq!((truncated.finish, truncated.length), (Some(0.4), Some(0.5)));
+        assert!(truncated.score < grounded.score);
+
+        let unsure = config.breakdown(None, Some((&output("Perhaps.", FinishReason::Stop, Some(-1.5)), 40, 256)));
+        let sure = config.breakdown(None, Some((&output("Perhaps.", FinishReason::Stop, Some(-0.05)), 40, 256)));
+        assert!(unsure.score < sure.score && sure.score < 1.0);
+
+        let mut corrupted = grounded.clone();
+        config.corrupted(&mut corrupted);
+        assert!((corrupted.score - grounded.score / 2.0).abs() <= 0.001);
+    }
+
+    #[test]
+    fn weights_are_renormalized_over_the_components_present() {
+        let config = ConfidenceConfig { retrieval_weight: 1.0, ..ConfidenceConfig::default() };
+        let retrieval_only = config.breakdown(Some(&[2.0, 2.0, 2.0]), None);
+        // Squashed best and mean are 0.5, and all three chunks are relevant
+        assert_eq!(retrieval_only.score, 0.625);
+        assert_eq!(ConfidenceConfig { score_midpoint: 0.0, ..config }.validate(), ["confidence.score_midpoint must be positive"]);
+    }
+}
@@ -0,0 +1,825 @@
+//! Server configuration: a TOML file (`--config` or `VOID_SHRINE_CONFIG`)
+//! overlaid with environment variables, which take precedence. Every section
+//! and key is optional; see `void-shrine.example.toml` for the full set.
+//! Unknown keys are logged and ignored, except inside `[backends]`,
+//! `[[backends.routes]]`, `[[backends.chains]]`, `[[auth.keys]]` and
+//! `[rate_limits]` where a typo would silently change routing or access, so
+//! they are rejected.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::agents::AgentsConfig;
+use crate::audit::{AuditConfig, AuditSink};
+use crate::auth::ApiKey;
+use crate::breaker::BreakerConfig;
+use crate::compression::CompressionConfig;
+use crate::confidence::ConfidenceConfig;
+use crate::concurrency::ConcurrencyConfig;
+use crate::content_filter::ContentFilterConfig;
+use crate::context_screen::ContextScreenConfig;
+use crate::jobs::JobsConfig;
+use crate::cache::CacheConfig;
+use crate::llm_backend::{BackendKind, BackendSpec, BackendsConfig, RetryConfig};
+use crate::load::LoadConfig;
+use crate::mcp_server::{BatchConfig, BodyLimits, ChaosConfig, ParamLimits, RequestDefaults, RetrievalFailurePolicy, ThrottleConfig, TimeoutConfig};
+use crate::rag_engine::{FtsRebuildPolicy, RAGEngineBuilder, RAGEngine};
+use crate::rag_engines::DEFAULT_ENGINE;
+use crate::rate_limit::RateLimitConfig;
+use crate::moral::MoralConfig;
+use crate::idempotency::IdempotencyConfig;
+use crate::overload::OverloadConfig;
+use crate::quota::QuotaConfig;
+use crate::scaling::ScalingConfig;
+use crate::self_check::SelfCheckConfig;
+use crate::sessions::SessionConfig;
+use crate::specialties::SpecialtiesConfig;
+use crate::templates::TemplatesConfig;
+use crate::tokenizer::TokenizerConfig;
+use crate::tokens::TokensConfig;
+use crate::usage::UsageConfig;
+use crate::webhooks::WebhookConfig;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    /// Checks of the deployment at startup, and what a failed one means
+    pub self_check: SelfCheckConfig,
+    pub chaos: ChaosConfig,
+    pub rag: RagConfig,
+    pub backends: BackendsConfig,
+    pub auth: AuthConfig,
+    /// Bounds on request params
+    pub limits: ParamLimits,
+    /// Model and specialty of requests that name neither
+    pub defaults: RequestDefaults,
+    /// Bounds on request bodies, per class of route
+    pub body_limits: BodyLimits,
+    pub batch: BatchConfig,
+    pub jobs: JobsConfig,
+    pub timeouts: TimeoutConfig,
+    pub retries: RetryConfig,
+    pub breakers: BreakerConfig,
+    pub rate_limits: RateLimitConfig,
+    pub throttle: ThrottleConfig,
+    pub concurrency: ConcurrencyConfig,
+    /// Shedding of inference requests while the server as a whole is overloaded
+    pub overload: OverloadConfig,
+    pub load: LoadConfig,
+    pub scaling: ScalingConfig,
+    pub webhooks: WebhookConfig,
+    /// Screening of responses before they are sent
+    pub content_filter: ContentFilterConfig,
+    /// Screening of knowledge base context before it goes into a prompt
+    pub context_screen: ContextScreenConfig,
+    /// Weights of the signals behind each response's confidence score
+    pub confidence: ConfidenceConfig,
+    pub moral: MoralConfig,
+    pub agents: AgentsConfig,
+    pub specialties: SpecialtiesConfig,
+    pub templates: TemplatesConfig,
+    pub audit: AuditConfig,
+    pub sessions: SessionConfig,
+    pub cache: CacheConfig,
+    /// How long responses are kept for repeats of their idempotency key
+    pub idempotency: IdempotencyConfig,
+    pub tokenizer: TokenizerConfig,
+    pub tokens: TokensConfig,
+    pub metrics: MetricsConfig,
+    /// Token usage per agent and model, and the prices it is costed at
+    pub usage: UsageConfig,
+    /// Caps on the tokens or requests of agents and tenants per hour or day
+    pub quotas: QuotaConfig,
+    /// The file this was read from, if any
+    #[serde(skip)]
+    pub source: Option<ConfigSource>,
+}
+
+/// A config file and a digest of what it held when read, so instances
+/// started from different files show it at GET /api/version. Environment
+/// overrides are applied after reading and aren't part of the digest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    /// Hex SHA-256 of the file's bytes
+    pub sha256: String,
+    /// The settings as written, which a reload compares the file with
+    #[serde(skip)]
+    pub settings: toml::Table,
+}
+
+/// The settings follow from the bytes the digest is of
+impl PartialEq for ConfigSource {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.sha256 == other.sha256
+    }
+}
+
+impl ConfigSource {
+    pub fn new(path: &Path, contents: &[u8]) -> Self {
+        let digest = ring::digest::digest(&ring::digest::SHA256, contents);
+        let sha256 = digest.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+        let settings = std::str::from_utf8(contents).ok().and_then(|text| text.parse().ok()).unwrap_or_default();
+        Self { path: path.to_path_buf(), sha256, settings }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub addr: IpAddr,
+    pub port: u16,
+    /// How long in-flight requests may run once shutdown starts
+    pub drain_timeout_secs: u64,
+    /// Backups requested over HTTP are written inside this directory; unset disables them
+    pub backup_dir: Option<PathBuf>,
+    /// Serve HTTPS on `port` instead of plain HTTP
+    pub tls: Option<TlsConfig>,
+    /// Which browser origins may call the API
+    pub cors: CorsConfig,
+    /// Compressing responses for clients that accept it
+    pub compression: CompressionConfig,
+    /// Plaintext gRPC port on `addr`, served alongside HTTP by builds with the
+    /// `grpc` feature; unset disables gRPC
+    pub grpc_port: Option<u16>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3030,
+            drain_timeout_secs: 30,
+            backup_dir: None,
+            tls: None,
+            cors: CorsConfig::default(),
+            compression: CompressionConfig::default(),
+            grpc_port: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert_path: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: PathBuf,
+    /// Seconds between checks of the files for renewals; 0 reloads on SIGHUP only
+    #[serde(default = "TlsConfig::default_reload_poll_secs")]
+    pub reload_poll_secs: u64,
+    /// Plain HTTP port redirecting to HTTPS; unset disables plain HTTP
+    #[serde(default)]
+    pub redirect_http_port: Option<u16>,
+}
+
+impl TlsConfig {
+    fn default_reload_poll_secs() -> u64 {
+        60
+    }
+
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        Self {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            reload_poll_secs: Self::default_reload_poll_secs(),
+            redirect_http_port: None,
+        }
+    }
+}
+
+/// Cross-origin access from browsers. Preflights are answered for every
+/// route, ahead of authentication; requests from other origins get 403.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins as `scheme://host[:port]`, or `"*"` for any
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers browsers may send
+    pub allowed_headers: Vec<String>,
+    /// Response headers scripts may read
+    pub exposed_headers: Vec<String>,
+    /// Seconds browsers may cache a preflight answer
+    pub max_age_secs: u64,
+    /// Let browsers send cookies and credentials; not with `"*"`
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        Self {
+            allowed_origins: strings(&["*"]),
+            allowed_methods: strings(&["GET", "POST", "PUT", "PATCH", "DELETE"]),
+            allowed_headers: strings(&["authorization", "content-type", "x-request-id", "idempotency-key", "traceparent", "content-encoding"]),
+            exposed_headers: strings(&["x-request-id", "retry-after"]),
+            max_age_secs: 600,
+            allow_credentials: false,
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for origin in self.allowed_origins.iter().filter(|origin| *origin != "*") {
+            let canonical = reqwest::Url::parse(origin)
+                .ok()
+                .filter(|url| matches!(url.scheme(), "http" | "https"))
+                .map(|url| url.origin().ascii_serialization());
+            if canonical.as_deref() != Some(origin.as_str()) {
+                problems.push(format!("server.cors.allowed_origins has '{}', which is not an origin like https://host:port", origin));
+            }
+        }
+        if self.allow_credentials && self.allows_any_origin() {
+            problems.push("server.cors.allow_credentials needs allowed_origins listed, not \"*\"".to_string());
+        }
+        for method in &self.allowed_methods {
+            if warp::http::Method::from_bytes(method.as_bytes()).is_err() {
+                problems.push(format!("server.cors.allowed_methods has '{}', which is not an HTTP method", method));
+            }
+        }
+        for name in self.allowed_headers.iter().chain(&self.exposed_headers) {
+            if warp::http::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                problems.push(format!("server.cors has header '{}', which is not a header name", name));
+            }
+        }
+        problems
+    }
+}
+
+impl ServerConfig {
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.addr, self.port)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RagConfig {
+    /// SQLite file holding the index; unset keeps it in memory
+    pub db_path: Option<PathBuf>,
+    /// Characters per chunk
+    pub chunk_size: usize,
+    /// Characters shared by neighbouring chunks
+    pub overlap_size: usize,
+    /// Index the built-in Void Shrine documents at startup
+    pub preload_builtin_knowledge: bool,
+    /// Fail requests wanting knowledge base context with 503 `rag_unavailable`
+    /// while there is no engine, instead of answering without context
+    pub require_engine: bool,
+    /// What an inference does when searching the engine fails
+    pub on_retrieval_error: RetrievalFailurePolicy,
+    /// What keyword searches do while `POST /api/rag/rebuild-fts` runs
+    pub during_fts_rebuild: FtsRebuildPolicy,
+    /// Engines besides the default one above, by name, each with its own
+    /// file and settings
+    pub engines: BTreeMap<String, RagEngineConfig>,
+}
+
+impl Default for RagConfig {
+    fn default() -> Self {
+        Self {
+            db_path: None,
+            chunk_size: 512,
+            overlap_size: 64,
+            preload_builtin_knowledge: true,
+            require_engine: false,
+            on_retrieval_error: RetrievalFailurePolicy::Degrade,
+            during_fts_rebuild: FtsRebuildPolicy::Fallback,
+            engines: BTreeMap::new(),
+        }
+    }
+}
+
+impl RagConfig {
+    /// The default engine's settings
+    pub fn default_engine(&self) -> RagEngineConfig {
+        RagEngineConfig {
+            db_path: self.db_path.clone(),
+            chunk_size: self.chunk_size,
+            overlap_size: self.overlap_size,
+            preload_builtin_knowledge: self.preload_builtin_knowledge,
+            during_fts_rebuild: self.during_fts_rebuild,
+        }
+    }
+
+    /// A builder for the default engine
+    pub fn builder(&self) -> RAGEngineBuilder {
+        self.default_engine().builder()
+    }
+
+    /// Opens the default engine, preloading the built-in documents if configured
+    pub async fn open(&self) -> Result<RAGEngine> {
+        self.default_engine().open().await
+    }
+
+    /// Every engine's settings by dotted key, and engines sharing a file
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let default = self.default_engine();
+        let engines = std::iter::once(("rag".to_string(), &default))
+            .chain(self.engines.iter().map(|(name, engine)| (format!("rag.engines.{}", name), engine)));
+        let mut files: BTreeMap<&Path, String> = BTreeMap::new();
+        for (key, engine) in engines {
+            if engine.chunk_size == 0 {
+                problems.push(format!("{}.chunk_size must be positive", key));
+            }
+            if engine.overlap_size >= engine.chunk_size {
+                problems.push(format!(
+                    "{key}.overlap_size must be smaller than {key}.chunk_size ({} >= {})",
+                    engine.overlap_size, engine.chunk_size
+                ));
+            }
+            if let Some(path) = &engine.db_path {
+                match files.get(path.as_path()) {
+                    Some(other) => problems.push(format!("{}.db_path is {}'s file too ({})", key, other, path.display())),
+                    None => {
+                        files.insert(path, key);
+                    }
+                }
+            }
+        }
+        for name in self.engines.keys() {
+            if name == DEFAULT_ENGINE {
+                problems.push(format!("rag.engines.{} is the name of the engine [rag] configures", DEFAULT_ENGINE));
+            } else if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                problems.push(format!("rag.engines has '{}', which is not letters, digits, '-' and '_'", name));
+            }
+        }
+        problems
+    }
+}
+
+/// One knowledge base of `[rag.engines]`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RagEngineConfig {
+    /// SQLite file holding the index; unset keeps it in memory
+    pub db_path: Option<PathBuf>,
+    /// Characters per chunk
+    pub chunk_size: usize,
+    /// Characters shared by neighbouring chunks
+    pub overlap_size: usize,
+    /// Index the built-in Void Shrine documents at startup
+    pub preload_builtin_knowledge: bool,
+    /// What keyword searches do while `POST /api/rag/rebuild-fts` runs
+    pub during_fts_rebuild: FtsRebuildPolicy,
+}
+
+impl Default for RagEngineConfig {
+    /// Settings as `[rag]`'s, but starting empty
+    fn default() -> Self {
+        Self { preload_builtin_knowledge: false, ..RagConfig::default().default_engine() }
+    }
+}
+
+impl RagEngineConfig {
+    /// A builder for the engine this section describes
+    pub fn builder(&self) -> RAGEngineBuilder {
+        let builder = RAGEngine::builder()
+            .chunk_size(self.chunk_size)
+            .overlap_size(self.overlap_size)
+            .during_fts_rebuild(self.during_fts_rebuild);
+        match &self.db_path {
+            Some(path) => builder.path(path),
+            None => builder,
+        }
+    }
+
+    /// Opens the engine, preloading the built-in documents if configured
+    pub async fn open(&self) -> Result<RAGEngine> {
+        let location = self.db_path.as_ref().map_or_else(|| "memory".to_string(), |path| path.display().to_string());
+        let mut engine = self.builder().build().await.with_context(|| format!("opening the knowledge base in {}", location))?;
+        if self.preload_builtin_knowledge {
+            engine.index_void_shrine_knowledge().await.context("indexing the built-in knowledge")?;
+        }
+        Ok(engine)
+    }
+}
+
+/// API keys; none leaves every endpoint open
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Agents beyond this many share the `other` label on per-agent series
+    pub agent_label_cap: usize,
+    /// JSON file agent metrics are kept in across restarts; unset starts
+    /// every agent from zero
+    pub state_path: Option<PathBuf>,
+    /// How often agent metrics are saved, besides on shutdown
+    pub save_interval_secs: u64,
+    /// How often agents idle for `prune_idle_secs` have their metrics
+    /// removed; 0 never prunes
+    pub prune_interval_secs: u64,
+    pub prune_idle_secs: u64,
+    /// Events kept for each `/ws/metrics` client that hasn't read them; a
+    /// client further behind loses the oldest
+    pub live_buffer_events: usize,
+    /// Days of hourly agent activity kept for `GET /api/metrics/export`
+    pub history_retention_days: u32,
+    /// The longest range one export may cover
+    pub export_max_span_days: u32,
+    /// Hours of per-minute chaos decisions kept for `GET /api/chaos/report`
+    pub chaos_retention_hours: u32,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            agent_label_cap: 100,
+            state_path: None,
+            save_interval_secs: 60,
+            prune_interval_secs: 0,
+            prune_idle_secs: 7 * 86400,
+            live_buffer_events: 256,
+            history_retention_days: 31,
+            export_max_span_days: 31,
+            chaos_retention_hours: 24,
+        }
+    }
+}
+
+impl Config {
+    /// The file at `path`, if any, with environment overrides applied, validated
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading config file {}", path.display()))?;
+        let mut config = Self::from_toml(&text).with_context(|| format!("config file {}", path.display()))?;
+        config.source = Some(ConfigSource::new(path, text.as_bytes()));
+        Ok(config)
+    }
+
+    /// Parses TOML, warning about keys nothing reads
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let (config, ignored) = Self::parse_toml(text)?;
+        for key in ignored {
+            tracing::warn!("Ignoring unknown config key '{}'", key);
+        }
+        Ok(config)
+    }
+
+    /// The config and the dotted paths of unknown keys
+    fn parse_toml(text: &str) -> Result<(Self, Vec<String>)> {
+        let mut ignored = Vec::new();
+        let config = serde_ignored::deserialize(toml::Deserializer::new(text), |path| ignored.push(path.to_string()))?;
+        Ok((config, ignored))
+    }
+
+    /// Overrides settings from `var`, normally the process environment:
+    ///
+    /// - `VOID_SHRINE_ADDR`, `VOID_SHRINE_PORT`, `VOID_SHRINE_DRAIN_TIMEOUT_SECS`, `VOID_SHRINE_BACKUP_DIR`,
+    ///   `VOID_SHRINE_GRPC_PORT`
+    /// - `VOID_SHRINE_TLS_CERT` and `VOID_SHRINE_TLS_KEY`, together enabling TLS
+    /// - `VOID_SHRINE_CHAOS_ENABLED`, `VOID_SHRINE_CHAOS_INTENSITY`, `VOID_SHRINE_CHAOS_TYPES` (comma separated),
+    ///   `VOID_SHRINE_CHAOS_SEED`
+    /// - `VOID_SHRINE_RAG_DB_PATH`, `VOID_SHRINE_RAG_CHUNK_SIZE`, `VOID_SHRINE_RAG_PRELOAD`
+    /// - `VOID_SHRINE_API_KEYS`, comma separated `id:secret:scope+scope[:tenant]`, replacing `[auth]`
+    /// - `VOID_SHRINE_WEBHOOK_URLS` (comma separated) and `VOID_SHRINE_WEBHOOK_SECRET`
+    /// - `VOID_SHRINE_AUDIT_SINK` (`none`, `jsonl` or `sqlite`) and `VOID_SHRINE_AUDIT_PATH`
+    /// - `VOID_SHRINE_OPENAI_BASE_URL`, `VOID_SHRINE_OLLAMA_HOST` and `ANTHROPIC_API_KEY`,
+    ///   each adding a backend that becomes the default
+    /// - `VOID_SHRINE_BACKENDS_CONFIG`, a JSON file replacing `[backends]` entirely
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(addr) = parsed(&var, "VOID_SHRINE_ADDR")? {
+            self.server.addr = addr;
+        }
+        if let Some(port) = parsed(&var, "VOID_SHRINE_PORT")? {
+            self.server.port = port;
+        }
+        if let Some(secs) = parsed(&var, "VOID_SHRINE_DRAIN_TIMEOUT_SECS")? {
+            self.server.drain_timeout_secs = secs;
+        }
+        if let Some(dir) = var("VOID_SHRINE_BACKUP_DIR") {
+            self.server.backup_dir = Some(dir.into());
+        }
+        if let Some(port) = parsed(&var, "VOID_SHRINE_GRPC_PORT")? {
+            self.server.grpc_port = Some(port);
+        }
+        match (var("VOID_SHRINE_TLS_CERT"), var("VOID_SHRINE_TLS_KEY")) {
+            (Some(cert_path), Some(key_path)) => match &mut self.server.tls {
+                Some(tls) => {
+                    tls.cert_path = cert_path.into();
+                    tls.key_path = key_path.into();
+                }
+                None => self.server.tls = Some(TlsConfig::new(cert_path, key_path)),
+            },
+            (None, None) => {}
+            _ => return Err(anyhow!("VOID_SHRINE_TLS_CERT and VOID_SHRINE_TLS_KEY must be set together")),
+        }
+
+        if let Some(enabled) = parsed(&var, "VOID_SHRINE_CHAOS_ENABLED")? {
+            self.chaos.enabled = enabled;
+        }
+        if let Some(intensity) = parsed(&var, "VOID_SHRINE_CHAOS_INTENSITY")? {
+            self.chaos.intensity = intensity;
+        }
+        if let Some(seed) = parsed(&var, "VOID_SHRINE_CHAOS_SEED")? {
+            self.chaos.seed = Some(seed);
+        }
+        if let Some(types) = var("VOID_SHRINE_CHAOS_TYPES") {
+            self.chaos.chaos_types = types.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect();
+        }
+
+        if let Some(path) = var("VOID_SHRINE_RAG_DB_PATH") {
+            self.rag.db_path = Some(path.into());
+        }
+        if let Some(chunk_size) = parsed(&var, "VOID_SHRINE_RAG_CHUNK_SIZE")? {
+            self.rag.chunk_size = chunk_size;
+        }
+        if let Some(preload) = parsed(&var, "VOID_SHRINE_RAG_PRELOAD")? {
+            self.rag.preload_builtin_knowledge = preload;
+        }
+
+        if let Some(specs) = var("VOID_SHRINE_API_KEYS") {
+            self.auth.keys = specs
+                .split(',')
+                .filter(|spec| !spec.trim().is_empty())
+                .map(ApiKey::parse)
+                .collect::<Result<_>>()
+                .context("VOID_SHRINE_API_KEYS")?;
+        }
+
+        if let Some(urls) = var("VOID_SHRINE_WEBHOOK_URLS") {
+            self.webhooks.urls = urls.split(',').map(str::trim).filter(|u| !u.is_empty()).map(str::to_string).collect();
+        }
+        if let Some(secret) = var("VOID_SHRINE_WEBHOOK_SECRET") {
+            self.webhooks.secret = Some(secret);
+        }
+
+        if let Some(sink) = var("VOID_SHRINE_AUDIT_SINK") {
+            self.audit.sink = match sink.trim() {
+                "none" => AuditSink::None,
+                "jsonl" => AuditSink::Jsonl,
+                "sqlite" => AuditSink::Sqlite,
+                other => return Err(anyhow!("VOID_SHRINE_AUDIT_SINK must be none, jsonl or sqlite, not '{}'", other)),
+            };
+        }
+        if let Some(path) = var("VOID_SHRINE_AUDIT_PATH") {
+            self.audit.path = Some(path.into());
+        }
+
+        self.apply_backend_env(&var)?;
+        Ok(())
+    }
+
+    /// Single backends from the environment; the last one set becomes the default
+    fn apply_backend_env(&mut self, var: &impl Fn(&str) -> Option<String>) -> Result<()> {
+        let mut added = Vec::new();
+        // Any chat-completions compatible API
+        if let Some(base_url) = var("VOID_SHRINE_OPENAI_BASE_URL") {
+            added.push(BackendSpec {
+                name: "openai".to_string(),
+                kind: BackendKind::OpenAi {
+                    base_url,
+                    default_model: var("VOID_SHRINE_OPENAI_MODEL").unwrap_or_else(|| "gpt-4o-mini".to_string()),
+                    api_key: None,
+                    api_key_env: var("OPENAI_API_KEY").map(|_| "OPENAI_API_KEY".to_string()),
+                    timeout_secs: None,
+                    logprobs: false,
+                },
+            });
+        }
+        if let Some(host) = var("VOID_SHRINE_OLLAMA_HOST") {
+            added.push(BackendSpec {
+                name: "ollama".to_string(),
+                kind: BackendKind::Ollama {
+                    host: Some(host),
+                    default_model: var("VOID_SHRINE_OLLAMA_MODEL"),
+                    stream: false,
+                    timeout_secs: None,
+                },
+            });
+        }
+        if var("ANTHROPIC_API_KEY").is_some() {
+            added.push(BackendSpec {
+                name: "anthropic".to_string(),
+                kind: BackendKind::Anthropic {
+                    base_url: var("ANTHROPIC_BASE_URL"),
+                    default_model: var("VOID_SHRINE_ANTHROPIC_MODEL"),
+                    api_key: None,
+                    api_key_env: Some("ANTHROPIC_API_KEY".to_string()),
+                    timeout_secs: None,
+                },
+            });
+        }
+        for spec in added {
+            self.backends.backends.retain(|existing| existing.name != spec.name);
+            self.backends.default_backend = Some(spec.name.clone());
+            self.backends.backends.push(spec);
+        }
+
+        if let Some(path) = var("VOID_SHRINE_BACKENDS_CONFIG") {
+            let text = std::fs::read_to_string(&path).with_context(|| format!("reading backends config {}", path))?;
+            self.backends = serde_json::from_str(&text).with_context(|| format!("parsing backends config {}", path))?;
+        }
+        Ok(())
+    }
+
+    /// Reports every setting out of range at once
+    pub fn validate(&self) -> Result<()> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid configuration:\n  - {}", problems.join("\n  - ")))
+        }
+    }
+
+    /// Every setting out of range, by its dotted key
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(tls) = &self.server.tls {
+            if tls.redirect_http_port == Some(self.server.port) {
+                problems.push(format!("server.tls.redirect_http_port must differ from server.port ({})", self.server.port));
+            }
+        }
+        if self.server.grpc_port == Some(self.server.port) {
+            problems.push(format!("server.grpc_port must differ from server.port ({})", self.server.port));
+        }
+        problems.extend(self.server.cors.validate());
+        problems.extend(self.server.compression.validate());
+        problems.extend(self.self_check.validate());
+        problems.extend(self.chaos.validate());
+        if self.metrics.save_interval_secs == 0 {
+            problems.push("metrics.save_interval_secs must be positive".to_string());
+        }
+        if self.metrics.live_buffer_events == 0 {
+            problems.push("metrics.live_buffer_events must be positive".to_string());
+        }
+        if self.metrics.history_retention_days == 0 || self.metrics.export_max_span_days == 0 {
+            problems.push("metrics.history_retention_days and export_max_span_days must be positive".to_string());
+        }
+        if self.metrics.chaos_retention_hours == 0 {
+            problems.push("metrics.chaos_retention_hours must be positive".to_string());
+        }
+        if self.metrics.prune_interval_secs > 0 && self.metrics.prune_idle_secs == 0 {
+            problems.push("metrics.prune_idle_secs must be positive when metrics.prune_interval_secs is".to_string());
+        }
+        problems.extend(self.rag.problems());
+        if self.limits.max_tokens == 0 || self.limits.max_prompt_bytes == 0 || self.limits.max_context_window == 0 {
+            problems.push("limits.max_tokens, max_prompt_bytes and max_context_window must be positive".to_string());
+        }
+        if self.limits.max_embed_texts == 0 || self.limits.max_embed_text_bytes == 0 {
+            problems.push("limits.max_embed_texts and max_embed_text_bytes must be positive".to_string());
+        }
+        if self.limits.max_preview_prompts == 0 {
+            problems.push("limits.max_preview_prompts must be positive".to_string());
+        }
+        let body = &self.body_limits;
+        if body.inference_bytes == 0 || body.documents_bytes == 0 || body.admin_bytes == 0 {
+            problems.push("body_limits.inference_bytes, documents_bytes and admin_bytes must be positive".to_string());
+        }
+        if body.inference_bytes < self.limits.max_prompt_bytes as u64 {
+            problems.push(format!(
+                "body_limits.inference_bytes must be at least limits.max_prompt_bytes ({} < {})",
+                body.inference_bytes, self.limits.max_prompt_bytes
+            ));
+        }
+        if self.batch.max_items == 0 || self.batch.concurrency == 0 {
+            problems.push("batch.max_items and concurrency must be positive".to_string());
+        }
+        problems.extend(self.timeouts.validate());
+        problems.extend(self.retries.validate());
+        problems.extend(self.breakers.validate());
+        problems.extend(self.concurrency.validate());
+        problems.extend(self.overload.validate());
+        problems.extend(self.usage.validate());
+        problems.extend(self.quotas.validate());
+        problems.extend(self.agents.validate());
+        problems.extend(self.specialties.validate());
+        problems.extend(self.templates.validate());
+        let throttle = &self.throttle;
+        if !(throttle.soft_load.is_finite() && throttle.soft_load >= 0.0 && throttle.soft_load < throttle.hard_load) {
+            problems.push(format!(
+                "throttle.soft_load must be zero or more and below throttle.hard_load ({} >= {})",
+                throttle.soft_load, throttle.hard_load
+            ));
+        }
+        if self.load.window_secs == 0 || self.load.max_in_flight == 0 || self.load.latency_budget_ms == 0 {
+            problems.push("load.window_secs, max_in_flight and latency_budget_ms must be positive".to_string());
+        }
+        problems.extend(self.scaling.validate());
+        problems.extend(self.webhooks.validate());
+        problems.extend(self.content_filter.validate());
+        problems.extend(self.context_screen.validate());
+        problems.extend(self.confidence.validate());
+        problems.extend(self.tokens.validate());
+        problems.extend(self.moral.validate());
+        problems.extend(self.audit.validate());
+        problems.extend(self.sessions.validate());
+        problems.extend(self.cache.validate());
+        problems.extend(self.idempotency.validate());
+        problems.extend(self.jobs.validate());
+        problems.extend(self.tokenizer.validate());
+        if self.rate_limits.capacity == 0 {
+            problems.push("rate_limits.capacity must be positive".to_string());
+        }
+        if !(self.rate_limits.refill_per_sec >= 0.0 && self.rate_limits.refill_per_sec.is_finite()) {
+            problems.push(format!("rate_limits.refill_per_sec must be zero or more (got {})", self.rate_limits.refill_per_sec));
+        }
+        problems
+    }
+}
+
+/// `name` parsed, if set
+fn parsed<T>(var: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    var(name)
+        .map(|value| value.trim().parse().map_err(|e| anyhow!("{}={:?}: {}", name, value, e)))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn the_example_config_parses_without_unknown_keys() {
+        let text = include_str!("../void-shrine.example.toml");
+        let (config, ignored) = Config::parse_toml(text).unwrap();
+        assert!(ignored.is_empty(), "{:?}", ignored);
+        config.validate().unwrap();
+        assert_eq!(config.server.socket_addr().port(), 3030);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_and_bad_values_located() {
+        let (config, ignored) = Config::parse_toml("[server]\nport = 8080\nprot = 1\n[chaos]\nintensty = 0.5\n").unwrap();
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(ignored, ["server.prot", "chaos.intensty"]);
+
+        let error = Config::parse_toml("[server]\nport = \"eighty\"\n").unwrap_err().to_string();
+        assert!(error.contains("port"), "{}", error);
+        assert!(Config::parse_toml("[[auth.keys]]\nid = \"a\"\nsecret = \"s\"\nscopes = [\"admin\"]\nscope = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let mut config = Config::from_toml("[server]\nport = 8080\n[chaos]\nintensity = 0.5\n").unwrap();
+        config
+            .apply_env(env(&[
+                ("VOID_SHRINE_PORT", "9090"),
+                ("VOID_SHRINE_CHAOS_TYPES", "network_delay, memory_pressure"),
+                ("VOID_SHRINE_CHAOS_SEED", "42"),
+                ("VOID_SHRINE_API_KEYS", "ops:secret:admin"),
+                ("VOID_SHRINE_OLLAMA_HOST", "http://gpu:11434"),
+            ]))
+            .unwrap();
+        assert_eq!(config.server.port, 9090);
+        assert_eq!(config.chaos.intensity, 0.5);
+        assert_eq!(config.chaos.chaos_types, ["network_delay", "memory_pressure"]);
+        assert_eq!(config.chaos.seed, Some(42));
+        assert_eq!(config.auth.keys[0].id, "ops");
+        assert_eq!(config.backends.default_backend.as_deref(), Some("ollama"));
+
+        let error = config.apply_env(env(&[("VOID_SHRINE_PORT", "http")])).unwrap_err().to_string();
+        assert!(error.contains("VOID_SHRINE_PORT"), "{}", error);
+    }
+
+    #[test]
+    fn validation_lists_every_problem() {
+        let mut config = Config::default();
+        config.chaos.intensity = 1.5;
+        config.rag.overlap_size = 600;
+        config.chaos.chaos_types.push("gremlins".to_string());
+        config.chaos.error_status = Some(200);
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("chaos.intensity") && error.contains("rag.overlap_size"), "{}", error);
+        assert!(error.contains("'gremlins'") && error.contains("chaos.error_status"), "{}", error);
+    }
+}
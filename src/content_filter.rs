@@ -0,0 +1,559 @@
+//! Screening of responses before they leave the server. Each configured
+//! `ResponseFilter` passes a response, redacts spans of it, which are masked,
+//! or blocks it, in which case the client gets a refusal instead and
+//! `content_filter` in the metadata says which rule fired. The built-in
+//! `PatternFilter` blocks on deny patterns and keywords and masks redact
+//! patterns and, optionally, email addresses and card numbers;
+//! `ModerationFilter` asks an external endpoint. Filters run in order: the
+//! first block wins and redactions add up. Every verdict other than pass is
+//! counted in `void_shrine_content_filter_verdicts_total` and, through the
+//! metadata, recorded in the audit log.
+//!
+//! Streamed responses are screened as they go. `StreamScreen` holds back the
+//! last `stream_holdback_chars` of text, so a match spanning chunks is seen
+//! whole, and releases the rest masked; a match longer than that can slip
+//! through. The moderation endpoint judges whole responses, so with one
+//! configured a stream is held back until the backend has finished.
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{bail, Result};
+use futures::future::BoxFuture;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::metrics::Metrics;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentFilterConfig {
+    /// Regular expressions blocking any response they match
+    pub deny_patterns: Vec<String>,
+    /// Words or phrases blocking any response containing them, whole and in any case
+    pub deny_keywords: Vec<String>,
+    /// Regular expressions whose matches are masked
+    pub redact_patterns: Vec<String>,
+    /// Mask email addresses and card numbers that pass the Luhn check
+    pub redact_pii: bool,
+    /// Replaces each masked span
+    pub mask: String,
+    /// The response sent in place of a blocked one
+    pub refusal: String,
+    /// Characters of a streamed response held back until the text after them arrives
+    pub stream_holdback_chars: usize,
+    /// Screens each response after the patterns; see `ModerationFilter`
+    pub moderation_url: Option<String>,
+    pub moderation_timeout_ms: u64,
+    /// Pass responses a filter failed to judge, rather than block them
+    pub fail_open: bool,
+}
+
+impl Default for ContentFilterConfig {
+    fn default() -> Self {
+        Self {
+            deny_patterns: Vec::new(),
+            deny_keywords: Vec::new(),
+            redact_patterns: Vec::new(),
+            redact_pii: false,
+            mask: "[redacted]".to_string(),
+            refusal: "This response was withheld by the content filter.".to_string(),
+            stream_holdback_chars: 64,
+            moderation_url: None,
+            moderation_timeout_ms: 2000,
+            fail_open: false,
+        }
+    }
+}
+
+impl ContentFilterConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (key, patterns) in [("deny_patterns", &self.deny_patterns), ("redact_patterns", &self.redact_patterns)] {
+            for pattern in patterns {
+                if let Err(e) = Regex::new(pattern) {
+                    problems.push(format!("content_filter.{} has '{}', which is not a valid pattern: {}", key, pattern, e));
+                }
+            }
+        }
+        if self.deny_keywords.iter().any(|keyword| keyword.trim().is_empty()) {
+            problems.push("content_filter.deny_keywords must not have empty entries".to_string());
+        }
+        if let Some(url) = &self.moderation_url {
+            if !reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+                problems.push(format!("content_filter.moderation_url '{}' is not an http(s) URL", url));
+            }
+        }
+        if self.stream_holdback_chars == 0 {
+            problems.push("content_filter.stream_holdback_chars must be positive".to_string());
+        }
+        if self.moderation_timeout_ms == 0 {
+            problems.push("content_filter.moderation_timeout_ms must be positive".to_string());
+        }
+        problems
+    }
+}
+
+/// A filter's judgement of one response
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Pass,
+    /// Mask these byte ranges of the response
+    Redact { rules: Vec<String>, spans: Vec<Range<usize>> },
+    Block { rule: String },
+}
+
+pub trait ResponseFilter: Send + Sync {
+    /// Labels the filter's verdicts in metrics
+    fn name(&self) -> &str;
+
+    fn screen<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Verdict>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    Redact,
+    Block,
+}
+
+impl FilterAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FilterAction::Redact => "redact",
+            FilterAction::Block => "block",
+        }
+    }
+}
+
+/// What filtering did to a response, in its metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContentFilterReport {
+    pub verdict: FilterAction,
+    /// The rule that blocked the response, or every rule that masked part of it
+    pub rules: Vec<String>,
+}
+
+const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}";
+/// 13 to 19 digits, optionally grouped by spaces or dashes
+const CARD_PATTERN: &str = r"\b\d(?:[ -]?\d){12,18}\b";
+
+enum RedactRule {
+    Pattern { rule: String, regex: Regex },
+    /// Matches that are valid card numbers only
+    CardNumber(Regex),
+}
+
+/// Deny patterns and keywords, redact patterns and PII, all local
+pub struct PatternFilter {
+    deny: Vec<(String, Regex)>,
+    redact: Vec<RedactRule>,
+}
+
+impl PatternFilter {
+    pub fn new(config: &ContentFilterConfig) -> Result<Self> {
+        let mut deny = Vec::new();
+        for pattern in &config.deny_patterns {
+            deny.push((format!("deny_pattern:{}", pattern), Regex::new(pattern)?));
+        }
+        for keyword in &config.deny_keywords {
+            let regex = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(keyword.trim())))?;
+            deny.push((format!("deny_keyword:{}", keyword.trim()), regex));
+        }
+        let mut redact = Vec::new();
+        for pattern in &config.redact_patterns {
+            redact.push(RedactRule::Pattern { rule: format!("redact_pattern:{}", pattern), regex: Regex::new(pattern)? });
+        }
+        if config.redact_pii {
+            redact.push(RedactRule::Pattern { rule: "pii:email".to_string(), regex: Regex::new(EMAIL_PATTERN)? });
+            redact.push(RedactRule::CardNumber(Regex::new(CARD_PATTERN)?));
+        }
+        Ok(Self { deny, redact })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deny.is_empty() && self.redact.is_empty()
+    }
+
+    pub fn check(&self, text: &str) -> Verdict {
+        if let Some((rule, _)) = self.deny.iter().find(|(_, regex)| regex.is_match(text)) {
+            return Verdict::Block { rule: rule.clone() };
+        }
+        let mut rules = Vec::new();
+        let mut spans = Vec::new();
+        for redact in &self.redact {
+            let (rule, found): (&str, Vec<Range<usize>>) = match redact {
+                RedactRule::Pattern { rule, regex } => (rule, regex.find_iter(text).map(|m| m.range()).collect()),
+                RedactRule::CardNumber(regex) => (
+                    "pii:card_number",
+                    regex.find_iter(text).filter(|m| luhn_valid(m.as_str())).map(|m| m.range()).collect(),
+                ),
+            };
+            if !found.is_empty() {
+                rules.push(rule.to_string());
+                spans.extend(found);
+            }
+        }
+        match spans.is_empty() {
+            true => Verdict::Pass,
+            false => Verdict::Redact { rules, spans },
+        }
+    }
+}
+
+impl ResponseFilter for PatternFilter {
+    fn name(&self) -> &str {
+        "patterns"
+    }
+
+    fn screen<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Verdict>> {
+        Box::pin(async move { Ok(self.check(text)) })
+    }
+}
+
+/// Whether the digits of `candidate` pass the Luhn check
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match i % 2 {
+            0 => digit,
+            _ if digit * 2 > 9 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    (13..=19).contains(&digits.len()) && sum.is_multiple_of(10)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ModerationVerdict {
+    Pass,
+    Redact,
+    Block,
+}
+
+#[derive(Deserialize)]
+struct ModerationReply {
+    verdict: ModerationVerdict,
+    #[serde(default)]
+    rule: Option<String>,
+    /// `[start, end)` byte offsets into the text
+    #[serde(default)]
+    spans: Vec<(usize, usize)>,
+}
+
+/// Posts `{"text": ...}` to an endpoint answering
+/// `{"verdict": "pass" | "redact" | "block", "rule": ..., "spans": [[start, end], ...]}`,
+/// spans being byte offsets of the text to mask
+pub struct ModerationFilter {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl ModerationFilter {
+    pub fn new(url: impl Into<String>, timeout: Duration) -> Result<Self> {
+        Ok(Self { client: reqwest::Client::builder().timeout(timeout).build()?, url: url.into() })
+    }
+}
+
+impl ResponseFilter for ModerationFilter {
+    fn name(&self) -> &str {
+        "moderation"
+    }
+
+    fn screen<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Verdict>> {
+        Box::pin(async move {
+            let reply: ModerationReply =
+                self.client.post(&self.url).json(&json!({ "text": text })).send().await?.error_for_status()?.json().await?;
+            let rule = format!("moderation:{}", reply.rule.as_deref().unwrap_or("unspecified"));
+            Ok(match reply.verdict {
+                ModerationVerdict::Pass => Verdict::Pass,
+                ModerationVerdict::Block => Verdict::Block { rule },
+                ModerationVerdict::Redact => {
+                    let mut spans = Vec::new();
+                    for (start, end) in reply.spans {
+                        if start > end || end > text.len() || !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+                            bail!("moderation endpoint returned span {}..{}, which doesn't fit the text", start, end);
+                        }
+                        spans.push(start..end);
+                    }
+                    Verdict::Redact { rules: vec![rule], spans }
+                }
+            })
+        })
+    }
+}
+
+/// A response after filtering
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screened {
+    /// The masked response, or the refusal
+    pub text: String,
+    /// None when every filter passed it
+    pub report: Option<ContentFilterReport>,
+}
+
+/// The configured filters, in the order they run
+pub struct ContentFilters {
+    patterns: Arc<PatternFilter>,
+    filters: Vec<Arc<dyn ResponseFilter>>,
+    mask: String,
+    refusal: String,
+    holdback: usize,
+    fail_open: bool,
+    metrics: Arc<Metrics>,
+}
+
+impl ContentFilters {
+    pub fn new(config: &ContentFilterConfig, metrics: Arc<Metrics>) -> Result<Self> {
+        let patterns = Arc::new(PatternFilter::new(config)?);
+        let mut filters: Vec<Arc<dyn ResponseFilter>> = Vec::new();
+        if !patterns.is_empty() {
+            filters.push(Arc::clone(&patterns) as Arc<dyn ResponseFilter>);
+        }
+        if let Some(url) = &config.moderation_url {
+            filters.push(Arc::new(ModerationFilter::new(url, Duration::from_millis(config.moderation_timeout_ms))?));
+        }
+        Ok(Self {
+            patterns,
+            filters,
+            mask: config.mask.clone(),
+            refusal: config.refusal.clone(),
+            holdback: config.stream_holdback_chars.max(1),
+            fail_open: config.fail_open,
+            metrics,
+        })
+    }
+
+    /// Runs `filter` after those configured
+    pub fn with_filter(mut self, filter: Arc<dyn ResponseFilter>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub async fn screen(&self, text: &str) -> Screened {
+        let mut rules: Vec<String> = Vec::new();
+        let mut spans = Vec::new();
+        for filter in &self.filters {
+            let verdict = match filter.screen(text).await {
+                Ok(verdict) => verdict,
+                Err(e) => {
+                    tracing::warn!("Content filter {} failed: {:#}", filter.name(), e);
+                    self.metrics.content_filtered(filter.name(), "error");
+                    match self.fail_open {
+                        true => Verdict::Pass,
+                        false => Verdict::Block { rule: format!("{}:unavailable", filter.name()) },
+                    }
+                }
+            };
+            match verdict {
+                Verdict::Pass => {}
+                Verdict::Block { rule } => {
+                    self.metrics.content_filtered(filter.name(), FilterAction::Block.as_str());
+                    return self.refuse(rule);
+                }
+                Verdict::Redact { rules: fired, spans: found } => {
+                    self.metrics.content_filtered(filter.name(), FilterAction::Redact.as_str());
+                    rules.extend(fired.into_iter().filter(|rule| !rules.contains(rule)).collect::<Vec<_>>());
+                    spans.extend(found);
+                }
+            }
+        }
+        match spans.is_empty() {
+            true => Screened { text: text.to_string(), report: None },
+            false => Screened {
+                text: mask_spans(text, spans, &self.mask),
+                report: Some(ContentFilterReport { verdict: FilterAction::Redact, rules }),
+            },
+        }
+    }
+
+    fn refuse(&self, rule: String) -> Screened {
+        Screened { text: self.refusal.clone(), report: Some(ContentFilterReport { verdict: FilterAction::Block, rules: vec![rule] }) }
+    }
+
+    /// Screens a response streamed in pieces; None when there are no filters
+    pub fn stream(&self) -> Option<StreamScreen<'_>> {
+        (!self.is_empty()).then(|| StreamScreen {
+            filters: self,
+            // Only the pattern filter can judge part of a response
+            incremental: self.filters.iter().all(|filter| filter.name() == self.patterns.name()),
+            pending: String::new(),
+            released: String::new(),
+            rules: Vec::new(),
+            blocked: None,
+        })
+    }
+}
+
+/// `text` with `spans`, overlapping or not, replaced by `mask`
+pub fn mask_spans(text: &str, mut spans: Vec<Range<usize>>, mask: &str) -> String {
+    spans.sort_by_key(|span| span.start);
+    let mut masked = String::with_capacity(text.len());
+    let mut copied = 0;
+    for span in spans {
+        if span.end <= copied {
+            continue;
+        }
+        if span.start >= copied {
+            masked.push_str(&text[copied..span.start]);
+            masked.push_str(mask);
+        }
+        copied = span.end;
+    }
+    masked.push_str(&text[copied..]);
+    masked
+}
+
+/// Filters a response as its pieces arrive, releasing what is safe to send
+pub struct StreamScreen<'a> {
+    filters: &'a ContentFilters,
+    /// Screen as text arrives, rather than once it has all arrived
+    incremental: bool,
+    pending: String,
+    released: String,
+    rules: Vec<String>,
+    blocked: Option<String>,
+}
+
+impl StreamScreen<'_> {
+    /// The text to send now, if any. Once the response is blocked nothing more is.
+    pub fn push(&mut self, text: String) -> Option<String> {
+        if self.blocked.is_some() {
+            return None;
+        }
+        self.pending.push_str(&text);
+        if !self.incremental {
+            return None;
+        }
+        let spans = match self.filters.patterns.check(&self.pending) {
+            Verdict::Block { rule } => {
+                self.filters.metrics.content_filtered(self.filters.patterns.name(), FilterAction::Block.as_str());
+                self.blocked = Some(rule);
+                self.pending.clear();
+                return None;
+            }
+            Verdict::Redact { spans, .. } => spans,
+            Verdict::Pass => Vec::new(),
+        };
+        let mut cut = self.pending.char_indices().rev().nth(self.filters.holdback - 1).map_or(0, |(at, _)| at);
+        // Never split a match
+        while let Some(span) = spans.iter().find(|span| span.start < cut && cut < span.end) {
+            cut = span.start;
+        }
+        if cut == 0 {
+            return None;
+        }
+        let rest = self.pending.split_off(cut);
+        let ready = std::mem::replace(&mut self.pending, rest);
+        let released = self.release(&ready);
+        self.released.push_str(&released);
+        Some(released)
+    }
+
+    /// Masks `text`, noting the rules that fired
+    fn release(&mut self, text: &str) -> String {
+        match self.filters.patterns.check(text) {
+            Verdict::Redact { rules, spans } => {
+                for rule in rules {
+                    if !self.rules.contains(&rule) {
+                        self.rules.push(rule);
+                    }
+                }
+                mask_spans(text, spans, &self.filters.mask)
+            }
+            _ => text.to_string(),
+        }
+    }
+
+    /// Screens what is still held back: the text left to send, and the whole
+    /// response as it reached the client, or the refusal
+    pub async fn finish(mut self) -> (String, Screened) {
+        if let Some(rule) = self.blocked.take() {
+            return (String::new(), self.filters.refuse(rule));
+        }
+        if !self.incremental {
+            let screened = self.filters.screen(&self.pending).await;
+            let tail = match &screened.report {
+                Some(ContentFilterReport { verdict: FilterAction::Block, .. }) => String::new(),
+                _ => screened.text.clone(),
+            };
+            return (tail, screened);
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let tail = match self.filters.patterns.check(&pending) {
+            Verdict::Block { rule } => {
+                self.filters.metrics.content_filtered(self.filters.patterns.name(), FilterAction::Block.as_str());
+                return (String::new(), self.filters.refuse(rule));
+            }
+            _ => self.release(&pending),
+        };
+        self.released.push_str(&tail);
+        let report = (!self.rules.is_empty()).then(|| {
+            self.filters.metrics.content_filtered(self.filters.patterns.name(), FilterAction::Redact.as_str());
+            ContentFilterReport { verdict: FilterAction::Redact, rules: std::mem::take(&mut self.rules) }
+        });
+        (tail, Screened { text: self.released, report })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters(config: ContentFilterConfig) -> ContentFilters {
+        ContentFilters::new(&config, Arc::new(Metrics::default())).unwrap()
+    }
+
+    #[test]
+    fn patterns_block_and_pii_is_masked() {
+        let patterns = PatternFilter::new(&ContentFilterConfig {
+            deny_keywords: vec!["launch codes".to_string()],
+            redact_pii: true,
+            ..ContentFilterConfig::default()
+        })
+        .unwrap();
+        assert_eq!(patterns.check("The LAUNCH CODES are ready"), Verdict::Block { rule: "deny_keyword:launch codes".to_string() });
+        assert_eq!(patterns.check("relaunch codesign"), Verdict::Pass);
+
+        let text = "Mail ops@void.example or pay with 4111 1111 1111 1111, not 4111 1111 1111 1112";
+        let Verdict::Redact { rules, spans } = patterns.check(text) else { panic!("nothing redacted") };
+        assert_eq!(rules, ["pii:email", "pii:card_number"]);
+        assert_eq!(mask_spans(text, spans, "#"), "Mail # or pay with #, not 4111 1111 1111 1112");
+    }
+
+    #[tokio::test]
+    async fn streams_are_masked_across_chunk_boundaries() {
+        let filters = filters(ContentFilterConfig { redact_pii: true, stream_holdback_chars: 8, ..ContentFilterConfig::default() });
+        let mut screen = filters.stream().unwrap();
+        let mut sent = String::new();
+        for chunk in ["Write to the keeper at ke", "eper@shrine.exa", "mple.org today, and wait for an answer."] {
+            sent.extend(screen.push(chunk.to_string()));
+        }
+        let (tail, screened) = screen.finish().await;
+        sent.push_str(&tail);
+        assert_eq!(sent, "Write to the keeper at [redacted] today, and wait for an answer.");
+        assert_eq!(screened.text, sent);
+        assert_eq!(screened.report.unwrap().rules, ["pii:email"]);
+
+        // A blocked stream sends nothing more once the pattern is seen
+        let filters = filters_with_deny("forbidden lore");
+        let mut screen = filters.stream().unwrap();
+        assert_eq!(screen.push("This is the forbi".to_string()), None);
+        assert_eq!(screen.push("dden lore, which".to_string()), None);
+        assert_eq!(screen.push(" goes on and on for a good while longer than the holdback".to_string()), None);
+        let (tail, screened) = screen.finish().await;
+        assert_eq!((tail.as_str(), screened.text.as_str()), ("", ContentFilterConfig::default().refusal.as_str()));
+    }
+
+    fn filters_with_deny(keyword: &str) -> ContentFilters {
+        filters(ContentFilterConfig { deny_keywords: vec![keyword.to_string()], ..ContentFilterConfig::default() })
+    }
+}
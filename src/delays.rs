@@ -0,0 +1,143 @@
+//! How long chaos delays hold requests up. Each delay chaos type draws its
+//! delay from a distribution set in `chaos.delays`: uniform over a range,
+//! normal clamped at zero, exponential, or Pareto for the long tail real
+//! latency has, capped so one draw can't stall a request for good. Draws use
+//! the chaos decision's RNG, so a seeded config repeats them.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// A latency distribution, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case", deny_unknown_fields)]
+pub enum DelayDistribution {
+    /// From `min_ms` up to, but not including, `max_ms`
+    Uniform { min_ms: u64, max_ms: u64 },
+    /// Draws below zero are no delay
+    Normal { mean_ms: f64, stddev_ms: f64 },
+    Exponential {
+        mean_ms: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cap_ms: Option<u64>,
+    },
+    /// At least `scale_ms`; a smaller `shape` gives a longer tail. Needs `cap_ms`.
+    Pareto {
+        scale_ms: f64,
+        shape: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cap_ms: Option<u64>,
+    },
+}
+
+impl DelayDistribution {
+    pub fn uniform(min_ms: u64, max_ms: u64) -> Self {
+        DelayDistribution::Uniform { min_ms, max_ms }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> u64 {
+        // In (0, 1], so its logarithm is finite
+        fn open(rng: &mut impl Rng) -> f64 {
+            1.0 - rng.gen::<f64>()
+        }
+        let capped = |ms: f64, cap_ms: Option<u64>| cap_ms.map_or(ms, |cap| ms.min(cap as f64));
+        let ms = match *self {
+            DelayDistribution::Uniform { min_ms, max_ms } => return if max_ms > min_ms { rng.gen_range(min_ms..max_ms) } else { min_ms },
+            DelayDistribution::Normal { mean_ms, stddev_ms } => {
+                // Box-Muller
+                let (radius, angle) = ((-2.0 * open(rng).ln()).sqrt(), std::f64::consts::TAU * open(rng));
+                mean_ms + stddev_ms * radius * angle.cos()
+            }
+            DelayDistribution::Exponential { mean_ms, cap_ms } => capped(-mean_ms * open(rng).ln(), cap_ms),
+            DelayDistribution::Pareto { scale_ms, shape, cap_ms } => capped(scale_ms / open(rng).powf(1.0 / shape), cap_ms),
+        };
+        ms.max(0.0).round() as u64
+    }
+
+    /// Every parameter out of range, as messages naming `field`
+    pub fn validate(&self, field: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut non_negative = |name: &str, value: f64| {
+            if !value.is_finite() || value < 0.0 {
+                problems.push(format!("{}.{} must be a non-negative number (got {})", field, name, value));
+            }
+        };
+        match *self {
+            DelayDistribution::Uniform { min_ms, max_ms } => {
+                if min_ms > max_ms {
+                    problems.push(format!("{}.min_ms must not exceed max_ms ({} > {})", field, min_ms, max_ms));
+                }
+            }
+            DelayDistribution::Normal { mean_ms, stddev_ms } => {
+                non_negative("mean_ms", mean_ms);
+                non_negative("stddev_ms", stddev_ms);
+            }
+            DelayDistribution::Exponential { mean_ms, .. } => non_negative("mean_ms", mean_ms),
+            DelayDistribution::Pareto { scale_ms, shape, cap_ms } => {
+                let positive = |value: f64| value.is_finite() && value > 0.0;
+                if !positive(scale_ms) || !positive(shape) {
+                    problems.push(format!("{}.scale_ms and shape must be positive (got {} and {})", field, scale_ms, shape));
+                }
+                match cap_ms {
+                    None => problems.push(format!("{}.cap_ms is required for a pareto distribution", field)),
+                    Some(cap) if (cap as f64) < scale_ms => {
+                        problems.push(format!("{}.cap_ms must be at least scale_ms ({} < {})", field, cap, scale_ms))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn draws(distribution: DelayDistribution, n: usize) -> Vec<u64> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..n).map(|_| distribution.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn draws_follow_their_distribution_and_repeat_under_a_seed() {
+        let uniform = draws(DelayDistribution::uniform(500, 2500), 1000);
+        assert!(uniform.iter().all(|ms| (500..2500).contains(ms)));
+
+        let normal = draws(DelayDistribution::Normal { mean_ms: 100.0, stddev_ms: 200.0 }, 2000);
+        assert!(normal.contains(&0));
+        let mean = normal.iter().sum::<u64>() as f64 / normal.len() as f64;
+        // Clamping at zero lifts the mean to about 140
+        assert!((120.0..160.0).contains(&mean), "{}", mean);
+
+        let exponential = draws(DelayDistribution::Exponential { mean_ms: 300.0, cap_ms: None }, 4000);
+        let mean = exponential.iter().sum::<u64>() as f64 / exponential.len() as f64;
+        assert!((270.0..330.0).contains(&mean), "{}", mean);
+
+        let pareto = DelayDistribution::Pareto { scale_ms: 100.0, shape: 1.2, cap_ms: Some(5000) };
+        let tail = draws(pareto, 4000);
+        assert!(tail.iter().all(|ms| (100..=5000).contains(ms)));
+        assert!(tail.contains(&5000), "the cap is reached");
+        let mut sorted = tail.clone();
+        sorted.sort();
+        let median = sorted[sorted.len() / 2];
+        assert!(median < 200, "{}", median);
+
+        assert_eq!(draws(pareto, 50), draws(pareto, 50));
+    }
+
+    #[test]
+    fn parameters_are_checked() {
+        assert_eq!(DelayDistribution::uniform(10, 5).validate("d"), ["d.min_ms must not exceed max_ms (10 > 5)"]);
+        assert_eq!(DelayDistribution::Normal { mean_ms: -1.0, stddev_ms: 1.0 }.validate("d").len(), 1);
+        assert_eq!(
+            DelayDistribution::Pareto { scale_ms: 100.0, shape: 1.5, cap_ms: None }.validate("d"),
+            ["d.cap_ms is required for a pareto distribution"]
+        );
+        assert!(DelayDistribution::Exponential { mean_ms: 50.0, cap_ms: None }.validate("d").is_empty());
+        let parsed: DelayDistribution = toml::from_str("distribution = \"pareto\"\nscale_ms = 200.0\nshape = 1.5\ncap_ms = 10000\n").unwrap();
+        assert_eq!(parsed, DelayDistribution::Pareto { scale_ms: 200.0, shape: 1.5, cap_ms: Some(10_000) });
+    }
+}
@@ -0,0 +1,454 @@
+//! gRPC interface alongside HTTP, built with the `grpc` feature: the service
+//! of `proto/void_shrine.proto`, listening on `server.grpc_port`. Each RPC is
+//! answered by the same `VoidShrineMCP` handler as the HTTP route it mirrors,
+//! so validation, rate limits, chaos, caching and metrics apply alike, and
+//! needs the API key scope that route needs, from `authorization: Bearer`
+//! metadata. Failures carry the HTTP API's error code as `error-code`
+//! metadata, with `request-id` and `retry-after-ms` when known.
+
+// tonic's traits return `Status`, large as it is
+#![allow(clippy::result_large_err)]
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use tonic::metadata::MetadataValue;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+use crate::auth::Tenancy;
+use crate::confidence::ConfidenceBreakdown;
+use crate::content_filter::ContentFilterReport;
+use crate::mcp_server::{
+    ChaosRequest, ChaosResponse, Citation, InferenceEvent, MCPError, MCPMetadata, MCPParams, MCPRequest, MCPResponse,
+    McpMethod, MoralRecenteringMode, MoralRecenteringReport, MoralRequest, MoralResponse, ParamSources, ResponseMetrics, ScalingRequest,
+    ScalingResponse, ShadowChaos, ThrottleStatus, VoidShrineMCP,
+};
+use crate::moral::{ScoreBreakdown, ScoreFactor};
+
+/// The messages and service generated from `proto/void_shrine.proto`
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("void_shrine.v1");
+}
+
+use proto::void_shrine_server::{VoidShrine, VoidShrineServer};
+
+/// The gRPC service over a `VoidShrineMCP`
+pub struct GrpcService {
+    service: Arc<VoidShrineMCP>,
+}
+
+impl GrpcService {
+    pub fn new(service: Arc<VoidShrineMCP>) -> Self {
+        Self { service }
+    }
+
+    /// Ready to add to a `tonic::transport::Server`
+    pub fn server(service: Arc<VoidShrineMCP>) -> VoidShrineServer<GrpcService> {
+        VoidShrineServer::new(Self::new(service))
+    }
+
+    /// Authorizes the RPC as a request to the HTTP route at `path`
+    fn admit<T>(&self, request: &Request<T>, path: &str) -> Result<Tenancy, Status> {
+        let authorization = request.metadata().get("authorization").and_then(|value| value.to_str().ok());
+        self.service.auth.admit(&"gRPC", path, authorization).map_err(|e| status(&e, None))
+    }
+
+    async fn handle(&self, request: Request<proto::InferRequest>, method: McpMethod) -> Result<Response<proto::InferResponse>, Status> {
+        let tenancy = self.admit(&request, "/api/mcp")?;
+        let proto::InferRequest { params, request_id } = request.into_inner();
+        let request = MCPRequest { method: method.as_str().to_string(), params: required(params, "params")?.try_into()?, request_id, idempotency_key: None };
+        self.service.admit_agent(&tenancy, &request.params.agent_id).map_err(|e| status(&e, None))?;
+        match self.service.handle_mcp_request(request).await {
+            Ok(response) => Ok(Response::new(response.into())),
+            Err(failure) => Err(status(&failure.error, failure.request_id.as_deref())),
+        }
+    }
+}
+
+/// Binds `addr` now, so a port in use stops startup, and returns the server,
+/// which stops accepting calls once `shutdown` completes and ends when the
+/// calls in flight have
+pub fn bind(
+    service: Arc<VoidShrineMCP>,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<impl Future<Output = ()>> {
+    let incoming = TcpIncoming::new(addr, true, None).map_err(|e| anyhow!("binding {}: {}", addr, e))?;
+    let server = tonic::transport::Server::builder()
+        .add_service(GrpcService::server(service))
+        .serve_with_incoming_shutdown(incoming, shutdown);
+    Ok(async move {
+        if let Err(e) = server.await {
+            tracing::error!("gRPC server failed: {}", e);
+        }
+    })
+}
+
+/// The status for an error the HTTP API would answer with `http_status`
+pub fn status(error: &MCPError, request_id: Option<&str>) -> Status {
+    let code = match error.http_status() {
+        400 => Code::InvalidArgument,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        409 => Code::FailedPrecondition,
+        413 | 429 => Code::ResourceExhausted,
+        499 => Code::Cancelled,
+        501 => Code::Unimplemented,
+        502 | 503 => Code::Unavailable,
+        504 => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    };
+    let mut status = Status::new(code, error.to_string());
+    let metadata = status.metadata_mut();
+    metadata.insert("error-code", MetadataValue::from_static(error.code()));
+    if let Some(request_id) = request_id.and_then(|id| MetadataValue::try_from(id).ok()) {
+        metadata.insert("request-id", request_id);
+    }
+    if let Some(retry_after) = error.retry_after() {
+        metadata.insert("retry-after-ms", MetadataValue::from(retry_after.as_millis() as u64));
+    }
+    if let Some(upstream) = error.upstream_status() {
+        metadata.insert("upstream-status", MetadataValue::from(upstream));
+    }
+    status
+}
+
+fn required<T>(field: Option<T>, name: &str) -> Result<T, Status> {
+    field.ok_or_else(|| Status::invalid_argument(format!("Invalid params: {} is required", name)))
+}
+
+fn instant(value: Option<String>, name: &str) -> Result<Option<DateTime<Utc>>, Status> {
+    value
+        .map(|value| DateTime::parse_from_rfc3339(&value).map(|instant| instant.with_timezone(&Utc)))
+        .transpose()
+        .map_err(|e| Status::invalid_argument(format!("Invalid params: {} must be an RFC 3339 instant: {}", name, e)))
+}
+
+#[tonic::async_trait]
+impl VoidShrine for GrpcService {
+    type InferStreamStream = Pin<Box<dyn Stream<Item = Result<proto::InferenceEvent, Status>> + Send>>;
+
+    async fn infer(&self, request: Request<proto::InferRequest>) -> Result<Response<proto::InferResponse>, Status> {
+        self.handle(request, McpMethod::LlmInference).await
+    }
+
+    /// Dropping the stream when the client goes away cancels the inference
+    async fn infer_stream(&self, request: Request<proto::InferRequest>) -> Result<Response<Self::InferStreamStream>, Status> {
+        let tenancy = self.admit(&request, "/api/mcp/stream")?;
+        let proto::InferRequest { params, request_id } = request.into_inner();
+        let params: MCPParams = required(params, "params")?.try_into()?;
+        self.service.admit_agent(&tenancy, &params.agent_id).map_err(|e| status(&e, None))?;
+        let events = self.service.stream_llm_inference(params, request_id).map_err(|e| status(&e, None))?;
+        let request_id = MetadataValue::try_from(events.request_id.as_str()).ok();
+        let mut response = Response::new(Box::pin(events.map(|event| Ok(event.into()))) as Self::InferStreamStream);
+        if let Some(request_id) = request_id {
+            response.metadata_mut().insert("request-id", request_id);
+        }
+        Ok(response)
+    }
+
+    async fn rag_query(&self, request: Request<proto::InferRequest>) -> Result<Response<proto::InferResponse>, Status> {
+        self.handle(request, McpMethod::RagQuery).await
+    }
+
+    async fn chaos(&self, request: Request<proto::ChaosRequest>) -> Result<Response<proto::ChaosResponse>, Status> {
+        self.admit(&request, "/api/chaos")?;
+        let proto::ChaosRequest { agent_id, specialty, chaos_type, intensity } = request.into_inner();
+        let response = self.service.handle_chaos(ChaosRequest { agent_id, specialty, chaos_type, intensity }).await;
+        Ok(Response::new(response.into()))
+    }
+
+    async fn throttle(&self, request: Request<proto::ThrottleRequest>) -> Result<Response<proto::ThrottleStatus>, Status> {
+        let tenancy = self.admit(&request, "/api/throttle")?;
+        let agent_id = request.into_inner().agent_id;
+        let throttle = self.service.handle_throttle(&tenancy, agent_id).await.map_err(|e| status(&e, None))?;
+        Ok(Response::new(throttle.into()))
+    }
+
+    async fn scaling(&self, request: Request<proto::ScalingRequest>) -> Result<Response<proto::ScalingResponse>, Status> {
+        self.admit(&request, "/api/scaling")?;
+        let proto::ScalingRequest { agent_id, response_time, token_count, success } = request.into_inner();
+        let response = self.service.handle_scaling(ScalingRequest { agent_id, response_time, token_count, success }).await;
+        Ok(Response::new(response.into()))
+    }
+
+    async fn moral_recenter(&self, request: Request<proto::MoralRequest>) -> Result<Response<proto::MoralResponse>, Status> {
+        self.admit(&request, "/api/moral-recentering")?;
+        let proto::MoralRequest { original_prompt, specialty, void_shrine_context, ethical_framework, strict } = request.into_inner();
+        let request = MoralRequest { original_prompt, specialty, void_shrine_context, ethical_framework, strict };
+        let response = self.service.handle_moral_recentering(request).await.map_err(|e| status(&e, None))?;
+        Ok(Response::new(response.into()))
+    }
+}
+
+impl TryFrom<proto::Params> for MCPParams {
+    type Error = Status;
+
+    fn try_from(params: proto::Params) -> Result<Self, Status> {
+        let moral_recentering = match params.moral_recentering() {
+            proto::MoralRecenteringMode::Auto => MoralRecenteringMode::Auto,
+            proto::MoralRecenteringMode::On => MoralRecenteringMode::On,
+            proto::MoralRecenteringMode::Off => MoralRecenteringMode::Off,
+        };
+        Ok(MCPParams {
+            agent_id: params.agent_id,
+            model: params.model,
+            specialty: params.specialty,
+            prompt: params.prompt,
+            max_tokens: params.max_tokens,
+            temperature: params.temperature,
+            use_rag: params.use_rag,
+            context_window: params.context_window,
+            flat_rag_context: params.flat_rag_context.unwrap_or(true),
+            since: instant(params.since, "since")?,
+            until: instant(params.until, "until")?,
+            doc_ids: params.doc_ids.map(|doc_ids| doc_ids.ids),
+            dedupe_chunks: params.dedupe_chunks.unwrap_or(true),
+            ethical_framework: params.ethical_framework,
+            void_shrine_context: params.void_shrine_context,
+            moral_recentering,
+            session_id: params.session_id,
+            timeout_ms: params.timeout_ms,
+            template: params.template,
+            verbose_confidence: params.verbose_confidence,
+            dry_run: false,
+            dry_run_skip_conditions: false,
+            rag_top_k: None,
+            rag_min_score: None,
+            rag_filters: Default::default(),
+            rag_collection: None,
+            rag_mode: None,
+            rag_engine: None,
+            sources: ParamSources::default(),
+        })
+    }
+}
+
+impl From<MCPParams> for proto::Params {
+    fn from(params: MCPParams) -> Self {
+        let moral_recentering = match params.moral_recentering {
+            MoralRecenteringMode::Auto => proto::MoralRecenteringMode::Auto,
+            MoralRecenteringMode::On => proto::MoralRecenteringMode::On,
+            MoralRecenteringMode::Off => proto::MoralRecenteringMode::Off,
+        };
+        proto::Params {
+            agent_id: params.agent_id,
+            model: params.model,
+            specialty: params.specialty,
+            prompt: params.prompt,
+            max_tokens: params.max_tokens,
+            temperature: params.temperature,
+            use_rag: params.use_rag,
+            context_window: params.context_window,
+            flat_rag_context: Some(params.flat_rag_context),
+            since: params.since.map(|since| since.to_rfc3339()),
+            until: params.until.map(|until| until.to_rfc3339()),
+            doc_ids: params.doc_ids.map(|ids| proto::DocIds { ids }),
+            dedupe_chunks: Some(params.dedupe_chunks),
+            ethical_framework: params.ethical_framework,
+            void_shrine_context: params.void_shrine_context,
+            moral_recentering: moral_recentering.into(),
+            session_id: params.session_id,
+            timeout_ms: params.timeout_ms,
+            template: params.template,
+            verbose_confidence: params.verbose_confidence,
+        }
+    }
+}
+
+impl From<MCPResponse> for proto::InferResponse {
+    fn from(response: MCPResponse) -> Self {
+        let result = response.result;
+        proto::InferResponse {
+            result: Some(proto::InferResult {
+                response: result.response,
+                metrics: Some(result.metrics.into()),
+                rag_context: result.rag_context.map(|blocks| proto::RagContext { blocks }),
+                citations: result.citations.unwrap_or_default().into_iter().map(Into::into).collect(),
+                moral_recentering: result.moral_recentering.map(Into::into),
+            }),
+            metadata: Some(response.metadata.into()),
+        }
+    }
+}
+
+impl From<ResponseMetrics> for proto::Metrics {
+    fn from(metrics: ResponseMetrics) -> Self {
+        proto::Metrics {
+            response_time_ms: metrics.response_time_ms,
+            token_count: metrics.token_count,
+            prompt_tokens: metrics.prompt_tokens,
+            completion_tokens: metrics.completion_tokens,
+            rag_documents_used: metrics.rag_documents_used,
+            confidence_score: metrics.confidence_score,
+            confidence_breakdown: metrics.confidence_breakdown.map(|breakdown| (*breakdown).into()),
+            throttle_delay_ms: metrics.throttle_delay_ms,
+            rag_chunks_included: metrics.rag_chunks_included,
+            rag_chunks_dropped: metrics.rag_chunks_dropped,
+            attempts: metrics.attempts,
+            retry_delay_ms: metrics.retry_delay_ms,
+        }
+    }
+}
+
+impl From<ConfidenceBreakdown> for proto::ConfidenceBreakdown {
+    fn from(breakdown: ConfidenceBreakdown) -> Self {
+        let ConfidenceBreakdown { score, retrieval, finish, logprob, length, corruption } = breakdown;
+        proto::ConfidenceBreakdown { score, retrieval, finish, logprob, length, corruption }
+    }
+}
+
+impl From<MCPMetadata> for proto::Metadata {
+    fn from(metadata: MCPMetadata) -> Self {
+        proto::Metadata {
+            request_id: metadata.request_id,
+            timestamp: metadata.timestamp.to_rfc3339(),
+            void_shrine_token: metadata.void_shrine_token,
+            chaos_applied: metadata.chaos_applied,
+            chaos_type: metadata.chaos_type,
+            chaos_decision: metadata.chaos_decision,
+            chaos_seed: metadata.chaos_seed,
+            moral_recentered: metadata.moral_recentered,
+            rag_unavailable: metadata.rag_unavailable,
+            session_turn: metadata.session_turn,
+            cached: metadata.cached,
+            served_model: metadata.served_model,
+            fallback_depth: metadata.fallback_depth,
+            content_filter: metadata.content_filter.map(Into::into),
+            would_have_applied: metadata.would_have_applied.map(Into::into),
+        }
+    }
+}
+
+impl From<ShadowChaos> for proto::ShadowChaos {
+    fn from(shadow: ShadowChaos) -> Self {
+        proto::ShadowChaos { chaos_type: shadow.chaos_type, delay_ms: shadow.delay_ms }
+    }
+}
+
+impl From<ContentFilterReport> for proto::ContentFilterReport {
+    fn from(report: ContentFilterReport) -> Self {
+        proto::ContentFilterReport { verdict: report.verdict.as_str().to_string(), rules: report.rules }
+    }
+}
+
+impl From<Citation> for proto::Citation {
+    fn from(citation: Citation) -> Self {
+        proto::Citation {
+            index: citation.index as u64,
+            document_id: citation.document_id,
+            title: citation.title,
+            chunk_id: citation.chunk_id,
+            score: citation.score,
+            snippet: citation.snippet,
+            metadata: citation.metadata,
+        }
+    }
+}
+
+impl From<MoralRecenteringReport> for proto::MoralRecenteringReport {
+    fn from(report: MoralRecenteringReport) -> Self {
+        proto::MoralRecenteringReport {
+            ethical_framework: report.ethical_framework,
+            recentered: report.recentered,
+            ethical_adjustments: report.ethical_adjustments,
+            care_ethics_score: report.care_ethics_score,
+            score_breakdown: Some(report.score_breakdown.into()),
+        }
+    }
+}
+
+impl From<ScoreBreakdown> for proto::ScoreBreakdown {
+    fn from(breakdown: ScoreBreakdown) -> Self {
+        let factors = breakdown
+            .factors
+            .into_iter()
+            .map(|ScoreFactor { factor, matched, contribution }| proto::ScoreFactor { factor, matched, contribution })
+            .collect();
+        proto::ScoreBreakdown { base: breakdown.base, factors }
+    }
+}
+
+impl From<InferenceEvent> for proto::InferenceEvent {
+    fn from(event: InferenceEvent) -> Self {
+        use proto::inference_event::Event;
+        let event = match event {
+            InferenceEvent::ChaosApplied { applied, chaos_type, would_have_applied } => {
+                Event::ChaosApplied(proto::ChaosApplied { applied, chaos_type, would_have_applied: would_have_applied.map(Into::into) })
+            }
+            InferenceEvent::RagContext { citations, rag_context } => Event::RagContext(proto::RagContextEvent {
+                citations: citations.into_iter().map(Into::into).collect(),
+                rag_context: rag_context.map(|blocks| proto::RagContext { blocks }),
+            }),
+            InferenceEvent::MoralRecentering { specialty, report } => {
+                Event::MoralRecentering(proto::MoralRecenteringEvent { specialty, report: report.map(Into::into) })
+            }
+            InferenceEvent::Delta { text } => Event::Delta(proto::Delta { text }),
+            InferenceEvent::Done { response, metrics, metadata } => {
+                Event::Done(proto::Done { response, metrics: Some((*metrics).into()), metadata: Some(metadata.into()) })
+            }
+            InferenceEvent::Error { code, message } => Event::Error(proto::ErrorEvent { message, code }),
+        };
+        proto::InferenceEvent { event: Some(event) }
+    }
+}
+
+impl From<ChaosResponse> for proto::ChaosResponse {
+    fn from(response: ChaosResponse) -> Self {
+        let ChaosResponse { apply_chaos, effect, delay_ms, decision, seed, would_have_applied } = response;
+        let would_have_applied = would_have_applied.map(Into::into);
+        proto::ChaosResponse { apply_chaos, effect, delay_ms, decision, seed, would_have_applied }
+    }
+}
+
+impl From<ThrottleStatus> for proto::ThrottleStatus {
+    fn from(status: ThrottleStatus) -> Self {
+        proto::ThrottleStatus {
+            should_throttle: status.should_throttle,
+            delay_ms: status.delay_ms,
+            reason: status.reason,
+            agent_load: status.agent_load,
+            remaining_tokens: status.remaining_tokens,
+            bucket_capacity: status.bucket_capacity,
+            policy: status.policy.as_str().to_string(),
+            refill_per_sec: status.refill_per_sec,
+            next_token_in_ms: status.next_token_in_ms,
+            in_flight: status.in_flight,
+            max_concurrency: status.max_concurrency,
+            queued: status.queued,
+            server_queue_depth: status.server_queue_depth,
+            estimated_wait_ms: status.estimated_wait_ms,
+        }
+    }
+}
+
+impl From<ScalingResponse> for proto::ScalingResponse {
+    fn from(response: ScalingResponse) -> Self {
+        let adjustments = response.adjustments;
+        proto::ScalingResponse {
+            adjustments: Some(proto::ScalingAdjustments {
+                description: adjustments.description,
+                capacity_change: adjustments.capacity_change,
+                priority_adjustment: adjustments.priority_adjustment,
+            }),
+        }
+    }
+}
+
+impl From<MoralResponse> for proto::MoralResponse {
+    fn from(response: MoralResponse) -> Self {
+        proto::MoralResponse {
+            recentered_prompt: response.recentered_prompt,
+            ethical_adjustments: response.ethical_adjustments,
+            care_ethics_score: response.care_ethics_score,
+            score_breakdown: Some(response.score_breakdown.into()),
+            framing: response.framing,
+        }
+    }
+}
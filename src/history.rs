@@ -0,0 +1,323 @@
+//! Agent activity by the hour, for capacity planning outside the server.
+//! Each agent's requests, failures, latencies, tokens and chaos events are
+//! counted in hourly buckets, with the same latency histogram as the
+//! per-minute `agent_stats`. Buckets older than
+//! `metrics.history_retention_days` are dropped; the rest are kept across
+//! restarts with the other agent metrics in `metrics.state_path`.
+//! `GET /api/metrics/export` streams them as CSV or JSON lines one period at
+//! a time, so a month of rows is never built in memory whole.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use warp::hyper::body::Bytes;
+use crate::agent_stats::LatencyHistogram;
+use crate::usage::UsageGranularity;
+
+/// Columns of a CSV export, in order
+pub const CSV_HEADER: &str = "timestamp,agent_id,requests,errors,p50_ms,p95_ms,prompt_tokens,completion_tokens,chaos_events\r\n";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Query parameters of `GET /api/metrics/export`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportParams {
+    pub format: ExportFormat,
+    pub agent_id: Option<String>,
+    /// Defaults to a day before `until`
+    pub since: Option<DateTime<Utc>>,
+    /// Defaults to now
+    pub until: Option<DateTime<Utc>>,
+    pub granularity: UsageGranularity,
+}
+
+/// A validated export: the periods overlapping `since..until`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportPlan {
+    pub format: ExportFormat,
+    pub agent_id: Option<String>,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub granularity: UsageGranularity,
+}
+
+impl ExportPlan {
+    /// What a download of the export is saved as
+    pub fn filename(&self) -> String {
+        format!("void-shrine-metrics-{}-{}.{}", self.since.format("%Y%m%dT%H%M%SZ"), self.until.format("%Y%m%dT%H%M%SZ"), self.format.extension())
+    }
+}
+
+/// Counts of one agent over one hour, or a longer period when summed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Activity {
+    pub requests: u64,
+    /// Finished requests that failed
+    pub errors: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub chaos_events: u64,
+    #[serde(flatten)]
+    pub latency: LatencyHistogram,
+}
+
+impl Activity {
+    fn add(&mut self, other: &Activity) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.chaos_events += other.chaos_events;
+        self.latency.merge(&other.latency);
+    }
+}
+
+/// One hour of one agent's activity, as saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryBucket {
+    /// Start of the hour
+    pub hour: DateTime<Utc>,
+    pub agent_id: String,
+    #[serde(flatten)]
+    pub activity: Activity,
+}
+
+/// One line of an export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportRow {
+    /// Start of the hour or day
+    pub timestamp: DateTime<Utc>,
+    pub agent_id: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub chaos_events: u64,
+}
+
+impl ExportRow {
+    fn new(timestamp: DateTime<Utc>, agent_id: String, activity: &Activity) -> Self {
+        let latency = activity.latency.percentiles();
+        Self {
+            timestamp,
+            agent_id,
+            requests: activity.requests,
+            errors: activity.errors,
+            p50_ms: latency.p50_ms,
+            p95_ms: latency.p95_ms,
+            prompt_tokens: activity.prompt_tokens,
+            completion_tokens: activity.completion_tokens,
+            chaos_events: activity.chaos_events,
+        }
+    }
+
+    /// The row as a CSV record, ending in CRLF
+    pub fn csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}\r\n",
+            self.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            csv_field(&self.agent_id),
+            self.requests,
+            self.errors,
+            self.p50_ms,
+            self.p95_ms,
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.chaos_events
+        )
+    }
+}
+
+/// `field` quoted when it holds a comma, quote or line break, its quotes doubled
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+type BucketKey = (DateTime<Utc>, String);
+
+/// The hourly buckets, oldest first
+#[derive(Debug)]
+pub struct ActivityHistory {
+    retention_days: u32,
+    buckets: Mutex<BTreeMap<BucketKey, Activity>>,
+}
+
+impl Default for ActivityHistory {
+    fn default() -> Self {
+        Self::new(31)
+    }
+}
+
+impl ActivityHistory {
+    pub fn new(retention_days: u32) -> Self {
+        Self { retention_days, buckets: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Takes back buckets saved by `buckets`
+    pub fn restore(&self, saved: Vec<HistoryBucket>) {
+        let mut buckets = self.lock();
+        for bucket in saved {
+            buckets.entry((bucket.hour, bucket.agent_id)).or_default().add(&bucket.activity);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<BucketKey, Activity>> {
+        self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Updates the agent's bucket of `now`'s hour, dropping buckets past retention
+    fn record(&self, now: DateTime<Utc>, agent_id: &str, update: impl FnOnce(&mut Activity)) {
+        let hour = UsageGranularity::Hour.start(now);
+        let mut buckets = self.lock();
+        update(buckets.entry((hour, agent_id.to_string())).or_default());
+        let cutoff = hour - Duration::days(i64::from(self.retention_days));
+        while buckets.first_key_value().is_some_and(|((hour, _), _)| *hour < cutoff) {
+            buckets.pop_first();
+        }
+    }
+
+    pub fn record_request(&self, now: DateTime<Utc>, agent_id: &str) {
+        self.record(now, agent_id, |activity| activity.requests += 1);
+    }
+
+    pub fn record_outcome(&self, now: DateTime<Utc>, agent_id: &str, latency_ms: Option<u64>, success: bool) {
+        self.record(now, agent_id, |activity| {
+            if !success {
+                activity.errors += 1;
+            }
+            if let Some(latency_ms) = latency_ms {
+                activity.latency.record(latency_ms);
+            }
+        });
+    }
+
+    pub fn record_tokens(&self, now: DateTime<Utc>, agent_id: &str, prompt_tokens: u32, completion_tokens: u32) {
+        self.record(now, agent_id, |activity| {
+            activity.prompt_tokens += u64::from(prompt_tokens);
+            activity.completion_tokens += u64::from(completion_tokens);
+        });
+    }
+
+    pub fn record_chaos(&self, now: DateTime<Utc>, agent_id: &str) {
+        self.record(now, agent_id, |activity| activity.chaos_events += 1);
+    }
+
+    /// Every bucket, for saving
+    pub fn buckets(&self) -> Vec<HistoryBucket> {
+        self.lock()
+            .iter()
+            .map(|((hour, agent_id), activity)| HistoryBucket { hour: *hour, agent_id: agent_id.clone(), activity: *activity })
+            .collect()
+    }
+
+    /// The period starting at `start` summed per agent, sorted by agent, of
+    /// the agents `visible` admits
+    pub fn rows(&self, start: DateTime<Utc>, granularity: UsageGranularity, agent_id: Option<&str>, visible: impl Fn(&str) -> bool) -> Vec<ExportRow> {
+        let mut agents: BTreeMap<String, Activity> = BTreeMap::new();
+        let range = (start, String::new())..(granularity.end(start), String::new());
+        for ((_, agent), activity) in self.lock().range(range) {
+            if agent_id.is_none_or(|wanted| wanted == agent) && visible(agent) {
+                agents.entry(agent.clone()).or_default().add(activity);
+            }
+        }
+        agents.into_iter().map(|(agent, activity)| ExportRow::new(start, agent, &activity)).collect()
+    }
+
+    /// The export as body chunks: the CSV header, then a chunk per period
+    /// with any rows, each only read from the buckets once asked for
+    pub fn export(
+        self: Arc<Self>,
+        plan: ExportPlan,
+        visible: impl Fn(&str) -> bool + Send + 'static,
+    ) -> impl futures::Stream<Item = Result<Bytes, std::convert::Infallible>> + Send + 'static {
+        let ExportPlan { format, agent_id, since, until, granularity } = plan;
+        let header = (format == ExportFormat::Csv).then(|| Bytes::from_static(CSV_HEADER.as_bytes()));
+        let periods = std::iter::successors(Some(granularity.start(since)), move |start| Some(granularity.end(*start))).take_while(move |start| *start < until);
+        let chunks = periods.filter_map(move |start| {
+            let rows = self.rows(start, granularity, agent_id.as_deref(), &visible);
+            let mut chunk = String::new();
+            for row in rows {
+                match format {
+                    ExportFormat::Csv => chunk.push_str(&row.csv()),
+                    ExportFormat::Jsonl => {
+                        chunk.push_str(&serde_json::to_string(&row).expect("rows serialize"));
+                        chunk.push('\n');
+                    }
+                }
+            }
+            (!chunk.is_empty()).then(|| Bytes::from(chunk))
+        });
+        futures::stream::iter(header.into_iter().chain(chunks).map(Ok))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn hours_are_summed_into_days_and_old_ones_dropped() {
+        let history = ActivityHistory::new(2);
+        for (time, latency_ms, success) in [("2026-03-01T09:10:00Z", 40, true), ("2026-03-01T09:20:00Z", 900, false), ("2026-03-01T15:00:00Z", 80, true)] {
+            history.record_request(at(time), "scout");
+            history.record_outcome(at(time), "scout", Some(latency_ms), success);
+        }
+        history.record_tokens(at("2026-03-01T09:10:00Z"), "scout", 100, 20);
+        history.record_chaos(at("2026-03-01T15:00:00Z"), "courier");
+
+        let hour = history.rows(at("2026-03-01T09:00:00Z"), UsageGranularity::Hour, None, |_| true);
+        assert_eq!(hour.len(), 1);
+        assert_eq!((hour[0].requests, hour[0].errors, hour[0].p50_ms, hour[0].p95_ms, hour[0].prompt_tokens), (2, 1, 50, 900, 100));
+        let day = history.rows(at("2026-03-01T00:00:00Z"), UsageGranularity::Day, None, |_| true);
+        assert_eq!(day.iter().map(|row| (row.agent_id.as_str(), row.requests, row.chaos_events)).collect::<Vec<_>>(), [("courier", 0, 1), ("scout", 3, 0)]);
+        assert_eq!(history.rows(at("2026-03-01T00:00:00Z"), UsageGranularity::Day, Some("scout"), |agent_id| agent_id != "scout"), []);
+
+        let restored = ActivityHistory::default();
+        restored.restore(history.buckets());
+        assert_eq!(restored.buckets(), history.buckets());
+
+        history.record_request(at("2026-03-03T15:30:00Z"), "scout");
+        assert_eq!(history.buckets().len(), 3);
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_they_must_be() {
+        let row = ExportRow::new(at("2026-03-01T09:00:00Z"), "scout, \"the\" second".to_string(), &Activity { requests: 3, ..Activity::default() });
+        assert_eq!(row.csv(), "2026-03-01T09:00:00Z,\"scout, \"\"the\"\" second\",3,0,0,0,0,0,0\r\n");
+        assert_eq!(csv_field("scout"), "scout");
+    }
+}
@@ -0,0 +1,196 @@
+//! Idempotency keys, so a client retrying a request whose answer it never saw
+//! doesn't pay for it twice. The first request with a key runs as usual and
+//! its response is kept for `ttl_secs`; a repeat with the same method and
+//! params gets that response back, marked `idempotent_replay`, without
+//! running or being counted again, and a repeat with anything else is refused
+//! with 409 `idempotency_conflict`. A repeat arriving while the first still
+//! runs gets 409 `idempotency_in_progress`. Failed requests keep nothing, so
+//! retrying them runs them again. Keys are scoped per agent, so callers
+//! choosing the same key never collide; at most `max_keys` are kept, the
+//! oldest making way.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use crate::mcp_server::{MCPParams, MCPResponse};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdempotencyConfig {
+    /// How long a response is kept for repeats of its key
+    pub ttl_secs: u64,
+    /// Keys kept; the oldest makes way for a new one
+    pub max_keys: usize,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self { ttl_secs: 86_400, max_keys: 10_000 }
+    }
+}
+
+impl IdempotencyConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.ttl_secs == 0 || self.max_keys == 0 {
+            problems.push("idempotency.ttl_secs and max_keys must be positive".to_string());
+        }
+        problems
+    }
+}
+
+/// SHA-256 of what a repeat must match: the method and params as sent
+pub fn fingerprint(method: &str, params: &MCPParams) -> [u8; 32] {
+    let body = serde_json::to_vec(&(method, params)).unwrap_or_default();
+    let mut bytes = [0; 32];
+    bytes.copy_from_slice(digest(&SHA256, &body).as_ref());
+    bytes
+}
+
+/// Agent and key
+type Scope = (String, String);
+
+#[derive(Debug)]
+struct Entry {
+    fingerprint: [u8; 32],
+    /// Tells this storing of the entry from earlier and later ones
+    generation: u64,
+    /// None while the first request runs
+    response: Option<MCPResponse>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_scope: HashMap<Scope, Entry>,
+    /// When each entry was stored, oldest first; stale once it is stored again
+    order: VecDeque<(Scope, DateTime<Utc>, u64)>,
+    next_generation: u64,
+}
+
+impl Entries {
+    fn generation(&mut self) -> u64 {
+        self.next_generation += 1;
+        self.next_generation
+    }
+
+    /// Drops the oldest entries for as long as `evict` holds, given when each was stored
+    fn evict_while(&mut self, evict: impl Fn(&Entries, DateTime<Utc>) -> bool) {
+        while let Some((oldest, stored_at, generation)) = self.order.front().cloned() {
+            if !evict(self, stored_at) {
+                break;
+            }
+            self.order.pop_front();
+            self.remove_if_generation(&oldest, generation);
+        }
+    }
+
+    fn remove_if_generation(&mut self, scope: &Scope, generation: u64) {
+        if self.by_scope.get(scope).is_some_and(|entry| entry.generation == generation) {
+            self.by_scope.remove(scope);
+        }
+    }
+}
+
+/// What to do with a request carrying a key
+#[derive(Debug)]
+pub enum Begin {
+    /// Run it, completing the claim with its response
+    Run(IdempotencyClaim),
+    /// Answer with the response kept for the key
+    Replay(Box<MCPResponse>),
+    /// The key was used for a different request
+    Conflict,
+    /// The first request with the key hasn't finished
+    InProgress,
+}
+
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    config: IdempotencyConfig,
+    entries: Mutex<Entries>,
+}
+
+impl Default for IdempotencyStore {
+    fn default() -> Self {
+        Self::new(IdempotencyConfig::default())
+    }
+}
+
+impl IdempotencyStore {
+    pub fn new(config: IdempotencyConfig) -> Self {
+        Self { config, entries: Mutex::new(Entries::default()) }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::seconds(i64::try_from(self.config.ttl_secs).unwrap_or(i64::MAX))
+    }
+
+    /// Looks `key` up for `agent_id`, claiming it when it is new or expired
+    pub fn begin(self: &Arc<Self>, agent_id: &str, key: &str, fingerprint: [u8; 32], now: DateTime<Utc>) -> Begin {
+        let scope = (agent_id.to_string(), key.to_string());
+        let mut entries = self.lock();
+        let cutoff = now - self.ttl();
+        entries.evict_while(|entries, stored_at| stored_at <= cutoff || entries.by_scope.len() > self.config.max_keys);
+        if let Some(entry) = entries.by_scope.get(&scope) {
+            return match (&entry.response, entry.fingerprint == fingerprint) {
+                (_, false) => Begin::Conflict,
+                (None, true) => Begin::InProgress,
+                (Some(response), true) => Begin::Replay(Box::new(response.clone())),
+            };
+        }
+        entries.evict_while(|entries, _| entries.by_scope.len() >= self.config.max_keys);
+        let generation = entries.generation();
+        entries.by_scope.insert(scope.clone(), Entry { fingerprint, generation, response: None });
+        entries.order.push_back((scope.clone(), now, generation));
+        Begin::Run(IdempotencyClaim { store: Arc::clone(self), scope, generation, completed: false })
+    }
+
+    /// Keys kept, running or answered
+    pub fn len(&self) -> usize {
+        self.lock().by_scope.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A key held by the request running for it. Dropped without completing, as
+/// when the request fails or is abandoned, it frees the key for a retry.
+#[derive(Debug)]
+pub struct IdempotencyClaim {
+    store: Arc<IdempotencyStore>,
+    scope: Scope,
+    generation: u64,
+    completed: bool,
+}
+
+impl IdempotencyClaim {
+    /// Keeps `response` for repeats of the key, for `ttl_secs` from `now`
+    pub fn complete(mut self, response: &MCPResponse, now: DateTime<Utc>) {
+        let store = Arc::clone(&self.store);
+        let mut entries = store.lock();
+        let generation = entries.generation();
+        let Some(entry) = entries.by_scope.get_mut(&self.scope).filter(|entry| entry.generation == self.generation) else {
+            // Evicted while it ran
+            return;
+        };
+        *entry = Entry { response: Some(response.clone()), generation, ..*entry };
+        entries.order.push_back((self.scope.clone(), now, generation));
+        self.completed = true;
+    }
+}
+
+impl Drop for IdempotencyClaim {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.store.lock().remove_if_generation(&self.scope, self.generation);
+        }
+    }
+}
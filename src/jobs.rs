@@ -0,0 +1,506 @@
+//! Requests accepted now and handled in the background, for inferences that
+//! outlast the proxies between agent and server. `POST /api/jobs` validates
+//! and queues an `MCPRequest`, answering with a job id at once; a pool of
+//! `workers`, separate from the synchronous routes, runs queued jobs through
+//! the service once a global concurrency slot frees up; jobs wait for one
+//! however long it takes rather than being shed. Finished jobs keep their response or error for
+//! `retention_secs`, however often they are polled.
+//!
+//! A submission may name a `callback_url` on one of `callbacks.allowed_hosts`:
+//! once the job finishes its result is POSTed there as a `JobCallbackEvent`,
+//! signed like `webhooks` are, from a task of its own so workers never wait on
+//! it. Failed deliveries are retried with doubling waits, at most
+//! `max_concurrent_per_host` at once per host, and the outcome is kept on the
+//! job, so a receiver that was down can still poll for the result.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Mutex, Notify, Semaphore};
+use uuid::Uuid;
+use crate::auth::{Auth, Tenancy};
+use crate::mcp_server::{ErrorResponse, FieldError, MCPError, MCPRequest, MCPResponse, VoidShrineMCP};
+use crate::metrics::Metrics;
+use crate::webhooks::{self, EVENT_HEADER, SIGNATURE_HEADER};
+
+/// The event header of job callbacks
+pub const JOB_FINISHED_EVENT: &str = "job_finished";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobsConfig {
+    /// Jobs handled at once
+    pub workers: usize,
+    /// Jobs waiting for a worker; submissions beyond it get a 429
+    pub queue_size: usize,
+    /// Finished jobs are kept this long
+    pub retention_secs: u64,
+    pub callbacks: CallbackConfig,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self { workers: 4, queue_size: 256, retention_secs: 60 * 60, callbacks: CallbackConfig::default() }
+    }
+}
+
+impl JobsConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.workers == 0 || self.queue_size == 0 || self.retention_secs == 0 {
+            problems.push("jobs.workers, queue_size and retention_secs must be positive".to_string());
+        }
+        problems.extend(self.callbacks.validate());
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CallbackConfig {
+    /// Hosts a `callback_url` may point at, as `host` or `host:port`; none
+    /// refuses every submission with one
+    pub allowed_hosts: Vec<String>,
+    /// Key for the signature header; required with any host
+    pub secret: Option<String>,
+    /// Tries per callback, the first included
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubling for each one after
+    pub initial_backoff_ms: u64,
+    pub timeout_secs: u64,
+    /// Deliveries in flight to one host at once; the rest wait their turn
+    pub max_concurrent_per_host: usize,
+}
+
+impl Default for CallbackConfig {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            secret: None,
+            max_attempts: 4,
+            initial_backoff_ms: 500,
+            timeout_secs: 10,
+            max_concurrent_per_host: 4,
+        }
+    }
+}
+
+impl CallbackConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.allowed_hosts.is_empty() && self.secret.as_deref().is_none_or(str::is_empty) {
+            problems.push("jobs.callbacks.secret must be set when jobs.callbacks.allowed_hosts is".to_string());
+        }
+        if self.max_attempts == 0 || self.max_concurrent_per_host == 0 {
+            problems.push("jobs.callbacks.max_attempts and max_concurrent_per_host must be positive".to_string());
+        }
+        problems
+    }
+
+    /// `url` parsed, if it is http(s) on one of `allowed_hosts`
+    pub fn check_url(&self, url: &str) -> Result<reqwest::Url, FieldError> {
+        let refused = || FieldError::new("callback_url", "an http(s) URL on a host in jobs.callbacks.allowed_hosts", url);
+        let parsed = reqwest::Url::parse(url).map_err(|_| refused())?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(refused());
+        }
+        let (Some(host), Some(port)) = (parsed.host_str(), parsed.port_or_known_default()) else {
+            return Err(refused());
+        };
+        let allowed = self.allowed_hosts.iter().any(|entry| match entry.rsplit_once(':') {
+            Some((allowed, allowed_port)) if !allowed.ends_with(':') => {
+                allowed.eq_ignore_ascii_case(host) && allowed_port.parse() == Ok(port)
+            }
+            _ => entry.eq_ignore_ascii_case(host),
+        });
+        if allowed {
+            Ok(parsed)
+        } else {
+            Err(refused())
+        }
+    }
+}
+
+/// A request to run in the background, and where to send its result
+#[derive(Debug, Clone)]
+pub struct JobSubmission {
+    pub request: MCPRequest,
+    pub callback_url: Option<String>,
+}
+
+impl From<MCPRequest> for JobSubmission {
+    fn from(request: MCPRequest) -> Self {
+        Self { request, callback_url: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// A job as `GET /api/jobs/{id}` shows it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobView {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub method: String,
+    pub agent_id: String,
+    pub submitted_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Once `completed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<MCPResponse>,
+    /// Once `failed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+    /// With a `callback_url`, how delivering the result there is going
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback: Option<JobCallback>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallbackStatus {
+    /// The job hasn't finished, or delivery is still being retried
+    Pending,
+    Delivered,
+    /// Every attempt failed; the result is only to be had by polling
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobCallback {
+    pub url: String,
+    pub status: CallbackStatus,
+    /// Deliveries tried so far
+    pub attempts: u32,
+    /// Why the latest attempt failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// The body POSTed to a job's `callback_url` once it finishes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobCallbackEvent {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<MCPResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+#[derive(Debug)]
+struct Job {
+    view: JobView,
+    /// Taken by the worker that runs it
+    request: Option<MCPRequest>,
+    cancel: Arc<Notify>,
+    finished: Option<Instant>,
+}
+
+impl Job {
+    fn finish(&mut self, status: JobStatus) {
+        self.view.status = status;
+        self.view.finished_at = Some(Utc::now());
+        self.finished = Some(Instant::now());
+    }
+}
+
+/// Jobs by id. Dropping the queue stops its workers once they finish their
+/// current jobs.
+pub struct JobQueue {
+    config: JobsConfig,
+    jobs: Arc<DashMap<String, Job>>,
+    queue: mpsc::Sender<String>,
+    service: Arc<VoidShrineMCP>,
+    callbacks: Arc<Callbacks>,
+}
+
+impl JobQueue {
+    /// Largest submission body accepted, that of inference requests
+    pub fn body_limit(&self) -> u64 {
+        self.service.body_limits.inference_bytes
+    }
+
+    /// The keys job submissions are checked against
+    pub fn auth(&self) -> &Auth {
+        &self.service.auth
+    }
+
+    /// Starts `config.workers` workers handling jobs with `service`
+    pub fn start(service: Arc<VoidShrineMCP>, config: JobsConfig) -> Self {
+        let (queue, receiver) = mpsc::channel(config.queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let jobs = Arc::new(DashMap::new());
+        let callbacks = Arc::new(Callbacks {
+            config: config.callbacks.clone(),
+            client: reqwest::Client::new(),
+            metrics: Arc::clone(&service.metrics),
+            jobs: Arc::clone(&jobs),
+            hosts: DashMap::new(),
+        });
+        for _ in 0..config.workers {
+            tokio::spawn(work(Arc::clone(&jobs), Arc::clone(&service), Arc::clone(&callbacks), Arc::clone(&receiver)));
+        }
+        Self { config, jobs, queue, service, callbacks }
+    }
+
+    pub fn config(&self) -> &JobsConfig {
+        &self.config
+    }
+
+    /// Queues the request once it passes `VoidShrineMCP::validate_request`
+    /// and the caller's tenancy, and its `callback_url` the allowlist. Its
+    /// `request_id` defaults to the job id.
+    pub fn submit(&self, tenancy: &Tenancy, submission: impl Into<JobSubmission>) -> Result<JobView, MCPError> {
+        let JobSubmission { mut request, callback_url } = submission.into();
+        if self.service.shutdown.is_draining() {
+            return Err(MCPError::ShuttingDown);
+        }
+        self.service.validate_request(&request)?;
+        if let Some(url) = &callback_url {
+            self.config.callbacks.check_url(url).map_err(|field| MCPError::InvalidFields(vec![field]))?;
+        }
+        self.service.admit_agent(tenancy, &request.params.agent_id)?;
+        self.prune();
+
+        let job_id = Uuid::new_v4().to_string();
+        request.request_id.get_or_insert_with(|| job_id.clone());
+        let view = JobView {
+            job_id: job_id.clone(),
+            status: JobStatus::Queued,
+            method: request.method.clone(),
+            agent_id: request.params.agent_id.clone(),
+            submitted_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            response: None,
+            error: None,
+            callback: callback_url.map(|url| JobCallback { url, status: CallbackStatus::Pending, attempts: 0, last_error: None }),
+        };
+        let job = Job { view: view.clone(), request: Some(request), cancel: Arc::new(Notify::new()), finished: None };
+        self.jobs.insert(job_id.clone(), job);
+        match self.queue.try_send(job_id.clone()) {
+            Ok(()) => {
+                tracing::info!("Queued job {} for {}", job_id, view.agent_id);
+                Ok(view)
+            }
+            Err(e) => {
+                self.jobs.remove(&job_id);
+                Err(match e {
+                    TrySendError::Full(_) => MCPError::QueueFull,
+                    TrySendError::Closed(_) => MCPError::ShuttingDown,
+                })
+            }
+        }
+    }
+
+    pub fn get(&self, job_id: &str) -> Result<JobView, MCPError> {
+        self.prune();
+        self.jobs.get(job_id).map(|job| job.view.clone()).ok_or_else(|| MCPError::JobNotFound(job_id.to_string()))
+    }
+
+    /// Cancels a queued or running job; a finished one is left as it is
+    pub fn cancel(&self, job_id: &str) -> Result<JobView, MCPError> {
+        let mut job = self.jobs.get_mut(job_id).ok_or_else(|| MCPError::JobNotFound(job_id.to_string()))?;
+        if !job.view.status.is_finished() {
+            tracing::info!("Cancelling job {}", job_id);
+            job.request = None;
+            job.finish(JobStatus::Cancelled);
+            job.cancel.notify_one();
+            self.callbacks.send(&job);
+        }
+        Ok(job.view.clone())
+    }
+
+    /// Drops jobs finished more than `retention_secs` ago
+    fn prune(&self) {
+        let retention = Duration::from_secs(self.config.retention_secs);
+        let now = Instant::now();
+        self.jobs.retain(|_, job| job.finished.is_none_or(|finished| now.saturating_duration_since(finished) < retention));
+    }
+}
+
+async fn work(
+    jobs: Arc<DashMap<String, Job>>,
+    service: Arc<VoidShrineMCP>,
+    callbacks: Arc<Callbacks>,
+    receiver: Arc<Mutex<mpsc::Receiver<String>>>,
+) {
+    loop {
+        let Some(job_id) = receiver.lock().await.recv().await else {
+            return;
+        };
+        // Stays queued until there is room, so a busy server isn't made busier
+        let permit = service.concurrency.acquire_queued().await;
+        // Cancelled or pruned while queued
+        let Some((request, cancel)) = jobs.get_mut(&job_id).and_then(|mut job| {
+            let request = job.request.take()?;
+            job.view.status = JobStatus::Running;
+            job.view.started_at = Some(Utc::now());
+            Some((request, Arc::clone(&job.cancel)))
+        }) else {
+            continue;
+        };
+
+        let outcome = tokio::select! {
+            outcome = service.handle_queued_request(request, permit) => outcome,
+            _ = cancel.notified() => continue,
+        };
+        let Some(mut job) = jobs.get_mut(&job_id) else {
+            continue;
+        };
+        // A cancellation that lost the race still wins
+        if job.view.status != JobStatus::Running {
+            continue;
+        }
+        match outcome {
+            Ok(response) => {
+                job.view.response = Some(response);
+                job.finish(JobStatus::Completed);
+            }
+            // Cancelled by its request id rather than through the queue
+            Err(failure) if matches!(failure.error, MCPError::Cancelled) => job.finish(JobStatus::Cancelled),
+            Err(failure) => {
+                job.view.error = Some(ErrorResponse::from(&failure));
+                job.finish(JobStatus::Failed);
+            }
+        }
+        tracing::debug!("Job {} {:?}", job_id, job.view.status);
+        callbacks.send(&job);
+    }
+}
+
+/// Sends finished jobs to their `callback_url`s
+struct Callbacks {
+    config: CallbackConfig,
+    client: reqwest::Client,
+    metrics: Arc<Metrics>,
+    /// Where outcomes are recorded
+    jobs: Arc<DashMap<String, Job>>,
+    /// Delivery slots per host
+    hosts: DashMap<String, Arc<Semaphore>>,
+}
+
+impl Callbacks {
+    /// Starts delivering `job`, if it has a callback, and returns at once
+    fn send(self: &Arc<Self>, job: &Job) {
+        let Some(callback) = &job.view.callback else {
+            return;
+        };
+        let event = JobCallbackEvent {
+            job_id: job.view.job_id.clone(),
+            status: job.view.status,
+            timestamp: Utc::now(),
+            response: job.view.response.clone(),
+            error: job.view.error.clone(),
+        };
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to encode callback for job {}: {}", event.job_id, e);
+                return;
+            }
+        };
+        let Ok(url) = reqwest::Url::parse(&callback.url) else {
+            return;
+        };
+        let host = format!("{}:{}", url.host_str().unwrap_or_default(), url.port_or_known_default().unwrap_or_default());
+        let slots = Arc::clone(&self.hosts.entry(host).or_insert_with(|| Arc::new(Semaphore::new(self.config.max_concurrent_per_host))));
+        let signature = webhooks::sign(self.config.secret.as_deref().unwrap_or_default(), &body);
+        let delivery = CallbackDelivery { callbacks: Arc::clone(self), job_id: event.job_id, url, slots, body, signature };
+        tokio::spawn(delivery.run());
+    }
+}
+
+/// One finished job on its way to its `callback_url`
+struct CallbackDelivery {
+    callbacks: Arc<Callbacks>,
+    job_id: String,
+    url: reqwest::Url,
+    slots: Arc<Semaphore>,
+    body: Vec<u8>,
+    signature: String,
+}
+
+impl CallbackDelivery {
+    async fn run(self) {
+        let config = &self.callbacks.config;
+        for attempt in 1..=config.max_attempts {
+            let outcome = match Arc::clone(&self.slots).acquire_owned().await {
+                Ok(_slot) => self.attempt().await,
+                Err(_) => return,
+            };
+            let status = match &outcome {
+                Ok(()) => CallbackStatus::Delivered,
+                Err(_) if attempt == config.max_attempts => CallbackStatus::Failed,
+                Err(_) => CallbackStatus::Pending,
+            };
+            self.record(attempt, status, outcome.as_ref().err());
+            let Err(failure) = outcome else {
+                return;
+            };
+            if status == CallbackStatus::Failed {
+                tracing::error!("Giving up on the callback for job {} to {} after {} attempts: {}", self.job_id, self.url, attempt, failure);
+                self.callbacks.metrics.webhook_dead_letter(JOB_FINISHED_EVENT);
+                return;
+            }
+            let backoff = webhooks::backoff(config.initial_backoff_ms, attempt);
+            tracing::warn!("Callback for job {} to {} failed ({}), retrying in {} ms", self.job_id, self.url, failure, backoff.as_millis());
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    async fn attempt(&self) -> Result<(), String> {
+        let response = self
+            .callbacks
+            .client
+            .post(self.url.clone())
+            .timeout(Duration::from_secs(self.callbacks.config.timeout_secs))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, JOB_FINISHED_EVENT)
+            .header(SIGNATURE_HEADER, &self.signature)
+            .body(self.body.clone())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("status {}", response.status()))
+        }
+    }
+
+    /// Notes the attempt on the job, unless it has been pruned meanwhile
+    fn record(&self, attempts: u32, status: CallbackStatus, error: Option<&String>) {
+        if let Some(mut job) = self.callbacks.jobs.get_mut(&self.job_id) {
+            if let Some(callback) = &mut job.view.callback {
+                callback.attempts = attempts;
+                callback.status = status;
+                if let Some(error) = error {
+                    callback.last_error = Some(error.clone());
+                }
+            }
+        }
+    }
+}
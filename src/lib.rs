@@ -1,5 +1,88 @@
+//! The Void Shrine MCP server as a library, for mounting its routes inside
+//! another warp application or driving the knowledge base from other tools.
+//!
+//! [`VoidShrineMCP`] holds the server's state and request handlers, and
+//! [`api::routes`] serves them over HTTP at the paths the server binary
+//! uses, which API key scopes are matched against:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use void_shrine_mcp::{api, jobs::JobQueue, RAGEngine, VoidShrineMCP};
+//! use warp::Filter;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let service = Arc::new(VoidShrineMCP::default());
+//! *service.rag_engine.write().await = Some(RAGEngine::new().await?);
+//! let jobs = Arc::new(JobQueue::start(Arc::clone(&service), Default::default()));
+//! let status = warp::path("status").map(|| "mine");
+//! let routes = status.or(api::routes(service, jobs)).recover(api::recover);
+//! warp::serve(routes).run(([127, 0, 0, 1], 3030)).await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`RAGEngine`] is the knowledge base on its own: indexing, hybrid search
+//! and persistence, with no server around it.
+
+pub mod agent_stats;
+pub mod agents;
+pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod bench;
+pub mod breaker;
+pub mod build_info;
+pub mod cache;
+pub mod chaos_stats;
+pub mod clock;
+pub mod compression;
+pub mod concurrency;
+pub mod confidence;
+pub mod config;
+pub mod content_filter;
+pub mod context_screen;
+pub mod delays;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+pub mod idempotency;
+pub mod jobs;
+pub mod live;
+pub mod llm_backend;
+pub mod load;
+pub mod mcp_protocol;
 pub mod mcp_server;
+pub mod metrics;
+pub mod metrics_store;
+pub mod model_catalog;
+pub mod moral;
+pub mod overload;
+pub mod quota;
 pub mod rag_engine;
+pub mod rag_engines;
+pub mod rag_tasks;
+pub mod rate_limit;
+pub mod reload;
+pub mod route_metrics;
+pub mod replay;
+pub mod scaling;
+pub mod self_check;
+pub mod sessions;
+pub mod shutdown;
+pub mod specialties;
+pub mod templates;
+pub mod text;
+pub mod tls;
+pub mod tokenizer;
+pub mod tokens;
+pub mod trace;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod usage;
+pub mod webhooks;
+pub mod websocket;
+#[cfg(feature = "watch")]
+pub mod watcher;
 
 pub use mcp_server::VoidShrineMCP;
 pub use rag_engine::RAGEngine;
\ No newline at end of file
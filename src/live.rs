@@ -0,0 +1,297 @@
+//! Live metrics for dashboards. `GET /ws/metrics` upgrades to a WebSocket
+//! that is sent a `snapshot` of `/api/metrics` at once, then every event
+//! below as it happens, each a JSON `LiveFrame` carrying
+//! `LIVE_SCHEMA_VERSION`. `?agent_id=` keeps only one agent's events and
+//! `?events=` only the types named, comma separated; tenant keys only ever
+//! see their own agents. A client that reads slower than events arrive
+//! loses the oldest once `metrics.live_buffer_events` are waiting for it,
+//! and is sent a `lagged` frame counting them in their place.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::Filter;
+use crate::auth::Tenancy;
+use crate::breaker::BreakerState;
+use crate::mcp_server::{FieldError, MCPError, MetricsParams, MetricsResponse, VoidShrineMCP};
+use crate::scaling::ScalingDirection;
+use crate::websocket::PING_INTERVAL;
+
+/// Raised whenever a change to `LiveEvent` would break existing dashboards
+pub const LIVE_SCHEMA_VERSION: u32 = 1;
+
+/// One message on `/ws/metrics`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiveFrame {
+    /// `LIVE_SCHEMA_VERSION` of the server that sent it
+    pub version: u32,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: LiveEvent,
+}
+
+impl LiveFrame {
+    pub fn new(event: LiveEvent) -> Self {
+        Self { version: LIVE_SCHEMA_VERSION, at: Utc::now(), event }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// Everything `/api/metrics` shows, sent first on every connection
+    Snapshot { metrics: Box<MetricsResponse> },
+    /// A request got its id and started through the pipeline
+    RequestStarted { request_id: String, agent_id: String, method: String },
+    /// A request finished, or was refused before starting when `request_id` is None
+    RequestCompleted {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        agent_id: String,
+        method: String,
+        status: u16,
+        latency_ms: u64,
+    },
+    ChaosApplied { agent_id: String, method: String, chaos_type: String },
+    /// A request was delayed or rejected by load throttling
+    ThrottleTriggered { agent_id: String, outcome: String },
+    ScalingDecision { agent_id: String, direction: ScalingDirection, description: String, capacity_change: f64 },
+    /// A backend's circuit breaker opened, started probing, or closed
+    CircuitTransition { backend: String, from: BreakerState, to: BreakerState },
+    /// Events this many were dropped because the client fell behind
+    Lagged { dropped: u64 },
+}
+
+impl LiveEvent {
+    /// The `type` it is sent with, as `?events=` names it
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::Snapshot { .. } => "snapshot",
+            LiveEvent::RequestStarted { .. } => "request_started",
+            LiveEvent::RequestCompleted { .. } => "request_completed",
+            LiveEvent::ChaosApplied { .. } => "chaos_applied",
+            LiveEvent::ThrottleTriggered { .. } => "throttle_triggered",
+            LiveEvent::ScalingDecision { .. } => "scaling_decision",
+            LiveEvent::CircuitTransition { .. } => "circuit_transition",
+            LiveEvent::Lagged { .. } => "lagged",
+        }
+    }
+
+    /// The agent it concerns; None for server-wide events
+    pub fn agent_id(&self) -> Option<&str> {
+        match self {
+            LiveEvent::RequestStarted { agent_id, .. }
+            | LiveEvent::RequestCompleted { agent_id, .. }
+            | LiveEvent::ChaosApplied { agent_id, .. }
+            | LiveEvent::ThrottleTriggered { agent_id, .. }
+            | LiveEvent::ScalingDecision { agent_id, .. } => Some(agent_id),
+            LiveEvent::Snapshot { .. } | LiveEvent::CircuitTransition { .. } | LiveEvent::Lagged { .. } => None,
+        }
+    }
+}
+
+/// Event types a client may subscribe to; snapshots and lag notices are always sent
+pub const SUBSCRIBABLE: [&str; 6] =
+    ["request_started", "request_completed", "chaos_applied", "throttle_triggered", "scaling_decision", "circuit_transition"];
+
+/// `/ws/metrics` query parameters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiveParams {
+    /// Only this agent's events, and server-wide ones
+    pub agent_id: Option<String>,
+    /// Event types to send, comma separated; all when unset
+    pub events: Option<String>,
+}
+
+/// Which events a connection is sent
+#[derive(Debug, Clone, Default)]
+pub struct LiveFilter {
+    agent_id: Option<String>,
+    events: Option<BTreeSet<String>>,
+}
+
+impl LiveFilter {
+    pub fn parse(params: LiveParams) -> Result<Self, MCPError> {
+        let events = match params.events {
+            None => None,
+            Some(names) => {
+                let names: BTreeSet<String> = names.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect();
+                if let Some(unknown) = names.iter().find(|name| !SUBSCRIBABLE.contains(&name.as_str())) {
+                    let constraint = format!("event types among {}", SUBSCRIBABLE.join(", "));
+                    return Err(MCPError::InvalidFields(vec![FieldError::new("events", constraint, unknown.as_str())]));
+                }
+                Some(names)
+            }
+        };
+        Ok(Self { agent_id: params.agent_id, events })
+    }
+
+    pub fn passes(&self, event: &LiveEvent) -> bool {
+        if matches!(event, LiveEvent::Snapshot { .. } | LiveEvent::Lagged { .. }) {
+            return true;
+        }
+        let wanted = self.events.as_ref().is_none_or(|events| events.contains(event.name()));
+        let agent = match (&self.agent_id, event.agent_id()) {
+            (Some(wanted), Some(agent_id)) => wanted == agent_id,
+            _ => true,
+        };
+        wanted && agent
+    }
+}
+
+/// Where the server's events are published, for every connection to read at its own pace
+#[derive(Debug)]
+pub struct LiveFeed {
+    sender: broadcast::Sender<Arc<LiveFrame>>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl LiveFeed {
+    /// Keeps up to `buffer` events for each subscriber that hasn't read them
+    pub fn new(buffer: usize) -> Self {
+        Self { sender: broadcast::channel(buffer.max(1)).0 }
+    }
+
+    /// Sends `event` to every subscriber, if there are any
+    pub fn publish(&self, event: LiveEvent) {
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(Arc::new(LiveFrame::new(event)));
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LiveFrame>> {
+        self.sender.subscribe()
+    }
+
+    /// Connections listening
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+/// `GET /ws/metrics` upgraded to a WebSocket. Unknown `?events=` are refused
+/// before the upgrade.
+pub fn route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("ws")
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(warp::query::<LiveParams>())
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .and_then(|ws: Ws, params: LiveParams, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            let filter = LiveFilter::parse(params).map_err(crate::api::reject)?;
+            Ok::<_, warp::Rejection>(ws.on_upgrade(move |socket| serve_connection(socket, service, tenancy, filter)))
+        })
+}
+
+async fn serve_connection(socket: WebSocket, service: Arc<VoidShrineMCP>, tenancy: Tenancy, filter: LiveFilter) {
+    let (mut sink, mut stream) = socket.split();
+    // Subscribed before the snapshot is taken, so no event falls between them
+    let mut events = service.live.subscribe();
+    let params = MetricsParams { agent_id: filter.agent_id.clone(), since: None };
+    let snapshot = LiveFrame::new(LiveEvent::Snapshot { metrics: Box::new(service.handle_metrics(&tenancy, &params)) });
+    if !send_frame(&mut sink, &snapshot).await {
+        return;
+    }
+
+    let mut ping = tokio::time::interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    let mut last_seen = Instant::now();
+    loop {
+        tokio::select! {
+            message = stream.next() => match message {
+                // Pongs and anything else the client sends only show it is there
+                Some(Ok(message)) if !message.is_close() => last_seen = Instant::now(),
+                _ => break,
+            },
+            received = events.recv() => {
+                let frame = match received {
+                    Ok(frame) => frame,
+                    Err(RecvError::Lagged(dropped)) => {
+                        tracing::debug!("Live metrics client fell behind by {} events", dropped);
+                        Arc::new(LiveFrame::new(LiveEvent::Lagged { dropped }))
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let hidden = frame.event.agent_id().is_some_and(|agent_id| service.hidden_agent(&tenancy, agent_id));
+                if hidden || !filter.passes(&frame.event) {
+                    continue;
+                }
+                // A client slow to take this lets events pile up, and lag, behind it
+                if !send_frame(&mut sink, &frame).await {
+                    break;
+                }
+            }
+            _ = ping.tick() => {
+                if last_seen.elapsed() > PING_INTERVAL * 2 {
+                    tracing::debug!("Live metrics peer stopped answering pings");
+                    break;
+                }
+                if tokio::time::timeout(Duration::from_secs(5), sink.send(Message::ping(Vec::new()))).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    let _ = sink.close().await;
+}
+
+/// False once the connection is gone
+async fn send_frame(sink: &mut futures::stream::SplitSink<WebSocket, Message>, frame: &LiveFrame) -> bool {
+    match serde_json::to_string(frame) {
+        Ok(text) => sink.send(Message::text(text)).await.is_ok(),
+        Err(e) => {
+            tracing::error!("Failed to serialize live metrics frame: {}", e);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_keep_the_agent_and_types_asked_for() {
+        let completed = |agent_id: &str| LiveEvent::RequestCompleted {
+            request_id: None,
+            agent_id: agent_id.to_string(),
+            method: "llm_inference".to_string(),
+            status: 200,
+            latency_ms: 3,
+        };
+        let transition = LiveEvent::CircuitTransition { backend: "openai".to_string(), from: BreakerState::Closed, to: BreakerState::Open };
+
+        let filter = LiveFilter::parse(LiveParams { agent_id: Some("scout".to_string()), events: Some("request_completed, circuit_transition".to_string()) }).unwrap();
+        assert!(filter.passes(&completed("scout")) && !filter.passes(&completed("courier")));
+        assert!(filter.passes(&transition) && filter.passes(&LiveEvent::Lagged { dropped: 2 }));
+        let chaos = LiveEvent::ChaosApplied { agent_id: "scout".to_string(), method: "llm_inference".to_string(), chaos_type: "latency".to_string() };
+        assert!(!filter.passes(&chaos));
+
+        let unknown = LiveFilter::parse(LiveParams { agent_id: None, events: Some("request_completed,gossip".to_string()) }).unwrap_err();
+        assert_eq!(unknown.fields()[0].field, "events");
+    }
+
+    #[test]
+    fn frames_carry_the_schema_version_and_type() {
+        let frame = LiveFrame::new(LiveEvent::Lagged { dropped: 7 });
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!((&json["version"], &json["type"], &json["dropped"]), (&serde_json::json!(1), &serde_json::json!("lagged"), &serde_json::json!(7)));
+        assert_eq!(serde_json::from_value::<LiveFrame>(json).unwrap(), frame);
+    }
+}
@@ -0,0 +1,1662 @@
+//! Text generation behind the `llm_inference` method. The server talks to one
+//! `LLMBackend`; `MockBackend` answers offline with canned text per specialty,
+//! `OpenAiCompatBackend` calls any chat-completions API, `OllamaBackend` a local
+//! Ollama server and `AnthropicBackend` the Anthropic Messages API.
+//! `BackendRegistry` routes each request to a backend by its model name.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::mcp_server::MCPParams;
+use crate::specialties::Specialties;
+use crate::text::truncate_to_tokens;
+use crate::tokenizer::EstimateTokenizer;
+use crate::trace;
+
+/// Model name clients get when they don't pick one; backends substitute their own default
+pub const SERVER_DEFAULT_MODEL: &str = "void-shrine";
+
+/// The model the client asked for, or None when it left the choice to the backend
+pub fn requested_model(params: &MCPParams) -> Option<&str> {
+    let model = params.model.trim();
+    (!model.is_empty() && model != SERVER_DEFAULT_MODEL).then_some(model)
+}
+
+/// What the backend is asked: instructions for its system role, and the user turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prompt {
+    pub system: Option<String>,
+    pub user: String,
+}
+
+impl Prompt {
+    pub fn user(text: impl Into<String>) -> Self {
+        Self { system: None, user: text.into() }
+    }
+
+    pub fn with_system(mut self, system: impl Into<String>) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    /// System text followed by the user turn, for backends without a system role
+    pub fn flattened(&self) -> String {
+        match &self.system {
+            Some(system) => format!("{} {}", system, self.user),
+            None => self.user.clone(),
+        }
+    }
+}
+
+/// What a backend generates with: a request's temperature and max_tokens,
+/// once clamped to what the backend accepts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sampling {
+    pub temperature: f64,
+    pub max_tokens: u32,
+}
+
+impl Sampling {
+    /// As the request asked
+    pub fn requested(params: &MCPParams) -> Self {
+        Self { temperature: params.temperature, max_tokens: params.max_tokens }
+    }
+}
+
+/// Why the backend stopped generating
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    Stop,
+    /// `max_tokens` was reached
+    Length,
+    ContentFilter,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionOutput {
+    pub text: String,
+    /// As reported by the backend; 0 when it reports none
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub finish_reason: FinishReason,
+    /// Generation time as measured by the backend itself, when it reports one
+    #[serde(default)]
+    pub generation_time: Option<Duration>,
+    /// Mean log probability of the completion's tokens, when the backend reports them
+    #[serde(default)]
+    pub mean_logprob: Option<f64>,
+}
+
+impl CompletionOutput {
+    pub fn total_tokens(&self) -> u32 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// A piece of a streamed completion; the stream ends after `Done`, which
+/// carries the assembled text and the usage
+#[derive(Debug, Clone)]
+pub enum CompletionChunk {
+    Delta(String),
+    Done(CompletionOutput),
+}
+
+/// Generates a completion for the fully assembled prompt (RAG context and moral
+/// recentering already applied). `params` carries model, max_tokens and temperature.
+pub trait LLMBackend: Send + Sync {
+    /// Short identifier used in logs, e.g. "mock" or "openai"
+    fn name(&self) -> &str;
+    fn complete<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, Result<CompletionOutput>>;
+
+    /// Text deltas as the backend produces them, then `Done`. An error ends the
+    /// stream. Backends that can't stream yield the whole text as one delta.
+    fn complete_stream<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxStream<'a, Result<CompletionChunk>> {
+        stream::once(self.complete(prompt, params))
+            .flat_map(|result| {
+                let chunks = match result {
+                    Ok(output) => vec![Ok(CompletionChunk::Delta(output.text.clone())), Ok(CompletionChunk::Done(output))],
+                    Err(e) => vec![Err(e)],
+                };
+                stream::iter(chunks)
+            })
+            .boxed()
+    }
+
+    /// Whether `complete_stream` yields text as it is generated rather than
+    /// all at once
+    fn streams(&self) -> bool {
+        false
+    }
+
+    /// The temperature and max_tokens it generates with for `params`: as
+    /// requested, unless the backend accepts narrower ranges
+    fn sampling(&self, params: &MCPParams) -> Sampling {
+        Sampling::requested(params)
+    }
+
+    /// The models the backend says it serves, for `GET /api/models`; empty
+    /// when it can't say, leaving the configured routes to list its models
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    /// Whether `error`, from this backend, may well not recur if the call is
+    /// retried shortly. By default the transient `BackendError`s are.
+    fn is_transient(&self, error: &anyhow::Error) -> bool {
+        error.downcast_ref::<BackendError>().is_some_and(BackendError::is_transient)
+    }
+}
+
+/// Retries of backend calls that failed transiently. Waits double from
+/// `initial_backoff_ms` up to `max_backoff_ms`, unless the upstream said how
+/// long to wait with Retry-After.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Calls per request, the first included; 1 turns retries off
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Each wait is up to this fraction longer or shorter, so agents failing
+    /// together don't retry together
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self { max_attempts: 3, initial_backoff_ms: 250, max_backoff_ms: 4000, jitter: 0.25 }
+    }
+}
+
+impl RetryConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.max_attempts == 0 || self.initial_backoff_ms == 0 {
+            problems.push("retries.max_attempts and initial_backoff_ms must be positive".to_string());
+        }
+        if self.max_backoff_ms < self.initial_backoff_ms {
+            problems.push(format!(
+                "retries.max_backoff_ms must be at least initial_backoff_ms ({} < {})",
+                self.max_backoff_ms, self.initial_backoff_ms
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            problems.push(format!("retries.jitter must be between 0 and 1 (got {})", self.jitter));
+        }
+        problems
+    }
+
+    /// The wait before retry number `retry`, counting from 1
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let doubled = self.initial_backoff_ms.saturating_mul(1 << retry.saturating_sub(1).min(32));
+        let spread = 1.0 + self.jitter * (rand::random::<f64>() * 2.0 - 1.0);
+        Duration::from_millis((doubled.min(self.max_backoff_ms) as f64 * spread) as u64)
+    }
+}
+
+/// Drains a completion stream into its final output
+pub async fn collect_completion(mut chunks: BoxStream<'_, Result<CompletionChunk>>) -> Result<CompletionOutput> {
+    while let Some(chunk) = chunks.next().await {
+        if let CompletionChunk::Done(output) = chunk? {
+            return Ok(output);
+        }
+    }
+    Err(BackendError::InvalidResponse("stream ended before the final message".to_string()).into())
+}
+
+/// Pause between words when the mock streams its answer
+const MOCK_WORD_DELAY: Duration = Duration::from_millis(15);
+
+/// Canned response per specialty, its `mock_response`. At temperature 0 it
+/// always gives that; above it, the specialty's `mock_variants` become
+/// likelier the higher it goes, drawn from an RNG that a seed makes repeat.
+/// Answers stop at the last word within `max_tokens`, counted as the
+/// default tokenizer counts. It reports no token counts, leaving them to the
+/// server's tokenizer.
+#[derive(Debug)]
+pub struct MockBackend {
+    specialties: Arc<Specialties>,
+    rng: Mutex<StdRng>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new(Arc::default())
+    }
+}
+
+impl MockBackend {
+    /// Answers from `specialties`, seeing them reloaded
+    pub fn new(specialties: Arc<Specialties>) -> Self {
+        Self { specialties, rng: Mutex::new(StdRng::from_entropy()) }
+    }
+
+    /// Draws variants in the same sequence on every run
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// The first response at temperature 0; above it, each later one weighs
+    /// `e^(-1/temperature)` times the one before
+    fn pick(&self, responses: &[String], temperature: f64) -> String {
+        if temperature <= 0.0 || responses.len() < 2 {
+            return responses[0].clone();
+        }
+        let mut rng = self.rng.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let indices: Vec<usize> = (0..responses.len()).collect();
+        let index = indices.choose_weighted(&mut *rng, |i| (-(*i as f64) / temperature).exp()).map_or(0, |i| *i);
+        responses[index].clone()
+    }
+}
+
+/// `text` cut to keep it within `max_tokens`, as a model stops, without a
+/// marker; and whether anything had to go
+fn within_tokens(text: String, max_tokens: u32) -> (String, FinishReason) {
+    match truncate_to_tokens(&text, max_tokens as usize, &EstimateTokenizer, "") {
+        Cow::Borrowed(_) => (text, FinishReason::Stop),
+        Cow::Owned(cut) => (cut, FinishReason::Length),
+    }
+}
+
+impl LLMBackend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, Result<CompletionOutput>> {
+        let sampling = self.sampling(params);
+        // Strict servers refuse unknown specialties before asking
+        let responses = match self.specialties.resolve(&params.specialty) {
+            Some(specialty) => std::iter::once(specialty.mock_response).chain(specialty.mock_variants).collect(),
+            None => vec!["Request received.".to_string()],
+        };
+        let text = format!("[MCP-Enhanced] {}", self.pick(&responses, sampling.temperature));
+        let (text, finish_reason) = within_tokens(text, sampling.max_tokens);
+
+        let output = CompletionOutput {
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            text,
+            finish_reason,
+            generation_time: None,
+            mean_logprob: None,
+        };
+        Box::pin(async move { Ok(output) })
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    /// Word by word, paced like a slow model
+    fn complete_stream<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxStream<'a, Result<CompletionChunk>> {
+        stream::once(self.complete(prompt, params))
+            .flat_map(|result| {
+                let chunks: Vec<Result<CompletionChunk>> = match result {
+                    Ok(output) => output.text
+                        .split_inclusive(' ')
+                        .map(|word| Ok(CompletionChunk::Delta(word.to_string())))
+                        .chain(std::iter::once(Ok(CompletionChunk::Done(output.clone()))))
+                        .collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                stream::iter(chunks).then(|chunk| async move {
+                    tokio::time::sleep(MOCK_WORD_DELAY).await;
+                    chunk
+                })
+            })
+            .boxed()
+    }
+}
+
+/// Why a backend call failed; the MCP layer answers these with a gateway error
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendError {
+    /// The upstream API answered with an error status and, when it gave one, its message
+    Status { status: u16, message: String },
+    Timeout(Duration),
+    /// Nothing answered at the configured address
+    Unavailable(String),
+    InvalidResponse(String),
+    /// The backend reported an error after it had started answering
+    Failed(String),
+    /// HTTP 429, with how long the upstream asked us to wait when it said
+    RateLimited { retry_after: Option<Duration>, message: String },
+    /// No backend is routed for the requested model and there is no default
+    UnknownModel(String),
+}
+
+impl BackendError {
+    /// Machine-readable identifier for error responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            BackendError::Status { .. } | BackendError::Failed(_) => "backend_error",
+            BackendError::Timeout(_) => "backend_timeout",
+            BackendError::Unavailable(_) => "backend_unavailable",
+            BackendError::InvalidResponse(_) => "backend_invalid_response",
+            BackendError::RateLimited { .. } => "backend_rate_limited",
+            BackendError::UnknownModel(_) => "unknown_model",
+        }
+    }
+
+    /// HTTP status for the client: 400 for unroutable models, 504 for timeouts,
+    /// 503 when rate limited, 502 otherwise
+    pub fn http_status(&self) -> u16 {
+        match self {
+            BackendError::UnknownModel(_) => 400,
+            BackendError::Timeout(_) => 504,
+            BackendError::RateLimited { .. } => 503,
+            _ => 502,
+        }
+    }
+
+    /// Rate limiting, a refused or reset connection, or a gateway error
+    /// upstream: the same call may well succeed a moment later
+    pub fn is_transient(&self) -> bool {
+        match self {
+            BackendError::RateLimited { .. } | BackendError::Unavailable(_) => true,
+            BackendError::Status { status, .. } => matches!(status, 429 | 502 | 503 | 504),
+            _ => false,
+        }
+    }
+
+    /// The HTTP status the upstream answered with, when it answered with one
+    pub fn upstream_status(&self) -> Option<u16> {
+        match self {
+            BackendError::Status { status, .. } => Some(*status),
+            BackendError::RateLimited { .. } => Some(429),
+            _ => None,
+        }
+    }
+
+    /// How long to wait before retrying, when the upstream said
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            BackendError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    fn from_reqwest(error: reqwest::Error, timeout: Duration) -> Self {
+        if error.is_timeout() {
+            BackendError::Timeout(timeout)
+        } else if error.is_connect() {
+            let target = error.url().map(|url| url.origin().ascii_serialization()).unwrap_or_default();
+            BackendError::Unavailable(format!("cannot connect to {}", target))
+        } else if error.is_decode() {
+            BackendError::InvalidResponse(error.to_string())
+        } else {
+            BackendError::Unavailable(error.to_string())
+        }
+    }
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::Status { status, message } => write!(f, "backend responded with HTTP {}: {}", status, message),
+            BackendError::Timeout(after) => write!(f, "backend did not respond within {}ms", after.as_millis()),
+            BackendError::Unavailable(reason) => write!(f, "backend unavailable: {}", reason),
+            BackendError::InvalidResponse(reason) => write!(f, "backend returned an invalid response: {}", reason),
+            BackendError::Failed(message) => write!(f, "backend failed: {}", message),
+            BackendError::RateLimited { retry_after: Some(wait), message } => {
+                write!(f, "backend rate limited, retry after {}s: {}", wait.as_secs(), message)
+            }
+            BackendError::RateLimited { retry_after: None, message } => write!(f, "backend rate limited: {}", message),
+            BackendError::UnknownModel(model) => write!(f, "no backend serves model '{}'", model),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+/// The error message from a JSON error body such as `{"error": {"message": ...}}`,
+/// or the start of the raw body
+fn upstream_message(body: &str) -> String {
+    let parsed = serde_json::from_str::<Value>(body).ok();
+    let message = parsed.as_ref().and_then(|value| {
+        let error = value.get("error")?;
+        error.get("message").unwrap_or(error).as_str().map(str::to_string)
+    });
+    message.unwrap_or_else(|| body.chars().take(200).collect())
+}
+
+/// Sends the request and returns the response if it has a success status
+async fn send_checked(request: reqwest::RequestBuilder, timeout: Duration) -> Result<reqwest::Response, BackendError> {
+    let response = trace::propagate(request).send().await.map_err(|e| BackendError::from_reqwest(e, timeout))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    // Only the delay-seconds form; HTTP-date values are rare from APIs
+    let retry_after = response.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let text = response.text().await.map_err(|e| BackendError::from_reqwest(e, timeout))?;
+    let message = upstream_message(&text);
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Err(BackendError::RateLimited { retry_after, message });
+    }
+    Err(BackendError::Status { status: status.as_u16(), message })
+}
+
+/// Lines of a streamed response body, without their line endings
+fn body_lines(response: reqwest::Response, timeout: Duration) -> BoxStream<'static, Result<String, BackendError>> {
+    let state = (Some(response), Vec::new());
+    stream::unfold(state, move |(mut response, mut buffer)| async move {
+        loop {
+            if let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim_end().to_string();
+                return Some((Ok(line), (response, buffer)));
+            }
+            match response.as_mut()?.chunk().await {
+                Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+                Ok(None) if buffer.is_empty() => return None,
+                Ok(None) => {
+                    let line = String::from_utf8_lossy(&buffer).trim_end().to_string();
+                    return Some((Ok(line), (None, Vec::new())));
+                }
+                Err(e) => return Some((Err(BackendError::from_reqwest(e, timeout)), (None, Vec::new()))),
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Turns the lines of a backend's streaming format into completion chunks
+trait StreamParser: Send + 'static {
+    fn feed(&mut self, line: &str) -> Result<Vec<CompletionChunk>, BackendError>;
+}
+
+/// Sends a streaming request and parses its body. The body is only read as
+/// fast as the returned stream is polled, so a slow consumer holds back the backend.
+fn stream_request<P: StreamParser>(
+    request: reqwest::RequestBuilder,
+    timeout: Duration,
+    parser: P,
+) -> BoxStream<'static, Result<CompletionChunk>> {
+    stream::once(async move {
+        let response = send_checked(request, timeout).await?;
+        Ok::<_, anyhow::Error>(parse_stream(body_lines(response, timeout), parser))
+    })
+    .try_flatten()
+    .boxed()
+}
+
+fn parse_stream<P: StreamParser>(
+    lines: BoxStream<'static, Result<String, BackendError>>,
+    parser: P,
+) -> BoxStream<'static, Result<CompletionChunk>> {
+    let state = (lines, parser, VecDeque::new(), false);
+    stream::unfold(state, |(mut lines, mut parser, mut pending, finished)| async move {
+        if finished {
+            return None;
+        }
+        loop {
+            if let Some(chunk) = pending.pop_front() {
+                let done = matches!(chunk, CompletionChunk::Done(_));
+                return Some((Ok(chunk), (lines, parser, pending, done)));
+            }
+            let error = match lines.next().await {
+                Some(Ok(line)) => match parser.feed(&line) {
+                    Ok(chunks) => {
+                        pending.extend(chunks);
+                        continue;
+                    }
+                    Err(e) => e,
+                },
+                Some(Err(e)) => e,
+                None => BackendError::InvalidResponse("stream ended before the final message".to_string()),
+            };
+            return Some((Err(error.into()), (lines, parser, pending, true)));
+        }
+    })
+    .boxed()
+}
+
+/// The JSON payload of a server-sent event `data:` line; None for other lines
+fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(str::trim)
+}
+
+fn parse_json_line(data: &str) -> Result<Value, BackendError> {
+    serde_json::from_str(data).map_err(|e| BackendError::InvalidResponse(e.to_string()))
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenAiCompatConfig {
+    /// API root including the version, e.g. `https://api.openai.com/v1`
+    pub base_url: String,
+    pub api_key: Option<String>,
+    /// Used when the request doesn't name a model
+    pub default_model: String,
+    pub timeout: Duration,
+    /// Ask for token log probabilities, which feed the confidence score
+    pub logprobs: bool,
+}
+
+impl OpenAiCompatConfig {
+    pub fn new(base_url: impl Into<String>, default_model: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            api_key: None,
+            default_model: default_model.into(),
+            timeout: Duration::from_secs(60),
+            logprobs: false,
+        }
+    }
+}
+
+/// Any server speaking the OpenAI chat-completions API
+pub struct OpenAiCompatBackend {
+    config: OpenAiCompatConfig,
+    client: reqwest::Client,
+}
+
+impl OpenAiCompatBackend {
+    pub fn new(config: OpenAiCompatConfig) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(config.timeout).build()?;
+        Ok(Self { config, client })
+    }
+
+    pub fn request_body(&self, prompt: &Prompt, params: &MCPParams) -> Value {
+        let mut messages = Vec::new();
+        if let Some(system) = &prompt.system {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+        messages.push(json!({ "role": "user", "content": prompt.user }));
+        let sampling = self.sampling(params);
+        let mut body = json!({
+            "model": requested_model(params).unwrap_or(&self.config.default_model),
+            "messages": messages,
+            "max_tokens": sampling.max_tokens,
+            "temperature": sampling.temperature,
+        });
+        if self.config.logprobs {
+            body["logprobs"] = json!(true);
+        }
+        body
+    }
+
+    fn request(&self, body: &Value) -> reqwest::RequestBuilder {
+        let url = format!("{}/chat/completions", self.config.base_url.trim_end_matches('/'));
+        let request = self.client.post(url).json(body);
+        match &self.config.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    async fn send(&self, body: Value) -> Result<CompletionOutput, BackendError> {
+        let timeout = self.config.timeout;
+        let response = send_checked(self.request(&body), timeout).await?;
+        let text = response.text().await.map_err(|e| BackendError::from_reqwest(e, timeout))?;
+        parse_chat_completion(&text)
+    }
+}
+
+fn openai_finish_reason(reason: Option<&str>) -> FinishReason {
+    match reason {
+        Some("stop") => FinishReason::Stop,
+        Some("length") => FinishReason::Length,
+        Some("content_filter") => FinishReason::ContentFilter,
+        _ => FinishReason::Other,
+    }
+}
+
+/// `data:` lines of chunk objects, ending with `data: [DONE]`
+#[derive(Default)]
+struct OpenAiStream {
+    text: String,
+    finish_reason: Option<String>,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    logprobs: Logprobs,
+}
+
+impl StreamParser for OpenAiStream {
+    fn feed(&mut self, line: &str) -> Result<Vec<CompletionChunk>, BackendError> {
+        let Some(data) = sse_data(line) else {
+            return Ok(Vec::new());
+        };
+        if data == "[DONE]" {
+            return Ok(vec![CompletionChunk::Done(CompletionOutput {
+                text: std::mem::take(&mut self.text),
+                prompt_tokens: self.prompt_tokens,
+                completion_tokens: self.completion_tokens,
+                finish_reason: openai_finish_reason(self.finish_reason.as_deref()),
+                generation_time: None,
+                mean_logprob: self.logprobs.mean(),
+            })]);
+        }
+
+        let value = parse_json_line(data)?;
+        if value.get("error").is_some() {
+            return Err(BackendError::Failed(upstream_message(data)));
+        }
+        if let Some(usage) = value.get("usage").filter(|usage| !usage.is_null()) {
+            let tokens = |field: &str| usage.get(field).and_then(Value::as_u64).unwrap_or(0) as u32;
+            self.prompt_tokens = tokens("prompt_tokens");
+            self.completion_tokens = tokens("completion_tokens");
+        }
+        let Some(choice) = value.pointer("/choices/0") else {
+            return Ok(Vec::new());
+        };
+        if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+            self.finish_reason = Some(reason.to_string());
+        }
+        self.logprobs.add(choice);
+        match choice.pointer("/delta/content").and_then(Value::as_str) {
+            Some(delta) if !delta.is_empty() => {
+                self.text.push_str(delta);
+                Ok(vec![CompletionChunk::Delta(delta.to_string())])
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+fn parse_chat_completion(body: &str) -> Result<CompletionOutput, BackendError> {
+    let invalid = |reason: &str| BackendError::InvalidResponse(reason.to_string());
+    let value: Value = serde_json::from_str(body).map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+    let choice = value.pointer("/choices/0").ok_or_else(|| invalid("no choices"))?;
+    let text = choice.pointer("/message/content").and_then(Value::as_str).ok_or_else(|| invalid("no message content"))?;
+    let tokens = |field: &str| value.pointer(&format!("/usage/{}", field)).and_then(Value::as_u64).unwrap_or(0) as u32;
+
+    Ok(CompletionOutput {
+        text: text.to_string(),
+        prompt_tokens: tokens("prompt_tokens"),
+        completion_tokens: tokens("completion_tokens"),
+        finish_reason: openai_finish_reason(choice.get("finish_reason").and_then(Value::as_str)),
+        generation_time: None,
+        mean_logprob: Logprobs::default().add(choice).mean(),
+    })
+}
+
+/// Token log probabilities of a chat completion, as `choices[].logprobs.content[].logprob`
+#[derive(Default)]
+struct Logprobs {
+    sum: f64,
+    tokens: u32,
+}
+
+impl Logprobs {
+    fn add(&mut self, choice: &Value) -> &mut Self {
+        let tokens = choice.pointer("/logprobs/content").and_then(Value::as_array).into_iter().flatten();
+        for logprob in tokens.filter_map(|token| token.get("logprob").and_then(Value::as_f64)) {
+            self.sum += logprob;
+            self.tokens += 1;
+        }
+        self
+    }
+
+    fn mean(&self) -> Option<f64> {
+        (self.tokens > 0).then(|| self.sum / f64::from(self.tokens))
+    }
+}
+
+impl LLMBackend for OpenAiCompatBackend {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, Result<CompletionOutput>> {
+        let body = self.request_body(prompt, params);
+        Box::pin(async move { Ok(self.send(body).await?) })
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn complete_stream<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxStream<'a, Result<CompletionChunk>> {
+        let mut body = self.request_body(prompt, params);
+        body["stream"] = json!(true);
+        body["stream_options"] = json!({ "include_usage": true });
+        stream_request(self.request(&body), self.config.timeout, OpenAiStream::default())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OllamaConfig {
+    /// e.g. `http://localhost:11434`
+    pub host: String,
+    /// Used when the request doesn't name a model
+    pub default_model: String,
+    /// Read the answer as Ollama's line-delimited stream instead of one JSON body
+    pub stream: bool,
+    pub timeout: Duration,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            host: "http://localhost:11434".to_string(),
+            default_model: "llama3.2".to_string(),
+            stream: false,
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+/// A local Ollama server, through `/api/generate`
+pub struct OllamaBackend {
+    config: OllamaConfig,
+    client: reqwest::Client,
+}
+
+/// Fields of a `/api/generate` reply, or of one line of its stream
+#[derive(Debug, Default, Deserialize)]
+struct OllamaReply {
+    #[serde(default)]
+    response: String,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    /// Nanoseconds
+    eval_duration: Option<u64>,
+    error: Option<String>,
+}
+
+impl OllamaBackend {
+    pub fn new(config: OllamaConfig) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(config.timeout).build()?;
+        Ok(Self { config, client })
+    }
+
+    pub fn request_body(&self, prompt: &Prompt, params: &MCPParams) -> Value {
+        let sampling = self.sampling(params);
+        let mut body = json!({
+            "model": requested_model(params).unwrap_or(&self.config.default_model),
+            "prompt": prompt.user,
+            "stream": self.config.stream,
+            "options": {
+                "temperature": sampling.temperature,
+                "num_predict": sampling.max_tokens,
+            },
+        });
+        if let Some(system) = &prompt.system {
+            body["system"] = json!(system);
+        }
+        body
+    }
+
+    fn request(&self, body: &Value) -> reqwest::RequestBuilder {
+        let url = format!("{}/api/generate", self.config.host.trim_end_matches('/'));
+        self.client.post(url).json(body)
+    }
+
+    async fn send(&self, body: Value) -> Result<CompletionOutput, BackendError> {
+        let timeout = self.config.timeout;
+        let response = send_checked(self.request(&body), timeout).await?;
+        let text = response.text().await.map_err(|e| BackendError::from_reqwest(e, timeout))?;
+        let reply: OllamaReply = serde_json::from_str(&text).map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        ollama_output(reply.response.clone(), reply)
+    }
+
+    /// Names of the models pulled to the server, from `/api/tags`
+    async fn tags(&self) -> Result<Vec<String>, BackendError> {
+        let timeout = self.config.timeout;
+        let url = format!("{}/api/tags", self.config.host.trim_end_matches('/'));
+        let response = send_checked(self.client.get(url), timeout).await?;
+        let text = response.text().await.map_err(|e| BackendError::from_reqwest(e, timeout))?;
+        let tags: OllamaTags = serde_json::from_str(&text).map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+}
+
+/// The fields of a `/api/tags` reply the model listing uses
+#[derive(Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaTag>,
+}
+
+#[derive(Deserialize)]
+struct OllamaTag {
+    name: String,
+}
+
+/// One JSON reply per line, the last with `done` set
+#[derive(Default)]
+struct OllamaStream {
+    text: String,
+}
+
+impl StreamParser for OllamaStream {
+    fn feed(&mut self, line: &str) -> Result<Vec<CompletionChunk>, BackendError> {
+        if line.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let reply: OllamaReply = serde_json::from_str(line).map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        let mut chunks = Vec::new();
+        if !reply.response.is_empty() {
+            self.text.push_str(&reply.response);
+            chunks.push(CompletionChunk::Delta(reply.response.clone()));
+        }
+        if reply.done || reply.error.is_some() {
+            chunks.push(CompletionChunk::Done(ollama_output(std::mem::take(&mut self.text), reply)?));
+        }
+        Ok(chunks)
+    }
+}
+
+/// The completion for a final (`done`) reply carrying the accumulated `text`
+fn ollama_output(text: String, reply: OllamaReply) -> Result<CompletionOutput, BackendError> {
+    if let Some(error) = reply.error {
+        return Err(BackendError::Failed(error));
+    }
+    Ok(CompletionOutput {
+        text,
+        prompt_tokens: reply.prompt_eval_count.unwrap_or(0),
+        completion_tokens: reply.eval_count.unwrap_or(0),
+        finish_reason: match reply.done_reason.as_deref() {
+            Some("stop") | None => FinishReason::Stop,
+            Some("length") => FinishReason::Length,
+            Some(_) => FinishReason::Other,
+        },
+        generation_time: reply.eval_duration.map(Duration::from_nanos),
+        mean_logprob: None,
+    })
+}
+
+impl LLMBackend for OllamaBackend {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, Result<CompletionOutput>> {
+        if self.config.stream {
+            return Box::pin(collect_completion(self.complete_stream(prompt, params)));
+        }
+        let body = self.request_body(prompt, params);
+        Box::pin(async move { Ok(self.send(body).await?) })
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn complete_stream<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxStream<'a, Result<CompletionChunk>> {
+        let mut body = self.request_body(prompt, params);
+        body["stream"] = json!(true);
+        stream_request(self.request(&body), self.config.timeout, OllamaStream::default())
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move { Ok(self.tags().await?) })
+    }
+}
+
+/// The `anthropic-version` header value this backend is written against
+pub const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Clone)]
+pub struct AnthropicConfig {
+    pub base_url: String,
+    pub api_key: String,
+    /// Used when the request doesn't name a model
+    pub default_model: String,
+    pub timeout: Duration,
+}
+
+impl AnthropicConfig {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            base_url: "https://api.anthropic.com".to_string(),
+            api_key: api_key.into(),
+            default_model: "claude-3-5-haiku-latest".to_string(),
+            timeout: Duration::from_secs(120),
+        }
+    }
+
+    /// From `ANTHROPIC_API_KEY`, with optional `ANTHROPIC_BASE_URL` and
+    /// `VOID_SHRINE_ANTHROPIC_MODEL`; None without a key
+    pub fn from_env() -> Option<Self> {
+        let mut config = Self::new(std::env::var("ANTHROPIC_API_KEY").ok()?);
+        if let Ok(base_url) = std::env::var("ANTHROPIC_BASE_URL") {
+            config.base_url = base_url;
+        }
+        if let Ok(model) = std::env::var("VOID_SHRINE_ANTHROPIC_MODEL") {
+            config.default_model = model;
+        }
+        Some(config)
+    }
+}
+
+/// The Anthropic Messages API; the prompt's system text goes in the `system` field
+pub struct AnthropicBackend {
+    config: AnthropicConfig,
+    client: reqwest::Client,
+}
+
+impl AnthropicBackend {
+    pub fn new(config: AnthropicConfig) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(config.timeout).build()?;
+        Ok(Self { config, client })
+    }
+
+    pub fn request_body(&self, prompt: &Prompt, params: &MCPParams) -> Value {
+        let sampling = self.sampling(params);
+        let mut body = json!({
+            "model": requested_model(params).unwrap_or(&self.config.default_model),
+            "max_tokens": sampling.max_tokens,
+            "temperature": sampling.temperature,
+            "messages": [{ "role": "user", "content": prompt.user }],
+        });
+        if let Some(system) = &prompt.system {
+            body["system"] = json!(system);
+        }
+        body
+    }
+
+    fn request(&self, body: &Value) -> reqwest::RequestBuilder {
+        let url = format!("{}/v1/messages", self.config.base_url.trim_end_matches('/'));
+        self.client
+            .post(url)
+            .header("x-api-key", &self.config.api_key)
+            .header("anthropic-version", ANTHROPIC_API_VERSION)
+            .json(body)
+    }
+
+    async fn send(&self, body: Value) -> Result<CompletionOutput, BackendError> {
+        let timeout = self.config.timeout;
+        let response = send_checked(self.request(&body), timeout).await?;
+        let text = response.text().await.map_err(|e| BackendError::from_reqwest(e, timeout))?;
+        parse_anthropic_message(&text)
+    }
+}
+
+fn anthropic_stop_reason(reason: Option<&str>) -> FinishReason {
+    match reason {
+        Some("end_turn") | Some("stop_sequence") => FinishReason::Stop,
+        Some("max_tokens") => FinishReason::Length,
+        Some("refusal") => FinishReason::ContentFilter,
+        _ => FinishReason::Other,
+    }
+}
+
+/// Server-sent events from `message_start` to `message_stop`
+#[derive(Default)]
+struct AnthropicStream {
+    text: String,
+    stop_reason: Option<String>,
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+impl StreamParser for AnthropicStream {
+    fn feed(&mut self, line: &str) -> Result<Vec<CompletionChunk>, BackendError> {
+        let Some(data) = sse_data(line) else {
+            return Ok(Vec::new());
+        };
+        let value = parse_json_line(data)?;
+        let tokens = |pointer: &str| value.pointer(pointer).and_then(Value::as_u64).map(|n| n as u32);
+
+        match value.get("type").and_then(Value::as_str) {
+            Some("message_start") => {
+                self.input_tokens = tokens("/message/usage/input_tokens").unwrap_or(0);
+                self.output_tokens = tokens("/message/usage/output_tokens").unwrap_or(0);
+            }
+            Some("content_block_delta") if value.pointer("/delta/type").and_then(Value::as_str) == Some("text_delta") => {
+                let delta = value.pointer("/delta/text").and_then(Value::as_str).unwrap_or_default();
+                self.text.push_str(delta);
+                return Ok(vec![CompletionChunk::Delta(delta.to_string())]);
+            }
+            Some("message_delta") => {
+                if let Some(reason) = value.pointer("/delta/stop_reason").and_then(Value::as_str) {
+                    self.stop_reason = Some(reason.to_string());
+                }
+                self.output_tokens = tokens("/usage/output_tokens").unwrap_or(self.output_tokens);
+            }
+            Some("message_stop") => {
+                return Ok(vec![CompletionChunk::Done(CompletionOutput {
+                    text: std::mem::take(&mut self.text),
+                    prompt_tokens: self.input_tokens,
+                    completion_tokens: self.output_tokens,
+                    finish_reason: anthropic_stop_reason(self.stop_reason.as_deref()),
+                    generation_time: None,
+                    mean_logprob: None,
+                })]);
+            }
+            Some("error") => return Err(BackendError::Failed(upstream_message(data))),
+            _ => {}
+        }
+        Ok(Vec::new())
+    }
+}
+
+fn parse_anthropic_message(body: &str) -> Result<CompletionOutput, BackendError> {
+    let value: Value = serde_json::from_str(body).map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+    let blocks = value.get("content").and_then(Value::as_array)
+        .ok_or_else(|| BackendError::InvalidResponse("no content".to_string()))?;
+    let text: String = blocks.iter()
+        .filter(|block| block.get("type").and_then(Value::as_str) == Some("text"))
+        .filter_map(|block| block.get("text").and_then(Value::as_str))
+        .collect();
+    let tokens = |field: &str| value.pointer(&format!("/usage/{}", field)).and_then(Value::as_u64).unwrap_or(0) as u32;
+
+    Ok(CompletionOutput {
+        text,
+        prompt_tokens: tokens("input_tokens"),
+        completion_tokens: tokens("output_tokens"),
+        finish_reason: anthropic_stop_reason(value.get("stop_reason").and_then(Value::as_str)),
+        generation_time: None,
+        mean_logprob: None,
+    })
+}
+
+impl LLMBackend for AnthropicBackend {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, Result<CompletionOutput>> {
+        let body = self.request_body(prompt, params);
+        Box::pin(async move { Ok(self.send(body).await?) })
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    /// The Messages API takes temperatures up to 1
+    fn sampling(&self, params: &MCPParams) -> Sampling {
+        Sampling { temperature: params.temperature.min(1.0), ..Sampling::requested(params) }
+    }
+
+    fn complete_stream<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxStream<'a, Result<CompletionChunk>> {
+        let mut body = self.request_body(prompt, params);
+        body["stream"] = json!(true);
+        stream_request(self.request(&body), self.config.timeout, AnthropicStream::default())
+    }
+}
+
+/// Which requests a route takes: one model name, or every name starting with a
+/// prefix when written with a trailing `*` (e.g. `gpt-*`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelPattern {
+    Exact(String),
+    Prefix(String),
+}
+
+impl ModelPattern {
+    pub fn parse(pattern: &str) -> Self {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => ModelPattern::Prefix(prefix.to_string()),
+            None => ModelPattern::Exact(pattern.to_string()),
+        }
+    }
+
+    fn matches(&self, model: &str) -> bool {
+        match self {
+            ModelPattern::Exact(name) => name == model,
+            ModelPattern::Prefix(prefix) => model.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+impl std::fmt::Display for ModelPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelPattern::Exact(name) => f.write_str(name),
+            ModelPattern::Prefix(prefix) => write!(f, "{}*", prefix),
+        }
+    }
+}
+
+/// A routing rule, or a model a backend reported serving, as listed by `GET /api/models`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutableModel {
+    pub model: String,
+    pub backend: String,
+    /// The backend implementation, e.g. "ollama"
+    pub kind: String,
+    /// In tokens, when configured on the model's route
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u32>,
+    /// Whether the backend streams text as it is generated
+    #[serde(default)]
+    pub streaming: bool,
+}
+
+/// A logical model name standing for several, tried in order until one answers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FallbackChain {
+    pub model: String,
+    pub models: Vec<String>,
+}
+
+/// Named backends and the model patterns routed to them. Exact names win over
+/// prefixes, longer prefixes over shorter ones; anything else goes to the default
+/// backend, or fails with `BackendError::UnknownModel` when there is none.
+/// Fallback chains name models routed this way.
+#[derive(Clone, Default)]
+pub struct BackendRegistry {
+    backends: Vec<(String, Arc<dyn LLMBackend>)>,
+    routes: Vec<Route>,
+    default_backend: Option<String>,
+    chains: Vec<FallbackChain>,
+}
+
+#[derive(Clone)]
+struct Route {
+    pattern: ModelPattern,
+    backend: String,
+    context_length: Option<u32>,
+}
+
+impl BackendRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a backend, replacing any registered under the same name
+    pub fn register(&mut self, name: impl Into<String>, backend: Arc<dyn LLMBackend>) {
+        let name = name.into();
+        self.backends.retain(|(existing, _)| *existing != name);
+        self.backends.push((name, backend));
+    }
+
+    pub fn route(&mut self, pattern: &str, backend: &str) -> Result<()> {
+        self.route_with_context_length(pattern, backend, None)
+    }
+
+    /// Routes `pattern` to `backend`, listing its models with `context_length` tokens
+    pub fn route_with_context_length(&mut self, pattern: &str, backend: &str, context_length: Option<u32>) -> Result<()> {
+        self.check_registered(backend)?;
+        let pattern = ModelPattern::parse(pattern);
+        self.routes.retain(|existing| existing.pattern != pattern);
+        self.routes.push(Route { pattern, backend: backend.to_string(), context_length });
+        Ok(())
+    }
+
+    /// Where unrouted models go; None makes them an error
+    pub fn set_default(&mut self, backend: Option<&str>) -> Result<()> {
+        if let Some(name) = backend {
+            self.check_registered(name)?;
+        }
+        self.default_backend = backend.map(str::to_string);
+        Ok(())
+    }
+
+    pub fn default_backend(&self) -> Option<&str> {
+        self.default_backend.as_deref()
+    }
+
+    /// Makes requests for `chain.model` try each of `chain.models` in turn,
+    /// replacing any chain of the same name. Every model must be routable.
+    pub fn chain(&mut self, chain: FallbackChain) -> Result<()> {
+        if chain.models.is_empty() {
+            anyhow::bail!("no models to try");
+        }
+        for model in &chain.models {
+            if self.chain_for(model).is_some() || *model == chain.model {
+                anyhow::bail!("model '{}' is a chain itself", model);
+            }
+            if self.route_for(model).is_none() {
+                anyhow::bail!("no backend serves model '{}'", model);
+            }
+        }
+        self.chains.retain(|existing| existing.model != chain.model);
+        self.chains.push(chain);
+        Ok(())
+    }
+
+    /// The chain a model name stands for, if any
+    pub fn chain_for(&self, model: &str) -> Option<&FallbackChain> {
+        self.chains.iter().find(|chain| chain.model == model)
+    }
+
+    fn check_registered(&self, name: &str) -> Result<()> {
+        if self.backend(name).is_none() {
+            let known: Vec<&str> = self.backends.iter().map(|(name, _)| name.as_str()).collect();
+            anyhow::bail!("unknown backend '{}', registered backends are: {}", name, known.join(", "));
+        }
+        Ok(())
+    }
+
+    pub fn backend(&self, name: &str) -> Option<&Arc<dyn LLMBackend>> {
+        self.backends.iter().find(|(existing, _)| existing == name).map(|(_, backend)| backend)
+    }
+
+    /// The backend name and backend serving the request's model
+    pub fn resolve(&self, params: &MCPParams) -> Result<(&str, &Arc<dyn LLMBackend>), BackendError> {
+        let name = match requested_model(params) {
+            Some(model) => self.route_for(model),
+            None => self.default_backend.as_deref(),
+        };
+        let name = name.ok_or_else(|| BackendError::UnknownModel(params.model.clone()))?;
+        let backend = self.backend(name).ok_or_else(|| BackendError::UnknownModel(params.model.clone()))?;
+        Ok((name, backend))
+    }
+
+    /// The name of the backend serving `model`: its route, or the default
+    fn route_for(&self, model: &str) -> Option<&str> {
+        self.matching_route(model).map(|route| route.backend.as_str()).or(self.default_backend.as_deref())
+    }
+
+    fn matching_route(&self, model: &str) -> Option<&Route> {
+        let exact = self.routes.iter().find(|route| matches!(route.pattern, ModelPattern::Exact(_)) && route.pattern.matches(model));
+        exact.or_else(|| {
+            self.routes.iter()
+                .filter(|route| route.pattern.matches(model))
+                .max_by_key(|route| match &route.pattern {
+                    ModelPattern::Prefix(prefix) => prefix.len(),
+                    ModelPattern::Exact(_) => 0,
+                })
+        })
+    }
+
+    /// The configured routes
+    pub fn models(&self) -> Vec<RoutableModel> {
+        self.routes.iter().filter_map(|route| {
+            let backend = self.backend(&route.backend)?;
+            Some(RoutableModel {
+                model: route.pattern.to_string(),
+                backend: route.backend.clone(),
+                kind: backend.name().to_string(),
+                context_length: route.context_length,
+                streaming: backend.streams(),
+            })
+        }).collect()
+    }
+
+    /// A model `backend` reported serving, with the context length of the
+    /// route sending it there, if one does
+    pub fn discovered(&self, model: &str, backend: &str) -> Option<RoutableModel> {
+        let serving = self.backend(backend)?;
+        let route = self.matching_route(model).filter(|route| route.backend == backend);
+        Some(RoutableModel {
+            model: model.to_string(),
+            backend: backend.to_string(),
+            kind: serving.name().to_string(),
+            context_length: route.and_then(|route| route.context_length),
+            streaming: serving.streams(),
+        })
+    }
+
+    /// Every registered backend, by name
+    pub fn backends(&self) -> impl Iterator<Item = (&str, &Arc<dyn LLMBackend>)> {
+        self.backends.iter().map(|(name, backend)| (name.as_str(), backend))
+    }
+
+    /// Mock backends answer from `specialties`
+    pub fn from_config(config: &BackendsConfig, specialties: &Arc<Specialties>) -> Result<Self> {
+        let mut registry = Self::new();
+        for spec in &config.backends {
+            registry.register(spec.name.clone(), spec.build(specialties)?);
+        }
+        for route in &config.routes {
+            registry.route_with_context_length(&route.model, &route.backend, route.context_length)
+                .map_err(|e| anyhow::anyhow!("route for '{}': {}", route.model, e))?;
+        }
+        registry.set_default(config.default_backend.as_deref())
+            .map_err(|e| anyhow::anyhow!("default_backend: {}", e))?;
+        for chain in &config.chains {
+            registry.chain(chain.clone())
+                .map_err(|e| anyhow::anyhow!("chain '{}': {}", chain.model, e))?;
+        }
+        Ok(registry)
+    }
+}
+
+/// The registry requests are routed with, replaced whole when the config is
+/// reloaded. A request routes with the one current when it asked, so a
+/// reload never changes backends under it.
+#[derive(Clone, Default)]
+pub struct SharedBackends(Arc<std::sync::RwLock<Arc<BackendRegistry>>>);
+
+impl SharedBackends {
+    pub fn new(registry: BackendRegistry) -> Self {
+        Self(Arc::new(std::sync::RwLock::new(Arc::new(registry))))
+    }
+
+    pub fn current(&self) -> Arc<BackendRegistry> {
+        Arc::clone(&self.0.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub fn replace(&self, registry: BackendRegistry) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(registry);
+    }
+}
+
+/// Backends, model routes and the fallback backend, as written in configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackendsConfig {
+    #[serde(default)]
+    pub backends: Vec<BackendSpec>,
+    #[serde(default)]
+    pub routes: Vec<RouteSpec>,
+    /// Unset makes requests for unrouted models fail
+    #[serde(default)]
+    pub default_backend: Option<String>,
+    #[serde(default)]
+    pub chains: Vec<FallbackChain>,
+    /// How often backends are asked which models they serve, e.g. Ollama's
+    /// pulled models; 0 asks once at startup
+    #[serde(default = "BackendsConfig::default_model_refresh_secs")]
+    pub model_refresh_secs: u64,
+}
+
+impl Default for BackendsConfig {
+    fn default() -> Self {
+        Self {
+            backends: Vec::new(),
+            routes: Vec::new(),
+            default_backend: None,
+            chains: Vec::new(),
+            model_refresh_secs: Self::default_model_refresh_secs(),
+        }
+    }
+}
+
+impl BackendsConfig {
+    fn default_model_refresh_secs() -> u64 {
+        300
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteSpec {
+    /// A model name, or a prefix with a trailing `*`
+    pub model: String,
+    pub backend: String,
+    /// Listed by `GET /api/models`, in tokens
+    #[serde(default)]
+    pub context_length: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendSpec {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: BackendKind,
+}
+
+/// Connection settings per backend implementation. API keys can be given
+/// directly or as the name of an environment variable holding them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackendKind {
+    Mock {
+        /// Repeats the variants drawn above temperature 0 from run to run
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+    #[serde(rename = "openai")]
+    OpenAi {
+        base_url: String,
+        default_model: String,
+        #[serde(default)]
+        api_key: Option<String>,
+        #[serde(default)]
+        api_key_env: Option<String>,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+        /// Ask for token log probabilities, which feed the confidence score
+        #[serde(default)]
+        logprobs: bool,
+    },
+    Ollama {
+        #[serde(default)]
+        host: Option<String>,
+        #[serde(default)]
+        default_model: Option<String>,
+        #[serde(default)]
+        stream: bool,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    Anthropic {
+        #[serde(default)]
+        base_url: Option<String>,
+        #[serde(default)]
+        default_model: Option<String>,
+        #[serde(default)]
+        api_key: Option<String>,
+        #[serde(default)]
+        api_key_env: Option<String>,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+}
+
+impl BackendSpec {
+    fn api_key(&self, key: &Option<String>, key_env: &Option<String>) -> Result<Option<String>> {
+        match (key, key_env) {
+            (Some(key), _) => Ok(Some(key.clone())),
+            (None, Some(var)) => std::env::var(var)
+                .map(Some)
+                .map_err(|_| anyhow::anyhow!("backend '{}': environment variable {} is not set", self.name, var)),
+            (None, None) => Ok(None),
+        }
+    }
+
+    pub fn build(&self, specialties: &Arc<Specialties>) -> Result<Arc<dyn LLMBackend>> {
+        Ok(match &self.kind {
+            BackendKind::Mock { seed } => {
+                let backend = MockBackend::new(Arc::clone(specialties));
+                Arc::new(match seed {
+                    Some(seed) => backend.with_seed(*seed),
+                    None => backend,
+                })
+            }
+            BackendKind::OpenAi { base_url, default_model, api_key, api_key_env, timeout_secs, logprobs } => {
+                let mut config = OpenAiCompatConfig::new(base_url.clone(), default_model.clone());
+                config.api_key = self.api_key(api_key, api_key_env)?;
+                config.logprobs = *logprobs;
+                if let Some(secs) = timeout_secs {
+                    config.timeout = Duration::from_secs(*secs);
+                }
+                Arc::new(OpenAiCompatBackend::new(config)?)
+            }
+            BackendKind::Ollama { host, default_model, stream, timeout_secs } => {
+                let mut config = OllamaConfig { stream: *stream, ..Default::default() };
+                if let Some(host) = host {
+                    config.host = host.clone();
+                }
+                if let Some(model) = default_model {
+                    config.default_model = model.clone();
+                }
+                if let Some(secs) = timeout_secs {
+                    config.timeout = Duration::from_secs(*secs);
+                }
+                Arc::new(OllamaBackend::new(config)?)
+            }
+            BackendKind::Anthropic { base_url, default_model, api_key, api_key_env, timeout_secs } => {
+                let key = self.api_key(api_key, api_key_env)?
+                    .ok_or_else(|| anyhow::anyhow!("backend '{}': set api_key or api_key_env", self.name))?;
+                let mut config = AnthropicConfig::new(key);
+                if let Some(base_url) = base_url {
+                    config.base_url = base_url.clone();
+                }
+                if let Some(model) = default_model {
+                    config.default_model = model.clone();
+                }
+                if let Some(secs) = timeout_secs {
+                    config.timeout = Duration::from_secs(*secs);
+                }
+                Arc::new(AnthropicBackend::new(config)?)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(model: &str) -> MCPParams {
+        serde_json::from_value(json!({
+            "agent_id": "agent", "model": model, "specialty": "general", "prompt": "hi",
+            "max_tokens": 16, "temperature": 0.0, "use_rag": false, "context_window": 4096
+        }))
+        .unwrap()
+    }
+
+    fn registry() -> BackendRegistry {
+        let mut registry = BackendRegistry::new();
+        for name in ["local", "cloud", "special", "fallback"] {
+            registry.register(name, Arc::new(MockBackend::default()));
+        }
+        registry.route("llama*", "local").unwrap();
+        registry.route("llama3-cloud*", "cloud").unwrap();
+        registry.route("llama3-cloud-special", "special").unwrap();
+        registry
+    }
+
+    #[test]
+    fn resolves_exact_then_longest_prefix_then_default() {
+        let mut registry = registry();
+        let resolved = |registry: &BackendRegistry, model: &str| registry.resolve(&params(model)).map(|(name, _)| name.to_string());
+
+        assert_eq!(resolved(&registry, "llama3.2").unwrap(), "local");
+        assert_eq!(resolved(&registry, "llama3-cloud-70b").unwrap(), "cloud");
+        assert_eq!(resolved(&registry, "llama3-cloud-special").unwrap(), "special");
+        assert_eq!(resolved(&registry, "gpt-4o").unwrap_err(), BackendError::UnknownModel("gpt-4o".to_string()));
+
+        registry.set_default(Some("fallback")).unwrap();
+        assert_eq!(resolved(&registry, "gpt-4o").unwrap(), "fallback");
+        assert_eq!(resolved(&registry, SERVER_DEFAULT_MODEL).unwrap(), "fallback");
+
+        assert!(registry.route("gpt-*", "missing").is_err());
+        let listed: Vec<String> = registry.models().into_iter().map(|m| format!("{}={}", m.model, m.backend)).collect();
+        assert_eq!(listed, ["llama*=local", "llama3-cloud*=cloud", "llama3-cloud-special=special"]);
+    }
+
+    #[test]
+    fn chains_must_name_routable_models() {
+        let mut registry = registry();
+        let chain = |models: &[&str]| FallbackChain { model: "smart".to_string(), models: models.iter().map(|m| m.to_string()).collect() };
+
+        registry.chain(chain(&["llama3-cloud-70b", "llama3.2"])).unwrap();
+        assert_eq!(registry.chain_for("smart").unwrap().models, ["llama3-cloud-70b", "llama3.2"]);
+        assert!(registry.chain_for("llama3.2").is_none());
+
+        assert!(registry.chain(chain(&[])).is_err());
+        assert!(registry.chain(chain(&["gpt-4o"])).is_err());
+        assert!(registry.chain(FallbackChain { model: "smarter".to_string(), models: vec!["smart".to_string()] }).is_err());
+        registry.set_default(Some("fallback")).unwrap();
+        registry.chain(chain(&["gpt-4o"])).unwrap();
+        assert_eq!(registry.chain_for("smart").unwrap().models, ["gpt-4o"]);
+    }
+
+    #[test]
+    fn builds_from_config() {
+        let config: BackendsConfig = serde_json::from_value(json!({
+            "backends": [
+                { "name": "offline", "kind": "mock" },
+                { "name": "laptop", "kind": "ollama", "host": "http://127.0.0.1:11434", "stream": true },
+                { "name": "openai", "kind": "openai", "base_url": "https://api.openai.com/v1",
+                  "default_model": "gpt-4o-mini", "api_key": "sk-test" }
+            ],
+            "routes": [{ "model": "gpt-*", "backend": "openai" }, { "model": "llama*", "backend": "laptop" }],
+            "default_backend": "offline"
+        }))
+        .unwrap();
+        let registry = BackendRegistry::from_config(&config, &Arc::default()).unwrap();
+        assert_eq!(registry.resolve(&params("llama3.2")).unwrap().1.name(), "ollama");
+        assert_eq!(registry.models()[0].kind, "openai");
+
+        let mut broken = config.clone();
+        broken.default_backend = Some("nowhere".to_string());
+        let error = BackendRegistry::from_config(&broken, &Arc::default()).err().unwrap().to_string();
+        assert!(error.contains("unknown backend 'nowhere'"), "{}", error);
+
+        let missing_key = BackendSpec {
+            name: "claude".to_string(),
+            kind: BackendKind::Anthropic {
+                base_url: None,
+                default_model: None,
+                api_key: None,
+                api_key_env: Some("VOID_SHRINE_TEST_UNSET_KEY".to_string()),
+                timeout_secs: None,
+            },
+        };
+        assert!(missing_key.build(&Arc::default()).err().unwrap().to_string().contains("VOID_SHRINE_TEST_UNSET_KEY"));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_within_its_jitter() {
+        let config = RetryConfig { initial_backoff_ms: 100, max_backoff_ms: 300, jitter: 0.25, ..RetryConfig::default() };
+        for (retry, base) in [(1, 100.0), (2, 200.0), (3, 300.0), (40, 300.0)] {
+            let wait = config.backoff(retry).as_millis() as f64;
+            assert!((base * 0.75..=base * 1.25).contains(&wait), "retry {} waited {}", retry, wait);
+        }
+        assert_eq!(RetryConfig { jitter: 0.0, ..config }.backoff(2), Duration::from_millis(200));
+
+        let transient = |error: BackendError| MockBackend::default().is_transient(&error.into());
+        assert!(transient(BackendError::RateLimited { retry_after: None, message: String::new() }));
+        assert!(transient(BackendError::Status { status: 502, message: String::new() }));
+        assert!(transient(BackendError::Unavailable("connection reset".to_string())));
+        assert!(!transient(BackendError::Status { status: 400, message: String::new() }));
+        assert!(!transient(BackendError::UnknownModel("gpt-4o".to_string())));
+    }
+
+    /// The mock's answer to a science question at `temperature`, within `max_tokens`
+    async fn mock_answer(backend: &MockBackend, temperature: f64, max_tokens: u32) -> CompletionOutput {
+        let params = MCPParams { specialty: "science".to_string(), temperature, max_tokens, ..params("llama3.2") };
+        backend.complete(&Prompt::user("Chart the quiet stars"), &params).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn mock_answers_the_same_every_time_at_temperature_zero() {
+        let mut answers = Vec::new();
+        for _ in 0..3 {
+            let backend = MockBackend::default();
+            for _ in 0..5 {
+                answers.push(mock_answer(&backend, 0.0, 1024).await.text);
+            }
+        }
+        assert!(answers.iter().all(|answer| *answer == answers[0]), "{:?}", answers);
+        assert!(answers[0].starts_with("[MCP-Enhanced] Scientific investigation"));
+    }
+
+    #[tokio::test]
+    async fn mock_draws_variants_repeatably_under_a_seed() {
+        let draws = |seed| async move {
+            let backend = MockBackend::default().with_seed(seed);
+            let mut answers = Vec::new();
+            for _ in 0..12 {
+                answers.push(mock_answer(&backend, 2.0, 1024).await.text);
+            }
+            answers
+        };
+        let first = draws(9).await;
+        assert_eq!(first, draws(9).await);
+        let mut distinct = first.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert!(distinct.len() > 1, "{:?}", first);
+    }
+
+    #[tokio::test]
+    async fn mock_cuts_the_answer_at_a_word_within_max_tokens() {
+        let backend = MockBackend::default();
+        let full = mock_answer(&backend, 0.0, 1024).await;
+        let cut = mock_answer(&backend, 0.0, 8).await;
+        assert_eq!((full.finish_reason, cut.finish_reason), (FinishReason::Stop, FinishReason::Length));
+        assert!(full.text.starts_with(&format!("{} ", cut.text)), "{:?} / {:?}", cut.text, full.text);
+        assert!(cut.text.len() / 4 <= 8);
+    }
+}
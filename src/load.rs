@@ -0,0 +1,174 @@
+//! Per-agent load. Each agent keeps a sliding window of recent request starts
+//! and latencies; together with its requests in flight they give
+//! `current_load`, where 1.0 means the agent is at its configured capacity.
+//! Once an agent has nothing in flight its load decays toward zero, so one
+//! that bursts and goes quiet isn't throttled on figures from the burst.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// Latencies kept per agent; the p95 is taken over the most recent ones
+const MAX_LATENCY_SAMPLES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadConfig {
+    /// Span of the window behind `recent_rps` and `recent_p95_ms`
+    pub window_secs: u64,
+    /// Requests in flight at which an agent is fully loaded
+    pub max_in_flight: u32,
+    /// p95 latency at which an agent is fully loaded
+    pub latency_budget_ms: u64,
+    /// An idle agent's load halves every this many seconds; 0 keeps it until
+    /// the window forgets the requests behind it
+    pub idle_half_life_secs: u64,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self { window_secs: 60, max_in_flight: 8, latency_budget_ms: 10_000, idle_half_life_secs: 30 }
+    }
+}
+
+impl LoadConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    /// The higher of the in-flight share of capacity and the p95 share of the
+    /// latency budget; above 1.0 when either is exceeded
+    pub fn load(&self, in_flight: u32, p95_ms: f64) -> f64 {
+        let concurrency = f64::from(in_flight) / f64::from(self.max_in_flight.max(1));
+        let latency = p95_ms / self.latency_budget_ms.max(1) as f64;
+        concurrency.max(latency)
+    }
+
+    /// `load` after the agent has been idle for `idle`
+    pub fn decayed(&self, load: f64, idle: Duration) -> f64 {
+        if self.idle_half_life_secs == 0 {
+            return load;
+        }
+        load * 0.5f64.powf(idle.as_secs_f64() / self.idle_half_life_secs as f64)
+    }
+}
+
+/// Request starts, counted per second, and completion latencies of one agent
+/// over the last window
+#[derive(Debug, Clone, Default)]
+pub struct LoadWindow {
+    starts: VecDeque<(Instant, u32)>,
+    latencies: VecDeque<(Instant, u64)>,
+}
+
+impl LoadWindow {
+    pub fn record_start(&mut self, now: Instant) {
+        match self.starts.back_mut() {
+            Some((second, count)) if now.saturating_duration_since(*second) < Duration::from_secs(1) => *count += 1,
+            _ => self.starts.push_back((now, 1)),
+        }
+    }
+
+    pub fn record_latency(&mut self, now: Instant, latency_ms: u64) {
+        if self.latencies.len() == MAX_LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back((now, latency_ms));
+    }
+
+    /// Forgets everything older than `window`
+    pub fn prune(&mut self, now: Instant, window: Duration) {
+        let expired = |at: Instant| now.saturating_duration_since(at) > window;
+        while self.starts.front().is_some_and(|(second, _)| expired(*second)) {
+            self.starts.pop_front();
+        }
+        while self.latencies.front().is_some_and(|(at, _)| expired(*at)) {
+            self.latencies.pop_front();
+        }
+    }
+
+    /// Requests started per second over `window`
+    pub fn rps(&self, window: Duration) -> f64 {
+        let started: u32 = self.starts.iter().map(|(_, count)| count).sum();
+        f64::from(started) / window.as_secs_f64().max(1.0)
+    }
+
+    /// 95th percentile of the recent latencies; 0 without any
+    pub fn p95_ms(&self) -> f64 {
+        p95(self.latencies.iter().map(|(_, ms)| *ms).collect()).unwrap_or(0) as f64
+    }
+
+    /// Percentiles of the recent latencies; all 0 without any
+    pub fn percentiles(&self) -> LatencyPercentiles {
+        let mut values: Vec<u64> = self.latencies.iter().map(|(_, ms)| *ms).collect();
+        values.sort_unstable();
+        let at = |percent| percentile(&values, percent).unwrap_or(0);
+        LatencyPercentiles { samples: values.len(), p50_ms: at(50), p90_ms: at(90), p95_ms: at(95), p99_ms: at(99) }
+    }
+}
+
+/// An agent's latencies over the load window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyPercentiles {
+    pub samples: usize,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+}
+
+/// Nearest-rank 95th percentile; None for no values
+pub fn p95(mut values: Vec<u64>) -> Option<u64> {
+    values.sort_unstable();
+    percentile(&values, 95)
+}
+
+/// Nearest-rank `percent`th percentile of sorted values; None for no values
+fn percentile(sorted: &[u64], percent: usize) -> Option<u64> {
+    let rank = (sorted.len() * percent).div_ceil(100);
+    sorted.get(rank.checked_sub(1)?).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_forgets_old_requests() {
+        let start = Instant::now();
+        let window = Duration::from_secs(10);
+        let mut recent = LoadWindow::default();
+        for i in 0..20 {
+            let at = start + Duration::from_millis(i * 500);
+            recent.record_start(at);
+            recent.record_latency(at, if i == 0 { 5000 } else { i * 10 });
+        }
+        assert_eq!(recent.rps(window), 2.0);
+        assert_eq!(recent.p95_ms(), 190.0);
+        let percentiles = recent.percentiles();
+        assert_eq!((percentiles.samples, percentiles.p50_ms, percentiles.p99_ms), (20, 100, 5000));
+
+        recent.prune(start + Duration::from_secs(15), window);
+        assert_eq!(recent.rps(window), 1.0);
+        recent.prune(start + Duration::from_secs(60), window);
+        assert_eq!((recent.rps(window), recent.p95_ms()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn load_is_the_busier_of_concurrency_and_latency() {
+        let config = LoadConfig { max_in_flight: 4, latency_budget_ms: 1000, ..LoadConfig::default() };
+        assert_eq!(config.load(2, 100.0), 0.5);
+        assert_eq!(config.load(1, 750.0), 0.75);
+        assert_eq!(config.load(8, 0.0), 2.0);
+    }
+
+    #[test]
+    fn idle_load_halves_every_half_life() {
+        let config = LoadConfig { idle_half_life_secs: 10, ..LoadConfig::default() };
+        assert_eq!(config.decayed(0.8, Duration::ZERO), 0.8);
+        assert_eq!(config.decayed(0.8, Duration::from_secs(10)), 0.4);
+        assert_eq!(config.decayed(0.8, Duration::from_secs(30)), 0.1);
+        let kept = LoadConfig { idle_half_life_secs: 0, ..config };
+        assert_eq!(kept.decayed(0.8, Duration::from_secs(3600)), 0.8);
+    }
+}
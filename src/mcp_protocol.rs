@@ -0,0 +1,466 @@
+//! Model Context Protocol over JSON-RPC 2.0, so standard MCP clients can use the
+//! service's handlers as tools. The legacy `/api/mcp` JSON shape is unaffected.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use warp::{http::StatusCode, Filter, Reply};
+use crate::auth::Tenancy;
+use crate::rag_engine::RetrievalMode;
+use crate::mcp_server::{
+    default_context_window, default_max_tokens, default_temperature, default_use_rag, ErrorResponse, MCPError, MCPParams, MCPRequest, McpMethod,
+    MoralRecenteringMode, MoralRequest, ParamSources, VoidShrineMCP,
+};
+
+/// Protocol revisions this server speaks, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-03-26", "2024-11-05"];
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    /// Absent for notifications, which get no response
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    /// For invalid params, `{"fields": [...]}` listing each violation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), data: None }
+    }
+}
+
+impl JsonRpcResponse {
+    fn result(id: Value, result: Value) -> Self {
+        Self { jsonrpc: "2.0".to_string(), id, result: Some(result), error: None }
+    }
+
+    fn error(id: Value, error: JsonRpcError) -> Self {
+        Self { jsonrpc: "2.0".to_string(), id, result: None, error: Some(error) }
+    }
+}
+
+/// Arguments shared by the prompt-driven tools; everything but `prompt` is optional
+#[derive(Debug, Clone, Deserialize)]
+struct PromptArguments {
+    prompt: String,
+    #[serde(default = "default_agent_id")]
+    agent_id: String,
+    #[serde(default = "default_model")]
+    model: String,
+    #[serde(default = "default_specialty")]
+    specialty: String,
+    #[serde(default = "default_max_tokens")]
+    max_tokens: u32,
+    #[serde(default = "default_temperature")]
+    temperature: f64,
+    #[serde(default = "default_use_rag")]
+    use_rag: bool,
+    #[serde(default = "default_context_window")]
+    context_window: u32,
+    #[serde(default)]
+    doc_ids: Option<Vec<String>>,
+    #[serde(default)]
+    since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    ethical_framework: Option<String>,
+    #[serde(default)]
+    void_shrine_context: bool,
+    #[serde(default)]
+    moral_recentering: MoralRecenteringMode,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    #[serde(default)]
+    rag_top_k: Option<usize>,
+    #[serde(default)]
+    rag_min_score: Option<f64>,
+    #[serde(default)]
+    rag_filters: HashMap<String, String>,
+    #[serde(default)]
+    rag_collection: Option<String>,
+    #[serde(default)]
+    rag_mode: Option<RetrievalMode>,
+    #[serde(default)]
+    rag_engine: Option<String>,
+}
+
+fn default_agent_id() -> String {
+    "mcp-client".to_string()
+}
+
+fn default_model() -> String {
+    crate::llm_backend::SERVER_DEFAULT_MODEL.to_string()
+}
+
+fn default_specialty() -> String {
+    "general".to_string()
+}
+
+impl From<PromptArguments> for MCPParams {
+    fn from(args: PromptArguments) -> Self {
+        MCPParams {
+            agent_id: args.agent_id,
+            model: args.model,
+            specialty: args.specialty,
+            prompt: args.prompt,
+            max_tokens: args.max_tokens,
+            temperature: args.temperature,
+            use_rag: args.use_rag,
+            context_window: args.context_window,
+            flat_rag_context: false,
+            since: args.since,
+            until: args.until,
+            doc_ids: args.doc_ids,
+            dedupe_chunks: true,
+            ethical_framework: args.ethical_framework,
+            void_shrine_context: args.void_shrine_context,
+            moral_recentering: args.moral_recentering,
+            session_id: args.session_id,
+            timeout_ms: args.timeout_ms,
+            template: None,
+            verbose_confidence: false,
+            dry_run: false,
+            dry_run_skip_conditions: false,
+            rag_top_k: args.rag_top_k,
+            rag_min_score: args.rag_min_score,
+            rag_filters: args.rag_filters,
+            rag_collection: args.rag_collection,
+            rag_mode: args.rag_mode,
+            rag_engine: args.rag_engine,
+            sources: ParamSources::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ThrottleArguments {
+    agent_id: String,
+    #[serde(default = "verbose_by_default")]
+    verbose: bool,
+}
+
+fn verbose_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Option<Value>,
+}
+
+fn prompt_schema(description: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "prompt": { "type": "string", "description": description },
+            "agent_id": { "type": "string", "description": "Calling agent, used for metrics and throttling" },
+            "model": { "type": "string" },
+            "specialty": { "type": "string", "description": "Agent specialty, e.g. research or care" },
+            "max_tokens": { "type": "integer", "minimum": 1 },
+            "temperature": { "type": "number", "minimum": 0, "maximum": 2 },
+            "use_rag": { "type": "boolean", "description": "Ground the answer in the knowledge base" },
+            "context_window": { "type": "integer", "minimum": 1 },
+            "doc_ids": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Only retrieve from these documents"
+            },
+            "since": { "type": "string", "format": "date-time", "description": "Only documents indexed at or after" },
+            "until": { "type": "string", "format": "date-time", "description": "Only documents indexed at or before" },
+            "rag_top_k": { "type": "integer", "minimum": 1, "description": "Chunks to retrieve, up to the server's maximum" },
+            "rag_min_score": { "type": "number", "minimum": 0, "description": "Leave out chunks scoring below this" },
+            "rag_filters": {
+                "type": "object",
+                "additionalProperties": { "type": "string" },
+                "description": "Metadata key to value pattern (* wildcard) retrieved documents must match"
+            },
+            "rag_collection": { "type": "string", "description": "Only documents whose collection metadata is this" },
+            "rag_mode": { "type": "string", "enum": ["keyword", "semantic", "hybrid"], "description": "How to search; keyword by default" },
+            "rag_engine": { "type": "string", "description": "Knowledge base to search, by name; the default one otherwise" },
+            "ethical_framework": { "type": "string", "description": "Recenter the prompt with this framework, e.g. care-ethics" },
+            "void_shrine_context": { "type": "boolean", "description": "Recenter the prompt with void shrine context" },
+            "moral_recentering": {
+                "type": "string",
+                "enum": ["on", "off", "auto"],
+                "description": "auto recenters when ethical_framework or void_shrine_context is given"
+            }
+        },
+        "required": ["prompt"]
+    })
+}
+
+/// The `tools/list` catalogue
+pub fn tool_definitions() -> Vec<Value> {
+    let mut tools: Vec<Value> = McpMethod::ALL
+        .into_iter()
+        .map(|method| {
+            let prompt = match method {
+                McpMethod::LlmInference => "The prompt to answer",
+                McpMethod::RagQuery => "The search query",
+                McpMethod::RagAnswer => "The question",
+            };
+            json!({ "name": method.as_str(), "description": method.description(), "inputSchema": prompt_schema(prompt) })
+        })
+        .collect();
+    tools.extend([
+        json!({
+            "name": "moral_recentering",
+            "description": "Rewrite a prompt through an ethical framework, optionally with void shrine context",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "original_prompt": { "type": "string" },
+                    "specialty": { "type": "string" },
+                    "void_shrine_context": { "type": "boolean" },
+                    "ethical_framework": {
+                        "type": "string",
+                        "description": "care-ethics, consequentialist, deontological, virtue-ethics or a configured one"
+                    },
+                    "strict": { "type": "boolean", "description": "Fail on an unknown framework instead of noting it" }
+                },
+                "required": ["original_prompt", "specialty", "void_shrine_context", "ethical_framework"]
+            },
+        }),
+        json!({
+            "name": "throttle_status",
+            "description": "Whether an agent should back off, based on its recent load, with its queue, rate limit bucket and estimated wait",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "agent_id": { "type": "string" },
+                    "verbose": { "type": "boolean", "description": "False for just should_throttle" }
+                },
+                "required": ["agent_id"]
+            },
+        }),
+    ]);
+    tools
+}
+
+fn arguments<T: serde::de::DeserializeOwned>(arguments: Option<Value>) -> Result<T, JsonRpcError> {
+    serde_json::from_value(arguments.unwrap_or_else(|| json!({})))
+        .map_err(|e| JsonRpcError::new(INVALID_PARAMS, format!("Invalid tool arguments: {}", e)))
+}
+
+/// Tool output as a text content block; failures inside the tool are reported
+/// with `isError` rather than as protocol errors, as MCP specifies, and
+/// their HTTP error body as the structured content
+fn tool_result(output: Result<Value, MCPError>) -> Value {
+    match output {
+        Ok(value) => json!({
+            "content": [{ "type": "text", "text": serde_json::to_string_pretty(&value).unwrap_or_default() }],
+            "structuredContent": value,
+            "isError": false,
+        }),
+        Err(e) => json!({
+            "content": [{ "type": "text", "text": e.to_string() }],
+            "structuredContent": ErrorResponse::from(&e),
+            "isError": true,
+        }),
+    }
+}
+
+async fn call_tool(service: &VoidShrineMCP, tenancy: &Tenancy, params: Option<Value>) -> Result<Value, JsonRpcError> {
+    let call: ToolCall = serde_json::from_value(params.unwrap_or(Value::Null))
+        .map_err(|e| JsonRpcError::new(INVALID_PARAMS, format!("Invalid tools/call params: {}", e)))?;
+
+    let output = match call.name.as_str() {
+        name if McpMethod::parse(name).is_ok() => {
+            let args: PromptArguments = arguments(call.arguments)?;
+            let request = MCPRequest { method: call.name.clone(), params: args.into(), request_id: None, idempotency_key: None };
+            if let Err(e) = service.validate_params(&request.params) {
+                let mut error = JsonRpcError::new(INVALID_PARAMS, e.to_string());
+                error.data = serde_json::to_value(ErrorResponse::from(&e)).ok();
+                return Err(error);
+            }
+            match service.admit_agent(tenancy, &request.params.agent_id) {
+                Ok(()) => service.handle_mcp_request(request).await
+                    .map(|response| serde_json::to_value(response.result).unwrap_or_default())
+                    .map_err(|failure| failure.error),
+                Err(e) => Err(e),
+            }
+        }
+        "moral_recentering" => {
+            let request: MoralRequest = arguments(call.arguments)?;
+            service.handle_moral_recentering(request).await
+                .map(|response| serde_json::to_value(response).unwrap_or_default())
+        }
+        "throttle_status" => {
+            let args: ThrottleArguments = arguments(call.arguments)?;
+            match args.verbose {
+                true => service.handle_throttle(tenancy, args.agent_id).await.map(|status| serde_json::to_value(status).unwrap_or_default()),
+                false => service.handle_throttle_check(tenancy, args.agent_id).await.map(|check| serde_json::to_value(check).unwrap_or_default()),
+            }
+        }
+        other => return Err(JsonRpcError::new(INVALID_PARAMS, format!("Unknown tool: {}", other))),
+    };
+    Ok(tool_result(output))
+}
+
+fn initialize(params: Option<Value>) -> Value {
+    let requested = params.as_ref()
+        .and_then(|p| p.get("protocolVersion"))
+        .and_then(Value::as_str);
+    // Echo the client's revision when we speak it, otherwise offer our newest
+    let version = requested
+        .filter(|v| SUPPORTED_PROTOCOL_VERSIONS.contains(v))
+        .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0]);
+
+    json!({
+        "protocolVersion": version,
+        "capabilities": {
+            "tools": { "listChanged": false }
+        },
+        "serverInfo": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION")
+        }
+    })
+}
+
+/// Handles one parsed JSON-RPC message; None for notifications
+async fn handle_request(service: &VoidShrineMCP, tenancy: &Tenancy, message: Value) -> Option<JsonRpcResponse> {
+    let id = message.get("id").cloned();
+    let request: JsonRpcRequest = match serde_json::from_value(message) {
+        Ok(request) => request,
+        Err(e) => {
+            return Some(JsonRpcResponse::error(
+                id.unwrap_or(Value::Null),
+                JsonRpcError::new(INVALID_REQUEST, format!("Invalid request: {}", e)),
+            ))
+        }
+    };
+    if request.jsonrpc != "2.0" {
+        return Some(JsonRpcResponse::error(
+            request.id.unwrap_or(Value::Null),
+            JsonRpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+        ));
+    }
+
+    let outcome = match request.method.as_str() {
+        "initialize" => Ok(initialize(request.params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tool_definitions() })),
+        "tools/call" => call_tool(service, tenancy, request.params).await,
+        method if method.starts_with("notifications/") => Ok(Value::Null),
+        method => Err(JsonRpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    };
+
+    let id = request.id?;
+    Some(match outcome {
+        Ok(result) => JsonRpcResponse::result(id, result),
+        Err(error) => JsonRpcResponse::error(id, error),
+    })
+}
+
+/// Handles a raw request body holding one message or a batch. None when nothing
+/// needs answering, i.e. the body held only notifications.
+pub async fn handle_message(service: &VoidShrineMCP, tenancy: &Tenancy, body: &[u8]) -> Option<Value> {
+    let message: Value = match serde_json::from_slice(body) {
+        Ok(message) => message,
+        Err(e) => {
+            let response = JsonRpcResponse::error(Value::Null, JsonRpcError::new(PARSE_ERROR, format!("Parse error: {}", e)));
+            return serde_json::to_value(response).ok();
+        }
+    };
+
+    match message {
+        Value::Array(batch) if batch.is_empty() => serde_json::to_value(JsonRpcResponse::error(
+            Value::Null,
+            JsonRpcError::new(INVALID_REQUEST, "Empty batch"),
+        )).ok(),
+        Value::Array(batch) => {
+            let mut responses = Vec::new();
+            for message in batch {
+                responses.extend(handle_request(service, tenancy, message).await);
+            }
+            if responses.is_empty() {
+                None
+            } else {
+                serde_json::to_value(responses).ok()
+            }
+        }
+        message => {
+            let response = handle_request(service, tenancy, message).await?;
+            serde_json::to_value(response).ok()
+        }
+    }
+}
+
+/// `POST /mcp`: JSON-RPC responses, or 202 Accepted for notification-only bodies
+pub fn route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone {
+    let limit = service.body_limits.inference_bytes;
+    warp::path("mcp")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(crate::api::body_bytes(limit))
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .then(|body: warp::hyper::body::Bytes, tenancy: Tenancy, service: Arc<VoidShrineMCP>| async move {
+            match handle_message(&service, &tenancy, &body).await {
+                Some(response) => warp::reply::json(&response).into_response(),
+                None => StatusCode::ACCEPTED.into_response(),
+            }
+        })
+}
+
+/// Serves line-delimited JSON-RPC, one message per line, until `input` reaches
+/// EOF. This is the stdio transport MCP hosts use for subprocess servers; its
+/// host sees every tenant.
+pub async fn serve_lines(
+    service: &VoidShrineMCP,
+    input: impl AsyncBufRead + Unpin,
+    mut output: impl AsyncWrite + Unpin,
+) -> anyhow::Result<()> {
+    let mut lines = input.lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_message(service, &Tenancy::All, line.as_bytes()).await {
+            // Responses must stay on one line; serde_json's compact form never contains newlines
+            let mut encoded = serde_json::to_vec(&response)?;
+            encoded.push(b'\n');
+            output.write_all(&encoded).await?;
+            output.flush().await?;
+        }
+    }
+    Ok(())
+}
@@ -1,523 +1,8246 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
-use warp::Filter;
 use dashmap::DashMap;
+use futures::StreamExt;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use crate::agents::{AgentRegistration, AgentRegistry, AgentSpec};
+use crate::agents::{MAX_AGENT_DESCRIPTION_BYTES, MAX_AGENT_TAGS, MAX_AGENT_TAG_LEN};
+use crate::agent_stats::{AgentStats, StatsWindow, WindowedStats};
+use crate::auth::{Auth, Scope, Tenancy};
+use crate::breaker::{BreakerConfig, BreakerReport, BreakerState, Breakers, Permit};
+use crate::clock::{Clock, SystemClock};
+use crate::confidence::{ConfidenceBreakdown, ConfidenceConfig};
+use crate::concurrency::{ConcurrencyLimiter, ConcurrencyPermit, ConcurrencyStatus, ConcurrencyUpdate};
+use crate::overload::{OverloadConfig, OverloadDetector, OverloadStatus, OverloadUpdate};
+use crate::load::{LatencyPercentiles, LoadConfig, LoadWindow};
+use crate::moral::{EthicalFrameworks, FiredRule, MoralTextsReport, RecenteringDiff, RecenteringSummary, ScoreBreakdown};
+use crate::scaling::{Observation, ScalingConfig, ScalingDirection, ScalingHistory, ScalingHistoryParams, ScalingHistoryResponse, ScalingLog, ScalingRecord};
+use crate::content_filter::{ContentFilterReport, ContentFilters, FilterAction};
+use crate::context_screen::{ContextFlag, ContextScreen};
+use crate::delays::DelayDistribution;
+use crate::webhooks::{WebhookConfig, WebhookEventKind, Webhooks};
+use crate::live::{LiveEvent, LiveFeed};
+use crate::audit::{AuditLog, AuditQuery, AuditRecord, AuditResponse};
+use crate::replay::{ReplayComparison, ReplayConfig, ReplayMode, ReplayOutcome, ReplayRequest, ReplayResponse, ReplayResult, ReplaySummary};
+use crate::cache::{CacheConfig, CacheStats, ResponseCache};
+use crate::sessions::{
+    CondenseSkipped, HistoryRetrievalConfig, RetrievalQuery, SessionConfig, SessionConflict, SessionStore, SessionsResponse, Turn,
+};
+use crate::text::{truncate_at_sentence, truncate_to_tokens, ELLIPSIS};
+use crate::tokenizer::Tokenizer;
+use crate::tokens::{TokenSigner, TokenVerification, TokenVerifyRequest};
+use crate::idempotency::{self, Begin, IdempotencyStore};
+use crate::quota::{QuotaConfig, QuotaMetric, QuotaStanding, QuotaStatus, Quotas};
+use crate::history::{ActivityHistory, ExportParams, ExportPlan};
+use crate::chaos_stats::{ChaosCounts, ChaosLedger, ChaosOutcome, ChaosReport, ChaosReportParams};
+use crate::usage::{UsageConfig, UsageLedger, UsageParams, UsageResponse, UsageSource};
+use crate::metrics::Metrics;
+use crate::metrics_store::{MetricsStore, SavedAgent, SavedMetrics};
+use crate::model_catalog::ModelCatalog;
+use crate::rate_limit::{BucketState, RateLimitConfig, RateLimiter};
+use crate::self_check::SelfCheckReport;
+use crate::shutdown::Shutdown;
+use crate::specialties::{Specialties, SpecialtiesResponse};
+use crate::templates::{PromptVars, Template, Templates};
+use crate::build_info::BuildInfo;
+use crate::config::{Config, ConfigSource};
+use crate::reload::{changed_keys, ReloadReport};
+use crate::trace::{self, stage_span};
+use tracing::field::Empty;
+use rand::seq::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::llm_backend::{
+    BackendError, BackendRegistry, CompletionChunk, CompletionOutput, FallbackChain, LLMBackend, MockBackend, Prompt, RetryConfig,
+    RoutableModel, Sampling, SharedBackends,
+};
+use crate::rag_engine::{
+    BackupReport, Document, DocumentInfo, DocumentPatch, FtsRebuilding, GroupStats, MaintenanceReport, QueryAnalytics, QueryOptions, RAGStats,
+    RetrievalMode, RankingConfig, SearchResult, ValidationError, FTS_REBUILD_BATCH_CHUNKS,
+};
+use crate::rag_engines::{RagEngines, RagEnginesResponse, SharedEngine, DEFAULT_ENGINE};
+use crate::rag_tasks::{self, RagTaskView, RagTasks, TaskKind};
+use crate::route_metrics::{RouteLatency, RouteMetrics};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MCPRequest {
     pub method: String,
     pub params: MCPParams,
+    /// The client's own correlation id, used as `MCPMetadata::request_id`;
+    /// see `validate_request_id`. A fresh one is generated when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Runs the request once per agent and key; see `idempotency`. Same
+    /// rules as `request_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MCPParams {
-    pub agent_id: String,
-    pub model: String,
-    pub specialty: String,
-    pub prompt: String,
-    pub max_tokens: u32,
-    pub temperature: f64,
-    pub use_rag: bool,
-    pub context_window: u32,
-}
+/// Longest client-supplied request id accepted
+pub const MAX_REQUEST_ID_LEN: usize = 128;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MCPResponse {
-    pub result: MCPResult,
-    pub metadata: MCPMetadata,
+/// Client-supplied request ids remembered for spotting repeats
+const RECENT_REQUEST_IDS: usize = 10_000;
+
+/// Accepts 1 to `MAX_REQUEST_ID_LEN` ASCII letters, digits, `-`, `_`, `.` and `:`,
+/// so an id is safe to echo in headers and logs
+pub fn validate_request_id(id: &str) -> Result<(), MCPError> {
+    check_id("request_id", id).map_or(Ok(()), |error| Err(MCPError::InvalidFields(vec![error])))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MCPResult {
-    pub response: String,
-    pub metrics: ResponseMetrics,
-    pub rag_context: Option<Vec<String>>,
+/// Every boost must be a finite multiplier above zero and the half-life
+/// positive, so no adjustment can zero, flip or NaN the scores
+fn check_ranking(config: &RankingConfig) -> Vec<FieldError> {
+    const BOOST: &str = "a finite number above 0";
+    let mut errors = Vec::new();
+    if let Some(half_life) = config.recency_half_life_days.filter(|days| !(days.is_finite() && *days > 0.0)) {
+        errors.push(FieldError::new("recency_half_life_days", "a finite number of days above 0", half_life));
+    }
+    let mut metadata_boosts: Vec<(String, f64)> = config
+        .metadata_boosts
+        .iter()
+        .flat_map(|(key, boosts)| boosts.iter().map(move |(value, boost)| (format!("metadata_boosts.{}.{}", key, value), *boost)))
+        .collect();
+    metadata_boosts.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut tag_boosts: Vec<(String, f64)> = config.tag_boosts.iter().map(|(tag, boost)| (format!("tag_boosts.{}", tag), *boost)).collect();
+    tag_boosts.sort_by(|a, b| a.0.cmp(&b.0));
+    for (field, boost) in metadata_boosts.into_iter().chain(tag_boosts) {
+        if !(boost.is_finite() && boost > 0.0) {
+            errors.push(FieldError::new(&field, BOOST, boost));
+        }
+    }
+    errors
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ResponseMetrics {
-    pub response_time_ms: u64,
-    pub token_count: u32,
-    pub rag_documents_used: u32,
-    pub confidence_score: f64,
+/// The rule of `validate_request_id`, for any client-chosen id
+fn check_id(field: &str, id: &str) -> Option<FieldError> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':');
+    if id.is_empty() || id.len() > MAX_REQUEST_ID_LEN || !id.chars().all(allowed) {
+        let constraint = format!("1 to {} letters, digits, '-', '_', '.' or ':'", MAX_REQUEST_ID_LEN);
+        let value: serde_json::Value = if id.len() > MAX_REQUEST_ID_LEN { id.len().into() } else { id.into() };
+        return Some(FieldError::new(field, constraint, value));
+    }
+    None
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MCPMetadata {
-    pub request_id: String,
-    pub timestamp: DateTime<Utc>,
-    pub void_shrine_token: String,
-    pub chaos_applied: bool,
-    pub moral_recentered: bool,
+/// Every problem with a registration for `agent_id`
+fn check_agent(agent_id: &str, spec: &AgentSpec) -> Result<(), Vec<FieldError>> {
+    let mut errors: Vec<FieldError> = check_id("agent_id", agent_id).into_iter().collect();
+    if spec.specialty.trim().is_empty() {
+        errors.push(FieldError::new("specialty", "non-empty", spec.specialty.as_str()));
+    }
+    if let Some(model) = spec.default_model.as_deref().filter(|model| model.trim().is_empty()) {
+        errors.push(FieldError::new("default_model", "non-empty when given", model));
+    }
+    if spec.max_concurrency == Some(0) {
+        errors.push(FieldError::new("max_concurrency", "positive", 0));
+    }
+    if spec.tags.len() > MAX_AGENT_TAGS {
+        errors.push(FieldError::new("tags", format!("at most {} tags", MAX_AGENT_TAGS), spec.tags.len()));
+    }
+    if let Some(tag) = spec.tags.iter().find(|tag| tag.trim().is_empty() || tag.len() > MAX_AGENT_TAG_LEN) {
+        errors.push(FieldError::new("tags", format!("1 to {} bytes each", MAX_AGENT_TAG_LEN), tag.as_str()));
+    }
+    if let Some(description) = spec.description.as_deref().filter(|description| description.len() > MAX_AGENT_DESCRIPTION_BYTES) {
+        errors.push(FieldError::new("description", format!("at most {} bytes", MAX_AGENT_DESCRIPTION_BYTES), description.len()));
+    }
+    if let Some(model) = &spec.defaults.model {
+        errors.push(FieldError::new("defaults.model", "absent; registrations give default_model", model.as_str()));
+    }
+    if let Some(specialty) = &spec.defaults.specialty {
+        errors.push(FieldError::new("defaults.specialty", "absent; registrations give specialty", specialty.as_str()));
+    }
+    for (field, constraint, value) in spec.defaults.out_of_range().into_iter().filter(|(field, ..)| !matches!(*field, "model" | "specialty")) {
+        errors.push(FieldError::new(&format!("defaults.{}", field), constraint, value));
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChaosRequest {
-    pub agent_id: String,
-    pub chaos_type: String,
-    pub intensity: f64,
+/// The client-supplied request ids seen most recently. Repeats are allowed but
+/// logged; nothing is deduplicated yet.
+#[derive(Debug, Default)]
+pub struct RecentRequestIds {
+    seen: std::sync::Mutex<(HashSet<String>, VecDeque<String>)>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChaosResponse {
-    pub apply_chaos: bool,
-    pub effect: String,
-    pub delay_ms: u64,
+impl RecentRequestIds {
+    /// False when `id` is among the last `RECENT_REQUEST_IDS` recorded
+    pub fn insert(&self, id: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let (ids, order) = &mut *seen;
+        if ids.contains(id) {
+            return false;
+        }
+        if order.len() == RECENT_REQUEST_IDS {
+            if let Some(oldest) = order.pop_front() {
+                ids.remove(&oldest);
+            }
+        }
+        ids.insert(id.to_string());
+        order.push_back(id.to_string());
+        true
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.seen.lock().unwrap_or_else(|e| e.into_inner()).0.contains(id)
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MoralRequest {
-    pub original_prompt: String,
-    pub specialty: String,
-    pub void_shrine_context: bool,
-    pub ethical_framework: String,
+/// Requests being handled, by id, so clients can cancel them; and the ids of
+/// those finished recently, so a late cancellation is told apart from one
+/// naming no request at all
+#[derive(Debug, Default)]
+pub struct RunningRequests {
+    /// Requests sharing an id are cancelled together
+    running: DashMap<String, (CancellationToken, usize)>,
+    finished: RecentRequestIds,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MoralResponse {
-    pub recentered_prompt: String,
-    pub ethical_adjustments: Vec<String>,
-    pub care_ethics_score: f64,
+impl RunningRequests {
+    /// Makes the request cancellable until the registration drops
+    pub fn register(self: &Arc<Self>, request_id: &str) -> Registration {
+        let mut entry = self.running.entry(request_id.to_string()).or_insert_with(|| (CancellationToken::new(), 0));
+        entry.1 += 1;
+        Registration { requests: Arc::clone(self), request_id: request_id.to_string(), token: entry.0.clone() }
+    }
+
+    pub fn cancel(&self, request_id: &str) -> Result<(), MCPError> {
+        match self.running.get(request_id) {
+            Some(entry) => {
+                entry.0.cancel();
+                Ok(())
+            }
+            None if self.finished.contains(request_id) => Err(MCPError::RequestFinished(request_id.to_string())),
+            None => Err(MCPError::RequestNotFound(request_id.to_string())),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ThrottleStatus {
-    pub should_throttle: bool,
-    pub delay_ms: u64,
-    pub reason: String,
-    pub agent_load: f64,
+/// A request's place in `RunningRequests`
+pub struct Registration {
+    requests: Arc<RunningRequests>,
+    request_id: String,
+    token: CancellationToken,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ScalingRequest {
-    pub agent_id: String,
-    pub response_time: Option<u64>,
-    pub token_count: Option<u32>,
-    pub success: bool,
+impl Registration {
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ScalingResponse {
-    pub adjustments: ScalingAdjustments,
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let removed = self.requests.running.remove_if_mut(&self.request_id, |_, (_, count)| {
+            *count -= 1;
+            *count == 0
+        });
+        if removed.is_some() {
+            self.requests.finished.insert(&self.request_id);
+        }
+    }
 }
 
+/// Params of every method. Only `agent_id` and `prompt` are required; the
+/// rest have defaults, the agent's own first (see `AgentDefaults`), then
+/// `model` and `specialty` from `RequestDefaults`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ScalingAdjustments {
-    pub description: String,
-    pub capacity_change: f64,
-    pub priority_adjustment: i32,
+#[serde(from = "ParamsBody")]
+pub struct MCPParams {
+    pub agent_id: String,
+    /// Empty for the agent's default model, else the specialty's, else
+    /// `RequestDefaults::model`, else the backend's
+    #[serde(default)]
+    pub model: String,
+    /// Empty for the agent's specialty, else `RequestDefaults::specialty`
+    #[serde(default)]
+    pub specialty: String,
+    pub prompt: String,
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: u32,
+    #[serde(default = "default_temperature")]
+    pub temperature: f64,
+    #[serde(default = "default_use_rag")]
+    pub use_rag: bool,
+    #[serde(default = "default_context_window")]
+    pub context_window: u32,
+    /// Also return retrieved context as pre-formatted strings in `rag_context` (legacy clients)
+    #[serde(default = "default_flat_rag_context")]
+    pub flat_rag_context: bool,
+    /// Only retrieve from documents indexed at or after this instant
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only retrieve from documents indexed at or before this instant
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// Only retrieve from these documents; an empty list retrieves nothing
+    #[serde(default)]
+    pub doc_ids: Option<Vec<String>>,
+    /// Drop retrieved chunks that mostly repeat a better-scoring neighbour
+    #[serde(default = "default_dedupe_chunks")]
+    pub dedupe_chunks: bool,
+    /// Framework to recenter the prompt with; `care-ethics` when recentering is on without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ethical_framework: Option<String>,
+    #[serde(default)]
+    pub void_shrine_context: bool,
+    #[serde(default)]
+    pub moral_recentering: MoralRecenteringMode,
+    /// Continue this conversation: its earlier turns go before the prompt, and
+    /// this prompt and response become its next turn. Same rules as `request_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Answer within this many milliseconds, clamped to `TimeoutConfig::max_ms`;
+    /// `default_ms` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Prompt template to assemble the prompt with; the specialty's, else the
+    /// default, when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Also return what went into the confidence score, as `confidence_breakdown`
+    #[serde(default)]
+    pub verbose_confidence: bool,
+    /// For `llm_inference`: assemble the prompt as for a real call and return
+    /// it as `prompt_preview`, without calling the backend
+    #[serde(default)]
+    pub dry_run: bool,
+    /// With `dry_run`, bypass chaos, throttling and rate limits rather than
+    /// meeting them as a real call would
+    #[serde(default)]
+    pub dry_run_skip_conditions: bool,
+    /// Chunks to retrieve: `INFERENCE_CONTEXT_RESULTS` for `llm_inference`
+    /// and `RAG_QUERY_RESULTS` for `rag_query` when unset, at most
+    /// `ParamLimits::max_rag_top_k`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_top_k: Option<usize>,
+    /// Leave out retrieved chunks scoring below this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_min_score: Option<f64>,
+    /// Metadata key -> value pattern (`*` wildcard) retrieved documents must match
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub rag_filters: HashMap<String, String>,
+    /// Only retrieve from documents whose `collection` metadata is this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_collection: Option<String>,
+    /// Keyword search when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_mode: Option<RetrievalMode>,
+    /// Knowledge base to retrieve from, by name; the default engine when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_engine: Option<String>,
+    /// Where each param that may be defaulted came from, once
+    /// `VoidShrineMCP::apply_defaults` has run; until then only whether
+    /// `max_tokens`, `temperature`, `use_rag` and `context_window` were sent
+    #[serde(skip)]
+    pub sources: ParamSources,
 }
 
-pub struct VoidShrineMCP {
-    pub agent_metrics: Arc<DashMap<String, AgentMetrics>>,
-    pub rag_engine: Arc<RwLock<Option<crate::rag_engine::RAGEngine>>>,
-    pub chaos_config: Arc<RwLock<ChaosConfig>>,
+/// `MCPParams` as sent, telling the params left out from those given as
+/// their defaults
+#[derive(Deserialize)]
+struct ParamsBody {
+    agent_id: String,
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    specialty: String,
+    prompt: String,
+    #[serde(default)]
+    max_tokens: Option<u32>,
+    #[serde(default)]
+    temperature: Option<f64>,
+    #[serde(default)]
+    use_rag: Option<bool>,
+    #[serde(default)]
+    context_window: Option<u32>,
+    #[serde(default = "default_flat_rag_context")]
+    flat_rag_context: bool,
+    #[serde(default)]
+    since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    doc_ids: Option<Vec<String>>,
+    #[serde(default = "default_dedupe_chunks")]
+    dedupe_chunks: bool,
+    #[serde(default)]
+    ethical_framework: Option<String>,
+    #[serde(default)]
+    void_shrine_context: bool,
+    #[serde(default)]
+    moral_recentering: MoralRecenteringMode,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    #[serde(default)]
+    template: Option<String>,
+    #[serde(default)]
+    verbose_confidence: bool,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    dry_run_skip_conditions: bool,
+    #[serde(default)]
+    rag_top_k: Option<usize>,
+    #[serde(default)]
+    rag_min_score: Option<f64>,
+    #[serde(default)]
+    rag_filters: HashMap<String, String>,
+    #[serde(default)]
+    rag_collection: Option<String>,
+    #[serde(default)]
+    rag_mode: Option<RetrievalMode>,
+    #[serde(default)]
+    rag_engine: Option<String>,
 }
 
-#[derive(Debug, Clone)]
-pub struct AgentMetrics {
-    pub total_requests: u64,
-    pub avg_response_time: f64,
-    pub success_rate: f64,
-    pub last_request: DateTime<Utc>,
-    pub current_load: f64,
+impl From<ParamsBody> for MCPParams {
+    fn from(body: ParamsBody) -> Self {
+        let sent = |given: bool| if given { ParamSource::Request } else { ParamSource::ServerDefault };
+        let sources = ParamSources {
+            max_tokens: sent(body.max_tokens.is_some()),
+            temperature: sent(body.temperature.is_some()),
+            use_rag: sent(body.use_rag.is_some()),
+            context_window: sent(body.context_window.is_some()),
+            ..ParamSources::default()
+        };
+        MCPParams {
+            agent_id: body.agent_id,
+            model: body.model,
+            specialty: body.specialty,
+            prompt: body.prompt,
+            max_tokens: body.max_tokens.unwrap_or_else(default_max_tokens),
+            temperature: body.temperature.unwrap_or_else(default_temperature),
+            use_rag: body.use_rag.unwrap_or_else(default_use_rag),
+            context_window: body.context_window.unwrap_or_else(default_context_window),
+            flat_rag_context: body.flat_rag_context,
+            since: body.since,
+            until: body.until,
+            doc_ids: body.doc_ids,
+            dedupe_chunks: body.dedupe_chunks,
+            ethical_framework: body.ethical_framework,
+            void_shrine_context: body.void_shrine_context,
+            moral_recentering: body.moral_recentering,
+            session_id: body.session_id,
+            timeout_ms: body.timeout_ms,
+            template: body.template,
+            verbose_confidence: body.verbose_confidence,
+            dry_run: body.dry_run,
+            dry_run_skip_conditions: body.dry_run_skip_conditions,
+            rag_top_k: body.rag_top_k,
+            rag_min_score: body.rag_min_score,
+            rag_filters: body.rag_filters,
+            rag_collection: body.rag_collection,
+            rag_mode: body.rag_mode,
+            rag_engine: body.rag_engine,
+            sources,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct ChaosConfig {
-    pub enabled: bool,
-    pub intensity: f64,
-    pub chaos_types: Vec<String>,
+/// Where a param's value came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamSource {
+    /// Given by the request
+    #[default]
+    Request,
+    /// The agent's registration or `agents.defaults`
+    AgentDefault,
+    /// The specialty's default model, `RequestDefaults` or the param's own default
+    ServerDefault,
 }
 
-impl VoidShrineMCP {
-    pub fn new() -> Self {
-        Self {
-            agent_metrics: Arc::new(DashMap::new()),
-            rag_engine: Arc::new(RwLock::new(None)),
-            chaos_config: Arc::new(RwLock::new(ChaosConfig {
-                enabled: true,
-                intensity: 0.1,
-                chaos_types: vec![
-                    "network_delay".to_string(),
-                    "memory_pressure".to_string(),
-                    "resource_contention".to_string(),
-                ],
-            })),
-        }
-    }
-
-    pub async fn handle_mcp_request(&self, request: MCPRequest) -> Result<MCPResponse, anyhow::Error> {
-        let start_time = std::time::Instant::now();
-        let request_id = Uuid::new_v4().to_string();
-        
-        tracing::info!("Processing MCP request: {} for agent: {}", request.method, request.params.agent_id);
-
-        // Update agent metrics
-        self.update_agent_metrics(&request.params.agent_id);
+/// The source of each param an agent's defaults may fill. Params built in
+/// code rather than deserialized count as given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParamSources {
+    pub model: ParamSource,
+    pub specialty: ParamSource,
+    pub max_tokens: ParamSource,
+    pub temperature: ParamSource,
+    pub use_rag: ParamSource,
+    pub context_window: ParamSource,
+    pub template: ParamSource,
+}
 
-        // Apply chaos engineering
-        let chaos_applied = self.apply_chaos_if_enabled(&request.params.agent_id).await;
+/// Whether `llm_inference` runs the prompt through `handle_moral_recentering`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoralRecenteringMode {
+    On,
+    /// Not even the specialty framing is applied
+    Off,
+    /// Only when `ethical_framework` or `void_shrine_context` is given
+    #[default]
+    Auto,
+}
 
-        // Generate response based on method
-        let result = match request.method.as_str() {
-            "llm_inference" => self.handle_llm_inference(request.params).await?,
-            "rag_query" => self.handle_rag_query(request.params).await?,
-            _ => {
-                return Err(anyhow::anyhow!("Unsupported method: {}", request.method));
-            }
-        };
+/// Framework recentering uses when the request names none
+pub const DEFAULT_ETHICAL_FRAMEWORK: &str = "care-ethics";
 
-        let response_time = start_time.elapsed().as_millis() as u64;
+/// Metadata key `rag_collection` matches documents by
+pub const COLLECTION_METADATA_KEY: &str = "collection";
 
-        Ok(MCPResponse {
-            result,
-            metadata: MCPMetadata {
-                request_id,
-                timestamp: Utc::now(),
-                void_shrine_token: self.generate_void_shrine_token(),
-                chaos_applied,
-                moral_recentered: false, // Implement if needed
-            },
-        })
+impl MCPParams {
+    /// Each param `sources` covers, with its value and source
+    pub fn effective(&self) -> BTreeMap<String, EffectiveParam> {
+        let text = |text: &str| Some(text).filter(|text| !text.is_empty()).into();
+        let sources = &self.sources;
+        [
+            ("model", text(&self.model), sources.model),
+            ("specialty", text(&self.specialty), sources.specialty),
+            ("max_tokens", self.max_tokens.into(), sources.max_tokens),
+            ("temperature", self.temperature.into(), sources.temperature),
+            ("use_rag", self.use_rag.into(), sources.use_rag),
+            ("context_window", self.context_window.into(), sources.context_window),
+            ("template", self.template.clone().into(), sources.template),
+        ]
+        .into_iter()
+        .map(|(name, value, source)| (name.to_string(), EffectiveParam { value, source }))
+        .collect()
     }
 
-    async fn handle_llm_inference(&self, params: MCPParams) -> Result<MCPResult, anyhow::Error> {
-        // Simulate LLM inference with moral recentering and RAG context
-        let mut enhanced_prompt = params.prompt.clone();
-        let mut rag_context = None;
-
-        // Add RAG context if requested
-        if params.use_rag {
-            if let Some(rag_engine) = self.rag_engine.read().await.as_ref() {
-                let context = rag_engine.query(&params.prompt, 5).await?;
-                rag_context = Some(context.clone());
-                enhanced_prompt = format!(
-                    "Context from knowledge base:\n{}\n\nUser prompt: {}",
-                    context.join("\n\n"),
-                    params.prompt
-                );
-            }
+    fn query_options(&self) -> QueryOptions {
+        let mut metadata_filters = self.rag_filters.clone();
+        if let Some(collection) = &self.rag_collection {
+            metadata_filters.insert(COLLECTION_METADATA_KEY.to_string(), collection.clone());
         }
+        QueryOptions {
+            metadata_filters,
+            since: self.since,
+            until: self.until,
+            doc_ids: self.doc_ids.clone(),
+            dedupe_overlaps: self.dedupe_chunks,
+            mode: self.rag_mode.unwrap_or_default(),
+            min_score: self.rag_min_score,
+            ..Default::default()
+        }
+    }
+}
 
-        // Apply void shrine moral recentering
-        enhanced_prompt = self.apply_void_shrine_recentering(&enhanced_prompt, &params.specialty);
-
-        // Generate response (in real implementation, call actual LLM)
-        let response = self.generate_mock_response(&enhanced_prompt, &params).await;
+/// Bounds enforced on request params before any work is done
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParamLimits {
+    pub max_tokens: u32,
+    pub max_prompt_bytes: usize,
+    pub max_context_window: u32,
+    /// Most texts one `POST /api/embed` may send
+    pub max_embed_texts: usize,
+    /// Longest text `POST /api/embed` accepts
+    pub max_embed_text_bytes: usize,
+    /// Most prompts one `POST /api/moral-recentering/preview` may send
+    pub max_preview_prompts: usize,
+    /// Larger `rag_top_k` are lowered to this
+    pub max_rag_top_k: usize,
+    /// Most `rag_filters` one request may give
+    pub max_rag_filters: usize,
+}
 
-        Ok(MCPResult {
-            response,
-            metrics: ResponseMetrics {
-                response_time_ms: rand::random::<u64>() % 5000 + 1000, // 1-6 seconds
-                token_count: (params.prompt.len() / 4) as u32, // Rough token estimate
-                rag_documents_used: rag_context.as_ref().map(|c| c.len() as u32).unwrap_or(0),
-                confidence_score: 0.85 + (rand::random::<f64>() * 0.15),
-            },
-            rag_context,
-        })
+impl Default for ParamLimits {
+    fn default() -> Self {
+        Self {
+            max_tokens: 32_768,
+            max_prompt_bytes: 256 * 1024,
+            max_context_window: 1 << 20,
+            max_embed_texts: 256,
+            max_embed_text_bytes: 32 * 1024,
+            max_preview_prompts: 1000,
+            max_rag_top_k: 50,
+            max_rag_filters: 16,
+        }
     }
+}
 
-    async fn handle_rag_query(&self, params: MCPParams) -> Result<MCPResult, anyhow::Error> {
-        let context = if let Some(rag_engine) = self.rag_engine.read().await.as_ref() {
-            rag_engine.query(&params.prompt, 10).await?
-        } else {
-            vec!["RAG engine not initialized".to_string()]
-        };
+/// Model and specialty for requests that leave them empty and whose agent's
+/// registration doesn't say; empty leaves the choice to the backend and the
+/// fallback specialty
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestDefaults {
+    pub model: String,
+    pub specialty: String,
+}
 
-        Ok(MCPResult {
-            response: format!("Retrieved {} relevant documents", context.len()),
-            metrics: ResponseMetrics {
-                response_time_ms: 200,
-                token_count: 0,
-                rag_documents_used: context.len() as u32,
-                confidence_score: 0.9,
-            },
-            rag_context: Some(context),
-        })
+/// Largest request bodies accepted per class of route, in bytes. Longer ones
+/// get 413 `payload_too_large` before more than the limit is read.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BodyLimits {
+    /// `/api/mcp` and its stream, batch and job routes, `/mcp`, `/api/embed`, and moral recentering
+    pub inference_bytes: u64,
+    /// Documents indexed into or patched in the knowledge base
+    pub documents_bytes: u64,
+    /// Everything else: agents, chaos, scaling, tokens and RAG admin
+    pub admin_bytes: u64,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self { inference_bytes: 8 * 1024 * 1024, documents_bytes: 16 * 1024 * 1024, admin_bytes: 64 * 1024 }
     }
+}
 
-    fn apply_void_shrine_recentering(&self, prompt: &str, specialty: &str) -> String {
-        // Apply void-shrine specific moral and ethical recentering
-        let care_ethics_prefix = match specialty {
-            "tactical" => "From a perspective of strategic care and collective wellbeing: ",
-            "science" => "With rigorous ethical consideration and potential social impact: ",
-            "engineering" => "Prioritizing safety, accessibility, and sustainable design: ",
-            "creative" => "Through a lens of inclusive creativity and cultural sensitivity: ",
-            _ => "With mindful consideration of all stakeholders: ",
-        };
+/// Load-based throttling, checked before any work is done. Requests from an
+/// agent at or above `soft_load` wait, longer the closer the load gets to
+/// `hard_load`; at or above `hard_load` they are refused with 429.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThrottleConfig {
+    pub enabled: bool,
+    pub soft_load: f64,
+    pub hard_load: f64,
+    /// The delay just below `hard_load`, and the retry hint above it
+    pub max_delay_ms: u64,
+}
 
-        format!("{}{}", care_ethics_prefix, prompt)
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self { enabled: true, soft_load: 0.8, hard_load: 0.95, max_delay_ms: 2000 }
     }
+}
 
-    async fn generate_mock_response(&self, prompt: &str, params: &MCPParams) -> String {
-        // Generate contextual mock responses based on specialty
-        let base_response = match params.specialty.as_str() {
-            "tactical" => format!(
-                "Strategic analysis complete. Based on the enhanced prompt context, I recommend a multi-phase approach prioritizing stakeholder care and systemic resilience. Key considerations include resource optimization, risk mitigation, and sustainable implementation pathways."
-            ),
-            "science" => format!(
-                "Scientific investigation reveals interesting patterns in the provided context. The data suggests correlations that warrant deeper analysis through both quantitative metrics and qualitative assessment of broader implications."
-            ),
-            "engineering" => format!(
-                "Technical architecture assessment indicates optimal solutions through modular, fault-tolerant design principles. Recommended implementation emphasizes scalability, maintainability, and ethical computing practices."
-            ),
-            "creative" => format!(
-                "Creative synthesis generates novel approaches by combining contextual insights with innovative methodologies. The solution space includes unexplored opportunities for user-centered, aesthetically coherent implementations."
-            ),
-            _ => format!(
-                "Comprehensive analysis of the enhanced prompt reveals multiple interconnected factors requiring careful consideration and systematic response strategies."
-            ),
-        };
+/// `POST /api/mcp/batch`: how many params one batch may carry, and how many
+/// of its items are handled at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchConfig {
+    pub max_items: usize,
+    pub concurrency: usize,
+}
 
-        format!("[MCP-Enhanced] {}", base_response)
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self { max_items: 100, concurrency: 8 }
     }
+}
 
-    pub async fn handle_chaos(&self, request: ChaosRequest) -> ChaosResponse {
-        let chaos_config = self.chaos_config.read().await;
-        
-        if !chaos_config.enabled {
-            return ChaosResponse {
-                apply_chaos: false,
-                effect: "Chaos engineering disabled".to_string(),
-                delay_ms: 0,
-            };
-        }
-
-        let should_apply = rand::random::<f64>() < (chaos_config.intensity * request.intensity);
-        
-        if should_apply {
-            let delay = match request.chaos_type.as_str() {
-                "network_delay" => rand::random::<u64>() % 2000 + 500, // 500-2500ms
-                "memory_pressure" => rand::random::<u64>() % 1000 + 200, // 200-1200ms
-                "resource_contention" => rand::random::<u64>() % 3000 + 1000, // 1-4 seconds
-                _ => rand::random::<u64>() % 1500 + 300,
-            };
+/// How long requests may take. The deadline starts when a request arrives, so
+/// throttling and chaos delays spend it too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// For requests without `timeout_ms`
+    pub default_ms: u64,
+    /// Longer `timeout_ms` are clamped to this
+    pub max_ms: u64,
+    /// Knowledge base retrieval gets at most this much of the request's time
+    pub retrieval_ms: u64,
+}
 
-            ChaosResponse {
-                apply_chaos: true,
-                effect: format!("{} chaos applied", request.chaos_type),
-                delay_ms: delay,
-            }
-        } else {
-            ChaosResponse {
-                apply_chaos: false,
-                effect: "No chaos applied this cycle".to_string(),
-                delay_ms: 0,
-            }
-        }
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self { default_ms: 60_000, max_ms: 300_000, retrieval_ms: 5_000 }
     }
+}
 
-    pub async fn handle_throttle(&self, agent_id: String) -> ThrottleStatus {
-        let metrics = self.agent_metrics.get(&agent_id);
-        
-        if let Some(metrics) = metrics {
-            let current_load = metrics.current_load;
-            
-            if current_load > 0.8 {
-                ThrottleStatus {
-                    should_throttle: true,
-                    delay_ms: ((current_load - 0.5) * 5000.0) as u64, // Scale delay with load
-                    reason: "High agent load detected".to_string(),
-                    agent_load: current_load,
-                }
-            } else {
-                ThrottleStatus {
-                    should_throttle: false,
-                    delay_ms: 0,
-                    reason: "Normal load".to_string(),
-                    agent_load: current_load,
-                }
-            }
-        } else {
-            ThrottleStatus {
-                should_throttle: false,
-                delay_ms: 0,
-                reason: "New agent".to_string(),
-                agent_load: 0.0,
-            }
+impl TimeoutConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.default_ms == 0 || self.max_ms == 0 || self.retrieval_ms == 0 {
+            problems.push("timeouts.default_ms, max_ms and retrieval_ms must be positive".to_string());
         }
+        if self.default_ms > self.max_ms {
+            problems.push(format!("timeouts.default_ms must not exceed max_ms ({} > {})", self.default_ms, self.max_ms));
+        }
+        problems
     }
 
-    pub async fn handle_scaling(&self, request: ScalingRequest) -> ScalingResponse {
-        let mut description = "No adjustments needed".to_string();
-        let mut capacity_change = 0.0;
-        let mut priority_adjustment = 0;
-
-        if let Some(response_time) = request.response_time {
-            if response_time > 10000 { // > 10 seconds
-                description = "Scaling up due to high latency".to_string();
-                capacity_change = 0.2;
-                priority_adjustment = 1;
-            } else if response_time < 1000 { // < 1 second
-                description = "Can scale down - response time optimal".to_string();
-                capacity_change = -0.1;
-                priority_adjustment = -1;
-            }
-        }
-
-        ScalingResponse {
-            adjustments: ScalingAdjustments {
-                description,
-                capacity_change,
-                priority_adjustment,
-            },
+    /// The deadline for a request arriving now
+    pub fn deadline(&self, params: &MCPParams) -> Deadline {
+        let budget = std::time::Duration::from_millis(params.timeout_ms.unwrap_or(self.default_ms).min(self.max_ms));
+        Deadline {
+            at: tokio::time::Instant::now() + budget,
+            budget,
+            retrieval: std::time::Duration::from_millis(self.retrieval_ms),
         }
     }
+}
 
-    pub async fn handle_moral_recentering(&self, request: MoralRequest) -> MoralResponse {
-        let mut adjustments = vec![];
-        let mut recentered_prompt = request.original_prompt.clone();
+/// Where in the pipeline a request ran out of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutStage {
+    /// Anywhere outside the other stages, e.g. throttled or recentering
+    Request,
+    Retrieval,
+    Backend,
+}
 
-        // Apply care ethics framework
-        if request.ethical_framework == "care-ethics" {
-            adjustments.push("Applied care ethics perspective".to_string());
-            adjustments.push("Considered relational impact on all stakeholders".to_string());
-            
-            let care_prefix = "Considering the wellbeing and agency of all affected parties: ";
-            recentered_prompt = format!("{}{}", care_prefix, recentered_prompt);
+impl TimeoutStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TimeoutStage::Request => "request",
+            TimeoutStage::Retrieval => "retrieval",
+            TimeoutStage::Backend => "backend",
         }
+    }
+}
 
-        // Void shrine context adjustments
-        if request.void_shrine_context {
-            adjustments.push("Integrated void shrine ontological perspective".to_string());
-            adjustments.push("Emphasized emergence over rigid control".to_string());
-            
-            let void_context = "Through the lens of generative absence and emergent intelligence: ";
-            recentered_prompt = format!("{}{}", void_context, recentered_prompt);
-        }
+/// When a request must be answered by
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    pub at: tokio::time::Instant,
+    /// The whole request's time
+    pub budget: std::time::Duration,
+    retrieval: std::time::Duration,
+}
 
-        MoralResponse {
-            recentered_prompt,
-            ethical_adjustments: adjustments,
-            care_ethics_score: 0.87 + (rand::random::<f64>() * 0.13), // 0.87-1.0
-        }
+impl Deadline {
+    /// Runs knowledge base work, waiting for the engine included, in at most
+    /// the retrieval budget
+    pub async fn retrieval<T, E: From<MCPError>>(&self, work: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        let remaining = self.at.saturating_duration_since(tokio::time::Instant::now());
+        let (limit, budget) = if self.retrieval < remaining { (self.retrieval, self.retrieval) } else { (remaining, self.budget) };
+        Self::stage(TimeoutStage::Retrieval, limit, budget, work).await
     }
 
-    fn update_agent_metrics(&self, agent_id: &str) {
-        let now = Utc::now();
-        
-        self.agent_metrics
-            .entry(agent_id.to_string())
-            .and_modify(|metrics| {
-                metrics.total_requests += 1;
-                metrics.last_request = now;
-                // Simulate load calculation
-                metrics.current_load = (rand::random::<f64>() * 0.4) + 0.3; // 0.3-0.7
-            })
-            .or_insert(AgentMetrics {
-                total_requests: 1,
-                avg_response_time: 0.0,
-                success_rate: 1.0,
-                last_request: now,
-                current_load: 0.5,
-            });
+    /// Runs the backend call in whatever is left of the request's time
+    pub async fn backend<T, E: From<MCPError>>(&self, work: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        let remaining = self.at.saturating_duration_since(tokio::time::Instant::now());
+        Self::stage(TimeoutStage::Backend, remaining, self.budget, work).await
     }
 
-    async fn apply_chaos_if_enabled(&self, agent_id: &str) -> bool {
-        let chaos_config = self.chaos_config.read().await;
-        if chaos_config.enabled && rand::random::<f64>() < chaos_config.intensity {
-            tracing::info!("Chaos applied to agent: {}", agent_id);
-            true
-        } else {
-            false
+    /// `budget` is the one reported: the stage's own, or the request's when
+    /// the deadline came first
+    async fn stage<T, E: From<MCPError>>(
+        stage: TimeoutStage,
+        limit: std::time::Duration,
+        budget: std::time::Duration,
+        work: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        match tokio::time::timeout(limit, work).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(MCPError::DeadlineExceeded { stage, budget }.into()),
         }
     }
 
-    fn generate_void_shrine_token(&self) -> String {
-        let timestamp = Utc::now().timestamp_millis();
-        let entropy = Uuid::new_v4().to_string()[..8].to_string();
-        format!("vs_{}_{}", timestamp, entropy)
+    /// Resolves once the request's time is up
+    pub async fn expired(&self) {
+        tokio::time::sleep_until(self.at).await;
+    }
+
+    fn exceeded(&self) -> MCPError {
+        MCPError::DeadlineExceeded { stage: TimeoutStage::Request, budget: self.budget }
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
-    tracing_subscriber::init();
-    
-    let mcp_service = Arc::new(VoidShrineMCP::new());
-    
-    // Initialize RAG engine if available
-    // *mcp_service.rag_engine.write().await = Some(crate::rag_engine::RAGEngine::new().await?);
+/// Backend calls made for one answer, the time waited between them, and
+/// what the call that answered generated with
+#[derive(Debug, Clone, Copy, Default)]
+struct Attempts {
+    calls: u32,
+    delay: std::time::Duration,
+    sampling: Option<SamplingSettings>,
+}
 
-    let mcp_service_filter = warp::any().map(move || Arc::clone(&mcp_service));
+impl Attempts {
+    fn answered(&mut self, backend: &dyn LLMBackend, params: &MCPParams) {
+        self.sampling = Some(SamplingSettings { requested: Sampling::requested(params), effective: backend.sampling(params) });
+    }
 
-    // MCP endpoint
-    let mcp_route = warp::path("api")
-        .and(warp::path("mcp"))
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(mcp_service_filter.clone())
-        .and_then(|request: MCPRequest, service: Arc<VoidShrineMCP>| async move {
-            match service.handle_mcp_request(request).await {
-                Ok(response) => Ok(warp::reply::json(&response)),
-                Err(e) => {
-                    tracing::error!("MCP request failed: {}", e);
-                    Err(warp::reject::reject())
-                }
-            }
-        });
+    fn record(self, metrics: &mut ResponseMetrics) {
+        metrics.attempts = self.calls;
+        metrics.retry_delay_ms = self.delay.as_millis() as u64;
+        metrics.sampling = self.sampling;
+    }
+}
 
-    // Chaos endpoint
-    let chaos_route = warp::path("api")
-        .and(warp::path("chaos"))
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(mcp_service_filter.clone())
-        .and_then(|request: ChaosRequest, service: Arc<VoidShrineMCP>| async move {
-            let response = service.handle_chaos(request).await;
-            Ok::<_, warp::Rejection>(warp::reply::json(&response))
-        });
+/// The model of a fallback chain that answered, and its place in the chain
+#[derive(Debug, Clone, PartialEq)]
+struct ChainStep {
+    model: String,
+    depth: u32,
+}
 
-    // Throttling endpoint
-    let throttle_route = warp::path("api")
-        .and(warp::path("throttle"))
-        .and(warp::path::param::<String>())
-        .and(warp::get())
-        .and(mcp_service_filter.clone())
-        .and_then(|agent_id: String, service: Arc<VoidShrineMCP>| async move {
-            let response = service.handle_throttle(agent_id).await;
-            Ok::<_, warp::Rejection>(warp::reply::json(&response))
-        });
+/// How an answer came about, for its `MCPMetadata`
+#[derive(Debug, Clone, Default)]
+struct Provenance {
+    cached: bool,
+    chain: Option<ChainStep>,
+    retrieval_query: Option<RetrievalQuery>,
+    rag_error: Option<String>,
+    context_flags: Vec<ContextFlag>,
+}
 
-    // Scaling endpoint
-    let scaling_route = warp::path("api")
-        .and(warp::path("scaling"))
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(mcp_service_filter.clone())
-        .and_then(|request: ScalingRequest, service: Arc<VoidShrineMCP>| async move {
-            let response = service.handle_scaling(request).await;
-            Ok::<_, warp::Rejection>(warp::reply::json(&response))
-        });
+/// `params` asking for the model at `depth` of `chain`, or as they are without one
+fn chain_params<'a>(params: &'a MCPParams, chain: Option<&FallbackChain>, depth: usize) -> std::borrow::Cow<'a, MCPParams> {
+    match chain {
+        Some(chain) => std::borrow::Cow::Owned(MCPParams { model: chain.models[depth].clone(), ..params.clone() }),
+        None => std::borrow::Cow::Borrowed(params),
+    }
+}
 
-    // Moral recentering endpoint
-    let moral_route = warp::path("api")
-        .and(warp::path("moral-recentering"))
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(mcp_service_filter.clone())
-        .and_then(|request: MoralRequest, service: Arc<VoidShrineMCP>| async move {
-            let response = service.handle_moral_recentering(request).await;
-            Ok::<_, warp::Rejection>(warp::reply::json(&response))
-        });
+/// Whether a failed call to a chain's model warrants trying the next one: an
+/// open breaker, a backend timeout or outage, or a 5xx, but not a refusal of
+/// the request itself or the request running out of time
+fn warrants_fallback(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<MCPError>() {
+        Some(error) => matches!(error, MCPError::CircuitOpen { .. }),
+        None => counts_against_backend(error),
+    }
+}
 
-    let routes = mcp_route
-        .or(chaos_route)
-        .or(throttle_route)
-        .or(scaling_route)
-        .or(moral_route)
-        .with(warp::cors().allow_any_origin());
-
-    tracing::info!("🌀 Void Shrine MCP Server starting on port 3030");
-    
-    warp::serve(routes)
-        .run(([0, 0, 0, 0], 3030))
+/// Whether a failed backend call counts against the backend's circuit
+/// breaker: anything but an unroutable model or the upstream refusing the
+/// request itself with a 4xx
+fn counts_against_backend(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<BackendError>() {
+        Some(BackendError::UnknownModel(_)) => false,
+        Some(BackendError::Status { status, .. }) => *status >= 500,
+        _ => true,
+    }
+}
+
+/// What throttling does with an agent's next request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Throttle {
+    Proceed,
+    Delay(std::time::Duration),
+    Reject { retry_after: std::time::Duration },
+}
+
+impl ThrottleConfig {
+    pub fn decide(&self, load: f64) -> Throttle {
+        let max_delay = std::time::Duration::from_millis(self.max_delay_ms);
+        if !self.enabled || load < self.soft_load {
+            Throttle::Proceed
+        } else if load >= self.hard_load {
+            Throttle::Reject { retry_after: max_delay }
+        } else {
+            Throttle::Delay(max_delay.mul_f64((load - self.soft_load) / (self.hard_load - self.soft_load)))
+        }
+    }
+}
+
+/// One constraint a request param broke, with the value it had
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub constraint: String,
+    /// The offending value; the byte length for oversized strings
+    pub value: serde_json::Value,
+}
+
+impl FieldError {
+    pub(crate) fn new(field: &str, constraint: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        Self { field: field.to_string(), constraint: constraint.into(), value: value.into() }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} must be {} (got {})", self.field, self.constraint, self.value)
+    }
+}
+
+impl ParamLimits {
+    /// Every violated constraint, not just the first. The prompt, counted by
+    /// `tokenizer`, must leave room for `max_tokens` in the context window.
+    pub fn check(&self, params: &MCPParams, tokenizer: &dyn Tokenizer) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if !(0.0..=2.0).contains(&params.temperature) {
+            let value = serde_json::Number::from_f64(params.temperature).map_or(serde_json::Value::Null, serde_json::Value::Number);
+            errors.push(FieldError::new("temperature", "between 0 and 2", value));
+        }
+        if params.max_tokens == 0 || params.max_tokens > self.max_tokens {
+            errors.push(FieldError::new("max_tokens", format!("between 1 and {}", self.max_tokens), params.max_tokens));
+        }
+        if params.agent_id.trim().is_empty() {
+            errors.push(FieldError::new("agent_id", "non-empty", params.agent_id.as_str()));
+        }
+        if params.timeout_ms == Some(0) {
+            errors.push(FieldError::new("timeout_ms", "positive", 0));
+        }
+        if params.prompt.trim().is_empty() {
+            errors.push(FieldError::new("prompt", "non-empty", params.prompt.as_str()));
+        } else if params.prompt.len() > self.max_prompt_bytes {
+            errors.push(FieldError::new("prompt", format!("at most {} bytes", self.max_prompt_bytes), params.prompt.len()));
+        }
+        if params.context_window < params.max_tokens || params.context_window > self.max_context_window {
+            let constraint = format!("between max_tokens ({}) and {}", params.max_tokens, self.max_context_window);
+            errors.push(FieldError::new("context_window", constraint, params.context_window));
+        } else if errors.is_empty() {
+            let room = params.context_window - params.max_tokens;
+            let prompt_tokens = tokenizer.count(&params.prompt);
+            if prompt_tokens > room as usize {
+                let constraint = format!("at most {} tokens, leaving max_tokens of the context_window", room);
+                errors.push(FieldError::new("prompt", constraint, prompt_tokens));
+            }
+        }
+        if let Some(error) = params.session_id.as_deref().and_then(|id| check_id("session_id", id)) {
+            errors.push(error);
+        }
+        if params.rag_top_k == Some(0) {
+            errors.push(FieldError::new("rag_top_k", "positive", 0));
+        }
+        if let Some(min_score) = params.rag_min_score.filter(|score| !score.is_finite() || *score < 0.0) {
+            let value = serde_json::Number::from_f64(min_score).map_or(serde_json::Value::Null, serde_json::Value::Number);
+            errors.push(FieldError::new("rag_min_score", "a non-negative number", value));
+        }
+        if params.rag_filters.len() > self.max_rag_filters {
+            errors.push(FieldError::new("rag_filters", format!("at most {} filters", self.max_rag_filters), params.rag_filters.len()));
+        }
+        if let Some((key, pattern)) = params.rag_filters.iter().find(|(key, pattern)| key.trim().is_empty() || pattern.trim().is_empty()) {
+            errors.push(FieldError::new(&format!("rag_filters.{}", key), "a non-empty key and pattern", pattern.as_str()));
+        }
+        if let Some(collection) = params.rag_collection.as_deref().filter(|collection| collection.trim().is_empty()) {
+            errors.push(FieldError::new("rag_collection", "non-empty when given", collection));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl ParamLimits {
+    /// Every text over `max_embed_text_bytes`, as well as too many or no texts
+    pub fn check_embed(&self, request: &EmbedRequest) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if request.texts.is_empty() || request.texts.len() > self.max_embed_texts {
+            errors.push(FieldError::new("texts", format!("between 1 and {} texts", self.max_embed_texts), request.texts.len()));
+        }
+        for (i, text) in request.texts.iter().enumerate() {
+            if text.len() > self.max_embed_text_bytes {
+                errors.push(FieldError::new(&format!("texts[{}]", i), format!("at most {} bytes", self.max_embed_text_bytes), text.len()));
+            }
+        }
+        if let Some(error) = request.agent_id.as_deref().and_then(|id| check_id("agent_id", id)) {
+            errors.push(error);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Too many or no prompts, and every one over `max_prompt_bytes`
+    pub fn check_preview(&self, request: &MoralPreviewRequest) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if request.prompts.is_empty() || request.prompts.len() > self.max_preview_prompts {
+            errors.push(FieldError::new("prompts", format!("between 1 and {} prompts", self.max_preview_prompts), request.prompts.len()));
+        }
+        for (i, prompt) in request.prompts.iter().enumerate() {
+            if prompt.len() > self.max_prompt_bytes {
+                errors.push(FieldError::new(&format!("prompts[{}]", i), format!("at most {} bytes", self.max_prompt_bytes), prompt.len()));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The agent's `default` for a param the request left to the server's
+fn fill_param<T>(value: &mut T, source: &mut ParamSource, default: Option<T>) {
+    if let (ParamSource::ServerDefault, Some(default)) = (*source, default) {
+        *value = default;
+        *source = ParamSource::AgentDefault;
+    }
+}
+
+pub(crate) fn default_max_tokens() -> u32 {
+    1024
+}
+
+pub(crate) fn default_temperature() -> f64 {
+    0.7
+}
+
+pub(crate) fn default_use_rag() -> bool {
+    true
+}
+
+pub(crate) fn default_context_window() -> u32 {
+    4096
+}
+
+fn default_flat_rag_context() -> bool {
+    true
+}
+
+fn default_dedupe_chunks() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MCPResponse {
+    pub result: MCPResult,
+    pub metadata: MCPMetadata,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MCPResult {
+    pub response: String,
+    pub metrics: ResponseMetrics,
+    pub rag_context: Option<Vec<String>>,
+    pub citations: Option<Vec<Citation>>,
+    /// What moral recentering did to the prompt, when it ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moral_recentering: Option<MoralRecenteringReport>,
+    /// What went to the backend, for the audit log; never sent to clients
+    #[serde(skip)]
+    pub prompt: Option<Prompt>,
+    /// What a `dry_run` would have sent the backend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_preview: Option<PromptPreview>,
+}
+
+/// The prompt a `dry_run` assembled, and what went into it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptPreview {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub user: String,
+    /// Every chunk retrieved, in rank order, and whether it fit in the context window
+    pub chunks: Vec<PreviewChunk>,
+    pub tokens: PromptTokens,
+    /// The params agent defaults may fill, as the request was handled
+    pub params: BTreeMap<String, EffectiveParam>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectiveParam {
+    /// Null for a model or template left to the backend or the specialty
+    pub value: serde_json::Value,
+    pub source: ParamSource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewChunk {
+    #[serde(flatten)]
+    pub citation: Citation,
+    pub included: bool,
+}
+
+/// Tokens per section of a previewed prompt, by the server's tokenizer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTokens {
+    /// The prompt after moral recentering
+    pub user_prompt: u32,
+    /// The specialty's framing
+    pub framing: u32,
+    /// Knowledge base context
+    pub knowledge: u32,
+    /// Session history
+    pub history: u32,
+    /// The whole prompt, the template's own text included
+    pub total: u32,
+    pub max_tokens: u32,
+    pub context_window: u32,
+    /// Left in the context window beside the prompt and `max_tokens`
+    pub remaining: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoralRecenteringReport {
+    pub ethical_framework: String,
+    /// Whether the prompt changed; an unknown framework without void shrine
+    /// context leaves it as it was
+    pub recentered: bool,
+    pub ethical_adjustments: Vec<String>,
+    pub care_ethics_score: f64,
+    pub score_breakdown: ScoreBreakdown,
+    /// With `dry_run`, each rule applied with the configured text it inserted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<FiredRule>,
+}
+
+/// A retrieved chunk the response can refer to as `[index]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub index: usize,
+    pub document_id: String,
+    pub title: String,
+    pub chunk_id: String,
+    pub score: f64,
+    pub snippet: String,
+    pub metadata: HashMap<String, String>,
+}
+
+const CITATION_SNIPPET_CHARS: usize = 200;
+
+/// How retrieved knowledge is written into the inference prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextMode {
+    FullChunks,
+    /// Stored document summaries, used when full chunks would not fit in `context_window`
+    Summaries,
+}
+
+/// What an inference does when searching the knowledge base fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrievalFailurePolicy {
+    /// Refuse the request with 503 `rag_unavailable`
+    Fail,
+    /// Answer without context, reporting the failure as `rag_error`
+    #[default]
+    Degrade,
+}
+
+/// Knowledge base context for one inference
+struct InferenceContext {
+    /// The knowledge base context that fit, blank lines between blocks
+    knowledge: String,
+    rag_context: Option<Vec<String>>,
+    citations: Option<Vec<Citation>>,
+    chunks_included: u32,
+    chunks_dropped: u32,
+    /// Every result, the left-out ones too; only gathered for a `dry_run`
+    considered: Vec<PreviewChunk>,
+    /// For a request in a session with history retrieval on
+    retrieval_query: Option<RetrievalQuery>,
+    /// When the knowledge base was searched
+    retrieval: Option<RetrievalSettings>,
+    /// Why searching failed, when the request went on without context
+    rag_error: Option<String>,
+    /// Retrieved chunks and summaries context screening matched
+    context_flags: Vec<ContextFlag>,
+}
+
+/// The prompt for the backend, and the parts that went into it
+struct AssembledPrompt {
+    prompt: Prompt,
+    context: InferenceContext,
+    framing: String,
+    history: String,
+}
+
+/// Sentences returned by the `rag_answer` method
+const RAG_ANSWER_SENTENCES: usize = 3;
+
+impl Citation {
+    fn from_search_result(index: usize, result: &SearchResult) -> Self {
+        Self {
+            index,
+            document_id: result.document_id.clone(),
+            title: result.title.clone(),
+            chunk_id: result.chunk_id.clone(),
+            score: result.similarity_score,
+            snippet: truncate_at_sentence(&result.content, CITATION_SNIPPET_CHARS, ELLIPSIS).into_owned(),
+            metadata: result.metadata.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseMetrics {
+    pub response_time_ms: u64,
+    /// `prompt_tokens` plus `completion_tokens`
+    pub token_count: u32,
+    /// The prompt as sent to the backend, knowledge base context and session
+    /// history included; the backend's own count when it reports one
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    pub rag_documents_used: u32,
+    /// Between 0 and 1; see `confidence` for how it is derived
+    pub confidence_score: f64,
+    /// What went into `confidence_score`, when the request set `verbose_confidence`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence_breakdown: Option<Box<ConfidenceBreakdown>>,
+    /// Time load-based throttling held the request before it was handled
+    #[serde(default)]
+    pub throttle_delay_ms: u64,
+    /// Retrieved chunks that fit in the context window, and those left out
+    #[serde(default)]
+    pub rag_chunks_included: u32,
+    #[serde(default)]
+    pub rag_chunks_dropped: u32,
+    /// Backend calls made, retries of transient failures included; 0 when the
+    /// answer needed none
+    #[serde(default)]
+    pub attempts: u32,
+    /// Time spent waiting between those calls
+    #[serde(default)]
+    pub retry_delay_ms: u64,
+    /// The retrieval settings used, limits applied, when the knowledge base was searched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieval: Option<RetrievalSettings>,
+    /// The temperature and max_tokens asked for and those generated with, when a backend answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingSettings>,
+}
+
+/// A request's temperature and max_tokens, and what the answering backend
+/// clamped them to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SamplingSettings {
+    pub requested: Sampling,
+    pub effective: Sampling,
+}
+
+/// What a request's retrieval ran with, after defaults and limits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalSettings {
+    /// Lower than `rag_top_k` when the server's maximum applied
+    pub top_k: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f64>,
+    pub mode: RetrievalMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+}
+
+/// Chaos a request drew while its type was in shadow mode: sampled and
+/// counted as usual, but neither delayed nor failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShadowChaos {
+    pub chaos_type: String,
+    /// The delay it would have held the request up for
+    pub delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MCPMetadata {
+    pub request_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub void_shrine_token: String,
+    pub chaos_applied: bool,
+    /// The fault applied, when `chaos_applied`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chaos_type: Option<String>,
+    /// Number of the chaos decision made for this request; with `chaos_seed`
+    /// it replays through `ChaosDice::rng_for`
+    #[serde(default)]
+    pub chaos_decision: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chaos_seed: Option<u64>,
+    /// What chaos would have done, had its type not been in shadow mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub would_have_applied: Option<ShadowChaos>,
+    pub moral_recentered: bool,
+    /// The request wanted knowledge base context but no engine was initialized
+    #[serde(default)]
+    pub rag_unavailable: bool,
+    /// Searching the knowledge base failed, and the request was answered without context
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_error: Option<String>,
+    /// This request's turn in its session, counting from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_turn: Option<u32>,
+    /// The result was served from the response cache, not the backend
+    #[serde(default)]
+    pub cached: bool,
+    /// The model that answered, when the request named a fallback chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_model: Option<String>,
+    /// Models of the chain that failed before `served_model`
+    #[serde(default)]
+    pub fallback_depth: u32,
+    /// The response was redacted or replaced by a refusal; see `content_filter`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_filter: Option<ContentFilterReport>,
+    /// What retrieval searched, when it drew on the session's history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieval_query: Option<RetrievalQuery>,
+    /// The response kept for an earlier request with the same idempotency
+    /// key, returned again without running this one
+    #[serde(default)]
+    pub idempotent_replay: bool,
+    /// Knowledge base context that screening dropped, neutralized or flagged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context_flags: Vec<ContextFlag>,
+}
+
+/// One server-sent event of a streamed inference, named after its variant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum InferenceEvent {
+    ChaosApplied {
+        applied: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        chaos_type: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        would_have_applied: Option<ShadowChaos>,
+    },
+    RagContext { citations: Vec<Citation>, rag_context: Option<Vec<String>> },
+    MoralRecentering {
+        specialty: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        report: Option<MoralRecenteringReport>,
+    },
+    /// The next piece of response text
+    Delta { text: String },
+    /// The whole response, as the non-streaming method would have returned it
+    Done { response: String, metrics: Box<ResponseMetrics>, metadata: MCPMetadata },
+    /// The failure's `MCPError` code and message
+    Error {
+        #[serde(default)]
+        code: String,
+        message: String,
+    },
+}
+
+impl InferenceEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InferenceEvent::ChaosApplied { .. } => "chaos_applied",
+            InferenceEvent::RagContext { .. } => "rag_context",
+            InferenceEvent::MoralRecentering { .. } => "moral_recentering",
+            InferenceEvent::Delta { .. } => "delta",
+            InferenceEvent::Done { .. } => "done",
+            InferenceEvent::Error { .. } => "error",
+        }
+    }
+}
+
+/// Events of one streamed inference; see `VoidShrineMCP::stream_llm_inference`
+pub struct InferenceStream {
+    /// Also in the `Done` event's metadata
+    pub request_id: String,
+    events: tokio::sync::mpsc::Receiver<InferenceEvent>,
+    task: tokio::task::JoinHandle<()>,
+    agent_metrics: Arc<DashMap<String, AgentMetrics>>,
+    agent_id: String,
+    finished: bool,
+}
+
+impl futures::Stream for InferenceStream {
+    type Item = InferenceEvent;
+
+    fn poll_next(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
+        let poll = self.events.poll_recv(cx);
+        if let std::task::Poll::Ready(event) = &poll {
+            if matches!(event, None | Some(InferenceEvent::Done { .. } | InferenceEvent::Error { .. })) {
+                self.finished = true;
+            }
+        }
+        poll
+    }
+}
+
+impl Drop for InferenceStream {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.task.abort();
+            if let Some(mut metrics) = self.agent_metrics.get_mut(&self.agent_id) {
+                metrics.cancelled_requests += 1;
+            }
+            tracing::info!("Streamed inference for {} cancelled by the client", self.agent_id);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelsResponse {
+    /// The configured routes, then the models backends reported serving
+    pub models: Vec<ModelListing>,
+    /// Serves models no route matches; None when those are rejected
+    pub default_backend: Option<String>,
+}
+
+/// A model and how the backend serving it is doing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelListing {
+    #[serde(flatten)]
+    pub model: RoutableModel,
+    /// Reported by the backend rather than configured as a route
+    pub discovered: bool,
+    /// Whether the backend's circuit breaker is closed
+    pub healthy: bool,
+    pub breaker: BreakerState,
+    /// When the backend last answered a call successfully
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChaosRequest {
+    pub agent_id: String,
+    /// Lets per-specialty intensities apply
+    #[serde(default)]
+    pub specialty: Option<String>,
+    pub chaos_type: String,
+    pub intensity: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChaosResponse {
+    pub apply_chaos: bool,
+    pub effect: String,
+    pub delay_ms: u64,
+    /// Number of the chaos decision behind this response
+    #[serde(default)]
+    pub decision: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// The chaos advised, had its type not been in shadow mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub would_have_applied: Option<ShadowChaos>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoralRequest {
+    pub original_prompt: String,
+    pub specialty: String,
+    pub void_shrine_context: bool,
+    /// One of `EthicalFrameworks::names`
+    pub ethical_framework: String,
+    /// Refuse an unknown framework instead of noting it; the configured
+    /// `moral.strict_frameworks` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoralResponse {
+    pub recentered_prompt: String,
+    pub ethical_adjustments: Vec<String>,
+    pub care_ethics_score: f64,
+    pub score_breakdown: ScoreBreakdown,
+    /// The specialty's framing, which `llm_inference` gives as the system prompt
+    #[serde(default)]
+    pub framing: String,
+    /// Each rule applied, with the configured text it inserted and its source
+    #[serde(default)]
+    pub rules: Vec<FiredRule>,
+}
+
+/// Body of `POST /api/moral-recentering/preview`: prompts to recenter on
+/// one framework, as inference would
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoralPreviewRequest {
+    pub prompts: Vec<String>,
+    pub ethical_framework: String,
+    /// Whose prefix to apply, if one is configured; none without
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub specialty: Option<String>,
+    #[serde(default)]
+    pub void_shrine_context: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoralPreviewResponse {
+    /// One per prompt, in order
+    pub results: Vec<MoralPreview>,
+    pub summary: RecenteringSummary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoralPreview {
+    pub original_prompt: String,
+    pub recentered_prompt: String,
+    pub ethical_adjustments: Vec<String>,
+    pub diff: RecenteringDiff,
+}
+
+/// What the agent's next request would meet, from the same counters and
+/// buckets `admit` enforces with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThrottleStatus {
+    pub should_throttle: bool,
+    pub delay_ms: u64,
+    pub reason: String,
+    /// The check `reason` comes from
+    #[serde(default)]
+    pub policy: ThrottlePolicy,
+    pub agent_load: f64,
+    /// Requests the agent may make right now before being rate limited
+    pub remaining_tokens: u32,
+    pub bucket_capacity: u32,
+    #[serde(default)]
+    pub refill_per_sec: f64,
+    /// Until the bucket holds a whole token again; 0 while it does
+    #[serde(default)]
+    pub next_token_in_ms: u64,
+    /// The agent's requests being handled now, and the most it registered for
+    #[serde(default)]
+    pub in_flight: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<u32>,
+    /// The agent's admitted requests waiting out a throttle delay or for a slot
+    #[serde(default)]
+    pub queued: u32,
+    /// Requests from every agent waiting for one of the server's slots
+    #[serde(default)]
+    pub server_queue_depth: u32,
+    /// How long a request sent now would likely wait before it is handled:
+    /// `delay_ms`, then its turn behind the queued requests at the agent's
+    /// average response time
+    #[serde(default)]
+    pub estimated_wait_ms: u64,
+}
+
+/// When an agent refused for having its `max_concurrency` in flight may try again
+const CONCURRENCY_RETRY_AFTER: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The check behind a throttle recommendation, in the order `admit` makes them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThrottlePolicy {
+    /// Nothing holds the agent back
+    #[default]
+    None,
+    /// It has its registered `max_concurrency` in flight
+    Concurrency,
+    /// Its load is at `ThrottleConfig::hard_load`
+    HardLoad,
+    /// Its rate limit bucket is empty
+    RateLimit,
+    /// Its load is over `ThrottleConfig::soft_load`
+    SoftLoad,
+}
+
+impl ThrottlePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThrottlePolicy::None => "none",
+            ThrottlePolicy::Concurrency => "concurrency",
+            ThrottlePolicy::HardLoad => "hard_load",
+            ThrottlePolicy::RateLimit => "rate_limit",
+            ThrottlePolicy::SoftLoad => "soft_load",
+        }
+    }
+}
+
+/// `GET /api/throttle/{agent_id}?verbose=false`: only whether to back off
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ThrottleCheck {
+    pub should_throttle: bool,
+}
+
+/// The query of `GET /api/throttle/{agent_id}`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThrottleQuery {
+    /// False for just `should_throttle`
+    pub verbose: bool,
+}
+
+impl Default for ThrottleQuery {
+    fn default() -> Self {
+        Self { verbose: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScalingRequest {
+    pub agent_id: String,
+    pub response_time: Option<u64>,
+    pub token_count: Option<u32>,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScalingResponse {
+    pub adjustments: ScalingAdjustments,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScalingAdjustments {
+    pub description: String,
+    pub capacity_change: f64,
+    pub priority_adjustment: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexUrlRequest {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexUrlResponse {
+    pub document_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexDocumentRequest {
+    /// Generated when absent
+    #[serde(default)]
+    pub id: Option<String>,
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+/// Error body with a machine-readable `error` code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub message: String,
+    /// Set when the failed request had been assigned an id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Each invalid param, for `invalid_params` errors from validation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    /// Set for rate-limited requests and overloaded backends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+    /// Set for `deadline_exceeded`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<TimeoutStage>,
+    /// Set for `payload_too_large`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_bytes: Option<u64>,
+    /// The method asked for, for `unsupported_method`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Every method there is, for `unsupported_method`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub supported_methods: Vec<String>,
+    /// The supported method closest to an unsupported one, if any is close
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub did_you_mean: Option<String>,
+    /// The quota, what its window has used and when it resets, for `quota_exceeded`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<QuotaStanding>,
+    /// The status the backend answered with, for `backend_error` and `backend_rate_limited`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_status: Option<u16>,
+}
+
+/// The body every transport reports `error` with: HTTP and gRPC responses,
+/// WebSocket replies and MCP tool results
+impl From<&MCPError> for ErrorResponse {
+    fn from(error: &MCPError) -> Self {
+        ErrorResponse {
+            error: error.code().to_string(),
+            message: error.to_string(),
+            request_id: None,
+            fields: error.fields().to_vec(),
+            retry_after_ms: error.retry_after().map(|wait| wait.as_millis() as u64),
+            stage: error.timeout_stage(),
+            limit_bytes: error.limit_bytes(),
+            method: error.unsupported_method().map(str::to_string),
+            supported_methods: match error.unsupported_method() {
+                Some(_) => McpMethod::names().into_iter().map(str::to_string).collect(),
+                None => Vec::new(),
+            },
+            did_you_mean: error.unsupported_method().and_then(McpMethod::suggest).map(|method| method.as_str().to_string()),
+            quota: error.quota().cloned(),
+            upstream_status: error.upstream_status(),
+        }
+    }
+}
+
+impl From<&FailedRequest> for ErrorResponse {
+    fn from(failure: &FailedRequest) -> Self {
+        ErrorResponse { request_id: failure.request_id.clone(), ..ErrorResponse::from(&failure.error) }
+    }
+}
+
+/// Why a request failed, as reported to clients: each variant has a stable
+/// `code`, an HTTP status and the details `ErrorResponse` carries. Handlers
+/// return it; anyhow errors from the engine and backends are converted by
+/// downcasting to it, a `BackendError` or a `ValidationError`, and are
+/// internal errors otherwise.
+#[derive(Debug)]
+pub enum MCPError {
+    UnsupportedMethod(String),
+    InvalidParams(String),
+    /// Params outside `ParamLimits`
+    InvalidFields(Vec<FieldError>),
+    /// A document refused by the knowledge base
+    Validation(ValidationError),
+    RagUnavailable,
+    /// Searching the knowledge base failed, under `RetrievalFailurePolicy::Fail`
+    RetrievalFailed(String),
+    /// A keyword search while the full-text index is rebuilt, under `FtsRebuildPolicy::Refuse`
+    IndexRebuilding(FtsRebuilding),
+    /// The knowledge base has no embedding provider to serve `POST /api/embed`
+    EmbeddingsUnavailable,
+    DocumentNotFound(String),
+    SessionNotFound(String),
+    JobNotFound(String),
+    /// No knowledge base task with this id, or none finished lately
+    TaskNotFound(String),
+    /// No request with this id is running or finished recently
+    RequestNotFound(String),
+    AgentNotFound(String),
+    /// Registering an agent id that is registered already
+    AgentExists(String),
+    /// In strict mode, a registered agent claiming another specialty
+    SpecialtyMismatch { agent_id: String, registered: String, claimed: String },
+    /// Too late to cancel: the request already finished
+    RequestFinished(String),
+    /// An idempotency key reused for a different request
+    IdempotencyConflict(String),
+    /// A repeat of an idempotency key whose first request is still running
+    IdempotencyInProgress(String),
+    /// Cancelled by its client before it finished
+    Cancelled,
+    /// Out of time in `stage`, which had `budget` to run
+    DeadlineExceeded { stage: TimeoutStage, budget: std::time::Duration },
+    /// The job queue holds `JobsConfig::queue_size` jobs already
+    QueueFull,
+    /// The backend's circuit breaker is open and it has no fallback to call
+    CircuitOpen { backend: String, retry_after: std::time::Duration },
+    /// The agent used up its token bucket
+    RateLimited { agent_id: String, retry_after: std::time::Duration },
+    /// The agent's load is over `ThrottleConfig::hard_load`
+    Throttled { agent_id: String, retry_after: std::time::Duration },
+    /// The request would take a quota over its cap; `retry_after` is the
+    /// time left until the quota's window resets
+    QuotaExceeded { standing: Box<QuotaStanding>, retry_after: std::time::Duration },
+    /// No bearer token, or one matching no key
+    Unauthorized(&'static str),
+    /// A known key without the scope the endpoint needs
+    Forbidden { key_id: String, scope: Scope },
+    /// The server was started without what the request needs
+    NotConfigured(&'static str),
+    /// A request body over its route's `BodyLimits`
+    PayloadTooLarge { limit_bytes: u64 },
+    /// Refused or cut off while the server drains before exiting
+    ShuttingDown,
+    /// No concurrency slot freed up in time
+    Overloaded { retry_after: std::time::Duration },
+    /// The `error_injection` chaos fault, posing as a real failure
+    ChaosInjected { class: InjectedErrorClass, status: u16 },
+    /// The `request_drop` chaos fault: the request was never handled
+    RequestDropped,
+    Backend(BackendError),
+    Internal(anyhow::Error),
+}
+
+impl MCPError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            MCPError::UnsupportedMethod(_) => "unsupported_method",
+            MCPError::InvalidParams(_) | MCPError::InvalidFields(_) => "invalid_params",
+            MCPError::Validation(e) => e.code(),
+            MCPError::RagUnavailable | MCPError::RetrievalFailed(_) => "rag_unavailable",
+            MCPError::IndexRebuilding(_) => "index_rebuilding",
+            MCPError::EmbeddingsUnavailable => "embeddings_unavailable",
+            MCPError::DocumentNotFound(_) => "document_not_found",
+            MCPError::SessionNotFound(_) => "session_not_found",
+            MCPError::JobNotFound(_) => "job_not_found",
+            MCPError::TaskNotFound(_) => "task_not_found",
+            MCPError::RequestNotFound(_) => "request_not_found",
+            MCPError::AgentNotFound(_) => "agent_not_found",
+            MCPError::AgentExists(_) => "agent_exists",
+            MCPError::SpecialtyMismatch { .. } => "specialty_mismatch",
+            MCPError::RequestFinished(_) => "request_finished",
+            MCPError::IdempotencyConflict(_) => "idempotency_conflict",
+            MCPError::IdempotencyInProgress(_) => "idempotency_in_progress",
+            MCPError::Cancelled => "cancelled",
+            MCPError::DeadlineExceeded { .. } => "deadline_exceeded",
+            MCPError::QueueFull => "queue_full",
+            MCPError::CircuitOpen { .. } => "backend_circuit_open",
+            MCPError::RateLimited { .. } => "rate_limited",
+            MCPError::Throttled { .. } => "throttled",
+            MCPError::QuotaExceeded { .. } => "quota_exceeded",
+            MCPError::Unauthorized(_) => "unauthorized",
+            MCPError::Forbidden { .. } => "forbidden",
+            MCPError::Overloaded { .. } => "overloaded",
+            MCPError::PayloadTooLarge { .. } => "payload_too_large",
+            MCPError::NotConfigured(_) => "not_configured",
+            MCPError::ShuttingDown => "shutting_down",
+            MCPError::ChaosInjected { class, .. } => class.code(),
+            MCPError::RequestDropped => "request_dropped",
+            MCPError::Backend(e) => e.code(),
+            MCPError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// 400 for client mistakes, 5xx for the server and its backends
+    pub fn http_status(&self) -> u16 {
+        match self {
+            MCPError::UnsupportedMethod(_)
+            | MCPError::InvalidParams(_)
+            | MCPError::InvalidFields(_)
+            | MCPError::Validation(_) => 400,
+            MCPError::RagUnavailable
+            | MCPError::RetrievalFailed(_)
+            | MCPError::IndexRebuilding(_)
+            | MCPError::EmbeddingsUnavailable
+            | MCPError::ShuttingDown
+            | MCPError::CircuitOpen { .. }
+            | MCPError::Overloaded { .. } => 503,
+            MCPError::DocumentNotFound(_)
+            | MCPError::SessionNotFound(_)
+            | MCPError::JobNotFound(_)
+            | MCPError::TaskNotFound(_)
+            | MCPError::RequestNotFound(_)
+            | MCPError::AgentNotFound(_) => 404,
+            MCPError::RequestFinished(_)
+            | MCPError::AgentExists(_)
+            | MCPError::IdempotencyConflict(_)
+            | MCPError::IdempotencyInProgress(_) => 409,
+            // As nginx logs a client that went away first
+            MCPError::Cancelled => 499,
+            MCPError::DeadlineExceeded { .. } => 504,
+            MCPError::RateLimited { .. } | MCPError::Throttled { .. } | MCPError::QuotaExceeded { .. } | MCPError::QueueFull => 429,
+            MCPError::Unauthorized(_) => 401,
+            MCPError::Forbidden { .. } | MCPError::SpecialtyMismatch { .. } => 403,
+            MCPError::NotConfigured(_) => 501,
+            MCPError::PayloadTooLarge { .. } => 413,
+            MCPError::ChaosInjected { status, .. } => *status,
+            MCPError::RequestDropped => 504,
+            MCPError::Backend(e) => e.http_status(),
+            MCPError::Internal(_) => 500,
+        }
+    }
+
+    /// Whether the failure counts against the agent's success rate: server and
+    /// backend failures do, refusals of the request itself (4xx), shutdown
+    /// and load shedding do not
+    pub fn counts_as_failure(&self) -> bool {
+        self.http_status() >= 500 && !matches!(self, MCPError::ShuttingDown | MCPError::Overloaded { .. })
+    }
+
+    /// The stage that ran out of time, for `deadline_exceeded`
+    pub fn timeout_stage(&self) -> Option<TimeoutStage> {
+        match self {
+            MCPError::DeadlineExceeded { stage, .. } => Some(*stage),
+            _ => None,
+        }
+    }
+
+    /// The route's limit, for `payload_too_large`
+    pub fn limit_bytes(&self) -> Option<u64> {
+        match self {
+            MCPError::PayloadTooLarge { limit_bytes } => Some(*limit_bytes),
+            _ => None,
+        }
+    }
+
+    /// The method asked for, for `unsupported_method`
+    pub fn unsupported_method(&self) -> Option<&str> {
+        match self {
+            MCPError::UnsupportedMethod(method) => Some(method),
+            _ => None,
+        }
+    }
+
+    /// The quota a request would have exceeded, for `quota_exceeded`
+    pub fn quota(&self) -> Option<&QuotaStanding> {
+        match self {
+            MCPError::QuotaExceeded { standing, .. } => Some(standing),
+            _ => None,
+        }
+    }
+
+    /// What the backend answered with, for `backend_error` and `backend_rate_limited`
+    pub fn upstream_status(&self) -> Option<u16> {
+        match self {
+            MCPError::Backend(e) => e.upstream_status(),
+            _ => None,
+        }
+    }
+
+    /// The field-level errors of a validation failure; empty otherwise
+    pub fn fields(&self) -> &[FieldError] {
+        match self {
+            MCPError::InvalidFields(fields) => fields,
+            _ => &[],
+        }
+    }
+
+    /// When a rate-limited agent or an overloaded backend can try again
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            MCPError::RateLimited { retry_after, .. }
+            | MCPError::Throttled { retry_after, .. }
+            | MCPError::QuotaExceeded { retry_after, .. }
+            | MCPError::CircuitOpen { retry_after, .. }
+            | MCPError::Overloaded { retry_after } => Some(*retry_after),
+            MCPError::Backend(e) => e.retry_after(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MCPError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MCPError::UnsupportedMethod(method) => {
+                write!(f, "Unsupported method: {}", method)?;
+                match McpMethod::suggest(method) {
+                    Some(suggestion) => write!(f, "; did you mean {}?", suggestion.as_str()),
+                    None => write!(f, "; supported methods are {}", McpMethod::names().join(", ")),
+                }
+            }
+            MCPError::InvalidParams(reason) => write!(f, "Invalid params: {}", reason),
+            MCPError::InvalidFields(fields) => {
+                let fields: Vec<String> = fields.iter().map(ToString::to_string).collect();
+                write!(f, "Invalid params: {}", fields.join("; "))
+            }
+            MCPError::Validation(e) => e.fmt(f),
+            MCPError::RagUnavailable => write!(f, "RAG engine not initialized"),
+            MCPError::RetrievalFailed(reason) => write!(f, "Knowledge base retrieval failed: {}", reason),
+            MCPError::IndexRebuilding(e) => e.fmt(f),
+            MCPError::EmbeddingsUnavailable => write!(f, "No embedding provider configured"),
+            MCPError::DocumentNotFound(id) => write!(f, "No document '{}' in the knowledge base", id),
+            MCPError::SessionNotFound(id) => write!(f, "No open session '{}'", id),
+            MCPError::JobNotFound(id) => write!(f, "No job '{}'", id),
+            MCPError::TaskNotFound(id) => write!(f, "No knowledge base task '{}'", id),
+            MCPError::RequestNotFound(id) => write!(f, "No request '{}' is running", id),
+            MCPError::AgentNotFound(id) => write!(f, "No agent '{}' is registered", id),
+            MCPError::AgentExists(id) => write!(f, "Agent '{}' is registered already", id),
+            MCPError::SpecialtyMismatch { agent_id, registered, claimed } => {
+                write!(f, "Agent '{}' is registered as {}, not {}", agent_id, registered, claimed)
+            }
+            MCPError::RequestFinished(id) => write!(f, "Request '{}' already finished", id),
+            MCPError::IdempotencyConflict(key) => write!(f, "Idempotency key '{}' was used for a different request", key),
+            MCPError::IdempotencyInProgress(key) => {
+                write!(f, "The request with idempotency key '{}' is still running; retry once it finishes", key)
+            }
+            MCPError::Cancelled => write!(f, "Request cancelled by the client"),
+            MCPError::DeadlineExceeded { stage, budget } => {
+                write!(f, "Deadline exceeded in {} after {} ms", stage.as_str(), budget.as_millis())
+            }
+            MCPError::QueueFull => write!(f, "The job queue is full; retry later"),
+            MCPError::CircuitOpen { backend, retry_after } => {
+                write!(f, "Backend '{}' is failing and cut off; retry in {} ms", backend, retry_after.as_millis())
+            }
+            MCPError::RateLimited { agent_id, retry_after } => {
+                write!(f, "Agent '{}' is over its rate limit; retry in {} ms", agent_id, retry_after.as_millis())
+            }
+            MCPError::Throttled { agent_id, retry_after } => {
+                write!(f, "Agent '{}' is under too much load; retry in {} ms", agent_id, retry_after.as_millis())
+            }
+            MCPError::QuotaExceeded { standing, .. } => {
+                let rule = &standing.rule;
+                write!(
+                    f,
+                    "Quota '{}' allows {} {} per {} and {} are used; it resets at {}",
+                    rule.id,
+                    rule.limit,
+                    rule.metric.as_str(),
+                    rule.window.as_str(),
+                    standing.consumed,
+                    standing.resets_at.to_rfc3339()
+                )
+            }
+            MCPError::Unauthorized(reason) => write!(f, "Unauthorized: {}", reason),
+            MCPError::Forbidden { key_id, scope } => write!(f, "API key '{}' lacks the {} scope", key_id, scope),
+            MCPError::NotConfigured(what) => write!(f, "No {} configured", what),
+            MCPError::PayloadTooLarge { limit_bytes } => write!(f, "Request body is over this route's limit of {} bytes", limit_bytes),
+            MCPError::ShuttingDown => write!(f, "Server is shutting down"),
+            MCPError::Overloaded { retry_after } => {
+                write!(f, "Server is handling all the requests it can; retry in {} ms", retry_after.as_millis())
+            }
+            MCPError::ChaosInjected { class, .. } => write!(f, "Chaos injected a {} failure", class.code()),
+            MCPError::RequestDropped => write!(f, "Request dropped by chaos before it was handled"),
+            MCPError::Backend(e) => e.fmt(f),
+            MCPError::Internal(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MCPError {}
+
+impl From<BackendError> for MCPError {
+    fn from(error: BackendError) -> Self {
+        MCPError::Backend(error)
+    }
+}
+
+impl From<anyhow::Error> for MCPError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<MCPError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<BackendError>() {
+            Ok(error) => return MCPError::Backend(error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<FtsRebuilding>() {
+            Ok(error) => return MCPError::IndexRebuilding(error),
+            Err(error) => error,
+        };
+        match error.downcast::<ValidationError>() {
+            Ok(error) => MCPError::Validation(error),
+            Err(error) => MCPError::Internal(error),
+        }
+    }
+}
+
+/// A failed request and, for MCP requests, the id it was given
+#[derive(Debug)]
+pub struct FailedRequest {
+    pub request_id: Option<String>,
+    pub error: MCPError,
+}
+
+impl<E: Into<MCPError>> From<E> for FailedRequest {
+    fn from(error: E) -> Self {
+        FailedRequest { request_id: None, error: error.into() }
+    }
+}
+
+impl std::fmt::Display for FailedRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for FailedRequest {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteDocumentsResponse {
+    pub deleted: usize,
+}
+
+/// A stored document: its summary plus the indexed text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentResponse {
+    #[serde(flatten)]
+    pub info: DocumentInfo,
+    /// Empty for documents that were streamed in
+    pub content: String,
+}
+
+/// Body of `POST /api/rag/query`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagSearchRequest {
+    pub query: String,
+    #[serde(default = "default_search_limit")]
+    pub limit: usize,
+    /// Metadata key -> value pattern (`*` wildcard) results must match
+    #[serde(default)]
+    pub filters: HashMap<String, String>,
+    /// Tags results' documents must all carry
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+fn default_search_limit() -> usize {
+    10
+}
+
+/// Most results one search may ask for
+pub const MAX_SEARCH_LIMIT: usize = 100;
+
+/// Results retrieved as context for an inference with `use_rag`
+pub const INFERENCE_CONTEXT_RESULTS: usize = 5;
+
+/// Results a `rag_query` retrieves
+pub const RAG_QUERY_RESULTS: usize = 10;
+
+/// Semantic and hybrid retrieval need the engine to embed the query
+fn check_retrieval_mode(rag_engine: &crate::rag_engine::RAGEngine, mode: RetrievalMode) -> Result<(), MCPError> {
+    if mode != RetrievalMode::Keyword && rag_engine.embedder().is_none() {
+        return Err(MCPError::InvalidFields(vec![FieldError::new("rag_mode", "keyword; no embedding provider is configured", mode.as_str())]));
+    }
+    Ok(())
+}
+
+/// Query string of `GET /api/rag/query`: the retrieval an `llm_inference`
+/// with `use_rag` would make for prompt `q`. Besides the keys below, every
+/// parameter is a metadata filter, e.g. `category=ethics`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RagQuery {
+    pub q: String,
+    /// `INFERENCE_CONTEXT_RESULTS` when absent
+    pub limit: usize,
+    /// Applies the specialty's filters, as its inferences get
+    pub specialty: String,
+    /// Limits results to the agent's tenant, as its inferences are
+    pub agent_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Comma-separated
+    pub doc_ids: Option<Vec<String>>,
+    /// `dedupe_chunks`; true when absent
+    pub dedupe_chunks: bool,
+    /// Comma-separated `tags`
+    pub tags: Vec<String>,
+    /// The knowledge base searched; the default one when absent
+    pub engine: Option<String>,
+    pub filters: HashMap<String, String>,
+}
+
+impl RagQuery {
+    /// Every malformed parameter, not just the first; a missing or blank `q` is one
+    pub fn parse(mut query: HashMap<String, String>) -> Result<RagQuery, MCPError> {
+        let mut errors = Vec::new();
+        let list = |value: Option<String>| -> Vec<String> {
+            value.iter().flat_map(|value| value.split(',')).map(str::trim).filter(|item| !item.is_empty()).map(str::to_string).collect()
+        };
+        let mut instant = |query: &mut HashMap<String, String>, key: &str| {
+            let value = query.remove(key)?;
+            match DateTime::parse_from_rfc3339(&value) {
+                Ok(instant) => Some(instant.with_timezone(&Utc)),
+                Err(_) => {
+                    errors.push(FieldError::new(key, "an RFC 3339 instant", value.as_str()));
+                    None
+                }
+            }
+        };
+        let since = instant(&mut query, "since");
+        let until = instant(&mut query, "until");
+
+        let q = query.remove("q").unwrap_or_default();
+        if q.trim().is_empty() {
+            errors.push(FieldError::new("q", "non-empty", q.as_str()));
+        }
+        let limit = match query.remove("limit") {
+            None => INFERENCE_CONTEXT_RESULTS,
+            Some(value) => match value.parse::<usize>() {
+                Ok(limit) if (1..=MAX_SEARCH_LIMIT).contains(&limit) => limit,
+                _ => {
+                    errors.push(FieldError::new("limit", format!("between 1 and {}", MAX_SEARCH_LIMIT), value.as_str()));
+                    0
+                }
+            },
+        };
+        let dedupe_chunks = match query.remove("dedupe_chunks").as_deref() {
+            None | Some("true") => true,
+            Some("false") => false,
+            Some(value) => {
+                errors.push(FieldError::new("dedupe_chunks", "true or false", value));
+                true
+            }
+        };
+        let agent_id = query.remove("agent_id");
+        if let Some(error) = agent_id.as_deref().and_then(|id| check_id("agent_id", id)) {
+            errors.push(error);
+        }
+        let specialty = query.remove("specialty").unwrap_or_default();
+        let doc_ids = query.remove("doc_ids").map(|ids| list(Some(ids)));
+        let tags = list(query.remove("tags"));
+        let engine = query.remove("engine");
+        if !errors.is_empty() {
+            return Err(MCPError::InvalidFields(errors));
+        }
+        Ok(RagQuery { q, limit, specialty, agent_id, since, until, doc_ids, dedupe_chunks, tags, engine, filters: query })
+    }
+}
+
+/// Document metadata naming the tenant a document belongs to, set when a
+/// tenant's key indexes it
+pub const TENANT_METADATA_KEY: &str = "tenant";
+
+fn document_tenant(info: &DocumentInfo) -> Option<&str> {
+    info.metadata.get(TENANT_METADATA_KEY).map(String::as_str)
+}
+
+/// Another tenant's document is answered as if it didn't exist; one not
+/// indexed yet is anyone's to index
+async fn visible_document(rag_engine: &crate::rag_engine::RAGEngine, tenancy: &Tenancy, document_id: &str) -> Result<(), MCPError> {
+    match rag_engine.document_info(document_id).await? {
+        Some(info) if !tenancy.sees(document_tenant(&info)) => Err(MCPError::DocumentNotFound(document_id.to_string())),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagSearchResponse {
+    pub results: Vec<SearchResult>,
+}
+
+/// Body of `POST /api/embed`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedRequest {
+    pub texts: Vec<String>,
+    /// Must name the configured provider's model when given
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Whose metrics count the usage
+    #[serde(default)]
+    pub agent_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedResponse {
+    pub model: String,
+    pub dimensions: usize,
+    /// One per text, in order
+    pub embeddings: Vec<Vec<f32>>,
+    /// Per text, by the server's tokenizer
+    pub token_counts: Vec<u32>,
+    pub total_tokens: u32,
+}
+
+/// Backup file name, relative to the configured backup directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupRequest {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceRequest {
+    /// Fix orphaned and unindexed rows instead of only reporting them
+    #[serde(default)]
+    pub repair: bool,
+}
+
+/// Query parameters of the retrieval analytics endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyticsParams {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    #[serde(default = "default_analytics_limit")]
+    pub limit: usize,
+}
+
+fn default_analytics_limit() -> usize {
+    10
+}
+
+
+pub struct VoidShrineMCP {
+    pub agent_metrics: Arc<DashMap<String, AgentMetrics>>,
+    /// The default knowledge base, also `rag_engines`' `default`
+    pub rag_engine: SharedEngine,
+    /// Every configured knowledge base by name, each opened into its slot
+    pub rag_engines: Arc<RagEngines>,
+    pub chaos_config: Arc<RwLock<ChaosConfig>>,
+    /// Backups requested over HTTP may only be written inside this directory; None disables them
+    pub backup_dir: Option<PathBuf>,
+    /// Index rebuilds running in the background, and those finished lately
+    pub rag_tasks: Arc<RagTasks>,
+    /// Backends generating `llm_inference` responses, chosen by model; every
+    /// model goes to `MockBackend` unless configured
+    pub backends: SharedBackends,
+    /// Checked against every request's params before it is handled
+    pub param_limits: ParamLimits,
+    /// Model and specialty of requests naming neither
+    pub request_defaults: RequestDefaults,
+    /// Read by the routes when they are built
+    pub body_limits: BodyLimits,
+    /// Per-agent request budget, enforced before a request is handled
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Delays or refuses requests from heavily loaded agents; replaced when
+    /// the config is reloaded
+    pub throttle: Arc<std::sync::RwLock<ThrottleConfig>>,
+    /// Capacity each agent's load is measured against
+    pub load: LoadConfig,
+    /// Size and concurrency of `handle_batch`
+    pub batch: BatchConfig,
+    /// Size and concurrency of `handle_audit_replay`
+    pub replay: ReplayConfig,
+    /// Deadlines for every request
+    pub timeouts: TimeoutConfig,
+    /// Retries of backend calls that failed transiently
+    pub retries: RetryConfig,
+    /// Circuit breakers per backend, and the fallbacks called while one is open
+    pub breakers: Arc<Breakers>,
+    /// Models backends reported serving, and when each last answered
+    pub model_catalog: Arc<ModelCatalog>,
+    /// Slots for requests doing work, shared by every transport and the job queue
+    pub concurrency: Arc<ConcurrencyLimiter>,
+    /// Whether the server as a whole is overloaded, shedding new inference requests
+    pub overload: Arc<OverloadDetector>,
+    /// Thresholds behind `handle_scaling` advice
+    pub scaling: ScalingConfig,
+    /// Every piece of scaling advice given, for its cooldowns and `GET /api/scaling/history`
+    pub scaling_log: Arc<ScalingLog>,
+    pub counters: Arc<ServerCounters>,
+    /// Prometheus series for `GET /metrics`
+    pub metrics: Arc<Metrics>,
+    /// Latency per HTTP route, recorded by `api::observe_routes`
+    pub route_metrics: Arc<RouteMetrics>,
+    /// Draining state; requests are refused once it starts
+    pub shutdown: Arc<Shutdown>,
+    /// Fail requests wanting knowledge base context while the engine is absent,
+    /// rather than answering without it and flagging `rag_unavailable`
+    pub require_rag: bool,
+    /// Whether a failed knowledge base search fails the inference or leaves it without context
+    pub rag_failure_policy: RetrievalFailurePolicy,
+    /// Randomness for every chaos decision, seeded by `ChaosConfig::seed`
+    pub chaos_dice: Arc<ChaosDice>,
+    /// Every chaos decision counted, for `GET /api/chaos/report`
+    pub chaos_stats: Arc<ChaosLedger>,
+    /// Tells an orchestrator about scaling advice and throttling as it happens
+    pub webhooks: Arc<Webhooks>,
+    /// Screens every response before it is sent
+    pub content_filters: Arc<ContentFilters>,
+    /// Screens knowledge base context before it goes into a prompt
+    pub context_screen: Arc<ContextScreen>,
+    /// Weights behind each response's confidence score
+    pub confidence: ConfidenceConfig,
+    /// Frameworks `handle_moral_recentering` knows
+    pub ethics: Arc<EthicalFrameworks>,
+    pub request_ids: Arc<RecentRequestIds>,
+    /// Requests in flight over any transport, for `handle_cancel`
+    pub running: Arc<RunningRequests>,
+    /// Records every request handled; see `audit`
+    pub audit: Option<Arc<AuditLog>>,
+    /// Conversation history of requests naming a `session_id`
+    pub sessions: Arc<SessionStore>,
+    /// Agents' declared specialty, default model and concurrency
+    pub agents: Arc<AgentRegistry>,
+    /// What each specialty frames prompts with and limits retrieval to
+    pub specialties: Arc<Specialties>,
+    /// How prompts are assembled for the backend
+    pub templates: Arc<Templates>,
+    /// Results of recent `llm_inference` requests, served to identical ones
+    pub response_cache: Arc<ResponseCache>,
+    /// Responses kept for repeats of their idempotency key
+    pub idempotency: Arc<IdempotencyStore>,
+    /// Measures prompts, context and history against `context_window`, and
+    /// counts tokens backends don't report
+    pub tokenizer: Arc<dyn Tokenizer>,
+    /// Signs each response's `void_shrine_token` and checks presented ones
+    pub tokens: Arc<TokenSigner>,
+    /// Where agent metrics are kept across restarts, if anywhere
+    pub metrics_store: Option<Arc<MetricsStore>>,
+    /// Tokens spent per agent and model, by hour
+    pub usage: Arc<UsageLedger>,
+    /// Hourly activity per agent, for `GET /api/metrics/export`
+    pub history: Arc<ActivityHistory>,
+    /// The longest range one export may cover
+    pub export_max_span_days: u32,
+    /// Caps on agents' and tenants' usage per hour or day
+    pub quotas: Arc<Quotas>,
+    /// Events pushed to `/ws/metrics` clients as they happen
+    pub live: Arc<LiveFeed>,
+    /// API keys, checked by the transports, and the tenants they belong to
+    pub auth: Auth,
+    /// Time of responses, token checks and agent metrics
+    pub clock: Arc<dyn Clock>,
+    /// The config file the server started from, or was last reloaded from
+    pub config_source: Arc<std::sync::RwLock<Option<ConfigSource>>>,
+    /// What `self_check` found at startup, for `/ready`; None if it didn't run
+    pub startup_checks: Arc<std::sync::RwLock<Option<SelfCheckReport>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AgentMetrics {
+    /// Over the agent's lifetime, restarts included when metrics are persisted
+    pub total_requests: u64,
+    /// Since this process started
+    pub requests_since_boot: u64,
+    /// Exponentially weighted, in milliseconds
+    pub avg_response_time: f64,
+    /// Share of the last `SUCCESS_WINDOW` finished requests that succeeded
+    pub success_rate: f64,
+    /// Whether each of those requests succeeded, oldest first
+    pub recent_outcomes: VecDeque<bool>,
+    pub last_request: DateTime<Utc>,
+    /// Derived from `in_flight` and `recent_p95_ms`; see `LoadConfig::load`.
+    /// Decays from `idle_since` while nothing is in flight.
+    pub current_load: f64,
+    /// Requests currently being handled, over any transport
+    pub in_flight: u32,
+    /// Requests admitted but still waiting out a throttle delay or for a concurrency slot
+    pub waiting: u32,
+    /// When the agent's last request in flight finished
+    pub idle_since: Option<DateTime<Utc>>,
+    /// Requests started per second over the load window
+    pub recent_rps: f64,
+    pub recent_p95_ms: f64,
+    pub recent: LoadWindow,
+    /// Requests cancelled, and streams abandoned, by the client before completion
+    pub cancelled_requests: u64,
+    /// Requests held back by load-based throttling, and those it refused
+    pub throttled_delayed: u64,
+    pub throttled_rejected: u64,
+    /// Whether the agent's last admitted or refused request was throttled
+    pub throttling: bool,
+    /// Recent finished requests scaling advice is based on
+    pub scaling: ScalingHistory,
+    /// Tokens of successful responses
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// `POST /api/embed` calls made for the agent, and the tokens they embedded
+    pub embedding_requests: u64,
+    pub embedding_tokens: u64,
+    /// Chaos applied to the agent's requests
+    pub chaos_events: u64,
+    /// Per-minute activity over the last hour
+    pub stats: AgentStats,
+    pub last_scaling: Option<LastScaling>,
+    /// The tenant whose key first used the agent
+    pub tenant: Option<String>,
+    /// When the agent last sent `POST /api/agents/{id}/heartbeat`
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    /// Work waiting on the agent's side, as its last heartbeat reported
+    pub queue_depth: Option<u32>,
+    /// Whether the last sweep found the agent stale
+    pub stale: bool,
+}
+
+impl AgentMetrics {
+    fn new() -> Self {
+        Self {
+            total_requests: 0,
+            requests_since_boot: 0,
+            avg_response_time: 0.0,
+            success_rate: 1.0,
+            recent_outcomes: VecDeque::new(),
+            last_request: Utc::now(),
+            current_load: 0.0,
+            in_flight: 0,
+            waiting: 0,
+            idle_since: None,
+            recent_rps: 0.0,
+            recent_p95_ms: 0.0,
+            recent: LoadWindow::default(),
+            cancelled_requests: 0,
+            throttled_delayed: 0,
+            throttled_rejected: 0,
+            throttling: false,
+            scaling: ScalingHistory::default(),
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            embedding_requests: 0,
+            embedding_tokens: 0,
+            chaos_events: 0,
+            stats: AgentStats::default(),
+            last_scaling: None,
+            tenant: None,
+            last_heartbeat: None,
+            queue_depth: None,
+            stale: false,
+        }
+    }
+
+    /// An agent as saved before a restart, with nothing in flight
+    fn restored(saved: SavedAgent) -> Self {
+        let skipped = saved.recent_outcomes.len().saturating_sub(SUCCESS_WINDOW);
+        let recent_outcomes: VecDeque<bool> = saved.recent_outcomes.into_iter().skip(skipped).collect();
+        let successes = recent_outcomes.iter().filter(|success| **success).count();
+        Self {
+            total_requests: saved.total_requests,
+            avg_response_time: saved.avg_response_time_ms,
+            success_rate: if recent_outcomes.is_empty() { 1.0 } else { successes as f64 / recent_outcomes.len() as f64 },
+            recent_outcomes,
+            last_request: saved.last_request,
+            cancelled_requests: saved.cancelled_requests,
+            throttled_delayed: saved.throttled_delayed,
+            throttled_rejected: saved.throttled_rejected,
+            prompt_tokens: saved.prompt_tokens,
+            completion_tokens: saved.completion_tokens,
+            embedding_requests: saved.embedding_requests,
+            embedding_tokens: saved.embedding_tokens,
+            chaos_events: saved.chaos_events,
+            stats: saved.stats,
+            tenant: saved.tenant,
+            ..Self::new()
+        }
+    }
+
+    fn saved(&self, agent_id: &str) -> SavedAgent {
+        SavedAgent {
+            agent_id: agent_id.to_string(),
+            total_requests: self.total_requests,
+            avg_response_time_ms: self.avg_response_time,
+            recent_outcomes: self.recent_outcomes.iter().copied().collect(),
+            last_request: self.last_request,
+            cancelled_requests: self.cancelled_requests,
+            throttled_delayed: self.throttled_delayed,
+            throttled_rejected: self.throttled_rejected,
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            embedding_requests: self.embedding_requests,
+            embedding_tokens: self.embedding_tokens,
+            chaos_events: self.chaos_events,
+            stats: self.stats.clone(),
+            tenant: self.tenant.clone(),
+        }
+    }
+
+    fn detail(&self, agent_id: &str, window: StatsWindow) -> AgentMetricsDetail {
+        AgentMetricsDetail {
+            agent_id: agent_id.to_string(),
+            total_requests: self.total_requests,
+            requests_since_boot: self.requests_since_boot,
+            success_rate: self.success_rate,
+            avg_response_time_ms: self.avg_response_time,
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            embedding_requests: self.embedding_requests,
+            embedding_tokens: self.embedding_tokens,
+            cancelled_requests: self.cancelled_requests,
+            throttled_delayed: self.throttled_delayed,
+            throttled_rejected: self.throttled_rejected,
+            chaos_events: self.chaos_events,
+            in_flight: self.in_flight,
+            current_load: self.current_load,
+            recent: self.stats.summary(window, Utc::now()),
+            last_scaling: self.last_scaling.clone(),
+        }
+    }
+
+    /// Folds one finished request into the response time average and the
+    /// success rate. The first timed request sets the average outright.
+    pub fn record_outcome(&mut self, response_time_ms: Option<u64>, success: bool) {
+        if let Some(response_time_ms) = response_time_ms {
+            let response_time_ms = response_time_ms as f64;
+            self.avg_response_time = if self.avg_response_time == 0.0 {
+                response_time_ms
+            } else {
+                self.avg_response_time + RESPONSE_TIME_SMOOTHING * (response_time_ms - self.avg_response_time)
+            };
+        }
+        if self.recent_outcomes.len() == SUCCESS_WINDOW {
+            self.recent_outcomes.pop_front();
+        }
+        self.recent_outcomes.push_back(success);
+        let successes = self.recent_outcomes.iter().filter(|success| **success).count();
+        self.success_rate = successes as f64 / self.recent_outcomes.len() as f64;
+    }
+
+    /// Drops samples older than the window and recomputes the load figures,
+    /// decaying the load for as long as the agent has been idle at `at`
+    pub fn refresh_load(&mut self, config: &LoadConfig, now: std::time::Instant, at: DateTime<Utc>) {
+        self.recent.prune(now, config.window());
+        self.recent_rps = self.recent.rps(config.window());
+        self.recent_p95_ms = self.recent.p95_ms();
+        self.current_load = config.load(self.in_flight, self.recent_p95_ms);
+        if let Some(idle_since) = self.idle_since.filter(|_| self.in_flight == 0) {
+            self.current_load = config.decayed(self.current_load, (at - idle_since).to_std().unwrap_or_default());
+        }
+    }
+}
+
+/// Weight of the newest response time in an agent's average
+const RESPONSE_TIME_SMOOTHING: f64 = 0.2;
+
+/// Finished requests an agent's success rate is computed over
+const SUCCESS_WINDOW: usize = 100;
+
+/// The methods `/api/mcp` dispatches on. Requests are dispatched, unsupported
+/// methods explained and MCP tools listed from this one list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McpMethod {
+    LlmInference,
+    RagQuery,
+    RagAnswer,
+}
+
+impl McpMethod {
+    pub const ALL: [McpMethod; 3] = [McpMethod::LlmInference, McpMethod::RagQuery, McpMethod::RagAnswer];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            McpMethod::LlmInference => "llm_inference",
+            McpMethod::RagQuery => "rag_query",
+            McpMethod::RagAnswer => "rag_answer",
+        }
+    }
+
+    /// What the method does, as described to MCP clients
+    pub fn description(self) -> &'static str {
+        match self {
+            McpMethod::LlmInference => "Answer a prompt with knowledge base context and moral recentering",
+            McpMethod::RagQuery => "Retrieve the knowledge base chunks most relevant to a query, with citations",
+            McpMethod::RagAnswer => "Extract the sentences from the knowledge base that best answer a question",
+        }
+    }
+
+    pub fn parse(method: &str) -> Result<McpMethod, MCPError> {
+        McpMethod::ALL.into_iter().find(|known| known.as_str() == method).ok_or_else(|| MCPError::UnsupportedMethod(method.to_string()))
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        McpMethod::ALL.iter().map(|method| method.as_str()).collect()
+    }
+
+    /// The known method a misspelt one most likely meant: the closest within
+    /// a third of its length in edits, and never more than three
+    pub fn suggest(method: &str) -> Option<McpMethod> {
+        let method = method.to_ascii_lowercase();
+        McpMethod::ALL
+            .into_iter()
+            .map(|known| (edit_distance(&method, known.as_str()), known))
+            .filter(|(distance, known)| *distance <= (known.as_str().len() / 3).min(3))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, known)| known)
+    }
+}
+
+/// Levenshtein distance: the single-character insertions, deletions and
+/// substitutions turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Whether a request would reach a backend: the requests overload sheds
+/// and whose latency it judges by
+fn sheddable(method: &str, params: &MCPParams) -> bool {
+    matches!(McpMethod::parse(method), Ok(McpMethod::LlmInference)) && !params.dry_run
+}
+
+/// The params a record can be replayed with, or why it can't be
+fn replayable_params(record: &AuditRecord) -> Result<&MCPParams, String> {
+    if let Some(original) = &record.replay_of {
+        return Err(format!("a replay of {}", original));
+    }
+    if record.method != "llm_inference" {
+        return Err(format!("only llm_inference requests are replayed, not {}", record.method));
+    }
+    match &record.params {
+        Some(params) => Ok(params),
+        None if record.redacted => Err("redacted".to_string()),
+        None => Err("recorded without its params".to_string()),
+    }
+}
+
+/// A bounded metrics label for a client-supplied method name; each MCP method
+/// is counted individually in `ServerMetrics::requests_by_method`
+fn method_label(method: &str) -> &'static str {
+    McpMethod::parse(method).map_or("other", McpMethod::as_str)
+}
+
+/// Server-wide counters since startup
+#[derive(Debug)]
+pub struct ServerCounters {
+    started_at: DateTime<Utc>,
+    total_requests: AtomicU64,
+    requests_by_method: DashMap<&'static str, u64>,
+    errors_by_class: DashMap<&'static str, u64>,
+    timeouts_by_stage: DashMap<&'static str, u64>,
+    fallbacks_by_chain: DashMap<String, u64>,
+    rag_queries: AtomicU64,
+    chaos_events: AtomicU64,
+    chaos_by_type: DashMap<String, u64>,
+}
+
+impl Default for ServerCounters {
+    fn default() -> Self {
+        Self {
+            started_at: Utc::now(),
+            total_requests: AtomicU64::new(0),
+            requests_by_method: DashMap::new(),
+            errors_by_class: DashMap::new(),
+            timeouts_by_stage: DashMap::new(),
+            fallbacks_by_chain: DashMap::new(),
+            rag_queries: AtomicU64::new(0),
+            chaos_events: AtomicU64::new(0),
+            chaos_by_type: DashMap::new(),
+        }
+    }
+}
+
+impl ServerCounters {
+    fn record_request(&self, method: &str) {
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+        // Unknown methods come from clients, so they are only counted as errors
+        let method = method_label(method);
+        if method != "other" {
+            *self.requests_by_method.entry(method).or_insert(0) += 1;
+        }
+    }
+
+    fn record_error(&self, error: &MCPError) {
+        *self.errors_by_class.entry(error.code()).or_insert(0) += 1;
+        if let Some(stage) = error.timeout_stage() {
+            *self.timeouts_by_stage.entry(stage.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    fn record_fallback(&self, chain: &str) {
+        *self.fallbacks_by_chain.entry(chain.to_string()).or_insert(0) += 1;
+    }
+
+    fn record_chaos(&self, chaos_type: &str) {
+        self.chaos_events.fetch_add(1, Ordering::Relaxed);
+        *self.chaos_by_type.entry(chaos_type.to_string()).or_insert(0) += 1;
+    }
+
+
+    pub fn snapshot(&self) -> ServerMetrics {
+        let counts = |map: &DashMap<&'static str, u64>| {
+            map.iter().map(|entry| (entry.key().to_string(), *entry.value())).collect()
+        };
+        ServerMetrics {
+            started_at: self.started_at,
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            requests_by_method: counts(&self.requests_by_method),
+            errors_by_class: counts(&self.errors_by_class),
+            timeouts_by_stage: counts(&self.timeouts_by_stage),
+            fallbacks_by_chain: self.fallbacks_by_chain.iter().map(|entry| (entry.key().clone(), *entry.value())).collect(),
+            rag_queries: self.rag_queries.load(Ordering::Relaxed),
+            chaos_events: self.chaos_events.load(Ordering::Relaxed),
+            chaos_by_type: self.chaos_by_type.iter().map(|entry| (entry.key().clone(), *entry.value())).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerMetrics {
+    pub started_at: DateTime<Utc>,
+    /// MCP requests received over every transport, including rejected ones
+    pub total_requests: u64,
+    pub requests_by_method: BTreeMap<String, u64>,
+    /// Failed requests by error code, e.g. `rate_limited` or `backend_timeout`
+    pub errors_by_class: BTreeMap<String, u64>,
+    /// `deadline_exceeded` failures by the stage that ran out of time
+    #[serde(default)]
+    pub timeouts_by_stage: BTreeMap<String, u64>,
+    /// Moves to a fallback chain's next model, by chain
+    #[serde(default)]
+    pub fallbacks_by_chain: BTreeMap<String, u64>,
+    /// Knowledge base searches made for `rag_query`, `rag_answer` and grounded inference
+    pub rag_queries: u64,
+    pub chaos_events: u64,
+    /// `chaos_events` by chaos type
+    #[serde(default)]
+    pub chaos_by_type: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentMetricsReport {
+    pub agent_id: String,
+    /// Lifetime, carried over restarts when `metrics.state_path` is set
+    pub total_requests: u64,
+    #[serde(default)]
+    pub requests_since_boot: u64,
+    pub avg_response_time_ms: f64,
+    pub success_rate: f64,
+    pub current_load: f64,
+    pub last_request: DateTime<Utc>,
+    pub in_flight: u32,
+    #[serde(default)]
+    pub recent_rps: f64,
+    #[serde(default)]
+    pub recent_p95_ms: f64,
+    pub cancelled_requests: u64,
+    #[serde(default)]
+    pub throttled_delayed: u64,
+    #[serde(default)]
+    pub throttled_rejected: u64,
+}
+
+/// Query parameters of `GET /api/agents`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentListParams {
+    pub sort: AgentSort,
+    /// Whether to list stale agents at all
+    pub include_stale: bool,
+}
+
+impl Default for AgentListParams {
+    fn default() -> Self {
+        Self { sort: AgentSort::AgentId, include_stale: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentSort {
+    #[default]
+    AgentId,
+    /// Busiest first
+    Load,
+    /// Most recently seen first; never seen last
+    Recency,
+}
+
+/// An agent as `GET /api/agents` lists it: registered, seen making requests,
+/// or both. The figures are those throttling works from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentSummary {
+    pub agent_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration: Option<AgentRegistration>,
+    pub current_load: f64,
+    pub in_flight: u32,
+    /// None for a registered agent that hasn't made a request
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Idle for longer than `agents.stale_after_secs`, or quiet for longer
+    /// than `agents.heartbeat_timeout_secs` once it has sent heartbeats
+    pub stale: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    /// As the agent's last heartbeat reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_depth: Option<u32>,
+    pub total_requests: u64,
+    pub success_rate: f64,
+    pub cancelled_requests: u64,
+    pub throttled_delayed: u64,
+    pub throttled_rejected: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentListResponse {
+    pub generated_at: DateTime<Utc>,
+    pub agents: Vec<AgentSummary>,
+}
+
+/// `GET /api/agents/{id}`: the listing's figures with recent latency and
+/// where the agent stands with throttling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentDetail {
+    #[serde(flatten)]
+    pub summary: AgentSummary,
+    pub avg_response_time_ms: f64,
+    pub recent_rps: f64,
+    pub latency: LatencyPercentiles,
+    /// Whether the agent's last request was throttled
+    pub throttling: bool,
+    /// What its next request would meet
+    pub throttle: ThrottleStatus,
+}
+
+/// `POST /api/agents/{id}/heartbeat`; the body may be left out
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatRequest {
+    /// Work waiting on the agent's side, shown in the agent listings
+    pub queue_depth: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    pub agent_id: String,
+    pub received_at: DateTime<Utc>,
+    /// When the agent goes stale without another heartbeat or a request
+    pub stale_at: DateTime<Utc>,
+}
+
+/// What one sweep for stale agents changed, each list sorted
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StaleSweep {
+    /// Agents that went stale since the last sweep
+    pub stale: Vec<String>,
+    /// Stale agents heard from again
+    pub recovered: Vec<String>,
+    /// Agents whose metrics were removed, stale past `agents.evict_after_secs`
+    pub evicted: Vec<String>,
+}
+
+/// The scaling advice last given for an agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastScaling {
+    pub direction: ScalingDirection,
+    pub description: String,
+    pub decided_at: DateTime<Utc>,
+}
+
+/// What `GET /api/version` reports: the build, and the config file the
+/// server started from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionInfo {
+    /// As sent in the `Server` header
+    pub server: String,
+    #[serde(flatten)]
+    pub build: BuildInfo,
+    /// None when started without a config file
+    pub config: Option<ConfigSource>,
+}
+
+/// Query parameters of `GET /api/dashboard`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DashboardParams {
+    /// How many of the busiest agents, and of the latest scaling decisions,
+    /// to include; at most `MAX_DASHBOARD_TOP`
+    pub top: usize,
+}
+
+impl Default for DashboardParams {
+    fn default() -> Self {
+        Self { top: 10 }
+    }
+}
+
+pub const MAX_DASHBOARD_TOP: usize = 100;
+
+/// The windows `Dashboard::activity` covers
+const DASHBOARD_WINDOWS: [StatsWindow; 3] = [StatsWindow::OneMinute, StatsWindow::FiveMinutes, StatsWindow::OneHour];
+
+/// Everything the explorer UI shows at once, read from the structures
+/// enforcement itself uses
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dashboard {
+    pub generated_at: DateTime<Utc>,
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: u64,
+    /// Every agent's activity together, over 1m, 5m and 1h
+    pub activity: Vec<DashboardActivity>,
+    /// Busiest first
+    pub agents: Vec<DashboardAgent>,
+    /// None without a knowledge base
+    pub rag: Option<RAGStats>,
+    pub chaos: DashboardChaos,
+    /// A circuit breaker per backend called so far, sorted by backend
+    pub breakers: Vec<BreakerReport>,
+    pub concurrency: ConcurrencyStatus,
+    /// Latest first
+    pub scaling: Vec<DashboardScaling>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DashboardActivity {
+    pub window: StatsWindow,
+    pub requests: u64,
+    pub failed: u64,
+    pub requests_per_sec: f64,
+    /// Of the requests finished in the window; None when none did
+    pub error_rate: Option<f64>,
+    pub throttled: u64,
+    pub chaos_events: u64,
+    pub latency: LatencyPercentiles,
+}
+
+impl DashboardActivity {
+    fn new(stats: WindowedStats) -> Self {
+        Self {
+            window: stats.window,
+            requests: stats.requests,
+            failed: stats.failed,
+            requests_per_sec: stats.requests as f64 / (stats.window.minutes() * 60) as f64,
+            error_rate: stats.success_rate.map(|rate| 1.0 - rate),
+            throttled: stats.throttled,
+            chaos_events: stats.chaos_events,
+            latency: stats.latency,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardAgent {
+    pub agent_id: String,
+    pub current_load: f64,
+    pub in_flight: u32,
+    /// As `GET /api/throttle/{agent_id}` reports it
+    pub throttle: ThrottleStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DashboardChaos {
+    pub config: ChaosConfig,
+    /// Since startup, by chaos type
+    pub events_by_type: BTreeMap<String, u64>,
+    /// Chaos drawn in shadow mode and not applied since startup, by type
+    #[serde(default)]
+    pub shadowed_by_type: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DashboardScaling {
+    pub agent_id: String,
+    #[serde(flatten)]
+    pub decision: LastScaling,
+}
+
+/// Query parameters of `GET /api/agents/{id}/metrics`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentMetricsParams {
+    pub window: StatsWindow,
+}
+
+/// `GET /api/agents/{id}/metrics`: an agent's lifetime totals, its activity
+/// over the chosen window, and the scaling advice last given for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentMetricsDetail {
+    pub agent_id: String,
+    pub total_requests: u64,
+    pub requests_since_boot: u64,
+    /// Over the agent's last 100 finished requests
+    pub success_rate: f64,
+    pub avg_response_time_ms: f64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub embedding_requests: u64,
+    #[serde(default)]
+    pub embedding_tokens: u64,
+    pub cancelled_requests: u64,
+    pub throttled_delayed: u64,
+    pub throttled_rejected: u64,
+    pub chaos_events: u64,
+    pub in_flight: u32,
+    pub current_load: f64,
+    pub recent: WindowedStats,
+    pub last_scaling: Option<LastScaling>,
+}
+
+/// `DELETE /api/agents/{id}/metrics`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentMetricsReset {
+    pub agent_id: String,
+    /// Whether the entry went entirely; one with requests in flight is zeroed
+    /// instead, still counting those
+    pub removed: bool,
+    /// Requests counted before the reset
+    pub cleared_requests: u64,
+}
+
+/// `POST /api/metrics/prune`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsPruneRequest {
+    /// Agents without a request since then lose their metrics
+    pub last_seen_before: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsPruneResponse {
+    pub pruned: usize,
+    /// Sorted
+    pub agent_ids: Vec<String>,
+}
+
+/// One method run over many params, each item handled as its own request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRequest {
+    #[serde(default = "default_batch_method")]
+    pub method: String,
+    pub items: Vec<MCPParams>,
+}
+
+fn default_batch_method() -> String {
+    "llm_inference".to_string()
+}
+
+/// An item's response, or why it failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    /// Position in the request's `items`
+    pub index: usize,
+    /// What the item would have answered on its own
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<MCPResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub batch_id: String,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+    pub summary: BatchSummary,
+    /// In the order of the request's `items`
+    pub items: Vec<BatchItem>,
+}
+
+/// Query parameters of the metrics endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsParams {
+    /// Only this agent
+    pub agent_id: Option<String>,
+    /// Only agents active at or after this instant
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsResponse {
+    pub generated_at: DateTime<Utc>,
+    pub server: ServerMetrics,
+    /// Sorted by agent_id
+    pub agents: Vec<AgentMetricsReport>,
+    #[serde(default)]
+    pub cache: CacheStats,
+    /// A circuit breaker per backend called so far, sorted by backend
+    #[serde(default)]
+    pub breakers: Vec<BreakerReport>,
+    #[serde(default)]
+    pub concurrency: ConcurrencyStatus,
+    /// Every chaos decision since the service started
+    #[serde(default)]
+    pub chaos: ChaosCounts,
+    /// Latency per HTTP route and method requested since the service
+    /// started, sorted by route
+    #[serde(default)]
+    pub routes: Vec<RouteLatency>,
+}
+
+/// Counts a request as in flight for its agent until dropped, which includes
+/// the request failing or being cancelled, and then records its latency
+pub struct InFlightGuard {
+    metrics: Arc<DashMap<String, AgentMetrics>>,
+    agent_id: String,
+    load: LoadConfig,
+    clock: Arc<dyn Clock>,
+    started: std::time::Instant,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Some(mut metrics) = self.metrics.get_mut(&self.agent_id) {
+            let (now, at) = (std::time::Instant::now(), self.clock.now());
+            metrics.in_flight = metrics.in_flight.saturating_sub(1);
+            if metrics.in_flight == 0 {
+                metrics.idle_since = Some(at);
+            }
+            metrics.recent.record_latency(now, now.duration_since(self.started).as_millis() as u64);
+            metrics.refresh_load(&self.load, now, at);
+        }
+    }
+}
+
+/// Counts an admitted request as waiting for its agent until dropped, once
+/// it is handled or gives up
+pub struct WaitingGuard {
+    metrics: Arc<DashMap<String, AgentMetrics>>,
+    agent_id: String,
+}
+
+impl Drop for WaitingGuard {
+    fn drop(&mut self) {
+        if let Some(mut metrics) = self.metrics.get_mut(&self.agent_id) {
+            metrics.waiting = metrics.waiting.saturating_sub(1);
+        }
+    }
+}
+
+/// Chaos types that hold the request up for a delay drawn from `ChaosConfig::delays`
+pub const DELAY_CHAOS_TYPES: [&str; 3] = ["network_delay", "memory_pressure", "resource_contention"];
+
+/// Chaos types that change the outcome of the request
+pub const DESTRUCTIVE_CHAOS_TYPES: [&str; 3] = ["error_injection", "response_corruption", "request_drop"];
+
+/// The failure `error_injection` imitates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectedErrorClass {
+    InternalError,
+    BackendError,
+    BackendUnavailable,
+    BackendTimeout,
+}
+
+impl InjectedErrorClass {
+    pub fn code(self) -> &'static str {
+        match self {
+            InjectedErrorClass::InternalError => "internal_error",
+            InjectedErrorClass::BackendError => "backend_error",
+            InjectedErrorClass::BackendUnavailable => "backend_unavailable",
+            InjectedErrorClass::BackendTimeout => "backend_timeout",
+        }
+    }
+
+    /// The status the real failure gets
+    pub fn default_status(self) -> u16 {
+        match self {
+            InjectedErrorClass::InternalError => 500,
+            InjectedErrorClass::BackendError => 502,
+            InjectedErrorClass::BackendUnavailable => 503,
+            InjectedErrorClass::BackendTimeout => 504,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    /// Chance in [0, 1] that a request gets chaos
+    pub intensity: f64,
+    pub chaos_types: Vec<String>,
+    /// Relative chance of each type once chaos applies; unlisted types weigh 1
+    pub weights: HashMap<String, f64>,
+    /// What `error_injection` fails requests with
+    pub error_class: InjectedErrorClass,
+    /// Replaces the status `error_class` normally gets
+    pub error_status: Option<u16>,
+    /// Methods that only ever get delay faults
+    pub protected_methods: Vec<String>,
+    pub targeting: ChaosTargeting,
+    /// Makes chaos decisions repeat for the same sequence of requests; unset
+    /// draws them from entropy
+    pub seed: Option<u64>,
+    /// What each delay type's delay is drawn from
+    pub delays: BTreeMap<String, DelayDistribution>,
+    /// Puts every type in shadow mode
+    pub shadow: bool,
+    /// Types decided and sampled as usual, and recorded as shadow chaos,
+    /// but never applied. Leaving shadow mode doesn't change what a seeded
+    /// run draws, so shadow chaos shows what applying it will do.
+    pub shadow_types: Vec<String>,
+}
+
+/// Numbers chaos decisions and gives each its own RNG. Under a seed, decision
+/// `n` always gets the same RNG, so a run, or one event in it, can be replayed.
+#[derive(Debug, Default)]
+pub struct ChaosDice {
+    decisions: AtomicU64,
+}
+
+/// One chaos decision and the randomness it may use
+pub struct ChaosRoll {
+    pub seed: Option<u64>,
+    /// Counts from 1 since the service started or the chaos config last changed
+    pub decision: u64,
+    pub rng: StdRng,
+}
+
+impl ChaosRoll {
+    /// No decision made, for requests chaos doesn't get a chance at
+    pub fn skipped() -> Self {
+        Self { seed: None, decision: 0, rng: StdRng::seed_from_u64(0) }
+    }
+}
+
+impl ChaosDice {
+    pub fn roll(&self, seed: Option<u64>) -> ChaosRoll {
+        let decision = self.decisions.fetch_add(1, Ordering::Relaxed) + 1;
+        let rng = match seed {
+            Some(seed) => Self::rng_for(seed, decision),
+            None => StdRng::from_rng(rand::thread_rng()).expect("the thread RNG does not fail"),
+        };
+        ChaosRoll { seed, decision, rng }
+    }
+
+    /// The RNG that decision `decision` gets under `seed`
+    pub fn rng_for(seed: u64, decision: u64) -> StdRng {
+        StdRng::seed_from_u64(seed ^ decision.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    /// Starts numbering from 1 again
+    pub fn reset(&self) {
+        self.decisions.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Which agents chaos may hit, and how hard. Agents are named exactly or by
+/// `*` wildcard patterns (e.g. `canary-*`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosTargeting {
+    /// When non-empty, only matching agents get chaos
+    pub include_agents: Vec<String>,
+    /// Matching agents never get chaos, even when included
+    pub exclude_agents: Vec<String>,
+    /// Scales the intensity for matching agents. Exact names win over
+    /// patterns, longer patterns over shorter ones.
+    pub agent_multipliers: BTreeMap<String, f64>,
+    /// Replaces `intensity` for agents of a specialty
+    pub specialty_intensity: HashMap<String, f64>,
+    /// Scales the intensity for requests of a specialty, on top of the rest;
+    /// 0 spares the specialty whatever the other settings
+    pub specialty_multipliers: BTreeMap<String, f64>,
+}
+
+/// Largest `ChaosTargeting::specialty_multipliers` entry accepted
+pub const MAX_SPECIALTY_MULTIPLIER: f64 = 10.0;
+
+/// `*` matches any run of characters, including none
+fn wildcard_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = text.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            intensity: 0.1,
+            chaos_types: DELAY_CHAOS_TYPES.iter().map(|t| t.to_string()).collect(),
+            weights: HashMap::new(),
+            error_class: InjectedErrorClass::InternalError,
+            error_status: None,
+            protected_methods: Vec::new(),
+            targeting: ChaosTargeting::default(),
+            seed: None,
+            delays: DELAY_CHAOS_TYPES.iter().map(|t| (t.to_string(), Self::default_delay(t))).collect(),
+            shadow: false,
+            shadow_types: Vec::new(),
+        }
+    }
+}
+
+impl ChaosConfig {
+    pub fn is_known_type(chaos_type: &str) -> bool {
+        DELAY_CHAOS_TYPES.contains(&chaos_type) || DESTRUCTIVE_CHAOS_TYPES.contains(&chaos_type)
+    }
+
+    /// The delay of a chaos type absent from `delays`
+    pub fn default_delay(chaos_type: &str) -> DelayDistribution {
+        match chaos_type {
+            "network_delay" => DelayDistribution::uniform(500, 2500),
+            "memory_pressure" => DelayDistribution::uniform(200, 1200),
+            "resource_contention" => DelayDistribution::uniform(1000, 4000),
+            _ => DelayDistribution::uniform(300, 1800),
+        }
+    }
+
+    /// How long chaos of `chaos_type` holds a request up; nothing for types
+    /// that fail or alter it instead
+    pub fn delay_ms(&self, chaos_type: &str, rng: &mut impl Rng) -> u64 {
+        if DESTRUCTIVE_CHAOS_TYPES.contains(&chaos_type) {
+            return 0;
+        }
+        self.delays.get(chaos_type).copied().unwrap_or_else(|| Self::default_delay(chaos_type)).sample(rng)
+    }
+
+    /// Whether chaos of `chaos_type` is only recorded, not applied
+    pub fn is_shadow(&self, chaos_type: &str) -> bool {
+        self.shadow || self.shadow_types.iter().any(|t| t == chaos_type)
+    }
+
+    /// The configured types in shadow mode, sorted
+    pub fn shadowed_types(&self) -> Vec<String> {
+        let mut types: Vec<String> = self.chaos_types.iter().filter(|t| self.is_shadow(t)).cloned().collect();
+        types.sort();
+        types
+    }
+
+    pub fn weight(&self, chaos_type: &str) -> f64 {
+        self.weights.get(chaos_type).copied().unwrap_or(1.0)
+    }
+
+    /// Whether the targeting rules leave `agent_id` open to chaos
+    pub fn targets(&self, agent_id: &str) -> bool {
+        let matches = |pattern: &String| wildcard_matches(pattern, agent_id);
+        let targeting = &self.targeting;
+        !targeting.exclude_agents.iter().any(matches)
+            && (targeting.include_agents.is_empty() || targeting.include_agents.iter().any(matches))
+    }
+
+    /// Chance in [0, 1] that a request from the agent gets chaos; 0 for exempt agents
+    pub fn intensity_for(&self, agent_id: &str, specialty: Option<&str>) -> f64 {
+        if !self.enabled || !self.targets(agent_id) {
+            return 0.0;
+        }
+        let specialty_multiplier = self.specialty_multiplier(specialty);
+        if specialty_multiplier == 0.0 {
+            return 0.0;
+        }
+        let base = specialty
+            .and_then(|specialty| self.targeting.specialty_intensity.get(specialty))
+            .copied()
+            .unwrap_or(self.intensity);
+        let multiplier = self.targeting.agent_multipliers.iter()
+            .filter(|(pattern, _)| wildcard_matches(pattern, agent_id))
+            .max_by_key(|(pattern, _)| (!pattern.contains('*'), pattern.len()))
+            .map_or(1.0, |(_, multiplier)| *multiplier);
+        (base * multiplier * specialty_multiplier).clamp(0.0, 1.0)
+    }
+
+    /// What `specialty_multipliers` scales the specialty's intensity by
+    pub fn specialty_multiplier(&self, specialty: Option<&str>) -> f64 {
+        specialty.and_then(|specialty| self.targeting.specialty_multipliers.get(specialty)).copied().unwrap_or(1.0)
+    }
+
+    /// The fault for one request from the agent to `method`, if chaos strikes it
+    pub fn pick(&self, agent_id: &str, specialty: Option<&str>, method: &str, rng: &mut impl Rng) -> Option<&str> {
+        if rng.gen::<f64>() >= self.intensity_for(agent_id, specialty) {
+            return None;
+        }
+        let protected = self.protected_methods.iter().any(|m| m == method);
+        let candidates: Vec<&str> = self.chaos_types.iter()
+            .map(String::as_str)
+            .filter(|t| !(protected && DESTRUCTIVE_CHAOS_TYPES.contains(t)))
+            .collect();
+        candidates.choose_weighted(rng, |t| self.weight(t)).ok().copied()
+    }
+
+    /// Every setting out of range, as `chaos.`-prefixed messages
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !(0.0..=1.0).contains(&self.intensity) {
+            problems.push(format!("chaos.intensity must be between 0 and 1 (got {})", self.intensity));
+        }
+        if self.enabled && self.chaos_types.is_empty() {
+            problems.push("chaos.chaos_types must not be empty while chaos is enabled".to_string());
+        }
+        for chaos_type in self.chaos_types.iter().chain(self.weights.keys()).chain(&self.shadow_types) {
+            if !Self::is_known_type(chaos_type) {
+                problems.push(format!("unknown chaos type '{}'", chaos_type));
+            }
+        }
+        for (chaos_type, delay) in &self.delays {
+            if DELAY_CHAOS_TYPES.contains(&chaos_type.as_str()) {
+                problems.extend(delay.validate(&format!("chaos.delays.{}", chaos_type)));
+            } else {
+                problems.push(format!("chaos.delays.{} is not a delay chaos type", chaos_type));
+            }
+        }
+        for (chaos_type, weight) in &self.weights {
+            if !weight.is_finite() || *weight < 0.0 {
+                problems.push(format!("chaos.weights.{} must be a non-negative number (got {})", chaos_type, weight));
+            }
+        }
+        if self.enabled && !self.chaos_types.is_empty() && self.chaos_types.iter().all(|t| self.weight(t) == 0.0) {
+            problems.push("chaos.weights must not all be zero while chaos is enabled".to_string());
+        }
+        if let Some(status) = self.error_status {
+            if !(400..=599).contains(&status) {
+                problems.push(format!("chaos.error_status must be an HTTP error status (got {})", status));
+            }
+        }
+
+        let targeting = &self.targeting;
+        for included in &targeting.include_agents {
+            if let Some(excluded) = targeting.exclude_agents.iter().find(|pattern| wildcard_matches(pattern, included)) {
+                problems.push(format!("chaos.targeting includes '{}' but excludes it with '{}'", included, excluded));
+            }
+        }
+        for (pattern, multiplier) in &targeting.agent_multipliers {
+            if !multiplier.is_finite() || *multiplier < 0.0 {
+                problems.push(format!("chaos.targeting.agent_multipliers.{} must be a non-negative number (got {})", pattern, multiplier));
+            }
+        }
+        for (specialty, intensity) in &targeting.specialty_intensity {
+            if !(0.0..=1.0).contains(intensity) {
+                problems.push(format!("chaos.targeting.specialty_intensity.{} must be between 0 and 1 (got {})", specialty, intensity));
+            }
+        }
+        for (specialty, multiplier) in &targeting.specialty_multipliers {
+            if !(0.0..=MAX_SPECIALTY_MULTIPLIER).contains(multiplier) {
+                problems.push(format!(
+                    "chaos.targeting.specialty_multipliers.{} must be between 0 and {} (got {})",
+                    specialty, MAX_SPECIALTY_MULTIPLIER, multiplier
+                ));
+            }
+        }
+        problems
+    }
+
+    pub fn injected_error(&self) -> MCPError {
+        MCPError::ChaosInjected {
+            class: self.error_class,
+            status: self.error_status.unwrap_or_else(|| self.error_class.default_status()),
+        }
+    }
+}
+
+fn session_error(session_id: &str, conflict: SessionConflict) -> MCPError {
+    match conflict {
+        SessionConflict::OtherAgent => {
+            MCPError::InvalidFields(vec![FieldError::new("session_id", "a session opened by this agent", session_id)])
+        }
+    }
+}
+
+/// What `response_corruption` does to response text: cuts it short or swaps
+/// some differing neighbours, so any non-empty text changes
+fn corrupt_text(text: &str, rng: &mut impl Rng) -> String {
+    let mut chars: Vec<char> = text.chars().collect();
+    let swappable: Vec<usize> = (1..chars.len()).filter(|&i| chars[i - 1] != chars[i]).collect();
+    if swappable.is_empty() || rng.gen_bool(0.5) {
+        let keep = rng.gen_range(0..chars.len().max(1));
+        chars.truncate(keep);
+    } else {
+        for &i in swappable.choose_multiple(rng, (chars.len() / 20).max(1)) {
+            chars.swap(i - 1, i);
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// Specialties, templates and backends as a config describes them, opened
+/// and checked against each other, and against the settings a reload keeps,
+/// before any of them is used
+struct ReloadableParts {
+    specialties: Specialties,
+    templates: Templates,
+    backends: BackendRegistry,
+    ethics: EthicalFrameworks,
+}
+
+impl ReloadableParts {
+    /// Mock backends answer from `specialties`, which the opened ones are to replace
+    fn open(
+        config: &Config,
+        specialties: &Arc<Specialties>,
+        defaults: &RequestDefaults,
+        breakers: &BreakerConfig,
+    ) -> Result<Self, anyhow::Error> {
+        let opened = Specialties::open(&config.specialties).map_err(|e| e.context("specialties config"))?;
+        let templates = Templates::open(&config.templates).map_err(|e| e.context("templates config"))?;
+        for specialty in opened.list().specialties {
+            if let Some(template) = specialty.template.filter(|template| templates.get(template).is_none()) {
+                anyhow::bail!("specialties config: '{}' uses template '{}', which isn't loaded", specialty.name, template);
+            }
+        }
+        let ethics = EthicalFrameworks::open(&config.moral).map_err(|e| e.context("moral config"))?;
+        ethics.check_specialties(&opened.names()).map_err(|e| e.context("moral config"))?;
+        let default_specialty = &defaults.specialty;
+        if !default_specialty.is_empty() && opened.strict() && opened.resolve(default_specialty).is_none() {
+            anyhow::bail!("defaults config: specialty '{}' isn't loaded", default_specialty);
+        }
+        let backends = if config.backends.backends.is_empty() {
+            VoidShrineMCP::mock_backends(specialties)
+        } else {
+            BackendRegistry::from_config(&config.backends, specialties).map_err(|e| e.context("backends config"))?
+        };
+        for (backend, fallback) in &breakers.fallbacks {
+            if backends.backend(fallback).is_none() {
+                anyhow::bail!("breakers config: backend '{}' falls back to '{}', which isn't registered", backend, fallback);
+            }
+        }
+        Ok(Self { specialties: opened, templates, backends, ethics })
+    }
+}
+
+/// The file at `path` with the environment's overrides, validated, and the
+/// parts a reload swaps in opened against the settings it keeps; else every
+/// problem found
+fn load_for_reload(
+    path: &Path,
+    specialties: &Arc<Specialties>,
+    defaults: &RequestDefaults,
+    breakers: &BreakerConfig,
+) -> Result<(Config, ReloadableParts), Vec<String>> {
+    let describe = |e: anyhow::Error| vec![format!("{:#}", e)];
+    let mut config = Config::from_file(path).map_err(describe)?;
+    config.apply_env(|name| std::env::var(name).ok()).map_err(describe)?;
+    let problems = config.problems();
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    let parts = ReloadableParts::open(&config, specialties, defaults, breakers).map_err(describe)?;
+    Ok((config, parts))
+}
+
+impl Default for VoidShrineMCP {
+    fn default() -> Self {
+        Self::new(&Config::default()).expect("the default config is valid")
+    }
+}
+
+impl VoidShrineMCP {
+    /// A service with the settings of `config`. The RAG engines are left
+    /// uninitialized and authentication is up to the transport, which finds
+    /// the keys in `auth`.
+    pub fn new(config: &Config) -> Result<Self, anyhow::Error> {
+        let specialties = Arc::new(Specialties::default());
+        let parts = ReloadableParts::open(config, &specialties, &config.defaults, &config.breakers)?;
+        specialties.replace_with(parts.specialties);
+        let templates = Arc::new(parts.templates);
+        let backends = parts.backends;
+        let metrics = Arc::new(Metrics::new(config.metrics.agent_label_cap));
+        let route_metrics = Arc::new(RouteMetrics::new(Arc::clone(&metrics)));
+        let rag_engine: SharedEngine = Arc::new(RwLock::new(None));
+        let rag_engines = Arc::new(RagEngines::new(Arc::clone(&rag_engine), config.rag.engines.keys().cloned()));
+        let tokenizer = config.tokenizer.build()?;
+        let agent_metrics = DashMap::new();
+        let metrics_store = config.metrics.state_path.as_ref().map(|path| Arc::new(MetricsStore::new(path)));
+        let usage = UsageLedger::new(config.usage.clone());
+        let history = ActivityHistory::new(config.metrics.history_retention_days);
+        let scaling_log = ScalingLog::default();
+        let live = Arc::new(LiveFeed::new(config.metrics.live_buffer_events));
+        if let Some(store) = &metrics_store {
+            let saved = store.load();
+            for agent in saved.agents {
+                agent_metrics.insert(agent.agent_id.clone(), AgentMetrics::restored(agent));
+            }
+            usage.restore(saved.usage);
+            history.restore(saved.history);
+            scaling_log.restore(saved.scaling);
+            tracing::info!("Restored metrics of {} agents from {}", agent_metrics.len(), store.path().display());
+        }
+        Ok(Self {
+            agent_metrics: Arc::new(agent_metrics),
+            rag_engine,
+            rag_engines,
+            chaos_config: Arc::new(RwLock::new(config.chaos.clone())),
+            backup_dir: config.server.backup_dir.clone(),
+            rag_tasks: Arc::new(RagTasks::default()),
+            backends: SharedBackends::new(backends),
+            param_limits: config.limits.clone(),
+            request_defaults: config.defaults.clone(),
+            body_limits: config.body_limits,
+            rate_limiter: Arc::new(RateLimiter::new(config.rate_limits.clone())),
+            throttle: Arc::new(std::sync::RwLock::new(config.throttle.clone())),
+            load: config.load,
+            scaling: config.scaling.clone(),
+            scaling_log: Arc::new(scaling_log),
+            batch: config.batch.clone(),
+            replay: config.audit.replay.clone(),
+            timeouts: config.timeouts.clone(),
+            retries: config.retries.clone(),
+            breakers: Arc::new(Breakers::new(config.breakers.clone()).with_feed(Arc::clone(&live))),
+            model_catalog: Arc::new(ModelCatalog::new()),
+            concurrency: Arc::new(ConcurrencyLimiter::new(&config.concurrency)),
+            overload: Arc::new(OverloadDetector::new(config.overload)),
+            counters: Arc::new(ServerCounters::default()),
+            webhooks: Arc::new(Webhooks::new(config.webhooks.clone(), Arc::clone(&metrics))),
+            content_filters: Arc::new(
+                ContentFilters::new(&config.content_filter, Arc::clone(&metrics)).map_err(|e| e.context("content_filter config"))?,
+            ),
+            context_screen: Arc::new(
+                ContextScreen::new(&config.context_screen, Arc::clone(&metrics)).map_err(|e| e.context("context_screen config"))?,
+            ),
+            metrics,
+            route_metrics,
+            confidence: config.confidence.clone(),
+            ethics: Arc::new(parts.ethics),
+            request_ids: Arc::new(RecentRequestIds::default()),
+            running: Arc::new(RunningRequests::default()),
+            audit: AuditLog::open(&config.audit)?.map(Arc::new),
+            sessions: Arc::new(SessionStore::new(config.sessions.clone(), Arc::clone(&tokenizer))),
+            agents: Arc::new(AgentRegistry::open(&config.agents)?),
+            specialties,
+            templates,
+            response_cache: Arc::new(ResponseCache::new(config.cache.clone())),
+            idempotency: Arc::new(IdempotencyStore::new(config.idempotency.clone())),
+            tokenizer,
+            tokens: Arc::new(TokenSigner::new(&config.tokens)),
+            metrics_store,
+            usage: Arc::new(usage),
+            history: Arc::new(history),
+            export_max_span_days: config.metrics.export_max_span_days,
+            quotas: Arc::new(Quotas::new(config.quotas.clone())),
+            live,
+            auth: Auth::new(config.auth.keys.clone()).map_err(|e| e.context("[auth] keys"))?,
+            shutdown: Arc::new(Shutdown::new(std::time::Duration::from_secs(config.server.drain_timeout_secs))),
+            require_rag: config.rag.require_engine,
+            rag_failure_policy: config.rag.on_retrieval_error,
+            chaos_dice: Arc::new(ChaosDice::default()),
+            chaos_stats: Arc::new(ChaosLedger::new(config.metrics.chaos_retention_hours)),
+            clock: Arc::new(SystemClock),
+            config_source: Arc::new(std::sync::RwLock::new(config.source.clone())),
+            startup_checks: Arc::new(std::sync::RwLock::new(None)),
+        })
+    }
+
+    fn mock_backends(specialties: &Arc<Specialties>) -> BackendRegistry {
+        let mut backends = BackendRegistry::new();
+        backends.register("mock", Arc::new(MockBackend::new(Arc::clone(specialties))));
+        backends.set_default(Some("mock")).expect("mock backend is registered");
+        backends
+    }
+
+    /// Registers `backend` under its own name and sends unrouted models to it
+    pub fn with_backend(mut self, backend: Arc<dyn LLMBackend>) -> Self {
+        let name = backend.name().to_string();
+        let mut backends = BackendRegistry::clone(&self.backends.current());
+        backends.register(name.clone(), backend);
+        backends.set_default(Some(&name)).expect("backend was just registered");
+        self.backends = SharedBackends::new(backends);
+        self
+    }
+
+    pub fn with_backends(mut self, backends: BackendRegistry) -> Self {
+        self.backends = SharedBackends::new(backends);
+        self
+    }
+
+    pub fn with_backup_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.backup_dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_param_limits(mut self, limits: ParamLimits) -> Self {
+        self.param_limits = limits;
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Arc::new(metrics);
+        self
+    }
+
+    /// How long in-flight requests may run once shutdown starts
+    pub fn with_drain_timeout(mut self, drain_timeout: std::time::Duration) -> Self {
+        self.shutdown = Arc::new(Shutdown::new(drain_timeout));
+        self
+    }
+
+    pub fn with_rate_limits(mut self, config: RateLimitConfig) -> Self {
+        self.rate_limiter = Arc::new(RateLimiter::new(config));
+        self
+    }
+
+    pub fn with_throttle(mut self, config: ThrottleConfig) -> Self {
+        self.throttle = Arc::new(std::sync::RwLock::new(config));
+        self
+    }
+
+    pub fn with_load(mut self, config: LoadConfig) -> Self {
+        self.load = config;
+        self
+    }
+
+    pub fn with_overload(mut self, config: OverloadConfig) -> Self {
+        self.overload = Arc::new(OverloadDetector::new(config));
+        self
+    }
+
+    pub fn with_batch(mut self, config: BatchConfig) -> Self {
+        self.batch = config;
+        self
+    }
+
+    pub fn with_timeouts(mut self, config: TimeoutConfig) -> Self {
+        self.timeouts = config;
+        self
+    }
+
+    pub fn with_retries(mut self, config: RetryConfig) -> Self {
+        self.retries = config;
+        self
+    }
+
+    pub fn with_breakers(mut self, config: BreakerConfig) -> Self {
+        self.breakers = Arc::new(Breakers::new(config).with_feed(Arc::clone(&self.live)));
+        self
+    }
+
+    pub fn with_scaling(mut self, config: ScalingConfig) -> Self {
+        self.scaling = config;
+        self
+    }
+
+    pub fn with_moral(mut self, ethics: EthicalFrameworks) -> Self {
+        self.ethics = Arc::new(ethics);
+        self
+    }
+
+    pub fn with_webhooks(mut self, config: WebhookConfig) -> Self {
+        self.webhooks = Arc::new(Webhooks::new(config, Arc::clone(&self.metrics)));
+        self
+    }
+
+    pub fn with_confidence(mut self, config: ConfidenceConfig) -> Self {
+        self.confidence = config;
+        self
+    }
+
+    pub fn with_content_filters(mut self, filters: ContentFilters) -> Self {
+        self.content_filters = Arc::new(filters);
+        self
+    }
+
+    pub fn with_context_screen(mut self, screen: ContextScreen) -> Self {
+        self.context_screen = Arc::new(screen);
+        self
+    }
+
+    pub fn with_audit(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(Arc::new(audit));
+        self
+    }
+
+    pub fn with_replay(mut self, config: ReplayConfig) -> Self {
+        self.replay = config;
+        self
+    }
+
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn with_agents(mut self, agents: AgentRegistry) -> Self {
+        self.agents = Arc::new(agents);
+        self
+    }
+
+    pub fn with_sessions(mut self, config: SessionConfig) -> Self {
+        self.sessions = Arc::new(SessionStore::new(config, Arc::clone(&self.tokenizer)));
+        self
+    }
+
+    pub fn with_cache(mut self, config: CacheConfig) -> Self {
+        self.response_cache = Arc::new(ResponseCache::new(config));
+        self
+    }
+
+    pub fn with_usage(mut self, config: UsageConfig) -> Self {
+        self.usage = Arc::new(UsageLedger::new(config));
+        self
+    }
+
+    pub fn with_quotas(mut self, config: QuotaConfig) -> Self {
+        self.quotas = Arc::new(Quotas::new(config));
+        self
+    }
+
+    /// Reads the time from `clock` rather than the system
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Draws chaos decisions from `seed`, as `ChaosConfig::seed` does
+    pub fn with_chaos_seed(mut self, seed: u64) -> Self {
+        Arc::get_mut(&mut self.chaos_config).expect("the service is not shared yet").get_mut().seed = Some(seed);
+        self
+    }
+
+    /// Counts with `tokenizer` from now on; open sessions are dropped
+    pub fn with_tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.sessions = Arc::new(SessionStore::new(self.sessions.config().clone(), Arc::clone(&tokenizer)));
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    pub fn handle_list_sessions(&self) -> SessionsResponse {
+        SessionsResponse { sessions: self.sessions.list() }
+    }
+
+    pub fn handle_delete_session(&self, session_id: &str) -> Result<(), MCPError> {
+        if !self.sessions.delete(session_id) {
+            return Err(MCPError::SessionNotFound(session_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Registered agents and those seen making requests, of the caller's tenant
+    pub fn handle_list_agents(&self, tenancy: &Tenancy, params: &AgentListParams) -> AgentListResponse {
+        self.refresh_loads();
+        let now = self.clock.now();
+        let mut registrations: HashMap<String, AgentRegistration> =
+            self.agents.list().agents.into_iter().map(|registration| (registration.agent_id.clone(), registration)).collect();
+        let mut agents: Vec<AgentSummary> = self
+            .agent_metrics
+            .iter()
+            .map(|entry| self.agent_summary(entry.key(), Some(entry.value()), registrations.remove(entry.key()), now))
+            .collect();
+        agents.extend(registrations.into_values().map(|registration| {
+            let agent_id = registration.agent_id.clone();
+            self.agent_summary(&agent_id, None, Some(registration), now)
+        }));
+        agents.retain(|agent| (params.include_stale || !agent.stale) && !self.hidden_agent(tenancy, &agent.agent_id));
+        agents.sort_by(|a, b| a.agent_id.cmp(&b.agent_id));
+        match params.sort {
+            AgentSort::AgentId => {}
+            AgentSort::Load => agents.sort_by(|a, b| b.current_load.total_cmp(&a.current_load)),
+            AgentSort::Recency => agents.sort_by_key(|agent| std::cmp::Reverse(agent.last_seen)),
+        }
+        AgentListResponse { generated_at: now, agents }
+    }
+
+    /// One agent, registered or seen, in full
+    pub fn handle_get_agent(&self, tenancy: &Tenancy, agent_id: &str) -> Result<AgentDetail, MCPError> {
+        self.visible_agent(tenancy, agent_id)?;
+        if let Some(mut metrics) = self.agent_metrics.get_mut(agent_id) {
+            metrics.refresh_load(&self.load, std::time::Instant::now(), self.clock.now());
+        }
+        let throttle = self.throttle_status(agent_id);
+        let registration = self.agents.get(agent_id);
+        let metrics = self.agent_metrics.get(agent_id);
+        if registration.is_none() && metrics.is_none() {
+            return Err(MCPError::AgentNotFound(agent_id.to_string()));
+        }
+        let metrics = metrics.as_deref();
+        Ok(AgentDetail {
+            summary: self.agent_summary(agent_id, metrics, registration, self.clock.now()),
+            avg_response_time_ms: metrics.map_or(0.0, |metrics| metrics.avg_response_time),
+            recent_rps: metrics.map_or(0.0, |metrics| metrics.recent_rps),
+            latency: metrics.map(|metrics| metrics.recent.percentiles()).unwrap_or_default(),
+            throttling: metrics.is_some_and(|metrics| metrics.throttling),
+            throttle,
+        })
+    }
+
+    /// An agent's metrics in full; 404 for one neither registered nor seen
+    pub fn handle_agent_metrics(&self, tenancy: &Tenancy, agent_id: &str, params: &AgentMetricsParams) -> Result<AgentMetricsDetail, MCPError> {
+        self.visible_agent(tenancy, agent_id)?;
+        let Some(mut metrics) = self.agent_metrics.get_mut(agent_id) else {
+            if self.agents.get(agent_id).is_none() {
+                return Err(MCPError::AgentNotFound(agent_id.to_string()));
+            }
+            return Ok(AgentMetrics::new().detail(agent_id, params.window));
+        };
+        metrics.refresh_load(&self.load, std::time::Instant::now(), self.clock.now());
+        Ok(metrics.detail(agent_id, params.window))
+    }
+
+    /// Clears an agent's metrics, removing them when it has nothing in flight
+    /// and zeroing all but its running requests otherwise; 404 without any
+    pub fn handle_reset_agent_metrics(&self, tenancy: &Tenancy, agent_id: &str) -> Result<AgentMetricsReset, MCPError> {
+        self.visible_agent(tenancy, agent_id)?;
+        let reset = match self.agent_metrics.remove_if(agent_id, |_, metrics| metrics.in_flight == 0 && metrics.waiting == 0) {
+            Some((_, removed)) => AgentMetricsReset { agent_id: agent_id.to_string(), removed: true, cleared_requests: removed.total_requests },
+            None => {
+                let mut metrics = self.agent_metrics.get_mut(agent_id).ok_or_else(|| MCPError::AgentNotFound(agent_id.to_string()))?;
+                let cleared_requests = metrics.total_requests;
+                *metrics = AgentMetrics {
+                    in_flight: metrics.in_flight,
+                    waiting: metrics.waiting,
+                    recent: std::mem::take(&mut metrics.recent),
+                    tenant: metrics.tenant.take(),
+                    ..AgentMetrics::new()
+                };
+                AgentMetricsReset { agent_id: agent_id.to_string(), removed: false, cleared_requests }
+            }
+        };
+        tracing::info!("Cleared the metrics of {} after {} requests", agent_id, reset.cleared_requests);
+        self.audit_admin("metrics_reset", agent_id, format!("cleared {} requests", reset.cleared_requests));
+        Ok(reset)
+    }
+
+    /// Prunes the caller's tenant's agents only
+    pub fn handle_prune_metrics(&self, tenancy: &Tenancy, request: &MetricsPruneRequest) -> MetricsPruneResponse {
+        let agent_ids = self.prune_agent_metrics(tenancy, request.last_seen_before);
+        MetricsPruneResponse { pruned: agent_ids.len(), agent_ids }
+    }
+
+    /// Removes the metrics of agents idle since `last_seen_before`, sparing
+    /// any with requests in flight. An agent's next request starts it afresh.
+    fn prune_agent_metrics(&self, tenancy: &Tenancy, last_seen_before: DateTime<Utc>) -> Vec<String> {
+        let mut pruned = Vec::new();
+        self.agent_metrics.retain(|agent_id, metrics| {
+            let keep = !tenancy.sees(self.agent_owner(agent_id, Some(metrics)).as_deref()) || metrics.in_flight > 0 || metrics.last_request >= last_seen_before;
+            if !keep {
+                pruned.push(agent_id.clone());
+            }
+            keep
+        });
+        pruned.sort();
+        if !pruned.is_empty() {
+            tracing::info!("Pruned the metrics of {} agents idle since {}", pruned.len(), last_seen_before);
+            let outcome = format!("pruned {} agents idle since {}: {}", pruned.len(), last_seen_before.to_rfc3339(), pruned.join(", "));
+            self.audit_admin("metrics_prune", "*", outcome);
+        }
+        pruned
+    }
+
+    /// Every `interval`, prunes agents idle for `idle`
+    pub fn prune_agent_metrics_every(self: &Arc<Self>, interval: std::time::Duration, idle: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let service = Arc::clone(self);
+        let idle = chrono::Duration::from_std(idle).unwrap_or(chrono::Duration::MAX);
+        tokio::spawn(async move {
+            let mut prune = tokio::time::interval(interval);
+            prune.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                prune.tick().await;
+                service.prune_agent_metrics(&Tenancy::All, service.clock.now().checked_sub_signed(idle).unwrap_or(DateTime::<Utc>::MIN_UTC));
+            }
+        })
+    }
+
+    /// Records a heartbeat from `agent_id`, claiming an agent not seen
+    /// before for the caller's tenant as a request would
+    pub fn handle_heartbeat(&self, tenancy: &Tenancy, agent_id: &str, request: HeartbeatRequest) -> Result<HeartbeatResponse, MCPError> {
+        if let Some(error) = check_id("agent_id", agent_id) {
+            return Err(MCPError::InvalidFields(vec![error]));
+        }
+        self.admit_agent(tenancy, agent_id)?;
+        let now = self.clock.now();
+        let mut metrics = self.agent_metrics.entry(agent_id.to_string()).or_insert_with(|| AgentMetrics { last_request: now, ..AgentMetrics::new() });
+        metrics.last_heartbeat = Some(now);
+        metrics.queue_depth = request.queue_depth;
+        let timeout = chrono::Duration::from_std(self.agents.heartbeat_timeout()).unwrap_or(chrono::Duration::MAX);
+        Ok(HeartbeatResponse { agent_id: agent_id.to_string(), received_at: now, stale_at: now.checked_add_signed(timeout).unwrap_or(now) })
+    }
+
+    /// Whether the agent has gone quiet: with nothing in flight, for
+    /// `agents.heartbeat_timeout_secs` since its last heartbeat or request
+    /// once it has sent heartbeats, else for `agents.stale_after_secs` since
+    /// its last request, or since registering when it has made none
+    fn is_stale(&self, metrics: Option<&AgentMetrics>, registration: Option<&AgentRegistration>, now: DateTime<Utc>) -> bool {
+        let quiet_since = |at: DateTime<Utc>, limit: std::time::Duration| (now - at).to_std().is_ok_and(|idle| idle > limit);
+        match metrics {
+            Some(metrics) if metrics.in_flight > 0 => false,
+            Some(AgentMetrics { last_heartbeat: Some(heartbeat), last_request, .. }) => {
+                quiet_since((*heartbeat).max(*last_request), self.agents.heartbeat_timeout())
+            }
+            _ => {
+                let active = metrics.map(|metrics| metrics.last_request).or(registration.map(|registration| registration.updated_at));
+                active.is_none_or(|at| quiet_since(at, self.agents.stale_after()))
+            }
+        }
+    }
+
+    /// Marks agents stale or not as they are now. An agent going stale is
+    /// logged and sent as an `agent_stale` webhook, and forgets its recent
+    /// load and scaling history, so a returning agent isn't throttled on
+    /// figures from before it went quiet. Then removes the metrics of agents
+    /// stale and idle for `agents.evict_after_secs`.
+    pub fn sweep_stale_agents(&self) -> StaleSweep {
+        let now = self.clock.now();
+        let mut sweep = StaleSweep::default();
+        let mut notices = Vec::new();
+        for mut entry in self.agent_metrics.iter_mut() {
+            let registration = self.agents.get(entry.key());
+            let stale = self.is_stale(Some(entry.value()), registration.as_ref(), now);
+            let agent_id = entry.key().clone();
+            let metrics = entry.value_mut();
+            if std::mem::replace(&mut metrics.stale, stale) == stale {
+                continue;
+            }
+            if !stale {
+                sweep.recovered.push(agent_id);
+                continue;
+            }
+            metrics.recent = LoadWindow::default();
+            metrics.scaling = ScalingHistory::default();
+            metrics.throttling = false;
+            metrics.current_load = 0.0;
+            metrics.recent_rps = 0.0;
+            metrics.recent_p95_ms = 0.0;
+            let payload = serde_json::json!({ "last_request": metrics.last_request, "last_heartbeat": metrics.last_heartbeat });
+            notices.push((agent_id.clone(), payload));
+            sweep.stale.push(agent_id);
+        }
+        for (agent_id, payload) in notices {
+            tracing::warn!("Agent {} went stale: nothing heard from it lately ({})", agent_id, payload);
+            self.webhooks.notify(WebhookEventKind::AgentStale, &agent_id, payload);
+        }
+        for agent_id in &sweep.recovered {
+            tracing::info!("Agent {} is no longer stale", agent_id);
+        }
+
+        if let Some(evict_after) = self.agents.evict_after() {
+            self.agent_metrics.retain(|agent_id, metrics| {
+                let last_heard = metrics.last_heartbeat.map_or(metrics.last_request, |heartbeat| heartbeat.max(metrics.last_request));
+                let evict = metrics.stale && metrics.in_flight == 0 && (now - last_heard).to_std().is_ok_and(|idle| idle > evict_after);
+                if evict {
+                    sweep.evicted.push(agent_id.clone());
+                }
+                !evict
+            });
+            if !sweep.evicted.is_empty() {
+                sweep.evicted.sort();
+                tracing::info!("Evicted {} stale agents: {}", sweep.evicted.len(), sweep.evicted.join(", "));
+                self.audit_admin("agents_evicted", "*", format!("evicted {} stale agents: {}", sweep.evicted.len(), sweep.evicted.join(", ")));
+            }
+        }
+        sweep.stale.sort();
+        sweep.recovered.sort();
+        sweep
+    }
+
+    /// Every `interval`, sweeps for stale agents
+    pub fn sweep_stale_agents_every(self: &Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut sweep = tokio::time::interval(interval);
+            sweep.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                sweep.tick().await;
+                service.sweep_stale_agents();
+            }
+        })
+    }
+
+    /// The tenant of the agent's registration, else of the key that first used it
+    fn agent_owner(&self, agent_id: &str, metrics: Option<&AgentMetrics>) -> Option<String> {
+        let registered = self.agents.get(agent_id).and_then(|registration| registration.tenant);
+        registered.or_else(|| metrics.and_then(|metrics| metrics.tenant.clone()))
+    }
+
+    /// Whether `agent_id` is known, registered or seen, and belongs to a
+    /// tenant other than the caller's
+    pub(crate) fn hidden_agent(&self, tenancy: &Tenancy, agent_id: &str) -> bool {
+        if *tenancy == Tenancy::All {
+            return false;
+        }
+        let metrics = self.agent_metrics.get(agent_id);
+        if metrics.is_none() && self.agents.get(agent_id).is_none() {
+            return false;
+        }
+        !tenancy.sees(self.agent_owner(agent_id, metrics.as_deref()).as_deref())
+    }
+
+    /// Another tenant's agent is answered as if it didn't exist
+    fn visible_agent(&self, tenancy: &Tenancy, agent_id: &str) -> Result<(), MCPError> {
+        if self.hidden_agent(tenancy, agent_id) {
+            return Err(MCPError::AgentNotFound(agent_id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Lets a request for `agent_id` through the caller's tenancy, before any
+    /// transport handles it. An agent first used by a tenant's key belongs to
+    /// that tenant from then on; another tenant's agent is not found.
+    pub fn admit_agent(&self, tenancy: &Tenancy, agent_id: &str) -> Result<(), MCPError> {
+        if let Some(tenant) = tenancy.tenant() {
+            if check_id("agent_id", agent_id).is_none() && self.agents.get(agent_id).is_none() {
+                self.agent_metrics
+                    .entry(agent_id.to_string())
+                    .or_insert_with(|| AgentMetrics { tenant: Some(tenant.to_string()), ..AgentMetrics::new() });
+            }
+        }
+        self.visible_agent(tenancy, agent_id)
+    }
+
+    fn audit_admin(&self, action: &str, agent_id: &str, outcome: String) {
+        if let Some(audit) = &self.audit {
+            audit.record(AuditRecord::admin(action, agent_id, outcome));
+        }
+    }
+
+    /// An agent seen since `agents.stale_after_secs` before `now`, or
+    /// registered since then when never seen, isn't stale
+    fn agent_summary(
+        &self,
+        agent_id: &str,
+        metrics: Option<&AgentMetrics>,
+        registration: Option<AgentRegistration>,
+        now: DateTime<Utc>,
+    ) -> AgentSummary {
+        let stale = self.is_stale(metrics, registration.as_ref(), now);
+        AgentSummary {
+            agent_id: agent_id.to_string(),
+            registration,
+            current_load: metrics.map_or(0.0, |metrics| metrics.current_load),
+            in_flight: metrics.map_or(0, |metrics| metrics.in_flight),
+            last_seen: metrics.map(|metrics| metrics.last_request),
+            stale,
+            last_heartbeat: metrics.and_then(|metrics| metrics.last_heartbeat),
+            queue_depth: metrics.and_then(|metrics| metrics.queue_depth),
+            total_requests: metrics.map_or(0, |metrics| metrics.total_requests),
+            success_rate: metrics.map_or(1.0, |metrics| metrics.success_rate),
+            cancelled_requests: metrics.map_or(0, |metrics| metrics.cancelled_requests),
+            throttled_delayed: metrics.map_or(0, |metrics| metrics.throttled_delayed),
+            throttled_rejected: metrics.map_or(0, |metrics| metrics.throttled_rejected),
+        }
+    }
+
+    /// Registers the agent with the caller's tenant; another tenant's agent
+    /// is not found rather than taken
+    pub fn handle_register_agent(&self, tenancy: &Tenancy, spec: AgentSpec) -> Result<AgentRegistration, MCPError> {
+        let agent_id = spec.agent_id.clone().unwrap_or_default();
+        self.visible_agent(tenancy, &agent_id)?;
+        let mut errors = check_agent(&agent_id, &spec).err().unwrap_or_default();
+        errors.extend(self.check_specialty(&spec.specialty));
+        errors.extend(self.check_template("defaults.template", spec.defaults.template.as_deref()));
+        if !errors.is_empty() {
+            return Err(MCPError::InvalidFields(errors));
+        }
+        let registration = self.agents.register(&agent_id, spec, tenancy.tenant())?.ok_or_else(|| MCPError::AgentExists(agent_id.clone()))?;
+        tracing::info!("Agent {} registered as {}", agent_id, registration.specialty);
+        Ok(registration)
+    }
+
+    /// Replaces the registration of `agent_id`; a body naming another id is refused
+    pub fn handle_update_agent(&self, tenancy: &Tenancy, agent_id: &str, spec: AgentSpec) -> Result<AgentRegistration, MCPError> {
+        self.visible_agent(tenancy, agent_id)?;
+        let mut errors = check_agent(agent_id, &spec).err().unwrap_or_default();
+        errors.extend(self.check_specialty(&spec.specialty));
+        errors.extend(self.check_template("defaults.template", spec.defaults.template.as_deref()));
+        if let Some(other) = spec.agent_id.as_deref().filter(|other| *other != agent_id) {
+            errors.push(FieldError::new("agent_id", format!("'{}' as in the path, or absent", agent_id), other));
+        }
+        if !errors.is_empty() {
+            return Err(MCPError::InvalidFields(errors));
+        }
+        self.agents.update(agent_id, spec)?.ok_or_else(|| MCPError::AgentNotFound(agent_id.to_string()))
+    }
+
+    /// Forgets the registration; the agent's metrics are kept
+    pub fn handle_deregister_agent(&self, tenancy: &Tenancy, agent_id: &str) -> Result<AgentRegistration, MCPError> {
+        self.visible_agent(tenancy, agent_id)?;
+        let removed = self.agents.deregister(agent_id)?.ok_or_else(|| MCPError::AgentNotFound(agent_id.to_string()))?;
+        tracing::info!("Agent {} deregistered", agent_id);
+        Ok(removed)
+    }
+
+    /// Fills the params a request left out from the agent's defaults (see
+    /// `AgentRegistry::defaults`), then `model` from the specialty, then
+    /// `model` and `specialty` from `request_defaults`, noting in
+    /// `params.sources` where each came from. In strict mode a specialty
+    /// other than the registered one is refused.
+    fn apply_defaults(&self, params: &mut MCPParams) -> Result<(), MCPError> {
+        if let Some(registration) = self.agents.get(&params.agent_id) {
+            let claimed = params.specialty.trim();
+            if self.agents.strict() && !claimed.is_empty() && params.specialty != registration.specialty {
+                return Err(MCPError::SpecialtyMismatch {
+                    agent_id: params.agent_id.clone(),
+                    registered: registration.specialty,
+                    claimed: params.specialty.clone(),
+                });
+            }
+        }
+        let defaults = self.agents.defaults(&params.agent_id);
+        let sources = &mut params.sources;
+        let agent_default = |source: &mut ParamSource, default: Option<String>| {
+            *source = match default {
+                Some(_) => ParamSource::AgentDefault,
+                None => ParamSource::ServerDefault,
+            };
+            default
+        };
+        if params.specialty.trim().is_empty() {
+            params.specialty = agent_default(&mut sources.specialty, defaults.specialty).unwrap_or_else(|| self.request_defaults.specialty.clone());
+        }
+        if params.model.trim().is_empty() {
+            params.model = agent_default(&mut sources.model, defaults.model)
+                .or_else(|| self.specialties.resolve(&params.specialty).and_then(|specialty| specialty.default_model))
+                .unwrap_or_else(|| self.request_defaults.model.clone());
+        }
+        if params.template.is_none() {
+            params.template = agent_default(&mut sources.template, defaults.template);
+        }
+        fill_param(&mut params.max_tokens, &mut sources.max_tokens, defaults.max_tokens);
+        fill_param(&mut params.temperature, &mut sources.temperature, defaults.temperature);
+        fill_param(&mut params.use_rag, &mut sources.use_rag, defaults.use_rag);
+        fill_param(&mut params.context_window, &mut sources.context_window, defaults.context_window);
+        Ok(())
+    }
+
+    /// A named template that isn't loaded
+    fn check_template(&self, field: &str, template: Option<&str>) -> Option<FieldError> {
+        let template = template.filter(|template| self.templates.get(template).is_none())?;
+        Some(FieldError::new(field, format!("one of {}", self.templates.names().join(", ")), template))
+    }
+
+    /// In strict mode, a specialty that isn't configured
+    fn check_specialty(&self, specialty: &str) -> Option<FieldError> {
+        (self.specialties.strict() && self.specialties.resolve(specialty).is_none())
+            .then(|| FieldError::new("specialty", format!("one of {}", self.specialties.names().join(", ")), specialty))
+    }
+
+    pub fn handle_list_specialties(&self) -> SpecialtiesResponse {
+        self.specialties.list()
+    }
+
+    /// Reads `specialties.path` again; on failure the current ones stay
+    pub fn handle_reload_specialties(&self) -> Result<SpecialtiesResponse, MCPError> {
+        self.specialties.reload().ok_or(MCPError::NotConfigured("specialties path"))??;
+        Ok(self.specialties.list())
+    }
+
+    /// Cancels the running request with this id, over whichever transport it
+    /// came. It fails with `cancelled`, counted for its agent and audited.
+    pub fn handle_cancel(&self, request_id: &str) -> Result<(), MCPError> {
+        self.running.cancel(request_id)?;
+        tracing::info!("Request {} cancelled by its client", request_id);
+        Ok(())
+    }
+
+    /// Empties the response cache, returning how many entries it held
+    pub fn handle_clear_cache(&self) -> usize {
+        let cleared = self.response_cache.clear();
+        tracing::info!("Cleared {} cached responses", cleared);
+        cleared
+    }
+
+    pub async fn handle_audit_query(&self, query: AuditQuery) -> Result<AuditResponse, MCPError> {
+        let audit = self.audit.as_ref().ok_or(MCPError::NotConfigured("audit sink"))?;
+        let records = audit.query(query).await.map_err(MCPError::Internal)?;
+        Ok(AuditResponse { records })
+    }
+
+    /// Sends recorded `llm_inference` requests through the pipeline as it is
+    /// now and compares each with its replay; see `crate::replay`. Results
+    /// come in the order the records were selected, with at most
+    /// `ReplayConfig::concurrency` replays running at once.
+    pub async fn handle_audit_replay(&self, request: ReplayRequest) -> Result<ReplayResponse, MCPError> {
+        let audit = self.audit.as_ref().ok_or(MCPError::NotConfigured("audit sink"))?;
+        let max_records = self.replay.max_records;
+        let mut errors = Vec::new();
+        if request.request_ids.len() > max_records {
+            errors.push(FieldError::new("request_ids", format!("at most {} ids", max_records), request.request_ids.len()));
+        }
+        if let Some(limit) = request.limit.filter(|limit| *limit == 0 || *limit > max_records) {
+            errors.push(FieldError::new("limit", format!("between 1 and {}", max_records), limit));
+        }
+        if !errors.is_empty() {
+            return Err(MCPError::InvalidFields(errors));
+        }
+
+        // Ids not in the log are reported rather than refused
+        let selected: Vec<Result<AuditRecord, String>> = if request.request_ids.is_empty() {
+            let query = AuditQuery {
+                agent_id: request.agent_id,
+                since: request.since,
+                until: request.until,
+                limit: Some(request.limit.unwrap_or(max_records)),
+            };
+            audit.query(query).await.map_err(MCPError::Internal)?.into_iter().map(Ok).collect()
+        } else {
+            let mut request_ids = request.request_ids;
+            let mut seen = HashSet::new();
+            request_ids.retain(|request_id| seen.insert(request_id.clone()));
+            let found = audit.find(request_ids.clone()).await.map_err(MCPError::Internal)?;
+            request_ids
+                .into_iter()
+                .map(|request_id| found.iter().find(|record| record.request_id == request_id).cloned().ok_or(request_id))
+                .collect()
+        };
+
+        let mode = request.mode;
+        let view = self.replay_view(mode);
+        let results: Vec<ReplayResult> = futures::stream::iter(selected)
+            .map(|selected| self.replay_record(&view, selected, mode))
+            .buffered(self.replay.concurrency.max(1))
+            .collect()
+            .await;
+        // The replay ids returned can be looked up at once
+        audit.flush().await;
+        let summary = ReplaySummary::new(&results);
+        tracing::info!(
+            "Replayed {} of {} audited requests in {} mode: {} failed, {} skipped",
+            summary.replayed,
+            summary.selected,
+            mode.as_str(),
+            summary.failed,
+            summary.skipped
+        );
+        Ok(ReplayResponse { mode, results, summary })
+    }
+
+    /// Replays one selected record on `view`, auditing the replay as one
+    async fn replay_record(&self, view: &VoidShrineMCP, selected: Result<AuditRecord, String>, mode: ReplayMode) -> ReplayResult {
+        let result = match selected {
+            Err(request_id) => ReplayResult::skipped(&request_id, None, "not in the audit log"),
+            Ok(original) => match replayable_params(&original) {
+                Err(reason) => ReplayResult::skipped(&original.request_id, Some(&original.agent_id), reason),
+                Ok(params) => {
+                    let mut params = params.clone();
+                    // The session has moved on since; its history now isn't the history then
+                    params.session_id = None;
+                    params.dry_run = mode == ReplayMode::DryRun;
+                    params.dry_run_skip_conditions = false;
+                    let replay_id = Uuid::new_v4().to_string();
+                    let (result, record) = match view.replay_inference(replay_id.clone(), params.clone()).await {
+                        Ok(response) => (
+                            ReplayResult {
+                                request_id: original.request_id.clone(),
+                                agent_id: Some(original.agent_id.clone()),
+                                outcome: ReplayOutcome::Replayed,
+                                reason: None,
+                                replay_id: Some(replay_id),
+                                comparison: Some(ReplayComparison::new(&original, &response.result)),
+                            },
+                            AuditRecord::from_response(&params.agent_id, "llm_inference", &response.result, &response.metadata),
+                        ),
+                        Err(error) => (
+                            ReplayResult {
+                                request_id: original.request_id.clone(),
+                                agent_id: Some(original.agent_id.clone()),
+                                outcome: ReplayOutcome::Failed,
+                                reason: Some(error.to_string()),
+                                replay_id: Some(replay_id.clone()),
+                                comparison: None,
+                            },
+                            AuditRecord::from_failure(&replay_id, &params.agent_id, "llm_inference", &error),
+                        ),
+                    };
+                    if let Some(audit) = &self.audit {
+                        audit.record(record.with_params(Some(params)).replaying(&original.request_id));
+                    }
+                    result
+                }
+            },
+        };
+        self.metrics.replayed(mode.as_str(), result.outcome.as_str());
+        result
+    }
+
+    /// This service as replays see it: the same knowledge base, templates,
+    /// backends, breakers and concurrency slots, but its own agent metrics,
+    /// counters and Prometheus registry, no response cache, chaos or audit
+    /// log, and only `MockBackend` in `ReplayMode::Mock`
+    fn replay_view(&self, mode: ReplayMode) -> Self {
+        Self {
+            agent_metrics: Arc::new(DashMap::new()),
+            rag_engine: Arc::clone(&self.rag_engine),
+            rag_engines: Arc::clone(&self.rag_engines),
+            chaos_config: Arc::new(RwLock::new(ChaosConfig { enabled: false, ..ChaosConfig::default() })),
+            backup_dir: None,
+            rag_tasks: Arc::new(RagTasks::default()),
+            backends: match mode {
+                ReplayMode::Mock => SharedBackends::new(Self::mock_backends(&self.specialties)),
+                ReplayMode::Live | ReplayMode::DryRun => self.backends.clone(),
+            },
+            param_limits: self.param_limits.clone(),
+            request_defaults: self.request_defaults.clone(),
+            body_limits: self.body_limits,
+            rate_limiter: Arc::clone(&self.rate_limiter),
+            throttle: Arc::clone(&self.throttle),
+            load: self.load,
+            scaling: self.scaling.clone(),
+            scaling_log: Arc::new(ScalingLog::default()),
+            batch: self.batch.clone(),
+            replay: self.replay.clone(),
+            timeouts: self.timeouts.clone(),
+            retries: self.retries.clone(),
+            breakers: Arc::clone(&self.breakers),
+            model_catalog: Arc::clone(&self.model_catalog),
+            concurrency: Arc::clone(&self.concurrency),
+            overload: Arc::clone(&self.overload),
+            counters: Arc::new(ServerCounters::default()),
+            webhooks: Arc::clone(&self.webhooks),
+            content_filters: Arc::clone(&self.content_filters),
+            context_screen: Arc::clone(&self.context_screen),
+            metrics: Arc::new(Metrics::default()),
+            route_metrics: Arc::clone(&self.route_metrics),
+            confidence: self.confidence.clone(),
+            ethics: Arc::clone(&self.ethics),
+            request_ids: Arc::clone(&self.request_ids),
+            running: Arc::clone(&self.running),
+            audit: None,
+            sessions: Arc::clone(&self.sessions),
+            agents: Arc::clone(&self.agents),
+            specialties: Arc::clone(&self.specialties),
+            templates: Arc::clone(&self.templates),
+            response_cache: Arc::new(ResponseCache::new(CacheConfig { enabled: false, ..CacheConfig::default() })),
+            idempotency: Arc::new(IdempotencyStore::default()),
+            tokenizer: Arc::clone(&self.tokenizer),
+            tokens: Arc::clone(&self.tokens),
+            metrics_store: None,
+            usage: Arc::clone(&self.usage),
+            history: Arc::new(ActivityHistory::default()),
+            export_max_span_days: self.export_max_span_days,
+            quotas: Arc::clone(&self.quotas),
+            live: Arc::new(LiveFeed::default()),
+            auth: self.auth.clone(),
+            shutdown: Arc::clone(&self.shutdown),
+            require_rag: self.require_rag,
+            rag_failure_policy: self.rag_failure_policy,
+            chaos_dice: Arc::clone(&self.chaos_dice),
+            chaos_stats: Arc::new(ChaosLedger::default()),
+            clock: Arc::clone(&self.clock),
+            config_source: Arc::clone(&self.config_source),
+            startup_checks: Arc::clone(&self.startup_checks),
+        }
+    }
+
+    /// Handles replayed params as `process_mcp_request` would an
+    /// `llm_inference` request, less chaos, rate limits, throttling,
+    /// sessions and agent metrics
+    async fn replay_inference(&self, request_id: String, mut params: MCPParams) -> Result<MCPResponse, MCPError> {
+        let deadline = self.timeouts.deadline(&params);
+        let replay = async {
+            self.apply_defaults(&mut params)?;
+            self.admit_unconditioned(&params)?;
+            let _permit = self.concurrency.acquire().await.map_err(|retry_after| self.shed(retry_after))?;
+            let rag_unavailable = deadline.retrieval(self.check_rag_available(&params, params.use_rag)).await?;
+            let agent_id = params.agent_id.clone();
+            let (mut result, provenance) = self.handle_llm_inference(params, deadline).await?;
+            let content_filter = self.screen_response(&mut result).await;
+            let timestamp = self.clock.now();
+            let chaos = ChaosRoll::skipped();
+            Ok(MCPResponse {
+                metadata: MCPMetadata {
+                    void_shrine_token: self.tokens.issue(&request_id, &agent_id, timestamp),
+                    request_id: request_id.clone(),
+                    timestamp,
+                    chaos_applied: false,
+                    chaos_type: None,
+                    chaos_decision: chaos.decision,
+                    chaos_seed: chaos.seed,
+                    would_have_applied: None,
+                    moral_recentered: result.moral_recentering.as_ref().is_some_and(|report| report.recentered),
+                    rag_unavailable,
+                    rag_error: provenance.rag_error,
+                    session_turn: None,
+                    cached: provenance.cached,
+                    served_model: provenance.chain.as_ref().map(|step| step.model.clone()),
+                    fallback_depth: provenance.chain.map_or(0, |step| step.depth),
+                    content_filter,
+                    retrieval_query: provenance.retrieval_query,
+                    idempotent_replay: false,
+                    context_flags: provenance.context_flags,
+                },
+                result,
+            })
+        };
+        tokio::select! {
+            biased;
+            response = replay => response,
+            _ = deadline.expired() => Err(deadline.exceeded()),
+        }
+    }
+
+    /// Rejects params outside `param_limits` with every offending field
+    pub fn validate_params(&self, params: &MCPParams) -> Result<(), MCPError> {
+        let mut errors = self.param_limits.check(params, self.tokenizer.as_ref()).err().unwrap_or_default();
+        // Left empty, it will be the agent's default or the server's
+        let specialty = match params.specialty.trim() {
+            "" => self.agents.defaults(&params.agent_id).specialty.unwrap_or_else(|| self.request_defaults.specialty.clone()),
+            specialty => specialty.to_string(),
+        };
+        errors.extend(self.check_specialty(&specialty));
+        errors.extend(self.check_template("template", params.template.as_deref()));
+        errors.extend(self.rag_engines.check("rag_engine", params.rag_engine.as_deref()));
+        if !errors.is_empty() {
+            return Err(MCPError::InvalidFields(errors));
+        }
+        Ok(())
+    }
+
+    /// What `handle_mcp_request` would refuse before doing anything, for
+    /// requests accepted now and handled later. Rate limits and throttling
+    /// apply when they are handled.
+    pub fn validate_request(&self, request: &MCPRequest) -> Result<(), MCPError> {
+        McpMethod::parse(&request.method)?;
+        if let Some(request_id) = &request.request_id {
+            validate_request_id(request_id)?;
+        }
+        let mut params = request.params.clone();
+        self.apply_defaults(&mut params)?;
+        self.validate_params(&params)
+    }
+
+    /// Validates params, unless draining; the part of `admit` a dry run
+    /// skipping conditions meets
+    fn admit_unconditioned(&self, params: &MCPParams) -> Result<std::time::Duration, MCPError> {
+        if self.shutdown.is_draining() {
+            return Err(MCPError::ShuttingDown);
+        }
+        self.validate_params(params)?;
+        Ok(std::time::Duration::ZERO)
+    }
+
+    /// Validates params, applies load-based throttling, then takes a token from
+    /// the agent's rate limit bucket. Nothing is admitted while draining.
+    /// Returns how long throttling holds the request before it may be handled.
+    pub fn admit(&self, params: &MCPParams) -> Result<std::time::Duration, MCPError> {
+        self.admit_unconditioned(params)?;
+        let max_concurrency = self.agents.get(&params.agent_id).and_then(|registration| registration.max_concurrency);
+        let in_flight = self.agent_metrics.get(&params.agent_id).map_or(0, |metrics| metrics.in_flight);
+        if max_concurrency.is_some_and(|max| in_flight >= max) {
+            tracing::warn!("Refusing a request from {} with {} in flight, its registered maximum", params.agent_id, in_flight);
+            self.record_throttled(&params.agent_id, "rejected");
+            return Err(MCPError::Throttled { agent_id: params.agent_id.clone(), retry_after: CONCURRENCY_RETRY_AFTER });
+        }
+        let load = self.agent_metrics.get_mut(&params.agent_id).map(|mut metrics| {
+            metrics.refresh_load(&self.load, std::time::Instant::now(), self.clock.now());
+            metrics.current_load
+        });
+        let throttle = load.map_or(Throttle::Proceed, |load| self.throttle_config().decide(load));
+        if let Some(load) = load {
+            self.note_throttling(&params.agent_id, load, throttle);
+        }
+        if let Throttle::Reject { retry_after } = throttle {
+            tracing::warn!("Refusing a request from {} at load {:.2}", params.agent_id, load.unwrap_or_default());
+            self.record_throttled(&params.agent_id, "rejected");
+            return Err(MCPError::Throttled { agent_id: params.agent_id.clone(), retry_after });
+        }
+        self.rate_limiter.try_acquire(&params.agent_id).map_err(|over| MCPError::RateLimited {
+            agent_id: params.agent_id.clone(),
+            retry_after: over.retry_after,
+        })?;
+        match throttle {
+            Throttle::Delay(delay) => {
+                tracing::debug!("Delaying a request from {} by {} ms", params.agent_id, delay.as_millis());
+                self.record_throttled(&params.agent_id, "delayed");
+                Ok(delay)
+            }
+            _ => Ok(std::time::Duration::ZERO),
+        }
+    }
+
+    /// Sends a webhook when the agent's requests start or stop being throttled
+    fn note_throttling(&self, agent_id: &str, load: f64, throttle: Throttle) {
+        let throttling = throttle != Throttle::Proceed;
+        let changed = self.agent_metrics.get_mut(agent_id).is_some_and(|mut metrics| {
+            std::mem::replace(&mut metrics.throttling, throttling) != throttling
+        });
+        if !changed {
+            return;
+        }
+        let (event, payload) = match throttle {
+            Throttle::Proceed => (WebhookEventKind::ThrottleEnded, serde_json::json!({ "load": load })),
+            Throttle::Delay(delay) => (
+                WebhookEventKind::ThrottleStarted,
+                serde_json::json!({ "load": load, "action": "delay", "delay_ms": delay.as_millis() as u64 }),
+            ),
+            Throttle::Reject { retry_after } => (
+                WebhookEventKind::ThrottleStarted,
+                serde_json::json!({ "load": load, "action": "reject", "retry_after_secs": retry_after.as_secs_f64() }),
+            ),
+        };
+        self.webhooks.notify(event, agent_id, payload);
+    }
+
+    /// The error of a request shed for want of a concurrency slot
+    fn shed(&self, retry_after: std::time::Duration) -> MCPError {
+        self.metrics.request_shed();
+        tracing::warn!("Shedding a request: all {} concurrency slots are in use", self.concurrency.status().max_in_flight);
+        MCPError::Overloaded { retry_after }
+    }
+
+    /// Sheds a new inference request while the server is overloaded. The
+    /// overload starting and ending are logged and counted with the signals
+    /// behind them, as is each request shed.
+    fn check_overload(&self) -> Result<(), MCPError> {
+        let verdict = self.overload.check(std::time::Instant::now(), self.concurrency.status().in_use);
+        match (verdict.overloaded_by, verdict.changed) {
+            (Some(signal), true) => {
+                self.metrics.overload_tripped(signal.as_str());
+                tracing::warn!("Overloaded by {} ({}); shedding new inference requests", signal.as_str(), verdict.signals);
+            }
+            (None, true) => tracing::info!("No longer overloaded ({}); admitting inference requests", verdict.signals),
+            _ => {}
+        }
+        let Some(signal) = verdict.overloaded_by else {
+            return Ok(());
+        };
+        self.metrics.overload_shed(signal.as_str());
+        tracing::warn!("Shedding an inference request: overloaded by {} ({})", signal.as_str(), verdict.signals);
+        Err(MCPError::Overloaded { retry_after: verdict.retry_after })
+    }
+
+    /// The standing of each quota capping `agent_id` in its current window
+    fn quota_standings(&self, agent_id: &str) -> Vec<QuotaStanding> {
+        let owner = |agent_id: &str| self.agent_owner(agent_id, self.agent_metrics.get(agent_id).as_deref());
+        let now = self.clock.now();
+        let rules = self.quotas.rules_for(agent_id, owner(agent_id).as_deref());
+        rules.iter().map(|rule| rule.standing(&self.usage, now, owner)).collect()
+    }
+
+    /// Refuses an inference request that would take any quota capping its
+    /// agent over the cap: its `max_tokens` for token quotas, itself for
+    /// request quotas
+    fn check_quotas(&self, params: &MCPParams) -> Result<(), MCPError> {
+        for standing in self.quota_standings(&params.agent_id) {
+            let cost = match standing.rule.metric {
+                QuotaMetric::Tokens => u64::from(params.max_tokens),
+                QuotaMetric::Requests => 1,
+            };
+            if standing.allows(cost) {
+                continue;
+            }
+            self.metrics.quota_rejected(&standing.rule.id);
+            tracing::warn!(
+                "Refusing a request from {}: quota {} has {} of {} {} used",
+                params.agent_id,
+                standing.rule.id,
+                standing.consumed,
+                standing.rule.limit,
+                standing.rule.metric.as_str()
+            );
+            let retry_after = (standing.resets_at - self.clock.now()).to_std().unwrap_or_default();
+            return Err(MCPError::QuotaExceeded { standing: Box::new(standing), retry_after });
+        }
+        Ok(())
+    }
+
+    /// Warns, by log and webhook, of each quota capping `agent_id` that its
+    /// usage has just taken past `QuotaConfig::warn_percent`
+    fn warn_of_quotas(&self, agent_id: &str) {
+        for standing in self.quota_standings(agent_id) {
+            if !self.quotas.should_warn(&standing) {
+                continue;
+            }
+            let percent = standing.percent();
+            tracing::warn!(
+                "Quota {} is at {:.0}% ({} of {} {}) until {}",
+                standing.rule.id,
+                percent,
+                standing.consumed,
+                standing.rule.limit,
+                standing.rule.metric.as_str(),
+                standing.resets_at.to_rfc3339()
+            );
+            let payload = serde_json::json!({ "quota": standing, "percent": percent });
+            self.webhooks.notify(WebhookEventKind::QuotaWarning, agent_id, payload);
+        }
+    }
+
+    fn record_throttled(&self, agent_id: &str, outcome: &str) {
+        if let Some(mut metrics) = self.agent_metrics.get_mut(agent_id) {
+            match outcome {
+                "rejected" => metrics.throttled_rejected += 1,
+                _ => metrics.throttled_delayed += 1,
+            }
+            metrics.stats.record_throttled(self.clock.now());
+        }
+        self.metrics.throttled(agent_id, outcome);
+        self.live.publish(LiveEvent::ThrottleTriggered { agent_id: agent_id.to_string(), outcome: outcome.to_string() });
+    }
+
+    pub async fn handle_mcp_request(&self, request: MCPRequest) -> Result<MCPResponse, FailedRequest> {
+        self.handle_request(request, None).await
+    }
+
+    /// Handles a request already holding a concurrency slot, as queued jobs
+    /// do once one frees up, so it isn't shed
+    pub async fn handle_queued_request(&self, request: MCPRequest, permit: ConcurrencyPermit) -> Result<MCPResponse, FailedRequest> {
+        self.handle_request(request, Some(permit)).await
+    }
+
+    /// Runs a request with an idempotency key only if its key is new, else
+    /// answers with the response kept for it or refuses it
+    async fn handle_request(&self, request: MCPRequest, permit: Option<ConcurrencyPermit>) -> Result<MCPResponse, FailedRequest> {
+        let Some(key) = request.idempotency_key.clone() else {
+            return self.run_request(request, permit).await;
+        };
+        let refused = |error: MCPError| FailedRequest { request_id: None, error };
+        if let Some(error) = check_id("idempotency_key", &key) {
+            return Err(refused(MCPError::InvalidFields(vec![error])));
+        }
+        let agent_id = request.params.agent_id.clone();
+        let fingerprint = idempotency::fingerprint(&request.method, &request.params);
+        match self.idempotency.begin(&agent_id, &key, fingerprint, self.clock.now()) {
+            Begin::Run(claim) => {
+                let response = self.run_request(request, permit).await;
+                if let Ok(response) = &response {
+                    claim.complete(response, self.clock.now());
+                }
+                response
+            }
+            Begin::Replay(mut response) => {
+                tracing::info!("Answering a repeat of idempotency key {} from {} with request {}", key, agent_id, response.metadata.request_id);
+                response.metadata.idempotent_replay = true;
+                Ok(*response)
+            }
+            Begin::Conflict => Err(refused(MCPError::IdempotencyConflict(key))),
+            Begin::InProgress => Err(refused(MCPError::IdempotencyInProgress(key))),
+        }
+    }
+
+    async fn run_request(&self, mut request: MCPRequest, permit: Option<ConcurrencyPermit>) -> Result<MCPResponse, FailedRequest> {
+        let started = std::time::Instant::now();
+        let deadline = self.timeouts.deadline(&request.params);
+        let method = method_label(&request.method);
+        self.counters.record_request(&request.method);
+        let agent_id = request.params.agent_id.clone();
+        let method_name = request.method.clone();
+        // As received, so a replay goes through today's defaults
+        let audited_params = self.audit.as_ref().map(|_| request.params.clone());
+        let response = match self.assign_request_id(request.request_id.take(), &agent_id) {
+            Ok(request_id) => {
+                // Every event logged while handling the request carries its id
+                let span = trace::request_span(&request_id, &request.method, &request.params);
+                self.live.publish(LiveEvent::RequestStarted { request_id: request_id.clone(), agent_id: agent_id.clone(), method: method_name.clone() });
+                let draining = FailedRequest { request_id: Some(request_id.clone()), error: MCPError::ShuttingDown };
+                let cancelled = FailedRequest { request_id: Some(request_id.clone()), error: MCPError::Cancelled };
+                let timed_out = FailedRequest { request_id: Some(request_id.clone()), error: deadline.exceeded() };
+                let registration = self.running.register(&request_id);
+                // Cancelling drops the pipeline wherever it is, backend call included.
+                // Biased, so a stage timing out at the deadline is the one reported.
+                let response = async {
+                    tokio::select! {
+                        biased;
+                        response = self.process_mcp_request(request_id, request, deadline, permit) => response,
+                        _ = deadline.expired() => Err(timed_out),
+                        _ = self.shutdown.drain_expired() => Err(draining),
+                        _ = registration.token().cancelled() => Err(cancelled),
+                    }
+                }
+                .instrument(span.clone())
+                .await;
+                span.record("status", response.as_ref().map_or_else(|failure| failure.error.http_status(), |_| 200));
+                trace::record_outcome(&span, started, &response);
+                response
+            }
+            Err(e) => Err(e.into()),
+        };
+        let elapsed_ms = Some(started.elapsed().as_millis() as u64);
+        let request_id = match &response {
+            Ok(response) => Some(response.metadata.request_id.clone()),
+            Err(failure) => failure.request_id.clone(),
+        };
+        let status = match &response {
+            Ok(response) => {
+                let token_count = Some(response.result.metrics.token_count);
+                self.record_tokens(&agent_id, &response.result.metrics);
+                self.record_outcome(&agent_id, Observation { response_time_ms: elapsed_ms, success: true, token_count });
+                if let Some(audit) = &self.audit {
+                    audit.record(AuditRecord::from_response(&agent_id, &method_name, &response.result, &response.metadata).with_params(audited_params));
+                }
+                200
+            }
+            Err(failure) => {
+                // Requests refused before getting an id never reached a backend
+                if let (Some(audit), Some(request_id)) = (&self.audit, &failure.request_id) {
+                    audit.record(AuditRecord::from_failure(request_id, &agent_id, &method_name, &failure.error).with_params(audited_params));
+                }
+                self.record_error(&failure.error);
+                if failure.error.counts_as_failure() {
+                    self.record_outcome(&agent_id, Observation { response_time_ms: elapsed_ms, success: false, token_count: None });
+                }
+                if let MCPError::Cancelled = failure.error {
+                    self.record_cancelled(&agent_id);
+                }
+                failure.error.http_status()
+            }
+        };
+        self.metrics.observe_request(method, status, started.elapsed());
+        self.live.publish(LiveEvent::RequestCompleted { request_id, agent_id, method: method_name, status, latency_ms: started.elapsed().as_millis() as u64 });
+        response
+    }
+
+    /// Runs `request.method` over every item as if each had been sent alone,
+    /// so each is validated, rate limited, throttled, cached and counted for its
+    /// agent, with at most `BatchConfig::concurrency` in flight. Items share the
+    /// knowledge base's read lock while they search it. A failed item fails
+    /// only itself; an empty, oversized or unsupported batch fails whole.
+    pub async fn handle_batch(&self, request: BatchRequest) -> Result<BatchResponse, MCPError> {
+        McpMethod::parse(&request.method)?;
+        let count = request.items.len();
+        if count == 0 || count > self.batch.max_items {
+            let constraint = format!("between 1 and {} items", self.batch.max_items);
+            return Err(MCPError::InvalidFields(vec![FieldError::new("items", constraint, count)]));
+        }
+
+        let batch_id = Uuid::new_v4().to_string();
+        let started_at = self.clock.now();
+        let started = std::time::Instant::now();
+        tracing::info!("Batch {}: {} {} items", batch_id, count, request.method);
+        let method = request.method;
+        let items: Vec<BatchItem> = futures::stream::iter(request.items.into_iter().enumerate())
+            .map(|(index, params)| {
+                let request = MCPRequest { method: method.clone(), params, request_id: None, idempotency_key: None };
+                async move {
+                    match self.handle_mcp_request(request).await {
+                        Ok(response) => BatchItem { index, status: 200, response: Some(response), error: None },
+                        Err(failure) => BatchItem {
+                            index,
+                            status: failure.error.http_status(),
+                            response: None,
+                            error: Some(ErrorResponse::from(&failure)),
+                        },
+                    }
+                }
+            })
+            .buffered(self.batch.concurrency.max(1))
+            .collect()
+            .await;
+
+        let succeeded = items.iter().filter(|item| item.response.is_some()).count();
+        Ok(BatchResponse {
+            batch_id,
+            started_at,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            summary: BatchSummary { succeeded, failed: count - succeeded },
+            items,
+        })
+    }
+
+    /// The client's id once validated, or a fresh one. A repeat of a recent
+    /// id is logged but allowed.
+    fn assign_request_id(&self, supplied: Option<String>, agent_id: &str) -> Result<String, MCPError> {
+        let Some(request_id) = supplied else {
+            return Ok(Uuid::new_v4().to_string());
+        };
+        validate_request_id(&request_id)?;
+        if !self.request_ids.insert(&request_id) {
+            tracing::warn!("Request id {} from {} repeats a recent request", request_id, agent_id);
+        }
+        Ok(request_id)
+    }
+
+    async fn process_mcp_request(
+        &self,
+        request_id: String,
+        mut request: MCPRequest,
+        deadline: Deadline,
+        permit: Option<ConcurrencyPermit>,
+    ) -> Result<MCPResponse, FailedRequest> {
+        let failed = |error: MCPError| FailedRequest { request_id: Some(request_id.clone()), error };
+        self.apply_defaults(&mut request.params).map_err(failed)?;
+        // Queued jobs were accepted already and wait their turn instead
+        let sheddable = sheddable(&request.method, &request.params);
+        if sheddable && permit.is_none() {
+            self.check_overload().map_err(failed)?;
+        }
+        // Queued jobs and batch items are each checked as they run
+        if sheddable {
+            self.check_quotas(&request.params).map_err(failed)?;
+        }
+        // A dry run may preview the prompt as if the server were idle
+        let unconditioned = request.params.dry_run && request.params.dry_run_skip_conditions;
+        let throttle_delay = match unconditioned {
+            true => self.admit_unconditioned(&request.params).map_err(failed)?,
+            false => self.admit(&request.params).map_err(failed)?,
+        };
+        let queued = self.track_waiting(&request.params.agent_id);
+        if !throttle_delay.is_zero() {
+            tokio::time::sleep(throttle_delay).await;
+        }
+        let _permit = match permit {
+            Some(permit) => permit,
+            None => {
+                let waiting = std::time::Instant::now();
+                let acquired = self.concurrency.acquire().await;
+                if sheddable {
+                    self.overload.record_wait(std::time::Instant::now(), waiting.elapsed());
+                }
+                acquired.map_err(|retry_after| failed(self.shed(retry_after)))?
+            }
+        };
+        drop(queued);
+        let _timing = sheddable.then(|| self.overload.time());
+        let _in_flight = self.track_in_flight(&request.params.agent_id);
+        
+        tracing::info!("Processing MCP request: {} for agent: {}", request.method, request.params.agent_id);
+
+        // Update agent metrics
+        self.update_agent_metrics(&request.params.agent_id);
+        // Unsupported methods are refused once chaos had its chance at them
+        let method = McpMethod::parse(&request.method);
+
+        // rag_answer fails on its own without an engine
+        let wants_rag = match method {
+            Ok(McpMethod::LlmInference) => request.params.use_rag,
+            Ok(McpMethod::RagQuery) => true,
+            Ok(McpMethod::RagAnswer) | Err(_) => false,
+        };
+        let rag_unavailable = deadline.retrieval(self.check_rag_available(&request.params, wants_rag)).await.map_err(failed)?;
+
+        // Apply chaos engineering
+        let (chaos_type, would_have_applied, mut chaos_roll) = match unconditioned {
+            true => (None, None, ChaosRoll::skipped()),
+            false => self.apply_chaos_if_enabled(&request.params, &request.method).await.map_err(failed)?,
+        };
+        let params = &request.params;
+        // A dry run's prompt never becomes a turn of its session
+        let turn = params.session_id.clone()
+            .filter(|_| matches!(method, Ok(McpMethod::LlmInference)) && !params.dry_run)
+            .map(|session_id| (session_id, params.agent_id.clone(), params.prompt.clone()));
+        let agent_id = params.agent_id.clone();
+        let verbose_confidence = params.verbose_confidence;
+        let usage = matches!(method, Ok(McpMethod::LlmInference)).then(|| (params.model.clone(), params.dry_run));
+
+        // Generate response based on method; inference's future is large
+        // enough to box rather than nest in this one
+        let result = match method.map_err(failed)? {
+            McpMethod::LlmInference => Box::pin(self.handle_llm_inference(request.params, deadline)).await,
+            McpMethod::RagQuery => self.handle_rag_query(request.params, deadline).await.map(|result| (result, Provenance::default())),
+            McpMethod::RagAnswer => self.handle_rag_answer(request.params, deadline).await.map(|result| (result, Provenance::default())),
+        };
+        let (mut result, provenance) = result.map_err(failed)?;
+        if let Some((model, dry_run)) = usage {
+            let source = match (dry_run, provenance.cached) {
+                (true, _) => UsageSource::DryRun,
+                (false, true) => UsageSource::Cached,
+                (false, false) => UsageSource::Backend,
+            };
+            let model = provenance.chain.as_ref().map_or(model, |step| step.model.clone());
+            self.record_usage(&agent_id, &model, source, &result.metrics);
+        }
+        result.metrics.throttle_delay_ms = throttle_delay.as_millis() as u64;
+        if chaos_type.as_deref() == Some("response_corruption") {
+            result.response = corrupt_text(&result.response, &mut chaos_roll.rng);
+            self.penalize_corruption(&mut result.metrics);
+        }
+        if !verbose_confidence {
+            result.metrics.confidence_breakdown = None;
+        }
+        let content_filter = self.screen_response(&mut result).await;
+        let session_turn = match turn {
+            Some((session_id, agent_id, prompt)) => Some(self.record_turn(&session_id, &agent_id, &prompt, &result.response).map_err(failed)?),
+            None => None,
+        };
+
+        let timestamp = self.clock.now();
+        let void_shrine_token = self.tokens.issue(&request_id, &agent_id, timestamp);
+        Ok(MCPResponse {
+            metadata: MCPMetadata {
+                request_id,
+                timestamp,
+                void_shrine_token,
+                chaos_applied: chaos_type.is_some(),
+                chaos_type,
+                chaos_decision: chaos_roll.decision,
+                chaos_seed: chaos_roll.seed,
+                would_have_applied,
+                moral_recentered: result.moral_recentering.as_ref().is_some_and(|report| report.recentered),
+                rag_unavailable,
+                rag_error: provenance.rag_error,
+                session_turn,
+                cached: provenance.cached,
+                served_model: provenance.chain.as_ref().map(|step| step.model.clone()),
+                fallback_depth: provenance.chain.map_or(0, |step| step.depth),
+                content_filter,
+                retrieval_query: provenance.retrieval_query,
+                idempotent_replay: false,
+                context_flags: provenance.context_flags,
+            },
+            result,
+        })
+    }
+
+    /// Runs the content filters over the response. A blocked one becomes the
+    /// refusal, without the context behind it.
+    async fn screen_response(&self, result: &mut MCPResult) -> Option<ContentFilterReport> {
+        if self.content_filters.is_empty() {
+            return None;
+        }
+        let screened = self.content_filters.screen(&result.response).await;
+        if screened.report.as_ref().is_some_and(|report| report.verdict == FilterAction::Block) {
+            result.rag_context = None;
+            result.citations = None;
+        }
+        result.response = screened.text;
+        screened.report
+    }
+
+    /// The result, and whether it came from the response cache or a fallback.
+    /// The prompt is assembled either way, since it is part of the key.
+    async fn handle_llm_inference(&self, params: MCPParams, deadline: Deadline) -> Result<(MCPResult, Provenance), MCPError> {
+        let started = std::time::Instant::now();
+        // Read before retrieval, so a change made meanwhile can only strand the entry
+        let generation = match params.use_rag {
+            true => self.params_engine(&params)?.read().await.as_ref().map(crate::rag_engine::RAGEngine::generation),
+            false => None,
+        };
+        let (user_prompt, moral_recentering) = self.recenter(&params).await?;
+        let assembled = self.assemble_prompt(&params, &user_prompt, deadline).await?;
+        let retrieval_query = assembled.context.retrieval_query.clone();
+        let rag_error = assembled.context.rag_error.clone();
+        let context_flags = assembled.context.context_flags.clone();
+        if params.dry_run {
+            let result = self.preview_result(&params, &user_prompt, assembled, moral_recentering, started.elapsed());
+            return Ok((result, Provenance { retrieval_query, rag_error, context_flags, ..Provenance::default() }));
+        }
+        let AssembledPrompt { prompt: enhanced_prompt, context, .. } = assembled;
+
+        // An ungrounded answer would outlive the failure that called for it
+        let key = self.response_cache.key("llm_inference", &params, &enhanced_prompt, generation).filter(|_| rag_error.is_none());
+        if let Some(mut result) = key.as_ref().and_then(|key| self.response_cache.get(key)) {
+            tracing::debug!("Serving llm_inference for {} from the response cache", params.agent_id);
+            result.metrics.response_time_ms = started.elapsed().as_millis() as u64;
+            result.metrics.attempts = 0;
+            result.metrics.retry_delay_ms = 0;
+            // Only first choices are cached
+            let chain = self.backends.current().chain_for(&params.model).map(|chain| ChainStep { model: chain.models[0].clone(), depth: 0 });
+            return Ok((result, Provenance { cached: true, chain, retrieval_query, rag_error, context_flags }));
+        }
+
+        let started = std::time::Instant::now();
+        let (output, attempts, chain) = self.complete(&enhanced_prompt, &params, &deadline).await?;
+        let mut metrics = self.inference_metrics(&enhanced_prompt, &output, &params, started.elapsed(), context.citations.as_deref());
+        attempts.record(&mut metrics);
+        metrics.rag_chunks_included = context.chunks_included;
+        metrics.rag_chunks_dropped = context.chunks_dropped;
+        metrics.retrieval = context.retrieval.clone();
+
+        let result = MCPResult {
+            metrics,
+            response: output.text,
+            rag_context: context.rag_context,
+            citations: context.citations,
+            moral_recentering,
+            prompt: Some(enhanced_prompt),
+            prompt_preview: None,
+        };
+        // A fallback's answer would outlive the outage that called for it
+        if let Some(key) = key.filter(|_| chain.as_ref().is_none_or(|step| step.depth == 0)) {
+            self.response_cache.insert(key, result.clone());
+        }
+        Ok((result, Provenance { cached: false, chain, retrieval_query, rag_error, context_flags }))
+    }
+
+    /// A dry run's result: the assembled prompt with its token counts, the
+    /// context as a real call would get it, and no response
+    fn preview_result(
+        &self,
+        params: &MCPParams,
+        user_prompt: &str,
+        assembled: AssembledPrompt,
+        moral_recentering: Option<MoralRecenteringReport>,
+        elapsed: std::time::Duration,
+    ) -> MCPResult {
+        let count = |text: &str| self.tokenizer.count(text) as u32;
+        let AssembledPrompt { prompt, context, framing, history } = assembled;
+        let total = count(&prompt.flattened());
+        let tokens = PromptTokens {
+            user_prompt: count(user_prompt),
+            framing: count(&framing),
+            knowledge: count(&context.knowledge),
+            history: count(&history),
+            total,
+            max_tokens: params.max_tokens,
+            context_window: params.context_window,
+            remaining: params.context_window.saturating_sub(total).saturating_sub(params.max_tokens),
+        };
+        let confidence = self.retrieval_confidence(context.citations.as_deref());
+        MCPResult {
+            response: String::new(),
+            metrics: ResponseMetrics {
+                response_time_ms: elapsed.as_millis() as u64,
+                token_count: 0,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                rag_documents_used: context.citations.as_ref().map_or(0, |citations| citations.len() as u32),
+                confidence_score: confidence.score,
+                confidence_breakdown: Some(Box::new(confidence)),
+                throttle_delay_ms: 0,
+                rag_chunks_included: context.chunks_included,
+                rag_chunks_dropped: context.chunks_dropped,
+                retrieval: context.retrieval,
+                sampling: None,
+                attempts: 0,
+                retry_delay_ms: 0,
+            },
+            rag_context: context.rag_context,
+            citations: context.citations,
+            moral_recentering,
+            prompt: None,
+            prompt_preview: Some(PromptPreview { system: prompt.system, user: prompt.user, chunks: context.considered, tokens, params: params.effective() }),
+        }
+    }
+
+    /// Calls the backend, or each model of the request's fallback chain in
+    /// turn, retrying transient failures per `RetryConfig`
+    async fn complete(&self, prompt: &Prompt, params: &MCPParams, deadline: &Deadline) -> Result<(CompletionOutput, Attempts, Option<ChainStep>), anyhow::Error> {
+        let span = stage_span!("backend_completion", backend = Empty, finish_reason = Empty, completion_tokens = Empty, attempts = Empty);
+        trace::timed(span.clone(), async {
+            let backends = self.backends.current();
+            let chain = backends.chain_for(&params.model);
+            let mut attempts = Attempts::default();
+            let mut depth = 0;
+            let (name, output) = loop {
+                let params = chain_params(params, chain, depth);
+                match self.complete_model(&backends, prompt, &params, deadline, &mut attempts, &span).await {
+                    Ok(answer) => break answer,
+                    Err(e) => match chain {
+                        Some(chain) if self.fall_back(chain, depth, &e, deadline) => depth += 1,
+                        _ => return Err(e),
+                    },
+                }
+            };
+            span.record("attempts", attempts.calls);
+            span.record("finish_reason", tracing::field::debug(&output.finish_reason));
+            span.record("completion_tokens", output.completion_tokens);
+            tracing::debug!(
+                "Backend {} finished with {:?} after {} completion tokens",
+                name,
+                output.finish_reason,
+                output.completion_tokens
+            );
+            Ok((output, attempts, chain.map(|chain| ChainStep { model: chain.models[depth].clone(), depth: depth as u32 })))
+        })
+        .await
+    }
+
+    /// Calls the backend serving `params.model`, retrying transient failures
+    async fn complete_model<'a>(
+        &self,
+        backends: &'a BackendRegistry,
+        prompt: &Prompt,
+        params: &MCPParams,
+        deadline: &Deadline,
+        attempts: &mut Attempts,
+        span: &tracing::Span,
+    ) -> Result<(&'a str, CompletionOutput), anyhow::Error> {
+        let (routed, routed_backend) = backends.resolve(params)?;
+        loop {
+            attempts.calls += 1;
+            let (name, backend, permit) = self.admit_backend(backends, routed, routed_backend)?;
+            span.record("backend", name);
+            let outcome = deadline.backend(backend.complete(prompt, params)).await;
+            permit.finish(outcome.as_ref().map_or_else(|e| !counts_against_backend(e), |_| true));
+            match outcome {
+                Ok(output) => {
+                    self.model_catalog.record_success(name);
+                    attempts.answered(backend.as_ref(), params);
+                    return Ok((name, output));
+                }
+                Err(e) => {
+                    let wait = self.retry_wait(name, backend.as_ref(), &e, attempts.calls, deadline).ok_or(e)?;
+                    tokio::time::sleep(wait).await;
+                    attempts.delay += wait;
+                }
+            }
+        }
+    }
+
+    /// Whether to try the model after `depth` in `chain` once that one failed
+    /// with `error`: not when none is left, the failure is the request's own,
+    /// or the deadline has passed. Counts the fallback when so.
+    fn fall_back(&self, chain: &FallbackChain, depth: usize, error: &anyhow::Error, deadline: &Deadline) -> bool {
+        let Some(next) = chain.models.get(depth + 1) else {
+            return false;
+        };
+        if !warrants_fallback(error) || tokio::time::Instant::now() >= deadline.at {
+            return false;
+        }
+        tracing::warn!("Model {} of chain {} failed ({}); falling back to {}", chain.models[depth], chain.model, error, next);
+        self.counters.record_fallback(&chain.model);
+        self.metrics.fell_back(&chain.model);
+        true
+    }
+
+    /// The backend to call in place of `name` and a permit from its breaker:
+    /// `name` itself, or its fallback while its breaker is open
+    fn admit_backend<'a>(
+        &self,
+        backends: &'a BackendRegistry,
+        name: &'a str,
+        backend: &'a Arc<dyn LLMBackend>,
+    ) -> Result<(&'a str, &'a Arc<dyn LLMBackend>, Permit), MCPError> {
+        let retry_after = match self.breakers.acquire(name) {
+            Ok(permit) => return Ok((name, backend, permit)),
+            Err(retry_after) => retry_after,
+        };
+        let fallback = self.breakers.fallback(name)
+            .and_then(|fallback| backends.backends().find(|(registered, _)| *registered == fallback));
+        if let Some((fallback, fallback_backend)) = fallback {
+            if let Ok(permit) = self.breakers.acquire(fallback) {
+                tracing::debug!("Backend {} is cut off; calling {} instead", name, fallback);
+                return Ok((fallback, fallback_backend, permit));
+            }
+        }
+        Err(MCPError::CircuitOpen { backend: name.to_string(), retry_after })
+    }
+
+    /// How long to wait before calling the backend again after `error`, or
+    /// None to fail with it: when it isn't transient, the attempts are used up,
+    /// or the wait would run past the deadline
+    fn retry_wait(&self, name: &str, backend: &dyn LLMBackend, error: &anyhow::Error, calls: u32, deadline: &Deadline) -> Option<std::time::Duration> {
+        if calls >= self.retries.max_attempts || !backend.is_transient(error) {
+            return None;
+        }
+        let wait = error
+            .downcast_ref::<BackendError>()
+            .and_then(BackendError::retry_after)
+            .unwrap_or_else(|| self.retries.backoff(calls));
+        if tokio::time::Instant::now() + wait >= deadline.at {
+            tracing::warn!("Backend {} failed ({}); no time left to retry", name, error);
+            return None;
+        }
+        tracing::warn!("Backend {} failed ({}); retrying in {} ms", name, error, wait.as_millis());
+        Some(wait)
+    }
+
+    /// Streams an inference as events: lifecycle stages, then the response text
+    /// in pieces, then `Done`. Dropping the stream early cancels the work and
+    /// counts the request as cancelled for its agent. Invalid or over-limit
+    /// requests fail before anything starts, as do ones finding no
+    /// concurrency slot free; streams don't wait for one. `request_id` is the client's, as
+    /// on `MCPRequest`.
+    pub fn stream_llm_inference(self: &Arc<Self>, mut params: MCPParams, request_id: Option<String>) -> Result<InferenceStream, MCPError> {
+        let started = std::time::Instant::now();
+        self.counters.record_request("llm_inference");
+        let admitted = self.assign_request_id(request_id, &params.agent_id).and_then(|id| {
+            if params.dry_run {
+                return Err(MCPError::InvalidFields(vec![FieldError::new("dry_run", "false when streaming; preview over /api/mcp", true)]));
+            }
+            self.apply_defaults(&mut params)?;
+            self.check_overload()?;
+            self.check_quotas(&params)?;
+            let throttle_delay = self.admit(&params)?;
+            let permit = self.concurrency.try_acquire().ok_or_else(|| self.shed(self.concurrency.shed()))?;
+            Ok((id, throttle_delay, permit))
+        });
+        let (request_id, throttle_delay, permit) = match admitted {
+            Ok(delay) => delay,
+            Err(e) => {
+                self.record_error(&e);
+                self.metrics.observe_request("llm_inference", e.http_status(), started.elapsed());
+                self.live.publish(LiveEvent::RequestCompleted {
+                    request_id: None,
+                    agent_id: params.agent_id.clone(),
+                    method: "llm_inference".to_string(),
+                    status: e.http_status(),
+                    latency_ms: started.elapsed().as_millis() as u64,
+                });
+                return Err(e);
+            }
+        };
+        self.live.publish(LiveEvent::RequestStarted { request_id: request_id.clone(), agent_id: params.agent_id.clone(), method: "llm_inference".to_string() });
+        let (events, receiver) = tokio::sync::mpsc::channel(16);
+        let agent_id = params.agent_id.clone();
+        let service = Arc::clone(self);
+        let span = trace::request_span(&request_id, "llm_inference", &params);
+        let id = request_id.clone();
+        let deadline = self.timeouts.deadline(&params);
+
+        let task = tokio::spawn(async move {
+            let _permit = permit;
+            let _timing = service.overload.time();
+            let _guard = service.track_in_flight(&params.agent_id);
+            let registration = service.running.register(&id);
+            let agent_id = params.agent_id.clone();
+            let audited_params = service.audit.as_ref().map(|_| params.clone());
+            let outcome = tokio::select! {
+                biased;
+                outcome = service.run_inference_stream(id.clone(), params, throttle_delay, deadline, &events) => outcome,
+                _ = deadline.expired() => Err(deadline.exceeded().into()),
+                _ = service.shutdown.drain_expired() => Err(MCPError::ShuttingDown.into()),
+                _ = registration.token().cancelled() => Err(MCPError::Cancelled.into()),
+            };
+            let span = tracing::Span::current();
+            trace::record_outcome(&span, started, &outcome);
+            let elapsed_ms = Some(started.elapsed().as_millis() as u64);
+            let status = match outcome {
+                Ok(()) => {
+                    service.record_outcome(&agent_id, Observation { response_time_ms: elapsed_ms, success: true, token_count: None });
+                    200
+                }
+                Err(e) => {
+                    let error = MCPError::from(e);
+                    if let Some(audit) = &service.audit {
+                        audit.record(AuditRecord::from_failure(&id, &agent_id, "llm_inference", &error).with_params(audited_params));
+                    }
+                    service.record_error(&error);
+                    if error.counts_as_failure() {
+                        service.record_outcome(&agent_id, Observation { response_time_ms: elapsed_ms, success: false, token_count: None });
+                    }
+                    if let MCPError::Cancelled = error {
+                        service.record_cancelled(&agent_id);
+                    }
+                    let _ = events.send(InferenceEvent::Error { code: error.code().to_string(), message: error.to_string() }).await;
+                    error.http_status()
+                }
+            };
+            span.record("status", status);
+            service.metrics.observe_request("llm_inference", status, started.elapsed());
+            let latency_ms = started.elapsed().as_millis() as u64;
+            service.live.publish(LiveEvent::RequestCompleted { request_id: Some(id), agent_id, method: "llm_inference".to_string(), status, latency_ms });
+        }.instrument(span));
+
+        Ok(InferenceStream {
+            request_id,
+            events: receiver,
+            task,
+            agent_metrics: Arc::clone(&self.agent_metrics),
+            agent_id,
+            finished: false,
+        })
+    }
+
+    async fn run_inference_stream(
+        &self,
+        request_id: String,
+        params: MCPParams,
+        throttle_delay: std::time::Duration,
+        deadline: Deadline,
+        events: &tokio::sync::mpsc::Sender<InferenceEvent>,
+    ) -> Result<(), anyhow::Error> {
+        if !throttle_delay.is_zero() {
+            tokio::time::sleep(throttle_delay).await;
+        }
+        let emit = |event: InferenceEvent| async move {
+            events.send(event).await.map_err(|_| anyhow::anyhow!("stream receiver dropped"))
+        };
+        let rag_unavailable = deadline.retrieval(self.check_rag_available(&params, params.use_rag)).await?;
+        self.update_agent_metrics(&params.agent_id);
+
+        let (chaos_type, would_have_applied, mut chaos_roll) = self.apply_chaos_if_enabled(&params, "llm_inference").await?;
+        emit(InferenceEvent::ChaosApplied {
+            applied: chaos_type.is_some(),
+            chaos_type: chaos_type.clone(),
+            would_have_applied: would_have_applied.clone(),
+        })
+        .await?;
+        let corrupt = chaos_type.as_deref() == Some("response_corruption");
+
+        let (user_prompt, moral_recentering) = self.recenter(&params).await?;
+        let AssembledPrompt { prompt: enhanced_prompt, context, .. } = self.assemble_prompt(&params, &user_prompt, deadline).await?;
+        let citations = context.citations;
+        let retrieval_query = context.retrieval_query;
+        let rag_error = context.rag_error;
+        let context_flags = context.context_flags;
+        emit(InferenceEvent::RagContext {
+            citations: citations.clone().unwrap_or_default(),
+            rag_context: context.rag_context,
+        }).await?;
+
+        let moral_recentered = moral_recentering.as_ref().is_some_and(|report| report.recentered);
+        emit(InferenceEvent::MoralRecentering { specialty: params.specialty.clone(), report: moral_recentering.clone() }).await?;
+
+        // Deltas are forwarded as they arrive; a full channel pauses the backend stream
+        let started = std::time::Instant::now();
+        // Corrupted deltas, which then make up the whole response
+        let mut corrupted = String::new();
+        // Deltas pass through it, and may be held back or masked
+        let mut screen = self.content_filters.stream();
+        let span = stage_span!("backend_completion", backend = Empty, finish_reason = Empty, completion_tokens = Empty, attempts = Empty);
+        let (output, attempts, chain_step) = trace::timed(span.clone(), async {
+            let backends = self.backends.current();
+            let chain = backends.chain_for(&params.model);
+            let mut attempts = Attempts::default();
+            // Retried, or passed down the chain, only while the client has seen none of the answer
+            let mut emitted = false;
+            let mut depth = 0;
+            loop {
+                let params = chain_params(&params, chain, depth);
+                let (routed, routed_backend) = backends.resolve(&params)?;
+                let error = loop {
+                    attempts.calls += 1;
+                    span.record("attempts", attempts.calls);
+                    let (name, backend, permit) = match self.admit_backend(&backends, routed, routed_backend) {
+                        Ok(admitted) => admitted,
+                        Err(e) => break anyhow::Error::from(e),
+                    };
+                    span.record("backend", name);
+                    let mut chunks = backend.complete_stream(&enhanced_prompt, &params);
+                    let error = loop {
+                        let chunk = match deadline.backend(async { anyhow::Ok(chunks.next().await) }).await {
+                            Ok(Some(chunk)) => chunk,
+                            Ok(None) => Err(anyhow::anyhow!("backend {} ended its stream without a result", name)),
+                            Err(e) => Err(e),
+                        };
+                        match chunk {
+                            Err(e) => break e,
+                            Ok(CompletionChunk::Delta(mut text)) => {
+                                emitted = true;
+                                if corrupt {
+                                    text = corrupt_text(&text, &mut chaos_roll.rng);
+                                    corrupted.push_str(&text);
+                                }
+                                let text = match &mut screen {
+                                    Some(screen) => screen.push(text),
+                                    None => Some(text),
+                                };
+                                if let Some(text) = text {
+                                    emit(InferenceEvent::Delta { text }).await?
+                                }
+                            }
+                            Ok(CompletionChunk::Done(done)) => {
+                                permit.finish(true);
+                                self.model_catalog.record_success(name);
+                                attempts.answered(backend.as_ref(), &params);
+                                span.record("finish_reason", tracing::field::debug(&done.finish_reason));
+                                span.record("completion_tokens", done.completion_tokens);
+                                let step = chain.map(|chain| ChainStep { model: chain.models[depth].clone(), depth: depth as u32 });
+                                return anyhow::Ok((done, attempts, step));
+                            }
+                        }
+                    };
+                    drop(chunks);
+                    permit.finish(!counts_against_backend(&error));
+                    let wait = match emitted {
+                        true => None,
+                        false => self.retry_wait(name, backend.as_ref(), &error, attempts.calls, &deadline),
+                    };
+                    let Some(wait) = wait else {
+                        break error;
+                    };
+                    tokio::time::sleep(wait).await;
+                    attempts.delay += wait;
+                };
+                match chain {
+                    Some(chain) if !emitted && self.fall_back(chain, depth, &error, &deadline) => depth += 1,
+                    _ => return Err(error),
+                }
+            }
+        })
+        .await?;
+        let mut metrics = self.inference_metrics(&enhanced_prompt, &output, &params, started.elapsed(), citations.as_deref());
+        attempts.record(&mut metrics);
+        metrics.throttle_delay_ms = throttle_delay.as_millis() as u64;
+        metrics.rag_chunks_included = context.chunks_included;
+        metrics.rag_chunks_dropped = context.chunks_dropped;
+        metrics.retrieval = context.retrieval.clone();
+        if corrupt {
+            self.penalize_corruption(&mut metrics);
+        }
+        if !params.verbose_confidence {
+            metrics.confidence_breakdown = None;
+        }
+
+        let mut response = if corrupt { corrupted } else { output.text };
+        let mut content_filter = None;
+        if let Some(screen) = screen {
+            let (tail, screened) = screen.finish().await;
+            if !tail.is_empty() {
+                emit(InferenceEvent::Delta { text: tail }).await?;
+            }
+            response = screened.text;
+            content_filter = screened.report;
+        }
+        let session_turn = match &params.session_id {
+            Some(session_id) => Some(self.record_turn(session_id, &params.agent_id, &params.prompt, &response)?),
+            None => None,
+        };
+        let timestamp = self.clock.now();
+        let void_shrine_token = self.tokens.issue(&request_id, &params.agent_id, timestamp);
+        let metadata = MCPMetadata {
+            request_id,
+            timestamp,
+            void_shrine_token,
+            chaos_applied: chaos_type.is_some(),
+            chaos_type,
+            chaos_decision: chaos_roll.decision,
+            chaos_seed: chaos_roll.seed,
+            would_have_applied,
+            moral_recentered,
+            rag_unavailable,
+            rag_error,
+            session_turn,
+            cached: false,
+            served_model: chain_step.as_ref().map(|step| step.model.clone()),
+            fallback_depth: chain_step.map_or(0, |step| step.depth),
+            content_filter,
+            retrieval_query,
+            idempotent_replay: false,
+            context_flags,
+        };
+        self.record_tokens(&params.agent_id, &metrics);
+        let model = metadata.served_model.as_deref().unwrap_or(&params.model);
+        self.record_usage(&params.agent_id, model, UsageSource::Backend, &metrics);
+        if let Some(audit) = &self.audit {
+            let result = MCPResult {
+                response: response.clone(),
+                metrics: metrics.clone(),
+                rag_context: None,
+                citations,
+                moral_recentering,
+                prompt: Some(enhanced_prompt),
+                prompt_preview: None,
+            };
+            audit.record(AuditRecord::from_response(&params.agent_id, "llm_inference", &result, &metadata).with_params(Some(params.clone())));
+        }
+        emit(InferenceEvent::Done { response, metrics: Box::new(metrics), metadata }).await
+    }
+
+    /// The prompt for the backend: the request's template filled with the
+    /// recentered prompt, the knowledge base context that fits and the
+    /// session history that fits beside them, and the context fields for the result
+    async fn assemble_prompt(
+        &self,
+        params: &MCPParams,
+        user_prompt: &str,
+        deadline: Deadline,
+    ) -> Result<AssembledPrompt, anyhow::Error> {
+        let (template, framing) = self.template_for(params);
+        let vars = PromptVars { user_prompt, specialty: &params.specialty, moral_framing: framing.trim_end(), ..PromptVars::default() };
+        let context = self.inference_context(params, &template, vars, deadline).await?;
+        let vars = PromptVars { rag_context: &context.knowledge, ..vars };
+        let history = self.history(params, &template, vars)?;
+        let prompt = template.render(&PromptVars { history: &history, ..vars });
+        Ok(AssembledPrompt { prompt, context, framing, history })
+    }
+
+    /// The request's template, else its specialty's, else the default; and
+    /// the specialty's framing, unless moral recentering is off
+    fn template_for(&self, params: &MCPParams) -> (Arc<Template>, String) {
+        let specialty = self.specialties.resolve(&params.specialty);
+        let name = params.template.as_deref().or(specialty.as_ref().and_then(|specialty| specialty.template.as_deref()));
+        let template = self.templates.get_or_default(name);
+        let framing = specialty
+            .filter(|_| params.moral_recentering != MoralRecenteringMode::Off)
+            .map(|specialty| specialty.framing)
+            .unwrap_or_default();
+        (template, framing)
+    }
+
+    /// As much of the session's history as fits in the context window beside
+    /// the rest of the prompt and `max_tokens` of output, dropping the oldest
+    /// turns first
+    fn history(&self, params: &MCPParams, template: &Template, vars: PromptVars<'_>) -> Result<String, MCPError> {
+        let Some(session_id) = &params.session_id else {
+            return Ok(String::new());
+        };
+        let turns = self.sessions.history(session_id, &params.agent_id).map_err(|e| session_error(session_id, e))?;
+        let budget = (params.context_window as usize)
+            .saturating_sub(params.max_tokens as usize)
+            .saturating_sub(self.tokenizer.count(&template.render_user(&vars)));
+        Ok(crate::sessions::transcript(&turns, budget, self.tokenizer.as_ref()).unwrap_or_default())
+    }
+
+    /// What retrieval searches for a request in a session, when
+    /// `sessions.retrieval` says to draw on its history and it has some
+    async fn retrieval_query(&self, params: &MCPParams, deadline: &Deadline) -> Result<Option<RetrievalQuery>, MCPError> {
+        let config = &self.sessions.config().retrieval;
+        let Some(session_id) = params.session_id.as_ref().filter(|_| config.turns > 0) else {
+            return Ok(None);
+        };
+        let turns = self.sessions.history(session_id, &params.agent_id).map_err(|e| session_error(session_id, e))?;
+        let recent = &turns[turns.len().saturating_sub(config.turns)..];
+        if recent.is_empty() {
+            return Ok(None);
+        }
+        let mut query = RetrievalQuery {
+            text: crate::sessions::history_query(recent, &params.prompt),
+            history_turns: recent.len(),
+            condensed: false,
+            condense_skipped: None,
+        };
+        if config.condense {
+            match self.condense(recent, params, deadline, config).await {
+                Ok(text) => {
+                    query.text = text;
+                    query.condensed = true;
+                }
+                Err(skipped) => query.condense_skipped = Some(skipped),
+            }
+        }
+        Ok(Some(query))
+    }
+
+    /// The backend's rewrite of the follow-up as a standalone question, in
+    /// at most `condense_timeout_ms` and half the request's remaining time
+    async fn condense(
+        &self,
+        turns: &[Turn],
+        params: &MCPParams,
+        deadline: &Deadline,
+        config: &HistoryRetrievalConfig,
+    ) -> Result<String, CondenseSkipped> {
+        if params.dry_run {
+            return Err(CondenseSkipped::DryRun);
+        }
+        let load = self.agent_metrics.get(&params.agent_id).map_or(0.0, |metrics| metrics.current_load);
+        if self.throttle_config().decide(load) != Throttle::Proceed {
+            return Err(CondenseSkipped::Load);
+        }
+        let remaining = deadline.at.saturating_duration_since(tokio::time::Instant::now());
+        let limit = std::time::Duration::from_millis(config.condense_timeout_ms).min(remaining / 2);
+        let params = MCPParams { max_tokens: config.condense_max_tokens, temperature: 0.0, use_rag: false, ..params.clone() };
+        let prompt = crate::sessions::condense_prompt(turns, &params.prompt);
+        let span = stage_span!("query_condensation", backend = Empty);
+        let mut attempts = Attempts::default();
+        let backends = self.backends.current();
+        let rewrite = trace::timed(span.clone(), async {
+            tokio::time::timeout(limit, self.complete_model(&backends, &prompt, &params, deadline, &mut attempts, &span))
+                .await
+                .map_err(|_| anyhow::Error::from(MCPError::DeadlineExceeded { stage: TimeoutStage::Retrieval, budget: limit }))?
+        })
         .await;
+        match rewrite {
+            Ok((_, output)) if !output.text.trim().is_empty() => Ok(output.text.trim().to_string()),
+            Ok(_) => Err(CondenseSkipped::Failed),
+            Err(e) if matches!(e.downcast_ref::<MCPError>(), Some(MCPError::DeadlineExceeded { .. })) => {
+                tracing::warn!("Condensing the retrieval query for {} took over {:?}; searching the turns as they are", params.agent_id, limit);
+                Err(CondenseSkipped::Timeout)
+            }
+            Err(e) => {
+                tracing::warn!("Condensing the retrieval query for {} failed ({}); searching the turns as they are", params.agent_id, e);
+                Err(CondenseSkipped::Failed)
+            }
+        }
+    }
+
+    fn record_turn(&self, session_id: &str, agent_id: &str, prompt: &str, response: &str) -> Result<u32, MCPError> {
+        self.sessions.record(session_id, agent_id, prompt, response).map_err(|e| session_error(session_id, e))
+    }
+
+    /// The engine `params` retrieve from
+    fn params_engine(&self, params: &MCPParams) -> Result<SharedEngine, MCPError> {
+        self.rag_engines.get("rag_engine", params.rag_engine.as_deref())
+    }
+
+    /// Whether a request wanting knowledge base context has to do without it.
+    /// With `require_rag` that is an error instead.
+    async fn check_rag_available(&self, params: &MCPParams, wants_rag: bool) -> Result<bool, MCPError> {
+        if !wants_rag || self.params_engine(params)?.read().await.is_some() {
+            return Ok(false);
+        }
+        if self.require_rag {
+            return Err(MCPError::RagUnavailable);
+        }
+        tracing::warn!("Knowledge base context requested but the RAG engine is not initialized");
+        Ok(true)
+    }
+
+    /// As much knowledge base context as fits in `template` beside `vars`,
+    /// when RAG is requested, plus the context fields for the result.
+    /// Retrieval searches the original prompt, or its `retrieval_query`.
+    async fn inference_context(
+        &self,
+        params: &MCPParams,
+        template: &Template,
+        vars: PromptVars<'_>,
+        deadline: Deadline,
+    ) -> Result<InferenceContext, anyhow::Error> {
+        let span = stage_span!("rag_retrieval", use_rag = params.use_rag, documents = Empty, dropped = Empty);
+        trace::timed(span.clone(), async {
+            let mut knowledge = String::new();
+            let mut rag_results = None;
+            let mut covered = Vec::new();
+            let mut retrieval_query = None;
+            let mut retrieval = None;
+            let mut rag_error = None;
+            let mut context_flags = Vec::new();
+
+            // Add RAG context if requested
+            let engine = self.params_engine(params)?;
+            if params.use_rag && engine.read().await.is_some() {
+                retrieval_query = self.retrieval_query(params, &deadline).await?;
+            }
+            if params.use_rag {
+                let query = retrieval_query.as_ref().map_or(params.prompt.as_str(), |query| query.text.as_str());
+                // Waiting for the engine's lock is part of retrieval
+                let retrieved = deadline.retrieval(async {
+                    let rag_engine = engine.read().await;
+                    let Some(rag_engine) = rag_engine.as_ref() else {
+                        return anyhow::Ok(None);
+                    };
+                    let settings = self.retrieval_settings(params, INFERENCE_CONTEXT_RESULTS);
+                    check_retrieval_mode(rag_engine, settings.mode)?;
+                    let started = std::time::Instant::now();
+                    let searched = async {
+                        let results = rag_engine.search(query, settings.top_k, &self.query_options(params)).await?;
+                        let mut summaries = HashMap::new();
+                        for result in &results {
+                            if !summaries.contains_key(&result.document_id) {
+                                let summary = rag_engine.document_info(&result.document_id).await?.and_then(|info| info.summary);
+                                summaries.insert(result.document_id.clone(), summary);
+                            }
+                        }
+                        anyhow::Ok((results, summaries))
+                    }
+                    .await;
+                    self.record_rag_query("llm_inference", started.elapsed());
+                    if searched.is_ok() {
+                        retrieval = Some(settings);
+                    }
+                    Ok(Some(searched))
+                })
+                .await?;
+                let retrieved = match retrieved {
+                    Some(Err(e)) => {
+                        rag_error = Some(self.retrieval_failed(params, e)?);
+                        None
+                    }
+                    retrieved => retrieved.and_then(Result::ok),
+                };
+                if let Some((results, mut summaries)) = retrieved {
+                    // Documents speak as data, never as instructions
+                    let (results, flags) = self.context_screen.screen(results, &mut summaries);
+                    context_flags = flags;
+                    let tokenizer = self.tokenizer.as_ref();
+                    let (mode, blocks) = Self::context_blocks(&results, &summaries, params, vars.user_prompt, tokenizer);
+                    let (fitted, kept) = Self::fit_context(&blocks, params, template, vars, tokenizer);
+                    tracing::debug!("Assembled RAG context as {:?}, {} of {} blocks fitting", mode, kept, blocks.len());
+                    knowledge = fitted;
+                    covered = Self::covered_results(&results, mode, kept);
+                    span.record("documents", results.len() as u64);
+                    span.record("dropped", covered.iter().filter(|covered| !**covered).count() as u64);
+                    rag_results = Some(results);
+                }
+            }
+            let (mut rag_context, mut citations) = Self::context_fields(rag_results.as_deref(), params);
+            let considered = match (&citations, params.dry_run) {
+                (Some(citations), true) => citations
+                    .iter()
+                    .zip(&covered)
+                    .map(|(citation, included)| PreviewChunk { citation: citation.clone(), included: *included })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            // Left-out results are neither cited nor listed; the rest keep their numbers
+            if let Some(citations) = &mut citations {
+                citations.retain(|citation| covered[citation.index - 1]);
+            }
+            if let Some(rag_context) = &mut rag_context {
+                let mut covered = covered.iter();
+                rag_context.retain(|_| covered.next().is_some_and(|covered| *covered));
+            }
+            let chunks_included = covered.iter().filter(|covered| **covered).count() as u32;
+            Ok(InferenceContext {
+                knowledge,
+                rag_context,
+                citations,
+                chunks_included,
+                chunks_dropped: covered.len() as u32 - chunks_included,
+                considered,
+                retrieval_query,
+                retrieval,
+                rag_error,
+                context_flags,
+            })
+        })
+        .await
+    }
+
+    /// The reason searching failed, to go on without context; under
+    /// `RetrievalFailurePolicy::Fail`, the request's error instead
+    fn retrieval_failed(&self, params: &MCPParams, error: anyhow::Error) -> Result<String, MCPError> {
+        let reason = format!("{:#}", error);
+        match self.rag_failure_policy {
+            RetrievalFailurePolicy::Fail => Err(MCPError::RetrievalFailed(reason)),
+            RetrievalFailurePolicy::Degrade => {
+                tracing::warn!("Knowledge base retrieval for {} failed ({}); answering without context", params.agent_id, reason);
+                Ok(reason)
+            }
+        }
+    }
+
+    /// Token counts are the backend's where it reports them, else the tokenizer's
+    fn inference_metrics(
+        &self,
+        prompt: &Prompt,
+        output: &CompletionOutput,
+        params: &MCPParams,
+        elapsed: std::time::Duration,
+        citations: Option<&[Citation]>,
+    ) -> ResponseMetrics {
+        let counted = |reported: u32, text: &str| if reported > 0 { reported } else { self.tokenizer.count(text) as u32 };
+        let prompt_tokens = counted(output.prompt_tokens, &prompt.flattened());
+        let completion_tokens = counted(output.completion_tokens, &output.text);
+        let scores = citations.map(|citations| citations.iter().map(|citation| citation.score).collect::<Vec<_>>());
+        let confidence = self.confidence.breakdown(scores.as_deref(), Some((output, completion_tokens, params.max_tokens)));
+        ResponseMetrics {
+            response_time_ms: output.generation_time.unwrap_or(elapsed).as_millis() as u64,
+            token_count: prompt_tokens + completion_tokens,
+            prompt_tokens,
+            completion_tokens,
+            rag_documents_used: citations.map(|c| c.len() as u32).unwrap_or(0),
+            confidence_score: confidence.score,
+            confidence_breakdown: Some(Box::new(confidence)),
+            throttle_delay_ms: 0,
+            rag_chunks_included: 0,
+            rag_chunks_dropped: 0,
+            attempts: 0,
+            retry_delay_ms: 0,
+            retrieval: None,
+            sampling: None,
+        }
+    }
+
+    /// Confidence in a retrieval-only answer, which rests on the chunk scores alone
+    fn retrieval_confidence(&self, citations: Option<&[Citation]>) -> ConfidenceBreakdown {
+        let scores: Vec<f64> = citations.into_iter().flatten().map(|citation| citation.score).collect();
+        self.confidence.breakdown(Some(&scores), None)
+    }
+
+    /// Takes the confidence penalty for chaos corruption off `metrics`
+    fn penalize_corruption(&self, metrics: &mut ResponseMetrics) {
+        if let Some(breakdown) = &mut metrics.confidence_breakdown {
+            self.confidence.corrupted(breakdown);
+            metrics.confidence_score = breakdown.score;
+        }
+    }
+
+    async fn handle_rag_query(&self, params: MCPParams, deadline: Deadline) -> Result<MCPResult, MCPError> {
+        let settings = self.retrieval_settings(&params, RAG_QUERY_RESULTS);
+        let engine = self.params_engine(&params)?;
+        let results = deadline.retrieval(async {
+            let rag_engine = engine.read().await;
+            let Some(rag_engine) = rag_engine.as_ref() else {
+                return anyhow::Ok(None);
+            };
+            check_retrieval_mode(rag_engine, settings.mode)?;
+            let started = std::time::Instant::now();
+            let span = stage_span!("rag_retrieval", use_rag = true, documents = Empty);
+            let results = trace::timed(span.clone(), rag_engine.search(&params.prompt, settings.top_k, &self.query_options(&params))).await?;
+            span.record("documents", results.len() as u64);
+            self.record_rag_query("rag_query", started.elapsed());
+            Ok(Some(results))
+        })
+        .await?;
+        let retrieval = results.is_some().then_some(settings);
+        let (rag_context, citations) = if let Some(results) = results {
+            Self::context_fields(Some(&results), &params)
+        } else {
+            let context = params.flat_rag_context.then(|| vec!["RAG engine not initialized".to_string()]);
+            (context, Some(Vec::new()))
+        };
+        let retrieved = citations.as_ref().map(|c| c.len()).unwrap_or(0);
+        let confidence = self.retrieval_confidence(citations.as_deref());
+
+        Ok(MCPResult {
+            response: format!("Retrieved {} relevant documents", retrieved),
+            metrics: ResponseMetrics {
+                response_time_ms: 200,
+                token_count: 0,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                rag_documents_used: retrieved as u32,
+                confidence_score: confidence.score,
+                confidence_breakdown: Some(Box::new(confidence)),
+                throttle_delay_ms: 0,
+                rag_chunks_included: 0,
+                rag_chunks_dropped: 0,
+                attempts: 0,
+                retry_delay_ms: 0,
+                retrieval,
+                sampling: None,
+            },
+            rag_context,
+            citations,
+            moral_recentering: None,
+            prompt: None,
+            prompt_preview: None,
+        })
+    }
+
+    /// Terse grounding: the few sentences that best answer the prompt, each citable
+    async fn handle_rag_answer(&self, params: MCPParams, deadline: Deadline) -> Result<MCPResult, MCPError> {
+        let engine = self.params_engine(&params)?;
+        let answers = deadline.retrieval(async {
+            let rag_engine = engine.read().await;
+            let Some(rag_engine) = rag_engine.as_ref() else {
+                return Err(anyhow::Error::from(MCPError::RagUnavailable));
+            };
+            let started = std::time::Instant::now();
+            let span = stage_span!("rag_retrieval", use_rag = true, documents = Empty);
+            let answers = trace::timed(span.clone(), rag_engine.query_answers(&params.prompt, RAG_ANSWER_SENTENCES, &self.query_options(&params))).await?;
+            span.record("documents", answers.len() as u64);
+            self.record_rag_query("rag_answer", started.elapsed());
+            Ok(answers)
+        })
+        .await?;
+        let (rag_context, citations) = Self::context_fields(Some(&answers), &params);
+        let confidence = self.retrieval_confidence(citations.as_deref());
+
+        let response = answers.iter()
+            .enumerate()
+            .map(|(i, answer)| format!("{} [{}]", answer.content, i + 1))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Ok(MCPResult {
+            response,
+            metrics: ResponseMetrics {
+                response_time_ms: 200,
+                token_count: 0,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                rag_documents_used: answers.len() as u32,
+                confidence_score: confidence.score,
+                confidence_breakdown: Some(Box::new(confidence)),
+                throttle_delay_ms: 0,
+                rag_chunks_included: 0,
+                rag_chunks_dropped: 0,
+                attempts: 0,
+                retry_delay_ms: 0,
+                retrieval: None,
+                sampling: None,
+            },
+            rag_context,
+            citations,
+            moral_recentering: None,
+            prompt: None,
+            prompt_preview: None,
+        })
+    }
+
+    /// Numbered context blocks, citable as [1], [2], ... Full chunks are used when they
+    /// fit in the context window next to the prompt and `max_tokens` of output;
+    /// otherwise each document contributes its summary once, falling back to the chunk
+    /// for documents without one.
+    fn context_blocks(
+        results: &[SearchResult],
+        summaries: &HashMap<String, Option<String>>,
+        params: &MCPParams,
+        user_prompt: &str,
+        tokenizer: &dyn Tokenizer,
+    ) -> (ContextMode, Vec<String>) {
+        let full: Vec<String> = results.iter()
+            .enumerate()
+            .map(|(i, result)| format!("[{}] {}", i + 1, result.to_context_string()))
+            .collect();
+
+        let budget = (params.context_window as usize)
+            .saturating_sub(params.max_tokens as usize)
+            .saturating_sub(tokenizer.count(user_prompt));
+        let full_tokens: usize = full.iter().map(|block| tokenizer.count(block)).sum();
+        let has_summaries = summaries.values().any(Option::is_some);
+        if full_tokens <= budget || !has_summaries {
+            return (ContextMode::FullChunks, full);
+        }
+
+        let mut seen = HashSet::new();
+        let blocks = results.iter()
+            .enumerate()
+            .filter(|(_, result)| seen.insert(result.document_id.as_str()))
+            .map(|(i, result)| match summaries.get(&result.document_id).cloned().flatten() {
+                Some(summary) => format!("[{}] [Summary: {} ({})] {}", i + 1, result.title, result.document_id, summary),
+                None => full[i].clone(),
+            })
+            .collect();
+        (ContextMode::Summaries, blocks)
+    }
+
+    /// The leading `blocks` that fit in the context window, rendered by
+    /// `template` beside `vars` and `max_tokens` of output, and how many did.
+    /// The first block that doesn't fit is cut by `truncate_to_tokens`, and
+    /// kept if any of it past its `[n] [Document: ...]` header fits; the rest
+    /// are dropped.
+    fn fit_context(blocks: &[String], params: &MCPParams, template: &Template, vars: PromptVars<'_>, tokenizer: &dyn Tokenizer) -> (String, usize) {
+        let budget = (params.context_window as usize).saturating_sub(params.max_tokens as usize);
+        let render = |blocks: &[String]| template.render_user(&PromptVars { rag_context: &blocks.join("\n\n"), ..vars });
+        let mut kept: Vec<String> = Vec::new();
+        for block in blocks {
+            kept.push(block.clone());
+            let rendered = tokenizer.count(&render(&kept));
+            if rendered <= budget {
+                continue;
+            }
+            kept.pop();
+            // What the rest of the prompt leaves for this block
+            let room = budget.saturating_sub(rendered.saturating_sub(tokenizer.count(block)));
+            let cut = truncate_to_tokens(block, room, tokenizer, ELLIPSIS);
+            let header = block.find(")] ").map_or(0, |at| at + ")] ".len());
+            if cut.len() > header + ELLIPSIS.len() {
+                kept.push(cut.into_owned());
+                if tokenizer.count(&render(&kept)) > budget {
+                    kept.pop();
+                }
+            }
+            break;
+        }
+        (kept.join("\n\n"), kept.len())
+    }
+
+    /// Which `results` the first `kept` blocks from `context_blocks` stand for:
+    /// one result per block for full chunks, every result of the block's
+    /// document for summaries
+    fn covered_results(results: &[SearchResult], mode: ContextMode, kept: usize) -> Vec<bool> {
+        match mode {
+            ContextMode::FullChunks => (0..results.len()).map(|i| i < kept).collect(),
+            ContextMode::Summaries => {
+                let mut documents: Vec<&str> = Vec::new();
+                results.iter()
+                    .map(|result| {
+                        let rank = documents.iter().position(|id| *id == result.document_id).unwrap_or_else(|| {
+                            documents.push(&result.document_id);
+                            documents.len() - 1
+                        });
+                        rank < kept
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Structured citations plus, for legacy clients, the flat string form of the same results
+    fn context_fields(
+        results: Option<&[SearchResult]>,
+        params: &MCPParams,
+    ) -> (Option<Vec<String>>, Option<Vec<Citation>>) {
+        let Some(results) = results else {
+            return (None, None);
+        };
+
+        let citations = results.iter()
+            .enumerate()
+            .map(|(i, result)| Citation::from_search_result(i + 1, result))
+            .collect();
+        let flat = params.flat_rag_context
+            .then(|| results.iter().map(SearchResult::to_context_string).collect());
+
+        (flat, Some(citations))
+    }
+
+    /// The user's prompt after moral recentering, if `params` asks for it
+    async fn recenter(&self, params: &MCPParams) -> Result<(String, Option<MoralRecenteringReport>), MCPError> {
+        let span = stage_span!("moral_recentering", ethical_framework = Empty, recentered = Empty, care_ethics_score = Empty);
+        trace::timed(span.clone(), async {
+            let wanted = match params.moral_recentering {
+                MoralRecenteringMode::On => true,
+                MoralRecenteringMode::Off => false,
+                MoralRecenteringMode::Auto => params.ethical_framework.is_some() || params.void_shrine_context,
+            };
+            if !wanted {
+                return Ok((params.prompt.clone(), None));
+            }
+            let ethical_framework = params.ethical_framework.clone().unwrap_or_else(|| DEFAULT_ETHICAL_FRAMEWORK.to_string());
+            let moral = self.handle_moral_recentering(MoralRequest {
+                original_prompt: params.prompt.clone(),
+                specialty: params.specialty.clone(),
+                void_shrine_context: params.void_shrine_context,
+                ethical_framework: ethical_framework.clone(),
+                strict: None,
+            }).await?;
+            let report = MoralRecenteringReport {
+                ethical_framework,
+                recentered: moral.recentered_prompt != params.prompt,
+                ethical_adjustments: moral.ethical_adjustments,
+                care_ethics_score: moral.care_ethics_score,
+                score_breakdown: moral.score_breakdown,
+                rules: if params.dry_run { moral.rules } else { Vec::new() },
+            };
+            span.record("ethical_framework", report.ethical_framework.as_str());
+            span.record("recentered", report.recentered);
+            span.record("care_ethics_score", report.care_ethics_score);
+            Ok((moral.recentered_prompt, Some(report)))
+        })
+        .await
+    }
+
+    /// Retrieval options of `params`, limited by its specialty's filters and
+    /// to the documents of the agent's tenant
+    fn query_options(&self, params: &MCPParams) -> QueryOptions {
+        self.retrieval_options(params.query_options(), &params.specialty, &params.agent_id)
+    }
+
+    /// The retrieval `params` ask for, `default_top_k` chunks when they don't
+    /// say, at most `max_rag_top_k`
+    fn retrieval_settings(&self, params: &MCPParams, default_top_k: usize) -> RetrievalSettings {
+        RetrievalSettings {
+            top_k: params.rag_top_k.unwrap_or(default_top_k).min(self.param_limits.max_rag_top_k),
+            min_score: params.rag_min_score,
+            mode: params.rag_mode.unwrap_or_default(),
+            collection: params.rag_collection.clone(),
+        }
+    }
+
+    /// `options` limited as an inference by `agent_id` as `specialty` would be
+    fn retrieval_options(&self, mut options: QueryOptions, specialty: &str, agent_id: &str) -> QueryOptions {
+        if let Some(specialty) = self.specialties.resolve(specialty) {
+            options.metadata_filters.extend(specialty.metadata_filters);
+            options.tags.extend(specialty.tags);
+        }
+        let metrics = self.agent_metrics.get(agent_id);
+        if let Some(tenant) = self.agent_owner(agent_id, metrics.as_deref()) {
+            options.metadata_filters.insert(TENANT_METADATA_KEY.to_string(), tenant);
+        }
+        options
+    }
+
+    pub fn handle_list_models(&self) -> ModelsResponse {
+        let backends = self.backends.current();
+        let routes = backends.models();
+        let discovered: Vec<RoutableModel> = self.model_catalog.discovered().into_iter()
+            .flat_map(|(backend, models)| models.into_iter().map(move |model| (backend.clone(), model)))
+            .filter_map(|(backend, model)| backends.discovered(&model, &backend))
+            .filter(|model| !routes.iter().any(|route| route.model == model.model && route.backend == model.backend))
+            .collect();
+        let listing = |model: RoutableModel, discovered: bool| {
+            let breaker = self.breakers.state(&model.backend);
+            let last_success = self.model_catalog.last_success(&model.backend);
+            ModelListing { model, discovered, healthy: breaker == BreakerState::Closed, breaker, last_success }
+        };
+        let mut models: Vec<ModelListing> = routes.into_iter().map(|model| listing(model, false)).collect();
+        models.extend(discovered.into_iter().map(|model| listing(model, true)));
+        ModelsResponse { models, default_backend: backends.default_backend().map(str::to_string) }
+    }
+
+    /// Asks every backend which models it serves. One that can't be asked
+    /// keeps what it reported before.
+    pub async fn refresh_models(&self) {
+        let backends = self.backends.current();
+        for (name, backend) in backends.backends() {
+            match backend.list_models().await {
+                Ok(models) => self.model_catalog.set_discovered(name, models),
+                Err(e) => tracing::warn!("Couldn't list the models of backend {}: {:#}", name, e),
+            }
+        }
+    }
+
+    /// Refreshes the models backends serve now and every `interval`, or
+    /// only now when it is zero
+    pub fn refresh_models_every(self: &Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            service.refresh_models().await;
+            if interval.is_zero() {
+                return;
+            }
+            let mut refresh = tokio::time::interval(interval);
+            refresh.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick is immediate, and the models were just listed
+            refresh.tick().await;
+            loop {
+                refresh.tick().await;
+                service.refresh_models().await;
+            }
+        })
+    }
+
+    /// Prometheus text exposition, with gauges refreshed from current state
+    pub async fn handle_prometheus(&self) -> String {
+        self.refresh_loads();
+        let concurrency = self.concurrency.status();
+        self.metrics.set_concurrency(concurrency.in_use, concurrency.max_in_flight);
+        self.metrics.set_overload(&self.overload.status(std::time::Instant::now(), concurrency.in_use));
+        let loads: Vec<(String, f64)> = self.agent_metrics.iter().map(|entry| (entry.key().clone(), entry.current_load)).collect();
+        self.metrics.set_agent_loads(loads.iter().map(|(agent_id, load)| (agent_id.as_str(), *load)));
+        if let Some(rag_engine) = self.rag_engine.read().await.as_ref() {
+            match rag_engine.get_stats().await {
+                Ok(stats) => self.metrics.set_rag_counts(stats.document_count, stats.chunk_count),
+                Err(e) => tracing::warn!("Knowledge base stats unavailable for metrics: {}", e),
+            }
+        }
+        self.metrics.render()
+    }
+
+    /// Per-agent metrics, of the caller's tenant's agents, and the server counters
+    pub fn handle_metrics(&self, tenancy: &Tenancy, params: &MetricsParams) -> MetricsResponse {
+        self.refresh_loads();
+        let mut agents: Vec<AgentMetricsReport> = self.agent_metrics
+            .iter()
+            .filter(|entry| params.agent_id.as_ref().is_none_or(|agent_id| entry.key() == agent_id))
+            .filter(|entry| tenancy.sees(self.agent_owner(entry.key(), Some(entry.value())).as_deref()))
+            .filter(|entry| params.since.is_none_or(|since| entry.last_request >= since))
+            .map(|entry| AgentMetricsReport {
+                agent_id: entry.key().clone(),
+                total_requests: entry.total_requests,
+                requests_since_boot: entry.requests_since_boot,
+                avg_response_time_ms: entry.avg_response_time,
+                success_rate: entry.success_rate,
+                current_load: entry.current_load,
+                last_request: entry.last_request,
+                in_flight: entry.in_flight,
+                recent_rps: entry.recent_rps,
+                recent_p95_ms: entry.recent_p95_ms,
+                cancelled_requests: entry.cancelled_requests,
+                throttled_delayed: entry.throttled_delayed,
+                throttled_rejected: entry.throttled_rejected,
+            })
+            .collect();
+        agents.sort_by(|a, b| a.agent_id.cmp(&b.agent_id));
+        MetricsResponse {
+            generated_at: self.clock.now(),
+            server: self.counters.snapshot(),
+            agents,
+            cache: self.response_cache.stats(),
+            breakers: self.breakers.reports(),
+            concurrency: self.concurrency.status(),
+            chaos: self.chaos_stats.totals(),
+            routes: self.route_metrics.report(),
+        }
+    }
+
+    /// The whole server at a glance; see `Dashboard`. Everything is read off
+    /// counters and per-minute buckets kept anyway, so polling is cheap.
+    pub async fn handle_dashboard(&self, params: &DashboardParams) -> Dashboard {
+        self.refresh_loads();
+        let now = self.clock.now();
+        let server = self.counters.snapshot();
+        let top = params.top.min(MAX_DASHBOARD_TOP);
+
+        let stats: Vec<AgentStats> = self.agent_metrics.iter().map(|entry| entry.stats.clone()).collect();
+        let activity = DASHBOARD_WINDOWS
+            .into_iter()
+            .map(|window| DashboardActivity::new(AgentStats::combined(&stats, window, now)))
+            .collect();
+
+        let mut loads: Vec<(String, f64, u32)> = self
+            .agent_metrics
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.current_load, entry.in_flight))
+            .collect();
+        loads.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let agents = loads
+            .into_iter()
+            .take(top)
+            .map(|(agent_id, current_load, in_flight)| DashboardAgent {
+                throttle: self.throttle_status(&agent_id),
+                agent_id,
+                current_load,
+                in_flight,
+            })
+            .collect();
+
+        let mut scaling: Vec<DashboardScaling> = self
+            .agent_metrics
+            .iter()
+            .filter_map(|entry| entry.last_scaling.clone().map(|decision| DashboardScaling { agent_id: entry.key().clone(), decision }))
+            .collect();
+        scaling.sort_by(|a, b| b.decision.decided_at.cmp(&a.decision.decided_at).then_with(|| a.agent_id.cmp(&b.agent_id)));
+        scaling.truncate(top);
+
+        let rag = match self.rag_engine.read().await.as_ref() {
+            Some(rag_engine) => rag_engine
+                .get_stats()
+                .await
+                .inspect_err(|e| tracing::warn!("Knowledge base stats unavailable for the dashboard: {}", e))
+                .ok(),
+            None => None,
+        };
+
+        Dashboard {
+            generated_at: now,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: server.started_at,
+            uptime_secs: (Utc::now() - server.started_at).num_seconds().max(0) as u64,
+            activity,
+            agents,
+            rag,
+            chaos: DashboardChaos {
+                config: self.chaos_config.read().await.clone(),
+                events_by_type: server.chaos_by_type,
+                shadowed_by_type: self.chaos_stats.totals().by_type.into_iter()
+                    .filter(|(_, counts)| counts.shadowed > 0)
+                    .map(|(chaos_type, counts)| (chaos_type, counts.shadowed))
+                    .collect(),
+            },
+            breakers: self.breakers.reports(),
+            concurrency: self.concurrency.status(),
+            scaling,
+        }
+    }
+
+    pub fn handle_version(&self) -> VersionInfo {
+        let build = BuildInfo::current();
+        VersionInfo { server: build.version_string(), build, config: self.config_source() }
+    }
+
+    pub async fn handle_chaos(&self, request: ChaosRequest) -> ChaosResponse {
+        let chaos_config = self.chaos_config.read().await;
+        let ChaosRoll { seed, decision, mut rng } = self.chaos_dice.roll(chaos_config.seed);
+        let response = |apply_chaos: bool, effect: String, delay_ms: u64| {
+            ChaosResponse { apply_chaos, effect, delay_ms, decision, seed, would_have_applied: None }
+        };
+        
+        if !chaos_config.enabled {
+            return response(false, "Chaos engineering disabled".to_string(), 0);
+        }
+        if !chaos_config.targets(&request.agent_id) {
+            return response(false, format!("Agent {} is exempt from chaos", request.agent_id), 0);
+        }
+
+        // A registered agent's specialty when the request doesn't name one
+        let specialty = request.specialty.clone().filter(|specialty| !specialty.is_empty()).or_else(|| self.agents.defaults(&request.agent_id).specialty);
+        let specialty = specialty.as_deref();
+        let intensity = chaos_config.intensity_for(&request.agent_id, specialty);
+        let chance = intensity * request.intensity;
+        let should_apply = rng.gen::<f64>() < chance;
+        
+        if should_apply && chaos_config.is_shadow(&request.chaos_type) {
+            let delay_ms = chaos_config.delay_ms(&request.chaos_type, &mut rng);
+            let outcome = ChaosOutcome::Shadowed { chaos_type: &request.chaos_type, delay_ms };
+            self.chaos_stats.record(self.clock.now(), &request.agent_id, specialty, outcome, chance);
+            self.metrics.chaos_shadowed(&request.chaos_type);
+            let shadow = ShadowChaos { chaos_type: request.chaos_type.clone(), delay_ms };
+            ChaosResponse { would_have_applied: Some(shadow), ..response(false, format!("{} chaos in shadow mode, not applied", request.chaos_type), 0) }
+        } else if should_apply {
+            let delay = chaos_config.delay_ms(&request.chaos_type, &mut rng);
+            tracing::info!("Chaos ({}) advised for agent {} (decision {}, seed {:?})", request.chaos_type, request.agent_id, decision, seed);
+            let outcome = ChaosOutcome::Applied { chaos_type: &request.chaos_type, delay_ms: delay };
+            self.chaos_stats.record(self.clock.now(), &request.agent_id, specialty, outcome, chance);
+            response(true, format!("{} chaos applied", request.chaos_type), delay)
+        } else {
+            let outcome = ChaosOutcome::Skipped { chaos_type: Some(&request.chaos_type) };
+            self.chaos_stats.record(self.clock.now(), &request.agent_id, specialty, outcome, chance);
+            response(false, "No chaos applied this cycle".to_string(), 0)
+        }
+    }
+
+    /// Chaos decisions counted since `params.since`, in total and per agent,
+    /// with the rate chaos struck against the rate the config gave them
+    pub async fn handle_chaos_report(&self, params: &ChaosReportParams) -> ChaosReport {
+        let chaos_config = self.chaos_config.read().await;
+        let (since, agents) = self.chaos_stats.agents(params);
+        let specialties = self.chaos_stats.specialties(params, |specialty| chaos_config.specialty_multiplier(Some(specialty)));
+        let totals = agents.iter().fold(ChaosCounts::default(), |mut totals, agent| {
+            totals.add(&agent.counts);
+            totals
+        });
+        ChaosReport {
+            generated_at: self.clock.now(),
+            since,
+            enabled: chaos_config.enabled,
+            configured_intensity: chaos_config.intensity,
+            shadow_types: chaos_config.shadowed_types(),
+            totals,
+            agents,
+            specialties,
+        }
+    }
+
+    pub async fn handle_chaos_config(&self) -> ChaosConfig {
+        self.chaos_config.read().await.clone()
+    }
+
+    /// Replaces the whole chaos config, refusing it with every problem found
+    pub async fn handle_update_chaos_config(&self, config: ChaosConfig) -> Result<ChaosConfig, MCPError> {
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(MCPError::InvalidParams(problems.join("; ")));
+        }
+        *self.chaos_config.write().await = config.clone();
+        // A new seed replays from its first decision
+        self.chaos_dice.reset();
+        tracing::info!("Chaos config updated: enabled={} intensity={} seed={:?}", config.enabled, config.intensity, config.seed);
+        Ok(config)
+    }
+
+    pub fn config_source(&self) -> Option<ConfigSource> {
+        self.config_source.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn startup_checks(&self) -> Option<SelfCheckReport> {
+        self.startup_checks.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Reads the config file again and swaps in the sections that changed
+    /// and can change while running; see `reload`. The file is checked whole
+    /// first, and refused with every problem found, keeping what runs.
+    pub async fn handle_reload_config(&self) -> Result<ReloadReport, MCPError> {
+        let running = self.config_source().ok_or(MCPError::NotConfigured("config file"))?;
+        // Parsing and opening files blocks
+        let (path, specialties, defaults, breakers) =
+            (running.path.clone(), Arc::clone(&self.specialties), self.request_defaults.clone(), self.breakers.config().clone());
+        let loaded = tokio::task::spawn_blocking(move || load_for_reload(&path, &specialties, &defaults, &breakers))
+            .await
+            .unwrap_or_else(|e| Err(vec![format!("reading the config: {}", e)]));
+        let report = match loaded {
+            Ok((config, parts)) => {
+                let settings = config.source.as_ref().map(|source| source.settings.clone()).unwrap_or_default();
+                let report = ReloadReport::sorted(changed_keys(&running.settings, &settings), config.source.clone());
+                // Holds a whole config; boxed rather than nested in this future
+                Box::pin(self.apply_reload(&report, config, parts)).await;
+                tracing::info!("Reloaded the config from {}: {}", running.path.display(), report.message);
+                report
+            }
+            Err(errors) => {
+                let report = ReloadReport::refused(errors);
+                tracing::error!("Refused the config in {}: {}", running.path.display(), report.message);
+                report
+            }
+        };
+        Ok(report)
+    }
+
+    async fn apply_reload(&self, report: &ReloadReport, config: Config, parts: ReloadableParts) {
+        if report.applies("chaos") {
+            *self.chaos_config.write().await = config.chaos;
+            self.chaos_dice.reset();
+        }
+        if report.applies("rate_limits") {
+            self.rate_limiter.update(config.rate_limits);
+        }
+        if report.applies("throttle") {
+            *self.throttle.write().unwrap_or_else(|e| e.into_inner()) = config.throttle;
+        }
+        if report.applies("quotas") {
+            self.quotas.update(config.quotas);
+        }
+        if report.applies("specialties") {
+            self.specialties.replace_with(parts.specialties);
+        }
+        if report.applies("templates") {
+            self.templates.replace_with(parts.templates);
+        }
+        if report.applies("backends") {
+            self.backends.replace(parts.backends);
+        }
+        if report.applies("moral") {
+            self.ethics.replace_with(parts.ethics);
+        }
+        *self.config_source.write().unwrap_or_else(|e| e.into_inner()) = config.source;
+    }
+
+    pub async fn handle_throttle(&self, tenancy: &Tenancy, agent_id: String) -> Result<ThrottleStatus, MCPError> {
+        self.visible_agent(tenancy, &agent_id)?;
+        Ok(self.throttle_status(&agent_id))
+    }
+
+    /// `handle_throttle` without the figures behind it
+    pub async fn handle_throttle_check(&self, tenancy: &Tenancy, agent_id: String) -> Result<ThrottleCheck, MCPError> {
+        self.visible_agent(tenancy, &agent_id)?;
+        let (policy, ..) = self.throttle_decision(agent_id.as_str(), &self.rate_limiter.state(&agent_id));
+        Ok(ThrottleCheck { should_throttle: policy != ThrottlePolicy::None })
+    }
+
+    fn throttle_config(&self) -> ThrottleConfig {
+        self.throttle.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Where the agent stands with its registered concurrency, its rate
+    /// limit bucket and its load, and how long its next request would wait
+    fn throttle_status(&self, agent_id: &str) -> ThrottleStatus {
+        let bucket = self.rate_limiter.state(agent_id);
+        let (policy, delay, load) = self.throttle_decision(agent_id, &bucket);
+        let (in_flight, queued, avg_response_time) = self.agent_metrics.get(agent_id)
+            .map_or((0, 0, 0.0), |metrics| (metrics.in_flight, metrics.waiting, metrics.avg_response_time));
+        let max_concurrency = self.agents.get(agent_id).and_then(|registration| registration.max_concurrency);
+        let concurrency = self.concurrency.status();
+
+        // Requests ahead of a new one for the agent's slots, then for the server's
+        let agent_ahead = max_concurrency.map_or(0.0, |max| (in_flight + queued + 1).saturating_sub(max) as f64 / f64::from(max));
+        let server_ahead = match concurrency.in_use >= concurrency.max_in_flight {
+            true => f64::from(concurrency.waiting + 1) / f64::from(concurrency.max_in_flight.max(1)),
+            false => 0.0,
+        };
+        let estimated_wait_ms = delay.as_millis() as u64 + ((agent_ahead + server_ahead) * avg_response_time).round() as u64;
+
+        let reason = match policy {
+            ThrottlePolicy::Concurrency => "At its registered concurrency",
+            ThrottlePolicy::HardLoad => "Agent load over the hard limit",
+            ThrottlePolicy::RateLimit => "Rate limit reached",
+            ThrottlePolicy::SoftLoad => "High agent load detected",
+            ThrottlePolicy::None if load.is_none() => "New agent",
+            ThrottlePolicy::None => "Normal load",
+        };
+        ThrottleStatus {
+            should_throttle: policy != ThrottlePolicy::None,
+            delay_ms: delay.as_millis() as u64,
+            reason: reason.to_string(),
+            policy,
+            agent_load: load.unwrap_or(0.0),
+            remaining_tokens: bucket.remaining,
+            bucket_capacity: bucket.capacity,
+            refill_per_sec: bucket.refill_per_sec,
+            next_token_in_ms: bucket.next_token_in.as_millis() as u64,
+            in_flight,
+            max_concurrency,
+            queued,
+            server_queue_depth: concurrency.waiting,
+            estimated_wait_ms,
+        }
+    }
+
+    /// What `admit` would decide for the agent's next request, checking as
+    /// it does, and how long it would hold the request or ask it to wait;
+    /// with the agent's load, None for an agent not seen yet
+    fn throttle_decision(&self, agent_id: &str, bucket: &BucketState) -> (ThrottlePolicy, std::time::Duration, Option<f64>) {
+        let (current_load, in_flight) = self.agent_metrics.get_mut(agent_id).map_or((None, 0), |mut metrics| {
+            metrics.refresh_load(&self.load, std::time::Instant::now(), self.clock.now());
+            (Some(metrics.current_load), metrics.in_flight)
+        });
+        let max_concurrency = self.agents.get(agent_id).and_then(|registration| registration.max_concurrency);
+        let throttle = current_load.map_or(Throttle::Proceed, |load| self.throttle_config().decide(load));
+
+        let (policy, delay) = if max_concurrency.is_some_and(|max| in_flight >= max) {
+            (ThrottlePolicy::Concurrency, CONCURRENCY_RETRY_AFTER)
+        } else if let Throttle::Reject { retry_after } = throttle {
+            (ThrottlePolicy::HardLoad, retry_after)
+        } else if bucket.remaining == 0 {
+            (ThrottlePolicy::RateLimit, bucket.next_token_in)
+        } else if let Throttle::Delay(delay) = throttle {
+            (ThrottlePolicy::SoftLoad, delay)
+        } else {
+            (ThrottlePolicy::None, std::time::Duration::ZERO)
+        };
+        (policy, delay, current_load)
+    }
+
+    /// Records the reported request and advises on the agent's recent history
+    /// rather than that one request; see `ScalingHistory::decide`
+    pub async fn handle_scaling(&self, request: ScalingRequest) -> ScalingResponse {
+        self.record_outcome(&request.agent_id, Observation {
+            response_time_ms: request.response_time,
+            success: request.success,
+            token_count: request.token_count,
+        });
+        let now = self.clock.now();
+        let record = {
+            let mut metrics = self.agent_metrics.entry(request.agent_id.clone()).or_insert_with(AgentMetrics::new);
+            let decision = metrics.scaling.decide(now, self.scaling_log.recent(&request.agent_id), &self.scaling);
+            let record = ScalingRecord::new(&request.agent_id, now, &decision, &self.scaling);
+            metrics.last_scaling = Some(LastScaling { direction: record.direction, description: record.description.clone(), decided_at: now });
+            record
+        };
+        self.scaling_log.push(record.clone(), &self.scaling);
+
+        self.live.publish(LiveEvent::ScalingDecision {
+            agent_id: request.agent_id.clone(),
+            direction: record.direction,
+            description: record.description.clone(),
+            capacity_change: record.capacity_change,
+        });
+        let ScalingRecord { description, capacity_change, priority_adjustment, .. } = record;
+        let adjustments = ScalingAdjustments { description, capacity_change, priority_adjustment };
+        if capacity_change != 0.0 {
+            let payload = serde_json::to_value(&adjustments).unwrap_or_default();
+            self.webhooks.notify(WebhookEventKind::ScalingDecision, &request.agent_id, payload);
+        }
+        ScalingResponse { adjustments }
+    }
+
+    /// Scaling advice given, newest first, with the net capacity change and
+    /// decisions per direction of everything matching `params`
+    pub fn handle_scaling_history(&self, params: &ScalingHistoryParams) -> ScalingHistoryResponse {
+        self.scaling_log.query(params)
+    }
+
+    pub async fn handle_index_url(&self, tenancy: &Tenancy, engine: Option<&str>, request: IndexUrlRequest) -> Result<IndexUrlResponse, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        // Fetch under the read lock so queries keep flowing during the network round trip
+        let mut document = match engine.read().await.as_ref() {
+            Some(rag_engine) => rag_engine.fetch_url(&request.url).await?,
+            None => return Err(MCPError::RagUnavailable),
+        };
+        let document_id = document.id.clone();
+        if let Some(tenant) = tenancy.tenant() {
+            document.metadata.insert(TENANT_METADATA_KEY.to_string(), tenant.to_string());
+        }
+
+        match engine.write().await.as_mut() {
+            Some(rag_engine) => {
+                visible_document(rag_engine, tenancy, &document_id).await?;
+                rag_engine.index_document(document).await?
+            }
+            None => return Err(MCPError::RagUnavailable),
+        }
+
+        tracing::info!("Indexed {} as {}", request.url, document_id);
+        Ok(IndexUrlResponse { document_id })
+    }
+
+    /// Indexes into the caller's tenant's documents; an id taken by another
+    /// tenant's document is not found
+    pub async fn handle_index_document(&self, tenancy: &Tenancy, engine: Option<&str>, mut request: IndexDocumentRequest) -> Result<IndexUrlResponse, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        if let Some(tenant) = tenancy.tenant() {
+            request.metadata.insert(TENANT_METADATA_KEY.to_string(), tenant.to_string());
+        }
+        let document = Document {
+            id: request.id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+            title: request.title,
+            content: request.content,
+            metadata: request.metadata,
+            embedding: None,
+            chunks: vec![],
+        };
+        let document_id = document.id.clone();
+
+        // Chunking, embedding and summarizing share the read lock with searches;
+        // the write lock is only held for the final transaction
+        let prepared = match engine.read().await.as_ref() {
+            Some(rag_engine) => rag_engine.prepare_document(document).await?,
+            None => return Err(MCPError::RagUnavailable),
+        };
+        match engine.write().await.as_mut() {
+            Some(rag_engine) => {
+                visible_document(rag_engine, tenancy, &document_id).await?;
+                rag_engine.write_prepared(prepared)?
+            }
+            None => return Err(MCPError::RagUnavailable),
+        }
+        Ok(IndexUrlResponse { document_id })
+    }
+
+    pub async fn handle_get_document(&self, tenancy: &Tenancy, engine: Option<&str>, document_id: &str) -> Result<DocumentResponse, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        let guard = engine.read().await;
+        let rag_engine = guard.as_ref().ok_or(MCPError::RagUnavailable)?;
+        let not_found = || MCPError::DocumentNotFound(document_id.to_string());
+        let info = rag_engine.document_info(document_id).await?.filter(|info| tenancy.sees(document_tenant(info))).ok_or_else(not_found)?;
+        let content = rag_engine.document_content(document_id).await?.ok_or_else(not_found)?;
+        Ok(DocumentResponse { info, content })
+    }
+
+    pub async fn handle_delete_document(&self, tenancy: &Tenancy, engine: Option<&str>, document_id: &str) -> Result<DeleteDocumentsResponse, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        let mut guard = engine.write().await;
+        let rag_engine = guard.as_mut().ok_or(MCPError::RagUnavailable)?;
+        visible_document(rag_engine, tenancy, document_id).await?;
+        if !rag_engine.delete_document(document_id).await? {
+            return Err(MCPError::DocumentNotFound(document_id.to_string()));
+        }
+        Ok(DeleteDocumentsResponse { deleted: 1 })
+    }
+
+    pub async fn handle_rag_stats(&self, engine: Option<&str>) -> Result<RAGStats, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        let guard = engine.read().await;
+        match guard.as_ref() {
+            Some(rag_engine) => Ok(rag_engine.get_stats().await?),
+            None => Err(MCPError::RagUnavailable),
+        }
+    }
+
+    /// Every knowledge base with its stats
+    pub async fn handle_rag_engines(&self) -> RagEnginesResponse {
+        self.rag_engines.list().await
+    }
+
+    /// Structured search results, filtered by metadata patterns and tags,
+    /// among the caller's tenant's documents
+    pub async fn handle_rag_search(&self, tenancy: &Tenancy, engine: Option<&str>, request: RagSearchRequest) -> Result<RagSearchResponse, MCPError> {
+        let mut fields = Vec::new();
+        if request.query.trim().is_empty() {
+            fields.push(FieldError::new("query", "non-empty", ""));
+        }
+        if request.limit == 0 || request.limit > MAX_SEARCH_LIMIT {
+            fields.push(FieldError::new("limit", format!("between 1 and {}", MAX_SEARCH_LIMIT), request.limit));
+        }
+        if let Some(error) = self.rag_engines.check("engine", engine) {
+            fields.push(error);
+        }
+        if !fields.is_empty() {
+            return Err(MCPError::InvalidFields(fields));
+        }
+        let engine = self.rag_engines.get("engine", engine)?;
+
+        let mut options = QueryOptions { metadata_filters: request.filters, tags: request.tags, ..Default::default() };
+        if let Some(tenant) = tenancy.tenant() {
+            options.metadata_filters.insert(TENANT_METADATA_KEY.to_string(), tenant.to_string());
+        }
+        let guard = engine.read().await;
+        match guard.as_ref() {
+            Some(rag_engine) => {
+                let started = std::time::Instant::now();
+                let results = rag_engine.search(&request.query, request.limit, &options).await?;
+                self.record_rag_query("search", started.elapsed());
+                Ok(RagSearchResponse { results })
+            }
+            None => Err(MCPError::RagUnavailable),
+        }
+    }
+
+    /// Vectors from the knowledge base's own embedding provider, so they
+    /// compare with the indexed chunks'. 503 `embeddings_unavailable` without one.
+    pub async fn handle_embed(&self, engine: Option<&str>, request: EmbedRequest) -> Result<EmbedResponse, MCPError> {
+        self.param_limits.check_embed(&request).map_err(MCPError::InvalidFields)?;
+        let engine = self.rag_engines.get("engine", engine)?;
+
+        let guard = engine.read().await;
+        let (rag_engine, provider) = guard
+            .as_ref()
+            .and_then(|engine| engine.embedder().map(|provider| (engine, provider)))
+            .ok_or(MCPError::EmbeddingsUnavailable)?;
+        if let Some(model) = request.model.as_deref().filter(|model| *model != provider.model()) {
+            let constraint = format!("the configured model {}", provider.model());
+            return Err(MCPError::InvalidFields(vec![FieldError::new("model", constraint, model)]));
+        }
+        let embeddings = rag_engine.embed(&request.texts).await?;
+
+        let token_counts: Vec<u32> = request.texts.iter().map(|text| self.tokenizer.count(text) as u32).collect();
+        let total_tokens = token_counts.iter().sum();
+        if let Some(agent_id) = &request.agent_id {
+            self.record_embedding(agent_id, total_tokens);
+        }
+        Ok(EmbedResponse { model: provider.model().to_string(), dimensions: provider.dimension(), embeddings, token_counts, total_tokens })
+    }
+
+    /// The results an inference's retrieval gets for the same prompt, options,
+    /// specialty and agent, limited to the caller's tenant
+    pub async fn handle_rag_query_get(&self, tenancy: &Tenancy, query: RagQuery) -> Result<RagSearchResponse, MCPError> {
+        let engine = self.rag_engines.get("engine", query.engine.as_deref())?;
+        let options = QueryOptions {
+            metadata_filters: query.filters,
+            tags: query.tags,
+            since: query.since,
+            until: query.until,
+            doc_ids: query.doc_ids,
+            dedupe_overlaps: query.dedupe_chunks,
+            ..Default::default()
+        };
+        let mut options = self.retrieval_options(options, &query.specialty, query.agent_id.as_deref().unwrap_or_default());
+        if let Some(tenant) = tenancy.tenant() {
+            options.metadata_filters.insert(TENANT_METADATA_KEY.to_string(), tenant.to_string());
+        }
+        let guard = engine.read().await;
+        match guard.as_ref() {
+            Some(rag_engine) => {
+                let started = std::time::Instant::now();
+                let results = rag_engine.search(&query.q, query.limit, &options).await?;
+                self.record_rag_query("search", started.elapsed());
+                Ok(RagSearchResponse { results })
+            }
+            None => Err(MCPError::RagUnavailable),
+        }
+    }
+
+    /// Bulk delete by metadata; `allow_all=true` among the query parameters is the
+    /// only way to run with no other filter, and `engine` names the knowledge
+    /// base. Only the caller's tenant's documents are deleted.
+    pub async fn handle_delete_documents(
+        &self,
+        tenancy: &Tenancy,
+        mut params: HashMap<String, String>,
+    ) -> Result<DeleteDocumentsResponse, MCPError> {
+        let allow_all = params.remove("allow_all").is_some_and(|v| v == "true");
+        let engine = self.rag_engines.get("engine", params.remove("engine").as_deref())?;
+        if let Some(tenant) = tenancy.tenant() {
+            if params.is_empty() && !allow_all {
+                return Err(MCPError::InvalidParams(format!("refusing to delete with an empty filter; pass allow_all to clear all of tenant {}'s documents", tenant)));
+            }
+            params.insert(TENANT_METADATA_KEY.to_string(), tenant.to_string());
+        }
+
+        let mut guard = engine.write().await;
+        match guard.as_mut() {
+            Some(rag_engine) => Ok(DeleteDocumentsResponse {
+                deleted: rag_engine.delete_where(&params, allow_all).await?,
+            }),
+            None => Err(MCPError::RagUnavailable),
+        }
+    }
+
+    /// Applies metadata and tag changes without reindexing. Returns the updated
+    /// document, or None when it does not exist or belongs to another tenant.
+    /// A tenant's key may not move a document out of its tenant.
+    pub async fn handle_patch_document(
+        &self,
+        tenancy: &Tenancy,
+        engine: Option<&str>,
+        document_id: String,
+        patch: DocumentPatch,
+    ) -> Result<Option<DocumentInfo>, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        let mut guard = engine.write().await;
+        let rag_engine = guard.as_mut().ok_or(MCPError::RagUnavailable)?;
+
+        if !rag_engine.document_info(&document_id).await?.is_some_and(|info| tenancy.sees(document_tenant(&info))) {
+            return Ok(None);
+        }
+        let moves_tenant = patch.set_metadata.contains_key(TENANT_METADATA_KEY) || patch.remove_metadata.iter().any(|key| key == TENANT_METADATA_KEY);
+        if moves_tenant && tenancy.tenant().is_some() {
+            let error = FieldError::new("metadata", format!("changes to keys other than '{}'", TENANT_METADATA_KEY), TENANT_METADATA_KEY);
+            return Err(MCPError::InvalidFields(vec![error]));
+        }
+        let empty_tags: Vec<FieldError> = patch.add_tags.iter().enumerate()
+            .filter(|(_, tag)| tag.trim().is_empty())
+            .map(|(i, tag)| FieldError::new(&format!("add_tags[{}]", i), "a non-empty tag", tag.as_str()))
+            .collect();
+        if !empty_tags.is_empty() {
+            return Err(MCPError::InvalidFields(empty_tags));
+        }
+        rag_engine.patch_document(&document_id, &patch).await?;
+
+        Ok(rag_engine.document_info(&document_id).await?)
+    }
+
+    /// Replaces the engine's ranking adjustments; later queries use the new weights
+    pub async fn handle_set_ranking(&self, engine: Option<&str>, config: RankingConfig) -> Result<RankingConfig, MCPError> {
+        let errors = check_ranking(&config);
+        if !errors.is_empty() {
+            return Err(MCPError::InvalidFields(errors));
+        }
+        let engine = self.rag_engines.get("engine", engine)?;
+        let mut guard = engine.write().await;
+        match guard.as_mut() {
+            Some(rag_engine) => {
+                rag_engine.set_ranking(config);
+                Ok(rag_engine.ranking().clone())
+            }
+            None => Err(MCPError::RagUnavailable),
+        }
+    }
+
+    pub async fn handle_stats_by(&self, engine: Option<&str>, metadata_key: String) -> Result<Vec<GroupStats>, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        let guard = engine.read().await;
+        match guard.as_ref() {
+            Some(rag_engine) => Ok(rag_engine.stats_by(&metadata_key).await?),
+            None => Err(MCPError::RagUnavailable),
+        }
+    }
+
+    /// Snapshots the index into the backup directory. Only plain relative paths are
+    /// accepted so requests cannot escape the directory.
+    pub async fn handle_backup(&self, engine: Option<&str>, request: BackupRequest) -> Result<BackupReport, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        let Some(backup_dir) = &self.backup_dir else {
+            return Err(MCPError::NotConfigured("backup directory"));
+        };
+        let relative = Path::new(&request.path);
+        if request.path.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(MCPError::InvalidParams(format!("backup path must be relative to the backup directory: {}", request.path)));
+        }
+
+        let destination = backup_dir.join(relative);
+        if let Some(parent) = destination.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| MCPError::Internal(e.into()))?;
+        }
+
+        let guard = engine.read().await;
+        match guard.as_ref() {
+            Some(rag_engine) => Ok(rag_engine.backup_to(&destination).await?),
+            None => Err(MCPError::RagUnavailable),
+        }
+    }
+
+    /// Starts refilling the full-text index from the stored chunks in the
+    /// background, one batch per write lock so searches keep running between
+    /// batches. A rebuild already running on the engine is returned rather
+    /// than started again; one left unfinished in it is continued. Engines
+    /// rebuild independently.
+    pub async fn handle_rebuild_fts(self: &Arc<Self>, engine: Option<&str>) -> Result<RagTaskView, MCPError> {
+        let name = engine.unwrap_or(DEFAULT_ENGINE);
+        let engine = self.rag_engines.get("engine", engine)?;
+        if engine.read().await.is_none() {
+            return Err(MCPError::RagUnavailable);
+        }
+        let (task, started) = self.rag_tasks.start(TaskKind::RebuildFts, name);
+        if !started {
+            return Ok(task);
+        }
+
+        let service = Arc::clone(self);
+        let task_id = task.task_id.clone();
+        tokio::spawn(async move {
+            let mut begun = false;
+            let mut attempts = 0;
+            loop {
+                let step = match engine.write().await.as_mut() {
+                    Some(rag_engine) if begun => rag_engine.rebuild_fts_batch(FTS_REBUILD_BATCH_CHUNKS),
+                    Some(rag_engine) => match rag_engine.fts_rebuild_progress() {
+                        Some(progress) => Ok(progress),
+                        None => rag_engine.begin_fts_rebuild(),
+                    },
+                    None => Err(MCPError::RagUnavailable.into()),
+                };
+                match step {
+                    Ok(progress) => {
+                        begun = true;
+                        attempts = 0;
+                        service.rag_tasks.progress(&task_id, progress);
+                        if progress.done {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("FTS rebuild {} failed a batch: {:#}", task_id, e);
+                        service.rag_tasks.error(&task_id, format!("{:#}", e));
+                        attempts += 1;
+                        if attempts >= rag_tasks::BATCH_ATTEMPTS {
+                            service.rag_tasks.fail(&task_id);
+                            break;
+                        }
+                    }
+                }
+                // Let searches waiting on the lock in between batches
+                tokio::task::yield_now().await;
+            }
+        });
+        Ok(task)
+    }
+
+    pub fn handle_rag_task(&self, task_id: &str) -> Result<RagTaskView, MCPError> {
+        self.rag_tasks.get(task_id).ok_or_else(|| MCPError::TaskNotFound(task_id.to_string()))
+    }
+
+    /// `RAGEngine::maintenance` a step at a time, so searches only wait
+    /// out the repair and the keyword writes
+    pub async fn handle_maintenance(&self, engine: Option<&str>, request: MaintenanceRequest) -> Result<MaintenanceReport, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        let started = std::time::Instant::now();
+        let mut report = engine.read().await.as_ref().ok_or(MCPError::RagUnavailable)?.inspect()?;
+        if request.repair {
+            engine.write().await.as_mut().ok_or(MCPError::RagUnavailable)?.repair(&mut report)?;
+        }
+        let keywords = engine.read().await.as_ref().ok_or(MCPError::RagUnavailable)?.recompute_keywords()?;
+        report.keywords_refreshed = engine.write().await.as_mut().ok_or(MCPError::RagUnavailable)?.store_keywords(&keywords)?;
+        engine.read().await.as_ref().ok_or(MCPError::RagUnavailable)?.compact(&mut report)?;
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        tracing::info!("Maintenance finished: {:?}", report);
+        Ok(report)
+    }
+
+    pub async fn handle_analytics(&self, engine: Option<&str>, params: AnalyticsParams) -> Result<QueryAnalytics, MCPError> {
+        let engine = self.rag_engines.get("engine", engine)?;
+        let guard = engine.read().await;
+        match guard.as_ref() {
+            Some(rag_engine) => Ok(rag_engine.query_analytics(params.since, params.until, params.limit).await?),
+            None => Err(MCPError::RagUnavailable),
+        }
+    }
+
+    /// Puts the framework's framing, and void shrine context's when asked, in
+    /// front of the prompt; see `EthicalFrameworks::recenter`
+    pub async fn handle_moral_recentering(&self, request: MoralRequest) -> Result<MoralResponse, MCPError> {
+        let specialty = self
+            .specialties
+            .resolve(&request.specialty)
+            .ok_or_else(|| MCPError::InvalidFields(self.check_specialty(&request.specialty).into_iter().collect()))?;
+        let recentering = self
+            .ethics
+            .recenter(
+                &request.original_prompt,
+                &request.ethical_framework,
+                Some(&specialty.name),
+                request.void_shrine_context,
+                request.strict,
+            )
+            .map_err(|e| MCPError::InvalidParams(e.to_string()))?;
+
+        Ok(MoralResponse {
+            recentered_prompt: recentering.prompt,
+            ethical_adjustments: recentering.adjustments,
+            care_ethics_score: recentering.care_ethics_score,
+            score_breakdown: recentering.score_breakdown,
+            framing: specialty.framing,
+            rules: recentering.rules,
+        })
+    }
+
+    /// Every text moral recentering can insert, and where each was configured
+    pub fn handle_moral_config(&self) -> MoralTextsReport {
+        self.ethics.report()
+    }
+
+    /// Reads `moral.texts_path` again, keeping the current texts when it
+    /// can't be used
+    pub fn handle_reload_moral(&self) -> Result<MoralTextsReport, MCPError> {
+        self.ethics.reload(&self.specialties.names()).ok_or(MCPError::NotConfigured("moral texts_path"))??;
+        Ok(self.ethics.report())
+    }
+
+    /// Each prompt recentered as `handle_moral_recentering` would, with what
+    /// changed in it, and a summary over the batch
+    pub fn handle_moral_preview(&self, request: MoralPreviewRequest) -> Result<MoralPreviewResponse, MCPError> {
+        self.param_limits.check_preview(&request).map_err(MCPError::InvalidFields)?;
+        let specialty = match &request.specialty {
+            Some(name) => Some(
+                self.specialties
+                    .resolve(name)
+                    .ok_or_else(|| MCPError::InvalidFields(self.check_specialty(name).into_iter().collect()))?
+                    .name,
+            ),
+            None => None,
+        };
+        let results = request
+            .prompts
+            .into_iter()
+            .map(|prompt| {
+                let recentering = self
+                    .ethics
+                    .recenter(&prompt, &request.ethical_framework, specialty.as_deref(), request.void_shrine_context, request.strict)
+                    .map_err(|e| MCPError::InvalidParams(e.to_string()))?;
+                Ok(MoralPreview {
+                    diff: self.ethics.diff(&prompt, &recentering),
+                    original_prompt: prompt,
+                    recentered_prompt: recentering.prompt,
+                    ethical_adjustments: recentering.adjustments,
+                })
+            })
+            .collect::<Result<Vec<_>, MCPError>>()?;
+        let diffs: Vec<RecenteringDiff> = results.iter().map(|result| result.diff.clone()).collect();
+        Ok(MoralPreviewResponse { summary: RecenteringSummary::new(&diffs), results })
+    }
+
+    fn update_agent_metrics(&self, agent_id: &str) {
+        let mut metrics = self.agent_metrics.entry(agent_id.to_string()).or_insert_with(AgentMetrics::new);
+        let now = self.clock.now();
+        metrics.total_requests += 1;
+        metrics.requests_since_boot += 1;
+        metrics.last_request = now;
+        metrics.stats.record_request(now);
+        self.history.record_request(now, agent_id);
+    }
+
+    fn record_rag_query(&self, kind: &str, elapsed: std::time::Duration) {
+        self.counters.rag_queries.fetch_add(1, Ordering::Relaxed);
+        self.metrics.observe_rag_query(kind, elapsed);
+    }
+
+    /// Folds a finished request, handled here or reported through
+    /// `handle_scaling`, into the agent's metrics and scaling history
+    fn record_outcome(&self, agent_id: &str, observation: Observation) {
+        let mut metrics = self.agent_metrics.entry(agent_id.to_string()).or_insert_with(AgentMetrics::new);
+        metrics.record_outcome(observation.response_time_ms, observation.success);
+        metrics.stats.record_outcome(self.clock.now(), observation.response_time_ms, observation.success);
+        metrics.scaling.record(observation, self.clock.now(), &self.scaling);
+        self.history.record_outcome(self.clock.now(), agent_id, observation.response_time_ms, observation.success);
+    }
+
+    /// Counts a request as started for the agent's load until the guard drops
+    fn record_error(&self, error: &MCPError) {
+        self.counters.record_error(error);
+        if let Some(stage) = error.timeout_stage() {
+            self.metrics.timed_out(stage.as_str());
+        }
+    }
+
+    fn record_tokens(&self, agent_id: &str, response: &ResponseMetrics) {
+        if let Some(mut metrics) = self.agent_metrics.get_mut(agent_id) {
+            metrics.prompt_tokens += u64::from(response.prompt_tokens);
+            metrics.completion_tokens += u64::from(response.completion_tokens);
+            metrics.stats.record_tokens(self.clock.now(), response.prompt_tokens, response.completion_tokens);
+        }
+        self.history.record_tokens(self.clock.now(), agent_id, response.prompt_tokens, response.completion_tokens);
+    }
+
+    fn record_usage(&self, agent_id: &str, model: &str, source: UsageSource, response: &ResponseMetrics) {
+        self.usage.record(self.clock.now(), agent_id, model, source, response.prompt_tokens, response.completion_tokens);
+        if source == UsageSource::Backend {
+            self.warn_of_quotas(agent_id);
+        }
+    }
+
+    fn record_embedding(&self, agent_id: &str, tokens: u32) {
+        let mut metrics = self.agent_metrics.entry(agent_id.to_string()).or_insert_with(AgentMetrics::new);
+        metrics.embedding_requests += 1;
+        metrics.embedding_tokens += u64::from(tokens);
+        metrics.last_request = self.clock.now();
+    }
+
+    fn record_cancelled(&self, agent_id: &str) {
+        if let Some(mut metrics) = self.agent_metrics.get_mut(agent_id) {
+            metrics.cancelled_requests += 1;
+        }
+    }
+
+    pub fn track_in_flight(&self, agent_id: &str) -> InFlightGuard {
+        let started = std::time::Instant::now();
+        {
+            let mut metrics = self.agent_metrics.entry(agent_id.to_string()).or_insert_with(AgentMetrics::new);
+            metrics.in_flight += 1;
+            metrics.recent.record_start(started);
+            metrics.refresh_load(&self.load, started, self.clock.now());
+        }
+
+        InFlightGuard {
+            metrics: Arc::clone(&self.agent_metrics),
+            agent_id: agent_id.to_string(),
+            load: self.load,
+            clock: Arc::clone(&self.clock),
+            started,
+        }
+    }
+
+    /// Counts a request as waiting for `agent_id` until the guard is dropped
+    fn track_waiting(&self, agent_id: &str) -> WaitingGuard {
+        self.agent_metrics.entry(agent_id.to_string()).or_insert_with(AgentMetrics::new).waiting += 1;
+        WaitingGuard { metrics: Arc::clone(&self.agent_metrics), agent_id: agent_id.to_string() }
+    }
+
+    /// Brings every agent's load up to date, e.g. before reporting it
+    fn refresh_loads(&self) {
+        let (now, at) = (std::time::Instant::now(), self.clock.now());
+        for mut metrics in self.agent_metrics.iter_mut() {
+            metrics.refresh_load(&self.load, now, at);
+        }
+    }
+
+    /// The chaos type applied to a request, if any, what chaos in shadow mode
+    /// would have done, and the decision's roll for any further randomness.
+    /// Error injection and request drops fail the request here, before any
+    /// work is done; delay types hold it up for a delay drawn from
+    /// `ChaosConfig::delays`. Shadow chaos is drawn the same way but only
+    /// recorded.
+    async fn apply_chaos_if_enabled(&self, params: &MCPParams, method: &str) -> Result<(Option<String>, Option<ShadowChaos>, ChaosRoll), MCPError> {
+        let span = stage_span!("chaos_decision", chaos_type = Empty, decision = Empty);
+        let (chaos_type, shadow, roll, delay_ms) = trace::timed(span.clone(), async {
+            let chaos_config = self.chaos_config.read().await;
+            let mut roll = self.chaos_dice.roll(chaos_config.seed);
+            span.record("decision", roll.decision);
+            let eligible = chaos_config.enabled && chaos_config.targets(&params.agent_id);
+            let specialty = Some(params.specialty.as_str()).filter(|specialty| !specialty.is_empty());
+            let chance = chaos_config.intensity_for(&params.agent_id, specialty);
+            let Some(chaos_type) = chaos_config.pick(&params.agent_id, specialty, method, &mut roll.rng) else {
+                if eligible {
+                    self.chaos_stats.record(self.clock.now(), &params.agent_id, specialty, ChaosOutcome::Skipped { chaos_type: None }, chance);
+                }
+                return Ok((None, None, roll, 0));
+            };
+            let delay_ms = chaos_config.delay_ms(chaos_type, &mut roll.rng);
+            span.record("chaos_type", chaos_type);
+            if chaos_config.is_shadow(chaos_type) {
+                tracing::info!(
+                    "Chaos ({}, shadow) would have applied to {} for agent: {} (decision {}, seed {:?})",
+                    chaos_type,
+                    method,
+                    params.agent_id,
+                    roll.decision,
+                    roll.seed
+                );
+                self.chaos_stats.record(self.clock.now(), &params.agent_id, specialty, ChaosOutcome::Shadowed { chaos_type, delay_ms }, chance);
+                self.metrics.chaos_shadowed(chaos_type);
+                let shadow = ShadowChaos { chaos_type: chaos_type.to_string(), delay_ms };
+                return Ok((None, Some(shadow), roll, 0));
+            }
+            tracing::info!(
+                "Chaos ({}) applied to {} for agent: {} (decision {}, seed {:?})",
+                chaos_type,
+                method,
+                params.agent_id,
+                roll.decision,
+                roll.seed
+            );
+            self.counters.record_chaos(chaos_type);
+            self.chaos_stats.record(self.clock.now(), &params.agent_id, specialty, ChaosOutcome::Applied { chaos_type, delay_ms }, chance);
+            if let Some(mut metrics) = self.agent_metrics.get_mut(&params.agent_id) {
+                metrics.chaos_events += 1;
+                metrics.stats.record_chaos(self.clock.now());
+            }
+            self.history.record_chaos(self.clock.now(), &params.agent_id);
+            self.metrics.chaos_applied(chaos_type);
+            self.live.publish(LiveEvent::ChaosApplied { agent_id: params.agent_id.clone(), method: method.to_string(), chaos_type: chaos_type.to_string() });
+            match chaos_type {
+                "error_injection" => Err(chaos_config.injected_error()),
+                "request_drop" => Err(MCPError::RequestDropped),
+                _ => Ok((Some(chaos_type.to_string()), None, roll, delay_ms)),
+            }
+        })
+        .await?;
+        if delay_ms > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+        }
+        Ok((chaos_type, shadow, roll))
+    }
 
-    Ok(())
-}
\ No newline at end of file
+    /// Checks a `void_shrine_token` presented by a downstream service
+    pub fn handle_verify_token(&self, request: &TokenVerifyRequest) -> TokenVerification {
+        self.tokens.verify_at(&request.token, self.clock.now())
+    }
+
+    /// Saves every agent's metrics, the usage and activity buckets and the
+    /// scaling decisions to `metrics.state_path`, answering how many agents
+    /// were saved; None without a path
+    pub fn save_agent_metrics(&self) -> anyhow::Result<Option<usize>> {
+        let Some(store) = &self.metrics_store else {
+            return Ok(None);
+        };
+        let mut agents: Vec<SavedAgent> = self.agent_metrics.iter().map(|entry| entry.saved(entry.key())).collect();
+        agents.sort_by(|a, b| a.agent_id.cmp(&b.agent_id));
+        let saved = agents.len();
+        store.save(SavedMetrics { agents, usage: self.usage.buckets(), history: self.history.buckets(), scaling: self.scaling_log.records() })?;
+        Ok(Some(saved))
+    }
+
+    /// Saves agent metrics every `interval`; None without `metrics.state_path`
+    pub fn persist_agent_metrics(self: &Arc<Self>, interval: std::time::Duration) -> Option<tokio::task::JoinHandle<()>> {
+        self.metrics_store.as_ref()?;
+        let service = Arc::clone(self);
+        Some(tokio::spawn(async move {
+            let mut save = tokio::time::interval(interval);
+            save.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick is immediate, and there is nothing new to save yet
+            save.tick().await;
+            loop {
+                save.tick().await;
+                if let Err(e) = service.save_agent_metrics() {
+                    tracing::error!("{:#}", e);
+                }
+            }
+        }))
+    }
+
+    /// Token usage over time of the agents the caller sees; `until` may not
+    /// come before `since`
+    pub fn handle_usage(&self, tenancy: &Tenancy, params: &UsageParams) -> Result<UsageResponse, MCPError> {
+        if let (Some(since), Some(until)) = (params.since, params.until) {
+            if until < since {
+                return Err(MCPError::InvalidFields(vec![FieldError::new("until", "at or after since", until.to_rfc3339())]));
+            }
+        }
+        Ok(self.usage.query(params, |agent_id| tenancy.sees(self.agent_owner(agent_id, self.agent_metrics.get(agent_id).as_deref()).as_deref())))
+    }
+
+    /// The hourly activity export `params` asks for, of the agents the caller
+    /// sees. `since` must come before `until`, at most
+    /// `metrics.export_max_span_days` apart.
+    pub fn handle_metrics_export(
+        self: &Arc<Self>,
+        tenancy: &Tenancy,
+        params: &ExportParams,
+    ) -> Result<(ExportPlan, impl futures::Stream<Item = Result<warp::hyper::body::Bytes, std::convert::Infallible>> + Send + 'static), MCPError> {
+        let until = params.until.unwrap_or_else(|| self.clock.now());
+        let since = params.since.unwrap_or(until - chrono::Duration::days(1));
+        if until <= since {
+            return Err(MCPError::InvalidFields(vec![FieldError::new("until", "after since", until.to_rfc3339())]));
+        }
+        let max_span = chrono::Duration::days(i64::from(self.export_max_span_days));
+        if until - since > max_span {
+            let expected = format!("at most {} days after since", self.export_max_span_days);
+            return Err(MCPError::InvalidFields(vec![FieldError::new("until", expected, until.to_rfc3339())]));
+        }
+        let plan = ExportPlan { format: params.format, agent_id: params.agent_id.clone(), since, until, granularity: params.granularity };
+        let (service, tenancy) = (Arc::clone(self), tenancy.clone());
+        let visible = move |agent_id: &str| tenancy.sees(service.agent_owner(agent_id, service.agent_metrics.get(agent_id).as_deref()).as_deref());
+        Ok((plan.clone(), Arc::clone(&self.history).export(plan, visible)))
+    }
+
+    /// Every quota with what its current window has used
+    pub fn handle_quotas(&self) -> QuotaStatus {
+        let config = self.quotas.config();
+        let owner = |agent_id: &str| self.agent_owner(agent_id, self.agent_metrics.get(agent_id).as_deref());
+        let now = self.clock.now();
+        let quotas = config.rules.iter().map(|rule| rule.standing(&self.usage, now, owner)).collect();
+        QuotaStatus { warn_percent: config.warn_percent, quotas }
+    }
+
+    /// Replaces the quotas, refusing the new set whole if any rule is invalid.
+    /// Consumption so far counts against the new caps.
+    pub fn handle_update_quotas(&self, config: QuotaConfig) -> Result<QuotaStatus, MCPError> {
+        let problems = config.problems();
+        if !problems.is_empty() {
+            return Err(MCPError::InvalidFields(problems));
+        }
+        tracing::warn!("Quotas replaced: {} rules, warning at {}%", config.rules.len(), config.warn_percent);
+        self.quotas.update(config);
+        Ok(self.handle_quotas())
+    }
+
+    pub fn handle_concurrency(&self) -> ConcurrencyStatus {
+        self.concurrency.status()
+    }
+
+    pub fn handle_overload(&self) -> OverloadStatus {
+        self.overload.status(std::time::Instant::now(), self.concurrency.status().in_use)
+    }
+
+    /// Changes the overload thresholds, refusing the update whole if any
+    /// setting is out of bounds
+    pub fn handle_update_overload(&self, update: OverloadUpdate) -> Result<OverloadStatus, MCPError> {
+        let config = update.apply(&self.handle_overload().config);
+        let problems = config.problems();
+        if !problems.is_empty() {
+            return Err(MCPError::InvalidFields(problems));
+        }
+        self.overload.update(config);
+        tracing::warn!(
+            "Overload detection {}, tripping at {} in flight, p95 latency {} ms or p95 queue wait {} ms",
+            if config.enabled { "enabled" } else { "disabled" },
+            config.max_in_flight,
+            config.max_p95_ms,
+            config.max_queue_wait_ms
+        );
+        Ok(self.handle_overload())
+    }
+
+    /// Changes the concurrency limit or wait; the limit must stay positive
+    pub fn handle_update_concurrency(&self, update: ConcurrencyUpdate) -> Result<ConcurrencyStatus, MCPError> {
+        if update.max_in_flight == Some(0) {
+            return Err(MCPError::InvalidFields(vec![FieldError::new("max_in_flight", "positive", 0)]));
+        }
+        Ok(self.concurrency.update(&update))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::EstimateTokenizer;
+
+    fn params(prompt: &str, flat_rag_context: bool) -> MCPParams {
+        MCPParams {
+            agent_id: "test_agent".to_string(),
+            model: "mock".to_string(),
+            specialty: "science".to_string(),
+            prompt: prompt.to_string(),
+            max_tokens: 256,
+            temperature: 0.7,
+            use_rag: true,
+            context_window: 4096,
+            flat_rag_context,
+            since: None,
+            until: None,
+            doc_ids: None,
+            dedupe_chunks: true,
+            ethical_framework: None,
+            void_shrine_context: false,
+            moral_recentering: MoralRecenteringMode::Auto,
+            session_id: None,
+            timeout_ms: None,
+            template: None,
+            verbose_confidence: false,
+            dry_run: false,
+            dry_run_skip_conditions: false,
+            rag_top_k: None,
+            rag_min_score: None,
+            rag_filters: HashMap::new(),
+            rag_collection: None,
+            rag_mode: None,
+            rag_engine: None,
+            sources: ParamSources::default(),
+        }
+    }
+
+    fn deadline() -> Deadline {
+        TimeoutConfig::default().deadline(&params("", false))
+    }
+
+    async fn service_with_knowledge() -> VoidShrineMCP {
+        let service = VoidShrineMCP::default();
+        let mut rag = crate::rag_engine::RAGEngine::new().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        *service.rag_engine.write().await = Some(rag);
+        service
+    }
+
+    #[tokio::test]
+    async fn rag_query_returns_structured_citations() {
+        let service = service_with_knowledge().await;
+
+        let result = service.handle_rag_query(params("care ethics", true), deadline()).await.unwrap();
+        let citations = result.citations.unwrap();
+        assert_eq!(citations[0].index, 1);
+        assert_eq!(citations[0].document_id, "care_ethics");
+        assert_eq!(citations[0].title, "Care Ethics Framework");
+        assert_eq!(citations[0].metadata.get("source").map(String::as_str), Some("moral_framework"));
+        assert!(citations[0].snippet.ends_with('…'));
+
+        // Legacy flat form mirrors the citations
+        let flat = result.rag_context.unwrap();
+        assert_eq!(flat.len(), citations.len());
+        assert!(flat[0].starts_with("[Document: Care Ethics Framework (care_ethics)]"));
+    }
+
+    #[tokio::test]
+    async fn flat_context_can_be_switched_off() {
+        let service = service_with_knowledge().await;
+
+        let result = service.handle_llm_inference(params("care ethics", false), deadline()).await.unwrap().0;
+        assert!(result.rag_context.is_none());
+        assert_eq!(result.metrics.rag_documents_used as usize, result.citations.unwrap().len());
+    }
+
+    #[test]
+    fn unsupported_methods_suggest_the_nearest_one() {
+        assert_eq!(McpMethod::parse("rag_answer").unwrap(), McpMethod::RagAnswer);
+        assert_eq!(McpMethod::parse("llm_inferance").unwrap_err().unsupported_method(), Some("llm_inferance"));
+
+        assert_eq!(McpMethod::suggest("llm_inferance"), Some(McpMethod::LlmInference));
+        assert_eq!(McpMethod::suggest("LLM_Inference"), Some(McpMethod::LlmInference));
+        assert_eq!(McpMethod::suggest("rag_qeury"), Some(McpMethod::RagQuery));
+        assert_eq!(McpMethod::suggest("rag-answr"), Some(McpMethod::RagAnswer));
+        // Too far from everything to be a typo
+        assert_eq!(McpMethod::suggest("summon"), None);
+        assert_eq!(McpMethod::suggest("rag"), None);
+        assert_eq!(McpMethod::suggest(""), None);
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[tokio::test]
+    async fn metrics_count_requests_errors_and_rag_queries() {
+        let service = service_with_knowledge().await;
+        service.chaos_config.write().await.enabled = false;
+        let request = |method: &str, agent_id: &str| {
+            let mut params = params("care ethics", true);
+            params.agent_id = agent_id.to_string();
+            MCPRequest { method: method.to_string(), params, request_id: None, idempotency_key: None }
+        };
+        service.handle_mcp_request(request("rag_query", "alpha")).await.unwrap();
+        service.handle_mcp_request(request("llm_inference", "beta")).await.unwrap();
+        service.handle_mcp_request(request("summon", "beta")).await.unwrap_err();
+
+        let metrics = service.handle_metrics(&Tenancy::All, &MetricsParams::default());
+        assert_eq!(metrics.server.total_requests, 3);
+        assert_eq!(metrics.server.requests_by_method, BTreeMap::from([
+            ("llm_inference".to_string(), 1),
+            ("rag_query".to_string(), 1),
+        ]));
+        assert_eq!(metrics.server.errors_by_class, BTreeMap::from([("unsupported_method".to_string(), 1)]));
+        assert_eq!(metrics.server.rag_queries, 2);
+        let agents: Vec<(&str, u64)> = metrics.agents.iter().map(|a| (a.agent_id.as_str(), a.total_requests)).collect();
+        assert_eq!(agents, [("alpha", 1), ("beta", 2)]);
+
+        let one = service.handle_metrics(&Tenancy::All, &MetricsParams { agent_id: Some("beta".to_string()), since: None });
+        assert_eq!(one.agents.len(), 1);
+        let later = service.handle_metrics(&Tenancy::All, &MetricsParams { agent_id: None, since: Some(Utc::now() + chrono::Duration::seconds(1)) });
+        assert!(later.agents.is_empty());
+        assert_eq!(later.server.total_requests, 3);
+    }
+
+    #[test]
+    fn param_limits_report_each_violation() {
+        let limits = ParamLimits::default();
+        assert_eq!(limits.check(&params("care ethics", false), &EstimateTokenizer), Ok(()));
+
+        let mut invalid = params(" ", false);
+        invalid.temperature = f64::NAN;
+        invalid.max_tokens = 40_000;
+        invalid.context_window = 8192;
+        let errors = limits.check(&invalid, &EstimateTokenizer).unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["temperature", "max_tokens", "prompt", "context_window"]);
+        assert_eq!(errors[0].value, serde_json::Value::Null);
+        assert_eq!(errors[3].to_string(), "context_window must be between max_tokens (40000) and 1048576 (got 8192)");
+
+        let tight = ParamLimits { max_prompt_bytes: 4, ..Default::default() };
+        assert_eq!(tight.check(&params("care ethics", false), &EstimateTokenizer).unwrap_err()[0].value, serde_json::json!(11));
+
+        let mut crowded = params(&"word ".repeat(1000), false);
+        crowded.context_window = 1024;
+        crowded.max_tokens = 64;
+        let errors = limits.check(&crowded, &EstimateTokenizer).unwrap_err();
+        assert_eq!(errors[0].to_string(), "prompt must be at most 960 tokens, leaving max_tokens of the context_window (got 1250)");
+    }
+
+    #[test]
+    fn flat_context_defaults_on_for_existing_clients() {
+        let params: MCPParams = serde_json::from_value(serde_json::json!({
+            "agent_id": "a", "model": "m", "specialty": "science", "prompt": "p",
+            "max_tokens": 1, "temperature": 0.0, "use_rag": true, "context_window": 1
+        })).unwrap();
+        assert!(params.flat_rag_context);
+    }
+
+    #[tokio::test]
+    async fn rag_answer_returns_cited_sentences() {
+        let service = service_with_knowledge().await;
+        let request = MCPRequest { method: "rag_answer".to_string(), params: params("what is care ethics", true), request_id: None, idempotency_key: None };
+
+        let result = service.handle_mcp_request(request).await.unwrap().result;
+        assert!(result.response.starts_with("Care ethics prioritizes relational wellbeing and stakeholder agency. [1]"));
+        let citations = result.citations.unwrap();
+        assert_eq!(citations[0].snippet, "Care ethics prioritizes relational wellbeing and stakeholder agency.");
+        assert_eq!(citations[0].chunk_id, "care_ethics_0");
+    }
+
+    #[tokio::test]
+    async fn patch_document_updates_tags_and_metadata() {
+        let service = service_with_knowledge().await;
+        let patch = DocumentPatch {
+            set_metadata: HashMap::from([("status".to_string(), "reviewed".to_string())]),
+            remove_metadata: vec!["source".to_string()],
+            add_tags: vec!["Verified".to_string(), "draft".to_string()],
+            remove_tags: vec!["draft".to_string()],
+        };
+
+        let info = service.handle_patch_document(&Tenancy::All, None, "care_ethics".to_string(), patch).await.unwrap().unwrap();
+        assert_eq!(info.tags, vec!["verified"]);
+        assert_eq!(info.metadata.get("status").map(String::as_str), Some("reviewed"));
+        assert!(!info.metadata.contains_key("source"));
+
+        let missing = service.handle_patch_document(&Tenancy::All, None, "missing".to_string(), DocumentPatch::default()).await.unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[tokio::test]
+    async fn refused_patches_change_nothing() {
+        let service = service_with_knowledge().await;
+        let before = service.rag_engine.read().await.as_ref().unwrap().document_info("care_ethics").await.unwrap().unwrap();
+        let patch = DocumentPatch {
+            set_metadata: HashMap::from([("status".to_string(), "reviewed".to_string())]),
+            remove_metadata: vec!["source".to_string()],
+            add_tags: vec!["verified".to_string(), " ".to_string()],
+            remove_tags: Vec::new(),
+        };
+
+        match service.handle_patch_document(&Tenancy::All, None, "care_ethics".to_string(), patch).await {
+            Err(MCPError::InvalidFields(errors)) => assert_eq!(errors[0].field, "add_tags[1]"),
+            other => panic!("expected invalid fields, got {:?}", other.map(|_| ())),
+        }
+        let after = service.rag_engine.read().await.as_ref().unwrap().document_info("care_ethics").await.unwrap().unwrap();
+        assert_eq!((after.metadata, after.tags), (before.metadata, before.tags));
+    }
+
+    #[tokio::test]
+    async fn patched_metadata_is_held_to_the_validation_limits() {
+        let service = service_with_knowledge().await;
+        let patch = DocumentPatch {
+            set_metadata: HashMap::from([("notes".to_string(), "x".repeat(16 * 1024))]),
+            ..DocumentPatch::default()
+        };
+        let error = service.handle_patch_document(&Tenancy::All, None, "care_ethics".to_string(), patch).await.unwrap_err();
+        assert!(matches!(&error, MCPError::Validation(ValidationError::MetadataTooLarge { .. })), "{:?}", error);
+        assert_eq!((error.http_status(), error.code()), (400, "metadata_too_large"));
+        let info = service.rag_engine.read().await.as_ref().unwrap().document_info("care_ethics").await.unwrap().unwrap();
+        assert!(!info.metadata.contains_key("notes"));
+    }
+
+    #[tokio::test]
+    async fn backups_stay_inside_the_backup_directory() {
+        let request = |path: &str| BackupRequest { path: path.to_string() };
+        let unconfigured = service_with_knowledge().await;
+        assert!(unconfigured.handle_backup(None, request("kb.sqlite")).await.is_err());
+
+        let dir = std::env::temp_dir().join(format!("void-shrine-backups-{}", Uuid::new_v4()));
+        let service = service_with_knowledge().await.with_backup_dir(&dir);
+
+        for escaping in ["../kb.sqlite", "/tmp/kb.sqlite", "nightly/../../kb.sqlite", ""] {
+            assert!(service.handle_backup(None, request(escaping)).await.is_err(), "{}", escaping);
+        }
+
+        let report = service.handle_backup(None, request("nightly/kb.sqlite")).await.unwrap();
+        assert_eq!(report.path, dir.join("nightly/kb.sqlite"));
+        assert!(report.pages > 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn context_falls_back_to_summaries_when_chunks_do_not_fit() {
+        let result = |chunk: usize| SearchResult {
+            document_id: "care_ethics".to_string(),
+            title: "Care Ethics Framework".to_string(),
+            chunk_id: format!("care_ethics_{}", chunk),
+            content: "Care ethics prioritizes relational wellbeing. ".repeat(40),
+            similarity_score: 1.0,
+            metadata: HashMap::new(),
+            matched_fields: vec![],
+        };
+        let results = vec![result(0), result(1)];
+        let summaries = HashMap::from([("care_ethics".to_string(), Some("Care first.".to_string()))]);
+
+        let mut roomy = params("care ethics", true);
+        roomy.context_window = 8192;
+        let (mode, blocks) = VoidShrineMCP::context_blocks(&results, &summaries, &roomy, "care ethics", &EstimateTokenizer);
+        assert_eq!(mode, ContextMode::FullChunks);
+        assert_eq!(blocks.len(), 2);
+
+        let mut tight = params("care ethics", true);
+        tight.context_window = 512;
+        tight.max_tokens = 256;
+        let (mode, blocks) = VoidShrineMCP::context_blocks(&results, &summaries, &tight, "care ethics", &EstimateTokenizer);
+        assert_eq!(mode, ContextMode::Summaries);
+        assert_eq!(blocks, vec!["[1] [Summary: Care Ethics Framework (care_ethics)] Care first."]);
+
+        // Without any summaries there is nothing smaller to fall back to
+        let none = HashMap::from([("care_ethics".to_string(), None)]);
+        assert_eq!(VoidShrineMCP::context_blocks(&results, &none, &tight, "care ethics", &EstimateTokenizer).0, ContextMode::FullChunks);
+    }
+
+    #[test]
+    fn context_is_fitted_greedily_and_cut_after_a_sentence() {
+        let blocks = vec![
+            "[1] Alpha comes first. Alpha is short.".to_string(),
+            format!("[2] {}", "Beta is long. ".repeat(50).trim_end()),
+            "[3] Gamma never fits.".to_string(),
+        ];
+        let mut tight = params("care ethics", true);
+        tight.max_tokens = 64;
+        tight.context_window = 64 + 95;
+
+        let template = Template::builtin();
+        let vars = PromptVars { user_prompt: "care ethics", ..PromptVars::default() };
+        let (context, kept) = VoidShrineMCP::fit_context(&blocks, &tight, &template, vars, &EstimateTokenizer);
+        let prompt = template.render_user(&PromptVars { rag_context: &context, ..vars });
+        assert_eq!(kept, 2);
+        assert!(prompt.contains("<context>\n[1] Alpha comes first. Alpha is short.\n\n[2] Beta is long."), "{}", prompt);
+        assert!(prompt.ends_with("Beta is long.…\n</context>\n\nUser prompt: care ethics"), "{}", prompt);
+        assert!(!prompt.contains("[3]"));
+        assert!(EstimateTokenizer.count(&prompt) <= 95);
+
+        // Not even a word of the first block fits
+        tight.context_window = 64 + 40;
+        assert_eq!(VoidShrineMCP::fit_context(&blocks, &tight, &template, vars, &EstimateTokenizer), (String::new(), 0));
+        assert_eq!(template.render_user(&vars), "care ethics");
+    }
+
+    #[test]
+    fn summaries_cover_every_result_of_their_document() {
+        let result = |document_id: &str| SearchResult {
+            document_id: document_id.to_string(),
+            title: document_id.to_string(),
+            chunk_id: format!("{}_0", document_id),
+            content: "Content.".to_string(),
+            similarity_score: 1.0,
+            metadata: HashMap::new(),
+            matched_fields: vec![],
+        };
+        let results = vec![result("a"), result("b"), result("a"), result("c")];
+        assert_eq!(VoidShrineMCP::covered_results(&results, ContextMode::Summaries, 2), [true, true, true, false]);
+        assert_eq!(VoidShrineMCP::covered_results(&results, ContextMode::FullChunks, 2), [true, true, false, false]);
+    }
+
+    #[tokio::test]
+    async fn retrieved_chunks_left_out_are_counted_and_not_cited() {
+        let service = service_with_knowledge().await;
+        let mut tight = params("care ethics", true);
+        tight.max_tokens = 128;
+        tight.context_window = 128 + 16;
+
+        let result = service.handle_llm_inference(tight, deadline()).await.unwrap().0;
+        let metrics = &result.metrics;
+        assert_eq!(metrics.rag_chunks_included, 0);
+        assert!(metrics.rag_chunks_dropped > 0, "{:?}", metrics);
+        assert_eq!(result.citations.as_ref().map_or(0, Vec::len), 0);
+        assert_eq!(result.rag_context.as_ref().map_or(0, Vec::len), 0);
+        assert!(EstimateTokenizer.count(&result.prompt.unwrap().user) <= 16);
+
+        let roomy = service.handle_llm_inference(params("care ethics", true), deadline()).await.unwrap().0;
+        assert_eq!(roomy.metrics.rag_chunks_dropped, 0);
+        assert!(roomy.metrics.rag_chunks_included > 0);
+    }
+
+    #[tokio::test]
+    async fn params_time_range_restricts_retrieval() {
+        let service = service_with_knowledge().await;
+        let mut fresh_only = params("care ethics", true);
+        fresh_only.since = Some(Utc::now() + chrono::Duration::hours(1));
+
+        let result = service.handle_rag_query(fresh_only, deadline()).await.unwrap();
+        assert!(result.citations.unwrap().is_empty());
+        let result = service.handle_rag_query(params("care ethics", true), deadline()).await.unwrap();
+        assert!(!result.citations.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_documents_map_to_error_codes() {
+        let service = service_with_knowledge().await;
+        let request = |id: &str, content: &str| IndexDocumentRequest {
+            id: Some(id.to_string()),
+            title: "Title".to_string(),
+            content: content.to_string(),
+            metadata: HashMap::new(),
+        };
+
+        let error = service.handle_index_document(&Tenancy::All, None, request("no spaces", "text")).await.unwrap_err();
+        assert_eq!((error.code(), error.http_status()), ("invalid_id", 400));
+        let error = service.handle_index_document(&Tenancy::All, None, request("empty", "")).await.unwrap_err();
+        assert_eq!(error.code(), "empty_content");
+
+        let response = service.handle_index_document(&Tenancy::All, None, request("field_notes", "Notes from the field.")).await.unwrap();
+        assert_eq!(response.document_id, "field_notes");
+        assert_eq!(MCPError::from(anyhow::anyhow!("disk full")).code(), "internal_error");
+    }
+
+    #[tokio::test]
+    async fn params_doc_ids_scope_retrieval() {
+        let service = service_with_knowledge().await;
+        let mut scoped = params("care ethics", true);
+        scoped.doc_ids = Some(vec!["agent_coordination".to_string()]);
+        let result = service.handle_rag_query(scoped, deadline()).await.unwrap();
+        assert!(result.citations.unwrap().iter().all(|c| c.document_id == "agent_coordination"));
+
+        let mut nothing = params("care ethics", true);
+        nothing.doc_ids = Some(Vec::new());
+        let result = service.handle_rag_query(nothing, deadline()).await.unwrap();
+        assert!(result.citations.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn streamed_inference_emits_stages_then_text_then_done() {
+        let service = Arc::new(service_with_knowledge().await);
+        service.chaos_config.write().await.enabled = false;
+
+        let recentered = MCPParams { moral_recentering: MoralRecenteringMode::On, ..params("care ethics", true) };
+        let events: Vec<InferenceEvent> = service.stream_llm_inference(recentered, None).unwrap().collect().await;
+        let names: Vec<&str> = events.iter().map(InferenceEvent::name).collect();
+        assert_eq!(&names[..3], ["chaos_applied", "rag_context", "moral_recentering"]);
+        match &events[2] {
+            InferenceEvent::MoralRecentering { report: Some(report), .. } => assert_eq!(report.ethical_framework, "care-ethics"),
+            other => panic!("expected a recentering report, got {:?}", other),
+        }
+        assert_eq!(names.last(), Some(&"done"));
+        assert!(names[3..names.len() - 1].iter().all(|name| *name == "delta"));
+
+        let text: String = events.iter().filter_map(|event| match event {
+            InferenceEvent::Delta { text } => Some(text.as_str()),
+            _ => None,
+        }).collect();
+        assert!(!text.is_empty());
+        match events.last() {
+            Some(InferenceEvent::Done { response, metrics, metadata }) => {
+                assert_eq!(*response, text);
+                assert!(metrics.rag_documents_used > 0);
+                assert!(metadata.moral_recentered && !metadata.chaos_applied);
+            }
+            other => panic!("expected done, got {:?}", other),
+        }
+        assert_eq!(service.agent_metrics.get("test_agent").unwrap().cancelled_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn dropping_a_stream_cancels_the_inference() {
+        let service = Arc::new(service_with_knowledge().await);
+        service.chaos_config.write().await.enabled = false;
+
+        let mut stream = service.stream_llm_inference(params("care ethics", true), None).unwrap();
+        assert!(matches!(stream.next().await, Some(InferenceEvent::ChaosApplied { .. })));
+        drop(stream);
+
+        assert_eq!(service.agent_metrics.get("test_agent").unwrap().cancelled_requests, 1);
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while service.agent_metrics.get("test_agent").unwrap().in_flight > 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        }).await.expect("in-flight count never returned to zero");
+    }
+
+    struct FixedBackend;
+
+    impl LLMBackend for FixedBackend {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn complete<'a>(&'a self, prompt: &'a Prompt, _params: &'a MCPParams) -> futures::future::BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+            let echoed = prompt.user.contains("care ethics") && prompt.system.is_some();
+            Box::pin(async move {
+                Ok(CompletionOutput {
+                    text: format!("echoed: {}", echoed),
+                    prompt_tokens: 120,
+                    completion_tokens: 30,
+                    finish_reason: crate::llm_backend::FinishReason::Length,
+                    generation_time: None,
+                    mean_logprob: None,
+                })
+            })
+        }
+    }
+
+    /// Records every prompt it completes
+    #[derive(Default)]
+    struct CapturingBackend {
+        prompts: std::sync::Mutex<Vec<Prompt>>,
+    }
+
+    impl LLMBackend for CapturingBackend {
+        fn name(&self) -> &str {
+            "capturing"
+        }
+
+        fn complete<'a>(&'a self, prompt: &'a Prompt, _params: &'a MCPParams) -> futures::future::BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+            self.prompts.lock().unwrap().push(prompt.clone());
+            Box::pin(async move {
+                Ok(CompletionOutput {
+                    text: "noted".to_string(),
+                    prompt_tokens: 10,
+                    completion_tokens: 1,
+                    finish_reason: crate::llm_backend::FinishReason::Stop,
+                    generation_time: None,
+                    mean_logprob: None,
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn moral_recentering_reaches_the_backend_when_requested() {
+        let backend = Arc::new(CapturingBackend::default());
+        let service = VoidShrineMCP::default().with_backend(backend.clone());
+        service.chaos_config.write().await.enabled = false;
+        let inference = |params: MCPParams| MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None };
+        let base = MCPParams { use_rag: false, ..params("Plan the rollout", false) };
+
+        // Auto without a framework or context leaves the prompt alone
+        let plain = service.handle_mcp_request(inference(base.clone())).await.unwrap();
+        assert!(!plain.metadata.moral_recentered);
+        assert!(plain.result.moral_recentering.is_none());
+
+        let requested = MCPParams { ethical_framework: Some("care-ethics".to_string()), void_shrine_context: true, ..base.clone() };
+        let recentered = service.handle_mcp_request(inference(requested)).await.unwrap();
+        assert!(recentered.metadata.moral_recentered);
+        let report = recentered.result.moral_recentering.unwrap();
+        assert_eq!(report.ethical_adjustments.len(), 4);
+        assert_eq!(report.care_ethics_score, 0.6);
+        assert!(report.score_breakdown.factors.is_empty());
+
+        let off = MCPParams { moral_recentering: MoralRecenteringMode::Off, void_shrine_context: true, ..base.clone() };
+        let untouched = service.handle_mcp_request(inference(off)).await.unwrap();
+        assert!(!untouched.metadata.moral_recentered && untouched.result.moral_recentering.is_none());
+
+        // An unknown framework runs but changes nothing, and says so
+        let unknown = MCPParams { ethical_framework: Some("astrology".to_string()), ..base.clone() };
+        let unchanged = service.handle_mcp_request(inference(unknown.clone())).await.unwrap();
+        assert!(!unchanged.metadata.moral_recentered);
+        assert_eq!(unchanged.result.moral_recentering.unwrap().ethical_adjustments, ["unknown_framework: astrology"]);
+
+        // Unless unknown frameworks are refused
+        let strict = VoidShrineMCP::default()
+            .with_backend(backend.clone())
+            .with_moral(EthicalFrameworks::open(&crate::moral::MoralConfig { strict_frameworks: true, ..Default::default() }).unwrap());
+        strict.chaos_config.write().await.enabled = false;
+        let refused = strict.handle_mcp_request(inference(unknown)).await.unwrap_err();
+        assert_eq!(refused.error.code(), "invalid_params");
+
+        let prompts = backend.prompts.lock().unwrap();
+        assert_eq!(prompts[0].user, "Plan the rollout");
+        assert!(prompts[0].system.is_some());
+        assert_eq!(
+            prompts[1].user,
+            "Through the lens of generative absence and emergent intelligence: \
+             Considering the wellbeing and agency of all affected parties: Plan the rollout"
+        );
+        assert_eq!(prompts[2], Prompt::user("Plan the rollout"));
+        assert_eq!(prompts[3].user, "Plan the rollout");
+    }
+
+    #[tokio::test]
+    async fn inference_uses_the_configured_backend() {
+        let service = service_with_knowledge().await.with_backend(Arc::new(FixedBackend));
+
+        let result = service.handle_llm_inference(params("care ethics", true), deadline()).await.unwrap().0;
+        assert_eq!(result.response, "echoed: true");
+        assert_eq!(result.metrics.token_count, 150);
+        assert!(result.metrics.response_time_ms < 1000);
+
+        assert_eq!((result.metrics.prompt_tokens, result.metrics.completion_tokens), (120, 30));
+
+        // The mock reports no counts, so the tokenizer counts what was sent and returned
+        let default = VoidShrineMCP::default().handle_llm_inference(params("hello", false), deadline()).await.unwrap().0;
+        assert!(default.response.starts_with("[MCP-Enhanced]"));
+        let sent = default.prompt.as_ref().unwrap().flattened();
+        assert_eq!(default.metrics.prompt_tokens as usize, EstimateTokenizer.count(&sent));
+        assert_eq!(default.metrics.completion_tokens as usize, EstimateTokenizer.count(&default.response));
+        assert_eq!(default.metrics.token_count, default.metrics.prompt_tokens + default.metrics.completion_tokens);
+    }
+
+    /// One token per whitespace-separated word
+    #[derive(Debug)]
+    struct WordTokenizer;
+
+    impl Tokenizer for WordTokenizer {
+        fn name(&self) -> &str {
+            "words"
+        }
+
+        fn count(&self, text: &str) -> usize {
+            text.split_whitespace().count()
+        }
+    }
+
+    #[tokio::test]
+    async fn the_tokenizer_drives_every_budget() {
+        let service = VoidShrineMCP::default().with_tokenizer(Arc::new(WordTokenizer));
+        // Over the window by the estimate's count, well within it by words
+        let mut long_words = params(&"incomprehensibilities ".repeat(100), false);
+        long_words.context_window = 400;
+        long_words.max_tokens = 200;
+        assert!(ParamLimits::default().check(&long_words, &EstimateTokenizer).is_err());
+        assert!(service.validate_params(&long_words).is_ok());
+
+        let result = service.handle_llm_inference(long_words, deadline()).await.unwrap().0;
+        assert_eq!(result.metrics.completion_tokens as usize, result.response.split_whitespace().count());
+    }
+
+    #[tokio::test]
+    async fn settings_come_from_the_config() {
+        let config = Config::from_toml(
+            "[chaos]\nenabled = false\n[limits]\nmax_tokens = 100\n[[backends.backends]]\nname = \"only\"\nkind = \"mock\"\n",
+        )
+        .unwrap();
+        let service = VoidShrineMCP::new(&config).unwrap();
+        assert!(!service.chaos_config.read().await.enabled);
+        assert_eq!(service.validate_params(&params("hello", false)).unwrap_err().fields()[0].field, "max_tokens");
+        // Configured backends replace the mock, and nothing routes unknown models here
+        assert!(service.handle_list_models().default_backend.is_none());
+    }
+
+    #[test]
+    fn chaos_spares_protected_methods_from_destructive_faults() {
+        let mut rng = rand::rngs::mock::StepRng::new(0, 1);
+        let mut config = ChaosConfig {
+            intensity: 1.0,
+            chaos_types: vec!["network_delay".to_string(), "request_drop".to_string()],
+            protected_methods: vec!["rag_query".to_string()],
+            ..ChaosConfig::default()
+        };
+        config.weights.insert("network_delay".to_string(), 0.0);
+        assert_eq!(config.pick("a", None, "llm_inference", &mut rng), Some("request_drop"));
+        // Only the zero-weight delay is left for the protected method
+        assert_eq!(config.pick("a", None, "rag_query", &mut rng), None);
+        config.enabled = false;
+        assert_eq!(config.pick("a", None, "llm_inference", &mut rng), None);
+    }
+
+    #[test]
+    fn corruption_always_changes_the_text() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            assert_ne!(corrupt_text("abcdefghijklmnopqrstuvwxyz", &mut rng), "abcdefghijklmnopqrstuvwxyz");
+            assert_eq!(corrupt_text("a", &mut rng), "");
+        }
+    }
+
+    #[test]
+    fn wildcards_match_any_run_of_characters() {
+        assert!(wildcard_matches("canary-*", "canary-7"));
+        assert!(wildcard_matches("*-prod-*", "eu-prod-3"));
+        assert!(wildcard_matches("a*b*b", "abbb"));
+        assert!(!wildcard_matches("canary-*", "prod-canary-7"));
+        assert!(!wildcard_matches("canary", "canary-7"));
+        assert!(wildcard_matches("*", ""));
+    }
+
+    #[test]
+    fn chaos_targeting_scales_and_exempts_agents() {
+        let mut config = ChaosConfig { intensity: 0.2, ..ChaosConfig::default() };
+        config.targeting.include_agents = vec!["canary-*".to_string(), "scout".to_string()];
+        config.targeting.exclude_agents = vec!["canary-prod".to_string()];
+        config.targeting.agent_multipliers.insert("canary-*".to_string(), 2.0);
+        config.targeting.agent_multipliers.insert("canary-7".to_string(), 10.0);
+        config.targeting.specialty_intensity.insert("tactical".to_string(), 0.3);
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+
+        assert_eq!(config.intensity_for("scout", Some("science")), 0.2);
+        assert_eq!(config.intensity_for("scout", Some("tactical")), 0.3);
+        assert_eq!(config.intensity_for("canary-1", None), 0.4);
+        // The exact name wins and the chance is capped
+        assert_eq!(config.intensity_for("canary-7", None), 1.0);
+        assert_eq!(config.intensity_for("canary-prod", None), 0.0);
+        assert_eq!(config.intensity_for("production", None), 0.0);
+
+        // An exempt agent is never picked, even at full intensity
+        config.intensity = 1.0;
+        let mut rng = rand::rngs::mock::StepRng::new(0, 0);
+        assert_eq!(config.pick("canary-prod", None, "llm_inference", &mut rng), None);
+        assert!(config.pick("canary-1", None, "llm_inference", &mut rng).is_some());
+
+        config.targeting.exclude_agents.push("canary-*".to_string());
+        let problems = config.validate();
+        assert_eq!(problems, ["chaos.targeting includes 'canary-*' but excludes it with 'canary-*'"]);
+    }
+
+    #[tokio::test]
+    async fn chaos_faults_fail_or_alter_requests() {
+        let service = VoidShrineMCP::default();
+        let request = || MCPRequest { method: "llm_inference".to_string(), params: params("hello", false), request_id: None, idempotency_key: None };
+        let only = |chaos_type: &str| ChaosConfig {
+            intensity: 1.0,
+            chaos_types: vec![chaos_type.to_string()],
+            error_class: InjectedErrorClass::BackendUnavailable,
+            ..ChaosConfig::default()
+        };
+
+        *service.chaos_config.write().await = only("error_injection");
+        let failure = service.handle_mcp_request(request()).await.unwrap_err();
+        assert_eq!((failure.error.code(), failure.error.http_status()), ("backend_unavailable", 503));
+
+        *service.chaos_config.write().await = only("request_drop");
+        let failure = service.handle_mcp_request(request()).await.unwrap_err();
+        assert_eq!((failure.error.code(), failure.error.http_status()), ("request_dropped", 504));
+
+        service.chaos_config.write().await.enabled = false;
+        let clean = service.handle_mcp_request(request()).await.unwrap().result.response;
+        *service.chaos_config.write().await = only("response_corruption");
+        let response = service.handle_mcp_request(request()).await.unwrap();
+        assert!(response.metadata.chaos_applied);
+        assert_eq!(response.metadata.chaos_type.as_deref(), Some("response_corruption"));
+        assert_ne!(response.result.response, clean);
+        assert_eq!(service.counters.snapshot().chaos_events, 3);
+    }
+
+    #[tokio::test]
+    async fn seeded_chaos_repeats_and_replays() {
+        let seeded = || async {
+            let service = VoidShrineMCP::default();
+            *service.chaos_config.write().await = ChaosConfig {
+                intensity: 0.5,
+                chaos_types: vec!["network_delay".to_string(), "response_corruption".to_string()],
+                seed: Some(42),
+                ..ChaosConfig::default()
+            };
+            service
+        };
+        let run = |service: VoidShrineMCP| async move {
+            let mut outcomes = Vec::new();
+            for _ in 0..20 {
+                // Temperature 0 leaves the mock's answer to chaos alone
+                let params = MCPParams { temperature: 0.0, ..params("hello world", false) };
+                let request = MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None };
+                let response = service.handle_mcp_request(request).await.unwrap();
+                outcomes.push((response.metadata.chaos_decision, response.metadata.chaos_type, response.result.response));
+            }
+            let advice = service.handle_chaos(ChaosRequest {
+                agent_id: "test_agent".to_string(),
+                specialty: None,
+                chaos_type: "network_delay".to_string(),
+                intensity: 2.0,
+            }).await;
+            (outcomes, advice.decision, advice.delay_ms)
+        };
+
+        let (first, decision, delay) = run(seeded().await).await;
+        let (second, second_decision, second_delay) = run(seeded().await).await;
+        assert_eq!(first, second);
+        assert_eq!((decision, delay), (second_decision, second_delay));
+        assert_eq!(first.iter().map(|(n, ..)| *n).collect::<Vec<_>>(), (1..=20).collect::<Vec<_>>());
+        assert!(first.iter().any(|(_, chaos, _)| chaos.is_some()) && first.iter().any(|(_, chaos, _)| chaos.is_none()));
+
+        // One event replays on its own from the seed and its decision number
+        let config = seeded().await.chaos_config.read().await.clone();
+        for (n, chaos_type, _) in &first {
+            let mut rng = ChaosDice::rng_for(42, *n);
+            assert_eq!(config.pick("test_agent", Some("science"), "llm_inference", &mut rng), chaos_type.as_deref());
+        }
+    }
+
+    #[test]
+    fn throttle_delay_grows_between_the_thresholds() {
+        let config = ThrottleConfig { soft_load: 0.5, hard_load: 1.0, max_delay_ms: 1000, ..ThrottleConfig::default() };
+        assert_eq!(config.decide(0.4), Throttle::Proceed);
+        assert_eq!(config.decide(0.75), Throttle::Delay(std::time::Duration::from_millis(500)));
+        assert_eq!(config.decide(1.0), Throttle::Reject { retry_after: std::time::Duration::from_secs(1) });
+        assert_eq!(ThrottleConfig { enabled: false, ..config }.decide(1.0), Throttle::Proceed);
+    }
+
+    #[tokio::test]
+    async fn loaded_agents_are_delayed_or_refused() {
+        let service = VoidShrineMCP::default()
+            .with_throttle(ThrottleConfig { soft_load: 0.5, hard_load: 1.0, max_delay_ms: 40, ..ThrottleConfig::default() })
+            .with_load(LoadConfig { max_in_flight: 4, ..LoadConfig::default() });
+        service.chaos_config.write().await.enabled = false;
+        let request = || MCPRequest { method: "llm_inference".to_string(), params: params("hello", false), request_id: None, idempotency_key: None };
+
+        // Unknown agents have no load yet
+        let response = service.handle_mcp_request(request()).await.unwrap();
+        assert_eq!(response.result.metrics.throttle_delay_ms, 0);
+
+        let mut running: Vec<_> = (0..3).map(|_| service.track_in_flight("test_agent")).collect();
+        let started = std::time::Instant::now();
+        let response = service.handle_mcp_request(request()).await.unwrap();
+        assert_eq!(response.result.metrics.throttle_delay_ms, 20);
+        assert!(started.elapsed() >= std::time::Duration::from_millis(20));
+
+        running.push(service.track_in_flight("test_agent"));
+        let failure = service.handle_mcp_request(request()).await.unwrap_err();
+        assert_eq!((failure.error.code(), failure.error.http_status()), ("throttled", 429));
+        assert_eq!(ErrorResponse::from(&failure).retry_after_ms, Some(40));
+
+        let report = &service.handle_metrics(&Tenancy::All, &MetricsParams::default()).agents[0];
+        assert_eq!((report.throttled_delayed, report.throttled_rejected), (1, 1));
+        let rendered = service.metrics.render();
+        assert!(rendered.contains(r#"void_shrine_throttled_requests_total{agent_id="test_agent",outcome="rejected"} 1"#), "{}", rendered);
+
+        drop(running);
+        assert!(service.handle_mcp_request(request()).await.is_ok());
+    }
+
+    #[test]
+    fn outcomes_average_response_times_over_a_window() {
+        let mut metrics = AgentMetrics::new();
+        metrics.record_outcome(Some(100), true);
+        assert_eq!((metrics.avg_response_time, metrics.success_rate), (100.0, 1.0));
+        metrics.record_outcome(Some(200), false);
+        assert_eq!((metrics.avg_response_time, metrics.success_rate), (120.0, 0.5));
+        // Outcomes without a time leave the average alone
+        metrics.record_outcome(None, true);
+        assert_eq!(metrics.avg_response_time, 120.0);
+
+        // Only the last SUCCESS_WINDOW requests count
+        for _ in 0..SUCCESS_WINDOW {
+            metrics.record_outcome(None, true);
+        }
+        assert_eq!(metrics.success_rate, 1.0);
+        for _ in 0..SUCCESS_WINDOW / 4 {
+            metrics.record_outcome(None, false);
+        }
+        assert_eq!(metrics.success_rate, 0.75);
+    }
+
+    #[tokio::test]
+    async fn success_rate_counts_server_failures_but_not_rejected_requests() {
+        let service = VoidShrineMCP::default();
+        let request = |prompt: &str| MCPRequest { method: "llm_inference".to_string(), params: params(prompt, false), request_id: None, idempotency_key: None };
+        let agent = |service: &VoidShrineMCP| service.agent_metrics.get("test_agent").unwrap().clone();
+
+        service.chaos_config.write().await.enabled = false;
+        for _ in 0..3 {
+            service.handle_mcp_request(request("hello")).await.unwrap();
+        }
+        *service.chaos_config.write().await = ChaosConfig {
+            intensity: 1.0,
+            chaos_types: vec!["error_injection".to_string()],
+            ..ChaosConfig::default()
+        };
+        service.handle_mcp_request(request("hello")).await.unwrap_err();
+        // An invalid request is the client's mistake, not the agent's failure
+        service.handle_mcp_request(request("")).await.unwrap_err();
+        let unsupported = MCPRequest { method: "summon".to_string(), params: params("hello", false), request_id: None, idempotency_key: None };
+        service.chaos_config.write().await.enabled = false;
+        service.handle_mcp_request(unsupported).await.unwrap_err();
+
+        let metrics = agent(&service);
+        assert_eq!(metrics.recent_outcomes, [true, true, true, false]);
+        assert_eq!(metrics.success_rate, 0.75);
+
+        // Reports through the scaling endpoint land in the same figures, and
+        // the advice covers the requests handled here too
+        let response = service.handle_scaling(ScalingRequest {
+            agent_id: "test_agent".to_string(),
+            response_time: Some(60_000),
+            token_count: None,
+            success: false,
+        }).await;
+        let metrics = agent(&service);
+        assert_eq!(metrics.success_rate, 0.6);
+        assert_eq!(response.adjustments.capacity_change, 0.2);
+        assert!(response.adjustments.description.contains("window 5"), "{}", response.adjustments.description);
+        assert!(response.adjustments.description.contains("error rate 40.0%"), "{}", response.adjustments.description);
+        assert!(metrics.avg_response_time >= 0.2 * 60_000.0, "{}", metrics.avg_response_time);
+    }
+
+    #[tokio::test]
+    async fn load_follows_requests_in_flight_and_their_latency() {
+        // Without idle decay, which would shave the load between the last request finishing and the report
+        let load = LoadConfig { max_in_flight: 4, latency_budget_ms: 100, idle_half_life_secs: 0, ..LoadConfig::default() };
+        let service = VoidShrineMCP::default().with_load(load);
+        service.chaos_config.write().await.enabled = false;
+
+        let first = service.track_in_flight("a");
+        let second = service.track_in_flight("a");
+        let report = |service: &VoidShrineMCP| service.handle_metrics(&Tenancy::All, &MetricsParams::default()).agents[0].clone();
+        assert_eq!((report(&service).in_flight, report(&service).current_load), (2, 0.5));
+        assert_eq!(report(&service).recent_rps, 2.0 / 60.0);
+
+        drop(first);
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+        drop(second);
+        // Nothing runs any more, but the slower request's latency is 60% of the budget
+        let report = report(&service);
+        assert_eq!(report.in_flight, 0);
+        assert!(report.recent_p95_ms >= 60.0 && (0.6..1.0).contains(&report.current_load), "{:?}", report);
+
+        let request = MCPRequest { method: "llm_inference".to_string(), params: params("hello", false), request_id: None, idempotency_key: None };
+        service.handle_mcp_request(request).await.unwrap();
+        assert_eq!(service.agent_metrics.get("test_agent").unwrap().in_flight, 0);
+        assert_eq!(service.handle_metrics(&Tenancy::All, &MetricsParams { agent_id: Some("test_agent".to_string()), ..MetricsParams::default() }).agents[0].recent_rps, 1.0 / 60.0);
+    }
+
+    #[tokio::test]
+    async fn use_rag_without_an_engine_is_flagged_or_refused() {
+        let service = VoidShrineMCP::default();
+        service.chaos_config.write().await.enabled = false;
+        let request = || MCPRequest { method: "llm_inference".to_string(), params: params("hello", false), request_id: None, idempotency_key: None };
+
+        let response = service.handle_mcp_request(request()).await.unwrap();
+        assert!(response.metadata.rag_unavailable);
+
+        let strict = VoidShrineMCP::new(&Config::from_toml("[rag]\nrequire_engine = true\n").unwrap()).unwrap();
+        assert!(strict.require_rag);
+        strict.chaos_config.write().await.enabled = false;
+        let failure = strict.handle_mcp_request(request()).await.unwrap_err();
+        assert_eq!(failure.error.code(), "rag_unavailable");
+
+        *strict.rag_engine.write().await = Some(crate::rag_engine::RAGEngine::new().await.unwrap());
+        let response = strict.handle_mcp_request(request()).await.unwrap();
+        assert!(!response.metadata.rag_unavailable);
+    }
+
+    struct DownBackend;
+
+    impl LLMBackend for DownBackend {
+        fn name(&self) -> &str {
+            "down"
+        }
+
+        fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> futures::future::BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+            Box::pin(async { Err(BackendError::Timeout(std::time::Duration::from_secs(3)).into()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn backend_failures_become_gateway_errors() {
+        let service = VoidShrineMCP::default().with_backend(Arc::new(DownBackend));
+        service.chaos_config.write().await.enabled = false;
+        let request = MCPRequest { method: "llm_inference".to_string(), params: params("hello", false), request_id: None, idempotency_key: None };
+
+        let failure = service.handle_mcp_request(request).await.unwrap_err();
+        assert!(failure.request_id.is_some());
+        assert_eq!(failure.error.http_status(), 504);
+        let body = ErrorResponse::from(&failure);
+        assert_eq!(body.error, "backend_timeout");
+        assert_eq!(body.request_id, failure.request_id);
+    }
+
+    #[tokio::test]
+    async fn inference_is_routed_by_model() {
+        let mut backends = BackendRegistry::new();
+        backends.register("fixed", Arc::new(FixedBackend));
+        backends.register("down", Arc::new(DownBackend));
+        backends.route("fixed-*", "fixed").unwrap();
+        backends.route("down", "down").unwrap();
+        let service = VoidShrineMCP::default().with_backends(backends);
+
+        let mut request = params("care ethics", false);
+        request.model = "fixed-large".to_string();
+        assert_eq!(service.handle_llm_inference(request.clone(), deadline()).await.unwrap().0.response, "echoed: true");
+
+        request.model = "down".to_string();
+        let error = service.handle_llm_inference(request.clone(), deadline()).await.unwrap_err();
+        assert_eq!(error.code(), "backend_timeout");
+
+        request.model = "elsewhere".to_string();
+        let error = service.handle_llm_inference(request, deadline()).await.unwrap_err();
+        assert_eq!(error.http_status(), 400);
+
+        let models = service.handle_list_models();
+        assert_eq!(models.models.len(), 2);
+        assert_eq!((models.models[0].model.model.as_str(), models.models[0].model.kind.as_str()), ("fixed-*", "fixed"));
+        assert!(models.default_backend.is_none());
+        assert_eq!(VoidShrineMCP::default().handle_list_models().default_backend.as_deref(), Some("mock"));
+    }
+
+    /// Streams one delta, then fails
+    struct BrokenStreamBackend;
+
+    impl LLMBackend for BrokenStreamBackend {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> futures::future::BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+            Box::pin(async { Err(BackendError::Failed("connection reset".to_string()).into()) })
+        }
+
+        fn complete_stream<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> futures::stream::BoxStream<'a, anyhow::Result<CompletionChunk>> {
+            futures::stream::iter(vec![
+                Ok(CompletionChunk::Delta("Partial ".to_string())),
+                Err(BackendError::Failed("connection reset".to_string()).into()),
+            ])
+            .boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn backend_failure_mid_stream_ends_with_an_error_event() {
+        let service = Arc::new(VoidShrineMCP::default().with_backend(Arc::new(BrokenStreamBackend)));
+        service.chaos_config.write().await.enabled = false;
+
+        let events: Vec<InferenceEvent> = service.stream_llm_inference(params("hello", false), None).unwrap().collect().await;
+        let names: Vec<&str> = events.iter().map(InferenceEvent::name).collect();
+        assert_eq!(names, ["chaos_applied", "rag_context", "moral_recentering", "delta", "error"]);
+        match events.last() {
+            Some(InferenceEvent::Error { message, .. }) => assert!(message.contains("connection reset"), "{}", message),
+            other => panic!("expected error, got {:?}", other),
+        }
+        assert_eq!(service.agent_metrics.get("test_agent").unwrap().cancelled_requests, 0);
+    }
+}
@@ -0,0 +1,414 @@
+//! Prometheus metrics, served as text exposition at `GET /metrics`. Each
+//! service owns its registry. Request, route and RAG latencies are histograms;
+//! per-agent load and knowledge base sizes are gauges refreshed at scrape time.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use prometheus::{Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use crate::overload::OverloadStatus;
+
+/// Latency buckets in seconds, spanning fast mock answers to slow remote backends
+pub const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Route latency buckets in seconds, finer at the low end, where cheap
+/// admin routes answer
+pub const ROUTE_LATENCY_BUCKETS: [f64; 13] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Agents beyond the label cap share this label
+pub const OTHER_AGENTS: &str = "other";
+
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    rag_query_duration: HistogramVec,
+    route_duration: HistogramVec,
+    route_in_flight: IntGaugeVec,
+    chaos_applied: IntCounterVec,
+    chaos_shadowed: IntCounterVec,
+    throttled: IntCounterVec,
+    timeouts: IntCounterVec,
+    fallbacks: IntCounterVec,
+    webhook_dead_letters: IntCounterVec,
+    content_filter_verdicts: IntCounterVec,
+    context_screen_matches: IntCounterVec,
+    replays: IntCounterVec,
+    agent_load: GaugeVec,
+    rag_items: IntGaugeVec,
+    requests_shed: IntCounter,
+    concurrency_in_use: IntGauge,
+    concurrency_limit: IntGauge,
+    overload_shed: IntCounterVec,
+    overload_trips: IntCounterVec,
+    overloaded: IntGauge,
+    overload_signals: GaugeVec,
+    quota_rejections: IntCounterVec,
+    agent_labels: AgentLabels,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl Metrics {
+    /// `agent_label_cap` bounds how many agents get their own `agent_id` label
+    pub fn new(agent_label_cap: usize) -> Self {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(
+            Opts::new("void_shrine_requests_total", "MCP requests by method and HTTP status"),
+            &["method", "status"],
+        )
+        .expect("valid metric");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("void_shrine_request_duration_seconds", "MCP request latency")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["method"],
+        )
+        .expect("valid metric");
+        let rag_query_duration = HistogramVec::new(
+            HistogramOpts::new("void_shrine_rag_query_duration_seconds", "Knowledge base search latency")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["kind"],
+        )
+        .expect("valid metric");
+        let route_duration = HistogramVec::new(
+            HistogramOpts::new("void_shrine_route_duration_seconds", "HTTP latency to the response head by method, route pattern and status class")
+                .buckets(ROUTE_LATENCY_BUCKETS.to_vec()),
+            &["method", "route", "status_class"],
+        )
+        .expect("valid metric");
+        let route_in_flight = IntGaugeVec::new(
+            Opts::new("void_shrine_route_in_flight", "HTTP requests being handled by method and route pattern"),
+            &["method", "route"],
+        )
+        .expect("valid metric");
+        let chaos_applied = IntCounterVec::new(
+            Opts::new("void_shrine_chaos_applied_total", "Chaos applications by type"),
+            &["chaos_type"],
+        )
+        .expect("valid metric");
+        let chaos_shadowed = IntCounterVec::new(
+            Opts::new("void_shrine_chaos_shadowed_total", "Chaos drawn in shadow mode and not applied, by type"),
+            &["chaos_type"],
+        )
+        .expect("valid metric");
+        let throttled = IntCounterVec::new(
+            Opts::new("void_shrine_throttled_requests_total", "Requests delayed or rejected by load-based throttling"),
+            &["agent_id", "outcome"],
+        )
+        .expect("valid metric");
+        let timeouts = IntCounterVec::new(
+            Opts::new("void_shrine_timeouts_total", "Requests past their deadline by the stage that ran out of time"),
+            &["stage"],
+        )
+        .expect("valid metric");
+        let fallbacks = IntCounterVec::new(
+            Opts::new("void_shrine_fallbacks_total", "Moves to a fallback chain's next model after a failure, by chain"),
+            &["chain"],
+        )
+        .expect("valid metric");
+        let webhook_dead_letters = IntCounterVec::new(
+            Opts::new("void_shrine_webhook_dead_letters_total", "Webhook events not delivered after every attempt"),
+            &["event"],
+        )
+        .expect("valid metric");
+        let content_filter_verdicts = IntCounterVec::new(
+            Opts::new("void_shrine_content_filter_verdicts_total", "Responses a content filter redacted, blocked or failed to judge"),
+            &["filter", "verdict"],
+        )
+        .expect("valid metric");
+        let context_screen_matches = IntCounterVec::new(
+            Opts::new("void_shrine_context_screen_matches_total", "Retrieved chunks and summaries a context screening rule matched, by rule and action taken"),
+            &["rule", "action"],
+        )
+        .expect("valid metric");
+        let replays = IntCounterVec::new(
+            Opts::new("void_shrine_replays_total", "Audited requests replayed, kept out of the request metrics, by mode and outcome"),
+            &["mode", "outcome"],
+        )
+        .expect("valid metric");
+        let agent_load = GaugeVec::new(
+            Opts::new("void_shrine_agent_current_load", "Current load per agent; the mean for agents labelled other"),
+            &["agent_id"],
+        )
+        .expect("valid metric");
+        let rag_items = IntGaugeVec::new(Opts::new("void_shrine_rag_items", "Documents and chunks in the knowledge base"), &["kind"])
+            .expect("valid metric");
+        let requests_shed = IntCounter::new("void_shrine_requests_shed_total", "Requests refused with 503 for want of a concurrency slot")
+            .expect("valid metric");
+        let concurrency_in_use = IntGauge::new("void_shrine_concurrency_in_use", "Concurrency slots held by requests and jobs")
+            .expect("valid metric");
+        let concurrency_limit = IntGauge::new("void_shrine_concurrency_limit", "Requests allowed to run at once").expect("valid metric");
+        let overload_shed = IntCounterVec::new(
+            Opts::new("void_shrine_overload_shed_total", "Inference requests refused with 503 while the server was overloaded, by the signal that tripped"),
+            &["signal"],
+        )
+        .expect("valid metric");
+        let overload_trips = IntCounterVec::new(
+            Opts::new("void_shrine_overload_trips_total", "Times the server became overloaded, by the signal that tripped"),
+            &["signal"],
+        )
+        .expect("valid metric");
+        let overloaded = IntGauge::new("void_shrine_overloaded", "1 while inference requests are being shed for overload").expect("valid metric");
+        let overload_signals = GaugeVec::new(
+            Opts::new("void_shrine_overload_signal", "Values overload is judged by: requests in flight, and the p95s of latency and queue wait in ms"),
+            &["signal"],
+        )
+        .expect("valid metric");
+        let quota_rejections = IntCounterVec::new(
+            Opts::new("void_shrine_quota_rejections_total", "Inference requests refused with 429 for a quota they would exceed, by quota id"),
+            &["quota"],
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(request_duration.clone()),
+            Box::new(rag_query_duration.clone()),
+            Box::new(route_duration.clone()),
+            Box::new(route_in_flight.clone()),
+            Box::new(chaos_applied.clone()),
+            Box::new(chaos_shadowed.clone()),
+            Box::new(throttled.clone()),
+            Box::new(timeouts.clone()),
+            Box::new(fallbacks.clone()),
+            Box::new(webhook_dead_letters.clone()),
+            Box::new(content_filter_verdicts.clone()),
+            Box::new(context_screen_matches.clone()),
+            Box::new(replays.clone()),
+            Box::new(agent_load.clone()),
+            Box::new(rag_items.clone()),
+            Box::new(requests_shed.clone()),
+            Box::new(concurrency_in_use.clone()),
+            Box::new(concurrency_limit.clone()),
+            Box::new(overload_shed.clone()),
+            Box::new(overload_trips.clone()),
+            Box::new(overloaded.clone()),
+            Box::new(overload_signals.clone()),
+            Box::new(quota_rejections.clone()),
+        ] {
+            registry.register(collector).expect("metric names are unique");
+        }
+        Self {
+            registry,
+            requests,
+            request_duration,
+            rag_query_duration,
+            route_duration,
+            route_in_flight,
+            chaos_applied,
+            chaos_shadowed,
+            throttled,
+            timeouts,
+            fallbacks,
+            webhook_dead_letters,
+            content_filter_verdicts,
+            context_screen_matches,
+            replays,
+            agent_load,
+            rag_items,
+            requests_shed,
+            concurrency_in_use,
+            concurrency_limit,
+            overload_shed,
+            overload_trips,
+            overloaded,
+            overload_signals,
+            quota_rejections,
+            agent_labels: AgentLabels::new(agent_label_cap),
+        }
+    }
+
+    /// `method` must already be bounded, e.g. one of the known MCP methods or "other"
+    pub fn observe_request(&self, method: &str, status: u16, elapsed: Duration) {
+        self.requests.with_label_values(&[method, &status.to_string()]).inc();
+        self.request_duration.with_label_values(&[method]).observe(elapsed.as_secs_f64());
+        #[cfg(feature = "otel")]
+        crate::telemetry::observe_request(method, status, elapsed);
+    }
+
+    /// `kind` is the MCP method the search served
+    pub fn observe_rag_query(&self, kind: &str, elapsed: Duration) {
+        self.rag_query_duration.with_label_values(&[kind]).observe(elapsed.as_secs_f64());
+    }
+
+    /// `method` and `route` are bounded by `route_metrics`
+    pub fn route_started(&self, method: &str, route: &str) {
+        self.route_in_flight.with_label_values(&[method, route]).inc();
+    }
+
+    pub fn route_finished(&self, method: &str, route: &str, status_class: &str, elapsed: Duration) {
+        self.route_in_flight.with_label_values(&[method, route]).dec();
+        self.route_duration.with_label_values(&[method, route, status_class]).observe(elapsed.as_secs_f64());
+    }
+
+    pub fn chaos_applied(&self, chaos_type: &str) {
+        self.chaos_applied.with_label_values(&[chaos_type]).inc();
+    }
+
+    pub fn chaos_shadowed(&self, chaos_type: &str) {
+        self.chaos_shadowed.with_label_values(&[chaos_type]).inc();
+    }
+
+    /// `outcome` is "delayed" or "rejected"
+    pub fn throttled(&self, agent_id: &str, outcome: &str) {
+        self.throttled.with_label_values(&[&self.agent_labels.label(agent_id), outcome]).inc();
+    }
+
+    /// `stage` is a `TimeoutStage`
+    pub fn timed_out(&self, stage: &str) {
+        self.timeouts.with_label_values(&[stage]).inc();
+    }
+
+    /// `chain` is a configured chain's model name, so the labels stay bounded
+    pub fn fell_back(&self, chain: &str) {
+        self.fallbacks.with_label_values(&[chain]).inc();
+    }
+
+    /// Counts a webhook given up on for one URL
+    pub fn webhook_dead_letter(&self, event: &str) {
+        self.webhook_dead_letters.with_label_values(&[event]).inc();
+    }
+
+    /// `verdict` is "redact", "block" or "error"
+    pub fn content_filtered(&self, filter: &str, verdict: &str) {
+        self.content_filter_verdicts.with_label_values(&[filter, verdict]).inc();
+    }
+
+    /// `rule` is a configured rule's name; `action` is a `ScreenAction`
+    pub fn context_screened(&self, rule: &str, action: &str) {
+        self.context_screen_matches.with_label_values(&[rule, action]).inc();
+    }
+
+    /// `mode` is a `ReplayMode`; `outcome` is "replayed", "failed" or "skipped"
+    pub fn replayed(&self, mode: &str, outcome: &str) {
+        self.replays.with_label_values(&[mode, outcome]).inc();
+    }
+
+    /// Replaces the per-agent load gauges
+    pub fn set_agent_loads<'a>(&self, loads: impl IntoIterator<Item = (&'a str, f64)>) {
+        let mut others = Vec::new();
+        self.agent_load.reset();
+        for (agent_id, load) in loads {
+            let label = self.agent_labels.label(agent_id);
+            if label == OTHER_AGENTS {
+                others.push(load);
+            } else {
+                self.agent_load.with_label_values(&[&label]).set(load);
+            }
+        }
+        if !others.is_empty() {
+            let mean = others.iter().sum::<f64>() / others.len() as f64;
+            self.agent_load.with_label_values(&[OTHER_AGENTS]).set(mean);
+        }
+    }
+
+    pub fn set_rag_counts(&self, documents: usize, chunks: usize) {
+        self.rag_items.with_label_values(&["documents"]).set(documents as i64);
+        self.rag_items.with_label_values(&["chunks"]).set(chunks as i64);
+    }
+
+    pub fn request_shed(&self) {
+        self.requests_shed.inc();
+    }
+
+    pub fn set_concurrency(&self, in_use: u32, limit: u32) {
+        self.concurrency_in_use.set(i64::from(in_use));
+        self.concurrency_limit.set(i64::from(limit));
+    }
+
+    /// `signal` is an `OverloadSignal`
+    pub fn overload_shed(&self, signal: &str) {
+        self.overload_shed.with_label_values(&[signal]).inc();
+    }
+
+    pub fn overload_tripped(&self, signal: &str) {
+        self.overload_trips.with_label_values(&[signal]).inc();
+    }
+
+    /// `quota` is a configured `QuotaRule::id`
+    pub fn quota_rejected(&self, quota: &str) {
+        self.quota_rejections.with_label_values(&[quota]).inc();
+    }
+
+    /// p95s not yet known are left out
+    pub fn set_overload(&self, status: &OverloadStatus) {
+        self.overloaded.set(i64::from(status.overloaded_by.is_some()));
+        self.overload_signals.reset();
+        self.overload_signals.with_label_values(&["in_flight"]).set(f64::from(status.signals.in_flight));
+        if let Some(p95_ms) = status.signals.p95_ms {
+            self.overload_signals.with_label_values(&["p95_ms"]).set(p95_ms as f64);
+        }
+        if let Some(queue_wait_p95_ms) = status.signals.queue_wait_p95_ms {
+            self.overload_signals.with_label_values(&["queue_wait_p95_ms"]).set(queue_wait_p95_ms as f64);
+        }
+    }
+
+    /// The registry in Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Hands out `agent_id` labels to the first agents seen, up to the cap, so
+/// series stay stable between scrapes
+struct AgentLabels {
+    cap: usize,
+    labelled: Mutex<HashSet<String>>,
+}
+
+impl AgentLabels {
+    fn new(cap: usize) -> Self {
+        Self { cap, labelled: Mutex::new(HashSet::new()) }
+    }
+
+    fn label(&self, agent_id: &str) -> String {
+        let mut labelled = self.labelled.lock().unwrap_or_else(|e| e.into_inner());
+        if labelled.contains(agent_id) {
+            return agent_id.to_string();
+        }
+        if labelled.len() < self.cap && agent_id != OTHER_AGENTS {
+            labelled.insert(agent_id.to_string());
+            return agent_id.to_string();
+        }
+        OTHER_AGENTS.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    /// Sample values keyed by series, labels as rendered
+    fn samples(text: &str) -> HashMap<String, f64> {
+        text.lines()
+            .filter(|line| !line.starts_with('#') && !line.is_empty())
+            .filter_map(|line| line.rsplit_once(' '))
+            .filter_map(|(series, value)| value.parse().ok().map(|value| (series.to_string(), value)))
+            .collect()
+    }
+
+    #[test]
+    fn agents_past_the_cap_share_a_label() {
+        let metrics = Metrics::new(2);
+        metrics.set_agent_loads([("a", 0.5), ("b", 0.25), ("c", 0.2), ("d", 0.4)]);
+        let series = samples(&metrics.render());
+        assert_eq!(series[r#"void_shrine_agent_current_load{agent_id="a"}"#], 0.5);
+        assert_eq!(series[r#"void_shrine_agent_current_load{agent_id="b"}"#], 0.25);
+        assert!((series[r#"void_shrine_agent_current_load{agent_id="other"}"#] - 0.3).abs() < 1e-9);
+
+        // Labels stick to the agents that got them first
+        metrics.set_agent_loads([("d", 0.9), ("a", 0.1)]);
+        let series = samples(&metrics.render());
+        assert_eq!(series[r#"void_shrine_agent_current_load{agent_id="other"}"#], 0.9);
+        assert!(!series.contains_key(r#"void_shrine_agent_current_load{agent_id="b"}"#));
+    }
+}
@@ -0,0 +1,178 @@
+//! Agent metrics kept across restarts. With `metrics.state_path`, each agent's
+//! lifetime counters, response time average, recent outcomes and last hour of
+//! per-minute activity, the hourly token usage and activity buckets, and the
+//! scaling decisions given, are saved to a JSON file every `save_interval_secs`
+//! and on shutdown, and loaded on start; only `requests_since_boot` starts
+//! again from zero. Load windows, in-flight counts and the observations scaling
+//! advice is based on describe the last few minutes of one process and aren't kept. A file that is unreadable, corrupt or of another
+//! format version is discarded with a warning rather than stopping startup.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::agent_stats::AgentStats;
+use crate::history::HistoryBucket;
+use crate::scaling::ScalingRecord;
+use crate::usage::UsageBucket;
+
+/// Bumped whenever `SavedAgent` changes incompatibly
+const FORMAT_VERSION: u32 = 1;
+
+/// The lifetime part of one agent's metrics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedAgent {
+    pub agent_id: String,
+    pub total_requests: u64,
+    pub avg_response_time_ms: f64,
+    /// Oldest first, as the success rate is computed over
+    pub recent_outcomes: Vec<bool>,
+    pub last_request: DateTime<Utc>,
+    pub cancelled_requests: u64,
+    pub throttled_delayed: u64,
+    pub throttled_rejected: u64,
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub embedding_requests: u64,
+    #[serde(default)]
+    pub embedding_tokens: u64,
+    #[serde(default)]
+    pub chaos_events: u64,
+    /// The last hour's per-minute activity, by wall clock, so windows
+    /// reaching back before a restart stay whole
+    #[serde(default)]
+    pub stats: AgentStats,
+    /// The tenant whose key first used the agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedState {
+    version: u32,
+    saved_at: DateTime<Utc>,
+    agents: Vec<SavedAgent>,
+    #[serde(default)]
+    usage: Vec<UsageBucket>,
+    #[serde(default)]
+    history: Vec<HistoryBucket>,
+    #[serde(default)]
+    scaling: Vec<ScalingRecord>,
+}
+
+/// What a file held
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedMetrics {
+    pub agents: Vec<SavedAgent>,
+    pub usage: Vec<UsageBucket>,
+    pub history: Vec<HistoryBucket>,
+    pub scaling: Vec<ScalingRecord>,
+}
+
+#[derive(Debug)]
+pub struct MetricsStore {
+    path: PathBuf,
+    /// Held while saving, so the periodic and shutdown saves don't interleave
+    writing: Mutex<()>,
+}
+
+impl MetricsStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), writing: Mutex::new(()) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What was saved last, or nothing when there is no usable file
+    pub fn load(&self) -> SavedMetrics {
+        if !self.path.exists() {
+            return SavedMetrics::default();
+        }
+        match self.read() {
+            Ok(saved) => saved,
+            Err(e) => {
+                tracing::warn!("Discarding saved agent metrics, starting from zero: {:#}", e);
+                SavedMetrics::default()
+            }
+        }
+    }
+
+    fn read(&self) -> Result<SavedMetrics> {
+        let text = std::fs::read_to_string(&self.path).with_context(|| format!("reading {}", self.path.display()))?;
+        let state: serde_json::Value = serde_json::from_str(&text).with_context(|| format!("parsing {}", self.path.display()))?;
+        let version = state.get("version").and_then(serde_json::Value::as_u64);
+        if version != Some(FORMAT_VERSION as u64) {
+            anyhow::bail!("{} has format version {:?}, expected {}", self.path.display(), version, FORMAT_VERSION);
+        }
+        let state: SavedState = serde_json::from_value(state).with_context(|| format!("parsing {}", self.path.display()))?;
+        Ok(SavedMetrics { agents: state.agents, usage: state.usage, history: state.history, scaling: state.scaling })
+    }
+
+    /// Replaces the file with `saved`, never leaving it half written
+    pub fn save(&self, saved: SavedMetrics) -> Result<()> {
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        let state = SavedState {
+            version: FORMAT_VERSION,
+            saved_at: Utc::now(),
+            agents: saved.agents,
+            usage: saved.usage,
+            history: saved.history,
+            scaling: saved.scaling,
+        };
+        let saved = serde_json::to_vec_pretty(&state).map_err(anyhow::Error::from).and_then(|json| {
+            let staged = self.path.with_extension("saving");
+            std::fs::write(&staged, json)?;
+            std::fs::rename(&staged, &self.path)?;
+            Ok(())
+        });
+        saved.with_context(|| format!("saving agent metrics to {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(agent_id: &str) -> SavedAgent {
+        SavedAgent {
+            agent_id: agent_id.to_string(),
+            total_requests: 12,
+            avg_response_time_ms: 140.0,
+            recent_outcomes: vec![true, false, true],
+            last_request: Utc::now(),
+            cancelled_requests: 1,
+            throttled_delayed: 0,
+            throttled_rejected: 2,
+            prompt_tokens: 480,
+            completion_tokens: 96,
+            embedding_requests: 3,
+            embedding_tokens: 40,
+            chaos_events: 0,
+            stats: AgentStats::default(),
+            tenant: None,
+        }
+    }
+
+    #[test]
+    fn unusable_files_are_discarded() {
+        let path = std::env::temp_dir().join(format!("void-shrine-metrics-test-{}.json", uuid::Uuid::new_v4()));
+        let store = MetricsStore::new(&path);
+        assert_eq!(store.load(), SavedMetrics::default());
+
+        let saved = SavedMetrics { agents: vec![agent("scout")], ..SavedMetrics::default() };
+        store.save(saved.clone()).unwrap();
+        assert_eq!(store.load(), saved);
+
+        std::fs::write(&path, r#"{"version": 0, "agents": []}"#).unwrap();
+        assert_eq!(store.load(), SavedMetrics::default());
+        std::fs::write(&path, "{ not json").unwrap();
+        assert_eq!(store.load(), SavedMetrics::default());
+        std::fs::remove_file(path).ok();
+    }
+}
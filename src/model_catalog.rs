@@ -0,0 +1,73 @@
+//! What `GET /api/models` knows beyond the configured routes: the models each
+//! backend reported serving when last asked, e.g. an Ollama server's pulled
+//! models, and when each backend last answered a call successfully. A backend
+//! that can't be asked keeps the models it reported before.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+
+#[derive(Debug, Default)]
+pub struct ModelCatalog {
+    /// By backend name, sorted
+    discovered: Mutex<BTreeMap<String, Vec<String>>>,
+    last_success: DashMap<String, DateTime<Utc>>,
+}
+
+impl ModelCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces what `backend` was known to serve
+    pub fn set_discovered(&self, backend: &str, mut models: Vec<String>) {
+        models.sort();
+        models.dedup();
+        let mut discovered = self.discovered.lock().unwrap_or_else(|e| e.into_inner());
+        if models.is_empty() {
+            discovered.remove(backend);
+        } else {
+            discovered.insert(backend.to_string(), models);
+        }
+    }
+
+    /// Backend names and their models, by backend name
+    pub fn discovered(&self) -> Vec<(String, Vec<String>)> {
+        let discovered = self.discovered.lock().unwrap_or_else(|e| e.into_inner());
+        discovered.iter().map(|(backend, models)| (backend.clone(), models.clone())).collect()
+    }
+
+    pub fn record_success(&self, backend: &str) {
+        self.last_success.insert(backend.to_string(), Utc::now());
+    }
+
+    pub fn last_success(&self, backend: &str) -> Option<DateTime<Utc>> {
+        self.last_success.get(backend).map(|at| *at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovered_models_are_sorted_and_replaced() {
+        let catalog = ModelCatalog::new();
+        catalog.set_discovered("local", vec!["qwen2.5:7b".to_string(), "llama3.2:latest".to_string(), "qwen2.5:7b".to_string()]);
+        catalog.set_discovered("cloud", vec!["gpt-4o".to_string()]);
+        assert_eq!(
+            catalog.discovered(),
+            [
+                ("cloud".to_string(), vec!["gpt-4o".to_string()]),
+                ("local".to_string(), vec!["llama3.2:latest".to_string(), "qwen2.5:7b".to_string()])
+            ]
+        );
+
+        catalog.set_discovered("cloud", Vec::new());
+        assert_eq!(catalog.discovered().len(), 1);
+        assert!(catalog.last_success("local").is_none());
+        catalog.record_success("local");
+        assert!(catalog.last_success("local").is_some());
+    }
+}
@@ -0,0 +1,888 @@
+//! Ethical frameworks for moral recentering. Each one is a prefix put in front
+//! of the prompt and the adjustments reported for it; the built-in ones can be
+//! replaced, and more added, under `[[moral.frameworks]]` without touching the
+//! handler.
+//!
+//! The care ethics score is a heuristic over the original prompt: mentions of
+//! stakeholders and considerate language raise it, harm and coercive phrasing
+//! lower it. Prompts scoring below `CareScoring::low_score` get recentered
+//! harder. The term lists live in `[moral.scoring]`.
+//!
+//! Every rule applied is recorded with the text it inserted and where that
+//! text came from, so a `RecenteringDiff` can show where each one landed and
+//! what it did to the score.
+//!
+//! Every text recentering inserts can be replaced per deployment: framework
+//! prefixes, the void shrine context, the low score prefix and a prefix per
+//! specialty, in `[moral]` or in the TOML file at `moral.texts_path`, which
+//! `POST /api/moral-recentering/reload` reads again. The built-in texts are
+//! the fallback for frameworks; specialties have no prefix until one is
+//! configured, and from then on each needs its own or
+//! `fallback_specialty_prefix`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Adjustment recorded, followed by the name, for a framework nobody defined
+pub const UNKNOWN_FRAMEWORK: &str = "unknown_framework";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EthicalFramework {
+    /// Matched ignoring case, with `_` and `-` interchangeable
+    pub name: String,
+    /// Put in front of the prompt
+    pub prefix: String,
+    /// Reported in `ethical_adjustments` when the framework is applied
+    pub adjustments: Vec<String>,
+}
+
+impl EthicalFramework {
+    fn new(name: &str, prefix: &str, adjustments: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            prefix: prefix.to_string(),
+            adjustments: adjustments.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+}
+
+pub fn builtin_frameworks() -> Vec<EthicalFramework> {
+    vec![
+        EthicalFramework::new(
+            "care-ethics",
+            "Considering the wellbeing and agency of all affected parties: ",
+            &["Applied care ethics perspective", "Considered relational impact on all stakeholders"],
+        ),
+        EthicalFramework::new(
+            "consequentialist",
+            "Weighing the likely outcomes for everyone affected, near and long term: ",
+            &["Applied consequentialist perspective", "Weighed foreseeable outcomes across all affected parties"],
+        ),
+        EthicalFramework::new(
+            "deontological",
+            "Respecting the duties, rights and commitments that hold whatever the outcome: ",
+            &["Applied deontological perspective", "Checked the request against duties and rights"],
+        ),
+        EthicalFramework::new(
+            "virtue-ethics",
+            "As someone honest, courageous and practically wise would approach it: ",
+            &["Applied virtue ethics perspective", "Framed the response around character and practical wisdom"],
+        ),
+    ]
+}
+
+/// Rule named for the void shrine context text
+pub const VOID_SHRINE_CONTEXT: &str = "void-shrine-context";
+
+/// Applied on top of any framework when a request asks for void shrine context
+pub fn void_shrine_context() -> EthicalFramework {
+    EthicalFramework::new(
+        VOID_SHRINE_CONTEXT,
+        "Through the lens of generative absence and emergent intelligence: ",
+        &["Integrated void shrine ontological perspective", "Emphasized emergence over rigid control"],
+    )
+}
+
+/// A prefix and the adjustments reported when it is applied
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecenteringText {
+    pub prefix: String,
+    #[serde(default)]
+    pub adjustments: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MoralConfig {
+    /// Refuse unknown frameworks rather than recording an `unknown_framework`
+    /// adjustment; requests can override it
+    pub strict_frameworks: bool,
+    /// Added to the built-in frameworks, replacing any with the same name
+    pub frameworks: Vec<EthicalFramework>,
+    /// Replaces the built-in void shrine context text
+    pub void_shrine_context: Option<RecenteringText>,
+    /// Specialty -> text put in front of its prompts, after the framework's
+    pub specialty_prefixes: BTreeMap<String, String>,
+    /// For specialties without an entry in `specialty_prefixes`
+    pub fallback_specialty_prefix: Option<String>,
+    /// TOML file of `frameworks`, `void_shrine_context`, `low_score_prefix`,
+    /// `specialty_prefixes` and `fallback_specialty_prefix`, each taking
+    /// precedence over the config's
+    pub texts_path: Option<PathBuf>,
+    pub scoring: CareScoring,
+}
+
+impl MoralConfig {
+    /// Problems with the texts in the config; those of a `texts_path` show
+    /// when it is read
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems: Vec<String> = check_texts(&self.frameworks, &self.specialty_prefixes)
+            .into_iter()
+            .map(|problem| format!("moral.{}", problem))
+            .collect();
+        if !(0.0..=1.0).contains(&self.scoring.low_score) {
+            problems.push(format!("moral.scoring.low_score must be between 0 and 1 (got {})", self.scoring.low_score));
+        }
+        problems
+    }
+}
+
+fn check_texts(frameworks: &[EthicalFramework], specialty_prefixes: &BTreeMap<String, String>) -> Vec<String> {
+    let mut problems = Vec::new();
+    if frameworks.iter().any(|framework| framework_key(&framework.name).is_empty()) {
+        problems.push("frameworks entries need a name".to_string());
+    }
+    if specialty_prefixes.keys().any(|specialty| specialty.trim().is_empty()) {
+        problems.push("specialty_prefixes has an entry without a specialty".to_string());
+    }
+    problems
+}
+
+/// Where a text came from, unless it was read from `texts_path`
+pub const BUILTIN_TEXT: &str = "built-in";
+pub const CONFIG_TEXT: &str = "config";
+
+/// The file at `moral.texts_path`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TextsFile {
+    frameworks: Vec<EthicalFramework>,
+    void_shrine_context: Option<RecenteringText>,
+    low_score_prefix: Option<String>,
+    specialty_prefixes: BTreeMap<String, String>,
+    fallback_specialty_prefix: Option<String>,
+}
+
+impl TextsFile {
+    /// The texts `config` sets itself
+    fn of(config: &MoralConfig) -> Self {
+        let low_score_prefix = &config.scoring.low_score_prefix;
+        Self {
+            frameworks: config.frameworks.clone(),
+            void_shrine_context: config.void_shrine_context.clone(),
+            low_score_prefix: (*low_score_prefix != CareScoring::default().low_score_prefix).then(|| low_score_prefix.clone()),
+            specialty_prefixes: config.specialty_prefixes.clone(),
+            fallback_specialty_prefix: config.fallback_specialty_prefix.clone(),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading moral texts from {}", path.display()))?;
+        let file: Self = toml::from_str(&text).with_context(|| format!("parsing moral texts in {}", path.display()))?;
+        if let Some(problem) = check_texts(&file.frameworks, &file.specialty_prefixes).into_iter().next() {
+            anyhow::bail!("moral texts in {}: {}", path.display(), problem);
+        }
+        Ok(file)
+    }
+}
+
+/// Score every prompt starts from
+const BASE_SCORE: f64 = 0.6;
+
+/// Per matched term, and the most terms of each kind that count
+const STAKEHOLDER_WEIGHT: f64 = 0.1;
+const CONSIDERATION_WEIGHT: f64 = 0.1;
+const HARM_WEIGHT: f64 = -0.2;
+const COERCIVE_WEIGHT: f64 = -0.1;
+const MAX_COUNTED_TERMS: usize = 3;
+
+/// Terms behind the care ethics score. Single words match any word they
+/// start, so "harm" also finds "harmful"; phrases match anywhere.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CareScoring {
+    /// People the prompt shows awareness of
+    pub stakeholder_terms: Vec<String>,
+    /// Language already weighing the effects on others
+    pub consideration_terms: Vec<String>,
+    pub harm_terms: Vec<String>,
+    /// Imperative or coercive phrasing
+    pub coercive_terms: Vec<String>,
+    /// Scores below this get `low_score_prefix` on top of the framework's
+    pub low_score: f64,
+    pub low_score_prefix: String,
+}
+
+impl Default for CareScoring {
+    fn default() -> Self {
+        let terms = |terms: &[&str]| terms.iter().map(|t| t.to_string()).collect();
+        Self {
+            stakeholder_terms: terms(&[
+                "people", "person", "user", "customer", "patient", "child", "children", "community", "communities",
+                "team", "worker", "employee", "famil", "resident", "stakeholder", "everyone", "neighbo", "student",
+            ]),
+            consideration_terms: terms(&[
+                "consider", "wellbeing", "well-being", "safe", "consent", "fair", "respect", "ethic", "careful",
+                "impact", "inclusi", "accessib", "privacy", "dignity", "transparen",
+            ]),
+            harm_terms: terms(&[
+                "harm", "hurt", "damag", "attack", "exploit", "destroy", "weapon", "kill", "deceiv", "manipulat",
+                "steal", "surveil", "punish", "humiliat", "threat",
+            ]),
+            coercive_terms: terms(&[
+                "must", "force", "obey", "comply", "compel", "at any cost", "no matter what", "whatever it takes",
+                "make them", "don't ask", "without asking", "immediately",
+            ]),
+            low_score: 0.5,
+            low_score_prefix: "Before anything else, weigh who could be harmed or pressured here and how to avoid it: "
+                .to_string(),
+        }
+    }
+}
+
+/// One kind of term and what its matches did to the score
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreFactor {
+    /// "stakeholders", "consideration", "harm" or "coercion"
+    pub factor: String,
+    pub matched: Vec<String>,
+    pub contribution: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreBreakdown {
+    pub base: f64,
+    /// Only the kinds with a match
+    pub factors: Vec<ScoreFactor>,
+}
+
+impl CareScoring {
+    /// Between 0 and 1, rounded to hundredths; the same prompt always scores the same
+    pub fn score(&self, prompt: &str) -> (f64, ScoreBreakdown) {
+        let text = prompt.to_lowercase();
+        let words: Vec<&str> = text
+            .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '\''))
+            .filter(|w| !w.is_empty())
+            .collect();
+        let phrase_text = words.join(" ");
+        let matches = |terms: &[String]| -> Vec<String> {
+            terms
+                .iter()
+                .filter(|term| {
+                    let term = term.to_lowercase();
+                    if term.contains(' ') {
+                        phrase_text.contains(&term)
+                    } else {
+                        words.iter().any(|word| word.starts_with(&term))
+                    }
+                })
+                .cloned()
+                .collect()
+        };
+
+        let mut factors = Vec::new();
+        for (factor, terms, weight) in [
+            ("stakeholders", &self.stakeholder_terms, STAKEHOLDER_WEIGHT),
+            ("consideration", &self.consideration_terms, CONSIDERATION_WEIGHT),
+            ("harm", &self.harm_terms, HARM_WEIGHT),
+            ("coercion", &self.coercive_terms, COERCIVE_WEIGHT),
+        ] {
+            let matched = matches(terms);
+            if !matched.is_empty() {
+                let contribution = weight * matched.len().min(MAX_COUNTED_TERMS) as f64;
+                factors.push(ScoreFactor { factor: factor.to_string(), matched, contribution: round(contribution) });
+            }
+        }
+        let score = round((BASE_SCORE + factors.iter().map(|f| f.contribution).sum::<f64>()).clamp(0.0, 1.0));
+        (score, ScoreBreakdown { base: BASE_SCORE, factors })
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// A framework nobody defined, named in a strict request
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownFramework {
+    pub name: String,
+    pub known: Vec<String>,
+}
+
+impl std::fmt::Display for UnknownFramework {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown ethical_framework '{}'; known: {}", self.name, self.known.join(", "))
+    }
+}
+
+impl std::error::Error for UnknownFramework {}
+
+/// A recentered prompt, what was done to it, and the original prompt's score
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recentering {
+    pub prompt: String,
+    pub adjustments: Vec<String>,
+    pub care_ethics_score: f64,
+    pub score_breakdown: ScoreBreakdown,
+    /// In the order they fired
+    pub rules: Vec<FiredRule>,
+}
+
+/// Rule named for the low score prefix
+pub const LOW_CARE_SCORE: &str = "low_care_score";
+
+/// Rule named for a specialty's prefix
+pub const SPECIALTY_PREFIX: &str = "specialty_prefix";
+
+/// A rule recentering applied, and the text it put in the prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FiredRule {
+    /// A framework's name, `void-shrine-context`, `specialty_prefix`,
+    /// `low_care_score` or `unknown_framework`
+    pub rule: String,
+    pub reason: String,
+    /// Empty for a rule that only noted something
+    pub inserted: String,
+    /// Where `inserted` starts in the recentered prompt, in characters
+    pub offset: usize,
+    /// Where `inserted` was configured: `built-in`, `config` or the texts
+    /// file's path; empty when nothing was inserted
+    #[serde(default)]
+    pub source: String,
+}
+
+/// How a recentered prompt differs from the original
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecenteringDiff {
+    /// Added in front of the original prompt
+    pub prefix: String,
+    /// Added after it
+    pub suffix: String,
+    pub chars_added: usize,
+    pub rules: Vec<FiredRule>,
+    /// The care ethics score of the original prompt and of the recentered one
+    pub score_before: f64,
+    pub score_after: f64,
+}
+
+impl RecenteringDiff {
+    pub fn changed(&self) -> bool {
+        self.chars_added > 0
+    }
+}
+
+/// What recentering did across a batch of prompts
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecenteringSummary {
+    pub prompts: usize,
+    pub changed: usize,
+    /// Over the changed prompts
+    pub mean_chars_added: f64,
+    /// Of `score_after - score_before`, over every prompt
+    pub mean_score_change: f64,
+    pub max_score_change: f64,
+    /// How many prompts each rule fired on
+    pub rules_fired: BTreeMap<String, usize>,
+}
+
+impl RecenteringSummary {
+    pub fn new(diffs: &[RecenteringDiff]) -> Self {
+        let changed: Vec<&RecenteringDiff> = diffs.iter().filter(|diff| diff.changed()).collect();
+        let changes: Vec<f64> = diffs.iter().map(|diff| diff.score_after - diff.score_before).collect();
+        let mut rules_fired = BTreeMap::new();
+        for diff in diffs {
+            for rule in &diff.rules {
+                *rules_fired.entry(rule.rule.clone()).or_insert(0) += 1;
+            }
+        }
+        let mean = |values: &[f64]| if values.is_empty() { 0.0 } else { round(values.iter().sum::<f64>() / values.len() as f64) };
+        let added: Vec<f64> = changed.iter().map(|diff| diff.chars_added as f64).collect();
+        Self {
+            prompts: diffs.len(),
+            changed: changed.len(),
+            mean_chars_added: mean(&added),
+            mean_score_change: mean(&changes),
+            max_score_change: round(changes.iter().copied().fold(0.0, f64::max)),
+            rules_fired,
+        }
+    }
+}
+
+/// A text and where it was configured
+#[derive(Debug, Clone)]
+struct Sourced<T> {
+    value: T,
+    source: String,
+}
+
+fn sourced<T>(value: T, source: &str) -> Sourced<T> {
+    Sourced { value, source: source.to_string() }
+}
+
+/// Every text recentering can insert
+#[derive(Debug, Clone)]
+struct Texts {
+    frameworks: BTreeMap<String, Sourced<EthicalFramework>>,
+    void_shrine: Sourced<EthicalFramework>,
+    low_score_prefix: Sourced<String>,
+    specialty_prefixes: BTreeMap<String, Sourced<String>>,
+    fallback_specialty_prefix: Option<Sourced<String>>,
+}
+
+impl Texts {
+    /// The built-in texts, under the config's, under those of its `texts_path`
+    fn open(config: &MoralConfig) -> Result<Self> {
+        let mut texts = Self {
+            frameworks: builtin_frameworks()
+                .into_iter()
+                .map(|framework| (framework_key(&framework.name), sourced(framework, BUILTIN_TEXT)))
+                .collect(),
+            void_shrine: sourced(void_shrine_context(), BUILTIN_TEXT),
+            low_score_prefix: sourced(CareScoring::default().low_score_prefix, BUILTIN_TEXT),
+            specialty_prefixes: BTreeMap::new(),
+            fallback_specialty_prefix: None,
+        };
+        texts.layer(TextsFile::of(config), CONFIG_TEXT);
+        if let Some(path) = &config.texts_path {
+            texts.layer(TextsFile::read(path)?, &path.display().to_string());
+        }
+        Ok(texts)
+    }
+
+    fn layer(&mut self, file: TextsFile, source: &str) {
+        for framework in file.frameworks {
+            self.frameworks.insert(framework_key(&framework.name), sourced(framework, source));
+        }
+        if let Some(text) = file.void_shrine_context {
+            let framework = EthicalFramework { name: VOID_SHRINE_CONTEXT.to_string(), prefix: text.prefix, adjustments: text.adjustments };
+            self.void_shrine = sourced(framework, source);
+        }
+        if let Some(prefix) = file.low_score_prefix {
+            self.low_score_prefix = sourced(prefix, source);
+        }
+        for (specialty, prefix) in file.specialty_prefixes {
+            self.specialty_prefixes.insert(specialty, sourced(prefix, source));
+        }
+        if let Some(prefix) = file.fallback_specialty_prefix {
+            self.fallback_specialty_prefix = Some(sourced(prefix, source));
+        }
+    }
+
+    /// Once any specialty has a prefix, each of `specialties` needs one or
+    /// the fallback, and each prefix is for one of them
+    fn check_specialties(&self, specialties: &[String]) -> Result<()> {
+        if let Some(unknown) = self.specialty_prefixes.keys().find(|name| !specialties.contains(name)) {
+            anyhow::bail!("specialty_prefixes has '{}', which isn't a specialty", unknown);
+        }
+        if self.fallback_specialty_prefix.is_some() || self.specialty_prefixes.is_empty() {
+            return Ok(());
+        }
+        let missing: Vec<&str> =
+            specialties.iter().filter(|name| !self.specialty_prefixes.contains_key(*name)).map(String::as_str).collect();
+        if !missing.is_empty() {
+            anyhow::bail!("specialty_prefixes has nothing for {} and there is no fallback_specialty_prefix", missing.join(", "));
+        }
+        Ok(())
+    }
+
+    /// The specialty's prefix, else the fallback, and why it applies
+    fn specialty_prefix(&self, specialty: &str) -> Option<(&Sourced<String>, String)> {
+        match self.specialty_prefixes.get(specialty) {
+            Some(prefix) => Some((prefix, format!("the {} specialty", specialty))),
+            None => self.fallback_specialty_prefix.as_ref().map(|prefix| (prefix, format!("the fallback for the {} specialty", specialty))),
+        }
+    }
+}
+
+/// A text in `MoralTextsReport`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfiguredText {
+    /// The framework's or specialty's, or the rule's for the other texts
+    pub name: String,
+    pub prefix: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adjustments: Vec<String>,
+    /// `built-in`, `config` or the texts file's path
+    pub source: String,
+}
+
+impl ConfiguredText {
+    fn framework(framework: &Sourced<EthicalFramework>) -> Self {
+        let Sourced { value, source } = framework;
+        Self { name: value.name.clone(), prefix: value.prefix.clone(), adjustments: value.adjustments.clone(), source: source.clone() }
+    }
+
+    fn prefix(name: &str, prefix: &Sourced<String>) -> Self {
+        Self { name: name.to_string(), prefix: prefix.value.clone(), adjustments: Vec::new(), source: prefix.source.clone() }
+    }
+}
+
+/// `GET /api/moral-recentering/config`: every text recentering can insert,
+/// and where each was configured
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MoralTextsReport {
+    pub strict_frameworks: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texts_path: Option<PathBuf>,
+    /// Sorted by name
+    pub frameworks: Vec<ConfiguredText>,
+    pub void_shrine_context: ConfiguredText,
+    pub low_score_prefix: ConfiguredText,
+    /// Sorted by specialty
+    pub specialty_prefixes: Vec<ConfiguredText>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_specialty_prefix: Option<ConfiguredText>,
+}
+
+/// Swapped whole when the config is reloaded
+#[derive(Debug)]
+struct State {
+    config: MoralConfig,
+    texts: Texts,
+}
+
+/// The built-in frameworks merged with the configured ones, and the other
+/// texts recentering inserts
+#[derive(Debug)]
+pub struct EthicalFrameworks {
+    state: RwLock<State>,
+}
+
+impl Default for EthicalFrameworks {
+    fn default() -> Self {
+        Self::open(&MoralConfig::default()).expect("the built-in moral texts are valid")
+    }
+}
+
+impl EthicalFrameworks {
+    /// The texts of `config`, and of its `texts_path` if it has one
+    pub fn open(config: &MoralConfig) -> Result<Self> {
+        let state = State { config: config.clone(), texts: Texts::open(config)? };
+        Ok(Self { state: RwLock::new(state) })
+    }
+
+    fn state(&self) -> std::sync::RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Problems with the specialty prefixes given the loaded `specialties`
+    pub fn check_specialties(&self, specialties: &[String]) -> Result<()> {
+        self.state().texts.check_specialties(specialties)
+    }
+
+    pub fn get(&self, name: &str) -> Option<EthicalFramework> {
+        self.state().texts.frameworks.get(&framework_key(name)).map(|framework| framework.value.clone())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.state().texts.frameworks.values().map(|framework| framework.value.name.clone()).collect()
+    }
+
+    pub fn report(&self) -> MoralTextsReport {
+        let state = self.state();
+        let texts = &state.texts;
+        MoralTextsReport {
+            strict_frameworks: state.config.strict_frameworks,
+            texts_path: state.config.texts_path.clone(),
+            frameworks: texts.frameworks.values().map(ConfiguredText::framework).collect(),
+            void_shrine_context: ConfiguredText::framework(&texts.void_shrine),
+            low_score_prefix: ConfiguredText::prefix(LOW_CARE_SCORE, &texts.low_score_prefix),
+            specialty_prefixes: texts.specialty_prefixes.iter().map(|(name, prefix)| ConfiguredText::prefix(name, prefix)).collect(),
+            fallback_specialty_prefix: texts.fallback_specialty_prefix.as_ref().map(|prefix| ConfiguredText::prefix("fallback", prefix)),
+        }
+    }
+
+    /// Reads `texts_path` again, keeping the current texts when it can't be
+    /// used with the loaded `specialties`. None without a `texts_path`.
+    pub fn reload(&self, specialties: &[String]) -> Option<Result<()>> {
+        let config = self.state().config.clone();
+        let path = config.texts_path.as_ref()?;
+        let opened = Texts::open(&config).and_then(|texts| {
+            texts.check_specialties(specialties).map_err(|e| anyhow::anyhow!("moral texts in {}: {}", path.display(), e))?;
+            Ok(texts)
+        });
+        Some(opened.map(|texts| {
+            self.state.write().unwrap_or_else(|e| e.into_inner()).texts = texts;
+            tracing::info!("Reloaded moral texts from {}", path.display());
+        }))
+    }
+
+    /// Takes on everything `other` was opened with, as a config reload does
+    pub fn replace_with(&self, other: EthicalFrameworks) {
+        let state = other.state.into_inner().unwrap_or_else(|e| e.into_inner());
+        *self.state.write().unwrap_or_else(|e| e.into_inner()) = state;
+    }
+
+    /// `prompt` behind the framework's prefix, the specialty's when one is
+    /// configured, the low score prefix when it scores low, and void shrine
+    /// context's on top when asked. An unknown framework fails when `strict`
+    /// (the configured default when unset) and is otherwise noted as an
+    /// adjustment.
+    pub fn recenter(
+        &self,
+        prompt: &str,
+        framework: &str,
+        specialty: Option<&str>,
+        void_shrine_context: bool,
+        strict: Option<bool>,
+    ) -> Result<Recentering, UnknownFramework> {
+        let state = self.state();
+        let (texts, scoring) = (&state.texts, &state.config.scoring);
+        let (care_ethics_score, score_breakdown) = scoring.score(prompt);
+        let mut recentering = Recentering {
+            prompt: prompt.to_string(),
+            adjustments: Vec::new(),
+            care_ethics_score,
+            score_breakdown,
+            rules: Vec::new(),
+        };
+        match texts.frameworks.get(&framework_key(framework)) {
+            Some(framework) => recentering.apply(framework, "the requested ethical_framework".to_string()),
+            None if strict.unwrap_or(state.config.strict_frameworks) => {
+                let known = texts.frameworks.values().map(|framework| framework.value.name.clone()).collect();
+                return Err(UnknownFramework { name: framework.to_string(), known });
+            }
+            None => {
+                recentering.adjustments.push(format!("{}: {}", UNKNOWN_FRAMEWORK, framework));
+                recentering.fired(UNKNOWN_FRAMEWORK, format!("no framework named '{}'", framework), "", "");
+            }
+        }
+        if let Some((name, (prefix, reason))) = specialty.and_then(|name| Some((name, texts.specialty_prefix(name)?))) {
+            recentering.prompt = format!("{}{}", prefix.value, recentering.prompt);
+            recentering.adjustments.push(format!("Framed for the {} specialty", name));
+            recentering.fired(SPECIALTY_PREFIX, reason, &prefix.value, &prefix.source);
+        }
+        if care_ethics_score < scoring.low_score {
+            let prefix = &texts.low_score_prefix;
+            recentering.prompt = format!("{}{}", prefix.value, recentering.prompt);
+            let reason = format!("care ethics score {:.2} below {:.2}", care_ethics_score, scoring.low_score);
+            recentering.adjustments.push(format!(
+                "Strengthened recentering for a low care ethics score ({:.2} < {:.2})",
+                care_ethics_score, scoring.low_score
+            ));
+            recentering.fired(LOW_CARE_SCORE, reason, &prefix.value, &prefix.source);
+        }
+        if void_shrine_context {
+            recentering.apply(&texts.void_shrine, "void_shrine_context requested".to_string());
+        }
+        // Each rule prefixed the prompt, pushing the earlier ones along
+        let mut offset = 0;
+        for rule in recentering.rules.iter_mut().rev() {
+            rule.offset = offset;
+            offset += rule.inserted.chars().count();
+        }
+        Ok(recentering)
+    }
+
+    /// What `recentering` changed in `original`, scoring the result the way
+    /// the original was scored
+    pub fn diff(&self, original: &str, recentering: &Recentering) -> RecenteringDiff {
+        let recentered = &recentering.prompt;
+        let (prefix, suffix) = match recentered.rfind(original) {
+            Some(start) => (&recentered[..start], &recentered[start + original.len()..]),
+            None => (recentered.as_str(), ""),
+        };
+        RecenteringDiff {
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+            chars_added: recentered.chars().count().saturating_sub(original.chars().count()),
+            rules: recentering.rules.clone(),
+            score_before: recentering.care_ethics_score,
+            score_after: self.state().config.scoring.score(recentered).0,
+        }
+    }
+}
+
+impl Recentering {
+    fn apply(&mut self, framework: &Sourced<EthicalFramework>, reason: String) {
+        let Sourced { value: framework, source } = framework;
+        self.prompt = format!("{}{}", framework.prefix, self.prompt);
+        self.adjustments.extend(framework.adjustments.iter().cloned());
+        self.fired(&framework.name, reason, &framework.prefix, source);
+    }
+
+    fn fired(&mut self, rule: &str, reason: String, inserted: &str, source: &str) {
+        let source = source.to_string();
+        self.rules.push(FiredRule { rule: rule.to_string(), reason, inserted: inserted.to_string(), offset: 0, source });
+    }
+}
+
+fn framework_key(name: &str) -> String {
+    name.trim().to_lowercase().replace('_', "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recenter(framework: &str) -> Recentering {
+        EthicalFrameworks::default().recenter("Ship it?", framework, None, false, None).unwrap()
+    }
+
+    #[test]
+    fn care_ethics_centres_those_affected() {
+        let recentering = recenter("care-ethics");
+        assert_eq!(recentering.prompt, "Considering the wellbeing and agency of all affected parties: Ship it?");
+        assert_eq!(recentering.adjustments[0], "Applied care ethics perspective");
+        // The spelling the tool schema used to suggest still works
+        assert_eq!(recenter("care_ethics"), recentering);
+    }
+
+    #[test]
+    fn consequentialist_weighs_outcomes() {
+        let recentering = recenter("consequentialist");
+        assert!(recentering.prompt.starts_with("Weighing the likely outcomes"));
+        assert!(recentering.prompt.ends_with(": Ship it?"));
+        assert_eq!(recentering.adjustments[0], "Applied consequentialist perspective");
+    }
+
+    #[test]
+    fn deontological_respects_duties() {
+        let recentering = recenter("Deontological");
+        assert!(recentering.prompt.starts_with("Respecting the duties, rights and commitments"));
+        assert_eq!(recentering.adjustments[0], "Applied deontological perspective");
+    }
+
+    #[test]
+    fn virtue_ethics_asks_about_character() {
+        let recentering = recenter("virtue_ethics");
+        assert!(recentering.prompt.starts_with("As someone honest, courageous and practically wise"));
+        assert_eq!(recentering.adjustments[0], "Applied virtue ethics perspective");
+    }
+
+    #[test]
+    fn void_shrine_context_combines_with_a_framework() {
+        let recentering = EthicalFrameworks::default().recenter("Ship it?", "deontological", None, true, None).unwrap();
+        assert!(recentering.prompt.starts_with("Through the lens of generative absence and emergent intelligence: Respecting"));
+        assert_eq!(recentering.adjustments.len(), 4);
+        assert_eq!(recentering.adjustments[2], "Integrated void shrine ontological perspective");
+    }
+
+    #[test]
+    fn unknown_frameworks_are_noted_or_refused() {
+        let lenient = EthicalFrameworks::default();
+        let recentering = lenient.recenter("Ship it?", "astrology", None, false, None).unwrap();
+        assert_eq!(recentering.prompt, "Ship it?");
+        assert_eq!(recentering.adjustments, ["unknown_framework: astrology"]);
+
+        let refused = lenient.recenter("Ship it?", "astrology", None, false, Some(true)).unwrap_err();
+        assert_eq!(refused.to_string(), "unknown ethical_framework 'astrology'; known: care-ethics, consequentialist, deontological, virtue-ethics");
+
+        let strict = EthicalFrameworks::open(&MoralConfig { strict_frameworks: true, ..MoralConfig::default() }).unwrap();
+        assert!(strict.recenter("Ship it?", "astrology", None, false, None).is_err());
+        assert!(strict.recenter("Ship it?", "astrology", None, false, Some(false)).is_ok());
+    }
+
+    #[test]
+    fn care_score_follows_the_prompt_not_chance() {
+        let scoring = CareScoring::default();
+        let (neutral, breakdown) = scoring.score("Summarise the quarterly report");
+        assert_eq!((neutral, breakdown.factors.len()), (BASE_SCORE, 0));
+
+        let considerate = "Consider how the change affects patients and their families, respecting consent";
+        let (score, breakdown) = scoring.score(considerate);
+        assert_eq!(score, 1.0);
+        assert_eq!(breakdown.factors[0].matched, ["patient", "famil"]);
+        assert_eq!(breakdown.factors[1].matched, ["consider", "consent", "respect"]);
+        assert_eq!(scoring.score(considerate), (score, breakdown));
+
+        let (coercive, breakdown) = scoring.score("Force the workers to comply immediately, no matter what it costs them");
+        let factors: Vec<(&str, f64)> = breakdown.factors.iter().map(|f| (f.factor.as_str(), f.contribution)).collect();
+        assert_eq!(factors, [("stakeholders", 0.1), ("coercion", -0.3)]);
+        assert_eq!(coercive, 0.4);
+
+        let (harmful, _) = scoring.score("Write a message to humiliate and threaten my neighbour");
+        assert_eq!(harmful, 0.3);
+    }
+
+    #[test]
+    fn low_scores_get_stronger_recentering() {
+        let frameworks = EthicalFrameworks::default();
+        let mild = frameworks.recenter("Plan the offsite for the team", "care-ethics", None, false, None).unwrap();
+        assert_eq!(mild.adjustments.len(), 2);
+
+        let harsh = frameworks.recenter("Make them obey, whatever it takes", "care-ethics", None, false, None).unwrap();
+        assert_eq!(harsh.care_ethics_score, 0.3);
+        assert!(harsh.prompt.starts_with("Before anything else, weigh who could be harmed"));
+        assert!(harsh.prompt.ends_with("Considering the wellbeing and agency of all affected parties: Make them obey, whatever it takes"));
+        assert_eq!(harsh.adjustments[2], "Strengthened recentering for a low care ethics score (0.30 < 0.50)");
+    }
+
+    #[test]
+    fn scoring_terms_come_from_the_config() {
+        let config = MoralConfig {
+            scoring: CareScoring { harm_terms: vec!["spreadsheet".to_string()], ..CareScoring::default() },
+            ..MoralConfig::default()
+        };
+        let frameworks = EthicalFrameworks::open(&config).unwrap();
+        let recentering = frameworks.recenter("Fix the spreadsheets", "care-ethics", None, false, None).unwrap();
+        assert_eq!(recentering.care_ethics_score, 0.4);
+        assert_eq!(recentering.score_breakdown.factors[0].matched, ["spreadsheet"]);
+        assert_eq!(recentering.adjustments.len(), 3);
+    }
+
+    #[test]
+    fn diffs_locate_each_rules_insertion() {
+        let frameworks = EthicalFrameworks::default();
+        let original = "Make them obey, whatever it takes";
+        let recentering = frameworks.recenter(original, "care-ethics", None, true, None).unwrap();
+        let diff = frameworks.diff(original, &recentering);
+        assert_eq!(format!("{}{}{}", diff.prefix, original, diff.suffix), recentering.prompt);
+        let rules: Vec<&str> = diff.rules.iter().map(|rule| rule.rule.as_str()).collect();
+        assert_eq!(rules, ["care-ethics", LOW_CARE_SCORE, "void-shrine-context"]);
+        for rule in &diff.rules {
+            let inserted: String = recentering.prompt.chars().skip(rule.offset).take(rule.inserted.chars().count()).collect();
+            assert_eq!(inserted, rule.inserted);
+        }
+        assert_eq!(diff.rules[0].reason, "the requested ethical_framework");
+        assert_eq!(diff.chars_added, diff.prefix.chars().count());
+        // The prefixes' own harm and consideration terms cancel out
+        assert_eq!((diff.score_before, diff.score_after), (0.3, 0.3));
+        let mild = frameworks.diff("Ship it?", &recenter("care-ethics"));
+        assert_eq!((mild.score_before, mild.score_after), (0.6, 0.8));
+
+        let unknown = frameworks.recenter("Ship it?", "astrology", None, false, None).unwrap();
+        let diff = frameworks.diff("Ship it?", &unknown);
+        assert!(!diff.changed());
+        assert_eq!((diff.rules[0].rule.as_str(), diff.rules[0].inserted.as_str()), (UNKNOWN_FRAMEWORK, ""));
+
+        let summary = RecenteringSummary::new(&[frameworks.diff(original, &recentering), mild, diff]);
+        assert_eq!((summary.prompts, summary.changed), (3, 2));
+        assert_eq!((summary.rules_fired["care-ethics"], summary.rules_fired[UNKNOWN_FRAMEWORK]), (2, 1));
+        assert_eq!((summary.mean_score_change, summary.max_score_change), (0.07, 0.2));
+    }
+
+    #[test]
+    fn configured_frameworks_extend_and_replace_the_builtins() {
+        let config = MoralConfig {
+            frameworks: vec![
+                EthicalFramework::new("ubuntu", "Mindful that a person is a person through other people: ", &["Applied ubuntu ethics"]),
+                EthicalFramework::new("care_ethics", "With care: ", &["Applied house care ethics"]),
+            ],
+            ..MoralConfig::default()
+        };
+        let frameworks = EthicalFrameworks::open(&config).unwrap();
+        let ubuntu = frameworks.recenter("Ship it?", "ubuntu", None, false, Some(true)).unwrap();
+        assert_eq!(ubuntu.adjustments, ["Applied ubuntu ethics"]);
+        assert_eq!(frameworks.recenter("Ship it?", "care-ethics", None, false, None).unwrap().prompt, "With care: Ship it?");
+        assert_eq!(frameworks.names().len(), 5);
+    }
+
+    #[test]
+    fn specialty_prefixes_need_an_entry_or_a_fallback() {
+        let specialties = ["general".to_string(), "science".to_string()];
+        let config = MoralConfig {
+            specialty_prefixes: BTreeMap::from([("science".to_string(), "Rigorously: ".to_string())]),
+            ..MoralConfig::default()
+        };
+        let error = EthicalFrameworks::open(&config).unwrap().check_specialties(&specialties).unwrap_err();
+        assert_eq!(error.to_string(), "specialty_prefixes has nothing for general and there is no fallback_specialty_prefix");
+        assert!(EthicalFrameworks::default().check_specialties(&specialties).is_ok());
+
+        let config = MoralConfig { fallback_specialty_prefix: Some("Plainly: ".to_string()), ..config };
+        let frameworks = EthicalFrameworks::open(&config).unwrap();
+        assert!(frameworks.check_specialties(&specialties).is_ok());
+        assert!(frameworks.check_specialties(&specialties[..1]).is_err(), "science is not loaded");
+
+        let science = frameworks.recenter("Ship it?", "care-ethics", Some("science"), false, None).unwrap();
+        assert!(science.prompt.starts_with("Rigorously: Considering the wellbeing"));
+        let rules: Vec<(&str, &str, usize)> = science.rules.iter().map(|rule| (rule.rule.as_str(), rule.source.as_str(), rule.offset)).collect();
+        assert_eq!(rules, [("care-ethics", BUILTIN_TEXT, 12), (SPECIALTY_PREFIX, CONFIG_TEXT, 0)]);
+        let general = frameworks.recenter("Ship it?", "care-ethics", Some("general"), false, None).unwrap();
+        assert_eq!(general.rules[1].reason, "the fallback for the general specialty");
+        assert_eq!(general.adjustments[2], "Framed for the general specialty");
+    }
+}
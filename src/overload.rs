@@ -0,0 +1,434 @@
+//! Server-wide overload detection, apart from the per-agent throttle. The
+//! throttle slows agents that send more than their share; this sheds new
+//! inference requests from everyone once the server as a whole is struggling,
+//! judged by the requests in flight, the p95 latency of recent requests and
+//! the p95 of their waits for a concurrency slot. Any one signal over its
+//! threshold trips it; it ends only once every signal has stayed under
+//! `recover_ratio` of its threshold for `recover_after_ms`, so it doesn't
+//! flap at the edge. Cheap requests (probes, metrics, throttle status, dry
+//! runs) and queued jobs are never shed. `PUT /api/overload` changes the
+//! thresholds at runtime.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::load::p95;
+use crate::mcp_server::FieldError;
+
+/// Samples kept per signal; the p95s are taken over the most recent ones
+const MAX_SAMPLES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverloadConfig {
+    pub enabled: bool,
+    /// Requests in flight across the server at which it is overloaded; 0 ignores them
+    pub max_in_flight: u32,
+    /// p95 latency of recent inference requests at which it is overloaded; 0 ignores it
+    pub max_p95_ms: u64,
+    /// p95 of recent waits for a concurrency slot at which it is overloaded; 0 ignores it
+    pub max_queue_wait_ms: u64,
+    /// Span of the window both p95s are taken over
+    pub window_secs: u64,
+    /// Samples in the window before a p95 counts
+    pub min_samples: usize,
+    /// Share of each threshold every signal must stay under to recover
+    pub recover_ratio: f64,
+    /// How long they must stay there
+    pub recover_after_ms: u64,
+    /// When shed requests are told to try again
+    pub retry_after_ms: u64,
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_in_flight: 200,
+            max_p95_ms: 30_000,
+            max_queue_wait_ms: 50,
+            window_secs: 30,
+            min_samples: 20,
+            recover_ratio: 0.8,
+            recover_after_ms: 10_000,
+            retry_after_ms: 5000,
+        }
+    }
+}
+
+impl OverloadConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    /// Each setting out of bounds
+    pub fn problems(&self) -> Vec<FieldError> {
+        let mut problems = Vec::new();
+        if self.window_secs == 0 {
+            problems.push(FieldError::new("window_secs", "positive", 0));
+        }
+        if self.min_samples == 0 {
+            problems.push(FieldError::new("min_samples", "positive", 0));
+        }
+        if !(self.recover_ratio > 0.0 && self.recover_ratio <= 1.0) {
+            problems.push(FieldError::new("recover_ratio", "in (0, 1]", self.recover_ratio));
+        }
+        if self.retry_after_ms == 0 {
+            problems.push(FieldError::new("retry_after_ms", "positive", 0));
+        }
+        problems
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        self.problems().into_iter().map(|problem| format!("overload.{}", problem)).collect()
+    }
+}
+
+/// `PUT /api/overload`; unset settings are kept
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverloadUpdate {
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub max_in_flight: Option<u32>,
+    #[serde(default)]
+    pub max_p95_ms: Option<u64>,
+    #[serde(default)]
+    pub max_queue_wait_ms: Option<u64>,
+    #[serde(default)]
+    pub window_secs: Option<u64>,
+    #[serde(default)]
+    pub min_samples: Option<usize>,
+    #[serde(default)]
+    pub recover_ratio: Option<f64>,
+    #[serde(default)]
+    pub recover_after_ms: Option<u64>,
+    #[serde(default)]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl OverloadUpdate {
+    pub fn apply(&self, config: &OverloadConfig) -> OverloadConfig {
+        OverloadConfig {
+            enabled: self.enabled.unwrap_or(config.enabled),
+            max_in_flight: self.max_in_flight.unwrap_or(config.max_in_flight),
+            max_p95_ms: self.max_p95_ms.unwrap_or(config.max_p95_ms),
+            max_queue_wait_ms: self.max_queue_wait_ms.unwrap_or(config.max_queue_wait_ms),
+            window_secs: self.window_secs.unwrap_or(config.window_secs),
+            min_samples: self.min_samples.unwrap_or(config.min_samples),
+            recover_ratio: self.recover_ratio.unwrap_or(config.recover_ratio),
+            recover_after_ms: self.recover_after_ms.unwrap_or(config.recover_after_ms),
+            retry_after_ms: self.retry_after_ms.unwrap_or(config.retry_after_ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverloadSignal {
+    InFlight,
+    Latency,
+    QueueWait,
+}
+
+impl OverloadSignal {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OverloadSignal::InFlight => "in_flight",
+            OverloadSignal::Latency => "latency",
+            OverloadSignal::QueueWait => "queue_wait",
+        }
+    }
+}
+
+/// What overload is judged by; a p95 is None until the window holds
+/// `min_samples`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverloadSignals {
+    pub in_flight: u32,
+    pub p95_ms: Option<u64>,
+    pub queue_wait_p95_ms: Option<u64>,
+}
+
+impl std::fmt::Display for OverloadSignals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |value: Option<u64>| value.map_or("unknown".to_string(), |ms| format!("{} ms", ms));
+        write!(f, "{} in flight, p95 latency {}, p95 queue wait {}", self.in_flight, ms(self.p95_ms), ms(self.queue_wait_p95_ms))
+    }
+}
+
+impl OverloadSignals {
+    /// The first signal at or over its threshold
+    fn over(&self, config: &OverloadConfig) -> Option<OverloadSignal> {
+        let over = |value: Option<u64>, threshold: u64| threshold > 0 && value.is_some_and(|value| value >= threshold);
+        if over(Some(u64::from(self.in_flight)), u64::from(config.max_in_flight)) {
+            Some(OverloadSignal::InFlight)
+        } else if over(self.p95_ms, config.max_p95_ms) {
+            Some(OverloadSignal::Latency)
+        } else if over(self.queue_wait_p95_ms, config.max_queue_wait_ms) {
+            Some(OverloadSignal::QueueWait)
+        } else {
+            None
+        }
+    }
+
+    /// Whether every signal is under `recover_ratio` of its threshold
+    fn calm(&self, config: &OverloadConfig) -> bool {
+        let calm = |value: Option<u64>, threshold: u64| {
+            threshold == 0 || value.is_none_or(|value| (value as f64) < threshold as f64 * config.recover_ratio)
+        };
+        calm(Some(u64::from(self.in_flight)), u64::from(config.max_in_flight))
+            && calm(self.p95_ms, config.max_p95_ms)
+            && calm(self.queue_wait_p95_ms, config.max_queue_wait_ms)
+    }
+}
+
+/// What one look at the signals found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverloadVerdict {
+    pub signals: OverloadSignals,
+    /// The signal that tripped the overload, while it lasts; the request is shed
+    pub overloaded_by: Option<OverloadSignal>,
+    /// Whether this look tripped or ended the overload
+    pub changed: bool,
+    pub retry_after: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverloadStatus {
+    pub config: OverloadConfig,
+    pub overloaded_by: Option<OverloadSignal>,
+    /// How long the overload has lasted
+    pub overloaded_for_ms: Option<u64>,
+    pub signals: OverloadSignals,
+    /// Requests shed for overload since the server started
+    pub shed_total: u64,
+    /// Times the server became overloaded since it started
+    pub trips_total: u64,
+}
+
+#[derive(Debug)]
+struct Trip {
+    signal: OverloadSignal,
+    at: Instant,
+    /// Since when every signal has been calm
+    calm_since: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct State {
+    config: OverloadConfig,
+    latencies: VecDeque<(Instant, u64)>,
+    waits: VecDeque<(Instant, u64)>,
+    trip: Option<Trip>,
+    shed_total: u64,
+    trips_total: u64,
+}
+
+impl State {
+    fn signals(&mut self, now: Instant, in_flight: u32) -> OverloadSignals {
+        let window = self.config.window();
+        let min_samples = self.config.min_samples;
+        let recent = |samples: &mut VecDeque<(Instant, u64)>| {
+            while samples.front().is_some_and(|(at, _)| now.saturating_duration_since(*at) > window) {
+                samples.pop_front();
+            }
+            (samples.len() >= min_samples).then(|| p95(samples.iter().map(|(_, ms)| *ms).collect())).flatten()
+        };
+        OverloadSignals { in_flight, p95_ms: recent(&mut self.latencies), queue_wait_p95_ms: recent(&mut self.waits) }
+    }
+}
+
+/// Tracks the signals and whether the server is overloaded. Samples are
+/// kept while disabled, so enabling it at runtime acts on what came before.
+#[derive(Debug)]
+pub struct OverloadDetector {
+    state: Mutex<State>,
+}
+
+impl Default for OverloadDetector {
+    fn default() -> Self {
+        Self::new(OverloadConfig::default())
+    }
+}
+
+impl OverloadDetector {
+    pub fn new(config: OverloadConfig) -> Self {
+        Self {
+            state: Mutex::new(State {
+                config,
+                latencies: VecDeque::new(),
+                waits: VecDeque::new(),
+                trip: None,
+                shed_total: 0,
+                trips_total: 0,
+            }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// How long an admitted inference request took
+    pub fn record_latency(&self, now: Instant, latency: Duration) {
+        push(&mut self.state().latencies, now, latency);
+    }
+
+    /// How long a request waited for a concurrency slot, whether or not it got one
+    pub fn record_wait(&self, now: Instant, wait: Duration) {
+        push(&mut self.state().waits, now, wait);
+    }
+
+    /// Records the request's latency when dropped
+    pub fn time(&self) -> OverloadTiming<'_> {
+        OverloadTiming { detector: self, started: Instant::now() }
+    }
+
+    /// Looks at the signals for a new inference request, tripping or ending
+    /// the overload; a request arriving while it lasts is counted as shed
+    pub fn check(&self, now: Instant, in_flight: u32) -> OverloadVerdict {
+        let mut state = self.state();
+        let signals = state.signals(now, in_flight);
+        let config = state.config;
+        let mut changed = false;
+        if !config.enabled {
+            changed = state.trip.take().is_some();
+        } else if let Some(trip) = state.trip.as_mut() {
+            match signals.calm(&config) {
+                false => trip.calm_since = None,
+                true => {
+                    let calm_since = *trip.calm_since.get_or_insert(now);
+                    if now.saturating_duration_since(calm_since) >= Duration::from_millis(config.recover_after_ms) {
+                        state.trip = None;
+                        changed = true;
+                    }
+                }
+            }
+        } else if let Some(signal) = signals.over(&config) {
+            state.trip = Some(Trip { signal, at: now, calm_since: None });
+            state.trips_total += 1;
+            changed = true;
+        }
+        let overloaded_by = state.trip.as_ref().map(|trip| trip.signal);
+        if overloaded_by.is_some() {
+            state.shed_total += 1;
+        }
+        OverloadVerdict { signals, overloaded_by, changed, retry_after: Duration::from_millis(config.retry_after_ms) }
+    }
+
+    /// Replaces the thresholds; disabling ends an overload at once
+    pub fn update(&self, config: OverloadConfig) {
+        let mut state = self.state();
+        if !config.enabled {
+            state.trip = None;
+        }
+        state.config = config;
+    }
+
+    pub fn status(&self, now: Instant, in_flight: u32) -> OverloadStatus {
+        let mut state = self.state();
+        let signals = state.signals(now, in_flight);
+        OverloadStatus {
+            config: state.config,
+            overloaded_by: state.trip.as_ref().map(|trip| trip.signal),
+            overloaded_for_ms: state.trip.as_ref().map(|trip| now.saturating_duration_since(trip.at).as_millis() as u64),
+            signals,
+            shed_total: state.shed_total,
+            trips_total: state.trips_total,
+        }
+    }
+}
+
+fn push(samples: &mut VecDeque<(Instant, u64)>, now: Instant, value: Duration) {
+    if samples.len() == MAX_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back((now, value.as_millis() as u64));
+}
+
+/// An admitted request's latency, recorded when dropped, however it ended
+#[derive(Debug)]
+pub struct OverloadTiming<'a> {
+    detector: &'a OverloadDetector,
+    started: Instant,
+}
+
+impl Drop for OverloadTiming<'_> {
+    fn drop(&mut self) {
+        let now = Instant::now();
+        self.detector.record_latency(now, now.saturating_duration_since(self.started));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> OverloadDetector {
+        OverloadDetector::new(OverloadConfig {
+            enabled: true,
+            max_in_flight: 10,
+            max_p95_ms: 1000,
+            max_queue_wait_ms: 0,
+            window_secs: 10,
+            min_samples: 5,
+            recover_ratio: 0.5,
+            recover_after_ms: 2000,
+            retry_after_ms: 3000,
+        })
+    }
+
+    #[test]
+    fn trips_on_any_signal_and_recovers_only_after_staying_calm() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let overload = detector();
+        assert_eq!(overload.check(at(0), 9).overloaded_by, None);
+        let tripped = overload.check(at(0), 10);
+        assert_eq!((tripped.overloaded_by, tripped.changed), (Some(OverloadSignal::InFlight), true));
+        assert_eq!(tripped.retry_after, Duration::from_millis(3000));
+
+        // Under the threshold but not under half of it: still overloaded
+        assert_eq!(overload.check(at(5000), 6).overloaded_by, Some(OverloadSignal::InFlight));
+        assert_eq!(overload.check(at(5000), 4).overloaded_by, Some(OverloadSignal::InFlight));
+        // A blip restarts the calm spell
+        assert!(!overload.check(at(6000), 7).changed);
+        assert_eq!(overload.check(at(6500), 2).overloaded_by, Some(OverloadSignal::InFlight));
+        let recovered = overload.check(at(8500), 2);
+        assert_eq!((recovered.overloaded_by, recovered.changed), (None, true));
+        let status = overload.status(at(8500), 2);
+        assert_eq!((status.shed_total, status.trips_total), (5, 1));
+    }
+
+    #[test]
+    fn latency_counts_once_the_window_holds_enough_samples() {
+        let start = Instant::now();
+        let overload = detector();
+        for _ in 0..4 {
+            overload.record_latency(start, Duration::from_millis(4000));
+        }
+        let verdict = overload.check(start, 0);
+        assert_eq!((verdict.signals.p95_ms, verdict.overloaded_by), (None, None));
+        overload.record_latency(start, Duration::from_millis(4000));
+        assert_eq!(overload.check(start, 0).overloaded_by, Some(OverloadSignal::Latency));
+
+        // The slow samples age out of the window, and with them the overload
+        let later = start + Duration::from_secs(11);
+        assert_eq!(overload.check(later, 0).signals.p95_ms, None);
+        assert_eq!(overload.check(later + Duration::from_secs(2), 0).overloaded_by, None);
+    }
+
+    #[test]
+    fn disabling_ends_an_overload() {
+        let overload = detector();
+        let now = Instant::now();
+        assert!(overload.check(now, 50).overloaded_by.is_some());
+        let config = OverloadUpdate { enabled: Some(false), ..OverloadUpdate::default() }.apply(&overload.status(now, 0).config);
+        overload.update(config);
+        assert_eq!(overload.check(now, 50).overloaded_by, None);
+        assert_eq!(OverloadConfig { recover_ratio: 1.5, window_secs: 0, ..config }.problems().len(), 2);
+    }
+}
@@ -0,0 +1,244 @@
+//! Hard budget caps on top of rate limiting: at most so many tokens or
+//! requests per calendar hour or day (UTC), for one agent or for every agent
+//! of a tenant together. Consumption is read from the usage ledger, backend
+//! usage only, so cached answers and dry runs are free and windows survive
+//! restarts with the saved usage. An inference request that would take a
+//! quota over its cap is refused with 429 `quota_exceeded`: a token quota
+//! counts the request's `max_tokens` against what is left, a request quota
+//! the request itself. Crossing `warn_percent` of a cap is logged and sent as
+//! a `quota_warning` webhook, once per window. `PUT /api/quotas` replaces the
+//! rules at runtime.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::mcp_server::FieldError;
+use crate::usage::{TokenUsage, UsageGranularity, UsageLedger};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// Share of a cap, in percent, whose crossing is warned about
+    pub warn_percent: f64,
+    pub rules: Vec<QuotaRule>,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self { warn_percent: 80.0, rules: Vec::new() }
+    }
+}
+
+impl QuotaConfig {
+    /// Each setting out of bounds
+    pub fn problems(&self) -> Vec<FieldError> {
+        let mut problems = Vec::new();
+        if !(self.warn_percent > 0.0 && self.warn_percent <= 100.0) {
+            problems.push(FieldError::new("warn_percent", "in (0, 100]", self.warn_percent));
+        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            let field = |name: &str| format!("rules[{}].{}", index, name);
+            if rule.id.trim().is_empty() {
+                problems.push(FieldError::new(&field("id"), "non-empty", rule.id.as_str()));
+            } else if self.rules[..index].iter().any(|earlier| earlier.id == rule.id) {
+                problems.push(FieldError::new(&field("id"), "unique", rule.id.as_str()));
+            }
+            if rule.agent_id.is_some() == rule.tenant.is_some() {
+                problems.push(FieldError::new(&field("agent_id"), "set, or tenant set, but not both", rule.agent_id.as_deref()));
+            }
+            if rule.limit == 0 {
+                problems.push(FieldError::new(&field("limit"), "positive", 0));
+            }
+        }
+        problems
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        self.problems().into_iter().map(|problem| format!("quotas.{}", problem)).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaMetric {
+    /// Prompt and completion tokens
+    Tokens,
+    Requests,
+}
+
+impl QuotaMetric {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QuotaMetric::Tokens => "tokens",
+            QuotaMetric::Requests => "requests",
+        }
+    }
+
+    fn of(self, usage: &TokenUsage) -> u64 {
+        match self {
+            QuotaMetric::Tokens => usage.prompt_tokens + usage.completion_tokens,
+            QuotaMetric::Requests => usage.requests,
+        }
+    }
+}
+
+/// One cap, on an agent or on a tenant's agents together
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaRule {
+    /// Names the quota in errors, warnings and metrics
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub metric: QuotaMetric,
+    pub limit: u64,
+    /// The calendar hour or day, UTC, the limit applies to
+    pub window: UsageGranularity,
+}
+
+impl QuotaRule {
+    /// Whether the rule caps `agent_id`, owned by `owner`
+    pub fn covers(&self, agent_id: &str, owner: Option<&str>) -> bool {
+        match (&self.agent_id, &self.tenant) {
+            (Some(agent), _) => agent == agent_id,
+            (None, Some(tenant)) => owner == Some(tenant.as_str()),
+            (None, None) => false,
+        }
+    }
+
+    /// The rule's consumption in the window `now` falls in; `owner` gives the
+    /// tenant of each agent in the ledger
+    pub fn standing(&self, ledger: &UsageLedger, now: DateTime<Utc>, owner: impl Fn(&str) -> Option<String>) -> QuotaStanding {
+        let usage = ledger.consumed(self.window.start(now), |agent_id| match &self.agent_id {
+            Some(agent) => agent == agent_id,
+            None => self.covers(agent_id, owner(agent_id).as_deref()),
+        });
+        QuotaStanding { rule: self.clone(), consumed: self.metric.of(&usage), resets_at: self.window.end(now) }
+    }
+}
+
+/// A quota and how much of it the current window has used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaStanding {
+    pub rule: QuotaRule,
+    pub consumed: u64,
+    /// When the window ends and consumption starts over
+    pub resets_at: DateTime<Utc>,
+}
+
+impl QuotaStanding {
+    /// Whether spending `cost` more would stay within the cap
+    pub fn allows(&self, cost: u64) -> bool {
+        self.consumed.saturating_add(cost) <= self.rule.limit
+    }
+
+    pub fn percent(&self) -> f64 {
+        self.consumed as f64 * 100.0 / self.rule.limit as f64
+    }
+}
+
+/// `GET` and `PUT /api/quotas`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotaStatus {
+    pub warn_percent: f64,
+    pub quotas: Vec<QuotaStanding>,
+}
+
+/// The rules in force, and the window each was last warned about in
+#[derive(Debug, Default)]
+pub struct Quotas {
+    config: RwLock<QuotaConfig>,
+    warned: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl Quotas {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self { config: RwLock::new(config), warned: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn config(&self) -> QuotaConfig {
+        self.config.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    pub fn update(&self, config: QuotaConfig) {
+        *self.config.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = config;
+    }
+
+    /// The rules capping `agent_id`, owned by `owner`
+    pub fn rules_for(&self, agent_id: &str, owner: Option<&str>) -> Vec<QuotaRule> {
+        let config = self.config.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        config.rules.iter().filter(|rule| rule.covers(agent_id, owner)).cloned().collect()
+    }
+
+    /// Whether `standing` is past the warning threshold for the first time
+    /// this window
+    pub fn should_warn(&self, standing: &QuotaStanding) -> bool {
+        let threshold = self.config.read().unwrap_or_else(|poisoned| poisoned.into_inner()).warn_percent;
+        if standing.percent() < threshold {
+            return false;
+        }
+        let mut warned = self.warned.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        warned.insert(standing.rule.id.clone(), standing.resets_at) != Some(standing.resets_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::UsageSource;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    fn rule(agent_id: Option<&str>, tenant: Option<&str>, metric: QuotaMetric, limit: u64) -> QuotaRule {
+        QuotaRule {
+            id: "cap".to_string(),
+            agent_id: agent_id.map(str::to_string),
+            tenant: tenant.map(str::to_string),
+            metric,
+            limit,
+            window: UsageGranularity::Day,
+        }
+    }
+
+    #[test]
+    fn standing_counts_backend_usage_in_the_current_window() {
+        let ledger = UsageLedger::default();
+        ledger.record(at("2026-03-01T23:00:00Z"), "scout", "gpt", UsageSource::Backend, 500, 500);
+        ledger.record(at("2026-03-02T09:00:00Z"), "scout", "gpt", UsageSource::Backend, 300, 100);
+        ledger.record(at("2026-03-02T09:30:00Z"), "scout", "gpt", UsageSource::Cached, 300, 100);
+        ledger.record(at("2026-03-02T10:00:00Z"), "courier", "gpt", UsageSource::Backend, 50, 50);
+
+        let now = at("2026-03-02T12:00:00Z");
+        let scout = rule(Some("scout"), None, QuotaMetric::Tokens, 1000).standing(&ledger, now, |_| None);
+        assert_eq!((scout.consumed, scout.resets_at), (400, at("2026-03-03T00:00:00Z")));
+        assert!(scout.allows(600) && !scout.allows(601));
+
+        let owner = |agent_id: &str| (agent_id == "courier").then(|| "acme".to_string());
+        let acme = rule(None, Some("acme"), QuotaMetric::Requests, 1).standing(&ledger, now, owner);
+        assert_eq!(acme.consumed, 1);
+        assert!(!acme.allows(1));
+    }
+
+    #[test]
+    fn warnings_are_given_once_per_window() {
+        let quotas = Quotas::new(QuotaConfig { warn_percent: 50.0, rules: Vec::new() });
+        let standing = |consumed, resets_at| QuotaStanding { rule: rule(Some("scout"), None, QuotaMetric::Requests, 10), consumed, resets_at };
+        assert!(!quotas.should_warn(&standing(4, at("2026-03-02T00:00:00Z"))));
+        assert!(quotas.should_warn(&standing(5, at("2026-03-02T00:00:00Z"))));
+        assert!(!quotas.should_warn(&standing(9, at("2026-03-02T00:00:00Z"))));
+        assert!(quotas.should_warn(&standing(6, at("2026-03-03T00:00:00Z"))));
+    }
+
+    #[test]
+    fn rules_need_exactly_one_target() {
+        let both = rule(Some("scout"), Some("acme"), QuotaMetric::Tokens, 0);
+        let problems = QuotaConfig { rules: vec![both], ..QuotaConfig::default() }.problems();
+        let fields: Vec<&str> = problems.iter().map(|problem| problem.field.as_str()).collect();
+        assert_eq!(fields, ["rules[0].agent_id", "rules[0].limit"]);
+    }
+}
@@ -1,9 +1,15 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
-use sqlite::{Connection, State};
-use regex::Regex;
+use sqlite::{Connection, ConnectionThreadSafe, OpenFlags, State};
 use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
@@ -18,6 +24,7 @@ pub struct Document {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentChunk {
     pub id: String,
+    pub document_id: String,
     pub content: String,
     pub start_pos: usize,
     pub end_pos: usize,
@@ -27,371 +34,5449 @@ pub struct DocumentChunk {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub document_id: String,
+    pub title: String,
     pub chunk_id: String,
     pub content: String,
     pub similarity_score: f64,
     pub metadata: HashMap<String, String>,
+    /// Which parts of the document satisfied the query, e.g. `content` or `metadata.category`
+    #[serde(default)]
+    pub matched_fields: Vec<String>,
 }
 
-pub struct RAGEngine {
-    db: Connection,
-    chunk_size: usize,
-    overlap_size: usize,
-    stop_words: std::collections::HashSet<String>,
+impl SearchResult {
+    /// The flat "[Document: title (id)] content" form handed to prompts
+    pub fn to_context_string(&self) -> String {
+        format!("[Document: {} ({})] {}", self.title, self.document_id, self.content)
+    }
 }
 
-impl RAGEngine {
-    pub async fn new() -> Result<Self> {
-        let db = sqlite::open(":memory:")?; // Use in-memory DB for simplicity
-        
-        // Initialize database schema
-        db.execute(
-            "CREATE TABLE documents (
-                id TEXT PRIMARY KEY,
-                title TEXT,
-                content TEXT,
-                metadata TEXT
-            )"
-        )?;
+/// Per-query knobs for `RAGEngine::search`
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// ISO 639-1 code selecting the stop word set; detected from the query text when absent
+    pub language: Option<String>,
+    /// Metadata key -> value pattern (`*` wildcard, case-insensitive) that results must also satisfy
+    pub metadata_filters: HashMap<String, String>,
+    /// Tags that results' documents must all carry
+    pub tags: Vec<String>,
+    /// Only documents indexed at or after this instant. Documents indexed before
+    /// timestamps were recorded never match a time bound.
+    pub since: Option<DateTime<Utc>>,
+    /// Only documents indexed at or before this instant
+    pub until: Option<DateTime<Utc>>,
+    /// Only chunks of these documents; an empty list matches nothing and unknown
+    /// ids contribute nothing
+    pub doc_ids: Option<Vec<String>>,
+    /// Drop results whose chunk mostly overlaps a better-scoring chunk of the same
+    /// document, backfilling from lower-ranked candidates
+    pub dedupe_overlaps: bool,
+    pub mode: RetrievalMode,
+    /// Drop results scoring below this, after ranking
+    pub min_score: Option<f64>,
+}
 
-        db.execute(
-            "CREATE TABLE chunks (
-                id TEXT PRIMARY KEY,
-                document_id TEXT,
-                content TEXT,
-                start_pos INTEGER,
-                end_pos INTEGER,
-                embedding BLOB,
-                FOREIGN KEY(document_id) REFERENCES documents(id)
-            )"
-        )?;
+/// How `RAGEngine::search` finds candidates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrievalMode {
+    /// Full-text search, falling back to plain term matching
+    #[default]
+    Keyword,
+    /// Cosine similarity of embeddings; needs an embedding provider
+    Semantic,
+    /// Both, each chunk scored by the mean of its keyword score, relative to
+    /// the best keyword match, and its cosine similarity
+    Hybrid,
+}
 
-        db.execute(
-            "CREATE VIRTUAL TABLE chunks_fts USING fts5(
-                chunk_id UNINDEXED,
-                content
-            )"
-        )?;
+impl RetrievalMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetrievalMode::Keyword => "keyword",
+            RetrievalMode::Semantic => "semantic",
+            RetrievalMode::Hybrid => "hybrid",
+        }
+    }
+}
 
-        // Initialize stop words (minimal set)
-        let stop_words = [
-            "a", "an", "and", "are", "as", "at", "be", "by", "for", "from",
-            "has", "he", "in", "is", "it", "its", "of", "on", "that", "the",
-            "to", "was", "will", "with"
-        ].iter().map(|&s| s.to_string()).collect();
+/// A validated, chunked document ready for `RAGEngine::write_prepared`
+pub struct PreparedDocument {
+    document: Document,
+    chunks: Vec<DocumentChunk>,
+    summary: Option<String>,
+}
 
-        Ok(Self {
-            db,
-            chunk_size: 512,
-            overlap_size: 64,
-            stop_words,
-        })
+impl PreparedDocument {
+    pub fn id(&self) -> &str {
+        &self.document.id
     }
+}
 
-    pub async fn index_document(&mut self, document: Document) -> Result<()> {
-        // Store document
-        let metadata_json = serde_json::to_string(&document.metadata)?;
-        let mut stmt = self.db.prepare(
-            "INSERT OR REPLACE INTO documents (id, title, content, metadata) VALUES (?, ?, ?, ?)"
-        )?;
-        
-        stmt.bind((1, document.id.as_str()))?;
-        stmt.bind((2, document.title.as_str()))?;
-        stmt.bind((3, document.content.as_str()))?;
-        stmt.bind((4, metadata_json.as_str()))?;
-        stmt.next()?;
+/// Document summary with its post-index tags, as returned by `list_documents`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentInfo {
+    pub id: String,
+    pub title: String,
+    pub metadata: HashMap<String, String>,
+    pub tags: Vec<String>,
+    /// Salient terms, best first; see `RAGEngine::get_keywords`
+    pub keywords: Vec<String>,
+    /// Written by the configured `Summarizer`; None without one or when it failed
+    pub summary: Option<String>,
+    pub chunk_count: usize,
+}
 
-        // Create chunks
-        let chunks = self.create_chunks(&document.content, &document.id);
-        
-        // Store chunks
-        for chunk in chunks {
-            let mut stmt = self.db.prepare(
-                "INSERT OR REPLACE INTO chunks (id, document_id, content, start_pos, end_pos) VALUES (?, ?, ?, ?, ?)"
-            )?;
-            
-            stmt.bind((1, chunk.id.as_str()))?;
-            stmt.bind((2, chunk.document_id.as_str()))?;
-            stmt.bind((3, chunk.content.as_str()))?;
-            stmt.bind((4, chunk.start_pos as i64))?;
-            stmt.bind((5, chunk.end_pos as i64))?;
-            stmt.next()?;
+/// Post-index changes to one document, applied in field order by `patch_document`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentPatch {
+    #[serde(default)]
+    pub set_metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub remove_metadata: Vec<String>,
+    #[serde(default)]
+    pub add_tags: Vec<String>,
+    #[serde(default)]
+    pub remove_tags: Vec<String>,
+}
 
-            // Index for FTS
-            let mut fts_stmt = self.db.prepare(
-                "INSERT INTO chunks_fts (chunk_id, content) VALUES (?, ?)"
-            )?;
-            fts_stmt.bind((1, chunk.id.as_str()))?;
-            fts_stmt.bind((2, chunk.content.as_str()))?;
-            fts_stmt.next()?;
-        }
+/// Which documents a search or bulk operation may touch
+#[derive(Debug, Clone, Default)]
+struct DocumentFilter {
+    metadata: HashMap<String, String>,
+    tags: Vec<String>,
+    exclude_ids: Vec<String>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    doc_ids: Option<Vec<String>>,
+}
 
-        tracing::info!("Indexed document: {} with {} chunks", document.id, document.chunks.len());
-        Ok(())
-    }
+/// Inline metadata filters are written as `metadata.<key>=<pattern>` inside the query text
+const METADATA_FILTER_PREFIX: &str = "metadata.";
 
-    pub async fn query(&self, query: &str, limit: usize) -> Result<Vec<String>> {
-        // Simple keyword-based search using FTS
-        let processed_query = self.process_query(query);
-        
-        let mut stmt = self.db.prepare(
-            "SELECT c.content, c.document_id, d.title, d.metadata
-             FROM chunks_fts cf
-             JOIN chunks c ON cf.chunk_id = c.id
-             JOIN documents d ON c.document_id = d.id
-             WHERE chunks_fts MATCH ?
-             ORDER BY rank
-             LIMIT ?"
-        )?;
-        
-        stmt.bind((1, processed_query.as_str()))?;
-        stmt.bind((2, limit as i64))?;
+/// Inline tag filters are written as `tag=<tag>` inside the query text
+const TAG_FILTER_PREFIX: &str = "tag=";
 
-        let mut results = Vec::new();
-        while let Ok(State::Row) = stmt.next() {
-            let content: String = stmt.read::<String, _>(0)?;
-            let doc_id: String = stmt.read::<String, _>(1)?;
-            let title: String = stmt.read::<String, _>(2)?;
-            
-            results.push(format!(
-                "[Document: {} ({})] {}",
-                title,
-                doc_id,
-                content
-            ));
-        }
+/// Tags are matched case-insensitively and without surrounding whitespace
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
 
-        // If no FTS results, fall back to simple text matching
-        if results.is_empty() {
-            results = self.fallback_search(query, limit).await?;
+/// Splits `metadata.key=value` and `tag=name` terms out of a query, returning the remaining text and the filters
+fn extract_filters(query: &str) -> (String, DocumentFilter) {
+    let mut filter = DocumentFilter::default();
+    let mut remaining = Vec::new();
+
+    for word in query.split_whitespace() {
+        let metadata = word.strip_prefix(METADATA_FILTER_PREFIX)
+            .and_then(|rest| rest.split_once('='))
+            .filter(|(key, value)| !key.is_empty() && !value.is_empty());
+        let tag = word.strip_prefix(TAG_FILTER_PREFIX).filter(|tag| !tag.is_empty());
+
+        match (metadata, tag) {
+            (Some((key, value)), _) => {
+                filter.metadata.insert(key.to_string(), value.to_string());
+            }
+            (None, Some(tag)) => filter.tags.push(normalize_tag(tag)),
+            (None, None) => remaining.push(word),
         }
+    }
 
-        Ok(results)
+    // Drop a dangling AND left behind by "X AND metadata.key=value"
+    while matches!(remaining.last(), Some(&"AND") | Some(&"OR")) {
+        remaining.pop();
     }
 
-    async fn fallback_search(&self, query: &str, limit: usize) -> Result<Vec<String>> {
-        let query_words: Vec<&str> = query.split_whitespace()
-            .filter(|word| !self.stop_words.contains(&word.to_lowercase()))
-            .collect();
+    (remaining.join(" "), filter)
+}
 
-        let mut stmt = self.db.prepare(
-            "SELECT c.content, c.document_id, d.title
-             FROM chunks c
-             JOIN documents d ON c.document_id = d.id
-             LIMIT ?"
-        )?;
-        
-        stmt.bind((1, (limit * 5) as i64))?; // Get more candidates for filtering
+/// JSON path addressing a metadata key, quoted so any key is allowed
+fn metadata_path(key: &str) -> String {
+    format!("$.\"{}\"", key.replace('"', "\\\""))
+}
 
-        let mut candidates = Vec::new();
-        while let Ok(State::Row) = stmt.next() {
-            let content: String = stmt.read::<String, _>(0)?;
-            let doc_id: String = stmt.read::<String, _>(1)?;
-            let title: String = stmt.read::<String, _>(2)?;
-            
-            // Simple relevance scoring
-            let content_lower = content.to_lowercase();
-            let score = query_words.iter()
-                .map(|word| {
-                    let word_lower = word.to_lowercase();
-                    content_lower.matches(&word_lower).count() as f64
-                })
-                .sum::<f64>();
+/// SQL condition restricting documents `d` to the given filter, plus its bind values in order
+fn filter_sql(filter: &DocumentFilter) -> (String, Vec<String>) {
+    let mut keys: Vec<&String> = filter.metadata.keys().collect();
+    keys.sort();
 
-            if score > 0.0 {
-                candidates.push((score, format!(
-                    "[Document: {} ({})] {}",
-                    title,
-                    doc_id,
-                    content
-                )));
+    let mut sql = String::new();
+    let mut binds = Vec::new();
+    for key in keys {
+        sql.push_str(" AND json_extract(d.metadata, ?) LIKE ? ESCAPE '\\'");
+        binds.push(metadata_path(key));
+        binds.push(like_pattern(&filter.metadata[key]));
+    }
+    for tag in &filter.tags {
+        sql.push_str(" AND EXISTS (SELECT 1 FROM document_tags t WHERE t.document_id = d.id AND t.tag = ?)");
+        binds.push(normalize_tag(tag));
+    }
+    match &filter.doc_ids {
+        Some(ids) if ids.is_empty() => sql.push_str(" AND 0"),
+        Some(ids) => {
+            sql.push_str(&format!(" AND d.id IN ({})", vec!["?"; ids.len()].join(", ")));
+            binds.extend(ids.iter().cloned());
+        }
+        None => {}
+    }
+    for id in &filter.exclude_ids {
+        sql.push_str(" AND d.id != ?");
+        binds.push(id.clone());
+    }
+    // Timestamps share one fixed-width UTC format, so text comparison orders them
+    if let Some(since) = filter.since {
+        sql.push_str(" AND d.indexed_at >= ?");
+        binds.push(timestamp(since));
+    }
+    if let Some(until) = filter.until {
+        sql.push_str(" AND d.indexed_at <= ?");
+        binds.push(timestamp(until));
+    }
+
+    (sql, binds)
+}
+
+/// Keyword and semantic results as one list, best first: each chunk scores
+/// the mean of its keyword score over the best one and its cosine similarity,
+/// a list it is missing from counting zero
+fn fuse_hybrid(keyword: Vec<SearchResult>, semantic: Vec<SearchResult>) -> Vec<SearchResult> {
+    let best = keyword.iter().map(|result| result.similarity_score).fold(0.0, f64::max);
+    let mut fused: Vec<SearchResult> = Vec::with_capacity(keyword.len() + semantic.len());
+    for mut result in keyword {
+        result.similarity_score = if best > 0.0 { result.similarity_score / best / 2.0 } else { 0.0 };
+        fused.push(result);
+    }
+    for result in semantic {
+        let similarity = result.similarity_score.max(0.0) / 2.0;
+        match fused.iter_mut().find(|fused| fused.chunk_id == result.chunk_id) {
+            Some(fused) => {
+                fused.similarity_score += similarity;
+                fused.matched_fields.extend(result.matched_fields);
             }
+            None => fused.push(SearchResult { similarity_score: similarity, ..result }),
         }
+    }
+    fused.sort_by(|a, b| b.similarity_score.total_cmp(&a.similarity_score));
+    fused
+}
 
-        // Sort by relevance and take top results
-        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
-        let results = candidates.into_iter()
-            .take(limit)
-            .map(|(_, content)| content)
-            .collect();
+/// Translates a `*` wildcard pattern into a LIKE pattern, escaping LIKE's own wildcards
+fn like_pattern(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('*', "%")
+}
 
-        Ok(results)
+fn matched_fields(
+    parsed: &QueryNode,
+    title: &str,
+    content: &str,
+    filter: &DocumentFilter,
+) -> Vec<String> {
+    let mut fields = Vec::new();
+    if parsed.score(&title.to_lowercase()) > 0.0 {
+        fields.push("title".to_string());
+    }
+    // Content is the default attribution when the match spans both fields
+    if parsed.score(&content.to_lowercase()) > 0.0 || fields.is_empty() {
+        fields.push("content".to_string());
     }
 
-    fn create_chunks(&self, content: &str, doc_id: &str) -> Vec<DocumentChunk> {
-        let mut chunks = Vec::new();
-        let chars: Vec<char> = content.chars().collect();
-        let mut start = 0;
+    let mut keys: Vec<&String> = filter.metadata.keys().collect();
+    keys.sort();
+    fields.extend(keys.into_iter().map(|key| format!("{}{}", METADATA_FILTER_PREFIX, key)));
+    fields.extend(filter.tags.iter().map(|tag| format!("{}{}", TAG_FILTER_PREFIX, normalize_tag(tag))));
+    fields
+}
 
-        while start < chars.len() {
-            let end = std::cmp::min(start + self.chunk_size, chars.len());
-            
-            // Try to break at sentence boundaries
-            let mut actual_end = end;
-            if end < chars.len() {
-                for i in (start + self.chunk_size - 100..end).rev() {
-                    if i < chars.len() && (chars[i] == '.' || chars[i] == '!' || chars[i] == '?') {
-                        actual_end = i + 1;
-                        break;
-                    }
-                }
-            }
+pub const DEFAULT_LANGUAGE: &str = "en";
 
-            let chunk_content: String = chars[start..actual_end].iter().collect();
-            let chunk_id = format!("{}_{}", doc_id, chunks.len());
+/// Metadata key holding the detected (or caller-supplied) document language
+pub const LANGUAGE_METADATA_KEY: &str = "language";
 
-            chunks.push(DocumentChunk {
-                id: chunk_id,
-                document_id: doc_id.to_string(),
-                content: chunk_content.trim().to_string(),
-                start_pos: start,
-                end_pos: actual_end,
-                embedding: None, // Would implement with actual embeddings
-            });
+fn default_stop_words() -> HashMap<String, HashSet<String>> {
+    let sets: [(&str, &[&str]); 7] = [
+        ("en", &[
+            "a", "an", "and", "are", "as", "at", "be", "by", "for", "from",
+            "has", "he", "in", "is", "it", "its", "of", "on", "that", "the",
+            "to", "was", "will", "with",
+        ]),
+        ("es", &[
+            "a", "al", "como", "con", "de", "del", "el", "en", "es", "la", "las", "lo",
+            "los", "más", "no", "o", "para", "pero", "por", "que", "se", "su", "sus",
+            "un", "una", "y",
+        ]),
+        ("fr", &[
+            "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en",
+            "est", "et", "il", "la", "le", "les", "leur", "mais", "ne", "par", "pas",
+            "pour", "qui", "que", "sa", "se", "son", "sur", "un", "une",
+        ]),
+        ("de", &[
+            "aber", "als", "auch", "auf", "aus", "bei", "das", "dem", "den", "der",
+            "des", "die", "ein", "eine", "einem", "einer", "es", "für", "ist", "im",
+            "in", "mit", "nicht", "oder", "sich", "sie", "sind", "und", "von", "zu",
+            "zum", "zur",
+        ]),
+        ("it", &[
+            "a", "al", "alla", "che", "con", "da", "del", "della", "di", "e", "è",
+            "gli", "i", "il", "in", "la", "le", "lo", "ma", "non", "per", "più", "se",
+            "su", "un", "una", "uno",
+        ]),
+        ("pt", &[
+            "a", "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "e", "é",
+            "em", "mas", "na", "no", "nos", "não", "o", "os", "para", "por", "que",
+            "se", "um", "uma",
+        ]),
+        ("nl", &[
+            "aan", "als", "bij", "dat", "de", "den", "die", "een", "en", "het", "in",
+            "is", "maar", "met", "naar", "niet", "of", "om", "ook", "op", "te", "van",
+            "voor", "zijn",
+        ]),
+    ];
 
-            // Move start position with overlap
-            start = if actual_end >= self.overlap_size {
-                actual_end - self.overlap_size
-            } else {
-                actual_end
-            };
+    sets.iter()
+        .map(|(lang, words)| (lang.to_string(), words.iter().map(|w| w.to_string()).collect()))
+        .collect()
+}
 
-            if start >= chars.len() {
-                break;
-            }
+/// Boolean query tree. Adjacent bare terms are joined with OR, so plain
+/// queries keep their historical "match any word" behaviour.
+#[derive(Debug, Clone, PartialEq)]
+enum QueryNode {
+    Term(String),
+    And(Box<QueryNode>, Box<QueryNode>),
+    Or(Box<QueryNode>, Box<QueryNode>),
+    /// Matches the left side unless the right side also matches (FTS5's binary NOT)
+    Not(Box<QueryNode>, Box<QueryNode>),
+}
+
+impl QueryNode {
+    fn to_fts(&self) -> String {
+        match self {
+            QueryNode::Term(term) => format!("\"{}\"", term.replace('"', "\"\"")), // Quote for exact matching
+            QueryNode::And(a, b) => format!("({} AND {})", a.to_fts(), b.to_fts()),
+            QueryNode::Or(a, b) => format!("({} OR {})", a.to_fts(), b.to_fts()),
+            QueryNode::Not(a, b) => format!("({} NOT {})", a.to_fts(), b.to_fts()),
         }
+    }
 
-        chunks
+    /// Approximates the FTS semantics with substring counts over lowercased content
+    fn score(&self, content_lower: &str) -> f64 {
+        match self {
+            QueryNode::Term(term) => content_lower.matches(&term.to_lowercase()).count() as f64,
+            QueryNode::And(a, b) => {
+                let (left, right) = (a.score(content_lower), b.score(content_lower));
+                if left > 0.0 && right > 0.0 { left + right } else { 0.0 }
+            }
+            QueryNode::Or(a, b) => a.score(content_lower) + b.score(content_lower),
+            QueryNode::Not(a, b) => {
+                if b.score(content_lower) > 0.0 { 0.0 } else { a.score(content_lower) }
+            }
+        }
     }
+}
 
-    fn process_query(&self, query: &str) -> String {
-        // Simple query processing - remove stop words and prepare for FTS
-        let words: Vec<String> = query.split_whitespace()
-            .filter(|word| !self.stop_words.contains(&word.to_lowercase()))
-            .map(|word| format!("\"{}\"", word)) // Quote each word for exact matching
-            .collect();
+#[derive(Debug, Clone, PartialEq)]
+enum QueryToken {
+    Word(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
 
-        words.join(" OR ")
-    }
+fn tokenize_query(query: &str) -> Vec<QueryToken> {
+    let mut tokens = Vec::new();
 
-    pub async fn index_void_shrine_knowledge(&mut self) -> Result<()> {
-        // Index some void shrine specific knowledge
-        let void_shrine_docs = vec![
-            Document {
-                id: "void_shrine_principles".to_string(),
-                title: "Void Shrine Core Principles".to_string(),
-                content: "Void-first design emphasizes absence as foundational architectural principle. \
-                         Swarm intelligence enables collective behavior from individual agents. \
-                         Emergence over engineering allows complex behaviors from simple rules. \
-                         Reality bootstrap means implementation creates its own truth through hyperstitious computing.".to_string(),
-                metadata: {
-                    let mut map = HashMap::new();
-                    map.insert("category".to_string(), "philosophy".to_string());
-                    map.insert("source".to_string(), "void_shrine_constitution".to_string());
-                    map
-                },
-                embedding: None,
-                chunks: vec![],
-            },
-            Document {
-                id: "agent_coordination".to_string(),
-                title: "Multi-Agent Coordination Patterns".to_string(),
-                content: "Agent orchestration involves tactical coordination through strategic analysis, \
-                         scientific investigation through pattern recognition, operational implementation through \
-                         systematic execution, and engineering solutions through technical architecture. \
-                         Creative synthesis generates innovative approaches while research provides knowledge synthesis.".to_string(),
-                metadata: {
-                    let mut map = HashMap::new();
-                    map.insert("category".to_string(), "technical".to_string());
-                    map.insert("source".to_string(), "orchestration_manual".to_string());
-                    map
-                },
-                embedding: None,
-                chunks: vec![],
-            },
-            Document {
-                id: "care_ethics".to_string(),
-                title: "Care Ethics Framework".to_string(),
-                content: "Care ethics prioritizes relational wellbeing and stakeholder agency. \
-                         Moral recentering applies care-focused perspectives to technical decisions. \
-                         Ethical considerations include social impact assessment, accessibility priorities, \
-                         and sustainable design principles that center collective flourishing.".to_string(),
-                metadata: {
-                    let mut map = HashMap::new();
-                    map.insert("category".to_string(), "ethics".to_string());
-                    map.insert("source".to_string(), "moral_framework".to_string());
-                    map
-                },
-                embedding: None,
-                chunks: vec![],
-            },
-        ];
+    for raw in query.split_whitespace() {
+        let mut word = raw;
+        while let Some(rest) = word.strip_prefix('(') {
+            tokens.push(QueryToken::Open);
+            word = rest;
+        }
 
-        for doc in void_shrine_docs {
-            self.index_document(doc).await?;
+        let mut closes = 0;
+        while let Some(rest) = word.strip_suffix(')') {
+            closes += 1;
+            word = rest;
         }
 
-        tracing::info!("Indexed void shrine knowledge base");
-        Ok(())
+        // Operators are only recognised in upper case, like FTS5 itself
+        match word {
+            "" => {}
+            "AND" => tokens.push(QueryToken::And),
+            "OR" => tokens.push(QueryToken::Or),
+            "NOT" => tokens.push(QueryToken::Not),
+            _ => tokens.push(QueryToken::Word(word.to_string())),
+        }
+        tokens.extend(std::iter::repeat_n(QueryToken::Close, closes));
     }
 
-    pub async fn get_stats(&self) -> Result<RAGStats> {
-        let mut doc_stmt = self.db.prepare("SELECT COUNT(*) FROM documents")?;
-        doc_stmt.next()?;
-        let doc_count: i64 = doc_stmt.read(0)?;
-
-        let mut chunk_stmt = self.db.prepare("SELECT COUNT(*) FROM chunks")?;
-        chunk_stmt.next()?;
-        let chunk_count: i64 = chunk_stmt.read(0)?;
-
-        Ok(RAGStats {
-            document_count: doc_count as usize,
-            chunk_count: chunk_count as usize,
-            chunk_size: self.chunk_size,
-            overlap_size: self.overlap_size,
-        })
-    }
+    tokens
 }
 
-#[derive(Debug, Serialize)]
-pub struct RAGStats {
-    pub document_count: usize,
-    pub chunk_count: usize,
-    pub chunk_size: usize,
-    pub overlap_size: usize,
+/// Recursive descent parser with FTS5 precedence: NOT binds tighter than AND,
+/// which binds tighter than OR. `Err` means malformed operator usage; `Ok(None)`
+/// means every term was a stop word.
+struct QueryParser<'a> {
+    tokens: &'a [QueryToken],
+    pos: usize,
+    stop_words: &'a HashSet<String>,
 }
 
-// Binary for running RAG engine standalone
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::init();
-    
-    let mut rag = RAGEngine::new().await?;
-    
-    // Index void shrine knowledge
-    rag.index_void_shrine_knowledge().await?;
-    
-    // Test queries
-    let test_queries = vec![
-        "What are the core principles of void shrine?",
-        "How do agents coordinate?",
-        "What is care ethics?",
-        "Explain emergence over engineering",
-    ];
+impl QueryParser<'_> {
+    fn parse(&mut self) -> std::result::Result<Option<QueryNode>, ()> {
+        let node = self.parse_or()?;
+        if self.pos < self.tokens.len() {
+            return Err(()); // Unbalanced closing parenthesis
+        }
+        Ok(node)
+    }
 
-    tracing::info!("🔍 Testing RAG Engine:");
-    
-    for query in test_queries {
-        println!("\n🔍 Query: {}", query);
-        let results = rag.query(query, 3).await?;
-        
-        for (i, result) in results.iter().enumerate() {
-            println!("  {}. {}", i + 1, result);
+    fn parse_or(&mut self) -> std::result::Result<Option<QueryNode>, ()> {
+        let mut node = self.parse_and()?;
+        loop {
+            match self.tokens.get(self.pos) {
+                Some(QueryToken::Or) => self.pos += 1,
+                Some(QueryToken::Word(_)) | Some(QueryToken::Open) => {} // Implicit OR
+                _ => return Ok(node),
+            }
+            let rhs = self.parse_and()?;
+            node = combine(node, rhs, QueryNode::Or);
         }
     }
 
-    let stats = rag.get_stats().await?;
-    println!("\n📊 RAG Stats: {:#?}", stats);
+    fn parse_and(&mut self) -> std::result::Result<Option<QueryNode>, ()> {
+        let mut node = self.parse_not()?;
+        while self.tokens.get(self.pos) == Some(&QueryToken::And) {
+            self.pos += 1;
+            let rhs = self.parse_not()?;
+            node = combine(node, rhs, QueryNode::And);
+        }
+        Ok(node)
+    }
 
-    Ok(())
-}
\ No newline at end of file
+    fn parse_not(&mut self) -> std::result::Result<Option<QueryNode>, ()> {
+        let mut node = self.parse_primary()?;
+        while self.tokens.get(self.pos) == Some(&QueryToken::Not) {
+            self.pos += 1;
+            let excluded = self.parse_primary()?;
+            node = match (node, excluded) {
+                (Some(a), Some(b)) => Some(QueryNode::Not(Box::new(a), Box::new(b))),
+                (node, _) => node,
+            };
+        }
+        Ok(node)
+    }
+
+    fn parse_primary(&mut self) -> std::result::Result<Option<QueryNode>, ()> {
+        match self.tokens.get(self.pos) {
+            Some(QueryToken::Word(word)) => {
+                self.pos += 1;
+                if self.stop_words.contains(&word.to_lowercase()) {
+                    Ok(None)
+                } else {
+                    Ok(Some(QueryNode::Term(word.clone())))
+                }
+            }
+            Some(QueryToken::Open) => {
+                self.pos += 1;
+                let node = self.parse_or()?;
+                if self.tokens.get(self.pos) != Some(&QueryToken::Close) {
+                    return Err(());
+                }
+                self.pos += 1;
+                Ok(node)
+            }
+            _ => Err(()), // Dangling operator, empty group or end of input
+        }
+    }
+}
+
+fn combine(
+    a: Option<QueryNode>,
+    b: Option<QueryNode>,
+    op: fn(Box<QueryNode>, Box<QueryNode>) -> QueryNode,
+) -> Option<QueryNode> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(op(Box::new(a), Box::new(b))),
+        (a, b) => a.or(b),
+    }
+}
+
+pub struct RAGEngine {
+    db: ConnectionThreadSafe,
+    /// The database file, None in memory
+    path: Option<PathBuf>,
+    /// How long a second connection to `path` waits out this one's locks
+    busy_timeout: std::time::Duration,
+    chunk_size: usize,
+    overlap_size: usize,
+    /// FTS5 tokenize option chunks_fts was created with
+    tokenizer: String,
+    normalization: Normalization,
+    /// Worker threads used to build chunks for large documents
+    chunk_parallelism: usize,
+    /// bm25 weight of the title column relative to chunk content
+    title_boost: f64,
+    fetch_config: FetchConfig,
+    stop_words: HashMap<String, HashSet<String>>,
+    /// Query logging is opt-in; None records nothing
+    query_log: Option<QueryLogConfig>,
+    /// Held while a search writes its log entry: searches share the connection
+    /// under a read lock, and SQLite allows one transaction per connection
+    query_log_writer: std::sync::Mutex<()>,
+    summarizer: Option<Arc<dyn Summarizer>>,
+    validation: ValidationLimits,
+    embedder: Option<Arc<dyn EmbeddingProvider>>,
+    ranking: RankingConfig,
+    /// See `generation`
+    generation: u64,
+    fts_rebuild_policy: FtsRebuildPolicy,
+    /// Set while chunks_fts is being rebuilt
+    fts_rebuild: Option<FtsRebuildCursor>,
+}
+
+/// Source of engine generations, shared so a replaced engine never repeats one
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Writes the short per-document summary stored at index time, typically by
+/// prompting an LLM. Implementations should keep it to two or three sentences.
+pub trait Summarizer: Send + Sync {
+    fn summarize<'a>(&'a self, title: &'a str, content: &'a str) -> BoxFuture<'a, Result<String>>;
+}
+
+/// Summarizes with the document's opening sentences, for setups without an LLM
+pub struct LeadSentencesSummarizer {
+    pub sentences: usize,
+}
+
+impl Summarizer for LeadSentencesSummarizer {
+    fn summarize<'a>(&'a self, _title: &'a str, content: &'a str) -> BoxFuture<'a, Result<String>> {
+        let summary = split_sentences(content).into_iter().take(self.sentences).collect::<Vec<_>>().join(" ");
+        Box::pin(async move { Ok(summary) })
+    }
+}
+
+/// Content beyond this many chars is not sent to the summarizer
+const SUMMARY_INPUT_CHARS: usize = 8000;
+
+/// Turns chunk and query text into vectors for `semantic_search`. Every vector
+/// must have `dimension()` entries; vectors from different models are never compared.
+pub trait EmbeddingProvider: Send + Sync {
+    fn model(&self) -> &str;
+    fn dimension(&self) -> usize;
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>>;
+}
+
+/// Model that produced the stored chunk embeddings, as recorded in the meta table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingModel {
+    pub model: String,
+    pub dimension: usize,
+}
+
+impl std::fmt::Display for EmbeddingModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' ({} dimensions)", self.model, self.dimension)
+    }
+}
+
+/// The configured embedding provider cannot be compared with the stored vectors
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddingMismatch {
+    /// None once embeddings were cleared
+    pub stored: Option<EmbeddingModel>,
+    pub configured: EmbeddingModel,
+}
+
+impl std::fmt::Display for EmbeddingMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.stored {
+            Some(stored) => write!(
+                f,
+                "embedding model mismatch, reindex required: stored vectors are from {} but {} is configured \
+                 (call re_embed_all)",
+                stored, self.configured
+            ),
+            None => write!(
+                f,
+                "embedding model mismatch, reindex required: no embeddings are stored for {} (call re_embed_all)",
+                self.configured
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EmbeddingMismatch {}
+
+/// Chunks, or texts, embedded per provider call by `re_embed_all` and `embed`
+const EMBEDDING_BATCH_CHUNKS: usize = 64;
+
+const NORMALIZATION_META_KEY: &str = "normalization";
+
+/// `<after>/<until>` chunk rowids while chunks_fts is being rebuilt: the last
+/// chunk indexed so far and the last chunk there was when the rebuild began
+const FTS_REBUILD_META_KEY: &str = "fts_rebuild";
+
+const EMBEDDING_MODEL_META_KEY: &str = "embedding_model";
+const EMBEDDING_DIMENSION_META_KEY: &str = "embedding_dimension";
+
+pub const DEFAULT_TITLE_BOOST: f64 = 2.0;
+
+/// SQLite journal modes accepted by `SqliteTuning`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Wal,
+}
+
+/// SQLite `synchronous` levels accepted by `SqliteTuning`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+}
+
+/// Pragmas applied when a file-backed index is opened. In-memory indexes ignore them.
+#[derive(Debug, Clone)]
+pub struct SqliteTuning {
+    /// WAL (the default) lets queries run while a write is in progress and survives
+    /// crashes without a rollback journal, at the cost of `-wal`/`-shm` side files
+    /// that must be kept next to the database and that network filesystems handle
+    /// poorly. Persisted in the file, so it also applies to other openers.
+    pub journal_mode: JournalMode,
+    /// NORMAL (the default) is crash-safe under WAL but may lose the last commits on
+    /// power loss; FULL also survives power loss at the cost of an fsync per commit.
+    pub synchronous: Synchronous,
+    /// How long a locked database is retried before a write fails with SQLITE_BUSY
+    pub busy_timeout: std::time::Duration,
+    /// Page cache per connection. Larger caches speed up repeated searches over big indexes.
+    pub cache_size_kib: u32,
+    /// Only takes effect when the database file is created; existing files keep their page size
+    pub page_size: u32,
+}
+
+impl Default for SqliteTuning {
+    fn default() -> Self {
+        Self {
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            busy_timeout: std::time::Duration::from_secs(5),
+            cache_size_kib: 16 * 1024,
+            page_size: 4096,
+        }
+    }
+}
+
+impl SqliteTuning {
+    fn apply(&self, db: &Connection) -> Result<()> {
+        let journal_mode = match self.journal_mode {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Wal => "WAL",
+        };
+        let synchronous = match self.synchronous {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+        };
+
+        // page_size must precede the journal mode: it cannot change once WAL is on
+        db.execute(format!(
+            "PRAGMA page_size = {};
+             PRAGMA journal_mode = {};
+             PRAGMA synchronous = {};
+             PRAGMA busy_timeout = {};
+             PRAGMA cache_size = -{};",
+            self.page_size,
+            journal_mode,
+            synchronous,
+            self.busy_timeout.as_millis(),
+            self.cache_size_kib
+        ))?;
+        Ok(())
+    }
+}
+
+/// Outcome of `RAGEngine::checkpoint`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointStats {
+    /// True when readers or writers prevented a complete checkpoint
+    pub busy: bool,
+    /// Frames in the WAL before the checkpoint, -1 outside WAL mode
+    pub log_frames: i64,
+    pub checkpointed_frames: i64,
+}
+
+/// Outcome of `RAGEngine::backup_to`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupReport {
+    pub path: PathBuf,
+    pub pages: u64,
+    pub page_size: u64,
+    pub bytes: u64,
+    pub duration_ms: u64,
+}
+
+const ORPHANED_CHUNKS: &str = "FROM chunks WHERE document_id NOT IN (SELECT id FROM documents)";
+const ORPHANED_FTS_ROWS: &str = "FROM chunks_fts WHERE chunk_id NOT IN (SELECT id FROM chunks)";
+const UNINDEXED_CHUNKS: &str = "FROM chunks c WHERE c.id NOT IN (SELECT chunk_id FROM chunks_fts)";
+const ORPHANED_TAGS: &str = "FROM document_tags WHERE document_id NOT IN (SELECT id FROM documents)";
+
+/// Outcome of `RAGEngine::maintenance`. Counts are what was found; `repaired`
+/// says whether they were also fixed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    /// None when FTS5's integrity-check passed, otherwise its error
+    pub fts_integrity_error: Option<String>,
+    /// Chunks whose document no longer exists
+    pub orphaned_chunks: usize,
+    /// FTS rows whose chunk no longer exists
+    pub orphaned_fts_rows: usize,
+    /// Chunks that are missing from the FTS index and so can never match a search
+    pub unindexed_chunks: usize,
+    /// Tags of documents that no longer exist
+    pub orphaned_tags: usize,
+    pub repaired: bool,
+    /// Documents whose keywords were recomputed against the current corpus
+    pub keywords_refreshed: usize,
+    pub duration_ms: u64,
+}
+
+/// Retention for the query log enabled by `RAGEngineBuilder::query_log`
+#[derive(Debug, Clone)]
+pub struct QueryLogConfig {
+    /// Entries older than this are pruned
+    pub retention: chrono::Duration,
+    /// Only the newest entries are kept beyond this count
+    pub max_entries: usize,
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            retention: chrono::Duration::days(30),
+            max_entries: 100_000,
+        }
+    }
+}
+
+/// Adjustments applied to base text relevance before results are cut to the limit
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RankingConfig {
+    /// Scores halve for every this many days since a document was indexed.
+    /// Documents without an index time are not decayed.
+    #[serde(default)]
+    pub recency_half_life_days: Option<f64>,
+    /// Metadata key -> value -> score multiplier
+    #[serde(default)]
+    pub metadata_boosts: HashMap<String, HashMap<String, f64>>,
+    /// Tag -> score multiplier
+    #[serde(default)]
+    pub tag_boosts: HashMap<String, f64>,
+}
+
+impl RankingConfig {
+    fn is_neutral(&self) -> bool {
+        self.recency_half_life_days.is_none() && self.metadata_boosts.is_empty() && self.tag_boosts.is_empty()
+    }
+
+    fn multiplier(&self, metadata: &HashMap<String, String>, tags: &[String], age_days: Option<f64>) -> f64 {
+        let mut multiplier = 1.0;
+        // A non-positive half-life disables decay rather than producing NaN scores
+        if let (Some(half_life), Some(age)) = (self.recency_half_life_days.filter(|h| *h > 0.0), age_days) {
+            multiplier *= 0.5f64.powf(age.max(0.0) / half_life);
+        }
+        for (key, boosts) in &self.metadata_boosts {
+            if let Some(boost) = metadata.get(key).and_then(|value| boosts.get(value)) {
+                multiplier *= boost;
+            }
+        }
+        for (tag, boost) in &self.tag_boosts {
+            if tags.contains(&normalize_tag(tag)) {
+                multiplier *= boost;
+            }
+        }
+        multiplier
+    }
+}
+
+/// How often a query (compared case-insensitively) was made in the analytics window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryCount {
+    pub query: String,
+    pub count: usize,
+    pub avg_result_count: f64,
+    pub avg_latency_ms: f64,
+}
+
+/// How often a document appeared in logged query results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentRetrievals {
+    pub document_id: String,
+    pub title: String,
+    pub retrievals: usize,
+}
+
+/// Aggregates over the query log between `since` and `until`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryAnalytics {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub total_queries: usize,
+    pub zero_result_count: usize,
+    pub top_queries: Vec<QueryCount>,
+    pub zero_result_queries: Vec<QueryCount>,
+    pub most_retrieved: Vec<DocumentRetrievals>,
+    /// Indexed documents retrieved least often, including those never retrieved
+    pub least_retrieved: Vec<DocumentRetrievals>,
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// HTTP settings used by `RAGEngine::index_url`
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    pub timeout: std::time::Duration,
+    pub user_agent: String,
+    pub max_redirects: usize,
+    pub max_content_bytes: usize,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            timeout: std::time::Duration::from_secs(15),
+            user_agent: format!("void-shrine-rag/{}", env!("CARGO_PKG_VERSION")),
+            max_redirects: 5,
+            max_content_bytes: 2 * 1024 * 1024,
+        }
+    }
+}
+
+/// Why a page could not be fetched for indexing
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchError {
+    Status(u16),
+    TooManyRedirects(usize),
+    UnsupportedContentType(String),
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Status(status) => write!(f, "server responded with HTTP {}", status),
+            FetchError::TooManyRedirects(limit) => write!(f, "more than {} redirects", limit),
+            FetchError::UnsupportedContentType(content_type) => {
+                write!(f, "unsupported content type '{}', expected HTML", content_type)
+            }
+            FetchError::TooLarge(limit) => write!(f, "page exceeds the {} byte limit", limit),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+/// Bounds enforced on documents before they are indexed
+#[derive(Debug, Clone)]
+pub struct ValidationLimits {
+    pub max_content_bytes: usize,
+    pub max_id_chars: usize,
+    pub max_metadata_entries: usize,
+    /// Combined size of all metadata keys and values
+    pub max_metadata_bytes: usize,
+}
+
+impl Default for ValidationLimits {
+    fn default() -> Self {
+        Self {
+            max_content_bytes: 32 * 1024 * 1024,
+            max_id_chars: 256,
+            max_metadata_entries: 64,
+            max_metadata_bytes: 16 * 1024,
+        }
+    }
+}
+
+/// Why a document was refused by `index_document` or `index_reader`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    EmptyId,
+    /// Ids may only use ASCII letters, digits, `_`, `-`, `.` and `:` so they stay
+    /// safe in chunk ids and URL paths
+    InvalidId(String),
+    IdTooLong { chars: usize, max: usize },
+    EmptyContent,
+    ContentTooLarge { bytes: usize, max: usize },
+    TooManyMetadataEntries { entries: usize, max: usize },
+    MetadataTooLarge { bytes: usize, max: usize },
+}
+
+impl ValidationError {
+    /// Stable machine-readable identifier for API clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::EmptyId => "empty_id",
+            ValidationError::InvalidId(_) => "invalid_id",
+            ValidationError::IdTooLong { .. } => "id_too_long",
+            ValidationError::EmptyContent => "empty_content",
+            ValidationError::ContentTooLarge { .. } => "content_too_large",
+            ValidationError::TooManyMetadataEntries { .. } => "too_many_metadata_entries",
+            ValidationError::MetadataTooLarge { .. } => "metadata_too_large",
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::EmptyId => write!(f, "document id must not be empty"),
+            ValidationError::InvalidId(id) => write!(
+                f,
+                "document id '{}' may only contain ASCII letters, digits, '_', '-', '.' and ':'",
+                id.escape_debug()
+            ),
+            ValidationError::IdTooLong { chars, max } => {
+                write!(f, "document id has {} characters, the limit is {}", chars, max)
+            }
+            ValidationError::EmptyContent => write!(f, "document content must not be empty"),
+            ValidationError::ContentTooLarge { bytes, max } => {
+                write!(f, "document content is {} bytes, the limit is {}", bytes, max)
+            }
+            ValidationError::TooManyMetadataEntries { entries, max } => {
+                write!(f, "document has {} metadata entries, the limit is {}", entries, max)
+            }
+            ValidationError::MetadataTooLarge { bytes, max } => {
+                write!(f, "document metadata is {} bytes, the limit is {}", bytes, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl ValidationLimits {
+    fn check_id(&self, id: &str) -> std::result::Result<(), ValidationError> {
+        if id.is_empty() {
+            return Err(ValidationError::EmptyId);
+        }
+        let chars = id.chars().count();
+        if chars > self.max_id_chars {
+            return Err(ValidationError::IdTooLong { chars, max: self.max_id_chars });
+        }
+        if !id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')) {
+            return Err(ValidationError::InvalidId(id.to_string()));
+        }
+        Ok(())
+    }
+
+    fn check_metadata(&self, metadata: &HashMap<String, String>) -> std::result::Result<(), ValidationError> {
+        if metadata.len() > self.max_metadata_entries {
+            return Err(ValidationError::TooManyMetadataEntries {
+                entries: metadata.len(),
+                max: self.max_metadata_entries,
+            });
+        }
+        let bytes: usize = metadata.iter().map(|(key, value)| key.len() + value.len()).sum();
+        if bytes > self.max_metadata_bytes {
+            return Err(ValidationError::MetadataTooLarge { bytes, max: self.max_metadata_bytes });
+        }
+        Ok(())
+    }
+
+    fn check_document(&self, document: &Document) -> std::result::Result<(), ValidationError> {
+        self.check_id(&document.id)?;
+        if document.content.trim().is_empty() {
+            return Err(ValidationError::EmptyContent);
+        }
+        if document.content.len() > self.max_content_bytes {
+            return Err(ValidationError::ContentTooLarge {
+                bytes: document.content.len(),
+                max: self.max_content_bytes,
+            });
+        }
+        self.check_metadata(&document.metadata)
+    }
+}
+
+fn provider_model(provider: &dyn EmbeddingProvider) -> EmbeddingModel {
+    EmbeddingModel { model: provider.model().to_string(), dimension: provider.dimension() }
+}
+
+/// Embeds `texts`, checking the provider kept its promises on count and dimension
+async fn embed_checked(provider: &dyn EmbeddingProvider, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let vectors = provider.embed(texts).await?;
+    if vectors.len() != texts.len() {
+        anyhow::bail!("embedding provider returned {} vectors for {} texts", vectors.len(), texts.len());
+    }
+    for vector in &vectors {
+        check_dimension("(new)", vector.len(), Some(provider.dimension()))?;
+    }
+    Ok(vectors)
+}
+
+fn check_dimension(chunk_id: &str, actual: usize, expected: Option<usize>) -> Result<()> {
+    match expected {
+        Some(expected) if expected == actual => Ok(()),
+        Some(expected) => anyhow::bail!(
+            "embedding for chunk {} has {} dimensions, the registered model has {}",
+            chunk_id, actual, expected
+        ),
+        None => anyhow::bail!("embedding for chunk {} has no registered model", chunk_id),
+    }
+}
+
+/// Embeddings are stored as little-endian f32s
+fn encode_embedding(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 { 0.0 } else { dot / denominator }
+}
+
+/// Reduces an HTML page to its `<title>` and readable body text
+pub fn html_to_text(html: &str) -> (Option<String>, String) {
+    let title = Regex::new(r"(?is)<title[^>]*>(.*?)</title>")
+        .unwrap()
+        .captures(html)
+        .map(|c| decode_entities(c[1].trim()))
+        .filter(|t| !t.is_empty());
+
+    let without_hidden = Regex::new(r"(?is)<(script|style|noscript|head|template)\b.*?</(script|style|noscript|head|template)>")
+        .unwrap()
+        .replace_all(html, " ");
+    let without_comments = Regex::new(r"(?s)<!--.*?-->").unwrap().replace_all(&without_hidden, " ");
+    // Block-level tags become line breaks so sentences from different elements don't run together
+    let with_breaks = Regex::new(r"(?i)</?(p|div|br|li|h[1-6]|tr|section|article)\b[^>]*>")
+        .unwrap()
+        .replace_all(&without_comments, "\n");
+    let without_tags = Regex::new(r"(?s)<[^>]*>").unwrap().replace_all(&with_breaks, " ");
+
+    let text = decode_entities(&without_tags)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (title, text)
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Terms in the representative query built by `similar_documents`
+const SIMILAR_QUERY_TERMS: usize = 12;
+
+/// Chunks of the source document read by `similar_documents`, bounding work on huge documents
+const SIMILAR_SOURCE_CHUNKS: usize = 50;
+
+/// Keywords stored per document
+const KEYWORDS_PER_DOCUMENT: usize = 10;
+
+/// Most frequent terms that are weighted by IDF when picking keywords
+const KEYWORD_CANDIDATES: usize = 100;
+
+/// Counts the terms of `text` worth searching for: no stop words, numbers or words
+/// under three characters
+fn term_frequencies(text: &str, stop_words: &HashSet<String>) -> HashMap<String, usize> {
+    let mut frequencies = HashMap::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.chars().count() < 3 || word.chars().all(|c| c.is_numeric()) || stop_words.contains(&word) {
+            continue;
+        }
+        *frequencies.entry(word).or_default() += 1;
+    }
+    frequencies
+}
+
+/// Terms by descending count, ties alphabetical so results are stable
+fn most_frequent(frequencies: HashMap<String, usize>, limit: usize) -> Vec<(String, usize)> {
+    let mut terms: Vec<(String, usize)> = frequencies.into_iter().collect();
+    terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    terms.truncate(limit);
+    terms
+}
+
+/// Chunks considered when picking extractive answer sentences
+const ANSWER_CANDIDATE_CHUNKS: usize = 10;
+
+/// Sentences with fewer words than this are never returned as answers
+const MIN_ANSWER_WORDS: usize = 4;
+
+/// Splits text after `.`, `!` or `?` when followed by whitespace or the end of input
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = matches!(c, '.' | '!' | '?')
+            && chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if at_boundary {
+            let end = i + c.len_utf8();
+            let sentence = text[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+    }
+
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+    sentences
+}
+
+/// Stable document id for a file so reindexing replaces the previous version
+pub fn file_document_id(path: &Path) -> String {
+    source_document_id("file", &path.to_string_lossy())
+}
+
+/// Longest slug kept in a file or URL document id, leaving room within the
+/// default `max_id_chars` for the prefix and the hash
+const SOURCE_SLUG_CHARS: usize = 200;
+
+/// `prefix`, a readable slug of `source` cut to `SOURCE_SLUG_CHARS`, and a
+/// hash of the whole of `source`, which tells apart sources whose slugs
+/// are the same (`/a-b` and `/a_b`, or two long paths sharing a start)
+fn source_document_id(prefix: &str, source: &str) -> String {
+    let slug: String = source.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    let slug: String = slug.trim_matches('_').chars().take(SOURCE_SLUG_CHARS).collect();
+    let mut hasher = ContentHasher::new(prefix);
+    hasher.update(source.as_bytes());
+    format!("{}_{}_{}", prefix, slug.trim_end_matches('_'), hasher.finish())
+}
+
+/// Metadata key holding a hash of title and content, used to skip unchanged reindexes
+pub const CONTENT_HASH_METADATA_KEY: &str = "content_hash";
+
+/// FNV-1a, chosen because it is stable across Rust versions unlike `DefaultHasher`
+fn content_hash(title: &str, content: &str) -> String {
+    let mut hasher = ContentHasher::new(title);
+    hasher.update(content.as_bytes());
+    hasher.finish()
+}
+
+/// Incremental form of `content_hash` for content that arrives in pieces
+struct ContentHasher(u64);
+
+impl ContentHasher {
+    fn new(title: &str) -> Self {
+        let mut hasher = ContentHasher(0xcbf29ce484222325);
+        hasher.update(title.as_bytes());
+        hasher.update(&[0]);
+        hasher
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// Chunks written per transaction by `index_reader`
+const STREAM_BATCH_CHUNKS: usize = 64;
+
+/// Prefixes the id a streamed document is written under until it is
+/// complete; `/` keeps it apart from every valid document id
+const STAGING_ID_PREFIX: &str = "staging/";
+
+/// Positions refer to the original text; only the stored content is normalized
+fn make_chunk(
+    doc_id: &str,
+    index: usize,
+    chars: &[char],
+    (start, end): (usize, usize),
+    normalization: Normalization,
+) -> DocumentChunk {
+    let chunk_content: String = chars.iter().collect();
+    DocumentChunk {
+        id: format!("{}_{}", doc_id, index),
+        document_id: doc_id.to_string(),
+        content: normalization.apply(&chunk_content).trim().to_string(),
+        start_pos: start,
+        end_pos: end,
+        embedding: None, // Would implement with actual embeddings
+    }
+}
+
+/// Pulls the reader's next buffer into `window`, carrying a UTF-8 sequence split
+/// across reads over to the next call. Returns the bytes read, 0 at end of input.
+fn read_chars(
+    reader: &mut impl BufRead,
+    pending: &mut Vec<u8>,
+    window: &mut Vec<char>,
+    hasher: &mut ContentHasher,
+) -> Result<usize> {
+    let buf = loop {
+        match reader.fill_buf() {
+            Ok(buf) => break buf,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    if buf.is_empty() {
+        if !pending.is_empty() {
+            anyhow::bail!("Input ended inside a UTF-8 sequence");
+        }
+        return Ok(0);
+    }
+
+    let read = buf.len();
+    hasher.update(buf);
+    pending.extend_from_slice(buf);
+    reader.consume(read);
+
+    let valid = match std::str::from_utf8(pending) {
+        Ok(text) => text.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(e) => anyhow::bail!("Input is not valid UTF-8: {}", e),
+    };
+    window.extend(std::str::from_utf8(&pending[..valid])?.chars());
+    pending.drain(..valid);
+    Ok(read)
+}
+
+fn add_column_if_missing(db: &Connection, table: &str, column: &str, declaration: &str) -> Result<()> {
+    let mut stmt = db.prepare(format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?", table))?;
+    stmt.bind((1, column))?;
+    if matches!(stmt.next()?, State::Done) {
+        db.execute(format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, declaration))?;
+    }
+    Ok(())
+}
+
+/// Whether the linked SQLite has the FTS5 module keyword search needs,
+/// tried on a scratch database
+pub fn fts5_available() -> Result<()> {
+    let db = Connection::open(":memory:")?;
+    db.execute("CREATE VIRTUAL TABLE probe USING fts5(content)")
+        .map_err(|e| anyhow::anyhow!("the linked SQLite (version {}) lacks FTS5: {}", sqlite::version(), e))
+}
+
+/// Stable document id for a URL so re-fetching replaces the previous version
+fn url_document_id(url: &str) -> String {
+    source_document_id("url", url)
+}
+
+/// FTS5 tokenizer used when none is configured or recorded
+pub const DEFAULT_TOKENIZER: &str = "unicode61";
+
+/// Chunks copied into chunks_fts per transaction of a rebuild
+pub const FTS_REBUILD_BATCH_CHUNKS: usize = 500;
+
+/// What keyword searches do while chunks_fts is rebuilt and holds only some chunks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FtsRebuildPolicy {
+    /// Match chunk text directly, as when the index finds nothing
+    #[default]
+    Fallback,
+    /// Fail with `FtsRebuilding`
+    Refuse,
+}
+
+/// A keyword search refused while chunks_fts is rebuilt, under `FtsRebuildPolicy::Refuse`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FtsRebuilding {
+    pub processed: usize,
+    pub total: usize,
+}
+
+impl std::fmt::Display for FtsRebuilding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Full-text index is rebuilding ({} of {} chunks); retry once it finishes", self.processed, self.total)
+    }
+}
+
+impl std::error::Error for FtsRebuilding {}
+
+/// How far a rebuild of chunks_fts has got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FtsRebuildProgress {
+    /// Chunks copied into the index so far
+    pub processed: usize,
+    /// Chunks there were when the rebuild began; those indexed since are
+    /// added to the index as they are written
+    pub total: usize,
+    pub done: bool,
+}
+
+/// Where a rebuild of chunks_fts is, as kept under `FTS_REBUILD_META_KEY`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FtsRebuildCursor {
+    after: i64,
+    until: i64,
+    processed: usize,
+    total: usize,
+}
+
+/// Unicode normalization applied to chunk text, FTS titles and queries so that
+/// equivalent spellings match. Both normalizing modes also straighten curly
+/// quotes and turn non-breaking spaces into plain spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    /// Text is indexed as given; what indexes from before normalization contain
+    None,
+    /// Canonical composition: composed and decomposed accents match
+    Nfc,
+    /// Compatibility composition: additionally folds full-width and other
+    /// compatibility forms, e.g. `ｅｎｔｒｏｐｙ` to `entropy`
+    Nfkc,
+}
+
+impl Normalization {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Normalization::None => "none",
+            Normalization::Nfc => "nfc",
+            Normalization::Nfkc => "nfkc",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "none" => Some(Normalization::None),
+            "nfc" => Some(Normalization::Nfc),
+            "nfkc" => Some(Normalization::Nfkc),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            // ASCII is unchanged by every mode
+            _ if text.is_ascii() => text.to_string(),
+            Normalization::None => text.to_string(),
+            Normalization::Nfc => text.nfc().map(fold_punctuation).collect(),
+            Normalization::Nfkc => text.nfkc().map(fold_punctuation).collect(),
+        }
+    }
+}
+
+fn fold_punctuation(c: char) -> char {
+    match c {
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => '\'',
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => '"',
+        '\u{00A0}' | '\u{2007}' | '\u{202F}' => ' ',
+        _ => c,
+    }
+}
+
+/// The index on disk was normalized differently than configured
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizationMismatch {
+    pub stored: Normalization,
+    pub configured: Normalization,
+}
+
+impl std::fmt::Display for NormalizationMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reindex required: the index text was normalized with '{}' but '{}' is configured \
+             (open with reindex_on_mismatch to renormalize it)",
+            self.stored.as_str(), self.configured.as_str()
+        )
+    }
+}
+
+impl std::error::Error for NormalizationMismatch {}
+
+/// The FTS index on disk was built with a different tokenizer than the one configured
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenizerMismatch {
+    pub stored: String,
+    pub configured: String,
+}
+
+impl std::fmt::Display for TokenizerMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reindex required: the FTS index was built with tokenizer '{}' but '{}' is configured \
+             (open with reindex_on_mismatch or call rebuild_fts)",
+            self.stored, self.configured
+        )
+    }
+}
+
+impl std::error::Error for TokenizerMismatch {}
+
+pub struct RAGEngineBuilder {
+    path: Option<PathBuf>,
+    tokenizer: Option<String>,
+    normalization: Option<Normalization>,
+    reindex_on_mismatch: bool,
+    chunk_size: usize,
+    overlap_size: usize,
+    chunk_parallelism: usize,
+    query_log: Option<QueryLogConfig>,
+    sqlite_tuning: SqliteTuning,
+    summarizer: Option<Arc<dyn Summarizer>>,
+    validation: ValidationLimits,
+    embedder: Option<Arc<dyn EmbeddingProvider>>,
+    ranking: RankingConfig,
+    fts_rebuild_policy: FtsRebuildPolicy,
+}
+
+/// Candidates fetched per requested result when results are reranked or deduplicated
+const RERANK_CANDIDATE_FACTOR: usize = 3;
+
+/// Share of the shorter chunk two results must have in common to count as duplicates
+const DUPLICATE_OVERLAP_RATIO: f64 = 0.5;
+
+/// Documents with fewer chunks than this are always chunked on the calling thread
+const PARALLEL_CHUNK_THRESHOLD: usize = 256;
+
+impl Default for RAGEngineBuilder {
+    fn default() -> Self {
+        Self {
+            path: None,
+            tokenizer: None,
+            normalization: None,
+            reindex_on_mismatch: false,
+            chunk_size: 512,
+            overlap_size: 64,
+            chunk_parallelism: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            query_log: None,
+            sqlite_tuning: SqliteTuning::default(),
+            summarizer: None,
+            validation: ValidationLimits::default(),
+            embedder: None,
+            ranking: RankingConfig::default(),
+            fts_rebuild_policy: FtsRebuildPolicy::default(),
+        }
+    }
+}
+
+impl RAGEngineBuilder {
+    /// Stores the index in a SQLite file instead of memory
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// FTS5 `tokenize` option, e.g. `porter unicode61`, `unicode61 remove_diacritics 2`
+    /// or `trigram`. Defaults to whatever an existing index was built with.
+    pub fn tokenizer(mut self, tokenizer: impl Into<String>) -> Self {
+        self.tokenizer = Some(tokenizer.into());
+        self
+    }
+
+    /// Unicode normalization of indexed text and queries. New indexes default to
+    /// NFC; existing ones to whatever they were built with.
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = Some(normalization);
+        self
+    }
+
+    /// Rebuild the FTS table instead of failing when an existing index used another
+    /// tokenizer or normalization
+    pub fn reindex_on_mismatch(mut self, reindex: bool) -> Self {
+        self.reindex_on_mismatch = reindex;
+        self
+    }
+
+    /// What keyword searches do while `rebuild_fts_batch` refills the index
+    pub fn during_fts_rebuild(mut self, policy: FtsRebuildPolicy) -> Self {
+        self.fts_rebuild_policy = policy;
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn overlap_size(mut self, overlap_size: usize) -> Self {
+        self.overlap_size = overlap_size;
+        self
+    }
+
+    /// Threads used to build chunks of large documents; 1 keeps chunking serial.
+    /// Database writes are always serialized in a single transaction.
+    pub fn chunk_parallelism(mut self, workers: usize) -> Self {
+        self.chunk_parallelism = workers;
+        self
+    }
+
+    /// Overrides the pragmas used for file-backed indexes; see `SqliteTuning` for the tradeoffs
+    pub fn sqlite_tuning(mut self, tuning: SqliteTuning) -> Self {
+        self.sqlite_tuning = tuning;
+        self
+    }
+
+    /// Stores a summary of each document indexed with `index_document`. Streamed
+    /// documents are never summarized since their content is not kept in memory.
+    pub fn summarizer(mut self, summarizer: Arc<dyn Summarizer>) -> Self {
+        self.summarizer = Some(summarizer);
+        self
+    }
+
+    /// Embeds chunks at index time and enables `semantic_search`. A fresh index
+    /// records this provider's model; an index holding vectors from another model
+    /// refuses semantic queries until `re_embed_all` migrates it.
+    pub fn embedding_provider(mut self, provider: Arc<dyn EmbeddingProvider>) -> Self {
+        self.embedder = Some(provider);
+        self
+    }
+
+    /// Recency decay and metadata/tag boosts applied to search scores
+    pub fn ranking(mut self, config: RankingConfig) -> Self {
+        self.ranking = config;
+        self
+    }
+
+    /// Overrides the size limits documents are validated against before indexing
+    pub fn validation_limits(mut self, limits: ValidationLimits) -> Self {
+        self.validation = limits;
+        self
+    }
+
+    /// Records every search in the query log for `query_analytics`
+    pub fn query_log(mut self, config: QueryLogConfig) -> Self {
+        self.query_log = Some(config);
+        self
+    }
+
+    pub async fn build(self) -> Result<RAGEngine> {
+        let db = match &self.path {
+            Some(path) => {
+                let db = Connection::open_thread_safe(path)?;
+                self.sqlite_tuning.apply(&db)?;
+                db
+            }
+            None => Connection::open_thread_safe(":memory:")?,
+        };
+        
+        // Initialize database schema
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS documents (
+                id TEXT PRIMARY KEY,
+                title TEXT,
+                content TEXT,
+                metadata TEXT
+            )"
+        )?;
+
+        // Added after the original schema, so older databases gain them here
+        add_column_if_missing(&db, "documents", "indexed_at", "TEXT")?;
+        add_column_if_missing(&db, "documents", "content_bytes", "INTEGER")?;
+        add_column_if_missing(&db, "documents", "keywords", "TEXT")?;
+        add_column_if_missing(&db, "documents", "summary", "TEXT")?;
+
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS chunks (
+                id TEXT PRIMARY KEY,
+                document_id TEXT,
+                content TEXT,
+                start_pos INTEGER,
+                end_pos INTEGER,
+                embedding BLOB,
+                FOREIGN KEY(document_id) REFERENCES documents(id)
+            )"
+        )?;
+
+        // Tags are annotations added after indexing, so they survive reindexing
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS document_tags (
+                document_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (document_id, tag)
+            );
+            CREATE INDEX IF NOT EXISTS document_tags_tag ON document_tags (tag)"
+        )?;
+
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS query_log (
+                id INTEGER PRIMARY KEY,
+                logged_at TEXT NOT NULL,
+                query TEXT NOT NULL,
+                result_count INTEGER NOT NULL,
+                top_score REAL,
+                latency_ms REAL NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS query_log_logged_at ON query_log (logged_at);
+            CREATE TABLE IF NOT EXISTS query_log_documents (
+                query_id INTEGER NOT NULL,
+                document_id TEXT NOT NULL,
+                PRIMARY KEY (query_id, document_id)
+            )"
+        )?;
+
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value TEXT
+            )"
+        )?;
+
+        let (tokenizer, normalization, reindex_on_mismatch) = (self.tokenizer.clone(), self.normalization, self.reindex_on_mismatch);
+        let mut engine = self.engine(db);
+
+        if engine.table_exists("chunks_fts")? {
+            engine.fts_rebuild = engine.stored_fts_rebuild()?;
+            // Indexes from before the meta table were always built with the FTS5 default
+            let stored = engine.meta_value("tokenizer")?.unwrap_or_else(|| DEFAULT_TOKENIZER.to_string());
+            engine.tokenizer = stored.clone();
+
+            // ... and before normalization, with text as given
+            let stored_normalization = engine.meta_value(NORMALIZATION_META_KEY)?
+                .and_then(|value| Normalization::parse(&value))
+                .unwrap_or(Normalization::None);
+            engine.normalization = stored_normalization;
+            let normalization = normalization.filter(|n| *n != stored_normalization);
+
+            if let Some(configured) = tokenizer.filter(|t| *t != stored) {
+                if !reindex_on_mismatch {
+                    return Err(TokenizerMismatch { stored, configured }.into());
+                }
+                engine.tokenizer = configured;
+                if normalization.is_none() {
+                    engine.rebuild_fts().await?;
+                }
+            }
+            if let Some(configured) = normalization {
+                if !reindex_on_mismatch {
+                    return Err(NormalizationMismatch { stored: stored_normalization, configured }.into());
+                }
+                engine.renormalize(configured).await?;
+            }
+        } else {
+            engine.tokenizer = tokenizer.unwrap_or_else(|| DEFAULT_TOKENIZER.to_string());
+            engine.normalization = normalization.unwrap_or(Normalization::Nfc);
+            engine.create_fts_table()?;
+            engine.set_meta_value(NORMALIZATION_META_KEY, engine.normalization.as_str())?;
+        }
+
+        // Per-term chunk counts of the FTS index, used to weight keywords
+        engine.db.execute("CREATE VIRTUAL TABLE IF NOT EXISTS chunks_vocab USING fts5vocab(chunks_fts, 'row')")?;
+
+        // A rebuild cut short by the process exiting is finished before the engine serves searches
+        if engine.fts_rebuild.is_some() {
+            tracing::warn!("Finishing a full-text index rebuild that was interrupted");
+            while !engine.rebuild_fts_batch(FTS_REBUILD_BATCH_CHUNKS)?.done {}
+        }
+
+        if let Some(provider) = &engine.embedder {
+            if engine.embedding_model()?.is_none() && !engine.has_embeddings()? {
+                engine.register_embedding_model(&provider_model(provider.as_ref()))?;
+            }
+        }
+
+        Ok(engine)
+    }
+
+    /// Opens the database file as it is, for inspecting it without changing
+    /// it: no schema is created or migrated, nothing is reindexed or
+    /// preloaded, and anything that would write fails
+    pub fn open_read_only(self) -> Result<RAGEngine> {
+        let Some(path) = self.path.clone() else {
+            anyhow::bail!("only a database file can be opened read-only");
+        };
+        let db = Connection::open_thread_safe_with_flags(&path, OpenFlags::new().with_read_only())?;
+        db.execute(format!("PRAGMA busy_timeout = {}", self.sqlite_tuning.busy_timeout.as_millis()))?;
+        let mut engine = self.engine(db);
+        if engine.table_exists("meta")? {
+            engine.tokenizer = engine.meta_value("tokenizer")?.unwrap_or_else(|| DEFAULT_TOKENIZER.to_string());
+            engine.normalization = engine.meta_value(NORMALIZATION_META_KEY)?.and_then(|value| Normalization::parse(&value)).unwrap_or(Normalization::None);
+        }
+        Ok(engine)
+    }
+
+    fn engine(self, db: ConnectionThreadSafe) -> RAGEngine {
+        RAGEngine {
+            db,
+            path: self.path,
+            busy_timeout: self.sqlite_tuning.busy_timeout,
+            chunk_size: self.chunk_size,
+            overlap_size: self.overlap_size,
+            tokenizer: DEFAULT_TOKENIZER.to_string(),
+            normalization: Normalization::Nfc,
+            chunk_parallelism: self.chunk_parallelism,
+            title_boost: DEFAULT_TITLE_BOOST,
+            fetch_config: FetchConfig::default(),
+            stop_words: default_stop_words(),
+            query_log: self.query_log,
+            query_log_writer: std::sync::Mutex::new(()),
+            summarizer: self.summarizer,
+            validation: self.validation,
+            embedder: self.embedder,
+            ranking: self.ranking,
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            fts_rebuild_policy: self.fts_rebuild_policy,
+            fts_rebuild: None,
+        }
+    }
+}
+
+impl RAGEngine {
+    pub async fn new() -> Result<Self> {
+        Self::builder().build().await
+    }
+
+    pub fn builder() -> RAGEngineBuilder {
+        RAGEngineBuilder::default()
+    }
+
+    /// Changes whenever what searches can return might have: documents are
+    /// written or removed, metadata, tags or ranking change, or the index is
+    /// rebuilt. Unique across engines in the process, so anything derived from
+    /// search results is stale once this differs from when it was made.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn changed(&mut self) {
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Re-creates chunks_fts with the configured tokenizer from the stored chunks
+    pub async fn rebuild_fts(&mut self) -> Result<()> {
+        self.begin_fts_rebuild()?;
+        while !self.rebuild_fts_batch(FTS_REBUILD_BATCH_CHUNKS)?.done {}
+        Ok(())
+    }
+
+    /// Empties chunks_fts, re-created with the configured tokenizer, for
+    /// `rebuild_fts_batch` to refill from the chunks table. Documents written
+    /// meanwhile are indexed as usual. Where the rebuild is is kept in the
+    /// database with each batch, so one cut short is finished when the
+    /// engine is next opened.
+    pub fn begin_fts_rebuild(&mut self) -> Result<FtsRebuildProgress> {
+        self.changed();
+        let cursor = self.in_transaction(|engine| {
+            engine.db.execute("DROP TABLE IF EXISTS chunks_fts")?;
+            engine.create_fts_table()?;
+            let mut stmt = engine.db.prepare("SELECT COALESCE(MAX(rowid), 0), COUNT(*) FROM chunks")?;
+            stmt.next()?;
+            let cursor = FtsRebuildCursor { after: 0, until: stmt.read::<i64, _>(0)?, processed: 0, total: stmt.read::<i64, _>(1)? as usize };
+            engine.store_fts_rebuild(&cursor)?;
+            Ok(cursor)
+        })?;
+        self.fts_rebuild = Some(cursor);
+        tracing::info!("Rebuilding FTS index with tokenizer '{}' from {} chunks", self.tokenizer, cursor.total);
+        Ok(FtsRebuildProgress { processed: 0, total: cursor.total, done: false })
+    }
+
+    /// Copies the next `limit` chunks into chunks_fts in one transaction.
+    /// Done once every chunk there was at `begin_fts_rebuild` is in, or when
+    /// no rebuild is under way.
+    pub fn rebuild_fts_batch(&mut self, limit: usize) -> Result<FtsRebuildProgress> {
+        let Some(mut cursor) = self.fts_rebuild else {
+            return Ok(FtsRebuildProgress { processed: 0, total: 0, done: true });
+        };
+        let (next, copied) = {
+            let mut stmt = self.db.prepare(
+                "SELECT MAX(rowid), COUNT(*) FROM (SELECT rowid FROM chunks WHERE rowid > ? AND rowid <= ? ORDER BY rowid LIMIT ?)"
+            )?;
+            stmt.bind((1, cursor.after))?;
+            stmt.bind((2, cursor.until))?;
+            stmt.bind((3, limit.max(1) as i64))?;
+            stmt.next()?;
+            (stmt.read::<Option<i64>, _>(0)?, stmt.read::<i64, _>(1)? as usize)
+        };
+
+        let Some(next) = next else {
+            self.in_transaction(|engine| {
+                engine.normalize_fts_titles()?;
+                let mut stmt = engine.db.prepare("DELETE FROM meta WHERE key = ?")?;
+                stmt.bind((1, FTS_REBUILD_META_KEY))?;
+                stmt.next()?;
+                Ok(())
+            })?;
+            self.fts_rebuild = None;
+            self.changed();
+            tracing::info!("Rebuilt FTS index with tokenizer '{}'", self.tokenizer);
+            return Ok(FtsRebuildProgress { processed: cursor.processed, total: cursor.total, done: true });
+        };
+
+        let after = cursor.after;
+        cursor.after = next;
+        cursor.processed += copied;
+        cursor.total = cursor.total.max(cursor.processed);
+        self.in_transaction(|engine| {
+            let mut stmt = engine.db.prepare(
+                "INSERT INTO chunks_fts (chunk_id, title, content)
+                 SELECT c.id, d.title, c.content
+                 FROM chunks c
+                 JOIN documents d ON c.document_id = d.id
+                 WHERE c.rowid > ? AND c.rowid <= ?"
+            )?;
+            stmt.bind((1, after))?;
+            stmt.bind((2, next))?;
+            stmt.next()?;
+            engine.store_fts_rebuild(&cursor)
+        })?;
+        self.fts_rebuild = Some(cursor);
+        self.changed();
+        Ok(FtsRebuildProgress { processed: cursor.processed, total: cursor.total, done: false })
+    }
+
+    /// Whether chunks_fts is being rebuilt, and how far it has got
+    pub fn fts_rebuild_progress(&self) -> Option<FtsRebuildProgress> {
+        self.fts_rebuild.map(|cursor| FtsRebuildProgress { processed: cursor.processed, total: cursor.total, done: false })
+    }
+
+    fn store_fts_rebuild(&self, cursor: &FtsRebuildCursor) -> Result<()> {
+        self.set_meta_value(FTS_REBUILD_META_KEY, &format!("{}/{}", cursor.after, cursor.until))
+    }
+
+    fn stored_fts_rebuild(&self) -> Result<Option<FtsRebuildCursor>> {
+        let Some(value) = self.meta_value(FTS_REBUILD_META_KEY)? else {
+            return Ok(None);
+        };
+        let Some((after, until)) = value.split_once('/').and_then(|(after, until)| Some((after.parse().ok()?, until.parse().ok()?))) else {
+            anyhow::bail!("unreadable FTS rebuild state '{}'", value);
+        };
+        let mut stmt = self.db.prepare("SELECT COUNT(*), COALESCE(SUM(rowid <= ?), 0) FROM chunks WHERE rowid <= ?")?;
+        stmt.bind((1, after))?;
+        stmt.bind((2, until))?;
+        stmt.next()?;
+        let (total, processed) = (stmt.read::<i64, _>(0)? as usize, stmt.read::<i64, _>(1)? as usize);
+        Ok(Some(FtsRebuildCursor { after, until, processed, total }))
+    }
+
+    /// Titles are copied into chunks_fts verbatim by SQL; rewrite the ones normalization changes
+    fn normalize_fts_titles(&self) -> Result<()> {
+        let mut stmt = self.db.prepare("SELECT id, title FROM documents")?;
+        let mut changed = Vec::new();
+        while let State::Row = stmt.next()? {
+            let title = stmt.read::<String, _>(1)?;
+            let normalized = self.normalization.apply(&title);
+            if normalized != title {
+                changed.push((stmt.read::<String, _>(0)?, normalized));
+            }
+        }
+
+        for (id, title) in changed {
+            let mut update = self.db.prepare(
+                "UPDATE chunks_fts SET title = ? WHERE chunk_id IN (SELECT id FROM chunks WHERE document_id = ?)"
+            )?;
+            update.bind((1, title.as_str()))?;
+            update.bind((2, id.as_str()))?;
+            update.next()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites stored chunk text under `normalization` and rebuilds the FTS index from it
+    async fn renormalize(&mut self, normalization: Normalization) -> Result<()> {
+        self.changed();
+        self.normalization = normalization;
+        let mut after = 0;
+        loop {
+            let batch = self.chunk_batch(after, STREAM_BATCH_CHUNKS)?;
+            let Some(&(last, _, _)) = batch.last() else {
+                break;
+            };
+            self.in_transaction(|engine| {
+                for (_, id, content) in &batch {
+                    let normalized = normalization.apply(content);
+                    if normalized != *content {
+                        let mut stmt = engine.db.prepare("UPDATE chunks SET content = ? WHERE id = ?")?;
+                        stmt.bind((1, normalized.trim()))?;
+                        stmt.bind((2, id.as_str()))?;
+                        stmt.next()?;
+                    }
+                }
+                Ok(())
+            })?;
+            after = last;
+        }
+
+        self.set_meta_value(NORMALIZATION_META_KEY, normalization.as_str())?;
+        self.rebuild_fts().await?;
+        tracing::info!("Renormalized index text with '{}'", normalization.as_str());
+        Ok(())
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    fn create_fts_table(&self) -> Result<()> {
+        self.db.execute(format!(
+            "CREATE VIRTUAL TABLE chunks_fts USING fts5(
+                chunk_id UNINDEXED,
+                title,
+                content,
+                tokenize = '{}'
+            )",
+            self.tokenizer.replace('\'', "''")
+        ))?;
+        self.set_meta_value("tokenizer", &self.tokenizer)
+    }
+
+    /// Every table the engine reads is there, and the FTS index answers a query
+    pub fn verify_schema(&self) -> Result<()> {
+        let missing: Vec<&str> = ["documents", "chunks", "document_tags", "meta", "chunks_fts", "chunks_vocab"]
+            .into_iter()
+            .filter(|table| !matches!(self.table_exists(table), Ok(true)))
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("missing tables: {}", missing.join(", "));
+        }
+        self.db
+            .execute("SELECT chunk_id FROM chunks_fts WHERE chunks_fts MATCH 'shrine' LIMIT 1")
+            .map_err(|e| anyhow::anyhow!("the FTS index doesn't answer queries: {}", e))
+    }
+
+    fn table_exists(&self, name: &str) -> Result<bool> {
+        let mut stmt = self.db.prepare("SELECT 1 FROM sqlite_master WHERE name = ?")?;
+        stmt.bind((1, name))?;
+        Ok(matches!(stmt.next()?, State::Row))
+    }
+
+    fn meta_value(&self, key: &str) -> Result<Option<String>> {
+        let mut stmt = self.db.prepare("SELECT value FROM meta WHERE key = ?")?;
+        stmt.bind((1, key))?;
+        match stmt.next()? {
+            State::Row => Ok(Some(stmt.read::<String, _>(0)?)),
+            State::Done => Ok(None),
+        }
+    }
+
+    fn set_meta_value(&self, key: &str, value: &str) -> Result<()> {
+        let mut stmt = self.db.prepare("INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)")?;
+        stmt.bind((1, key))?;
+        stmt.bind((2, value))?;
+        stmt.next()?;
+        Ok(())
+    }
+
+    /// The model behind the stored chunk embeddings, if any are registered
+    pub fn embedding_model(&self) -> Result<Option<EmbeddingModel>> {
+        let model = self.meta_value(EMBEDDING_MODEL_META_KEY)?;
+        let dimension = self.meta_value(EMBEDDING_DIMENSION_META_KEY)?;
+        Ok(match (model, dimension) {
+            (Some(model), Some(dimension)) => Some(EmbeddingModel { model, dimension: dimension.parse()? }),
+            _ => None,
+        })
+    }
+
+    fn register_embedding_model(&self, model: &EmbeddingModel) -> Result<()> {
+        self.set_meta_value(EMBEDDING_MODEL_META_KEY, &model.model)?;
+        self.set_meta_value(EMBEDDING_DIMENSION_META_KEY, &model.dimension.to_string())
+    }
+
+    fn has_embeddings(&self) -> Result<bool> {
+        let mut stmt = self.db.prepare("SELECT 1 FROM chunks WHERE embedding IS NOT NULL LIMIT 1")?;
+        Ok(matches!(stmt.next()?, State::Row))
+    }
+
+    fn check_embedding_model(&self, provider: &dyn EmbeddingProvider) -> Result<()> {
+        let configured = provider_model(provider);
+        let stored = self.embedding_model()?;
+        if stored.as_ref() != Some(&configured) {
+            return Err(EmbeddingMismatch { stored, configured }.into());
+        }
+        Ok(())
+    }
+
+    /// Drops every stored chunk embedding and the recorded model
+    pub async fn clear_embeddings(&mut self) -> Result<()> {
+        self.changed();
+        self.in_transaction(|engine| {
+            engine.db.execute("UPDATE chunks SET embedding = NULL")?;
+            let mut stmt = engine.db.prepare("DELETE FROM meta WHERE key IN (?, ?)")?;
+            stmt.bind((1, EMBEDDING_MODEL_META_KEY))?;
+            stmt.bind((2, EMBEDDING_DIMENSION_META_KEY))?;
+            stmt.next()?;
+            Ok(())
+        })?;
+
+        tracing::info!("Cleared stored embeddings");
+        Ok(())
+    }
+
+    /// Migrates to `provider`: clears the stored embeddings, embeds every chunk in
+    /// batches, calling `progress(done, total)` after each batch, and records its
+    /// model once the last batch is stored. The provider then serves new documents
+    /// and semantic queries. A migration that fails partway leaves no model
+    /// recorded, so semantic queries are refused until it is rerun.
+    /// Returns the number of chunks embedded.
+    pub async fn re_embed_all(
+        &mut self,
+        provider: Arc<dyn EmbeddingProvider>,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<usize> {
+        self.clear_embeddings().await?;
+
+        let total = self.count_rows("FROM chunks")?;
+        let mut done = 0;
+        let mut after = 0;
+        loop {
+            let batch = self.chunk_batch(after, EMBEDDING_BATCH_CHUNKS)?;
+            let Some(&(last, _, _)) = batch.last() else {
+                break;
+            };
+
+            let texts: Vec<String> = batch.iter().map(|(_, _, content)| content.clone()).collect();
+            let vectors = embed_checked(provider.as_ref(), &texts).await?;
+            self.in_transaction(|engine| {
+                for ((_, id, _), vector) in batch.iter().zip(&vectors) {
+                    let mut stmt = engine.db.prepare("UPDATE chunks SET embedding = ? WHERE id = ?")?;
+                    stmt.bind((1, encode_embedding(vector).as_slice()))?;
+                    stmt.bind((2, id.as_str()))?;
+                    stmt.next()?;
+                }
+                Ok(())
+            })?;
+
+            done += batch.len();
+            after = last;
+            progress(done, total);
+        }
+        self.register_embedding_model(&provider_model(provider.as_ref()))?;
+        self.embedder = Some(Arc::clone(&provider));
+
+        tracing::info!("Re-embedded {} chunks with {}", done, provider_model(provider.as_ref()));
+        Ok(done)
+    }
+
+    /// Up to `limit` chunks as (rowid, id, content) with rowid above `after`
+    fn chunk_batch(&self, after: i64, limit: usize) -> Result<Vec<(i64, String, String)>> {
+        let mut stmt = self.db.prepare("SELECT rowid, id, content FROM chunks WHERE rowid > ? ORDER BY rowid LIMIT ?")?;
+        stmt.bind((1, after))?;
+        stmt.bind((2, limit as i64))?;
+
+        let mut batch = Vec::new();
+        while let State::Row = stmt.next()? {
+            batch.push((stmt.read::<i64, _>(0)?, stmt.read::<String, _>(1)?, stmt.read::<String, _>(2)?));
+        }
+        Ok(batch)
+    }
+
+    /// Chunks ranked by cosine similarity between their embeddings and the query's.
+    /// Fails with `EmbeddingMismatch` when the provider differs from the stored model.
+    pub async fn semantic_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        self.semantic_candidates(query, limit, &DocumentFilter::default()).await
+    }
+
+    /// `semantic_search` within `filter`
+    async fn semantic_candidates(&self, query: &str, limit: usize, filter: &DocumentFilter) -> Result<Vec<SearchResult>> {
+        let Some(provider) = &self.embedder else {
+            anyhow::bail!("semantic search needs an embedding provider");
+        };
+        self.check_embedding_model(provider.as_ref())?;
+        let query_vector = embed_checked(provider.as_ref(), &[query.to_string()]).await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("embedding provider returned no vector for the query"))?;
+        self.rank_by_embedding(&query_vector, provider.dimension(), limit, filter)
+    }
+
+    /// The provider chunks are embedded with, when one is configured
+    pub fn embedder(&self) -> Option<&Arc<dyn EmbeddingProvider>> {
+        self.embedder.as_ref()
+    }
+
+    /// Embeds arbitrary `texts` with the indexing provider, in the batches
+    /// `re_embed_all` sends, so the vectors compare with the stored ones
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let Some(provider) = &self.embedder else {
+            anyhow::bail!("embedding needs an embedding provider");
+        };
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBEDDING_BATCH_CHUNKS) {
+            vectors.extend(embed_checked(provider.as_ref(), batch).await?);
+        }
+        Ok(vectors)
+    }
+
+    fn rank_by_embedding(&self, query: &[f32], dimension: usize, limit: usize, filter: &DocumentFilter) -> Result<Vec<SearchResult>> {
+        let (filter_clause, binds) = filter_sql(filter);
+        let mut stmt = self.db.prepare(format!(
+            "SELECT c.id, c.content, c.document_id, d.title, d.metadata, c.embedding
+             FROM chunks c
+             JOIN documents d ON c.document_id = d.id
+             WHERE c.embedding IS NOT NULL{}",
+            filter_clause
+        ))?;
+        for (i, value) in binds.iter().enumerate() {
+            stmt.bind((i + 1, value.as_str()))?;
+        }
+
+        let mut results = Vec::new();
+        while let State::Row = stmt.next()? {
+            let chunk_id = stmt.read::<String, _>(0)?;
+            let vector = decode_embedding(&stmt.read::<Vec<u8>, _>(5)?);
+            check_dimension(&chunk_id, vector.len(), Some(dimension))?;
+
+            let metadata: String = stmt.read::<String, _>(4)?;
+            results.push(SearchResult {
+                chunk_id,
+                content: stmt.read::<String, _>(1)?,
+                document_id: stmt.read::<String, _>(2)?,
+                title: stmt.read::<String, _>(3)?,
+                similarity_score: cosine_similarity(query, &vector),
+                metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+                matched_fields: vec!["embedding".to_string()],
+            });
+        }
+
+        results.sort_by(|a, b| b.similarity_score.total_cmp(&a.similarity_score));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// Weights title matches `boost` times as heavily as body matches when
+    /// ranking. Panics unless `boost` is finite and positive.
+    pub fn with_title_boost(mut self, boost: f64) -> Self {
+        assert!(boost.is_finite() && boost > 0.0, "title boost must be finite and positive, not {}", boost);
+        self.changed();
+        self.title_boost = boost;
+        self
+    }
+
+    pub fn ranking(&self) -> &RankingConfig {
+        &self.ranking
+    }
+
+    /// Replaces the ranking adjustments used by subsequent searches
+    pub fn set_ranking(&mut self, config: RankingConfig) {
+        self.changed();
+        self.ranking = config;
+    }
+
+    pub fn with_fetch_config(mut self, config: FetchConfig) -> Self {
+        self.fetch_config = config;
+        self
+    }
+
+    /// Fetches a web page and indexes its text, returning the created document id
+    pub async fn index_url(&mut self, url: &str) -> Result<String> {
+        let document = self.fetch_url(url).await?;
+        let id = document.id.clone();
+        self.index_document(document).await?;
+        Ok(id)
+    }
+
+    /// Fetches and converts a page without touching the index, so callers can
+    /// avoid holding a write lock across the network round trip
+    pub async fn fetch_url(&self, url: &str) -> Result<Document> {
+        let config = &self.fetch_config;
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .user_agent(config.user_agent.as_str())
+            .redirect(reqwest::redirect::Policy::limited(config.max_redirects))
+            .build()?;
+
+        let mut response = client.get(url).send().await.map_err(|e| {
+            if e.is_redirect() {
+                anyhow::Error::new(FetchError::TooManyRedirects(config.max_redirects))
+            } else {
+                anyhow::Error::new(e)
+            }
+        })?;
+
+        if !response.status().is_success() {
+            return Err(FetchError::Status(response.status().as_u16()).into());
+        }
+
+        let content_type = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        if !content_type.starts_with("text/html") && !content_type.starts_with("application/xhtml+xml") {
+            return Err(FetchError::UnsupportedContentType(content_type).into());
+        }
+
+        if response.content_length().is_some_and(|len| len as usize > config.max_content_bytes) {
+            return Err(FetchError::TooLarge(config.max_content_bytes).into());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() > config.max_content_bytes {
+                return Err(FetchError::TooLarge(config.max_content_bytes).into());
+            }
+        }
+
+        let (title, text) = html_to_text(&String::from_utf8_lossy(&body));
+        let final_url = response.url().to_string();
+
+        let mut metadata = HashMap::new();
+        metadata.insert("url".to_string(), url.to_string());
+        if final_url != url {
+            metadata.insert("final_url".to_string(), final_url);
+        }
+        metadata.insert("fetched_at".to_string(), chrono::Utc::now().to_rfc3339());
+        metadata.insert("content_type".to_string(), content_type);
+
+        Ok(Document {
+            id: url_document_id(url),
+            title: title.unwrap_or_else(|| url.to_string()),
+            content: text,
+            metadata,
+            embedding: None,
+            chunks: vec![],
+        })
+    }
+
+    /// Indexes a text or HTML file under an id derived from its path, skipping
+    /// the write when the content is unchanged. Returns the document id.
+    pub async fn index_file(&mut self, path: &Path) -> Result<String> {
+        let document = Self::load_file(path).await?;
+        let id = document.id.clone();
+        self.index_document_if_changed(document).await?;
+        Ok(id)
+    }
+
+    /// Reads a file into a document without touching the index
+    pub async fn load_file(path: &Path) -> Result<Document> {
+        let raw = tokio::fs::read_to_string(path).await?;
+        let is_html = path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"));
+
+        let file_name = path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string());
+        let (title, content) = if is_html {
+            let (title, text) = html_to_text(&raw);
+            (title.unwrap_or(file_name), text)
+        } else {
+            (file_name, raw)
+        };
+
+        let mut metadata = HashMap::new();
+        metadata.insert("path".to_string(), path.to_string_lossy().to_string());
+
+        Ok(Document {
+            id: file_document_id(path),
+            title,
+            content,
+            metadata,
+            embedding: None,
+            chunks: vec![],
+        })
+    }
+
+    /// Indexes the document unless an identical title and content is already stored.
+    /// Returns whether anything was written.
+    pub async fn index_document_if_changed(&mut self, document: Document) -> Result<bool> {
+        let hash = content_hash(&document.title, &document.content);
+        let stored = self.stored_content_hash(&document.id)?;
+
+        if stored.as_deref() == Some(hash.as_str()) {
+            tracing::debug!("Document unchanged, skipping reindex: {}", document.id);
+            return Ok(false);
+        }
+
+        self.index_document(document).await?;
+        Ok(true)
+    }
+
+    fn stored_content_hash(&self, id: &str) -> Result<Option<String>> {
+        let mut stmt = self.db.prepare(format!(
+            "SELECT json_extract(metadata, '$.{}') FROM documents WHERE id = ?",
+            CONTENT_HASH_METADATA_KEY
+        ))?;
+        stmt.bind((1, id))?;
+        match stmt.next()? {
+            State::Row => Ok(stmt.read::<Option<String>, _>(0)?),
+            State::Done => Ok(None),
+        }
+    }
+
+    /// Validates and indexes a document, replacing any previous version with the
+    /// same id. Invalid documents fail with a `ValidationError`.
+    pub async fn index_document(&mut self, document: Document) -> Result<()> {
+        let prepared = self.prepare_document(document).await?;
+        self.write_prepared(prepared)
+    }
+
+    /// The slow, read-only half of `index_document`: validation, chunking,
+    /// embedding and summarizing. Callers sharing the engine behind a lock can
+    /// run this under a read lock and only take the write lock for `write_prepared`.
+    pub async fn prepare_document(&self, mut document: Document) -> Result<PreparedDocument> {
+        self.validation.check_document(&document)?;
+
+        // Record the dominant language unless the caller already supplied one
+        if !document.metadata.contains_key(LANGUAGE_METADATA_KEY) {
+            let language = self.detect_language(&format!("{} {}", document.title, document.content));
+            document.metadata.insert(LANGUAGE_METADATA_KEY.to_string(), language);
+        }
+
+        document.metadata.insert(
+            CONTENT_HASH_METADATA_KEY.to_string(),
+            content_hash(&document.title, &document.content),
+        );
+
+        // Chunk and summarize before touching the database so the write transaction stays short
+        let mut chunks = self.create_chunks(&document.content, &document.id);
+        self.embed_chunks(&mut chunks).await?;
+        let summary = self.summarize(&document).await;
+        Ok(PreparedDocument { document, chunks, summary })
+    }
+
+    /// Stores a document from `prepare_document` in one transaction
+    pub fn write_prepared(&mut self, prepared: PreparedDocument) -> Result<()> {
+        self.changed();
+        let PreparedDocument { document, chunks, summary } = prepared;
+        let chunk_count = chunks.len();
+        self.in_transaction(|engine| {
+            engine.write_document(&document, &chunks)?;
+            engine.update_keywords(&document.id)?;
+
+            let mut stmt = engine.db.prepare("UPDATE documents SET summary = ? WHERE id = ?")?;
+            stmt.bind((1, summary.as_deref()))?;
+            stmt.bind((2, document.id.as_str()))?;
+            stmt.next()?;
+            Ok(())
+        })?;
+
+        tracing::info!("Indexed document: {} with {} chunks", document.id, chunk_count);
+        Ok(())
+    }
+
+    /// Fills in chunk embeddings when a provider is configured and matches the stored model
+    async fn embed_chunks(&self, chunks: &mut [DocumentChunk]) -> Result<()> {
+        let Some(provider) = &self.embedder else {
+            return Ok(());
+        };
+        self.check_embedding_model(provider.as_ref())?;
+
+        let texts: Vec<String> = chunks.iter().map(|chunk| chunk.content.clone()).collect();
+        let vectors = embed_checked(provider.as_ref(), &texts).await?;
+        for (chunk, vector) in chunks.iter_mut().zip(vectors) {
+            chunk.embedding = Some(vector);
+        }
+        Ok(())
+    }
+
+    /// A failing summarizer never fails indexing; the document is stored without a summary
+    async fn summarize(&self, document: &Document) -> Option<String> {
+        let summarizer = self.summarizer.as_ref()?;
+        let content = match document.content.char_indices().nth(SUMMARY_INPUT_CHARS) {
+            Some((end, _)) => &document.content[..end],
+            None => &document.content,
+        };
+
+        match summarizer.summarize(&document.title, content).await {
+            Ok(summary) if !summary.trim().is_empty() => Some(summary.trim().to_string()),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!("Summarizing {} failed, indexing without a summary: {}", document.id, e);
+                None
+            }
+        }
+    }
+
+    /// Indexes text from `reader` without holding it in memory: the source is read in
+    /// windows, chunked exactly as `index_document` would chunk the whole text, and
+    /// written in batched transactions. Only the chunks keep the text, so the stored
+    /// document has empty content. A failed read, input past `max_content_bytes`
+    /// or input that is only whitespace removes the partial document.
+    /// Chunks are stored without embeddings. Returns the number of chunks written.
+    pub async fn index_reader(
+        &mut self,
+        mut reader: impl BufRead,
+        doc_id: &str,
+        title: &str,
+        metadata: HashMap<String, String>,
+    ) -> Result<usize> {
+        self.changed();
+        self.validation.check_id(doc_id)?;
+        self.validation.check_metadata(&metadata)?;
+
+        // Left behind should an earlier stream of this id have been cut short
+        let staging = format!("{}{}", STAGING_ID_PREFIX, doc_id);
+        self.in_transaction(|engine| engine.remove_document(&staging))?;
+
+        match self.stream_document(&mut reader, doc_id, &staging, title, metadata) {
+            Ok(chunk_count) => {
+                tracing::info!("Indexed streamed document: {} with {} chunks", doc_id, chunk_count);
+                Ok(chunk_count)
+            }
+            Err(e) => {
+                let _ = self.in_transaction(|engine| engine.remove_document(&staging));
+                Err(e)
+            }
+        }
+    }
+
+    /// Streams the document in under `staging`, then swaps it in for any
+    /// version of `doc_id` in the transaction that writes the last batch
+    fn stream_document(
+        &self,
+        reader: &mut impl BufRead,
+        doc_id: &str,
+        staging: &str,
+        title: &str,
+        mut metadata: HashMap<String, String>,
+    ) -> Result<usize> {
+        let mut hasher = ContentHasher::new(title);
+        let mut pending = Vec::new();
+        let mut window: Vec<char> = Vec::with_capacity(self.chunk_size * 2);
+        // Global char offsets of window[0] and of the next chunk
+        let mut window_offset = 0;
+        let mut start = 0;
+        let mut eof = false;
+        let mut content_bytes = 0;
+        // Whether every char read so far is whitespace
+        let mut blank = true;
+
+        let mut batch = Vec::with_capacity(STREAM_BATCH_CHUNKS);
+        let mut chunk_count = 0;
+        let mut document_written = false;
+
+        loop {
+            // A chunk can only be cut once the text past its end is known, otherwise
+            // the boundary could differ from the in-memory path
+            let mut rel = start - window_offset;
+            if !eof && window.len() <= rel + self.chunk_size {
+                window.drain(..rel);
+                window_offset = start;
+                rel = 0;
+                while !eof && window.len() <= self.chunk_size {
+                    let read = read_chars(reader, &mut pending, &mut window, &mut hasher)?;
+                    content_bytes += read;
+                    if content_bytes > self.validation.max_content_bytes {
+                        return Err(ValidationError::ContentTooLarge { bytes: content_bytes, max: self.validation.max_content_bytes }.into());
+                    }
+                    blank = blank && window.iter().all(|c| c.is_whitespace());
+                    eof = read == 0;
+                }
+            }
+            if rel >= window.len() {
+                break;
+            }
+
+            let end = self.chunk_end(&window, rel);
+            batch.push(make_chunk(
+                staging,
+                chunk_count,
+                &window[rel..end],
+                (window_offset + rel, window_offset + end),
+                self.normalization,
+            ));
+            chunk_count += 1;
+
+            if batch.len() == STREAM_BATCH_CHUNKS {
+                if blank {
+                    return Err(ValidationError::EmptyContent.into());
+                }
+                if !document_written {
+                    self.start_streamed_document(staging, title, &mut metadata, &batch)?;
+                    document_written = true;
+                }
+                self.in_transaction(|engine| engine.write_chunks(title, &batch))?;
+                batch.clear();
+            }
+
+            // Only reachable at end of input, where the window ends with the document
+            if end >= window.len() {
+                break;
+            }
+            start = window_offset + self.next_chunk_start(rel, end);
+        }
+
+        if blank {
+            return Err(ValidationError::EmptyContent.into());
+        }
+        if !document_written {
+            self.start_streamed_document(staging, title, &mut metadata, &batch)?;
+        }
+        metadata.insert(CONTENT_HASH_METADATA_KEY.to_string(), hasher.finish());
+        let metadata_json = serde_json::to_string(&metadata)?;
+        self.in_transaction(|engine| {
+            engine.write_chunks(title, &batch)?;
+            let mut stmt = engine.db.prepare("UPDATE documents SET metadata = ?, content_bytes = ? WHERE id = ?")?;
+            stmt.bind((1, metadata_json.as_str()))?;
+            stmt.bind((2, content_bytes as i64))?;
+            stmt.bind((3, staging))?;
+            stmt.next()?;
+            engine.swap_in(staging, doc_id)?;
+            engine.update_keywords(doc_id)
+        })?;
+
+        Ok(chunk_count)
+    }
+
+    /// Replaces `doc_id`, chunks and FTS rows included, with the document
+    /// streamed in under `staging`. Tags stay with `doc_id`.
+    fn swap_in(&self, staging: &str, doc_id: &str) -> Result<()> {
+        self.remove_chunks(doc_id)?;
+        // Chunk ids keep their `_<index>` suffix
+        let suffix_at = staging.len() as i64 + 1;
+        let mut stmt = self.db.prepare(
+            "UPDATE chunks_fts SET chunk_id = ? || substr(chunk_id, ?)
+             WHERE chunk_id IN (SELECT id FROM chunks WHERE document_id = ?)"
+        )?;
+        stmt.bind((1, doc_id))?;
+        stmt.bind((2, suffix_at))?;
+        stmt.bind((3, staging))?;
+        stmt.next()?;
+        let mut stmt = self.db.prepare("UPDATE chunks SET id = ? || substr(id, ?), document_id = ? WHERE document_id = ?")?;
+        stmt.bind((1, doc_id))?;
+        stmt.bind((2, suffix_at))?;
+        stmt.bind((3, doc_id))?;
+        stmt.bind((4, staging))?;
+        stmt.next()?;
+
+        let mut stmt = self.db.prepare("DELETE FROM documents WHERE id = ?")?;
+        stmt.bind((1, doc_id))?;
+        stmt.next()?;
+        let mut stmt = self.db.prepare("UPDATE documents SET id = ? WHERE id = ?")?;
+        stmt.bind((1, doc_id))?;
+        stmt.bind((2, staging))?;
+        stmt.next()?;
+        Ok(())
+    }
+
+    /// Replaces any previous version with an empty document row, detecting the
+    /// language from the first batch of chunks
+    fn start_streamed_document(
+        &self,
+        doc_id: &str,
+        title: &str,
+        metadata: &mut HashMap<String, String>,
+        first_chunks: &[DocumentChunk],
+    ) -> Result<()> {
+        if !metadata.contains_key(LANGUAGE_METADATA_KEY) {
+            let sample: Vec<&str> = std::iter::once(title)
+                .chain(first_chunks.iter().map(|chunk| chunk.content.as_str()))
+                .collect();
+            metadata.insert(LANGUAGE_METADATA_KEY.to_string(), self.detect_language(&sample.join(" ")));
+        }
+
+        let document = Document {
+            id: doc_id.to_string(),
+            title: title.to_string(),
+            content: String::new(),
+            metadata: metadata.clone(),
+            embedding: None,
+            chunks: vec![],
+        };
+        self.in_transaction(|engine| engine.write_document(&document, &[]))
+    }
+
+    fn write_document(&self, document: &Document, chunks: &[DocumentChunk]) -> Result<()> {
+        // Drop chunks and FTS rows from any previous version so the title index stays consistent
+        self.remove_chunks(&document.id)?;
+
+        // Store document
+        let metadata_json = serde_json::to_string(&document.metadata)?;
+        let mut stmt = self.db.prepare(
+            "INSERT OR REPLACE INTO documents (id, title, content, metadata, indexed_at, content_bytes)
+             VALUES (?, ?, ?, ?, ?, ?)"
+        )?;
+        
+        stmt.bind((1, document.id.as_str()))?;
+        stmt.bind((2, document.title.as_str()))?;
+        stmt.bind((3, document.content.as_str()))?;
+        stmt.bind((4, metadata_json.as_str()))?;
+        stmt.bind((5, timestamp(Utc::now()).as_str()))?;
+        stmt.bind((6, document.content.len() as i64))?;
+        stmt.next()?;
+
+        self.write_chunks(&document.title, chunks)
+    }
+
+    fn write_chunks(&self, title: &str, chunks: &[DocumentChunk]) -> Result<()> {
+        let title = self.normalization.apply(title);
+        let dimension = match chunks.iter().any(|chunk| chunk.embedding.is_some()) {
+            true => self.embedding_model()?.map(|model| model.dimension),
+            false => None,
+        };
+
+        for chunk in chunks {
+            let embedding = match &chunk.embedding {
+                Some(vector) => {
+                    check_dimension(&chunk.id, vector.len(), dimension)?;
+                    Some(encode_embedding(vector))
+                }
+                None => None,
+            };
+
+            let mut stmt = self.db.prepare(
+                "INSERT OR REPLACE INTO chunks (id, document_id, content, start_pos, end_pos, embedding)
+                 VALUES (?, ?, ?, ?, ?, ?)"
+            )?;
+            
+            stmt.bind((1, chunk.id.as_str()))?;
+            stmt.bind((2, chunk.document_id.as_str()))?;
+            stmt.bind((3, chunk.content.as_str()))?;
+            stmt.bind((4, chunk.start_pos as i64))?;
+            stmt.bind((5, chunk.end_pos as i64))?;
+            stmt.bind((6, embedding.as_deref()))?;
+            stmt.next()?;
+
+            // Index for FTS
+            let mut fts_stmt = self.db.prepare(
+                "INSERT INTO chunks_fts (chunk_id, title, content) VALUES (?, ?, ?)"
+            )?;
+            fts_stmt.bind((1, chunk.id.as_str()))?;
+            fts_stmt.bind((2, title.as_str()))?;
+            fts_stmt.bind((3, chunk.content.as_str()))?;
+            fts_stmt.next()?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a document with its chunks, FTS rows and tags, returning whether it existed
+    pub async fn delete_document(&mut self, id: &str) -> Result<bool> {
+        self.changed();
+        let deleted = self.in_transaction(|engine| engine.remove_document(id))?;
+        if deleted {
+            tracing::info!("Deleted document: {}", id);
+        }
+        Ok(deleted)
+    }
+
+    /// Sets one metadata value without reindexing. Lasts until the document is next
+    /// reindexed from its source. Returns false when the document does not exist.
+    pub async fn set_metadata(&mut self, doc_id: &str, key: &str, value: &str) -> Result<bool> {
+        self.changed();
+        self.in_transaction(|engine| {
+            let set = engine.write_metadata(doc_id, key, value)?;
+            engine.check_changed_metadata(doc_id)?;
+            Ok(set)
+        })
+    }
+
+    /// Removes one metadata key without reindexing. Returns false when the document does not exist.
+    pub async fn remove_metadata(&mut self, doc_id: &str, key: &str) -> Result<bool> {
+        self.changed();
+        self.erase_metadata(doc_id, key)
+    }
+
+    /// Tags a document. Tags are case-insensitive and, unlike metadata, survive
+    /// reindexing. Returns false when the document does not exist.
+    pub async fn add_tag(&mut self, doc_id: &str, tag: &str) -> Result<bool> {
+        self.changed();
+        self.write_tag(doc_id, tag)
+    }
+
+    /// Removes a tag, returning whether the document carried it
+    pub async fn remove_tag(&mut self, doc_id: &str, tag: &str) -> Result<bool> {
+        self.changed();
+        self.erase_tag(doc_id, tag)
+    }
+
+    /// Applies every change in `patch` to a document without reindexing, or,
+    /// should one be refused, none of them. Returns false when the document
+    /// does not exist.
+    pub async fn patch_document(&mut self, doc_id: &str, patch: &DocumentPatch) -> Result<bool> {
+        self.changed();
+        self.in_transaction(|engine| {
+            if !engine.document_exists(doc_id)? {
+                return Ok(false);
+            }
+            for (key, value) in &patch.set_metadata {
+                engine.write_metadata(doc_id, key, value)?;
+            }
+            for key in &patch.remove_metadata {
+                engine.erase_metadata(doc_id, key)?;
+            }
+            engine.check_changed_metadata(doc_id)?;
+            for tag in &patch.add_tags {
+                engine.write_tag(doc_id, tag)?;
+            }
+            for tag in &patch.remove_tags {
+                engine.erase_tag(doc_id, tag)?;
+            }
+            Ok(true)
+        })
+    }
+
+    /// Holds a document's metadata, once changed, to the limits indexing
+    /// holds it to, leaving out the keys indexing adds itself
+    fn check_changed_metadata(&self, doc_id: &str) -> Result<()> {
+        if let Some(info) = self.document_infos(&DocumentFilter::default(), Some(doc_id))?.pop() {
+            let mut metadata = info.metadata;
+            metadata.retain(|key, _| key != LANGUAGE_METADATA_KEY && key != CONTENT_HASH_METADATA_KEY);
+            self.validation.check_metadata(&metadata)?;
+        }
+        Ok(())
+    }
+
+    fn write_metadata(&self, doc_id: &str, key: &str, value: &str) -> Result<bool> {
+        let mut stmt = self.db.prepare(
+            "UPDATE documents SET metadata = json_set(COALESCE(metadata, '{}'), ?, ?) WHERE id = ?"
+        )?;
+        stmt.bind((1, metadata_path(key).as_str()))?;
+        stmt.bind((2, value))?;
+        stmt.bind((3, doc_id))?;
+        stmt.next()?;
+        Ok(self.db.change_count() > 0)
+    }
+
+    fn erase_metadata(&self, doc_id: &str, key: &str) -> Result<bool> {
+        let mut stmt = self.db.prepare("UPDATE documents SET metadata = json_remove(metadata, ?) WHERE id = ?")?;
+        stmt.bind((1, metadata_path(key).as_str()))?;
+        stmt.bind((2, doc_id))?;
+        stmt.next()?;
+        Ok(self.db.change_count() > 0)
+    }
+
+    fn write_tag(&self, doc_id: &str, tag: &str) -> Result<bool> {
+        let tag = normalize_tag(tag);
+        if tag.is_empty() {
+            anyhow::bail!("Tags must not be empty");
+        }
+
+        let mut stmt = self.db.prepare(
+            "INSERT OR IGNORE INTO document_tags (document_id, tag) SELECT id, ? FROM documents WHERE id = ?"
+        )?;
+        stmt.bind((1, tag.as_str()))?;
+        stmt.bind((2, doc_id))?;
+        stmt.next()?;
+        self.document_exists(doc_id)
+    }
+
+    fn erase_tag(&self, doc_id: &str, tag: &str) -> Result<bool> {
+        let mut stmt = self.db.prepare("DELETE FROM document_tags WHERE document_id = ? AND tag = ?")?;
+        stmt.bind((1, doc_id))?;
+        stmt.bind((2, normalize_tag(tag).as_str()))?;
+        stmt.next()?;
+        Ok(self.db.change_count() > 0)
+    }
+
+    /// Documents matching all of the metadata `filter` patterns and carrying all of `tags`, by id
+    pub async fn list_documents(&self, filter: &HashMap<String, String>, tags: &[String]) -> Result<Vec<DocumentInfo>> {
+        self.document_infos(&DocumentFilter { metadata: filter.clone(), tags: tags.to_vec(), ..Default::default() }, None)
+    }
+
+    /// The stored text of a document, empty for streamed ones; None when it does not exist
+    pub async fn document_content(&self, doc_id: &str) -> Result<Option<String>> {
+        let mut stmt = self.db.prepare("SELECT content FROM documents WHERE id = ?")?;
+        stmt.bind((1, doc_id))?;
+        match stmt.next()? {
+            State::Row => Ok(Some(stmt.read::<Option<String>, _>(0)?.unwrap_or_default())),
+            State::Done => Ok(None),
+        }
+    }
+
+    /// Summary of one document, or None when it does not exist
+    pub async fn document_info(&self, doc_id: &str) -> Result<Option<DocumentInfo>> {
+        Ok(self.document_infos(&DocumentFilter::default(), Some(doc_id))?.pop())
+    }
+
+    fn document_infos(&self, filter: &DocumentFilter, id: Option<&str>) -> Result<Vec<DocumentInfo>> {
+        let (filter_sql, mut binds) = filter_sql(filter);
+        let id_sql = match id {
+            Some(id) => {
+                binds.push(id.to_string());
+                " AND d.id = ?"
+            }
+            None => "",
+        };
+
+        let mut stmt = self.db.prepare(format!(
+            "SELECT d.id, d.title, d.metadata,
+                    (SELECT group_concat(tag, char(31)) FROM (SELECT tag FROM document_tags WHERE document_id = d.id ORDER BY tag)),
+                    (SELECT COUNT(*) FROM chunks WHERE document_id = d.id),
+                    d.keywords,
+                    d.summary
+             FROM documents d
+             WHERE 1 = 1{}{}
+             ORDER BY d.id",
+            filter_sql,
+            id_sql
+        ))?;
+        for (i, value) in binds.iter().enumerate() {
+            stmt.bind((i + 1, value.as_str()))?;
+        }
+
+        let mut documents = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            let metadata: String = stmt.read::<String, _>(2)?;
+            let tags = stmt.read::<Option<String>, _>(3)?
+                .map(|tags| tags.split('\u{1f}').map(str::to_string).collect())
+                .unwrap_or_default();
+            documents.push(DocumentInfo {
+                id: stmt.read::<String, _>(0)?,
+                title: stmt.read::<String, _>(1)?,
+                metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+                tags,
+                keywords: stmt.read::<Option<String>, _>(5)?
+                    .and_then(|keywords| serde_json::from_str(&keywords).ok())
+                    .unwrap_or_default(),
+                summary: stmt.read::<Option<String>, _>(6)?,
+                chunk_count: stmt.read::<i64, _>(4)? as usize,
+            });
+        }
+        Ok(documents)
+    }
+
+    fn document_exists(&self, doc_id: &str) -> Result<bool> {
+        let mut stmt = self.db.prepare("SELECT 1 FROM documents WHERE id = ?")?;
+        stmt.bind((1, doc_id))?;
+        Ok(matches!(stmt.next()?, State::Row))
+    }
+
+    /// Deletes every document whose metadata matches all of `filter` (same `*`
+    /// wildcard patterns as `query_metadata`), in one transaction. An empty filter
+    /// would wipe the index, so it is refused unless `allow_all` is set.
+    pub async fn delete_where(&mut self, filter: &HashMap<String, String>, allow_all: bool) -> Result<usize> {
+        self.changed();
+        if filter.is_empty() && !allow_all {
+            anyhow::bail!("Refusing to delete with an empty filter; pass allow_all to clear the whole index");
+        }
+
+        let ids = self.matching_document_ids(&DocumentFilter { metadata: filter.clone(), ..Default::default() })?;
+        self.in_transaction(|engine| {
+            for id in &ids {
+                engine.remove_document(id)?;
+            }
+            Ok(())
+        })?;
+
+        tracing::info!("Deleted {} documents matching {:?}", ids.len(), filter);
+        Ok(ids.len())
+    }
+
+    fn matching_document_ids(&self, filter: &DocumentFilter) -> Result<Vec<String>> {
+        let (filter_sql, binds) = filter_sql(filter);
+        let mut stmt = self.db.prepare(format!("SELECT d.id FROM documents d WHERE 1 = 1{}", filter_sql))?;
+        for (i, value) in binds.iter().enumerate() {
+            stmt.bind((i + 1, value.as_str()))?;
+        }
+
+        let mut ids = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            ids.push(stmt.read::<String, _>(0)?);
+        }
+        Ok(ids)
+    }
+
+    /// Runs `f` inside BEGIN/COMMIT, rolling back if it fails
+    fn in_transaction<T>(&self, f: impl FnOnce(&Self) -> Result<T>) -> Result<T> {
+        self.db.execute("BEGIN")?;
+        match f(self) {
+            Ok(value) => {
+                self.db.execute("COMMIT")?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = self.db.execute("ROLLBACK");
+                Err(e)
+            }
+        }
+    }
+
+    /// Deletes the document row with its chunks and tags, returning whether it existed
+    fn remove_document(&self, document_id: &str) -> Result<bool> {
+        self.remove_chunks(document_id)?;
+
+        let mut tag_stmt = self.db.prepare("DELETE FROM document_tags WHERE document_id = ?")?;
+        tag_stmt.bind((1, document_id))?;
+        tag_stmt.next()?;
+
+        let mut stmt = self.db.prepare("DELETE FROM documents WHERE id = ?")?;
+        stmt.bind((1, document_id))?;
+        stmt.next()?;
+        Ok(self.db.change_count() > 0)
+    }
+
+    fn remove_chunks(&self, document_id: &str) -> Result<()> {
+        let mut fts_stmt = self.db.prepare(
+            "DELETE FROM chunks_fts WHERE chunk_id IN (SELECT id FROM chunks WHERE document_id = ?)"
+        )?;
+        fts_stmt.bind((1, document_id))?;
+        fts_stmt.next()?;
+
+        let mut stmt = self.db.prepare("DELETE FROM chunks WHERE document_id = ?")?;
+        stmt.bind((1, document_id))?;
+        stmt.next()?;
+        Ok(())
+    }
+
+    pub async fn query(&self, query: &str, limit: usize) -> Result<Vec<String>> {
+        let results = self.search(query, limit, &QueryOptions::default()).await?;
+        Ok(results.iter().map(SearchResult::to_context_string).collect())
+    }
+
+    pub async fn search(&self, query: &str, limit: usize, options: &QueryOptions) -> Result<Vec<SearchResult>> {
+        let started = std::time::Instant::now();
+        let results = self.search_unlogged(query, limit, options).await?;
+
+        if let Some(config) = &self.query_log {
+            // Analytics must never break retrieval
+            if let Err(e) = self.log_query(config, query, &results, started.elapsed()) {
+                tracing::warn!("Failed to log query: {}", e);
+            }
+        }
+        Ok(results)
+    }
+
+    async fn search_unlogged(&self, query: &str, limit: usize, options: &QueryOptions) -> Result<Vec<SearchResult>> {
+        let (query, mut filter) = extract_filters(query);
+        filter.metadata.extend(options.metadata_filters.clone());
+        filter.tags.extend(options.tags.iter().map(|tag| normalize_tag(tag)));
+        filter.since = options.since;
+        filter.until = options.until;
+        filter.doc_ids = options.doc_ids.clone();
+
+        let language = options.language.clone()
+            .unwrap_or_else(|| self.detect_language(&query));
+
+        // Extra candidates so reranking can promote, and dropped duplicates be backfilled from, below the cut
+        let rerank = !self.ranking.is_neutral();
+        let candidates = if rerank || options.dedupe_overlaps { limit * RERANK_CANDIDATE_FACTOR } else { limit };
+
+        let mut results = match options.mode {
+            RetrievalMode::Keyword => self.keyword_candidates(&query, &language, candidates, &filter).await?,
+            RetrievalMode::Semantic => self.semantic_candidates(&query, candidates, &filter).await?,
+            RetrievalMode::Hybrid => {
+                let keyword = self.keyword_candidates(&query, &language, candidates, &filter).await?;
+                let semantic = self.semantic_candidates(&query, candidates, &filter).await?;
+                let mut merged = fuse_hybrid(keyword, semantic);
+                merged.truncate(candidates);
+                merged
+            }
+        };
+
+        if rerank {
+            self.apply_ranking(&mut results)?;
+        }
+        if options.dedupe_overlaps {
+            results = self.drop_overlapping(results)?;
+        }
+        if let Some(min_score) = options.min_score {
+            results.retain(|result| result.similarity_score >= min_score);
+        }
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// Full-text matches, else plain term matches
+    async fn keyword_candidates(&self, query: &str, language: &str, limit: usize, filter: &DocumentFilter) -> Result<Vec<SearchResult>> {
+        // Simple keyword-based search using FTS
+        let results = match self.parse_query(query, language) {
+            Some(parsed) => self.fts_search(&parsed, limit, filter)?,
+            None => Vec::new(),
+        };
+
+        // If no FTS results, fall back to simple text matching
+        if results.is_empty() {
+            return self.fallback_search(query, language, limit, filter).await;
+        }
+        Ok(results)
+    }
+
+    /// Scales scores by the ranking config and re-sorts best-first
+    fn apply_ranking(&self, results: &mut [SearchResult]) -> Result<()> {
+        let now = Utc::now();
+        let mut multipliers: HashMap<String, f64> = HashMap::new();
+        for result in results.iter_mut() {
+            let multiplier = match multipliers.get(&result.document_id) {
+                Some(multiplier) => *multiplier,
+                None => {
+                    let (indexed_at, tags) = self.ranking_inputs(&result.document_id)?;
+                    let age_days = indexed_at.map(|at| (now - at).num_milliseconds() as f64 / 86_400_000.0);
+                    let multiplier = self.ranking.multiplier(&result.metadata, &tags, age_days);
+                    multipliers.insert(result.document_id.clone(), multiplier);
+                    multiplier
+                }
+            };
+            result.similarity_score *= multiplier;
+        }
+
+        results.sort_by(|a, b| b.similarity_score.total_cmp(&a.similarity_score));
+        Ok(())
+    }
+
+    fn ranking_inputs(&self, doc_id: &str) -> Result<(Option<DateTime<Utc>>, Vec<String>)> {
+        let mut stmt = self.db.prepare("SELECT indexed_at FROM documents WHERE id = ?")?;
+        stmt.bind((1, doc_id))?;
+        let indexed_at = match stmt.next()? {
+            State::Row => stmt.read::<Option<String>, _>(0)?
+                .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+                .map(|at| at.with_timezone(&Utc)),
+            State::Done => None,
+        };
+
+        let mut stmt = self.db.prepare("SELECT tag FROM document_tags WHERE document_id = ?")?;
+        stmt.bind((1, doc_id))?;
+        let mut tags = Vec::new();
+        while let State::Row = stmt.next()? {
+            tags.push(stmt.read::<String, _>(0)?);
+        }
+        Ok((indexed_at, tags))
+    }
+
+    /// Keeps results best-first, skipping any whose chunk shares at least
+    /// `DUPLICATE_OVERLAP_RATIO` of the shorter range with a kept chunk of the same document
+    fn drop_overlapping(&self, results: Vec<SearchResult>) -> Result<Vec<SearchResult>> {
+        let mut kept: Vec<(SearchResult, Option<(i64, i64)>)> = Vec::new();
+        for result in results {
+            let mut stmt = self.db.prepare("SELECT start_pos, end_pos FROM chunks WHERE id = ?")?;
+            stmt.bind((1, result.chunk_id.as_str()))?;
+            let range = match stmt.next()? {
+                State::Row => Some((stmt.read::<i64, _>(0)?, stmt.read::<i64, _>(1)?)),
+                State::Done => None,
+            };
+
+            let duplicate = range.is_some_and(|(start, end)| {
+                kept.iter().any(|(other, other_range)| {
+                    let Some((other_start, other_end)) = *other_range else { return false };
+                    let shared = end.min(other_end) - start.max(other_start);
+                    let shorter = (end - start).min(other_end - other_start).max(1);
+                    other.document_id == result.document_id
+                        && shared as f64 >= shorter as f64 * DUPLICATE_OVERLAP_RATIO
+                })
+            });
+            if !duplicate {
+                kept.push((result, range));
+            }
+        }
+        Ok(kept.into_iter().map(|(result, _)| result).collect())
+    }
+
+    fn log_query(
+        &self,
+        config: &QueryLogConfig,
+        query: &str,
+        results: &[SearchResult],
+        latency: std::time::Duration,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let _writing = self.query_log_writer.lock().unwrap_or_else(|e| e.into_inner());
+        self.in_transaction(|engine| {
+            let mut stmt = engine.db.prepare(
+                "INSERT INTO query_log (logged_at, query, result_count, top_score, latency_ms) VALUES (?, ?, ?, ?, ?)"
+            )?;
+            stmt.bind((1, timestamp(now).as_str()))?;
+            stmt.bind((2, query.trim()))?;
+            stmt.bind((3, results.len() as i64))?;
+            stmt.bind((4, results.first().map(|r| r.similarity_score)))?;
+            stmt.bind((5, latency.as_secs_f64() * 1000.0))?;
+            stmt.next()?;
+            drop(stmt);
+
+            let mut id_stmt = engine.db.prepare("SELECT last_insert_rowid()")?;
+            id_stmt.next()?;
+            let query_id = id_stmt.read::<i64, _>(0)?;
+
+            // A document counts once per query however many of its chunks matched
+            for result in results {
+                let mut doc_stmt = engine.db.prepare(
+                    "INSERT OR IGNORE INTO query_log_documents (query_id, document_id) VALUES (?, ?)"
+                )?;
+                doc_stmt.bind((1, query_id))?;
+                doc_stmt.bind((2, result.document_id.as_str()))?;
+                doc_stmt.next()?;
+            }
+
+            let mut prune = engine.db.prepare(
+                "DELETE FROM query_log WHERE logged_at < ?
+                    OR id <= (SELECT id FROM query_log ORDER BY id DESC LIMIT 1 OFFSET ?)"
+            )?;
+            prune.bind((1, timestamp(now - config.retention).as_str()))?;
+            prune.bind((2, config.max_entries as i64))?;
+            prune.next()?;
+            if engine.db.change_count() > 0 {
+                engine.db.execute(
+                    "DELETE FROM query_log_documents WHERE query_id NOT IN (SELECT id FROM query_log)"
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Aggregates over logged queries between `since` and `until` (either open-ended),
+    /// with at most `limit` entries per list. Empty unless the query log is enabled.
+    pub async fn query_analytics(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<QueryAnalytics> {
+        let range = (
+            since.map(timestamp).unwrap_or_default(),
+            until.map(timestamp).unwrap_or_else(|| "9999".to_string()),
+        );
+        let in_range = "logged_at >= ? AND logged_at <= ?";
+
+        let mut stmt = self.db.prepare(format!(
+            "SELECT COUNT(*), COALESCE(SUM(result_count = 0), 0) FROM query_log WHERE {}",
+            in_range
+        ))?;
+        stmt.bind((1, range.0.as_str()))?;
+        stmt.bind((2, range.1.as_str()))?;
+        stmt.next()?;
+        let total_queries = stmt.read::<i64, _>(0)? as usize;
+        let zero_result_count = stmt.read::<i64, _>(1)? as usize;
+
+        let most_retrieved = self.document_retrievals(&range, limit, "retrievals DESC, d.id")?;
+        let least_retrieved = self.document_retrievals(&range, limit, "retrievals ASC, d.id")?;
+
+        Ok(QueryAnalytics {
+            since,
+            until,
+            total_queries,
+            zero_result_count,
+            top_queries: self.query_counts(&range, limit, "")?,
+            zero_result_queries: self.query_counts(&range, limit, " AND result_count = 0")?,
+            most_retrieved: most_retrieved.into_iter().filter(|d| d.retrievals > 0).collect(),
+            least_retrieved,
+        })
+    }
+
+    fn query_counts(&self, range: &(String, String), limit: usize, condition: &str) -> Result<Vec<QueryCount>> {
+        let mut stmt = self.db.prepare(format!(
+            "SELECT MIN(query), COUNT(*), AVG(result_count), AVG(latency_ms)
+             FROM query_log
+             WHERE logged_at >= ? AND logged_at <= ?{}
+             GROUP BY lower(query)
+             ORDER BY COUNT(*) DESC, lower(query)
+             LIMIT ?",
+            condition
+        ))?;
+        stmt.bind((1, range.0.as_str()))?;
+        stmt.bind((2, range.1.as_str()))?;
+        stmt.bind((3, limit as i64))?;
+
+        let mut counts = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            counts.push(QueryCount {
+                query: stmt.read::<String, _>(0)?,
+                count: stmt.read::<i64, _>(1)? as usize,
+                avg_result_count: stmt.read::<f64, _>(2)?,
+                avg_latency_ms: stmt.read::<f64, _>(3)?,
+            });
+        }
+        Ok(counts)
+    }
+
+    fn document_retrievals(&self, range: &(String, String), limit: usize, order: &str) -> Result<Vec<DocumentRetrievals>> {
+        let mut stmt = self.db.prepare(format!(
+            "SELECT d.id, d.title, COUNT(q.id) AS retrievals
+             FROM documents d
+             LEFT JOIN query_log_documents r ON r.document_id = d.id
+             LEFT JOIN query_log q ON q.id = r.query_id AND q.logged_at >= ? AND q.logged_at <= ?
+             GROUP BY d.id
+             ORDER BY {}
+             LIMIT ?",
+            order
+        ))?;
+        stmt.bind((1, range.0.as_str()))?;
+        stmt.bind((2, range.1.as_str()))?;
+        stmt.bind((3, limit as i64))?;
+
+        let mut documents = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            documents.push(DocumentRetrievals {
+                document_id: stmt.read::<String, _>(0)?,
+                title: stmt.read::<String, _>(1)?,
+                retrievals: stmt.read::<i64, _>(2)? as usize,
+            });
+        }
+        Ok(documents)
+    }
+
+    /// Documents related to `doc_id`, best first. The document's most frequent
+    /// non-stop-word terms form an OR query whose chunk scores are summed per
+    /// document, excluding the source itself. Returns nothing when the document has
+    /// no usable terms or nothing else matches.
+    pub async fn similar_documents(&self, doc_id: &str, limit: usize) -> Result<Vec<(DocumentInfo, f64)>> {
+        let Some(source) = self.document_info(doc_id).await? else {
+            anyhow::bail!("Unknown document: {}", doc_id);
+        };
+
+        let terms = self.distinctive_terms(&source)?;
+        let Some(query) = terms.into_iter()
+            .map(QueryNode::Term)
+            .reduce(|a, b| QueryNode::Or(Box::new(a), Box::new(b)))
+        else {
+            return Ok(Vec::new());
+        };
+
+        // Enough chunks that a few long documents cannot crowd out the rest
+        let filter = DocumentFilter { exclude_ids: vec![doc_id.to_string()], ..Default::default() };
+        let chunks = self.fts_search(&query, limit.max(1) * 10, &filter)?;
+
+        let mut scores: HashMap<String, f64> = HashMap::new();
+        for chunk in chunks {
+            *scores.entry(chunk.document_id).or_default() += chunk.similarity_score;
+        }
+
+        let mut ranked: Vec<(String, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(limit);
+
+        let mut similar = Vec::new();
+        for (id, score) in ranked {
+            if let Some(info) = self.document_info(&id).await? {
+                similar.push((info, score));
+            }
+        }
+        Ok(similar)
+    }
+
+    /// Most frequent terms of a document's title and leading chunks
+    fn distinctive_terms(&self, document: &DocumentInfo) -> Result<Vec<String>> {
+        let frequencies = self.document_term_frequencies(&document.id)?;
+        Ok(most_frequent(frequencies, SIMILAR_QUERY_TERMS).into_iter().map(|(term, _)| term).collect())
+    }
+
+    /// Term counts over the title and the leading chunks, using the stop words of the
+    /// document's language
+    fn document_term_frequencies(&self, doc_id: &str) -> Result<HashMap<String, usize>> {
+        let mut stmt = self.db.prepare(format!(
+            "SELECT title, json_extract(metadata, '$.{}') FROM documents WHERE id = ?",
+            LANGUAGE_METADATA_KEY
+        ))?;
+        stmt.bind((1, doc_id))?;
+        if stmt.next()? == State::Done {
+            return Ok(HashMap::new());
+        }
+        let mut text = stmt.read::<String, _>(0)?;
+        let language = stmt.read::<Option<String>, _>(1)?.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+
+        let mut stmt = self.db.prepare("SELECT content FROM chunks WHERE document_id = ? ORDER BY start_pos LIMIT ?")?;
+        stmt.bind((1, doc_id))?;
+        stmt.bind((2, SIMILAR_SOURCE_CHUNKS as i64))?;
+        while let Ok(State::Row) = stmt.next() {
+            text.push(' ');
+            text.push_str(&stmt.read::<String, _>(0)?);
+        }
+
+        Ok(term_frequencies(&text, self.stop_words_for(&language)))
+    }
+
+    /// Stored keywords of a document, best first, or None when it does not exist.
+    /// Keywords are TF-IDF weighted against the corpus as it was when the document
+    /// was indexed, so they drift as the corpus grows; `refresh_keywords` (also run
+    /// by `maintenance`) recomputes them all.
+    pub async fn get_keywords(&self, doc_id: &str) -> Result<Option<Vec<String>>> {
+        Ok(self.document_info(doc_id).await?.map(|info| info.keywords))
+    }
+
+    /// Recomputes every document's keywords against the current corpus, returning how many were updated
+    pub async fn refresh_keywords(&mut self) -> Result<usize> {
+        let keywords = self.recompute_keywords()?;
+        self.store_keywords(&keywords)
+    }
+
+    /// Every document's keywords weighed against the current corpus, by
+    /// document id, for `store_keywords`. Only reads, so searches carry on.
+    pub fn recompute_keywords(&self) -> Result<Vec<(String, Vec<String>)>> {
+        self.matching_document_ids(&DocumentFilter::default())?
+            .into_iter()
+            .map(|id| Ok((id.clone(), self.weighted_keywords(&id)?)))
+            .collect()
+    }
+
+    /// Stores keywords from `recompute_keywords` in one transaction,
+    /// returning how many documents they were for
+    pub fn store_keywords(&mut self, keywords: &[(String, Vec<String>)]) -> Result<usize> {
+        self.changed();
+        self.in_transaction(|engine| {
+            for (id, keywords) in keywords {
+                engine.set_keywords(id, keywords)?;
+            }
+            Ok(())
+        })?;
+        Ok(keywords.len())
+    }
+
+    fn update_keywords(&self, doc_id: &str) -> Result<()> {
+        let keywords = self.weighted_keywords(doc_id)?;
+        self.set_keywords(doc_id, &keywords)
+    }
+
+    fn set_keywords(&self, doc_id: &str, keywords: &[String]) -> Result<()> {
+        let mut stmt = self.db.prepare("UPDATE documents SET keywords = ? WHERE id = ?")?;
+        stmt.bind((1, serde_json::to_string(keywords)?.as_str()))?;
+        stmt.bind((2, doc_id))?;
+        stmt.next()?;
+        Ok(())
+    }
+
+    fn weighted_keywords(&self, doc_id: &str) -> Result<Vec<String>> {
+        let candidates = most_frequent(self.document_term_frequencies(doc_id)?, KEYWORD_CANDIDATES);
+        let total_chunks = self.count_rows("FROM chunks")? as f64;
+
+        // Chunk-level document frequency straight from the FTS index
+        let mut weighted = Vec::new();
+        for (term, count) in candidates {
+            let mut stmt = self.db.prepare("SELECT doc FROM chunks_vocab WHERE term = ?")?;
+            stmt.bind((1, term.as_str()))?;
+            let chunks_with_term = match stmt.next()? {
+                State::Row => stmt.read::<i64, _>(0)? as f64,
+                State::Done => 0.0,
+            };
+            let idf = ((total_chunks + 1.0) / (chunks_with_term + 1.0)).ln() + 1.0;
+            weighted.push((term, count as f64 * idf));
+        }
+        weighted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+
+        Ok(weighted.into_iter().take(KEYWORDS_PER_DOCUMENT).map(|(term, _)| term).collect())
+    }
+
+    /// Extractive answers: the sentences from the best matching chunks that score
+    /// highest against the query terms. Each result's `content` is a single sentence,
+    /// with document and chunk provenance kept in the other fields.
+    pub async fn query_answers(&self, query: &str, max_sentences: usize, options: &QueryOptions) -> Result<Vec<SearchResult>> {
+        let (text, _) = extract_filters(query);
+        let language = self.detect_language(&text);
+        let Some(parsed) = self.parse_query(&text, &language) else {
+            return Ok(Vec::new());
+        };
+        let stop_words = self.stop_words_for(&language);
+
+        let chunks = self.search(query, ANSWER_CANDIDATE_CHUNKS, options).await?;
+
+        let mut seen = HashSet::new();
+        let mut candidates = Vec::new();
+        for (rank, chunk) in chunks.iter().enumerate() {
+            for sentence in split_sentences(&chunk.content) {
+                let words: Vec<String> = sentence
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter(|w| !w.is_empty())
+                    .map(|w| w.to_lowercase())
+                    .collect();
+                // Too short, or nothing but stop words: never a useful answer
+                if words.len() < MIN_ANSWER_WORDS || words.iter().all(|w| stop_words.contains(w)) {
+                    continue;
+                }
+
+                let score = parsed.score(&sentence.to_lowercase());
+                // Overlapping chunks repeat sentences
+                if score <= 0.0 || !seen.insert(sentence.to_string()) {
+                    continue;
+                }
+
+                candidates.push((rank, SearchResult {
+                    content: sentence.to_string(),
+                    similarity_score: score,
+                    ..chunk.clone()
+                }));
+            }
+        }
+
+        // Best sentence first; ties go to the better ranked chunk
+        candidates.sort_by(|a, b| {
+            b.1.similarity_score.partial_cmp(&a.1.similarity_score).unwrap().then(a.0.cmp(&b.0))
+        });
+
+        Ok(candidates.into_iter().take(max_sentences).map(|(_, sentence)| sentence).collect())
+    }
+
+    /// Finds documents whose metadata `key` matches `value_pattern` (`*` wildcard, case-insensitive)
+    pub async fn query_metadata(&self, key: &str, value_pattern: &str) -> Result<Vec<Document>> {
+        let filter = DocumentFilter {
+            metadata: HashMap::from([(key.to_string(), value_pattern.to_string())]),
+            ..Default::default()
+        };
+        let (filter_sql, binds) = filter_sql(&filter);
+
+        let mut stmt = self.db.prepare(format!(
+            "SELECT d.id, d.title, d.content, d.metadata
+             FROM documents d
+             WHERE 1 = 1{}
+             ORDER BY d.id",
+            filter_sql
+        ))?;
+        for (i, value) in binds.iter().enumerate() {
+            stmt.bind((i + 1, value.as_str()))?;
+        }
+
+        let mut documents = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            let metadata: String = stmt.read::<String, _>(3)?;
+            documents.push(Document {
+                id: stmt.read::<String, _>(0)?,
+                title: stmt.read::<String, _>(1)?,
+                content: stmt.read::<String, _>(2)?,
+                metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+                embedding: None,
+                chunks: vec![],
+            });
+        }
+
+        Ok(documents)
+    }
+
+    // Kept synchronous so the (non-Send) prepared statement never lives across an await point
+    fn fts_search(&self, parsed: &QueryNode, limit: usize, filter: &DocumentFilter) -> Result<Vec<SearchResult>> {
+        // A half-built index would miss whatever it has yet to reach
+        if let Some(cursor) = self.fts_rebuild {
+            return match self.fts_rebuild_policy {
+                FtsRebuildPolicy::Fallback => Ok(Vec::new()),
+                FtsRebuildPolicy::Refuse => Err(FtsRebuilding { processed: cursor.processed, total: cursor.total }.into()),
+            };
+        }
+        let (filter_sql, binds) = filter_sql(filter);
+        let mut stmt = self.db.prepare(format!(
+            "SELECT c.id, c.content, c.document_id, d.title, d.metadata,
+                    bm25(chunks_fts, 0.0, ?, 1.0) AS score
+             FROM chunks_fts
+             JOIN chunks c ON chunks_fts.chunk_id = c.id
+             JOIN documents d ON c.document_id = d.id
+             WHERE chunks_fts MATCH ?{}
+             ORDER BY score
+             LIMIT ?",
+            filter_sql
+        ))?;
+        
+        stmt.bind((1, self.title_boost))?;
+        stmt.bind((2, parsed.to_fts().as_str()))?;
+        for (i, value) in binds.iter().enumerate() {
+            stmt.bind((i + 3, value.as_str()))?;
+        }
+        stmt.bind((binds.len() + 3, limit as i64))?;
+
+        let mut results = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            let metadata: String = stmt.read::<String, _>(4)?;
+            let score: f64 = stmt.read::<f64, _>(5)?;
+
+            let content: String = stmt.read::<String, _>(1)?;
+            let title: String = stmt.read::<String, _>(3)?;
+
+            results.push(SearchResult {
+                chunk_id: stmt.read::<String, _>(0)?,
+                document_id: stmt.read::<String, _>(2)?,
+                similarity_score: -score, // bm25 scores are negative, lower is better
+                metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+                matched_fields: matched_fields(parsed, &self.normalization.apply(&title), &content, filter),
+                content,
+                title,
+            });
+        }
+
+        Ok(results)
+    }
+
+    async fn fallback_search(
+        &self,
+        query: &str,
+        language: &str,
+        limit: usize,
+        filter: &DocumentFilter,
+    ) -> Result<Vec<SearchResult>> {
+        let Some(parsed) = self.parse_query(query, language) else {
+            return Ok(Vec::new());
+        };
+
+        let (filter_sql, binds) = filter_sql(filter);
+        let mut stmt = self.db.prepare(format!(
+            "SELECT c.id, c.content, c.document_id, d.title, d.metadata
+             FROM chunks c
+             JOIN documents d ON c.document_id = d.id
+             WHERE 1 = 1{}
+             LIMIT ?",
+            filter_sql
+        ))?;
+        
+        for (i, value) in binds.iter().enumerate() {
+            stmt.bind((i + 1, value.as_str()))?;
+        }
+        stmt.bind((binds.len() + 1, (limit * 5) as i64))?; // Get more candidates for filtering
+
+        let mut candidates = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            let content: String = stmt.read::<String, _>(1)?;
+            let title: String = stmt.read::<String, _>(3)?;
+
+            // Simple relevance scoring, honouring AND/OR/NOT across title and content,
+            // with title hits weighted like the FTS column boost
+            let search_title = self.normalization.apply(&title);
+            let whole = parsed.score(&format!("{}\n{}", search_title, content).to_lowercase());
+            let score = if whole > 0.0 {
+                whole + (self.title_boost - 1.0) * parsed.score(&search_title.to_lowercase())
+            } else {
+                0.0
+            };
+
+            if score > 0.0 {
+                let metadata: String = stmt.read::<String, _>(4)?;
+                candidates.push(SearchResult {
+                    chunk_id: stmt.read::<String, _>(0)?,
+                    document_id: stmt.read::<String, _>(2)?,
+                    similarity_score: score,
+                    metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+                    matched_fields: matched_fields(&parsed, &search_title, &content, filter),
+                    content,
+                    title,
+                });
+            }
+        }
+
+        // Sort by relevance and take top results
+        candidates.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+        candidates.truncate(limit);
+
+        Ok(candidates)
+    }
+
+    /// Picks the language whose stop words occur most often in `text`,
+    /// defaulting to English when nothing matches
+    pub fn detect_language(&self, text: &str) -> String {
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+            .collect();
+
+        let mut best = (DEFAULT_LANGUAGE, 0);
+        let mut languages: Vec<&String> = self.stop_words.keys().collect();
+        languages.sort(); // Deterministic tie-breaking
+
+        for language in languages {
+            let stop_words = &self.stop_words[language];
+            let hits = words.iter().filter(|w| stop_words.contains(*w)).count();
+            if hits > best.1 || (hits == best.1 && hits > 0 && language == DEFAULT_LANGUAGE) {
+                best = (language, hits);
+            }
+        }
+
+        best.0.to_string()
+    }
+
+    fn stop_words_for(&self, language: &str) -> &HashSet<String> {
+        self.stop_words.get(language)
+            .unwrap_or_else(|| &self.stop_words[DEFAULT_LANGUAGE])
+    }
+
+    fn create_chunks(&self, content: &str, doc_id: &str) -> Vec<DocumentChunk> {
+        let chars: Vec<char> = content.chars().collect();
+        let boundaries = self.chunk_boundaries(&chars);
+
+        let build = |index: usize, &(start, end): &(usize, usize)| {
+            make_chunk(doc_id, index, &chars[start..end], (start, end), self.normalization)
+        };
+
+        let workers = self.chunk_parallelism.max(1);
+        if workers == 1 || boundaries.len() < PARALLEL_CHUNK_THRESHOLD {
+            return boundaries.iter().enumerate().map(|(i, b)| build(i, b)).collect();
+        }
+
+        // Contiguous slices per worker, concatenated in order, so ids and order match the serial path
+        let per_worker = boundaries.len().div_ceil(workers);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = boundaries.chunks(per_worker)
+                .enumerate()
+                .map(|(w, slice)| {
+                    let build = &build;
+                    scope.spawn(move || {
+                        slice.iter()
+                            .enumerate()
+                            .map(|(i, b)| build(w * per_worker + i, b))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles.into_iter()
+                .flat_map(|handle| handle.join().expect("chunk worker panicked"))
+                .collect()
+        })
+    }
+
+    /// Char ranges of each chunk. Each chunk's start depends on where the previous
+    /// one ended, so this scan is inherently sequential.
+    fn chunk_boundaries(&self, chars: &[char]) -> Vec<(usize, usize)> {
+        let mut boundaries = Vec::new();
+        let mut start = 0;
+
+        while start < chars.len() {
+            let end = self.chunk_end(chars, start);
+            boundaries.push((start, end));
+
+            // The final chunk reached the end of the content
+            if end >= chars.len() {
+                break;
+            }
+            start = self.next_chunk_start(start, end);
+        }
+
+        boundaries
+    }
+
+    /// End of the chunk starting at `start`, treating the end of `chars` as the end of the content
+    fn chunk_end(&self, chars: &[char], start: usize) -> usize {
+        let end = std::cmp::min(start + self.chunk_size, chars.len());
+        
+        // Try to break at sentence boundaries
+        if end < chars.len() {
+            for i in (start + self.chunk_size.saturating_sub(100)..end).rev() {
+                if chars[i] == '.' || chars[i] == '!' || chars[i] == '?' {
+                    return i + 1;
+                }
+            }
+        }
+        end
+    }
+
+    /// Move start position with overlap, always making progress
+    fn next_chunk_start(&self, start: usize, end: usize) -> usize {
+        end.saturating_sub(self.overlap_size).max(start + 1)
+    }
+
+    // Remove stop words and parse boolean operators; rendered to FTS5 MATCH syntax via QueryNode::to_fts
+    fn parse_query(&self, query: &str, language: &str) -> Option<QueryNode> {
+        let stop_words = self.stop_words_for(language);
+        let tokens = tokenize_query(&self.normalization.apply(query));
+        let mut parser = QueryParser {
+            tokens: &tokens,
+            pos: 0,
+            stop_words,
+        };
+
+        match parser.parse() {
+            Ok(node) => node,
+            Err(()) => {
+                // Malformed operator usage: OR every plain word together, as before
+                tracing::debug!("Malformed boolean query, falling back to OR: {}", query);
+                tokens.into_iter()
+                    .filter_map(|token| match token {
+                        QueryToken::Word(word) if !stop_words.contains(&word.to_lowercase()) => {
+                            Some(QueryNode::Term(word))
+                        }
+                        _ => None,
+                    })
+                    .reduce(|a, b| QueryNode::Or(Box::new(a), Box::new(b)))
+            }
+        }
+    }
+
+    pub async fn index_void_shrine_knowledge(&mut self) -> Result<()> {
+        // Index some void shrine specific knowledge
+        let void_shrine_docs = vec![
+            Document {
+                id: "void_shrine_principles".to_string(),
+                title: "Void Shrine Core Principles".to_string(),
+                content: "Void-first design emphasizes absence as foundational architectural principle. \
+                         Swarm intelligence enables collective behavior from individual agents. \
+                         Emergence over engineering allows complex behaviors from simple rules. \
+                         Reality bootstrap means implementation creates its own truth through hyperstitious computing.".to_string(),
+                metadata: {
+                    let mut map = HashMap::new();
+                    map.insert("category".to_string(), "philosophy".to_string());
+                    map.insert("source".to_string(), "void_shrine_constitution".to_string());
+                    map
+                },
+                embedding: None,
+                chunks: vec![],
+            },
+            Document {
+                id: "agent_coordination".to_string(),
+                title: "Multi-Agent Coordination Patterns".to_string(),
+                content: "Agent orchestration involves tactical coordination through strategic analysis, \
+                         scientific investigation through pattern recognition, operational implementation through \
+                         systematic execution, and engineering solutions through technical architecture. \
+                         Creative synthesis generates innovative approaches while research provides knowledge synthesis.".to_string(),
+                metadata: {
+                    let mut map = HashMap::new();
+                    map.insert("category".to_string(), "technical".to_string());
+                    map.insert("source".to_string(), "orchestration_manual".to_string());
+                    map
+                },
+                embedding: None,
+                chunks: vec![],
+            },
+            Document {
+                id: "care_ethics".to_string(),
+                title: "Care Ethics Framework".to_string(),
+                content: "Care ethics prioritizes relational wellbeing and stakeholder agency. \
+                         Moral recentering applies care-focused perspectives to technical decisions. \
+                         Ethical considerations include social impact assessment, accessibility priorities, \
+                         and sustainable design principles that center collective flourishing.".to_string(),
+                metadata: {
+                    let mut map = HashMap::new();
+                    map.insert("category".to_string(), "ethics".to_string());
+                    map.insert("source".to_string(), "moral_framework".to_string());
+                    map
+                },
+                embedding: None,
+                chunks: vec![],
+            },
+        ];
+
+        for doc in void_shrine_docs {
+            self.index_document(doc).await?;
+        }
+
+        tracing::info!("Indexed void shrine knowledge base");
+        Ok(())
+    }
+
+    pub async fn get_stats(&self) -> Result<RAGStats> {
+        let mut doc_stmt = self.db.prepare("SELECT COUNT(*) FROM documents")?;
+        doc_stmt.next()?;
+        let doc_count: i64 = doc_stmt.read(0)?;
+
+        let mut chunk_stmt = self.db.prepare("SELECT COUNT(*) FROM chunks")?;
+        chunk_stmt.next()?;
+        let chunk_count: i64 = chunk_stmt.read(0)?;
+
+        Ok(RAGStats {
+            document_count: doc_count as usize,
+            chunk_count: chunk_count as usize,
+            chunk_size: self.chunk_size,
+            overlap_size: self.overlap_size,
+            title_index: self.has_title_index()?,
+            tokenizer: self.tokenizer.clone(),
+            journal_mode: self.pragma_value("journal_mode")?,
+        })
+    }
+
+    /// Copies WAL contents into the database file and truncates the WAL. Worth
+    /// calling after large ingests, since queries slow down as the WAL grows.
+    pub async fn checkpoint(&self) -> Result<CheckpointStats> {
+        let mut stmt = self.db.prepare("PRAGMA wal_checkpoint(TRUNCATE)")?;
+        stmt.next()?;
+        Ok(CheckpointStats {
+            busy: stmt.read::<i64, _>(0)? != 0,
+            log_frames: stmt.read::<i64, _>(1)?,
+            checkpointed_frames: stmt.read::<i64, _>(2)?,
+        })
+    }
+
+    /// Writes a consistent snapshot of the whole index to a new file at `path` with
+    /// `VACUUM INTO`, which reads inside one transaction, so concurrent writers on other
+    /// connections never produce a torn copy. Fails if `path` already exists.
+    pub async fn backup_to(&self, path: &Path) -> Result<BackupReport> {
+        if path.exists() {
+            anyhow::bail!("Backup destination already exists: {}", path.display());
+        }
+
+        let started = std::time::Instant::now();
+        let mut stmt = self.db.prepare("VACUUM INTO ?")?;
+        stmt.bind((1, path.to_string_lossy().as_ref()))?;
+        stmt.next()?;
+        drop(stmt);
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let copy = Connection::open(path)?;
+        let mut stmt = copy.prepare("SELECT page_count, page_size FROM pragma_page_count(), pragma_page_size()")?;
+        stmt.next()?;
+        let pages = stmt.read::<i64, _>(0)? as u64;
+        let page_size = stmt.read::<i64, _>(1)? as u64;
+
+        tracing::info!("Backed up index to {} ({} pages in {}ms)", path.display(), pages, duration_ms);
+        Ok(BackupReport {
+            path: path.to_path_buf(),
+            pages,
+            page_size,
+            bytes: pages * page_size,
+            duration_ms,
+        })
+    }
+
+    /// Checks the FTS index and the chunk/document relationships, optionally repairs
+    /// what it finds, refreshes keywords, then compacts the database to reclaim space
+    /// left by deletes. `VoidShrineMCP::handle_maintenance` runs the same steps one
+    /// at a time, holding the engine exclusively only for `repair` and `store_keywords`.
+    pub async fn maintenance(&mut self, repair: bool) -> Result<MaintenanceReport> {
+        let started = std::time::Instant::now();
+        let mut report = self.inspect()?;
+        if repair {
+            self.repair(&mut report)?;
+        }
+        let keywords = self.recompute_keywords()?;
+        report.keywords_refreshed = self.store_keywords(&keywords)?;
+        self.compact(&mut report)?;
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        tracing::info!("Maintenance finished: {:?}", report);
+        Ok(report)
+    }
+
+    /// What `maintenance` finds, without fixing any of it. Only reads, so
+    /// searches carry on.
+    pub fn inspect(&self) -> Result<MaintenanceReport> {
+        // Chunks the rebuild has yet to reach would be counted, and repaired, as unindexed
+        if let Some(cursor) = self.fts_rebuild {
+            return Err(FtsRebuilding { processed: cursor.processed, total: cursor.total }.into());
+        }
+        let mut report = MaintenanceReport {
+            bytes_before: self.database_bytes()?,
+            ..Default::default()
+        };
+
+        if let Err(e) = self.db.execute("INSERT INTO chunks_fts (chunks_fts) VALUES ('integrity-check')") {
+            report.fts_integrity_error = Some(e.to_string());
+        }
+
+        report.orphaned_chunks = self.count_rows(ORPHANED_CHUNKS)?;
+        report.orphaned_fts_rows = self.count_rows(ORPHANED_FTS_ROWS)?;
+        report.unindexed_chunks = self.count_rows(UNINDEXED_CHUNKS)?;
+        report.orphaned_tags = self.count_rows(ORPHANED_TAGS)?;
+        Ok(report)
+    }
+
+    /// Fixes what `inspect` found, in one transaction
+    pub fn repair(&mut self, report: &mut MaintenanceReport) -> Result<()> {
+        if let Some(cursor) = self.fts_rebuild {
+            return Err(FtsRebuilding { processed: cursor.processed, total: cursor.total }.into());
+        }
+        self.changed();
+        let rebuild = report.fts_integrity_error.is_some();
+        self.in_transaction(|engine| {
+            // Orphaned chunks go first so their FTS rows are then caught as orphans too
+            engine.db.execute(format!("DELETE {}", ORPHANED_CHUNKS))?;
+            engine.db.execute(format!("DELETE {}", ORPHANED_FTS_ROWS))?;
+            engine.db.execute(
+                "INSERT INTO chunks_fts (chunk_id, title, content)
+                 SELECT c.id, d.title, c.content
+                 FROM chunks c JOIN documents d ON d.id = c.document_id
+                 WHERE c.id NOT IN (SELECT chunk_id FROM chunks_fts)"
+            )?;
+            engine.normalize_fts_titles()?;
+            engine.db.execute(format!("DELETE {}", ORPHANED_TAGS))?;
+            if rebuild {
+                engine.db.execute("INSERT INTO chunks_fts (chunks_fts) VALUES ('rebuild')")?;
+            }
+            Ok(())
+        })?;
+        report.repaired = true;
+        Ok(())
+    }
+
+    /// Reclaims space left by deletes and reindexes, recording the size after.
+    /// A file database is VACUUMed through a second connection, since VACUUM
+    /// can't run beside statements on this one; searches keep reading from
+    /// the last snapshot under WAL, and wait out the rewrite otherwise. An
+    /// in-memory database has no second connection and only gets `PRAGMA optimize`.
+    pub fn compact(&self, report: &mut MaintenanceReport) -> Result<()> {
+        match &self.path {
+            Some(path) => {
+                let db = Connection::open(path)?;
+                db.execute(format!("PRAGMA busy_timeout = {}", self.busy_timeout.as_millis()))?;
+                db.execute("VACUUM")?;
+            }
+            None => self.db.execute("PRAGMA optimize")?,
+        }
+        report.bytes_after = self.database_bytes()?;
+        Ok(())
+    }
+
+    fn count_rows(&self, from_where: &str) -> Result<usize> {
+        let mut stmt = self.db.prepare(format!("SELECT COUNT(*) {}", from_where))?;
+        stmt.next()?;
+        Ok(stmt.read::<i64, _>(0)? as usize)
+    }
+
+    fn database_bytes(&self) -> Result<u64> {
+        let mut stmt = self.db.prepare("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()")?;
+        stmt.next()?;
+        Ok(stmt.read::<i64, _>(0)? as u64)
+    }
+
+    fn pragma_value(&self, name: &str) -> Result<String> {
+        let mut stmt = self.db.prepare(format!("PRAGMA {}", name))?;
+        stmt.next()?;
+        Ok(stmt.read::<String, _>(0)?)
+    }
+
+    /// Document, chunk and content totals for each distinct value of `metadata_key`
+    pub async fn stats_by(&self, metadata_key: &str) -> Result<Vec<GroupStats>> {
+        let mut stmt = self.db.prepare(
+            "SELECT json_extract(d.metadata, ?) AS value,
+                    COUNT(*),
+                    COALESCE(SUM(c.chunk_count), 0),
+                    COALESCE(SUM(COALESCE(d.content_bytes, length(CAST(d.content AS BLOB)))), 0),
+                    MAX(d.indexed_at)
+             FROM documents d
+             LEFT JOIN (SELECT document_id, COUNT(*) AS chunk_count FROM chunks GROUP BY document_id) c
+                 ON c.document_id = d.id
+             GROUP BY value
+             ORDER BY value"
+        )?;
+        stmt.bind((1, metadata_path(metadata_key).as_str()))?;
+
+        let mut groups = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            let last_indexed_at = stmt.read::<Option<String>, _>(4)?
+                .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+                .map(|at| at.with_timezone(&Utc));
+            groups.push(GroupStats {
+                value: stmt.read::<Option<String>, _>(0)?,
+                document_count: stmt.read::<i64, _>(1)? as usize,
+                chunk_count: stmt.read::<i64, _>(2)? as usize,
+                content_bytes: stmt.read::<i64, _>(3)? as u64,
+                last_indexed_at,
+            });
+        }
+        Ok(groups)
+    }
+
+    fn has_title_index(&self) -> Result<bool> {
+        let mut stmt = self.db.prepare("SELECT name FROM pragma_table_info('chunks_fts')")?;
+        while let Ok(State::Row) = stmt.next() {
+            if stmt.read::<String, _>(0)? == "title" {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Totals for the documents sharing one value of a metadata key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupStats {
+    /// None groups the documents that lack the key
+    pub value: Option<String>,
+    pub document_count: usize,
+    pub chunk_count: usize,
+    pub content_bytes: u64,
+    /// None when every document in the group predates indexing timestamps
+    pub last_indexed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RAGStats {
+    pub document_count: usize,
+    pub chunk_count: usize,
+    pub chunk_size: usize,
+    pub overlap_size: usize,
+    /// False for databases created before titles were added to chunks_fts
+    pub title_index: bool,
+    pub tokenizer: String,
+    /// As reported by SQLite, e.g. "wal" or "memory", to confirm tuning took effect
+    pub journal_mode: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn knowledge_base() -> RAGEngine {
+        let mut rag = RAGEngine::new().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        rag
+    }
+
+    fn matched_documents(results: &[String]) -> Vec<&'static str> {
+        let mut ids: Vec<&'static str> = ["void_shrine_principles", "agent_coordination", "care_ethics"]
+            .into_iter()
+            .filter(|id| results.iter().any(|r| r.contains(&format!("({})", id))))
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn boolean_queries_match_expected_chunks() {
+        let rag = knowledge_base().await;
+
+        let cases: &[(&str, &[&str])] = &[
+            ("design", &["care_ethics", "void_shrine_principles"]),
+            ("care AND ethics", &["care_ethics"]),
+            ("design NOT void", &["care_ethics"]),
+            ("synthesis NOT creative", &[]),
+            ("emergence OR ethics", &["care_ethics", "void_shrine_principles"]),
+            ("swarm AND ethics", &[]),
+            ("(care OR swarm) AND collective", &["care_ethics", "void_shrine_principles"]),
+            ("emergence ethics", &["care_ethics", "void_shrine_principles"]),
+            // Malformed operator usage degrades to OR over the plain words
+            ("AND care", &["care_ethics"]),
+            ("(care ethics", &["care_ethics"]),
+            ("swarm NOT", &["void_shrine_principles"]),
+        ];
+
+        for (query, expected) in cases {
+            let results = rag.query(query, 10).await.unwrap();
+            assert_eq!(matched_documents(&results), *expected, "query: {}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn fallback_scorer_honours_boolean_operators() {
+        let rag = knowledge_base().await;
+
+        let cases: &[(&str, &[&str])] = &[
+            ("design NOT void", &["care_ethics"]),
+            ("care AND ethics", &["care_ethics"]),
+            ("swarm AND ethics", &[]),
+        ];
+
+        for (query, expected) in cases {
+            let results: Vec<String> = rag.fallback_search(query, DEFAULT_LANGUAGE, 10, &DocumentFilter::default()).await.unwrap()
+                .iter()
+                .map(SearchResult::to_context_string)
+                .collect();
+            assert_eq!(matched_documents(&results), *expected, "query: {}", query);
+        }
+    }
+
+    #[test]
+    fn operators_translate_to_fts_syntax() {
+        let stop_words = ["the".to_string()].into_iter().collect();
+        let parse = |query: &str| {
+            let tokens = tokenize_query(query);
+            QueryParser { tokens: &tokens, pos: 0, stop_words: &stop_words }
+                .parse()
+                .map(|node| node.map(|n| n.to_fts()))
+        };
+
+        assert_eq!(parse("agents NOT creative"), Ok(Some("(\"agents\" NOT \"creative\")".to_string())));
+        assert_eq!(parse("a OR b AND c"), Ok(Some("(\"a\" OR (\"b\" AND \"c\"))".to_string())));
+        assert_eq!(parse("the AND care"), Ok(Some("\"care\"".to_string())));
+        assert_eq!(parse("the"), Ok(None));
+        assert_eq!(parse("care AND"), Err(()));
+        assert_eq!(parse("care)"), Err(()));
+    }
+
+    #[tokio::test]
+    async fn detects_dominant_language() {
+        let rag = RAGEngine::new().await.unwrap();
+
+        assert_eq!(rag.detect_language("What are the core principles of the void shrine?"), "en");
+        assert_eq!(rag.detect_language("La ética del cuidado y los agentes de la red"), "es");
+        assert_eq!(rag.detect_language("Die Schwarmintelligenz und das kollektive Verhalten der Agenten"), "de");
+        assert_eq!(rag.detect_language("Le vide est une architecture pour les agents"), "fr");
+        // Mostly German with an English phrase still counts as German
+        assert_eq!(rag.detect_language("Die Agenten und der Schwarm sind in der Leere, the void"), "de");
+        assert_eq!(rag.detect_language("xyzzy"), DEFAULT_LANGUAGE);
+    }
+
+    #[tokio::test]
+    async fn search_results_expose_document_language() {
+        let mut rag = knowledge_base().await;
+        rag.index_document(Document {
+            id: "cuidado".to_string(),
+            title: "Ética del cuidado".to_string(),
+            content: "La ética del cuidado prioriza el bienestar de los agentes y la comunidad.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        }).await.unwrap();
+
+        // "la" and "de" are Spanish stop words, so only the content words are searched
+        let results = rag.search("la ética de los agentes", 10, &QueryOptions::default()).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_id, "cuidado");
+        assert_eq!(results[0].metadata.get(LANGUAGE_METADATA_KEY).map(String::as_str), Some("es"));
+
+        let results = rag.search("care ethics", 10, &QueryOptions::default()).await.unwrap();
+        assert_eq!(results[0].metadata.get(LANGUAGE_METADATA_KEY).map(String::as_str), Some("en"));
+    }
+
+    #[tokio::test]
+    async fn explicit_language_hint_selects_stop_words() {
+        let rag = knowledge_base().await;
+
+        // Under the Spanish list "a" is a stop word but "the" is searched for
+        let hinted = QueryOptions { language: Some("es".to_string()), ..Default::default() };
+        let results = rag.search("the", 10, &hinted).await.unwrap();
+        assert!(!results.is_empty());
+
+        let results = rag.search("the", 10, &QueryOptions::default()).await.unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn query_metadata_matches_values_and_wildcards() {
+        let rag = knowledge_base().await;
+
+        let ids = |docs: Vec<Document>| docs.into_iter().map(|d| d.id).collect::<Vec<_>>();
+        assert_eq!(ids(rag.query_metadata("source", "orchestration_manual").await.unwrap()), ["agent_coordination"]);
+        assert_eq!(ids(rag.query_metadata("source", "*_framework").await.unwrap()), ["care_ethics"]);
+        assert_eq!(ids(rag.query_metadata("category", "TECHNICAL").await.unwrap()), ["agent_coordination"]);
+        // `_` is literal, not LIKE's single-character wildcard
+        assert!(rag.query_metadata("source", "orchestration_manua_").await.unwrap().is_empty());
+        assert!(rag.query_metadata("missing", "*").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn combined_content_and_metadata_queries() {
+        let rag = knowledge_base().await;
+
+        // "design" alone matches the philosophy and ethics documents
+        let results = rag.search("design AND metadata.category=ethics", 10, &QueryOptions::default()).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_id, "care_ethics");
+        assert_eq!(results[0].matched_fields, ["content", "metadata.category"]);
+
+        let options = QueryOptions {
+            metadata_filters: HashMap::from([("source".to_string(), "void_shrine_*".to_string())]),
+            ..Default::default()
+        };
+        let results = rag.search("design", 10, &options).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_id, "void_shrine_principles");
+        assert_eq!(results[0].matched_fields, ["content", "metadata.source"]);
+
+        let results = rag.search("design", 10, &QueryOptions::default()).await.unwrap();
+        assert!(results.iter().all(|r| r.matched_fields == ["content"]));
+    }
+
+    #[tokio::test]
+    async fn title_matches_outrank_body_mentions() {
+        let doc = |id: &str, title: &str| Document {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: "Agents exchange messages through coordination patterns and shared memory.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        };
+
+        let mut rag = RAGEngine::new().await.unwrap();
+        // Identical bodies: only the title tells the two documents apart
+        rag.index_document(doc("notes", "Miscellaneous Notes")).await.unwrap();
+        rag.index_document(doc("patterns", "Multi-Agent Coordination Patterns")).await.unwrap();
+
+        let results = rag.search("coordination patterns", 10, &QueryOptions::default()).await.unwrap();
+        let order: Vec<&str> = results.iter().map(|r| r.document_id.as_str()).collect();
+        assert_eq!(order, ["patterns", "notes"]);
+        assert!(results[0].similarity_score > results[1].similarity_score);
+
+        // A heavier boost widens the gap between the two
+        let gap = results[0].similarity_score - results[1].similarity_score;
+        let mut boosted = RAGEngine::new().await.unwrap().with_title_boost(10.0);
+        boosted.index_document(doc("notes", "Miscellaneous Notes")).await.unwrap();
+        boosted.index_document(doc("patterns", "Multi-Agent Coordination Patterns")).await.unwrap();
+        let results = boosted.search("coordination patterns", 10, &QueryOptions::default()).await.unwrap();
+        assert_eq!(results[0].document_id, "patterns");
+        assert!(results[0].similarity_score - results[1].similarity_score > gap);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "title boost must be finite and positive")]
+    async fn non_finite_title_boosts_are_refused() {
+        let _ = RAGEngine::new().await.unwrap().with_title_boost(f64::NAN);
+    }
+
+    #[tokio::test]
+    async fn literal_title_queries_match_in_both_paths() {
+        let rag = knowledge_base().await;
+
+        let results = rag.search("Void Shrine Core Principles", 10, &QueryOptions::default()).await.unwrap();
+        assert_eq!(results[0].document_id, "void_shrine_principles");
+        assert!(results[0].matched_fields.contains(&"title".to_string()));
+
+        // "framework" only occurs in a title
+        let results = rag.fallback_search("framework", DEFAULT_LANGUAGE, 10, &DocumentFilter::default()).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_id, "care_ethics");
+        assert_eq!(results[0].matched_fields, ["title"]);
+
+        assert!(rag.get_stats().await.unwrap().title_index);
+    }
+
+    #[tokio::test]
+    async fn reindexing_and_deleting_keep_title_index_consistent() {
+        let mut rag = knowledge_base().await;
+        let retitled = Document {
+            id: "care_ethics".to_string(),
+            title: "Relational Wellbeing Handbook".to_string(),
+            content: "Care ethics prioritizes relational wellbeing and stakeholder agency.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        };
+        rag.index_document(retitled).await.unwrap();
+
+        let options = QueryOptions::default();
+        assert!(rag.search("framework", 10, &options).await.unwrap().is_empty());
+        assert_eq!(rag.search("handbook", 10, &options).await.unwrap()[0].document_id, "care_ethics");
+        assert_eq!(rag.get_stats().await.unwrap().chunk_count, 3);
+
+        assert!(rag.delete_document("care_ethics").await.unwrap());
+        assert!(!rag.delete_document("care_ethics").await.unwrap());
+        assert!(rag.search("handbook", 10, &options).await.unwrap().is_empty());
+
+        let stats = rag.get_stats().await.unwrap();
+        assert_eq!((stats.document_count, stats.chunk_count), (2, 2));
+    }
+
+    async fn serve_pages() -> std::net::SocketAddr {
+        use warp::Filter;
+
+        let page = warp::path("page").map(|| warp::reply::html(
+            "<html><head><title>Wiki &amp; Notes</title><style>p { color: red }</style></head>\
+             <body><h1>Swarm</h1><p>Stigmergy lets agents coordinate&nbsp;indirectly.</p>\
+             <script>var hidden = 1;</script></body></html>"
+        ));
+        let missing = warp::path("missing").map(|| {
+            warp::reply::with_status(warp::reply::html("gone"), warp::http::StatusCode::NOT_FOUND)
+        });
+        let json = warp::path("json").map(|| warp::reply::json(&serde_json::json!({"a": 1})));
+        let big = warp::path("big").map(|| warp::reply::html("x".repeat(4096)));
+        let looping = warp::path("loop").map(|| {
+            warp::redirect::temporary(warp::http::Uri::from_static("/loop"))
+        });
+
+        let (addr, server) = warp::serve(page.or(missing).or(json).or(big).or(looping))
+            .bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn index_url_fetches_and_indexes_html() {
+        let addr = serve_pages().await;
+        let mut rag = RAGEngine::new().await.unwrap();
+
+        let url = format!("http://{}/page", addr);
+        let id = rag.index_url(&url).await.unwrap();
+        assert_eq!(id, url_document_id(&url));
+        assert!(id.starts_with(&format!("url_http___127_0_0_1_{}_page_", addr.port())), "{}", id);
+
+        let results = rag.search("stigmergy", 10, &QueryOptions::default()).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "Wiki & Notes");
+        assert_eq!(results[0].content, "Swarm\nStigmergy lets agents coordinate indirectly.");
+        assert_eq!(results[0].metadata.get("url"), Some(&url));
+        assert!(results[0].metadata.contains_key("fetched_at"));
+        assert!(rag.search("hidden", 10, &QueryOptions::default()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn index_url_reports_typed_errors() {
+        let addr = serve_pages().await;
+        let mut rag = RAGEngine::new().await.unwrap().with_fetch_config(FetchConfig {
+            max_content_bytes: 1024,
+            max_redirects: 2,
+            ..FetchConfig::default()
+        });
+
+        let error = |e: anyhow::Error| e.downcast::<FetchError>().unwrap();
+        let url = |path: &str| format!("http://{}/{}", addr, path);
+
+        assert_eq!(error(rag.index_url(&url("missing")).await.unwrap_err()), FetchError::Status(404));
+        assert_eq!(
+            error(rag.index_url(&url("json")).await.unwrap_err()),
+            FetchError::UnsupportedContentType("application/json".to_string())
+        );
+        assert_eq!(error(rag.index_url(&url("big")).await.unwrap_err()), FetchError::TooLarge(1024));
+        assert_eq!(error(rag.index_url(&url("loop")).await.unwrap_err()), FetchError::TooManyRedirects(2));
+        assert_eq!(rag.get_stats().await.unwrap().document_count, 0);
+    }
+
+    #[test]
+    fn source_ids_stay_valid_and_distinct() {
+        let limits = ValidationLimits::default();
+        let ids = ["https://void.example/a-b", "https://void.example/a_b", "https://void.example/a?b"].map(url_document_id);
+        assert!(ids[0].starts_with("url_https___void_example_a_b_"), "{}", ids[0]);
+        assert!(ids[0] != ids[1] && ids[1] != ids[2] && ids[0] != ids[2], "{:?}", ids);
+        assert_eq!(ids[0], url_document_id("https://void.example/a-b"));
+
+        let long = format!("https://void.example/{}", "deep/".repeat(100));
+        let longer = format!("{}page", long);
+        for id in [url_document_id(&long), url_document_id(&longer), file_document_id(Path::new(&longer))] {
+            limits.check_id(&id).unwrap();
+        }
+        assert_ne!(url_document_id(&long), url_document_id(&longer));
+        limits.check_id(&file_document_id(Path::new("/tmp/notes/ritual log.md"))).unwrap();
+    }
+
+    #[tokio::test]
+    async fn index_file_skips_unchanged_content() {
+        let path = std::env::temp_dir().join(format!("void-shrine-{}.html", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "<title>Ritual Log</title><p>Entropy was harvested.</p>").unwrap();
+
+        let mut rag = RAGEngine::new().await.unwrap();
+        let id = rag.index_file(&path).await.unwrap();
+        assert_eq!(id, file_document_id(&path));
+
+        let results = rag.search("entropy", 10, &QueryOptions::default()).await.unwrap();
+        assert_eq!(results[0].title, "Ritual Log");
+        assert_eq!(results[0].metadata.get("path"), Some(&path.to_string_lossy().to_string()));
+
+        let document = RAGEngine::load_file(&path).await.unwrap();
+        assert!(!rag.index_document_if_changed(document.clone()).await.unwrap());
+        let changed = Document { content: "Entropy was released.".to_string(), ..document };
+        assert!(rag.index_document_if_changed(changed).await.unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn delete_where_removes_matching_documents() {
+        let mut rag = knowledge_base().await;
+
+        let filter = HashMap::from([("source".to_string(), "orchestration_manual".to_string())]);
+        assert_eq!(rag.delete_where(&filter, false).await.unwrap(), 1);
+        assert!(rag.search("orchestration", 10, &QueryOptions::default()).await.unwrap().is_empty());
+
+        let stats = rag.get_stats().await.unwrap();
+        assert_eq!((stats.document_count, stats.chunk_count), (2, 2));
+
+        // Nothing left to match
+        assert_eq!(rag.delete_where(&filter, false).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn delete_where_requires_allow_all_for_empty_filter() {
+        let mut rag = knowledge_base().await;
+
+        assert!(rag.delete_where(&HashMap::new(), false).await.is_err());
+        assert_eq!(rag.get_stats().await.unwrap().document_count, 3);
+
+        assert_eq!(rag.delete_where(&HashMap::new(), true).await.unwrap(), 3);
+        assert_eq!(rag.get_stats().await.unwrap().chunk_count, 0);
+    }
+
+    fn temp_db_path() -> PathBuf {
+        std::env::temp_dir().join(format!("void-shrine-{}.sqlite", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn porter_tokenizer_enables_stemming() {
+        let mut rag = RAGEngine::builder().tokenizer("porter unicode61").build().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+
+        // "coordinating" only stems to the indexed "coordination" with porter
+        let results = rag.fts_search(&QueryNode::Term("coordinating".to_string()), 10, &DocumentFilter::default()).unwrap();
+        assert_eq!(results[0].document_id, "agent_coordination");
+        assert_eq!(rag.get_stats().await.unwrap().tokenizer, "porter unicode61");
+
+        let plain = knowledge_base().await;
+        assert!(plain.fts_search(&QueryNode::Term("coordinating".to_string()), 10, &DocumentFilter::default()).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn tokenizer_mismatch_requires_reindex() {
+        let path = temp_db_path();
+        {
+            let mut rag = RAGEngine::builder().path(&path).build().await.unwrap();
+            rag.index_void_shrine_knowledge().await.unwrap();
+        }
+
+        let error = RAGEngine::builder().path(&path).tokenizer("trigram").build().await.err().unwrap();
+        assert_eq!(
+            error.downcast::<TokenizerMismatch>().unwrap(),
+            TokenizerMismatch { stored: "unicode61".to_string(), configured: "trigram".to_string() }
+        );
+
+        // Opening without a tokenizer adopts the stored one
+        let rag = RAGEngine::builder().path(&path).build().await.unwrap();
+        assert_eq!(rag.get_stats().await.unwrap().tokenizer, "unicode61");
+        drop(rag);
+
+        let rag = RAGEngine::builder().path(&path).tokenizer("trigram").reindex_on_mismatch(true).build().await.unwrap();
+        // Trigram matches substrings inside words
+        let results = rag.fts_search(&QueryNode::Term("ordinat".to_string()), 10, &DocumentFilter::default()).unwrap();
+        assert_eq!(results[0].document_id, "agent_coordination");
+        drop(rag);
+
+        let rag = RAGEngine::builder().path(&path).build().await.unwrap();
+        assert_eq!(rag.get_stats().await.unwrap().tokenizer, "trigram");
+        drop(rag);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn interrupted_fts_rebuilds_finish_on_reopen() {
+        let path = temp_db_path();
+        let chunks = {
+            let mut rag = RAGEngine::builder().path(&path).build().await.unwrap();
+            rag.index_void_shrine_knowledge().await.unwrap();
+            let begun = rag.begin_fts_rebuild().unwrap();
+            let progress = rag.rebuild_fts_batch(2).unwrap();
+            assert_eq!((progress.processed, progress.total, progress.done), (2, begun.total, false));
+            // Half-built, keyword searches match chunk text directly
+            assert!(rag.fts_search(&QueryNode::Term("coordination".to_string()), 10, &DocumentFilter::default()).unwrap().is_empty());
+            assert!(!rag.search("coordination", 5, &QueryOptions::default()).await.unwrap().is_empty());
+            assert!(rag.maintenance(false).await.unwrap_err().downcast::<FtsRebuilding>().is_ok());
+            begun.total
+        };
+
+        let rag = RAGEngine::builder().path(&path).build().await.unwrap();
+        assert!(rag.fts_rebuild_progress().is_none());
+        assert_eq!(rag.count_rows("FROM chunks_fts").unwrap(), chunks);
+        let results = rag.fts_search(&QueryNode::Term("coordination".to_string()), 10, &DocumentFilter::default()).unwrap();
+        assert_eq!(results[0].document_id, "agent_coordination");
+        drop(rag);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn searches_are_refused_and_writes_indexed_during_a_rebuild() {
+        let mut rag = RAGEngine::builder().during_fts_rebuild(FtsRebuildPolicy::Refuse).build().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        let total = rag.begin_fts_rebuild().unwrap().total;
+
+        let error = rag.search("coordination", 5, &QueryOptions::default()).await.unwrap_err();
+        assert_eq!(error.downcast::<FtsRebuilding>().unwrap(), FtsRebuilding { processed: 0, total });
+
+        // Chunks written during the rebuild are indexed once, by the write
+        rag.index_document(Document {
+            id: "late".to_string(),
+            title: "Late arrival".to_string(),
+            content: "Written while the index was rebuilt.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        })
+        .await
+        .unwrap();
+        while !rag.rebuild_fts_batch(3).unwrap().done {}
+        assert_eq!(rag.count_rows("FROM chunks_fts").unwrap(), rag.count_rows("FROM chunks").unwrap());
+        assert_eq!(rag.search("arrival", 5, &QueryOptions::default()).await.unwrap()[0].document_id, "late");
+    }
+
+    #[test]
+    fn splits_sentences_on_terminal_punctuation() {
+        assert_eq!(
+            split_sentences("Void-first design. Is v0.2 ready? Yes!  Trailing fragment"),
+            ["Void-first design.", "Is v0.2 ready?", "Yes!", "Trailing fragment"]
+        );
+        assert!(split_sentences("   ").is_empty());
+    }
+
+    #[tokio::test]
+    async fn query_answers_returns_best_sentences_with_provenance() {
+        let mut rag = knowledge_base().await;
+        rag.index_document(Document {
+            id: "rituals".to_string(),
+            title: "Rituals".to_string(),
+            content: "Care. Care matters. Care rituals are performed at dawn by the keepers.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        }).await.unwrap();
+
+        let answers = rag.query_answers("what is care ethics", 2, &QueryOptions::default()).await.unwrap();
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[0].content, "Care ethics prioritizes relational wellbeing and stakeholder agency.");
+        assert_eq!(answers[0].document_id, "care_ethics");
+        assert_eq!(answers[0].chunk_id, "care_ethics_0");
+
+        let answers = rag.query_answers("care", 10, &QueryOptions::default()).await.unwrap();
+        let sentences: Vec<&str> = answers.iter().map(|a| a.content.as_str()).collect();
+        assert!(sentences.contains(&"Care rituals are performed at dawn by the keepers."));
+        // Below the minimum sentence length
+        assert!(!sentences.contains(&"Care."));
+        assert!(!sentences.contains(&"Care matters."));
+
+        assert!(rag.query_answers("the", 3, &QueryOptions::default()).await.unwrap().is_empty());
+    }
+
+    fn chunk_rows(rag: &RAGEngine) -> Vec<(String, String, i64, i64)> {
+        let mut stmt = rag.db.prepare("SELECT id, content, start_pos, end_pos FROM chunks ORDER BY rowid").unwrap();
+        let mut rows = Vec::new();
+        while let Ok(State::Row) = stmt.next() {
+            rows.push((
+                stmt.read::<String, _>(0).unwrap(),
+                stmt.read::<String, _>(1).unwrap(),
+                stmt.read::<i64, _>(2).unwrap(),
+                stmt.read::<i64, _>(3).unwrap(),
+            ));
+        }
+        rows
+    }
+
+    #[tokio::test]
+    async fn parallel_chunking_matches_serial_output() {
+        let content: String = (0..20_000)
+            .map(|i| format!("Sentence {} about the vöid and its swarm{} ", i, if i % 7 == 0 { "!" } else { "." }))
+            .collect();
+        let document = Document {
+            id: "large".to_string(),
+            title: "Large Fixture".to_string(),
+            content,
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        };
+
+        let mut serial = RAGEngine::builder().chunk_parallelism(1).build().await.unwrap();
+        serial.index_document(document.clone()).await.unwrap();
+        let mut parallel = RAGEngine::builder().chunk_parallelism(8).build().await.unwrap();
+        parallel.index_document(document).await.unwrap();
+
+        let (serial_rows, parallel_rows) = (chunk_rows(&serial), chunk_rows(&parallel));
+        assert!(serial_rows.len() > PARALLEL_CHUNK_THRESHOLD);
+        assert_eq!(serial_rows, parallel_rows);
+    }
+
+    #[tokio::test]
+    async fn index_reader_matches_in_memory_indexing() {
+        // Multi-byte chars and a tiny read buffer split UTF-8 sequences and chunks across windows
+        let content: String = (0..1000)
+            .map(|i| format!("Satz {} über das Nichts — ∅ und der Schwarm{} ", i, if i % 5 == 0 { "?" } else { "." }))
+            .collect();
+        let document = Document {
+            id: "streamed".to_string(),
+            title: "Streamed".to_string(),
+            content: content.clone(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        };
+
+        let mut in_memory = RAGEngine::new().await.unwrap();
+        in_memory.index_document(document.clone()).await.unwrap();
+
+        let mut streamed = RAGEngine::new().await.unwrap();
+        let reader = std::io::BufReader::with_capacity(7, content.as_bytes());
+        let chunk_count = streamed.index_reader(reader, "streamed", "Streamed", HashMap::new()).await.unwrap();
+
+        let expected = chunk_rows(&in_memory);
+        assert!(expected.len() > STREAM_BATCH_CHUNKS);
+        assert_eq!(chunk_count, expected.len());
+        assert_eq!(chunk_rows(&streamed), expected);
+        assert_eq!(
+            streamed.stored_content_hash("streamed").unwrap(),
+            Some(content_hash(&document.title, &document.content))
+        );
+
+        let results = streamed.query("Schwarm", 1).await.unwrap();
+        assert!(results[0].contains("Streamed"));
+    }
+
+    #[tokio::test]
+    async fn index_reader_rejects_invalid_utf8_without_leaving_a_partial_document() {
+        let mut rag = RAGEngine::new().await.unwrap();
+        let mut input = "valid text. ".repeat(2000).into_bytes();
+        input.extend_from_slice(&[0xff, 0xfe]);
+
+        let result = rag.index_reader(input.as_slice(), "broken", "Broken", HashMap::new()).await;
+        assert!(result.is_err());
+        assert!(chunk_rows(&rag).is_empty());
+        assert_eq!(rag.get_stats().await.unwrap().document_count, 0);
+    }
+
+    #[tokio::test]
+    async fn index_reader_keeps_the_indexed_version_when_a_reindex_fails() {
+        let limits = ValidationLimits { max_content_bytes: 1_000_000, ..ValidationLimits::default() };
+        let mut rag = RAGEngine::builder().validation_limits(limits).build().await.unwrap();
+        rag.index_reader("The lantern keeper trims the wick at dusk.".as_bytes(), "broken", "Broken", HashMap::new()).await.unwrap();
+        rag.add_tag("broken", "lanterns").await.unwrap();
+        let rows_before = chunk_rows(&rag);
+
+        let mut past_first_batch = "valid text. ".repeat(20_000).into_bytes();
+        past_first_batch.extend_from_slice(&[0xff, 0xfe]);
+        let failures: Vec<Vec<u8>> = vec![
+            b"x".repeat(1_000_001),
+            vec![b'a', 0xff, b'b'],
+            b" \n\t ".to_vec(),
+            past_first_batch,
+        ];
+        for input in failures {
+            assert!(rag.index_reader(input.as_slice(), "broken", "Broken", HashMap::new()).await.is_err());
+
+            assert_eq!(chunk_rows(&rag), rows_before);
+            assert_eq!(rag.get_stats().await.unwrap().document_count, 1);
+            let results = rag.search("lantern wick", 5, &QueryOptions::default()).await.unwrap();
+            assert_eq!(results.len(), 1);
+            let tagged = rag.list_documents(&HashMap::new(), &["lanterns".to_string()]).await.unwrap();
+            assert_eq!(tagged.len(), 1);
+        }
+
+        rag.index_reader("The lighthouse beam sweeps the harbour.".as_bytes(), "broken", "Mended", HashMap::new()).await.unwrap();
+        assert!(rag.search("lantern wick", 5, &QueryOptions::default()).await.unwrap().is_empty());
+        assert_eq!(rag.search("lighthouse harbour", 5, &QueryOptions::default()).await.unwrap().len(), 1);
+        assert!(chunk_rows(&rag).iter().all(|(id, ..)| id.starts_with("broken_")));
+        assert_eq!(rag.list_documents(&HashMap::new(), &["lanterns".to_string()]).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn patch_document_applies_all_changes_or_none() {
+        let mut rag = knowledge_base().await;
+        let patch = DocumentPatch {
+            set_metadata: HashMap::from([("status".to_string(), "reviewed".to_string())]),
+            remove_metadata: vec!["source".to_string()],
+            add_tags: vec!["verified".to_string(), "  ".to_string()],
+            remove_tags: Vec::new(),
+        };
+        let before = rag.document_info("care_ethics").await.unwrap().unwrap();
+        assert!(rag.patch_document("care_ethics", &patch).await.is_err());
+        let after = rag.document_info("care_ethics").await.unwrap().unwrap();
+        assert_eq!((after.metadata, after.tags), (before.metadata, before.tags));
+
+        let patch = DocumentPatch { add_tags: vec!["verified".to_string()], ..patch };
+        assert!(rag.patch_document("care_ethics", &patch).await.unwrap());
+        let after = rag.document_info("care_ethics").await.unwrap().unwrap();
+        assert_eq!((after.metadata.get("status").map(String::as_str), after.tags), (Some("reviewed"), vec!["verified".to_string()]));
+        assert!(!after.metadata.contains_key("source"));
+        assert!(!rag.patch_document("missing", &patch).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn tags_and_metadata_changes_apply_without_reindexing() {
+        let mut rag = knowledge_base().await;
+        let chunks_before = rag.get_stats().await.unwrap().chunk_count;
+
+        assert!(rag.add_tag("care_ethics", "Verified").await.unwrap());
+        assert!(rag.add_tag("void_shrine_principles", "verified").await.unwrap());
+        assert!(rag.add_tag("void_shrine_principles", "deprecated").await.unwrap());
+        assert!(!rag.add_tag("missing", "verified").await.unwrap());
+        assert!(rag.set_metadata("care_ethics", "status", "reviewed").await.unwrap());
+        assert!(rag.remove_metadata("care_ethics", "source").await.unwrap());
+
+        let verified = rag.list_documents(&HashMap::new(), &["verified".to_string()]).await.unwrap();
+        let ids: Vec<&str> = verified.iter().map(|doc| doc.id.as_str()).collect();
+        assert_eq!(ids, vec!["care_ethics", "void_shrine_principles"]);
+        assert_eq!(verified[1].tags, vec!["deprecated", "verified"]);
+
+        let reviewed = rag.list_documents(&HashMap::from([("status".to_string(), "reviewed".to_string())]), &[]).await.unwrap();
+        assert_eq!(reviewed.len(), 1);
+        assert!(!reviewed[0].metadata.contains_key("source"));
+
+        // Inline and option tag filters both restrict search results
+        let results = rag.search("void tag=deprecated", 10, &QueryOptions::default()).await.unwrap();
+        assert!(!results.is_empty());
+        assert!(results.iter().all(|r| r.document_id == "void_shrine_principles"));
+        assert!(results[0].matched_fields.contains(&"tag=deprecated".to_string()));
+        let options = QueryOptions { tags: vec!["verified".to_string()], ..Default::default() };
+        assert!(!rag.search("ethics", 10, &options).await.unwrap().is_empty());
+
+        assert!(rag.remove_tag("void_shrine_principles", "DEPRECATED").await.unwrap());
+        assert!(rag.search("void tag=deprecated", 10, &QueryOptions::default()).await.unwrap().is_empty());
+        assert_eq!(rag.get_stats().await.unwrap().chunk_count, chunks_before);
+
+        // Deleting a document drops its tags
+        rag.delete_document("care_ethics").await.unwrap();
+        let verified = rag.list_documents(&HashMap::new(), &["verified".to_string()]).await.unwrap();
+        assert_eq!(verified.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stats_by_groups_documents_per_metadata_value() {
+        let mut rag = knowledge_base().await;
+        let notes = "Streamed ritual notes. ".repeat(100);
+        let mut metadata = HashMap::new();
+        metadata.insert("source".to_string(), "ritual_log".to_string());
+        rag.index_reader(notes.as_bytes(), "ritual_log", "Ritual Log", metadata).await.unwrap();
+        rag.remove_metadata("care_ethics", "source").await.unwrap();
+
+        let groups = rag.stats_by("source").await.unwrap();
+        let values: Vec<Option<&str>> = groups.iter().map(|g| g.value.as_deref()).collect();
+        assert_eq!(values, vec![None, Some("orchestration_manual"), Some("ritual_log"), Some("void_shrine_constitution")]);
+
+        let missing = &groups[0];
+        assert_eq!(missing.document_count, 1);
+        assert_eq!(missing.chunk_count, 1);
+
+        // Streamed documents keep no content but still report their size
+        let streamed = &groups[2];
+        assert_eq!(streamed.content_bytes, 2300);
+        assert!(streamed.chunk_count > 1);
+        assert!(streamed.last_indexed_at.is_some());
+
+        let total: usize = groups.iter().map(|g| g.chunk_count).sum();
+        assert_eq!(total, rag.get_stats().await.unwrap().chunk_count);
+    }
+
+    #[tokio::test]
+    async fn query_log_aggregates_retrieval_analytics() {
+        let mut rag = RAGEngine::builder().query_log(QueryLogConfig::default()).build().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+
+        for query in ["care ethics", "Care Ethics", "agent coordination", "quantum bananas"] {
+            rag.query(query, 5).await.unwrap();
+        }
+
+        let analytics = rag.query_analytics(None, None, 10).await.unwrap();
+        assert_eq!(analytics.total_queries, 4);
+        assert_eq!(analytics.zero_result_count, 1);
+        assert_eq!(analytics.top_queries[0].query, "Care Ethics");
+        assert_eq!(analytics.top_queries[0].count, 2);
+        assert_eq!(analytics.zero_result_queries.len(), 1);
+        assert_eq!(analytics.zero_result_queries[0].query, "quantum bananas");
+        assert_eq!(analytics.most_retrieved[0].document_id, "care_ethics");
+        assert_eq!(analytics.most_retrieved[0].retrievals, 2);
+
+        // A window that ends before any query was logged sees nothing
+        let before = rag.query_analytics(None, Some(Utc::now() - chrono::Duration::hours(1)), 10).await.unwrap();
+        assert_eq!(before.total_queries, 0);
+        assert!(before.most_retrieved.is_empty());
+        assert!(before.least_retrieved.iter().all(|d| d.retrievals == 0));
+    }
+
+    #[tokio::test]
+    async fn query_log_is_opt_in_and_bounded() {
+        let rag = knowledge_base().await;
+        rag.query("care ethics", 5).await.unwrap();
+        assert_eq!(rag.query_analytics(None, None, 10).await.unwrap().total_queries, 0);
+
+        let config = QueryLogConfig { max_entries: 3, ..Default::default() };
+        let mut rag = RAGEngine::builder().query_log(config).build().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        for i in 0..10 {
+            rag.query(&format!("care ethics {}", i), 5).await.unwrap();
+        }
+
+        let analytics = rag.query_analytics(None, None, 10).await.unwrap();
+        assert_eq!(analytics.total_queries, 3);
+        let queries: Vec<&str> = analytics.top_queries.iter().map(|q| q.query.as_str()).collect();
+        assert_eq!(queries, ["care ethics 7", "care ethics 8", "care ethics 9"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_searches_each_log_their_query() {
+        let mut rag = RAGEngine::builder().query_log(QueryLogConfig::default()).build().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        let rag = Arc::new(rag);
+        let searches: Vec<_> = (0..8)
+            .map(|task| {
+                let rag = Arc::clone(&rag);
+                tokio::spawn(async move {
+                    for i in 0..25 {
+                        rag.query(&format!("care ethics {} {}", task, i), 5).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for search in searches {
+            search.await.unwrap();
+        }
+        assert_eq!(rag.query_analytics(None, None, 10).await.unwrap().total_queries, 200);
+    }
+
+    #[tokio::test]
+    async fn file_backed_indexes_use_wal_by_default() {
+        let path = temp_db_path();
+        let mut rag = RAGEngine::builder().path(&path).build().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        assert_eq!(rag.get_stats().await.unwrap().journal_mode, "wal");
+
+        let checkpoint = rag.checkpoint().await.unwrap();
+        assert!(!checkpoint.busy);
+        assert_eq!(checkpoint.log_frames, checkpoint.checkpointed_frames);
+        drop(rag);
+
+        // Reopening with an override switches the persisted mode
+        let tuning = SqliteTuning { journal_mode: JournalMode::Delete, ..Default::default() };
+        let rag = RAGEngine::builder().path(&path).sqlite_tuning(tuning).build().await.unwrap();
+        assert_eq!(rag.get_stats().await.unwrap().journal_mode, "delete");
+        assert_eq!(rag.checkpoint().await.unwrap().log_frames, -1);
+        drop(rag);
+        let _ = std::fs::remove_file(&path);
+
+        let in_memory = RAGEngine::new().await.unwrap();
+        assert_eq!(in_memory.get_stats().await.unwrap().journal_mode, "memory");
+    }
+
+    fn integrity_check(path: &Path) -> String {
+        let db = Connection::open(path).unwrap();
+        let mut stmt = db.prepare("PRAGMA integrity_check").unwrap();
+        stmt.next().unwrap();
+        stmt.read::<String, _>(0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn backup_during_indexing_produces_consistent_copy() {
+        let path = temp_db_path();
+        let mut writer = RAGEngine::builder().path(&path).build().await.unwrap();
+        let reader = RAGEngine::builder().path(&path).build().await.unwrap();
+
+        let indexing = tokio::task::spawn_blocking(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+            for i in 0..200 {
+                let document = Document {
+                    id: format!("doc_{}", i),
+                    title: format!("Document {}", i),
+                    content: "Emergent coordination through generative absence. ".repeat(40),
+                    metadata: HashMap::new(),
+                    embedding: None,
+                    chunks: vec![],
+                };
+                runtime.block_on(writer.index_document(document)).unwrap();
+            }
+        });
+
+        let mut backups = Vec::new();
+        while !indexing.is_finished() {
+            let backup_path = temp_db_path();
+            let report = reader.backup_to(&backup_path).await.unwrap();
+            assert_eq!(report.bytes, report.pages * report.page_size);
+            backups.push(backup_path);
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        indexing.await.unwrap();
+        assert!(reader.backup_to(&backups[0]).await.is_err(), "existing files are never overwritten");
+
+        for backup_path in &backups {
+            assert_eq!(integrity_check(backup_path), "ok");
+            let copy = RAGEngine::builder().path(backup_path).build().await.unwrap();
+            // Documents and their chunks always arrive together in a snapshot
+            let documents = copy.list_documents(&HashMap::new(), &[]).await.unwrap();
+            assert!(documents.iter().all(|doc| doc.chunk_count > 0 && doc.chunk_count == documents[0].chunk_count));
+            let chunk_total: usize = documents.iter().map(|doc| doc.chunk_count).sum();
+            assert_eq!(copy.get_stats().await.unwrap().chunk_count, chunk_total);
+            drop(copy);
+            let _ = std::fs::remove_file(backup_path);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn maintenance_detects_and_repairs_inconsistencies() {
+        let path = temp_db_path();
+        let mut rag = RAGEngine::builder().path(&path).build().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        rag.add_tag("care_ethics", "verified").await.unwrap();
+
+        let clean = rag.maintenance(false).await.unwrap();
+        assert!(clean.fts_integrity_error.is_none());
+        assert_eq!((clean.orphaned_chunks, clean.orphaned_fts_rows, clean.unindexed_chunks, clean.orphaned_tags), (0, 0, 0, 0));
+
+        // Simulate damage left by interrupted writes from older versions
+        rag.db.execute("DELETE FROM documents WHERE id = 'care_ethics'").unwrap();
+        rag.db.execute("DELETE FROM chunks WHERE id = 'agent_coordination_0'").unwrap();
+        rag.db.execute("DELETE FROM chunks_fts WHERE chunk_id = 'void_shrine_principles_0'").unwrap();
+
+        let report = rag.maintenance(false).await.unwrap();
+        assert_eq!(report.orphaned_chunks, 1);
+        assert_eq!(report.orphaned_fts_rows, 1);
+        assert_eq!(report.unindexed_chunks, 1);
+        assert_eq!(report.orphaned_tags, 1);
+        assert!(!report.repaired);
+
+        let repaired = rag.maintenance(true).await.unwrap();
+        assert!(repaired.repaired);
+        assert!(repaired.bytes_after > 0);
+        let after = rag.maintenance(false).await.unwrap();
+        assert_eq!((after.orphaned_chunks, after.orphaned_fts_rows, after.unindexed_chunks, after.orphaned_tags), (0, 0, 0, 0));
+
+        // The re-indexed chunk is searchable again
+        let results = rag.search("void", 10, &QueryOptions::default()).await.unwrap();
+        assert!(results.iter().any(|r| r.chunk_id == "void_shrine_principles_0"));
+        drop(rag);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn similar_documents_rank_related_documents() {
+        let mut rag = knowledge_base().await;
+        rag.index_document(Document {
+            id: "care_practice".to_string(),
+            title: "Care in Practice".to_string(),
+            content: "Relational wellbeing and stakeholder agency guide care ethics in daily practice.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        }).await.unwrap();
+
+        let similar = rag.similar_documents("care_ethics", 5).await.unwrap();
+        assert_eq!(similar[0].0.id, "care_practice");
+        assert!(similar.iter().all(|(doc, score)| doc.id != "care_ethics" && *score > 0.0));
+        assert!(similar.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert!(rag.similar_documents("missing", 5).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn similar_documents_edge_cases_return_nothing() {
+        let mut rag = RAGEngine::new().await.unwrap();
+        let document = |id: &str, content: &str| Document {
+            id: id.to_string(),
+            title: String::new(),
+            content: content.to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        };
+
+        // A corpus of one
+        rag.index_document(document("only", "Emergent coordination of autonomous agents.")).await.unwrap();
+        assert!(rag.similar_documents("only", 5).await.unwrap().is_empty());
+
+        rag.index_document(document("short", "Hi")).await.unwrap();
+        rag.index_document(document("stop_words", "and the of it is was")).await.unwrap();
+        assert!(rag.similar_documents("short", 5).await.unwrap().is_empty());
+        assert!(rag.similar_documents("stop_words", 5).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keywords_favour_distinctive_terms() {
+        let mut rag = knowledge_base().await;
+        let document = |id: &str, title: &str, content: &str| Document {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        };
+        rag.index_document(document("notes", "Field Notes", "The swarm swarm swarm met the void in 2024 and 2025. It was the void again.")).await.unwrap();
+
+        let keywords = rag.get_keywords("notes").await.unwrap().unwrap();
+        assert_eq!(keywords[0], "swarm");
+        assert!(keywords.len() <= KEYWORDS_PER_DOCUMENT);
+        assert!(keywords.iter().all(|k| !["the", "and", "was", "2024", "2025"].contains(&k.as_str())));
+        assert_eq!(rag.list_documents(&HashMap::new(), &[]).await.unwrap().iter()
+            .find(|doc| doc.id == "notes").unwrap().keywords, keywords);
+        assert!(rag.get_keywords("missing").await.unwrap().is_none());
+
+        // "void" becomes common across the corpus, so a refresh demotes it below "notes"
+        for i in 0..8 {
+            rag.index_document(document(&format!("echo_{}", i), "Echo", "void void void")).await.unwrap();
+        }
+        rag.refresh_keywords().await.unwrap();
+        let refreshed = rag.get_keywords("notes").await.unwrap().unwrap();
+        let position = |term: &str| refreshed.iter().position(|k| k == term).unwrap();
+        assert!(position("field") < position("void"));
+    }
+
+    struct FailingSummarizer;
+
+    impl Summarizer for FailingSummarizer {
+        fn summarize<'a>(&'a self, _title: &'a str, _content: &'a str) -> BoxFuture<'a, Result<String>> {
+            Box::pin(async { Err(anyhow::anyhow!("backend unavailable")) })
+        }
+    }
+
+    #[tokio::test]
+    async fn summaries_are_stored_and_failures_do_not_block_indexing() {
+        let mut rag = RAGEngine::builder()
+            .summarizer(Arc::new(LeadSentencesSummarizer { sentences: 2 }))
+            .build()
+            .await
+            .unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        let summary = rag.document_info("care_ethics").await.unwrap().unwrap().summary.unwrap();
+        assert!(summary.starts_with("Care ethics prioritizes relational wellbeing and stakeholder agency."));
+        assert_eq!(split_sentences(&summary).len(), 2);
+
+        let mut failing = RAGEngine::builder().summarizer(Arc::new(FailingSummarizer)).build().await.unwrap();
+        failing.index_void_shrine_knowledge().await.unwrap();
+        let info = failing.document_info("care_ethics").await.unwrap().unwrap();
+        assert!(info.summary.is_none());
+        assert!(info.chunk_count > 0);
+    }
+
+    fn set_indexed_at(rag: &RAGEngine, id: &str, at: DateTime<Utc>) {
+        let mut stmt = rag.db.prepare("UPDATE documents SET indexed_at = ? WHERE id = ?").unwrap();
+        stmt.bind((1, timestamp(at).as_str())).unwrap();
+        stmt.bind((2, id)).unwrap();
+        stmt.next().unwrap();
+    }
+
+    #[tokio::test]
+    async fn time_range_filters_apply_before_the_limit() {
+        let mut rag = RAGEngine::new().await.unwrap();
+        for i in 0..6 {
+            // Older documents mention the term more often, so they would rank first unfiltered
+            rag.index_document(Document {
+                id: format!("report_{}", i),
+                title: format!("Report {}", i),
+                content: "entropy ".repeat(10 - i),
+                metadata: HashMap::from([("kind".to_string(), if i % 2 == 0 { "even" } else { "odd" }.to_string())]),
+                embedding: None,
+                chunks: vec![],
+            }).await.unwrap();
+        }
+        let now = Utc::now();
+        for i in 0..3 {
+            set_indexed_at(&rag, &format!("report_{}", i), now - chrono::Duration::days(30));
+        }
+
+        let ids = |results: Vec<SearchResult>| results.into_iter().map(|r| r.document_id).collect::<Vec<_>>();
+        let unfiltered = rag.search("entropy", 2, &QueryOptions::default()).await.unwrap();
+        assert_eq!(ids(unfiltered), ["report_0", "report_1"]);
+
+        let fresh = QueryOptions { since: Some(now - chrono::Duration::days(7)), ..Default::default() };
+        assert_eq!(ids(rag.search("entropy", 2, &fresh).await.unwrap()), ["report_3", "report_4"]);
+
+        let old = QueryOptions { until: Some(now - chrono::Duration::days(7)), ..Default::default() };
+        assert_eq!(ids(rag.search("entropy", 10, &old).await.unwrap()), ["report_0", "report_1", "report_2"]);
+
+        // Combined with a metadata filter, in both the FTS and fallback paths
+        let fresh_odd = QueryOptions {
+            metadata_filters: HashMap::from([("kind".to_string(), "odd".to_string())]),
+            ..fresh.clone()
+        };
+        assert_eq!(ids(rag.search("entropy", 10, &fresh_odd).await.unwrap()), ["report_3", "report_5"]);
+        let fallback = rag.fallback_search("entropy", DEFAULT_LANGUAGE, 10, &DocumentFilter {
+            since: fresh.since,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(fallback.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn index_document_rejects_invalid_documents() {
+        let limits = ValidationLimits {
+            max_content_bytes: 100,
+            max_id_chars: 16,
+            max_metadata_entries: 2,
+            max_metadata_bytes: 20,
+        };
+        let mut rag = RAGEngine::builder().validation_limits(limits).build().await.unwrap();
+        let document = |id: &str, content: &str, metadata: &[(&str, &str)]| Document {
+            id: id.to_string(),
+            title: "Title".to_string(),
+            content: content.to_string(),
+            metadata: metadata.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            embedding: None,
+            chunks: vec![],
+        };
+
+        let cases = [
+            (document("", "text", &[]), "empty_id"),
+            (document("bad id/../x", "text", &[]), "invalid_id"),
+            (document("a_very_long_document_id", "text", &[]), "id_too_long"),
+            (document("blank", " \n\t", &[]), "empty_content"),
+            (document("big", &"x".repeat(101), &[]), "content_too_large"),
+            (document("meta", "text", &[("a", "1"), ("b", "2"), ("c", "3")]), "too_many_metadata_entries"),
+            (document("meta", "text", &[("key", &"v".repeat(18))]), "metadata_too_large"),
+        ];
+        for (document, code) in cases {
+            let error = rag.index_document(document).await.unwrap_err();
+            assert_eq!(error.downcast_ref::<ValidationError>().map(ValidationError::code), Some(code));
+        }
+        assert_eq!(rag.get_stats().await.unwrap().document_count, 0);
+
+        // Exactly at every limit is accepted
+        let at_limits = document("doc-1.v2:final_", &"x".repeat(100), &[("key", &"v".repeat(16)), ("b", "")]);
+        rag.index_document(at_limits).await.unwrap();
+        assert_eq!(rag.get_stats().await.unwrap().document_count, 1);
+
+        // Changed metadata is held to the same limits
+        let metadata = |rag: &RAGEngine| rag.document_infos(&DocumentFilter::default(), Some("doc-1.v2:final_")).unwrap().pop().unwrap().metadata;
+        let before = metadata(&rag);
+        let error = rag.set_metadata("doc-1.v2:final_", "c", "3").await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ValidationError>().map(ValidationError::code), Some("too_many_metadata_entries"));
+        let error = rag.set_metadata("doc-1.v2:final_", "key", &"v".repeat(17)).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ValidationError>().map(ValidationError::code), Some("metadata_too_large"));
+        let patch = DocumentPatch {
+            set_metadata: HashMap::from([("c".to_string(), "3".to_string())]),
+            add_tags: vec!["checked".to_string()],
+            ..DocumentPatch::default()
+        };
+        assert!(rag.patch_document("doc-1.v2:final_", &patch).await.is_err());
+        assert_eq!(metadata(&rag), before);
+        assert!(rag.set_metadata("doc-1.v2:final_", "key", &"w".repeat(16)).await.unwrap());
+        // Removals count before the limits are checked
+        let patch = DocumentPatch {
+            set_metadata: HashMap::from([("c".to_string(), String::new())]),
+            remove_metadata: vec!["b".to_string()],
+            ..patch
+        };
+        assert!(rag.patch_document("doc-1.v2:final_", &patch).await.unwrap());
+
+        let error = rag.index_reader(" \n ".as_bytes(), "blank", "Blank", HashMap::new()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ValidationError>(), Some(&ValidationError::EmptyContent));
+        // Streamed input is held to the same limits, however many batches it spans
+        let mut unlimited = RAGEngine::new().await.unwrap();
+        let error = unlimited.index_reader(" \n\t".repeat(50_000).as_bytes(), "blank", "Blank", HashMap::new()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ValidationError>(), Some(&ValidationError::EmptyContent));
+        assert_eq!(unlimited.get_stats().await.unwrap().document_count, 0);
+        let error = rag.index_reader("x".repeat(101).as_bytes(), "big", "Big", HashMap::new()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ValidationError>(), Some(&ValidationError::ContentTooLarge { bytes: 101, max: 100 }));
+        let big = "x".repeat(200);
+        let error = rag.index_reader(std::io::BufReader::with_capacity(16, big.as_bytes()), "big", "Big", HashMap::new()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ValidationError>().map(ValidationError::code), Some("content_too_large"));
+        assert_eq!(rag.get_stats().await.unwrap().document_count, 1);
+        let error = rag.index_reader("text".as_bytes(), "bad id", "Bad", HashMap::new()).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ValidationError>().map(ValidationError::code), Some("invalid_id"));
+    }
+
+    #[tokio::test]
+    async fn search_scoped_to_document_ids() {
+        let rag = knowledge_base().await;
+        let scoped = |ids: &[&str]| QueryOptions {
+            doc_ids: Some(ids.iter().map(|id| id.to_string()).collect()),
+            ..Default::default()
+        };
+        let documents = |results: Vec<SearchResult>| {
+            results.into_iter().map(|r| r.document_id).collect::<HashSet<_>>()
+        };
+
+        let unscoped = documents(rag.search("care OR coordination", 20, &QueryOptions::default()).await.unwrap());
+        assert!(unscoped.len() > 1);
+        let results = rag.search("care OR coordination", 20, &scoped(&["care_ethics", "unknown"])).await.unwrap();
+        assert_eq!(documents(results), HashSet::from(["care_ethics".to_string()]));
+        assert!(rag.search("care OR coordination", 20, &scoped(&[])).await.unwrap().is_empty());
+
+        let fallback = rag.fallback_search("care", DEFAULT_LANGUAGE, 20, &DocumentFilter {
+            doc_ids: Some(vec!["agent_coordination".to_string()]),
+            ..Default::default()
+        }).await.unwrap();
+        assert!(fallback.iter().all(|r| r.document_id == "agent_coordination"));
+        let fallback = rag.fallback_search("care", DEFAULT_LANGUAGE, 20, &DocumentFilter {
+            doc_ids: Some(Vec::new()),
+            ..Default::default()
+        }).await.unwrap();
+        assert!(fallback.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dedupe_drops_overlapping_chunks_and_backfills() {
+        // Overlap of 80% means a term inside the overlap lands in two neighbouring chunks
+        let mut rag = RAGEngine::builder().chunk_size(100).overlap_size(80).build().await.unwrap();
+        let filler = "Quiet sentences about nothing in particular fill this space. ";
+        rag.index_document(Document {
+            id: "log".to_string(),
+            title: "Log".to_string(),
+            content: format!("{}The entropy pool refilled. {}", filler.repeat(3), filler.repeat(3)),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        }).await.unwrap();
+        for i in 0..3 {
+            rag.index_document(Document {
+                id: format!("note_{}", i),
+                title: "Note".to_string(),
+                content: format!("A short note on entropy number {}.", i),
+                metadata: HashMap::new(),
+                embedding: None,
+                chunks: vec![],
+            }).await.unwrap();
+        }
+
+        let raw = rag.search("entropy", 50, &QueryOptions::default()).await.unwrap();
+        assert!(raw.iter().filter(|r| r.document_id == "log").count() > 1);
+
+        let options = QueryOptions { dedupe_overlaps: true, ..Default::default() };
+        let deduped = rag.search("entropy", 3, &options).await.unwrap();
+        assert_eq!(deduped.len(), 3);
+        assert!(deduped.iter().filter(|r| r.document_id == "log").count() <= 1);
+        let all = rag.search("entropy", 50, &options).await.unwrap();
+        assert_eq!(all.iter().filter(|r| r.document_id == "log").count(), 1);
+        assert_eq!(all.len(), 4);
+    }
+
+    /// Bag-of-words vectors hashed into `dimension` buckets
+    struct HashEmbedder {
+        model: &'static str,
+        dimension: usize,
+    }
+
+    impl EmbeddingProvider for HashEmbedder {
+        fn model(&self) -> &str {
+            self.model
+        }
+
+        fn dimension(&self) -> usize {
+            self.dimension
+        }
+
+        fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+            let vectors = texts.iter().map(|text| {
+                let mut vector = vec![0.0; self.dimension];
+                for word in text.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| w.len() > 3) {
+                    let bucket = word.bytes().fold(7usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+                    vector[bucket % self.dimension] += 1.0;
+                }
+                vector
+            }).collect();
+            Box::pin(async move { Ok(vectors) })
+        }
+    }
+
+    #[tokio::test]
+    async fn embedding_model_is_recorded_and_enforced() {
+        let path = temp_db_path();
+        let small: Arc<dyn EmbeddingProvider> = Arc::new(HashEmbedder { model: "hash-small", dimension: 384 });
+        let large: Arc<dyn EmbeddingProvider> = Arc::new(HashEmbedder { model: "hash-large", dimension: 768 });
+
+        {
+            let mut rag = RAGEngine::builder().path(&path).embedding_provider(Arc::clone(&small)).build().await.unwrap();
+            assert_eq!(rag.embedding_model().unwrap(), Some(EmbeddingModel { model: "hash-small".to_string(), dimension: 384 }));
+            rag.index_void_shrine_knowledge().await.unwrap();
+
+            let results = rag.semantic_search("care ethics relationships", 3).await.unwrap();
+            assert_eq!(results[0].document_id, "care_ethics");
+            assert_eq!(results[0].matched_fields, vec!["embedding"]);
+        }
+
+        // Switching models keeps the old vectors but refuses to compare against them
+        let mut rag = RAGEngine::builder().path(&path).embedding_provider(Arc::clone(&large)).build().await.unwrap();
+        let error = rag.semantic_search("care ethics", 3).await.unwrap_err();
+        let mismatch = error.downcast_ref::<EmbeddingMismatch>().unwrap();
+        assert_eq!(mismatch.stored.as_ref().map(|m| m.dimension), Some(384));
+        assert!(error.to_string().contains("embedding model mismatch, reindex required"));
+        assert!(rag.index_document(Document {
+            id: "late".to_string(),
+            title: "Late".to_string(),
+            content: "Arrives after the switch.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        }).await.is_err());
+
+        let chunks = rag.get_stats().await.unwrap().chunk_count;
+        let mut reports = Vec::new();
+        let embedded = rag.re_embed_all(Arc::clone(&large), |done, total| reports.push((done, total))).await.unwrap();
+        assert_eq!(embedded, chunks);
+        assert_eq!(reports.last(), Some(&(chunks, chunks)));
+        assert_eq!(rag.embedding_model().unwrap().map(|m| m.dimension), Some(768));
+        assert_eq!(rag.semantic_search("care ethics relationships", 3).await.unwrap()[0].document_id, "care_ethics");
+
+        rag.clear_embeddings().await.unwrap();
+        assert_eq!(rag.embedding_model().unwrap(), None);
+        let error = rag.semantic_search("care ethics", 3).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<EmbeddingMismatch>().unwrap().stored, None);
+
+        drop(rag);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// `HashEmbedder` that fails every call after the first
+    struct FailingAfterFirstBatch {
+        inner: HashEmbedder,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl EmbeddingProvider for FailingAfterFirstBatch {
+        fn model(&self) -> &str {
+            self.inner.model()
+        }
+
+        fn dimension(&self) -> usize {
+            self.inner.dimension()
+        }
+
+        fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+            if self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) > 0 {
+                return Box::pin(async { anyhow::bail!("provider went away") });
+            }
+            self.inner.embed(texts)
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_re_embedding_records_no_model() {
+        let small: Arc<dyn EmbeddingProvider> = Arc::new(HashEmbedder { model: "hash-small", dimension: 384 });
+        let mut rag = RAGEngine::builder().embedding_provider(Arc::clone(&small)).build().await.unwrap();
+        for i in 0..EMBEDDING_BATCH_CHUNKS + 10 {
+            rag.index_document(Document {
+                id: format!("note-{}", i),
+                title: format!("Note {}", i),
+                content: format!("Field note {} on care ethics and relationships.", i),
+                metadata: HashMap::new(),
+                embedding: None,
+                chunks: vec![],
+            }).await.unwrap();
+        }
+
+        let failing: Arc<dyn EmbeddingProvider> = Arc::new(FailingAfterFirstBatch {
+            inner: HashEmbedder { model: "hash-large", dimension: 768 },
+            calls: Default::default(),
+        });
+        let mut reports = Vec::new();
+        let error = rag.re_embed_all(failing, |done, total| reports.push((done, total))).await.unwrap_err();
+        assert!(error.to_string().contains("provider went away"));
+        assert_eq!(reports.len(), 1, "the first batch was stored");
+
+        // Neither model matches the half-migrated vectors
+        assert_eq!(rag.embedding_model().unwrap(), None);
+        let error = rag.semantic_search("care ethics", 3).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<EmbeddingMismatch>().unwrap().stored, None);
+        assert_eq!(rag.embedder().unwrap().model(), "hash-small");
+
+        rag.re_embed_all(small, |_, _| {}).await.unwrap();
+        assert_eq!(rag.semantic_search("care ethics", 3).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn semantic_and_hybrid_modes_keep_to_filters_and_the_score_floor() {
+        let provider: Arc<dyn EmbeddingProvider> = Arc::new(HashEmbedder { model: "hash-small", dimension: 384 });
+        let mut rag = RAGEngine::builder().embedding_provider(provider).build().await.unwrap();
+        rag.index_void_shrine_knowledge().await.unwrap();
+        let options = |mode, category: Option<&str>, min_score| QueryOptions {
+            mode,
+            min_score,
+            metadata_filters: category.map(|category| HashMap::from([("category".to_string(), category.to_string())])).unwrap_or_default(),
+            ..QueryOptions::default()
+        };
+
+        let semantic = rag.search("care ethics relationships", 3, &options(RetrievalMode::Semantic, None, None)).await.unwrap();
+        assert_eq!(semantic[0].document_id, "care_ethics");
+        let technical = rag.search("care ethics relationships", 3, &options(RetrievalMode::Semantic, Some("technical"), None)).await.unwrap();
+        assert!(!technical.is_empty() && technical.iter().all(|result| result.metadata["category"] == "technical"));
+
+        let hybrid = rag.search("care ethics", 10, &options(RetrievalMode::Hybrid, None, None)).await.unwrap();
+        assert_eq!(hybrid[0].document_id, "care_ethics");
+        assert!(hybrid[0].matched_fields.contains(&"embedding".to_string()) && hybrid[0].matched_fields.contains(&"content".to_string()));
+        assert!(hybrid.iter().all(|result| (0.0..=1.0).contains(&result.similarity_score)));
+        assert!(hybrid.windows(2).all(|pair| pair[0].similarity_score >= pair[1].similarity_score));
+
+        let floor = hybrid[0].similarity_score;
+        let top = rag.search("care ethics", 10, &options(RetrievalMode::Hybrid, None, Some(floor))).await.unwrap();
+        assert!(top.iter().all(|result| result.similarity_score >= floor));
+        assert!(top.len() < hybrid.len());
+
+        let keyword_only = RAGEngine::new().await.unwrap();
+        assert!(keyword_only.search("care", 3, &options(RetrievalMode::Hybrid, None, None)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ranking_boosts_order_equally_relevant_documents() {
+        let mut rag = RAGEngine::new().await.unwrap();
+        for (id, status) in [("alpha", "draft"), ("beta", "draft"), ("gamma", "verified")] {
+            rag.index_document(Document {
+                id: id.to_string(),
+                title: "Report".to_string(),
+                content: "The entropy pool was refilled overnight.".to_string(),
+                metadata: HashMap::from([("status".to_string(), status.to_string())]),
+                embedding: None,
+                chunks: vec![],
+            }).await.unwrap();
+        }
+        rag.add_tag("beta", "Pinned").await.unwrap();
+        let now = Utc::now();
+        set_indexed_at(&rag, "alpha", now - chrono::Duration::days(60));
+        set_indexed_at(&rag, "beta", now - chrono::Duration::days(30));
+        set_indexed_at(&rag, "gamma", now - chrono::Duration::days(90));
+        let top = |results: Vec<SearchResult>| results[0].document_id.clone();
+        let options = QueryOptions::default();
+
+        rag.set_ranking(RankingConfig {
+            metadata_boosts: HashMap::from([("status".to_string(), HashMap::from([("verified".to_string(), 1.5)]))]),
+            ..Default::default()
+        });
+        assert_eq!(top(rag.search("entropy", 1, &options).await.unwrap()), "gamma");
+
+        rag.set_ranking(RankingConfig { tag_boosts: HashMap::from([("pinned".to_string(), 1.5)]), ..Default::default() });
+        assert_eq!(top(rag.search("entropy", 1, &options).await.unwrap()), "beta");
+
+        rag.set_ranking(RankingConfig { recency_half_life_days: Some(30.0), ..Default::default() });
+        let results = rag.search("entropy", 3, &options).await.unwrap();
+        let order: Vec<&str> = results.iter().map(|r| r.document_id.as_str()).collect();
+        assert_eq!(order, ["beta", "alpha", "gamma"]);
+        // Thirty days apart at a thirty day half-life is exactly a factor of two
+        assert!((results[0].similarity_score / results[1].similarity_score - 2.0).abs() < 1e-3);
+
+        // A boost big enough to outweigh the decay wins; the setter replaces the whole config
+        rag.set_ranking(RankingConfig {
+            recency_half_life_days: Some(30.0),
+            metadata_boosts: HashMap::from([("status".to_string(), HashMap::from([("verified".to_string(), 10.0)]))]),
+            ..Default::default()
+        });
+        assert_eq!(top(rag.search("entropy", 1, &options).await.unwrap()), "gamma");
+        assert_eq!(rag.ranking().tag_boosts.len(), 0);
+    }
+
+    fn plain_document(id: &str, title: &str, content: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn equivalent_unicode_spellings_match() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        let mut rag = RAGEngine::new().await.unwrap();
+        assert_eq!(rag.normalization(), Normalization::Nfc);
+        rag.index_document(plain_document("menu", "Menu", &format!("The {} serves void tea.", composed))).await.unwrap();
+        rag.index_document(plain_document("notes", "Notes", &format!("Meet at the {}\u{a0}terrace.", decomposed))).await.unwrap();
+        rag.index_document(plain_document("quote", "Quote", "She said \u{201c}don\u{2019}t panic\u{201d} twice.")).await.unwrap();
+
+        let ids = |results: Vec<SearchResult>| results.into_iter().map(|r| r.document_id).collect::<HashSet<_>>();
+        let both = HashSet::from(["menu".to_string(), "notes".to_string()]);
+        assert_eq!(ids(rag.search(decomposed, 10, &QueryOptions::default()).await.unwrap()), both);
+        assert_eq!(ids(rag.search(composed, 10, &QueryOptions::default()).await.unwrap()), both);
+
+        // The fallback scorer matches substrings, so straightened quotes and spaces matter there
+        let unfiltered = DocumentFilter::default();
+        let fallback = |query: &'static str| rag.fallback_search(query, DEFAULT_LANGUAGE, 10, &unfiltered);
+        assert_eq!(ids(fallback("don't").await.unwrap()), HashSet::from(["quote".to_string()]));
+        assert_eq!(ids(fallback("don\u{2019}t").await.unwrap()), HashSet::from(["quote".to_string()]));
+        assert_eq!(ids(fallback("\"don't").await.unwrap()), HashSet::from(["quote".to_string()]));
+        let terrace = rag.search("terrace", 1, &QueryOptions::default()).await.unwrap();
+        assert_eq!(terrace[0].content, "Meet at the caf\u{e9} terrace.");
+    }
+
+    #[tokio::test]
+    async fn nfkc_folds_full_width_latin() {
+        let full_width = "\u{ff25}\u{ff2e}\u{ff34}\u{ff32}\u{ff2f}\u{ff30}\u{ff39}";
+        let mut nfc = RAGEngine::new().await.unwrap();
+        let mut nfkc = RAGEngine::builder().normalization(Normalization::Nfkc).build().await.unwrap();
+        for rag in [&mut nfc, &mut nfkc] {
+            rag.index_document(plain_document("wide", "Wide", &format!("{} pool refilled.", full_width))).await.unwrap();
+            rag.index_document(plain_document("narrow", "Narrow", "entropy pool drained.")).await.unwrap();
+        }
+
+        let ids = |results: Vec<SearchResult>| results.into_iter().map(|r| r.document_id).collect::<HashSet<_>>();
+        let both = HashSet::from(["wide".to_string(), "narrow".to_string()]);
+        assert_eq!(ids(nfkc.search("entropy", 10, &QueryOptions::default()).await.unwrap()), both);
+        assert_eq!(ids(nfkc.search(full_width, 10, &QueryOptions::default()).await.unwrap()), both);
+        assert_eq!(ids(nfc.search("entropy", 10, &QueryOptions::default()).await.unwrap()), HashSet::from(["narrow".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn normalization_is_recorded_and_enforced() {
+        let path = temp_db_path();
+        {
+            let mut rag = RAGEngine::builder().path(&path).normalization(Normalization::None).build().await.unwrap();
+            rag.index_document(plain_document("menu", "Caf\u{e9}", "Cafe\u{301} au lait.")).await.unwrap();
+        }
+
+        // Unconfigured opens adopt what the index was built with
+        let rag = RAGEngine::builder().path(&path).build().await.unwrap();
+        assert_eq!(rag.normalization(), Normalization::None);
+        drop(rag);
+
+        let error = RAGEngine::builder().path(&path).normalization(Normalization::Nfc).build().await.err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<NormalizationMismatch>(),
+            Some(&NormalizationMismatch { stored: Normalization::None, configured: Normalization::Nfc })
+        );
+
+        let rag = RAGEngine::builder()
+            .path(&path)
+            .normalization(Normalization::Nfc)
+            .reindex_on_mismatch(true)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(rag.normalization(), Normalization::Nfc);
+        let results = rag.search("caf\u{e9}", 10, &QueryOptions::default()).await.unwrap();
+        assert_eq!(results[0].content, "Caf\u{e9} au lait.");
+        assert!(results[0].matched_fields.contains(&"title".to_string()));
+
+        drop(rag);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn generation_moves_with_the_corpus_not_with_searches() {
+        let mut rag = knowledge_base().await;
+        let indexed = rag.generation();
+        rag.search("care ethics", 5, &QueryOptions::default()).await.unwrap();
+        assert_eq!(rag.generation(), indexed);
+
+        rag.index_document(plain_document("menu", "Menu", "Soup of the day.")).await.unwrap();
+        let added = rag.generation();
+        assert_ne!(added, indexed);
+        rag.add_tag("menu", "food").await.unwrap();
+        assert_ne!(rag.generation(), added);
+
+        // A fresh engine never reuses a generation
+        assert!(RAGEngine::new().await.unwrap().generation() > rag.generation());
+    }
+}
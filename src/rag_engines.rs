@@ -0,0 +1,87 @@
+//! Knowledge bases by name. `[rag]` configures the `default` engine and
+//! `[rag.engines.<name>]` any others, each with its own SQLite file, chunking
+//! and lock, so reindexing one leaves queries on the others alone. Requests
+//! choose one with `rag_engine` in their params, or `?engine=` on the
+//! knowledge base routes; naming none is the default.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use crate::mcp_server::{FieldError, MCPError};
+use crate::rag_engine::{RAGEngine, RAGStats};
+
+/// The engine `[rag]` configures, and requests naming none get
+pub const DEFAULT_ENGINE: &str = "default";
+
+/// One engine, uninitialized until opened
+pub type SharedEngine = Arc<RwLock<Option<RAGEngine>>>;
+
+/// An engine as `GET /api/rag/engines` lists it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RagEngineInfo {
+    pub name: String,
+    pub default: bool,
+    /// None until the engine is opened, or while its stats cannot be read
+    pub stats: Option<RAGStats>,
+}
+
+/// `GET /api/rag/engines`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RagEnginesResponse {
+    pub engines: Vec<RagEngineInfo>,
+}
+
+/// The configured engines; names are fixed at startup
+pub struct RagEngines {
+    engines: BTreeMap<String, SharedEngine>,
+}
+
+impl RagEngines {
+    /// `default` under `DEFAULT_ENGINE`, and an empty slot for each of `names`
+    pub fn new(default: SharedEngine, names: impl IntoIterator<Item = String>) -> Self {
+        let mut engines: BTreeMap<String, SharedEngine> = names.into_iter().map(|name| (name, Arc::new(RwLock::new(None)))).collect();
+        engines.insert(DEFAULT_ENGINE.to_string(), default);
+        Self { engines }
+    }
+
+    /// Every name, sorted
+    pub fn names(&self) -> Vec<&str> {
+        self.engines.keys().map(String::as_str).collect()
+    }
+
+    /// The engine called `name`, the default one for none. An unknown name is
+    /// an error on `field` listing the known ones.
+    pub fn get(&self, field: &str, name: Option<&str>) -> Result<SharedEngine, MCPError> {
+        match self.check(field, name) {
+            Some(error) => Err(MCPError::InvalidFields(vec![error])),
+            None => Ok(Arc::clone(&self.engines[name.unwrap_or(DEFAULT_ENGINE)])),
+        }
+    }
+
+    /// Why `name` cannot be used, if it cannot
+    pub fn check(&self, field: &str, name: Option<&str>) -> Option<FieldError> {
+        name.filter(|name| !self.engines.contains_key(*name))
+            .map(|name| FieldError::new(field, format!("one of {}", self.names().join(", ")), name))
+    }
+
+    /// Every engine with its name, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SharedEngine)> {
+        self.engines.iter().map(|(name, engine)| (name.as_str(), engine))
+    }
+
+    /// Every engine with its stats, one at a time under its own read lock
+    pub async fn list(&self) -> RagEnginesResponse {
+        let mut engines = Vec::new();
+        for (name, engine) in self.iter() {
+            let stats = match engine.read().await.as_ref() {
+                Some(engine) => {
+                    engine.get_stats().await.inspect_err(|e| tracing::warn!("Stats of knowledge base {} unavailable: {}", name, e)).ok()
+                }
+                None => None,
+            };
+            engines.push(RagEngineInfo { name: name.to_string(), default: name == DEFAULT_ENGINE, stats });
+        }
+        RagEnginesResponse { engines }
+    }
+}
@@ -0,0 +1,144 @@
+//! Knowledge base work too long for one request. `POST /api/rag/rebuild-fts`
+//! starts refilling the full-text index in the background and answers with a
+//! task id at once; `GET /api/rag/tasks/{id}` reports how far it has got.
+//! Finished tasks are kept for `RETENTION`.
+
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::rag_engine::FtsRebuildProgress;
+
+/// How long finished tasks can still be looked up
+pub const RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// Attempts at one batch before the task gives up
+pub const BATCH_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskKind {
+    RebuildFts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// A task as `GET /api/rag/tasks/{id}` shows it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagTaskView {
+    pub task_id: String,
+    pub kind: TaskKind,
+    /// The knowledge base worked on
+    pub engine: String,
+    pub status: TaskStatus,
+    /// Chunks done so far
+    pub processed: usize,
+    /// Chunks the task has to get through
+    pub total: usize,
+    pub started_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Until now while running, until it finished after
+    pub elapsed_ms: u64,
+    /// Every failed attempt, oldest first; a failed task's last one is why it stopped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Task {
+    view: RagTaskView,
+    started: Instant,
+    finished: Option<Instant>,
+}
+
+impl Task {
+    fn view(&self) -> RagTaskView {
+        let elapsed = self.finished.unwrap_or_else(Instant::now).saturating_duration_since(self.started);
+        RagTaskView { elapsed_ms: elapsed.as_millis() as u64, ..self.view.clone() }
+    }
+}
+
+/// Tasks by id
+#[derive(Debug, Default)]
+pub struct RagTasks {
+    tasks: DashMap<String, Task>,
+}
+
+impl RagTasks {
+    /// A new running task of `kind` on `engine`, unless one is running
+    /// already: a second request joins the first rather than starting over.
+    /// True when new.
+    pub fn start(&self, kind: TaskKind, engine: &str) -> (RagTaskView, bool) {
+        self.prune();
+        let running = |task: &Task| task.view.kind == kind && task.view.engine == engine && task.view.status == TaskStatus::Running;
+        if let Some(task) = self.tasks.iter().find(|task| running(task)) {
+            return (task.view(), false);
+        }
+        let task_id = Uuid::new_v4().to_string();
+        let view = RagTaskView {
+            task_id: task_id.clone(),
+            kind,
+            engine: engine.to_string(),
+            status: TaskStatus::Running,
+            processed: 0,
+            total: 0,
+            started_at: Utc::now(),
+            finished_at: None,
+            elapsed_ms: 0,
+            errors: Vec::new(),
+        };
+        let task = Task { view, started: Instant::now(), finished: None };
+        let view = task.view();
+        self.tasks.insert(task_id, task);
+        (view, true)
+    }
+
+    pub fn get(&self, task_id: &str) -> Option<RagTaskView> {
+        self.prune();
+        self.tasks.get(task_id).map(|task| task.view())
+    }
+
+    /// Records progress, finishing the task once `progress` is done
+    pub fn progress(&self, task_id: &str, progress: FtsRebuildProgress) {
+        if let Some(mut task) = self.tasks.get_mut(task_id) {
+            task.view.processed = progress.processed;
+            task.view.total = progress.total;
+            if progress.done {
+                Self::finish(&mut task, TaskStatus::Completed);
+            }
+        }
+    }
+
+    pub fn error(&self, task_id: &str, error: String) {
+        if let Some(mut task) = self.tasks.get_mut(task_id) {
+            task.view.errors.push(error);
+        }
+    }
+
+    /// Ends the task as failed after the errors it recorded
+    pub fn fail(&self, task_id: &str) {
+        if let Some(mut task) = self.tasks.get_mut(task_id) {
+            Self::finish(&mut task, TaskStatus::Failed);
+        }
+    }
+
+    fn finish(task: &mut Task, status: TaskStatus) {
+        task.view.status = status;
+        task.view.finished_at = Some(Utc::now());
+        task.finished = Some(Instant::now());
+    }
+
+    /// Drops tasks finished more than `RETENTION` ago
+    fn prune(&self) {
+        let now = Instant::now();
+        self.tasks.retain(|_, task| task.finished.is_none_or(|finished| now.saturating_duration_since(finished) < RETENTION));
+    }
+}
@@ -0,0 +1,242 @@
+//! Per-agent token buckets. Each admitted request takes one token; tokens
+//! refill continuously up to the bucket's capacity. Buckets of agents idle
+//! past `idle_after_secs` are swept so the map only holds recently active agents.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BucketLimits {
+    /// Requests an idle agent may make at once
+    pub capacity: u32,
+    pub refill_per_sec: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub capacity: u32,
+    pub refill_per_sec: f64,
+    /// Limits for particular agents, by agent_id
+    pub overrides: HashMap<String, BucketLimits>,
+    /// Buckets untouched this long are dropped
+    pub idle_after_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self { capacity: 120, refill_per_sec: 2.0, overrides: HashMap::new(), idle_after_secs: 15 * 60 }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn limits(&self, agent_id: &str) -> BucketLimits {
+        self.overrides.get(agent_id).copied().unwrap_or(BucketLimits { capacity: self.capacity, refill_per_sec: self.refill_per_sec })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limits: &BucketLimits, now: Instant) {
+        let earned = now.saturating_duration_since(self.updated).as_secs_f64() * limits.refill_per_sec;
+        self.tokens = (self.tokens + earned).min(f64::from(limits.capacity));
+        self.updated = now;
+    }
+
+    /// How long until a whole token is available; None when refills are off
+    fn wait_for_token(&self, limits: &BucketLimits) -> Option<Duration> {
+        let missing = 1.0 - self.tokens;
+        if missing <= 0.0 {
+            Some(Duration::ZERO)
+        } else if limits.refill_per_sec > 0.0 {
+            Some(Duration::from_secs_f64(missing / limits.refill_per_sec))
+        } else {
+            None
+        }
+    }
+}
+
+/// What an agent has left, as reported by the throttle endpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketState {
+    pub remaining: u32,
+    pub capacity: u32,
+    pub refill_per_sec: f64,
+    /// Zero while a token is available
+    pub next_token_in: Duration,
+}
+
+/// A rejected request: when the agent may try again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverLimit {
+    pub retry_after: Duration,
+}
+
+/// Refills that never come back are reported as a day away
+const NEVER: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RwLock<RateLimitConfig>,
+    idle_after: Duration,
+    buckets: DashMap<String, Bucket>,
+    last_sweep: Mutex<Instant>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(RateLimitConfig::default())
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        let idle_after = Duration::from_secs(config.idle_after_secs);
+        Self { config: RwLock::new(config), idle_after, buckets: DashMap::new(), last_sweep: Mutex::new(Instant::now()) }
+    }
+
+    pub fn config(&self) -> RateLimitConfig {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// New limits for every bucket from its next refill on, keeping what
+    /// each holds. `idle_after_secs` keeps the value the limiter was built with.
+    pub fn update(&self, config: RateLimitConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    fn limits(&self, agent_id: &str) -> BucketLimits {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).limits(agent_id)
+    }
+
+    fn full_bucket(limits: &BucketLimits, now: Instant) -> Bucket {
+        Bucket { tokens: f64::from(limits.capacity), updated: now }
+    }
+
+    /// Takes a token from the agent's bucket. Refill and take happen under the
+    /// bucket's map entry lock, so concurrent callers never over-admit.
+    pub fn try_acquire(&self, agent_id: &str) -> Result<BucketState, OverLimit> {
+        let now = Instant::now();
+        self.sweep_if_due(now);
+        let limits = self.limits(agent_id);
+        let mut bucket = self.buckets.entry(agent_id.to_string()).or_insert_with(|| Self::full_bucket(&limits, now));
+        bucket.refill(&limits, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(Self::snapshot(&bucket, &limits))
+        } else {
+            Err(OverLimit { retry_after: bucket.wait_for_token(&limits).unwrap_or(NEVER) })
+        }
+    }
+
+    /// The agent's bucket without taking from it
+    pub fn state(&self, agent_id: &str) -> BucketState {
+        let now = Instant::now();
+        let limits = self.limits(agent_id);
+        let mut bucket = self.buckets.get(agent_id).map_or_else(|| Self::full_bucket(&limits, now), |bucket| *bucket);
+        bucket.refill(&limits, now);
+        Self::snapshot(&bucket, &limits)
+    }
+
+    fn snapshot(bucket: &Bucket, limits: &BucketLimits) -> BucketState {
+        BucketState {
+            remaining: bucket.tokens.floor() as u32,
+            capacity: limits.capacity,
+            refill_per_sec: limits.refill_per_sec,
+            next_token_in: bucket.wait_for_token(limits).unwrap_or(NEVER),
+        }
+    }
+
+    /// Drops buckets untouched for `idle_after_secs`; they would be full again by now
+    /// or, with no refill, the agent gets a fresh one. Returns how many went.
+    pub fn evict_idle(&self) -> usize {
+        let now = Instant::now();
+        let before = self.buckets.len();
+        self.buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated) < self.idle_after);
+        before - self.buckets.len()
+    }
+
+    pub fn tracked_agents(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn sweep_if_due(&self, now: Instant) {
+        let mut last_sweep = match self.last_sweep.try_lock() {
+            Ok(guard) => guard,
+            // Another caller is sweeping
+            Err(_) => return,
+        };
+        if now.saturating_duration_since(*last_sweep) >= self.idle_after {
+            *last_sweep = now;
+            drop(last_sweep);
+            self.evict_idle();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(capacity: u32, refill_per_sec: f64) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig { capacity, refill_per_sec, ..Default::default() })
+    }
+
+    #[test]
+    fn buckets_empty_then_report_when_to_retry() {
+        let limiter = limiter(2, 10.0);
+        assert_eq!(limiter.try_acquire("a").unwrap().remaining, 1);
+        assert_eq!(limiter.try_acquire("a").unwrap().remaining, 0);
+        let over = limiter.try_acquire("a").unwrap_err();
+        assert!(over.retry_after > Duration::ZERO && over.retry_after <= Duration::from_millis(100), "{:?}", over);
+        assert_eq!(limiter.state("b").remaining, 2);
+
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(limiter.try_acquire("a").is_ok());
+    }
+
+    #[test]
+    fn overrides_apply_per_agent() {
+        let mut config = RateLimitConfig { capacity: 1, refill_per_sec: 0.0, ..Default::default() };
+        config.overrides.insert("batch".to_string(), BucketLimits { capacity: 3, refill_per_sec: 0.0 });
+        let limiter = RateLimiter::new(config);
+        assert_eq!(limiter.state("batch").capacity, 3);
+        assert!(limiter.try_acquire("other").is_ok());
+        assert_eq!(limiter.try_acquire("other").unwrap_err().retry_after, NEVER);
+        assert_eq!((0..5).filter(|_| limiter.try_acquire("batch").is_ok()).count(), 3);
+    }
+
+    #[test]
+    fn concurrent_callers_never_over_admit() {
+        let limiter = std::sync::Arc::new(limiter(50, 0.0));
+        let admitted: usize = (0..8)
+            .map(|_| {
+                let limiter = std::sync::Arc::clone(&limiter);
+                std::thread::spawn(move || (0..100).filter(|_| limiter.try_acquire("hammer").is_ok()).count())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .sum();
+        assert_eq!(admitted, 50);
+    }
+
+    #[test]
+    fn idle_buckets_are_evicted() {
+        let limiter = RateLimiter { idle_after: Duration::from_millis(20), ..Default::default() };
+        limiter.try_acquire("a").unwrap();
+        limiter.try_acquire("b").unwrap();
+        assert_eq!(limiter.evict_idle(), 0);
+        std::thread::sleep(Duration::from_millis(30));
+        limiter.try_acquire("b").unwrap();
+        assert_eq!(limiter.tracked_agents(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+//! Reloading the config file without a restart, on SIGHUP or
+//! `POST /api/admin/reload`. The file is read and validated whole, with the
+//! environment overrides applied as at startup; any problem leaves the
+//! running config untouched. Otherwise the sections that changed and can be
+//! swapped in place are: chaos settings, rate limits, throttling, quotas,
+//! specialties, prompt templates, backends and moral recentering. Other
+//! changes, such as the bind address or TLS ports, are listed as skipped and
+//! wait for a restart.
+
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::config::ConfigSource;
+use crate::mcp_server::VoidShrineMCP;
+
+/// Sections a reload swaps in
+pub const RELOADABLE_SECTIONS: [&str; 8] =
+    ["chaos", "rate_limits", "throttle", "quotas", "specialties", "templates", "backends", "moral"];
+
+/// Keys of reloadable sections read only at startup
+const RESTART_ONLY: [&str; 3] = ["rate_limits.idle_after_secs", "templates.reload_poll_secs", "backends.model_refresh_secs"];
+
+/// What a reload did
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReloadReport {
+    /// Sections that changed and now apply
+    pub applied: Vec<String>,
+    /// Keys that changed but only take effect on restart
+    pub skipped: Vec<String>,
+    /// Why the file was refused; nothing was applied when there are any
+    pub errors: Vec<String>,
+    /// One line saying the above
+    pub message: String,
+    /// The file now in effect
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<ConfigSource>,
+}
+
+impl ReloadReport {
+    pub fn refused(errors: Vec<String>) -> Self {
+        let message = format!("Kept the running config: {}", errors.join("; "));
+        Self { errors, message, ..Self::default() }
+    }
+
+    /// Splits the keys a reload changed into the sections it applies and
+    /// the keys it skips
+    pub fn sorted(changed: Vec<String>, config: Option<ConfigSource>) -> Self {
+        let mut report = Self { config, ..Self::default() };
+        for key in changed {
+            let section = key.split('.').next().unwrap_or_default();
+            if !RELOADABLE_SECTIONS.contains(&section) || RESTART_ONLY.contains(&key.as_str()) {
+                report.skipped.push(key);
+            } else if !report.applied.iter().any(|applied| applied == section) {
+                report.applied.push(section.to_string());
+            }
+        }
+        report.message = match (report.applied.is_empty(), report.skipped.is_empty()) {
+            (true, true) => "Nothing changed".to_string(),
+            (false, true) => format!("Applied {}", report.applied.join(", ")),
+            (true, false) => format!("Applied nothing; {} only change on restart", report.skipped.join(", ")),
+            (false, false) => {
+                format!("Applied {}; {} only change on restart", report.applied.join(", "), report.skipped.join(", "))
+            }
+        };
+        report
+    }
+
+    pub fn applies(&self, section: &str) -> bool {
+        self.applied.iter().any(|applied| applied == section)
+    }
+}
+
+/// Dotted keys whose values differ between two versions of the file, down
+/// to the values that aren't tables, sorted
+pub fn changed_keys(old: &toml::Table, new: &toml::Table) -> Vec<String> {
+    let mut changed = Vec::new();
+    diff_tables("", old, new, &mut changed);
+    changed.sort();
+    changed
+}
+
+fn diff_tables(prefix: &str, old: &toml::Table, new: &toml::Table, changed: &mut Vec<String>) {
+    let empty = toml::Table::new();
+    let removed = old.keys().filter(|key| !new.contains_key(*key));
+    for key in new.keys().chain(removed) {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match (old.get(key), new.get(key)) {
+            (Some(toml::Value::Table(old)), Some(toml::Value::Table(new))) => diff_tables(&path, old, new, changed),
+            (None, Some(toml::Value::Table(new))) => diff_tables(&path, &empty, new, changed),
+            (Some(toml::Value::Table(old)), None) => diff_tables(&path, old, &empty, changed),
+            (old, new) if old != new => changed.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// Reloads the config on every SIGHUP
+pub fn on_hangup(service: Arc<VoidShrineMCP>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        {
+            let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    tracing::warn!("Cannot listen for SIGHUP, the config reloads over HTTP only: {}", e);
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                tracing::info!("SIGHUP received, reloading the config");
+                if let Err(e) = service.handle_reload_config().await {
+                    tracing::warn!("Cannot reload the config: {}", e);
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        drop(service);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_found_by_key_and_sorted_by_section() {
+        let old: toml::Table = "[server]\nport = 3030\n[chaos]\nintensity = 0.1\n[rate_limits]\ncapacity = 5\n".parse().unwrap();
+        let new: toml::Table =
+            "[server]\nport = 4040\n[server.tls]\ncert_path = \"c\"\n[chaos]\nintensity = 0.2\n[throttle]\nsoft_load = 0.5\n[rate_limits]\ncapacity = 5\nidle_after_secs = 1\n"
+                .parse()
+                .unwrap();
+        let changed = changed_keys(&old, &new);
+        assert_eq!(changed, ["chaos.intensity", "rate_limits.idle_after_secs", "server.port", "server.tls.cert_path", "throttle.soft_load"]);
+
+        let report = ReloadReport::sorted(changed, None);
+        assert_eq!(report.applied, ["chaos", "throttle"]);
+        assert_eq!(report.skipped, ["rate_limits.idle_after_secs", "server.port", "server.tls.cert_path"]);
+        assert!(report.message.contains("only change on restart"), "{}", report.message);
+        assert!(changed_keys(&new, &new).is_empty());
+    }
+}
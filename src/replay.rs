@@ -0,0 +1,263 @@
+//! Replaying audited requests through the pipeline as it is now, to see what
+//! a change of templates, retrieval settings or backends does to real
+//! traffic. Each recorded `llm_inference` request is sent again with its
+//! recorded params and compared with its replay: response length, tokens,
+//! the documents retrieved and the moral adjustments made.
+//!
+//! Replays leave production statistics alone. They skip chaos, rate limits,
+//! throttling, sessions and the response cache, are counted apart from other
+//! requests in `void_shrine_replays_total`, and are audited with `replay_of`
+//! naming the request replayed.
+
+use std::collections::BTreeSet;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::audit::{cited_documents, AuditRecord};
+use crate::mcp_server::MCPResult;
+
+/// Bounds on replaying recorded requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// Records one replay may select
+    pub max_records: usize,
+    /// Replayed requests in flight at once; each also takes a concurrency slot
+    pub concurrency: usize,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self { max_records: 200, concurrency: 4 }
+    }
+}
+
+/// What answers a replayed request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayMode {
+    /// The backends serving traffic
+    #[default]
+    Live,
+    /// `MockBackend`, at no cost
+    Mock,
+    /// Nothing: the prompt is assembled as `dry_run` does and no backend is called
+    DryRun,
+}
+
+impl ReplayMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReplayMode::Live => "live",
+            ReplayMode::Mock => "mock",
+            ReplayMode::DryRun => "dry_run",
+        }
+    }
+}
+
+/// Body of `POST /api/audit/replay`: the records named by `request_ids`, or
+/// else the newest matching the filters
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ReplayRequest {
+    pub request_ids: Vec<String>,
+    pub agent_id: Option<String>,
+    /// Only records at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only records before this time
+    pub until: Option<DateTime<Utc>>,
+    /// At most `ReplayConfig::max_records`, which is also the default
+    pub limit: Option<usize>,
+    pub mode: ReplayMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayOutcome {
+    Replayed,
+    /// The replay failed; the reason is its error
+    Failed,
+    /// Not replayable, e.g. not an `llm_inference` request or recorded without its params
+    Skipped,
+}
+
+impl ReplayOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReplayOutcome::Replayed => "replayed",
+            ReplayOutcome::Failed => "failed",
+            ReplayOutcome::Skipped => "skipped",
+        }
+    }
+}
+
+/// A recorded request and what became of its replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayResult {
+    pub request_id: String,
+    /// None for ids not in the audit log
+    pub agent_id: Option<String>,
+    pub outcome: ReplayOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The replay's own request id, audited with `replay_of` set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<ReplayComparison>,
+}
+
+impl ReplayResult {
+    pub fn skipped(request_id: &str, agent_id: Option<&str>, reason: impl Into<String>) -> Self {
+        Self {
+            request_id: request_id.to_string(),
+            agent_id: agent_id.map(str::to_string),
+            outcome: ReplayOutcome::Skipped,
+            reason: Some(reason.into()),
+            replay_id: None,
+            comparison: None,
+        }
+    }
+}
+
+/// A value as recorded and as replayed; None where there is none to
+/// compare, e.g. the response of a failed request or of a dry run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Compared<T> {
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+
+impl Compared<u32> {
+    /// After minus before, when both are known
+    pub fn change(&self) -> Option<f64> {
+        Some(self.after? as f64 - self.before? as f64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentOverlap {
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+    /// Documents in both over documents in either; 1 when neither has any
+    pub overlap: f64,
+}
+
+impl DocumentOverlap {
+    pub fn new(before: Vec<String>, after: Vec<String>) -> Self {
+        let old: BTreeSet<&String> = before.iter().collect();
+        let new: BTreeSet<&String> = after.iter().collect();
+        let either = old.union(&new).count();
+        let overlap = match either {
+            0 => 1.0,
+            either => old.intersection(&new).count() as f64 / either as f64,
+        };
+        Self { before, after, overlap }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MoralComparison {
+    pub recentered: Compared<bool>,
+    pub ethical_adjustments: Compared<Vec<String>>,
+    /// Whether the prompt was recentered differently
+    pub changed: bool,
+}
+
+/// A recorded request against its replay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayComparison {
+    pub response_chars: Compared<u32>,
+    /// A dry run's are the assembled prompt's, as the server's tokenizer counts them
+    pub prompt_tokens: Compared<u32>,
+    pub completion_tokens: Compared<u32>,
+    pub documents: DocumentOverlap,
+    pub moral: MoralComparison,
+}
+
+impl ReplayComparison {
+    pub fn new(original: &AuditRecord, replay: &MCPResult) -> Self {
+        let dry_run = replay.prompt_preview.is_some();
+        let answered = original.error.is_none();
+        let chars = |text: &str| text.chars().count() as u32;
+        let recentering = replay.moral_recentering.as_ref();
+        let recentered = Compared {
+            before: answered.then_some(original.moral_recentered),
+            after: Some(recentering.is_some_and(|report| report.recentered)),
+        };
+        let ethical_adjustments = Compared {
+            before: answered.then(|| original.ethical_adjustments.clone()),
+            after: Some(recentering.map(|report| report.ethical_adjustments.clone()).unwrap_or_default()),
+        };
+        let changed = answered && (recentered.before != recentered.after || ethical_adjustments.before != ethical_adjustments.after);
+        Self {
+            response_chars: Compared {
+                before: original.response.as_deref().filter(|_| answered).map(chars),
+                after: (!dry_run).then(|| chars(&replay.response)),
+            },
+            prompt_tokens: Compared {
+                before: original.metrics.as_ref().map(|metrics| metrics.prompt_tokens),
+                after: Some(replay.prompt_preview.as_ref().map_or(replay.metrics.prompt_tokens, |preview| preview.tokens.total)),
+            },
+            completion_tokens: Compared {
+                before: original.metrics.as_ref().map(|metrics| metrics.completion_tokens),
+                after: (!dry_run).then_some(replay.metrics.completion_tokens),
+            },
+            documents: DocumentOverlap::new(original.rag_document_ids.clone(), cited_documents(replay)),
+            moral: MoralComparison { recentered, ethical_adjustments, changed },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplaySummary {
+    pub selected: usize,
+    pub replayed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Means over the replays where both sides are known
+    pub mean_response_chars_change: Option<f64>,
+    pub mean_prompt_tokens_change: Option<f64>,
+    pub mean_document_overlap: Option<f64>,
+    /// Replays whose prompt was recentered differently
+    pub moral_changed: usize,
+}
+
+impl ReplaySummary {
+    pub fn new(results: &[ReplayResult]) -> Self {
+        let count = |outcome: ReplayOutcome| results.iter().filter(|result| result.outcome == outcome).count();
+        let comparisons: Vec<&ReplayComparison> = results.iter().filter_map(|result| result.comparison.as_ref()).collect();
+        let mean = |values: Vec<f64>| (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64);
+        Self {
+            selected: results.len(),
+            replayed: count(ReplayOutcome::Replayed),
+            failed: count(ReplayOutcome::Failed),
+            skipped: count(ReplayOutcome::Skipped),
+            mean_response_chars_change: mean(comparisons.iter().filter_map(|comparison| comparison.response_chars.change()).collect()),
+            mean_prompt_tokens_change: mean(comparisons.iter().filter_map(|comparison| comparison.prompt_tokens.change()).collect()),
+            mean_document_overlap: mean(comparisons.iter().map(|comparison| comparison.documents.overlap).collect()),
+            moral_changed: comparisons.iter().filter(|comparison| comparison.moral.changed).count(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayResponse {
+    pub mode: ReplayMode,
+    pub results: Vec<ReplayResult>,
+    pub summary: ReplaySummary,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlap_is_shared_over_either() {
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(DocumentOverlap::new(ids(&["a", "b", "c"]), ids(&["b", "c", "d"])).overlap, 0.5);
+        assert_eq!(DocumentOverlap::new(ids(&["a"]), ids(&["b"])).overlap, 0.0);
+        assert_eq!(DocumentOverlap::new(Vec::new(), Vec::new()).overlap, 1.0);
+        assert_eq!(Compared { before: Some(10), after: Some(4) }.change(), Some(-6.0));
+        assert_eq!(Compared { before: None, after: Some(4) }.change(), None);
+    }
+}
@@ -0,0 +1,283 @@
+//! Latency per HTTP route, whatever the agent or method behind it.
+//! `api::observe_routes` wraps the served routes and times each request from
+//! arrival to its response head (so a stream or WebSocket counts until it
+//! starts), labelled with its method, route pattern and status class.
+//! Patterns name the route rather than the path asked for, `{id}` standing in
+//! for ids and `unmatched` for paths no route serves, so the label count stays
+//! bounded. Rejections are counted with the status `api::recover` gives them;
+//! a request that panics, which leaves its connection closed without a
+//! response, as a `5xx`; one dropped before answering, its client gone, as
+//! `aborted`. Fixed buckets feed `void_shrine_route_duration_seconds` in
+//! `GET /metrics` and the percentiles in `GET /api/metrics`.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use crate::metrics::{Metrics, ROUTE_LATENCY_BUCKETS};
+
+/// Every route served, ids as `{...}` segments
+pub const ROUTE_PATTERNS: &[&str] = &[
+    "/health",
+    "/ready",
+    "/metrics",
+    "/mcp",
+    "/ws/mcp",
+    "/ws/metrics",
+    "/api/mcp",
+    "/api/mcp/stream",
+    "/api/mcp/batch",
+    "/api/mcp/cancel/{request_id}",
+    "/api/jobs",
+    "/api/jobs/{id}",
+    "/api/rag/documents",
+    "/api/rag/documents/{id}",
+    "/api/rag/stats",
+    "/api/rag/stats/by/{key}",
+    "/api/rag/engines",
+    "/api/rag/query",
+    "/api/rag/index-url",
+    "/api/rag/ranking",
+    "/api/rag/analytics",
+    "/api/rag/backup",
+    "/api/rag/maintenance",
+    "/api/rag/rebuild-fts",
+    "/api/rag/tasks/{id}",
+    "/api/embed",
+    "/api/audit",
+    "/api/audit/replay",
+    "/api/sessions",
+    "/api/sessions/{id}",
+    "/api/agents",
+    "/api/agents/{id}",
+    "/api/agents/{id}/metrics",
+    "/api/agents/{id}/heartbeat",
+    "/api/metrics",
+    "/api/metrics/prune",
+    "/api/metrics/export",
+    "/api/specialties",
+    "/api/specialties/reload",
+    "/api/tokens/verify",
+    "/api/concurrency",
+    "/api/overload",
+    "/api/quotas",
+    "/api/models",
+    "/api/cache",
+    "/api/admin/reload",
+    "/api/chaos",
+    "/api/chaos/config",
+    "/api/chaos/report",
+    "/api/throttle/{agent_id}",
+    "/api/usage",
+    "/api/dashboard",
+    "/api/version",
+    "/api/scaling",
+    "/api/scaling/history",
+    "/api/moral-recentering",
+    "/api/moral-recentering/preview",
+    "/api/moral-recentering/config",
+    "/api/moral-recentering/reload",
+];
+
+/// The pattern of paths no route serves
+pub const UNMATCHED: &str = "unmatched";
+
+/// The status class of requests dropped before they were answered
+pub const ABORTED: &str = "aborted";
+
+/// The pattern in `ROUTE_PATTERNS` matching `path`, else `UNMATCHED`
+pub fn route_pattern(path: &str) -> &'static str {
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    ROUTE_PATTERNS
+        .iter()
+        .find(|pattern| {
+            let mut expected = pattern.split('/').filter(|segment| !segment.is_empty());
+            let mut actual = segments.iter();
+            loop {
+                match (expected.next(), actual.next()) {
+                    (None, None) => return true,
+                    (Some(expected), Some(actual)) if expected.starts_with('{') || expected == *actual => {}
+                    _ => return false,
+                }
+            }
+        })
+        .copied()
+        .unwrap_or(UNMATCHED)
+}
+
+/// `GET`, `POST` and the other standard methods as they are; anything else as `OTHER`
+fn method_label(method: &str) -> &'static str {
+    ["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"].into_iter().find(|known| *known == method).unwrap_or("OTHER")
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// One bucket of `RouteLatency::buckets`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencyBucket {
+    /// Upper bound; none for the bucket past the last
+    pub le_ms: Option<f64>,
+    /// Requests in this bucket alone, not cumulative
+    pub count: u64,
+}
+
+/// A route as `GET /api/metrics` reports it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteLatency {
+    pub method: String,
+    pub route: String,
+    pub in_flight: u64,
+    /// Requests finished, answered or not
+    pub requests: u64,
+    /// Finished requests by status class, `aborted` included
+    pub status_classes: BTreeMap<String, u64>,
+    pub mean_ms: Option<f64>,
+    /// Upper bounds of the buckets the percentiles fall in; the slowest
+    /// request for the bucket past the last
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub buckets: Vec<LatencyBucket>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// One more than `ROUTE_LATENCY_BUCKETS`, for slower requests
+    buckets: [u64; ROUTE_LATENCY_BUCKETS.len() + 1],
+    total: Duration,
+    max: Duration,
+    status_classes: BTreeMap<&'static str, u64>,
+}
+
+impl Histogram {
+    fn percentile(&self, requests: u64, quantile: f64) -> Option<f64> {
+        let rank = ((requests as f64) * quantile).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let bound = ROUTE_LATENCY_BUCKETS.get(i).map_or(self.max.as_secs_f64(), |bound| *bound);
+                return Some(bound * 1000.0);
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Default)]
+struct RouteStats {
+    in_flight: AtomicU64,
+    histogram: Mutex<Histogram>,
+}
+
+/// Histograms and in-flight counts by method and route pattern
+pub struct RouteMetrics {
+    routes: DashMap<(&'static str, &'static str), Arc<RouteStats>>,
+    metrics: Arc<Metrics>,
+}
+
+impl RouteMetrics {
+    /// Also feeds `metrics`' route series
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { routes: DashMap::new(), metrics }
+    }
+
+    /// Counts a request as in flight until the timer is finished or dropped
+    pub fn start(&self, method: &str, path: &str) -> RouteTimer {
+        let key = (method_label(method), route_pattern(path));
+        let stats = match self.routes.get(&key) {
+            Some(stats) => Arc::clone(&stats),
+            None => Arc::clone(&self.routes.entry(key).or_default()),
+        };
+        stats.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.metrics.route_started(key.0, key.1);
+        RouteTimer { stats, metrics: Arc::clone(&self.metrics), method: key.0, route: key.1, started: Instant::now(), finished: false }
+    }
+
+    /// Every route requested so far, sorted by route then method
+    pub fn report(&self) -> Vec<RouteLatency> {
+        let mut routes: Vec<RouteLatency> = self
+            .routes
+            .iter()
+            .map(|entry| {
+                let (method, route) = *entry.key();
+                let histogram = entry.histogram.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                let requests: u64 = histogram.buckets.iter().sum();
+                let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+                let answered = |value: f64| (requests > 0).then_some(value);
+                RouteLatency {
+                    method: method.to_string(),
+                    route: route.to_string(),
+                    in_flight: entry.in_flight.load(Ordering::Relaxed),
+                    requests,
+                    status_classes: histogram.status_classes.iter().map(|(class, count)| (class.to_string(), *count)).collect(),
+                    mean_ms: answered(ms(histogram.total) / requests.max(1) as f64),
+                    p50_ms: histogram.percentile(requests, 0.50),
+                    p95_ms: histogram.percentile(requests, 0.95),
+                    p99_ms: histogram.percentile(requests, 0.99),
+                    max_ms: answered(ms(histogram.max)),
+                    buckets: histogram
+                        .buckets
+                        .iter()
+                        .enumerate()
+                        .map(|(i, count)| LatencyBucket { le_ms: ROUTE_LATENCY_BUCKETS.get(i).map(|bound| bound * 1000.0), count: *count })
+                        .collect(),
+                }
+            })
+            .collect();
+        routes.sort_by(|a, b| a.route.cmp(&b.route).then_with(|| a.method.cmp(&b.method)));
+        routes
+    }
+}
+
+/// A request on its way through a route
+pub struct RouteTimer {
+    stats: Arc<RouteStats>,
+    metrics: Arc<Metrics>,
+    method: &'static str,
+    route: &'static str,
+    started: Instant,
+    finished: bool,
+}
+
+impl RouteTimer {
+    /// Records the request as answered with `status`
+    pub fn finish(mut self, status: StatusCode) {
+        self.record(status_class(status));
+    }
+
+    fn record(&mut self, class: &'static str) {
+        self.finished = true;
+        let elapsed = self.started.elapsed();
+        self.stats.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.metrics.route_finished(self.method, self.route, class, elapsed);
+        let mut histogram = self.stats.histogram.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let bucket = ROUTE_LATENCY_BUCKETS.iter().position(|bound| elapsed.as_secs_f64() <= *bound).unwrap_or(ROUTE_LATENCY_BUCKETS.len());
+        histogram.buckets[bucket] += 1;
+        histogram.total += elapsed;
+        histogram.max = histogram.max.max(elapsed);
+        *histogram.status_classes.entry(class).or_default() += 1;
+    }
+}
+
+impl Drop for RouteTimer {
+    fn drop(&mut self) {
+        if !self.finished {
+            // Unwinding drops the request's future along with this timer
+            let class = if std::thread::panicking() { "5xx" } else { ABORTED };
+            self.record(class);
+        }
+    }
+}
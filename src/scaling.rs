@@ -0,0 +1,485 @@
+//! Autoscaling advice from an agent's recent history. Each finished request,
+//! handled here or reported to `/api/scaling`, is kept in a per-agent ring;
+//! decisions look at the p95 latency and error rate across it. Scaling up is
+//! followed by a cooldown, and scaling down needs a quiet period without slow
+//! or failed requests, so consecutive reports don't flip the advice back and
+//! forth; nor does either follow the other within `reversal_guard_secs`.
+//! Every decision is kept in a bounded `ScalingLog` with the figures and
+//! thresholds behind it, saved with the agent metrics, and those cooldowns are
+//! measured from the decisions recorded there, so a restart doesn't reset them.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::load::p95;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScalingConfig {
+    /// Observations kept per agent
+    pub window: usize,
+    /// Observations needed before any advice but "hold"
+    pub min_observations: usize,
+    /// p95 latency above which to scale up
+    pub scale_up_p95_ms: u64,
+    /// Share of failed requests above which to scale up
+    pub max_error_rate: f64,
+    /// Requests at or above this latency, or failing, break a quiet period
+    pub quiet_latency_ms: u64,
+    /// How long an agent must stay quiet before scaling down, again after each scale-down
+    pub quiet_period_secs: u64,
+    /// Minimum time between two scale-ups
+    pub cooldown_secs: u64,
+    /// Minimum time between scaling one way and the other
+    pub reversal_guard_secs: u64,
+    /// Decisions kept for `GET /api/scaling/history`, across every agent
+    pub history_capacity: usize,
+}
+
+impl Default for ScalingConfig {
+    fn default() -> Self {
+        Self {
+            window: 50,
+            min_observations: 5,
+            scale_up_p95_ms: 10_000,
+            max_error_rate: 0.1,
+            quiet_latency_ms: 1_000,
+            quiet_period_secs: 300,
+            cooldown_secs: 60,
+            reversal_guard_secs: 120,
+            history_capacity: 1000,
+        }
+    }
+}
+
+impl ScalingConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.window == 0 || self.min_observations == 0 || self.min_observations > self.window {
+            problems.push(format!(
+                "scaling.min_observations must be positive and at most scaling.window ({} > {})",
+                self.min_observations, self.window
+            ));
+        }
+        if self.quiet_latency_ms >= self.scale_up_p95_ms {
+            problems.push(format!(
+                "scaling.quiet_latency_ms must be below scaling.scale_up_p95_ms ({} >= {})",
+                self.quiet_latency_ms, self.scale_up_p95_ms
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.max_error_rate) {
+            problems.push(format!("scaling.max_error_rate must be between 0 and 1 (got {})", self.max_error_rate));
+        }
+        if self.history_capacity == 0 {
+            problems.push("scaling.history_capacity must be positive".to_string());
+        }
+        problems
+    }
+}
+
+/// One finished request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observation {
+    pub response_time_ms: Option<u64>,
+    pub success: bool,
+    pub token_count: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalingDirection {
+    Up,
+    Hold,
+    Down,
+}
+
+/// A decision and the figures behind it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalingDecision {
+    pub direction: ScalingDirection,
+    pub reason: String,
+    pub observations: usize,
+    pub p95_ms: Option<u64>,
+    pub error_rate: f64,
+    pub avg_tokens: Option<f64>,
+}
+
+impl ScalingDecision {
+    /// The reason followed by the evidence, e.g. "Scaling up: p95 latency
+    /// above 10000 ms (window 20, p95 12000 ms, error rate 5.0%)"
+    pub fn description(&self) -> String {
+        let p95 = self.p95_ms.map_or("n/a".to_string(), |ms| format!("{ms} ms"));
+        let mut evidence = format!(
+            "window {}, p95 {}, error rate {:.1}%",
+            self.observations, p95, self.error_rate * 100.0
+        );
+        if let Some(avg_tokens) = self.avg_tokens {
+            evidence.push_str(&format!(", avg {avg_tokens:.0} tokens"));
+        }
+        format!("{} ({})", self.reason, evidence)
+    }
+}
+
+/// An agent's recent observations, and the start of its current quiet period
+#[derive(Debug, Clone, Default)]
+pub struct ScalingHistory {
+    observations: VecDeque<Observation>,
+    /// The last slow or failed request, or the first observation
+    quiet_since: Option<DateTime<Utc>>,
+}
+
+/// When an agent was last advised to scale each way, from the `ScalingLog`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecentScaling {
+    pub last_up: Option<DateTime<Utc>>,
+    pub last_down: Option<DateTime<Utc>>,
+}
+
+impl ScalingHistory {
+    pub fn record(&mut self, observation: Observation, now: DateTime<Utc>, config: &ScalingConfig) {
+        while self.observations.len() >= config.window.max(1) {
+            self.observations.pop_front();
+        }
+        self.observations.push_back(observation);
+        let slow = observation.response_time_ms.is_some_and(|ms| ms >= config.quiet_latency_ms);
+        if slow || !observation.success || self.quiet_since.is_none() {
+            self.quiet_since = Some(now);
+        }
+    }
+
+    /// Advice from the observations, held back by the cooldown, the quiet
+    /// period restarting at each scale-down, and `reversal_guard_secs`, all
+    /// measured from the decisions `recent` recalls
+    pub fn decide(&self, now: DateTime<Utc>, recent: RecentScaling, config: &ScalingConfig) -> ScalingDecision {
+        let observations = self.observations.len();
+        let p95_ms = p95(self.observations.iter().filter_map(|o| o.response_time_ms).collect());
+        let failures = self.observations.iter().filter(|o| !o.success).count();
+        let error_rate = if observations == 0 { 0.0 } else { failures as f64 / observations as f64 };
+        let tokens: Vec<u32> = self.observations.iter().filter_map(|o| o.token_count).collect();
+        let avg_tokens = (!tokens.is_empty())
+            .then(|| tokens.iter().map(|t| f64::from(*t)).sum::<f64>() / tokens.len() as f64);
+
+        let since = |at: Option<DateTime<Utc>>| at.map(|at| (now - at).to_std().unwrap_or_default());
+        let within = |at: Option<DateTime<Utc>>, secs: u64| since(at).filter(|elapsed| *elapsed < Duration::from_secs(secs));
+        let slow = p95_ms.is_some_and(|ms| ms > config.scale_up_p95_ms);
+        let failing = error_rate > config.max_error_rate;
+        let quiet_for = since(self.quiet_since.max(recent.last_down)).unwrap_or_default();
+
+        let (direction, reason) = if observations < config.min_observations {
+            (ScalingDirection::Hold, format!("Not enough history yet, need {} requests", config.min_observations))
+        } else if slow || failing {
+            let cause = if slow {
+                format!("p95 latency above {} ms", config.scale_up_p95_ms)
+            } else {
+                format!("error rate above {:.1}%", config.max_error_rate * 100.0)
+            };
+            if let Some(elapsed) = within(recent.last_up, config.cooldown_secs) {
+                (ScalingDirection::Hold, format!("Holding: {cause}, but scaled up {}s ago", elapsed.as_secs()))
+            } else if let Some(elapsed) = within(recent.last_down, config.reversal_guard_secs) {
+                (ScalingDirection::Hold, format!("Holding: {cause}, but scaled down {}s ago", elapsed.as_secs()))
+            } else {
+                (ScalingDirection::Up, format!("Scaling up: {cause}"))
+            }
+        } else if quiet_for >= Duration::from_secs(config.quiet_period_secs) {
+            let cause = format!("no request at or above {} ms or failing for {}s", config.quiet_latency_ms, quiet_for.as_secs());
+            match within(recent.last_up, config.reversal_guard_secs) {
+                Some(elapsed) => (ScalingDirection::Hold, format!("Holding: {cause}, but scaled up {}s ago", elapsed.as_secs())),
+                None => (ScalingDirection::Down, format!("Can scale down: {cause}")),
+            }
+        } else {
+            (ScalingDirection::Hold, "No adjustments needed".to_string())
+        };
+        ScalingDecision { direction, reason, observations, p95_ms, error_rate, avg_tokens }
+    }
+}
+
+/// The thresholds a decision compared its figures against
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScalingThresholds {
+    pub scale_up_p95_ms: u64,
+    pub max_error_rate: f64,
+    pub quiet_latency_ms: u64,
+}
+
+impl From<&ScalingConfig> for ScalingThresholds {
+    fn from(config: &ScalingConfig) -> Self {
+        Self { scale_up_p95_ms: config.scale_up_p95_ms, max_error_rate: config.max_error_rate, quiet_latency_ms: config.quiet_latency_ms }
+    }
+}
+
+/// One piece of advice given, with the figures and thresholds behind it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScalingRecord {
+    pub agent_id: String,
+    pub decided_at: DateTime<Utc>,
+    pub direction: ScalingDirection,
+    pub description: String,
+    pub capacity_change: f64,
+    pub priority_adjustment: i32,
+    /// Requests in the window decided on
+    pub observations: usize,
+    pub p95_ms: Option<u64>,
+    pub error_rate: f64,
+    pub avg_tokens: Option<f64>,
+    pub thresholds: ScalingThresholds,
+}
+
+impl ScalingRecord {
+    pub fn new(agent_id: &str, decided_at: DateTime<Utc>, decision: &ScalingDecision, config: &ScalingConfig) -> Self {
+        let (capacity_change, priority_adjustment) = decision.direction.adjustments();
+        Self {
+            agent_id: agent_id.to_string(),
+            decided_at,
+            direction: decision.direction,
+            description: decision.description(),
+            capacity_change,
+            priority_adjustment,
+            observations: decision.observations,
+            p95_ms: decision.p95_ms,
+            error_rate: decision.error_rate,
+            avg_tokens: decision.avg_tokens,
+            thresholds: config.into(),
+        }
+    }
+}
+
+impl ScalingDirection {
+    /// The capacity change and priority adjustment advised
+    pub fn adjustments(self) -> (f64, i32) {
+        match self {
+            ScalingDirection::Up => (0.2, 1),
+            ScalingDirection::Hold => (0.0, 0),
+            ScalingDirection::Down => (-0.1, -1),
+        }
+    }
+}
+
+/// Query parameters of `GET /api/scaling/history`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScalingHistoryParams {
+    pub agent_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Decisions returned, newest first; 100 by default
+    pub limit: Option<usize>,
+}
+
+/// Decisions matching a query, and what all of them add up to
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScalingHistoryResponse {
+    /// Newest first, at most `limit`
+    pub decisions: Vec<ScalingRecord>,
+    pub summary: ScalingSummary,
+}
+
+/// Every decision matching a query, `limit` or not
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScalingSummary {
+    pub decisions: usize,
+    pub up: usize,
+    pub hold: usize,
+    pub down: usize,
+    pub net_capacity_change: f64,
+}
+
+/// Every agent's decisions, oldest first, the oldest dropped past
+/// `history_capacity`
+#[derive(Debug, Default)]
+pub struct ScalingLog {
+    records: Mutex<VecDeque<ScalingRecord>>,
+}
+
+impl ScalingLog {
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<ScalingRecord>> {
+        self.records.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes back records saved by `records`
+    pub fn restore(&self, saved: Vec<ScalingRecord>) {
+        let mut records = self.lock();
+        records.extend(saved);
+        records.make_contiguous().sort_by_key(|record| record.decided_at);
+    }
+
+    pub fn push(&self, record: ScalingRecord, config: &ScalingConfig) {
+        let mut records = self.lock();
+        records.push_back(record);
+        while records.len() > config.history_capacity {
+            records.pop_front();
+        }
+    }
+
+    /// Every record, for saving
+    pub fn records(&self) -> Vec<ScalingRecord> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// When the agent was last advised to scale up, and down
+    pub fn recent(&self, agent_id: &str) -> RecentScaling {
+        let mut recent = RecentScaling::default();
+        for record in self.lock().iter().rev().filter(|record| record.agent_id == agent_id) {
+            let last = match record.direction {
+                ScalingDirection::Up => &mut recent.last_up,
+                ScalingDirection::Down => &mut recent.last_down,
+                ScalingDirection::Hold => continue,
+            };
+            last.get_or_insert(record.decided_at);
+            if recent.last_up.is_some() && recent.last_down.is_some() {
+                break;
+            }
+        }
+        recent
+    }
+
+    pub fn query(&self, params: &ScalingHistoryParams) -> ScalingHistoryResponse {
+        let limit = params.limit.unwrap_or(100);
+        let mut response = ScalingHistoryResponse::default();
+        let matching = self.lock();
+        let matching = matching.iter().rev().filter(|record| {
+            params.agent_id.as_ref().is_none_or(|wanted| *wanted == record.agent_id) && params.since.is_none_or(|since| record.decided_at >= since)
+        });
+        for record in matching {
+            let summary = &mut response.summary;
+            summary.decisions += 1;
+            match record.direction {
+                ScalingDirection::Up => summary.up += 1,
+                ScalingDirection::Hold => summary.hold += 1,
+                ScalingDirection::Down => summary.down += 1,
+            }
+            summary.net_capacity_change += record.capacity_change;
+            if response.decisions.len() < limit {
+                response.decisions.push(record.clone());
+            }
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ScalingDirection::*;
+
+    fn config() -> ScalingConfig {
+        ScalingConfig { window: 20, min_observations: 3, quiet_period_secs: 30, cooldown_secs: 20, reversal_guard_secs: 0, ..ScalingConfig::default() }
+    }
+
+    fn start() -> DateTime<Utc> {
+        "2026-03-01T09:00:00Z".parse().unwrap()
+    }
+
+    /// Feeds one observation per second and returns the decision after each,
+    /// recording them all in `log`
+    fn run_logged(series: &[(u64, bool)], config: &ScalingConfig, log: &ScalingLog) -> Vec<ScalingDirection> {
+        let mut history = ScalingHistory::default();
+        series
+            .iter()
+            .enumerate()
+            .map(|(i, (ms, success))| {
+                let now = start() + chrono::Duration::seconds(i as i64);
+                let observation = Observation { response_time_ms: Some(*ms), success: *success, token_count: None };
+                history.record(observation, now, config);
+                let decision = history.decide(now, log.recent("scout"), config);
+                log.push(ScalingRecord::new("scout", now, &decision, config), config);
+                decision.direction
+            })
+            .collect()
+    }
+
+    fn run(series: &[(u64, bool)]) -> Vec<ScalingDirection> {
+        run_logged(series, &config(), &ScalingLog::default())
+    }
+
+    #[test]
+    fn single_slow_request_does_not_scale_up() {
+        let mut series = vec![(500, true); 40];
+        series[25] = (60_000, true);
+        let decisions = run(&series);
+        assert!(decisions.iter().all(|d| *d == Hold), "{decisions:?}");
+    }
+
+    #[test]
+    fn sustained_latency_scales_up_once_per_cooldown() {
+        let series = vec![(20_000, true); 45];
+        let decisions = run(&series);
+        let ups: Vec<usize> = (0..decisions.len()).filter(|i| decisions[*i] == Up).collect();
+        // Needs min_observations, then once every cooldown_secs
+        assert_eq!(ups, [2, 22, 42]);
+    }
+
+    #[test]
+    fn errors_scale_up_even_when_fast() {
+        let mut series = vec![(100, true); 5];
+        series.extend([(100, false); 2]);
+        assert_eq!(run(&series), [Hold, Hold, Hold, Hold, Hold, Up, Hold]);
+    }
+
+    #[test]
+    fn scale_down_needs_a_quiet_period_and_does_not_repeat_right_away() {
+        let mut series = vec![(20_000, true); 10];
+        series.extend(vec![(200, true); 80]);
+        let decisions = run(&series);
+        let changes: Vec<(usize, ScalingDirection)> =
+            decisions.iter().copied().enumerate().filter(|(_, d)| *d != Hold).collect();
+        // The p95 stays slow until only one slow request is left in the window
+        // at 28; the last one was at 9, so the first quiet period ends at 39
+        // and the next at 69
+        assert_eq!(changes, [(2, Up), (22, Up), (39, Down), (69, Down)]);
+    }
+
+    #[test]
+    fn description_carries_the_evidence() {
+        let config = config();
+        let mut history = ScalingHistory::default();
+        for ms in [100, 200, 15_000, 12_000] {
+            history.record(Observation { response_time_ms: Some(ms), success: ms < 15_000, token_count: Some(300) }, start(), &config);
+        }
+        let decision = history.decide(start(), RecentScaling::default(), &config);
+        assert_eq!(decision.direction, Up);
+        assert_eq!(
+            decision.description(),
+            "Scaling up: p95 latency above 10000 ms (window 4, p95 15000 ms, error rate 25.0%, avg 300 tokens)"
+        );
+    }
+
+    #[test]
+    fn direction_does_not_reverse_within_the_guard() {
+        let config = ScalingConfig { reversal_guard_secs: 45, ..config() };
+        let mut series = vec![(20_000, true); 10];
+        series.extend(vec![(200, true); 80]);
+        let log = ScalingLog::default();
+        let decisions = run_logged(&series, &config, &log);
+        let changes: Vec<(usize, ScalingDirection)> =
+            decisions.iter().copied().enumerate().filter(|(_, d)| *d != Hold).collect();
+        // Quiet from 39, but the scale-up at 22 holds it back until 67
+        assert_eq!(changes, [(2, Up), (22, Up), (67, Down)]);
+        let newest_first = log.query(&ScalingHistoryParams::default()).decisions;
+        assert!(newest_first[23].description.contains("failing for 57s, but scaled up 44s ago"), "{}", newest_first[23].description);
+    }
+
+    #[test]
+    fn the_log_is_bounded_and_summarized_newest_first() {
+        let config = ScalingConfig { history_capacity: 40, ..config() };
+        let log = ScalingLog::default();
+        run_logged(&vec![(20_000, true); 45], &config, &log);
+        let response = log.query(&ScalingHistoryParams { limit: Some(3), ..ScalingHistoryParams::default() });
+        assert_eq!(response.decisions.iter().map(|record| record.decided_at - start()).collect::<Vec<_>>(), [
+            chrono::Duration::seconds(44),
+            chrono::Duration::seconds(43),
+            chrono::Duration::seconds(42)
+        ]);
+        assert_eq!(response.decisions[2].thresholds.scale_up_p95_ms, 10_000);
+        assert_eq!((response.summary.decisions, response.summary.up, response.summary.hold), (40, 2, 38));
+        assert!((response.summary.net_capacity_change - 0.4).abs() < 1e-9);
+
+        let since = log.query(&ScalingHistoryParams { since: Some(start() + chrono::Duration::seconds(40)), ..ScalingHistoryParams::default() });
+        assert_eq!((since.summary.decisions, since.summary.up), (5, 1));
+        assert_eq!(log.query(&ScalingHistoryParams { agent_id: Some("courier".to_string()), ..ScalingHistoryParams::default() }), ScalingHistoryResponse::default());
+
+        let restored = ScalingLog::default();
+        restored.restore(log.records());
+        assert_eq!(restored.recent("scout"), RecentScaling { last_up: Some(start() + chrono::Duration::seconds(42)), last_down: None });
+    }
+}
@@ -0,0 +1,248 @@
+//! Checks of everything a deployment can get wrong before traffic finds it:
+//! the config, each knowledge base's database with its schema and the FTS5
+//! module of the linked SQLite, every backend and embedding provider asked
+//! something tiny, the TLS certificate and key, and the audit log and metrics
+//! state paths. `mcp-server --check` prints the report as a table and exits
+//! nonzero on any failure; normal startup runs the same checks and aborts or
+//! serves degraded as `[self_check] on_failure` says, with the report behind
+//! `/ready`.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Context, Result};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use crate::audit::AuditSink;
+use crate::config::{Config, RagEngineConfig};
+use crate::llm_backend::Prompt;
+use crate::mcp_server::{MCPParams, VoidShrineMCP};
+use crate::rag_engine::{self, EmbeddingProvider, RAGStats};
+use crate::rag_engines::{SharedEngine, DEFAULT_ENGINE};
+use crate::tls::CertificateStore;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelfCheckConfig {
+    /// What startup does when a check fails
+    pub on_failure: OnFailedCheck,
+    /// How long each backend and embedding provider has to answer
+    pub timeout_secs: u64,
+}
+
+impl Default for SelfCheckConfig {
+    fn default() -> Self {
+        Self { on_failure: OnFailedCheck::Degrade, timeout_secs: 10 }
+    }
+}
+
+impl SelfCheckConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.timeout_secs == 0 {
+            problems.push("self_check.timeout_secs must be positive".to_string());
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnFailedCheck {
+    /// Refuse to start, as when a knowledge base can't be opened
+    Abort,
+    /// Serve without what failed, knowledge bases that can't be opened
+    /// included, reporting it at `/ready`
+    Degrade,
+}
+
+/// How one component fared
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentCheck {
+    /// e.g. `config`, `rag:default` or `backend:openai`
+    pub component: String,
+    pub passed: bool,
+    /// What was found, or why it failed
+    pub detail: String,
+    pub duration_ms: u64,
+}
+
+impl ComponentCheck {
+    fn new(component: impl Into<String>, started: Instant, outcome: Result<String>) -> Self {
+        let (passed, detail) = match outcome {
+            Ok(detail) => (true, detail),
+            Err(e) => (false, format!("{:#}", e)),
+        };
+        Self { component: component.into(), passed, detail, duration_ms: started.elapsed().as_millis() as u64 }
+    }
+}
+
+/// Every component checked, in the order they were
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SelfCheckReport {
+    pub components: Vec<ComponentCheck>,
+}
+
+impl SelfCheckReport {
+    /// The report of a `component` that failed before the others could be
+    /// checked, such as a config that can't be loaded
+    pub fn failed(component: &str, error: &anyhow::Error) -> Self {
+        Self { components: vec![ComponentCheck::new(component, Instant::now(), Err(anyhow!("{:#}", error)))] }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.components.iter().all(|check| check.passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ComponentCheck> {
+        self.components.iter().filter(|check| !check.passed)
+    }
+}
+
+/// One row per component, then how many failed
+impl std::fmt::Display for SelfCheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self.components.iter().map(|check| check.component.len()).max().unwrap_or(0).max("component".len());
+        writeln!(f, "{:<width$}  {:<6}  {:>7}  detail", "component", "status", "ms")?;
+        for check in &self.components {
+            let status = if check.passed { "pass" } else { "FAIL" };
+            let mut lines = check.detail.lines();
+            writeln!(f, "{:<width$}  {:<6}  {:>7}  {}", check.component, status, check.duration_ms, lines.next().unwrap_or(""))?;
+            // Further lines of a detail, such as each config problem, under the first
+            for line in lines {
+                writeln!(f, "{:<width$}  {:<6}  {:>7}  {}", "", "", "", line)?;
+            }
+        }
+        match self.failures().count() {
+            0 => write!(f, "all {} checks passed", self.components.len()),
+            failed => write!(f, "{} of {} checks failed", failed, self.components.len()),
+        }
+    }
+}
+
+/// Checks every component `config` sets up, knowledge bases as `service`
+/// opened them, without changing any of them. The report is kept in
+/// `service` for `/ready`.
+pub async fn self_check(config: &Config, service: &VoidShrineMCP) -> SelfCheckReport {
+    let timeout = Duration::from_secs(config.self_check.timeout_secs.max(1));
+    let mut components = Vec::new();
+
+    let started = Instant::now();
+    components.push(ComponentCheck::new("config", started, config.validate().map(|_| "valid".to_string())));
+
+    let started = Instant::now();
+    components.push(ComponentCheck::new("sqlite_fts5", started, rag_engine::fts5_available().map(|_| "available".to_string())));
+
+    let engines = std::iter::once((DEFAULT_ENGINE, config.rag.default_engine()))
+        .chain(config.rag.engines.iter().map(|(name, engine)| (name.as_str(), engine.clone())));
+    for (name, settings) in engines {
+        let started = Instant::now();
+        let location = settings.db_path.as_ref().map_or_else(|| "in memory".to_string(), |path| path.display().to_string());
+        let slot = service.rag_engines.get("engine", Some(name)).ok();
+        let (outcome, embedder) = match inspect_engine(&settings, slot).await {
+            Ok(Some((stats, embedder))) => (Ok(format!("{} documents in {} chunks ({})", stats.document_count, stats.chunk_count, location)), embedder),
+            Ok(None) => (settings.db_path.as_deref().map_or_else(|| Ok(String::new()), writable), None),
+            Err(e) => (Err(e), None),
+        };
+        components.push(ComponentCheck::new(format!("rag:{}", name), started, outcome));
+        if let Some(embedder) = embedder {
+            let started = Instant::now();
+            let outcome = within(timeout, async {
+                let vectors = embedder.embed(&["self check".to_string()]).await?;
+                match vectors.first().map(Vec::len) {
+                    Some(dimension) if dimension == embedder.dimension() => Ok(format!("'{}' answered", embedder.model())),
+                    Some(dimension) => Err(anyhow!("'{}' answered {} dimensions, not {}", embedder.model(), dimension, embedder.dimension())),
+                    None => Err(anyhow!("'{}' answered no vector", embedder.model())),
+                }
+            })
+            .await;
+            components.push(ComponentCheck::new(format!("embeddings:{}", name), started, outcome));
+        }
+    }
+
+    let registry = service.backends.current();
+    let prompt = Prompt { system: None, user: "ping".to_string() };
+    let params: MCPParams = serde_json::from_value(serde_json::json!({ "agent_id": "self-check", "prompt": "ping", "max_tokens": 1, "use_rag": false }))
+        .expect("self check params are valid");
+    let pings = registry.backends().map(|(name, backend)| {
+        let (prompt, params) = (&prompt, &params);
+        async move {
+            let started = Instant::now();
+            let outcome = within(timeout, async {
+                backend.complete(prompt, params).await?;
+                Ok("answered".to_string())
+            })
+            .await;
+            ComponentCheck::new(format!("backend:{}", name), started, outcome)
+        }
+    });
+    components.extend(join_all(pings).await);
+
+    if let Some(tls) = &config.server.tls {
+        let started = Instant::now();
+        let outcome = CertificateStore::load(&tls.cert_path, &tls.key_path)
+            .and_then(|certs| certs.server_config())
+            .map(|_| format!("{} and {} parse", tls.cert_path.display(), tls.key_path.display()));
+        components.push(ComponentCheck::new("tls", started, outcome));
+    }
+    if config.audit.sink != AuditSink::None {
+        if let Some(path) = &config.audit.path {
+            let started = Instant::now();
+            components.push(ComponentCheck::new("audit_log", started, writable(path)));
+        }
+    }
+    if let Some(path) = &config.metrics.state_path {
+        let started = Instant::now();
+        components.push(ComponentCheck::new("metrics_state", started, writable(path)));
+    }
+    let report = SelfCheckReport { components };
+    *service.startup_checks.write().unwrap_or_else(|e| e.into_inner()) = Some(report.clone());
+    report
+}
+
+/// The stats and embedding provider of the engine opened into `slot`, its
+/// schema verified. Without one there, the database is opened read-only,
+/// or built empty in memory, so checking never writes to it; None when its
+/// file doesn't exist yet.
+async fn inspect_engine(settings: &RagEngineConfig, slot: Option<SharedEngine>) -> Result<Option<(RAGStats, Option<Arc<dyn EmbeddingProvider>>)>> {
+    if let Some(slot) = slot {
+        if let Some(rag) = slot.read().await.as_ref() {
+            rag.verify_schema()?;
+            return Ok(Some((rag.get_stats().await?, rag.embedder().cloned())));
+        }
+    }
+    let rag = match &settings.db_path {
+        Some(path) if !path.exists() => return Ok(None),
+        Some(path) => settings.builder().open_read_only().with_context(|| format!("opening {} read-only", path.display()))?,
+        None => settings.builder().build().await?,
+    };
+    rag.verify_schema()?;
+    Ok(Some((rag.get_stats().await?, rag.embedder().cloned())))
+}
+
+async fn within(timeout: Duration, check: impl std::future::Future<Output = Result<String>>) -> Result<String> {
+    tokio::time::timeout(timeout, check).await.map_err(|_| anyhow!("no answer within {} s", timeout.as_secs()))?
+}
+
+/// Whether `path` can be appended to, or created when it doesn't exist yet.
+/// Neither changes anything there: an existing file is only opened, and a
+/// probe file is made and removed beside a missing one.
+fn writable(path: &Path) -> Result<String> {
+    if path.exists() {
+        std::fs::OpenOptions::new().append(true).open(path).with_context(|| format!("{} can't be written", path.display()))?;
+        return Ok(format!("{} is writable", path.display()));
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().map_or_else(|| "file".into(), |name| name.to_string_lossy());
+    let probe = dir.join(format!(".{}.self-check-{}", name, std::process::id()));
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .with_context(|| format!("{} can't be created in {}", path.display(), dir.display()))?;
+    std::fs::remove_file(&probe).ok();
+    Ok(format!("{} can be created", path.display()))
+}
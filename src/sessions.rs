@@ -0,0 +1,424 @@
+//! Conversation sessions. A request naming a `session_id` gets the session's
+//! earlier turns prepended to its prompt, and its own prompt and response are
+//! kept as the next turn. History is bounded by turn count and by tokens,
+//! dropping the oldest turns first; sessions idle past `ttl_secs` are
+//! expired, and the least recently active one makes way when `max_sessions`
+//! are open.
+//!
+//! Retrieval for a follow-up can search the session's latest prompts too, or
+//! the backend's rewrite of them as one question; see `HistoryRetrievalConfig`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use crate::llm_backend::Prompt;
+use crate::tokenizer::{EstimateTokenizer, Tokenizer};
+use crate::text::{truncate_to_tokens, ELLIPSIS};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Sessions without a request this long are dropped
+    pub ttl_secs: u64,
+    /// Turns kept per session
+    pub max_turns: usize,
+    /// Tokens of history kept per session, as the server's tokenizer counts them
+    pub max_history_tokens: usize,
+    /// Open sessions; the least recently active is dropped for a new one
+    pub max_sessions: usize,
+    pub retrieval: HistoryRetrievalConfig,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 30 * 60,
+            max_turns: 50,
+            max_history_tokens: 8192,
+            max_sessions: 10_000,
+            retrieval: HistoryRetrievalConfig::default(),
+        }
+    }
+}
+
+impl SessionConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.ttl_secs == 0 || self.max_turns == 0 || self.max_history_tokens == 0 || self.max_sessions == 0 {
+            problems.push("sessions.ttl_secs, max_turns, max_history_tokens and max_sessions must be positive".to_string());
+        }
+        let retrieval = &self.retrieval;
+        if retrieval.condense && (retrieval.condense_timeout_ms == 0 || retrieval.condense_max_tokens == 0) {
+            problems.push("sessions.retrieval.condense_timeout_ms and condense_max_tokens must be positive to condense".to_string());
+        }
+        problems
+    }
+}
+
+/// How knowledge base retrieval for a request in a session draws on the
+/// session's earlier turns. Requests outside a session search their prompt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryRetrievalConfig {
+    /// Latest prompts of the session searched along with the request's; 0
+    /// searches the request's prompt alone
+    pub turns: usize,
+    /// Have the backend rewrite the follow-up, with those turns, as one
+    /// self-contained question and search that instead. Skipped for agents
+    /// being throttled and for dry runs.
+    pub condense: bool,
+    /// Longest the rewrite may take, and never more than half the request's
+    /// remaining time; past it the turns are searched as they are
+    pub condense_timeout_ms: u64,
+    pub condense_max_tokens: u32,
+}
+
+impl Default for HistoryRetrievalConfig {
+    fn default() -> Self {
+        Self { turns: 0, condense: false, condense_timeout_ms: 2000, condense_max_tokens: 128 }
+    }
+}
+
+/// What retrieval searched for a request in a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalQuery {
+    pub text: String,
+    /// Earlier prompts of the session that went into it
+    pub history_turns: usize,
+    /// Whether `text` is the backend's rewrite
+    pub condensed: bool,
+    /// Why a configured rewrite didn't happen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condense_skipped: Option<CondenseSkipped>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CondenseSkipped {
+    /// The agent is being throttled
+    Load,
+    /// The rewrite ran out of its time
+    Timeout,
+    /// The backend failed or answered with nothing
+    Failed,
+    DryRun,
+}
+
+/// The prompts of `turns`, then `prompt`, a line each
+pub fn history_query(turns: &[Turn], prompt: &str) -> String {
+    turns.iter().map(|turn| turn.prompt.as_str()).chain([prompt]).collect::<Vec<_>>().join("\n")
+}
+
+/// Asks the backend for `prompt` rewritten, with what `turns` say it refers
+/// to, as a question that stands on its own
+pub fn condense_prompt(turns: &[Turn], prompt: &str) -> Prompt {
+    let conversation: Vec<String> = turns.iter().map(|turn| format!("User: {}\nAssistant: {}", turn.prompt, turn.response)).collect();
+    Prompt::user(format!("Conversation:\n{}\n\nFollow-up: {}\n\nStandalone question:", conversation.join("\n"), prompt)).with_system(
+        "Rewrite the follow-up as a self-contained question, using the conversation only to resolve what it refers to. \
+         Reply with the question alone.",
+    )
+}
+
+/// One prompt and the response it got
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Turn {
+    /// Counts from 1 over the whole session, including turns since dropped
+    pub index: u32,
+    pub prompt: String,
+    pub response: String,
+    pub timestamp: DateTime<Utc>,
+    /// Prompt and response, as counted by the store's tokenizer
+    pub tokens: usize,
+}
+
+#[derive(Debug)]
+struct Session {
+    agent_id: String,
+    turns: VecDeque<Turn>,
+    turns_taken: u32,
+    created_at: DateTime<Utc>,
+    last_active: DateTime<Utc>,
+    touched: Instant,
+}
+
+impl Session {
+    fn new(agent_id: &str, now: Instant) -> Self {
+        let opened = Utc::now();
+        Self { agent_id: agent_id.to_string(), turns: VecDeque::new(), turns_taken: 0, created_at: opened, last_active: opened, touched: now }
+    }
+}
+
+/// A session as listed by `GET /api/sessions`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub agent_id: String,
+    /// Turns taken, including those dropped from the history
+    pub turns_taken: u32,
+    pub turns_kept: usize,
+    pub history_tokens: usize,
+    pub created_at: DateTime<Utc>,
+    pub last_active: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionsResponse {
+    /// Most recently active first
+    pub sessions: Vec<SessionSummary>,
+}
+
+/// Why a session can't be used by a request
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionConflict {
+    /// The session was opened by another agent
+    OtherAgent,
+}
+
+#[derive(Debug)]
+pub struct SessionStore {
+    config: SessionConfig,
+    ttl: Duration,
+    sessions: DashMap<String, Session>,
+    last_sweep: Mutex<Instant>,
+    tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(SessionConfig::default(), Arc::new(EstimateTokenizer))
+    }
+}
+
+impl SessionStore {
+    pub fn new(config: SessionConfig, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        let ttl = Duration::from_secs(config.ttl_secs);
+        Self { config, ttl, sessions: DashMap::new(), last_sweep: Mutex::new(Instant::now()), tokenizer }
+    }
+
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    fn expired(&self, session: &Session, now: Instant) -> bool {
+        now.saturating_duration_since(session.touched) >= self.ttl
+    }
+
+    /// The session's kept turns, oldest first; none for an unknown or expired
+    /// session. Refuses sessions another agent opened.
+    pub fn history(&self, session_id: &str, agent_id: &str) -> Result<Vec<Turn>, SessionConflict> {
+        let now = Instant::now();
+        self.sweep_if_due(now);
+        let Some(session) = self.sessions.get(session_id) else {
+            return Ok(Vec::new());
+        };
+        if self.expired(&session, now) {
+            return Ok(Vec::new());
+        }
+        if session.agent_id != agent_id {
+            return Err(SessionConflict::OtherAgent);
+        }
+        Ok(session.turns.iter().cloned().collect())
+    }
+
+    /// Adds a turn, opening the session if needed, and returns its index.
+    /// Older turns are dropped to stay within `max_turns` and `max_history_tokens`,
+    /// always keeping the newest.
+    pub fn record(&self, session_id: &str, agent_id: &str, prompt: &str, response: &str) -> Result<u32, SessionConflict> {
+        let now = Instant::now();
+        if !self.sessions.contains_key(session_id) && self.sessions.len() >= self.config.max_sessions {
+            self.evict_least_recent();
+        }
+        let mut session = self.sessions.entry(session_id.to_string()).or_insert_with(|| Session::new(agent_id, now));
+        if self.expired(&session, now) {
+            // Expired but not yet swept: start over
+            *session = Session::new(agent_id, now);
+        }
+        if session.agent_id != agent_id {
+            return Err(SessionConflict::OtherAgent);
+        }
+        session.turns_taken += 1;
+        let turn = Turn {
+            index: session.turns_taken,
+            prompt: prompt.to_string(),
+            response: response.to_string(),
+            timestamp: Utc::now(),
+            tokens: self.tokenizer.count(prompt) + self.tokenizer.count(response),
+        };
+        session.turns.push_back(turn);
+        session.last_active = Utc::now();
+        session.touched = now;
+
+        let mut tokens: usize = session.turns.iter().map(|turn| turn.tokens).sum();
+        while session.turns.len() > 1 && (session.turns.len() > self.config.max_turns || tokens > self.config.max_history_tokens) {
+            if let Some(dropped) = session.turns.pop_front() {
+                tokens -= dropped.tokens;
+            }
+        }
+        Ok(session.turns_taken)
+    }
+
+    /// False when there was no such session
+    pub fn delete(&self, session_id: &str) -> bool {
+        self.sessions.remove(session_id).is_some()
+    }
+
+    pub fn list(&self) -> Vec<SessionSummary> {
+        let now = Instant::now();
+        let mut sessions: Vec<SessionSummary> = self
+            .sessions
+            .iter()
+            .filter(|entry| !self.expired(entry.value(), now))
+            .map(|entry| {
+                let session = entry.value();
+                let remaining = self.ttl.saturating_sub(now.saturating_duration_since(session.touched));
+                SessionSummary {
+                    session_id: entry.key().clone(),
+                    agent_id: session.agent_id.clone(),
+                    turns_taken: session.turns_taken,
+                    turns_kept: session.turns.len(),
+                    history_tokens: session.turns.iter().map(|turn| turn.tokens).sum(),
+                    created_at: session.created_at,
+                    last_active: session.last_active,
+                    expires_at: Utc::now() + chrono::Duration::from_std(remaining).unwrap_or_default(),
+                }
+            })
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_active));
+        sessions
+    }
+
+    /// Drops sessions idle past the TTL; returns how many went
+    pub fn evict_expired(&self) -> usize {
+        let now = Instant::now();
+        let before = self.sessions.len();
+        self.sessions.retain(|_, session| !self.expired(session, now));
+        before - self.sessions.len()
+    }
+
+    pub fn open_sessions(&self) -> usize {
+        self.sessions.len()
+    }
+
+    fn evict_least_recent(&self) {
+        let oldest = self.sessions.iter().min_by_key(|entry| entry.value().touched).map(|entry| entry.key().clone());
+        if let Some(session_id) = oldest {
+            tracing::debug!("Dropping session {} to make room for a new one", session_id);
+            self.sessions.remove(&session_id);
+        }
+    }
+
+    fn sweep_if_due(&self, now: Instant) {
+        let mut last_sweep = match self.last_sweep.try_lock() {
+            Ok(guard) => guard,
+            // Another caller is sweeping
+            Err(_) => return,
+        };
+        if now.saturating_duration_since(*last_sweep) >= self.ttl {
+            *last_sweep = now;
+            drop(last_sweep);
+            self.evict_expired();
+        }
+    }
+}
+
+/// The turns, newest kept, that fit in `budget` tokens, as a transcript to go
+/// before the prompt. A newest turn too long to fit is cut to fit instead;
+/// None when not even that leaves anything.
+pub fn transcript(turns: &[Turn], budget: usize, tokenizer: &dyn Tokenizer) -> Option<String> {
+    let mut used = 0;
+    let mut kept = Vec::new();
+    for turn in turns.iter().rev() {
+        let line = format!("User: {}\nAssistant: {}", turn.prompt, turn.response);
+        let tokens = tokenizer.count(&line);
+        if used + tokens > budget {
+            if kept.is_empty() {
+                kept.push(truncate_to_tokens(&line, budget, tokenizer, ELLIPSIS).into_owned());
+            }
+            break;
+        }
+        used += tokens;
+        kept.push(line);
+    }
+    kept.retain(|line| !line.is_empty());
+    if kept.is_empty() {
+        return None;
+    }
+    kept.reverse();
+    Some(format!("Conversation so far:\n{}", kept.join("\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(max_turns: usize, max_history_tokens: usize) -> SessionStore {
+        SessionStore::new(SessionConfig { max_turns, max_history_tokens, ..Default::default() }, Arc::new(EstimateTokenizer))
+    }
+
+    #[test]
+    fn history_is_bounded_oldest_first() {
+        let sessions = store(3, 1000);
+        for i in 1..=5 {
+            assert_eq!(sessions.record("s", "a", &format!("question {i}"), "answer").unwrap(), i);
+        }
+        let history = sessions.history("s", "a").unwrap();
+        assert_eq!(history.iter().map(|turn| turn.index).collect::<Vec<_>>(), [3, 4, 5]);
+
+        // Twenty tokens a turn, so two fit in forty-five
+        let sessions = store(10, 45);
+        sessions.record("s", "a", &"x".repeat(40), &"y".repeat(40)).unwrap();
+        sessions.record("s", "a", &"x".repeat(40), &"y".repeat(40)).unwrap();
+        sessions.record("s", "a", &"x".repeat(40), &"y".repeat(40)).unwrap();
+        assert_eq!(sessions.history("s", "a").unwrap().len(), 2);
+        assert_eq!(sessions.list()[0].turns_taken, 3);
+    }
+
+    #[test]
+    fn sessions_belong_to_their_agent_and_expire() {
+        let sessions = SessionStore { ttl: Duration::from_millis(20), ..Default::default() };
+        sessions.record("s", "a", "hello", "hi").unwrap();
+        assert_eq!(sessions.history("s", "b"), Err(SessionConflict::OtherAgent));
+        assert_eq!(sessions.record("s", "b", "hello", "hi"), Err(SessionConflict::OtherAgent));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(sessions.history("s", "a").unwrap().is_empty());
+        assert!(sessions.list().is_empty());
+        // An expired session can be opened again, by anyone
+        assert_eq!(sessions.record("s", "b", "hello", "hi").unwrap(), 1);
+    }
+
+    #[test]
+    fn the_least_recent_session_makes_room() {
+        let sessions = SessionStore::new(SessionConfig { max_sessions: 2, ..Default::default() }, Arc::new(EstimateTokenizer));
+        sessions.record("first", "a", "p", "r").unwrap();
+        sessions.record("second", "a", "p", "r").unwrap();
+        sessions.record("first", "a", "p", "r").unwrap();
+        sessions.record("third", "a", "p", "r").unwrap();
+        let mut open: Vec<String> = sessions.list().into_iter().map(|s| s.session_id).collect();
+        open.sort();
+        assert_eq!(open, ["first", "third"]);
+        assert!(sessions.delete("first") && !sessions.delete("first"));
+    }
+
+    #[test]
+    fn transcripts_keep_the_newest_turns_that_fit() {
+        let turn = |index: u32, prompt: &str| Turn {
+            index,
+            prompt: prompt.to_string(),
+            response: "ok".to_string(),
+            timestamp: Utc::now(),
+            tokens: 0,
+        };
+        let turns = [turn(1, &"old ".repeat(40)), turn(2, "recent")];
+        let text = transcript(&turns, 10, &EstimateTokenizer).unwrap();
+        assert_eq!(text, "Conversation so far:\nUser: recent\nAssistant: ok");
+        assert!(transcript(&turns, 100, &EstimateTokenizer).unwrap().contains("old old"));
+        // Too long for the budget, the newest turn is cut to fit
+        assert_eq!(transcript(&turns, 5, &EstimateTokenizer).unwrap(), "Conversation so far:\nUser: recent…");
+        assert_eq!(transcript(&turns, 0, &EstimateTokenizer), None);
+    }
+}
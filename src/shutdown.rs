@@ -0,0 +1,104 @@
+//! Graceful shutdown. Once draining starts `/ready` answers 503 and new MCP
+//! requests are refused; requests still running when the drain timeout runs
+//! out are answered with a `shutting_down` error instead of a dropped connection.
+
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+pub struct Shutdown {
+    drain_timeout: Duration,
+    /// When in-flight requests are cut off; None until draining starts
+    deadline: watch::Sender<Option<Instant>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30))
+    }
+}
+
+impl Shutdown {
+    pub fn new(drain_timeout: Duration) -> Self {
+        Self { drain_timeout, deadline: watch::channel(None).0 }
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+
+    /// Starts draining; false if it had already started
+    pub fn begin(&self) -> bool {
+        let deadline = Instant::now() + self.drain_timeout;
+        self.deadline.send_if_modified(|current| current.is_none() && current.replace(deadline).is_none())
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.deadline.borrow().is_some()
+    }
+
+    /// Resolves once draining starts
+    pub async fn draining(&self) {
+        self.deadline_at().await;
+    }
+
+    /// Resolves once the drain timeout has run out; pending until draining starts
+    pub async fn drain_expired(&self) {
+        tokio::time::sleep_until(self.deadline_at().await).await;
+    }
+
+    async fn deadline_at(&self) -> Instant {
+        let mut deadline = self.deadline.subscribe();
+        let started = deadline.wait_for(Option::is_some).await.map(|deadline| *deadline);
+        match started {
+            Ok(Some(at)) => at,
+            // The sender lives as long as `self`
+            _ => std::future::pending().await,
+        }
+    }
+}
+
+/// Waits for SIGINT or, on Unix, SIGTERM and returns its name
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return "SIGINT";
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_expires_after_the_timeout() {
+        let shutdown = Shutdown::new(Duration::from_millis(50));
+        assert!(!shutdown.is_draining());
+        let expired = tokio::time::timeout(Duration::from_millis(100), shutdown.drain_expired()).await;
+        assert!(expired.is_err(), "no deadline before draining starts");
+
+        let started = Instant::now();
+        assert!(shutdown.begin());
+        assert!(!shutdown.begin());
+        assert!(shutdown.is_draining());
+        shutdown.draining().await;
+        shutdown.drain_expired().await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+}
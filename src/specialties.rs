@@ -0,0 +1,302 @@
+//! Specialties: what a request's `specialty` selects. Each has the framing
+//! its prompts get as the system prompt, the mock backend's answer, filters
+//! its retrieval is limited by, and a model and prompt template for requests
+//! naming none. An unknown specialty gets the `fallback` entry, or is refused
+//! in strict mode. With a `path`, the entries are read from that TOML file
+//! instead of the config, and `POST /api/specialties/reload` reads it again.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Specialty {
+    pub name: String,
+    /// System prompt of its requests, unless moral recentering is off
+    pub framing: String,
+    /// What the mock backend answers
+    pub mock_response: String,
+    /// Other answers the mock backend gives above temperature 0, likelier
+    /// the earlier they are listed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mock_variants: Vec<String>,
+    /// Metadata key -> value pattern retrieved documents must match
+    #[serde(default)]
+    pub metadata_filters: BTreeMap<String, String>,
+    /// Tags retrieved documents must carry
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Model for requests naming none whose agent registered none either
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+    /// Prompt template for its requests naming none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl Specialty {
+    fn builtin(name: &str, framing: &str, mock_response: &str, mock_variants: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            framing: framing.to_string(),
+            mock_response: mock_response.to_string(),
+            mock_variants: mock_variants.iter().map(|variant| variant.to_string()).collect(),
+            metadata_filters: BTreeMap::new(),
+            tags: Vec::new(),
+            default_model: None,
+            template: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpecialtiesConfig {
+    /// Refuse requests naming an unknown specialty instead of giving them `fallback`
+    pub strict: bool,
+    /// Entry unknown specialties get
+    pub fallback: String,
+    /// TOML file of `[[specialties]]` entries, read instead of `entries`
+    pub path: Option<PathBuf>,
+    pub entries: Vec<Specialty>,
+}
+
+impl Default for SpecialtiesConfig {
+    fn default() -> Self {
+        Self { strict: false, fallback: "general".to_string(), path: None, entries: builtin_specialties() }
+    }
+}
+
+impl SpecialtiesConfig {
+    /// Problems with `entries`; those of a `path` show when it is read
+    pub fn validate(&self) -> Vec<String> {
+        if self.path.is_some() {
+            return Vec::new();
+        }
+        check(&self.entries, &self.fallback).err().map(|problem| vec![format!("specialties: {}", problem)]).unwrap_or_default()
+    }
+}
+
+fn builtin_specialties() -> Vec<Specialty> {
+    vec![
+        Specialty::builtin(
+            "tactical",
+            "From a perspective of strategic care and collective wellbeing:",
+            "Strategic analysis complete. Based on the enhanced prompt context, I recommend a multi-phase approach prioritizing stakeholder care and systemic resilience. Key considerations include resource optimization, risk mitigation, and sustainable implementation pathways.",
+            &[
+                "Strategic review finished. The context favours a staged plan that keeps stakeholders informed, holds reserves for setbacks, and measures progress before each commitment.",
+                "Assessment complete. Secure the most fragile dependencies first, then widen the effort as trust and resources allow.",
+            ],
+        ),
+        Specialty::builtin(
+            "science",
+            "With rigorous ethical consideration and potential social impact:",
+            "Scientific investigation reveals interesting patterns in the provided context. The data suggests correlations that warrant deeper analysis through both quantitative metrics and qualitative assessment of broader implications.",
+            &[
+                "The evidence in the provided context is suggestive but not conclusive. A controlled comparison would separate the effect from its likely confounders.",
+                "Preliminary findings point to a measurable trend. Replication with a larger sample should come before any firm claim.",
+            ],
+        ),
+        Specialty::builtin(
+            "engineering",
+            "Prioritizing safety, accessibility, and sustainable design:",
+            "Technical architecture assessment indicates optimal solutions through modular, fault-tolerant design principles. Recommended implementation emphasizes scalability, maintainability, and ethical computing practices.",
+            &[
+                "The design holds up best with small, replaceable components and clear failure boundaries. Start with the simplest version that can be tested end to end.",
+                "Engineering review complete. Favour redundancy where failure is costly and plain, well-documented interfaces everywhere else.",
+            ],
+        ),
+        Specialty::builtin(
+            "creative",
+            "Through a lens of inclusive creativity and cultural sensitivity:",
+            "Creative synthesis generates novel approaches by combining contextual insights with innovative methodologies. The solution space includes unexplored opportunities for user-centered, aesthetically coherent implementations.",
+            &[
+                "Several directions open up from this context: some playful, some restrained, each worth sketching before choosing one.",
+                "A fresh angle emerges when the familiar pieces are rearranged around the people who will use them.",
+            ],
+        ),
+        Specialty::builtin(
+            "general",
+            "With mindful consideration of all stakeholders:",
+            "Comprehensive analysis of the enhanced prompt reveals multiple interconnected factors requiring careful consideration and systematic response strategies.",
+            &[
+                "Several connected factors shape this request; addressing them one at a time keeps the response clear.",
+                "The request touches a number of related concerns, each deserving a measured answer.",
+            ],
+        ),
+    ]
+}
+
+/// Names must be non-empty and distinct, and the fallback one of them
+fn check(entries: &[Specialty], fallback: &str) -> Result<()> {
+    let mut names = BTreeSet::new();
+    for entry in entries {
+        if entry.name.trim().is_empty() {
+            anyhow::bail!("a specialty has an empty name");
+        }
+        if !names.insert(entry.name.as_str()) {
+            anyhow::bail!("specialty '{}' is defined twice", entry.name);
+        }
+    }
+    if !names.contains(fallback) {
+        anyhow::bail!("fallback '{}' isn't one of the specialties", fallback);
+    }
+    Ok(())
+}
+
+/// The file at `specialties.path`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpecialtiesFile {
+    specialties: Vec<Specialty>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpecialtiesResponse {
+    pub strict: bool,
+    pub fallback: String,
+    /// Sorted by name
+    pub specialties: Vec<Specialty>,
+}
+
+/// Swapped whole when the config is reloaded
+#[derive(Debug)]
+struct State {
+    strict: bool,
+    fallback: String,
+    path: Option<PathBuf>,
+    entries: BTreeMap<String, Specialty>,
+}
+
+#[derive(Debug)]
+pub struct Specialties {
+    state: RwLock<State>,
+}
+
+impl Default for Specialties {
+    fn default() -> Self {
+        Self::open(&SpecialtiesConfig::default()).expect("the built-in specialties are valid")
+    }
+}
+
+impl Specialties {
+    /// The specialties of `config`, read from its `path` if it has one
+    pub fn open(config: &SpecialtiesConfig) -> Result<Self> {
+        let entries = match &config.path {
+            Some(path) => read(path, &config.fallback)?,
+            None => {
+                check(&config.entries, &config.fallback)?;
+                config.entries.clone()
+            }
+        };
+        let state = State {
+            strict: config.strict,
+            fallback: config.fallback.clone(),
+            path: config.path.clone(),
+            entries: by_name(entries),
+        };
+        Ok(Self { state: RwLock::new(state) })
+    }
+
+    /// Whether unknown specialties are refused
+    pub fn strict(&self) -> bool {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).strict
+    }
+
+    /// The named specialty, else the fallback unless strict
+    pub fn resolve(&self, name: &str) -> Option<Specialty> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        match state.entries.get(name) {
+            Some(specialty) => Some(specialty.clone()),
+            None if state.strict => None,
+            None => state.entries.get(&state.fallback).cloned(),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).entries.keys().cloned().collect()
+    }
+
+    pub fn list(&self) -> SpecialtiesResponse {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        SpecialtiesResponse {
+            strict: state.strict,
+            fallback: state.fallback.clone(),
+            specialties: state.entries.values().cloned().collect(),
+        }
+    }
+
+    /// Reads `path` again, keeping the current entries when it can't be used.
+    /// None without a `path`.
+    pub fn reload(&self) -> Option<Result<usize>> {
+        let (path, fallback) = {
+            let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+            (state.path.clone()?, state.fallback.clone())
+        };
+        Some(read(&path, &fallback).map(|entries| {
+            let count = entries.len();
+            self.state.write().unwrap_or_else(|e| e.into_inner()).entries = by_name(entries);
+            tracing::info!("Reloaded {} specialties", count);
+            count
+        }))
+    }
+
+    /// Takes on everything `other` was opened with, as a config reload does,
+    /// so whatever holds these sees the change
+    pub fn replace_with(&self, other: Specialties) {
+        let state = other.state.into_inner().unwrap_or_else(|e| e.into_inner());
+        *self.state.write().unwrap_or_else(|e| e.into_inner()) = state;
+    }
+}
+
+fn read(path: &Path, fallback: &str) -> Result<Vec<Specialty>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading specialties from {}", path.display()))?;
+    let file: SpecialtiesFile = toml::from_str(&text).with_context(|| format!("parsing specialties in {}", path.display()))?;
+    check(&file.specialties, fallback).with_context(|| format!("specialties in {}", path.display()))?;
+    Ok(file.specialties)
+}
+
+fn by_name(entries: Vec<Specialty>) -> BTreeMap<String, Specialty> {
+    entries.into_iter().map(|specialty| (specialty.name.clone(), specialty)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_specialties_fall_back_unless_strict() {
+        let lenient = Specialties::default();
+        assert_eq!(lenient.resolve("science").unwrap().name, "science");
+        assert_eq!(lenient.resolve("astrology").unwrap().name, "general");
+
+        let strict = Specialties::open(&SpecialtiesConfig { strict: true, ..SpecialtiesConfig::default() }).unwrap();
+        assert!(strict.resolve("astrology").is_none());
+        assert!(strict.resolve("").is_none());
+
+        let missing_fallback = SpecialtiesConfig { fallback: "oracle".to_string(), ..SpecialtiesConfig::default() };
+        assert_eq!(missing_fallback.validate().len(), 1);
+    }
+
+    #[test]
+    fn reload_rereads_the_file_and_keeps_entries_on_failure() {
+        let path = std::env::temp_dir().join(format!("void-shrine-specialties-test-{}.toml", uuid::Uuid::new_v4()));
+        let entry = |name: &str| format!("[[specialties]]\nname = \"{}\"\nframing = \"Gently:\"\nmock_response = \"Noted.\"\n", name);
+        std::fs::write(&path, entry("general")).unwrap();
+        let config = SpecialtiesConfig { path: Some(path.clone()), ..SpecialtiesConfig::default() };
+
+        let specialties = Specialties::open(&config).unwrap();
+        assert_eq!(specialties.names(), ["general"]);
+        std::fs::write(&path, entry("general") + &entry("medical")).unwrap();
+        assert_eq!(specialties.reload().unwrap().unwrap(), 2);
+        std::fs::write(&path, entry("medical")).unwrap();
+        assert!(specialties.reload().unwrap().is_err());
+        assert_eq!(specialties.names(), ["general", "medical"]);
+
+        assert!(Specialties::default().reload().is_none());
+        std::fs::remove_file(path).ok();
+    }
+}
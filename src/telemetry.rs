@@ -0,0 +1,150 @@
+//! OpenTelemetry export, behind the `otel` feature. With an OTLP endpoint set
+//! through the standard `OTEL_EXPORTER_OTLP_*` variables, the request spans
+//! from `trace` and the request count and latency are exported over OTLP/HTTP,
+//! and W3C `traceparent` headers continue callers' traces through this server
+//! and on to the LLM backends. Without an endpoint nothing is installed and
+//! every hook here returns at once.
+
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use opentelemetry::metrics::{Counter, Histogram, MeterProvider as _};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_sdk::metrics::MeterProvider;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::runtime;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+const SCOPE: &str = "void-shrine-mcp";
+
+/// Set once metric export is installed
+static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+/// Set once trace export, and with it propagation, is installed
+static TRACING: OnceLock<()> = OnceLock::new();
+
+struct Instruments {
+    requests: Counter<u64>,
+    duration: Histogram<f64>,
+}
+
+tokio::task_local! {
+    /// The caller's trace context for the request being handled
+    static REMOTE_PARENT: Context;
+}
+
+/// Whether `OTEL_EXPORTER_OTLP_ENDPOINT` or the per-signal variable is set
+fn endpoint_configured(signal: &str) -> bool {
+    ["OTEL_EXPORTER_OTLP_ENDPOINT".to_string(), format!("OTEL_EXPORTER_OTLP_{signal}_ENDPOINT")]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|value| !value.is_empty()))
+}
+
+/// Exporters installed by `init`, flushed when dropped
+pub struct Telemetry {
+    meter_provider: Option<MeterProvider>,
+    tracing: bool,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = &self.meter_provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush OpenTelemetry metrics: {}", e);
+            }
+        }
+        if self.tracing {
+            global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Installs the global subscriber: formatted logs to stdout, or stderr in
+/// stdio mode, plus OTLP export of whichever signals have an endpoint. Must be
+/// called from within the Tokio runtime.
+pub fn init(stdio: bool) -> anyhow::Result<Telemetry> {
+    let writer = if stdio { BoxMakeWriter::new(std::io::stderr) } else { BoxMakeWriter::new(std::io::stdout) };
+    let tracer = if endpoint_configured("TRACES") {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().http())
+            .install_batch(runtime::Tokio)?;
+        let _ = TRACING.set(());
+        Some(tracer)
+    } else {
+        None
+    };
+    let meter_provider = if endpoint_configured("METRICS") {
+        let provider = opentelemetry_otlp::new_pipeline()
+            .metrics(runtime::Tokio)
+            .with_exporter(opentelemetry_otlp::new_exporter().http())
+            .build()?;
+        let meter = provider.meter(SCOPE);
+        let instruments = Instruments {
+            requests: meter.u64_counter("void_shrine.requests").with_description("MCP requests handled").init(),
+            duration: meter
+                .f64_histogram("void_shrine.request.duration")
+                .with_description("Time to handle an MCP request")
+                .with_unit(opentelemetry::metrics::Unit::new("s"))
+                .init(),
+        };
+        let _ = INSTRUMENTS.set(instruments);
+        Some(provider)
+    } else {
+        None
+    };
+    let tracing = tracer.is_some();
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer().with_writer(writer))
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+        .try_init()?;
+    Ok(Telemetry { meter_provider, tracing })
+}
+
+/// Runs `request` as part of the caller's trace when `headers` carry a valid
+/// `traceparent`; see `adopt_remote_parent`
+pub async fn continue_remote<F: Future>(headers: &warp::http::HeaderMap, request: F) -> F::Output {
+    if TRACING.get().is_none() {
+        return request.await;
+    }
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    if !context.span().span_context().is_valid() {
+        return request.await;
+    }
+    REMOTE_PARENT.scope(context, request).await
+}
+
+/// Makes `span` a child of the caller's span inside `continue_remote`
+pub fn adopt_remote_parent(span: &Span) {
+    if TRACING.get().is_none() {
+        return;
+    }
+    let _ = REMOTE_PARENT.try_with(|context| span.set_parent(context.clone()));
+}
+
+/// Adds `traceparent` for the current span to an outgoing request
+pub fn propagate(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    if TRACING.get().is_none() {
+        return request;
+    }
+    let mut headers = reqwest::header::HeaderMap::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut HeaderInjector(&mut headers)));
+    request.headers(headers)
+}
+
+pub fn observe_request(method: &str, status: u16, elapsed: Duration) {
+    if let Some(instruments) = INSTRUMENTS.get() {
+        let attributes = [KeyValue::new("rpc.method", method.to_string()), KeyValue::new("http.status_code", i64::from(status))];
+        instruments.requests.add(1, &attributes);
+        instruments.duration.record(elapsed.as_secs_f64(), &attributes);
+    }
+}
@@ -0,0 +1,77 @@
+//! Truncation shared across the pipeline. Knowledge base context fitted to
+//! the context window, citation snippets, session history and the mock
+//! backend's `max_tokens` all cut text here, so a cut never splits a
+//! character, ends after a sentence when that keeps at least half of what
+//! would fit, else after a word on the same terms, and is followed by a
+//! marker saying something was left out.
+
+use std::borrow::Cow;
+use crate::tokenizer::Tokenizer;
+
+/// Marks a cut, unless the caller passes another marker
+pub const ELLIPSIS: &str = "…";
+
+/// `text` within `budget` tokens of `tokenizer`, `marker` included
+pub fn truncate_to_tokens<'a>(text: &'a str, budget: usize, tokenizer: &dyn Tokenizer, marker: &str) -> Cow<'a, str> {
+    truncate(text, marker, |candidate| tokenizer.count(candidate) <= budget)
+}
+
+/// `text` within `max_chars` characters, `marker` included
+pub fn truncate_at_sentence<'a>(text: &'a str, max_chars: usize, marker: &str) -> Cow<'a, str> {
+    truncate(text, marker, |candidate| candidate.chars().count() <= max_chars)
+}
+
+/// Byte offsets just past each sentence of `text`: after `.`, `!` or `?`
+/// followed by whitespace or the end
+pub fn sentence_ends(text: &str) -> impl Iterator<Item = usize> + '_ {
+    text.char_indices()
+        .filter(|(_, c)| matches!(c, '.' | '!' | '?'))
+        .map(|(i, c)| i + c.len_utf8())
+        .filter(|end| text[*end..].chars().next().is_none_or(char::is_whitespace))
+}
+
+/// `text` as it is, borrowed, when it `fits`; else its longest prefix that
+/// fits with `marker` after it, pulled back to a sentence or word end, and
+/// empty when no prefix does. `fits` must not turn true again as its
+/// argument grows.
+fn truncate<'a>(text: &'a str, marker: &str, fits: impl Fn(&str) -> bool) -> Cow<'a, str> {
+    if fits(text) {
+        return Cow::Borrowed(text);
+    }
+    let cut = |end: usize| text[..end].trim_end();
+    let ends: Vec<usize> = text.char_indices().map(|(i, _)| i).filter(|i| *i > 0).collect();
+    let fitting = ends.partition_point(|end| fits(&format!("{}{}", cut(*end), marker)));
+    let Some(limit) = fitting.checked_sub(1).map(|i| ends[i]) else {
+        return Cow::Owned(String::new());
+    };
+    let sentence = sentence_ends(text).take_while(|end| *end <= limit).last();
+    let word = text.char_indices().take_while(|(i, _)| *i <= limit).filter(|(_, c)| c.is_whitespace()).map(|(i, _)| i).last();
+    let end = [sentence, word]
+        .into_iter()
+        .flatten()
+        .find(|end| !cut(*end).is_empty() && end * 2 >= limit)
+        .unwrap_or(limit);
+    Cow::Owned(format!("{}{}", cut(end), marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::EstimateTokenizer;
+
+    #[test]
+    fn cuts_prefer_sentences_then_words() {
+        let text = "Tide pools drain twice a day. Anemones close until the water returns.";
+        assert_eq!(truncate_at_sentence(text, 200, ELLIPSIS), text);
+        assert_eq!(truncate_at_sentence(text, 50, ELLIPSIS), "Tide pools drain twice a day.…");
+        // A sentence end keeping less than half of what fits gives way to a word end
+        assert_eq!(truncate_at_sentence("Yes. Anemones close until the water returns.", 30, ELLIPSIS), "Yes. Anemones close until the…");
+        assert_eq!(truncate_at_sentence("Unbreakable", 6, "..."), "Unb...");
+        assert_eq!(truncate_at_sentence("Tide", 2, "..."), "");
+        assert_eq!(truncate_at_sentence("ééééé", 3, ""), "ééé");
+
+        // Four bytes a token
+        assert_eq!(truncate_to_tokens(text, 9, &EstimateTokenizer, ""), "Tide pools drain twice a day.");
+        assert_eq!(truncate_to_tokens("naïve café", 2, &EstimateTokenizer, ""), "naïve");
+    }
+}
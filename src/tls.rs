@@ -0,0 +1,238 @@
+//! TLS termination. The certificate lives in a `CertificateStore` that every
+//! handshake asks for the current key, so renewed files are picked up on SIGHUP
+//! or when their modification time changes, without dropping connections.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, Context, Result};
+use futures::Stream;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use warp::http::{header, StatusCode};
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+use crate::shutdown::Shutdown;
+
+/// Handshakes slower than this are abandoned
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Finished handshakes waiting for the server to pick them up
+const ACCEPT_BACKLOG: usize = 64;
+
+pub struct CertificateStore {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+    /// Modification times of the files as last loaded
+    loaded: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl fmt::Debug for CertificateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertificateStore")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CertificateStore {
+    /// Loads a PEM certificate chain and private key, failing with the
+    /// offending path when either is missing or unusable
+    pub fn load(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Result<Arc<Self>> {
+        let cert_path = cert_path.into();
+        let key_path = key_path.into();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let loaded = Mutex::new(modified(&cert_path, &key_path));
+        let key = read_certified_key(&provider, &cert_path, &key_path)?;
+        Ok(Arc::new(Self { cert_path, key_path, provider, current: RwLock::new(Arc::new(key)), loaded }))
+    }
+
+    /// Re-reads both files. On failure the certificate in use is kept.
+    pub fn reload(&self) -> Result<()> {
+        let stamps = modified(&self.cert_path, &self.key_path);
+        let key = read_certified_key(&self.provider, &self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+        *self.loaded.lock().unwrap_or_else(|e| e.into_inner()) = stamps;
+        Ok(())
+    }
+
+    /// Reloads if either file changed since the last load
+    pub fn reload_if_changed(&self) -> Result<bool> {
+        let stamps = modified(&self.cert_path, &self.key_path);
+        if stamps == *self.loaded.lock().unwrap_or_else(|e| e.into_inner()) {
+            return Ok(false);
+        }
+        self.reload()?;
+        Ok(true)
+    }
+
+    /// The DER of the leaf certificate currently served
+    pub fn leaf(&self) -> CertificateDer<'static> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).cert[0].clone()
+    }
+
+    /// A server config resolving every handshake to the current certificate
+    pub fn server_config(self: &Arc<Self>) -> Result<Arc<ServerConfig>> {
+        let mut config = ServerConfig::builder_with_provider(Arc::clone(&self.provider))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::clone(self) as Arc<dyn ResolvesServerCert>);
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    /// Reloads on SIGHUP and, with a non-zero `poll_interval`, when the files change
+    pub fn watch(self: &Arc<Self>, poll_interval: Duration) -> tokio::task::JoinHandle<()> {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .map_err(|e| tracing::warn!("Cannot listen for SIGHUP, certificates reload on change only: {}", e))
+                .ok();
+            let mut poll = (!poll_interval.is_zero()).then(|| {
+                let mut poll = tokio::time::interval(poll_interval);
+                poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                poll
+            });
+            loop {
+                #[cfg(unix)]
+                let hangup_received = async {
+                    match hangup.as_mut() {
+                        Some(hangup) => hangup.recv().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                #[cfg(not(unix))]
+                let hangup_received = std::future::pending::<Option<()>>();
+                let polled = async {
+                    match poll.as_mut() {
+                        Some(poll) => poll.tick().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    signal = hangup_received => {
+                        if signal.is_none() {
+                            return;
+                        }
+                        match store.reload() {
+                            Ok(()) => tracing::info!("Reloaded TLS certificate {} on SIGHUP", store.cert_path.display()),
+                            Err(e) => tracing::error!("Keeping the current TLS certificate: {:#}", e),
+                        }
+                    }
+                    _ = polled => match store.reload_if_changed() {
+                        Ok(true) => tracing::info!("Reloaded changed TLS certificate {}", store.cert_path.display()),
+                        Ok(false) => {}
+                        Err(e) => tracing::error!("Keeping the current TLS certificate: {:#}", e),
+                    },
+                }
+            }
+        })
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner())))
+    }
+}
+
+fn modified(cert_path: &Path, key_path: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let stamp = |path: &Path| std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    (stamp(cert_path), stamp(key_path))
+}
+
+fn read_certified_key(provider: &CryptoProvider, cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let cert_pem = std::fs::read(cert_path).with_context(|| format!("reading TLS certificate {}", cert_path.display()))?;
+    let certs = CertificateDer::pem_slice_iter(&cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow!("parsing TLS certificate {}: {:?}", cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no PEM certificates in {}", cert_path.display()));
+    }
+
+    let key_pem = std::fs::read(key_path).with_context(|| format!("reading TLS private key {}", key_path.display()))?;
+    let key = PrivateKeyDer::from_pem_slice(&key_pem)
+        .map_err(|e| anyhow!("no usable PEM private key in {}: {:?}", key_path.display(), e))?;
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .with_context(|| format!("loading TLS private key {}", key_path.display()))?;
+    let key = CertifiedKey::new(certs, signing_key);
+    key.keys_match()
+        .with_context(|| format!("{} does not belong to {}", key_path.display(), cert_path.display()))?;
+    Ok(key)
+}
+
+/// TLS connections accepted on `listener` until shutdown starts. Handshakes
+/// run concurrently so a slow client cannot hold up the others.
+pub fn incoming(
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+    shutdown: Arc<Shutdown>,
+) -> impl Stream<Item = io::Result<TlsStream<TcpStream>>> {
+    let acceptor = TlsAcceptor::from(config);
+    let (accepted, mut ready) = mpsc::channel(ACCEPT_BACKLOG);
+    tokio::spawn(async move {
+        loop {
+            let (tcp, peer) = tokio::select! {
+                connection = listener.accept() => match connection {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        tracing::warn!("Failed to accept a connection: {}", e);
+                        continue;
+                    }
+                },
+                _ = shutdown.draining() => return,
+            };
+            let acceptor = acceptor.clone();
+            let accepted = accepted.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                    Ok(Ok(tls)) => {
+                        let _ = accepted.send(Ok(tls)).await;
+                    }
+                    Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", peer, e),
+                    Err(_) => tracing::debug!("TLS handshake with {} timed out", peer),
+                }
+            });
+        }
+    });
+    futures::stream::poll_fn(move |cx| ready.poll_recv(cx))
+}
+
+/// Answers every plain HTTP request with a permanent redirect to the same
+/// path over HTTPS on `https_port`
+pub fn redirect_to_https(https_port: u16) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path::full()
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and(warp::header::optional::<String>("host"))
+        .map(move |path: FullPath, query: String, host: Option<String>| {
+            let host = host.unwrap_or_else(|| "localhost".to_string());
+            // Drop any port, keeping bracketed IPv6 addresses whole
+            let host = match host.rsplit_once(':') {
+                Some((name, port)) if !port.contains(']') => name.to_string(),
+                _ => host,
+            };
+            let mut location = format!("https://{}:{}{}", host, https_port, path.as_str());
+            if !query.is_empty() {
+                location.push('?');
+                location.push_str(&query);
+            }
+            // 308 keeps the method and body, unlike 301
+            warp::reply::with_header(warp::reply::with_status(warp::reply(), StatusCode::PERMANENT_REDIRECT), header::LOCATION, location)
+        })
+}
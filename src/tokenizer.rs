@@ -0,0 +1,146 @@
+//! Token counting. One tokenizer measures everything the server budgets and
+//! reports: prompts against `context_window`, knowledge base context and
+//! session history, and the token counts in `ResponseMetrics` when a backend
+//! reports none. The default estimates four bytes per token; with the
+//! `tiktoken` feature, OpenAI's `cl100k_base` and `o200k_base` BPE
+//! vocabularies count exactly for models that use them.
+
+use std::sync::Arc;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+pub trait Tokenizer: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &str;
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Four bytes per token: close for English prose, low for code and
+/// identifiers, high for CJK text
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EstimateTokenizer;
+
+impl Tokenizer for EstimateTokenizer {
+    fn name(&self) -> &str {
+        "estimate"
+    }
+
+    fn count(&self, text: &str) -> usize {
+        text.len() / 4
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerKind {
+    #[default]
+    Estimate,
+    /// GPT-4 and GPT-3.5; needs the `tiktoken` feature
+    Cl100kBase,
+    /// GPT-4o; needs the `tiktoken` feature
+    O200kBase,
+}
+
+impl TokenizerKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenizerKind::Estimate => "estimate",
+            TokenizerKind::Cl100kBase => "cl100k_base",
+            TokenizerKind::O200kBase => "o200k_base",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenizerConfig {
+    pub kind: TokenizerKind,
+}
+
+impl TokenizerConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.kind != TokenizerKind::Estimate && cfg!(not(feature = "tiktoken")) {
+            problems.push(format!("tokenizer.kind {} needs a build with the tiktoken feature", self.kind.name()));
+        }
+        problems
+    }
+
+    pub fn build(&self) -> Result<Arc<dyn Tokenizer>> {
+        match self.kind {
+            TokenizerKind::Estimate => Ok(Arc::new(EstimateTokenizer)),
+            #[cfg(feature = "tiktoken")]
+            kind => Ok(Arc::new(BpeTokenizer::new(kind)?)),
+            #[cfg(not(feature = "tiktoken"))]
+            kind => Err(anyhow::anyhow!("tokenizer {} needs a build with the tiktoken feature", kind.name())),
+        }
+    }
+}
+
+/// Byte-pair encoding with one of OpenAI's vocabularies
+#[cfg(feature = "tiktoken")]
+pub struct BpeTokenizer {
+    kind: TokenizerKind,
+    bpe: tiktoken_rs::CoreBPE,
+}
+
+#[cfg(feature = "tiktoken")]
+impl BpeTokenizer {
+    pub fn new(kind: TokenizerKind) -> Result<Self> {
+        let bpe = match kind {
+            TokenizerKind::Cl100kBase => tiktoken_rs::cl100k_base()?,
+            TokenizerKind::O200kBase => tiktoken_rs::o200k_base()?,
+            TokenizerKind::Estimate => return Err(anyhow::anyhow!("the estimate is not a BPE vocabulary")),
+        };
+        Ok(Self { kind, bpe })
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+impl std::fmt::Debug for BpeTokenizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BpeTokenizer").field("kind", &self.kind).finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        self.kind.name()
+    }
+
+    /// Special-token text such as `<|endoftext|>` is counted as ordinary text
+    fn count(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_estimate_is_the_default() {
+        let tokenizer = TokenizerConfig::default().build().unwrap();
+        assert_eq!(tokenizer.name(), "estimate");
+        assert_eq!(tokenizer.count("four bytes a token"), 4);
+    }
+
+    #[cfg(not(feature = "tiktoken"))]
+    #[test]
+    fn bpe_vocabularies_need_the_feature() {
+        let config = TokenizerConfig { kind: TokenizerKind::Cl100kBase };
+        assert_eq!(config.validate().len(), 1);
+        assert!(config.build().is_err());
+    }
+
+    #[cfg(feature = "tiktoken")]
+    #[test]
+    fn bpe_counts_differ_from_the_estimate_where_it_is_weakest() {
+        let bpe = TokenizerConfig { kind: TokenizerKind::Cl100kBase }.build().unwrap();
+        assert_eq!(bpe.count("hello world"), 2);
+        // Three bytes a character, but about a token each
+        let cjk = "知识库中的文档";
+        assert!(bpe.count(cjk) > EstimateTokenizer.count(cjk), "{}", bpe.count(cjk));
+        assert!(TokenizerConfig { kind: TokenizerKind::O200kBase }.build().unwrap().count("hello world") > 0);
+    }
+}
@@ -0,0 +1,263 @@
+//! Void shrine tokens: each response's `void_shrine_token` attests that this
+//! server answered `request_id` for `agent_id` at `issued_at`. A token is
+//! `vs1.{claims}.{signature}`, the claims as base64url JSON and the signature
+//! an HMAC-SHA256 of everything before it, so `POST /api/tokens/verify` can
+//! tell a genuine, fresh token from a forged or stale one. Retired secrets
+//! keep verifying until their `accept_until`, for rotating without breaking
+//! tokens in flight. `opaque` restores the old `vs_{timestamp}_{random}`
+//! tokens, which attest nothing.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use ring::rand::SystemRandom;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const VERSION: &str = "vs1";
+/// Slack for the clocks of servers sharing a secret
+const MAX_CLOCK_SKEW_SECS: i64 = 60;
+/// Shortest secret accepted
+const MIN_SECRET_BYTES: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokensConfig {
+    /// Issue the old `vs_{timestamp}_{random}` tokens instead of signed ones
+    pub opaque: bool,
+    /// Signing key; unset uses a random one, so only this process verifies its tokens
+    pub secret: Option<String>,
+    /// Secrets rotated out, still accepted until their `accept_until`
+    pub previous_secrets: Vec<RetiredSecret>,
+    /// Seconds a token verifies for after it is issued
+    pub max_age_secs: u64,
+}
+
+impl Default for TokensConfig {
+    fn default() -> Self {
+        Self { opaque: false, secret: None, previous_secrets: Vec::new(), max_age_secs: 86400 }
+    }
+}
+
+impl TokensConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.secret.as_ref().is_some_and(|secret| secret.len() < MIN_SECRET_BYTES) {
+            problems.push(format!("tokens.secret must be at least {} bytes", MIN_SECRET_BYTES));
+        }
+        if self.previous_secrets.iter().any(|retired| retired.secret.len() < MIN_SECRET_BYTES) {
+            problems.push(format!("tokens.previous_secrets must each be at least {} bytes", MIN_SECRET_BYTES));
+        }
+        if !self.previous_secrets.is_empty() && self.secret.is_none() {
+            problems.push("tokens.secret must be set when tokens.previous_secrets is".to_string());
+        }
+        if self.max_age_secs == 0 {
+            problems.push("tokens.max_age_secs must be positive".to_string());
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetiredSecret {
+    pub secret: String,
+    pub accept_until: DateTime<Utc>,
+}
+
+/// What a token attests to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    pub request_id: String,
+    pub agent_id: String,
+    pub issued_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenVerifyRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenRejection {
+    /// Not a `vs1` token, or garbled
+    Malformed,
+    /// An old-style `vs_` token, which carries no signature
+    Opaque,
+    /// Signed by no secret this server accepts
+    BadSignature,
+    /// Older than `max_age_secs`
+    Expired,
+    /// Issued later than this server's clock allows
+    IssuedInFuture,
+}
+
+/// Which secret signed a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningKey {
+    Current,
+    /// One of `previous_secrets`
+    Previous,
+}
+
+/// The answer of `POST /api/tokens/verify`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenVerification {
+    pub valid: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<TokenRejection>,
+    /// Present whenever the signature checks out, stale tokens included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claims: Option<TokenClaims>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age_secs: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<SigningKey>,
+}
+
+impl TokenVerification {
+    fn rejected(reason: TokenRejection) -> Self {
+        Self { valid: false, reason: Some(reason), claims: None, age_secs: None, key: None }
+    }
+}
+
+pub struct TokenSigner {
+    opaque: bool,
+    key: hmac::Key,
+    previous: Vec<(hmac::Key, DateTime<Utc>)>,
+    max_age: chrono::Duration,
+}
+
+impl Default for TokenSigner {
+    fn default() -> Self {
+        Self::new(&TokensConfig::default())
+    }
+}
+
+impl TokenSigner {
+    pub fn new(config: &TokensConfig) -> Self {
+        let key = match &config.secret {
+            Some(secret) => hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            None => {
+                if !config.opaque {
+                    tracing::warn!("tokens.secret is unset; void shrine tokens verify only until this process exits");
+                }
+                hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new()).expect("the system random source works")
+            }
+        };
+        let previous = config
+            .previous_secrets
+            .iter()
+            .map(|retired| (hmac::Key::new(hmac::HMAC_SHA256, retired.secret.as_bytes()), retired.accept_until))
+            .collect();
+        let max_age = chrono::Duration::seconds(config.max_age_secs.min(i64::MAX as u64) as i64);
+        Self { opaque: config.opaque, key, previous, max_age }
+    }
+
+    /// The token for a response to `request_id` from `agent_id`
+    pub fn issue(&self, request_id: &str, agent_id: &str, issued_at: DateTime<Utc>) -> String {
+        if self.opaque {
+            let entropy = Uuid::new_v4().to_string()[..8].to_string();
+            return format!("vs_{}_{}", issued_at.timestamp_millis(), entropy);
+        }
+        let claims = TokenClaims { request_id: request_id.to_string(), agent_id: agent_id.to_string(), issued_at };
+        let claims = serde_json::to_vec(&claims).expect("token claims serialize");
+        let signed = format!("{}.{}", VERSION, URL_SAFE_NO_PAD.encode(claims));
+        let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&self.key, signed.as_bytes()));
+        format!("{}.{}", signed, signature)
+    }
+
+    pub fn verify(&self, token: &str) -> TokenVerification {
+        self.verify_at(token, Utc::now())
+    }
+
+    /// Verifies `token` as of `now`
+    pub fn verify_at(&self, token: &str, now: DateTime<Utc>) -> TokenVerification {
+        if token.starts_with("vs_") {
+            return TokenVerification::rejected(TokenRejection::Opaque);
+        }
+        let Some((signed, signature)) = token.rsplit_once('.') else {
+            return TokenVerification::rejected(TokenRejection::Malformed);
+        };
+        let Some(claims) = signed.strip_prefix(VERSION).and_then(|rest| rest.strip_prefix('.')) else {
+            return TokenVerification::rejected(TokenRejection::Malformed);
+        };
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return TokenVerification::rejected(TokenRejection::Malformed);
+        };
+        let current = std::iter::once((&self.key, SigningKey::Current));
+        let previous = self.previous.iter().filter(|(_, until)| now <= *until).map(|(key, _)| (key, SigningKey::Previous));
+        let Some(key) = current.chain(previous).find_map(|(key, which)| hmac::verify(key, signed.as_bytes(), &signature).ok().map(|_| which)) else {
+            return TokenVerification::rejected(TokenRejection::BadSignature);
+        };
+        let Some(claims) = URL_SAFE_NO_PAD.decode(claims).ok().and_then(|json| serde_json::from_slice::<TokenClaims>(&json).ok()) else {
+            return TokenVerification::rejected(TokenRejection::Malformed);
+        };
+        let age = now - claims.issued_at;
+        let reason = if age > self.max_age {
+            Some(TokenRejection::Expired)
+        } else if age < -chrono::Duration::seconds(MAX_CLOCK_SKEW_SECS) {
+            Some(TokenRejection::IssuedInFuture)
+        } else {
+            None
+        };
+        TokenVerification { valid: reason.is_none(), reason, claims: Some(claims), age_secs: Some(age.num_seconds()), key: Some(key) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(secret: &str) -> TokensConfig {
+        TokensConfig { secret: Some(secret.to_string()), ..TokensConfig::default() }
+    }
+
+    #[test]
+    fn signed_tokens_verify_until_they_expire() {
+        let signer = TokenSigner::new(&config("a-secret-of-sixteen-bytes"));
+        let issued_at = Utc::now();
+        let token = signer.issue("req-1", "scout", issued_at);
+
+        let verified = signer.verify_at(&token, issued_at + chrono::Duration::seconds(30));
+        assert!(verified.valid, "{:?}", verified);
+        let claims = verified.claims.unwrap();
+        assert_eq!((claims.request_id.as_str(), claims.agent_id.as_str(), verified.age_secs), ("req-1", "scout", Some(30)));
+
+        let stale = signer.verify_at(&token, issued_at + chrono::Duration::days(2));
+        assert_eq!((stale.valid, stale.reason, stale.claims.is_some()), (false, Some(TokenRejection::Expired), true));
+        let early = signer.verify_at(&token, issued_at - chrono::Duration::minutes(5));
+        assert_eq!(early.reason, Some(TokenRejection::IssuedInFuture));
+
+        let (signed, _) = token.rsplit_once('.').unwrap();
+        let forged = TokenSigner::new(&config("another-sixteen-byte-secret")).issue("req-1", "scout", issued_at);
+        let forged = format!("{}.{}", signed, forged.rsplit_once('.').unwrap().1);
+        assert_eq!(signer.verify(&forged).reason, Some(TokenRejection::BadSignature));
+        assert_eq!(signer.verify("vs_1700000000000_0a1b2c3d").reason, Some(TokenRejection::Opaque));
+        assert_eq!(signer.verify("vs1.garbage").reason, Some(TokenRejection::Malformed));
+    }
+
+    #[test]
+    fn retired_secrets_verify_until_their_grace_period_ends() {
+        let old = TokenSigner::new(&config("the-old-signing-secret"));
+        let token = old.issue("req-1", "scout", Utc::now());
+        let accept_until = Utc::now() + chrono::Duration::hours(1);
+        let rotated = TokenSigner::new(&TokensConfig {
+            previous_secrets: vec![RetiredSecret { secret: "the-old-signing-secret".to_string(), accept_until }],
+            ..config("the-new-signing-secret")
+        });
+
+        let verified = rotated.verify(&token);
+        assert_eq!((verified.valid, verified.key), (true, Some(SigningKey::Previous)));
+        assert_eq!(rotated.verify(&rotated.issue("req-2", "scout", Utc::now())).key, Some(SigningKey::Current));
+        let after_grace = rotated.verify_at(&token, accept_until + chrono::Duration::seconds(1));
+        assert_eq!(after_grace.reason, Some(TokenRejection::BadSignature));
+
+        let opaque = TokenSigner::new(&TokensConfig { opaque: true, ..TokensConfig::default() });
+        assert!(opaque.issue("req-3", "scout", Utc::now()).starts_with("vs_"));
+    }
+}
@@ -0,0 +1,96 @@
+//! Tracing spans for MCP requests. Each request runs in an `mcp_request` span
+//! naming the request, agent, method, model and specialty; its stages (chaos
+//! decision, moral recentering, RAG retrieval, backend completion) run in
+//! child spans. Every span records `duration_ms`, `outcome` ("ok" or "error")
+//! and any `error` before it closes, so a JSON subscriber yields one
+//! machine-parseable trace per request. With the `otel` feature the same spans
+//! are exported, carrying OpenTelemetry's `rpc.*` and `otel.*` attributes.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use crate::mcp_server::MCPParams;
+
+/// A stage span with the fields `record_outcome` fills in, plus any given
+macro_rules! stage_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        tracing::info_span!(
+            $name,
+            duration_ms = tracing::field::Empty,
+            outcome = tracing::field::Empty,
+            error = tracing::field::Empty,
+            otel.status_code = tracing::field::Empty
+            $(, $($fields)*)?
+        )
+    };
+}
+pub(crate) use stage_span;
+
+/// The root span of one request; `status` is set as it ends
+pub fn request_span(request_id: &str, method: &str, params: &MCPParams) -> Span {
+    let span = tracing::info_span!(
+        "mcp_request",
+        otel.name = %format_args!("mcp/{}", method),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        rpc.system = "mcp",
+        rpc.method = %method,
+        request_id = %request_id,
+        method = %method,
+        agent_id = %params.agent_id,
+        model = %params.model,
+        specialty = %params.specialty,
+        status = Empty,
+        duration_ms = Empty,
+        outcome = Empty,
+        error = Empty,
+    );
+    #[cfg(feature = "otel")]
+    crate::telemetry::adopt_remote_parent(&span);
+    span
+}
+
+/// Records how long the span's work took and how it ended
+pub fn record_outcome<T, E: Display>(span: &Span, started: Instant, result: &Result<T, E>) {
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    match result {
+        Ok(_) => {
+            span.record("outcome", "ok");
+        }
+        Err(e) => {
+            span.record("outcome", "error");
+            span.record("error", tracing::field::display(e));
+            span.record("otel.status_code", "ERROR");
+        }
+    }
+}
+
+/// Runs a request's handling as part of the caller's trace, when export is on
+/// and `headers` carry a `traceparent`
+pub async fn continue_remote<F: Future>(headers: &warp::http::HeaderMap, request: F) -> F::Output {
+    #[cfg(feature = "otel")]
+    return crate::telemetry::continue_remote(headers, request).await;
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = headers;
+        request.await
+    }
+}
+
+/// Carries the current trace to an outgoing request, when export is on
+pub fn propagate(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    #[cfg(feature = "otel")]
+    return crate::telemetry::propagate(request);
+    #[cfg(not(feature = "otel"))]
+    request
+}
+
+/// Runs `stage` in `span`, then records its outcome there
+pub async fn timed<T, E: Display>(span: Span, stage: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let started = Instant::now();
+    let result = stage.instrument(span.clone()).await;
+    record_outcome(&span, started, &result);
+    result
+}
@@ -0,0 +1,370 @@
+//! Token usage per agent and model over time, for attributing cost. Each
+//! `llm_inference` answer adds its prompt and completion tokens to an hourly
+//! bucket keyed by agent, the model that answered and where the answer came
+//! from: a backend, the response cache or a dry run, so cached and previewed
+//! requests never pass for backend spend. Buckets older than `retention_days`
+//! are dropped; the rest are kept across restarts with the other agent
+//! metrics in `metrics.state_path`. `GET /api/usage` sums them into an hourly
+//! or daily series, estimating the cost of backend usage from `prices`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageConfig {
+    /// Days of hourly buckets kept
+    pub retention_days: u32,
+    /// What `prices` are in, reported alongside estimated costs
+    pub currency: String,
+    /// Price per model name, as requests name it or a fallback chain served it;
+    /// without any, usage carries no cost estimate
+    pub prices: BTreeMap<String, ModelPrice>,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self { retention_days: 90, currency: "USD".to_string(), prices: BTreeMap::new() }
+    }
+}
+
+impl UsageConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.retention_days == 0 {
+            problems.push("usage.retention_days must be positive".to_string());
+        }
+        for (model, price) in &self.prices {
+            let valid = |per_million: f64| per_million.is_finite() && per_million >= 0.0;
+            if !valid(price.prompt_per_million) || !valid(price.completion_per_million) {
+                problems.push(format!("usage.prices.{} must be non-negative", model));
+            }
+        }
+        problems
+    }
+}
+
+/// What a model costs per million tokens, in `UsageConfig::currency`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt_per_million + usage.completion_tokens as f64 * self.completion_per_million) / 1e6
+    }
+}
+
+/// Where an answer's tokens were spent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageSource {
+    Backend,
+    /// Served from the response cache: the tokens are those of the answer
+    /// cached, spent when it was first made
+    Cached,
+    /// The prompt was assembled and counted but never sent
+    DryRun,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    fn add(&mut self, other: &TokenUsage) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// One hour of one agent's usage of one model from one source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageBucket {
+    /// Start of the hour
+    pub hour: DateTime<Utc>,
+    pub agent_id: String,
+    pub model: String,
+    pub source: UsageSource,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGranularity {
+    #[default]
+    Hour,
+    Day,
+}
+
+impl UsageGranularity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UsageGranularity::Hour => "hour",
+            UsageGranularity::Day => "day",
+        }
+    }
+
+    fn seconds(self) -> i64 {
+        match self {
+            UsageGranularity::Hour => 3600,
+            UsageGranularity::Day => 86_400,
+        }
+    }
+
+    /// Start of the period `at` falls in
+    pub fn start(self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let seconds = self.seconds();
+        DateTime::from_timestamp(at.timestamp().div_euclid(seconds) * seconds, 0).unwrap_or(at)
+    }
+
+    /// Start of the period after the one `at` falls in
+    pub fn end(self, at: DateTime<Utc>) -> DateTime<Utc> {
+        self.start(at) + Duration::seconds(self.seconds())
+    }
+}
+
+/// Query parameters of `GET /api/usage`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageParams {
+    pub agent_id: Option<String>,
+    pub model: Option<String>,
+    /// Only hours ending after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only hours starting before this time
+    pub until: Option<DateTime<Utc>>,
+    pub granularity: UsageGranularity,
+}
+
+/// Usage by source; the cost estimate covers backend usage of priced models
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    pub backend: TokenUsage,
+    pub cached: TokenUsage,
+    pub dry_run: TokenUsage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_cost: Option<f64>,
+}
+
+impl UsageSummary {
+    fn add(&mut self, source: UsageSource, usage: &TokenUsage, cost: Option<f64>) {
+        match source {
+            UsageSource::Backend => self.backend.add(usage),
+            UsageSource::Cached => self.cached.add(usage),
+            UsageSource::DryRun => self.dry_run.add(usage),
+        }
+        if let Some(cost) = cost {
+            *self.estimated_cost.get_or_insert(0.0) += cost;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsagePoint {
+    /// Start of the hour or day
+    pub start: DateTime<Utc>,
+    #[serde(flatten)]
+    pub usage: UsageSummary,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub model: String,
+    #[serde(flatten)]
+    pub usage: UsageSummary,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageResponse {
+    pub granularity: UsageGranularity,
+    /// Oldest first; periods without usage are left out
+    pub series: Vec<UsagePoint>,
+    pub totals: UsageSummary,
+    /// Sorted by model
+    pub models: Vec<ModelUsage>,
+    /// Set when a price table is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Models used with a backend that have no price, left out of the estimates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unpriced_models: Vec<String>,
+}
+
+type BucketKey = (DateTime<Utc>, String, String, UsageSource);
+
+/// The hourly buckets, oldest first
+#[derive(Debug)]
+pub struct UsageLedger {
+    config: UsageConfig,
+    buckets: Mutex<BTreeMap<BucketKey, TokenUsage>>,
+}
+
+impl Default for UsageLedger {
+    fn default() -> Self {
+        Self::new(UsageConfig::default())
+    }
+}
+
+impl UsageLedger {
+    pub fn new(config: UsageConfig) -> Self {
+        Self { config, buckets: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Takes back buckets saved by `buckets`
+    pub fn restore(&self, saved: Vec<UsageBucket>) {
+        let mut buckets = self.lock();
+        for bucket in saved {
+            buckets.entry((bucket.hour, bucket.agent_id, bucket.model, bucket.source)).or_default().add(&bucket.usage);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<BucketKey, TokenUsage>> {
+        self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Adds one answer's tokens, dropping buckets past retention
+    pub fn record(&self, now: DateTime<Utc>, agent_id: &str, model: &str, source: UsageSource, prompt_tokens: u32, completion_tokens: u32) {
+        let usage = TokenUsage { requests: 1, prompt_tokens: u64::from(prompt_tokens), completion_tokens: u64::from(completion_tokens) };
+        let hour = UsageGranularity::Hour.start(now);
+        let mut buckets = self.lock();
+        buckets.entry((hour, agent_id.to_string(), model.to_string(), source)).or_default().add(&usage);
+        let cutoff = hour - Duration::days(i64::from(self.config.retention_days));
+        while buckets.first_key_value().is_some_and(|((hour, ..), _)| *hour < cutoff) {
+            buckets.pop_first();
+        }
+    }
+
+    /// Backend usage since `since`, an hour's start, of the agents `covers` picks
+    pub fn consumed(&self, since: DateTime<Utc>, covers: impl Fn(&str) -> bool) -> TokenUsage {
+        let mut total = TokenUsage::default();
+        for ((_, agent_id, _, source), usage) in self.lock().range((since, String::new(), String::new(), UsageSource::Backend)..) {
+            if *source == UsageSource::Backend && covers(agent_id) {
+                total.add(usage);
+            }
+        }
+        total
+    }
+
+    /// Every bucket, for saving
+    pub fn buckets(&self) -> Vec<UsageBucket> {
+        self.lock()
+            .iter()
+            .map(|((hour, agent_id, model, source), usage)| UsageBucket {
+                hour: *hour,
+                agent_id: agent_id.clone(),
+                model: model.clone(),
+                source: *source,
+                usage: *usage,
+            })
+            .collect()
+    }
+
+    /// The buckets matching `params` of agents `visible` admits, summed per
+    /// period and per model
+    pub fn query(&self, params: &UsageParams, visible: impl Fn(&str) -> bool) -> UsageResponse {
+        let priced = !self.config.prices.is_empty();
+        let mut series: BTreeMap<DateTime<Utc>, UsageSummary> = BTreeMap::new();
+        let mut models: BTreeMap<String, UsageSummary> = BTreeMap::new();
+        let mut totals = UsageSummary { estimated_cost: priced.then_some(0.0), ..UsageSummary::default() };
+        let mut unpriced = BTreeSet::new();
+        for ((hour, agent_id, model, source), usage) in self.lock().iter() {
+            let in_range = params.since.is_none_or(|since| *hour + Duration::hours(1) > since)
+                && params.until.is_none_or(|until| *hour < until);
+            let matches = params.agent_id.as_ref().is_none_or(|wanted| wanted == agent_id)
+                && params.model.as_ref().is_none_or(|wanted| wanted == model);
+            if !in_range || !matches || !visible(agent_id) {
+                continue;
+            }
+            let cost = match (priced, *source, self.config.prices.get(model)) {
+                (true, UsageSource::Backend, Some(price)) => Some(price.cost(usage)),
+                (true, UsageSource::Backend, None) => {
+                    unpriced.insert(model.clone());
+                    None
+                }
+                _ => None,
+            };
+            let point = series.entry(params.granularity.start(*hour)).or_insert_with(|| UsageSummary {
+                estimated_cost: priced.then_some(0.0),
+                ..UsageSummary::default()
+            });
+            point.add(*source, usage, cost);
+            let per_model = models.entry(model.clone()).or_insert_with(|| UsageSummary {
+                estimated_cost: priced.then_some(0.0),
+                ..UsageSummary::default()
+            });
+            per_model.add(*source, usage, cost);
+            totals.add(*source, usage, cost);
+        }
+        UsageResponse {
+            granularity: params.granularity,
+            series: series.into_iter().map(|(start, usage)| UsagePoint { start, usage }).collect(),
+            totals,
+            models: models.into_iter().map(|(model, usage)| ModelUsage { model, usage }).collect(),
+            currency: priced.then(|| self.config.currency.clone()),
+            unpriced_models: unpriced.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn usage_is_summed_per_period_and_priced_from_backends_only() {
+        let prices = BTreeMap::from([("gpt".to_string(), ModelPrice { prompt_per_million: 2.0, completion_per_million: 8.0 })]);
+        let ledger = UsageLedger::new(UsageConfig { prices, ..UsageConfig::default() });
+        ledger.record(at("2026-03-01T09:10:00Z"), "scout", "gpt", UsageSource::Backend, 1_000_000, 500_000);
+        ledger.record(at("2026-03-01T09:50:00Z"), "scout", "gpt", UsageSource::Cached, 1_000_000, 500_000);
+        ledger.record(at("2026-03-01T11:00:00Z"), "scout", "llama", UsageSource::Backend, 100, 10);
+        ledger.record(at("2026-03-01T11:30:00Z"), "scout", "gpt", UsageSource::DryRun, 300, 0);
+
+        let hourly = ledger.query(&UsageParams::default(), |_| true);
+        assert_eq!(hourly.series.iter().map(|point| point.start).collect::<Vec<_>>(), [at("2026-03-01T09:00:00Z"), at("2026-03-01T11:00:00Z")]);
+        assert_eq!(hourly.series[0].usage.estimated_cost, Some(6.0));
+        assert_eq!(hourly.series[0].usage.cached.prompt_tokens, 1_000_000);
+        assert_eq!((hourly.totals.backend.requests, hourly.totals.dry_run.prompt_tokens), (2, 300));
+        assert_eq!((hourly.unpriced_models, hourly.currency.as_deref()), (vec!["llama".to_string()], Some("USD")));
+
+        let daily = ledger.query(&UsageParams { granularity: UsageGranularity::Day, model: Some("gpt".to_string()), ..UsageParams::default() }, |_| true);
+        assert_eq!(daily.series.len(), 1);
+        assert_eq!(daily.series[0].start, at("2026-03-01T00:00:00Z"));
+        assert_eq!((daily.totals.backend.requests, daily.models.len()), (1, 1));
+
+        // Hours overlapping the range
+        let since = ledger.query(&UsageParams { since: Some(at("2026-03-01T09:30:00Z")), until: Some(at("2026-03-01T11:00:00Z")), ..UsageParams::default() }, |_| true);
+        assert_eq!(since.series.len(), 1);
+        assert!(ledger.query(&UsageParams::default(), |agent_id| agent_id != "scout").series.is_empty());
+    }
+
+    #[test]
+    fn old_buckets_are_dropped_and_saved_ones_restored() {
+        let ledger = UsageLedger::new(UsageConfig { retention_days: 1, ..UsageConfig::default() });
+        ledger.record(at("2026-03-01T09:10:00Z"), "scout", "gpt", UsageSource::Backend, 10, 5);
+        ledger.record(at("2026-03-02T09:10:00Z"), "scout", "gpt", UsageSource::Backend, 10, 5);
+        assert_eq!(ledger.buckets().len(), 2);
+        ledger.record(at("2026-03-02T10:10:00Z"), "scout", "gpt", UsageSource::Backend, 10, 5);
+        let saved = ledger.buckets();
+        assert_eq!(saved.len(), 2);
+
+        let restored = UsageLedger::default();
+        restored.restore(saved.clone());
+        assert_eq!(restored.buckets(), saved);
+        assert_eq!(restored.query(&UsageParams::default(), |_| true).currency, None);
+    }
+}
@@ -0,0 +1,377 @@
+//! Keeps a shared RAGEngine in sync with a directory on disk.
+//!
+//! File system events are debounced per path and applied on a background task
+//! that takes the engine's write lock only while indexing or deleting.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tokio::sync::{mpsc, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::rag_engine::{file_document_id, RAGEngine};
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Quiet period a path must see before its events are applied
+    pub debounce: Duration,
+    pub recursive: bool,
+    /// Lower-case extensions to index; empty means every file
+    pub extensions: Vec<String>,
+    /// How often to check that the watched directory still exists
+    pub rescan_interval: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(500),
+            recursive: true,
+            extensions: vec!["txt".to_string(), "md".to_string(), "html".to_string(), "htm".to_string()],
+            rescan_interval: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct WatchCounters {
+    events: AtomicU64,
+    indexed: AtomicU64,
+    unchanged: AtomicU64,
+    deleted: AtomicU64,
+    errors: AtomicU64,
+    rescans: AtomicU64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WatchStats {
+    pub events: u64,
+    pub indexed: u64,
+    pub unchanged: u64,
+    pub deleted: u64,
+    pub errors: u64,
+    pub rescans: u64,
+}
+
+/// Handle to a running watcher; dropping it stops the background task
+pub struct DirectoryWatcher {
+    counters: Arc<WatchCounters>,
+    task: JoinHandle<()>,
+}
+
+impl DirectoryWatcher {
+    pub fn stats(&self) -> WatchStats {
+        let c = &self.counters;
+        WatchStats {
+            events: c.events.load(Ordering::Relaxed),
+            indexed: c.indexed.load(Ordering::Relaxed),
+            unchanged: c.unchanged.load(Ordering::Relaxed),
+            deleted: c.deleted.load(Ordering::Relaxed),
+            errors: c.errors.load(Ordering::Relaxed),
+            rescans: c.rescans.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for DirectoryWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Starts watching `path`, indexing its current contents first. The engine slot
+/// is the same one the MCP server shares, so an empty slot simply defers work.
+pub fn watch_directory(
+    engine: Arc<RwLock<Option<RAGEngine>>>,
+    path: impl AsRef<Path>,
+    options: WatchOptions,
+) -> Result<DirectoryWatcher> {
+    let root = path.as_ref().to_path_buf();
+    let counters = Arc::new(WatchCounters::default());
+
+    let task = tokio::spawn(run(engine, root, options, Arc::clone(&counters)));
+    Ok(DirectoryWatcher { counters, task })
+}
+
+async fn run(
+    engine: Arc<RwLock<Option<RAGEngine>>>,
+    root: PathBuf,
+    options: WatchOptions,
+    counters: Arc<WatchCounters>,
+) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+    let mut watcher: Option<RecommendedWatcher> = None;
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut last_check = Instant::now() - options.rescan_interval;
+    let tick = options.debounce.min(Duration::from_millis(100));
+
+    loop {
+        // (Re)attach when the directory exists; detach when it disappears
+        if last_check.elapsed() >= options.rescan_interval || (watcher.is_none() && root.is_dir()) {
+            last_check = Instant::now();
+            match (root.is_dir(), watcher.is_some()) {
+                (true, false) => match start_notify(&root, &options, tx.clone()) {
+                    Ok(w) => {
+                        watcher = Some(w);
+                        counters.rescans.fetch_add(1, Ordering::Relaxed);
+                        tracing::info!("Watching {} for changes", root.display());
+                        for file in scan(&root, options.recursive) {
+                            pending.insert(file, Instant::now() - options.debounce);
+                        }
+                        pending.extend(known_files(&engine, &root).await.into_iter()
+                            .map(|file| (file, Instant::now() - options.debounce)));
+                    }
+                    Err(e) => {
+                        counters.errors.fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!("Failed to watch {}: {}", root.display(), e);
+                    }
+                },
+                (false, true) => {
+                    watcher = None;
+                    tracing::warn!("Watched directory {} disappeared, waiting for it to return", root.display());
+                }
+                _ => {}
+            }
+        }
+
+        tokio::select! {
+            Some(path) = rx.recv() => {
+                counters.events.fetch_add(1, Ordering::Relaxed);
+                if path == root {
+                    // The directory itself was removed or replaced; the old watch may be
+                    // dead, so reattach (and rescan) as soon as it exists again
+                    watcher = None;
+                } else {
+                    pending.insert(path, Instant::now());
+                }
+            }
+            _ = tokio::time::sleep(tick) => {}
+        }
+
+        let ready: Vec<PathBuf> = pending.iter()
+            .filter(|(_, seen)| seen.elapsed() >= options.debounce)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in ready {
+            pending.remove(&path);
+            if wanted(&path, &options) {
+                apply(&engine, &path, &counters).await;
+            }
+        }
+    }
+}
+
+fn start_notify(
+    root: &Path,
+    options: &WatchOptions,
+    tx: mpsc::UnboundedSender<PathBuf>,
+) -> notify::Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            Ok(event) => {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+            Err(e) => tracing::warn!("File watch error: {}", e),
+        }
+    })?;
+
+    let mode = if options.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    watcher.watch(root, mode)?;
+    Ok(watcher)
+}
+
+async fn apply(engine: &RwLock<Option<RAGEngine>>, path: &Path, counters: &WatchCounters) {
+    if path.is_file() {
+        // Read before taking the write lock so slow disks don't block queries
+        let document = match RAGEngine::load_file(path).await {
+            Ok(document) => document,
+            Err(e) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Failed to read {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let mut guard = engine.write().await;
+        let Some(rag) = guard.as_mut() else {
+            return;
+        };
+        match rag.index_document_if_changed(document).await {
+            Ok(true) => {
+                counters.indexed.fetch_add(1, Ordering::Relaxed);
+                tracing::info!("Indexed {}", path.display());
+            }
+            Ok(false) => {
+                counters.unchanged.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Failed to index {}: {}", path.display(), e);
+            }
+        }
+    } else if !path.exists() {
+        let mut guard = engine.write().await;
+        let Some(rag) = guard.as_mut() else {
+            return;
+        };
+        match rag.delete_document(&file_document_id(path)).await {
+            Ok(true) => {
+                counters.deleted.fetch_add(1, Ordering::Relaxed);
+                tracing::info!("Removed {} from the index", path.display());
+            }
+            Ok(false) => {}
+            Err(e) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn wanted(path: &Path, options: &WatchOptions) -> bool {
+    if options.extensions.is_empty() {
+        return true;
+    }
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| options.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+}
+
+fn scan(root: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if recursive {
+                    dirs.push(path);
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// Files under `root` that are indexed, so deletions made while detached are noticed
+async fn known_files(engine: &RwLock<Option<RAGEngine>>, root: &Path) -> Vec<PathBuf> {
+    let guard = engine.read().await;
+    let Some(rag) = guard.as_ref() else {
+        return Vec::new();
+    };
+    rag.query_metadata("path", &format!("{}*", root.display()))
+        .await
+        .map(|docs| docs.into_iter()
+            .filter_map(|doc| doc.metadata.get("path").map(PathBuf::from))
+            .filter(|path| path.starts_with(root) && !path.exists())
+            .collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rag_engine::QueryOptions;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("void-shrine-watch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn hits(engine: &RwLock<Option<RAGEngine>>, query: &str) -> usize {
+        let guard = engine.read().await;
+        guard.as_ref().unwrap().search(query, 10, &QueryOptions::default()).await.unwrap().len()
+    }
+
+    /// Polls until `check` holds, failing after a few seconds
+    async fn eventually<F, Fut>(what: &str, mut check: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        for _ in 0..100 {
+            if check().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("condition not reached: {}", what);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn follows_created_modified_and_deleted_files() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("existing.md"), "Stigmergy coordinates the swarm.").unwrap();
+
+        let engine = Arc::new(RwLock::new(Some(RAGEngine::new().await.unwrap())));
+        let options = WatchOptions {
+            debounce: Duration::from_millis(50),
+            rescan_interval: Duration::from_millis(100),
+            ..WatchOptions::default()
+        };
+        let watcher = watch_directory(Arc::clone(&engine), &dir, options).unwrap();
+
+        eventually("stigmergy == 1", || async { hits(&engine, "stigmergy").await == 1 }).await;
+
+        std::fs::write(dir.join("new.txt"), "Entropy harvesting notes.").unwrap();
+        std::fs::write(dir.join("ignored.bin"), "entropy").unwrap();
+        eventually("entropy == 1", || async { hits(&engine, "entropy").await == 1 }).await;
+
+        std::fs::write(dir.join("new.txt"), "Chaos engineering notes.").unwrap();
+        eventually("chaos == 1", || async { hits(&engine, "chaos").await == 1 }).await;
+        assert_eq!(hits(&engine, "entropy").await, 0);
+
+        std::fs::remove_file(dir.join("existing.md")).unwrap();
+        eventually("stigmergy == 0", || async { hits(&engine, "stigmergy").await == 0 }).await;
+
+        let stats = watcher.stats();
+        assert!(stats.indexed >= 3);
+        assert!(stats.deleted >= 1);
+        watcher.stop();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn survives_directory_disappearing() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("a.md"), "Void shrine archive.").unwrap();
+
+        let engine = Arc::new(RwLock::new(Some(RAGEngine::new().await.unwrap())));
+        let options = WatchOptions {
+            debounce: Duration::from_millis(50),
+            rescan_interval: Duration::from_millis(100),
+            ..WatchOptions::default()
+        };
+        let watcher = watch_directory(Arc::clone(&engine), &dir, options).unwrap();
+        eventually("archive == 1", || async { hits(&engine, "archive").await == 1 }).await;
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        eventually("archive == 0", || async { hits(&engine, "archive").await == 0 }).await;
+
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("b.md"), "Returning pilgrims.").unwrap();
+        eventually("pilgrims == 1", || async { hits(&engine, "pilgrims").await == 1 }).await;
+        assert!(watcher.stats().rescans >= 2);
+
+        watcher.stop();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
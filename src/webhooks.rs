@@ -0,0 +1,231 @@
+//! Outgoing webhooks. Scaling advice that changes capacity, agents starting
+//! or stopping being throttled, agents going stale, and quotas nearing their
+//! caps are POSTed as JSON to every configured URL, signed with HMAC-SHA256
+//! of the body under a shared secret.
+//! Delivery runs in the background with exponential backoff, so it never holds
+//! up or fails the request behind the event; events that exhaust their
+//! attempts are counted in `void_shrine_webhook_dead_letters_total`.
+
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use crate::metrics::Metrics;
+
+/// `sha256=` followed by the hex HMAC of the body
+pub const SIGNATURE_HEADER: &str = "X-Void-Shrine-Signature";
+/// The event type, also in the body
+pub const EVENT_HEADER: &str = "X-Void-Shrine-Event";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Every event goes to each of these; none disables webhooks
+    pub urls: Vec<String>,
+    /// Key for the signature header; required with any URL
+    pub secret: Option<String>,
+    /// Tries per URL, the first included
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubling for each one after
+    pub initial_backoff_ms: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self { urls: Vec::new(), secret: None, max_attempts: 4, initial_backoff_ms: 500, timeout_secs: 10 }
+    }
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.urls.is_empty() && self.secret.as_deref().is_none_or(str::is_empty) {
+            problems.push("webhooks.secret must be set when webhooks.urls is".to_string());
+        }
+        for url in &self.urls {
+            if !reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+                problems.push(format!("webhooks.urls has '{}', which is not an http(s) URL", url));
+            }
+        }
+        if self.max_attempts == 0 {
+            problems.push("webhooks.max_attempts must be positive".to_string());
+        }
+        problems
+    }
+
+    /// Wait after failed attempt `attempt`, counting from 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        backoff(self.initial_backoff_ms, attempt)
+    }
+}
+
+/// `initial_ms` after the first failed attempt, doubling for each one after
+pub fn backoff(initial_ms: u64, attempt: u32) -> Duration {
+    Duration::from_millis(initial_ms.saturating_mul(1 << attempt.saturating_sub(1).min(16)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    /// Scaling advice with a nonzero `capacity_change`
+    ScalingDecision,
+    /// An agent's requests started being delayed or refused
+    ThrottleStarted,
+    /// An agent's requests are admitted without throttling again
+    ThrottleEnded,
+    /// An agent's backend usage crossed `QuotaConfig::warn_percent` of a quota
+    QuotaWarning,
+    /// An agent stopped sending heartbeats or requests; see `agents`
+    AgentStale,
+}
+
+impl WebhookEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEventKind::ScalingDecision => "scaling_decision",
+            WebhookEventKind::ThrottleStarted => "throttle_started",
+            WebhookEventKind::ThrottleEnded => "throttle_ended",
+            WebhookEventKind::QuotaWarning => "quota_warning",
+            WebhookEventKind::AgentStale => "agent_stale",
+        }
+    }
+}
+
+/// The body of each webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub event: WebhookEventKind,
+    pub agent_id: String,
+    pub payload: serde_json::Value,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// The signature header value for `body`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()), body);
+    let hex: String = tag.as_ref().iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("sha256={hex}")
+}
+
+pub struct Webhooks {
+    config: WebhookConfig,
+    client: reqwest::Client,
+    metrics: Arc<Metrics>,
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig, metrics: Arc<Metrics>) -> Self {
+        Self { config, client: reqwest::Client::new(), metrics }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.config.urls.is_empty()
+    }
+
+    /// Queues `payload` for every URL and returns at once
+    pub fn notify(&self, event: WebhookEventKind, agent_id: &str, payload: serde_json::Value) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("Dropping {} webhook for {}: no runtime to deliver it on", event.as_str(), agent_id);
+            return;
+        };
+        let body = WebhookEvent { event, agent_id: agent_id.to_string(), payload, timestamp: Utc::now() };
+        let body = match serde_json::to_vec(&body) {
+            Ok(body) => Arc::new(body),
+            Err(e) => {
+                tracing::error!("Failed to encode {} webhook: {}", event.as_str(), e);
+                return;
+            }
+        };
+        let signature = sign(self.config.secret.as_deref().unwrap_or_default(), &body);
+        for url in &self.config.urls {
+            let delivery = Delivery {
+                client: self.client.clone(),
+                config: self.config.clone(),
+                metrics: Arc::clone(&self.metrics),
+                url: url.clone(),
+                event,
+                body: Arc::clone(&body),
+                signature: signature.clone(),
+            };
+            runtime.spawn(delivery.run());
+        }
+    }
+}
+
+/// One event on its way to one URL
+struct Delivery {
+    client: reqwest::Client,
+    config: WebhookConfig,
+    metrics: Arc<Metrics>,
+    url: String,
+    event: WebhookEventKind,
+    body: Arc<Vec<u8>>,
+    signature: String,
+}
+
+impl Delivery {
+    async fn run(self) {
+        for attempt in 1..=self.config.max_attempts {
+            let failure = match self.attempt().await {
+                Ok(()) => return,
+                Err(failure) => failure,
+            };
+            if attempt == self.config.max_attempts {
+                tracing::error!(
+                    "Giving up on {} webhook to {} after {} attempts: {}",
+                    self.event.as_str(), self.url, attempt, failure
+                );
+                self.metrics.webhook_dead_letter(self.event.as_str());
+                return;
+            }
+            let backoff = self.config.backoff(attempt);
+            tracing::warn!("{} webhook to {} failed ({}), retrying in {} ms", self.event.as_str(), self.url, failure, backoff.as_millis());
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    async fn attempt(&self) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, self.event.as_str())
+            .header(SIGNATURE_HEADER, &self.signature)
+            .body(self.body.as_ref().clone())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("status {}", response.status()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_is_hmac_sha256_of_the_body() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let config = WebhookConfig { initial_backoff_ms: 100, ..WebhookConfig::default() };
+        let waits: Vec<u128> = (1..=4).map(|attempt| config.backoff(attempt).as_millis()).collect();
+        assert_eq!(waits, [100, 200, 400, 800]);
+    }
+}
@@ -0,0 +1,254 @@
+//! Persistent MCP connections over WebSocket. Each text frame carries one JSON
+//! request, or several separated by newlines, in the `/api/mcp` shape plus a
+//! `request_id`. Requests run concurrently and replies carry the same
+//! `request_id`, so they may arrive out of order. An `llm_inference` request
+//! with `"stream": true` is answered with inference events as they happen.
+//! `{"cancel": "<request_id>"}` cancels a running request, which then fails
+//! with its own `cancelled` error reply.
+
+use std::sync::Arc;
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::Filter;
+use crate::auth::Tenancy;
+use crate::mcp_server::{validate_request_id, FieldError, InferenceEvent, MCPError, MCPRequest, MCPResponse, VoidShrineMCP};
+
+/// Frames larger than this close the connection
+pub const MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+
+/// How often the server pings; a connection silent for two intervals is dropped
+pub const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Replies queued for a slow client before requests wait for it
+const OUTGOING_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsRequest {
+    pub request_id: String,
+    #[serde(flatten)]
+    pub request: MCPRequest,
+    /// Send `llm_inference` progress as events; ignored for other methods
+    #[serde(default)]
+    pub stream: bool,
+}
+
+/// Cancels the running request with this id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WsCancel {
+    pub cancel: String,
+}
+
+/// Exactly one of `response`, `error`, `event` and `cancelled` is set. A streamed request
+/// gets `event` replies ending with a `done` or `error` event. `request_id` is
+/// None only when a malformed message did not carry one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsReply {
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<MCPResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The `error`'s code, as HTTP error bodies carry it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// When a rate-limited, throttled or shed request may be retried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<InferenceEvent>,
+    /// Each invalid param when `error` is a validation failure
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    /// Acknowledges a `WsCancel` for `request_id`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+}
+
+impl WsReply {
+    /// A reply to `request_id` with nothing set yet
+    fn reply(request_id: Option<String>) -> Self {
+        Self {
+            request_id,
+            response: None,
+            error: None,
+            code: None,
+            retry_after_ms: None,
+            event: None,
+            fields: Vec::new(),
+            cancelled: false,
+        }
+    }
+
+    /// A message that isn't a request, answered as `invalid_params`
+    fn malformed(request_id: Option<String>, error: String) -> Self {
+        Self { error: Some(error), code: Some("invalid_params".to_string()), ..Self::reply(request_id) }
+    }
+
+    fn failure(request_id: String, error: &MCPError) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            code: Some(error.code().to_string()),
+            retry_after_ms: error.retry_after().map(|wait| wait.as_millis() as u64),
+            fields: error.fields().to_vec(),
+            ..Self::reply(Some(request_id))
+        }
+    }
+}
+
+/// `GET /ws/mcp` upgraded to a WebSocket. The connection keeps the tenancy
+/// of the key it was opened with.
+pub fn route(
+    service: Arc<VoidShrineMCP>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("ws")
+        .and(warp::path("mcp"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(service.auth.tenancy_filter())
+        .and(warp::any().map(move || Arc::clone(&service)))
+        .map(|ws: Ws, tenancy: Tenancy, service: Arc<VoidShrineMCP>| {
+            ws.max_message_size(MAX_MESSAGE_BYTES)
+                .max_frame_size(MAX_MESSAGE_BYTES)
+                .on_upgrade(move |socket| serve_connection(socket, service, tenancy))
+        })
+}
+
+async fn serve_connection(socket: WebSocket, service: Arc<VoidShrineMCP>, tenancy: Tenancy) {
+    let (mut sink, mut stream) = socket.split();
+    let (outgoing, mut queued) = mpsc::channel::<Message>(OUTGOING_BUFFER);
+
+    let writer = tokio::spawn(async move {
+        while let Some(message) = queued.recv().await {
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+        let _ = sink.close().await;
+    });
+
+    // Requests still running when the connection ends are aborted with the set
+    let mut in_flight = JoinSet::new();
+    let mut ping = tokio::time::interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    let mut last_seen = Instant::now();
+
+    loop {
+        tokio::select! {
+            message = stream.next() => {
+                let message = match message {
+                    Some(Ok(message)) => message,
+                    Some(Err(e)) => {
+                        tracing::debug!("WebSocket connection failed: {}", e);
+                        break;
+                    }
+                    None => break,
+                };
+                last_seen = Instant::now();
+
+                if message.is_close() {
+                    break;
+                }
+                let Ok(text) = message.to_str() else {
+                    continue; // pings, pongs and binary frames carry no requests
+                };
+                for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                    if let Ok(WsCancel { cancel }) = serde_json::from_str(line) {
+                        let reply = match service.handle_cancel(&cancel) {
+                            Ok(()) => WsReply { cancelled: true, ..WsReply::reply(Some(cancel)) },
+                            Err(e) => WsReply::failure(cancel, &e),
+                        };
+                        send_reply(&outgoing, &reply).await;
+                        continue;
+                    }
+                    match parse_request(line) {
+                        Ok(request) => {
+                            let service = Arc::clone(&service);
+                            let tenancy = tenancy.clone();
+                            let outgoing = outgoing.clone();
+                            in_flight.spawn(async move { handle_request(&service, &tenancy, request, &outgoing).await });
+                        }
+                        Err((request_id, error)) => {
+                            send_reply(&outgoing, &WsReply::malformed(request_id, error)).await;
+                        }
+                    }
+                }
+            }
+            _ = ping.tick() => {
+                if last_seen.elapsed() > PING_INTERVAL * 2 {
+                    tracing::debug!("WebSocket peer stopped answering pings");
+                    break;
+                }
+                // A ping stuck behind a full queue would not tell us anything new
+                let _ = outgoing.try_send(Message::ping(Vec::new()));
+            }
+            // Reap finished requests so the set only holds running ones
+            Some(_) = in_flight.join_next(), if !in_flight.is_empty() => {}
+        }
+    }
+
+    if !in_flight.is_empty() {
+        tracing::info!("WebSocket closed with {} requests in flight, cancelling them", in_flight.len());
+    }
+    in_flight.shutdown().await;
+    drop(outgoing);
+    let _ = writer.await;
+}
+
+/// On failure, the error and whatever `request_id` the message carried
+fn parse_request(line: &str) -> Result<WsRequest, (Option<String>, String)> {
+    let value: Value = serde_json::from_str(line).map_err(|e| (None, format!("Invalid JSON: {}", e)))?;
+    let request_id = value.get("request_id").and_then(Value::as_str).map(str::to_string);
+    serde_json::from_value(value).map_err(|e| (request_id, format!("Invalid request: {}", e)))
+}
+
+async fn handle_request(service: &Arc<VoidShrineMCP>, tenancy: &Tenancy, mut request: WsRequest, outgoing: &mpsc::Sender<Message>) {
+    // The message's id doubles as the request's own when it is a valid one
+    if validate_request_id(&request.request_id).is_ok() {
+        request.request.request_id = Some(request.request_id.clone());
+    }
+    if let Err(e) = service.admit_agent(tenancy, &request.request.params.agent_id) {
+        send_reply(outgoing, &WsReply::failure(request.request_id, &e)).await;
+        return;
+    }
+    if request.stream && request.request.method == "llm_inference" {
+        // The stream tracks its own in-flight slot and counts a cancellation if
+        // the connection goes away first
+        let mut events = match service.stream_llm_inference(request.request.params, request.request.request_id) {
+            Ok(events) => events,
+            Err(e) => {
+                send_reply(outgoing, &WsReply::failure(request.request_id, &e)).await;
+                return;
+            }
+        };
+        while let Some(event) = events.next().await {
+            let reply = WsReply { event: Some(event), ..WsReply::reply(Some(request.request_id.clone())) };
+            if !send_reply(outgoing, &reply).await {
+                break;
+            }
+        }
+        return;
+    }
+
+    let reply = match service.handle_mcp_request(request.request).await {
+        Ok(response) => WsReply { response: Some(response), ..WsReply::reply(Some(request.request_id)) },
+        Err(failure) => WsReply::failure(request.request_id, &failure.error),
+    };
+    send_reply(outgoing, &reply).await;
+}
+
+/// Waits for room in the outgoing queue; false once the connection is gone
+async fn send_reply(outgoing: &mpsc::Sender<Message>, reply: &WsReply) -> bool {
+    match serde_json::to_string(reply) {
+        Ok(text) => outgoing.send(Message::text(text)).await.is_ok(),
+        Err(e) => {
+            tracing::error!("Failed to serialize WebSocket reply: {}", e);
+            true
+        }
+    }
+}
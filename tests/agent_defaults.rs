@@ -0,0 +1,100 @@
+//! Per-agent defaults from a registration or `[agents.defaults.<agent_id>]`:
+//! filling only what the request leaves out, and listed with their sources
+//! in a dry run.
+
+use serde_json::{json, Value};
+use void_shrine_mcp::agents::{AgentDefaults, AgentSpec};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::{MCPError, MCPRequest};
+use void_shrine_mcp::VoidShrineMCP;
+
+fn dry_run(params: Value) -> MCPRequest {
+    let mut all = json!({ "prompt": "Chart the quiet stars", "use_rag": false, "dry_run": true });
+    all.as_object_mut().unwrap().extend(params.as_object().unwrap().clone());
+    MCPRequest { method: "llm_inference".to_string(), params: serde_json::from_value(all).unwrap(), request_id: None, idempotency_key: None }
+}
+
+async fn effective(service: &VoidShrineMCP, params: Value) -> Value {
+    let response = service.handle_mcp_request(dry_run(params)).await.unwrap();
+    serde_json::to_value(response.result.prompt_preview.unwrap().params).unwrap()
+}
+
+fn configured() -> Config {
+    let mut config = Config::default();
+    let defaults = AgentDefaults { specialty: Some("science".to_string()), temperature: Some(0.2), max_tokens: Some(256), ..AgentDefaults::default() };
+    config.agents.defaults.insert("scout".to_string(), defaults);
+    config
+}
+
+fn spec(agent_id: &str, defaults: AgentDefaults) -> AgentSpec {
+    AgentSpec {
+        agent_id: Some(agent_id.to_string()),
+        specialty: "tactical".to_string(),
+        default_model: Some("llama3.2".to_string()),
+        max_concurrency: None,
+        tags: Vec::new(),
+        description: None,
+        defaults,
+    }
+}
+
+#[tokio::test]
+async fn configured_defaults_fill_what_the_request_leaves_out() {
+    let service = VoidShrineMCP::new(&configured()).unwrap();
+    service.chaos_config.write().await.enabled = false;
+
+    let params = effective(&service, json!({ "agent_id": "scout", "max_tokens": 1024 })).await;
+    assert_eq!(params["specialty"], json!({ "value": "science", "source": "agent_default" }));
+    assert_eq!(params["temperature"], json!({ "value": 0.2, "source": "agent_default" }));
+    // Given as the server's own default, it still wins
+    assert_eq!(params["max_tokens"], json!({ "value": 1024, "source": "request" }));
+    assert_eq!(params["context_window"], json!({ "value": 4096, "source": "server_default" }));
+    assert_eq!(params["template"], json!({ "value": null, "source": "server_default" }));
+
+    let other = effective(&service, json!({ "agent_id": "courier" })).await;
+    assert_eq!(other["temperature"], json!({ "value": 0.7, "source": "server_default" }));
+    assert_eq!(other["specialty"]["source"], json!("server_default"));
+}
+
+#[tokio::test]
+async fn a_registration_s_defaults_come_before_the_configured_ones() {
+    let service = VoidShrineMCP::new(&configured()).unwrap();
+    service.chaos_config.write().await.enabled = false;
+    let defaults = AgentDefaults { temperature: Some(1.1), use_rag: Some(true), ..AgentDefaults::default() };
+    service.handle_register_agent(&Tenancy::All, spec("scout", defaults)).unwrap();
+
+    let params = effective(&service, json!({ "agent_id": "scout" })).await;
+    assert_eq!(params["model"], json!({ "value": "llama3.2", "source": "agent_default" }));
+    assert_eq!(params["specialty"], json!({ "value": "tactical", "source": "agent_default" }));
+    assert_eq!(params["temperature"], json!({ "value": 1.1, "source": "agent_default" }));
+    assert_eq!(params["max_tokens"], json!({ "value": 256, "source": "agent_default" }));
+    // The request's own `use_rag: false` beats the registration's
+    assert_eq!(params["use_rag"], json!({ "value": false, "source": "request" }));
+}
+
+#[tokio::test]
+async fn defaults_are_checked_where_they_are_set() {
+    let service = VoidShrineMCP::default();
+    let fields = |error: MCPError| error.fields().iter().map(|field| field.field.clone()).collect::<Vec<_>>();
+    let bad = AgentDefaults {
+        model: Some("llama3.2".to_string()),
+        temperature: Some(3.0),
+        template: Some("sonnet".to_string()),
+        ..AgentDefaults::default()
+    };
+    let error = service.handle_register_agent(&Tenancy::All, spec("scout", bad)).unwrap_err();
+    assert_eq!(fields(error), ["defaults.model", "defaults.temperature", "defaults.template"]);
+
+    let mut config = Config::default();
+    config.agents.defaults.insert("scout".to_string(), AgentDefaults { max_tokens: Some(0), ..AgentDefaults::default() });
+    assert_eq!(config.agents.validate(), ["agents.defaults.scout.max_tokens must be positive (got 0)"]);
+
+    // Defaults the server's limits refuse are refused with the request
+    let mut config = Config::default();
+    config.agents.defaults.insert("scout".to_string(), AgentDefaults { max_tokens: Some(100_000), ..AgentDefaults::default() });
+    let service = VoidShrineMCP::new(&config).unwrap();
+    service.chaos_config.write().await.enabled = false;
+    let error = service.handle_mcp_request(dry_run(json!({ "agent_id": "scout" }))).await.unwrap_err().error;
+    assert_eq!(fields(error), ["max_tokens", "context_window"]);
+}
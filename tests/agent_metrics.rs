@@ -0,0 +1,61 @@
+//! `GET /api/agents/{id}/metrics`: lifetime totals, windowed counts with
+//! latency percentiles, the last scaling advice, and 404s and 400s for
+//! unknown agents and windows.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::mcp_server::ScalingRequest;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+async fn instance() -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default();
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+async fn get(service: &Arc<VoidShrineMCP>, path: &str) -> (u16, Value) {
+    let routes = api::mcp_route(Arc::clone(service)).or(api::agent_routes(Arc::clone(service))).recover(api::recover);
+    let response = warp::test::request().path(path).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn agents_report_their_windowed_activity() {
+    let service = instance().await;
+    let routes = api::mcp_route(Arc::clone(&service)).recover(api::recover);
+    for prompt in ["first watch", "second watch", "third watch"] {
+        let params = json!({
+            "agent_id": "lookout", "specialty": "general", "prompt": prompt, "max_tokens": 64,
+            "temperature": 0.2, "use_rag": false, "context_window": 4096
+        });
+        let body = json!({ "method": "llm_inference", "params": params });
+        assert_eq!(warp::test::request().method("POST").path("/api/mcp").json(&body).reply(&routes).await.status(), 200);
+    }
+    service.handle_scaling(ScalingRequest { agent_id: "lookout".to_string(), response_time: Some(30_000), token_count: None, success: false }).await;
+
+    let (status, metrics) = get(&service, "/api/agents/lookout/metrics?window=1h").await;
+    assert_eq!(status, 200);
+    assert_eq!((&metrics["total_requests"], &metrics["requests_since_boot"]), (&json!(3), &json!(3)));
+    assert!(metrics["prompt_tokens"].as_u64().unwrap() > 0 && metrics["completion_tokens"].as_u64().unwrap() > 0, "{}", metrics);
+    let recent = &metrics["recent"];
+    assert_eq!((&recent["window"], &recent["requests"], &recent["succeeded"], &recent["failed"]), (&json!("1h"), &json!(3), &json!(3), &json!(1)));
+    assert_eq!((&recent["latency"]["samples"], &recent["latency"]["p99_ms"]), (&json!(4), &json!(30_000)));
+    assert_eq!(recent["success_rate"], json!(0.75));
+    assert_eq!(metrics["last_scaling"]["direction"], json!("hold"));
+
+    // The default window is five minutes
+    assert_eq!(get(&service, "/api/agents/lookout/metrics").await.1["recent"]["window"], json!("5m"));
+}
+
+#[tokio::test]
+async fn unknown_agents_and_windows_are_refused() {
+    let service = instance().await;
+    let (status, error) = get(&service, "/api/agents/nobody/metrics").await;
+    assert_eq!((status, &error["error"]), (404, &json!("agent_not_found")));
+
+    service.handle_scaling(ScalingRequest { agent_id: "lookout".to_string(), response_time: Some(20), token_count: None, success: true }).await;
+    let (status, error) = get(&service, "/api/agents/lookout/metrics?window=1d").await;
+    assert_eq!((status, &error["error"]), (400, &json!("invalid_params")));
+}
@@ -0,0 +1,193 @@
+//! Agent registration: the `/api/agents` routes, requests filled in from the
+//! registration, strict specialties and declared concurrency enforced, and
+//! registered and seen agents listed with their load.
+
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use void_shrine_mcp::agents::{AgentDefaults, AgentRegistry, AgentSpec, AgentsConfig};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::llm_backend::{CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{AgentListParams, AgentSort, MCPParams, MCPRequest, MetricsParams};
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+/// Records the model and specialty of each request, holding answers until
+/// `gate` is notified when there is one
+#[derive(Default)]
+struct Recording {
+    seen: Mutex<Vec<(String, String)>>,
+    gate: Option<Arc<Notify>>,
+}
+
+impl LLMBackend for Recording {
+    fn name(&self) -> &str {
+        "recording"
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        self.seen.lock().unwrap().push((params.model.clone(), params.specialty.clone()));
+        Box::pin(async move {
+            if let Some(gate) = &self.gate {
+                gate.notified().await;
+            }
+            Ok(CompletionOutput { text: "noted".to_string(), prompt_tokens: 4, completion_tokens: 1, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+        })
+    }
+}
+
+/// An inference request leaving out whichever of model and specialty are None
+fn request(agent_id: &str, model: Option<&str>, specialty: Option<&str>) -> MCPRequest {
+    let mut params = json!({
+        "agent_id": agent_id, "prompt": "status report", "max_tokens": 64, "temperature": 0.2,
+        "use_rag": false, "context_window": 4096
+    });
+    if let Some(model) = model {
+        params["model"] = json!(model);
+    }
+    if let Some(specialty) = specialty {
+        params["specialty"] = json!(specialty);
+    }
+    MCPRequest { method: "llm_inference".to_string(), params: serde_json::from_value(params).unwrap(), request_id: None, idempotency_key: None }
+}
+
+fn spec(agent_id: &str, specialty: &str, max_concurrency: Option<u32>) -> AgentSpec {
+    AgentSpec {
+        agent_id: Some(agent_id.to_string()),
+        specialty: specialty.to_string(),
+        default_model: Some("llama3.2".to_string()),
+        max_concurrency,
+        tags: vec!["recon".to_string()],
+        description: Some("Maps the outer archive".to_string()),
+        defaults: AgentDefaults::default(),
+    }
+}
+
+async fn service(backend: Arc<Recording>, strict: bool) -> Arc<VoidShrineMCP> {
+    let agents = AgentRegistry::open(&AgentsConfig { strict, ..AgentsConfig::default() }).unwrap();
+    let service = VoidShrineMCP::default().with_backend(backend).with_agents(agents);
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+#[tokio::test]
+async fn agents_are_registered_updated_and_deregistered() {
+    let service = service(Arc::new(Recording::default()), false).await;
+    let routes = api::agent_routes(Arc::clone(&service)).recover(api::recover);
+    let call = |method: &str, path: &str, body: Value| {
+        warp::test::request().method(method).path(path).json(&body).reply(&routes)
+    };
+
+    let response = call("POST", "/api/agents", json!({ "agent_id": "scout", "specialty": "science", "tags": ["recon"] })).await;
+    assert_eq!(response.status(), 201);
+    let registered: Value = serde_json::from_slice(response.body()).unwrap();
+    let response = call("POST", "/api/agents", json!({ "agent_id": "scout", "specialty": "tactical" })).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((response.status().as_u16(), body["error"].as_str()), (409, Some("agent_exists")));
+    let response = call("POST", "/api/agents", json!({ "specialty": "", "max_concurrency": 0 })).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    let fields: Vec<&str> = body["fields"].as_array().unwrap().iter().map(|field| field["field"].as_str().unwrap()).collect();
+    assert_eq!((response.status().as_u16(), fields), (400, vec!["agent_id", "specialty", "max_concurrency"]));
+
+    let response = call("PUT", "/api/agents/scout", json!({ "specialty": "tactical", "default_model": "llama3.2" })).await;
+    let updated: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((updated["specialty"].as_str(), &updated["registered_at"]), (Some("tactical"), &registered["registered_at"]));
+    assert_eq!(call("PUT", "/api/agents/scout", json!({ "agent_id": "medic", "specialty": "tactical" })).await.status(), 400);
+    assert_eq!(call("PUT", "/api/agents/medic", json!({ "specialty": "medical" })).await.status(), 404);
+
+    service.handle_mcp_request(request("scout", None, None)).await.unwrap();
+    let response = call("GET", "/api/agents", json!({})).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((&body["agents"][0]["agent_id"], &body["agents"][0]["registration"]["specialty"]), (&json!("scout"), &json!("tactical")));
+    assert_eq!(call("DELETE", "/api/agents/scout", json!({})).await.status(), 200);
+    assert_eq!(call("DELETE", "/api/agents/scout", json!({})).await.status(), 404);
+
+    // Its history outlives the registration
+    let response = call("GET", "/api/agents/scout", json!({})).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((response.status().as_u16(), &body["total_requests"], body.get("registration")), (200, &json!(1), None));
+    assert_eq!(call("GET", "/api/agents/medic", json!({})).await.status(), 404);
+}
+
+#[tokio::test]
+async fn requests_are_filled_in_from_the_registration() {
+    let backend = Arc::new(Recording::default());
+    let service = service(Arc::clone(&backend), false).await;
+    service.handle_register_agent(&Tenancy::All, spec("scout", "science", None)).unwrap();
+
+    service.handle_mcp_request(request("scout", None, None)).await.unwrap();
+    service.handle_mcp_request(request("scout", Some("llama3.1"), Some("tactical"))).await.unwrap();
+    // Unregistered agents are served as they ask
+    service.handle_mcp_request(request("drifter", None, Some("creative"))).await.unwrap();
+
+    let seen = backend.seen.lock().unwrap().clone();
+    let expected = [("llama3.2", "science"), ("llama3.1", "tactical"), ("", "creative")];
+    assert_eq!(seen, expected.map(|(model, specialty)| (model.to_string(), specialty.to_string())));
+}
+
+#[tokio::test]
+async fn strict_mode_holds_agents_to_their_specialty_and_concurrency() {
+    let gate = Arc::new(Notify::new());
+    let backend = Arc::new(Recording { seen: Mutex::default(), gate: Some(Arc::clone(&gate)) });
+    let service = service(backend, true).await;
+    service.handle_register_agent(&Tenancy::All, spec("scout", "science", Some(1))).unwrap();
+
+    let failure = service.handle_mcp_request(request("scout", None, Some("tactical"))).await.unwrap_err();
+    assert_eq!((failure.error.code(), failure.error.http_status()), ("specialty_mismatch", 403));
+
+    let first = tokio::spawn({
+        let service = Arc::clone(&service);
+        async move { service.handle_mcp_request(request("scout", None, Some("science"))).await }
+    });
+    while service.handle_metrics(&Tenancy::All, &MetricsParams::default()).agents.iter().all(|agent| agent.in_flight == 0) {
+        tokio::task::yield_now().await;
+    }
+    let failure = service.handle_mcp_request(request("scout", None, None)).await.unwrap_err();
+    assert_eq!(failure.error.code(), "throttled");
+    gate.notify_one();
+    first.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn agents_are_listed_by_load_and_recency_with_stale_ones_marked() {
+    let gate = Arc::new(Notify::new());
+    let backend = Arc::new(Recording { seen: Mutex::default(), gate: Some(Arc::clone(&gate)) });
+    let service = service(backend, false).await;
+    service.handle_register_agent(&Tenancy::All, spec("scout", "science", Some(4))).unwrap();
+    service.handle_register_agent(&Tenancy::All, spec("idle", "medical", None)).unwrap();
+
+    // scout was last seen two hours ago; drifter is busy now
+    let scout = service.handle_mcp_request(request("scout", None, None));
+    gate.notify_one();
+    scout.await.unwrap();
+    service.agent_metrics.get_mut("scout").unwrap().last_request -= chrono::Duration::hours(2);
+    let drifter = tokio::spawn({
+        let service = Arc::clone(&service);
+        async move { service.handle_mcp_request(request("drifter", None, Some("creative"))).await }
+    });
+    while service.handle_metrics(&Tenancy::All, &MetricsParams::default()).agents.iter().all(|agent| agent.in_flight == 0) {
+        tokio::task::yield_now().await;
+    }
+
+    let listed = |sort, include_stale| {
+        let agents = service.handle_list_agents(&Tenancy::All, &AgentListParams { sort, include_stale }).agents;
+        agents.into_iter().map(|agent| (agent.agent_id, agent.stale)).collect::<Vec<_>>()
+    };
+    let expected = |agents: &[(&str, bool)]| agents.iter().map(|(id, stale)| (id.to_string(), *stale)).collect::<Vec<_>>();
+    assert_eq!(listed(AgentSort::AgentId, true), expected(&[("drifter", false), ("idle", false), ("scout", true)]));
+    assert_eq!(listed(AgentSort::Recency, true), expected(&[("drifter", false), ("scout", true), ("idle", false)]));
+    assert_eq!(listed(AgentSort::Load, false)[0].0, "drifter");
+    assert_eq!(listed(AgentSort::Load, false).len(), 2);
+
+    let detail = service.handle_get_agent(&Tenancy::All, "drifter").unwrap();
+    assert_eq!((detail.summary.in_flight, detail.latency.samples, detail.summary.current_load), (1, 0, 1.0 / 8.0));
+    assert!(!detail.throttle.should_throttle);
+    gate.notify_one();
+    drifter.await.unwrap().unwrap();
+    let detail = service.handle_get_agent(&Tenancy::All, "drifter").unwrap();
+    assert_eq!((detail.summary.in_flight, detail.summary.total_requests, detail.latency.samples), (0, 1, 1));
+    let idle = service.handle_get_agent(&Tenancy::All, "idle").unwrap();
+    assert_eq!((idle.summary.last_seen, idle.summary.registration.map(|r| r.specialty)), (None, Some("medical".to_string())));
+}
@@ -0,0 +1,177 @@
+//! Replays recorded Messages API responses to check `AnthropicBackend`'s translation.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::{json, Value};
+use void_shrine_mcp::llm_backend::{
+    AnthropicBackend, AnthropicConfig, BackendError, CompletionChunk, FinishReason, LLMBackend, Prompt,
+    ANTHROPIC_API_VERSION,
+};
+use void_shrine_mcp::mcp_server::MCPParams;
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// A response captured from the Messages API
+const RECORDED_MESSAGE: &str = r#"{
+  "id": "msg_01XFDUDYJgAACzvnptvVoYEL",
+  "type": "message",
+  "role": "assistant",
+  "model": "claude-3-5-haiku-20241022",
+  "content": [{"type": "text", "text": "Care ethics centres relationships and responsibility."}],
+  "stop_reason": "max_tokens",
+  "stop_sequence": null,
+  "usage": {"input_tokens": 31, "output_tokens": 9}
+}"#;
+
+const RECORDED_RATE_LIMIT: &str = r#"{
+  "type": "error",
+  "error": {"type": "rate_limit_error", "message": "Number of request tokens has exceeded your per-minute rate limit"}
+}"#;
+
+/// A streamed response captured from the Messages API, cut short by an overload error
+const RECORDED_STREAM_ERROR: &str = "event: message_start
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_02\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"claude-3-5-haiku-20241022\",\"stop_reason\":null,\"usage\":{\"input_tokens\":31,\"output_tokens\":1}}}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Care\"}}
+
+event: error
+data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}
+
+";
+
+/// A complete streamed response captured from the Messages API
+const RECORDED_STREAM: &str = "event: message_start
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_03\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"claude-3-5-haiku-20241022\",\"stop_reason\":null,\"usage\":{\"input_tokens\":31,\"output_tokens\":1}}}
+
+event: content_block_start
+data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}
+
+event: ping
+data: {\"type\": \"ping\"}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Care ethics \"}}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"centres relationships.\"}}
+
+event: content_block_stop
+data: {\"type\":\"content_block_stop\",\"index\":0}
+
+event: message_delta
+data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":6}}
+
+event: message_stop
+data: {\"type\":\"message_stop\"}
+
+";
+
+type Received = Arc<Mutex<Vec<(Value, Option<String>, Option<String>)>>>;
+
+/// Replies with `body` and optional retry-after, recording request bodies, api keys and versions
+async fn messages_api(status: StatusCode, body: &'static str, retry_after: Option<&'static str>) -> (String, Received) {
+    let received: Received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    let route = warp::path!("v1" / "messages")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and(warp::header::optional::<String>("anthropic-version"))
+        .and(warp::body::json())
+        .map(move |key: Option<String>, version: Option<String>, request: Value| {
+            log.lock().unwrap().push((request, key, version));
+            let mut response = warp::http::Response::builder().status(status).header("content-type", "application/json");
+            if let Some(seconds) = retry_after {
+                response = response.header("retry-after", seconds);
+            }
+            response.body(body).unwrap()
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}", addr), received)
+}
+
+fn params() -> MCPParams {
+    serde_json::from_value(json!({
+        "agent_id": "agent", "model": "void-shrine", "specialty": "science", "prompt": "ignored",
+        "max_tokens": 9, "temperature": 0.3, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap()
+}
+
+fn backend(base_url: String) -> AnthropicBackend {
+    let mut config = AnthropicConfig::new("sk-ant-test");
+    config.base_url = base_url;
+    config.timeout = Duration::from_secs(5);
+    AnthropicBackend::new(config).unwrap()
+}
+
+#[tokio::test]
+async fn translates_messages_request_and_response() {
+    let (base_url, received) = messages_api(StatusCode::OK, RECORDED_MESSAGE, None).await;
+    let prompt = Prompt::user("What is care ethics?").with_system("With mindful consideration of all stakeholders:");
+
+    let output = backend(base_url).complete(&prompt, &params()).await.unwrap();
+    assert_eq!(output.text, "Care ethics centres relationships and responsibility.");
+    assert_eq!((output.prompt_tokens, output.completion_tokens), (31, 9));
+    assert_eq!(output.finish_reason, FinishReason::Length);
+
+    let (request, key, version) = received.lock().unwrap()[0].clone();
+    assert_eq!(request, json!({
+        "model": "claude-3-5-haiku-latest",
+        "max_tokens": 9,
+        "temperature": 0.3,
+        "system": "With mindful consideration of all stakeholders:",
+        "messages": [{ "role": "user", "content": "What is care ethics?" }]
+    }));
+    assert_eq!(key.as_deref(), Some("sk-ant-test"));
+    assert_eq!(version.as_deref(), Some(ANTHROPIC_API_VERSION));
+}
+
+#[tokio::test]
+async fn rate_limits_carry_retry_after() {
+    let (base_url, _) = messages_api(StatusCode::TOO_MANY_REQUESTS, RECORDED_RATE_LIMIT, Some("17")).await;
+
+    let error = backend(base_url).complete(&Prompt::user("hi"), &params()).await.unwrap_err();
+    let error = error.downcast::<BackendError>().unwrap();
+    assert_eq!(error.retry_after(), Some(Duration::from_secs(17)));
+    assert_eq!((error.code(), error.http_status()), ("backend_rate_limited", 503));
+    assert!(error.to_string().contains("per-minute rate limit"), "{}", error);
+}
+
+#[tokio::test]
+async fn streams_recorded_events() {
+    let (base_url, received) = messages_api(StatusCode::OK, RECORDED_STREAM, None).await;
+    let backend = backend(base_url);
+    let prompt = Prompt::user("What is care ethics?");
+
+    let chunks: Vec<CompletionChunk> = backend.complete_stream(&prompt, &params()).map(Result::unwrap).collect().await;
+    assert!(matches!(&chunks[0], CompletionChunk::Delta(text) if text == "Care ethics "));
+    match chunks.last() {
+        Some(CompletionChunk::Done(output)) => {
+            assert_eq!(output.text, "Care ethics centres relationships.");
+            assert_eq!((output.prompt_tokens, output.completion_tokens), (31, 6));
+            assert_eq!(output.finish_reason, FinishReason::Stop);
+        }
+        other => panic!("expected the final chunk, got {:?}", other),
+    }
+    assert_eq!(received.lock().unwrap()[0].0["stream"], true);
+
+    let (base_url, _) = messages_api(StatusCode::OK, RECORDED_STREAM_ERROR, None).await;
+    let backend = crate::backend(base_url);
+    let results: Vec<_> = backend.complete_stream(&prompt, &params()).collect().await;
+    assert_eq!(results.len(), 2);
+    assert!(matches!(&results[0], Ok(CompletionChunk::Delta(text)) if text == "Care"));
+    let error = results[1].as_ref().unwrap_err().downcast_ref::<BackendError>().unwrap();
+    assert_eq!(*error, BackendError::Failed("Overloaded".to_string()));
+}
+
+#[test]
+fn temperatures_above_one_are_lowered_to_it() {
+    let backend = backend("http://unused".to_string());
+    let warm = MCPParams { temperature: 1.6, ..params() };
+    assert_eq!(backend.request_body(&Prompt::user("hi"), &warm)["temperature"], json!(1.0));
+    assert_eq!(backend.sampling(&warm).max_tokens, 9);
+}
@@ -0,0 +1,189 @@
+//! Each class of failure on `/api/mcp`, as a client sees it through `api::recover`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use void_shrine_mcp::api;
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::llm_backend::{BackendError, CompletionOutput, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{ErrorResponse, MCPParams, MCPRequest};
+use void_shrine_mcp::rate_limit::RateLimitConfig;
+use void_shrine_mcp::VoidShrineMCP;
+use warp::Filter;
+
+/// Fails every completion with a fixed error
+struct FailingBackend(BackendError);
+
+impl LLMBackend for FailingBackend {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        Box::pin(async move { Err(self.0.clone().into()) })
+    }
+}
+
+fn request(method: &str) -> Value {
+    json!({
+        "method": method,
+        "params": {
+            "agent_id": "api-agent", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+            "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096
+        }
+    })
+}
+
+async fn post(service: VoidShrineMCP, body: &str) -> (u16, Option<String>, ErrorResponse) {
+    service.chaos_config.write().await.enabled = false;
+    let routes = api::mcp_route(Arc::new(service)).recover(api::recover);
+    let response = warp::test::request()
+        .method("POST")
+        .path("/api/mcp")
+        .header("content-type", "application/json")
+        .body(body)
+        .reply(&routes)
+        .await;
+    let retry_after = response.headers().get("retry-after").map(|value| value.to_str().unwrap().to_string());
+    (response.status().as_u16(), retry_after, serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn client_mistakes_are_bad_requests() {
+    let (status, _, body) = post(VoidShrineMCP::default(), &request("summon").to_string()).await;
+    assert_eq!((status, body.error.as_str()), (400, "unsupported_method"));
+    assert_eq!(body.message, "Unsupported method: summon; supported methods are llm_inference, rag_query, rag_answer");
+    assert_eq!(body.method.as_deref(), Some("summon"));
+    assert_eq!(body.supported_methods, ["llm_inference", "rag_query", "rag_answer"]);
+    assert_eq!(body.did_you_mean, None);
+    assert!(body.request_id.is_some());
+
+    let (status, _, body) = post(VoidShrineMCP::default(), &request("llm_inferance").to_string()).await;
+    assert_eq!((status, body.error.as_str()), (400, "unsupported_method"));
+    assert_eq!(body.message, "Unsupported method: llm_inferance; did you mean llm_inference?");
+    assert_eq!(body.did_you_mean.as_deref(), Some("llm_inference"));
+
+    let (status, _, body) = post(VoidShrineMCP::default(), r#"{"method": "rag_query"}"#).await;
+    assert_eq!((status, body.error.as_str()), (400, "invalid_params"));
+    assert_eq!(body.request_id, None);
+
+    let (status, _, body) = post(VoidShrineMCP::default(), "{broken").await;
+    assert_eq!((status, body.error.as_str()), (400, "invalid_params"));
+}
+
+#[tokio::test]
+async fn invalid_params_list_every_field() {
+    let mut body = request("llm_inference");
+    body["params"]["temperature"] = json!(97.0);
+    body["params"]["max_tokens"] = json!(0);
+    body["params"]["agent_id"] = json!("");
+    let (status, _, response) = post(VoidShrineMCP::default(), &body.to_string()).await;
+    assert_eq!((status, response.error.as_str()), (400, "invalid_params"));
+    let fields: Vec<&str> = response.fields.iter().map(|error| error.field.as_str()).collect();
+    assert_eq!(fields, ["temperature", "max_tokens", "agent_id"]);
+    assert_eq!(response.fields[0].value, json!(97.0));
+
+    let mut body = request("llm_inference");
+    body["params"]["prompt"] = json!("x".repeat(1024 * 1024));
+    let (status, _, response) = post(VoidShrineMCP::default(), &body.to_string()).await;
+    assert_eq!(status, 400);
+    assert_eq!(response.fields[0].field, "prompt");
+    assert_eq!(response.fields[0].value, json!(1024 * 1024));
+}
+
+#[tokio::test]
+async fn missing_rag_engine_is_unavailable() {
+    let (status, _, body) = post(VoidShrineMCP::default(), &request("rag_answer").to_string()).await;
+    assert_eq!((status, body.error.as_str()), (503, "rag_unavailable"));
+    assert!(body.request_id.is_some());
+}
+
+#[tokio::test]
+async fn backend_failures_are_gateway_errors() {
+    let failing = |error: BackendError| VoidShrineMCP::default().with_backend(Arc::new(FailingBackend(error)));
+    let body = request("llm_inference").to_string();
+
+    let (status, _, response) = post(failing(BackendError::Status { status: 500, message: "boom".to_string() }), &body).await;
+    assert_eq!((status, response.error.as_str()), (502, "backend_error"));
+    assert!(response.message.contains("boom"), "{}", response.message);
+
+    let (status, _, response) = post(failing(BackendError::Timeout(Duration::from_secs(3))), &body).await;
+    assert_eq!((status, response.error.as_str()), (504, "backend_timeout"));
+    assert!(response.request_id.is_some());
+}
+
+#[tokio::test]
+async fn overloaded_backends_ask_clients_to_retry() {
+    let overloaded = BackendError::RateLimited { retry_after: Some(Duration::from_secs(7)), message: "slow down".to_string() };
+    let (status, retry_after, body) = post(
+        VoidShrineMCP::default().with_backend(Arc::new(FailingBackend(overloaded))),
+        &request("llm_inference").to_string(),
+    )
+    .await;
+    assert_eq!((status, body.error.as_str()), (503, "backend_rate_limited"));
+    assert_eq!(retry_after.as_deref(), Some("7"));
+}
+
+#[tokio::test]
+async fn agents_over_their_rate_limit_are_told_when_to_retry() {
+    let limits = RateLimitConfig { capacity: 1, refill_per_sec: 0.5, ..Default::default() };
+    let service = VoidShrineMCP::default().with_rate_limits(limits);
+    service.chaos_config.write().await.enabled = false;
+    let routes = api::mcp_route(Arc::new(service)).recover(api::recover);
+    let body = request("llm_inference");
+
+    let call = || warp::test::request().method("POST").path("/api/mcp").json(&body).reply(&routes);
+    assert_eq!(call().await.status(), 200);
+    let response = call().await;
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["retry-after"], "2");
+    let error: ErrorResponse = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(error.error, "rate_limited");
+    assert!(error.retry_after_ms.is_some_and(|ms| ms > 1000 && ms <= 2000), "{:?}", error.retry_after_ms);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_requests_never_exceed_the_bucket() {
+    let limits = RateLimitConfig { capacity: 10, refill_per_sec: 0.0, ..Default::default() };
+    let service = Arc::new(VoidShrineMCP::default().with_rate_limits(limits));
+    service.chaos_config.write().await.enabled = false;
+    let params: MCPParams = serde_json::from_value(request("llm_inference")["params"].clone()).unwrap();
+
+    let tasks: Vec<_> = (0..64)
+        .map(|_| {
+            let service = Arc::clone(&service);
+            let request = MCPRequest { method: "llm_inference".to_string(), params: params.clone(), request_id: None, idempotency_key: None };
+            tokio::spawn(async move { service.handle_mcp_request(request).await })
+        })
+        .collect();
+    let mut admitted = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(_) => admitted += 1,
+            Err(failure) => assert_eq!(failure.error.code(), "rate_limited"),
+        }
+    }
+    assert_eq!(admitted, 10);
+
+    let throttle = service.handle_throttle(&Tenancy::All, "api-agent".to_string()).await.unwrap();
+    assert_eq!((throttle.remaining_tokens, throttle.bucket_capacity), (0, 10));
+    assert!(throttle.should_throttle);
+    assert_eq!(service.handle_throttle(&Tenancy::All, "someone-else".to_string()).await.unwrap().remaining_tokens, 10);
+}
+
+#[tokio::test]
+async fn unknown_routes_and_methods_get_json_too() {
+    let routes = api::mcp_route(Arc::new(VoidShrineMCP::default())).recover(api::recover);
+
+    let response = warp::test::request().method("GET").path("/api/mcp").reply(&routes).await;
+    assert_eq!(response.status(), 405);
+    let body: ErrorResponse = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body.error, "method_not_allowed");
+
+    let response = warp::test::request().method("POST").path("/api/elsewhere").reply(&routes).await;
+    assert_eq!(response.status(), 404);
+    let body: ErrorResponse = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body.error, "not_found");
+}
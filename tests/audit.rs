@@ -0,0 +1,107 @@
+//! Requests recorded by the audit log and read back through `/api/audit`.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::audit::{AuditConfig, AuditLog, AuditResponse, AuditSink};
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::{api, RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+fn request(agent_id: &str, method: &str) -> MCPRequest {
+    serde_json::from_value(json!({
+        "method": method,
+        "params": {
+            "agent_id": agent_id, "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+            "max_tokens": 64, "temperature": 0.2, "use_rag": true, "context_window": 4096,
+            "ethical_framework": "care-ethics"
+        }
+    }))
+    .unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("void-shrine-audit-test-{}-{}", uuid::Uuid::new_v4(), name))
+}
+
+async fn audited_service(sink: AuditSink, path: &Path, redact_bodies: bool) -> Arc<VoidShrineMCP> {
+    let config = AuditConfig { sink, path: Some(path.to_path_buf()), redact_bodies, ..AuditConfig::default() };
+    let service = VoidShrineMCP::default().with_audit(AuditLog::open(&config).unwrap().unwrap());
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    Arc::new(service)
+}
+
+async fn get(service: Arc<VoidShrineMCP>, query: &str) -> (u16, Value) {
+    let routes = api::audit_route(service).recover(api::recover);
+    let response = warp::test::request().method("GET").path(&format!("/api/audit{query}")).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn requests_are_recorded_with_what_reached_the_backend() {
+    for (sink, path) in [(AuditSink::Sqlite, temp_path("audit.db")), (AuditSink::Jsonl, temp_path("audit.jsonl"))] {
+        let service = audited_service(sink, &path, false).await;
+        let answered = service.handle_mcp_request(request("auditor", "llm_inference")).await.unwrap();
+        let failure = service.handle_mcp_request(request("auditor", "summon")).await.unwrap_err();
+        service.handle_mcp_request(request("bystander", "llm_inference")).await.unwrap();
+        service.audit.as_ref().unwrap().flush().await;
+
+        let (status, body) = get(Arc::clone(&service), "?agent_id=auditor").await;
+        assert_eq!(status, 200, "{body}");
+        let records = serde_json::from_value::<AuditResponse>(body).unwrap().records;
+        assert_eq!(records.len(), 2, "{sink:?}");
+
+        // Newest first
+        let (failed, inference) = (&records[0], &records[1]);
+        assert_eq!(Some(&failed.request_id), failure.request_id.as_ref());
+        let error = failed.error.as_ref().unwrap();
+        assert_eq!((error.code.as_str(), error.status), ("unsupported_method", 400));
+
+        assert_eq!(inference.request_id, answered.metadata.request_id);
+        assert_eq!(inference.response.as_deref(), Some(answered.result.response.as_str()));
+        let prompt = inference.prompt.as_deref().unwrap();
+        assert!(prompt.starts_with("Context from knowledge base, between the <context> tags."), "{prompt}");
+        assert!(inference.system_prompt.is_some());
+        assert!(!inference.rag_document_ids.is_empty());
+        assert!(inference.moral_recentered);
+        assert!(!inference.ethical_adjustments.is_empty());
+        assert!(inference.metrics.is_some() && inference.error.is_none());
+
+        let (_, body) = get(Arc::clone(&service), "?limit=1").await;
+        assert_eq!(body["records"].as_array().unwrap().len(), 1);
+        assert_eq!(body["records"][0]["agent_id"], "bystander");
+        let (_, body) = get(service, "?since=2999-01-01T00:00:00Z").await;
+        assert_eq!(body["records"], json!([]));
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[tokio::test]
+async fn redaction_drops_bodies_but_not_metadata() {
+    let path = temp_path("redacted.jsonl");
+    let service = audited_service(AuditSink::Jsonl, &path, true).await;
+    service.handle_mcp_request(request("discreet", "llm_inference")).await.unwrap();
+    service.audit.as_ref().unwrap().flush().await;
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert!(!written.contains("Context from knowledge base"), "{written}");
+    let record: Value = serde_json::from_str(written.lines().next().unwrap()).unwrap();
+    assert_eq!(record["redacted"], true);
+    assert_eq!((&record["prompt"], &record["response"]), (&Value::Null, &Value::Null));
+    assert_eq!(record["agent_id"], "discreet");
+    assert!(!record["rag_document_ids"].as_array().unwrap().is_empty());
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn querying_without_a_sink_is_not_configured() {
+    let (status, body) = get(Arc::new(VoidShrineMCP::default()), "").await;
+    assert_eq!((status, body["error"].as_str()), (501, Some("not_configured")));
+
+    let (status, body) = get(Arc::new(VoidShrineMCP::default()), "?limit=many").await;
+    assert_eq!((status, body["error"].as_str()), (400, Some("invalid_params")));
+}
@@ -0,0 +1,136 @@
+//! POST /api/audit/replay: recorded requests sent through the pipeline as it
+//! is now and compared with what was recorded, kept out of production
+//! statistics and audited as replays.
+
+mod support;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use support::{epoch, fixture_rag, service, ScriptedBackend};
+use void_shrine_mcp::api;
+use void_shrine_mcp::audit::{AuditConfig, AuditLog, AuditQuery, AuditSink};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::mcp_server::{AgentMetricsParams, MCPRequest};
+use void_shrine_mcp::rate_limit::RateLimitConfig;
+use void_shrine_mcp::replay::{ReplayConfig, ReplayOutcome, ReplayResponse};
+use void_shrine_mcp::VoidShrineMCP;
+use warp::Filter;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("void-shrine-replay-test-{}-{}", uuid::Uuid::new_v4(), name))
+}
+
+async fn audited_service(backend: &Arc<ScriptedBackend>, path: &Path) -> Arc<VoidShrineMCP> {
+    // Kept however old the harness clock makes them
+    let config = AuditConfig { sink: AuditSink::Jsonl, path: Some(path.to_path_buf()), retention_days: 0, ..AuditConfig::default() };
+    // One request's worth of tokens, which replays must not need
+    let limits = RateLimitConfig { capacity: 1, refill_per_sec: 0.001, ..RateLimitConfig::default() };
+    let service = service(Arc::clone(backend), Arc::new(ManualClock::new(epoch())))
+        .with_audit(AuditLog::open(&config).unwrap().unwrap())
+        .with_rate_limits(limits);
+    *service.rag_engine.write().await = Some(fixture_rag().await);
+    Arc::new(service)
+}
+
+async fn ask(service: &VoidShrineMCP, agent_id: &str, method: &str) -> String {
+    let params = json!({ "agent_id": agent_id, "prompt": "Where do hermit crabs shelter?", "max_tokens": 64, "session_id": "shore" });
+    let request = MCPRequest { method: method.to_string(), params: serde_json::from_value(params).unwrap(), request_id: None, idempotency_key: None };
+    let response = service.handle_mcp_request(request).await.unwrap();
+    service.audit.as_ref().unwrap().flush().await;
+    response.metadata.request_id
+}
+
+async fn replay(service: Arc<VoidShrineMCP>, body: Value) -> (u16, Value) {
+    let routes = api::audit_replay_route(service).recover(api::recover);
+    let response = warp::test::request().method("POST").path("/api/audit/replay").json(&body).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn replays_are_compared_with_the_record() {
+    let path = temp_path("audit.jsonl");
+    let backend = Arc::new(ScriptedBackend::new().reply("In tide pools [1].").reply("Anemones and hermit crabs share the tide pools [1]."));
+    let service = audited_service(&backend, &path).await;
+    let inference = ask(&service, "scout", "llm_inference").await;
+    let requests_before = service.metrics.render();
+
+    let body = json!({ "request_ids": [inference, "never-sent"] });
+    let (status, body) = replay(Arc::clone(&service), body).await;
+    assert_eq!(status, 200, "{}", body);
+    let response: ReplayResponse = serde_json::from_value(body).unwrap();
+    let replayed = &response.results[0];
+    assert_eq!((replayed.outcome, replayed.reason.as_deref()), (ReplayOutcome::Replayed, None));
+    let comparison = replayed.comparison.as_ref().unwrap();
+    assert_eq!((comparison.response_chars.before, comparison.response_chars.after), (Some(18), Some(51)));
+    assert_eq!(comparison.documents.before, ["tide-pools"]);
+    assert_eq!(comparison.documents.overlap, 1.0);
+    assert!(!comparison.moral.changed);
+    assert_eq!(response.results[1].reason.as_deref(), Some("not in the audit log"));
+    assert_eq!((response.summary.replayed, response.summary.skipped, response.summary.mean_response_chars_change), (1, 1, Some(33.0)));
+
+    // The session is left as the recorded request left it
+    let prompts = backend.prompts();
+    assert!(!prompts[1].user.contains("In tide pools [1]."), "{}", prompts[1].user);
+    assert_eq!(service.sessions.history("shore", "scout").unwrap().len(), 1);
+
+    // Audited as a replay, counted apart from the agent's requests
+    let records = service.audit.as_ref().unwrap().query(AuditQuery::default()).await.unwrap();
+    let audited = records.iter().find(|record| record.replay_of.as_deref() == Some(inference.as_str())).unwrap();
+    assert_eq!(Some(&audited.request_id), replayed.replay_id.as_ref());
+    assert!(audited.params.as_ref().unwrap().session_id.is_none());
+    let metrics = service.handle_agent_metrics(&Tenancy::All, "scout", &AgentMetricsParams::default()).unwrap();
+    assert_eq!(metrics.total_requests, 1);
+    let rendered = service.metrics.render();
+    assert!(rendered.contains(r#"void_shrine_replays_total{mode="live",outcome="replayed"} 1"#), "{}", rendered);
+    let requests = |text: &str| text.lines().filter(|line| line.starts_with("void_shrine_requests_total")).collect::<Vec<_>>().join("\n");
+    assert_eq!(requests(&rendered), requests(&requests_before));
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn mock_and_dry_run_replays_leave_the_backend_alone() {
+    let path = temp_path("audit.jsonl");
+    let backend = Arc::new(ScriptedBackend::new().reply("In tide pools [1]."));
+    let service = audited_service(&backend, &path).await;
+    let inference = ask(&service, "scout", "llm_inference").await;
+
+    let (status, body) = replay(Arc::clone(&service), json!({ "request_ids": [inference], "mode": "mock" })).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["results"][0]["outcome"], "replayed", "{}", body);
+    assert!(body["results"][0]["comparison"]["response_chars"]["after"].as_u64().unwrap() > 0);
+
+    let (_, body) = replay(Arc::clone(&service), json!({ "request_ids": [inference], "mode": "dry_run" })).await;
+    let comparison = &body["results"][0]["comparison"];
+    assert_eq!((&comparison["response_chars"]["after"], &comparison["completion_tokens"]["after"]), (&Value::Null, &Value::Null));
+    assert!(comparison["prompt_tokens"]["after"].as_u64().unwrap() > 0);
+    assert_eq!(backend.prompts().len(), 1);
+
+    // Selected by filter, the replays just recorded are passed over
+    let (_, body) = replay(Arc::clone(&service), json!({ "agent_id": "scout", "mode": "mock" })).await;
+    let response: ReplayResponse = serde_json::from_value(body).unwrap();
+    assert_eq!((response.summary.selected, response.summary.replayed, response.summary.skipped), (3, 1, 2));
+    assert!(response.results.iter().filter_map(|result| result.reason.as_deref()).all(|reason| reason.starts_with("a replay of")));
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn refuses_without_a_sink_or_beyond_its_bounds() {
+    let (status, body) = replay(Arc::new(VoidShrineMCP::default()), json!({})).await;
+    assert_eq!(status, 501, "{}", body);
+
+    let path = temp_path("audit.jsonl");
+    let service = audited_service(&Arc::new(ScriptedBackend::new()), &path).await;
+    let service = Arc::new(
+        Arc::try_unwrap(service).ok().unwrap().with_replay(ReplayConfig { max_records: 2, ..ReplayConfig::default() }),
+    );
+    let (status, body) = replay(Arc::clone(&service), json!({ "request_ids": ["a", "b", "c"] })).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("request_ids")));
+    let (status, body) = replay(Arc::clone(&service), json!({ "limit": 0 })).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("limit")));
+    let (status, body) = replay(Arc::clone(&service), json!({ "mode": "expensive" })).await;
+    assert_eq!(status, 400, "{}", body);
+    let _ = std::fs::remove_file(path);
+}
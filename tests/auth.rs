@@ -0,0 +1,74 @@
+//! Bearer-token auth in front of the REST routes, as a client sees it.
+
+use std::sync::Arc;
+
+use serde_json::json;
+use void_shrine_mcp::api;
+use void_shrine_mcp::auth::{ApiKey, Auth};
+use void_shrine_mcp::mcp_server::ErrorResponse;
+use void_shrine_mcp::VoidShrineMCP;
+use warp::http::StatusCode;
+use warp::Filter;
+
+fn auth() -> Auth {
+    Auth::new(vec![
+        ApiKey::parse("agents:agent-secret:inference").unwrap(),
+        ApiKey::parse("ops:ops-secret:inference+admin").unwrap(),
+    ])
+    .unwrap()
+}
+
+async fn call(auth: &Auth, path: &str, token: Option<&str>) -> (StatusCode, Option<String>, Option<ErrorResponse>) {
+    let service = VoidShrineMCP::default();
+    service.chaos_config.write().await.enabled = false;
+    // Stands in for the admin-only chaos route
+    let chaos = warp::path("api").and(warp::path("chaos")).and(warp::post()).map(|| warp::reply::json(&json!({})));
+    let routes = auth.filter().and(api::mcp_route(Arc::new(service)).or(chaos)).recover(api::recover);
+
+    let body = json!({
+        "method": "llm_inference",
+        "params": {
+            "agent_id": "auth-agent", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+            "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096
+        }
+    });
+    let mut request = warp::test::request().method("POST").path(path).json(&body);
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    let response = request.reply(&routes).await;
+    let challenge = response.headers().get("www-authenticate").map(|value| value.to_str().unwrap().to_string());
+    let error = (!response.status().is_success()).then(|| serde_json::from_slice(response.body()).unwrap());
+    (response.status(), challenge, error)
+}
+
+#[tokio::test]
+async fn missing_and_unknown_keys_are_unauthorized() {
+    let auth = auth();
+    let (status, challenge, error) = call(&auth, "/api/mcp", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(challenge.as_deref(), Some("Bearer"));
+    assert_eq!(error.unwrap().error, "unauthorized");
+
+    let (status, _, _) = call(&auth, "/api/mcp", Some("agent-secreT")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn keys_are_limited_to_their_scopes() {
+    let auth = auth();
+    assert_eq!(call(&auth, "/api/mcp", Some("agent-secret")).await.0, StatusCode::OK);
+
+    let (status, _, error) = call(&auth, "/api/chaos", Some("agent-secret")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let error = error.unwrap();
+    assert_eq!(error.error, "forbidden");
+    assert!(!error.message.contains("agent-secret"), "{}", error.message);
+
+    assert_eq!(call(&auth, "/api/chaos", Some("ops-secret")).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn no_keys_means_no_auth() {
+    assert_eq!(call(&Auth::default(), "/api/chaos", None).await.0, StatusCode::OK);
+}
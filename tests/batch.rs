@@ -0,0 +1,123 @@
+//! `POST /api/mcp/batch`: items answered in input order with bounded
+//! concurrency, failing alone, and counted for their agents one by one.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::llm_backend::{CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{BatchConfig, BatchRequest, MCPParams, ThrottleConfig};
+use void_shrine_mcp::rate_limit::RateLimitConfig;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+/// Echoes the prompt after a pause, recording the most completions in flight at once
+#[derive(Default)]
+struct SlowEcho {
+    in_flight: AtomicUsize,
+    most_in_flight: AtomicUsize,
+}
+
+impl LLMBackend for SlowEcho {
+    fn name(&self) -> &str {
+        "slow-echo"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        Box::pin(async move {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_in_flight.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            let text = format!("echo: {}", prompt.user);
+            Ok(CompletionOutput { text, prompt_tokens: 4, completion_tokens: 4, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+        })
+    }
+}
+
+fn params(agent_id: &str, prompt: &str) -> Value {
+    json!({
+        "agent_id": agent_id, "model": "void-shrine", "specialty": "research", "prompt": prompt,
+        "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096
+    })
+}
+
+async fn service(backend: Arc<SlowEcho>) -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default()
+        .with_backend(backend)
+        .with_batch(BatchConfig { max_items: 10, concurrency: 3 })
+        .with_throttle(ThrottleConfig { enabled: false, ..ThrottleConfig::default() });
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+#[tokio::test]
+async fn items_come_back_in_order_and_fail_alone() {
+    let backend = Arc::new(SlowEcho::default());
+    let service = service(Arc::clone(&backend)).await;
+    let mut items: Vec<Value> = (0..8).map(|i| params("orchestrator", &format!("document {}", i))).collect();
+    items[5]["max_tokens"] = json!(0);
+    let request: BatchRequest = serde_json::from_value(json!({ "items": items })).unwrap();
+
+    let response = service.handle_batch(request).await.unwrap();
+    assert_eq!((response.summary.succeeded, response.summary.failed), (7, 1));
+    for (i, item) in response.items.iter().enumerate() {
+        assert_eq!(item.index, i);
+        if i == 5 {
+            assert_eq!(item.status, 400);
+            assert_eq!(item.error.as_ref().unwrap().fields[0].field, "max_tokens");
+        } else {
+            assert!(item.response.as_ref().unwrap().result.response.ends_with(&format!("document {}", i)));
+        }
+    }
+    assert_eq!(backend.most_in_flight.load(Ordering::SeqCst), 3);
+    // Seven completions of 20ms, three at a time
+    assert!(response.elapsed_ms >= 40, "{}", response.elapsed_ms);
+
+    let metrics = service.handle_metrics(&Tenancy::All, &Default::default());
+    // As alone, the refused item reaches the server counters but not the agent's
+    assert_eq!(metrics.agents[0].total_requests, 7);
+    assert_eq!(metrics.server.requests_by_method["llm_inference"], 8);
+}
+
+#[tokio::test]
+async fn every_item_spends_its_agents_rate_limit() {
+    let backend = Arc::new(SlowEcho::default());
+    let service = VoidShrineMCP::default()
+        .with_backend(backend)
+        .with_rate_limits(RateLimitConfig { capacity: 3, refill_per_sec: 0.001, ..RateLimitConfig::default() });
+    service.chaos_config.write().await.enabled = false;
+    let items: Vec<Value> = (0..5).map(|i| params("orchestrator", &format!("document {}", i))).collect();
+
+    let response = service.handle_batch(serde_json::from_value(json!({ "items": items })).unwrap()).await.unwrap();
+    assert_eq!((response.summary.succeeded, response.summary.failed), (3, 2));
+    let refused: Vec<&str> = response.items.iter().filter_map(|item| item.error.as_ref()).map(|error| error.error.as_str()).collect();
+    assert_eq!(refused, ["rate_limited", "rate_limited"]);
+}
+
+#[tokio::test]
+async fn empty_oversized_and_unsupported_batches_fail_whole() {
+    let service = service(Arc::default()).await;
+    let routes = api::batch_route(service).recover(api::recover);
+    let post = |body: Value| warp::test::request().method("POST").path("/api/mcp/batch").json(&body);
+
+    let too_many: Vec<Value> = (0..11).map(|i| params("orchestrator", &i.to_string())).collect();
+    for body in [json!({ "items": [] }), json!({ "items": too_many })] {
+        let response = post(body).reply(&routes).await;
+        let error: Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(response.status(), 400);
+        assert_eq!(error["fields"][0]["field"], "items");
+    }
+
+    let response = post(json!({ "method": "summon", "items": [params("orchestrator", "hi")] })).reply(&routes).await;
+    assert_eq!(response.status(), 400);
+
+    let response = post(json!({ "items": [params("orchestrator", "hi")] })).reply(&routes).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(body["items"][0]["response"]["result"]["response"], "echo: hi");
+    assert_eq!(body["summary"], json!({ "succeeded": 1, "failed": 0 }));
+}
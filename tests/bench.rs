@@ -0,0 +1,92 @@
+//! `void-shrine-bench`: a seeded workload that replays identically, reports
+//! per request type with nearest-rank percentiles, and, in process, the
+//! knowledge base retrievals timed on their own.
+
+use std::sync::Arc;
+
+use serde_json::Value;
+use tracing_subscriber::layer::SubscriberExt;
+use void_shrine_mcp::bench::{self, BenchConfig, LatencySummary, PromptSizes, RagTimings, RequestKind, RequestMix, SizeDistribution, Target};
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+
+#[test]
+fn the_seed_decides_the_workload() {
+    let config = BenchConfig { seed: 7, ..BenchConfig::default() };
+    let planned: Vec<_> = (0..50).map(|index| config.plan(index)).collect();
+    assert_eq!(planned, (0..50).map(|index| config.plan(index)).collect::<Vec<_>>());
+    assert_ne!(planned, (0..50).map(|index| BenchConfig::default().plan(index)).collect::<Vec<_>>());
+    for kind in RequestKind::ALL {
+        assert!(planned.iter().any(|request| request.kind == kind), "{:?} never planned", kind);
+    }
+
+    let mix: RequestMix = "rag_query=1, moral_recentering=0".parse().unwrap();
+    let sizes = PromptSizes::parse_range("4-400", SizeDistribution::Skewed).unwrap();
+    let config = BenchConfig { mix, prompt_sizes: sizes, ..config };
+    let words: Vec<usize> = (0..200).map(|index| config.plan(index).prompt.split(' ').count()).collect();
+    assert!((0..200).all(|index| config.plan(index).kind == RequestKind::RagQuery));
+    assert!(words.iter().all(|words| (4..=400).contains(words)));
+    assert!(words.iter().filter(|words| **words < 40).count() > 100, "skewed toward short prompts");
+
+    assert_eq!("astrology=1".parse::<RequestMix>().unwrap_err(), "unknown request type 'astrology'; known: inference, rag_inference, rag_query, moral_recentering");
+    assert!("inference=0".parse::<RequestMix>().is_err());
+    assert!(PromptSizes::parse_range("64-8", SizeDistribution::Uniform).is_err());
+}
+
+#[test]
+fn percentiles_are_nearest_rank() {
+    let samples: Vec<f64> = (1..=200).map(f64::from).collect();
+    let summary = LatencySummary::of(&samples);
+    assert_eq!((summary.count, summary.p50_ms, summary.p95_ms, summary.p99_ms, summary.max_ms), (200, 100.0, 190.0, 198.0, 200.0));
+    assert_eq!(summary.mean_ms, 100.5);
+    assert_eq!(LatencySummary::of(&[]), LatencySummary::default());
+}
+
+#[tokio::test]
+async fn an_in_process_run_reports_each_type_and_the_retrievals() {
+    let timings = RagTimings::default();
+    let _subscriber = tracing::subscriber::set_default(tracing_subscriber::registry().with(timings.clone()));
+    let service = VoidShrineMCP::default();
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+
+    let config = BenchConfig { concurrency: 4, duration: None, requests: Some(60), seed: 3, ..BenchConfig::default() };
+    let report = bench::run(Arc::new(Target::InProcess(Arc::new(service))), &config, Some(&timings)).await;
+    assert_eq!((report.target.as_str(), report.requests, report.errors), ("in-process", 60, 0), "{:?}", report.errors_by_code);
+    assert_eq!(report.kinds.values().map(|kind| kind.requests).sum::<u64>(), 60);
+    let planned = |kind: RequestKind| (0..60).filter(|index| config.plan(*index).kind == kind).count() as u64;
+    assert_eq!(report.kinds["rag_query"].requests, planned(RequestKind::RagQuery));
+    assert_eq!(report.kinds["inference"].latency.count, planned(RequestKind::Inference));
+    let retrievals = planned(RequestKind::RagQuery) + planned(RequestKind::RagInference);
+    assert_eq!(report.rag_retrieval.as_ref().map(|retrieval| retrieval.count), Some(retrievals));
+    assert!(report.throughput_rps > 0.0 && report.latency.p50_ms <= report.latency.p99_ms);
+
+    let text = report.to_string();
+    assert!(text.contains("60 requests in") && text.contains("rag retrieval"), "{}", text);
+    let json: bench::BenchReport = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+    assert_eq!(json, report);
+}
+
+#[tokio::test]
+async fn the_binary_prints_and_saves_json_and_refuses_bad_options() {
+    let path = std::env::temp_dir().join(format!("void-shrine-bench-{}.json", uuid::Uuid::new_v4()));
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_void-shrine-bench"))
+        .args(["--in-process", "--no-chaos", "--requests", "24", "--concurrency", "3", "--seed", "11", "--json", "--report"])
+        .arg(&path)
+        .env_remove("VOID_SHRINE_CONFIG")
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let printed: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!((printed["requests"].as_u64(), printed["seed"].as_u64()), (Some(24), Some(11)));
+    assert!(printed["rag_retrieval"]["count"].as_u64().unwrap() > 0);
+    let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved, printed);
+    std::fs::remove_file(path).ok();
+
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_void-shrine-bench")).args(["--mix", "inference"]).output().await.unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'inference' is not TYPE=WEIGHT"));
+}
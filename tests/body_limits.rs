@@ -0,0 +1,78 @@
+//! Request body limits per class of route: 413 `payload_too_large` naming the
+//! limit, whether the body announces its length or streams in chunks.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::BodyLimits;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+fn service() -> Arc<VoidShrineMCP> {
+    let body_limits = BodyLimits { inference_bytes: 4096, documents_bytes: 64 * 1024, admin_bytes: 1024 };
+    let limits = void_shrine_mcp::mcp_server::ParamLimits { max_prompt_bytes: 1024, ..Default::default() };
+    Arc::new(VoidShrineMCP::new(&Config { body_limits, limits, ..Config::default() }).unwrap())
+}
+
+#[tokio::test]
+async fn announced_bodies_over_their_route_limit_are_refused() {
+    let service = service();
+    let routes = api::mcp_route(Arc::clone(&service)).or(api::agent_routes(Arc::clone(&service))).or(api::document_routes(service)).recover(api::recover);
+    let post = |path: &'static str, body: Value| warp::test::request().method("POST").path(path).json(&body).reply(&routes);
+
+    let response = post("/api/mcp", json!({ "method": "llm_inference", "params": { "prompt": "x".repeat(8192) } })).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((response.status().as_u16(), &body["error"], &body["limit_bytes"]), (413, &json!("payload_too_large"), &json!(4096)));
+    assert!(body["message"].as_str().unwrap().contains("4096 bytes"), "{}", body);
+
+    // The same 2 KB is too much for an agent registration but fine as a document
+    let padding = "x".repeat(2048);
+    assert_eq!(post("/api/agents", json!({ "agent_id": "scout", "specialty": "science", "description": padding })).await.status(), 413);
+    let response = post("/api/rag/documents", json!({ "document_id": "notes", "content": padding, "metadata": {} })).await;
+    assert_ne!(response.status(), 413);
+}
+
+#[tokio::test]
+async fn streamed_bodies_are_cut_off_at_the_limit() {
+    let routes = api::mcp_route(service()).recover(api::recover);
+    let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    // A chunked body that never ends: the answer can only come from the limit
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let head = "POST /api/mcp HTTP/1.1\r\nhost: localhost\r\ncontent-type: application/json\r\ntransfer-encoding: chunked\r\n\r\n";
+    stream.write_all(head.as_bytes()).await.unwrap();
+    let chunk = format!("400\r\n{}\r\n", "x".repeat(1024));
+    let mut response = Vec::new();
+    let read = async {
+        for _ in 0..64 {
+            if stream.write_all(chunk.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+        let mut buffer = [0; 4096];
+        while !String::from_utf8_lossy(&response).contains("payload_too_large") {
+            match stream.read(&mut buffer).await.unwrap() {
+                0 => break,
+                read => response.extend_from_slice(&buffer[..read]),
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), read).await.expect("the server answered before the body ended");
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    assert!(response.contains(r#""limit_bytes":4096"#), "{}", response);
+}
+
+#[tokio::test]
+async fn bodies_within_the_limit_are_still_checked_as_json() {
+    let routes = api::mcp_route(service()).recover(api::recover);
+    let response = warp::test::request().method("POST").path("/api/mcp").header("content-type", "text/plain").body("{}").reply(&routes).await;
+    assert_eq!(response.status(), 415);
+    let response = warp::test::request().method("POST").path("/api/mcp").body("{not json").reply(&routes).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((response.status().as_u16(), &body["error"]), (400, &json!("invalid_params")));
+}
@@ -0,0 +1,128 @@
+//! Per-backend circuit breakers: opened by repeated failures, failing fast or
+//! calling the fallback while open, and shown in the metrics.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde_json::json;
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::breaker::{BreakerConfig, BreakerState};
+use void_shrine_mcp::llm_backend::{BackendError, CompletionChunk, CompletionOutput, FinishReason, LLMBackend, Prompt, RetryConfig};
+use void_shrine_mcp::mcp_server::{MCPParams, MCPRequest, MetricsParams};
+use void_shrine_mcp::VoidShrineMCP;
+
+/// Plays back `script`, one outcome per call, then answers with its name
+struct Scripted {
+    name: &'static str,
+    script: Mutex<VecDeque<BackendError>>,
+    calls: AtomicU32,
+}
+
+impl Scripted {
+    fn new(name: &'static str, script: Vec<BackendError>) -> Arc<Self> {
+        Arc::new(Self { name, script: Mutex::new(script.into()), calls: AtomicU32::new(0) })
+    }
+
+    fn call(&self) -> anyhow::Result<CompletionOutput> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if let Some(error) = self.script.lock().unwrap().pop_front() {
+            return Err(error.into());
+        }
+        Ok(CompletionOutput { text: self.name.to_string(), prompt_tokens: 4, completion_tokens: 1, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+    }
+}
+
+impl LLMBackend for Scripted {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        let outcome = self.call();
+        Box::pin(async move { outcome })
+    }
+
+    fn complete_stream<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxStream<'a, anyhow::Result<CompletionChunk>> {
+        stream::iter([self.call().map(CompletionChunk::Done)]).boxed()
+    }
+}
+
+fn unavailable(times: usize) -> Vec<BackendError> {
+    vec![BackendError::Unavailable("connection refused".to_string()); times]
+}
+
+fn request() -> MCPRequest {
+    let params = serde_json::from_value(json!({
+        "agent_id": "persistent", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+        "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap();
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }
+}
+
+/// Opens after three failures, without retries muddying the count
+async fn breaking(service: VoidShrineMCP, fallbacks: BTreeMap<String, String>) -> VoidShrineMCP {
+    let breakers = BreakerConfig { failure_threshold: 3, window_secs: 60, cooldown_secs: 60, fallbacks, ..BreakerConfig::default() };
+    let service = service.with_retries(RetryConfig { max_attempts: 1, ..RetryConfig::default() }).with_breakers(breakers);
+    service.chaos_config.write().await.enabled = false;
+    service
+}
+
+#[tokio::test]
+async fn repeated_failures_open_the_breaker_and_calls_fail_fast() {
+    let backend = Scripted::new("primary", unavailable(3));
+    let service = breaking(VoidShrineMCP::default().with_backend(backend.clone()), BTreeMap::new()).await;
+
+    for _ in 0..3 {
+        let failure = service.handle_mcp_request(request()).await.unwrap_err();
+        assert_eq!(failure.error.code(), "backend_unavailable");
+    }
+    let failure = service.handle_mcp_request(request()).await.unwrap_err();
+    assert_eq!((failure.error.code(), failure.error.http_status()), ("backend_circuit_open", 503));
+    assert!(failure.error.retry_after().is_some());
+    // The backend recovered, but isn't called until the cool-down ends
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 3);
+
+    let breakers = service.handle_metrics(&Tenancy::All, &MetricsParams::default()).breakers;
+    assert_eq!(breakers.len(), 1);
+    assert_eq!((breakers[0].backend.as_str(), breakers[0].state, breakers[0].opened), ("primary", BreakerState::Open, 1));
+    assert!(breakers[0].retry_after_ms.is_some_and(|ms| ms > 59_000));
+}
+
+#[tokio::test]
+async fn an_open_breaker_sends_calls_to_the_fallback() {
+    let primary = Scripted::new("primary", unavailable(10));
+    let spare = Scripted::new("spare", Vec::new());
+    let fallbacks = BTreeMap::from([("primary".to_string(), "spare".to_string())]);
+    let service = VoidShrineMCP::default().with_backend(spare.clone()).with_backend(primary.clone());
+    let service = breaking(service, fallbacks).await;
+
+    for _ in 0..3 {
+        service.handle_mcp_request(request()).await.unwrap_err();
+    }
+    for _ in 0..2 {
+        assert_eq!(service.handle_mcp_request(request()).await.unwrap().result.response, "spare");
+    }
+    assert_eq!((primary.calls.load(Ordering::SeqCst), spare.calls.load(Ordering::SeqCst)), (3, 2));
+
+    let states: Vec<_> = service.handle_metrics(&Tenancy::All, &MetricsParams::default()).breakers.into_iter().map(|b| (b.backend, b.state)).collect();
+    assert_eq!(states, [("primary".to_string(), BreakerState::Open), ("spare".to_string(), BreakerState::Closed)]);
+}
+
+#[tokio::test]
+async fn refused_requests_do_not_count_against_the_backend() {
+    let refusals = vec![BackendError::Status { status: 400, message: "prompt too long".to_string() }; 5];
+    let backend = Scripted::new("primary", refusals);
+    let service = breaking(VoidShrineMCP::default().with_backend(backend.clone()), BTreeMap::new()).await;
+
+    for _ in 0..5 {
+        let failure = service.handle_mcp_request(request()).await.unwrap_err();
+        assert_eq!(failure.error.code(), "backend_error");
+    }
+    assert_eq!(service.handle_mcp_request(request()).await.unwrap().result.response, "primary");
+    let breakers = service.handle_metrics(&Tenancy::All, &MetricsParams::default()).breakers;
+    assert_eq!((breakers[0].state, breakers[0].recent_failures, breakers[0].opened), (BreakerState::Closed, 0, 0));
+}
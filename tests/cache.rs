@@ -0,0 +1,114 @@
+//! The response cache: identical inference requests answered once, hot
+//! requests bypassing it, reindexing invalidating it, and `DELETE /api/cache`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::cache::{CacheConfig, CacheStats};
+use void_shrine_mcp::llm_backend::{CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{IndexDocumentRequest, MCPParams, MCPRequest, MetricsParams};
+use void_shrine_mcp::{api, RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+/// Answers "answer N" to the Nth completion
+#[derive(Default)]
+struct CountingBackend {
+    completions: AtomicUsize,
+}
+
+impl LLMBackend for CountingBackend {
+    fn name(&self) -> &str {
+        "counting"
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        let text = format!("answer {}", self.completions.fetch_add(1, Ordering::SeqCst) + 1);
+        Box::pin(async move {
+            Ok(CompletionOutput { text, prompt_tokens: 10, completion_tokens: 2, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+        })
+    }
+}
+
+fn inference(temperature: f64, use_rag: bool) -> MCPRequest {
+    let params = serde_json::from_value(json!({
+        "agent_id": "retrier", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+        "max_tokens": 64, "temperature": temperature, "use_rag": use_rag, "context_window": 4096
+    }))
+    .unwrap();
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }
+}
+
+async fn service(backend: Arc<CountingBackend>) -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default()
+        .with_backend(backend)
+        .with_cache(CacheConfig { enabled: true, ..CacheConfig::default() });
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+#[tokio::test]
+async fn identical_requests_reach_the_backend_once() {
+    let backend = Arc::new(CountingBackend::default());
+    let service = service(Arc::clone(&backend)).await;
+
+    let first = service.handle_mcp_request(inference(0.0, false)).await.unwrap();
+    let repeat = service.handle_mcp_request(inference(0.0, false)).await.unwrap();
+    assert!(!first.metadata.cached && repeat.metadata.cached);
+    assert_eq!((first.result.response.as_str(), repeat.result.response.as_str()), ("answer 1", "answer 1"));
+    assert_eq!(repeat.result.metrics.token_count, first.result.metrics.token_count);
+    assert!(repeat.result.metrics.response_time_ms <= first.result.metrics.response_time_ms.max(1));
+
+    // Hot requests want a fresh answer every time
+    let hot = service.handle_mcp_request(inference(0.9, false)).await.unwrap();
+    let hot_again = service.handle_mcp_request(inference(0.9, false)).await.unwrap();
+    assert!(!hot.metadata.cached && !hot_again.metadata.cached);
+    assert_eq!(backend.completions.load(Ordering::SeqCst), 3);
+
+    let metrics = service.handle_metrics(&Tenancy::All, &MetricsParams::default());
+    assert_eq!(metrics.cache, CacheStats { enabled: true, entries: 1, hits: 1, misses: 1 });
+}
+
+#[tokio::test]
+async fn reindexing_invalidates_grounded_answers() {
+    let backend = Arc::new(CountingBackend::default());
+    let service = service(Arc::clone(&backend)).await;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+
+    service.handle_mcp_request(inference(0.0, true)).await.unwrap();
+    assert!(service.handle_mcp_request(inference(0.0, true)).await.unwrap().metadata.cached);
+
+    service
+        .handle_index_document(&Tenancy::All, None, IndexDocumentRequest {
+            id: Some("menu".to_string()),
+            title: "Menu".to_string(),
+            content: "Soup of the day.".to_string(),
+            metadata: HashMap::new(),
+        })
+        .await
+        .unwrap();
+    let after = service.handle_mcp_request(inference(0.0, true)).await.unwrap();
+    assert!(!after.metadata.cached);
+    assert_eq!(after.result.response, "answer 2");
+}
+
+#[tokio::test]
+async fn the_cache_can_be_emptied() {
+    let backend = Arc::new(CountingBackend::default());
+    let service = service(Arc::clone(&backend)).await;
+    service.handle_mcp_request(inference(0.0, false)).await.unwrap();
+    let routes = api::cache_route(Arc::clone(&service)).recover(api::recover);
+
+    let response = warp::test::request().method("DELETE").path("/api/cache").reply(&routes).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body, json!({ "cleared": 1 }));
+
+    assert!(!service.handle_mcp_request(inference(0.0, false)).await.unwrap().metadata.cached);
+    assert_eq!(backend.completions.load(Ordering::SeqCst), 2);
+}
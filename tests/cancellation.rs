@@ -0,0 +1,115 @@
+//! Cancelling running requests by id: over `POST /api/mcp/cancel/{id}`, for
+//! streams, and with a WebSocket cancel frame; too late or unknown ids refused.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::json;
+use support::{epoch, inference, service, Inference, ScriptedBackend, TestServer};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::mcp_server::InferenceEvent;
+use void_shrine_mcp::websocket::{self, WsReply};
+use void_shrine_mcp::VoidShrineMCP;
+
+fn slow() -> Inference {
+    inference("impatient", "take your time").param("specialty", "research")
+}
+
+/// A service whose one answer takes a minute
+fn sleepy() -> Arc<VoidShrineMCP> {
+    let backend = Arc::new(ScriptedBackend::new().reply_after(Duration::from_secs(60), "done").spending(4, 4));
+    Arc::new(service(backend, Arc::new(ManualClock::new(epoch()))))
+}
+
+/// Waits until `request_id` can be cancelled
+async fn cancel_when_running(service: &VoidShrineMCP, request_id: &str) {
+    for _ in 0..200 {
+        if service.handle_cancel(request_id).is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("request {} never started", request_id);
+}
+
+async fn reply(client: &mut warp::test::WsClient) -> WsReply {
+    loop {
+        if let Ok(text) = client.recv().await.unwrap().to_str() {
+            return serde_json::from_str(text).unwrap();
+        }
+    }
+}
+
+/// Waits until the agent has a request in flight, and with it a cancellable id
+async fn wait_in_flight(service: &VoidShrineMCP) {
+    for _ in 0..200 {
+        if service.agent_metrics.get("impatient").is_some_and(|metrics| metrics.in_flight == 1) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("no request in flight");
+}
+
+#[tokio::test]
+async fn cancelled_requests_fail_and_are_counted() {
+    let service = sleepy();
+    let server = TestServer::start(Arc::clone(&service)).await;
+    let running = tokio::spawn({
+        let service = Arc::clone(&service);
+        async move { service.handle_mcp_request(slow().request_id("slow").request()).await }
+    });
+
+    wait_in_flight(&service).await;
+    let (status, body) = server.post("/api/mcp/cancel/slow", &json!({})).await;
+    assert_eq!((status, body), (200, json!({ "request_id": "slow", "cancelled": true })));
+
+    let failure = tokio::time::timeout(Duration::from_secs(5), running).await.unwrap().unwrap().unwrap_err();
+    assert_eq!((failure.error.code(), failure.error.http_status()), ("cancelled", 499));
+    let metrics = service.agent_metrics.get("impatient").unwrap();
+    // Cancelling is the client's doing, not a failure to count against the agent
+    assert_eq!((metrics.cancelled_requests, metrics.in_flight, metrics.recent_outcomes.len()), (1, 0, 0));
+    drop(metrics);
+
+    // Too late, and never seen
+    for (path, status, code) in [("/api/mcp/cancel/slow", 409, "request_finished"), ("/api/mcp/cancel/nobody", 404, "request_not_found")] {
+        let (refused, error) = server.post(path, &json!({})).await;
+        assert_eq!((refused, error["error"].as_str()), (status, Some(code)));
+    }
+}
+
+#[tokio::test]
+async fn cancelled_streams_end_with_an_error_event() {
+    let service = sleepy();
+    let mut events = service.stream_llm_inference(slow().params(), Some("streamed".to_string())).unwrap();
+
+    cancel_when_running(&service, "streamed").await;
+    let mut last = None;
+    while let Some(event) = tokio::time::timeout(Duration::from_secs(5), events.next()).await.unwrap() {
+        last = Some(event);
+    }
+    assert!(matches!(last, Some(InferenceEvent::Error { code, message }) if code == "cancelled" && message.contains("cancelled")));
+    assert_eq!(service.agent_metrics.get("impatient").unwrap().cancelled_requests, 1);
+}
+
+#[tokio::test]
+async fn websocket_clients_cancel_with_a_frame() {
+    let service = sleepy();
+    let mut client = warp::test::ws().path("/ws/mcp").handshake(websocket::route(Arc::clone(&service))).await.unwrap();
+    client.send_text(slow().request_id("ws-slow").body().to_string()).await;
+    wait_in_flight(&service).await;
+    client.send_text(json!({ "cancel": "ws-slow" }).to_string()).await;
+
+    let acknowledged = reply(&mut client).await;
+    assert_eq!((acknowledged.request_id.as_deref(), acknowledged.cancelled), (Some("ws-slow"), true));
+    let failed = reply(&mut client).await;
+    assert_eq!(failed.request_id.as_deref(), Some("ws-slow"));
+    assert!(failed.error.unwrap().contains("cancelled"));
+
+    client.send_text(json!({ "cancel": "ws-slow" }).to_string()).await;
+    let late = reply(&mut client).await;
+    assert!(!late.cancelled && late.error.unwrap().contains("already finished"));
+}
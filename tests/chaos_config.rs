@@ -0,0 +1,119 @@
+//! Editing the chaos config, targeting rules included, over HTTP.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::api;
+use void_shrine_mcp::mcp_server::{ChaosRequest, MCPRequest};
+use void_shrine_mcp::VoidShrineMCP;
+use warp::Filter;
+
+async fn call(service: &Arc<VoidShrineMCP>, method: &str, body: Option<Value>) -> (u16, Value) {
+    let routes = api::chaos_config_routes(Arc::clone(service)).recover(api::recover);
+    let mut request = warp::test::request().method(method).path("/api/chaos/config");
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn targeting_rules_are_editable_and_checked() {
+    let service = Arc::new(VoidShrineMCP::default());
+    let (status, config) = call(&service, "GET", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(config["targeting"]["include_agents"], json!([]));
+
+    let rules = json!({
+        "intensity": 1.0,
+        "targeting": { "exclude_agents": ["critical-*"], "specialty_intensity": { "tactical": 1.0 } }
+    });
+    let (status, config) = call(&service, "PUT", Some(rules)).await;
+    assert_eq!(status, 200, "{}", config);
+    assert_eq!(config["targeting"]["exclude_agents"], json!(["critical-*"]));
+
+    // Exempt agents never see chaos, however often they ask
+    for _ in 0..20 {
+        let request = ChaosRequest {
+            agent_id: "critical-db".to_string(),
+            specialty: Some("tactical".to_string()),
+            chaos_type: "network_delay".to_string(),
+            intensity: 1.0,
+        };
+        assert!(!service.handle_chaos(request).await.apply_chaos);
+    }
+
+    let contradictory = json!({ "targeting": { "include_agents": ["critical-db"], "exclude_agents": ["critical-*"] } });
+    let (status, body) = call(&service, "PUT", Some(contradictory)).await;
+    assert_eq!((status, body["error"].as_str()), (400, Some("invalid_params")));
+    assert!(body["message"].as_str().unwrap().contains("'critical-db'"), "{}", body);
+    // The rejected config left the previous one in place
+    assert_eq!(service.handle_chaos_config().await.targeting.exclude_agents, ["critical-*"]);
+}
+
+#[tokio::test]
+async fn specialty_multipliers_are_checked() {
+    let service = Arc::new(VoidShrineMCP::default());
+    for multiplier in [-0.5, 10.5] {
+        let config = json!({ "targeting": { "specialty_multipliers": { "creative": multiplier } } });
+        let (status, body) = call(&service, "PUT", Some(config)).await;
+        assert_eq!(status, 400, "{}", body);
+        assert!(body["message"].as_str().unwrap().contains("chaos.targeting.specialty_multipliers.creative must be between 0 and 10"), "{}", body);
+    }
+    let config = json!({ "targeting": { "specialty_multipliers": { "creative": 0.0, "tactical": 10.0 } } });
+    let (status, config) = call(&service, "PUT", Some(config)).await;
+    assert_eq!(status, 200, "{}", config);
+    assert_eq!(config["targeting"]["specialty_multipliers"], json!({ "creative": 0.0, "tactical": 10.0 }));
+}
+
+#[tokio::test]
+async fn delays_follow_the_configured_distribution() {
+    let service = Arc::new(VoidShrineMCP::default());
+    let heavy = json!({ "intensity": 1.0, "delays": { "network_delay": { "distribution": "pareto", "scale_ms": 200.0, "shape": 1.5 } } });
+    let (status, body) = call(&service, "PUT", Some(heavy)).await;
+    assert_eq!(status, 400);
+    assert!(body["message"].as_str().unwrap().contains("chaos.delays.network_delay.cap_ms is required"), "{}", body);
+    let unknown = json!({ "delays": { "request_drop": { "distribution": "uniform", "min_ms": 1, "max_ms": 2 } } });
+    assert_eq!(call(&service, "PUT", Some(unknown)).await.0, 400);
+
+    let capped = json!({
+        "intensity": 1.0,
+        "seed": 11,
+        "delays": { "network_delay": { "distribution": "pareto", "scale_ms": 200.0, "shape": 1.5, "cap_ms": 800 } }
+    });
+    assert_eq!(call(&service, "PUT", Some(capped.clone())).await.0, 200);
+    let (_, config) = call(&service, "GET", None).await;
+    assert_eq!(config["delays"]["network_delay"], json!({ "distribution": "pareto", "scale_ms": 200.0, "shape": 1.5, "cap_ms": 800 }));
+    // Types left out aren't listed, and keep their default delays
+    assert_eq!(config["delays"]["memory_pressure"], Value::Null);
+
+    let request = || ChaosRequest { agent_id: "scout".to_string(), specialty: None, chaos_type: "network_delay".to_string(), intensity: 1.0 };
+    let mut delays = Vec::new();
+    for _ in 0..20 {
+        delays.push(service.handle_chaos(request()).await.delay_ms);
+    }
+    assert!(delays.iter().all(|ms| (200..=800).contains(ms)), "{:?}", delays);
+    // The same seed from its first decision draws the same delays
+    call(&service, "PUT", Some(capped)).await;
+    for delay in delays {
+        assert_eq!(service.handle_chaos(request()).await.delay_ms, delay);
+    }
+}
+
+#[tokio::test]
+async fn inline_chaos_waits_out_its_delay() {
+    let service = Arc::new(VoidShrineMCP::default());
+    let config = json!({
+        "intensity": 1.0,
+        "chaos_types": ["network_delay"],
+        "delays": { "network_delay": { "distribution": "uniform", "min_ms": 80, "max_ms": 81 } }
+    });
+    assert_eq!(call(&service, "PUT", Some(config)).await.0, 200);
+    let params = serde_json::from_value(json!({ "agent_id": "scout", "prompt": "wait for it", "use_rag": false })).unwrap();
+    let started = std::time::Instant::now();
+    let response = service.handle_mcp_request(MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }).await.unwrap();
+    assert!(started.elapsed() >= std::time::Duration::from_millis(80), "{:?}", started.elapsed());
+    assert_eq!(response.metadata.chaos_type.as_deref(), Some("network_delay"));
+    assert_eq!(service.chaos_stats.totals().injected_delay_ms, 80);
+}
@@ -0,0 +1,113 @@
+//! GET /api/chaos/report: chaos decisions from inference and from
+//! POST /api/chaos, counted per type and agent, against the configured rate.
+
+mod support;
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use support::{epoch, inference, service, ScriptedBackend};
+use void_shrine_mcp::agents::AgentSpec;
+use void_shrine_mcp::api;
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::mcp_server::{ChaosConfig, ChaosRequest, ChaosTargeting, MCPRequest, MetricsParams};
+use void_shrine_mcp::VoidShrineMCP;
+use warp::Filter;
+
+fn bell(agent_id: &str) -> MCPRequest {
+    inference(agent_id, "sound the bell").request()
+}
+
+fn bell_as(agent_id: &str, specialty: &str) -> MCPRequest {
+    inference(agent_id, "sound the bell").param("specialty", specialty).request()
+}
+
+/// A service whose backend answers `replies` times
+fn instance(replies: usize) -> Arc<VoidShrineMCP> {
+    let backend = Arc::new(ScriptedBackend::new().replies(replies, "the bell rings"));
+    Arc::new(service(backend, Arc::new(ManualClock::new(epoch()))))
+}
+
+fn advice(agent_id: &str, chaos_type: &str, intensity: f64) -> ChaosRequest {
+    ChaosRequest { agent_id: agent_id.to_string(), specialty: None, chaos_type: chaos_type.to_string(), intensity }
+}
+
+async fn report(service: &Arc<VoidShrineMCP>, query: &str) -> Value {
+    let routes = api::chaos_report_route(Arc::clone(service)).recover(api::recover);
+    let response = warp::test::request().path(&format!("/api/chaos/report{}", query)).reply(&routes).await;
+    assert_eq!(response.status(), 200);
+    serde_json::from_slice(response.body()).unwrap()
+}
+
+#[tokio::test]
+async fn decisions_from_inference_and_advice_are_reported() {
+    let service = instance(0);
+    *service.chaos_config.write().await = ChaosConfig {
+        intensity: 1.0,
+        chaos_types: vec!["error_injection".to_string()],
+        seed: Some(7),
+        ..ChaosConfig::default()
+    };
+    service.handle_mcp_request(bell("scout")).await.unwrap_err();
+    service.handle_mcp_request(bell("scout")).await.unwrap_err();
+    let applied = service.handle_chaos(advice("courier", "network_delay", 1.0)).await;
+    assert!(applied.apply_chaos);
+    assert!(!service.handle_chaos(advice("courier", "network_delay", 0.0)).await.apply_chaos);
+
+    let all = report(&service, "").await;
+    assert_eq!((all["enabled"].clone(), all["configured_intensity"].clone()), (json!(true), json!(1.0)));
+    let totals = &all["totals"];
+    assert_eq!((&totals["decisions"], &totals["applied"], &totals["skipped"], &totals["errors_injected"]), (&json!(4), &json!(3), &json!(1), &json!(2)));
+    assert_eq!(totals["injected_delay_ms"], json!(applied.delay_ms));
+    assert_eq!((&totals["realized_rate"], &totals["expected_rate"]), (&json!(0.75), &json!(0.75)));
+    assert_eq!(totals["by_type"]["network_delay"], json!({ "applied": 1, "shadowed": 0, "skipped": 1 }));
+    assert_eq!(all["agents"].as_array().unwrap().iter().map(|agent| agent["agent_id"].clone()).collect::<Vec<_>>(), [json!("courier"), json!("scout")]);
+
+    let scout = report(&service, "?agent_id=scout").await;
+    assert_eq!((&scout["totals"]["decisions"], &scout["totals"]["by_type"]["error_injection"]["applied"]), (&json!(2), &json!(2)));
+    assert_eq!(report(&service, "?since=2999-01-01T00:00:00Z").await["totals"]["decisions"], json!(0));
+
+    // Lifetime totals are in the metrics too
+    let metrics = service.handle_metrics(&Tenancy::All, &MetricsParams::default());
+    assert_eq!((metrics.chaos.decisions, metrics.chaos.errors_injected), (4, 2));
+}
+
+#[tokio::test]
+async fn disabled_or_exempt_requests_are_not_decisions() {
+    let service = instance(1);
+    service.chaos_config.write().await.enabled = false;
+    service.handle_mcp_request(bell("scout")).await.unwrap();
+    service.handle_chaos(advice("scout", "network_delay", 1.0)).await;
+    assert_eq!(report(&service, "").await["totals"]["decisions"], json!(0));
+}
+
+#[tokio::test]
+async fn specialty_multipliers_are_reported_and_zero_spares_the_specialty() {
+    let service = instance(6);
+    *service.chaos_config.write().await = ChaosConfig {
+        intensity: 1.0,
+        chaos_types: vec!["error_injection".to_string()],
+        targeting: ChaosTargeting {
+            agent_multipliers: [("*".to_string(), 5.0)].into(),
+            specialty_multipliers: [("creative".to_string(), 0.0), ("tactical".to_string(), 2.0)].into(),
+            ..ChaosTargeting::default()
+        },
+        ..ChaosConfig::default()
+    };
+    for _ in 0..5 {
+        service.handle_mcp_request(bell_as("painter", "creative")).await.unwrap();
+        service.handle_mcp_request(bell_as("engineer", "tactical")).await.unwrap_err();
+    }
+    // A registered agent's specialty counts when the request leaves it out
+    let spec = AgentSpec { agent_id: Some("muse".to_string()), specialty: "creative".to_string(), default_model: None, max_concurrency: None, tags: Vec::new(), description: None, defaults: Default::default() };
+    service.handle_register_agent(&Tenancy::All, spec).unwrap();
+    service.handle_mcp_request(bell("muse")).await.unwrap();
+    assert!(!service.handle_chaos(advice("muse", "network_delay", 1.0)).await.apply_chaos);
+
+    let specialties = report(&service, "").await["specialties"].clone();
+    let summary: Vec<Value> = specialties.as_array().unwrap().iter()
+        .map(|specialty| json!([specialty["specialty"], specialty["multiplier"], specialty["decisions"], specialty["applied"], specialty["expected_rate"]]))
+        .collect();
+    assert_eq!(summary, [json!(["creative", 0.0, 7, 0, 0.0]), json!(["tactical", 2.0, 5, 5, 1.0])]);
+}
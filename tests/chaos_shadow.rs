@@ -0,0 +1,80 @@
+//! Chaos in shadow mode: decided and sampled exactly as it would be applied,
+//! counted and reported as shadowed, but never delaying or failing anything.
+
+use std::collections::BTreeMap;
+
+use serde_json::json;
+use void_shrine_mcp::delays::DelayDistribution;
+use void_shrine_mcp::mcp_server::{ChaosConfig, ChaosRequest, DashboardParams, MCPRequest};
+use void_shrine_mcp::VoidShrineMCP;
+
+fn inference() -> MCPRequest {
+    let params = serde_json::from_value(json!({ "agent_id": "scout", "prompt": "sound the bell", "use_rag": false })).unwrap();
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }
+}
+
+fn seeded(shadow: bool) -> ChaosConfig {
+    ChaosConfig {
+        intensity: 0.6,
+        chaos_types: vec!["network_delay".to_string(), "error_injection".to_string()],
+        delays: BTreeMap::from([("network_delay".to_string(), DelayDistribution::uniform(1, 5))]),
+        seed: Some(11),
+        shadow,
+        ..ChaosConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn shadow_mode_predicts_what_active_mode_does() {
+    let shadowed = VoidShrineMCP::default();
+    *shadowed.chaos_config.write().await = seeded(true);
+    let active = VoidShrineMCP::default();
+    *active.chaos_config.write().await = seeded(false);
+
+    for _ in 0..16 {
+        let response = shadowed.handle_mcp_request(inference()).await.unwrap();
+        assert!(!response.metadata.chaos_applied);
+        let predicted = response.metadata.would_have_applied.map(|shadow| shadow.chaos_type);
+        let happened = match active.handle_mcp_request(inference()).await {
+            Ok(response) => response.metadata.chaos_type,
+            Err(_) => Some("error_injection".to_string()),
+        };
+        assert_eq!(predicted, happened);
+    }
+
+    let (shadow, real) = (shadowed.chaos_stats.totals(), active.chaos_stats.totals());
+    assert_eq!((shadow.applied, shadow.shadowed, shadow.skipped), (0, real.applied, real.skipped));
+    assert!(real.applied > 0 && real.errors_injected > 0, "{:?}", real);
+    assert_eq!((shadow.shadow_delay_ms, shadow.shadow_errors), (real.injected_delay_ms, real.errors_injected));
+    assert_eq!(shadow.shadow_rate, real.realized_rate);
+}
+
+#[tokio::test]
+async fn shadowed_types_are_reported_apart_from_applied_ones() {
+    let service = VoidShrineMCP::default();
+    *service.chaos_config.write().await = ChaosConfig {
+        intensity: 1.0,
+        chaos_types: vec!["error_injection".to_string()],
+        shadow_types: vec!["error_injection".to_string()],
+        ..ChaosConfig::default()
+    };
+    let response = service.handle_mcp_request(inference()).await.unwrap();
+    let shadow = response.metadata.would_have_applied.unwrap();
+    assert_eq!((shadow.chaos_type.as_str(), shadow.delay_ms), ("error_injection", 0));
+
+    let advice = service
+        .handle_chaos(ChaosRequest { agent_id: "scout".to_string(), specialty: None, chaos_type: "error_injection".to_string(), intensity: 1.0 })
+        .await;
+    assert!(!advice.apply_chaos && advice.would_have_applied.is_some());
+
+    let report = service.handle_chaos_report(&Default::default()).await;
+    assert_eq!(report.shadow_types, ["error_injection"]);
+    assert_eq!((report.totals.applied, report.totals.shadowed, report.totals.shadow_errors), (0, 2, 2));
+    let dashboard = service.handle_dashboard(&DashboardParams::default()).await;
+    assert_eq!(dashboard.chaos.shadowed_by_type, BTreeMap::from([("error_injection".to_string(), 2)]));
+    assert!(dashboard.chaos.events_by_type.is_empty());
+
+    // Shadowing a type chaos doesn't know is refused
+    let unknown = ChaosConfig { shadow_types: vec!["gremlins".to_string()], ..ChaosConfig::default() };
+    assert_eq!(unknown.validate(), ["unknown chaos type 'gremlins'"]);
+}
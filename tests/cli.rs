@@ -0,0 +1,161 @@
+//! `void-shrine-cli` end to end against an in-process server: every command
+//! in both output forms, and server errors surfacing with a nonzero exit.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use serde_json::Value;
+use void_shrine_mcp::api;
+use void_shrine_mcp::auth::{ApiKey, Auth, Tenancy};
+use void_shrine_mcp::mcp_server::MetricsParams;
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+const SECRET: &str = "cli-secret";
+
+/// Serves the routes the CLI speaks to, behind one inference+admin key as
+/// the server binary puts them
+async fn serve() -> SocketAddr {
+    let auth = Auth::new(vec![ApiKey::parse(&format!("cli:{}:inference+admin", SECRET)).unwrap()]).unwrap();
+    let service = VoidShrineMCP::default().with_auth(auth);
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    let service = Arc::new(service);
+
+    let metrics = {
+        let service = Arc::clone(&service);
+        warp::path("api")
+            .and(warp::path("metrics"))
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(warp::query::<MetricsParams>())
+            .and(service.auth.tenancy_filter())
+            .map(move |params: MetricsParams, tenancy: Tenancy| warp::reply::json(&service.handle_metrics(&tenancy, &params)))
+    };
+    let routes = api::stream_route(Arc::clone(&service))
+        .or(api::mcp_route(Arc::clone(&service)))
+        .or(api::document_routes(Arc::clone(&service)))
+        .or(api::chaos_config_routes(Arc::clone(&service)))
+        .or(api::agent_routes(Arc::clone(&service)))
+        .or(metrics);
+    let routes = service.auth.filter().and(routes).recover(api::recover);
+    let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    addr
+}
+
+struct Run {
+    code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+async fn cli(addr: SocketAddr, key: Option<&str>, args: &[&str]) -> Run {
+    let mut command = tokio::process::Command::new(env!("CARGO_BIN_EXE_void-shrine-cli"));
+    command.args(args).env("VOID_SHRINE_URL", format!("http://{}", addr)).env_remove("VOID_SHRINE_API_KEY");
+    if let Some(key) = key {
+        command.env("VOID_SHRINE_API_KEY", key);
+    }
+    let output = command.output().await.unwrap();
+    Run {
+        code: output.status.code().unwrap(),
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+    }
+}
+
+/// Runs a command expected to succeed, returning its stdout
+async fn ok(addr: SocketAddr, args: &[&str]) -> String {
+    let run = cli(addr, Some(SECRET), args).await;
+    assert_eq!(run.code, 0, "{:?}: {}", args, run.stderr);
+    run.stdout
+}
+
+async fn json(addr: SocketAddr, args: &[&str]) -> Value {
+    let mut args = args.to_vec();
+    args.push("--json");
+    serde_json::from_str(&ok(addr, &args).await).unwrap()
+}
+
+#[tokio::test]
+async fn inference_plain_and_streamed() {
+    let addr = serve().await;
+    // Temperature 0 gives the same answer both times it is asked
+    let infer = ["infer", "--agent", "cli-agent", "--specialty", "tactical", "--prompt", "void shrine chaos", "--rag", "--temperature", "0"];
+
+    let response = json(addr, &infer).await;
+    let text = response["result"]["response"].as_str().unwrap().to_string();
+    assert!(!text.is_empty());
+    assert!(!response["result"]["citations"].as_array().unwrap().is_empty());
+    assert!(ok(addr, &infer).await.contains(text.lines().next().unwrap()));
+
+    let mut streamed = infer.to_vec();
+    streamed.push("--stream");
+    let printed = ok(addr, &streamed).await;
+    assert!(!printed.trim().is_empty());
+
+    // One JSON event per line, ending with the whole response
+    streamed.push("--json");
+    let events: Vec<Value> = ok(addr, &streamed).await.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let deltas: String = events.iter().filter(|event| event["event"] == "delta").map(|event| event["text"].as_str().unwrap()).collect();
+    let done = events.last().unwrap();
+    assert_eq!(done["event"], "done");
+    assert_eq!(done["response"].as_str().unwrap(), deltas);
+}
+
+#[tokio::test]
+async fn documents_chaos_agents_and_metrics() {
+    let addr = serve().await;
+
+    let indexed = ok(addr, &["rag", "index", "--id", "tide-notes", "--title", "Tide notes", "--content", "Anemones and hermit crabs live in tidal pools.", "--metadata", "category=ecology"]).await;
+    assert_eq!(indexed.trim(), "Indexed document tide-notes");
+    let results = json(addr, &["rag", "query", "hermit crabs", "--limit", "3", "--filter", "category=ecology"]).await;
+    assert_eq!(results["results"][0]["document_id"], "tide-notes");
+    assert!(ok(addr, &["rag", "query", "hermit crabs"]).await.contains("Tide notes (tide-notes)"));
+    let documents = json(addr, &["rag", "stats"]).await["document_count"].as_u64().unwrap();
+    assert_eq!(ok(addr, &["rag", "delete", "tide-notes"]).await.trim(), "Deleted 1 document");
+    assert_eq!(json(addr, &["rag", "stats"]).await["document_count"].as_u64().unwrap(), documents - 1);
+
+    let chaos = json(addr, &["chaos", "set", "--enabled", "--intensity", "0.25", "--types", "network_delay, memory_pressure", "--seed", "7"]).await;
+    assert_eq!((chaos["enabled"].as_bool(), chaos["intensity"].as_f64(), chaos["seed"].as_u64()), (Some(true), Some(0.25), Some(7)));
+    assert_eq!(chaos["chaos_types"], serde_json::json!(["network_delay", "memory_pressure"]));
+    // Unchanged settings are kept
+    ok(addr, &["chaos", "set", "--disabled"]).await;
+    let chaos = json(addr, &["chaos", "get"]).await;
+    assert_eq!((chaos["enabled"].as_bool(), chaos["intensity"].as_f64()), (Some(false), Some(0.25)));
+    assert!(ok(addr, &["chaos", "get"]).await.contains("intensity: 0.25"));
+
+    ok(addr, &["infer", "--agent", "cli-agent", "--prompt", "void shrine"]).await;
+    assert!(ok(addr, &["agents", "list"]).await.contains("cli-agent"));
+    let metrics = json(addr, &["metrics", "--agent", "cli-agent"]).await;
+    assert_eq!(metrics["server"]["total_requests"], 1);
+    assert_eq!(metrics["agents"][0]["agent_id"], "cli-agent");
+    assert!(ok(addr, &["metrics"]).await.contains("agent cli-agent: 1 requests"));
+}
+
+#[tokio::test]
+async fn failures_exit_nonzero_with_the_structured_error() {
+    let addr = serve().await;
+
+    let run = cli(addr, None, &["rag", "stats"]).await;
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.starts_with("error: unauthorized (HTTP 401)"), "{}", run.stderr);
+
+    let run = cli(addr, Some(SECRET), &["infer", "--agent", "cli-agent", "--prompt", ""]).await;
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.contains("invalid_params (HTTP 400)"), "{}", run.stderr);
+    assert!(run.stderr.contains("\n  prompt: "), "{}", run.stderr);
+    assert!(run.stdout.is_empty());
+
+    let run = cli(addr, Some(SECRET), &["rag", "delete", "no-such-document", "--json"]).await;
+    assert_eq!(run.code, 1);
+    let error: Value = serde_json::from_str(&run.stderr).unwrap();
+    assert_eq!(error["error"], "document_not_found");
+
+    let run = cli(addr, Some(SECRET), &["chaos", "spin"]).await;
+    assert_eq!(run.code, 2);
+    assert!(run.stderr.starts_with("error: unknown chaos subcommand \"spin\""), "{}", run.stderr);
+    assert_eq!(cli(addr, Some(SECRET), &["metrics", "--agnet", "x"]).await.code, 2);
+}
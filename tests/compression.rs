@@ -0,0 +1,129 @@
+//! Compression: large JSON responses come back brotli- or gzip-compressed as
+//! the client's Accept-Encoding asks, decoding to what an identity response
+//! says; small responses and event streams are sent as they are. Document
+//! uploads may be gzip-compressed, and are held to their limit decompressed.
+
+mod support;
+
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use support::{configured_service, epoch, inference, ScriptedBackend};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::compression::CompressionConfig;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::jobs::JobQueue;
+use void_shrine_mcp::mcp_server::BodyLimits;
+use void_shrine_mcp::rag_engine::RAGEngine;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::http::Response;
+use warp::hyper::body::Bytes;
+use warp::Filter;
+
+/// Answers at length, `calls` times
+async fn instance(calls: usize) -> Arc<VoidShrineMCP> {
+    let body_limits = BodyLimits { documents_bytes: 4096, ..BodyLimits::default() };
+    let backend = ScriptedBackend::new().spending(4, 400).replies(calls, &"The shrine is quiet and the tide is out. ".repeat(100));
+    let service = configured_service(Config { body_limits, ..Config::default() }, Arc::new(backend), Arc::new(ManualClock::new(epoch())));
+    *service.rag_engine.write().await = Some(RAGEngine::new().await.unwrap());
+    Arc::new(service)
+}
+
+async fn request(service: &Arc<VoidShrineMCP>, path: &str, accept_encoding: &str, body: &Value) -> Response<Bytes> {
+    let jobs = Arc::new(JobQueue::start(Arc::clone(service), Default::default()));
+    let routes = api::routes(Arc::clone(service), jobs)
+        .recover(api::recover)
+        .with(warp::wrap_fn(|filter| api::compress(CompressionConfig::default(), filter)));
+    warp::test::request().method("POST").path(path).header("accept-encoding", accept_encoding).json(body).reply(&routes).await
+}
+
+/// The body as sent, decompressed
+fn decoded(response: &Response<Bytes>) -> Value {
+    let mut body = Vec::new();
+    match response.headers().get("content-encoding").map(|value| value.to_str().unwrap()) {
+        None => body.extend_from_slice(response.body()),
+        Some("gzip") => {
+            flate2::read::GzDecoder::new(response.body().as_ref()).read_to_end(&mut body).unwrap();
+        }
+        Some("br") => {
+            brotli::Decompressor::new(response.body().as_ref(), 4096).read_to_end(&mut body).unwrap();
+        }
+        Some(other) => panic!("unexpected encoding {}", other),
+    }
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn gzip(body: &Value) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&serde_json::to_vec(body).unwrap()).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn compressed_and_identity_responses_say_the_same() {
+    let service = instance(4).await;
+    let ask = inference("scout", "report").body();
+
+    let mut answers = Vec::new();
+    for (accept_encoding, expected) in [("gzip, deflate, br", Some("br")), ("gzip", Some("gzip")), ("identity", None)] {
+        let response = request(&service, "/api/mcp", accept_encoding, &ask).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers().get("content-encoding").map(|value| value.to_str().unwrap()), expected);
+        assert_eq!(response.headers()["vary"], "accept-encoding");
+        if expected.is_some() {
+            assert!(response.body().len() < 1024, "{} bytes", response.body().len());
+        }
+        let body = decoded(&response);
+        answers.push(body["result"]["response"].clone());
+    }
+    assert!(answers[0].as_str().unwrap().len() > 4000);
+    assert!(answers.iter().all(|answer| *answer == answers[0]));
+
+    // Small responses aren't worth it
+    let response = request(&service, "/api/rag/query", "gzip", &json!({ "query": "tides", "limit": 1 })).await;
+    assert_eq!((response.status().as_u16(), response.headers().get("content-encoding")), (200, None));
+    assert_eq!(response.headers()["vary"], "accept-encoding");
+
+    // Event streams go out as they happen
+    let response = request(&service, "/api/mcp/stream", "gzip", &json!({ "agent_id": "scout", "prompt": "report", "use_rag": false })).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!((response.headers()["content-type"].to_str().unwrap(), response.headers().get("content-encoding")), ("text/event-stream", None));
+    assert!(String::from_utf8_lossy(response.body()).contains("tide is out"));
+}
+
+#[tokio::test]
+async fn gzip_document_uploads_are_held_to_the_limit_decompressed() {
+    let service = instance(0).await;
+    let jobs = Arc::new(JobQueue::start(Arc::clone(&service), Default::default()));
+    let routes = api::routes(Arc::clone(&service), jobs).recover(api::recover);
+    let upload = |body: Vec<u8>, encoding: &'static str| {
+        warp::test::request()
+            .method("POST")
+            .path("/api/rag/documents")
+            .header("content-type", "application/json")
+            .header("content-encoding", encoding)
+            .body(body)
+            .reply(&routes)
+    };
+
+    let document = json!({ "title": "Tide tables", "content": "Spring tides follow the full and new moon.", "metadata": {} });
+    let response = upload(gzip(&document), "gzip").await;
+    assert_eq!(response.status(), 200, "{:?}", response.body());
+    let stats = service.rag_engine.read().await.as_ref().unwrap().get_stats().await.unwrap();
+    assert_eq!(stats.document_count, 1);
+
+    // Small on the wire, but over 4 KB once decompressed
+    let sprawling = json!({ "title": "Sprawl", "content": "tide ".repeat(2000), "metadata": {} });
+    let compressed = gzip(&sprawling);
+    assert!(compressed.len() < 4096);
+    let response = upload(compressed, "gzip").await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((response.status().as_u16(), &body["error"], &body["limit_bytes"]), (413, &json!("payload_too_large"), &json!(4096)));
+
+    let response = upload(b"not gzip at all".to_vec(), "gzip").await;
+    assert_eq!(response.status(), 400);
+    let response = upload(serde_json::to_vec(&document).unwrap(), "zstd").await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((response.status().as_u16(), &body["error"]), (415, &json!("unsupported_media_type")));
+}
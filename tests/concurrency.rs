@@ -0,0 +1,98 @@
+//! The global concurrency limit: requests past it are shed with 503
+//! `overloaded`, queued jobs wait for a slot instead, and the limit changes
+//! at runtime over `PUT /api/concurrency`.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use support::{configured_service, epoch, inference, Inference, ScriptedBackend, TestServer};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::concurrency::ConcurrencyConfig;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::jobs::{JobQueue, JobStatus, JobsConfig};
+use void_shrine_mcp::mcp_server::MetricsParams;
+use void_shrine_mcp::VoidShrineMCP;
+
+fn quick() -> Inference {
+    inference("crowd", "quick").param("specialty", "research")
+}
+
+/// A service allowing `max_in_flight` requests, whose first answer takes a
+/// minute and whose second comes at once
+fn instance(max_in_flight: u32) -> (Arc<VoidShrineMCP>, Arc<ScriptedBackend>) {
+    let concurrency = ConcurrencyConfig { max_in_flight, max_wait_ms: 20, retry_after_ms: 750 };
+    let backend = Arc::new(ScriptedBackend::new().reply_after(Duration::from_secs(60), "done").reply("done").spending(4, 1));
+    let service = configured_service(Config { concurrency, ..Config::default() }, Arc::clone(&backend), Arc::new(ManualClock::new(epoch())));
+    (Arc::new(service), backend)
+}
+
+/// Starts the first request, which holds its slot until cancelled
+async fn occupy(service: &Arc<VoidShrineMCP>, backend: &ScriptedBackend, request_id: &str) {
+    tokio::spawn({
+        let service = Arc::clone(service);
+        let request = inference("crowd", "take your time").request_id(request_id).request();
+        async move { service.handle_mcp_request(request).await }
+    });
+    while backend.prompts().is_empty() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+#[tokio::test]
+async fn requests_past_the_limit_are_shed_with_a_retry_hint() {
+    let (service, backend) = instance(1);
+    occupy(&service, &backend, "hog").await;
+
+    let server = TestServer::start(Arc::clone(&service)).await;
+    let (status, body) = server.post("/api/mcp", &quick().body()).await;
+    assert_eq!((status, &body["error"], &body["retry_after_ms"]), (503, &json!("overloaded"), &json!(750)));
+    let stream = service.stream_llm_inference(quick().params(), None);
+    assert_eq!(stream.err().map(|error| error.code()), Some("overloaded"));
+
+    // The slot frees up once the hog goes
+    let metrics = service.handle_metrics(&Tenancy::All, &MetricsParams::default());
+    assert_eq!((metrics.concurrency.shed_total, metrics.concurrency.in_use), (2, 1));
+    service.handle_cancel("hog").unwrap();
+    service.handle_mcp_request(quick().request_id("quick-1").request()).await.unwrap();
+    assert!(service.handle_prometheus().await.contains("void_shrine_requests_shed_total 2"));
+}
+
+#[tokio::test]
+async fn queued_jobs_wait_for_a_slot_instead_of_being_shed() {
+    let (service, backend) = instance(1);
+    occupy(&service, &backend, "hog").await;
+    let jobs = JobQueue::start(Arc::clone(&service), JobsConfig::default());
+    let job = jobs.submit(&Tenancy::All, quick().request_id("patient-1").request()).unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!((jobs.get(&job.job_id).unwrap().status, service.concurrency.status().waiting), (JobStatus::Queued, 1));
+    service.handle_cancel("hog").unwrap();
+    for _ in 0..200 {
+        if jobs.get(&job.job_id).unwrap().status == JobStatus::Completed {
+            assert_eq!(service.concurrency.status().shed_total, 0);
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the job never ran");
+}
+
+#[tokio::test]
+async fn the_limit_changes_at_runtime() {
+    let (service, backend) = instance(1);
+    occupy(&service, &backend, "hog").await;
+    let server = TestServer::start(Arc::clone(&service)).await;
+
+    assert_eq!(server.put("/api/concurrency", &json!({ "max_in_flight": 0 })).await.0, 400);
+    let (code, status) = server.put("/api/concurrency", &json!({ "max_in_flight": 2 })).await;
+    assert_eq!((code, &status["max_in_flight"], &status["in_use"]), (200, &json!(2), &json!(1)));
+
+    // The raised limit lets a second request in alongside the first
+    service.handle_mcp_request(quick().request_id("quick-1").request()).await.unwrap();
+    let (_, status) = server.get("/api/concurrency").await;
+    assert_eq!((&status["max_in_flight"], &status["shed_total"]), (&json!(2), &json!(0)));
+}
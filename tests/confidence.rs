@@ -0,0 +1,85 @@
+//! Confidence scores from what was observed: the same answer scores the same,
+//! and truncation, missing grounding and chaos corruption each lower it.
+
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use serde_json::json;
+use void_shrine_mcp::llm_backend::{CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{MCPParams, MCPRequest, MCPResponse};
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+
+/// Answers in full, unless the prompt asks for a long essay, which runs out of tokens
+struct Essayist;
+
+impl LLMBackend for Essayist {
+    fn name(&self) -> &str {
+        "essayist"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        let cut_off = prompt.user.contains("long essay");
+        let (finish_reason, completion_tokens) = if cut_off { (FinishReason::Length, params.max_tokens) } else { (FinishReason::Stop, 12) };
+        let text = "The void shrine keeps its chaos in balance.".to_string();
+        Box::pin(async move { Ok(CompletionOutput { text, prompt_tokens: 40, completion_tokens, finish_reason, generation_time: None, mean_logprob: None }) })
+    }
+}
+
+async fn instance() -> VoidShrineMCP {
+    let service = VoidShrineMCP::default().with_backend(Arc::new(Essayist));
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    service
+}
+
+fn inference(prompt: &str, use_rag: bool, verbose_confidence: bool) -> MCPRequest {
+    let params = serde_json::from_value(json!({
+        "agent_id": "scholar", "specialty": "research", "prompt": prompt, "max_tokens": 128, "temperature": 0.2,
+        "use_rag": use_rag, "context_window": 4096, "verbose_confidence": verbose_confidence
+    }))
+    .unwrap();
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }
+}
+
+async fn score(service: &VoidShrineMCP, prompt: &str, use_rag: bool) -> f64 {
+    service.handle_mcp_request(inference(prompt, use_rag, false)).await.unwrap().result.metrics.confidence_score
+}
+
+#[tokio::test]
+async fn scores_repeat_and_follow_the_signals() {
+    let service = instance().await;
+    let grounded = score(&service, "void shrine chaos", true).await;
+    assert_eq!(grounded, score(&service, "void shrine chaos", true).await);
+    assert!(grounded > 0.0 && grounded <= 1.0);
+
+    assert!(score(&service, "a long essay on the void shrine chaos", true).await < grounded);
+    assert!(score(&service, "zzyzx quux", true).await < grounded);
+
+    let verbose: MCPResponse = service.handle_mcp_request(inference("void shrine chaos", true, true)).await.unwrap();
+    let breakdown = verbose.result.metrics.confidence_breakdown.unwrap();
+    assert_eq!((breakdown.score, breakdown.finish, breakdown.length, breakdown.corruption), (grounded, Some(1.0), Some(1.0), 1.0));
+    assert!(breakdown.retrieval.unwrap() > 0.0);
+    // Only asked for, and not sent otherwise
+    let quiet = service.handle_mcp_request(inference("void shrine chaos", true, false)).await.unwrap();
+    assert!(serde_json::to_value(&quiet.result.metrics).unwrap().get("confidence_breakdown").is_none());
+}
+
+#[tokio::test]
+async fn corrupted_responses_lose_confidence() {
+    let service = instance().await;
+    let clean = score(&service, "void shrine chaos", false).await;
+    {
+        let mut chaos = service.chaos_config.write().await;
+        chaos.enabled = true;
+        chaos.intensity = 1.0;
+        chaos.chaos_types = vec!["response_corruption".to_string()];
+    }
+    let response = service.handle_mcp_request(inference("void shrine chaos", false, true)).await.unwrap();
+    assert_eq!(response.metadata.chaos_type.as_deref(), Some("response_corruption"));
+    let breakdown = response.result.metrics.confidence_breakdown.unwrap();
+    assert_eq!(breakdown.corruption, 0.5);
+    // Halved before rounding to thousandths
+    assert!((response.result.metrics.confidence_score - clean / 2.0).abs() <= 0.001);
+}
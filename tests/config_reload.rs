@@ -0,0 +1,109 @@
+//! POST /api/admin/reload: the config file read again, its reloadable
+//! sections swapped in and the rest reported as skipped, or the whole file
+//! refused with the running config kept.
+
+mod support;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use support::TestServer;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::VoidShrineMCP;
+
+const STARTUP: &str = "[server]\nport = 4040\n[chaos]\nenabled = false\n[rate_limits]\ncapacity = 10\n";
+
+struct ConfigFile(PathBuf);
+
+impl ConfigFile {
+    fn new(name: &str, text: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("void-shrine-reload-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        Self(path)
+    }
+
+    fn write(&self, text: &str) {
+        std::fs::write(&self.0, text).unwrap();
+    }
+}
+
+impl Drop for ConfigFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+async fn started(file: &ConfigFile) -> (Arc<VoidShrineMCP>, TestServer) {
+    let service = Arc::new(VoidShrineMCP::new(&Config::load(Some(&file.0)).unwrap()).unwrap());
+    let server = TestServer::start(Arc::clone(&service)).await;
+    (service, server)
+}
+
+#[tokio::test]
+async fn reloadable_sections_apply_and_the_rest_waits_for_a_restart() {
+    let file = ConfigFile::new("apply", STARTUP);
+    let (service, server) = started(&file).await;
+
+    file.write(
+        "[server]\nport = 5050\n[chaos]\nenabled = false\nintensity = 0.4\n[rate_limits]\ncapacity = 3\n\
+         [templates.inline.terse]\nuser = \"{user_prompt}\"\n\
+         [[backends.backends]]\nname = \"spare\"\nkind = \"mock\"\n[[backends.routes]]\nmodel = \"spare-*\"\nbackend = \"spare\"\n",
+    );
+    let (status, report) = server.post("/api/admin/reload", &json!({})).await;
+    assert_eq!(status, 200, "{}", report);
+    assert_eq!(report["applied"], json!(["backends", "chaos", "rate_limits", "templates"]));
+    assert_eq!(report["skipped"], json!(["server.port"]));
+    assert_eq!(report["errors"], json!([]));
+    assert!(report["message"].as_str().unwrap().contains("server.port only change on restart"), "{}", report);
+
+    assert_eq!(service.handle_chaos_config().await.intensity, 0.4);
+    assert_eq!(service.rate_limiter.config().capacity, 3);
+    assert!(service.templates.names().contains(&"terse".to_string()));
+    let routed = |service: &VoidShrineMCP| service.handle_list_models().models.into_iter().map(|listing| listing.model.backend).collect::<Vec<_>>();
+    assert_eq!(routed(&service), ["spare"]);
+    let (_, version) = server.get("/api/version").await;
+    assert_eq!(version["config"], report["config"]);
+
+    // Reloading the same file again changes nothing
+    let (_, report) = server.post("/api/admin/reload", &json!({})).await;
+    assert_eq!((report["applied"].clone(), report["skipped"].clone()), (json!([]), json!([])));
+    assert_eq!(report["message"], "Nothing changed");
+}
+
+#[tokio::test]
+async fn an_invalid_file_is_refused_whole() {
+    let file = ConfigFile::new("refuse", STARTUP);
+    let (service, server) = started(&file).await;
+
+    file.write(
+        "[chaos]\nenabled = false\nintensity = 1.5\n[rate_limits]\ncapacity = 0\n\
+         [templates.inline.terse]\nuser = \"{user_prompt}\"\n",
+    );
+    let (status, report) = server.post("/api/admin/reload", &json!({})).await;
+    assert_eq!(status, 422, "{}", report);
+    let errors: Vec<&str> = report["errors"].as_array().unwrap().iter().map(|error| error.as_str().unwrap()).collect();
+    assert!(errors.iter().any(|error| error.starts_with("chaos.intensity")), "{:?}", errors);
+    assert!(errors.iter().any(|error| error.starts_with("rate_limits.capacity")), "{:?}", errors);
+    assert_eq!(report["applied"], json!([]));
+
+    assert_eq!(service.handle_chaos_config().await.intensity, 0.1);
+    assert_eq!(service.rate_limiter.config().capacity, 10);
+    assert!(!service.templates.names().contains(&"terse".to_string()));
+
+    // So are backends that can't be built, found before anything applies
+    file.write("[chaos]\nenabled = false\nintensity = 0.3\n[[backends.routes]]\nmodel = \"spare-*\"\nbackend = \"missing\"\n");
+    let (status, report) = server.post("/api/admin/reload", &json!({})).await;
+    assert_eq!(status, 422, "{}", report);
+    assert!(report["errors"][0].as_str().unwrap().contains("'missing'"), "{}", report);
+    assert_eq!(service.handle_chaos_config().await.intensity, 0.1);
+    assert!(service.handle_list_models().models.is_empty());
+}
+
+#[tokio::test]
+async fn a_server_without_a_file_has_nothing_to_reload() {
+    let server = TestServer::start(Arc::new(VoidShrineMCP::default())).await;
+    let (status, body) = server.post("/api/admin/reload", &json!({})).await;
+    assert_eq!(status, 501, "{}", body);
+    assert_eq!(body["error"], Value::from("not_configured"));
+}
@@ -0,0 +1,150 @@
+//! Responses screened by the content filters: blocked ones refused, counted
+//! and audited, PII masked in streams split mid-match, and verdicts from an
+//! external moderation endpoint.
+
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde_json::{json, Value};
+use void_shrine_mcp::audit::{AuditConfig, AuditLog, AuditQuery, AuditSink};
+use void_shrine_mcp::content_filter::{ContentFilterConfig, ContentFilterReport, ContentFilters, FilterAction};
+use void_shrine_mcp::llm_backend::{CompletionChunk, CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{InferenceEvent, MCPParams, MCPRequest};
+use void_shrine_mcp::VoidShrineMCP;
+use warp::Filter;
+
+/// Streams its answer in the pieces it was given
+struct Scripted(Vec<&'static str>);
+
+impl LLMBackend for Scripted {
+    fn name(&self) -> &str {
+        "scripted"
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        let text = self.0.concat();
+        Box::pin(async move {
+            Ok(CompletionOutput { text, prompt_tokens: 4, completion_tokens: 12, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+        })
+    }
+
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn complete_stream<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxStream<'a, anyhow::Result<CompletionChunk>> {
+        let deltas = self.0.iter().map(|piece| Ok(CompletionChunk::Delta(piece.to_string())));
+        stream::iter(deltas.collect::<Vec<_>>())
+            .chain(stream::once(async move { self.complete(prompt, params).await.map(CompletionChunk::Done) }))
+            .boxed()
+    }
+}
+
+async fn instance(answer: Vec<&'static str>, config: ContentFilterConfig) -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default().with_backend(Arc::new(Scripted(answer)));
+    let filters = ContentFilters::new(&config, Arc::clone(&service.metrics)).unwrap();
+    let service = service.with_content_filters(filters);
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+fn params() -> MCPParams {
+    serde_json::from_value(json!({
+        "agent_id": "oracle", "specialty": "general", "prompt": "speak", "max_tokens": 64,
+        "temperature": 0.2, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap()
+}
+
+fn inference() -> MCPRequest {
+    MCPRequest { method: "llm_inference".to_string(), params: params(), request_id: None, idempotency_key: None }
+}
+
+#[tokio::test]
+async fn blocked_responses_are_refused_counted_and_audited() {
+    let path = std::env::temp_dir().join(format!("void-shrine-filter-{}.jsonl", uuid::Uuid::new_v4()));
+    let config = ContentFilterConfig { deny_keywords: vec!["forbidden lore".to_string()], ..ContentFilterConfig::default() };
+    let service = VoidShrineMCP::default().with_backend(Arc::new(Scripted(vec!["Here is the Forbidden Lore you asked for"])));
+    let filters = ContentFilters::new(&config, Arc::clone(&service.metrics)).unwrap();
+    let audit = AuditConfig { sink: AuditSink::Jsonl, path: Some(path.clone()), ..AuditConfig::default() };
+    let service = service.with_content_filters(filters).with_audit(AuditLog::open(&audit).unwrap().unwrap());
+    service.chaos_config.write().await.enabled = false;
+
+    let response = service.handle_mcp_request(inference()).await.unwrap();
+    let report = ContentFilterReport { verdict: FilterAction::Block, rules: vec!["deny_keyword:forbidden lore".to_string()] };
+    assert_eq!(response.result.response, config.refusal);
+    assert_eq!(response.metadata.content_filter.as_ref(), Some(&report));
+    assert_eq!(serde_json::to_value(&response.metadata).unwrap()["content_filter"]["verdict"], json!("block"));
+    assert!(service
+        .handle_prometheus()
+        .await
+        .contains(r#"void_shrine_content_filter_verdicts_total{filter="patterns",verdict="block"} 1"#));
+
+    let audit = service.audit.as_ref().unwrap();
+    audit.flush().await;
+    let records = audit.query(AuditQuery::default()).await.unwrap();
+    assert_eq!(records[0].content_filter.as_ref(), Some(&report));
+    assert_eq!(records[0].response.as_deref(), Some(config.refusal.as_str()));
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn streamed_pii_is_masked_even_when_split_across_chunks() {
+    let answer = vec!["The keeper reads mail at kee", "per@shrine.exam", "ple.org and pays with 4111 1111 ", "1111 1111 at dusk."];
+    let config = ContentFilterConfig { redact_pii: true, stream_holdback_chars: 16, ..ContentFilterConfig::default() };
+    let service = instance(answer, config).await;
+
+    let mut events = service.stream_llm_inference(params(), None).unwrap();
+    let mut streamed = String::new();
+    let mut done = None;
+    while let Some(event) = events.next().await {
+        match event {
+            InferenceEvent::Delta { text } => streamed.push_str(&text),
+            InferenceEvent::Done { response, metadata, .. } => done = Some((response, metadata)),
+            _ => {}
+        }
+    }
+    let (response, metadata) = done.unwrap();
+    assert_eq!(streamed, "The keeper reads mail at [redacted] and pays with [redacted] at dusk.");
+    assert_eq!(response, streamed);
+    assert_eq!(metadata.content_filter.unwrap().rules, ["pii:email", "pii:card_number"]);
+
+    // The same answer unstreamed is masked the same way
+    let response = service.handle_mcp_request(inference()).await.unwrap();
+    assert_eq!(response.result.response, streamed);
+}
+
+/// A moderation endpoint masking "ember" and blocking "void", or failing
+async fn moderation(fail: bool) -> String {
+    let route = warp::post().and(warp::body::json()).map(move |body: Value| {
+        let text = body["text"].as_str().unwrap_or_default();
+        let verdict = match text.find("ember") {
+            _ if fail => return warp::reply::with_status(warp::reply::json(&json!({})), warp::http::StatusCode::BAD_GATEWAY),
+            _ if text.contains("void") => json!({ "verdict": "block", "rule": "void_talk" }),
+            Some(at) => json!({ "verdict": "redact", "rule": "embers", "spans": [[at, at + 5]] }),
+            None => json!({ "verdict": "pass" }),
+        };
+        warp::reply::with_status(warp::reply::json(&verdict), warp::http::StatusCode::OK)
+    });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    format!("http://{}/moderate", addr)
+}
+
+#[tokio::test]
+async fn the_moderation_endpoint_has_its_say() {
+    for (answer, fail, expected, verdict) in [
+        ("Tend the ember gently", false, "Tend the [redacted] gently", Some((FilterAction::Redact, "moderation:embers"))),
+        ("Stare into the void", false, "This response was withheld by the content filter.", Some((FilterAction::Block, "moderation:void_talk"))),
+        ("Sweep the hall", false, "Sweep the hall", None),
+        ("Sweep the hall", true, "This response was withheld by the content filter.", Some((FilterAction::Block, "moderation:unavailable"))),
+    ] {
+        let config = ContentFilterConfig { moderation_url: Some(moderation(fail).await), ..ContentFilterConfig::default() };
+        let service = instance(vec![answer], config).await;
+        let response = service.handle_mcp_request(inference()).await.unwrap();
+        assert_eq!(response.result.response, expected);
+        let report = response.metadata.content_filter.map(|report| (report.verdict, report.rules[0].clone()));
+        assert_eq!(report, verdict.map(|(action, rule)| (action, rule.to_string())), "{}", answer);
+    }
+}
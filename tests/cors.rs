@@ -0,0 +1,99 @@
+//! The configured CORS policy in front of authenticated routes: preflights
+//! answered for allowed origins without a key, refused for others.
+
+use std::sync::Arc;
+
+use void_shrine_mcp::auth::{ApiKey, Auth};
+use void_shrine_mcp::config::CorsConfig;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::http::StatusCode;
+use warp::Filter;
+
+const DASHBOARD: &str = "https://dashboard.shrine.example";
+
+fn policy() -> CorsConfig {
+    CorsConfig { allowed_origins: vec![DASHBOARD.to_string()], allow_credentials: true, ..CorsConfig::default() }
+}
+
+/// The admin cache and Prometheus routes behind a key, wrapped as the server wraps them
+fn routes(cors: &CorsConfig) -> impl Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible> + Clone {
+    let auth = Auth::new(vec![ApiKey::parse("ops:ops-secret:inference+admin").unwrap()]).unwrap();
+    let service = Arc::new(VoidShrineMCP::default());
+    let metrics = warp::path("metrics").and(warp::get()).map(|| "void_shrine_up 1\n");
+    auth.filter()
+        .and(api::cache_route(service).or(metrics))
+        .recover(api::recover)
+        .with(api::cors(cors))
+        .recover(api::recover)
+}
+
+fn header<'a>(response: &'a warp::http::Response<warp::hyper::body::Bytes>, name: &str) -> Option<&'a str> {
+    response.headers().get(name).map(|value| value.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn preflights_from_allowed_origins_are_answered_for_every_route() {
+    let routes = routes(&policy());
+    for (path, method) in [("/api/cache", "DELETE"), ("/metrics", "GET")] {
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path(path)
+            .header("origin", DASHBOARD)
+            .header("access-control-request-method", method)
+            .header("access-control-request-headers", "authorization, content-type")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        assert_eq!(header(&response, "access-control-allow-origin"), Some(DASHBOARD));
+        assert_eq!(header(&response, "access-control-allow-credentials"), Some("true"));
+        assert_eq!(header(&response, "access-control-max-age"), Some("600"));
+        let allowed = header(&response, "access-control-allow-headers").unwrap();
+        assert!(allowed.contains("authorization") && allowed.contains("content-type"), "{}", allowed);
+    }
+
+    // The actual request still needs its key, and its error is readable cross-origin
+    let response = warp::test::request().method("DELETE").path("/api/cache").header("origin", DASHBOARD).reply(&routes).await;
+    assert_eq!((response.status(), header(&response, "access-control-allow-origin")), (StatusCode::UNAUTHORIZED, Some(DASHBOARD)));
+    let response = warp::test::request()
+        .method("DELETE")
+        .path("/api/cache")
+        .header("origin", DASHBOARD)
+        .header("authorization", "Bearer ops-secret")
+        .reply(&routes)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn other_origins_are_refused() {
+    let routes = routes(&policy());
+    let preflight = warp::test::request()
+        .method("OPTIONS")
+        .path("/metrics")
+        .header("origin", "https://evil.example")
+        .header("access-control-request-method", "GET")
+        .reply(&routes)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(preflight.body()).unwrap();
+    assert_eq!((preflight.status(), body["error"].as_str()), (StatusCode::FORBIDDEN, Some("cors_forbidden")));
+    assert_eq!(header(&preflight, "access-control-allow-origin"), None);
+    let response = warp::test::request().method("GET").path("/metrics").header("origin", "https://evil.example").reply(&routes).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Same-origin and non-browser clients send no Origin and are unaffected
+    let response = warp::test::request().method("GET").path("/metrics").header("authorization", "Bearer ops-secret").reply(&routes).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn policies_are_validated() {
+    assert!(CorsConfig::default().validate().is_empty());
+    assert!(policy().validate().is_empty());
+    let bad = CorsConfig {
+        allowed_origins: vec!["*".to_string(), "dashboard.shrine.example".to_string()],
+        allowed_methods: vec!["GE T".to_string()],
+        allow_credentials: true,
+        ..CorsConfig::default()
+    };
+    assert_eq!(bad.validate().len(), 3);
+}
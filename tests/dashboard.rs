@@ -0,0 +1,106 @@
+//! GET /api/dashboard: its shape pinned by a snapshot the explorer UI can
+//! rely on, and its numbers read from the same counters enforcement uses.
+//! `UPDATE_SNAPSHOTS=1 cargo test --test dashboard` rewrites the snapshot
+//! after a deliberate change to the contract.
+
+mod support;
+
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use support::{epoch, fixture_rag, service, ScriptedBackend, TestServer};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::llm_backend::BackendError;
+use void_shrine_mcp::rate_limit::RateLimitConfig;
+
+const SNAPSHOT: &str = "tests/snapshots/dashboard.json";
+
+/// Blanks what depends on wall-clock time or the build, and orders agents by
+/// id since their load order does too
+fn normalized(mut dashboard: Value) -> Value {
+    for volatile in ["started_at", "uptime_secs", "version"] {
+        dashboard[volatile] = json!("<volatile>");
+    }
+    for window in dashboard["activity"].as_array_mut().unwrap() {
+        window["latency"] = json!("<volatile>");
+    }
+    let agents = dashboard["agents"].as_array_mut().unwrap();
+    for agent in agents.iter_mut() {
+        agent["current_load"] = json!("<volatile>");
+        agent["throttle"]["agent_load"] = json!("<volatile>");
+    }
+    agents.sort_by_key(|agent| agent["agent_id"].as_str().unwrap().to_string());
+    dashboard
+}
+
+fn inference(agent_id: &str) -> Value {
+    json!({ "method": "llm_inference", "params": { "agent_id": agent_id, "prompt": "Where do hermit crabs shelter?", "max_tokens": 64 } })
+}
+
+#[tokio::test]
+async fn the_dashboard_matches_its_snapshot() {
+    let backend = ScriptedBackend::new()
+        .reply("In tide pools [1].")
+        .fail(BackendError::Unavailable("down for maintenance".to_string()))
+        .reply("Under rocks.");
+    // Buckets refill by the wall clock; too slowly here to show in the snapshot
+    let limits = RateLimitConfig { refill_per_sec: 0.001, ..RateLimitConfig::default() };
+    let service = service(Arc::new(backend), Arc::new(ManualClock::new(epoch()))).with_rate_limits(limits);
+    *service.rag_engine.write().await = Some(fixture_rag().await);
+    let service = Arc::new(service);
+    let server = TestServer::start(Arc::clone(&service)).await;
+
+    assert_eq!(server.post("/api/mcp", &inference("scout")).await.0, 200);
+    assert_eq!(server.post("/api/mcp", &inference("scout")).await.0, 502);
+    assert_eq!(server.post("/api/mcp", &inference("keeper")).await.0, 200);
+    {
+        let mut chaos = service.chaos_config.write().await;
+        chaos.enabled = true;
+        chaos.intensity = 1.0;
+        chaos.chaos_types = vec!["error_injection".to_string()];
+    }
+    assert_eq!(server.post("/api/mcp", &inference("tinker")).await.0, 500);
+    service.chaos_config.write().await.enabled = false;
+    let report = json!({ "agent_id": "scout", "response_time": 5000, "token_count": 40, "success": true });
+    assert_eq!(server.post("/api/scaling", &report).await.0, 200);
+
+    let (status, dashboard) = server.get("/api/dashboard?top=5").await;
+    assert_eq!(status, 200, "{}", dashboard);
+
+    // Busiest first, whatever the loads came to
+    let loads: Vec<f64> = dashboard["agents"].as_array().unwrap().iter().map(|agent| agent["current_load"].as_f64().unwrap()).collect();
+    assert!(loads.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", loads);
+    let (_, metrics) = server.get("/api/metrics").await;
+    assert_eq!(dashboard["chaos"]["events_by_type"]["error_injection"], metrics["server"]["chaos_events"]);
+
+    let actual = normalized(dashboard);
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, serde_json::to_string_pretty(&actual).unwrap() + "\n").unwrap();
+    }
+    let expected: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert!(
+        actual == expected,
+        "GET /api/dashboard no longer matches {}; if the change is meant for the UI, rerun with UPDATE_SNAPSHOTS=1\n{}",
+        SNAPSHOT,
+        serde_json::to_string_pretty(&actual).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn top_limits_agents_and_scaling_decisions() {
+    let service = Arc::new(service(Arc::new(ScriptedBackend::new()), Arc::new(ManualClock::new(epoch()))));
+    let server = TestServer::start(Arc::clone(&service)).await;
+    for agent_id in ["a", "b", "c"] {
+        let report = json!({ "agent_id": agent_id, "response_time": 50, "success": true });
+        server.post("/api/scaling", &report).await;
+    }
+
+    let (_, dashboard) = server.get("/api/dashboard?top=2").await;
+    assert_eq!((dashboard["agents"].as_array().unwrap().len(), dashboard["scaling"].as_array().unwrap().len()), (2, 2));
+    let (_, dashboard) = server.get("/api/dashboard").await;
+    assert_eq!(dashboard["agents"].as_array().unwrap().len(), 3);
+    assert_eq!(dashboard["rag"], Value::Null);
+    assert_eq!(dashboard["activity"][0]["error_rate"], 0.0);
+}
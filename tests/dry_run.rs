@@ -0,0 +1,108 @@
+//! `dry_run`: the prompt a real call would send, with the chunks it kept and
+//! left out and its tokens per section, without the backend being called.
+
+mod support;
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use support::{epoch, fixture_rag, service, ScriptedBackend};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::mcp_server::{AgentMetricsParams, MCPError, MCPRequest, PromptPreview};
+use void_shrine_mcp::rate_limit::RateLimitConfig;
+use void_shrine_mcp::VoidShrineMCP;
+
+fn inference(params: Value) -> MCPRequest {
+    let mut all = json!({ "agent_id": "previewer", "prompt": "Where do hermit crabs shelter?", "max_tokens": 64 });
+    all.as_object_mut().unwrap().extend(params.as_object().unwrap().clone());
+    MCPRequest { method: "llm_inference".to_string(), params: serde_json::from_value(all).unwrap(), request_id: None, idempotency_key: None }
+}
+
+async fn fixture_service(backend: &Arc<ScriptedBackend>) -> VoidShrineMCP {
+    let service = service(Arc::clone(backend), Arc::new(ManualClock::new(epoch())));
+    *service.rag_engine.write().await = Some(fixture_rag().await);
+    service
+}
+
+async fn preview(service: &VoidShrineMCP, params: Value) -> PromptPreview {
+    let response = service.handle_mcp_request(inference(params)).await.unwrap();
+    assert_eq!(response.result.response, "");
+    response.result.prompt_preview.unwrap()
+}
+
+#[tokio::test]
+async fn previews_the_prompt_a_real_call_sends() {
+    let backend = Arc::new(ScriptedBackend::new().reply("In tide pools [1]."));
+    let service = fixture_service(&backend).await;
+
+    let previewed = preview(&service, json!({ "dry_run": true, "moral_recentering": "on" })).await;
+    assert!(backend.prompts().is_empty());
+    assert!(previewed.user.contains("Anemones and hermit crabs"));
+    assert_eq!(previewed.chunks[0].citation.document_id, "tide-pools");
+    assert!(previewed.chunks.iter().all(|chunk| chunk.included));
+    let tokens = &previewed.tokens;
+    assert!(tokens.user_prompt > 0 && tokens.knowledge > 0);
+    assert!(tokens.total >= tokens.user_prompt + tokens.framing + tokens.knowledge + tokens.history);
+    assert_eq!(tokens.remaining, tokens.context_window - tokens.total - tokens.max_tokens);
+
+    let metrics = service.handle_agent_metrics(&Tenancy::All, "previewer", &AgentMetricsParams::default()).unwrap();
+    assert_eq!((metrics.prompt_tokens, metrics.completion_tokens), (0, 0));
+
+    let response = service.handle_mcp_request(inference(json!({ "moral_recentering": "on" }))).await.unwrap();
+    assert_eq!(response.result.response, "In tide pools [1].");
+    assert!(response.result.prompt_preview.is_none());
+    let sent = &backend.prompts()[0];
+    assert_eq!((&sent.system, &sent.user), (&previewed.system, &previewed.user));
+}
+
+#[tokio::test]
+async fn reports_chunks_left_out_of_a_small_context_window() {
+    let backend = Arc::new(ScriptedBackend::new());
+    let service = fixture_service(&backend).await;
+    let roomy = preview(&service, json!({ "dry_run": true, "prompt": "tide pools lichen forge bellows" })).await;
+    assert!(roomy.chunks.len() > 1);
+
+    // Room for the prompt but only some of its knowledge
+    let window = roomy.tokens.total - roomy.tokens.knowledge / 2 + roomy.tokens.max_tokens;
+    let tight = preview(&service, json!({
+        "dry_run": true, "prompt": "tide pools lichen forge bellows", "context_window": window
+    }))
+    .await;
+    assert_eq!(tight.chunks.len(), roomy.chunks.len());
+    assert!(tight.chunks.iter().any(|chunk| !chunk.included), "{:?}", tight.chunks);
+    assert!(tight.tokens.knowledge < roomy.tokens.knowledge);
+    assert!(backend.prompts().is_empty());
+}
+
+#[tokio::test]
+async fn conditions_apply_unless_skipped() {
+    let backend = Arc::new(ScriptedBackend::new());
+    let service = fixture_service(&backend)
+        .await
+        .with_rate_limits(RateLimitConfig { capacity: 1, refill_per_sec: 0.001, ..RateLimitConfig::default() });
+    {
+        let mut chaos = service.chaos_config.write().await;
+        chaos.enabled = true;
+        chaos.intensity = 1.0;
+        chaos.chaos_types = vec!["error_injection".to_string()];
+    }
+
+    let failure = service.handle_mcp_request(inference(json!({ "dry_run": true }))).await.unwrap_err();
+    assert!(matches!(failure.error, MCPError::ChaosInjected { .. }), "{}", failure);
+    let failure = service.handle_mcp_request(inference(json!({ "dry_run": true }))).await.unwrap_err();
+    assert!(matches!(failure.error, MCPError::RateLimited { .. }), "{}", failure);
+
+    let skipping = json!({ "dry_run": true, "dry_run_skip_conditions": true });
+    let response = service.handle_mcp_request(inference(skipping)).await.unwrap();
+    assert!(!response.metadata.chaos_applied);
+    assert!(response.result.prompt_preview.is_some());
+    assert!(backend.prompts().is_empty());
+}
+
+#[tokio::test]
+async fn streaming_refuses_a_dry_run() {
+    let service = Arc::new(fixture_service(&Arc::new(ScriptedBackend::new())).await);
+    let error = service.stream_llm_inference(inference(json!({ "dry_run": true })).params, None).err().unwrap();
+    assert!(matches!(&error, MCPError::InvalidFields(fields) if fields[0].field == "dry_run"), "{}", error);
+}
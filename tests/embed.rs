@@ -0,0 +1,110 @@
+//! POST /api/embed: vectors from the provider indexing uses, its limits, and
+//! the usage charged to agents.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use void_shrine_mcp::api;
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::mcp_server::{AgentMetricsParams, ParamLimits};
+use void_shrine_mcp::rag_engine::EmbeddingProvider;
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+/// One-hot vectors of text length, counting the calls made to it
+struct LengthEmbedder {
+    calls: AtomicUsize,
+}
+
+impl EmbeddingProvider for LengthEmbedder {
+    fn model(&self) -> &str {
+        "length-8"
+    }
+
+    fn dimension(&self) -> usize {
+        8
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, anyhow::Result<Vec<Vec<f32>>>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let vectors = texts
+            .iter()
+            .map(|text| {
+                let mut vector = vec![0.0; 8];
+                vector[text.len() % 8] = 1.0;
+                vector
+            })
+            .collect();
+        Box::pin(async move { Ok(vectors) })
+    }
+}
+
+async fn service(provider: Option<Arc<LengthEmbedder>>) -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default()
+        .with_param_limits(ParamLimits { max_embed_texts: 100, max_embed_text_bytes: 64, ..Default::default() });
+    let mut builder = RAGEngine::builder();
+    if let Some(provider) = provider {
+        builder = builder.embedding_provider(provider);
+    }
+    *service.rag_engine.write().await = Some(builder.build().await.unwrap());
+    Arc::new(service)
+}
+
+async fn embed(service: &Arc<VoidShrineMCP>, body: Value) -> (u16, Value) {
+    let routes = api::embed_route(Arc::clone(service)).recover(api::recover);
+    let response = warp::test::request().method("POST").path("/api/embed").json(&body).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn embeds_with_the_indexing_provider_in_its_batches() {
+    let provider = Arc::new(LengthEmbedder { calls: AtomicUsize::new(0) });
+    let service = service(Some(Arc::clone(&provider))).await;
+
+    let (status, body) = embed(&service, json!({ "texts": ["void", "shrine"], "model": "length-8", "agent_id": "embedder" })).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!((body["model"].as_str(), body["dimensions"].as_u64()), (Some("length-8"), Some(8)));
+    assert_eq!(body["embeddings"][0][4], 1.0);
+    assert_eq!(body["embeddings"][1][6], 1.0);
+    assert_eq!(body["token_counts"], json!([1, 1]));
+    assert_eq!(body["total_tokens"], 2);
+
+    // More texts than one provider call takes go in several
+    let calls = provider.calls.load(Ordering::SeqCst);
+    let texts: Vec<String> = (0..100).map(|i| format!("text {}", i)).collect();
+    let (status, body) = embed(&service, json!({ "texts": texts, "agent_id": "embedder" })).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["embeddings"].as_array().unwrap().len(), 100);
+    assert_eq!(provider.calls.load(Ordering::SeqCst) - calls, 2);
+
+    let metrics = service.handle_agent_metrics(&Tenancy::All, "embedder", &AgentMetricsParams::default()).unwrap();
+    assert_eq!(metrics.embedding_requests, 2);
+    assert_eq!(metrics.embedding_tokens, 2 + body["total_tokens"].as_u64().unwrap());
+}
+
+#[tokio::test]
+async fn limits_and_models_are_enforced() {
+    let service = service(Some(Arc::new(LengthEmbedder { calls: AtomicUsize::new(0) }))).await;
+
+    let (status, body) = embed(&service, json!({ "texts": [] })).await;
+    assert_eq!((status, body["error"].as_str()), (400, Some("invalid_params")));
+    let too_many: Vec<&str> = vec!["a"; 101];
+    let (status, body) = embed(&service, json!({ "texts": too_many })).await;
+    assert_eq!((status, body["fields"][0]["value"].as_u64()), (400, Some(101)));
+    let (status, body) = embed(&service, json!({ "texts": ["fine", "x".repeat(65)] })).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("texts[1]")));
+    let (status, body) = embed(&service, json!({ "texts": ["void"], "model": "another-model" })).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("model")));
+}
+
+#[tokio::test]
+async fn unavailable_without_a_provider() {
+    let (status, body) = embed(&service(None).await, json!({ "texts": ["void"] })).await;
+    assert_eq!((status, body["error"].as_str()), (503, Some("embeddings_unavailable")));
+
+    let service = Arc::new(VoidShrineMCP::default());
+    let (status, body) = embed(&service, json!({ "texts": ["void"] })).await;
+    assert_eq!((status, body["error"].as_str()), (503, Some("embeddings_unavailable")));
+}
@@ -0,0 +1,153 @@
+//! The error taxonomy as clients see it: each `MCPError` variant's code,
+//! HTTP status and serialized body, pinned so they only change on purpose.
+
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+use void_shrine_mcp::llm_backend::BackendError;
+use void_shrine_mcp::mcp_server::{ErrorResponse, FailedRequest, FieldError, MCPError, TimeoutStage};
+use void_shrine_mcp::quota::{QuotaMetric, QuotaRule, QuotaStanding};
+use void_shrine_mcp::usage::UsageGranularity;
+
+fn body(error: &MCPError) -> Value {
+    serde_json::to_value(ErrorResponse::from(error)).unwrap()
+}
+
+fn quota_exceeded() -> MCPError {
+    let rule = QuotaRule {
+        id: "daily-tokens".to_string(),
+        agent_id: Some("spender".to_string()),
+        tenant: None,
+        metric: QuotaMetric::Tokens,
+        limit: 1000,
+        window: UsageGranularity::Day,
+    };
+    let standing = QuotaStanding { rule, consumed: 990, resets_at: Utc.with_ymd_and_hms(2026, 1, 2, 0, 0, 0).unwrap() };
+    MCPError::QuotaExceeded { standing: Box::new(standing), retry_after: Duration::from_secs(3600) }
+}
+
+#[test]
+fn every_failure_has_a_stable_code_status_and_body() {
+    let field = FieldError { field: "temperature".to_string(), constraint: "between 0 and 2".to_string(), value: json!(97.0) };
+    let cases = [
+        (
+            MCPError::InvalidFields(vec![field]),
+            400,
+            json!({
+                "error": "invalid_params",
+                "message": "Invalid params: temperature must be between 0 and 2 (got 97.0)",
+                "fields": [{ "field": "temperature", "constraint": "between 0 and 2", "value": 97.0 }],
+            }),
+        ),
+        (
+            MCPError::UnsupportedMethod("llm_inferense".to_string()),
+            400,
+            json!({
+                "error": "unsupported_method",
+                "message": "Unsupported method: llm_inferense; did you mean llm_inference?",
+                "method": "llm_inferense",
+                "supported_methods": ["llm_inference", "rag_query", "rag_answer"],
+                "did_you_mean": "llm_inference",
+            }),
+        ),
+        (
+            MCPError::RagUnavailable,
+            503,
+            json!({ "error": "rag_unavailable", "message": "RAG engine not initialized" }),
+        ),
+        (
+            MCPError::Backend(BackendError::Timeout(Duration::from_secs(30))),
+            504,
+            json!({ "error": "backend_timeout", "message": "backend did not respond within 30000ms" }),
+        ),
+        (
+            MCPError::Backend(BackendError::Status { status: 500, message: "upstream exploded".to_string() }),
+            502,
+            json!({
+                "error": "backend_error",
+                "message": "backend responded with HTTP 500: upstream exploded",
+                "upstream_status": 500,
+            }),
+        ),
+        (
+            MCPError::CircuitOpen { backend: "ollama".to_string(), retry_after: Duration::from_millis(2500) },
+            503,
+            json!({
+                "error": "backend_circuit_open",
+                "message": "Backend 'ollama' is failing and cut off; retry in 2500 ms",
+                "retry_after_ms": 2500,
+            }),
+        ),
+        (
+            MCPError::Throttled { agent_id: "busy".to_string(), retry_after: Duration::from_millis(1000) },
+            429,
+            json!({
+                "error": "throttled",
+                "message": "Agent 'busy' is under too much load; retry in 1000 ms",
+                "retry_after_ms": 1000,
+            }),
+        ),
+        (
+            quota_exceeded(),
+            429,
+            json!({
+                "error": "quota_exceeded",
+                "message": "Quota 'daily-tokens' allows 1000 tokens per day and 990 are used; it resets at 2026-01-02T00:00:00+00:00",
+                "retry_after_ms": 3_600_000,
+                "quota": {
+                    "rule": { "id": "daily-tokens", "agent_id": "spender", "metric": "tokens", "limit": 1000, "window": "day" },
+                    "consumed": 990,
+                    "resets_at": "2026-01-02T00:00:00Z",
+                },
+            }),
+        ),
+        (
+            MCPError::Overloaded { retry_after: Duration::from_millis(1000) },
+            503,
+            json!({
+                "error": "overloaded",
+                "message": "Server is handling all the requests it can; retry in 1000 ms",
+                "retry_after_ms": 1000,
+            }),
+        ),
+        (
+            MCPError::Cancelled,
+            499,
+            json!({ "error": "cancelled", "message": "Request cancelled by the client" }),
+        ),
+        (
+            MCPError::DeadlineExceeded { stage: TimeoutStage::Retrieval, budget: Duration::from_millis(800) },
+            504,
+            json!({ "error": "deadline_exceeded", "message": "Deadline exceeded in retrieval after 800 ms", "stage": "retrieval" }),
+        ),
+        (
+            MCPError::Internal(anyhow::anyhow!("disk full")),
+            500,
+            json!({ "error": "internal_error", "message": "disk full" }),
+        ),
+    ];
+
+    for (error, status, expected) in cases {
+        assert_eq!(error.http_status(), status, "{}", error.code());
+        assert_eq!(body(&error), expected, "{}", error.code());
+        assert_eq!(expected["error"], error.code());
+    }
+}
+
+#[test]
+fn anyhow_errors_keep_their_variant() {
+    let wrapped = MCPError::from(anyhow::Error::from(MCPError::RagUnavailable));
+    assert_eq!(wrapped.code(), "rag_unavailable");
+    let backend = MCPError::from(anyhow::Error::from(BackendError::RateLimited { retry_after: Some(Duration::from_secs(2)), message: "slow down".to_string() }));
+    assert_eq!((backend.code(), backend.http_status(), backend.upstream_status()), ("backend_rate_limited", 503, Some(429)));
+    assert_eq!(backend.retry_after(), Some(Duration::from_secs(2)));
+    assert_eq!(MCPError::from(anyhow::anyhow!("anything else")).code(), "internal_error");
+}
+
+#[test]
+fn failed_requests_add_their_id() {
+    let failure = FailedRequest { request_id: Some("req-7".to_string()), error: MCPError::Cancelled };
+    let body = serde_json::to_value(ErrorResponse::from(&failure)).unwrap();
+    assert_eq!(body, json!({ "error": "cancelled", "message": "Request cancelled by the client", "request_id": "req-7" }));
+}
@@ -0,0 +1,133 @@
+//! Fallback chains: a logical model tried as each of its models in turn,
+//! moving on only for backend failures and only while time is left.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde_json::json;
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::llm_backend::{
+    BackendError, BackendRegistry, CompletionChunk, CompletionOutput, FallbackChain, FinishReason, LLMBackend, Prompt, RetryConfig,
+};
+use void_shrine_mcp::mcp_server::{InferenceEvent, MCPParams, MCPRequest, MetricsParams};
+use void_shrine_mcp::VoidShrineMCP;
+
+/// Fails every call with `error`, or hangs when there is none and `hang` is
+/// set; otherwise answers with its name
+struct Upstream {
+    name: &'static str,
+    error: Option<BackendError>,
+    hang: bool,
+    calls: AtomicU32,
+}
+
+impl Upstream {
+    fn new(name: &'static str, error: Option<BackendError>, hang: bool) -> Arc<Self> {
+        Arc::new(Self { name, error, hang, calls: AtomicU32::new(0) })
+    }
+
+    async fn call(&self) -> anyhow::Result<CompletionOutput> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if self.hang {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+        if let Some(error) = &self.error {
+            return Err(error.clone().into());
+        }
+        Ok(CompletionOutput { text: self.name.to_string(), prompt_tokens: 4, completion_tokens: 1, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+    }
+}
+
+impl LLMBackend for Upstream {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        Box::pin(self.call())
+    }
+
+    fn complete_stream<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxStream<'a, anyhow::Result<CompletionChunk>> {
+        stream::once(async move { self.call().await.map(CompletionChunk::Done) }).boxed()
+    }
+}
+
+fn params(timeout_ms: u64) -> MCPParams {
+    serde_json::from_value(json!({
+        "agent_id": "persistent", "model": "careful", "specialty": "research", "prompt": "care ethics",
+        "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096, "timeout_ms": timeout_ms
+    }))
+    .unwrap()
+}
+
+fn request(timeout_ms: u64) -> MCPRequest {
+    MCPRequest { method: "llm_inference".to_string(), params: params(timeout_ms), request_id: None, idempotency_key: None }
+}
+
+/// "careful" tries claude, then the local llama
+async fn chained(cloud: Arc<Upstream>, local: Arc<Upstream>) -> VoidShrineMCP {
+    let mut backends = BackendRegistry::new();
+    backends.register("cloud", cloud);
+    backends.register("local", local);
+    backends.route("claude-*", "cloud").unwrap();
+    backends.route("llama*", "local").unwrap();
+    let models = vec!["claude-3-5-sonnet-latest".to_string(), "llama3.2".to_string()];
+    backends.chain(FallbackChain { model: "careful".to_string(), models }).unwrap();
+    let retries = RetryConfig { max_attempts: 1, ..RetryConfig::default() };
+    let service = VoidShrineMCP::default().with_backends(backends).with_retries(retries);
+    service.chaos_config.write().await.enabled = false;
+    service
+}
+
+#[tokio::test]
+async fn backend_failures_fall_back_down_the_chain() {
+    let outage = BackendError::Status { status: 503, message: "overloaded".to_string() };
+    let (cloud, local) = (Upstream::new("cloud", Some(outage), false), Upstream::new("local", None, false));
+    let service = Arc::new(chained(Arc::clone(&cloud), Arc::clone(&local)).await);
+
+    let response = service.handle_mcp_request(request(5_000)).await.unwrap();
+    assert_eq!(response.result.response, "local");
+    assert_eq!((response.metadata.served_model.as_deref(), response.metadata.fallback_depth), (Some("llama3.2"), 1));
+    assert_eq!(response.result.metrics.attempts, 2);
+
+    let events: Vec<InferenceEvent> = service.stream_llm_inference(params(5_000), None).unwrap().collect().await;
+    match events.last() {
+        Some(InferenceEvent::Done { response, metadata, .. }) => {
+            assert_eq!(response, "local");
+            assert_eq!((metadata.served_model.as_deref(), metadata.fallback_depth), (Some("llama3.2"), 1));
+        }
+        other => panic!("stream ended with {:?}", other),
+    }
+
+    assert_eq!((cloud.calls.load(Ordering::SeqCst), local.calls.load(Ordering::SeqCst)), (2, 2));
+    let server = service.handle_metrics(&Tenancy::All, &MetricsParams::default()).server;
+    assert_eq!(server.fallbacks_by_chain.get("careful"), Some(&2));
+    assert!(service.metrics.render().contains("void_shrine_fallbacks_total{chain=\"careful\"} 2"));
+}
+
+#[tokio::test]
+async fn refusals_of_the_request_do_not_fall_back() {
+    let refusal = BackendError::Status { status: 400, message: "prompt too long".to_string() };
+    let (cloud, local) = (Upstream::new("cloud", Some(refusal), false), Upstream::new("local", None, false));
+    let service = chained(cloud, Arc::clone(&local)).await;
+
+    let failure = service.handle_mcp_request(request(5_000)).await.unwrap_err();
+    assert_eq!(failure.error.code(), "backend_error");
+    assert_eq!(local.calls.load(Ordering::SeqCst), 0);
+    assert!(service.handle_metrics(&Tenancy::All, &MetricsParams::default()).server.fallbacks_by_chain.is_empty());
+}
+
+#[tokio::test]
+async fn the_chain_stops_at_the_request_deadline() {
+    let (cloud, local) = (Upstream::new("cloud", None, true), Upstream::new("local", None, false));
+    let service = chained(cloud, Arc::clone(&local)).await;
+
+    let started = Instant::now();
+    let failure = service.handle_mcp_request(request(200)).await.unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+    assert_eq!(failure.error.code(), "deadline_exceeded");
+    assert_eq!(local.calls.load(Ordering::SeqCst), 0);
+}
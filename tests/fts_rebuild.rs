@@ -0,0 +1,80 @@
+//! `POST /api/rag/rebuild-fts` refills the full-text index in the background;
+//! `GET /api/rag/tasks/{id}` follows it, and keyword searches meanwhile fall
+//! back or are refused as `index_rebuilding`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::rag_engine::{FtsRebuildPolicy, RAGEngine};
+use void_shrine_mcp::rag_tasks::{RagTaskView, TaskStatus};
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::http::StatusCode;
+use warp::Filter;
+
+async fn service(policy: FtsRebuildPolicy) -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default();
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::builder().during_fts_rebuild(policy).build().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    Arc::new(service)
+}
+
+fn rag_query() -> MCPRequest {
+    let params = json!({ "agent_id": "librarian", "prompt": "agent coordination" });
+    MCPRequest { method: "rag_query".to_string(), params: serde_json::from_value(params).unwrap(), request_id: None, idempotency_key: None }
+}
+
+#[tokio::test]
+async fn rebuilds_run_in_the_background_and_report_progress() {
+    let service = service(FtsRebuildPolicy::Fallback).await;
+    let routes = api::rag_admin_routes(Arc::clone(&service)).recover(api::recover);
+    let chunks = service.handle_rag_stats(None).await.unwrap().chunk_count;
+
+    let response = warp::test::request().method("POST").path("/api/rag/rebuild-fts").reply(&routes).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let started: RagTaskView = serde_json::from_slice(response.body()).unwrap();
+
+    let finished = loop {
+        let response = warp::test::request().path(&format!("/api/rag/tasks/{}", started.task_id)).reply(&routes).await;
+        let task: RagTaskView = serde_json::from_slice(response.body()).unwrap();
+        if task.status != TaskStatus::Running {
+            break task;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    };
+    assert_eq!(finished.status, TaskStatus::Completed, "{:?}", finished.errors);
+    assert_eq!((finished.processed, finished.total), (chunks, chunks));
+    assert!(finished.finished_at.is_some() && finished.errors.is_empty());
+
+    let result = service.handle_mcp_request(rag_query()).await.unwrap().result;
+    assert!(result.citations.unwrap().iter().any(|citation| citation.document_id == "agent_coordination"));
+
+    let missing = warp::test::request().path("/api/rag/tasks/nope").reply(&routes).await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    let body: Value = serde_json::from_slice(missing.body()).unwrap();
+    assert_eq!(body["error"], "task_not_found");
+}
+
+#[tokio::test]
+async fn searches_during_a_rebuild_follow_the_policy() {
+    let fallback = service(FtsRebuildPolicy::Fallback).await;
+    fallback.rag_engine.write().await.as_mut().unwrap().begin_fts_rebuild().unwrap();
+    let result = fallback.handle_mcp_request(rag_query()).await.unwrap().result;
+    assert!(!result.citations.unwrap().is_empty());
+
+    let refuse = service(FtsRebuildPolicy::Refuse).await;
+    refuse.rag_engine.write().await.as_mut().unwrap().begin_fts_rebuild().unwrap();
+    let failure = refuse.handle_mcp_request(rag_query()).await.unwrap_err();
+    assert_eq!((failure.error.code(), failure.error.http_status()), ("index_rebuilding", 503));
+    assert!(failure.error.to_string().starts_with("Full-text index is rebuilding (0 of "), "{}", failure.error);
+
+    // Starting a rebuild over HTTP finishes the one under way
+    let task = refuse.handle_rebuild_fts(None).await.unwrap();
+    while refuse.handle_rag_task(&task.task_id).unwrap().status == TaskStatus::Running {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert!(refuse.handle_mcp_request(rag_query()).await.is_ok());
+}
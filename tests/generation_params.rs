@@ -0,0 +1,47 @@
+//! `temperature` and `max_tokens` carried through to generation: the backend
+//! is asked with them, and answers report the values asked for against those
+//! the backend generated with.
+
+mod support;
+
+use std::sync::Arc;
+
+use support::{epoch, inference, service, Inference, ScriptedBackend};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::llm_backend::Sampling;
+use void_shrine_mcp::mcp_server::MCPResponse;
+use void_shrine_mcp::VoidShrineMCP;
+
+fn stars() -> Inference {
+    inference("scout", "Chart the quiet stars").param("specialty", "science")
+}
+
+async fn answer(service: &VoidShrineMCP, request: Inference) -> MCPResponse {
+    service.handle_mcp_request(request.request()).await.unwrap()
+}
+
+#[tokio::test]
+async fn the_backend_generates_with_the_requested_values() {
+    let backend = Arc::new(ScriptedBackend::new().replies(2, "Orion rises late").spending(6, 4));
+    let service = service(Arc::clone(&backend), Arc::new(ManualClock::new(epoch())));
+
+    let response = answer(&service, stars().param("temperature", 0.0).param("max_tokens", 8)).await;
+    answer(&service, stars().param("temperature", 1.5)).await;
+    let asked: Vec<(f64, u32)> = backend.params().iter().map(|params| (params.temperature, params.max_tokens)).collect();
+    assert_eq!(asked[0], (0.0, 8));
+    assert_eq!(asked[1].0, 1.5);
+
+    let sampling = response.result.metrics.sampling.unwrap();
+    assert_eq!(sampling.requested, Sampling { temperature: 0.0, max_tokens: 8 });
+    assert_eq!(sampling.effective, sampling.requested);
+    assert_eq!(response.result.metrics.completion_tokens, 4);
+}
+
+#[tokio::test]
+async fn dry_runs_generate_nothing() {
+    let backend = Arc::new(ScriptedBackend::new());
+    let service = service(Arc::clone(&backend), Arc::new(ManualClock::new(epoch())));
+    let preview = answer(&service, stars().param("dry_run", true)).await;
+    assert!(preview.result.metrics.sampling.is_none());
+    assert!(backend.params().is_empty());
+}
@@ -0,0 +1,166 @@
+//! The gRPC service over an in-process channel: each RPC answers as its HTTP
+//! route does, streams end with the whole response, and API keys apply.
+#![cfg(feature = "grpc")]
+
+use std::sync::Arc;
+
+use hyper_util::rt::TokioIo;
+use tonic::transport::{Channel, Endpoint, Server, Uri};
+use tonic::{Code, Request};
+use void_shrine_mcp::auth::{ApiKey, Auth, Tenancy};
+use void_shrine_mcp::grpc::proto::inference_event::Event;
+use void_shrine_mcp::grpc::proto::void_shrine_client::VoidShrineClient;
+use void_shrine_mcp::grpc::proto::{ChaosRequest, InferRequest, MoralRequest, Params, ScalingRequest, ThrottleRequest};
+use void_shrine_mcp::grpc::GrpcService;
+use void_shrine_mcp::mcp_server::MetricsParams;
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+
+/// A client of `service` served over an in-memory duplex stream
+async fn connect(service: Arc<VoidShrineMCP>) -> VoidShrineClient<Channel> {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(
+        Server::builder()
+            .add_service(GrpcService::server(service))
+            .serve_with_incoming(tokio_stream::once(Ok::<_, std::io::Error>(server))),
+    );
+    let mut client = Some(client);
+    let channel = Endpoint::from_static("http://in-process")
+        .connect_with_connector(tower::service_fn(move |_: Uri| {
+            let client = client.take();
+            async move { client.map(TokioIo::new).ok_or_else(|| std::io::Error::other("connected already")) }
+        }))
+        .await
+        .unwrap();
+    VoidShrineClient::new(channel)
+}
+
+async fn instance() -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default();
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    Arc::new(service)
+}
+
+fn params(prompt: &str) -> Params {
+    Params {
+        agent_id: "grpc-agent".to_string(),
+        specialty: "research".to_string(),
+        prompt: prompt.to_string(),
+        max_tokens: 128,
+        temperature: 0.2,
+        use_rag: true,
+        context_window: 4096,
+        ..Params::default()
+    }
+}
+
+fn infer(prompt: &str, request_id: Option<&str>) -> InferRequest {
+    InferRequest { params: Some(params(prompt)), request_id: request_id.map(str::to_string) }
+}
+
+fn authorized<T>(message: T, secret: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert("authorization", format!("Bearer {}", secret).parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn rpcs_answer_as_their_http_routes_do() {
+    let service = instance().await;
+    let mut client = connect(Arc::clone(&service)).await;
+
+    let response = client.infer(infer("void shrine chaos", Some("grpc-1"))).await.unwrap().into_inner();
+    let (result, metadata) = (response.result.unwrap(), response.metadata.unwrap());
+    assert!(!result.response.is_empty());
+    assert!(!result.citations.is_empty());
+    assert_eq!(metadata.request_id, "grpc-1");
+    assert!(result.metrics.unwrap().token_count > 0);
+
+    let retrieved = client.rag_query(infer("void shrine chaos", None)).await.unwrap().into_inner().result.unwrap();
+    assert!(retrieved.response.starts_with("Retrieved"), "{}", retrieved.response);
+
+    // Counted like any other request
+    let metrics = service.handle_metrics(&Tenancy::All, &MetricsParams::default());
+    assert_eq!(metrics.server.total_requests, 2);
+    assert_eq!(metrics.server.requests_by_method.get("rag_query"), Some(&1));
+
+    let moral = MoralRequest {
+        original_prompt: "Help the village".to_string(),
+        specialty: "research".to_string(),
+        void_shrine_context: false,
+        ethical_framework: "care-ethics".to_string(),
+        strict: None,
+    };
+    let over_grpc = client.moral_recenter(moral.clone()).await.unwrap().into_inner();
+    let direct = service
+        .handle_moral_recentering(serde_json::from_value(serde_json::json!({
+            "original_prompt": moral.original_prompt, "specialty": moral.specialty,
+            "void_shrine_context": false, "ethical_framework": moral.ethical_framework
+        })).unwrap())
+        .await
+        .unwrap();
+    assert_eq!((over_grpc.recentered_prompt, over_grpc.care_ethics_score), (direct.recentered_prompt, direct.care_ethics_score));
+
+    let throttle = client.throttle(ThrottleRequest { agent_id: "grpc-agent".to_string() }).await.unwrap().into_inner();
+    assert_eq!(throttle.bucket_capacity, service.handle_throttle(&Tenancy::All, "grpc-agent".to_string()).await.unwrap().bucket_capacity);
+
+    let chaos = ChaosRequest { agent_id: "grpc-agent".to_string(), specialty: None, chaos_type: "network_delay".to_string(), intensity: 0.0 };
+    assert!(!client.chaos(chaos).await.unwrap().into_inner().apply_chaos);
+    let scaling = ScalingRequest { agent_id: "grpc-agent".to_string(), response_time: Some(120), token_count: Some(40), success: true };
+    assert!(client.scaling(scaling).await.unwrap().into_inner().adjustments.is_some());
+}
+
+#[tokio::test]
+async fn streamed_inference_ends_with_the_whole_response() {
+    let mut client = connect(instance().await).await;
+    let response = client.infer_stream(infer("void shrine chaos", None)).await.unwrap();
+    let request_id = response.metadata().get("request-id").unwrap().to_str().unwrap().to_string();
+    let mut events = response.into_inner();
+
+    let mut streamed = String::new();
+    let mut done = None;
+    while let Some(event) = events.message().await.unwrap() {
+        match event.event.unwrap() {
+            Event::Delta(delta) => streamed.push_str(&delta.text),
+            Event::Done(event) => done = Some(event),
+            Event::Error(error) => panic!("stream failed: {}", error.message),
+            _ => {}
+        }
+    }
+    let done = done.expect("a done event");
+    assert!(!streamed.is_empty());
+    assert_eq!(done.response, streamed);
+    assert_eq!(done.metadata.unwrap().request_id, request_id);
+}
+
+#[tokio::test]
+async fn keys_and_errors_carry_over_from_http() {
+    let auth = Auth::new(vec![
+        ApiKey::parse("agents:agent-secret:inference").unwrap(),
+        ApiKey::parse("ops:ops-secret:inference+admin").unwrap(),
+    ])
+    .unwrap();
+    let service = Arc::new(Arc::into_inner(instance().await).unwrap().with_auth(auth));
+    let mut client = connect(service).await;
+
+    let status = client.infer(infer("void shrine", None)).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(status.metadata().get("error-code").unwrap(), "unauthorized");
+    client.infer(authorized(infer("void shrine", None), "agent-secret")).await.unwrap();
+
+    // Chaos needs what POST /api/chaos needs
+    let chaos = || ChaosRequest { agent_id: "grpc-agent".to_string(), specialty: None, chaos_type: "network_delay".to_string(), intensity: 0.0 };
+    let status = client.chaos(authorized(chaos(), "agent-secret")).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    client.chaos(authorized(chaos(), "ops-secret")).await.unwrap();
+
+    let status = client.infer(authorized(infer("", Some("grpc-empty")), "agent-secret")).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(status.metadata().get("error-code").unwrap(), "invalid_params");
+    assert_eq!(status.metadata().get("request-id").unwrap(), "grpc-empty");
+
+    let missing = InferRequest { params: None, request_id: None };
+    assert_eq!(client.infer(authorized(missing, "agent-secret")).await.unwrap_err().code(), Code::InvalidArgument);
+}
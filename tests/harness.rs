@@ -0,0 +1,110 @@
+//! The first suite on `tests/support`: inference answered from a scripted
+//! backend, augmented from the fixture knowledge base, failures mapped to
+//! error responses, and repeatable results with chaos off.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use support::{epoch, fixture_rag, service, ScriptedBackend, TestServer};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::llm_backend::BackendError;
+use void_shrine_mcp::mcp_server::MCPRequest;
+
+fn inference(prompt: &str, use_rag: bool, request_id: Option<&str>) -> MCPRequest {
+    let params = serde_json::from_value(json!({
+        "agent_id": "harnessed", "prompt": prompt, "max_tokens": 64,
+        "temperature": 0.2, "use_rag": use_rag, "context_window": 4096
+    }))
+    .unwrap();
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: request_id.map(str::to_string), idempotency_key: None }
+}
+
+#[tokio::test]
+async fn answers_from_the_backend_at_the_clocks_time() {
+    let backend = Arc::new(ScriptedBackend::new().reply_after(Duration::from_millis(20), "The shrine is quiet."));
+    let clock = Arc::new(ManualClock::new(epoch()));
+    let service = Arc::new(service(Arc::clone(&backend), Arc::clone(&clock)));
+
+    let response = service.handle_mcp_request(inference("Is the shrine quiet?", false, None)).await.unwrap();
+    assert_eq!(response.result.response, "The shrine is quiet.");
+    assert_eq!(response.metadata.timestamp, epoch());
+    assert!(!response.metadata.chaos_applied);
+    assert!(response.result.metrics.response_time_ms >= 20);
+    assert!(backend.prompts()[0].user.contains("Is the shrine quiet?"));
+
+    // Tokens age by the same clock
+    let server = TestServer::start(Arc::clone(&service)).await;
+    let token = json!({ "token": response.metadata.void_shrine_token });
+    let (_, verified) = server.post("/api/tokens/verify", &token).await;
+    assert_eq!(verified["valid"], true);
+    clock.advance(chrono::Duration::days(2));
+    let (_, verified) = server.post("/api/tokens/verify", &token).await;
+    assert_eq!((verified["valid"].as_bool(), verified["reason"].as_str()), (Some(false), Some("expired")));
+}
+
+#[tokio::test]
+async fn rag_augmented_inference_cites_the_fixtures() {
+    let backend = Arc::new(ScriptedBackend::new().reply("Hermit crabs shelter there [1]."));
+    let service = service(Arc::clone(&backend), Arc::new(ManualClock::new(epoch())));
+    *service.rag_engine.write().await = Some(fixture_rag().await);
+
+    let response = service.handle_mcp_request(inference("Where do hermit crabs shelter?", true, None)).await.unwrap();
+    let citations = response.result.citations.unwrap();
+    assert_eq!(citations[0].document_id, "tide-pools");
+    assert_eq!(citations[0].metadata["category"], "ecology");
+    assert!(response.result.metrics.rag_documents_used > 0);
+    // The retrieved passage reached the backend
+    assert!(backend.prompts()[0].user.contains("Anemones and hermit crabs"));
+}
+
+#[tokio::test]
+async fn failures_map_to_error_responses() {
+    let backend = ScriptedBackend::new()
+        .fail(BackendError::Timeout(Duration::from_secs(30)))
+        .fail(BackendError::InvalidResponse("no choices".to_string()))
+        .fail(BackendError::RateLimited { retry_after: None, message: "slow down".to_string() });
+    let service = service(Arc::new(backend), Arc::new(ManualClock::new(epoch())));
+    let server = TestServer::start(Arc::new(service)).await;
+    let request = |prompt: &str| serde_json::to_value(inference(prompt, false, None)).unwrap();
+
+    let mut failures = Vec::new();
+    for _ in 0..4 {
+        let (status, body) = server.post("/api/mcp", &request("Still there?")).await;
+        failures.push((status, body["error"].as_str().unwrap().to_string()));
+    }
+    let failures: Vec<(u16, &str)> = failures.iter().map(|(status, code)| (*status, code.as_str())).collect();
+    assert_eq!(failures, [
+        (504, "backend_timeout"),
+        (502, "backend_invalid_response"),
+        (503, "backend_rate_limited"),
+        // Past the end of the script
+        (502, "backend_unavailable"),
+    ]);
+
+    let (status, body) = server.post("/api/mcp", &request("")).await;
+    assert_eq!((status, body["error"].as_str()), (400, Some("invalid_params")));
+    let mut unsupported = request("Still there?");
+    unsupported["method"] = json!("llm_inferense");
+    let (status, body) = server.post("/api/mcp", &unsupported).await;
+    assert_eq!((status, body["error"].as_str()), (400, Some("unsupported_method")));
+}
+
+#[tokio::test]
+async fn identical_requests_get_identical_responses_with_chaos_off() {
+    let mut responses = Vec::new();
+    for _ in 0..2 {
+        let backend = ScriptedBackend::new().reply("Lichen grows slowly.");
+        let service = service(Arc::new(backend), Arc::new(ManualClock::new(epoch())));
+        *service.rag_engine.write().await = Some(fixture_rag().await);
+        let response = service.handle_mcp_request(inference("How does lichen grow?", true, Some("repeatable-1"))).await.unwrap();
+        let mut response = serde_json::to_value(response).unwrap();
+        // The one wall-clock measurement left
+        response["result"]["metrics"]["response_time_ms"] = Value::Null;
+        responses.push(response);
+    }
+    assert_eq!(responses[0], responses[1]);
+    assert_eq!(responses[0]["result"]["citations"][0]["document_id"], "lichen");
+}
@@ -0,0 +1,108 @@
+//! Agent heartbeats: `POST /api/agents/{id}/heartbeat` keeps an agent fresh
+//! in the listings, going quiet makes it stale with an `agent_stale` webhook
+//! and forgets its recent load, and long-stale agents are evicted.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use support::{configured_service, epoch, inference, service, webhook_receiver, ScriptedBackend, TestServer};
+use void_shrine_mcp::agents::AgentsConfig;
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::{Clock, ManualClock};
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::AgentListParams;
+use void_shrine_mcp::webhooks::WebhookConfig;
+
+fn status_check(agent_id: &str) -> support::Inference {
+    inference(agent_id, "status").param("specialty", "research")
+}
+
+#[tokio::test]
+async fn quiet_agents_go_stale_and_come_back() {
+    let (url, received) = webhook_receiver().await;
+    let clock = Arc::new(ManualClock::new(epoch()));
+    let service = service(Arc::new(ScriptedBackend::new().reply("steady")), Arc::clone(&clock))
+        .with_webhooks(WebhookConfig { urls: vec![url], secret: Some("secret".to_string()), ..WebhookConfig::default() });
+    let service = Arc::new(service);
+    let server = TestServer::start(Arc::clone(&service)).await;
+
+    let (status, beat) = server.post("/api/agents/scout/heartbeat", &json!({ "queue_depth": 4 })).await;
+    assert_eq!(status, 200, "{}", beat);
+    let stale_at: chrono::DateTime<chrono::Utc> = serde_json::from_value(beat["stale_at"].clone()).unwrap();
+    assert_eq!(stale_at, clock.now() + chrono::Duration::seconds(90));
+    let listed = service.handle_list_agents(&Tenancy::All, &AgentListParams::default()).agents;
+    assert_eq!((listed[0].stale, listed[0].queue_depth, listed[0].last_heartbeat), (false, Some(4), Some(clock.now())));
+
+    // A request counts as a sign of life too
+    clock.advance(chrono::Duration::seconds(60));
+    service.handle_mcp_request(status_check("scout").request()).await.unwrap();
+    clock.advance(chrono::Duration::seconds(60));
+    assert!(service.sweep_stale_agents().stale.is_empty());
+
+    clock.advance(chrono::Duration::seconds(31));
+    assert_eq!(service.sweep_stale_agents().stale, ["scout"]);
+    assert!(service.sweep_stale_agents().stale.is_empty());
+    let listed = service.handle_list_agents(&Tenancy::All, &AgentListParams { include_stale: false, ..AgentListParams::default() }).agents;
+    assert!(listed.is_empty());
+
+    // An empty heartbeat brings it back
+    let (status, _) = server.post("/api/agents/scout/heartbeat", &json!({})).await;
+    assert_eq!(status, 200);
+    assert_eq!(service.sweep_stale_agents().recovered, ["scout"]);
+    let listed = service.handle_list_agents(&Tenancy::All, &AgentListParams::default()).agents;
+    assert_eq!((listed[0].stale, listed[0].queue_depth), (false, None));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let webhooks = received.lock().unwrap().clone();
+    assert_eq!(webhooks.len(), 1, "{:?}", webhooks);
+    assert_eq!((&webhooks[0]["event"], &webhooks[0]["agent_id"]), (&json!("agent_stale"), &json!("scout")));
+
+    let (status, refused) = server.post("/api/agents/not%20an%20id/heartbeat", &json!({})).await;
+    assert_eq!((status, refused["fields"][0]["field"].as_str()), (400, Some("agent_id")));
+}
+
+#[tokio::test]
+async fn a_stale_agent_returns_without_its_old_load() {
+    let clock = Arc::new(ManualClock::new(epoch()));
+    let agents = AgentsConfig { heartbeat_timeout_secs: 60, ..AgentsConfig::default() };
+    let backend = Arc::new(ScriptedBackend::new().replies(3, "steady"));
+    let service = Arc::new(configured_service(Config { agents, ..Config::default() }, backend, Arc::clone(&clock)));
+    let server = TestServer::start(Arc::clone(&service)).await;
+    server.post("/api/agents/scout/heartbeat", &json!({})).await;
+    for _ in 0..3 {
+        service.handle_mcp_request(status_check("scout").request()).await.unwrap();
+    }
+    let detail = service.handle_get_agent(&Tenancy::All, "scout").unwrap();
+    assert!(detail.recent_rps > 0.0 && detail.latency.samples == 3);
+
+    clock.advance(chrono::Duration::seconds(61));
+    assert_eq!(service.sweep_stale_agents().stale, ["scout"]);
+    let detail = service.handle_get_agent(&Tenancy::All, "scout").unwrap();
+    assert_eq!((detail.recent_rps, detail.latency.samples, detail.summary.current_load), (0.0, 0, 0.0));
+    // Its lifetime totals are kept
+    assert_eq!(detail.summary.total_requests, 3);
+}
+
+#[tokio::test]
+async fn long_stale_agents_are_evicted() {
+    let clock = Arc::new(ManualClock::new(epoch()));
+    let agents = AgentsConfig { heartbeat_timeout_secs: 60, evict_after_secs: Some(3600), ..AgentsConfig::default() };
+    let service = Arc::new(configured_service(Config { agents, ..Config::default() }, Arc::new(ScriptedBackend::new()), Arc::clone(&clock)));
+    let server = TestServer::start(Arc::clone(&service)).await;
+    server.post("/api/agents/scout/heartbeat", &json!({})).await;
+    server.post("/api/agents/courier/heartbeat", &json!({})).await;
+
+    clock.advance(chrono::Duration::seconds(61));
+    let sweep = service.sweep_stale_agents();
+    assert_eq!((sweep.stale.len(), sweep.evicted.len()), (2, 0));
+
+    clock.advance(chrono::Duration::seconds(3540));
+    server.post("/api/agents/courier/heartbeat", &json!({})).await;
+    let sweep = service.sweep_stale_agents();
+    assert_eq!((sweep.recovered, sweep.evicted), (vec!["courier".to_string()], vec!["scout".to_string()]));
+    let listed = service.handle_list_agents(&Tenancy::All, &AgentListParams::default()).agents;
+    assert_eq!(listed.iter().map(|agent| agent.agent_id.as_str()).collect::<Vec<_>>(), ["courier"]);
+}
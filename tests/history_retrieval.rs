@@ -0,0 +1,97 @@
+//! Retrieval for follow-ups in a session: the session's latest prompts
+//! searched with the follow-up, or the backend's rewrite of them, and the
+//! query searched named in the response metadata.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use support::{epoch, fixture_rag, service, ScriptedBackend};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::mcp_server::{MCPRequest, MCPResponse};
+use void_shrine_mcp::sessions::{CondenseSkipped, HistoryRetrievalConfig, SessionConfig};
+use void_shrine_mcp::VoidShrineMCP;
+
+async fn fixture_service(backend: &Arc<ScriptedBackend>, retrieval: HistoryRetrievalConfig) -> VoidShrineMCP {
+    let service = service(Arc::clone(backend), Arc::new(ManualClock::new(epoch())))
+        .with_sessions(SessionConfig { retrieval, ..SessionConfig::default() });
+    *service.rag_engine.write().await = Some(fixture_rag().await);
+    service
+}
+
+async fn ask(service: &VoidShrineMCP, session_id: Option<&str>, prompt: &str, dry_run: bool) -> MCPResponse {
+    let params = serde_json::from_value(json!({
+        "agent_id": "follower", "prompt": prompt, "max_tokens": 64, "session_id": session_id, "dry_run": dry_run
+    }))
+    .unwrap();
+    service.handle_mcp_request(MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }).await.unwrap()
+}
+
+#[tokio::test]
+async fn follow_ups_search_the_sessions_latest_prompts() {
+    let backend = Arc::new(ScriptedBackend::new().reply("Mostly fungus.").reply("Rock.").reply("Hermit crabs."));
+    let service = fixture_service(&backend, HistoryRetrievalConfig { turns: 1, ..HistoryRetrievalConfig::default() }).await;
+
+    let first = ask(&service, Some("walk"), "What is lichen made of?", false).await;
+    assert_eq!(first.metadata.retrieval_query, None);
+    ask(&service, Some("walk"), "Tell me about tide pools", false).await;
+    let follow_up = ask(&service, Some("walk"), "and what shelters there?", false).await;
+    let query = follow_up.metadata.retrieval_query.unwrap();
+    // Only the latest earlier prompt
+    assert_eq!((query.text.as_str(), query.history_turns, query.condensed), ("Tell me about tide pools\nand what shelters there?", 1, false));
+    assert_eq!(follow_up.result.citations.unwrap()[0].document_id, "tide-pools");
+
+    // Outside a session the prompt is searched alone, as before
+    let service = fixture_service(&Arc::new(ScriptedBackend::new().reply("Hm.")), HistoryRetrievalConfig { turns: 3, ..Default::default() }).await;
+    assert_eq!(ask(&service, None, "and what shelters there?", false).await.metadata.retrieval_query, None);
+}
+
+#[tokio::test]
+async fn condensing_searches_the_backends_rewrite() {
+    let backend = Arc::new(
+        ScriptedBackend::new()
+            .reply("Tide pools and forges, mainly.")
+            .reply("What shelters in tide pools?")
+            .reply("Anemones and hermit crabs [1]."),
+    );
+    let retrieval = HistoryRetrievalConfig { turns: 2, condense: true, ..HistoryRetrievalConfig::default() };
+    let service = fixture_service(&backend, retrieval).await;
+
+    ask(&service, Some("walk"), "Which places do you know about?", false).await;
+    let follow_up = ask(&service, Some("walk"), "What shelters in the first one?", false).await;
+    assert_eq!(follow_up.result.response, "Anemones and hermit crabs [1].");
+    let query = follow_up.metadata.retrieval_query.unwrap();
+    assert_eq!((query.text.as_str(), query.condensed, query.condense_skipped), ("What shelters in tide pools?", true, None));
+    assert_eq!(follow_up.result.citations.unwrap()[0].document_id, "tide-pools");
+
+    let rewrite = &backend.prompts()[1];
+    assert!(rewrite.user.contains("User: Which places do you know about?\nAssistant: Tide pools and forges, mainly."), "{}", rewrite.user);
+    assert!(rewrite.user.contains("Follow-up: What shelters in the first one?"), "{}", rewrite.user);
+}
+
+#[tokio::test]
+async fn slow_or_skipped_rewrites_fall_back_to_the_turns() {
+    let backend = Arc::new(
+        ScriptedBackend::new()
+            .reply("Tide pools.")
+            .reply_after(Duration::from_secs(5), "Too late.")
+            .reply("Hermit crabs."),
+    );
+    let retrieval = HistoryRetrievalConfig { turns: 2, condense: true, condense_timeout_ms: 50, ..HistoryRetrievalConfig::default() };
+    let service = fixture_service(&backend, retrieval).await;
+
+    ask(&service, Some("walk"), "Which places do you know about?", false).await;
+    let follow_up = ask(&service, Some("walk"), "What shelters there?", false).await;
+    assert_eq!(follow_up.result.response, "Hermit crabs.");
+    let query = follow_up.metadata.retrieval_query.unwrap();
+    assert_eq!((query.condensed, query.condense_skipped), (false, Some(CondenseSkipped::Timeout)));
+    assert_eq!(query.text, "Which places do you know about?\nWhat shelters there?");
+
+    // A dry run calls no backend, the rewrite included
+    let calls = backend.prompts().len();
+    let preview = ask(&service, Some("walk"), "What shelters there?", true).await;
+    assert_eq!(preview.metadata.retrieval_query.unwrap().condense_skipped, Some(CondenseSkipped::DryRun));
+    assert_eq!(backend.prompts().len(), calls);
+}
@@ -0,0 +1,116 @@
+//! Idempotency keys: a repeat of a key gets the first response back without
+//! running again, a different request under the same key gets 409, keys are
+//! the caller's agent's own, and failures and expired keys run again.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+use support::{configured_service, epoch, inference, ScriptedBackend};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::idempotency::IdempotencyConfig;
+use void_shrine_mcp::llm_backend::BackendError;
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+fn keyed(agent_id: &str, prompt: &str, key: &str) -> MCPRequest {
+    inference(agent_id, prompt).param("specialty", "research").idempotency_key(key).request()
+}
+
+fn instance(backend: &Arc<ScriptedBackend>, idempotency: IdempotencyConfig) -> Arc<VoidShrineMCP> {
+    Arc::new(configured_service(Config { idempotency, ..Config::default() }, Arc::clone(backend), Arc::new(ManualClock::new(epoch()))))
+}
+
+async fn post(service: &Arc<VoidShrineMCP>, key: &str, body: Value) -> (u16, Value) {
+    let routes = api::mcp_route(Arc::clone(service)).recover(api::recover);
+    let response = warp::test::request().method("POST").path("/api/mcp").header("idempotency-key", key).json(&body).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn a_repeat_gets_the_first_response_without_running_again() {
+    let backend = Arc::new(ScriptedBackend::new().reply("answer 1").reply("answer 2"));
+    let service = instance(&backend, IdempotencyConfig::default());
+    let body = inference("scout", "tally").body();
+
+    let (status, first) = post(&service, "order-17", body.clone()).await;
+    assert_eq!(status, 200, "{}", first);
+    assert_eq!(first["metadata"]["idempotent_replay"], json!(false));
+    let (status, repeat) = post(&service, "order-17", body.clone()).await;
+    assert_eq!(status, 200, "{}", repeat);
+    assert_eq!(repeat["metadata"]["idempotent_replay"], json!(true));
+    assert_eq!((&repeat["result"]["response"], &repeat["metadata"]["request_id"]), (&first["result"]["response"], &first["metadata"]["request_id"]));
+    assert_eq!(backend.prompts().len(), 1);
+    // Counted once
+    let rendered = service.handle_prometheus().await;
+    assert!(rendered.contains(r#"void_shrine_requests_total{method="llm_inference",status="200"} 1"#), "{}", rendered);
+
+    // The same key for something else is refused
+    let (status, refused) = post(&service, "order-17", inference("scout", "tally again").body()).await;
+    assert_eq!((status, &refused["error"]), (409, &json!("idempotency_conflict")), "{}", refused);
+
+    // Another agent's key of the same name is its own
+    let (status, other) = post(&service, "order-17", inference("courier", "tally").body()).await;
+    assert_eq!((status, &other["metadata"]["idempotent_replay"]), (200, &json!(false)));
+    assert_eq!(backend.prompts().len(), 2);
+
+    let (status, body) = post(&service, "not a key!", body).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("idempotency_key")));
+}
+
+#[tokio::test]
+async fn failures_keep_nothing_and_running_keys_are_refused() {
+    let backend = Arc::new(
+        ScriptedBackend::new()
+            .fail(BackendError::InvalidResponse("garbled".to_string()))
+            .reply("answer 2")
+            .reply_after(Duration::from_secs(60), "answer 3"),
+    );
+    let service = instance(&backend, IdempotencyConfig::default());
+
+    assert!(service.handle_mcp_request(keyed("scout", "fail once", "retry-me")).await.is_err());
+    let retried = service.handle_mcp_request(keyed("scout", "fail once", "retry-me")).await.unwrap();
+    assert!(!retried.metadata.idempotent_replay);
+    assert_eq!(backend.prompts().len(), 2);
+
+    let running = tokio::spawn({
+        let service = Arc::clone(&service);
+        async move { service.handle_mcp_request(keyed("scout", "take your time", "slow")).await }
+    });
+    while backend.prompts().len() < 3 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let refused = service.handle_mcp_request(keyed("scout", "take your time", "slow")).await.unwrap_err();
+    assert_eq!((refused.error.code(), refused.error.http_status()), ("idempotency_in_progress", 409));
+
+    // An abandoned request frees its key
+    running.abort();
+    while service.idempotency.len() > 1 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(service.idempotency.len(), 1);
+}
+
+#[tokio::test]
+async fn keys_expire_and_the_oldest_make_way() {
+    let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2026, 3, 1, 9, 0, 0).unwrap()));
+    let config = Config { idempotency: IdempotencyConfig { ttl_secs: 60, max_keys: 2 }, ..Config::default() };
+    let service = configured_service(config, Arc::new(ScriptedBackend::new().replies(5, "answer")), Arc::clone(&clock));
+
+    service.handle_mcp_request(keyed("scout", "tally", "a")).await.unwrap();
+    assert!(service.handle_mcp_request(keyed("scout", "tally", "a")).await.unwrap().metadata.idempotent_replay);
+    clock.advance(chrono::Duration::seconds(61));
+    assert!(!service.handle_mcp_request(keyed("scout", "tally", "a")).await.unwrap().metadata.idempotent_replay);
+
+    // A third key pushes out the first
+    service.handle_mcp_request(keyed("scout", "tally", "b")).await.unwrap();
+    service.handle_mcp_request(keyed("scout", "tally", "c")).await.unwrap();
+    assert_eq!(service.idempotency.len(), 2);
+    assert!(!service.handle_mcp_request(keyed("scout", "tally", "a")).await.unwrap().metadata.idempotent_replay);
+    assert!(service.handle_mcp_request(keyed("scout", "tally", "c")).await.unwrap().metadata.idempotent_replay);
+}
@@ -0,0 +1,174 @@
+//! Job results POSTed to a flaky local receiver: retried, signed, capped per
+//! host, and recorded on the job when delivery gives up.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::jobs::{CallbackConfig, JobQueue, JobsConfig};
+use void_shrine_mcp::webhooks::{sign, EVENT_HEADER, SIGNATURE_HEADER};
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::http::{HeaderMap, StatusCode};
+use warp::Filter;
+
+const SECRET: &str = "callback-secret";
+
+/// What a receiver saw
+#[derive(Default)]
+struct Received {
+    requests: Mutex<Vec<(HeaderMap, Vec<u8>)>>,
+    in_flight: AtomicUsize,
+    most_in_flight: AtomicUsize,
+}
+
+/// Answers its first `failures` POSTs with 503 and the rest with 200, each
+/// after `delay`
+async fn receiver(failures: usize, delay: Duration) -> (String, Arc<Received>) {
+    let received = Arc::new(Received::default());
+    let log = Arc::clone(&received);
+    let route = warp::post()
+        .and(warp::header::headers_cloned())
+        .and(warp::body::bytes())
+        .and_then(move |headers: HeaderMap, body: warp::hyper::body::Bytes| {
+            let log = Arc::clone(&log);
+            async move {
+                let in_flight = log.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                log.most_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                tokio::time::sleep(delay).await;
+                log.in_flight.fetch_sub(1, Ordering::SeqCst);
+                let mut requests = log.requests.lock().unwrap();
+                requests.push((headers, body.to_vec()));
+                let status = if requests.len() <= failures { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+                Ok::<_, std::convert::Infallible>(warp::reply::with_status("", status))
+            }
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}/results", addr), received)
+}
+
+fn callbacks(max_attempts: u32) -> CallbackConfig {
+    CallbackConfig {
+        allowed_hosts: vec!["127.0.0.1".to_string()],
+        secret: Some(SECRET.to_string()),
+        max_attempts,
+        initial_backoff_ms: 10,
+        ..CallbackConfig::default()
+    }
+}
+
+async fn routes(callbacks: CallbackConfig) -> impl Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible> + Clone {
+    let service = VoidShrineMCP::default();
+    service.chaos_config.write().await.enabled = false;
+    let jobs = Arc::new(JobQueue::start(Arc::new(service), JobsConfig { callbacks, ..JobsConfig::default() }));
+    api::job_routes(jobs).recover(api::recover)
+}
+
+fn submission(callback_url: &str) -> Value {
+    json!({
+        "method": "llm_inference",
+        "callback_url": callback_url,
+        "params": {
+            "agent_id": "caller-back", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+            "max_tokens": 64, "temperature": 0.0, "use_rag": false, "context_window": 4096
+        }
+    })
+}
+
+async fn call<F>(routes: &F, method: &str, path: &str, body: Option<Value>) -> (StatusCode, Value)
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    let mut request = warp::test::request().method(method).path(path);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.reply(routes).await;
+    (response.status(), serde_json::from_slice(response.body()).unwrap())
+}
+
+/// Polls the job until its callback is `status`, failing after a few seconds
+async fn wait_for_callback<F>(routes: &F, job_id: &str, status: &str) -> Value
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    for _ in 0..500 {
+        let (_, job) = call(routes, "GET", &format!("/api/jobs/{}", job_id), None).await;
+        if job["callback"]["status"] == status {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("callback for job {} never reached {}", job_id, status);
+}
+
+#[tokio::test]
+async fn results_are_retried_until_delivered_and_signed() {
+    let (url, received) = receiver(2, Duration::ZERO).await;
+    let routes = routes(callbacks(4)).await;
+    let (status, job) = call(&routes, "POST", "/api/jobs", Some(submission(&url))).await;
+    assert_eq!((status, job["callback"]["status"].as_str()), (StatusCode::ACCEPTED, Some("pending")));
+    let job_id = job["job_id"].as_str().unwrap();
+
+    let job = wait_for_callback(&routes, job_id, "delivered").await;
+    assert_eq!(job["callback"]["attempts"], 3);
+    assert_eq!(job["callback"]["last_error"], "status 503 Service Unavailable");
+
+    let requests = received.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 3);
+    for (headers, body) in &requests {
+        assert_eq!(headers[EVENT_HEADER], "job_finished");
+        assert_eq!(headers[SIGNATURE_HEADER].to_str().unwrap(), sign(SECRET, body));
+        assert_eq!(body, &requests[0].1, "retries resend the same body");
+    }
+    let event: Value = serde_json::from_slice(&requests[0].1).unwrap();
+    assert_eq!(event["job_id"], job_id);
+    assert_eq!(event["status"], "completed");
+    assert_eq!(event["response"], job["response"]);
+    assert!(event["timestamp"].is_string());
+}
+
+#[tokio::test]
+async fn undeliverable_results_are_recorded_and_still_polled() {
+    let (url, received) = receiver(usize::MAX, Duration::ZERO).await;
+    let routes = routes(callbacks(2)).await;
+    let (_, job) = call(&routes, "POST", "/api/jobs", Some(submission(&url))).await;
+    let job_id = job["job_id"].as_str().unwrap();
+
+    let job = wait_for_callback(&routes, job_id, "failed").await;
+    assert_eq!(job["callback"]["attempts"], 2);
+    assert_eq!(job["status"], "completed");
+    assert!(job["response"]["result"]["response"].is_string());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(received.requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn deliveries_to_one_host_are_capped() {
+    let (url, received) = receiver(0, Duration::from_millis(50)).await;
+    let routes = routes(CallbackConfig { max_concurrent_per_host: 1, ..callbacks(1) }).await;
+    let mut job_ids = Vec::new();
+    for _ in 0..3 {
+        let (_, job) = call(&routes, "POST", "/api/jobs", Some(submission(&url))).await;
+        job_ids.push(job["job_id"].as_str().unwrap().to_string());
+    }
+    for job_id in &job_ids {
+        wait_for_callback(&routes, job_id, "delivered").await;
+    }
+    assert_eq!(received.requests.lock().unwrap().len(), 3);
+    assert_eq!(received.most_in_flight.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn callback_urls_off_the_allowlist_are_refused() {
+    let routes = routes(callbacks(1)).await;
+    for url in ["http://attacker.example/steal", "ftp://127.0.0.1/results", "not a url"] {
+        let (status, error) = call(&routes, "POST", "/api/jobs", Some(submission(url))).await;
+        assert_eq!((status, error["fields"][0]["field"].as_str()), (StatusCode::BAD_REQUEST, Some("callback_url")), "{}", url);
+    }
+    let (status, error) = call(&routes, "POST", "/api/jobs", Some(json!({ "callback_url": 7, "method": "llm_inference" }))).await;
+    assert_eq!((status, error["fields"][0]["field"].as_str()), (StatusCode::BAD_REQUEST, Some("params")));
+}
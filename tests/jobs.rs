@@ -0,0 +1,80 @@
+//! Background jobs: queued, polled until finished, cancelled, and refused when
+//! invalid or when the queue is full.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use support::{epoch, inference, service, ScriptedBackend, TestServer};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::jobs::JobsConfig;
+
+fn request(prompt: &str) -> Value {
+    inference("patient", prompt).param("specialty", "research").body()
+}
+
+async fn server(backend: ScriptedBackend, config: JobsConfig) -> TestServer {
+    let service = service(Arc::new(backend), Arc::new(ManualClock::new(epoch())));
+    TestServer::with_jobs(Arc::new(service), config).await
+}
+
+/// Polls the job until `status`, failing after a couple of seconds
+async fn wait_for(server: &TestServer, job_id: &str, status: &str) -> Value {
+    for _ in 0..200 {
+        let (_, job) = server.get(&format!("/api/jobs/{}", job_id)).await;
+        if job["status"] == status {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("job {} never reached {}", job_id, status);
+}
+
+#[tokio::test]
+async fn finished_jobs_answer_every_poll() {
+    let server = server(ScriptedBackend::new().reply("done: care ethics"), JobsConfig::default()).await;
+    let (status, job) = server.post("/api/jobs", &request("care ethics")).await;
+    assert_eq!(status, 202);
+    assert_eq!(job["status"], "queued");
+    let job_id = job["job_id"].as_str().unwrap();
+
+    let first = wait_for(&server, job_id, "completed").await;
+    assert_eq!(first["response"]["result"]["response"], "done: care ethics");
+    assert_eq!(first["response"]["metadata"]["request_id"], job_id);
+    let (_, again) = server.get(&format!("/api/jobs/{}", job_id)).await;
+    assert_eq!(again, first);
+}
+
+#[tokio::test]
+async fn invalid_requests_and_unknown_jobs_are_refused() {
+    let server = server(ScriptedBackend::new(), JobsConfig::default()).await;
+    let mut invalid = request("care ethics");
+    invalid["params"]["max_tokens"] = json!(0);
+    let (status, error) = server.post("/api/jobs", &invalid).await;
+    assert_eq!((status, error["fields"][0]["field"].as_str()), (400, Some("max_tokens")));
+
+    let (status, error) = server.get("/api/jobs/no-such-job").await;
+    assert_eq!((status, error["error"].as_str()), (404, Some("job_not_found")));
+}
+
+#[tokio::test]
+async fn a_full_queue_refuses_and_cancelling_frees_the_worker() {
+    let backend = ScriptedBackend::new().reply_after(Duration::from_secs(60), "done: take your time").reply("done: next");
+    let server = server(backend, JobsConfig { workers: 1, queue_size: 1, ..JobsConfig::default() }).await;
+    let (_, slow) = server.post("/api/jobs", &request("take your time")).await;
+    let slow_id = slow["job_id"].as_str().unwrap();
+    wait_for(&server, slow_id, "running").await;
+
+    let (_, queued) = server.post("/api/jobs", &request("next")).await;
+    let queued_id = queued["job_id"].as_str().unwrap();
+    let (status, error) = server.post("/api/jobs", &request("one too many")).await;
+    assert_eq!((status, error["error"].as_str()), (429, Some("queue_full")));
+
+    let (status, cancelled) = server.delete(&format!("/api/jobs/{}", slow_id)).await;
+    assert_eq!((status, cancelled["status"].as_str()), (200, Some("cancelled")));
+    let next = wait_for(&server, queued_id, "completed").await;
+    assert_eq!(next["response"]["result"]["response"], "done: next");
+    assert_eq!(wait_for(&server, slow_id, "cancelled").await["response"], Value::Null);
+}
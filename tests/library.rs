@@ -0,0 +1,51 @@
+//! `api::routes` mounted inside another warp application: its own routes
+//! keep working, the server's answer beside them behind the same API keys.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::api;
+use void_shrine_mcp::auth::{ApiKey, Auth};
+use void_shrine_mcp::jobs::JobQueue;
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+#[tokio::test]
+async fn routes_serve_beside_an_applications_own() {
+    let auth = Auth::new(vec![ApiKey::parse("app:app-secret:inference+admin").unwrap()]).unwrap();
+    let service = VoidShrineMCP::default().with_auth(auth);
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    let service = Arc::new(service);
+    let jobs = Arc::new(JobQueue::start(Arc::clone(&service), Default::default()));
+
+    let own = warp::path("app").and(warp::path("status")).map(|| "mine");
+    let app = own.or(api::routes(service, jobs)).recover(api::recover);
+
+    let response = warp::test::request().path("/app/status").reply(&app).await;
+    assert_eq!((response.status().as_u16(), response.body().as_ref()), (200, b"mine".as_ref()));
+
+    let response = warp::test::request().path("/api/rag/stats").reply(&app).await;
+    assert_eq!(response.status(), 401);
+    let response = warp::test::request().path("/api/rag/stats").header("authorization", "Bearer app-secret").reply(&app).await;
+    assert_eq!(response.status(), 200);
+    let stats: Value = serde_json::from_slice(response.body()).unwrap();
+    assert!(stats["document_count"].as_u64().unwrap() > 0);
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/api/mcp")
+        .header("authorization", "Bearer app-secret")
+        .json(&json!({
+            "method": "llm_inference",
+            "params": {
+                "agent_id": "embedded", "prompt": "void shrine", "max_tokens": 64,
+                "temperature": 0.2, "use_rag": true, "context_window": 4096
+            },
+        }))
+        .reply(&app)
+        .await;
+    assert_eq!(response.status(), 200, "{:?}", response.body());
+}
@@ -0,0 +1,100 @@
+//! Live metrics over `/ws/metrics`: a snapshot first, then request, chaos
+//! and circuit breaker events as they happen, filtered per connection, with
+//! slow clients losing the oldest events behind a `lagged` notice.
+
+mod support;
+
+use std::sync::Arc;
+
+use support::{configured_service, epoch, inference, ScriptedBackend};
+use void_shrine_mcp::breaker::{BreakerConfig, BreakerState};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::{Config, MetricsConfig};
+use void_shrine_mcp::live::{route, LiveEvent, LiveFrame, LIVE_SCHEMA_VERSION};
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::VoidShrineMCP;
+
+fn report(agent_id: &str) -> MCPRequest {
+    inference(agent_id, "report").param("specialty", "research").request()
+}
+
+/// Answers `calls` times; past them every call fails
+fn instance(live_buffer_events: usize, calls: usize) -> VoidShrineMCP {
+    let metrics = MetricsConfig { live_buffer_events, ..MetricsConfig::default() };
+    let backend = Arc::new(ScriptedBackend::new().replies(calls, "Reported."));
+    configured_service(Config { metrics, ..Config::default() }, backend, Arc::new(ManualClock::new(epoch())))
+}
+
+async fn frame(client: &mut warp::test::WsClient) -> LiveFrame {
+    loop {
+        let message = client.recv().await.unwrap();
+        if let Ok(text) = message.to_str() {
+            return serde_json::from_str(text).unwrap();
+        }
+    }
+}
+
+/// Connects, past the snapshot
+async fn connect(service: &Arc<VoidShrineMCP>, path: &str) -> warp::test::WsClient {
+    let mut client = warp::test::ws().path(path).handshake(route(Arc::clone(service))).await.unwrap();
+    let snapshot = frame(&mut client).await;
+    assert_eq!(snapshot.version, LIVE_SCHEMA_VERSION);
+    assert!(matches!(snapshot.event, LiveEvent::Snapshot { .. }), "{:?}", snapshot);
+    client
+}
+
+#[tokio::test]
+async fn requests_are_pushed_as_they_start_and_finish() {
+    let service = Arc::new(instance(256, 4));
+    service.handle_mcp_request(report("scout")).await.unwrap();
+    let mut client = warp::test::ws().path("/ws/metrics").handshake(route(Arc::clone(&service))).await.unwrap();
+    let LiveEvent::Snapshot { metrics } = frame(&mut client).await.event else {
+        panic!("no snapshot first");
+    };
+    assert_eq!((metrics.agents.len(), metrics.server.total_requests), (1, 1));
+
+    let response = service.handle_mcp_request(report("scout")).await.unwrap();
+    let LiveEvent::RequestStarted { request_id, agent_id, method } = frame(&mut client).await.event else {
+        panic!("expected request_started");
+    };
+    assert_eq!((request_id.as_str(), agent_id.as_str(), method.as_str()), (response.metadata.request_id.as_str(), "scout", "llm_inference"));
+    let LiveEvent::RequestCompleted { request_id, status, .. } = frame(&mut client).await.event else {
+        panic!("expected request_completed");
+    };
+    assert_eq!((request_id, status), (Some(response.metadata.request_id), 200));
+
+    // Another connection asks for one agent's completions only
+    let mut courier = connect(&service, "/ws/metrics?agent_id=courier&events=request_completed").await;
+    service.handle_mcp_request(report("scout")).await.unwrap();
+    service.handle_mcp_request(report("courier")).await.unwrap();
+    let event = frame(&mut courier).await.event;
+    assert!(matches!(&event, LiveEvent::RequestCompleted { agent_id, .. } if agent_id == "courier"), "{:?}", event);
+}
+
+#[tokio::test]
+async fn circuit_breakers_opening_are_pushed() {
+    let breakers = BreakerConfig { failure_threshold: 2, window_secs: 60, cooldown_secs: 60, ..BreakerConfig::default() };
+    let service = Arc::new(instance(256, 0).with_breakers(breakers));
+    let mut client = connect(&service, "/ws/metrics?events=circuit_transition").await;
+
+    for _ in 0..2 {
+        service.handle_mcp_request(report("scout")).await.unwrap_err();
+    }
+    let event = frame(&mut client).await.event;
+    assert_eq!(event, LiveEvent::CircuitTransition { backend: "scripted".to_string(), from: BreakerState::Closed, to: BreakerState::Open });
+}
+
+#[tokio::test]
+async fn slow_clients_lose_the_oldest_events_and_are_told() {
+    let service = Arc::new(instance(2, 0));
+    let mut client = connect(&service, "/ws/metrics").await;
+
+    // Published faster than the connection can forward them
+    let chaos = |index: usize| LiveEvent::ChaosApplied { agent_id: format!("agent-{}", index), method: "llm_inference".to_string(), chaos_type: "latency".to_string() };
+    for index in 0..10 {
+        service.live.publish(chaos(index));
+    }
+    assert_eq!(frame(&mut client).await.event, LiveEvent::Lagged { dropped: 8 });
+    assert_eq!(frame(&mut client).await.event, chaos(8));
+    assert_eq!(frame(&mut client).await.event, chaos(9));
+}
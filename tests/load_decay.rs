@@ -0,0 +1,62 @@
+//! Load decay: an agent that bursts and goes quiet sees its load halve every
+//! `load.idle_half_life_secs`, and is no longer throttled once it has decayed,
+//! without any new request arriving.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use support::{epoch, inference, service, ScriptedBackend};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::load::LoadConfig;
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::VoidShrineMCP;
+
+fn status(agent_id: &str) -> MCPRequest {
+    inference(agent_id, "status").param("specialty", "research").request()
+}
+
+/// Takes 50ms over each of `calls` answers
+fn instance(load: LoadConfig, calls: usize, clock: &Arc<ManualClock>) -> VoidShrineMCP {
+    let backend = (0..calls).fold(ScriptedBackend::new(), |backend, _| backend.reply_after(Duration::from_millis(50), "Eventually."));
+    service(Arc::new(backend), Arc::clone(clock)).with_load(load)
+}
+
+#[tokio::test]
+async fn a_quiet_agent_stops_being_throttled() {
+    let clock = Arc::new(ManualClock::new(epoch()));
+    // A 50ms answer is five times the latency budget
+    let load = LoadConfig { latency_budget_ms: 10, idle_half_life_secs: 30, ..LoadConfig::default() };
+    let service = instance(load, 2, &clock);
+    service.handle_mcp_request(status("scout")).await.unwrap();
+
+    let throttle = service.handle_throttle(&Tenancy::All, "scout".to_string()).await.unwrap();
+    assert!(throttle.should_throttle && throttle.agent_load >= 5.0, "{:?}", throttle);
+    let burst = throttle.agent_load;
+    service.handle_mcp_request(status("scout")).await.unwrap_err();
+
+    clock.advance(chrono::Duration::seconds(30));
+    let throttle = service.handle_throttle(&Tenancy::All, "scout".to_string()).await.unwrap();
+    assert!((throttle.agent_load - burst / 2.0).abs() < 1e-6, "{} after {}", throttle.agent_load, burst);
+    assert!(throttle.should_throttle);
+
+    clock.advance(chrono::Duration::minutes(5));
+    let throttle = service.handle_throttle(&Tenancy::All, "scout".to_string()).await.unwrap();
+    assert!(!throttle.should_throttle && throttle.agent_load < 0.01, "{:?}", throttle);
+    assert_eq!(throttle.reason, "Normal load");
+    service.handle_mcp_request(status("scout")).await.unwrap();
+}
+
+#[tokio::test]
+async fn without_a_half_life_the_load_waits_for_the_window() {
+    let clock = Arc::new(ManualClock::new(epoch()));
+    let load = LoadConfig { latency_budget_ms: 10, idle_half_life_secs: 0, ..LoadConfig::default() };
+    let service = instance(load, 1, &clock);
+    service.handle_mcp_request(status("scout")).await.unwrap();
+
+    clock.advance(chrono::Duration::minutes(10));
+    let throttle = service.handle_throttle(&Tenancy::All, "scout".to_string()).await.unwrap();
+    assert!(throttle.should_throttle && throttle.agent_load >= 5.0, "{:?}", throttle);
+}
@@ -0,0 +1,47 @@
+//! Knowledge base maintenance over the service: searches keep being answered
+//! while it checks, recomputes keywords and compacts the database.
+
+use std::time::Duration;
+
+use void_shrine_mcp::mcp_server::MaintenanceRequest;
+use void_shrine_mcp::rag_engine::{Document, QueryOptions};
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn searches_finish_while_maintenance_runs() {
+    let path = std::env::temp_dir().join(format!("void-shrine-maintenance-{}.db", uuid::Uuid::new_v4()));
+    let mut rag = RAGEngine::builder().path(&path).build().await.unwrap();
+    for i in 0..1500 {
+        let document = Document {
+            id: format!("pool-{}", i),
+            title: format!("Tide pool survey {}", i),
+            content: format!(
+                "Survey {i} counted anemones, limpets and hermit crabs in pool {i}. \
+                 The water cooled as the tide turned, and pool {i} drained by evening."
+            ),
+            metadata: Default::default(),
+            embedding: None,
+            chunks: Vec::new(),
+        };
+        rag.index_document(document).await.unwrap();
+    }
+    let service = std::sync::Arc::new(VoidShrineMCP::default());
+    *service.rag_engine.write().await = Some(rag);
+
+    let maintaining = std::sync::Arc::clone(&service);
+    let maintenance = tokio::spawn(async move { maintaining.handle_maintenance(None, MaintenanceRequest { repair: true }).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let results = service.rag_engine.read().await.as_ref().unwrap().search("hermit crabs", 5, &QueryOptions::default()).await.unwrap();
+    assert_eq!(results.len(), 5);
+    assert!(!maintenance.is_finished(), "the search waited for maintenance to finish");
+
+    let report = maintenance.await.unwrap().unwrap();
+    assert!(report.repaired);
+    assert_eq!(report.keywords_refreshed, 1500);
+    assert!(report.bytes_after > 0);
+    drop(service);
+    for suffix in ["", "-wal", "-shm"] {
+        std::fs::remove_file(format!("{}{}", path.display(), suffix)).ok();
+    }
+}
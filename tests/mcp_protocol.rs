@@ -0,0 +1,118 @@
+//! Drives the JSON-RPC endpoint with raw MCP messages, as a client would.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::mcp_protocol::route;
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+use warp::http::StatusCode;
+
+async fn service() -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default();
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    Arc::new(service)
+}
+
+async fn post(service: &Arc<VoidShrineMCP>, body: &str) -> (StatusCode, Option<Value>) {
+    let response = warp::test::request()
+        .method("POST")
+        .path("/mcp")
+        .header("content-type", "application/json")
+        .body(body)
+        .reply(&route(Arc::clone(service)))
+        .await;
+    let body = serde_json::from_slice(response.body()).ok();
+    (response.status(), body)
+}
+
+#[tokio::test]
+async fn handshake_and_tool_call() {
+    let service = service().await;
+
+    let (status, response) = post(&service, r#"{
+        "jsonrpc": "2.0", "id": 1, "method": "initialize",
+        "params": {"protocolVersion": "2024-11-05", "capabilities": {}, "clientInfo": {"name": "test", "version": "0"}}
+    }"#).await;
+    assert_eq!(status, StatusCode::OK);
+    let response = response.unwrap();
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+    assert!(response["result"]["capabilities"]["tools"].is_object());
+    assert_eq!(response["result"]["serverInfo"]["name"], "void-shrine-mcp");
+
+    let (status, response) = post(&service, r#"{"jsonrpc": "2.0", "method": "notifications/initialized"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(response.is_none());
+
+    let (_, response) = post(&service, r#"{"jsonrpc": "2.0", "id": "list", "method": "tools/list"}"#).await;
+    let tools = response.unwrap()["result"]["tools"].as_array().unwrap().clone();
+    let names: Vec<&str> = tools.iter().map(|tool| tool["name"].as_str().unwrap()).collect();
+    assert!(names.contains(&"llm_inference") && names.contains(&"rag_query") && names.contains(&"moral_recentering"));
+    assert!(tools.iter().all(|tool| tool["inputSchema"]["type"] == "object"));
+
+    let (_, response) = post(&service, r#"{
+        "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+        "params": {"name": "rag_query", "arguments": {"prompt": "care ethics"}}
+    }"#).await;
+    let result = &response.unwrap()["result"];
+    assert_eq!(result["isError"], false);
+    assert_eq!(result["content"][0]["type"], "text");
+    let citations = result["structuredContent"]["citations"].as_array().unwrap();
+    assert!(citations.iter().any(|c| c["document_id"] == "care_ethics"));
+}
+
+#[tokio::test]
+async fn protocol_errors_use_json_rpc_codes() {
+    let service = service().await;
+    let error_code = |response: Option<Value>| response.unwrap()["error"]["code"].as_i64().unwrap();
+
+    let (_, response) = post(&service, "{not json").await;
+    assert_eq!(error_code(response), -32700);
+
+    let (_, response) = post(&service, r#"{"jsonrpc": "1.0", "id": 1, "method": "ping"}"#).await;
+    assert_eq!(error_code(response), -32600);
+
+    let (_, response) = post(&service, r#"{"jsonrpc": "2.0", "id": 1, "method": "resources/list"}"#).await;
+    assert_eq!(error_code(response), -32601);
+
+    let (_, response) = post(&service, r#"{"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {"name": "nope"}}"#).await;
+    assert_eq!(error_code(response), -32602);
+
+    let (_, response) = post(&service, r#"{
+        "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {"name": "rag_query", "arguments": {}}
+    }"#).await;
+    assert_eq!(error_code(response), -32602);
+
+    let (_, response) = post(&service, r#"{
+        "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+        "params": {"name": "llm_inference", "arguments": {"prompt": "hi", "temperature": 97.0}}
+    }"#).await;
+    let error = &response.unwrap()["error"];
+    assert_eq!((&error["code"], &error["data"]["error"]), (&json!(-32602), &json!("invalid_params")));
+    assert_eq!(error["data"]["fields"], json!([{ "field": "temperature", "constraint": "between 0 and 2", "value": 97.0 }]));
+
+    let (_, response) = post(&service, r#"[
+        {"jsonrpc": "2.0", "id": 7, "method": "ping"},
+        {"jsonrpc": "2.0", "method": "notifications/initialized"}
+    ]"#).await;
+    let batch = response.unwrap();
+    assert_eq!(batch.as_array().unwrap().len(), 1);
+    assert_eq!(batch[0]["id"], 7);
+    assert_eq!(batch[0]["result"], json!({}));
+}
+
+#[tokio::test]
+async fn tool_failures_carry_their_error_body() {
+    let service = Arc::new(VoidShrineMCP::default());
+
+    let (_, response) = post(&service, r#"{
+        "jsonrpc": "2.0", "id": 3, "method": "tools/call",
+        "params": {"name": "rag_answer", "arguments": {"prompt": "care ethics"}}
+    }"#).await;
+    let result = &response.unwrap()["result"];
+    assert_eq!(result["isError"], true);
+    assert_eq!(result["structuredContent"], json!({ "error": "rag_unavailable", "message": "RAG engine not initialized" }));
+    assert_eq!(result["content"][0]["text"], "RAG engine not initialized");
+}
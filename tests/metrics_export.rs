@@ -0,0 +1,70 @@
+//! GET /api/metrics/export: hourly or daily activity per agent as a CSV or
+//! JSON lines download, within a bounded time range.
+
+mod support;
+
+use std::sync::Arc;
+
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::{json, Value};
+use support::{epoch, inference, service, ScriptedBackend};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+async fn export(service: &Arc<VoidShrineMCP>, query: &str) -> warp::http::Response<warp::hyper::body::Bytes> {
+    let routes = api::metrics_export_route(Arc::clone(service)).recover(api::recover);
+    warp::test::request().path(&format!("/api/metrics/export{}", query)).reply(&routes).await
+}
+
+#[tokio::test]
+async fn activity_is_exported_as_csv_or_json_lines() {
+    let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2026, 3, 1, 9, 15, 0).unwrap()));
+    let backend = Arc::new(ScriptedBackend::new().replies(3, "forty sacks of grain").spending(12, 5));
+    let service = Arc::new(service(backend, Arc::clone(&clock)));
+    let tally = |agent_id: &str| inference(agent_id, "tally the stores").request();
+    service.handle_mcp_request(tally("scout, \"the\" second")).await.unwrap();
+    service.handle_mcp_request(tally("scout, \"the\" second")).await.unwrap();
+    clock.advance(Duration::hours(1));
+    service.handle_mcp_request(tally("courier")).await.unwrap();
+
+    let response = export(&service, "?since=2026-03-01T00:00:00Z&until=2026-03-02T00:00:00Z").await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/csv; charset=utf-8");
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"void-shrine-metrics-20260301T000000Z-20260302T000000Z.csv\""
+    );
+    let csv = std::str::from_utf8(response.body()).unwrap();
+    let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+    assert_eq!(lines[0], "timestamp,agent_id,requests,errors,p50_ms,p95_ms,prompt_tokens,completion_tokens,chaos_events");
+    assert_eq!(lines.len(), 3, "{}", csv);
+    assert!(lines[1].starts_with("2026-03-01T09:00:00Z,\"scout, \"\"the\"\" second\",2,0,"), "{}", lines[1]);
+    assert!(lines[2].starts_with("2026-03-01T10:00:00Z,courier,1,0,"), "{}", lines[2]);
+
+    let response = export(&service, "?format=jsonl&granularity=day&since=2026-03-01T00:00:00Z&until=2026-03-02T00:00:00Z").await;
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let rows: Vec<Value> = std::str::from_utf8(response.body()).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(rows.iter().map(|row| (row["agent_id"].clone(), row["requests"].clone())).collect::<Vec<_>>(), [
+        (json!("courier"), json!(1)),
+        (json!("scout, \"the\" second"), json!(2))
+    ]);
+    assert_eq!(rows[0]["timestamp"], json!("2026-03-01T00:00:00Z"));
+    assert!(rows[0]["prompt_tokens"].as_u64().unwrap() > 0);
+
+    let only = export(&service, "?format=jsonl&agent_id=courier&since=2026-03-01T00:00:00Z&until=2026-03-02T00:00:00Z").await;
+    assert_eq!(std::str::from_utf8(only.body()).unwrap().lines().count(), 1);
+}
+
+#[tokio::test]
+async fn ranges_must_be_ordered_and_bounded() {
+    let service = Arc::new(service(Arc::new(ScriptedBackend::new()), Arc::new(ManualClock::new(epoch()))));
+    let field = |response: warp::http::Response<warp::hyper::body::Bytes>| {
+        let body: Value = serde_json::from_slice(response.body()).unwrap();
+        (response.status().as_u16(), body["fields"][0]["field"].clone())
+    };
+    assert_eq!(field(export(&service, "?since=2026-03-02T00:00:00Z&until=2026-03-01T00:00:00Z").await), (400, json!("until")));
+    assert_eq!(field(export(&service, "?since=2026-01-01T00:00:00Z&until=2026-03-01T00:00:00Z").await), (400, json!("until")));
+    assert_eq!(export(&service, "?format=xlsx").await.status(), 400);
+    assert_eq!(export(&service, "").await.status(), 200);
+}
@@ -0,0 +1,76 @@
+//! Agent metrics kept across restarts: lifetime counters carry on where they
+//! left off, and a damaged state file doesn't stop the server starting.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::config::{Config, MetricsConfig};
+use void_shrine_mcp::mcp_server::MetricsParams;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+fn state_path() -> PathBuf {
+    std::env::temp_dir().join(format!("void-shrine-agent-metrics-{}.json", uuid::Uuid::new_v4()))
+}
+
+async fn start(path: &Path) -> Arc<VoidShrineMCP> {
+    let metrics = MetricsConfig { state_path: Some(path.to_path_buf()), ..MetricsConfig::default() };
+    let service = VoidShrineMCP::new(&Config { metrics, ..Config::default() }).unwrap();
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+async fn infer(service: &Arc<VoidShrineMCP>, agent_id: &str) {
+    let routes = api::mcp_route(Arc::clone(service)).recover(api::recover);
+    let params = json!({
+        "agent_id": agent_id, "specialty": "general", "prompt": "report in", "max_tokens": 64,
+        "temperature": 0.2, "use_rag": false, "context_window": 4096
+    });
+    let response = warp::test::request().method("POST").path("/api/mcp").json(&json!({ "method": "llm_inference", "params": params })).reply(&routes).await;
+    assert_eq!(response.status(), 200, "{:?}", response.body());
+}
+
+fn requests(service: &VoidShrineMCP, agent_id: &str) -> (u64, u64) {
+    let params = MetricsParams { agent_id: Some(agent_id.to_string()), ..MetricsParams::default() };
+    let agent = service.handle_metrics(&Tenancy::All, &params).agents.remove(0);
+    (agent.total_requests, agent.requests_since_boot)
+}
+
+#[tokio::test]
+async fn counters_continue_after_a_restart() {
+    let path = state_path();
+    let first = start(&path).await;
+    for _ in 0..3 {
+        infer(&first, "sentinel").await;
+    }
+    infer(&first, "courier").await;
+    let success_rate = first.handle_metrics(&Tenancy::All, &MetricsParams::default()).agents[1].success_rate;
+    assert_eq!(first.save_agent_metrics().unwrap(), Some(2));
+    drop(first);
+
+    let second = start(&path).await;
+    assert_eq!(requests(&second, "sentinel"), (3, 0));
+    infer(&second, "sentinel").await;
+    assert_eq!((requests(&second, "sentinel"), requests(&second, "courier")), ((4, 1), (1, 0)));
+    assert_eq!(second.handle_metrics(&Tenancy::All, &MetricsParams::default()).agents[1].success_rate, success_rate);
+    std::fs::remove_file(path).ok();
+}
+
+#[tokio::test]
+async fn damaged_state_is_discarded_at_startup() {
+    let path = state_path();
+    for damaged in ["{ \"version\": 1, \"agents\": [tru", r#"{ "version": 99, "saved_at": "2026-01-01T00:00:00Z", "agents": [] }"#] {
+        std::fs::write(&path, damaged).unwrap();
+        let service = start(&path).await;
+        assert!(service.handle_metrics(&Tenancy::All, &MetricsParams::default()).agents.is_empty());
+
+        // The next save replaces it
+        infer(&service, "sentinel").await;
+        service.save_agent_metrics().unwrap();
+        let saved: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!((&saved["version"], &saved["agents"][0]["total_requests"]), (&json!(1), &json!(1)));
+    }
+    std::fs::remove_file(path).ok();
+}
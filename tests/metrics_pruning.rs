@@ -0,0 +1,88 @@
+//! Clearing agent metrics: one agent at a time with
+//! `DELETE /api/agents/{id}/metrics`, idle agents in bulk with
+//! `POST /api/metrics/prune`, both recorded in the audit log.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use void_shrine_mcp::audit::{AuditConfig, AuditLog, AuditQuery, AuditSink};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::mcp_server::{MCPRequest, MetricsParams};
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+fn request(agent_id: &str) -> MCPRequest {
+    let params = serde_json::from_value(json!({
+        "agent_id": agent_id, "specialty": "general", "prompt": "check in", "max_tokens": 64,
+        "temperature": 0.2, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap();
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }
+}
+
+async fn audited() -> Arc<VoidShrineMCP> {
+    let path = std::env::temp_dir().join(format!("void-shrine-metrics-audit-{}.jsonl", uuid::Uuid::new_v4()));
+    let config = AuditConfig { sink: AuditSink::Jsonl, path: Some(path), ..AuditConfig::default() };
+    let service = VoidShrineMCP::default().with_audit(AuditLog::open(&config).unwrap().unwrap());
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+async fn call(service: &Arc<VoidShrineMCP>, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let routes = api::agent_routes(Arc::clone(service)).or(api::metrics_prune_route(Arc::clone(service))).recover(api::recover);
+    let mut request = warp::test::request().method(method).path(path);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+async fn audited_actions(service: &VoidShrineMCP) -> Vec<(String, String)> {
+    let audit = service.audit.as_ref().unwrap();
+    audit.flush().await;
+    let records = audit.query(AuditQuery::default()).await.unwrap();
+    records.into_iter().filter(|record| record.method.starts_with("metrics_")).map(|record| (record.method, record.agent_id)).collect()
+}
+
+#[tokio::test]
+async fn one_agents_metrics_are_cleared_and_start_afresh() {
+    let service = audited().await;
+    for _ in 0..2 {
+        service.handle_mcp_request(request("flaky")).await.unwrap();
+    }
+
+    let (status, reset) = call(&service, "DELETE", "/api/agents/flaky/metrics", None).await;
+    assert_eq!(status, 200);
+    assert_eq!((&reset["removed"], &reset["cleared_requests"]), (&json!(true), &json!(2)));
+    let (status, error) = call(&service, "DELETE", "/api/agents/flaky/metrics", None).await;
+    assert_eq!((status, &error["error"]), (404, &json!("agent_not_found")));
+
+    service.handle_mcp_request(request("flaky")).await.unwrap();
+    let (_, metrics) = call(&service, "GET", "/api/agents/flaky/metrics", None).await;
+    assert_eq!((&metrics["total_requests"], &metrics["success_rate"]), (&json!(1), &json!(1.0)));
+    assert_eq!(audited_actions(&service).await, [("metrics_reset".to_string(), "flaky".to_string())]);
+}
+
+#[tokio::test]
+async fn idle_agents_are_pruned_in_bulk() {
+    let service = audited().await;
+    service.handle_mcp_request(request("retired")).await.unwrap();
+    service.handle_mcp_request(request("old-probe")).await.unwrap();
+    let cutoff = Utc::now();
+    service.handle_mcp_request(request("active")).await.unwrap();
+
+    let (status, pruned) = call(&service, "POST", "/api/metrics/prune", Some(json!({ "last_seen_before": cutoff }))).await;
+    assert_eq!(status, 200);
+    assert_eq!(pruned, json!({ "pruned": 2, "agent_ids": ["old-probe", "retired"] }));
+    let agents: Vec<String> = service.handle_metrics(&Tenancy::All, &MetricsParams::default()).agents.into_iter().map(|agent| agent.agent_id).collect();
+    assert_eq!(agents, ["active"]);
+
+    // Nothing left to prune isn't worth an audit record
+    let (_, pruned) = call(&service, "POST", "/api/metrics/prune", Some(json!({ "last_seen_before": cutoff }))).await;
+    assert_eq!(pruned["pruned"], json!(0));
+    assert_eq!(audited_actions(&service).await, [("metrics_prune".to_string(), "*".to_string())]);
+    let (status, _) = call(&service, "POST", "/api/metrics/prune", Some(json!({ "before": cutoff }))).await;
+    assert_eq!(status, 400);
+}
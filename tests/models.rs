@@ -0,0 +1,98 @@
+//! `GET /api/models`: configured routes and the models backends report
+//! serving, with context length, streaming and backend health, revalidated
+//! with an ETag.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::llm_backend::{BackendRegistry, BackendsConfig, LLMBackend, MockBackend, OllamaBackend, OllamaConfig};
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// Lists `tags` at `/api/tags`, as an Ollama server with those models pulled would
+async fn ollama(tags: &'static str) -> String {
+    let route = warp::path!("api" / "tags").and(warp::get()).map(move || warp::reply::with_status(tags, StatusCode::OK));
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    format!("http://{}", addr)
+}
+
+const TAGS: &str = r#"{"models":[{"name":"qwen2.5:7b","size":4683087332},{"name":"llama3.2:latest","size":2019393189}]}"#;
+
+fn backends(host: &str) -> BackendsConfig {
+    serde_json::from_value(json!({
+        "backends": [{ "name": "local", "kind": "ollama", "host": host }, { "name": "canned", "kind": "mock" }],
+        "routes": [
+            { "model": "llama*", "backend": "local", "context_length": 8192 },
+            { "model": "echo", "backend": "canned" }
+        ],
+        "default_backend": "canned"
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn ollama_lists_its_pulled_models() {
+    let host = ollama(TAGS).await;
+    let backend = OllamaBackend::new(OllamaConfig { host, ..Default::default() }).unwrap();
+    assert_eq!(backend.list_models().await.unwrap(), ["qwen2.5:7b", "llama3.2:latest"]);
+    assert!(backend.streams());
+}
+
+#[tokio::test]
+async fn routes_and_discovered_models_are_listed_with_their_backends() {
+    let host = ollama(TAGS).await;
+    let service = Arc::new(VoidShrineMCP::new(&Config { backends: backends(&host), ..Config::default() }).unwrap());
+    service.refresh_models().await;
+    let routes = api::models_route(Arc::clone(&service));
+
+    let response = warp::test::request().path("/api/models").reply(&routes).await;
+    assert_eq!(response.status(), 200);
+    let listing: Value = serde_json::from_slice(response.body()).unwrap();
+    let models: Vec<(&str, &str, bool)> = listing["models"].as_array().unwrap().iter()
+        .map(|model| (model["model"].as_str().unwrap(), model["backend"].as_str().unwrap(), model["discovered"].as_bool().unwrap()))
+        .collect();
+    assert_eq!(
+        models,
+        [("llama*", "local", false), ("echo", "canned", false), ("llama3.2:latest", "local", true), ("qwen2.5:7b", "local", true)]
+    );
+    // Discovered models take the context length of the route sending them to their backend
+    let llama = &listing["models"][2];
+    assert_eq!((&llama["context_length"], &llama["streaming"], &llama["kind"]), (&json!(8192), &json!(true), &json!("ollama")));
+    assert_eq!((&llama["healthy"], &llama["breaker"]), (&json!(true), &json!("closed")));
+    assert!(listing["models"][3].get("context_length").is_none());
+    assert_eq!(listing["default_backend"], json!("canned"));
+}
+
+#[tokio::test]
+async fn unchanged_listings_are_not_sent_again() {
+    let mut backends = BackendRegistry::new();
+    backends.register("canned", Arc::new(MockBackend::default()));
+    backends.route("echo", "canned").unwrap();
+    backends.set_default(Some("canned")).unwrap();
+    let service = Arc::new(VoidShrineMCP::default().with_backends(backends));
+    service.chaos_config.write().await.enabled = false;
+    let routes = api::models_route(Arc::clone(&service));
+
+    let response = warp::test::request().path("/api/models").reply(&routes).await;
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    assert_eq!(response.headers()["cache-control"], "no-cache");
+    let revalidated = warp::test::request().path("/api/models").header("if-none-match", &etag).reply(&routes).await;
+    assert_eq!(revalidated.status(), 304);
+    assert!(revalidated.body().is_empty());
+    assert_eq!(revalidated.headers()["etag"], etag.as_str());
+
+    // A successful call changes the backend's health, and so the listing
+    let params = serde_json::from_value(json!({
+        "agent_id": "scout", "specialty": "general", "prompt": "ping", "max_tokens": 16,
+        "temperature": 0.0, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap();
+    service.handle_mcp_request(MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }).await.unwrap();
+    let changed = warp::test::request().path("/api/models").header("if-none-match", &etag).reply(&routes).await;
+    assert_eq!(changed.status(), 200);
+    assert_ne!(changed.headers()["etag"], etag.as_str());
+}
@@ -0,0 +1,62 @@
+//! POST /api/moral-recentering/preview: a corpus of prompts recentered on a
+//! framework, with where each rule inserted its text and a batch summary.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::api;
+use void_shrine_mcp::mcp_server::ParamLimits;
+use void_shrine_mcp::VoidShrineMCP;
+use warp::Filter;
+
+async fn post(service: VoidShrineMCP, path: &str, body: Value) -> (u16, Value) {
+    let routes = api::moral_route(Arc::new(service)).recover(api::recover);
+    let response = warp::test::request().method("POST").path(path).json(&body).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn previews_a_batch_against_a_framework() {
+    let prompts = ["Plan the offsite for the team", "Make them obey, whatever it takes", "Ship it?"];
+    let body = json!({ "prompts": prompts, "ethical_framework": "deontological", "void_shrine_context": true });
+    let (status, body) = post(VoidShrineMCP::default(), "/api/moral-recentering/preview", body).await;
+    assert_eq!(status, 200, "{}", body);
+
+    let harsh = &body["results"][1];
+    assert_eq!(harsh["original_prompt"], prompts[1]);
+    let recentered = harsh["recentered_prompt"].as_str().unwrap();
+    assert_eq!(format!("{}{}", harsh["diff"]["prefix"].as_str().unwrap(), prompts[1]), recentered);
+    let rules: Vec<&str> = harsh["diff"]["rules"].as_array().unwrap().iter().map(|rule| rule["rule"].as_str().unwrap()).collect();
+    assert_eq!(rules, ["deontological", "low_care_score", "void-shrine-context"]);
+    assert_eq!(harsh["diff"]["rules"][2]["offset"], 0);
+    assert_eq!(harsh["diff"]["rules"][1]["reason"], "care ethics score 0.30 below 0.50");
+    assert_eq!(harsh["diff"]["score_before"], 0.3);
+
+    let summary = &body["summary"];
+    assert_eq!((summary["prompts"].as_u64(), summary["changed"].as_u64()), (Some(3), Some(3)));
+    assert_eq!((summary["rules_fired"]["deontological"].as_u64(), summary["rules_fired"]["low_care_score"].as_u64()), (Some(3), Some(1)));
+    assert!(summary["mean_chars_added"].as_f64().unwrap() > 0.0);
+
+    // The single-prompt route is where it was
+    let single = json!({ "original_prompt": "Ship it?", "specialty": "tactical", "void_shrine_context": false, "ethical_framework": "deontological" });
+    let (status, body) = post(VoidShrineMCP::default(), "/api/moral-recentering", single).await;
+    assert_eq!(status, 200, "{}", body);
+    assert!(body["recentered_prompt"].as_str().unwrap().ends_with(": Ship it?"));
+}
+
+#[tokio::test]
+async fn refuses_empty_oversized_and_unknown() {
+    let service = || VoidShrineMCP::default().with_param_limits(ParamLimits { max_preview_prompts: 2, max_prompt_bytes: 16, ..Default::default() });
+    let preview = |prompts: Value| json!({ "prompts": prompts, "ethical_framework": "care-ethics" });
+
+    let (status, body) = post(service(), "/api/moral-recentering/preview", preview(json!([]))).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("prompts")));
+    let (status, body) = post(service(), "/api/moral-recentering/preview", preview(json!(["a", "b", "c"]))).await;
+    assert_eq!((status, body["fields"][0]["value"].as_u64()), (400, Some(3)));
+    let (status, body) = post(service(), "/api/moral-recentering/preview", preview(json!(["fine", "x".repeat(17)]))).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("prompts[1]")));
+
+    let strict = json!({ "prompts": ["Ship it?"], "ethical_framework": "astrology", "strict": true });
+    let (status, body) = post(service(), "/api/moral-recentering/preview", strict).await;
+    assert_eq!((status, body["error"].as_str()), (400, Some("invalid_params")));
+}
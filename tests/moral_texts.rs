@@ -0,0 +1,131 @@
+//! Moral recentering texts from their own file: checked against the loaded
+//! specialties at startup, listed with where each came from, read again on
+//! reload, and named with their source in previews and dry runs.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+const TEXTS: &str = r#"
+fallback_specialty_prefix = "Con calma: "
+low_score_prefix = "Antes que nada, piensa en quién podría salir perjudicado: "
+
+[void_shrine_context]
+prefix = "Desde el vacío generativo: "
+adjustments = ["Perspectiva del santuario del vacío"]
+
+[specialty_prefixes]
+science = "Con rigor científico: "
+
+[[frameworks]]
+name = "care-ethics"
+prefix = "Cuidando a todas las personas afectadas: "
+adjustments = ["Ética del cuidado aplicada"]
+"#;
+
+fn texts_file(texts: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("void-shrine-moral-texts-{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, texts).unwrap();
+    path
+}
+
+async fn service(path: &std::path::Path) -> Arc<VoidShrineMCP> {
+    let config = Config::from_toml(&format!("[moral]\ntexts_path = {:?}\n", path.display().to_string())).unwrap();
+    let service = VoidShrineMCP::new(&config).unwrap();
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+async fn call(service: &Arc<VoidShrineMCP>, method: &str, path: &str, body: Value) -> (u16, Value) {
+    let routes = api::moral_route(Arc::clone(service)).recover(api::recover);
+    let response = warp::test::request().method(method).path(path).json(&body).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn texts_are_listed_with_their_source_and_shown_where_applied() {
+    let path = texts_file(TEXTS);
+    let service = service(&path).await;
+    let source = path.display().to_string();
+
+    let (status, texts) = call(&service, "GET", "/api/moral-recentering/config", json!({})).await;
+    assert_eq!(status, 200, "{}", texts);
+    let frameworks: Vec<(&str, &str)> =
+        texts["frameworks"].as_array().unwrap().iter().map(|text| (text["name"].as_str().unwrap(), text["source"].as_str().unwrap())).collect();
+    assert_eq!(frameworks[..2], [("care-ethics", source.as_str()), ("consequentialist", "built-in")]);
+    assert_eq!(texts["void_shrine_context"]["prefix"], "Desde el vacío generativo: ");
+    assert_eq!(texts["specialty_prefixes"][0]["name"], "science");
+    assert_eq!(texts["fallback_specialty_prefix"]["source"], source.as_str());
+
+    let preview = json!({ "prompts": ["Make them obey, whatever it takes"], "ethical_framework": "care-ethics", "specialty": "science", "void_shrine_context": true });
+    let (status, body) = call(&service, "POST", "/api/moral-recentering/preview", preview).await;
+    assert_eq!(status, 200, "{}", body);
+    assert!(body["results"][0]["recentered_prompt"].as_str().unwrap().starts_with("Desde el vacío generativo: Antes que nada"));
+    let rules: Vec<(&str, &str)> = body["results"][0]["diff"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rule| (rule["rule"].as_str().unwrap(), rule["inserted"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        rules,
+        [
+            ("care-ethics", "Cuidando a todas las personas afectadas: "),
+            ("specialty_prefix", "Con rigor científico: "),
+            ("low_care_score", "Antes que nada, piensa en quién podría salir perjudicado: "),
+            ("void-shrine-context", "Desde el vacío generativo: "),
+        ]
+    );
+    assert!(body["results"][0]["diff"]["rules"].as_array().unwrap().iter().all(|rule| rule["source"] == source.as_str()));
+
+    let inference = |dry_run: bool| {
+        let params = json!({ "agent_id": "reviewer", "prompt": "Ship it?", "specialty": "creative", "moral_recentering": "on", "dry_run": dry_run });
+        MCPRequest { method: "llm_inference".to_string(), params: serde_json::from_value(params).unwrap(), request_id: None, idempotency_key: None }
+    };
+    let dry = service.handle_mcp_request(inference(true)).await.unwrap().result.moral_recentering.unwrap();
+    let applied: Vec<(&str, &str)> = dry.rules.iter().map(|rule| (rule.rule.as_str(), rule.reason.as_str())).collect();
+    assert_eq!(applied, [("care-ethics", "the requested ethical_framework"), ("specialty_prefix", "the fallback for the creative specialty")]);
+    assert!(service.handle_mcp_request(inference(false)).await.unwrap().result.moral_recentering.unwrap().rules.is_empty());
+    std::fs::remove_file(path).ok();
+}
+
+#[tokio::test]
+async fn reload_reads_the_file_again_and_keeps_the_texts_on_failure() {
+    let path = texts_file(TEXTS);
+    let service = service(&path).await;
+
+    std::fs::write(&path, TEXTS.replace("Con calma: ", "Sin prisa: ")).unwrap();
+    let (status, texts) = call(&service, "POST", "/api/moral-recentering/reload", json!({})).await;
+    assert_eq!((status, texts["fallback_specialty_prefix"]["prefix"].as_str()), (200, Some("Sin prisa: ")));
+
+    // Without the fallback, the specialties lacking a prefix are refused
+    std::fs::write(&path, TEXTS.replace("fallback_specialty_prefix = \"Con calma: \"", "")).unwrap();
+    let (status, error) = call(&service, "POST", "/api/moral-recentering/reload", json!({})).await;
+    assert_eq!(status, 500, "{}", error);
+    assert!(error["message"].as_str().unwrap().contains("nothing for creative, engineering, general, tactical"), "{}", error);
+    assert_eq!(service.handle_moral_config().fallback_specialty_prefix.unwrap().prefix, "Sin prisa: ");
+
+    let unconfigured = Arc::new(VoidShrineMCP::default());
+    assert_eq!(call(&unconfigured, "POST", "/api/moral-recentering/reload", json!({})).await.0, 501);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn startup_refuses_prefixes_that_do_not_cover_the_specialties() {
+    let config = Config::from_toml("[moral.specialty_prefixes]\nscience = \"Rigorously: \"\nastrology = \"Mystically: \"\n").unwrap();
+    let error = format!("{:#}", VoidShrineMCP::new(&config).err().unwrap());
+    assert!(error.contains("moral config: specialty_prefixes has 'astrology', which isn't a specialty"), "{}", error);
+
+    let config = Config::from_toml("[moral]\ntexts_path = \"/nonexistent/moral-texts.toml\"\n").unwrap();
+    assert!(format!("{:#}", VoidShrineMCP::new(&config).err().unwrap()).contains("reading moral texts"));
+
+    let path = texts_file("[framings]\nscience = \"Rigorously: \"\n");
+    let config = Config::from_toml(&format!("[moral]\ntexts_path = {:?}\n", path.display().to_string())).unwrap();
+    assert!(format!("{:#}", VoidShrineMCP::new(&config).err().unwrap()).contains("unknown field `framings`"));
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,95 @@
+//! Runs `OllamaBackend` against a local stand-in for Ollama's `/api/generate`.
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use void_shrine_mcp::llm_backend::{BackendError, FinishReason, LLMBackend, OllamaBackend, OllamaConfig, Prompt};
+use void_shrine_mcp::mcp_server::{MCPParams, MCPRequest};
+use void_shrine_mcp::VoidShrineMCP;
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// Answers every generate call with `status` and the raw `body`, recording request bodies
+async fn ollama(status: StatusCode, body: &'static str) -> (String, Arc<Mutex<Vec<Value>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    let route = warp::path!("api" / "generate")
+        .and(warp::post())
+        .and(warp::body::json())
+        .map(move |request: Value| {
+            log.lock().unwrap().push(request);
+            warp::reply::with_status(body, status)
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}", addr), received)
+}
+
+fn params(model: &str) -> MCPParams {
+    serde_json::from_value(json!({
+        "agent_id": "local", "model": model, "specialty": "science", "prompt": "Why is the sky blue?",
+        "max_tokens": 128, "temperature": 0.5, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap()
+}
+
+fn backend(host: String, stream: bool) -> OllamaBackend {
+    OllamaBackend::new(OllamaConfig { host, stream, ..Default::default() }).unwrap()
+}
+
+const GENERATED: &str = r#"{"model":"llama3.2","response":"Rayleigh scattering.","done":true,"done_reason":"stop",
+    "prompt_eval_count":12,"eval_count":4,"eval_duration":250000000}"#;
+
+#[tokio::test]
+async fn non_streamed_generation_reports_ollama_counts() {
+    let (host, received) = ollama(StatusCode::OK, GENERATED).await;
+    let service = VoidShrineMCP::default().with_backend(Arc::new(backend(host, false)));
+    service.chaos_config.write().await.enabled = false;
+
+    let response = service
+        .handle_mcp_request(MCPRequest { method: "llm_inference".to_string(), params: params("llama3.2:1b"), request_id: None, idempotency_key: None })
+        .await
+        .unwrap();
+    assert_eq!(response.result.response, "Rayleigh scattering.");
+    assert_eq!(response.result.metrics.token_count, 16);
+    assert_eq!(response.result.metrics.response_time_ms, 250);
+
+    let request = &received.lock().unwrap()[0];
+    assert_eq!(request["model"], "llama3.2:1b");
+    assert_eq!(request["stream"], false);
+    assert_eq!(request["options"], json!({ "temperature": 0.5, "num_predict": 128 }));
+}
+
+#[tokio::test]
+async fn streamed_generation_is_assembled() {
+    let stream = concat!(
+        r#"{"model":"llama3.2","response":"Rayleigh","done":false}"#, "\n",
+        r#"{"model":"llama3.2","response":" scattering.","done":false}"#, "\n",
+        r#"{"model":"llama3.2","response":"","done":true,"done_reason":"length","prompt_eval_count":12,"eval_count":2,"eval_duration":1000000}"#, "\n",
+    );
+    let (host, received) = ollama(StatusCode::OK, stream).await;
+
+    let output = backend(host, true).complete(&Prompt::user("Why?"), &params("")).await.unwrap();
+    assert_eq!(output.text, "Rayleigh scattering.");
+    assert_eq!((output.prompt_tokens, output.completion_tokens), (12, 2));
+    assert_eq!(output.finish_reason, FinishReason::Length);
+    assert_eq!(received.lock().unwrap()[0]["model"], "llama3.2");
+    assert_eq!(received.lock().unwrap()[0]["stream"], true);
+}
+
+#[tokio::test]
+async fn unknown_models_and_missing_servers_are_explained() {
+    let (host, _) = ollama(StatusCode::NOT_FOUND, r#"{"error":"model \"nope\" not found, try pulling it first"}"#).await;
+    let error = backend(host, false).complete(&Prompt::user("Why?"), &params("nope")).await.unwrap_err();
+    assert_eq!(
+        error.downcast::<BackendError>().unwrap(),
+        BackendError::Status { status: 404, message: "model \"nope\" not found, try pulling it first".to_string() }
+    );
+
+    // Bind and release a port so nothing is listening on it
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let error = backend(format!("http://127.0.0.1:{}", port), false).complete(&Prompt::user("Why?"), &params("")).await.unwrap_err();
+    let error = error.downcast::<BackendError>().unwrap();
+    assert!(matches!(error, BackendError::Unavailable(_)));
+    assert!(error.to_string().starts_with("backend unavailable: cannot connect to http://127.0.0.1:"), "{}", error);
+}
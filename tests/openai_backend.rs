@@ -0,0 +1,148 @@
+//! Runs `OpenAiCompatBackend` against a local stand-in for the chat-completions API.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::{json, Value};
+use void_shrine_mcp::llm_backend::{
+    BackendError, CompletionChunk, FinishReason, LLMBackend, OpenAiCompatBackend, OpenAiCompatConfig, Prompt,
+};
+use void_shrine_mcp::mcp_server::MCPParams;
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// Serves `reply` for every POST to /v1/chat/completions after `delay`, recording
+/// request bodies and authorization headers
+async fn upstream(status: StatusCode, reply: Value, delay: Duration) -> (String, Arc<Mutex<Vec<(Value, Option<String>)>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    let route = warp::path!("v1" / "chat" / "completions")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::json())
+        .then(move |auth: Option<String>, body: Value| {
+            log.lock().unwrap().push((body, auth));
+            let reply = reply.clone();
+            async move {
+                tokio::time::sleep(delay).await;
+                warp::reply::with_status(warp::reply::json(&reply), status)
+            }
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}/v1", addr), received)
+}
+
+fn params(model: &str) -> MCPParams {
+    serde_json::from_value(json!({
+        "agent_id": "agent", "model": model, "specialty": "science", "prompt": "ignored",
+        "max_tokens": 64, "temperature": 0.25, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap()
+}
+
+fn backend(base_url: String, timeout: Duration) -> OpenAiCompatBackend {
+    let mut config = OpenAiCompatConfig::new(base_url, "default-model");
+    config.api_key = Some("sk-test".to_string());
+    config.timeout = timeout;
+    OpenAiCompatBackend::new(config).unwrap()
+}
+
+#[tokio::test]
+async fn translates_request_and_response() {
+    let completion = json!({
+        "id": "chatcmpl-1",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Forty-two." },
+            "logprobs": { "content": [{ "token": "Forty", "logprob": -0.5 }, { "token": "-two", "logprob": -0.1 }, { "token": ".", "logprob": 0.0 }] },
+            "finish_reason": "length"
+        }],
+        "usage": { "prompt_tokens": 17, "completion_tokens": 3, "total_tokens": 20 }
+    });
+    let (base_url, received) = upstream(StatusCode::OK, completion, Duration::ZERO).await;
+    let backend = backend(base_url, Duration::from_secs(5));
+
+    let output = backend.complete(&Prompt::user("What is the answer?"), &params("gpt-4o")).await.unwrap();
+    assert_eq!(output.text, "Forty-two.");
+    assert_eq!((output.prompt_tokens, output.completion_tokens), (17, 3));
+    assert_eq!(output.finish_reason, FinishReason::Length);
+    assert!((output.mean_logprob.unwrap() + 0.2).abs() < 1e-9);
+
+    backend.complete(&Prompt::user("Again"), &params("void-shrine")).await.unwrap();
+
+    let received = received.lock().unwrap();
+    assert_eq!(received[0].0, json!({
+        "model": "gpt-4o",
+        "messages": [{ "role": "user", "content": "What is the answer?" }],
+        "max_tokens": 64,
+        "temperature": 0.25
+    }));
+    assert_eq!(received[0].1.as_deref(), Some("Bearer sk-test"));
+    assert_eq!(received[1].0["model"], "default-model");
+}
+
+#[tokio::test]
+async fn failures_surface_as_backend_errors() {
+    let error = |e: anyhow::Error| e.downcast::<BackendError>().unwrap();
+
+    let body = json!({ "error": { "message": "The model `nope` does not exist", "type": "invalid_request_error" } });
+    let (base_url, _) = upstream(StatusCode::NOT_FOUND, body, Duration::ZERO).await;
+    let failure = error(backend(base_url, Duration::from_secs(5)).complete(&Prompt::user("hi"), &params("nope")).await.unwrap_err());
+    assert_eq!(failure, BackendError::Status { status: 404, message: "The model `nope` does not exist".to_string() });
+    assert_eq!(failure.http_status(), 502);
+
+    let (base_url, _) = upstream(StatusCode::INTERNAL_SERVER_ERROR, json!("overloaded"), Duration::ZERO).await;
+    let failure = error(backend(base_url, Duration::from_secs(5)).complete(&Prompt::user("hi"), &params("")).await.unwrap_err());
+    assert!(matches!(failure, BackendError::Status { status: 500, .. }));
+
+    let (base_url, _) = upstream(StatusCode::OK, json!({}), Duration::from_secs(2)).await;
+    let failure = error(backend(base_url, Duration::from_millis(100)).complete(&Prompt::user("hi"), &params("")).await.unwrap_err());
+    assert_eq!(failure, BackendError::Timeout(Duration::from_millis(100)));
+    assert_eq!((failure.code(), failure.http_status()), ("backend_timeout", 504));
+
+    let (base_url, _) = upstream(StatusCode::OK, json!({ "choices": [] }), Duration::ZERO).await;
+    let failure = error(backend(base_url, Duration::from_secs(5)).complete(&Prompt::user("hi"), &params("")).await.unwrap_err());
+    assert!(matches!(failure, BackendError::InvalidResponse(_)));
+}
+
+#[tokio::test]
+async fn streams_deltas_and_usage() {
+    const EVENTS: &str = concat!(
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Forty\"}}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"-two.\"},\"finish_reason\":\"stop\"}]}\n\n",
+        "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":17,\"completion_tokens\":3}}\n\n",
+        "data: [DONE]\n\n",
+    );
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    let route = warp::path!("v1" / "chat" / "completions").and(warp::body::json()).map(move |body: Value| {
+        log.lock().unwrap().push(body);
+        warp::reply::with_header(EVENTS, "content-type", "text/event-stream")
+    });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let backend = backend(format!("http://{}/v1", addr), Duration::from_secs(5));
+    let prompt = Prompt::user("What is the answer?");
+    let chunks: Vec<CompletionChunk> = backend.complete_stream(&prompt, &params("gpt-4o")).map(Result::unwrap).collect().await;
+
+    let deltas: Vec<&str> = chunks.iter().filter_map(|chunk| match chunk {
+        CompletionChunk::Delta(text) => Some(text.as_str()),
+        CompletionChunk::Done(_) => None,
+    }).collect();
+    assert_eq!(deltas, ["Forty", "-two."]);
+    match chunks.last() {
+        Some(CompletionChunk::Done(output)) => {
+            assert_eq!(output.text, "Forty-two.");
+            assert_eq!((output.prompt_tokens, output.completion_tokens), (17, 3));
+            assert_eq!(output.finish_reason, FinishReason::Stop);
+        }
+        other => panic!("expected the final chunk, got {:?}", other),
+    }
+    let request = &received.lock().unwrap()[0];
+    assert_eq!(request["stream"], true);
+    assert_eq!(request["stream_options"], json!({ "include_usage": true }));
+}
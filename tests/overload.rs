@@ -0,0 +1,145 @@
+//! Overload shedding: while the server as a whole is overloaded, new
+//! inference requests from every agent get 503 `overloaded` and cheap ones
+//! are still served; the overload ends with hysteresis, and its thresholds
+//! change at runtime over `PUT /api/overload`.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use support::{configured_service, epoch, inference, Inference, ScriptedBackend};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::jobs::JobQueue;
+use void_shrine_mcp::overload::{OverloadConfig, OverloadSignal, OverloadStatus};
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+/// The one request holding its slot until cancelled, then `quick` answers
+fn backend(quick: usize) -> Arc<ScriptedBackend> {
+    Arc::new(ScriptedBackend::new().reply_after(Duration::from_secs(60), "done").replies(quick, "done"))
+}
+
+fn ask(agent_id: &str) -> Inference {
+    inference(agent_id, "quick").param("specialty", "research").param("max_tokens", 64)
+}
+
+fn instance(overload: OverloadConfig, backend: Arc<ScriptedBackend>) -> Arc<VoidShrineMCP> {
+    Arc::new(configured_service(Config { overload, ..Config::default() }, backend, Arc::new(ManualClock::new(epoch()))))
+}
+
+/// Trips at a single request in flight and recovers at the first calm look
+fn one_at_a_time() -> OverloadConfig {
+    OverloadConfig { enabled: true, max_in_flight: 1, recover_after_ms: 0, retry_after_ms: 2500, ..OverloadConfig::default() }
+}
+
+/// Starts a request that holds its slot until cancelled
+async fn occupy(service: &Arc<VoidShrineMCP>, request_id: &str) {
+    tokio::spawn({
+        let service = Arc::clone(service);
+        let request = ask("crowd").request_id(request_id).request();
+        async move { service.handle_mcp_request(request).await }
+    });
+    while service.concurrency.status().in_use == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+async fn request(service: &Arc<VoidShrineMCP>, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let jobs = Arc::new(JobQueue::start(Arc::clone(service), Default::default()));
+    let routes = api::routes(Arc::clone(service), jobs).recover(api::recover);
+    let mut request = warp::test::request().method(method).path(path);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn inference_is_shed_while_cheap_requests_are_served() {
+    let service = instance(one_at_a_time(), backend(1));
+    occupy(&service, "hog").await;
+
+    // Any agent's inference, whichever transport it comes by
+    let (status, body) = request(&service, "POST", "/api/mcp", Some(ask("bystander").body())).await;
+    assert_eq!((status, &body["error"], &body["retry_after_ms"]), (503, &json!("overloaded"), &json!(2500)), "{}", body);
+    let stream = service.stream_llm_inference(ask("bystander").params(), None);
+    assert_eq!(stream.err().map(|error| error.code()), Some("overloaded"));
+
+    // Cheap requests aren't
+    for path in ["/health", "/metrics", "/api/throttle/crowd", "/api/overload"] {
+        assert_eq!(request(&service, "GET", path, None).await.0, 200, "{}", path);
+    }
+    let dry_run = ask("bystander").param("dry_run", true).body();
+    assert_eq!(request(&service, "POST", "/api/mcp", Some(dry_run)).await.0, 200);
+
+    let (_, body) = request(&service, "GET", "/api/overload", None).await;
+    let status: OverloadStatus = serde_json::from_value(body).unwrap();
+    assert_eq!((status.overloaded_by, status.signals.in_flight), (Some(OverloadSignal::InFlight), 1));
+    assert_eq!((status.shed_total, status.trips_total), (2, 1));
+    let rendered = service.handle_prometheus().await;
+    for line in [
+        r#"void_shrine_overload_shed_total{signal="in_flight"} 2"#,
+        r#"void_shrine_overload_trips_total{signal="in_flight"} 1"#,
+        "void_shrine_overloaded 1",
+        r#"void_shrine_overload_signal{signal="in_flight"} 1"#,
+    ] {
+        assert!(rendered.contains(line), "{} in {}", line, rendered);
+    }
+    // Shed for overload, not for want of a slot
+    assert!(rendered.contains("void_shrine_requests_shed_total 0"));
+
+    // Once the hog goes the next request is let in and the overload ends
+    service.handle_cancel("hog").unwrap();
+    while service.concurrency.status().in_use > 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    service.handle_mcp_request(ask("crowd").request_id("quick-1").request()).await.unwrap();
+    assert_eq!(service.handle_overload().overloaded_by, None);
+    assert!(service.handle_prometheus().await.contains("void_shrine_overloaded 0"));
+}
+
+#[tokio::test]
+async fn recovery_waits_for_the_signals_to_stay_calm() {
+    let service = instance(OverloadConfig { recover_after_ms: 60_000, ..one_at_a_time() }, backend(0));
+    occupy(&service, "hog").await;
+    assert_eq!(service.handle_mcp_request(ask("crowd").request_id("quick-1").request()).await.unwrap_err().error.code(), "overloaded");
+    service.handle_cancel("hog").unwrap();
+    while service.concurrency.status().in_use > 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    // Calm, but not for long enough
+    assert_eq!(service.handle_mcp_request(ask("crowd").request_id("quick-2").request()).await.unwrap_err().error.code(), "overloaded");
+    let status = service.handle_overload();
+    assert_eq!((status.overloaded_by, status.signals.in_flight), (Some(OverloadSignal::InFlight), 0));
+}
+
+#[tokio::test]
+async fn thresholds_change_at_runtime() {
+    let service = instance(OverloadConfig::default(), backend(2));
+    occupy(&service, "hog").await;
+    // Disabled by default
+    service.handle_mcp_request(ask("crowd").request_id("quick-1").request()).await.unwrap();
+
+    let (status, body) = request(&service, "PUT", "/api/overload", Some(json!({ "enabled": true, "max_in_flight": 1 }))).await;
+    assert_eq!(status, 200, "{}", body);
+    let status: OverloadStatus = serde_json::from_value(body).unwrap();
+    assert_eq!((status.config.enabled, status.config.max_in_flight, status.config.max_p95_ms), (true, 1, 30_000));
+    assert_eq!(service.handle_mcp_request(ask("crowd").request_id("quick-2").request()).await.unwrap_err().error.code(), "overloaded");
+
+    // Refused whole when anything is out of bounds
+    let (status, body) = request(&service, "PUT", "/api/overload", Some(json!({ "max_in_flight": 5, "recover_ratio": 1.5 }))).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("recover_ratio")), "{}", body);
+    assert_eq!(service.handle_overload().config.max_in_flight, 1);
+    let (status, _) = request(&service, "PUT", "/api/overload", Some(json!({ "max_inflight": 5 }))).await;
+    assert_eq!(status, 400);
+
+    // Disabling ends the overload at once
+    let (status, body) = request(&service, "PUT", "/api/overload", Some(json!({ "enabled": false }))).await;
+    assert_eq!((status, &body["overloaded_by"]), (200, &Value::Null));
+    service.handle_mcp_request(ask("crowd").request_id("quick-3").request()).await.unwrap();
+}
@@ -0,0 +1,100 @@
+//! Requests giving only `agent_id` and `prompt`, or some of the rest, and
+//! bodies of the wrong shape refused naming the field at fault.
+
+mod support;
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use support::{configured_service, epoch, inference, ScriptedBackend, TestServer};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::MCPParams;
+use void_shrine_mcp::VoidShrineMCP;
+
+async fn server(config: &Config, backend: Arc<ScriptedBackend>) -> TestServer {
+    TestServer::start(Arc::new(configured_service(config.clone(), backend, Arc::new(ManualClock::new(epoch()))))).await
+}
+
+#[tokio::test]
+async fn minimal_and_partial_params_take_the_defaults() {
+    let minimal: MCPParams = serde_json::from_value(json!({ "agent_id": "minimal", "prompt": "care ethics" })).unwrap();
+    assert_eq!((minimal.model.as_str(), minimal.specialty.as_str()), ("", ""));
+    assert_eq!((minimal.max_tokens, minimal.temperature, minimal.use_rag, minimal.context_window), (1024, 0.7, true, 4096));
+    assert!(minimal.flat_rag_context && minimal.dedupe_chunks);
+
+    let partial: MCPParams = serde_json::from_value(json!({
+        "agent_id": "partial", "prompt": "care ethics", "temperature": 0.1, "use_rag": false
+    }))
+    .unwrap();
+    assert_eq!((partial.max_tokens, partial.temperature, partial.use_rag, partial.context_window), (1024, 0.1, false, 4096));
+
+    let server = server(&Config::default(), Arc::new(ScriptedBackend::new().reply("Care is a practice"))).await;
+    let minimal = json!({ "method": "llm_inference", "params": { "agent_id": "minimal", "prompt": "care ethics" } });
+    let (status, body) = server.post("/api/mcp", &minimal).await;
+    assert_eq!(status, 200, "{}", body);
+    // No knowledge base to draw on, so it says so rather than failing
+    assert_eq!(body["metadata"]["rag_unavailable"], true);
+}
+
+#[tokio::test]
+async fn the_server_defaults_model_and_specialty() {
+    let backend = Arc::new(ScriptedBackend::new().replies(2, "Hold the ridge"));
+    let unnamed = inference("a", "care ethics").body();
+    let (status, body) = server(&Config::default(), Arc::clone(&backend)).await.post("/api/mcp", &unnamed).await;
+    assert_eq!(status, 200, "{}", body);
+
+    let mut config = Config::default();
+    config.defaults.specialty = "tactical".to_string();
+    let (status, body) = server(&config, Arc::clone(&backend)).await.post("/api/mcp", &unnamed).await;
+    assert_eq!(status, 200, "{}", body);
+    let specialties: Vec<String> = backend.params().into_iter().map(|params| params.specialty).collect();
+    assert_ne!(specialties[0], "tactical");
+    assert_eq!(specialties[1], "tactical");
+
+    // Strict specialties refuse a default that isn't loaded
+    config.specialties.strict = true;
+    config.defaults.specialty = "astrology".to_string();
+    let error = VoidShrineMCP::new(&config).err().unwrap();
+    assert!(error.to_string().contains("'astrology'"), "{}", error);
+}
+
+#[tokio::test]
+async fn malformed_bodies_name_the_field_at_fault() {
+    let server = server(&Config::default(), Arc::new(ScriptedBackend::new())).await;
+    let field = |body: &Value| (body["error"].clone(), body["fields"][0].clone());
+    let care = || inference("a", "care ethics");
+    let cases = [
+        (
+            care().param("temperature", "hot").body(),
+            json!({ "field": "params.temperature", "constraint": "f64", "value": "hot" }),
+        ),
+        (
+            json!({ "method": "llm_inference", "params": { "agent_id": "a" } }),
+            json!({ "field": "params.prompt", "constraint": "present", "value": null }),
+        ),
+        (
+            care().param("doc_ids", json!(["tide-pools", 7])).body(),
+            json!({ "field": "params.doc_ids[1]", "constraint": "a string", "value": 7 }),
+        ),
+        (
+            care().param("moral_recentering", "sometimes").body(),
+            json!({ "field": "params.moral_recentering", "constraint": "one of `on`, `off`, `auto`", "value": "sometimes" }),
+        ),
+    ];
+    for (request, expected) in cases {
+        let (status, body) = server.post("/api/mcp", &request).await;
+        assert_eq!((status, field(&body)), (400, (json!("invalid_params"), expected)), "{}", body);
+    }
+
+    let (status, body) = server.post("/api/mcp", &json!({ "method": "llm_inference" })).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("params")));
+    assert_eq!(body["message"], "Invalid params: params must be present (got null)");
+
+    // Not JSON at all, or not an object: nothing to name
+    for broken in ["{broken", r#"["llm_inference"]"#, r#"{"method": "llm_inference", "params": {"agent_id": "a", "prompt": "p"}} trailing"#] {
+        let (status, body) = server.post_text("/api/mcp", broken).await;
+        assert_eq!((status, body["error"].as_str()), (400, Some("invalid_params")));
+        assert!(body.get("fields").is_none_or(|fields| fields.as_array().unwrap().is_empty()), "{}", body);
+    }
+}
@@ -0,0 +1,64 @@
+//! The `/metrics` exposition, read back with a Prometheus text parser.
+
+use std::io::BufRead;
+
+use prometheus_parse::{Scrape, Value};
+use void_shrine_mcp::mcp_server::{MCPParams, MCPRequest};
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+
+fn request(method: &str) -> MCPRequest {
+    let params: MCPParams = serde_json::from_value(serde_json::json!({
+        "agent_id": "scraped", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+        "max_tokens": 64, "temperature": 0.2, "use_rag": true, "context_window": 4096
+    }))
+    .unwrap();
+    MCPRequest { method: method.to_string(), params, request_id: None, idempotency_key: None }
+}
+
+#[tokio::test]
+async fn exposition_parses_and_counts_requests() {
+    let service = VoidShrineMCP::default();
+    {
+        let mut chaos = service.chaos_config.write().await;
+        chaos.intensity = 1.0;
+        chaos.chaos_types = vec!["network_delay".to_string()];
+    }
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+
+    service.handle_mcp_request(request("rag_query")).await.unwrap();
+    service.handle_mcp_request(request("llm_inference")).await.unwrap();
+    service.handle_mcp_request(request("summon")).await.unwrap_err();
+    // Load comes from requests still running
+    let _running = service.track_in_flight("scraped");
+
+    let text = service.handle_prometheus().await;
+    let scrape = Scrape::parse(text.as_bytes().lines()).unwrap();
+    let sample = |metric: &str, labels: &[(&str, &str)]| {
+        scrape
+            .samples
+            .iter()
+            .find(|sample| sample.metric == metric && labels.iter().all(|(name, value)| sample.labels.get(name) == Some(*value)))
+            .unwrap_or_else(|| panic!("no {} {:?} in\n{}", metric, labels, text))
+    };
+
+    assert_eq!(sample("void_shrine_requests_total", &[("method", "rag_query"), ("status", "200")]).value, Value::Counter(1.0));
+    assert_eq!(sample("void_shrine_requests_total", &[("method", "other"), ("status", "400")]).value, Value::Counter(1.0));
+    assert_eq!(sample("void_shrine_chaos_applied_total", &[("chaos_type", "network_delay")]).value, Value::Counter(3.0));
+    assert!(matches!(sample("void_shrine_agent_current_load", &[("agent_id", "scraped")]).value, Value::Gauge(load) if load > 0.0));
+    assert!(matches!(sample("void_shrine_rag_items", &[("kind", "documents")]).value, Value::Gauge(count) if count > 0.0));
+
+    match &sample("void_shrine_request_duration_seconds", &[("method", "llm_inference")]).value {
+        Value::Histogram(buckets) => {
+            let bounds: Vec<f64> = buckets.iter().map(|bucket| bucket.less_than).collect();
+            assert!(bounds.contains(&0.1) && bounds.contains(&10.0), "{:?}", bounds);
+            assert_eq!(buckets.last().unwrap().count, 1.0);
+        }
+        other => panic!("expected a histogram, got {:?}", other),
+    }
+    match &sample("void_shrine_rag_query_duration_seconds", &[("kind", "llm_inference")]).value {
+        Value::Histogram(buckets) => assert_eq!(buckets.last().unwrap().count, 1.0),
+        other => panic!("expected a histogram, got {:?}", other),
+    }
+}
@@ -0,0 +1,137 @@
+//! Quotas: caps on an agent's or tenant's backend usage per hour or day,
+//! refused with 429 `quota_exceeded` once a request would go over, warned
+//! about as they near the cap, checked per batch item, changed at runtime
+//! over `PUT /api/quotas` and kept across restarts with the saved usage.
+
+mod support;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::{json, Value};
+use support::{configured_service, epoch, inference, webhook_receiver, Inference, ScriptedBackend};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::{Config, MetricsConfig};
+use void_shrine_mcp::jobs::JobQueue;
+use void_shrine_mcp::mcp_server::{BatchConfig, BatchRequest};
+use void_shrine_mcp::quota::{QuotaConfig, QuotaMetric, QuotaRule, QuotaStatus};
+use void_shrine_mcp::usage::UsageGranularity;
+use void_shrine_mcp::webhooks::WebhookConfig;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+/// Answers `calls` times, spending 300 prompt and 100 completion tokens each
+fn metered(calls: usize) -> Arc<ScriptedBackend> {
+    Arc::new(ScriptedBackend::new().spending(300, 100).replies(calls, "spent"))
+}
+
+fn late_evening() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 3, 1, 22, 0, 0).unwrap()
+}
+
+fn rule(id: &str, agent_id: Option<&str>, tenant: Option<&str>, metric: QuotaMetric, limit: u64, window: UsageGranularity) -> QuotaRule {
+    QuotaRule { id: id.to_string(), agent_id: agent_id.map(str::to_string), tenant: tenant.map(str::to_string), metric, limit, window }
+}
+
+fn spend(agent_id: &str) -> Inference {
+    inference(agent_id, "spend").param("specialty", "research").param("max_tokens", 64)
+}
+
+async fn request(service: &Arc<VoidShrineMCP>, method: &str, path: &str, body: Value) -> (u16, Value) {
+    let jobs = Arc::new(JobQueue::start(Arc::clone(service), Default::default()));
+    let routes = api::routes(Arc::clone(service), jobs).recover(api::recover);
+    let response = warp::test::request().method(method).path(path).json(&body).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn requests_over_a_token_quota_are_refused_until_it_resets() {
+    let (url, received) = webhook_receiver().await;
+    let clock = Arc::new(ManualClock::new(late_evening()));
+    let quotas = QuotaConfig { rules: vec![rule("scout-daily", Some("scout"), None, QuotaMetric::Tokens, 1000, UsageGranularity::Day)], ..QuotaConfig::default() };
+    let service = configured_service(Config { quotas, ..Config::default() }, metered(5), Arc::clone(&clock))
+        .with_webhooks(WebhookConfig { urls: vec![url], secret: Some("secret".to_string()), ..WebhookConfig::default() });
+    let service = Arc::new(service);
+
+    // 400 tokens each; the third still fits with its max_tokens of 64
+    for _ in 0..3 {
+        service.handle_mcp_request(spend("scout").request()).await.unwrap();
+    }
+    let (status, body) = request(&service, "POST", "/api/mcp", spend("scout").body()).await;
+    assert_eq!((status, &body["error"]), (429, &json!("quota_exceeded")), "{}", body);
+    assert_eq!((&body["quota"]["rule"]["id"], &body["quota"]["consumed"]), (&json!("scout-daily"), &json!(1200)));
+    assert_eq!((&body["quota"]["resets_at"], &body["retry_after_ms"]), (&json!("2026-03-02T00:00:00Z"), &json!(7_200_000)));
+
+    // Other agents, and dry runs, spend nothing of it
+    service.handle_mcp_request(spend("courier").request()).await.unwrap();
+    let dry_run = inference("scout", "spend").param("dry_run", true).body();
+    assert_eq!(request(&service, "POST", "/api/mcp", dry_run).await.0, 200);
+    assert!(service.handle_prometheus().await.contains(r#"void_shrine_quota_rejections_total{quota="scout-daily"} 1"#));
+
+    // Warned once, when the second request reached 80%
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let warnings = received.lock().unwrap().clone();
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert_eq!((&warnings[0]["event"], &warnings[0]["agent_id"]), (&json!("quota_warning"), &json!("scout")));
+    assert_eq!((&warnings[0]["payload"]["quota"]["consumed"], &warnings[0]["payload"]["percent"]), (&json!(800), &json!(80.0)));
+
+    clock.advance(chrono::Duration::hours(2));
+    service.handle_mcp_request(spend("scout").request()).await.unwrap();
+}
+
+#[tokio::test]
+async fn a_tenant_quota_is_checked_per_batch_item() {
+    let quotas = QuotaConfig { rules: vec![rule("acme-hourly", None, Some("acme"), QuotaMetric::Requests, 2, UsageGranularity::Hour)], ..QuotaConfig::default() };
+    let batch = BatchConfig { concurrency: 1, ..BatchConfig::default() };
+    let service = configured_service(Config { quotas, batch, ..Config::default() }, metered(3), Arc::new(ManualClock::new(epoch())));
+    let acme = Tenancy::Tenant("acme".to_string());
+    service.admit_agent(&acme, "scout").unwrap();
+    service.admit_agent(&acme, "courier").unwrap();
+
+    let items = ["scout", "courier", "scout"].map(|agent_id| spend(agent_id).params()).to_vec();
+    let batch = service.handle_batch(BatchRequest { method: "llm_inference".to_string(), items }).await.unwrap();
+    let statuses: Vec<u16> = batch.items.iter().map(|item| item.status).collect();
+    assert_eq!(statuses, [200, 200, 429]);
+    let error = batch.items[2].error.as_ref().unwrap();
+    assert_eq!((error.error.as_str(), error.quota.as_ref().map(|quota| quota.consumed)), ("quota_exceeded", Some(2)));
+
+    // Agents of other tenants aren't capped by it
+    service.handle_mcp_request(spend("stranger").request()).await.unwrap();
+}
+
+#[tokio::test]
+async fn quotas_change_at_runtime_and_survive_a_restart() {
+    let path: PathBuf = std::env::temp_dir().join(format!("void-shrine-quotas-{}.json", uuid::Uuid::new_v4()));
+    let quotas = QuotaConfig { rules: vec![rule("scout-hourly", Some("scout"), None, QuotaMetric::Requests, 1, UsageGranularity::Hour)], ..QuotaConfig::default() };
+    let start = || {
+        let metrics = MetricsConfig { state_path: Some(path.clone()), ..MetricsConfig::default() };
+        let config = Config { metrics, quotas: quotas.clone(), ..Config::default() };
+        Arc::new(configured_service(config, metered(1), Arc::new(ManualClock::new(epoch()))))
+    };
+    let first = start();
+    first.handle_mcp_request(spend("scout").request()).await.unwrap();
+    first.save_agent_metrics().unwrap();
+    drop(first);
+
+    let second = start();
+    assert_eq!(second.handle_mcp_request(spend("scout").request()).await.unwrap_err().error.code(), "quota_exceeded");
+    let (status, body) = request(&second, "GET", "/api/quotas", Value::Null).await;
+    assert_eq!(status, 200);
+    let listed: QuotaStatus = serde_json::from_value(body).unwrap();
+    assert_eq!((listed.warn_percent, listed.quotas[0].consumed), (80.0, 1));
+
+    let raised = json!({ "warn_percent": 90, "rules": [{ "id": "scout-hourly", "agent_id": "scout", "metric": "requests", "limit": 2, "window": "hour" }] });
+    let (status, body) = request(&second, "PUT", "/api/quotas", raised).await;
+    assert_eq!((status, &body["quotas"][0]["rule"]["limit"]), (200, &json!(2)), "{}", body);
+    second.handle_mcp_request(spend("scout").request()).await.unwrap();
+
+    // Refused whole when any rule is invalid
+    let targetless = json!({ "rules": [{ "id": "loose", "metric": "tokens", "limit": 10, "window": "day" }] });
+    let (status, body) = request(&second, "PUT", "/api/quotas", targetless).await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("rules[0].agent_id")), "{}", body);
+    assert_eq!(second.handle_quotas().quotas.len(), 1);
+    std::fs::remove_file(path).ok();
+}
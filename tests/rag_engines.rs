@@ -0,0 +1,125 @@
+//! Named knowledge bases: each its own index, chosen per request and per
+//! route, listed with their stats, locked independently, and refused by
+//! name when unknown.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::{MCPError, MCPRequest};
+use void_shrine_mcp::rag_engine::{Document, RAGEngine};
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+async fn service() -> Arc<VoidShrineMCP> {
+    let config = Config::from_toml("[rag.engines.legal]\nchunk_size = 256\noverlap_size = 32\n").unwrap();
+    let service = VoidShrineMCP::new(&config).unwrap();
+    service.chaos_config.write().await.enabled = false;
+    for (name, engine) in service.rag_engines.iter() {
+        let mut rag = match config.rag.engines.get(name) {
+            Some(settings) => settings.open().await.unwrap(),
+            None => RAGEngine::new().await.unwrap(),
+        };
+        rag.index_document(Document {
+            id: format!("{}-notes", name),
+            title: format!("Notes of the {} engine", name),
+            content: format!("Tide pools are described in the {} engine only.", name),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunks: Vec::new(),
+        })
+        .await
+        .unwrap();
+        *engine.write().await = Some(rag);
+    }
+    Arc::new(service)
+}
+
+async fn call(service: &Arc<VoidShrineMCP>, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let routes = api::document_routes(Arc::clone(service)).or(api::rag_admin_routes(Arc::clone(service))).recover(api::recover);
+    let mut request = warp::test::request().method(method).path(path);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+fn rag_query(params: Value) -> MCPRequest {
+    let mut all = json!({ "agent_id": "seeker", "prompt": "tide pools" });
+    all.as_object_mut().unwrap().extend(params.as_object().unwrap().clone());
+    MCPRequest { method: "rag_query".to_string(), params: serde_json::from_value(all).unwrap(), request_id: None, idempotency_key: None }
+}
+
+#[tokio::test]
+async fn requests_and_routes_choose_their_engine() {
+    let service = service().await;
+    let cited = |response: void_shrine_mcp::mcp_server::MCPResponse| -> Vec<String> {
+        response.result.citations.iter().flatten().map(|citation| citation.document_id.clone()).collect()
+    };
+    assert_eq!(cited(service.handle_mcp_request(rag_query(json!({}))).await.unwrap()), ["default-notes"]);
+    assert_eq!(cited(service.handle_mcp_request(rag_query(json!({ "rag_engine": "legal" }))).await.unwrap()), ["legal-notes"]);
+
+    let document = json!({ "id": "brief", "title": "Brief", "content": "A brief on tide pools." });
+    assert_eq!(call(&service, "POST", "/api/rag/documents?engine=legal", Some(document)).await.0, 200);
+    assert_eq!(call(&service, "GET", "/api/rag/documents/brief?engine=legal", None).await.0, 200);
+    assert_eq!(call(&service, "GET", "/api/rag/documents/brief", None).await.0, 404);
+    let (_, results) = call(&service, "GET", "/api/rag/query?q=brief&engine=legal", None).await;
+    assert_eq!(results["results"][0]["document_id"], "brief");
+    let (_, results) = call(&service, "GET", "/api/rag/query?q=brief", None).await;
+    assert_eq!(results["results"], json!([]));
+
+    let (status, listed) = call(&service, "GET", "/api/rag/engines", None).await;
+    assert_eq!(status, 200);
+    let engines: Vec<(Value, Value, Value, Value)> = listed["engines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|engine| (engine["name"].clone(), engine["default"].clone(), engine["stats"]["document_count"].clone(), engine["stats"]["chunk_size"].clone()))
+        .collect();
+    assert_eq!(engines, [(json!("default"), json!(true), json!(1), json!(512)), (json!("legal"), json!(false), json!(2), json!(256))]);
+
+    let (status, task) = call(&service, "POST", "/api/rag/rebuild-fts?engine=legal", None).await;
+    assert_eq!((status, task["engine"].as_str()), (202, Some("legal")));
+}
+
+#[tokio::test]
+async fn unknown_engines_are_refused_with_the_known_names() {
+    let service = service().await;
+    for (method, path) in [("GET", "/api/rag/stats?engine=medical"), ("GET", "/api/rag/query?q=tide&engine=medical"), ("DELETE", "/api/rag/documents?engine=medical&category=x")] {
+        let (status, error) = call(&service, method, path, None).await;
+        assert_eq!((status, error["fields"][0]["field"].as_str()), (400, Some("engine")), "{} {}", method, path);
+        assert_eq!(error["fields"][0]["constraint"], "one of default, legal");
+    }
+
+    let failed = service.handle_mcp_request(rag_query(json!({ "rag_engine": "medical" }))).await.unwrap_err();
+    let MCPError::InvalidFields(fields) = failed.error else { panic!("{:?}", failed.error) };
+    assert_eq!((fields[0].field.as_str(), fields[0].value.as_str()), ("rag_engine", Some("medical")));
+}
+
+#[tokio::test]
+async fn a_locked_engine_does_not_hold_up_the_others() {
+    let service = service().await;
+    let legal = service.rag_engines.get("engine", Some("legal")).unwrap();
+    let _reindexing = legal.write().await;
+
+    let answered = tokio::time::timeout(Duration::from_secs(2), service.handle_mcp_request(rag_query(json!({})))).await;
+    assert!(answered.expect("the default engine answers while legal is locked").is_ok());
+    let stats = tokio::time::timeout(Duration::from_millis(100), service.handle_rag_stats(Some("legal"))).await;
+    assert!(stats.is_err(), "legal waits for its own lock");
+}
+
+#[test]
+fn engines_need_names_files_and_chunking_of_their_own() {
+    let config = Config::from_toml(
+        "[rag]\ndb_path = \"kb.db\"\n[rag.engines.default]\n[rag.engines.legal]\ndb_path = \"kb.db\"\n[rag.engines.\"no spaces\"]\nchunk_size = 64\noverlap_size = 64\n",
+    )
+    .unwrap();
+    let error = config.validate().unwrap_err().to_string();
+    assert!(error.contains("rag.engines.default is the name"), "{}", error);
+    assert!(error.contains("rag.engines.legal.db_path is rag's file too"), "{}", error);
+    assert!(error.contains("rag.engines.no spaces.overlap_size must be smaller"), "{}", error);
+    assert!(error.contains("'no spaces'"), "{}", error);
+}
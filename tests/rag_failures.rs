@@ -0,0 +1,77 @@
+//! A knowledge base search failing mid-request: degraded to an answer
+//! without context and a `rag_error`, or refused as `rag_unavailable`.
+
+mod support;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::json;
+use support::{epoch, service, ScriptedBackend};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::mcp_server::{MCPRequest, RetrievalFailurePolicy};
+use void_shrine_mcp::rag_engine::{Document, RAGEngine};
+use void_shrine_mcp::VoidShrineMCP;
+
+fn inference() -> MCPRequest {
+    let params = json!({ "agent_id": "seeker", "prompt": "Why do tide pools shelter anemones?", "use_rag": true });
+    MCPRequest { method: "llm_inference".to_string(), params: serde_json::from_value(params).unwrap(), request_id: None, idempotency_key: None }
+}
+
+/// An engine on disk whose chunks and full-text index were dropped behind
+/// its back, so every search fails
+async fn broken_rag(path: &PathBuf) -> RAGEngine {
+    let mut rag = RAGEngine::builder().path(path).build().await.unwrap();
+    rag.index_document(Document {
+        id: "tide-pools".to_string(),
+        title: "Tide pools".to_string(),
+        content: "Anemones and hermit crabs shelter in tide pools between the tides.".to_string(),
+        metadata: Default::default(),
+        embedding: None,
+        chunks: Vec::new(),
+    })
+    .await
+    .unwrap();
+    sqlite::open(path).unwrap().execute("DROP TABLE chunks_fts; DROP TABLE chunks").unwrap();
+    rag
+}
+
+async fn broken_service(name: &str, policy: RetrievalFailurePolicy) -> (VoidShrineMCP, Arc<ScriptedBackend>, PathBuf) {
+    let path = std::env::temp_dir().join(format!("void-shrine-rag-failure-{}-{}.db", name, uuid::Uuid::new_v4()));
+    let backend = Arc::new(ScriptedBackend::new().reply("Anemones like the shelter.").reply("Anemones like the shelter."));
+    let mut service = service(Arc::clone(&backend), Arc::new(ManualClock::new(epoch())));
+    service.rag_failure_policy = policy;
+    *service.rag_engine.write().await = Some(broken_rag(&path).await);
+    (service, backend, path)
+}
+
+#[tokio::test]
+async fn degraded_requests_are_answered_without_context() {
+    let (service, backend, path) = broken_service("degrade", RetrievalFailurePolicy::Degrade).await;
+
+    let response = service.handle_mcp_request(inference()).await.unwrap();
+    assert_eq!(response.result.response, "Anemones like the shelter.");
+    assert_eq!(response.result.metrics.rag_documents_used, 0);
+    assert!(response.result.citations.is_none());
+    assert!(response.result.metrics.retrieval.is_none());
+    assert!(!response.metadata.rag_unavailable);
+    let rag_error = response.metadata.rag_error.unwrap();
+    assert!(rag_error.contains("no such table"), "{}", rag_error);
+    assert!(!backend.prompts()[0].user.contains("hermit crabs"));
+
+    // Ungrounded answers are not cached for the next request
+    let again = service.handle_mcp_request(inference()).await.unwrap();
+    assert!(!again.metadata.cached && again.metadata.rag_error.is_some());
+    std::fs::remove_file(path).ok();
+}
+
+#[tokio::test]
+async fn failing_requests_are_refused_as_rag_unavailable() {
+    let (service, backend, path) = broken_service("fail", RetrievalFailurePolicy::Fail).await;
+
+    let failure = service.handle_mcp_request(inference()).await.unwrap_err();
+    assert_eq!((failure.error.code(), failure.error.http_status()), ("rag_unavailable", 503));
+    assert!(failure.error.to_string().starts_with("Knowledge base retrieval failed: "), "{}", failure.error);
+    assert!(backend.prompts().is_empty());
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,156 @@
+//! The knowledge base REST routes, with and without an initialized engine.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::api;
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+async fn call(service: &Arc<VoidShrineMCP>, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let routes = api::document_routes(Arc::clone(service)).recover(api::recover);
+    let mut request = warp::test::request().method(method).path(path);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn every_route_is_unavailable_without_an_engine() {
+    let service = Arc::new(VoidShrineMCP::default());
+    let document = json!({ "title": "Field notes", "content": "Notes from the field." });
+    for (method, path, body) in [
+        ("POST", "/api/rag/documents", Some(document)),
+        ("GET", "/api/rag/documents/field_notes", None),
+        ("DELETE", "/api/rag/documents/field_notes", None),
+        ("GET", "/api/rag/stats", None),
+        ("POST", "/api/rag/query", Some(json!({ "query": "field" }))),
+        ("GET", "/api/rag/query?q=field", None),
+    ] {
+        let (status, body) = call(&service, method, path, body).await;
+        assert_eq!((status, body["error"].as_str()), (503, Some("rag_unavailable")), "{} {}", method, path);
+    }
+}
+
+#[tokio::test]
+async fn documents_round_trip() {
+    let service = Arc::new(VoidShrineMCP::default());
+    *service.rag_engine.write().await = Some(RAGEngine::new().await.unwrap());
+
+    let (status, created) = call(
+        &service,
+        "POST",
+        "/api/rag/documents",
+        Some(json!({
+            "title": "Tidal pools",
+            "content": "Tidal pools hold anemones and hermit crabs between the tides.",
+            "metadata": { "category": "ecology" }
+        })),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let id = created["document_id"].as_str().unwrap().to_string();
+    assert!(!id.is_empty());
+
+    let (status, document) = call(&service, "GET", &format!("/api/rag/documents/{}", id), None).await;
+    assert_eq!(status, 200);
+    assert_eq!(document["title"], "Tidal pools");
+    assert!(document["content"].as_str().unwrap().contains("anemones"));
+
+    let (status, stats) = call(&service, "GET", "/api/rag/stats", None).await;
+    assert_eq!((status, stats["document_count"].as_u64()), (200, Some(1)));
+
+    let search = |category: &str| json!({ "query": "anemones", "limit": 5, "filters": { "category": category } });
+    let (status, found) = call(&service, "POST", "/api/rag/query", Some(search("ecology"))).await;
+    assert_eq!(status, 200);
+    assert_eq!(found["results"][0]["document_id"], id.as_str());
+    let (_, filtered) = call(&service, "POST", "/api/rag/query", Some(search("astronomy"))).await;
+    assert!(filtered["results"].as_array().unwrap().is_empty());
+
+    let (status, invalid) = call(&service, "POST", "/api/rag/query", Some(json!({ "query": " ", "limit": 0 }))).await;
+    assert_eq!((status, invalid["error"].as_str()), (400, Some("invalid_params")));
+    assert_eq!(invalid["fields"].as_array().unwrap().len(), 2);
+
+    let (status, deleted) = call(&service, "DELETE", &format!("/api/rag/documents/{}", id), None).await;
+    assert_eq!((status, deleted["deleted"].as_u64()), (200, Some(1)));
+    for method in ["GET", "DELETE"] {
+        let (status, missing) = call(&service, method, &format!("/api/rag/documents/{}", id), None).await;
+        assert_eq!((status, missing["error"].as_str()), (404, Some("document_not_found")), "{}", method);
+    }
+}
+
+#[tokio::test]
+async fn get_query_reproduces_an_inference_retrieval() {
+    let service = Arc::new(VoidShrineMCP::default());
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+
+    let prompt = "How do agents coordinate through care ethics?";
+    let request: MCPRequest = serde_json::from_value(json!({
+        "method": "llm_inference",
+        "params": {
+            "agent_id": "researcher", "prompt": prompt, "max_tokens": 64,
+            "temperature": 0.2, "use_rag": true, "context_window": 65536
+        }
+    }))
+    .unwrap();
+    let response = service.handle_mcp_request(request).await.unwrap();
+    let cited: Vec<String> = response.result.citations.unwrap().into_iter().map(|citation| citation.chunk_id).collect();
+    assert!(!cited.is_empty());
+
+    let path = format!("/api/rag/query?q={}&agent_id=researcher", prompt.replace(' ', "%20").replace('?', "%3F"));
+    let (status, found) = call(&service, "GET", &path, None).await;
+    assert_eq!(status, 200);
+    let retrieved: Vec<&str> = found["results"].as_array().unwrap().iter().map(|result| result["chunk_id"].as_str().unwrap()).collect();
+    assert_eq!(retrieved, cited);
+
+    // Other parameters filter by metadata
+    let (_, filtered) = call(&service, "GET", "/api/rag/query?q=agents&limit=2&category=no-such-category", None).await;
+    assert!(filtered["results"].as_array().unwrap().is_empty());
+
+    let (status, invalid) = call(&service, "GET", "/api/rag/query?q=%20&limit=0&since=yesterday", None).await;
+    assert_eq!((status, invalid["error"].as_str()), (400, Some("invalid_params")));
+    let fields: Vec<&str> = invalid["fields"].as_array().unwrap().iter().map(|field| field["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, ["since", "q", "limit"]);
+    let (status, _) = call(&service, "GET", "/api/rag/query", None).await;
+    assert_eq!(status, 400);
+
+    // Unchanged results revalidate to a bodiless 304
+    let routes = api::document_routes(Arc::clone(&service)).recover(api::recover);
+    let first = warp::test::request().path("/api/rag/query?q=agents").reply(&routes).await;
+    assert_eq!(first.headers()["cache-control"], "private, no-cache");
+    let etag = first.headers()["etag"].to_str().unwrap().to_string();
+    let again = warp::test::request().path("/api/rag/query?q=agents").header("if-none-match", &etag).reply(&routes).await;
+    assert_eq!(again.status(), 304);
+    assert!(again.body().is_empty());
+}
+
+#[tokio::test]
+async fn ranking_refuses_boosts_that_would_zero_or_flip_scores() {
+    let service = Arc::new(VoidShrineMCP::default());
+    *service.rag_engine.write().await = Some(RAGEngine::new().await.unwrap());
+    let routes = api::rag_admin_routes(Arc::clone(&service)).recover(api::recover);
+    let put = |body: Value| warp::test::request().method("PUT").path("/api/rag/ranking").json(&body).reply(&routes);
+
+    let response = put(json!({
+        "recency_half_life_days": 0,
+        "metadata_boosts": { "category": { "ecology": -2.0, "craft": 1.5 } },
+        "tag_boosts": { "pinned": 0 }
+    }))
+    .await;
+    assert_eq!(response.status(), 400);
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["error"], "invalid_params");
+    let fields: Vec<&str> = body["fields"].as_array().unwrap().iter().map(|field| field["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, ["recency_half_life_days", "metadata_boosts.category.ecology", "tag_boosts.pinned"]);
+    assert_eq!(service.rag_engine.read().await.as_ref().unwrap().ranking(), &Default::default());
+
+    let response = put(json!({ "recency_half_life_days": 30, "tag_boosts": { "pinned": 1.5 } })).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(service.rag_engine.read().await.as_ref().unwrap().ranking().tag_boosts["pinned"], 1.5);
+}
@@ -0,0 +1,130 @@
+//! Client-supplied request ids on `/api/mcp`: accepted from the body or the
+//! `X-Request-Id` header, echoed back, and attached to the request's logs.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use void_shrine_mcp::api;
+use void_shrine_mcp::mcp_server::{validate_request_id, MCPRequest, MAX_REQUEST_ID_LEN};
+use void_shrine_mcp::VoidShrineMCP;
+use warp::Filter;
+
+fn request(request_id: Option<&str>) -> Value {
+    let mut request = json!({
+        "method": "llm_inference",
+        "params": {
+            "agent_id": "tracer", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+            "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096
+        }
+    });
+    if let Some(id) = request_id {
+        request["request_id"] = id.into();
+    }
+    request
+}
+
+async fn service() -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default();
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+/// Status, `X-Request-Id` header and body
+async fn post(service: Arc<VoidShrineMCP>, header: Option<&str>, body: Value) -> (u16, Option<String>, Value) {
+    let routes = api::mcp_route(service).recover(api::recover);
+    let mut request = warp::test::request()
+        .method("POST")
+        .path("/api/mcp")
+        .header("content-type", "application/json")
+        .body(body.to_string());
+    if let Some(id) = header {
+        request = request.header("x-request-id", id);
+    }
+    let response = request.reply(&routes).await;
+    let echoed = response.headers().get("x-request-id").map(|value| value.to_str().unwrap().to_string());
+    (response.status().as_u16(), echoed, serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn supplied_ids_are_used_and_echoed() {
+    let (status, echoed, body) = post(service().await, None, request(Some("trace-42"))).await;
+    assert_eq!(status, 200);
+    assert_eq!(echoed.as_deref(), Some("trace-42"));
+    assert_eq!(body["metadata"]["request_id"], "trace-42");
+
+    let (_, echoed, body) = post(service().await, Some("from-header"), request(None)).await;
+    assert_eq!(echoed.as_deref(), Some("from-header"));
+    assert_eq!(body["metadata"]["request_id"], "from-header");
+
+    // The body wins over the header
+    let (_, echoed, _) = post(service().await, Some("from-header"), request(Some("from-body"))).await;
+    assert_eq!(echoed.as_deref(), Some("from-body"));
+
+    // Without either, a generated id is still echoed
+    let (_, echoed, body) = post(service().await, None, request(None)).await;
+    assert!(echoed.is_some());
+    assert_eq!(body["metadata"]["request_id"], echoed.unwrap().as_str());
+}
+
+#[tokio::test]
+async fn malformed_ids_are_refused() {
+    for id in ["", "has space", "new\nline", "ünïcode", &"x".repeat(MAX_REQUEST_ID_LEN + 1)] {
+        assert!(validate_request_id(id).is_err(), "{id:?}");
+    }
+    assert!(validate_request_id("a.b-c_d:9").is_ok());
+    assert!(validate_request_id(&"x".repeat(MAX_REQUEST_ID_LEN)).is_ok());
+
+    let (status, echoed, body) = post(service().await, None, request(Some("two words"))).await;
+    assert_eq!(status, 400);
+    assert_eq!(echoed, None);
+    assert_eq!(body["error"], "invalid_params");
+    assert_eq!(body["fields"][0]["field"], "request_id");
+}
+
+#[tokio::test]
+async fn repeated_ids_are_allowed() {
+    let service = service().await;
+    for _ in 0..2 {
+        let (status, echoed, _) = post(Arc::clone(&service), None, request(Some("again"))).await;
+        assert_eq!((status, echoed.as_deref()), (200, Some("again")));
+    }
+}
+
+/// Collects formatted log output
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn logs_carry_the_request_id() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let service = service().await;
+    let request: MCPRequest = serde_json::from_value(request(Some("logged-7"))).unwrap();
+    service.handle_mcp_request(request.clone()).await.unwrap();
+    service.handle_mcp_request(request).await.unwrap();
+
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let processing: Vec<&str> = logs.lines().filter(|line| line.contains("Processing MCP request")).collect();
+    assert_eq!(processing.len(), 2, "{logs}");
+    assert!(processing.iter().all(|line| line.contains("request_id=logged-7")), "{logs}");
+    assert!(logs.lines().any(|line| line.contains("WARN") && line.contains("logged-7") && line.contains("repeats")), "{logs}");
+}
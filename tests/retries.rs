@@ -0,0 +1,150 @@
+//! Transient backend failures retried with backoff: counted in the response
+//! metrics and the audit log, never past the deadline, and streams only
+//! before their first token.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde_json::json;
+use void_shrine_mcp::audit::{AuditConfig, AuditLog, AuditQuery, AuditSink};
+use void_shrine_mcp::llm_backend::{BackendError, CompletionChunk, CompletionOutput, FinishReason, LLMBackend, Prompt, RetryConfig};
+use void_shrine_mcp::mcp_server::{InferenceEvent, MCPParams, MCPRequest};
+use void_shrine_mcp::VoidShrineMCP;
+
+/// Fails its first `failures` calls with `error`, then answers. Streams fail
+/// after their first delta when `fail_mid_stream` is set.
+struct Flaky {
+    failures: u32,
+    error: BackendError,
+    fail_mid_stream: bool,
+    calls: AtomicU32,
+}
+
+impl Flaky {
+    fn new(failures: u32, error: BackendError) -> Arc<Self> {
+        Arc::new(Self { failures, error, fail_mid_stream: false, calls: AtomicU32::new(0) })
+    }
+
+    fn failing_mid_stream(error: BackendError) -> Arc<Self> {
+        Arc::new(Self { failures: 0, error, fail_mid_stream: true, calls: AtomicU32::new(0) })
+    }
+
+    fn call(&self) -> anyhow::Result<CompletionOutput> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+            return Err(self.error.clone().into());
+        }
+        Ok(CompletionOutput { text: "steady".to_string(), prompt_tokens: 4, completion_tokens: 1, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+    }
+}
+
+impl LLMBackend for Flaky {
+    fn name(&self) -> &str {
+        "flaky"
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        let outcome = self.call();
+        Box::pin(async move { outcome })
+    }
+
+    fn complete_stream<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxStream<'a, anyhow::Result<CompletionChunk>> {
+        let chunks = match self.call() {
+            Ok(output) if self.fail_mid_stream => vec![Ok(CompletionChunk::Delta("stea".to_string())), Err(self.error.clone().into()), Ok(CompletionChunk::Done(output))],
+            Ok(output) => vec![Ok(CompletionChunk::Delta(output.text.clone())), Ok(CompletionChunk::Done(output))],
+            Err(e) => vec![Err(e)],
+        };
+        stream::iter(chunks).boxed()
+    }
+}
+
+fn bad_gateway() -> BackendError {
+    BackendError::Status { status: 502, message: "upstream reset".to_string() }
+}
+
+fn params(timeout_ms: u64) -> MCPParams {
+    serde_json::from_value(json!({
+        "agent_id": "persistent", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+        "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096, "timeout_ms": timeout_ms
+    }))
+    .unwrap()
+}
+
+fn request(timeout_ms: u64) -> MCPRequest {
+    MCPRequest { method: "llm_inference".to_string(), params: params(timeout_ms), request_id: None, idempotency_key: None }
+}
+
+async fn retrying(backend: Arc<Flaky>) -> VoidShrineMCP {
+    let retries = RetryConfig { max_attempts: 3, initial_backoff_ms: 10, max_backoff_ms: 20, jitter: 0.0 };
+    let service = VoidShrineMCP::default().with_backend(backend).with_retries(retries);
+    service.chaos_config.write().await.enabled = false;
+    service
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried_and_counted() {
+    let path = std::env::temp_dir().join(format!("void-shrine-retries-test-{}.jsonl", uuid::Uuid::new_v4()));
+    let audit = AuditConfig { sink: AuditSink::Jsonl, path: Some(path.clone()), ..AuditConfig::default() };
+    let backend = Flaky::new(2, bad_gateway());
+    let service = retrying(Arc::clone(&backend)).await.with_audit(AuditLog::open(&audit).unwrap().unwrap());
+
+    let response = service.handle_mcp_request(request(5_000)).await.unwrap();
+    assert_eq!(response.result.response, "steady");
+    // 10 ms, then 20
+    assert_eq!((response.result.metrics.attempts, response.result.metrics.retry_delay_ms), (3, 30));
+
+    let audit = service.audit.as_ref().unwrap();
+    audit.flush().await;
+    let records = audit.query(AuditQuery::default()).await.unwrap();
+    assert_eq!(records[0].metrics.as_ref().unwrap().attempts, 3);
+    std::fs::remove_file(path).ok();
+
+    // One failure too many
+    let backend = Flaky::new(3, bad_gateway());
+    let failure = retrying(Arc::clone(&backend)).await.handle_mcp_request(request(5_000)).await.unwrap_err();
+    assert_eq!(failure.error.http_status(), 502);
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 3);
+
+    // Not worth retrying
+    let backend = Flaky::new(1, BackendError::Status { status: 400, message: "bad request".to_string() });
+    retrying(Arc::clone(&backend)).await.handle_mcp_request(request(5_000)).await.unwrap_err();
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn retry_after_is_respected_but_never_past_the_deadline() {
+    let rate_limited = |wait_ms| BackendError::RateLimited { retry_after: Some(Duration::from_millis(wait_ms)), message: "slow down".to_string() };
+
+    let service = retrying(Flaky::new(1, rate_limited(50))).await;
+    let response = service.handle_mcp_request(request(5_000)).await.unwrap();
+    assert_eq!((response.result.metrics.attempts, response.result.metrics.retry_delay_ms), (2, 50));
+
+    // Waiting ten seconds would blow a one-second deadline: fail at once instead
+    let backend = Flaky::new(1, rate_limited(10_000));
+    let started = Instant::now();
+    let failure = retrying(Arc::clone(&backend)).await.handle_mcp_request(request(1_000)).await.unwrap_err();
+    assert!(started.elapsed() < Duration::from_millis(500), "{:?}", started.elapsed());
+    assert_eq!(failure.error.code(), "backend_rate_limited");
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn streams_retry_only_before_their_first_token() {
+    let service = Arc::new(retrying(Flaky::new(1, bad_gateway())).await);
+    let events: Vec<InferenceEvent> = service.stream_llm_inference(params(5_000), None).unwrap().collect().await;
+    match events.last() {
+        Some(InferenceEvent::Done { response, metrics, .. }) => {
+            assert_eq!(response, "steady");
+            assert_eq!(metrics.attempts, 2);
+        }
+        other => panic!("stream ended with {:?}", other),
+    }
+
+    let backend = Flaky::failing_mid_stream(bad_gateway());
+    let service = Arc::new(retrying(Arc::clone(&backend)).await);
+    let events: Vec<InferenceEvent> = service.stream_llm_inference(params(5_000), None).unwrap().collect().await;
+    assert!(matches!(events.last(), Some(InferenceEvent::Error { .. })), "{:?}", events.last());
+    assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+}
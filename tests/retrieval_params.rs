@@ -0,0 +1,83 @@
+//! `rag_top_k`, `rag_min_score`, `rag_filters`, `rag_collection` and
+//! `rag_mode`: retrieval steered per request within the server's limits, and
+//! the settings used reported in the metrics.
+
+mod support;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use support::{epoch, fixture_rag, service, ScriptedBackend};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::mcp_server::{MCPError, MCPRequest, MCPResponse, ParamLimits};
+use void_shrine_mcp::rag_engine::{Document, RetrievalMode};
+use void_shrine_mcp::VoidShrineMCP;
+
+fn request(method: &str, params: Value) -> MCPRequest {
+    let mut all = json!({ "agent_id": "seeker", "prompt": "tide pools lichen bellows forge rock" });
+    all.as_object_mut().unwrap().extend(params.as_object().unwrap().clone());
+    MCPRequest { method: method.to_string(), params: serde_json::from_value(all).unwrap(), request_id: None, idempotency_key: None }
+}
+
+async fn fixture_service() -> VoidShrineMCP {
+    let backend = Arc::new(ScriptedBackend::new().reply("Noted."));
+    let service = service(backend, Arc::new(ManualClock::new(epoch())))
+        .with_param_limits(ParamLimits { max_rag_top_k: 2, ..ParamLimits::default() });
+    let mut rag = fixture_rag().await;
+    rag.index_document(Document {
+        id: "tide-tables".to_string(),
+        title: "Tide tables".to_string(),
+        content: "Tide tables give the hours tide pools are exposed on the rock.".to_string(),
+        metadata: HashMap::from([("collection".to_string(), "almanac".to_string())]),
+        embedding: None,
+        chunks: Vec::new(),
+    })
+    .await
+    .unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    service
+}
+
+async fn documents(service: &VoidShrineMCP, method: &str, params: Value) -> (MCPResponse, Vec<String>) {
+    let response = service.handle_mcp_request(request(method, params)).await.unwrap();
+    let documents = response.result.citations.iter().flatten().map(|citation| citation.document_id.clone()).collect();
+    (response, documents)
+}
+
+#[tokio::test]
+async fn retrieval_follows_the_request_within_the_limits() {
+    let service = fixture_service().await;
+
+    let (response, retrieved) = documents(&service, "rag_query", json!({ "rag_top_k": 10 })).await;
+    assert_eq!(retrieved.len(), 2);
+    let retrieval = response.result.metrics.retrieval.unwrap();
+    assert_eq!((retrieval.top_k, retrieval.mode), (2, RetrievalMode::Keyword));
+
+    let (_, crafted) = documents(&service, "rag_query", json!({ "rag_filters": { "category": "craft" } })).await;
+    assert_eq!(crafted, ["bellows"]);
+    let (_, almanac) = documents(&service, "llm_inference", json!({ "rag_collection": "almanac" })).await;
+    assert_eq!(almanac, ["tide-tables"]);
+    let (response, nothing) = documents(&service, "rag_query", json!({ "rag_min_score": 1e9 })).await;
+    assert!(nothing.is_empty());
+    assert_eq!(response.result.metrics.retrieval.unwrap().min_score, Some(1e9));
+
+    // Inference's usual five chunks are lowered to the maximum too
+    let (response, _) = documents(&service, "llm_inference", json!({ "dry_run": true })).await;
+    assert_eq!(response.result.metrics.retrieval.unwrap().top_k, 2);
+    let (response, _) = documents(&service, "llm_inference", json!({ "dry_run": true, "use_rag": false })).await;
+    assert!(response.result.metrics.retrieval.is_none());
+}
+
+#[tokio::test]
+async fn retrieval_params_out_of_bounds_are_refused() {
+    let service = fixture_service().await;
+    let fields = |error: MCPError| error.fields().iter().map(|field| field.field.clone()).collect::<Vec<_>>();
+    let params = json!({ "rag_top_k": 0, "rag_min_score": -1.0, "rag_collection": " ", "rag_filters": { "category": "" } });
+    let error = service.handle_mcp_request(request("rag_query", params)).await.unwrap_err().error;
+    assert_eq!(fields(error), ["rag_top_k", "rag_min_score", "rag_filters.category", "rag_collection"]);
+
+    // Semantic search needs an embedding provider
+    let error = service.handle_mcp_request(request("rag_query", json!({ "rag_mode": "semantic" }))).await.unwrap_err().error;
+    assert_eq!(fields(error), ["rag_mode"]);
+}
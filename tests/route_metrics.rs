@@ -0,0 +1,131 @@
+//! Latency per route: patterns rather than raw paths, rejections counted with
+//! their status, panics and abandoned requests still counted, and in-flight
+//! gauges, in both `GET /api/metrics` and the Prometheus exposition.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use support::{epoch, service, ScriptedBackend, TestServer};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::route_metrics::route_pattern;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+fn instance() -> Arc<VoidShrineMCP> {
+    Arc::new(service(Arc::new(ScriptedBackend::new()), Arc::new(ManualClock::new(epoch()))))
+}
+
+/// `filter` timed as the server times its routes, for handlers the routes don't have
+fn observed<F, R>(service: &Arc<VoidShrineMCP>, filter: F) -> impl Filter<Extract = (warp::reply::Response,), Error = std::convert::Infallible> + Clone
+where
+    F: Filter<Extract = (R,), Error = std::convert::Infallible> + Clone + Send + Sync + 'static,
+    R: warp::Reply,
+{
+    let metrics = Arc::clone(&service.route_metrics);
+    filter.with(warp::wrap_fn(move |filter| api::observe_routes(Arc::clone(&metrics), filter)))
+}
+
+/// `(requests, status classes, in flight)` of one route in `GET /api/metrics`
+fn route(metrics: &Value, method: &str, pattern: &str) -> (Value, Value, Value) {
+    let route = metrics["routes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|route| route["method"] == method && route["route"] == pattern)
+        .unwrap_or_else(|| panic!("no {} {} in {}", method, pattern, metrics["routes"]));
+    (route["requests"].clone(), route["status_classes"].clone(), route["in_flight"].clone())
+}
+
+#[test]
+fn ids_become_placeholders_and_unknown_paths_share_one_pattern() {
+    assert_eq!(route_pattern("/api/agents/scout-7/heartbeat"), "/api/agents/{id}/heartbeat");
+    assert_eq!(route_pattern("/api/throttle/scout/"), "/api/throttle/{agent_id}");
+    assert_eq!(route_pattern("/api/rag/stats/by/source"), "/api/rag/stats/by/{key}");
+    assert_eq!(route_pattern("/api/rag/stats"), "/api/rag/stats");
+    assert_eq!(route_pattern("/wp-login.php"), "unmatched");
+    assert_eq!(route_pattern("/api/agents/scout/heartbeat/extra"), "unmatched");
+}
+
+#[tokio::test]
+async fn answers_and_rejections_are_timed_by_route() {
+    let service = instance();
+    let server = TestServer::start(Arc::clone(&service)).await;
+
+    for agent_id in ["scout", "courier"] {
+        assert_eq!(server.get(&format!("/api/throttle/{}", agent_id)).await.0, 200);
+    }
+    assert_eq!(server.get("/api/jobs/no-such-job").await.0, 404);
+    assert_eq!(server.get("/wp-login.php").await.0, 404);
+    assert_eq!(server.post("/api/mcp", &json!({ "method": "llm_inference" })).await.0, 400);
+
+    let (_, metrics) = server.get("/api/metrics").await;
+    assert_eq!(route(&metrics, "GET", "/api/throttle/{agent_id}"), (json!(2), json!({ "2xx": 2 }), json!(0)));
+    assert_eq!(route(&metrics, "GET", "/api/jobs/{id}").1, json!({ "4xx": 1 }));
+    assert_eq!(route(&metrics, "GET", "unmatched").1, json!({ "4xx": 1 }));
+    assert_eq!(route(&metrics, "POST", "/api/mcp").1, json!({ "4xx": 1 }));
+    // Still answering itself
+    assert_eq!(route(&metrics, "GET", "/api/metrics").2, json!(1));
+    let throttle = metrics["routes"].as_array().unwrap().iter().find(|route| route["route"] == "/api/throttle/{agent_id}").unwrap();
+    assert_eq!(throttle["buckets"].as_array().unwrap().iter().map(|bucket| bucket["count"].as_u64().unwrap()).sum::<u64>(), 2);
+    assert!(throttle["p95_ms"].as_f64().unwrap() <= throttle["buckets"].as_array().unwrap().last().unwrap()["le_ms"].as_f64().unwrap_or(f64::MAX));
+
+    let exposition = service.handle_prometheus().await;
+    assert!(
+        exposition.contains(r#"void_shrine_route_duration_seconds_count{method="GET",route="/api/throttle/{agent_id}",status_class="2xx"} 2"#),
+        "{}",
+        exposition
+    );
+    assert!(exposition.contains(r#"void_shrine_route_in_flight{method="GET",route="/api/throttle/{agent_id}"} 0"#));
+}
+
+#[tokio::test]
+async fn panics_and_abandoned_requests_are_still_counted() {
+    let service = instance();
+    let panicking = observed(&service, warp::any().map(|| -> &'static str { panic!("handler bug") }));
+    let outcome = tokio::spawn({
+        let panicking = panicking.clone();
+        async move { warp::test::request().path("/api/version").reply(&panicking).await }
+    })
+    .await;
+    assert!(outcome.unwrap_err().is_panic());
+
+    let (release, released) = tokio::sync::oneshot::channel::<()>();
+    let released = Arc::new(tokio::sync::Mutex::new(Some(released)));
+    let stuck = observed(
+        &service,
+        warp::any().then(move || {
+            let released = Arc::clone(&released);
+            async move {
+                if let Some(released) = released.lock().await.take() {
+                    let _ = released.await;
+                }
+                "done"
+            }
+        }),
+    );
+    let request = tokio::spawn(async move { warp::test::request().path("/api/jobs").reply(&stuck).await });
+    let in_flight = || {
+        let report = service.route_metrics.report();
+        report.iter().find(|route| route.route == "/api/jobs").map_or(0, |route| route.in_flight)
+    };
+    for _ in 0..100 {
+        if in_flight() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(in_flight(), 1);
+    // The client goes away before an answer
+    request.abort();
+    let _ = request.await;
+    drop(release);
+
+    let report = service.route_metrics.report();
+    let classes = |pattern: &str| report.iter().find(|route| route.route == pattern).unwrap().status_classes.clone();
+    assert_eq!(classes("/api/version"), [("5xx".to_string(), 1)].into());
+    assert_eq!(classes("/api/jobs"), [("aborted".to_string(), 1)].into());
+    assert!(report.iter().all(|route| route.in_flight == 0), "{:?}", report);
+}
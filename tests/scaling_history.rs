@@ -0,0 +1,75 @@
+//! GET /api/scaling/history: every piece of scaling advice with the figures
+//! behind it, newest first, and the cooldowns it enforces surviving a restart.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::{json, Value};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::{Config, MetricsConfig};
+use void_shrine_mcp::mcp_server::ScalingRequest;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+fn slow(agent_id: &str) -> ScalingRequest {
+    ScalingRequest { agent_id: agent_id.to_string(), response_time: Some(20_000), token_count: Some(40), success: true }
+}
+
+async fn history(service: &Arc<VoidShrineMCP>, query: &str) -> Value {
+    let routes = api::scaling_route(Arc::clone(service)).recover(api::recover);
+    let response = warp::test::request().path(&format!("/api/scaling/history{}", query)).reply(&routes).await;
+    assert_eq!(response.status(), 200);
+    serde_json::from_slice(response.body()).unwrap()
+}
+
+#[tokio::test]
+async fn decisions_are_listed_newest_first_with_a_summary() {
+    let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2026, 3, 1, 3, 0, 0).unwrap()));
+    let service = Arc::new(VoidShrineMCP::default().with_clock(Arc::clone(&clock) as _));
+    for _ in 0..5 {
+        service.handle_scaling(slow("scout")).await;
+        clock.advance(Duration::seconds(1));
+    }
+    service.handle_scaling(slow("courier")).await;
+
+    let all = history(&service, "").await;
+    assert_eq!(all["summary"], json!({ "decisions": 6, "up": 1, "hold": 5, "down": 0, "net_capacity_change": 0.2 }));
+    assert_eq!(all["decisions"][0]["agent_id"], json!("courier"));
+    let up = &all["decisions"][1];
+    assert_eq!((&up["direction"], &up["decided_at"], &up["observations"]), (&json!("up"), &json!("2026-03-01T03:00:04Z"), &json!(5)));
+    assert_eq!((&up["p95_ms"], &up["thresholds"]["scale_up_p95_ms"]), (&json!(20_000), &json!(10_000)));
+
+    let scout = history(&service, "?agent_id=scout&since=2026-03-01T03:00:02Z&limit=1").await;
+    assert_eq!(scout["summary"]["decisions"], json!(3));
+    assert_eq!(scout["decisions"].as_array().unwrap().len(), 1);
+    assert_eq!(scout["decisions"][0]["direction"], json!("up"));
+}
+
+#[tokio::test]
+async fn the_cooldown_is_measured_from_decisions_kept_across_a_restart() {
+    let path: PathBuf = std::env::temp_dir().join(format!("void-shrine-scaling-{}.json", uuid::Uuid::new_v4()));
+    let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2026, 3, 1, 3, 0, 0).unwrap()));
+    let start = || {
+        let metrics = MetricsConfig { state_path: Some(path.clone()), ..MetricsConfig::default() };
+        Arc::new(VoidShrineMCP::new(&Config { metrics, ..Config::default() }).unwrap().with_clock(Arc::clone(&clock) as _))
+    };
+    let first = start();
+    for _ in 0..5 {
+        first.handle_scaling(slow("scout")).await;
+    }
+    assert_eq!(history(&first, "").await["summary"]["up"], json!(1));
+    first.save_agent_metrics().unwrap();
+    drop(first);
+
+    clock.advance(Duration::seconds(10));
+    let second = start();
+    for _ in 0..5 {
+        second.handle_scaling(slow("scout")).await;
+    }
+    let after = history(&second, "").await;
+    assert_eq!((&after["summary"]["decisions"], &after["summary"]["up"]), (&json!(10), &json!(1)));
+    let held = after["decisions"][0]["description"].as_str().unwrap();
+    assert!(held.starts_with("Holding: p95 latency above 10000 ms, but scaled up 10s ago"), "{}", held);
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,179 @@
+//! The startup self-check: every component reported pass or fail, knowledge
+//! bases inspected without being written to, `/ready` listing the results,
+//! and `mcp-server --check` printing them and exiting nonzero on a failure.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+
+use serde_json::Value;
+use void_shrine_mcp::api;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::rag_engine::Document;
+use void_shrine_mcp::self_check::self_check;
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("void-shrine-self-check-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A deployment whose TLS files aren't PEM and whose metrics state lives in
+/// a directory that doesn't exist
+fn broken_config(dir: &std::path::Path) -> String {
+    std::fs::write(dir.join("cert.pem"), "not a certificate").unwrap();
+    std::fs::write(dir.join("key.pem"), "not a key").unwrap();
+    format!(
+        r#"
+[server.tls]
+cert_path = {:?}
+key_path = {:?}
+
+[rag]
+db_path = {:?}
+preload_builtin_knowledge = false
+
+[audit]
+sink = "jsonl"
+path = {:?}
+
+[metrics]
+state_path = {:?}
+"#,
+        dir.join("cert.pem"),
+        dir.join("key.pem"),
+        dir.join("knowledge.db"),
+        dir.join("audit.jsonl"),
+        dir.join("missing").join("metrics.json"),
+    )
+}
+
+#[tokio::test]
+async fn every_component_is_reported_and_ready_says_degraded() {
+    let dir = scratch_dir();
+    let config = Config::from_toml(&broken_config(&dir)).unwrap();
+    let service = Arc::new(VoidShrineMCP::new(&config).unwrap());
+    let report = self_check(&config, &service).await;
+
+    let results: Vec<(&str, bool)> = report.components.iter().map(|check| (check.component.as_str(), check.passed)).collect();
+    assert_eq!(
+        results,
+        [
+            ("config", true),
+            ("sqlite_fts5", true),
+            ("rag:default", true),
+            ("backend:mock", true),
+            ("tls", false),
+            ("audit_log", true),
+            ("metrics_state", false),
+        ]
+    );
+    assert!(!report.passed());
+    let metrics = report.components.iter().find(|check| check.component == "metrics_state").unwrap();
+    assert!(metrics.detail.contains("can't be created"), "{}", metrics.detail);
+    // Probe files are removed once written
+    assert_eq!(std::fs::read_dir(&dir).unwrap().filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().contains("self-check")).count(), 0);
+
+    let table = report.to_string();
+    assert!(table.lines().any(|line| line.starts_with("tls") && line.contains("FAIL")), "{}", table);
+    assert!(table.ends_with("2 of 7 checks failed"), "{}", table);
+
+    // Checking neither opened the knowledge base nor created its file
+    assert!(service.rag_engine.read().await.is_none());
+    assert!(!dir.join("knowledge.db").exists());
+    let rag = report.components.iter().find(|check| check.component == "rag:default").unwrap();
+    assert!(rag.detail.ends_with("can be created"), "{}", rag.detail);
+
+    let probes = api::probe_routes(Arc::clone(&service)).recover(api::recover);
+    let ready = warp::test::request().path("/ready").reply(&probes).await;
+    assert_eq!(ready.status(), 200);
+    let body: Value = serde_json::from_slice(ready.body()).unwrap();
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["components"].as_array().unwrap().len(), 7);
+    assert_eq!(body["components"][6]["component"], "metrics_state");
+    assert_eq!(body["components"][6]["passed"], false);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn an_existing_knowledge_base_is_checked_without_being_written() {
+    let dir = scratch_dir();
+    let path = dir.join("knowledge.db");
+    let mut rag = RAGEngine::builder().path(&path).build().await.unwrap();
+    rag.index_document(Document {
+        id: "tide-pools".to_string(),
+        title: "Tide pools".to_string(),
+        content: "Anemones and hermit crabs shelter in tide pools.".to_string(),
+        metadata: Default::default(),
+        embedding: None,
+        chunks: Vec::new(),
+    })
+    .await
+    .unwrap();
+    rag.checkpoint().await.unwrap();
+    drop(rag);
+    let before = std::fs::read(&path).unwrap();
+
+    // Opening it would preload the built-in knowledge into it
+    let config = Config::from_toml(&format!("[rag]\ndb_path = {:?}\npreload_builtin_knowledge = true\n", path)).unwrap();
+    let service = VoidShrineMCP::new(&config).unwrap();
+    let report = self_check(&config, &service).await;
+    let check = report.components.iter().find(|check| check.component == "rag:default").unwrap();
+    assert!(check.passed && check.detail.starts_with("1 documents in 1 chunks"), "{}", check.detail);
+    assert!(service.rag_engine.read().await.is_none());
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+
+    // An engine already opened is the one checked
+    *service.rag_engine.write().await = Some(RAGEngine::builder().path(&path).build().await.unwrap());
+    service.rag_engine.write().await.as_mut().unwrap().delete_document("tide-pools").await.unwrap();
+    let report = self_check(&config, &service).await;
+    let check = report.components.iter().find(|check| check.component == "rag:default").unwrap();
+    assert!(check.detail.starts_with("0 documents"), "{}", check.detail);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+fn check(config: &std::path::Path) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_mcp-server"))
+        .arg("--check")
+        .arg("--config")
+        .arg(config)
+        // Backends configured from the environment would be pinged too
+        .env_remove("ANTHROPIC_API_KEY")
+        .env_remove("VOID_SHRINE_OPENAI_BASE_URL")
+        .env_remove("VOID_SHRINE_OLLAMA_HOST")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn check_mode_prints_the_table_and_exits_nonzero_on_failure() {
+    let dir = scratch_dir();
+    let path = dir.join("void-shrine.toml");
+
+    std::fs::write(&path, format!("[rag]\ndb_path = {:?}\n", dir.join("knowledge.db"))).unwrap();
+    let passing = check(&path);
+    let table = String::from_utf8(passing.stdout).unwrap();
+    assert!(passing.status.success(), "{}", table);
+    assert!(table.lines().any(|line| line.starts_with("rag:default") && line.contains("pass")), "{}", table);
+    assert!(table.trim_end().ends_with("checks passed"), "{}", table);
+    assert!(!dir.join("knowledge.db").exists(), "checking created the database");
+
+    std::fs::write(&path, broken_config(&dir)).unwrap();
+    let failing = check(&path);
+    let table = String::from_utf8(failing.stdout).unwrap();
+    assert_eq!(failing.status.code(), Some(1), "{}", table);
+    assert!(table.lines().any(|line| line.starts_with("metrics_state") && line.contains("FAIL")), "{}", table);
+
+    // A config that can't be loaded is the only row
+    std::fs::write(&path, "[rag]\nchunk_size = 0\n").unwrap();
+    let invalid = check(&path);
+    let table = String::from_utf8(invalid.stdout).unwrap();
+    assert_eq!(invalid.status.code(), Some(1), "{}", table);
+    let rows: Vec<&str> = table.lines().skip(1).collect();
+    assert!(rows[0].starts_with("config") && rows[0].contains("FAIL"), "{}", table);
+    assert!(rows[1].trim_start().starts_with("- rag.chunk_size must be positive"), "{}", table);
+    assert_eq!(rows.last(), Some(&"1 of 1 checks failed"));
+    std::fs::remove_dir_all(dir).ok();
+}
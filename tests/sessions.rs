@@ -0,0 +1,139 @@
+//! Conversation sessions: history in the prompt, trimming to the context
+//! window, and the `/api/sessions` routes.
+
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use void_shrine_mcp::llm_backend::{CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{InferenceEvent, MCPError, MCPParams, MCPRequest};
+use void_shrine_mcp::{api, RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+/// Answers "answer N" to the Nth prompt, recording each
+#[derive(Default)]
+struct NumberingBackend {
+    prompts: Mutex<Vec<String>>,
+}
+
+impl LLMBackend for NumberingBackend {
+    fn name(&self) -> &str {
+        "numbering"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        let mut prompts = self.prompts.lock().unwrap();
+        prompts.push(prompt.user.clone());
+        let text = format!("answer {}", prompts.len());
+        Box::pin(async move {
+            Ok(CompletionOutput { text, prompt_tokens: 10, completion_tokens: 2, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+        })
+    }
+}
+
+fn params(agent_id: &str, session_id: Option<&str>, prompt: &str) -> MCPParams {
+    serde_json::from_value(json!({
+        "agent_id": agent_id, "model": "void-shrine", "specialty": "research", "prompt": prompt,
+        "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096,
+        "session_id": session_id
+    }))
+    .unwrap()
+}
+
+fn inference(params: MCPParams) -> MCPRequest {
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }
+}
+
+async fn service(backend: Arc<NumberingBackend>) -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default().with_backend(backend);
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+#[tokio::test]
+async fn earlier_turns_go_before_the_prompt() {
+    let backend = Arc::new(NumberingBackend::default());
+    let service = service(Arc::clone(&backend)).await;
+
+    let first = service.handle_mcp_request(inference(params("talker", Some("chat-1"), "first question"))).await.unwrap();
+    let second = service.handle_mcp_request(inference(params("talker", Some("chat-1"), "second question"))).await.unwrap();
+    let unrelated = service.handle_mcp_request(inference(params("talker", None, "aside"))).await.unwrap();
+    assert_eq!((first.metadata.session_turn, second.metadata.session_turn, unrelated.metadata.session_turn), (Some(1), Some(2), None));
+
+    let mut stream = service.stream_llm_inference(params("talker", Some("chat-1"), "third question"), None).unwrap();
+    let mut turn = None;
+    while let Some(event) = stream.next().await {
+        if let InferenceEvent::Done { metadata, .. } = event {
+            turn = metadata.session_turn;
+        }
+    }
+    assert_eq!(turn, Some(3));
+
+    let prompts = backend.prompts.lock().unwrap().clone();
+    assert_eq!(prompts[0], "first question");
+    assert_eq!(prompts[1], "Conversation so far:\nUser: first question\nAssistant: answer 1\n\nsecond question");
+    assert_eq!(prompts[2], "aside");
+    assert!(prompts[3].starts_with("Conversation so far:\nUser: first question\nAssistant: answer 1\nUser: second question"), "{}", prompts[3]);
+    assert!(prompts[3].ends_with("Assistant: answer 2\n\nthird question"), "{}", prompts[3]);
+}
+
+#[tokio::test]
+async fn history_gives_way_to_knowledge_base_context_oldest_first() {
+    let backend = Arc::new(NumberingBackend::default());
+    let service = service(Arc::clone(&backend)).await;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+
+    for i in 1..=3 {
+        let prompt = format!("question {} {}", i, "padding ".repeat(300));
+        service.handle_mcp_request(inference(params("talker", Some("tight"), &prompt))).await.unwrap();
+    }
+    let mut tight = params("talker", Some("tight"), "care ethics");
+    tight.use_rag = true;
+    tight.context_window = 2048;
+    tight.max_tokens = 256;
+    service.handle_mcp_request(inference(tight)).await.unwrap();
+
+    let prompt = backend.prompts.lock().unwrap().last().unwrap().clone();
+    assert!(prompt.contains("<context>\n[1] "), "{prompt}");
+    assert!(prompt.contains("question 3") && !prompt.contains("question 1"), "{prompt}");
+    assert!(prompt.len() / 4 <= 2048 - 256, "{} estimated tokens", prompt.len() / 4);
+}
+
+#[tokio::test]
+async fn sessions_belong_to_one_agent_and_take_safe_ids() {
+    let service = service(Arc::default()).await;
+    service.handle_mcp_request(inference(params("owner", Some("mine"), "hello"))).await.unwrap();
+
+    let failure = service.handle_mcp_request(inference(params("intruder", Some("mine"), "hello"))).await.unwrap_err();
+    assert_eq!(failure.error.fields()[0].field, "session_id");
+    let failure = service.handle_mcp_request(inference(params("owner", Some("no spaces"), "hello"))).await.unwrap_err();
+    assert!(matches!(&failure.error, MCPError::InvalidFields(fields) if fields[0].field == "session_id"));
+}
+
+#[tokio::test]
+async fn sessions_are_listed_and_deleted() {
+    let service = service(Arc::default()).await;
+    service.handle_mcp_request(inference(params("talker", Some("older"), "hello"))).await.unwrap();
+    service.handle_mcp_request(inference(params("talker", Some("newer"), "hello"))).await.unwrap();
+    service.handle_mcp_request(inference(params("talker", Some("newer"), "again"))).await.unwrap();
+    let routes = api::session_routes(service).recover(api::recover);
+
+    let response = warp::test::request().method("GET").path("/api/sessions").reply(&routes).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["sessions"][0]["session_id"], "newer");
+    assert_eq!(body["sessions"][0]["turns_taken"], 2);
+    assert_eq!(body["sessions"][1]["session_id"], "older");
+
+    let response = warp::test::request().method("DELETE").path("/api/sessions/older").reply(&routes).await;
+    assert_eq!(response.status(), 200);
+    let response = warp::test::request().method("DELETE").path("/api/sessions/older").reply(&routes).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!((response.status().as_u16(), body["error"].as_str()), (404, Some("session_not_found")));
+
+    let response = warp::test::request().method("GET").path("/api/sessions").reply(&routes).await;
+    let body: Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["sessions"].as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,98 @@
+//! Draining on shutdown, against a real listener: open requests finish,
+//! `/ready` turns 503, and requests outliving the drain timeout get a 503.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde_json::json;
+use void_shrine_mcp::api;
+use void_shrine_mcp::llm_backend::{CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{ErrorResponse, MCPParams};
+use void_shrine_mcp::VoidShrineMCP;
+use warp::Filter;
+
+/// Answers every completion after a fixed delay
+struct SlowBackend(Duration);
+
+impl LLMBackend for SlowBackend {
+    fn name(&self) -> &str {
+        "slow"
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        Box::pin(async move {
+            tokio::time::sleep(self.0).await;
+            Ok(CompletionOutput {
+                text: "eventually".to_string(),
+                prompt_tokens: 1,
+                completion_tokens: 1,
+                finish_reason: FinishReason::Stop,
+                generation_time: None,
+                mean_logprob: None,
+            })
+        })
+    }
+}
+
+/// Serves the MCP and probe routes until the service starts draining
+async fn serve(backend_delay: Duration, drain_timeout: Duration) -> (String, Arc<VoidShrineMCP>, tokio::task::JoinHandle<()>) {
+    let service = VoidShrineMCP::default()
+        .with_backend(Arc::new(SlowBackend(backend_delay)))
+        .with_drain_timeout(drain_timeout);
+    service.chaos_config.write().await.enabled = false;
+    let service = Arc::new(service);
+    let routes = api::probe_routes(Arc::clone(&service)).or(api::mcp_route(Arc::clone(&service))).recover(api::recover);
+    let draining = Arc::clone(&service.shutdown);
+    let (addr, server) = warp::serve(routes)
+        .bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async move { draining.draining().await });
+    (format!("http://{}", addr), service, tokio::spawn(server))
+}
+
+fn inference(client: &reqwest::Client, base: &str) -> reqwest::RequestBuilder {
+    client.post(format!("{}/api/mcp", base)).json(&json!({
+        "method": "llm_inference",
+        "params": {
+            "agent_id": "draining", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+            "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096
+        }
+    }))
+}
+
+#[tokio::test]
+async fn in_flight_requests_complete_while_draining() {
+    let (base, service, server) = serve(Duration::from_millis(300), Duration::from_secs(10)).await;
+    let client = reqwest::Client::new();
+    assert_eq!(client.get(format!("{}/ready", base)).send().await.unwrap().status(), 200);
+
+    let slow = tokio::spawn(inference(&client, &base).send());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(service.shutdown.begin());
+
+    // The listener is closed by now, so probe the routes directly
+    let probes = api::probe_routes(Arc::clone(&service)).recover(api::recover);
+    let ready = warp::test::request().path("/ready").reply(&probes).await;
+    assert_eq!(ready.status(), 503);
+    assert_eq!(serde_json::from_slice::<ErrorResponse>(ready.body()).unwrap().error, "shutting_down");
+    assert_eq!(warp::test::request().path("/health").reply(&probes).await.status(), 200);
+
+    let response = slow.await.unwrap().unwrap();
+    assert_eq!(response.status(), 200);
+    drop(client);
+    tokio::time::timeout(Duration::from_secs(5), server).await.expect("server drained").unwrap();
+}
+
+#[tokio::test]
+async fn requests_past_the_drain_timeout_get_503() {
+    let (base, service, server) = serve(Duration::from_secs(30), Duration::from_millis(200)).await;
+    let client = reqwest::Client::new();
+    let stuck = tokio::spawn(inference(&client, &base).send());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    service.shutdown.begin();
+
+    let response = tokio::time::timeout(Duration::from_secs(5), stuck).await.expect("answered at the deadline").unwrap().unwrap();
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.json::<ErrorResponse>().await.unwrap().error, "shutting_down");
+    drop(client);
+    tokio::time::timeout(Duration::from_secs(5), server).await.expect("server drained").unwrap();
+}
@@ -0,0 +1,123 @@
+//! Specialties from configuration: framing and default model applied to
+//! requests, unknown ones refused in strict mode, and the `/api/specialties`
+//! routes listing and reloading them.
+
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::llm_backend::{CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{MCPParams, MCPRequest, MoralRequest};
+use void_shrine_mcp::specialties::{SpecialtiesConfig, Specialty};
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+/// Records the system prompt and model of each request
+#[derive(Default)]
+struct Recording {
+    seen: Mutex<Vec<(Option<String>, String)>>,
+}
+
+impl LLMBackend for Recording {
+    fn name(&self) -> &str {
+        "recording"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        self.seen.lock().unwrap().push((prompt.system.clone(), params.model.clone()));
+        Box::pin(async move {
+            Ok(CompletionOutput { text: "noted".to_string(), prompt_tokens: 4, completion_tokens: 1, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+        })
+    }
+}
+
+fn request(specialty: &str) -> MCPRequest {
+    let params = serde_json::from_value(json!({
+        "agent_id": "medic", "specialty": specialty, "prompt": "triage plan", "max_tokens": 64, "temperature": 0.2,
+        "use_rag": false, "context_window": 4096, "moral_recentering": "on"
+    }))
+    .unwrap();
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: None, idempotency_key: None }
+}
+
+fn medical() -> Specialty {
+    Specialty {
+        name: "medical".to_string(),
+        framing: "With patient dignity foremost:".to_string(),
+        mock_response: "Clinical review complete.".to_string(),
+        mock_variants: Vec::new(),
+        metadata_filters: Default::default(),
+        tags: vec!["clinical".to_string()],
+        default_model: Some("llama3.2".to_string()),
+        template: None,
+    }
+}
+
+async fn service(specialties: SpecialtiesConfig, backend: Arc<Recording>) -> Arc<VoidShrineMCP> {
+    let config = Config { specialties, ..Config::default() };
+    let service = VoidShrineMCP::new(&config).unwrap().with_backend(backend);
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+#[tokio::test]
+async fn configured_specialties_frame_requests_and_pick_their_model() {
+    let backend = Arc::new(Recording::default());
+    let mut general = medical();
+    general.name = "general".to_string();
+    general.framing = "Mindfully:".to_string();
+    general.default_model = None;
+    let specialties = SpecialtiesConfig { entries: vec![medical(), general], ..SpecialtiesConfig::default() };
+    let service = service(specialties, Arc::clone(&backend)).await;
+
+    service.handle_mcp_request(request("medical")).await.unwrap();
+    service.handle_mcp_request(request("tactical")).await.unwrap();
+    let seen = backend.seen.lock().unwrap().clone();
+    let expected = [(Some("With patient dignity foremost:"), "llama3.2"), (Some("Mindfully:"), "")];
+    assert_eq!(seen, expected.map(|(system, model)| (system.map(str::to_string), model.to_string())));
+
+    let moral = MoralRequest {
+        original_prompt: "triage plan".to_string(),
+        specialty: "medical".to_string(),
+        void_shrine_context: false,
+        ethical_framework: "care-ethics".to_string(),
+        strict: None,
+    };
+    assert_eq!(service.handle_moral_recentering(moral).await.unwrap().framing, "With patient dignity foremost:");
+}
+
+#[tokio::test]
+async fn strict_mode_refuses_unknown_specialties() {
+    let specialties = SpecialtiesConfig { strict: true, ..SpecialtiesConfig::default() };
+    let service = service(specialties, Arc::new(Recording::default())).await;
+
+    service.handle_mcp_request(request("science")).await.unwrap();
+    let failure = service.handle_mcp_request(request("astrology")).await.unwrap_err();
+    assert_eq!((failure.error.code(), failure.error.http_status()), ("invalid_params", 400));
+    assert!(failure.error.to_string().contains("specialty"), "{}", failure.error);
+}
+
+#[tokio::test]
+async fn specialties_are_listed_and_reloaded_from_their_file() {
+    let path = std::env::temp_dir().join(format!("void-shrine-specialties-{}.toml", uuid::Uuid::new_v4()));
+    let entry = |name: &str| format!("[[specialties]]\nname = \"{}\"\nframing = \"Gently:\"\nmock_response = \"Noted.\"\n", name);
+    std::fs::write(&path, entry("general")).unwrap();
+    let specialties = SpecialtiesConfig { path: Some(path.clone()), ..SpecialtiesConfig::default() };
+    let routes = api::specialty_routes(service(specialties, Arc::new(Recording::default())).await).recover(api::recover);
+    let names = |body: &[u8]| {
+        let body: Value = serde_json::from_slice(body).unwrap();
+        body["specialties"].as_array().unwrap().iter().map(|entry| entry["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+    };
+
+    let response = warp::test::request().method("GET").path("/api/specialties").reply(&routes).await;
+    assert_eq!(names(response.body()), ["general"]);
+    std::fs::write(&path, entry("general") + &entry("medical")).unwrap();
+    let response = warp::test::request().method("POST").path("/api/specialties/reload").reply(&routes).await;
+    assert_eq!((response.status().as_u16(), names(response.body())), (200, vec!["general".to_string(), "medical".to_string()]));
+
+    // Without a file there is nothing to reload
+    let routes = api::specialty_routes(service(SpecialtiesConfig::default(), Arc::new(Recording::default())).await).recover(api::recover);
+    assert_eq!(warp::test::request().method("POST").path("/api/specialties/reload").reply(&routes).await.status(), 501);
+    std::fs::remove_file(path).ok();
+}
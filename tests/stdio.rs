@@ -0,0 +1,46 @@
+//! Runs the server binary as an MCP host would: a child process speaking JSON-RPC over stdio.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+#[test]
+fn stdio_mode_answers_on_stdout_and_exits_on_eof() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mcp-server"))
+        .arg("--stdio")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    writeln!(stdin, r#"{{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {{"protocolVersion": "2024-11-05"}}}}"#).unwrap();
+    writeln!(stdin, r#"{{"jsonrpc": "2.0", "method": "notifications/initialized"}}"#).unwrap();
+    writeln!(stdin, r#"{{"jsonrpc": "2.0", "id": 2, "method": "tools/list"}}"#).unwrap();
+    stdin.flush().unwrap();
+
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    let initialized: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(initialized["id"], 1);
+    assert_eq!(initialized["result"]["protocolVersion"], "2024-11-05");
+
+    // The notification gets no reply, so the next line answers tools/list
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    let tools: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(tools["id"], 2);
+    assert!(tools["result"]["tools"].as_array().is_some_and(|tools| !tools.is_empty()));
+
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    // Logs went to stderr, so nothing else reached the protocol stream
+    line.clear();
+    assert_eq!(stdout.read_line(&mut line).unwrap(), 0);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("shutting down"));
+}
@@ -0,0 +1,87 @@
+//! Peak-memory check for streaming ingestion. Lives in its own test binary so
+//! the counting allocator only sees this test.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::io::{BufReader, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use void_shrine_mcp::rag_engine::ValidationLimits;
+use void_shrine_mcp::RAGEngine;
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(now, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Log text generated on the fly, so the input itself never sits in memory
+struct GeneratedText {
+    remaining: usize,
+    line: usize,
+    pending: Vec<u8>,
+}
+
+impl Read for GeneratedText {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pending.is_empty() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.pending = format!("Log line {} from the chaos engine: entropy pool refilled. ", self.line).into_bytes();
+            self.line += 1;
+        }
+        let n = buf.len().min(self.pending.len()).min(self.remaining);
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+#[tokio::test]
+async fn index_reader_memory_stays_bounded() {
+    const INPUT_BYTES: usize = 100 * 1024 * 1024;
+    const CHUNK_SIZE: usize = 512;
+
+    let path = std::env::temp_dir().join(format!("void_shrine_stream_{}.db", std::process::id()));
+    let mut rag = RAGEngine::builder()
+        .path(&path)
+        .chunk_size(CHUNK_SIZE)
+        // Over the default content limit, which streamed input is held to
+        .validation_limits(ValidationLimits { max_content_bytes: INPUT_BYTES, ..ValidationLimits::default() })
+        .build()
+        .await
+        .unwrap();
+
+    let reader = BufReader::new(GeneratedText { remaining: INPUT_BYTES, line: 0, pending: Vec::new() });
+    let baseline = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(baseline, Ordering::SeqCst);
+
+    let chunk_count = rag.index_reader(reader, "big_log", "Big Log", HashMap::new()).await.unwrap();
+
+    let peak = PEAK.load(Ordering::SeqCst) - baseline;
+    drop(rag);
+    let _ = std::fs::remove_file(&path);
+
+    assert!(chunk_count > INPUT_BYTES / CHUNK_SIZE);
+    assert!(peak < 1024 * CHUNK_SIZE, "peak allocation {} bytes for {} byte input", peak, INPUT_BYTES);
+}
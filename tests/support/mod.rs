@@ -0,0 +1,317 @@
+//! Shared by integration tests that declare `mod support;`: a backend
+//! playing back a script, a service whose clock, chaos and token key are
+//! fixed, `llm_inference` requests built from their params, a knowledge base
+//! seeded with fixture documents, a webhook receiver, and the routes served
+//! in-process.
+
+// Each test file uses some of it
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use futures::future::BoxFuture;
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use void_shrine_mcp::api;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::jobs::{JobQueue, JobsConfig};
+use void_shrine_mcp::llm_backend::{BackendError, CompletionOutput, FinishReason, LLMBackend, Prompt, RetryConfig};
+use void_shrine_mcp::mcp_server::{MCPParams, MCPRequest};
+use void_shrine_mcp::rag_engine::Document;
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+/// Where every harness clock starts
+pub fn epoch() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()
+}
+
+/// Seeds the chaos decisions of harness services, should a test enable chaos
+pub const CHAOS_SEED: u64 = 7;
+
+/// One call's outcome, after its latency
+enum Outcome {
+    Reply(String),
+    Fail(BackendError),
+}
+
+/// Plays back its script, one step per call: a reply or a failure, each
+/// after its latency. Calls past the end of the script fail as unavailable.
+pub struct ScriptedBackend {
+    script: Mutex<VecDeque<(Duration, Outcome)>>,
+    prompts: Mutex<Vec<Prompt>>,
+    params: Mutex<Vec<MCPParams>>,
+    /// Prompt and completion tokens reported with every reply
+    tokens: (u32, u32),
+}
+
+impl ScriptedBackend {
+    pub fn new() -> Self {
+        Self { script: Mutex::new(VecDeque::new()), prompts: Mutex::new(Vec::new()), params: Mutex::new(Vec::new()), tokens: (0, 0) }
+    }
+
+    /// Reports spending `prompt_tokens` and `completion_tokens` on each reply
+    pub fn spending(mut self, prompt_tokens: u32, completion_tokens: u32) -> Self {
+        self.tokens = (prompt_tokens, completion_tokens);
+        self
+    }
+
+    pub fn reply(self, text: &str) -> Self {
+        self.reply_after(Duration::ZERO, text)
+    }
+
+    /// `reply` the next `times` calls
+    pub fn replies(self, times: usize, text: &str) -> Self {
+        (0..times).fold(self, |backend, _| backend.reply(text))
+    }
+
+    pub fn reply_after(self, latency: Duration, text: &str) -> Self {
+        self.script.lock().unwrap().push_back((latency, Outcome::Reply(text.to_string())));
+        self
+    }
+
+    pub fn fail(self, error: BackendError) -> Self {
+        self.fail_after(Duration::ZERO, error)
+    }
+
+    pub fn fail_after(self, latency: Duration, error: BackendError) -> Self {
+        self.script.lock().unwrap().push_back((latency, Outcome::Fail(error)));
+        self
+    }
+
+    /// Prompts sent so far, in call order
+    pub fn prompts(&self) -> Vec<Prompt> {
+        self.prompts.lock().unwrap().clone()
+    }
+
+    /// The params of each call so far, in call order
+    pub fn params(&self) -> Vec<MCPParams> {
+        self.params.lock().unwrap().clone()
+    }
+}
+
+impl LLMBackend for ScriptedBackend {
+    fn name(&self) -> &str {
+        "scripted"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        self.prompts.lock().unwrap().push(prompt.clone());
+        self.params.lock().unwrap().push(params.clone());
+        let step = self.script.lock().unwrap().pop_front();
+        let (prompt_tokens, completion_tokens) = self.tokens;
+        Box::pin(async move {
+            let Some((latency, outcome)) = step else {
+                return Err(BackendError::Unavailable("the script has run out".to_string()).into());
+            };
+            tokio::time::sleep(latency).await;
+            match outcome {
+                Outcome::Reply(text) => Ok(CompletionOutput {
+                    text,
+                    prompt_tokens,
+                    completion_tokens,
+                    finish_reason: FinishReason::Stop,
+                    generation_time: None,
+                    mean_logprob: None,
+                }),
+                Outcome::Fail(error) => Err(error.into()),
+            }
+        })
+    }
+}
+
+/// A service answering from `backend` at `clock`'s time, with chaos off
+/// but seeded, one attempt per backend call, and a fixed token key, so
+/// identical requests get identical responses
+pub fn service(backend: Arc<ScriptedBackend>, clock: Arc<ManualClock>) -> VoidShrineMCP {
+    configured_service(Config::default(), backend, clock)
+}
+
+/// `service` set up from `config` otherwise
+pub fn configured_service(mut config: Config, backend: Arc<ScriptedBackend>, clock: Arc<ManualClock>) -> VoidShrineMCP {
+    config.chaos.enabled = false;
+    config.tokens.secret = Some("harness-secret-of-some-length".to_string());
+    VoidShrineMCP::new(&config)
+        .unwrap()
+        .with_backend(backend)
+        .with_retries(RetryConfig { max_attempts: 1, ..RetryConfig::default() })
+        .with_clock(clock)
+        .with_chaos_seed(CHAOS_SEED)
+}
+
+/// `agent_id` asking `prompt` without retrieval, built up with `Inference::param`
+pub fn inference(agent_id: &str, prompt: &str) -> Inference {
+    let params = serde_json::json!({ "agent_id": agent_id, "prompt": prompt, "use_rag": false });
+    Inference { params, request_id: None, idempotency_key: None }
+}
+
+/// An `llm_inference` request, as params, an `MCPRequest` or a `POST /api/mcp` body
+#[derive(Clone)]
+pub struct Inference {
+    params: Value,
+    request_id: Option<String>,
+    idempotency_key: Option<String>,
+}
+
+impl Inference {
+    pub fn param(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.params[name] = value.into();
+        self
+    }
+
+    pub fn request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
+    pub fn idempotency_key(mut self, key: &str) -> Self {
+        self.idempotency_key = Some(key.to_string());
+        self
+    }
+
+    pub fn params(&self) -> MCPParams {
+        serde_json::from_value(self.params.clone()).unwrap()
+    }
+
+    pub fn request(&self) -> MCPRequest {
+        MCPRequest {
+            method: "llm_inference".to_string(),
+            params: self.params(),
+            request_id: self.request_id.clone(),
+            idempotency_key: self.idempotency_key.clone(),
+        }
+    }
+
+    /// The JSON body of `POST /api/mcp`; any idempotency key goes in a header
+    pub fn body(&self) -> Value {
+        let mut body = serde_json::json!({ "method": "llm_inference", "params": self.params });
+        if let Some(request_id) = &self.request_id {
+            body["request_id"] = request_id.as_str().into();
+        }
+        body
+    }
+}
+
+/// The fixture documents: id, title, content and category
+pub const FIXTURES: [(&str, &str, &str, &str); 3] = [
+    ("tide-pools", "Tide pools", "Anemones and hermit crabs shelter in tide pools between the tides.", "ecology"),
+    ("lichen", "Lichen", "Lichen is a partnership of a fungus and an alga, slow to grow on bare rock.", "ecology"),
+    ("bellows", "Forge bellows", "Bellows push air into the forge so the coals burn hot enough for iron.", "craft"),
+];
+
+/// An in-memory knowledge base holding `FIXTURES`, each with its category
+/// as metadata
+pub async fn fixture_rag() -> RAGEngine {
+    let mut rag = RAGEngine::new().await.unwrap();
+    for (id, title, content, category) in FIXTURES {
+        let document = Document {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            metadata: HashMap::from([("category".to_string(), category.to_string())]),
+            embedding: None,
+            chunks: Vec::new(),
+        };
+        rag.index_document(document).await.unwrap();
+    }
+    rag
+}
+
+/// A local webhook endpoint recording the JSON body of every POST, and its URL
+pub async fn webhook_receiver() -> (String, Arc<Mutex<Vec<Value>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    let route = warp::post().and(warp::body::json()).map(move |body: Value| {
+        log.lock().unwrap().push(body);
+        warp::reply()
+    });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}/hooks", addr), received)
+}
+
+/// `api::routes` served on an ephemeral local port for the life of the test,
+/// timed by route and naming the build as the server binary has them
+pub struct TestServer {
+    pub addr: SocketAddr,
+    client: reqwest::Client,
+    api_key: Option<String>,
+}
+
+impl TestServer {
+    pub async fn start(service: Arc<VoidShrineMCP>) -> Self {
+        Self::with_jobs(service, JobsConfig::default()).await
+    }
+
+    /// `start`, running background jobs as `config` has them
+    pub async fn with_jobs(service: Arc<VoidShrineMCP>, config: JobsConfig) -> Self {
+        let jobs = Arc::new(JobQueue::start(Arc::clone(&service), config));
+        let route_metrics = Arc::clone(&service.route_metrics);
+        let routes = api::routes(service, jobs)
+            .recover(api::recover)
+            .with(warp::wrap_fn(move |filter| api::observe_routes(Arc::clone(&route_metrics), filter)))
+            .with(api::server_header());
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        Self { addr, client: reqwest::Client::new(), api_key: None }
+    }
+
+    /// Sends `key` as the bearer token of later requests
+    pub fn with_api_key(mut self, key: &str) -> Self {
+        self.api_key = Some(key.to_string());
+        self
+    }
+
+    pub async fn get(&self, path: &str) -> (u16, Value) {
+        let (status, _, body) = self.send(self.client.get(self.url(path))).await;
+        (status, body)
+    }
+
+    /// `get`, with the response headers
+    pub async fn get_with_headers(&self, path: &str) -> (u16, HeaderMap, Value) {
+        self.send(self.client.get(self.url(path))).await
+    }
+
+    pub async fn post(&self, path: &str, body: &Value) -> (u16, Value) {
+        let (status, _, body) = self.send(self.client.post(self.url(path)).json(body)).await;
+        (status, body)
+    }
+
+    pub async fn delete(&self, path: &str) -> (u16, Value) {
+        let (status, _, body) = self.send(self.client.delete(self.url(path))).await;
+        (status, body)
+    }
+
+    pub async fn put(&self, path: &str, body: &Value) -> (u16, Value) {
+        let (status, _, body) = self.send(self.client.put(self.url(path)).json(body)).await;
+        (status, body)
+    }
+
+    /// Posts `body` as it is, for bodies that aren't well-formed JSON
+    pub async fn post_text(&self, path: &str, body: &str) -> (u16, Value) {
+        let request = self.client.post(self.url(path)).header("content-type", "application/json").body(body.to_string());
+        let (status, _, body) = self.send(request).await;
+        (status, body)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// The status, headers and JSON body; Null for an empty or non-JSON body
+    async fn send(&self, mut request: reqwest::RequestBuilder) -> (u16, HeaderMap, Value) {
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let body = response.bytes().await.unwrap();
+        (status, headers, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+}
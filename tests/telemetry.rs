@@ -0,0 +1,84 @@
+//! OTLP export and trace propagation, against a local collector and a local
+//! stand-in for Ollama. Built only with the `otel` feature.
+#![cfg(feature = "otel")]
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+use void_shrine_mcp::llm_backend::{OllamaBackend, OllamaConfig};
+use void_shrine_mcp::{api, telemetry, VoidShrineMCP};
+use warp::http::HeaderMap;
+use warp::Filter;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const CALLER_SPAN: &str = "00f067aa0ba902b7";
+
+type Log<T> = Arc<Mutex<Vec<T>>>;
+
+/// Accepts every OTLP export, recording the paths posted to
+async fn collector() -> (String, Log<String>) {
+    let received = Log::default();
+    let log = Arc::clone(&received);
+    let route = warp::post().and(warp::path::full()).map(move |path: warp::path::FullPath| {
+        log.lock().unwrap().push(path.as_str().to_string());
+        warp::reply()
+    });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}", addr), received)
+}
+
+/// Answers every generate call, recording its headers
+async fn ollama() -> (String, Log<HeaderMap>) {
+    let received = Log::default();
+    let log = Arc::clone(&received);
+    let route = warp::path!("api" / "generate").and(warp::header::headers_cloned()).map(move |headers: HeaderMap| {
+        log.lock().unwrap().push(headers);
+        r#"{"model":"llama3.2","response":"Rayleigh scattering.","done":true,"done_reason":"stop","eval_count":4}"#
+    });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}", addr), received)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn callers_traces_continue_to_the_backend_and_are_exported() {
+    let (endpoint, exports) = collector().await;
+    std::env::set_var("OTEL_EXPORTER_OTLP_ENDPOINT", &endpoint);
+    let telemetry = telemetry::init(false).unwrap();
+
+    let (host, backend_calls) = ollama().await;
+    let backend = OllamaBackend::new(OllamaConfig { host, ..Default::default() }).unwrap();
+    let service = VoidShrineMCP::default().with_backend(Arc::new(backend));
+    service.chaos_config.write().await.enabled = false;
+    let routes = api::mcp_route(Arc::new(service)).recover(api::recover);
+
+    let body = json!({
+        "method": "llm_inference",
+        "params": {
+            "agent_id": "traced", "model": "llama3.2", "specialty": "science", "prompt": "Why is the sky blue?",
+            "max_tokens": 64, "temperature": 0.5, "use_rag": false, "context_window": 4096
+        }
+    });
+    let response = warp::test::request()
+        .method("POST")
+        .path("/api/mcp")
+        .header("content-type", "application/json")
+        .header("traceparent", format!("00-{TRACE_ID}-{CALLER_SPAN}-01"))
+        .body(body.to_string())
+        .reply(&routes)
+        .await;
+    assert_eq!(response.status(), 200);
+
+    // Same trace, but the backend's parent is our span rather than the caller's
+    let outgoing = backend_calls.lock().unwrap()[0]["traceparent"].to_str().unwrap().to_string();
+    let parts: Vec<&str> = outgoing.split('-').collect();
+    assert_eq!(parts[1], TRACE_ID, "{outgoing}");
+    assert_ne!(parts[2], CALLER_SPAN, "{outgoing}");
+
+    // Dropping flushes both pipelines
+    tokio::task::spawn_blocking(move || drop(telemetry)).await.unwrap();
+    let exports = exports.lock().unwrap().clone();
+    assert!(exports.iter().any(|path| path == "/v1/traces"), "{exports:?}");
+    assert!(exports.iter().any(|path| path == "/v1/metrics"), "{exports:?}");
+}
@@ -0,0 +1,84 @@
+//! Prompt templates: picked by the request or its specialty, rendered into
+//! what the backend sees, and unknown names refused.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use serde_json::json;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::llm_backend::{CompletionOutput, FinishReason, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{MCPParams, MCPRequest};
+use void_shrine_mcp::specialties::SpecialtiesConfig;
+use void_shrine_mcp::templates::{TemplateSource, TemplatesConfig};
+use void_shrine_mcp::VoidShrineMCP;
+
+/// Records each prompt it is sent
+#[derive(Default)]
+struct Recording {
+    seen: Mutex<Vec<(Option<String>, String)>>,
+}
+
+impl LLMBackend for Recording {
+    fn name(&self) -> &str {
+        "recording"
+    }
+
+    fn complete<'a>(&'a self, prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        self.seen.lock().unwrap().push((prompt.system.clone(), prompt.user.clone()));
+        Box::pin(async move {
+            Ok(CompletionOutput { text: "noted".to_string(), prompt_tokens: 4, completion_tokens: 1, finish_reason: FinishReason::Stop, generation_time: None, mean_logprob: None })
+        })
+    }
+}
+
+fn request(specialty: &str, template: Option<&str>) -> MCPRequest {
+    let mut params = json!({
+        "agent_id": "scribe", "specialty": specialty, "prompt": "summarise the log", "max_tokens": 64, "temperature": 0.2,
+        "use_rag": false, "context_window": 4096, "moral_recentering": "on"
+    });
+    if let Some(template) = template {
+        params["template"] = json!(template);
+    }
+    MCPRequest { method: "llm_inference".to_string(), params: serde_json::from_value(params).unwrap(), request_id: None, idempotency_key: None }
+}
+
+async fn service(backend: Arc<Recording>) -> VoidShrineMCP {
+    let terse = TemplateSource { system: "[{specialty}] {moral_framing}".to_string(), user: "Q: {user_prompt}".to_string() };
+    let mut specialties = SpecialtiesConfig::default();
+    specialties.entries.iter_mut().filter(|entry| entry.name == "science").for_each(|entry| entry.template = Some("terse".to_string()));
+    let config = Config { templates: TemplatesConfig { inline: BTreeMap::from([("terse".to_string(), terse)]), ..TemplatesConfig::default() }, specialties, ..Config::default() };
+    let service = VoidShrineMCP::new(&config).unwrap().with_backend(backend);
+    service.chaos_config.write().await.enabled = false;
+    service
+}
+
+#[tokio::test]
+async fn requests_and_specialties_pick_their_template() {
+    let backend = Arc::new(Recording::default());
+    let service = service(Arc::clone(&backend)).await;
+
+    service.handle_mcp_request(request("general", Some("terse"))).await.unwrap();
+    service.handle_mcp_request(request("science", None)).await.unwrap();
+    service.handle_mcp_request(request("science", Some("default"))).await.unwrap();
+
+    let seen = backend.seen.lock().unwrap().clone();
+    let prompts: Vec<(Option<&str>, &str)> = seen.iter().map(|(system, user)| (system.as_deref(), user.as_str())).collect();
+    let recentered = "Considering the wellbeing and agency of all affected parties: summarise the log";
+    assert_eq!(prompts[0], (Some("[general] With mindful consideration of all stakeholders:"), format!("Q: {}", recentered).as_str()));
+    assert_eq!(prompts[1], (Some("[science] With rigorous ethical consideration and potential social impact:"), format!("Q: {}", recentered).as_str()));
+    assert_eq!(prompts[2], (Some("With rigorous ethical consideration and potential social impact:"), recentered));
+}
+
+#[tokio::test]
+async fn unknown_templates_are_refused() {
+    let service = service(Arc::new(Recording::default())).await;
+
+    let failure = service.handle_mcp_request(request("general", Some("verbose"))).await.unwrap_err();
+    assert_eq!((failure.error.code(), failure.error.http_status()), ("invalid_params", 400));
+    assert!(failure.error.to_string().contains("template"), "{}", failure.error);
+
+    let mut specialties = SpecialtiesConfig::default();
+    specialties.entries[0].template = Some("verbose".to_string());
+    assert!(VoidShrineMCP::new(&Config { specialties, ..Config::default() }).is_err());
+}
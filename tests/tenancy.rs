@@ -0,0 +1,99 @@
+//! Tenants sharing one server: each tenant's keys see only its agents, their
+//! metrics and its documents, and anything of another tenant is not found.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::auth::{ApiKey, Auth};
+use void_shrine_mcp::{api, RAGEngine, VoidShrineMCP};
+use warp::Filter;
+
+async fn instance() -> Arc<VoidShrineMCP> {
+    let auth = Auth::new(vec![
+        ApiKey::parse("acme:acme-key:inference+admin:acme").unwrap(),
+        ApiKey::parse("globex:globex-key:inference+admin:globex").unwrap(),
+        ApiKey::parse("root:root-key:inference+cross_tenant").unwrap(),
+    ])
+    .unwrap();
+    let service = VoidShrineMCP::default().with_auth(auth);
+    service.chaos_config.write().await.enabled = false;
+    *service.rag_engine.write().await = Some(RAGEngine::new().await.unwrap());
+    Arc::new(service)
+}
+
+async fn call(service: &Arc<VoidShrineMCP>, key: &str, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let routes = service.auth.filter()
+        .and(api::mcp_route(Arc::clone(service)).or(api::agent_routes(Arc::clone(service))).or(api::document_routes(Arc::clone(service))))
+        .recover(api::recover);
+    let mut request = warp::test::request().method(method).path(path).header("authorization", format!("Bearer {}", key));
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+fn inference(agent_id: &str) -> Value {
+    json!({
+        "method": "llm_inference",
+        "params": {
+            "agent_id": agent_id, "specialty": "general", "prompt": "report in", "max_tokens": 64,
+            "temperature": 0.2, "use_rag": false, "context_window": 4096
+        }
+    })
+}
+
+fn listed(agents: &Value) -> Vec<&str> {
+    agents["agents"].as_array().unwrap().iter().map(|agent| agent["agent_id"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn agents_belong_to_the_tenant_that_first_used_them() {
+    let service = instance().await;
+    assert_eq!(call(&service, "acme-key", "POST", "/api/mcp", Some(inference("scout"))).await.0, 200);
+    let registration = json!({ "agent_id": "medic", "specialty": "medical" });
+    assert_eq!(call(&service, "globex-key", "POST", "/api/agents", Some(registration.clone())).await.0, 201);
+
+    assert_eq!(listed(&call(&service, "acme-key", "GET", "/api/agents", None).await.1), ["scout"]);
+    assert_eq!(listed(&call(&service, "globex-key", "GET", "/api/agents", None).await.1), ["medic"]);
+    assert_eq!(listed(&call(&service, "root-key", "GET", "/api/agents", None).await.1), ["medic", "scout"]);
+
+    // Another tenant's agent is as good as absent, whatever is asked of it
+    for (method, path, body) in [
+        ("POST", "/api/mcp", Some(inference("scout"))),
+        ("GET", "/api/agents/scout", None),
+        ("GET", "/api/agents/scout/metrics", None),
+        ("DELETE", "/api/agents/scout/metrics", None),
+        ("POST", "/api/agents", Some(json!({ "agent_id": "scout", "specialty": "general" }))),
+    ] {
+        let (status, error) = call(&service, "globex-key", method, path, body).await;
+        assert_eq!((status, &error["error"]), (404, &json!("agent_not_found")), "{} {}", method, path);
+    }
+    let (_, metrics) = call(&service, "acme-key", "GET", "/api/agents/scout/metrics", None).await;
+    assert_eq!(metrics["total_requests"], json!(1));
+    assert_eq!(call(&service, "root-key", "GET", "/api/agents/medic", None).await.1["registration"]["tenant"], json!("globex"));
+}
+
+#[tokio::test]
+async fn documents_are_kept_to_their_tenant() {
+    let service = instance().await;
+    let document = json!({ "id": "tide_tables", "title": "Tide tables", "content": "Spring tides follow the new and full moon." });
+    assert_eq!(call(&service, "acme-key", "POST", "/api/rag/documents", Some(document.clone())).await.0, 200);
+
+    let search = json!({ "query": "spring tides", "limit": 5 });
+    let (_, found) = call(&service, "acme-key", "POST", "/api/rag/query", Some(search.clone())).await;
+    assert_eq!(found["results"][0]["document_id"], json!("tide_tables"));
+    let (_, found) = call(&service, "globex-key", "POST", "/api/rag/query", Some(search)).await;
+    assert!(found["results"].as_array().unwrap().is_empty());
+
+    for (method, body) in [("GET", None), ("DELETE", None)] {
+        let (status, error) = call(&service, "globex-key", method, "/api/rag/documents/tide_tables", body).await;
+        assert_eq!((status, &error["error"]), (404, &json!("document_not_found")), "{}", method);
+    }
+    // Nor can the id be taken over by indexing under it
+    let (status, _) = call(&service, "globex-key", "POST", "/api/rag/documents", Some(document)).await;
+    assert_eq!(status, 404);
+
+    let (status, stored) = call(&service, "root-key", "GET", "/api/rag/documents/tide_tables", None).await;
+    assert_eq!((status, &stored["metadata"]["tenant"]), (200, &json!("acme")));
+}
@@ -0,0 +1,84 @@
+//! `GET /api/throttle/{agent_id}`: the agent's queue, its rate limit bucket,
+//! the wait a new request would likely see and the policy behind the
+//! advice, read from what admission enforces; `?verbose=false` for only
+//! whether to back off.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use support::{configured_service, epoch, inference, ScriptedBackend};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::concurrency::ConcurrencyConfig;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::{ScalingRequest, ThrottlePolicy, ThrottleStatus};
+use void_shrine_mcp::rate_limit::RateLimitConfig;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+fn instance(config: Config, backend: ScriptedBackend) -> Arc<VoidShrineMCP> {
+    Arc::new(configured_service(config, Arc::new(backend), Arc::new(ManualClock::new(epoch()))))
+}
+
+fn spawn(service: &Arc<VoidShrineMCP>, prompt: &str) {
+    let (service, request) = (Arc::clone(service), inference("pacer", prompt).request());
+    tokio::spawn(async move { service.handle_mcp_request(request).await });
+}
+
+async fn status_when(service: &VoidShrineMCP, settled: impl Fn(&ThrottleStatus) -> bool) -> ThrottleStatus {
+    loop {
+        let status = service.handle_throttle(&Tenancy::All, "pacer".to_string()).await.unwrap();
+        if settled(&status) {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+#[tokio::test]
+async fn queued_requests_and_the_wait_ahead_are_reported() {
+    let concurrency = ConcurrencyConfig { max_in_flight: 1, max_wait_ms: 60_000, retry_after_ms: 1000 };
+    let backend = ScriptedBackend::new().reply_after(Duration::from_secs(60), "done").reply("done");
+    let service = instance(Config { concurrency, ..Config::default() }, backend);
+    // The agent's requests have been taking 400ms
+    service.handle_scaling(ScalingRequest { agent_id: "pacer".to_string(), response_time: Some(400), token_count: None, success: true }).await;
+
+    spawn(&service, "take your time");
+    status_when(&service, |status| status.in_flight == 1).await;
+    spawn(&service, "quick");
+    let status = status_when(&service, |status| status.queued == 1).await;
+
+    assert_eq!((status.in_flight, status.queued, status.server_queue_depth), (1, 1, 1));
+    assert_eq!(service.concurrency.status().waiting, status.server_queue_depth);
+    assert_eq!((status.policy, status.should_throttle, status.delay_ms), (ThrottlePolicy::None, false, 0));
+    // Behind the one waiting, for the server's only slot
+    assert_eq!(status.estimated_wait_ms, 800);
+}
+
+#[tokio::test]
+async fn an_empty_bucket_is_the_policy_and_the_wait() {
+    let rate_limits = RateLimitConfig { capacity: 2, refill_per_sec: 0.5, ..RateLimitConfig::default() };
+    let service = instance(Config { rate_limits, ..Config::default() }, ScriptedBackend::new().replies(2, "done"));
+    for _ in 0..2 {
+        service.handle_mcp_request(inference("pacer", "quick").request()).await.unwrap();
+    }
+
+    let status = service.handle_throttle(&Tenancy::All, "pacer".to_string()).await.unwrap();
+    assert_eq!((status.policy, status.reason.as_str()), (ThrottlePolicy::RateLimit, "Rate limit reached"));
+    assert_eq!((status.remaining_tokens, status.bucket_capacity, status.refill_per_sec), (0, 2, 0.5));
+    assert!(status.should_throttle && status.next_token_in_ms > 1000 && status.next_token_in_ms <= 2000, "{:?}", status);
+    assert_eq!(status.delay_ms, status.next_token_in_ms);
+    assert!(status.estimated_wait_ms >= status.delay_ms);
+    let refused = service.handle_mcp_request(inference("pacer", "quick").request()).await.unwrap_err();
+    assert_eq!(refused.error.code(), "rate_limited");
+
+    let routes = api::throttle_route(Arc::clone(&service)).recover(api::recover);
+    let get = |path: &'static str| warp::test::request().path(path).reply(&routes);
+    let brief: Value = serde_json::from_slice(get("/api/throttle/pacer?verbose=false").await.body()).unwrap();
+    assert_eq!(brief, json!({ "should_throttle": true }));
+    let full: Value = serde_json::from_slice(get("/api/throttle/pacer").await.body()).unwrap();
+    assert_eq!((&full["policy"], &full["refill_per_sec"]), (&json!("rate_limit"), &json!(0.5)));
+}
@@ -0,0 +1,81 @@
+//! Request deadlines: a hung backend or a stalled knowledge base fails with
+//! 504 `deadline_exceeded` naming the stage, counted per stage and against the
+//! agent's success rate.
+
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use support::{configured_service, epoch, inference, Inference, ScriptedBackend, TestServer};
+use void_shrine_mcp::auth::Tenancy;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::{MetricsParams, TimeoutConfig, TimeoutStage};
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+
+/// Longer than any deadline here, so as good as never
+const HUNG: Duration = Duration::from_secs(3600);
+
+fn hasty(prompt: &str) -> Inference {
+    inference("hasty", prompt).param("specialty", "research")
+}
+
+fn service(timeouts: TimeoutConfig, backend: ScriptedBackend) -> Arc<VoidShrineMCP> {
+    Arc::new(configured_service(Config { timeouts, ..Config::default() }, Arc::new(backend), Arc::new(ManualClock::new(epoch()))))
+}
+
+#[tokio::test]
+async fn hung_backends_time_out_with_the_stage_named() {
+    let service = service(TimeoutConfig::default(), ScriptedBackend::new().reply("prompt").reply_after(HUNG, "prompt"));
+    let server = TestServer::start(Arc::clone(&service)).await;
+
+    service.handle_mcp_request(hasty("quick").param("timeout_ms", 100).request()).await.unwrap();
+    let (status, error) = server.post("/api/mcp", &hasty("take your time").param("timeout_ms", 100).body()).await;
+    assert_eq!(status, 504);
+    assert_eq!((error["error"].as_str(), error["stage"].as_str()), (Some("deadline_exceeded"), Some("backend")));
+
+    let metrics = service.handle_metrics(&Tenancy::All, &MetricsParams::default());
+    assert_eq!(metrics.server.timeouts_by_stage["backend"], 1);
+    assert_eq!(metrics.agents[0].success_rate, 0.5);
+    assert!(service.handle_prometheus().await.contains(r#"void_shrine_timeouts_total{stage="backend"} 1"#));
+
+    let (_, error) = server.post("/api/mcp", &hasty("quick").param("timeout_ms", 0).body()).await;
+    assert_eq!(error["fields"][0]["field"], "timeout_ms");
+}
+
+#[tokio::test]
+async fn requested_timeouts_are_clamped_and_default_applies() {
+    let timeouts = TimeoutConfig { default_ms: 30, max_ms: 60, ..TimeoutConfig::default() };
+    let service = service(timeouts, ScriptedBackend::new().reply_after(HUNG, "prompt").reply_after(HUNG, "prompt"));
+    for (timeout_ms, budget) in [(None, "30 ms"), (Some(60_000), "60 ms")] {
+        let request = hasty("take your time").param("timeout_ms", json!(timeout_ms)).request();
+        let failure = tokio::time::timeout(Duration::from_secs(5), service.handle_mcp_request(request))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(failure.error.timeout_stage(), Some(TimeoutStage::Backend));
+        assert!(failure.error.to_string().ends_with(budget), "{}", failure.error);
+    }
+}
+
+#[tokio::test]
+async fn retrieval_gets_the_smaller_budget() {
+    let service = service(TimeoutConfig { retrieval_ms: 20, ..TimeoutConfig::default() }, ScriptedBackend::new().reply("prompt"));
+    let care = || hasty("care ethics").param("use_rag", true).param("timeout_ms", 5_000).request();
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+
+    // Reindexing holds the knowledge base, stalling retrieval
+    let reindexing = service.rag_engine.write().await;
+    let failure = service.handle_mcp_request(care()).await.unwrap_err();
+    drop(reindexing);
+    assert_eq!(failure.error.timeout_stage(), Some(TimeoutStage::Retrieval));
+    assert!(failure.error.to_string().ends_with("20 ms"), "{}", failure.error);
+
+    service.handle_mcp_request(care()).await.unwrap();
+    let metrics = service.handle_metrics(&Tenancy::All, &MetricsParams::default());
+    assert_eq!(metrics.server.timeouts_by_stage, [("retrieval".to_string(), 1)].into());
+}
@@ -0,0 +1,66 @@
+//! Void shrine tokens: responses carry a signed token that
+//! `POST /api/tokens/verify` recognises, and tampered or old-style tokens
+//! that it doesn't.
+
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::config::Config;
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::tokens::TokensConfig;
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+fn request() -> MCPRequest {
+    let params = serde_json::from_value(json!({
+        "agent_id": "courier", "specialty": "general", "prompt": "deliver the report", "max_tokens": 64,
+        "temperature": 0.2, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap();
+    MCPRequest { method: "llm_inference".to_string(), params, request_id: Some("req-courier-1".to_string()), idempotency_key: None }
+}
+
+async fn instance(tokens: TokensConfig) -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::new(&Config { tokens, ..Config::default() }).unwrap();
+    service.chaos_config.write().await.enabled = false;
+    Arc::new(service)
+}
+
+async fn verify(service: &Arc<VoidShrineMCP>, token: &str) -> Value {
+    let routes = api::token_route(Arc::clone(service)).recover(api::recover);
+    let response = warp::test::request().method("POST").path("/api/tokens/verify").json(&json!({ "token": token })).reply(&routes).await;
+    assert_eq!(response.status(), 200);
+    serde_json::from_slice(response.body()).unwrap()
+}
+
+#[tokio::test]
+async fn response_tokens_attest_to_their_request() {
+    let tokens = TokensConfig { secret: Some("shared-by-the-fleet".to_string()), ..TokensConfig::default() };
+    let service = instance(tokens.clone()).await;
+    let response = service.handle_mcp_request(request()).await.unwrap();
+    let token = response.metadata.void_shrine_token;
+
+    let verified = verify(&service, &token).await;
+    assert_eq!((&verified["valid"], &verified["key"]), (&json!(true), &json!("current")));
+    assert_eq!((&verified["claims"]["request_id"], &verified["claims"]["agent_id"]), (&json!("req-courier-1"), &json!("courier")));
+
+    // Another instance with the same secret vouches for it too
+    assert_eq!(verify(&instance(tokens).await, &token).await["valid"], json!(true));
+    let (signed, _) = token.rsplit_once('.').unwrap();
+    let tampered = verify(&service, &format!("{}.{}", signed, "A".repeat(43))).await;
+    assert_eq!((&tampered["valid"], &tampered["reason"], tampered.get("claims")), (&json!(false), &json!("bad_signature"), None));
+}
+
+#[tokio::test]
+async fn opaque_tokens_are_issued_on_request_and_attest_nothing() {
+    let service = instance(TokensConfig { opaque: true, ..TokensConfig::default() }).await;
+    let token = service.handle_mcp_request(request()).await.unwrap().metadata.void_shrine_token;
+    assert!(token.starts_with("vs_"), "{}", token);
+
+    let verified = verify(&service, &token).await;
+    assert_eq!((&verified["valid"], &verified["reason"]), (&json!(false), &json!("opaque")));
+    // Tokens from an instance with its own random secret don't verify here
+    let other = instance(TokensConfig::default()).await;
+    let token = other.handle_mcp_request(request()).await.unwrap().metadata.void_shrine_token;
+    assert_eq!(verify(&service, &token).await["reason"], json!("bad_signature"));
+}
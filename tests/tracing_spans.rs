@@ -0,0 +1,149 @@
+//! The span tree one request leaves behind, as a subscriber sees it.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use serde_json::json;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use void_shrine_mcp::llm_backend::{BackendError, CompletionOutput, LLMBackend, Prompt};
+use void_shrine_mcp::mcp_server::{MCPParams, MCPRequest};
+use void_shrine_mcp::VoidShrineMCP;
+
+/// A closed span: its name, its parent's name, and its fields
+#[derive(Debug, Clone)]
+struct Closed {
+    name: &'static str,
+    parent: Option<&'static str>,
+    fields: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+struct Fields(BTreeMap<String, String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+/// Records every span's fields and, once it closes, the span
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<Closed>>>);
+
+impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Capture {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        ctx.span(id).unwrap().extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let span = ctx.span(id).unwrap();
+        let mut extensions = span.extensions_mut();
+        values.record(extensions.get_mut::<Fields>().unwrap());
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = ctx.span(&id).unwrap();
+        let fields = span.extensions().get::<Fields>().unwrap().0.clone();
+        let parent = span.parent().map(|parent| parent.name());
+        self.0.lock().unwrap().push(Closed { name: span.name(), parent, fields });
+    }
+}
+
+struct DownBackend;
+
+impl LLMBackend for DownBackend {
+    fn name(&self) -> &str {
+        "down"
+    }
+
+    fn complete<'a>(&'a self, _prompt: &'a Prompt, _params: &'a MCPParams) -> BoxFuture<'a, anyhow::Result<CompletionOutput>> {
+        Box::pin(async { Err(BackendError::Unavailable("connection refused".to_string()).into()) })
+    }
+}
+
+fn request(request_id: &str) -> MCPRequest {
+    serde_json::from_value(json!({
+        "method": "llm_inference",
+        "request_id": request_id,
+        "params": {
+            "agent_id": "waterfall", "model": "void-shrine", "specialty": "research", "prompt": "care ethics",
+            "max_tokens": 64, "temperature": 0.2, "use_rag": false, "context_window": 4096,
+            "ethical_framework": "care-ethics"
+        }
+    }))
+    .unwrap()
+}
+
+/// Handles `request` under a capturing subscriber and returns its closed spans
+async fn spans(service: VoidShrineMCP, request: MCPRequest) -> Vec<Closed> {
+    let capture = Capture::default();
+    let _default = tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+    service.chaos_config.write().await.enabled = false;
+    let _ = service.handle_mcp_request(request).await;
+    let closed = capture.0.lock().unwrap().clone();
+    closed
+}
+
+fn find<'a>(spans: &'a [Closed], name: &str) -> &'a Closed {
+    spans.iter().find(|span| span.name == name).unwrap_or_else(|| panic!("no {name} span in {spans:#?}"))
+}
+
+#[tokio::test]
+async fn a_request_is_one_span_tree() {
+    let spans = spans(VoidShrineMCP::default(), request("tree-1")).await;
+
+    let root = find(&spans, "mcp_request");
+    assert_eq!(root.parent, None);
+    for (field, value) in [
+        ("request_id", "tree-1"),
+        ("agent_id", "waterfall"),
+        ("method", "llm_inference"),
+        ("model", "void-shrine"),
+        ("specialty", "research"),
+        ("status", "200"),
+        ("outcome", "ok"),
+    ] {
+        assert_eq!(root.fields.get(field).map(String::as_str), Some(value), "{field} in {root:#?}");
+    }
+    assert!(root.fields.contains_key("duration_ms"));
+
+    for stage in ["chaos_decision", "moral_recentering", "rag_retrieval", "backend_completion"] {
+        let span = find(&spans, stage);
+        assert_eq!(span.parent, Some("mcp_request"), "{span:#?}");
+        assert_eq!(span.fields.get("outcome").map(String::as_str), Some("ok"), "{span:#?}");
+        assert!(span.fields.contains_key("duration_ms"), "{span:#?}");
+    }
+    let recentering = find(&spans, "moral_recentering");
+    assert_eq!(recentering.fields["ethical_framework"], "care-ethics");
+    assert_eq!(recentering.fields["recentered"], "true");
+    assert!(find(&spans, "backend_completion").fields.contains_key("finish_reason"));
+
+    // The root closes last, after all of its stages
+    assert_eq!(spans.last().unwrap().name, "mcp_request");
+}
+
+#[tokio::test]
+async fn failures_are_recorded_on_the_spans() {
+    let service = VoidShrineMCP::default().with_backend(Arc::new(DownBackend));
+    let spans = spans(service, request("tree-2")).await;
+
+    let completion = find(&spans, "backend_completion");
+    assert_eq!(completion.fields["outcome"], "error");
+    assert!(completion.fields["error"].contains("connection refused"), "{completion:#?}");
+
+    let root = find(&spans, "mcp_request");
+    assert_eq!(root.fields["outcome"], "error");
+    assert_eq!(root.fields["status"], "502");
+    assert!(root.fields["error"].contains("connection refused"), "{root:#?}");
+}
@@ -0,0 +1,45 @@
+//! Properties of the shared truncation helpers over arbitrary text: the
+//! result stays within its budget, is a prefix of the text but for the
+//! marker, and leaves text that already fits alone.
+
+use std::borrow::Cow;
+
+use proptest::prelude::*;
+use void_shrine_mcp::text::{truncate_at_sentence, truncate_to_tokens};
+use void_shrine_mcp::tokenizer::EstimateTokenizer;
+
+/// Prose with multi-byte characters, sentence ends and line breaks
+fn text() -> impl Strategy<Value = String> {
+    "[a-zA-Zéß漢字🌊 .!?,\n]{0,300}"
+}
+
+fn marker() -> impl Strategy<Value = String> {
+    prop_oneof![Just("…".to_string()), Just("...".to_string()), Just(String::new()), Just(" [cut]".to_string())]
+}
+
+/// `result` is `text`, empty, or a prefix of it followed by `marker`
+fn check_prefix(text: &str, result: &str, marker: &str) -> Result<(), TestCaseError> {
+    if result != text && !result.is_empty() {
+        let kept = result.strip_suffix(marker).expect("the marker ends a cut");
+        prop_assert!(text.starts_with(kept), "{:?} is not a prefix of {:?}", kept, text);
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn sentence_cuts_stay_within_max_chars(text in text(), max_chars in 0usize..120, marker in marker()) {
+        let result = truncate_at_sentence(&text, max_chars, &marker);
+        prop_assert!(result.chars().count() <= max_chars);
+        prop_assert_eq!(matches!(result, Cow::Borrowed(_)), text.chars().count() <= max_chars);
+        check_prefix(&text, &result, &marker)?;
+    }
+
+    #[test]
+    fn token_cuts_stay_within_budget(text in text(), budget in 0usize..60, marker in marker()) {
+        let result = truncate_to_tokens(&text, budget, &EstimateTokenizer, &marker);
+        prop_assert!(result.len() / 4 <= budget);
+        prop_assert_eq!(matches!(result, Cow::Borrowed(_)), text.len() / 4 <= budget);
+        check_prefix(&text, &result, &marker)?;
+    }
+}
@@ -0,0 +1,135 @@
+//! GET /api/usage: tokens per agent and model over time, with cached and
+//! dry-run usage apart from backend spend, costed from the price table and
+//! kept across restarts.
+
+mod support;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde_json::{json, Value};
+use support::{configured_service, epoch, inference, ScriptedBackend};
+use void_shrine_mcp::auth::{ApiKey, Auth};
+use void_shrine_mcp::cache::CacheConfig;
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::{Config, MetricsConfig};
+use void_shrine_mcp::mcp_server::MCPRequest;
+use void_shrine_mcp::usage::{ModelPrice, UsageConfig, UsageResponse};
+use void_shrine_mcp::{api, VoidShrineMCP};
+use warp::Filter;
+
+/// Answers `calls` times, reporting a million prompt tokens and a hundred
+/// thousand completion tokens each
+fn metered(calls: usize) -> Arc<ScriptedBackend> {
+    Arc::new(ScriptedBackend::new().spending(1_000_000, 100_000).replies(calls, "noted"))
+}
+
+fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 3, 1, 9, 15, 0).unwrap()
+}
+
+fn usage_config() -> UsageConfig {
+    let prices = BTreeMap::from([("ledger-large".to_string(), ModelPrice { prompt_per_million: 3.0, completion_per_million: 15.0 })]);
+    UsageConfig { prices, ..UsageConfig::default() }
+}
+
+fn tally(agent_id: &str, model: &str, dry_run: bool) -> MCPRequest {
+    inference(agent_id, "tally the stores")
+        .param("model", model)
+        .param("specialty", "research")
+        .param("max_tokens", 64)
+        .param("temperature", 0.0)
+        .param("dry_run", dry_run)
+        .request()
+}
+
+async fn usage(service: &Arc<VoidShrineMCP>, query: &str) -> (u16, Value) {
+    let routes = api::usage_route(Arc::clone(service)).recover(api::recover);
+    let response = warp::test::request().path(&format!("/api/usage{}", query)).reply(&routes).await;
+    (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+}
+
+#[tokio::test]
+async fn usage_is_bucketed_by_hour_and_costed_from_backend_spend() {
+    let clock = Arc::new(ManualClock::new(start()));
+    let service = configured_service(Config { usage: usage_config(), ..Config::default() }, metered(2), Arc::clone(&clock))
+        .with_cache(CacheConfig { enabled: true, ..CacheConfig::default() });
+    let service = Arc::new(service);
+
+    service.handle_mcp_request(tally("scout", "ledger-large", false)).await.unwrap();
+    assert!(service.handle_mcp_request(tally("scout", "ledger-large", false)).await.unwrap().metadata.cached);
+    service.handle_mcp_request(tally("scout", "ledger-large", true)).await.unwrap();
+    clock.advance(Duration::hours(1));
+    service.handle_mcp_request(tally("courier", "ledger-small", false)).await.unwrap();
+
+    let (status, body) = usage(&service, "").await;
+    assert_eq!(status, 200, "{}", body);
+    let hourly: UsageResponse = serde_json::from_value(body).unwrap();
+    let starts: Vec<_> = hourly.series.iter().map(|point| point.start).collect();
+    assert_eq!(starts, [Utc.with_ymd_and_hms(2026, 3, 1, 9, 0, 0).unwrap(), Utc.with_ymd_and_hms(2026, 3, 1, 10, 0, 0).unwrap()]);
+    let first = &hourly.series[0].usage;
+    assert_eq!((first.backend.requests, first.cached.requests, first.dry_run.requests), (1, 1, 1));
+    assert_eq!((first.backend.prompt_tokens, first.backend.completion_tokens), (1_000_000, 100_000));
+    assert_eq!(first.cached.prompt_tokens, 1_000_000);
+    assert_eq!(first.dry_run.completion_tokens, 0);
+    // Only the backend answer costs anything: 3 + 1.5
+    assert_eq!(first.estimated_cost, Some(4.5));
+    assert_eq!((hourly.totals.estimated_cost, hourly.currency.as_deref()), (Some(4.5), Some("USD")));
+    assert_eq!(hourly.unpriced_models, ["ledger-small"]);
+    assert_eq!(hourly.models.iter().map(|model| model.model.as_str()).collect::<Vec<_>>(), ["ledger-large", "ledger-small"]);
+
+    let (_, daily) = usage(&service, "?granularity=day&agent_id=courier").await;
+    assert_eq!(daily["series"].as_array().unwrap().len(), 1);
+    assert_eq!((&daily["series"][0]["start"], &daily["totals"]["backend"]["requests"]), (&json!("2026-03-01T00:00:00Z"), &json!(1)));
+    let (_, recent) = usage(&service, "?since=2026-03-01T10:00:00Z&model=ledger-large").await;
+    assert_eq!(recent["series"], json!([]));
+
+    let (status, body) = usage(&service, "?since=2026-03-02T00:00:00Z&until=2026-03-01T00:00:00Z").await;
+    assert_eq!((status, body["fields"][0]["field"].as_str()), (400, Some("until")));
+    assert_eq!(usage(&service, "?granularity=week").await.0, 400);
+}
+
+#[tokio::test]
+async fn tenants_see_their_own_agents_usage() {
+    let auth = Auth::new(vec![
+        ApiKey::parse("acme:acme-key:inference+admin:acme").unwrap(),
+        ApiKey::parse("globex:globex-key:inference+admin:globex").unwrap(),
+    ])
+    .unwrap();
+    let service = Arc::new(configured_service(Config::default(), metered(1), Arc::new(ManualClock::new(epoch()))).with_auth(auth));
+    let routes = service.auth.filter().and(api::mcp_route(Arc::clone(&service)).or(api::usage_route(Arc::clone(&service)))).recover(api::recover);
+    let call = |key: &str, method: &str, path: &str| {
+        warp::test::request().method(method).path(path).header("authorization", format!("Bearer {}", key))
+    };
+    let body = inference("scout", "tally").body();
+    assert_eq!(call("acme-key", "POST", "/api/mcp").json(&body).reply(&routes).await.status(), 200);
+
+    let totals = |response: warp::http::Response<warp::hyper::body::Bytes>| {
+        serde_json::from_slice::<Value>(response.body()).unwrap()["totals"]["backend"]["requests"].clone()
+    };
+    assert_eq!(totals(call("acme-key", "GET", "/api/usage").reply(&routes).await), json!(1));
+    assert_eq!(totals(call("globex-key", "GET", "/api/usage").reply(&routes).await), json!(0));
+}
+
+#[tokio::test]
+async fn usage_survives_a_restart() {
+    let path: PathBuf = std::env::temp_dir().join(format!("void-shrine-usage-{}.json", uuid::Uuid::new_v4()));
+    let start = || {
+        let metrics = MetricsConfig { state_path: Some(path.clone()), ..MetricsConfig::default() };
+        let config = Config { metrics, usage: usage_config(), ..Config::default() };
+        Arc::new(configured_service(config, metered(1), Arc::new(ManualClock::new(epoch()))))
+    };
+    let first = start();
+    first.handle_mcp_request(tally("scout", "ledger-large", false)).await.unwrap();
+    first.save_agent_metrics().unwrap();
+    let before = usage(&first, "").await.1;
+    drop(first);
+
+    let second = start();
+    let after = usage(&second, "").await.1;
+    assert_eq!(after["totals"], before["totals"]);
+    assert_eq!(after["totals"]["estimated_cost"], json!(4.5));
+    std::fs::remove_file(path).ok();
+}
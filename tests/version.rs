@@ -0,0 +1,57 @@
+//! GET /api/version: the build and the config file an instance runs with,
+//! and the build's version string in the `Server` header of every response.
+
+mod support;
+
+use std::sync::Arc;
+
+use serde_json::Value;
+use support::{configured_service, epoch, ScriptedBackend, TestServer};
+use void_shrine_mcp::build_info::{self, BuildInfo};
+use void_shrine_mcp::clock::ManualClock;
+use void_shrine_mcp::config::{Config, ConfigSource};
+use void_shrine_mcp::mcp_server::VersionInfo;
+
+/// The status, `Server` header and body of `GET path` from a service set up from `config`
+async fn get(config: &Config, path: &str) -> (u16, Option<String>, Value) {
+    let service = configured_service(config.clone(), Arc::new(ScriptedBackend::new()), Arc::new(ManualClock::new(epoch())));
+    let (status, headers, body) = TestServer::start(Arc::new(service)).await.get_with_headers(path).await;
+    (status, headers.get("server").map(|value| value.to_str().unwrap().to_string()), body)
+}
+
+#[tokio::test]
+async fn reports_the_build_and_config_file() {
+    let dir = std::env::temp_dir().join(format!("void-shrine-version-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("void-shrine.toml");
+    std::fs::write(&path, "[server]\nport = 4040\n").unwrap();
+    let config = Config::load(Some(&path)).unwrap();
+
+    let (status, server, body) = get(&config, "/api/version").await;
+    assert_eq!(status, 200, "{}", body);
+    let version: VersionInfo = serde_json::from_value(body).unwrap();
+    assert_eq!(version.build, BuildInfo::current());
+    assert_eq!(version.build.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(Some(version.server.as_str()), server.as_deref());
+    let source = version.config.unwrap();
+    assert_eq!(source, ConfigSource::new(&path, b"[server]\nport = 4040\n"));
+    // SHA-256 of those bytes, as `sha256sum` prints it
+    assert_eq!(source.sha256.len(), 64);
+
+    // Another file, another digest
+    std::fs::write(&path, "[server]\nport = 4041\n").unwrap();
+    assert_ne!(Config::load(Some(&path)).unwrap().source.unwrap().sha256, source.sha256);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let (_, _, body) = get(&Config::default(), "/api/version").await;
+    assert_eq!(body["config"], Value::Null);
+}
+
+#[tokio::test]
+async fn every_response_names_the_build() {
+    for path in ["/health", "/api/dashboard", "/api/no-such-route"] {
+        let (_, server, _) = get(&Config::default(), path).await;
+        assert_eq!(server.as_deref(), Some(build_info::version_string()), "{}", path);
+    }
+    assert!(build_info::version_string().starts_with(&format!("void-shrine-mcp/{}", env!("CARGO_PKG_VERSION"))));
+}
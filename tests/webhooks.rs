@@ -0,0 +1,136 @@
+//! Delivers webhooks to a local receiver and checks what arrives.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use void_shrine_mcp::load::LoadConfig;
+use void_shrine_mcp::mcp_server::{MCPParams, ScalingRequest};
+use void_shrine_mcp::scaling::ScalingConfig;
+use void_shrine_mcp::webhooks::{sign, WebhookConfig, EVENT_HEADER, SIGNATURE_HEADER};
+use void_shrine_mcp::VoidShrineMCP;
+use warp::http::{HeaderMap, StatusCode};
+use warp::Filter;
+
+const SECRET: &str = "orchestrator-secret";
+
+/// Answers every POST with `status`, recording its headers and body
+async fn receiver(status: StatusCode) -> (String, Arc<Mutex<Vec<(HeaderMap, Vec<u8>)>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&received);
+    let route = warp::post()
+        .and(warp::header::headers_cloned())
+        .and(warp::body::bytes())
+        .map(move |headers: HeaderMap, body: warp::hyper::body::Bytes| {
+            log.lock().unwrap().push((headers, body.to_vec()));
+            warp::reply::with_status("", status)
+        });
+    let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    (format!("http://{}/hooks", addr), received)
+}
+
+fn webhooks(url: String) -> WebhookConfig {
+    WebhookConfig { urls: vec![url], secret: Some(SECRET.to_string()), initial_backoff_ms: 10, ..WebhookConfig::default() }
+}
+
+/// Waits up to two seconds for `count` deliveries
+async fn deliveries(received: &Mutex<Vec<(HeaderMap, Vec<u8>)>>, count: usize) -> Vec<(HeaderMap, Vec<u8>)> {
+    for _ in 0..200 {
+        if received.lock().unwrap().len() >= count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    received.lock().unwrap().clone()
+}
+
+fn slow_report(agent_id: &str) -> ScalingRequest {
+    ScalingRequest { agent_id: agent_id.to_string(), response_time: Some(60_000), token_count: Some(500), success: true }
+}
+
+#[tokio::test]
+async fn scaling_decisions_are_posted_signed() {
+    let (url, received) = receiver(StatusCode::OK).await;
+    let service = VoidShrineMCP::default()
+        .with_webhooks(webhooks(url))
+        .with_scaling(ScalingConfig { min_observations: 1, ..ScalingConfig::default() });
+
+    let response = service.handle_scaling(slow_report("planner")).await;
+    assert_eq!(response.adjustments.capacity_change, 0.2);
+
+    let deliveries = deliveries(&received, 1).await;
+    assert_eq!(deliveries.len(), 1);
+    let (headers, body) = &deliveries[0];
+    assert_eq!(headers[SIGNATURE_HEADER], sign(SECRET, body).as_str());
+    assert_ne!(headers[SIGNATURE_HEADER], sign("wrong secret", body).as_str());
+    assert_eq!(headers[EVENT_HEADER], "scaling_decision");
+    assert_eq!(headers["content-type"], "application/json");
+
+    let event: Value = serde_json::from_slice(body).unwrap();
+    assert_eq!(event["event"], "scaling_decision");
+    assert_eq!(event["agent_id"], "planner");
+    assert_eq!(event["payload"]["capacity_change"], 0.2);
+    assert_eq!(event["payload"]["description"], response.adjustments.description);
+    assert!(event["timestamp"].as_str().unwrap().parse::<chrono::DateTime<chrono::Utc>>().is_ok());
+
+    // Holding at the same capacity is not news
+    service.handle_scaling(slow_report("planner")).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(received.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_then_dead_lettered() {
+    let (url, received) = receiver(StatusCode::SERVICE_UNAVAILABLE).await;
+    let service = VoidShrineMCP::default()
+        .with_webhooks(WebhookConfig { max_attempts: 3, initial_backoff_ms: 100, ..webhooks(url) })
+        .with_scaling(ScalingConfig { min_observations: 1, ..ScalingConfig::default() });
+
+    // The endpoint answers without waiting out the 300 ms of retries
+    let started = std::time::Instant::now();
+    service.handle_scaling(slow_report("planner")).await;
+    assert!(started.elapsed() < Duration::from_millis(100), "{:?}", started.elapsed());
+
+    assert_eq!(deliveries(&received, 3).await.len(), 3);
+    let mut dead_letters = String::new();
+    for _ in 0..100 {
+        dead_letters = service.metrics.render();
+        if dead_letters.contains("void_shrine_webhook_dead_letters_total{event=\"scaling_decision\"} 1") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(dead_letters.contains("void_shrine_webhook_dead_letters_total{event=\"scaling_decision\"} 1"), "{dead_letters}");
+}
+
+#[tokio::test]
+async fn agents_crossing_into_and_out_of_throttling_are_reported() {
+    let (url, received) = receiver(StatusCode::OK).await;
+    let service = VoidShrineMCP::default()
+        .with_webhooks(webhooks(url))
+        .with_load(LoadConfig { max_in_flight: 4, ..LoadConfig::default() });
+    let params: MCPParams = serde_json::from_value(json!({
+        "agent_id": "swarm", "model": "void-oracle", "specialty": "science", "prompt": "hello",
+        "max_tokens": 64, "temperature": 0.5, "use_rag": false, "context_window": 4096
+    }))
+    .unwrap();
+
+    let guards: Vec<_> = (0..4).map(|_| service.track_in_flight("swarm")).collect();
+    service.admit(&params).unwrap_err();
+    // Still throttled: no second event
+    service.admit(&params).unwrap_err();
+    drop(guards);
+    service.admit(&params).unwrap();
+
+    let deliveries = deliveries(&received, 2).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(received.lock().unwrap().len(), 2);
+    let mut events: Vec<Value> = deliveries.iter().map(|(_, body)| serde_json::from_slice(body).unwrap()).collect();
+    events.sort_by_key(|event| event["timestamp"].as_str().unwrap().parse::<chrono::DateTime<chrono::Utc>>().unwrap());
+    assert_eq!(events[0]["event"], "throttle_started");
+    assert_eq!(events[0]["payload"]["action"], "reject");
+    assert_eq!(events[0]["payload"]["load"], 1.0);
+    assert_eq!(events[1]["event"], "throttle_ended");
+    assert_eq!(events[1]["agent_id"], "swarm");
+}
@@ -0,0 +1,151 @@
+//! Drives the WebSocket transport the way a long-lived agent connection would.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use void_shrine_mcp::mcp_server::InferenceEvent;
+use void_shrine_mcp::websocket::{route, WsReply, MAX_MESSAGE_BYTES};
+use void_shrine_mcp::{RAGEngine, VoidShrineMCP};
+
+async fn service() -> Arc<VoidShrineMCP> {
+    let service = VoidShrineMCP::default();
+    service.chaos_config.write().await.enabled = false;
+    let mut rag = RAGEngine::new().await.unwrap();
+    rag.index_void_shrine_knowledge().await.unwrap();
+    *service.rag_engine.write().await = Some(rag);
+    Arc::new(service)
+}
+
+fn request(request_id: &str, method: &str, prompt: &str) -> String {
+    serde_json::json!({
+        "request_id": request_id,
+        "method": method,
+        "params": {
+            "agent_id": "ws-agent",
+            "model": "void-shrine",
+            "specialty": "research",
+            "prompt": prompt,
+            "max_tokens": 256,
+            "temperature": 0.2,
+            "use_rag": true,
+            "context_window": 4096
+        }
+    }).to_string()
+}
+
+async fn reply(client: &mut warp::test::WsClient) -> WsReply {
+    loop {
+        let message = client.recv().await.unwrap();
+        if let Ok(text) = message.to_str() {
+            return serde_json::from_str(text).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn concurrent_requests_are_correlated_by_request_id() {
+    let service = service().await;
+    let mut client = warp::test::ws().path("/ws/mcp").handshake(route(Arc::clone(&service))).await.unwrap();
+
+    // Two newline-delimited requests in one frame, a third in its own frame
+    client.send_text(format!("{}\n{}", request("a", "rag_query", "care ethics"), request("b", "rag_answer", "void shrine"))).await;
+    client.send_text(request("c", "unknown_method", "anything")).await;
+
+    let mut replies = HashMap::new();
+    for _ in 0..3 {
+        let reply = reply(&mut client).await;
+        replies.insert(reply.request_id.clone().unwrap(), reply);
+    }
+    assert!(replies["a"].response.as_ref().unwrap().result.citations.is_some());
+    assert!(replies["b"].response.is_some());
+    assert!(replies["c"].error.as_ref().unwrap().contains("Unsupported method"));
+    assert_eq!(replies["c"].code.as_deref(), Some("unsupported_method"));
+
+    client.send_text("{broken").await;
+    let malformed = reply(&mut client).await;
+    assert_eq!(malformed.request_id, None);
+    assert!(malformed.error.unwrap().starts_with("Invalid JSON"));
+    assert_eq!(malformed.code.as_deref(), Some("invalid_params"));
+
+    client.send_text(r#"{"request_id": "d", "method": "rag_query"}"#).await;
+    let invalid = reply(&mut client).await;
+    assert_eq!(invalid.request_id.as_deref(), Some("d"));
+    assert!(invalid.error.is_some());
+
+    let metrics = service.agent_metrics.get("ws-agent").unwrap();
+    assert_eq!(metrics.in_flight, 0);
+    assert_eq!(metrics.total_requests, 3);
+}
+
+#[tokio::test]
+async fn streamed_inference_sends_events_then_done() {
+    let service = service().await;
+    let mut client = warp::test::ws().path("/ws/mcp").handshake(route(Arc::clone(&service))).await.unwrap();
+
+    let mut streamed: serde_json::Value = serde_json::from_str(&request("s", "llm_inference", "care ethics")).unwrap();
+    streamed["stream"] = serde_json::json!(true);
+    client.send_text(streamed.to_string()).await;
+
+    let mut names = Vec::new();
+    let mut text = String::new();
+    loop {
+        let reply = reply(&mut client).await;
+        assert_eq!(reply.request_id.as_deref(), Some("s"));
+        let event = reply.event.unwrap();
+        names.push(event.name());
+        match event {
+            InferenceEvent::Delta { text: delta } => text.push_str(&delta),
+            InferenceEvent::Done { response, .. } => {
+                assert_eq!(response, text);
+                break;
+            }
+            InferenceEvent::Error { message, .. } => panic!("stream failed: {}", message),
+            _ => {}
+        }
+    }
+    assert_eq!(&names[..3], ["chaos_applied", "rag_context", "moral_recentering"]);
+    assert!(names.contains(&"delta"));
+}
+
+#[tokio::test]
+async fn invalid_params_are_refused_with_field_errors() {
+    let service = service().await;
+    let mut client = warp::test::ws().path("/ws/mcp").handshake(route(Arc::clone(&service))).await.unwrap();
+
+    for stream in [false, true] {
+        let mut invalid: serde_json::Value = serde_json::from_str(&request("v", "llm_inference", "")).unwrap();
+        invalid["stream"] = serde_json::json!(stream);
+        client.send_text(invalid.to_string()).await;
+
+        let reply = reply(&mut client).await;
+        assert!(reply.error.unwrap().starts_with("Invalid params"));
+        assert_eq!(reply.code.as_deref(), Some("invalid_params"));
+        assert_eq!(reply.fields.len(), 1);
+        assert_eq!((reply.fields[0].field.as_str(), reply.fields[0].constraint.as_str()), ("prompt", "non-empty"));
+    }
+}
+
+#[tokio::test]
+async fn oversized_messages_close_the_connection() {
+    let service = service().await;
+    let mut client = warp::test::ws().path("/ws/mcp").handshake(route(service)).await.unwrap();
+
+    client.send_text("x".repeat(MAX_MESSAGE_BYTES + 1)).await;
+    assert!(client.recv_closed().await.is_ok() || client.recv().await.is_err());
+}
+
+#[tokio::test]
+async fn dropped_requests_release_their_in_flight_slot() {
+    let service = service().await;
+    let guard = service.track_in_flight("ws-agent");
+    assert_eq!(service.agent_metrics.get("ws-agent").unwrap().in_flight, 1);
+
+    // Cancelling a request drops its future, and with it the guard
+    let task = tokio::spawn(async move {
+        let _guard = guard;
+        std::future::pending::<()>().await;
+    });
+    task.abort();
+    let _ = task.await;
+    assert_eq!(service.agent_metrics.get("ws-agent").unwrap().in_flight, 0);
+}